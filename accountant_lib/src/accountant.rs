@@ -0,0 +1,2230 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::cmp;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use actix::Actor;
+use actix::Addr;
+use actix::AsyncContext;
+use actix::Context;
+use actix::Handler;
+use actix::MessageResult;
+use actix::Recipient;
+use actix::Syn;
+use sub_lib::accountant::AccountantConfig;
+use sub_lib::accountant::AccountantSubs;
+use sub_lib::accountant::DEFAULT_RATES;
+use sub_lib::accountant::DelinquencyCurve;
+use sub_lib::accountant::FiatThresholds;
+use sub_lib::accountant::Financials;
+use sub_lib::accountant::GetFinancialsMsg;
+use sub_lib::accountant::GetNeighborStatsMsg;
+use sub_lib::accountant::GetNodeStatsMsg;
+use sub_lib::accountant::NeighborStats;
+use sub_lib::accountant::NodeStats;
+use sub_lib::accountant::PaymentTotals;
+use sub_lib::accountant::PendingTransaction;
+use sub_lib::accountant::RateSchedule;
+use sub_lib::accountant::ReportConsumingWalletMessage;
+use sub_lib::accountant::ReportEarningWalletMessage;
+use sub_lib::accountant::ReportExitServiceMessage;
+use sub_lib::accountant::ReportFiatPriceMessage;
+use sub_lib::accountant::ReportChannelMessage;
+use sub_lib::accountant::ReportPaymentReceivedMessage;
+use sub_lib::accountant::ReportRatesMessage;
+use sub_lib::accountant::ReportRoutingServiceMessage;
+use sub_lib::accountant::ReportTransactionMessage;
+use sub_lib::accountant::ServiceRefusal;
+use sub_lib::accountant::ServiceStanding;
+use sub_lib::accountant::ServiceStandingMessage;
+use sub_lib::accountant::ServiceTotals;
+use sub_lib::accountant::TransactionStatus;
+use sub_lib::accountant::fiat_to_sub;
+use sub_lib::blockchain_bridge::TransactionRequest;
+use sub_lib::blockchain_interface::BlockchainInterface;
+use sub_lib::cryptde::CryptDE;
+use sub_lib::cryptde::Key;
+use sub_lib::dispatcher::Component;
+use sub_lib::hopper::ExpiredCoresPackage;
+use sub_lib::hopper::IncipientCoresPackage;
+use sub_lib::logger::Logger;
+use sub_lib::neighborhood::BanNodeMsg;
+use sub_lib::neighborhood::Misbehaver;
+use sub_lib::neighborhood::NeighborMisbehavior;
+use sub_lib::neighborhood::NeighborMisbehaviorMessage;
+use sub_lib::neighborhood::UnbanNodeMsg;
+use sub_lib::peer_actors::BindMessage;
+use sub_lib::route::Route;
+use sub_lib::route::RouteSegment;
+use sub_lib::utils::NODE_MAILBOX_CAPACITY;
+use sub_lib::utils::to_string;
+use sub_lib::wallet::Wallet;
+use rand::random;
+use serde::Serialize;
+use invoice::Invoice;
+use invoice::Receipt;
+use ledger::Account;
+use ledger::Charge;
+use ledger::InvoiceRecord;
+use ledger::Ledger;
+use ledger::LedgerSide;
+use ledger::PaymentRecord;
+use payment_channel::BalanceUpdate;
+use payment_channel::PaymentChannel;
+
+// Past this many failures in a row, a payment is retried no less often than this allows
+const MAX_PAYMENT_RETRY_DOUBLINGS: u32 = 6;
+// A crash loses no more than this much of the Node's lifetime figures
+const STATS_FLUSH_INTERVAL_MS: u64 = 60000;
+// ...and no more than this much of what it's charged and been charged for service
+const CHARGE_FLUSH_INTERVAL_MS: u64 = 5000;
+// Channels other Nodes claim to pay through that can be waiting to be checked at once, so made-up
+// ones can't pile up or keep the BlockchainBridge busy
+const MAX_UNCHECKED_CHANNELS: usize = 100;
+// Nodes, and neighbor addresses, whose use of the free tier is remembered at once. When either is
+// full, what's used up or past its period is forgotten; if that isn't enough, newcomers are charged.
+const MAX_FREE_TIER_ENTRIES: usize = 10000;
+// One reading from the price oracle can move the price no further than this many times up or down,
+// so a wrong one moves the thresholds only a little before the right ones move them back
+const MAX_PRICE_CHANGE_FACTOR: f64 = 1.25;
+
+struct PaymentRetry {
+    failures: u32,
+    not_before: SystemTime,
+}
+
+struct TransactionInFlight {
+    request: TransactionRequest,
+    // None until it's submitted
+    transaction_hash_opt: Option<String>,
+}
+
+struct FreeTierUsage {
+    bytes: u64,
+    since: SystemTime,
+    exhausted: bool,
+}
+
+impl FreeTierUsage {
+    fn period_over (&self, period_ms: u64, now: SystemTime) -> bool {
+        match now.duration_since (self.since) {
+            Ok (age) => age >= Duration::from_millis (period_ms),
+            Err (_) => false
+        }
+    }
+}
+
+pub struct Accountant {
+    cryptde: &'static CryptDE,
+    config: AccountantConfig,
+    ledger: Box<Ledger>,
+    blockchain_interface: Box<BlockchainInterface>,
+    // What one SUB is worth in the currency of the fiat thresholds, once the price oracle has said
+    fiat_price_opt: Option<f64>,
+    payment_retries: HashMap<Key, PaymentRetry>,
+    // Transactions asked for and not yet confirmed or failed; a Node with one here isn't paid again
+    // meanwhile. They're only kept in memory, as the BlockchainBridge's watch on them is, so one
+    // still unconfirmed when the Node stops is forgotten, and its debt paid again after a restart.
+    in_flight: Vec<TransactionInFlight>,
+    // The latest balance update for each channel this Node hasn't seen before, waiting for the
+    // channel to check out on the blockchain; nothing is credited until it does
+    unchecked_updates: HashMap<String, BalanceUpdate>,
+    // Nodes this Node owes that won't relay for it until they're paid, so they're paid at the next
+    // scan however little is owed
+    refusing_payees: HashSet<Key>,
+    // Nodes banned for unpaid debt, until they pay it down
+    delinquents: HashSet<Key>,
+    // What each Node has had for nothing since this Node started
+    free_tier_usage: HashMap<Key, FreeTierUsage>,
+    // What's come in for nothing from each neighbor address this period, whichever Nodes it was for;
+    // these are never exhausted, but start over each period
+    free_tier_usage_by_ip: HashMap<IpAddr, FreeTierUsage>,
+    // Nodes that have never paid and owe too much for full service; the rest are in good standing
+    standings: HashMap<Key, ServiceStanding>,
+    // What each Node has been charged for since its last invoice; lost if this Node stops first
+    invoices_due: HashMap<Key, Invoice>,
+    // What other Nodes advertise they charge, for those that don't charge the default rates
+    advertised_rates: HashMap<Key, RateSchedule>,
+    to_neighborhood_bans: Option<Recipient<Syn, BanNodeMsg>>,
+    to_neighborhood_unbans: Option<Recipient<Syn, UnbanNodeMsg>>,
+    to_neighborhood_reports: Option<Recipient<Syn, NeighborMisbehaviorMessage>>,
+    to_hopper_standings: Option<Recipient<Syn, ServiceStandingMessage>>,
+    to_hopper: Option<Recipient<Syn, IncipientCoresPackage>>,
+    // Lifetime figures, as of the last flush except for uptime, which is counted up at each flush
+    stats: NodeStats,
+    uptime_counted_at: Instant,
+    logger: Logger,
+}
+
+impl Actor for Accountant {
+    type Context = Context<Self>;
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.flush_charges ();
+        self.flush_stats ()
+    }
+}
+
+impl Handler<BindMessage> for Accountant {
+    type Result = ();
+
+    fn handle(&mut self, msg: BindMessage, ctx: &mut Self::Context) -> Self::Result {
+        ctx.set_mailbox_capacity(NODE_MAILBOX_CAPACITY);
+        self.to_neighborhood_bans = Some (msg.peer_actors.neighborhood.ban_node);
+        self.to_neighborhood_unbans = Some (msg.peer_actors.neighborhood.unban_node);
+        self.to_neighborhood_reports = Some (msg.peer_actors.neighborhood.report_misbehavior);
+        self.to_hopper_standings = Some (msg.peer_actors.hopper.service_standing);
+        self.to_hopper = Some (msg.peer_actors.hopper.from_hopper_client);
+        ctx.run_interval (Duration::from_millis (STATS_FLUSH_INTERVAL_MS), |accountant, _ctx| {
+            accountant.flush_stats ()
+        });
+        ctx.run_interval (Duration::from_millis (CHARGE_FLUSH_INTERVAL_MS), |accountant, _ctx| {
+            accountant.flush_charges ()
+        });
+        if self.config.payable_scan_interval_ms > 0 {
+            ctx.run_interval (Duration::from_millis (self.config.payable_scan_interval_ms), |accountant, _ctx| {
+                accountant.scan_payables (SystemTime::now ())
+            });
+        }
+        if self.config.invoice_interval_ms > 0 {
+            ctx.run_interval (Duration::from_millis (self.config.invoice_interval_ms), |accountant, _ctx| {
+                accountant.send_invoices (SystemTime::now ())
+            });
+        }
+        // Scanning right away finds out who was banned for unpaid debt before a restart
+        if self.config.receivable_scan_interval_ms > 0 {
+            self.scan_receivables (SystemTime::now ());
+            ctx.run_interval (Duration::from_millis (self.config.receivable_scan_interval_ms), |accountant, _ctx| {
+                accountant.scan_receivables (SystemTime::now ())
+            });
+        }
+        ()
+    }
+}
+
+impl Handler<ReportRoutingServiceMessage> for Accountant {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportRoutingServiceMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.serve_routing (msg, SystemTime::now ());
+        ()
+    }
+}
+
+impl Handler<ReportExitServiceMessage> for Accountant {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportExitServiceMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.serve_exit (msg, SystemTime::now ());
+        ()
+    }
+}
+
+impl Handler<ReportPaymentReceivedMessage> for Accountant {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportPaymentReceivedMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.receive_payment (msg, SystemTime::now ());
+        ()
+    }
+}
+
+impl Handler<ReportTransactionMessage> for Accountant {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportTransactionMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.receive_transaction_report (msg, SystemTime::now ());
+        ()
+    }
+}
+
+impl Handler<ReportChannelMessage> for Accountant {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportChannelMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.receive_channel_report (msg, SystemTime::now ());
+        ()
+    }
+}
+
+impl Handler<ReportEarningWalletMessage> for Accountant {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportEarningWalletMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let payee = to_string (&msg.public_key.data);
+        match self.ledger.set_wallet (LedgerSide::Payable, &msg.public_key, &msg.earning_wallet) {
+            Ok (()) => self.logger.debug (format! ("Node {} is paid at {}", payee, msg.earning_wallet)),
+            Err (e) => self.logger.error (format! ("Couldn't record that Node {} is paid at {}: {}", payee, msg.earning_wallet, e)),
+        }
+        ()
+    }
+}
+
+impl Handler<ReportConsumingWalletMessage> for Accountant {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportConsumingWalletMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.record_consuming_wallet (&msg.public_key, &msg.consuming_wallet);
+        ()
+    }
+}
+
+impl Handler<ReportRatesMessage> for Accountant {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportRatesMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.logger.debug (format! ("Node {} charges {:?}", to_string (&msg.public_key.data), msg.rates));
+        self.advertised_rates.insert (msg.public_key, msg.rates);
+        ()
+    }
+}
+
+impl Handler<ReportFiatPriceMessage> for Accountant {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportFiatPriceMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.update_fiat_price (msg.price);
+        ()
+    }
+}
+
+impl Handler<ExpiredCoresPackage> for Accountant {
+    type Result = ();
+
+    fn handle(&mut self, msg: ExpiredCoresPackage, _ctx: &mut Self::Context) -> Self::Result {
+        if let Ok (update) = msg.payload::<BalanceUpdate> () {
+            self.receive_balance_update (update, SystemTime::now ())
+        }
+        else if let Ok (invoice) = msg.payload::<Invoice> () {
+            self.receive_invoice (invoice)
+        }
+        else if let Ok (receipt) = msg.payload::<Receipt> () {
+            self.receive_receipt (receipt)
+        }
+        else if let Ok (refusal) = msg.payload::<ServiceRefusal> () {
+            self.receive_refusal (refusal)
+        }
+        else {
+            self.logger.warning (String::from ("Discarded a package that isn't a balance update, invoice, receipt or refusal"))
+        }
+        ()
+    }
+}
+
+impl Handler<GetNeighborStatsMsg> for Accountant {
+    type Result = MessageResult<GetNeighborStatsMsg>;
+
+    fn handle(&mut self, msg: GetNeighborStatsMsg, _ctx: &mut Self::Context) -> Self::Result {
+        let result = self.neighbor_stats (window_start (SystemTime::now (), msg.window_ms_opt));
+        if let Err (ref e) = result {
+            self.logger.error (format! ("Couldn't total service per Node: {}", e));
+        }
+        MessageResult (result)
+    }
+}
+
+impl Handler<GetNodeStatsMsg> for Accountant {
+    type Result = MessageResult<GetNodeStatsMsg>;
+
+    fn handle(&mut self, _msg: GetNodeStatsMsg, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult (self.node_stats ())
+    }
+}
+
+impl Handler<GetFinancialsMsg> for Accountant {
+    type Result = MessageResult<GetFinancialsMsg>;
+
+    fn handle(&mut self, msg: GetFinancialsMsg, _ctx: &mut Self::Context) -> Self::Result {
+        let result = self.financials (&msg.windows_ms, SystemTime::now ());
+        if let Err (ref e) = result {
+            self.logger.error (format! ("Couldn't total the Node's finances: {}", e));
+        }
+        MessageResult (result)
+    }
+}
+
+impl Accountant {
+    pub fn new (cryptde: &'static CryptDE, config: AccountantConfig, ledger: Box<Ledger>, blockchain_interface: Box<BlockchainInterface>) -> Accountant {
+        let logger = Logger::new ("Accountant");
+        let stats = ledger.stats ().unwrap_or_else (|e| {
+            logger.error (format! ("Couldn't read the Node's lifetime figures; starting them over: {}", e));
+            NodeStats::default ()
+        });
+        Accountant {
+            cryptde,
+            config,
+            ledger,
+            blockchain_interface,
+            fiat_price_opt: None,
+            payment_retries: HashMap::new (),
+            in_flight: vec! (),
+            unchecked_updates: HashMap::new (),
+            refusing_payees: HashSet::new (),
+            delinquents: HashSet::new (),
+            free_tier_usage: HashMap::new (),
+            free_tier_usage_by_ip: HashMap::new (),
+            standings: HashMap::new (),
+            invoices_due: HashMap::new (),
+            advertised_rates: HashMap::new (),
+            to_neighborhood_bans: None,
+            to_neighborhood_unbans: None,
+            to_neighborhood_reports: None,
+            to_hopper_standings: None,
+            to_hopper: None,
+            stats,
+            uptime_counted_at: Instant::now (),
+            logger,
+        }
+    }
+
+    pub fn make_subs_from(addr: &Addr<Syn, Accountant>) -> AccountantSubs {
+        AccountantSubs {
+            bind: addr.clone ().recipient::<BindMessage>(),
+            report_routing_service: addr.clone ().recipient::<ReportRoutingServiceMessage>(),
+            report_exit_service: addr.clone ().recipient::<ReportExitServiceMessage>(),
+            report_payment_received: addr.clone ().recipient::<ReportPaymentReceivedMessage>(),
+            report_transaction: addr.clone ().recipient::<ReportTransactionMessage>(),
+            report_channel: addr.clone ().recipient::<ReportChannelMessage>(),
+            report_earning_wallet: addr.clone ().recipient::<ReportEarningWalletMessage>(),
+            report_consuming_wallet: addr.clone ().recipient::<ReportConsumingWalletMessage>(),
+            report_rates: addr.clone ().recipient::<ReportRatesMessage>(),
+            report_fiat_price: addr.clone ().recipient::<ReportFiatPriceMessage>(),
+            from_hopper: addr.clone ().recipient::<ExpiredCoresPackage>(),
+            get_neighbor_stats: addr.clone ().recipient::<GetNeighborStatsMsg>(),
+            get_node_stats: addr.clone ().recipient::<GetNodeStatsMsg>(),
+            get_financials: addr.clone ().recipient::<GetFinancialsMsg>(),
+        }
+    }
+
+    fn serve_routing (&mut self, msg: ReportRoutingServiceMessage, now: SystemTime) {
+        self.logger.debug (format! ("Relayed {} bytes for Node {}", msg.payload_size, to_string (&msg.consuming_node_key.data)));
+        self.stats.bytes_relayed += msg.payload_size as u64;
+        self.stats.packages_relayed += 1;
+        let charged = self.record_service (&msg.consuming_node_key, msg.neighbor_ip, Charge {
+            bytes_routed: msg.payload_size as u64,
+            bytes_exited: 0,
+            amount: self.config.rates.routing_charge (msg.payload_size),
+            timestamp: now,
+        });
+        if charged {
+            self.invoice_due (&msg.consuming_node_key, now).add_routing (msg.payload_size as u64);
+        }
+    }
+
+    fn serve_exit (&mut self, msg: ReportExitServiceMessage, now: SystemTime) {
+        self.logger.debug (format! ("Exited {} bytes for Node {}", msg.payload_size, to_string (&msg.consuming_node_key.data)));
+        self.stats.bytes_exited += msg.payload_size as u64;
+        self.stats.requests_served += 1;
+        let charged = self.record_service (&msg.consuming_node_key, msg.neighbor_ip, Charge {
+            bytes_routed: 0,
+            bytes_exited: msg.payload_size as u64,
+            amount: self.config.rates.exit_charge (msg.payload_size),
+            timestamp: now,
+        });
+        if charged {
+            self.invoice_due (&msg.consuming_node_key, now).add_exit (msg.payload_size as u64);
+        }
+    }
+
+    fn invoice_due (&mut self, consuming_node_key: &Key, now: SystemTime) -> &mut Invoice {
+        let rates = self.config.rates;
+        self.invoices_due.entry (consuming_node_key.clone ()).or_insert_with (|| Invoice::new (consuming_node_key, rates, to_secs (now)))
+    }
+
+    // Payments are credited to whichever Node's signed Gossip claimed the wallet first. A later
+    // claim by another Node is refused, or it could take the credit for the owner's payments.
+    fn record_consuming_wallet (&mut self, consuming_node_key: &Key, consuming_wallet: &Wallet) {
+        let payer = to_string (&consuming_node_key.data);
+        match self.ledger.set_wallet (LedgerSide::Receivable, consuming_node_key, consuming_wallet) {
+            Ok (()) => self.logger.debug (format! ("Node {} pays from {}", payer, consuming_wallet)),
+            Err (e) => self.logger.warning (format! ("Couldn't record that Node {} pays from {}: {}", payer, consuming_wallet, e)),
+        }
+    }
+
+    // Returns whether the Node was charged for the service
+    fn record_service (&mut self, consuming_node_key: &Key, neighbor_ip: IpAddr, charge: Charge) -> bool {
+        if self.is_free (consuming_node_key, neighbor_ip, &charge) {return false}
+        if let Err (e) = self.ledger.charge (LedgerSide::Receivable, consuming_node_key, &charge) {
+            self.logger.error (format! ("Couldn't charge Node {} {}: {}", to_string (&consuming_node_key.data), charge.amount, e));
+            return false
+        }
+        self.update_standing (consuming_node_key);
+        true
+    }
+
+    // A Node the ledger has never charged gets service for nothing until it has used up the free
+    // tier's bytes or period. New keys cost nothing to make, so the neighbor each package came in
+    // from gets no more for nothing per period, however many Nodes it's for. Usage isn't remembered
+    // across restarts, but a Node that has been charged even once is never free again.
+    fn is_free (&mut self, consuming_node_key: &Key, neighbor_ip: IpAddr, charge: &Charge) -> bool {
+        let bytes = charge.bytes_routed + charge.bytes_exited;
+        let key_bytes_opt = self.free_bytes_for_key (consuming_node_key, bytes, charge.timestamp);
+        let ip_bytes_opt = self.free_bytes_for_ip (neighbor_ip, bytes, charge.timestamp);
+        match (key_bytes_opt, ip_bytes_opt) {
+            (Some (key_bytes), Some (ip_bytes)) => {
+                if let Some (usage) = self.free_tier_usage.get_mut (consuming_node_key) {usage.bytes = key_bytes}
+                if let Some (usage) = self.free_tier_usage_by_ip.get_mut (&neighbor_ip) {usage.bytes = ip_bytes}
+                true
+            },
+            _ => {
+                if let Some (usage) = self.free_tier_usage.get_mut (consuming_node_key) {usage.exhausted = true}
+                false
+            }
+        }
+    }
+
+    // What the Node will have had for nothing with these bytes, if they're within its free tier
+    fn free_bytes_for_key (&mut self, consuming_node_key: &Key, bytes: u64, now: SystemTime) -> Option<u64> {
+        if !self.free_tier_usage.contains_key (consuming_node_key) {
+            let charged_before = match self.ledger.account (LedgerSide::Receivable, consuming_node_key) {
+                Ok (account_opt) => account_opt.is_some (),
+                Err (e) => {
+                    self.logger.error (format! ("Couldn't check whether Node {} has been charged before: {}", to_string (&consuming_node_key.data), e));
+                    true
+                }
+            };
+            if !self.make_room_for_key (now) {return None}
+            self.free_tier_usage.insert (consuming_node_key.clone (), FreeTierUsage {bytes: 0, since: now, exhausted: charged_before});
+        }
+        let free_tier = &self.config.free_tier;
+        let usage = self.free_tier_usage.get (consuming_node_key).expect ("Free tier usage disappeared");
+        if usage.exhausted || usage.period_over (free_tier.period_ms, now) || (usage.bytes + bytes > free_tier.bytes) {return None}
+        Some (usage.bytes + bytes)
+    }
+
+    // What the neighbor will have had for nothing this period with these bytes, if they're within
+    // the free tier
+    fn free_bytes_for_ip (&mut self, neighbor_ip: IpAddr, bytes: u64, now: SystemTime) -> Option<u64> {
+        let period_ms = self.config.free_tier.period_ms;
+        let known_period_over_opt = self.free_tier_usage_by_ip.get (&neighbor_ip).map (|usage| usage.period_over (period_ms, now));
+        let period_over = match known_period_over_opt {
+            Some (period_over) => period_over,
+            None => {
+                if !self.make_room_for_ip (now) {return None}
+                true
+            }
+        };
+        if period_over {
+            self.free_tier_usage_by_ip.insert (neighbor_ip, FreeTierUsage {bytes: 0, since: now, exhausted: false});
+        }
+        let ip_bytes = self.free_tier_usage_by_ip.get (&neighbor_ip).expect ("Free tier usage disappeared").bytes + bytes;
+        if ip_bytes > self.config.free_tier.bytes {None} else {Some (ip_bytes)}
+    }
+
+    // A Node forgotten once its free tier is used up or over is found in the ledger next time, since
+    // whatever it used after that was charged
+    fn make_room_for_key (&mut self, now: SystemTime) -> bool {
+        if self.free_tier_usage.len () < MAX_FREE_TIER_ENTRIES {return true}
+        let period_ms = self.config.free_tier.period_ms;
+        self.free_tier_usage.retain (|_, usage| !usage.exhausted && !usage.period_over (period_ms, now));
+        self.free_tier_usage.len () < MAX_FREE_TIER_ENTRIES
+    }
+
+    fn make_room_for_ip (&mut self, now: SystemTime) -> bool {
+        if self.free_tier_usage_by_ip.len () < MAX_FREE_TIER_ENTRIES {return true}
+        let period_ms = self.config.free_tier.period_ms;
+        self.free_tier_usage_by_ip.retain (|_, usage| !usage.period_over (period_ms, now));
+        self.free_tier_usage_by_ip.len () < MAX_FREE_TIER_ENTRIES
+    }
+
+    // Nothing is done for a Node over the debt ceiling until it pays. Short of that, Nodes that have
+    // paid at least once answer to the delinquency curve instead of the free tier's limits.
+    fn update_standing (&mut self, consuming_node_key: &Key) {
+        let account = match self.ledger.account (LedgerSide::Receivable, consuming_node_key) {
+            Ok (Some (account)) => account,
+            Ok (None) => return self.set_standing (consuming_node_key, ServiceStanding::Good),
+            Err (e) => {
+                self.logger.error (format! ("Couldn't check what Node {} owes: {}", to_string (&consuming_node_key.data), e));
+                return
+            }
+        };
+        let standing = if account.balance > self.config.debt_ceiling {
+            ServiceStanding::Refused
+        }
+        else if account.last_settled_timestamp_opt.is_some () {
+            ServiceStanding::Good
+        }
+        else if account.balance > self.config.free_tier.refusal_debt {
+            ServiceStanding::Refused
+        }
+        else if account.balance > self.config.free_tier.throttle_debt {
+            ServiceStanding::Throttled
+        }
+        else {
+            ServiceStanding::Good
+        };
+        self.set_standing (consuming_node_key, standing);
+    }
+
+    // The Hopper only hears about changes
+    fn set_standing (&mut self, consuming_node_key: &Key, standing: ServiceStanding) {
+        let previous = self.standings.get (consuming_node_key).cloned ().unwrap_or (ServiceStanding::Good);
+        if standing == previous {return}
+        self.logger.info (format! ("Node {} is now {:?}", to_string (&consuming_node_key.data), standing));
+        if standing == ServiceStanding::Good {
+            self.standings.remove (consuming_node_key);
+        }
+        else {
+            self.standings.insert (consuming_node_key.clone (), standing);
+        }
+        self.to_hopper_standings.as_ref ().expect ("Hopper unbound in Accountant").try_send (ServiceStandingMessage {
+            consuming_node_key: consuming_node_key.clone (),
+            standing,
+        }).expect ("Hopper is dead");
+    }
+
+    // Debts are paid in full, largest first, once they're big enough or old enough. Nodes whose
+    // last payment failed wait out their backoff before they're tried again, and Nodes with a
+    // payment on its way wait until it's confirmed or fails.
+    fn scan_payables (&mut self, now: SystemTime) {
+        self.retire_expired_channels (now);
+        let accounts = match self.ledger.accounts (LedgerSide::Payable) {
+            Ok (accounts) => accounts,
+            Err (e) => {
+                self.logger.error (format! ("Couldn't scan for debts to pay: {}", e));
+                return
+            }
+        };
+        let payment_threshold = self.payment_threshold ();
+        let mut due: Vec<Account> = accounts.into_iter ().filter (|account| self.is_due (account, payment_threshold, now)).collect ();
+        due.sort_by (|a, b| b.balance.cmp (&a.balance));
+        due.into_iter ().for_each (|account| self.pay (account, now));
+    }
+
+    fn is_due (&self, account: &Account, payment_threshold: i64, now: SystemTime) -> bool {
+        if account.balance <= 0 || self.is_being_paid (&account.public_key) {return false}
+        if let Some (retry) = self.payment_retries.get (&account.public_key) {
+            if now < retry.not_before {return false}
+        }
+        if account.balance >= payment_threshold || self.refusing_payees.contains (&account.public_key) {return true}
+        match now.duration_since (account.owed_since ()) {
+            Ok (age) => age >= Duration::from_millis (self.config.payment_age_threshold_ms),
+            Err (_) => false
+        }
+    }
+
+    // A Node that hasn't said where it wants to be paid waits until it does
+    fn pay (&mut self, account: Account, now: SystemTime) {
+        self.refusing_payees.remove (&account.public_key);
+        let payee = to_string (&account.public_key.data);
+        let earning_wallet = match self.ledger.wallet (LedgerSide::Payable, &account.public_key) {
+            Ok (Some (earning_wallet)) => earning_wallet,
+            Ok (None) => {
+                self.logger.warning (format! ("Can't pay Node {} {}: it has no earning wallet", payee, account.balance));
+                return
+            },
+            Err (e) => {
+                self.logger.error (format! ("Can't pay Node {} {}: {}", payee, account.balance, e));
+                return
+            }
+        };
+        if self.pay_through_channel (&account, &earning_wallet, now) {return}
+        self.request_transaction (TransactionRequest::Payment {
+            payee_key: account.public_key,
+            payee: earning_wallet,
+            amount: account.balance,
+        }, now);
+    }
+
+    fn is_being_paid (&self, public_key: &Key) -> bool {
+        self.in_flight.iter ().any (|transaction| match transaction.request {
+            TransactionRequest::Payment {ref payee_key, ..} | TransactionRequest::OpenChannel {ref payee_key, ..} => payee_key == public_key,
+            TransactionRequest::CloseChannel {..} => false,
+        })
+    }
+
+    fn is_being_closed (&self, channel_id: &str) -> bool {
+        self.in_flight.iter ().any (|transaction| match transaction.request {
+            TransactionRequest::CloseChannel {channel_id: ref closing_id, ..} => closing_id == channel_id,
+            _ => false,
+        })
+    }
+
+    // A request the BlockchainBridge can't take fails as its transaction would have
+    fn request_transaction (&mut self, request: TransactionRequest, now: SystemTime) {
+        match self.blockchain_interface.request (request.clone ()) {
+            Ok (()) => self.in_flight.push (TransactionInFlight {request, transaction_hash_opt: None}),
+            Err (e) => self.transaction_failed (request, e, now),
+        }
+    }
+
+    // Nothing is on the books until it's confirmed
+    fn receive_transaction_report (&mut self, msg: ReportTransactionMessage, now: SystemTime) {
+        let ReportTransactionMessage {request, transaction_hash_opt, status} = msg;
+        match status {
+            TransactionStatus::Submitted => {
+                for transaction in self.in_flight.iter_mut () {
+                    if transaction.request == request {
+                        transaction.transaction_hash_opt = transaction_hash_opt.clone ();
+                    }
+                }
+            },
+            TransactionStatus::Confirmed => {
+                self.in_flight.retain (|transaction| transaction.request != request);
+                self.transaction_confirmed (request, transaction_hash_opt.unwrap_or_default (), now)
+            },
+            TransactionStatus::Failed (e) => {
+                self.in_flight.retain (|transaction| transaction.request != request);
+                self.transaction_failed (request, e, now)
+            },
+        }
+    }
+
+    fn transaction_confirmed (&mut self, request: TransactionRequest, transaction_hash: String, now: SystemTime) {
+        match request {
+            TransactionRequest::Payment {payee_key, amount, ..} => {
+                self.payment_retries.remove (&payee_key);
+                let payee = to_string (&payee_key.data);
+                let payment = PaymentRecord {
+                    side: LedgerSide::Payable,
+                    public_key: payee_key,
+                    amount,
+                    transaction_hash,
+                    timestamp: now,
+                };
+                match self.ledger.record_payment (&payment) {
+                    Ok (()) => self.logger.info (format! ("Paid Node {} {} in transaction {}", payee, payment.amount, payment.transaction_hash)),
+                    Err (e) => self.logger.error (format! ("Paid Node {} {} in transaction {}, but couldn't record it: {}", payee, payment.amount, payment.transaction_hash, e)),
+                }
+            },
+            TransactionRequest::OpenChannel {channel_id, payee_key, deposit, expires, ..} => {
+                self.payment_retries.remove (&payee_key);
+                let payee = to_string (&payee_key.data);
+                let channel = PaymentChannel {
+                    channel_id,
+                    side: LedgerSide::Payable,
+                    public_key: payee_key,
+                    deposit,
+                    balance: 0,
+                    sequence: 0,
+                    signature: vec! (),
+                    expires_timestamp: UNIX_EPOCH + Duration::from_secs (expires),
+                    open: true,
+                };
+                match self.ledger.save_channel (&channel) {
+                    Ok (()) => self.logger.info (format! ("Opened channel {} to Node {} with a deposit of {} in transaction {}", channel.channel_id, payee, deposit, transaction_hash)),
+                    Err (e) => self.logger.error (format! ("Opened channel {} to Node {} in transaction {}, but couldn't record it: {}", channel.channel_id, payee, transaction_hash, e)),
+                }
+            },
+            TransactionRequest::CloseChannel {channel_id, ..} => {
+                let channel = match self.ledger.channel (&channel_id) {
+                    Ok (Some (channel)) => channel,
+                    Ok (None) => return self.logger.error (format! ("Closed channel {} in transaction {}, but it's not in the ledger", channel_id, transaction_hash)),
+                    Err (e) => return self.logger.error (format! ("Closed channel {} in transaction {}, but couldn't look it up: {}", channel_id, transaction_hash, e)),
+                };
+                match self.ledger.save_channel (&PaymentChannel {open: false, ..channel}) {
+                    Ok (()) => self.logger.info (format! ("Closed channel {} in transaction {}", channel_id, transaction_hash)),
+                    Err (e) => self.logger.error (format! ("Closed channel {} in transaction {}, but couldn't record it: {}", channel_id, transaction_hash, e)),
+                }
+            },
+        }
+    }
+
+    // A failed payment is tried again after a backoff that doubles with each failure in a row. A
+    // failed close is tried again at the next scan, since the channel is still open.
+    fn transaction_failed (&mut self, request: TransactionRequest, e: String, now: SystemTime) {
+        let payee_key = match request {
+            TransactionRequest::Payment {ref payee_key, ..} | TransactionRequest::OpenChannel {ref payee_key, ..} => payee_key.clone (),
+            TransactionRequest::CloseChannel {ref channel_id, ..} => {
+                self.logger.warning (format! ("Couldn't close channel {}: {}", channel_id, e));
+                return
+            }
+        };
+        let failures = self.payment_retries.get (&payee_key).map (|retry| retry.failures).unwrap_or (0) + 1;
+        let delay_ms = self.config.payment_retry_ms << cmp::min (failures - 1, MAX_PAYMENT_RETRY_DOUBLINGS);
+        self.logger.warning (format! ("Couldn't make {} for Node {}: {}; trying again in {}ms", request, to_string (&payee_key.data), e, delay_ms));
+        self.payment_retries.insert (payee_key, PaymentRetry {
+            failures,
+            not_before: now + Duration::from_millis (delay_ms),
+        });
+    }
+
+    // Nodes this Node pays often are paid through a channel, opened the first time a debt to one
+    // fits in it; the debt waits for the channel to be confirmed. Debts too big for what's left in
+    // the channel are paid on-chain as usual. Returns whether the debt is taken care of.
+    fn pay_through_channel (&mut self, account: &Account, earning_wallet: &Wallet, now: SystemTime) -> bool {
+        let payee = to_string (&account.public_key.data);
+        let channel = match self.ledger.open_channel (LedgerSide::Payable, &account.public_key) {
+            Ok (Some (channel)) => channel,
+            Ok (None) => return self.open_channel_to (account, earning_wallet, now),
+            Err (e) => {
+                self.logger.error (format! ("Couldn't look for a channel to Node {}: {}", payee, e));
+                return false
+            }
+        };
+        if channel.is_expired (now) || channel.remaining () < account.balance {return false}
+        let channel = PaymentChannel {balance: channel.balance + account.balance, sequence: channel.sequence + 1, ..channel};
+        let update = match BalanceUpdate::signed (&channel, self.cryptde) {
+            Ok (update) => update,
+            Err (e) => {
+                self.logger.error (format! ("Can't pay Node {} {} through channel {}: {}", payee, account.balance, channel.channel_id, e));
+                return false
+            }
+        };
+        let channel = PaymentChannel {signature: update.signature.data.clone (), ..channel};
+        let payment = PaymentRecord {
+            side: LedgerSide::Payable,
+            public_key: account.public_key.clone (),
+            amount: account.balance,
+            transaction_hash: format! ("{}#{}", channel.channel_id, channel.sequence),
+            timestamp: now,
+        };
+        if let Err (e) = self.ledger.record_channel_payment (&channel, &payment) {
+            self.logger.error (format! ("Can't pay Node {} {} through channel {}: {}", payee, account.balance, channel.channel_id, e));
+            return false
+        }
+        self.send_to_node (&account.public_key, update, "balance update");
+        self.payment_retries.remove (&account.public_key);
+        self.logger.info (format! ("Paid Node {} {} through channel {}", payee, payment.amount, channel.channel_id));
+        true
+    }
+
+    // After a failure, whether to open a channel or to pay, debts to the Node are paid on-chain
+    // until a payment goes through
+    fn open_channel_to (&mut self, account: &Account, earning_wallet: &Wallet, now: SystemTime) -> bool {
+        let payee = to_string (&account.public_key.data);
+        if self.config.channel_deposit < account.balance || self.payment_retries.contains_key (&account.public_key) {return false}
+        let payments_made = match self.ledger.payments (LedgerSide::Payable) {
+            Ok (payments) => payments.into_iter ().filter (|payment| payment.public_key == account.public_key).count (),
+            Err (e) => {
+                self.logger.error (format! ("Couldn't count payments to Node {}: {}", payee, e));
+                return false
+            }
+        };
+        if payments_made < self.config.channel_min_payments {return false}
+        self.request_transaction (TransactionRequest::OpenChannel {
+            channel_id: new_channel_id (),
+            payer_key: self.cryptde.public_key (),
+            payee_key: account.public_key.clone (),
+            payee: earning_wallet.clone (),
+            deposit: self.config.channel_deposit,
+            expires: to_secs (now + Duration::from_millis (self.config.channel_lifetime_ms)),
+        }, now);
+        true
+    }
+
+    // Balance updates, invoices and receipts go straight to the other Node rather than around a
+    // route. Frequent payees, and the Nodes this one relays for most, are neighbors in practice;
+    // the rest don't hear from this Node's Accountant.
+    fn send_to_node<T> (&self, public_key: &Key, payload: T, description: &str) where T: Serialize {
+        let route = match Route::new (vec! (
+            RouteSegment::new (vec! (&self.cryptde.public_key (), public_key), Component::Accountant)
+        ), self.cryptde) {
+            Ok (route) => route,
+            Err (e) => {
+                self.logger.error (format! ("Couldn't route {} to Node {}: {:?}", description, to_string (&public_key.data), e));
+                return
+            }
+        };
+        let package = IncipientCoresPackage::new (route, payload, public_key);
+        self.to_hopper.as_ref ().expect ("Hopper unbound in Accountant").try_send (package).expect ("Hopper is dead");
+    }
+
+    // Each Node charged since its last invoice is sent one for what it was charged, numbered on from
+    // the last and kept here whether or not it's ever countersigned
+    fn send_invoices (&mut self, now: SystemTime) {
+        let mut invoices: Vec<Invoice> = self.invoices_due.drain ().map (|(_, invoice)| invoice).collect ();
+        invoices.sort_by (|a, b| a.consuming_key.data.cmp (&b.consuming_key.data));
+        for invoice in invoices {
+            let consumer = to_string (&invoice.consuming_key.data);
+            let number = match self.ledger.last_invoice (LedgerSide::Receivable, &invoice.consuming_key) {
+                Ok (last_opt) => last_opt.map (|last| last.invoice.number).unwrap_or (0) + 1,
+                Err (e) => {
+                    self.logger.error (format! ("Couldn't number an invoice for {} to Node {}: {}", invoice.amount, consumer, e));
+                    continue
+                }
+            };
+            let invoice = match invoice.signed (number, to_secs (now), self.cryptde) {
+                Ok (invoice) => invoice,
+                Err (e) => {
+                    self.logger.error (format! ("Couldn't invoice Node {}: {}", consumer, e));
+                    continue
+                }
+            };
+            if let Err (e) = self.ledger.save_invoice (&InvoiceRecord {side: LedgerSide::Receivable, invoice: invoice.clone (), countersignature_opt: None}) {
+                self.logger.error (format! ("Couldn't keep invoice {} for {} to Node {}: {}", number, invoice.amount, consumer, e));
+                continue
+            }
+            self.logger.debug (format! ("Sent invoice {} for {} to Node {}", number, invoice.amount, consumer));
+            let consuming_key = invoice.consuming_key.clone ();
+            self.send_to_node (&consuming_key, invoice, "invoice");
+        }
+    }
+
+    // An invoice is countersigned if it's meant for this Node, newer than the last one from its
+    // Node, and priced at the rates that Node advertises. Whether its bytes were really carried
+    // can't be told from here; a Node that thinks it's overcharged can go elsewhere.
+    fn receive_invoice (&mut self, invoice: Invoice) {
+        let server = to_string (&invoice.serving_key.data);
+        let dispute_opt = if !invoice.verify (self.cryptde) {
+            Some (String::from ("bad signature"))
+        }
+        else if invoice.consuming_key != self.cryptde.public_key () {
+            Some (String::from ("it's for another Node"))
+        }
+        else if invoice.rates != self.advertised_rates.get (&invoice.serving_key).cloned ().unwrap_or (DEFAULT_RATES) {
+            Some (String::from ("it charges other rates than its Node advertises"))
+        }
+        else if invoice.amount != invoice.price () {
+            Some (format! ("it charges {} for service that comes to {}", invoice.amount, invoice.price ()))
+        }
+        else {
+            None
+        };
+        if let Some (dispute) = dispute_opt {
+            self.logger.warning (format! ("Disputed invoice {} from Node {}: {}", invoice.number, server, dispute));
+            return
+        }
+        let last_number = match self.ledger.last_invoice (LedgerSide::Payable, &invoice.serving_key) {
+            Ok (last_opt) => last_opt.map (|last| last.invoice.number).unwrap_or (0),
+            Err (e) => {
+                self.logger.error (format! ("Couldn't check invoice {} from Node {}: {}", invoice.number, server, e));
+                return
+            }
+        };
+        if invoice.number <= last_number {
+            self.logger.warning (format! ("Ignored invoice {} from Node {}: it's out of date", invoice.number, server));
+            return
+        }
+        let receipt = match Receipt::countersigned (invoice, self.cryptde) {
+            Ok (receipt) => receipt,
+            Err (e) => {
+                self.logger.error (format! ("Couldn't countersign an invoice from Node {}: {}", server, e));
+                return
+            }
+        };
+        let record = InvoiceRecord {side: LedgerSide::Payable, invoice: receipt.invoice.clone (), countersignature_opt: Some (receipt.countersignature.clone ())};
+        if let Err (e) = self.ledger.save_invoice (&record) {
+            self.logger.error (format! ("Couldn't keep invoice {} from Node {}: {}", record.invoice.number, server, e));
+            return
+        }
+        self.logger.info (format! ("Countersigned invoice {} for {} from Node {}", record.invoice.number, record.invoice.amount, server));
+        let serving_key = record.invoice.serving_key.clone ();
+        self.send_to_node (&serving_key, receipt, "receipt");
+    }
+
+    // A receipt holds its Node to an invoice this Node sent it
+    fn receive_receipt (&mut self, receipt: Receipt) {
+        let consumer = to_string (&receipt.invoice.consuming_key.data);
+        let number = receipt.invoice.number;
+        if !receipt.verify (self.cryptde) || (receipt.invoice.serving_key != self.cryptde.public_key ()) {
+            self.logger.warning (format! ("Ignored receipt for invoice {} from Node {}: bad signature", number, consumer));
+            return
+        }
+        let sent = match self.ledger.invoice (LedgerSide::Receivable, &receipt.invoice.consuming_key, number) {
+            Ok (Some (record)) => record.invoice == receipt.invoice,
+            Ok (None) => false,
+            Err (e) => {
+                self.logger.error (format! ("Couldn't look up invoice {} to Node {}: {}", number, consumer, e));
+                return
+            }
+        };
+        if !sent {
+            self.logger.warning (format! ("Ignored receipt for invoice {} from Node {}: this Node sent no such invoice", number, consumer));
+            return
+        }
+        let amount = receipt.invoice.amount;
+        let record = InvoiceRecord {side: LedgerSide::Receivable, invoice: receipt.invoice, countersignature_opt: Some (receipt.countersignature)};
+        match self.ledger.save_invoice (&record) {
+            Ok (()) => self.logger.info (format! ("Node {} countersigned invoice {} for {}", consumer, number, amount)),
+            Err (e) => self.logger.error (format! ("Node {} countersigned invoice {} for {}, but couldn't record it: {}", consumer, number, amount, e)),
+        }
+    }
+
+    // A relay's word is only taken for what this Node owes it anyway
+    fn receive_refusal (&mut self, refusal: ServiceRefusal) {
+        let server = to_string (&refusal.refusing_key.data);
+        if refusal.refused_key != self.cryptde.public_key () {
+            self.logger.warning (format! ("Ignored refusal of service from Node {}: it's for another Node", server));
+            return
+        }
+        match self.ledger.account (LedgerSide::Payable, &refusal.refusing_key) {
+            Ok (Some (ref account)) if account.balance > 0 => {
+                self.logger.warning (format! ("Node {} won't relay for this Node until it's paid; paying it {} at the next scan", server, account.balance));
+                self.refusing_payees.insert (refusal.refusing_key.clone ());
+            },
+            Ok (_) => self.logger.warning (format! ("Node {} won't relay for this Node, though this Node owes it nothing", server)),
+            Err (e) => self.logger.error (format! ("Node {} won't relay for this Node, and its account couldn't be read: {}", server, e)),
+        }
+    }
+
+    // Once a channel expires its payee closes it; this Node just stops paying through it
+    fn retire_expired_channels (&mut self, now: SystemTime) {
+        let channels = match self.ledger.open_channels (LedgerSide::Payable) {
+            Ok (channels) => channels,
+            Err (e) => {
+                self.logger.error (format! ("Couldn't check for expired channels: {}", e));
+                return
+            }
+        };
+        for channel in channels {
+            if !channel.is_expired (now) {continue}
+            let channel_id = channel.channel_id.clone ();
+            match self.ledger.save_channel (&PaymentChannel {open: false, ..channel}) {
+                Ok (()) => self.logger.info (format! ("Channel {} has expired", channel_id)),
+                Err (e) => self.logger.error (format! ("Couldn't retire expired channel {}: {}", channel_id, e)),
+            }
+        }
+    }
+
+    // A channel is closed, claiming the last balance its payer signed for, once its deposit is
+    // spent or it has expired. It's open until the close is confirmed.
+    fn close_finished_channels (&mut self, now: SystemTime) {
+        let channels = match self.ledger.open_channels (LedgerSide::Receivable) {
+            Ok (channels) => channels,
+            Err (e) => {
+                self.logger.error (format! ("Couldn't check for finished channels: {}", e));
+                return
+            }
+        };
+        for channel in channels {
+            if (channel.remaining () > 0 && !channel.is_expired (now)) || self.is_being_closed (&channel.channel_id) {continue}
+            self.request_transaction (TransactionRequest::CloseChannel {
+                channel_id: channel.channel_id,
+                balance: channel.balance,
+                signature: channel.signature,
+            }, now);
+        }
+    }
+
+    // The payer's signature is all it takes to be credited. The deposit behind it is only proven
+    // when the channel is closed, which is why channels are only opened between frequent peers.
+    fn receive_balance_update (&mut self, update: BalanceUpdate, now: SystemTime) {
+        let payer = to_string (&update.payer_key.data);
+        if !update.verify (self.cryptde) {
+            self.logger.warning (format! ("Ignored balance update {} for channel {} from Node {}: bad signature", update.sequence, update.channel_id, payer));
+            return
+        }
+        let channel_result = self.ledger.channel (&update.channel_id);
+        let previous = match channel_result {
+            Ok (Some (channel)) => channel,
+            Ok (None) => return self.check_channel (update),
+            Err (e) => {
+                self.logger.error (format! ("Couldn't look up channel {}: {}", update.channel_id, e));
+                return
+            }
+        };
+        self.credit_balance_update (previous, update, now)
+    }
+
+    // A channel this Node hasn't seen is only taken on once the BlockchainBridge finds it open on
+    // the blockchain, to this Node, for the Node that signed the update. Updates carry running
+    // totals, so only the latest one needs to wait.
+    fn check_channel (&mut self, update: BalanceUpdate) {
+        let payer = to_string (&update.payer_key.data);
+        let newer = match self.unchecked_updates.get (&update.channel_id) {
+            Some (waiting) if waiting.payer_key != update.payer_key => {
+                self.logger.warning (format! ("Ignored balance update {} for channel {} from Node {}: another Node claims the channel",
+                    update.sequence, update.channel_id, payer));
+                return
+            },
+            Some (waiting) => update.sequence > waiting.sequence,
+            None => {
+                if self.unchecked_updates.len () >= MAX_UNCHECKED_CHANNELS {
+                    self.logger.warning (format! ("Ignored balance update {} for channel {} from Node {}: too many channels are waiting to be checked",
+                        update.sequence, update.channel_id, payer));
+                    return
+                }
+                if let Err (e) = self.blockchain_interface.check_channel (&update.channel_id, &update.payer_key) {
+                    self.logger.warning (format! ("Ignored balance update {} for channel {} from Node {}: couldn't check the channel: {}",
+                        update.sequence, update.channel_id, payer, e));
+                    return
+                }
+                true
+            }
+        };
+        if newer {
+            self.unchecked_updates.insert (update.channel_id.clone (), update);
+        }
+    }
+
+    fn receive_channel_report (&mut self, msg: ReportChannelMessage, now: SystemTime) {
+        let update = match self.unchecked_updates.remove (&msg.channel_id) {
+            Some (update) => update,
+            None => return
+        };
+        let terms = match msg.result {
+            Ok (terms) => terms,
+            Err (e) => {
+                self.logger.warning (format! ("Ignored balance update {} for channel {} from Node {}: the channel doesn't check out: {}",
+                    update.sequence, update.channel_id, to_string (&update.payer_key.data), e));
+                return
+            }
+        };
+        let channel = PaymentChannel {
+            channel_id: update.channel_id.clone (),
+            side: LedgerSide::Receivable,
+            public_key: update.payer_key.clone (),
+            deposit: terms.deposit,
+            balance: 0,
+            sequence: 0,
+            signature: vec! (),
+            expires_timestamp: UNIX_EPOCH + Duration::from_secs (terms.expires),
+            open: true,
+        };
+        self.credit_balance_update (channel, update, now)
+    }
+
+    // The update is checked against what's known of the channel, which for a new one is what's on
+    // the blockchain
+    fn credit_balance_update (&mut self, previous: PaymentChannel, update: BalanceUpdate, now: SystemTime) {
+        let payer = to_string (&update.payer_key.data);
+        let refusal_opt = if previous.side != LedgerSide::Receivable || previous.public_key != update.payer_key
+                || previous.deposit != update.deposit || previous.expires_timestamp != update.expires_timestamp () {
+            Some ("it doesn't match the channel")
+        }
+        else if !previous.open || previous.is_expired (now) {
+            Some ("the channel is closed")
+        }
+        else if update.sequence <= previous.sequence {
+            Some ("it's out of date")
+        }
+        else if update.balance <= previous.balance || update.balance > update.deposit {
+            Some ("its balance is impossible")
+        }
+        else {
+            None
+        };
+        if let Some (refusal) = refusal_opt {
+            self.logger.warning (format! ("Ignored balance update {} for channel {} from Node {}: {}", update.sequence, update.channel_id, payer, refusal));
+            return
+        }
+        let amount = update.balance - previous.balance;
+        let channel = PaymentChannel {balance: update.balance, sequence: update.sequence, signature: update.signature.data.clone (), ..previous};
+        let payment = PaymentRecord {
+            side: LedgerSide::Receivable,
+            public_key: update.payer_key.clone (),
+            amount,
+            transaction_hash: format! ("{}#{}", update.channel_id, update.sequence),
+            timestamp: now,
+        };
+        if let Err (e) = self.ledger.record_channel_payment (&channel, &payment) {
+            self.logger.error (format! ("Couldn't record payment of {} from Node {} through channel {}: {}", amount, payer, channel.channel_id, e));
+            return
+        }
+        self.logger.info (format! ("Node {} paid {} through channel {}", payer, amount, channel.channel_id));
+        self.restore_standing (&payment.public_key);
+    }
+
+    // Deadbeats are banned once, and stay banned until a payment brings their debt back down. Their
+    // reputation suffers too, since they've been sent invoices they didn't pay.
+    fn scan_receivables (&mut self, now: SystemTime) {
+        self.close_finished_channels (now);
+        let accounts = match self.ledger.accounts (LedgerSide::Receivable) {
+            Ok (accounts) => accounts,
+            Err (e) => {
+                self.logger.error (format! ("Couldn't scan for unpaid debts: {}", e));
+                return
+            }
+        };
+        let delinquency_curve = self.delinquency_curve ();
+        for account in accounts {
+            if self.delinquents.contains (&account.public_key) || !is_delinquent (&account, &delinquency_curve, now) {continue}
+            let countersigned = self.ledger.countersigned_since (LedgerSide::Receivable, &account.public_key, account.owed_since ()).unwrap_or_else (|e| {
+                self.logger.error (format! ("Couldn't total the invoices Node {} countersigned: {}", to_string (&account.public_key.data), e));
+                0
+            });
+            self.logger.warning (format! ("Banning Node {}: it has owed {} since {:?}, and countersigned invoices for {} since then",
+                to_string (&account.public_key.data), account.balance, account.owed_since (), countersigned));
+            self.to_neighborhood_bans.as_ref ().expect ("Neighborhood unbound in Accountant").try_send (BanNodeMsg {
+                public_key: account.public_key.clone (),
+                reason: String::from ("Unpaid debt"),
+                share: false,
+            }).expect ("Neighborhood is dead");
+            self.to_neighborhood_reports.as_ref ().expect ("Neighborhood unbound in Accountant").try_send (NeighborMisbehaviorMessage {
+                misbehaver: Misbehaver::Node (account.public_key.clone ()),
+                misbehavior: NeighborMisbehavior::UnpaidDebt,
+            }).expect ("Neighborhood is dead");
+            self.delinquents.insert (account.public_key);
+        }
+    }
+
+    // The threshold in SUB, or the fiat one at the going price if there is one and a price to be had
+    fn payment_threshold (&self) -> i64 {
+        match self.fiat_price () {
+            Some ((fiat_thresholds, price)) => fiat_to_sub (fiat_thresholds.payment_threshold, price),
+            None => self.config.payment_threshold
+        }
+    }
+
+    fn delinquency_curve (&self) -> DelinquencyCurve {
+        match self.fiat_price () {
+            Some ((fiat_thresholds, price)) => DelinquencyCurve {
+                max_debt: fiat_to_sub (fiat_thresholds.max_debt, price),
+                min_debt: fiat_to_sub (fiat_thresholds.min_debt, price),
+                ..self.config.delinquency_curve.clone ()
+            },
+            None => self.config.delinquency_curve.clone ()
+        }
+    }
+
+    fn fiat_price (&self) -> Option<(&FiatThresholds, f64)> {
+        let fiat_thresholds = match self.config.fiat_thresholds_opt {
+            Some (ref fiat_thresholds) => fiat_thresholds,
+            None => return None
+        };
+        self.fiat_price_opt.map (|price| (fiat_thresholds, price))
+    }
+
+    // The first price is taken as it is; after that, each one is taken only as far as
+    // MAX_PRICE_CHANGE_FACTOR from the last
+    fn update_fiat_price (&mut self, price: f64) {
+        let new_price = match self.fiat_price_opt {
+            None => price,
+            Some (old_price) => {
+                let bounded_price = price.max (old_price / MAX_PRICE_CHANGE_FACTOR).min (old_price * MAX_PRICE_CHANGE_FACTOR);
+                if bounded_price != price {
+                    self.logger.warning (format! ("Price oracle says SUB went from {} to {}; taking it only as far as {}", old_price, price, bounded_price));
+                }
+                bounded_price
+            }
+        };
+        self.logger.debug (format! ("Converting fiat thresholds at {} per SUB", new_price));
+        self.fiat_price_opt = Some (new_price);
+    }
+
+    // Payments are credited to the Node whose routes last named the wallet they came from
+    fn receive_payment (&mut self, msg: ReportPaymentReceivedMessage, now: SystemTime) {
+        let payer_key = match self.ledger.key_for_wallet (LedgerSide::Receivable, &msg.payer) {
+            Ok (Some (payer_key)) => payer_key,
+            Ok (None) => {
+                self.logger.warning (format! ("Received {} from {}, which no known Node pays from, in transaction {}", msg.amount, msg.payer, msg.transaction_hash));
+                return
+            },
+            Err (e) => {
+                self.logger.error (format! ("Couldn't find out who paid {} from {} in transaction {}: {}", msg.amount, msg.payer, msg.transaction_hash, e));
+                return
+            }
+        };
+        let payer = to_string (&payer_key.data);
+        let payment = PaymentRecord {
+            side: LedgerSide::Receivable,
+            public_key: payer_key,
+            amount: msg.amount,
+            transaction_hash: msg.transaction_hash,
+            timestamp: now,
+        };
+        if let Err (e) = self.ledger.record_payment (&payment) {
+            self.logger.error (format! ("Couldn't record payment of {} from Node {} in transaction {}: {}", payment.amount, payer, payment.transaction_hash, e));
+            return
+        }
+        self.logger.info (format! ("Node {} paid {} in transaction {}", payer, payment.amount, payment.transaction_hash));
+        self.restore_standing (&payment.public_key);
+    }
+
+    // Any payment ends throttling; a banned Node has to bring its debt back down to be unbanned
+    fn restore_standing (&mut self, payer_key: &Key) {
+        self.update_standing (payer_key);
+        if !self.delinquents.contains (payer_key) {return}
+        let payer = to_string (&payer_key.data);
+        let balance = match self.ledger.account (LedgerSide::Receivable, payer_key) {
+            Ok (account_opt) => account_opt.map (|account| account.balance).unwrap_or (0),
+            Err (e) => {
+                self.logger.error (format! ("Couldn't check what Node {} still owes: {}", payer, e));
+                return
+            }
+        };
+        if balance > self.config.delinquency_curve.min_debt {
+            self.logger.info (format! ("Node {} stays banned: it still owes {}", payer, balance));
+            return
+        }
+        self.delinquents.remove (payer_key);
+        self.to_neighborhood_unbans.as_ref ().expect ("Neighborhood unbound in Accountant").try_send (UnbanNodeMsg {
+            public_key: payer_key.clone (),
+        }).expect ("Neighborhood is dead");
+    }
+
+    fn neighbor_stats (&self, since: SystemTime) -> Result<Vec<NeighborStats>, String> {
+        let mut stats: HashMap<Key, NeighborStats> = HashMap::new ();
+        for (public_key, served) in self.ledger.service_since (LedgerSide::Receivable, since)? {
+            stats_entry (&mut stats, public_key).served = served;
+        }
+        for (public_key, consumed) in self.ledger.service_since (LedgerSide::Payable, since)? {
+            stats_entry (&mut stats, public_key).consumed = consumed;
+        }
+        for account in self.ledger.accounts (LedgerSide::Receivable)? {
+            if (account.balance != 0) || stats.contains_key (&account.public_key) {
+                stats_entry (&mut stats, account.public_key).receivable_balance = account.balance;
+            }
+        }
+        for account in self.ledger.accounts (LedgerSide::Payable)? {
+            if (account.balance != 0) || stats.contains_key (&account.public_key) {
+                stats_entry (&mut stats, account.public_key).payable_balance = account.balance;
+            }
+        }
+        let mut stats: Vec<NeighborStats> = stats.into_iter ().map (|(_, neighbor_stats)| neighbor_stats).collect ();
+        stats.sort_by (|a, b| b.served.amount.cmp (&a.served.amount).then_with (|| a.public_key.data.cmp (&b.public_key.data)));
+        Ok (stats)
+    }
+
+    fn node_stats (&self) -> NodeStats {
+        NodeStats {
+            uptime_ms: self.stats.uptime_ms + to_ms (self.uptime_counted_at.elapsed ()),
+            ..self.stats.clone ()
+        }
+    }
+
+    // Payments not yet confirmed, failed ones waiting to be tried again, and open channels are
+    // what's pending
+    fn financials (&self, windows_ms: &[u64], now: SystemTime) -> Result<Financials, String> {
+        let payables = self.ledger.accounts (LedgerSide::Payable)?;
+        let receivables = self.ledger.accounts (LedgerSide::Receivable)?;
+        let paid = self.ledger.payments (LedgerSide::Payable)?;
+        let received = self.ledger.payments (LedgerSide::Receivable)?;
+        let payment_totals = windows_ms.iter ().map (|window_ms| {
+            let since = window_start (now, Some (*window_ms));
+            PaymentTotals {window_ms: *window_ms, paid: total_since (&paid, since), received: total_since (&received, since)}
+        }).collect ();
+        let mut pending_transactions: Vec<PendingTransaction> = payables.iter ()
+            .filter (|account| account.balance > 0)
+            .filter_map (|account| self.payment_retries.get (&account.public_key).map (|retry| PendingTransaction::PaymentRetry {
+                payee: account.public_key.clone (),
+                amount: account.balance,
+                failures: retry.failures,
+            }))
+            .collect ();
+        for transaction in &self.in_flight {
+            if let TransactionRequest::Payment {ref payee_key, amount, ..} = transaction.request {
+                pending_transactions.push (PendingTransaction::PaymentUnconfirmed {
+                    payee: payee_key.clone (), amount, transaction_hash_opt: transaction.transaction_hash_opt.clone (),
+                });
+            }
+        }
+        for channel in self.ledger.open_channels (LedgerSide::Payable)? {
+            pending_transactions.push (PendingTransaction::ChannelOut {
+                channel_id: channel.channel_id, payee: channel.public_key, balance: channel.balance, deposit: channel.deposit,
+            });
+        }
+        for channel in self.ledger.open_channels (LedgerSide::Receivable)? {
+            pending_transactions.push (PendingTransaction::ChannelIn {
+                channel_id: channel.channel_id, payer: channel.public_key, balance: channel.balance, deposit: channel.deposit,
+            });
+        }
+        Ok (Financials {
+            unpaid_payables: unpaid (&payables),
+            unpaid_receivables: unpaid (&receivables),
+            payment_totals,
+            pending_transactions,
+        })
+    }
+
+    fn flush_charges (&mut self) {
+        if let Err (e) = self.ledger.flush () {
+            self.logger.error (format! ("Couldn't save charges for service: {}", e));
+        }
+    }
+
+    fn flush_stats (&mut self) {
+        self.stats = self.node_stats ();
+        self.uptime_counted_at = Instant::now ();
+        if let Err (e) = self.ledger.save_stats (&self.stats) {
+            self.logger.error (format! ("Couldn't save the Node's lifetime figures: {}", e));
+        }
+    }
+}
+
+fn stats_entry (stats: &mut HashMap<Key, NeighborStats>, public_key: Key) -> &mut NeighborStats {
+    stats.entry (public_key.clone ()).or_insert_with (|| NeighborStats {
+        public_key,
+        served: ServiceTotals::default (),
+        consumed: ServiceTotals::default (),
+        receivable_balance: 0,
+        payable_balance: 0,
+    })
+}
+
+fn unpaid (accounts: &[Account]) -> i64 {
+    accounts.iter ().map (|account| cmp::max (account.balance, 0)).sum ()
+}
+
+fn total_since (payments: &[PaymentRecord], since: SystemTime) -> i64 {
+    payments.iter ().filter (|payment| payment.timestamp >= since).map (|payment| payment.amount).sum ()
+}
+
+fn to_ms (duration: Duration) -> u64 {
+    duration.as_secs () * 1000 + (duration.subsec_nanos () / 1000000) as u64
+}
+
+// Channels are named by their payers, at random, before they're opened
+fn new_channel_id () -> String {
+    let bytes: [u8; 32] = random ();
+    format! ("0x{}", bytes.iter ().map (|byte| format! ("{:02x}", byte)).collect::<Vec<String>> ().concat ())
+}
+
+fn to_secs (timestamp: SystemTime) -> u64 {
+    match timestamp.duration_since (UNIX_EPOCH) {
+        Ok (d) => d.as_secs (),
+        Err (_) => 0
+    }
+}
+
+fn is_delinquent (account: &Account, delinquency_curve: &DelinquencyCurve, now: SystemTime) -> bool {
+    match now.duration_since (account.owed_since ()) {
+        Ok (age) => delinquency_curve.is_delinquent (account.balance, age),
+        Err (_) => false
+    }
+}
+
+// A window longer than the clock has been running covers everything
+fn window_start (now: SystemTime, window_ms_opt: Option<u64>) -> SystemTime {
+    let window = match window_ms_opt {
+        Some (window_ms) => Duration::from_millis (window_ms),
+        None => return UNIX_EPOCH
+    };
+    match now.duration_since (UNIX_EPOCH) {
+        Ok (age) if window < age => now - window,
+        _ => UNIX_EPOCH
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::env::temp_dir;
+    use std::fs;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::thread;
+    use actix::Arbiter;
+    use actix::msgs;
+    use actix::System;
+    use sub_lib::accountant::DelinquencyCurve;
+    use sub_lib::accountant::EXIT_BYTE_RATE;
+    use sub_lib::accountant::EXIT_SERVICE_RATE;
+    use sub_lib::accountant::ROUTING_BYTE_RATE;
+    use sub_lib::accountant::ROUTING_SERVICE_RATE;
+    use sub_lib::accountant::FreeTier;
+    use sub_lib::blockchain_bridge::ChannelTerms;
+    use sub_lib::blockchain_interface::BlockchainInterfaceNull;
+    use serde_cbor;
+    use sub_lib::cryptde_null::CryptDENull;
+    use ledger::LEDGER_FILENAME;
+    use ledger::LedgerReal;
+    use test_utils::test_utils::Recorder;
+    use test_utils::test_utils::TestLogHandler;
+    use test_utils::test_utils::cryptde;
+    use test_utils::test_utils::init_test_logging;
+
+    // Takes every request unless it's given failures to return first
+    struct BlockchainInterfaceMock {
+        requests: Arc<Mutex<Vec<TransactionRequest>>>,
+        results: RefCell<Vec<Result<(), String>>>,
+        channel_checks: Arc<Mutex<Vec<(String, Key)>>>,
+    }
+
+    impl BlockchainInterface for BlockchainInterfaceMock {
+        fn request (&self, request: TransactionRequest) -> Result<(), String> {
+            self.requests.lock ().unwrap ().push (request);
+            let mut results = self.results.borrow_mut ();
+            if results.is_empty () {Ok (())} else {results.remove (0)}
+        }
+
+        fn check_channel (&self, channel_id: &str, payer_key: &Key) -> Result<(), String> {
+            self.channel_checks.lock ().unwrap ().push ((String::from (channel_id), payer_key.clone ()));
+            Ok (())
+        }
+    }
+
+    impl BlockchainInterfaceMock {
+        fn new (results: Vec<Result<(), String>>) -> BlockchainInterfaceMock {
+            BlockchainInterfaceMock {
+                requests: Arc::new (Mutex::new (vec! ())),
+                results: RefCell::new (results),
+                channel_checks: Arc::new (Mutex::new (vec! ())),
+            }
+        }
+    }
+
+    fn payment (payee_key: &Key, payee: Wallet, amount: i64) -> TransactionRequest {
+        TransactionRequest::Payment {payee_key: payee_key.clone (), payee, amount}
+    }
+
+    // As the BlockchainBridge would report it
+    fn report (subject: &mut Accountant, request: &TransactionRequest, transaction_hash: &str, status: TransactionStatus, seconds: u64) {
+        subject.receive_transaction_report (ReportTransactionMessage {
+            request: request.clone (),
+            transaction_hash_opt: Some (String::from (transaction_hash)),
+            status,
+        }, at (seconds));
+    }
+
+    fn at (seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs (seconds)
+    }
+
+    fn make_config () -> AccountantConfig {
+        AccountantConfig {
+            payable_scan_interval_ms: 0,
+            payment_threshold: 1000,
+            payment_age_threshold_ms: 10000,
+            payment_retry_ms: 1000,
+            receivable_scan_interval_ms: 0,
+            delinquency_curve: DelinquencyCurve {grace_period_ms: 10000, max_debt: 5000, decline_period_ms: 10000, min_debt: 1000},
+            free_tier: FreeTier {bytes: 0, period_ms: 0, throttle_debt: 100000, refusal_debt: 200000},
+            debt_ceiling: 1000000,
+            channel_deposit: 0,
+            channel_min_payments: 1,
+            channel_lifetime_ms: 1000000,
+            invoice_interval_ms: 0,
+            rates: DEFAULT_RATES,
+            price_oracle_url_opt: None,
+            fiat_thresholds_opt: None,
+        }
+    }
+
+    fn neighbor_ip () -> IpAddr {
+        IpAddr::from_str ("1.2.3.4").unwrap ()
+    }
+
+    fn wallet (digit: &str) -> Wallet {
+        Wallet::new (&digit.repeat (40)).unwrap ()
+    }
+
+    fn make_paying_subject (results: Vec<Result<(), String>>) -> (Accountant, Arc<Mutex<Vec<TransactionRequest>>>) {
+        let blockchain_interface = BlockchainInterfaceMock::new (results);
+        let requests = blockchain_interface.requests.clone ();
+        let subject = Accountant::new (cryptde (), make_config (), Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (blockchain_interface));
+        (subject, requests)
+    }
+
+    fn owe (subject: &mut Accountant, payee_key: &Key, amount: i64, seconds: u64) {
+        subject.ledger.charge (LedgerSide::Payable, payee_key, &Charge {bytes_routed: 0, bytes_exited: 0, amount, timestamp: at (seconds)}).unwrap ();
+    }
+
+    #[test]
+    fn charges_routing_and_exit_service_to_each_consuming_node () {
+        let mut subject = Accountant::new (cryptde (), make_config (), Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (BlockchainInterfaceNull::new ()));
+        let alice = Key::new (b"alice");
+        let bob = Key::new (b"bob");
+
+        subject.record_service (&alice, neighbor_ip (), Charge {bytes_routed: 1000, bytes_exited: 0, amount: 1100, timestamp: at (10)});
+        subject.record_service (&bob, neighbor_ip (), Charge {bytes_routed: 0, bytes_exited: 300, amount: 800, timestamp: at (20)});
+        subject.record_service (&alice, neighbor_ip (), Charge {bytes_routed: 0, bytes_exited: 400, amount: 1000, timestamp: at (30)});
+
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &alice).unwrap (), Some (Account {
+            public_key: alice.clone (),
+            bytes_routed: 1000,
+            bytes_exited: 400,
+            balance: 2100,
+            first_service_timestamp: at (10),
+            last_service_timestamp: at (30),
+            last_settled_timestamp_opt: None,
+        }));
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &bob).unwrap ().unwrap ().balance, 800);
+        assert_eq! (subject.ledger.accounts (LedgerSide::Payable).unwrap (), vec! ());
+    }
+
+    #[test]
+    fn pays_debts_that_are_big_enough_or_old_enough_largest_first_and_counts_them_paid_once_confirmed () {
+        let (mut subject, requests) = make_paying_subject (vec! ());
+        let alice = Key::new (b"alice");
+        let bob = Key::new (b"bob");
+        let carol = Key::new (b"carol");
+        owe (&mut subject, &bob, 500, 80);
+        owe (&mut subject, &alice, 1500, 99);
+        owe (&mut subject, &carol, 700, 95);
+        subject.ledger.set_wallet (LedgerSide::Payable, &alice, &wallet ("a")).unwrap ();
+        subject.ledger.set_wallet (LedgerSide::Payable, &bob, &wallet ("b")).unwrap ();
+        subject.ledger.set_wallet (LedgerSide::Payable, &carol, &wallet ("c")).unwrap ();
+
+        subject.scan_payables (at (100));
+        let payments_before_confirmation = subject.ledger.payments (LedgerSide::Payable).unwrap ();
+        report (&mut subject, &payment (&alice, wallet ("a"), 1500), "0xA1", TransactionStatus::Submitted, 101);
+        subject.scan_payables (at (102));
+        report (&mut subject, &payment (&alice, wallet ("a"), 1500), "0xA1", TransactionStatus::Confirmed, 110);
+        report (&mut subject, &payment (&bob, wallet ("b"), 500), "0xB0", TransactionStatus::Confirmed, 111);
+
+        assert_eq! (*requests.lock ().unwrap (), vec! (payment (&alice, wallet ("a"), 1500), payment (&bob, wallet ("b"), 500)));
+        assert_eq! (payments_before_confirmation, vec! ());
+        assert_eq! (subject.ledger.payments (LedgerSide::Payable).unwrap (), vec! (
+            PaymentRecord {side: LedgerSide::Payable, public_key: alice.clone (), amount: 1500, transaction_hash: String::from ("0xA1"), timestamp: at (110)},
+            PaymentRecord {side: LedgerSide::Payable, public_key: bob.clone (), amount: 500, transaction_hash: String::from ("0xB0"), timestamp: at (111)},
+        ));
+        assert_eq! (subject.ledger.account (LedgerSide::Payable, &alice).unwrap ().unwrap ().balance, 0);
+        assert_eq! (subject.ledger.account (LedgerSide::Payable, &carol).unwrap ().unwrap ().balance, 700);
+        assert_eq! (subject.in_flight.is_empty (), true);
+    }
+
+    #[test]
+    fn thresholds_in_fiat_are_converted_at_the_going_price_or_left_to_the_sub_ones_without_one () {
+        let blockchain_interface = BlockchainInterfaceMock::new (vec! ());
+        let requests = blockchain_interface.requests.clone ();
+        let config = AccountantConfig {
+            price_oracle_url_opt: Some (String::from ("https://prices.example.com#usd")),
+            fiat_thresholds_opt: Some (FiatThresholds {payment_threshold: 500, max_debt: 1000, min_debt: 200}),
+            ..make_config ()
+        };
+        let mut subject = Accountant::new (cryptde (), config, Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (blockchain_interface));
+        let payment_threshold_before_price = subject.payment_threshold ();
+        let delinquency_curve_before_price = subject.delinquency_curve ();
+        subject.update_fiat_price (0.25);
+        let alice = Key::new (b"alice");
+        let bob = Key::new (b"bob");
+        owe (&mut subject, &alice, 1500, 99);
+        owe (&mut subject, &bob, 2500, 99);
+        subject.ledger.set_wallet (LedgerSide::Payable, &alice, &wallet ("a")).unwrap ();
+        subject.ledger.set_wallet (LedgerSide::Payable, &bob, &wallet ("b")).unwrap ();
+
+        subject.scan_payables (at (100));
+
+        assert_eq! (*requests.lock ().unwrap (), vec! (payment (&bob, wallet ("b"), 2500)));
+        assert_eq! (subject.delinquency_curve (), DelinquencyCurve {max_debt: 4000, min_debt: 800, ..make_config ().delinquency_curve});
+        assert_eq! (payment_threshold_before_price, make_config ().payment_threshold);
+        assert_eq! (delinquency_curve_before_price, make_config ().delinquency_curve);
+    }
+
+    #[test]
+    fn one_price_can_move_the_going_price_only_so_far () {
+        init_test_logging ();
+        let mut subject = Accountant::new (cryptde (), make_config (), Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (BlockchainInterfaceNull::new ()));
+
+        subject.update_fiat_price (0.25);
+        let first_price = subject.fiat_price_opt;
+        subject.update_fiat_price (4.0);
+        let raised_price = subject.fiat_price_opt;
+        subject.update_fiat_price (0.01);
+        let lowered_price = subject.fiat_price_opt;
+        subject.update_fiat_price (0.3);
+
+        assert_eq! (first_price, Some (0.25));
+        assert_eq! (raised_price, Some (0.3125));
+        assert_eq! (lowered_price, Some (0.25));
+        assert_eq! (subject.fiat_price_opt, Some (0.3));
+        TestLogHandler::new ().exists_log_containing ("Price oracle says SUB went from 0.25 to 4; taking it only as far as 0.3125");
+    }
+
+    #[test]
+    fn failed_payments_are_retried_after_a_doubling_delay () {
+        let (mut subject, requests) = make_paying_subject (vec! (Err (String::from ("BlockchainBridge isn't taking requests"))));
+        let alice = Key::new (b"alice");
+        owe (&mut subject, &alice, 1500, 99);
+        subject.ledger.set_wallet (LedgerSide::Payable, &alice, &wallet ("a")).unwrap ();
+        let request = payment (&alice, wallet ("a"), 1500);
+
+        subject.scan_payables (at (100));
+        subject.scan_payables (at (100) + Duration::from_millis (999));
+        subject.scan_payables (at (101));
+        report (&mut subject, &request, "0xA0", TransactionStatus::Failed (String::from ("Reverted")), 101);
+        subject.scan_payables (at (102));
+        subject.scan_payables (at (103));
+        report (&mut subject, &request, "0xA1", TransactionStatus::Confirmed, 104);
+        subject.scan_payables (at (105));
+
+        assert_eq! (requests.lock ().unwrap ().len (), 3);
+        assert_eq! (subject.ledger.payments (LedgerSide::Payable).unwrap (), vec! (
+            PaymentRecord {side: LedgerSide::Payable, public_key: alice.clone (), amount: 1500, transaction_hash: String::from ("0xA1"), timestamp: at (104)},
+        ));
+        assert_eq! (subject.payment_retries.is_empty (), true);
+    }
+
+    #[test]
+    fn relays_that_refuse_this_node_are_paid_what_they_are_owed_at_the_next_scan () {
+        let (mut subject, requests) = make_paying_subject (vec! ());
+        let alice = Key::new (b"alice");
+        let bob = Key::new (b"bob");
+        owe (&mut subject, &alice, 500, 99);
+        owe (&mut subject, &bob, 600, 99);
+        subject.ledger.set_wallet (LedgerSide::Payable, &alice, &wallet ("a")).unwrap ();
+        subject.ledger.set_wallet (LedgerSide::Payable, &bob, &wallet ("b")).unwrap ();
+
+        subject.scan_payables (at (100));
+        let requests_before_refusal = requests.lock ().unwrap ().len ();
+        subject.receive_refusal (ServiceRefusal {refusing_key: alice.clone (), refused_key: cryptde ().public_key ()});
+        subject.receive_refusal (ServiceRefusal {refusing_key: bob.clone (), refused_key: Key::new (b"carol")});
+        subject.scan_payables (at (101));
+
+        assert_eq! (requests_before_refusal, 0);
+        assert_eq! (*requests.lock ().unwrap (), vec! (payment (&alice, wallet ("a"), 500)));
+        assert_eq! (subject.refusing_payees.is_empty (), true);
+    }
+
+    #[test]
+    fn debts_to_nodes_without_earning_wallets_wait_until_they_have_one () {
+        let (mut subject, requests) = make_paying_subject (vec! ());
+        let alice = Key::new (b"alice");
+        owe (&mut subject, &alice, 1500, 99);
+
+        subject.scan_payables (at (100));
+        subject.ledger.set_wallet (LedgerSide::Payable, &alice, &wallet ("a")).unwrap ();
+        subject.scan_payables (at (101));
+
+        assert_eq! (*requests.lock ().unwrap (), vec! (payment (&alice, wallet ("a"), 1500)));
+    }
+
+    #[test]
+    fn frequent_payees_are_paid_through_a_channel_while_it_has_room () {
+        let system = System::new ("frequent_payees_are_paid_through_a_channel_while_it_has_room");
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_addr: Addr<Syn, Recorder> = hopper.start ();
+        let blockchain_interface = BlockchainInterfaceMock::new (vec! ());
+        let requests = blockchain_interface.requests.clone ();
+        let config = AccountantConfig {channel_deposit: 5000, ..make_config ()};
+        let mut subject = Accountant::new (cryptde (), config, Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (blockchain_interface));
+        subject.to_hopper = Some (hopper_addr.recipient::<IncipientCoresPackage> ());
+        let alice = Key::new (b"alice");
+        subject.ledger.set_wallet (LedgerSide::Payable, &alice, &wallet ("a")).unwrap ();
+
+        owe (&mut subject, &alice, 1500, 99);
+        subject.scan_payables (at (100));
+        report (&mut subject, &payment (&alice, wallet ("a"), 1500), "0xA1", TransactionStatus::Confirmed, 100);
+        owe (&mut subject, &alice, 1200, 100);
+        subject.scan_payables (at (200));
+        let open_channel = requests.lock ().unwrap ()[1].clone ();
+        subject.scan_payables (at (210));
+        report (&mut subject, &open_channel, "0xB1", TransactionStatus::Confirmed, 220);
+        subject.scan_payables (at (230));
+        owe (&mut subject, &alice, 2000, 230);
+        subject.scan_payables (at (300));
+        owe (&mut subject, &alice, 2000, 300);
+        subject.scan_payables (at (400));
+        report (&mut subject, &payment (&alice, wallet ("a"), 2000), "0xA2", TransactionStatus::Confirmed, 400);
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let channel_id = match open_channel {
+            TransactionRequest::OpenChannel {ref channel_id, ref payer_key, ref payee_key, ref payee, deposit, expires} => {
+                assert_eq! ((payer_key, payee_key, payee, deposit, expires), (&cryptde ().public_key (), &alice, &wallet ("a"), 5000, 1200));
+                channel_id.clone ()
+            },
+            ref request => panic! ("Expected a channel to be opened, not {:?}", request),
+        };
+        assert_eq! (*requests.lock ().unwrap (), vec! (payment (&alice, wallet ("a"), 1500), open_channel.clone (), payment (&alice, wallet ("a"), 2000)));
+        assert_eq! (subject.ledger.payments (LedgerSide::Payable).unwrap ().into_iter ().map (|payment| (payment.amount, payment.transaction_hash)).collect::<Vec<(i64, String)>> (), vec! (
+            (1500, String::from ("0xA1")),
+            (1200, format! ("{}#1", channel_id)),
+            (2000, format! ("{}#2", channel_id)),
+            (2000, String::from ("0xA2")),
+        ));
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        assert_eq! (hopper_recording.len (), 2);
+        let package = hopper_recording.get_record::<IncipientCoresPackage> (1);
+        assert_eq! (package.payload_destination_key, alice);
+        let update = serde_cbor::de::from_slice::<BalanceUpdate> (&package.payload.data[..]).unwrap ();
+        assert_eq! ((&update.channel_id, update.deposit, update.balance, update.sequence), (&channel_id, 5000, 3200, 2));
+        assert_eq! (update.verify (cryptde ()), true);
+        assert_eq! (subject.ledger.open_channel (LedgerSide::Payable, &alice).unwrap ().unwrap ().signature, update.signature.data);
+    }
+
+    #[test]
+    fn balance_updates_are_credited_once_and_spent_channels_are_closed () {
+        let blockchain_interface = BlockchainInterfaceMock::new (vec! ());
+        let requests = blockchain_interface.requests.clone ();
+        let channel_checks = blockchain_interface.channel_checks.clone ();
+        let mut subject = Accountant::new (cryptde (), make_config (), Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (blockchain_interface));
+        let mut payer_cryptde = CryptDENull::new ();
+        payer_cryptde.generate_key_pair ();
+        let payer = payer_cryptde.public_key ();
+        subject.record_service (&payer, neighbor_ip (), Charge {bytes_routed: 2900, bytes_exited: 0, amount: 3000, timestamp: at (10)});
+        let channel = PaymentChannel {
+            channel_id: String::from ("0xC1"),
+            side: LedgerSide::Payable,
+            public_key: cryptde ().public_key (),
+            deposit: 3000,
+            balance: 1000,
+            sequence: 1,
+            signature: vec! (),
+            expires_timestamp: at (1000),
+            open: true,
+        };
+        let first = BalanceUpdate::signed (&channel, &payer_cryptde).unwrap ();
+        let second = BalanceUpdate::signed (&PaymentChannel {balance: 3000, sequence: 2, ..channel.clone ()}, &payer_cryptde).unwrap ();
+        let forged = BalanceUpdate {balance: 2000, sequence: 3, ..first.clone ()};
+
+        subject.receive_balance_update (first.clone (), at (20));
+        let credited_before_the_check = subject.ledger.payments (LedgerSide::Receivable).unwrap ().len ();
+        subject.receive_channel_report (ReportChannelMessage {channel_id: String::from ("0xC1"), result: Ok (ChannelTerms {deposit: 3000, expires: 1000})}, at (25));
+        subject.receive_balance_update (second.clone (), at (30));
+        subject.receive_balance_update (first, at (40));
+        subject.receive_balance_update (forged, at (50));
+        subject.scan_receivables (at (60));
+        subject.scan_receivables (at (70));
+        let open_after_requesting_close = subject.ledger.channel ("0xC1").unwrap ().unwrap ().open;
+        let close_channel = TransactionRequest::CloseChannel {channel_id: String::from ("0xC1"), balance: 3000, signature: second.signature.data.clone ()};
+        report (&mut subject, &close_channel, "0xD1", TransactionStatus::Confirmed, 80);
+
+        assert_eq! (subject.ledger.payments (LedgerSide::Receivable).unwrap ().into_iter ().map (|payment| (payment.amount, payment.transaction_hash)).collect::<Vec<(i64, String)>> (), vec! (
+            (1000, String::from ("0xC1#1")),
+            (2000, String::from ("0xC1#2")),
+        ));
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &payer).unwrap ().unwrap ().balance, 0);
+        assert_eq! (*channel_checks.lock ().unwrap (), vec! ((String::from ("0xC1"), payer.clone ())));
+        assert_eq! (credited_before_the_check, 0);
+        assert_eq! (*requests.lock ().unwrap (), vec! (close_channel));
+        assert_eq! (open_after_requesting_close, true);
+        assert_eq! (subject.ledger.channel ("0xC1").unwrap ().unwrap ().open, false);
+    }
+
+    #[test]
+    fn balance_updates_for_channels_that_don_t_check_out_on_the_blockchain_are_never_credited () {
+        let mut subject = Accountant::new (cryptde (), make_config (), Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (BlockchainInterfaceMock::new (vec! ())));
+        let mut payer_cryptde = CryptDENull::new ();
+        payer_cryptde.generate_key_pair ();
+        let payer = payer_cryptde.public_key ();
+        let mut claimant_cryptde = CryptDENull::new ();
+        claimant_cryptde.generate_key_pair ();
+        subject.record_service (&payer, neighbor_ip (), Charge {bytes_routed: 2900, bytes_exited: 0, amount: 3000, timestamp: at (10)});
+        let channel = PaymentChannel {
+            channel_id: String::from ("0xC1"),
+            side: LedgerSide::Payable,
+            public_key: cryptde ().public_key (),
+            deposit: 3000,
+            balance: 3000,
+            sequence: 1,
+            signature: vec! (),
+            expires_timestamp: at (1000),
+            open: true,
+        };
+        let made_up = BalanceUpdate::signed (&channel, &payer_cryptde).unwrap ();
+        let underfunded = BalanceUpdate::signed (&PaymentChannel {channel_id: String::from ("0xC2"), ..channel.clone ()}, &payer_cryptde).unwrap ();
+        let claimed = BalanceUpdate::signed (&PaymentChannel {channel_id: String::from ("0xC2"), sequence: 2, ..channel.clone ()}, &claimant_cryptde).unwrap ();
+
+        subject.receive_balance_update (made_up, at (20));
+        subject.receive_balance_update (underfunded, at (20));
+        subject.receive_balance_update (claimed, at (20));
+        subject.receive_channel_report (ReportChannelMessage {channel_id: String::from ("0xC1"), result: Err (String::from ("it isn't on the blockchain"))}, at (30));
+        subject.receive_channel_report (ReportChannelMessage {channel_id: String::from ("0xC2"), result: Ok (ChannelTerms {deposit: 1000, expires: 1000})}, at (30));
+
+        assert_eq! (subject.ledger.payments (LedgerSide::Receivable).unwrap (), vec! ());
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &payer).unwrap ().unwrap ().balance, 3000);
+        assert_eq! (subject.ledger.channel ("0xC1").unwrap (), None);
+        assert_eq! (subject.ledger.channel ("0xC2").unwrap (), None);
+        assert_eq! (subject.unchecked_updates.is_empty (), true);
+    }
+
+    #[test]
+    fn balance_updates_for_unseen_channels_are_ignored_without_a_blockchain () {
+        let mut subject = Accountant::new (cryptde (), make_config (), Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (BlockchainInterfaceNull::new ()));
+        let mut payer_cryptde = CryptDENull::new ();
+        payer_cryptde.generate_key_pair ();
+        let channel = PaymentChannel {
+            channel_id: String::from ("0xC1"),
+            side: LedgerSide::Payable,
+            public_key: cryptde ().public_key (),
+            deposit: 3000,
+            balance: 3000,
+            sequence: 1,
+            signature: vec! (),
+            expires_timestamp: at (1000),
+            open: true,
+        };
+
+        subject.receive_balance_update (BalanceUpdate::signed (&channel, &payer_cryptde).unwrap (), at (20));
+
+        assert_eq! (subject.unchecked_updates.is_empty (), true);
+        assert_eq! (subject.ledger.payments (LedgerSide::Receivable).unwrap (), vec! ());
+    }
+
+    #[test]
+    fn nodes_charged_for_service_are_invoiced_for_it_and_receipts_for_their_invoices_kept () {
+        let system = System::new ("nodes_charged_for_service_are_invoiced_for_it_and_receipts_for_their_invoices_kept");
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_addr: Addr<Syn, Recorder> = hopper.start ();
+        let mut subject = Accountant::new (cryptde (), make_config (), Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (BlockchainInterfaceNull::new ()));
+        subject.to_hopper = Some (hopper_addr.recipient::<IncipientCoresPackage> ());
+        let mut consumer_cryptde = CryptDENull::new ();
+        consumer_cryptde.generate_key_pair ();
+        let consumer = consumer_cryptde.public_key ();
+
+        subject.serve_routing (ReportRoutingServiceMessage {consuming_node_key: consumer.clone (), neighbor_ip: neighbor_ip (), payload_size: 1000}, at (10));
+        subject.serve_exit (ReportExitServiceMessage {consuming_node_key: consumer.clone (), neighbor_ip: neighbor_ip (), payload_size: 300}, at (20));
+        subject.send_invoices (at (30));
+        subject.serve_routing (ReportRoutingServiceMessage {consuming_node_key: consumer.clone (), neighbor_ip: neighbor_ip (), payload_size: 500}, at (40));
+        subject.send_invoices (at (50));
+        subject.send_invoices (at (60));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        assert_eq! (hopper_recording.len (), 2);
+        let invoices: Vec<Invoice> = (0..2).map (|index| {
+            let package = hopper_recording.get_record::<IncipientCoresPackage> (index);
+            assert_eq! (package.payload_destination_key, consumer);
+            serde_cbor::de::from_slice::<Invoice> (&package.payload.data[..]).unwrap ()
+        }).collect ();
+        assert_eq! ((invoices[0].number, invoices[0].packages_routed, invoices[0].requests_exited, invoices[0].amount, invoices[0].period_start, invoices[0].period_end),
+            (1, 1, 1, ROUTING_SERVICE_RATE + 1000 * ROUTING_BYTE_RATE + EXIT_SERVICE_RATE + 300 * EXIT_BYTE_RATE, 10, 30));
+        assert_eq! ((invoices[1].number, invoices[1].packages_routed, invoices[1].requests_exited, invoices[1].amount, invoices[1].period_start, invoices[1].period_end),
+            (2, 1, 0, ROUTING_SERVICE_RATE + 500 * ROUTING_BYTE_RATE, 40, 50));
+        assert_eq! (invoices[0].verify (cryptde ()), true);
+        let receipt = Receipt::countersigned (invoices[0].clone (), &consumer_cryptde).unwrap ();
+        let padded = Receipt::countersigned (Invoice {amount: invoices[1].amount + 1, ..invoices[1].clone ()}, &consumer_cryptde).unwrap ();
+        subject.receive_receipt (receipt.clone ());
+        subject.receive_receipt (padded);
+        assert_eq! (subject.ledger.invoice (LedgerSide::Receivable, &consumer, 1).unwrap (), Some (InvoiceRecord {
+            side: LedgerSide::Receivable,
+            invoice: invoices[0].clone (),
+            countersignature_opt: Some (receipt.countersignature),
+        }));
+        assert_eq! (subject.ledger.invoice (LedgerSide::Receivable, &consumer, 2).unwrap ().unwrap ().countersignature_opt, None);
+        assert_eq! (subject.ledger.countersigned_since (LedgerSide::Receivable, &consumer, at (0)).unwrap (), invoices[0].amount);
+    }
+
+    #[test]
+    fn invoices_at_the_advertised_rates_are_countersigned_and_the_rest_disputed () {
+        let system = System::new ("invoices_at_the_advertised_rates_are_countersigned_and_the_rest_disputed");
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_addr: Addr<Syn, Recorder> = hopper.start ();
+        let mut subject = Accountant::new (cryptde (), make_config (), Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (BlockchainInterfaceNull::new ()));
+        subject.to_hopper = Some (hopper_addr.recipient::<IncipientCoresPackage> ());
+        let mut server_cryptde = CryptDENull::new ();
+        server_cryptde.generate_key_pair ();
+        let rates = RateSchedule {routing_byte_rate: 3, ..DEFAULT_RATES};
+        subject.advertised_rates.insert (server_cryptde.public_key (), rates);
+        let mut tally = Invoice::new (&cryptde ().public_key (), rates, 10);
+        tally.add_routing (1000);
+        let invoice = tally.clone ().signed (1, 20, &server_cryptde).unwrap ();
+        let overpriced = Invoice {amount: tally.amount + 1, ..tally.clone ()}.signed (2, 30, &server_cryptde).unwrap ();
+        let misquoted = Invoice {rates: DEFAULT_RATES, amount: DEFAULT_RATES.routing_charge (1000), ..tally.clone ()}.signed (3, 30, &server_cryptde).unwrap ();
+        let someone_elses = Invoice::new (&Key::new (b"someone else"), rates, 10).signed (4, 30, &server_cryptde).unwrap ();
+        let forged = Invoice {amount: 1, ..invoice.clone ()};
+
+        subject.receive_invoice (invoice.clone ());
+        subject.receive_invoice (invoice.clone ());
+        subject.receive_invoice (overpriced);
+        subject.receive_invoice (misquoted);
+        subject.receive_invoice (someone_elses);
+        subject.receive_invoice (forged);
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        assert_eq! (hopper_recording.len (), 1);
+        let package = hopper_recording.get_record::<IncipientCoresPackage> (0);
+        assert_eq! (package.payload_destination_key, server_cryptde.public_key ());
+        let receipt = serde_cbor::de::from_slice::<Receipt> (&package.payload.data[..]).unwrap ();
+        assert_eq! (receipt.invoice, invoice);
+        assert_eq! (receipt.verify (&server_cryptde), true);
+        assert_eq! (subject.ledger.last_invoice (LedgerSide::Payable, &server_cryptde.public_key ()).unwrap (), Some (InvoiceRecord {
+            side: LedgerSide::Payable,
+            invoice,
+            countersignature_opt: Some (receipt.countersignature.clone ()),
+        }));
+    }
+
+    #[test]
+    fn payments_from_consuming_wallets_are_credited_to_their_nodes () {
+        let mut subject = Accountant::new (cryptde (), make_config (), Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (BlockchainInterfaceNull::new ()));
+        let alice = Key::new (b"alice");
+        subject.record_service (&alice, neighbor_ip (), Charge {bytes_routed: 1000, bytes_exited: 0, amount: 1100, timestamp: at (10)});
+        subject.record_consuming_wallet (&alice, &wallet ("a"));
+        subject.record_consuming_wallet (&alice, &wallet ("a"));
+
+        subject.receive_payment (ReportPaymentReceivedMessage {payer: wallet ("a"), amount: 1000, transaction_hash: String::from ("0xA1")}, at (20));
+        subject.receive_payment (ReportPaymentReceivedMessage {payer: wallet ("f"), amount: 50, transaction_hash: String::from ("0xF1")}, at (30));
+
+        assert_eq! (subject.ledger.wallet (LedgerSide::Receivable, &alice).unwrap (), Some (wallet ("a")));
+        assert_eq! (subject.ledger.payments (LedgerSide::Receivable).unwrap (), vec! (
+            PaymentRecord {side: LedgerSide::Receivable, public_key: alice.clone (), amount: 1000, transaction_hash: String::from ("0xA1"), timestamp: at (20)},
+        ));
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &alice).unwrap ().unwrap ().balance, 100);
+    }
+
+    #[test]
+    fn a_node_claiming_another_node_s_consuming_wallet_is_not_credited_with_its_payments () {
+        init_test_logging ();
+        let mut subject = Accountant::new (cryptde (), make_config (), Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (BlockchainInterfaceNull::new ()));
+        let victim = Key::new (b"victim");
+        let debtor = Key::new (b"debtor");
+        subject.record_service (&victim, neighbor_ip (), Charge {bytes_routed: 1000, bytes_exited: 0, amount: 1100, timestamp: at (10)});
+        subject.record_service (&debtor, neighbor_ip (), Charge {bytes_routed: 1000, bytes_exited: 0, amount: 1100, timestamp: at (10)});
+        subject.record_consuming_wallet (&victim, &wallet ("b"));
+
+        subject.record_consuming_wallet (&debtor, &wallet ("b"));
+        subject.receive_payment (ReportPaymentReceivedMessage {payer: wallet ("b"), amount: 1000, transaction_hash: String::from ("0xB1")}, at (20));
+
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &victim).unwrap ().unwrap ().balance, 100);
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &debtor).unwrap ().unwrap ().balance, 1100);
+        assert_eq! (subject.ledger.wallet (LedgerSide::Receivable, &debtor).unwrap (), None);
+        TestLogHandler::new ().exists_log_containing (&format! ("Couldn't record that Node {} pays from {}: {} already belongs to another Node",
+            to_string (&debtor.data), wallet ("b"), wallet ("b")));
+    }
+
+    #[test]
+    fn the_free_tier_is_not_charged_and_unpaid_debt_past_it_is_throttled_then_refused_until_paid () {
+        let system = System::new ("the_free_tier_is_not_charged_and_unpaid_debt_past_it_is_throttled_then_refused_until_paid");
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_addr: Addr<Syn, Recorder> = hopper.start ();
+        let config = AccountantConfig {
+            free_tier: FreeTier {bytes: 2000, period_ms: 10000, throttle_debt: 1000, refusal_debt: 3000},
+            ..make_config ()
+        };
+        let mut subject = Accountant::new (cryptde (), config, Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (BlockchainInterfaceNull::new ()));
+        subject.to_hopper_standings = Some (hopper_addr.recipient::<ServiceStandingMessage> ());
+        let newcomer = Key::new (b"newcomer");
+        let latecomer = Key::new (b"latecomer");
+        subject.record_service (&newcomer, neighbor_ip (), Charge {bytes_routed: 1000, bytes_exited: 0, amount: 1100, timestamp: at (10)});
+        subject.record_service (&newcomer, neighbor_ip (), Charge {bytes_routed: 0, bytes_exited: 900, amount: 2000, timestamp: at (11)});
+        subject.record_service (&latecomer, neighbor_ip (), Charge {bytes_routed: 10, bytes_exited: 0, amount: 110, timestamp: at (10)});
+        subject.record_service (&latecomer, neighbor_ip (), Charge {bytes_routed: 10, bytes_exited: 0, amount: 110, timestamp: at (20)});
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &newcomer).unwrap (), None);
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &latecomer).unwrap ().unwrap ().balance, 110);
+
+        subject.record_service (&newcomer, neighbor_ip (), Charge {bytes_routed: 200, bytes_exited: 0, amount: 300, timestamp: at (12)});
+        subject.record_service (&newcomer, neighbor_ip (), Charge {bytes_routed: 1000, bytes_exited: 0, amount: 1100, timestamp: at (13)});
+        subject.record_service (&newcomer, neighbor_ip (), Charge {bytes_routed: 500, bytes_exited: 0, amount: 600, timestamp: at (14)});
+        subject.record_service (&newcomer, neighbor_ip (), Charge {bytes_routed: 1500, bytes_exited: 0, amount: 1600, timestamp: at (15)});
+        subject.record_consuming_wallet (&newcomer, &wallet ("n"));
+        subject.receive_payment (ReportPaymentReceivedMessage {payer: wallet ("n"), amount: 100, transaction_hash: String::from ("0xN1")}, at (16));
+        subject.record_service (&newcomer, neighbor_ip (), Charge {bytes_routed: 5000, bytes_exited: 0, amount: 5100, timestamp: at (17)});
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        assert_eq! (hopper_recording.get_record::<ServiceStandingMessage> (0), &ServiceStandingMessage {consuming_node_key: newcomer.clone (), standing: ServiceStanding::Throttled});
+        assert_eq! (hopper_recording.get_record::<ServiceStandingMessage> (1), &ServiceStandingMessage {consuming_node_key: newcomer.clone (), standing: ServiceStanding::Refused});
+        assert_eq! (hopper_recording.get_record::<ServiceStandingMessage> (2), &ServiceStandingMessage {consuming_node_key: newcomer.clone (), standing: ServiceStanding::Good});
+        assert_eq! (hopper_recording.len (), 3);
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &newcomer).unwrap ().unwrap ().balance, 8600);
+        assert_eq! (subject.standings.is_empty (), true);
+    }
+
+    #[test]
+    fn newcomers_through_the_same_neighbor_share_its_free_tier_each_period () {
+        let config = AccountantConfig {
+            free_tier: FreeTier {bytes: 2000, period_ms: 10000, throttle_debt: 100000, refusal_debt: 200000},
+            ..make_config ()
+        };
+        let mut subject = Accountant::new (cryptde (), config, Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (BlockchainInterfaceNull::new ()));
+        let busy_ip = IpAddr::from_str ("1.1.1.1").unwrap ();
+        let quiet_ip = IpAddr::from_str ("2.2.2.2").unwrap ();
+        let alice = Key::new (b"alice");
+        let bob = Key::new (b"bob");
+        let carol = Key::new (b"carol");
+        let dave = Key::new (b"dave");
+
+        subject.record_service (&alice, busy_ip, Charge {bytes_routed: 1500, bytes_exited: 0, amount: 1600, timestamp: at (10)});
+        subject.record_service (&bob, busy_ip, Charge {bytes_routed: 1000, bytes_exited: 0, amount: 1100, timestamp: at (11)});
+        subject.record_service (&carol, quiet_ip, Charge {bytes_routed: 1000, bytes_exited: 0, amount: 1100, timestamp: at (11)});
+        subject.record_service (&bob, busy_ip, Charge {bytes_routed: 100, bytes_exited: 0, amount: 200, timestamp: at (25)});
+        subject.record_service (&dave, busy_ip, Charge {bytes_routed: 500, bytes_exited: 0, amount: 600, timestamp: at (25)});
+
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &alice).unwrap (), None);
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &bob).unwrap ().unwrap ().balance, 1300);
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &carol).unwrap (), None);
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &dave).unwrap (), None);
+    }
+
+    #[test]
+    fn a_full_free_tier_forgets_the_nodes_that_have_used_theirs_up_and_otherwise_charges_newcomers () {
+        let config = AccountantConfig {
+            free_tier: FreeTier {bytes: 2000, period_ms: 10000, throttle_debt: 100000, refusal_debt: 200000},
+            ..make_config ()
+        };
+        let mut subject = Accountant::new (cryptde (), config, Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (BlockchainInterfaceNull::new ()));
+        for index in 0..(MAX_FREE_TIER_ENTRIES - 1) {
+            subject.free_tier_usage.insert (Key::new (format! ("active {}", index).as_bytes ()), FreeTierUsage {bytes: 0, since: at (10), exhausted: false});
+        }
+        let used_up = Key::new (b"used up");
+        subject.free_tier_usage.insert (used_up.clone (), FreeTierUsage {bytes: 2000, since: at (10), exhausted: true});
+        let newcomer = Key::new (b"newcomer");
+        let latecomer = Key::new (b"latecomer");
+
+        subject.record_service (&newcomer, IpAddr::from_str ("1.1.1.1").unwrap (), Charge {bytes_routed: 100, bytes_exited: 0, amount: 200, timestamp: at (11)});
+        subject.record_service (&latecomer, IpAddr::from_str ("2.2.2.2").unwrap (), Charge {bytes_routed: 100, bytes_exited: 0, amount: 200, timestamp: at (11)});
+
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &newcomer).unwrap (), None);
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &latecomer).unwrap ().unwrap ().balance, 200);
+        assert_eq! (subject.free_tier_usage.contains_key (&used_up), false);
+        assert_eq! (subject.free_tier_usage.len (), MAX_FREE_TIER_ENTRIES);
+    }
+
+    #[test]
+    fn nodes_over_the_debt_ceiling_are_refused_however_they_have_paid_until_they_pay_their_way_under_it () {
+        let system = System::new ("nodes_over_the_debt_ceiling_are_refused_however_they_have_paid_until_they_pay_their_way_under_it");
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_addr: Addr<Syn, Recorder> = hopper.start ();
+        let config = AccountantConfig {
+            debt_ceiling: 5000,
+            ..make_config ()
+        };
+        let mut subject = Accountant::new (cryptde (), config, Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (BlockchainInterfaceNull::new ()));
+        subject.to_hopper_standings = Some (hopper_addr.recipient::<ServiceStandingMessage> ());
+        let customer = Key::new (b"customer");
+        subject.record_service (&customer, neighbor_ip (), Charge {bytes_routed: 900, bytes_exited: 0, amount: 1000, timestamp: at (10)});
+        subject.record_consuming_wallet (&customer, &wallet ("c"));
+        subject.receive_payment (ReportPaymentReceivedMessage {payer: wallet ("c"), amount: 1000, transaction_hash: String::from ("0xC1")}, at (11));
+        subject.record_service (&customer, neighbor_ip (), Charge {bytes_routed: 4900, bytes_exited: 0, amount: 5000, timestamp: at (12)});
+        subject.record_service (&customer, neighbor_ip (), Charge {bytes_routed: 900, bytes_exited: 0, amount: 1000, timestamp: at (13)});
+        subject.receive_payment (ReportPaymentReceivedMessage {payer: wallet ("c"), amount: 500, transaction_hash: String::from ("0xC2")}, at (14));
+        subject.receive_payment (ReportPaymentReceivedMessage {payer: wallet ("c"), amount: 1000, transaction_hash: String::from ("0xC3")}, at (15));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        assert_eq! (hopper_recording.get_record::<ServiceStandingMessage> (0), &ServiceStandingMessage {consuming_node_key: customer.clone (), standing: ServiceStanding::Refused});
+        assert_eq! (hopper_recording.get_record::<ServiceStandingMessage> (1), &ServiceStandingMessage {consuming_node_key: customer.clone (), standing: ServiceStanding::Good});
+        assert_eq! (hopper_recording.len (), 2);
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &customer).unwrap ().unwrap ().balance, 4500);
+    }
+
+    #[test]
+    fn neighbor_stats_total_service_both_ways_over_the_window_alongside_balances_owed_now () {
+        let mut subject = Accountant::new (cryptde (), make_config (), Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (BlockchainInterfaceNull::new ()));
+        let customer = Key::new (b"customer");
+        let supplier = Key::new (b"supplier");
+        let lapsed = Key::new (b"lapsed");
+        let settled = Key::new (b"settled");
+        subject.ledger.charge (LedgerSide::Receivable, &customer, &Charge {bytes_routed: 1000, bytes_exited: 0, amount: 1100, timestamp: at (3600)}).unwrap ();
+        subject.ledger.charge (LedgerSide::Receivable, &customer, &Charge {bytes_routed: 0, bytes_exited: 500, amount: 1200, timestamp: at (7300)}).unwrap ();
+        subject.ledger.charge (LedgerSide::Payable, &supplier, &Charge {bytes_routed: 300, bytes_exited: 0, amount: 400, timestamp: at (7300)}).unwrap ();
+        subject.ledger.charge (LedgerSide::Receivable, &lapsed, &Charge {bytes_routed: 100, bytes_exited: 0, amount: 200, timestamp: at (10)}).unwrap ();
+        subject.ledger.charge (LedgerSide::Payable, &settled, &Charge {bytes_routed: 100, bytes_exited: 0, amount: 200, timestamp: at (10)}).unwrap ();
+        subject.ledger.record_payment (&PaymentRecord {side: LedgerSide::Payable, public_key: settled.clone (), amount: 200,
+            transaction_hash: String::from ("0xS1"), timestamp: at (20)}).unwrap ();
+
+        let result = subject.neighbor_stats (window_start (at (10800), Some (3000000))).unwrap ();
+
+        assert_eq! (result, vec! (
+            NeighborStats {
+                public_key: customer,
+                served: ServiceTotals {bytes_routed: 0, bytes_exited: 500, amount: 1200},
+                consumed: ServiceTotals::default (),
+                receivable_balance: 2300,
+                payable_balance: 0,
+            },
+            NeighborStats {
+                public_key: lapsed,
+                served: ServiceTotals::default (),
+                consumed: ServiceTotals::default (),
+                receivable_balance: 200,
+                payable_balance: 0,
+            },
+            NeighborStats {
+                public_key: supplier,
+                served: ServiceTotals::default (),
+                consumed: ServiceTotals {bytes_routed: 300, bytes_exited: 0, amount: 400},
+                receivable_balance: 0,
+                payable_balance: 400,
+            },
+        ));
+        assert_eq! (subject.neighbor_stats (window_start (at (10800), None)).unwrap ()[0].served,
+            ServiceTotals {bytes_routed: 1000, bytes_exited: 500, amount: 2300});
+        assert_eq! (window_start (at (10), Some (20000)), UNIX_EPOCH);
+    }
+
+    #[test]
+    fn node_stats_carry_on_from_where_the_last_run_flushed_them () {
+        let data_directory = temp_dir ().join ("accountant").join ("node_stats_carry_on_from_where_the_last_run_flushed_them");
+        fs::create_dir_all (&data_directory).unwrap ();
+        let _ = fs::remove_file (data_directory.join (LEDGER_FILENAME));
+        let make_subject = || Accountant::new (cryptde (), make_config (), Box::new (LedgerReal::in_data_directory (&data_directory).unwrap ()),
+            Box::new (BlockchainInterfaceNull::new ()));
+        {
+            let mut subject = make_subject ();
+            subject.stats.bytes_relayed += 1500;
+            subject.stats.packages_relayed += 2;
+            subject.stats.bytes_exited += 300;
+            subject.stats.requests_served += 1;
+            thread::sleep (Duration::from_millis (50));
+            subject.flush_stats ();
+            subject.stats.requests_served += 1;
+        }
+
+        let subject = make_subject ();
+
+        let stats = subject.node_stats ();
+        assert_eq! (stats, NodeStats {uptime_ms: stats.uptime_ms, bytes_relayed: 1500, packages_relayed: 2, bytes_exited: 300, requests_served: 1});
+        assert_eq! (stats.uptime_ms >= 50, true, "{}", stats.uptime_ms);
+    }
+
+    #[test]
+    fn financials_total_what_is_owed_and_paid_each_way_and_what_is_still_pending () {
+        let mut subject = Accountant::new (cryptde (), make_config (), Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (BlockchainInterfaceNull::new ()));
+        let customer = Key::new (b"customer");
+        let prepaid = Key::new (b"prepaid");
+        let supplier = Key::new (b"supplier");
+        let relay = Key::new (b"relay");
+        owe (&mut subject, &supplier, 1500, 100);
+        owe (&mut subject, &relay, 700, 100);
+        subject.ledger.charge (LedgerSide::Receivable, &customer, &Charge {bytes_routed: 1000, bytes_exited: 0, amount: 2000, timestamp: at (100)}).unwrap ();
+        subject.ledger.charge (LedgerSide::Receivable, &prepaid, &Charge {bytes_routed: 100, bytes_exited: 0, amount: 100, timestamp: at (100)}).unwrap ();
+        subject.ledger.record_payment (&PaymentRecord {side: LedgerSide::Receivable, public_key: customer.clone (), amount: 800,
+            transaction_hash: String::from ("0xR1"), timestamp: at (200)}).unwrap ();
+        subject.ledger.record_payment (&PaymentRecord {side: LedgerSide::Receivable, public_key: prepaid.clone (), amount: 500,
+            transaction_hash: String::from ("0xR2"), timestamp: at (7000)}).unwrap ();
+        subject.ledger.record_payment (&PaymentRecord {side: LedgerSide::Payable, public_key: relay.clone (), amount: 300,
+            transaction_hash: String::from ("0xP1"), timestamp: at (7100)}).unwrap ();
+        subject.ledger.save_channel (&PaymentChannel {
+            channel_id: String::from ("0xC1"),
+            side: LedgerSide::Payable,
+            public_key: relay.clone (),
+            deposit: 1000,
+            balance: 300,
+            sequence: 1,
+            signature: vec! (),
+            expires_timestamp: at (100000),
+            open: true,
+        }).unwrap ();
+        subject.payment_retries.insert (supplier.clone (), PaymentRetry {failures: 2, not_before: at (8000)});
+        subject.in_flight.push (TransactionInFlight {
+            request: TransactionRequest::Payment {payee_key: relay.clone (), payee: wallet ("r"), amount: 400},
+            transaction_hash_opt: Some (String::from ("0xP2")),
+        });
+
+        let result = subject.financials (&[3600000, 86400000], at (7200)).unwrap ();
+
+        assert_eq! (result, Financials {
+            unpaid_payables: 1900,
+            unpaid_receivables: 1200,
+            payment_totals: vec! (
+                PaymentTotals {window_ms: 3600000, paid: 300, received: 500},
+                PaymentTotals {window_ms: 86400000, paid: 300, received: 1300},
+            ),
+            pending_transactions: vec! (
+                PendingTransaction::PaymentRetry {payee: supplier, amount: 1500, failures: 2},
+                PendingTransaction::PaymentUnconfirmed {payee: relay.clone (), amount: 400, transaction_hash_opt: Some (String::from ("0xP2"))},
+                PendingTransaction::ChannelOut {channel_id: String::from ("0xC1"), payee: relay, balance: 300, deposit: 1000},
+            ),
+        });
+    }
+
+    #[test]
+    fn bans_deadbeats_once_and_unbans_them_when_they_pay_their_debt_down () {
+        let system = System::new ("bans_deadbeats_once_and_unbans_them_when_they_pay_their_debt_down");
+        let neighborhood = Recorder::new ();
+        let neighborhood_recording_arc = neighborhood.get_recording ();
+        let neighborhood_addr: Addr<Syn, Recorder> = neighborhood.start ();
+        let mut subject = Accountant::new (cryptde (), make_config (), Box::new (LedgerReal::in_memory ().unwrap ()), Box::new (BlockchainInterfaceNull::new ()));
+        subject.to_neighborhood_bans = Some (neighborhood_addr.clone ().recipient::<BanNodeMsg> ());
+        subject.to_neighborhood_unbans = Some (neighborhood_addr.clone ().recipient::<UnbanNodeMsg> ());
+        subject.to_neighborhood_reports = Some (neighborhood_addr.recipient::<NeighborMisbehaviorMessage> ());
+        let deadbeat = Key::new (b"deadbeat");
+        let newcomer = Key::new (b"newcomer");
+        subject.record_service (&deadbeat, neighbor_ip (), Charge {bytes_routed: 2900, bytes_exited: 0, amount: 3000, timestamp: at (80)});
+        subject.record_service (&newcomer, neighbor_ip (), Charge {bytes_routed: 2900, bytes_exited: 0, amount: 3000, timestamp: at (95)});
+        subject.record_consuming_wallet (&deadbeat, &wallet ("d"));
+
+        subject.scan_receivables (at (100));
+        subject.scan_receivables (at (101));
+        subject.receive_payment (ReportPaymentReceivedMessage {payer: wallet ("d"), amount: 1000, transaction_hash: String::from ("0xD1")}, at (102));
+        subject.receive_payment (ReportPaymentReceivedMessage {payer: wallet ("d"), amount: 1500, transaction_hash: String::from ("0xD2")}, at (103));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let neighborhood_recording = neighborhood_recording_arc.lock ().unwrap ();
+        assert_eq! (neighborhood_recording.get_record::<BanNodeMsg> (0), &BanNodeMsg {
+            public_key: deadbeat.clone (),
+            reason: String::from ("Unpaid debt"),
+            share: false,
+        });
+        assert_eq! (neighborhood_recording.get_record::<NeighborMisbehaviorMessage> (1), &NeighborMisbehaviorMessage {
+            misbehaver: Misbehaver::Node (deadbeat.clone ()),
+            misbehavior: NeighborMisbehavior::UnpaidDebt,
+        });
+        assert_eq! (neighborhood_recording.get_record::<UnbanNodeMsg> (2), &UnbanNodeMsg {public_key: deadbeat.clone ()});
+        assert_eq! (neighborhood_recording.len (), 3);
+        assert_eq! (subject.ledger.account (LedgerSide::Receivable, &deadbeat).unwrap ().unwrap ().balance, 500);
+        assert_eq! (subject.delinquents.is_empty (), true);
+    }
+}
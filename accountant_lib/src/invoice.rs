@@ -0,0 +1,153 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use serde_cbor;
+use sub_lib::accountant::RateSchedule;
+use sub_lib::cryptde::CryptData;
+use sub_lib::cryptde::CryptDE;
+use sub_lib::cryptde::Key;
+use sub_lib::cryptde::PlainData;
+
+// What a serving Node says it did for a consuming Node over a period, and what that came to, signed
+// so it can't claim more later. The consuming Node countersigns it with a Receipt if the sums are
+// right, after which it can't claim less.
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Invoice {
+    pub serving_key: Key,
+    pub consuming_key: Key,
+    // invoices from one Node to another are numbered from 1
+    pub number: u64,
+    pub packages_routed: u64,
+    pub bytes_routed: u64,
+    pub requests_exited: u64,
+    pub bytes_exited: u64,
+    // what the serving Node charged for it all
+    pub rates: RateSchedule,
+    pub amount: i64,
+    // seconds since the epoch
+    pub period_start: u64,
+    pub period_end: u64,
+    pub signature: CryptData,
+}
+
+impl Invoice {
+    // A fresh invoice has nothing on it; service is added as it's done
+    pub fn new (consuming_key: &Key, rates: RateSchedule, period_start: u64) -> Invoice {
+        Invoice {
+            serving_key: Key::new (b""),
+            consuming_key: consuming_key.clone (),
+            number: 0,
+            packages_routed: 0,
+            bytes_routed: 0,
+            requests_exited: 0,
+            bytes_exited: 0,
+            rates,
+            amount: 0,
+            period_start,
+            period_end: period_start,
+            signature: CryptData::new (b""),
+        }
+    }
+
+    pub fn add_routing (&mut self, payload_size: u64) {
+        self.packages_routed += 1;
+        self.bytes_routed += payload_size;
+        self.amount += self.rates.routing_charge (payload_size as usize);
+    }
+
+    pub fn add_exit (&mut self, payload_size: u64) {
+        self.requests_exited += 1;
+        self.bytes_exited += payload_size;
+        self.amount += self.rates.exit_charge (payload_size as usize);
+    }
+
+    // What the service on the invoice comes to at the rates on it
+    pub fn price (&self) -> i64 {
+        (self.packages_routed as i64 * self.rates.routing_service_rate) + (self.bytes_routed as i64 * self.rates.routing_byte_rate)
+            + (self.requests_exited as i64 * self.rates.exit_service_rate) + (self.bytes_exited as i64 * self.rates.exit_byte_rate)
+    }
+
+    // Signed as the serving Node, which the invoice names as such
+    pub fn signed (self, number: u64, period_end: u64, cryptde: &CryptDE) -> Result<Invoice, String> {
+        let invoice = Invoice {serving_key: cryptde.public_key (), number, period_end, ..self};
+        let signature = cryptde.sign (&invoice.signed_data ()).map_err (|e| format! ("Couldn't sign invoice: {:?}", e))?;
+        Ok (Invoice {signature, ..invoice})
+    }
+
+    pub fn verify (&self, cryptde: &CryptDE) -> bool {
+        cryptde.verify_signature (&self.signed_data (), &self.signature, &self.serving_key)
+    }
+
+    fn signed_data (&self) -> PlainData {
+        let fields = (&self.serving_key.data, &self.consuming_key.data, self.number, self.packages_routed, self.bytes_routed,
+            self.requests_exited, self.bytes_exited, &self.rates, self.amount, self.period_start, self.period_end);
+        PlainData::new (&serde_cbor::ser::to_vec (&fields).expect ("Serialization failure")[..])
+    }
+}
+
+// A consuming Node's countersignature on an invoice it agrees with, sent back to the serving Node
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Receipt {
+    pub invoice: Invoice,
+    pub countersignature: CryptData,
+}
+
+impl Receipt {
+    pub fn countersigned (invoice: Invoice, cryptde: &CryptDE) -> Result<Receipt, String> {
+        let countersignature = cryptde.sign (&countersigned_data (&invoice))
+            .map_err (|e| format! ("Couldn't countersign invoice: {:?}", e))?;
+        Ok (Receipt {invoice, countersignature})
+    }
+
+    // Both signatures have to be good, each from the Node the invoice says
+    pub fn verify (&self, cryptde: &CryptDE) -> bool {
+        self.invoice.verify (cryptde)
+            && cryptde.verify_signature (&countersigned_data (&self.invoice), &self.countersignature, &self.invoice.consuming_key)
+    }
+}
+
+// The countersignature covers the serving Node's signature as well as what it signed
+fn countersigned_data (invoice: &Invoice) -> PlainData {
+    let mut data = invoice.signed_data ().data;
+    data.extend_from_slice (&invoice.signature.data[..]);
+    PlainData::new (&data[..])
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use sub_lib::accountant::DEFAULT_RATES;
+    use sub_lib::cryptde_null::CryptDENull;
+
+    fn make_cryptde () -> CryptDENull {
+        let mut cryptde = CryptDENull::new ();
+        cryptde.generate_key_pair ();
+        cryptde
+    }
+
+    #[test]
+    fn invoices_are_priced_at_the_going_rates_and_receipts_hold_both_parties_to_them () {
+        let server = make_cryptde ();
+        let consumer = make_cryptde ();
+        let rates = RateSchedule {routing_service_rate: 10, routing_byte_rate: 2, exit_service_rate: 30, exit_byte_rate: 4};
+        let mut invoice = Invoice::new (&consumer.public_key (), rates, 1000);
+        invoice.add_routing (1000);
+        invoice.add_routing (500);
+        invoice.add_exit (300);
+
+        let invoice = invoice.signed (3, 1600, &server).unwrap ();
+        let receipt = Receipt::countersigned (invoice.clone (), &consumer).unwrap ();
+
+        assert_eq! ((invoice.packages_routed, invoice.bytes_routed, invoice.requests_exited, invoice.bytes_exited), (2, 1500, 1, 300));
+        assert_eq! (invoice.amount, (2 * 10) + (1500 * 2) + 30 + (300 * 4));
+        assert_eq! (invoice.price (), invoice.amount);
+        assert_eq! (Invoice {rates: DEFAULT_RATES, ..invoice.clone ()}.price () == invoice.amount, false);
+        assert_eq! ((invoice.serving_key.clone (), invoice.number, invoice.period_start, invoice.period_end), (server.public_key (), 3, 1000, 1600));
+        assert_eq! (invoice.verify (&consumer), true);
+        assert_eq! (Invoice {amount: invoice.amount + 1, ..invoice.clone ()}.verify (&consumer), false);
+        assert_eq! (Invoice {rates: DEFAULT_RATES, ..invoice.clone ()}.verify (&consumer), false);
+        assert_eq! (receipt.verify (&server), true);
+        assert_eq! (Receipt {invoice: Invoice {number: 4, ..invoice.clone ()}, ..receipt.clone ()}.verify (&server), false);
+        assert_eq! (Receipt {countersignature: invoice.signature.clone (), ..receipt.clone ()}.verify (&server), false);
+        let serialized = serde_cbor::ser::to_vec (&receipt).unwrap ();
+        assert_eq! (serde_cbor::de::from_slice::<Receipt> (&serialized[..]).unwrap (), receipt);
+    }
+}
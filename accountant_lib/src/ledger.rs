@@ -0,0 +1,971 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use rusqlite;
+use rusqlite::Connection;
+use rusqlite::Row;
+use rusqlite::types::ToSql;
+use sub_lib::accountant::NodeStats;
+use sub_lib::accountant::RateSchedule;
+use sub_lib::accountant::ServiceTotals;
+use sub_lib::cryptde::CryptData;
+use sub_lib::cryptde::Key;
+use sub_lib::wallet::Wallet;
+use invoice::Invoice;
+use payment_channel::PaymentChannel;
+
+pub const LEDGER_FILENAME: &str = "accountant.db";
+
+// Every write happens inside a transaction, and a transaction isn't finished until it's on the
+// disk, so a crash leaves each account as it was either before or after a change. Charges come with
+// every package, too often for that, so they're written in batches: a crash loses the last batch.
+const SCHEMA: &str = "
+    PRAGMA journal_mode = WAL;
+    PRAGMA synchronous = FULL;
+    CREATE TABLE IF NOT EXISTS payables (
+        public_key BLOB PRIMARY KEY NOT NULL,
+        bytes_routed INTEGER NOT NULL,
+        bytes_exited INTEGER NOT NULL,
+        balance INTEGER NOT NULL,
+        first_service_timestamp INTEGER NOT NULL,
+        last_service_timestamp INTEGER NOT NULL,
+        last_settled_timestamp INTEGER
+    );
+    CREATE TABLE IF NOT EXISTS receivables (
+        public_key BLOB PRIMARY KEY NOT NULL,
+        bytes_routed INTEGER NOT NULL,
+        bytes_exited INTEGER NOT NULL,
+        balance INTEGER NOT NULL,
+        first_service_timestamp INTEGER NOT NULL,
+        last_service_timestamp INTEGER NOT NULL,
+        last_settled_timestamp INTEGER
+    );
+    CREATE TABLE IF NOT EXISTS payments (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        side TEXT NOT NULL,
+        public_key BLOB NOT NULL,
+        amount INTEGER NOT NULL,
+        transaction_hash TEXT NOT NULL,
+        timestamp INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS wallets (
+        side TEXT NOT NULL,
+        public_key BLOB NOT NULL,
+        wallet TEXT NOT NULL,
+        PRIMARY KEY (side, public_key)
+    );
+    CREATE TABLE IF NOT EXISTS channels (
+        channel_id TEXT PRIMARY KEY NOT NULL,
+        side TEXT NOT NULL,
+        public_key BLOB NOT NULL,
+        deposit INTEGER NOT NULL,
+        balance INTEGER NOT NULL,
+        sequence INTEGER NOT NULL,
+        signature BLOB NOT NULL,
+        expires_timestamp INTEGER NOT NULL,
+        open INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS hourly_service (
+        side TEXT NOT NULL,
+        public_key BLOB NOT NULL,
+        hour_timestamp INTEGER NOT NULL,
+        bytes_routed INTEGER NOT NULL,
+        bytes_exited INTEGER NOT NULL,
+        amount INTEGER NOT NULL,
+        PRIMARY KEY (side, public_key, hour_timestamp)
+    );
+    CREATE TABLE IF NOT EXISTS node_stats (
+        name TEXT PRIMARY KEY NOT NULL,
+        value INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS invoices (
+        side TEXT NOT NULL,
+        serving_key BLOB NOT NULL,
+        consuming_key BLOB NOT NULL,
+        number INTEGER NOT NULL,
+        packages_routed INTEGER NOT NULL,
+        bytes_routed INTEGER NOT NULL,
+        requests_exited INTEGER NOT NULL,
+        bytes_exited INTEGER NOT NULL,
+        routing_service_rate INTEGER NOT NULL,
+        routing_byte_rate INTEGER NOT NULL,
+        exit_service_rate INTEGER NOT NULL,
+        exit_byte_rate INTEGER NOT NULL,
+        amount INTEGER NOT NULL,
+        period_start INTEGER NOT NULL,
+        period_end INTEGER NOT NULL,
+        signature BLOB NOT NULL,
+        countersignature BLOB,
+        PRIMARY KEY (side, serving_key, consuming_key, number)
+    );
+";
+
+const ACCOUNT_COLUMNS: &str = "public_key, bytes_routed, bytes_exited, balance, first_service_timestamp, last_service_timestamp, last_settled_timestamp";
+const CHANNEL_COLUMNS: &str = "channel_id, side, public_key, deposit, balance, sequence, signature, expires_timestamp, open";
+const INVOICE_COLUMNS: &str = "side, serving_key, consuming_key, number, packages_routed, bytes_routed, requests_exited, bytes_exited, \
+    routing_service_rate, routing_byte_rate, exit_service_rate, exit_byte_rate, amount, period_start, period_end, signature, countersignature";
+// Service is also totalled by the hour, so it can be looked at over windows of time
+const SECONDS_PER_HOUR: i64 = 3600;
+
+#[derive (Clone, Copy, Debug, PartialEq)]
+pub enum LedgerSide {
+    // What this Node owes other Nodes for services it consumed
+    Payable,
+    // What other Nodes owe this Node for services it provided
+    Receivable,
+}
+
+impl LedgerSide {
+    fn table (&self) -> &'static str {
+        match *self {
+            LedgerSide::Payable => "payables",
+            LedgerSide::Receivable => "receivables",
+        }
+    }
+
+    fn from_table (table: &str) -> LedgerSide {
+        if table == LedgerSide::Payable.table () {LedgerSide::Payable} else {LedgerSide::Receivable}
+    }
+
+    // Which Node on an invoice is the other one
+    fn other_key_column (&self) -> &'static str {
+        match *self {
+            LedgerSide::Payable => "serving_key",
+            LedgerSide::Receivable => "consuming_key",
+        }
+    }
+}
+
+// Amounts are in the smallest unit of SUB; a positive balance is owed, a negative one is prepaid
+#[derive (Clone, Debug, PartialEq)]
+pub struct Account {
+    pub public_key: Key,
+    pub bytes_routed: u64,
+    pub bytes_exited: u64,
+    pub balance: i64,
+    pub first_service_timestamp: SystemTime,
+    pub last_service_timestamp: SystemTime,
+    pub last_settled_timestamp_opt: Option<SystemTime>,
+}
+
+impl Account {
+    // Any payment starts the clock over on what's still owed
+    pub fn owed_since (&self) -> SystemTime {
+        self.last_settled_timestamp_opt.unwrap_or (self.first_service_timestamp)
+    }
+}
+
+#[derive (Clone, Debug, PartialEq)]
+pub struct Charge {
+    pub bytes_routed: u64,
+    pub bytes_exited: u64,
+    pub amount: i64,
+    pub timestamp: SystemTime,
+}
+
+#[derive (Clone, Debug, PartialEq)]
+pub struct PaymentRecord {
+    pub side: LedgerSide,
+    pub public_key: Key,
+    pub amount: i64,
+    pub transaction_hash: String,
+    pub timestamp: SystemTime,
+}
+
+// Receivable if this Node sent the invoice, Payable if it was sent one
+#[derive (Clone, Debug, PartialEq)]
+pub struct InvoiceRecord {
+    pub side: LedgerSide,
+    pub invoice: Invoice,
+    pub countersignature_opt: Option<CryptData>,
+}
+
+pub trait Ledger {
+    // Charges are totalled in memory until the next flush writes them all at once; whatever's read
+    // from the ledger includes them either way
+    fn charge (&mut self, side: LedgerSide, public_key: &Key, charge: &Charge) -> Result<(), String>;
+    fn flush (&mut self) -> Result<(), String>;
+    fn record_payment (&mut self, payment: &PaymentRecord) -> Result<(), String>;
+    fn account (&self, side: LedgerSide, public_key: &Key) -> Result<Option<Account>, String>;
+    fn accounts (&self, side: LedgerSide) -> Result<Vec<Account>, String>;
+    fn payments (&self, side: LedgerSide) -> Result<Vec<PaymentRecord>, String>;
+    // What was charged to each Node from the start of the hour that since falls in
+    fn service_since (&self, side: LedgerSide, since: SystemTime) -> Result<Vec<(Key, ServiceTotals)>, String>;
+    // Payables are paid to the earning wallet on file for the Node; receivables are expected from
+    // its consuming wallet. A consuming wallet belongs to the first Node to claim it, and claims on
+    // it by any other Node fail, so nobody can be credited with somebody else's payments.
+    fn set_wallet (&mut self, side: LedgerSide, public_key: &Key, wallet: &Wallet) -> Result<(), String>;
+    fn wallet (&self, side: LedgerSide, public_key: &Key) -> Result<Option<Wallet>, String>;
+    fn key_for_wallet (&self, side: LedgerSide, wallet: &Wallet) -> Result<Option<Key>, String>;
+    // A Node has at most one open channel on each side; closed ones are kept for the record
+    fn save_channel (&mut self, channel: &PaymentChannel) -> Result<(), String>;
+    // A balance update and the payment it makes are recorded together or not at all
+    fn record_channel_payment (&mut self, channel: &PaymentChannel, payment: &PaymentRecord) -> Result<(), String>;
+    fn channel (&self, channel_id: &str) -> Result<Option<PaymentChannel>, String>;
+    fn open_channel (&self, side: LedgerSide, public_key: &Key) -> Result<Option<PaymentChannel>, String>;
+    fn open_channels (&self, side: LedgerSide) -> Result<Vec<PaymentChannel>, String>;
+    // Lifetime figures, replaced all together; a new ledger has all zeroes
+    fn save_stats (&mut self, stats: &NodeStats) -> Result<(), String>;
+    fn stats (&self) -> Result<NodeStats, String>;
+    // Invoices are kept by side and the other Node on them, and replaced when countersigned
+    fn save_invoice (&mut self, record: &InvoiceRecord) -> Result<(), String>;
+    fn invoice (&self, side: LedgerSide, public_key: &Key, number: u64) -> Result<Option<InvoiceRecord>, String>;
+    fn last_invoice (&self, side: LedgerSide, public_key: &Key) -> Result<Option<InvoiceRecord>, String>;
+    // The total of countersigned invoices for service that ended at or after since
+    fn countersigned_since (&self, side: LedgerSide, public_key: &Key, since: SystemTime) -> Result<i64, String>;
+}
+
+// What's been charged to one Node on one side since the last flush
+#[derive (Clone, Debug, PartialEq)]
+struct PendingCharge {
+    bytes_routed: u64,
+    bytes_exited: u64,
+    amount: i64,
+    first_timestamp: i64,
+    last_timestamp: i64,
+}
+
+pub struct LedgerReal {
+    connection: Connection,
+    // Keyed by side table and Node
+    pending_charges: HashMap<(&'static str, Key), PendingCharge>,
+    // Keyed by side table, Node and the hour the service was in
+    pending_hours: HashMap<(&'static str, Key, i64), ServiceTotals>,
+}
+
+impl Ledger for LedgerReal {
+    fn charge (&mut self, side: LedgerSide, public_key: &Key, charge: &Charge) -> Result<(), String> {
+        let timestamp = to_secs (charge.timestamp);
+        {
+            let pending = self.pending_charges.entry ((side.table (), public_key.clone ())).or_insert (PendingCharge {
+                bytes_routed: 0,
+                bytes_exited: 0,
+                amount: 0,
+                first_timestamp: timestamp,
+                last_timestamp: timestamp,
+            });
+            pending.bytes_routed += charge.bytes_routed;
+            pending.bytes_exited += charge.bytes_exited;
+            pending.amount += charge.amount;
+            pending.last_timestamp = timestamp;
+        }
+        let hour_timestamp = timestamp - timestamp % SECONDS_PER_HOUR;
+        let hour = self.pending_hours.entry ((side.table (), public_key.clone (), hour_timestamp)).or_insert (ServiceTotals::default ());
+        hour.bytes_routed += charge.bytes_routed;
+        hour.bytes_exited += charge.bytes_exited;
+        hour.amount += charge.amount;
+        Ok (())
+    }
+
+    fn flush (&mut self) -> Result<(), String> {
+        if self.pending_charges.is_empty () {return Ok (())}
+        {
+            let transaction = self.connection.transaction ().map_err (ledger_error)?;
+            for (key, pending) in self.pending_charges.iter () {
+                let (table, public_key) = (key.0, &key.1);
+                transaction.execute (&format! ("INSERT OR IGNORE INTO {} (public_key, bytes_routed, bytes_exited, balance, first_service_timestamp, last_service_timestamp) VALUES (?, 0, 0, 0, ?, ?)", table),
+                    &[&public_key.data as &ToSql, &pending.first_timestamp, &pending.first_timestamp]).map_err (ledger_error)?;
+                transaction.execute (&format! ("UPDATE {} SET bytes_routed = bytes_routed + ?, bytes_exited = bytes_exited + ?, balance = balance + ?, last_service_timestamp = ? WHERE public_key = ?", table),
+                    &[&(pending.bytes_routed as i64) as &ToSql, &(pending.bytes_exited as i64), &pending.amount, &pending.last_timestamp, &public_key.data]).map_err (ledger_error)?;
+            }
+            for (key, totals) in self.pending_hours.iter () {
+                let (side, public_key, hour_timestamp) = (String::from (key.0), &key.1, key.2);
+                transaction.execute ("INSERT OR IGNORE INTO hourly_service (side, public_key, hour_timestamp, bytes_routed, bytes_exited, amount) VALUES (?, ?, ?, 0, 0, 0)",
+                    &[&side as &ToSql, &public_key.data, &hour_timestamp]).map_err (ledger_error)?;
+                transaction.execute ("UPDATE hourly_service SET bytes_routed = bytes_routed + ?, bytes_exited = bytes_exited + ?, amount = amount + ? WHERE side = ? AND public_key = ? AND hour_timestamp = ?",
+                    &[&(totals.bytes_routed as i64) as &ToSql, &(totals.bytes_exited as i64), &totals.amount, &side, &public_key.data, &hour_timestamp]).map_err (ledger_error)?;
+            }
+            transaction.commit ().map_err (ledger_error)?;
+        }
+        self.pending_charges.clear ();
+        self.pending_hours.clear ();
+        Ok (())
+    }
+
+    // A payment settles the account as it's written, so pending charges are written first
+    fn record_payment (&mut self, payment: &PaymentRecord) -> Result<(), String> {
+        self.flush ()?;
+        let transaction = self.connection.transaction ().map_err (ledger_error)?;
+        insert_payment (&transaction, payment).map_err (ledger_error)?;
+        transaction.commit ().map_err (ledger_error)
+    }
+
+    fn account (&self, side: LedgerSide, public_key: &Key) -> Result<Option<Account>, String> {
+        let mut statement = self.connection.prepare (&format! ("SELECT {} FROM {} WHERE public_key = ?", ACCOUNT_COLUMNS, side.table ())).map_err (ledger_error)?;
+        let accounts = statement.query_map (&[&public_key.data as &ToSql], account_from_row).map_err (ledger_error)?
+            .collect::<Result<Vec<Account>, rusqlite::Error>> ().map_err (ledger_error)?;
+        Ok (self.with_pending_charge (side, public_key, accounts.into_iter ().next ()))
+    }
+
+    fn accounts (&self, side: LedgerSide) -> Result<Vec<Account>, String> {
+        let mut statement = self.connection.prepare (&format! ("SELECT {} FROM {} ORDER BY balance DESC", ACCOUNT_COLUMNS, side.table ())).map_err (ledger_error)?;
+        let written = statement.query_map (&[], account_from_row).map_err (ledger_error)?
+            .collect::<Result<Vec<Account>, rusqlite::Error>> ().map_err (ledger_error)?;
+        let unwritten_keys: Vec<Key> = self.pending_charges.keys ()
+            .filter (|key| (key.0 == side.table ()) && !written.iter ().any (|account| account.public_key == key.1))
+            .map (|key| key.1.clone ())
+            .collect ();
+        let mut accounts: Vec<Account> = written.into_iter ()
+            .map (|account| {
+                let public_key = account.public_key.clone ();
+                self.with_pending_charge (side, &public_key, Some (account)).expect ("Account disappeared")
+            })
+            .collect ();
+        for public_key in unwritten_keys {
+            accounts.push (self.with_pending_charge (side, &public_key, None).expect ("Pending charge disappeared"));
+        }
+        accounts.sort_by (|a, b| b.balance.cmp (&a.balance));
+        Ok (accounts)
+    }
+
+    fn payments (&self, side: LedgerSide) -> Result<Vec<PaymentRecord>, String> {
+        let side = String::from (side.table ());
+        let mut statement = self.connection.prepare ("SELECT side, public_key, amount, transaction_hash, timestamp FROM payments WHERE side = ? ORDER BY id").map_err (ledger_error)?;
+        let payments = statement.query_map (&[&side as &ToSql], payment_from_row).map_err (ledger_error)?
+            .collect::<Result<Vec<PaymentRecord>, rusqlite::Error>> ().map_err (ledger_error);
+        payments
+    }
+
+    fn service_since (&self, side: LedgerSide, since: SystemTime) -> Result<Vec<(Key, ServiceTotals)>, String> {
+        let table = side.table ();
+        let side = String::from (table);
+        let since = to_secs (since);
+        let hour_timestamp = since - since % SECONDS_PER_HOUR;
+        let mut statement = self.connection.prepare ("SELECT public_key, SUM(bytes_routed), SUM(bytes_exited), SUM(amount) FROM hourly_service WHERE side = ? AND hour_timestamp >= ? GROUP BY public_key ORDER BY SUM(amount) DESC").map_err (ledger_error)?;
+        let mut service = statement.query_map (&[&side as &ToSql, &hour_timestamp], service_from_row).map_err (ledger_error)?
+            .collect::<Result<Vec<(Key, ServiceTotals)>, rusqlite::Error>> ().map_err (ledger_error)?;
+        for (key, totals) in self.pending_hours.iter ().filter (|&(key, _)| (key.0 == table) && (key.2 >= hour_timestamp)) {
+            let index_opt = service.iter ().position (|entry| entry.0 == key.1);
+            match index_opt {
+                Some (index) => {
+                    let entry = &mut service[index].1;
+                    entry.bytes_routed += totals.bytes_routed;
+                    entry.bytes_exited += totals.bytes_exited;
+                    entry.amount += totals.amount;
+                },
+                None => service.push ((key.1.clone (), totals.clone ())),
+            }
+        }
+        service.sort_by (|a, b| (b.1).amount.cmp (&(a.1).amount));
+        Ok (service)
+    }
+
+    fn set_wallet (&mut self, side: LedgerSide, public_key: &Key, wallet: &Wallet) -> Result<(), String> {
+        if side == LedgerSide::Receivable {
+            match self.key_for_wallet (side, wallet)? {
+                Some (ref owner) if owner != public_key => return Err (format! ("{} already belongs to another Node", wallet)),
+                _ => ()
+            }
+        }
+        let side = String::from (side.table ());
+        self.connection.execute ("INSERT OR REPLACE INTO wallets (side, public_key, wallet) VALUES (?, ?, ?)",
+            &[&side as &ToSql, &public_key.data, &wallet.address]).map_err (ledger_error)?;
+        Ok (())
+    }
+
+    fn wallet (&self, side: LedgerSide, public_key: &Key) -> Result<Option<Wallet>, String> {
+        let side = String::from (side.table ());
+        let mut statement = self.connection.prepare ("SELECT wallet FROM wallets WHERE side = ? AND public_key = ?").map_err (ledger_error)?;
+        let wallets = statement.query_map (&[&side as &ToSql, &public_key.data], |row| Wallet {address: row.get (0)}).map_err (ledger_error)?
+            .collect::<Result<Vec<Wallet>, rusqlite::Error>> ().map_err (ledger_error)?;
+        Ok (wallets.into_iter ().next ())
+    }
+
+    // One operator's Nodes may share an earning wallet; the first Node on file with it is named
+    fn key_for_wallet (&self, side: LedgerSide, wallet: &Wallet) -> Result<Option<Key>, String> {
+        let side = String::from (side.table ());
+        let mut statement = self.connection.prepare ("SELECT public_key FROM wallets WHERE side = ? AND wallet = ? ORDER BY rowid").map_err (ledger_error)?;
+        let keys = statement.query_map (&[&side as &ToSql, &wallet.address], |row| {
+            let public_key: Vec<u8> = row.get (0);
+            Key::new (&public_key[..])
+        }).map_err (ledger_error)?
+            .collect::<Result<Vec<Key>, rusqlite::Error>> ().map_err (ledger_error)?;
+        Ok (keys.into_iter ().next ())
+    }
+
+    fn save_channel (&mut self, channel: &PaymentChannel) -> Result<(), String> {
+        replace_channel (&self.connection, channel).map_err (ledger_error)
+    }
+
+    fn record_channel_payment (&mut self, channel: &PaymentChannel, payment: &PaymentRecord) -> Result<(), String> {
+        self.flush ()?;
+        let transaction = self.connection.transaction ().map_err (ledger_error)?;
+        replace_channel (&transaction, channel).map_err (ledger_error)?;
+        insert_payment (&transaction, payment).map_err (ledger_error)?;
+        transaction.commit ().map_err (ledger_error)
+    }
+
+    fn channel (&self, channel_id: &str) -> Result<Option<PaymentChannel>, String> {
+        let channel_id = String::from (channel_id);
+        let mut statement = self.connection.prepare (&format! ("SELECT {} FROM channels WHERE channel_id = ?", CHANNEL_COLUMNS)).map_err (ledger_error)?;
+        let channels = statement.query_map (&[&channel_id as &ToSql], channel_from_row).map_err (ledger_error)?
+            .collect::<Result<Vec<PaymentChannel>, rusqlite::Error>> ().map_err (ledger_error)?;
+        Ok (channels.into_iter ().next ())
+    }
+
+    fn open_channel (&self, side: LedgerSide, public_key: &Key) -> Result<Option<PaymentChannel>, String> {
+        let side = String::from (side.table ());
+        let mut statement = self.connection.prepare (&format! ("SELECT {} FROM channels WHERE side = ? AND public_key = ? AND open = 1 ORDER BY rowid DESC", CHANNEL_COLUMNS)).map_err (ledger_error)?;
+        let channels = statement.query_map (&[&side as &ToSql, &public_key.data], channel_from_row).map_err (ledger_error)?
+            .collect::<Result<Vec<PaymentChannel>, rusqlite::Error>> ().map_err (ledger_error)?;
+        Ok (channels.into_iter ().next ())
+    }
+
+    fn open_channels (&self, side: LedgerSide) -> Result<Vec<PaymentChannel>, String> {
+        let side = String::from (side.table ());
+        let mut statement = self.connection.prepare (&format! ("SELECT {} FROM channels WHERE side = ? AND open = 1 ORDER BY rowid", CHANNEL_COLUMNS)).map_err (ledger_error)?;
+        let channels = statement.query_map (&[&side as &ToSql], channel_from_row).map_err (ledger_error)?
+            .collect::<Result<Vec<PaymentChannel>, rusqlite::Error>> ().map_err (ledger_error);
+        channels
+    }
+
+    fn save_stats (&mut self, stats: &NodeStats) -> Result<(), String> {
+        let transaction = self.connection.transaction ().map_err (ledger_error)?;
+        for (name, value) in stats_by_name (stats) {
+            let name = String::from (name);
+            transaction.execute ("INSERT OR REPLACE INTO node_stats (name, value) VALUES (?, ?)",
+                &[&name as &ToSql, &(value as i64)]).map_err (ledger_error)?;
+        }
+        transaction.commit ().map_err (ledger_error)
+    }
+
+    fn stats (&self) -> Result<NodeStats, String> {
+        let mut statement = self.connection.prepare ("SELECT name, value FROM node_stats").map_err (ledger_error)?;
+        let rows = statement.query_map (&[], |row| {
+            let name: String = row.get (0);
+            let value: i64 = row.get (1);
+            (name, value as u64)
+        }).map_err (ledger_error)?
+            .collect::<Result<Vec<(String, u64)>, rusqlite::Error>> ().map_err (ledger_error)?;
+        let mut stats = NodeStats::default ();
+        for (name, value) in rows {
+            match name.as_str () {
+                "bytes_relayed" => stats.bytes_relayed = value,
+                "packages_relayed" => stats.packages_relayed = value,
+                "bytes_exited" => stats.bytes_exited = value,
+                "requests_served" => stats.requests_served = value,
+                "uptime_ms" => stats.uptime_ms = value,
+                _ => ()
+            }
+        }
+        Ok (stats)
+    }
+
+    fn save_invoice (&mut self, record: &InvoiceRecord) -> Result<(), String> {
+        let side = String::from (record.side.table ());
+        let invoice = &record.invoice;
+        let countersignature_opt = record.countersignature_opt.as_ref ().map (|countersignature| countersignature.data.clone ());
+        self.connection.execute (&format! ("INSERT OR REPLACE INTO invoices ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)", INVOICE_COLUMNS),
+            &[&side as &ToSql, &invoice.serving_key.data, &invoice.consuming_key.data, &(invoice.number as i64),
+                &(invoice.packages_routed as i64), &(invoice.bytes_routed as i64), &(invoice.requests_exited as i64), &(invoice.bytes_exited as i64),
+                &invoice.rates.routing_service_rate, &invoice.rates.routing_byte_rate, &invoice.rates.exit_service_rate, &invoice.rates.exit_byte_rate,
+                &invoice.amount, &(invoice.period_start as i64), &(invoice.period_end as i64), &invoice.signature.data, &countersignature_opt]).map_err (ledger_error)?;
+        Ok (())
+    }
+
+    fn invoice (&self, side: LedgerSide, public_key: &Key, number: u64) -> Result<Option<InvoiceRecord>, String> {
+        let other_key_column = side.other_key_column ();
+        let side = String::from (side.table ());
+        let mut statement = self.connection.prepare (&format! ("SELECT {} FROM invoices WHERE side = ? AND {} = ? AND number = ?", INVOICE_COLUMNS, other_key_column)).map_err (ledger_error)?;
+        let invoices = statement.query_map (&[&side as &ToSql, &public_key.data, &(number as i64)], invoice_from_row).map_err (ledger_error)?
+            .collect::<Result<Vec<InvoiceRecord>, rusqlite::Error>> ().map_err (ledger_error)?;
+        Ok (invoices.into_iter ().next ())
+    }
+
+    fn last_invoice (&self, side: LedgerSide, public_key: &Key) -> Result<Option<InvoiceRecord>, String> {
+        let other_key_column = side.other_key_column ();
+        let side = String::from (side.table ());
+        let mut statement = self.connection.prepare (&format! ("SELECT {} FROM invoices WHERE side = ? AND {} = ? ORDER BY number DESC LIMIT 1", INVOICE_COLUMNS, other_key_column)).map_err (ledger_error)?;
+        let invoices = statement.query_map (&[&side as &ToSql, &public_key.data], invoice_from_row).map_err (ledger_error)?
+            .collect::<Result<Vec<InvoiceRecord>, rusqlite::Error>> ().map_err (ledger_error)?;
+        Ok (invoices.into_iter ().next ())
+    }
+
+    fn countersigned_since (&self, side: LedgerSide, public_key: &Key, since: SystemTime) -> Result<i64, String> {
+        let other_key_column = side.other_key_column ();
+        let side = String::from (side.table ());
+        let mut statement = self.connection.prepare (&format! ("SELECT COALESCE(SUM(amount), 0) FROM invoices WHERE side = ? AND {} = ? AND period_end >= ? AND countersignature IS NOT NULL", other_key_column)).map_err (ledger_error)?;
+        let totals = statement.query_map (&[&side as &ToSql, &public_key.data, &to_secs (since)], |row| row.get (0)).map_err (ledger_error)?
+            .collect::<Result<Vec<i64>, rusqlite::Error>> ().map_err (ledger_error)?;
+        Ok (totals.into_iter ().next ().unwrap_or (0))
+    }
+}
+
+fn stats_by_name (stats: &NodeStats) -> Vec<(&'static str, u64)> {
+    vec! (
+        ("bytes_relayed", stats.bytes_relayed),
+        ("packages_relayed", stats.packages_relayed),
+        ("bytes_exited", stats.bytes_exited),
+        ("requests_served", stats.requests_served),
+        ("uptime_ms", stats.uptime_ms),
+    )
+}
+
+fn insert_payment (connection: &Connection, payment: &PaymentRecord) -> Result<(), rusqlite::Error> {
+    let timestamp = to_secs (payment.timestamp);
+    let side = String::from (payment.side.table ());
+    connection.execute ("INSERT INTO payments (side, public_key, amount, transaction_hash, timestamp) VALUES (?, ?, ?, ?, ?)",
+        &[&side as &ToSql, &payment.public_key.data, &payment.amount, &payment.transaction_hash, &timestamp])?;
+    connection.execute (&format! ("UPDATE {} SET balance = balance - ?, last_settled_timestamp = ? WHERE public_key = ?", payment.side.table ()),
+        &[&payment.amount as &ToSql, &timestamp, &payment.public_key.data])?;
+    Ok (())
+}
+
+fn replace_channel (connection: &Connection, channel: &PaymentChannel) -> Result<(), rusqlite::Error> {
+    let side = String::from (channel.side.table ());
+    connection.execute (&format! ("INSERT OR REPLACE INTO channels ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)", CHANNEL_COLUMNS),
+        &[&channel.channel_id as &ToSql, &side, &channel.public_key.data, &channel.deposit, &channel.balance, &(channel.sequence as i64),
+            &channel.signature, &to_secs (channel.expires_timestamp), &channel.open])?;
+    Ok (())
+}
+
+impl LedgerReal {
+    pub fn in_data_directory (data_directory: &Path) -> Result<LedgerReal, String> {
+        let path = data_directory.join (LEDGER_FILENAME);
+        match Connection::open (&path) {
+            Ok (connection) => LedgerReal::initialize (connection),
+            Err (e) => Err (format! ("Couldn't open {:?}: {}", path, e))
+        }
+    }
+
+    // For a Node with no data directory: it keeps accounts until it shuts down
+    pub fn in_memory () -> Result<LedgerReal, String> {
+        match Connection::open_in_memory () {
+            Ok (connection) => LedgerReal::initialize (connection),
+            Err (e) => Err (format! ("Couldn't open ledger in memory: {}", e))
+        }
+    }
+
+    fn initialize (connection: Connection) -> Result<LedgerReal, String> {
+        match connection.execute_batch (SCHEMA) {
+            Ok (()) => Ok (LedgerReal {connection, pending_charges: HashMap::new (), pending_hours: HashMap::new ()}),
+            Err (e) => Err (format! ("Couldn't set up ledger: {}", e))
+        }
+    }
+
+    // An account as it was written, with what's been charged to it since
+    fn with_pending_charge (&self, side: LedgerSide, public_key: &Key, account_opt: Option<Account>) -> Option<Account> {
+        let pending = match self.pending_charges.get (&(side.table (), public_key.clone ())) {
+            None => return account_opt,
+            Some (pending) => pending,
+        };
+        Some (match account_opt {
+            Some (account) => Account {
+                bytes_routed: account.bytes_routed + pending.bytes_routed,
+                bytes_exited: account.bytes_exited + pending.bytes_exited,
+                balance: account.balance + pending.amount,
+                last_service_timestamp: from_secs (pending.last_timestamp),
+                ..account
+            },
+            None => Account {
+                public_key: public_key.clone (),
+                bytes_routed: pending.bytes_routed,
+                bytes_exited: pending.bytes_exited,
+                balance: pending.amount,
+                first_service_timestamp: from_secs (pending.first_timestamp),
+                last_service_timestamp: from_secs (pending.last_timestamp),
+                last_settled_timestamp_opt: None,
+            },
+        })
+    }
+}
+
+// Charges not yet flushed are written when the ledger is closed
+impl Drop for LedgerReal {
+    fn drop (&mut self) {
+        let _ = self.flush ();
+    }
+}
+
+fn account_from_row (row: &Row) -> Account {
+    let public_key: Vec<u8> = row.get (0);
+    let bytes_routed: i64 = row.get (1);
+    let bytes_exited: i64 = row.get (2);
+    let last_settled_timestamp_opt: Option<i64> = row.get (6);
+    Account {
+        public_key: Key::new (&public_key[..]),
+        bytes_routed: bytes_routed as u64,
+        bytes_exited: bytes_exited as u64,
+        balance: row.get (3),
+        first_service_timestamp: from_secs (row.get (4)),
+        last_service_timestamp: from_secs (row.get (5)),
+        last_settled_timestamp_opt: last_settled_timestamp_opt.map (from_secs),
+    }
+}
+
+fn channel_from_row (row: &Row) -> PaymentChannel {
+    let side: String = row.get (1);
+    let public_key: Vec<u8> = row.get (2);
+    let sequence: i64 = row.get (5);
+    PaymentChannel {
+        channel_id: row.get (0),
+        side: LedgerSide::from_table (&side),
+        public_key: Key::new (&public_key[..]),
+        deposit: row.get (3),
+        balance: row.get (4),
+        sequence: sequence as u64,
+        signature: row.get (6),
+        expires_timestamp: from_secs (row.get (7)),
+        open: row.get (8),
+    }
+}
+
+fn payment_from_row (row: &Row) -> PaymentRecord {
+    let side: String = row.get (0);
+    let public_key: Vec<u8> = row.get (1);
+    PaymentRecord {
+        side: LedgerSide::from_table (&side),
+        public_key: Key::new (&public_key[..]),
+        amount: row.get (2),
+        transaction_hash: row.get (3),
+        timestamp: from_secs (row.get (4)),
+    }
+}
+
+fn service_from_row (row: &Row) -> (Key, ServiceTotals) {
+    let public_key: Vec<u8> = row.get (0);
+    let bytes_routed: i64 = row.get (1);
+    let bytes_exited: i64 = row.get (2);
+    (Key::new (&public_key[..]), ServiceTotals {
+        bytes_routed: bytes_routed as u64,
+        bytes_exited: bytes_exited as u64,
+        amount: row.get (3),
+    })
+}
+
+fn invoice_from_row (row: &Row) -> InvoiceRecord {
+    let side: String = row.get (0);
+    let serving_key: Vec<u8> = row.get (1);
+    let consuming_key: Vec<u8> = row.get (2);
+    let signature: Vec<u8> = row.get (15);
+    let countersignature_opt: Option<Vec<u8>> = row.get (16);
+    let number: i64 = row.get (3);
+    let packages_routed: i64 = row.get (4);
+    let bytes_routed: i64 = row.get (5);
+    let requests_exited: i64 = row.get (6);
+    let bytes_exited: i64 = row.get (7);
+    let period_start: i64 = row.get (13);
+    let period_end: i64 = row.get (14);
+    InvoiceRecord {
+        side: LedgerSide::from_table (&side),
+        invoice: Invoice {
+            serving_key: Key::new (&serving_key[..]),
+            consuming_key: Key::new (&consuming_key[..]),
+            number: number as u64,
+            packages_routed: packages_routed as u64,
+            bytes_routed: bytes_routed as u64,
+            requests_exited: requests_exited as u64,
+            bytes_exited: bytes_exited as u64,
+            rates: RateSchedule {
+                routing_service_rate: row.get (8),
+                routing_byte_rate: row.get (9),
+                exit_service_rate: row.get (10),
+                exit_byte_rate: row.get (11),
+            },
+            amount: row.get (12),
+            period_start: period_start as u64,
+            period_end: period_end as u64,
+            signature: CryptData::new (&signature[..]),
+        },
+        countersignature_opt: countersignature_opt.map (|countersignature| CryptData::new (&countersignature[..])),
+    }
+}
+
+fn ledger_error (e: rusqlite::Error) -> String {
+    format! ("Ledger failure: {}", e)
+}
+
+fn to_secs (timestamp: SystemTime) -> i64 {
+    match timestamp.duration_since (UNIX_EPOCH) {
+        Ok (d) => d.as_secs () as i64,
+        Err (_) => 0
+    }
+}
+
+fn from_secs (secs: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs (secs as u64)
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs;
+    use std::path::PathBuf;
+    use sub_lib::accountant::DEFAULT_RATES;
+
+    fn data_directory (name: &str) -> PathBuf {
+        let data_directory = temp_dir ().join ("ledger").join (name);
+        fs::create_dir_all (&data_directory).unwrap ();
+        let _ = fs::remove_file (data_directory.join (LEDGER_FILENAME));
+        data_directory
+    }
+
+    fn at (seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs (seconds)
+    }
+
+    fn charge (bytes_routed: u64, bytes_exited: u64, amount: i64, seconds: u64) -> Charge {
+        Charge {bytes_routed, bytes_exited, amount, timestamp: at (seconds)}
+    }
+
+    #[test]
+    fn charges_accumulate_per_node_and_side () {
+        let mut subject = LedgerReal::in_memory ().unwrap ();
+        let alice = Key::new (b"alice");
+        let bob = Key::new (b"bob");
+
+        subject.charge (LedgerSide::Receivable, &alice, &charge (1000, 0, 1100, 10)).unwrap ();
+        subject.charge (LedgerSide::Receivable, &alice, &charge (0, 400, 900, 20)).unwrap ();
+        subject.charge (LedgerSide::Payable, &alice, &charge (50, 0, 150, 30)).unwrap ();
+        subject.charge (LedgerSide::Receivable, &bob, &charge (10, 0, 110, 40)).unwrap ();
+
+        assert_eq! (subject.account (LedgerSide::Receivable, &alice).unwrap (), Some (Account {
+            public_key: alice.clone (),
+            bytes_routed: 1000,
+            bytes_exited: 400,
+            balance: 2000,
+            first_service_timestamp: at (10),
+            last_service_timestamp: at (20),
+            last_settled_timestamp_opt: None,
+        }));
+        assert_eq! (subject.account (LedgerSide::Payable, &alice).unwrap ().unwrap ().balance, 150);
+        assert_eq! (subject.account (LedgerSide::Payable, &bob).unwrap (), None);
+        assert_eq! (subject.accounts (LedgerSide::Receivable).unwrap ().into_iter ().map (|account| account.public_key).collect::<Vec<Key>> (),
+            vec! (alice, bob));
+    }
+
+    #[test]
+    fn charges_are_written_only_when_flushed_but_are_read_back_either_way () {
+        let mut subject = LedgerReal::in_memory ().unwrap ();
+        let alice = Key::new (b"alice");
+        let bob = Key::new (b"bob");
+        subject.charge (LedgerSide::Receivable, &alice, &charge (1000, 0, 1100, 10)).unwrap ();
+        subject.charge (LedgerSide::Receivable, &bob, &charge (10, 0, 110, 20)).unwrap ();
+        subject.flush ().unwrap ();
+        subject.charge (LedgerSide::Receivable, &alice, &charge (0, 400, 900, 30)).unwrap ();
+        subject.charge (LedgerSide::Receivable, &bob, &charge (0, 0, 2000, 40)).unwrap ();
+        subject.charge (LedgerSide::Receivable, &Key::new (b"carol"), &charge (5, 0, 50, 50)).unwrap ();
+        let written_balance = |subject: &LedgerReal, public_key: &Key| -> Option<i64> {
+            let mut statement = subject.connection.prepare ("SELECT balance FROM receivables WHERE public_key = ?").unwrap ();
+            let balances = statement.query_map (&[&public_key.data as &ToSql], |row| row.get (0)).unwrap ()
+                .collect::<Result<Vec<i64>, rusqlite::Error>> ().unwrap ();
+            balances.into_iter ().next ()
+        };
+
+        let before_flush = (written_balance (&subject, &alice), written_balance (&subject, &Key::new (b"carol")));
+        let accounts = subject.accounts (LedgerSide::Receivable).unwrap ();
+        let service = subject.service_since (LedgerSide::Receivable, at (0)).unwrap ();
+        subject.flush ().unwrap ();
+
+        assert_eq! (before_flush, (Some (1100), None));
+        assert_eq! (accounts.iter ().map (|account| (account.public_key.clone (), account.balance)).collect::<Vec<(Key, i64)>> (),
+            vec! ((bob.clone (), 2110), (alice.clone (), 2000), (Key::new (b"carol"), 50)));
+        assert_eq! (accounts[1].bytes_exited, 400);
+        assert_eq! (accounts[1].first_service_timestamp, at (10));
+        assert_eq! (accounts[1].last_service_timestamp, at (30));
+        assert_eq! (service[0], (bob.clone (), ServiceTotals {bytes_routed: 10, bytes_exited: 0, amount: 2110}));
+        assert_eq! (written_balance (&subject, &alice), Some (2000));
+        assert_eq! (written_balance (&subject, &Key::new (b"carol")), Some (50));
+        assert_eq! (subject.accounts (LedgerSide::Receivable).unwrap (), accounts);
+        assert_eq! (subject.service_since (LedgerSide::Receivable, at (0)).unwrap (), service);
+    }
+
+    #[test]
+    fn service_is_totalled_per_node_from_the_hour_a_window_starts_in () {
+        let mut subject = LedgerReal::in_memory ().unwrap ();
+        let alice = Key::new (b"alice");
+        let bob = Key::new (b"bob");
+        subject.charge (LedgerSide::Receivable, &alice, &charge (1000, 0, 1100, 3599)).unwrap ();
+        subject.charge (LedgerSide::Receivable, &alice, &charge (0, 400, 900, 3600)).unwrap ();
+        subject.charge (LedgerSide::Receivable, &alice, &charge (200, 0, 300, 7300)).unwrap ();
+        subject.charge (LedgerSide::Receivable, &bob, &charge (10, 0, 110, 7000)).unwrap ();
+        subject.charge (LedgerSide::Payable, &bob, &charge (50, 0, 150, 7100)).unwrap ();
+
+        assert_eq! (subject.service_since (LedgerSide::Receivable, at (5000)).unwrap (), vec! (
+            (alice.clone (), ServiceTotals {bytes_routed: 200, bytes_exited: 400, amount: 1200}),
+            (bob.clone (), ServiceTotals {bytes_routed: 10, bytes_exited: 0, amount: 110}),
+        ));
+        assert_eq! (subject.service_since (LedgerSide::Receivable, at (0)).unwrap ()[0],
+            (alice.clone (), ServiceTotals {bytes_routed: 1200, bytes_exited: 400, amount: 2300}));
+        assert_eq! (subject.service_since (LedgerSide::Receivable, at (7200)).unwrap (), vec! (
+            (alice.clone (), ServiceTotals {bytes_routed: 200, bytes_exited: 0, amount: 300}),
+        ));
+        assert_eq! (subject.service_since (LedgerSide::Payable, at (0)).unwrap (), vec! (
+            (bob, ServiceTotals {bytes_routed: 50, bytes_exited: 0, amount: 150}),
+        ));
+    }
+
+    #[test]
+    fn a_payment_is_recorded_and_settles_the_balance () {
+        let mut subject = LedgerReal::in_memory ().unwrap ();
+        let alice = Key::new (b"alice");
+        subject.charge (LedgerSide::Payable, &alice, &charge (1000, 0, 1100, 10)).unwrap ();
+        let payment = PaymentRecord {
+            side: LedgerSide::Payable,
+            public_key: alice.clone (),
+            amount: 1000,
+            transaction_hash: String::from ("0x1234"),
+            timestamp: at (20),
+        };
+
+        subject.record_payment (&payment).unwrap ();
+
+        let account = subject.account (LedgerSide::Payable, &alice).unwrap ().unwrap ();
+        assert_eq! (account.balance, 100);
+        assert_eq! (account.last_settled_timestamp_opt, Some (at (20)));
+        assert_eq! (subject.payments (LedgerSide::Payable).unwrap (), vec! (payment));
+        assert_eq! (subject.payments (LedgerSide::Receivable).unwrap (), vec! ());
+    }
+
+    #[test]
+    fn wallets_are_kept_per_node_and_side () {
+        let mut subject = LedgerReal::in_memory ().unwrap ();
+        let alice = Key::new (b"alice");
+        let bob = Key::new (b"bob");
+        let consuming = Wallet::new ("0x3535353535353535353535353535353535353535").unwrap ();
+        let earning = Wallet::new ("0x5353535353535353535353535353535353535353").unwrap ();
+
+        subject.set_wallet (LedgerSide::Receivable, &alice, &consuming).unwrap ();
+        subject.set_wallet (LedgerSide::Payable, &alice, &earning).unwrap ();
+        subject.set_wallet (LedgerSide::Payable, &bob, &earning).unwrap ();
+
+        assert_eq! (subject.wallet (LedgerSide::Receivable, &alice).unwrap (), Some (consuming.clone ()));
+        assert_eq! (subject.wallet (LedgerSide::Payable, &alice).unwrap (), Some (earning.clone ()));
+        assert_eq! (subject.wallet (LedgerSide::Payable, &bob).unwrap (), Some (earning.clone ()));
+        assert_eq! (subject.wallet (LedgerSide::Receivable, &bob).unwrap (), None);
+        assert_eq! (subject.key_for_wallet (LedgerSide::Receivable, &consuming).unwrap (), Some (alice.clone ()));
+        assert_eq! (subject.key_for_wallet (LedgerSide::Payable, &earning).unwrap (), Some (alice));
+        assert_eq! (subject.key_for_wallet (LedgerSide::Receivable, &earning).unwrap (), None);
+    }
+
+    #[test]
+    fn a_consuming_wallet_can_t_be_claimed_by_a_second_node () {
+        let mut subject = LedgerReal::in_memory ().unwrap ();
+        let owner = Key::new (b"owner");
+        let claimant = Key::new (b"claimant");
+        let old_wallet = Wallet::new ("0x3535353535353535353535353535353535353535").unwrap ();
+        let new_wallet = Wallet::new ("0x5353535353535353535353535353535353535353").unwrap ();
+        subject.set_wallet (LedgerSide::Receivable, &owner, &old_wallet).unwrap ();
+
+        let result = subject.set_wallet (LedgerSide::Receivable, &claimant, &old_wallet);
+
+        assert_eq! (result, Err (String::from ("0x3535353535353535353535353535353535353535 already belongs to another Node")));
+        assert_eq! (subject.wallet (LedgerSide::Receivable, &claimant).unwrap (), None);
+        assert_eq! (subject.key_for_wallet (LedgerSide::Receivable, &old_wallet).unwrap (), Some (owner.clone ()));
+        subject.set_wallet (LedgerSide::Receivable, &owner, &old_wallet).unwrap ();
+        subject.set_wallet (LedgerSide::Receivable, &owner, &new_wallet).unwrap ();
+        subject.set_wallet (LedgerSide::Receivable, &claimant, &old_wallet).unwrap ();
+        assert_eq! (subject.key_for_wallet (LedgerSide::Receivable, &new_wallet).unwrap (), Some (owner));
+        assert_eq! (subject.key_for_wallet (LedgerSide::Receivable, &old_wallet).unwrap (), Some (claimant));
+    }
+
+    #[test]
+    fn channels_are_kept_per_node_and_side_and_pay_as_they_are_updated () {
+        let mut subject = LedgerReal::in_memory ().unwrap ();
+        let alice = Key::new (b"alice");
+        subject.charge (LedgerSide::Payable, &alice, &charge (1000, 0, 1100, 10)).unwrap ();
+        let old_channel = PaymentChannel {
+            channel_id: String::from ("0xC0"),
+            side: LedgerSide::Payable,
+            public_key: alice.clone (),
+            deposit: 5000,
+            balance: 5000,
+            sequence: 4,
+            signature: vec! (1, 2, 3),
+            expires_timestamp: at (5),
+            open: false,
+        };
+        let channel = PaymentChannel {
+            channel_id: String::from ("0xC1"),
+            balance: 0,
+            sequence: 0,
+            signature: vec! (),
+            expires_timestamp: at (1000),
+            open: true,
+            ..old_channel.clone ()
+        };
+        subject.save_channel (&old_channel).unwrap ();
+        subject.save_channel (&channel).unwrap ();
+        let updated = PaymentChannel {balance: 1000, sequence: 1, signature: vec! (4, 5, 6), ..channel.clone ()};
+        let payment = PaymentRecord {
+            side: LedgerSide::Payable,
+            public_key: alice.clone (),
+            amount: 1000,
+            transaction_hash: String::from ("0xC1#1"),
+            timestamp: at (20),
+        };
+
+        subject.record_channel_payment (&updated, &payment).unwrap ();
+
+        assert_eq! (subject.open_channel (LedgerSide::Payable, &alice).unwrap (), Some (updated.clone ()));
+        assert_eq! (subject.open_channel (LedgerSide::Receivable, &alice).unwrap (), None);
+        assert_eq! (subject.open_channels (LedgerSide::Payable).unwrap (), vec! (updated));
+        assert_eq! (subject.channel ("0xC0").unwrap (), Some (old_channel));
+        assert_eq! (subject.channel ("0xC2").unwrap (), None);
+        assert_eq! (subject.account (LedgerSide::Payable, &alice).unwrap ().unwrap ().balance, 100);
+        assert_eq! (subject.payments (LedgerSide::Payable).unwrap (), vec! (payment));
+    }
+
+    #[test]
+    fn the_ledger_survives_being_closed_and_reopened () {
+        let data_directory = data_directory ("the_ledger_survives_being_closed_and_reopened");
+        let alice = Key::new (b"alice");
+        {
+            let mut subject = LedgerReal::in_data_directory (&data_directory).unwrap ();
+            subject.charge (LedgerSide::Receivable, &alice, &charge (1000, 0, 1100, 10)).unwrap ();
+        }
+
+        let subject = LedgerReal::in_data_directory (&data_directory).unwrap ();
+
+        assert_eq! (subject.account (LedgerSide::Receivable, &alice).unwrap ().unwrap ().balance, 1100);
+    }
+
+    #[test]
+    fn invoices_are_kept_by_side_and_other_node_and_countersigned_ones_totalled () {
+        let mut subject = LedgerReal::in_memory ().unwrap ();
+        let me = Key::new (b"me");
+        let alice = Key::new (b"alice");
+        let invoice = |serving_key: &Key, consuming_key: &Key, number: u64, amount: i64, period_end: u64| Invoice {
+            serving_key: serving_key.clone (),
+            number,
+            amount,
+            period_end,
+            signature: CryptData::new (&[number as u8]),
+            ..Invoice::new (consuming_key, RateSchedule {exit_byte_rate: 3, ..DEFAULT_RATES}, period_end - 10)
+        };
+        let first = InvoiceRecord {side: LedgerSide::Receivable, invoice: invoice (&me, &alice, 1, 1000, 100), countersignature_opt: None};
+        let second = InvoiceRecord {side: LedgerSide::Receivable, invoice: invoice (&me, &alice, 2, 2000, 200), countersignature_opt: None};
+        let received = InvoiceRecord {side: LedgerSide::Payable, invoice: invoice (&alice, &me, 7, 500, 200), countersignature_opt: Some (CryptData::new (b"ok"))};
+        subject.save_invoice (&first).unwrap ();
+        subject.save_invoice (&second).unwrap ();
+        subject.save_invoice (&received).unwrap ();
+        let countersigned = InvoiceRecord {countersignature_opt: Some (CryptData::new (b"agreed")), ..second.clone ()};
+
+        subject.save_invoice (&countersigned).unwrap ();
+
+        assert_eq! (subject.invoice (LedgerSide::Receivable, &alice, 1).unwrap (), Some (first));
+        assert_eq! (subject.invoice (LedgerSide::Receivable, &alice, 3).unwrap (), None);
+        assert_eq! (subject.last_invoice (LedgerSide::Receivable, &alice).unwrap (), Some (countersigned));
+        assert_eq! (subject.last_invoice (LedgerSide::Payable, &alice).unwrap (), Some (received));
+        assert_eq! (subject.last_invoice (LedgerSide::Payable, &me).unwrap (), None);
+        assert_eq! (subject.countersigned_since (LedgerSide::Receivable, &alice, at (100)).unwrap (), 2000);
+        assert_eq! (subject.countersigned_since (LedgerSide::Receivable, &alice, at (201)).unwrap (), 0);
+    }
+
+    #[test]
+    fn stats_start_at_zero_and_are_replaced_when_saved () {
+        let mut subject = LedgerReal::in_memory ().unwrap ();
+        assert_eq! (subject.stats ().unwrap (), NodeStats::default ());
+        let first = NodeStats {bytes_relayed: 1000, packages_relayed: 2, bytes_exited: 300, requests_served: 1, uptime_ms: 60000};
+        let second = NodeStats {bytes_relayed: 1500, packages_relayed: 3, ..first.clone ()};
+
+        subject.save_stats (&first).unwrap ();
+        subject.save_stats (&second).unwrap ();
+
+        assert_eq! (subject.stats ().unwrap (), second);
+    }
+}
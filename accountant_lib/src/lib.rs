@@ -0,0 +1,17 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+extern crate actix;
+extern crate rand;
+extern crate rusqlite;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_cbor;
+extern crate sub_lib;
+
+#[cfg (test)]
+extern crate test_utils;
+
+pub mod accountant;
+pub mod invoice;
+pub mod ledger;
+pub mod payment_channel;
@@ -0,0 +1,134 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use serde_cbor;
+use sub_lib::cryptde::CryptData;
+use sub_lib::cryptde::CryptDE;
+use sub_lib::cryptde::Key;
+use sub_lib::cryptde::PlainData;
+use ledger::LedgerSide;
+
+// SUB a payer has locked up on the blockchain for one payee. Rather than pay on-chain for every
+// debt, the payer signs a new running total each time it pays; the payee claims the last total it
+// was sent when it closes the channel, and whatever is left of the deposit goes back to the payer.
+#[derive (Clone, Debug, PartialEq)]
+pub struct PaymentChannel {
+    // 32 random bytes in hex, chosen by the payer when it opens the channel; the channel contract
+    // knows the channel by it
+    pub channel_id: String,
+    // Payable if this Node pays through it, Receivable if this Node is paid through it
+    pub side: LedgerSide,
+    // the Node at the other end
+    pub public_key: Key,
+    pub deposit: i64,
+    // everything paid through the channel so far
+    pub balance: i64,
+    // balance updates signed so far
+    pub sequence: u64,
+    // the payer's signature on the latest update; empty before the first
+    pub signature: Vec<u8>,
+    pub expires_timestamp: SystemTime,
+    pub open: bool,
+}
+
+impl PaymentChannel {
+    pub fn remaining (&self) -> i64 {
+        self.deposit - self.balance
+    }
+
+    pub fn is_expired (&self, now: SystemTime) -> bool {
+        now >= self.expires_timestamp
+    }
+}
+
+// What a payer sends its payee in a CORES package to pay through their channel. It carries the
+// running total rather than the increment, so an update that goes astray costs nothing once the
+// next one arrives.
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BalanceUpdate {
+    pub channel_id: String,
+    pub payer_key: Key,
+    pub deposit: i64,
+    pub balance: i64,
+    pub sequence: u64,
+    // seconds since the epoch
+    pub expires: u64,
+    pub signature: CryptData,
+}
+
+impl BalanceUpdate {
+    pub fn signed (channel: &PaymentChannel, cryptde: &CryptDE) -> Result<BalanceUpdate, String> {
+        let expires = to_secs (channel.expires_timestamp);
+        let data = signed_data (&channel.channel_id, channel.deposit, channel.balance, channel.sequence, expires);
+        let signature = cryptde.sign (&data).map_err (|e| format! ("Couldn't sign balance update: {:?}", e))?;
+        Ok (BalanceUpdate {
+            channel_id: channel.channel_id.clone (),
+            payer_key: cryptde.public_key (),
+            deposit: channel.deposit,
+            balance: channel.balance,
+            sequence: channel.sequence,
+            expires,
+            signature,
+        })
+    }
+
+    pub fn verify (&self, cryptde: &CryptDE) -> bool {
+        let data = signed_data (&self.channel_id, self.deposit, self.balance, self.sequence, self.expires);
+        cryptde.verify_signature (&data, &self.signature, &self.payer_key)
+    }
+
+    pub fn expires_timestamp (&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs (self.expires)
+    }
+}
+
+fn signed_data (channel_id: &str, deposit: i64, balance: i64, sequence: u64, expires: u64) -> PlainData {
+    let serialized = serde_cbor::ser::to_vec (&(channel_id, deposit, balance, sequence, expires)).expect ("Serialization failure");
+    PlainData::new (&serialized[..])
+}
+
+fn to_secs (timestamp: SystemTime) -> u64 {
+    match timestamp.duration_since (UNIX_EPOCH) {
+        Ok (d) => d.as_secs (),
+        Err (_) => 0
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use sub_lib::cryptde_null::CryptDENull;
+
+    fn make_channel () -> PaymentChannel {
+        PaymentChannel {
+            channel_id: String::from ("0xC1"),
+            side: LedgerSide::Payable,
+            public_key: Key::new (b"payee"),
+            deposit: 10000,
+            balance: 2500,
+            sequence: 3,
+            signature: vec! (),
+            expires_timestamp: UNIX_EPOCH + Duration::from_secs (1000),
+            open: true,
+        }
+    }
+
+    #[test]
+    fn balance_updates_verify_only_as_signed_and_by_the_payer () {
+        let mut payer = CryptDENull::new ();
+        payer.generate_key_pair ();
+        let mut payee = CryptDENull::new ();
+        payee.generate_key_pair ();
+
+        let update = BalanceUpdate::signed (&make_channel (), &payer).unwrap ();
+
+        assert_eq! (update.payer_key, payer.public_key ());
+        assert_eq! (update.expires_timestamp (), UNIX_EPOCH + Duration::from_secs (1000));
+        assert_eq! (update.verify (&payee), true);
+        assert_eq! (BalanceUpdate {balance: 9999, ..update.clone ()}.verify (&payee), false);
+        assert_eq! (BalanceUpdate {payer_key: payee.public_key (), ..update.clone ()}.verify (&payee), false);
+        let serialized = serde_cbor::ser::to_vec (&update).unwrap ();
+        assert_eq! (serde_cbor::de::from_slice::<BalanceUpdate> (&serialized[..]).unwrap (), update);
+    }
+}
@@ -0,0 +1,687 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::mem;
+use std::time::Duration;
+use actix::Actor;
+use actix::Addr;
+use actix::AsyncContext;
+use actix::Context;
+use actix::Handler;
+use actix::MessageResult;
+use actix::Recipient;
+use actix::Syn;
+use sub_lib::accountant::ReportChannelMessage;
+use sub_lib::accountant::ReportPaymentReceivedMessage;
+use sub_lib::accountant::ReportTransactionMessage;
+use sub_lib::accountant::TransactionStatus;
+use sub_lib::blockchain_bridge::Balances;
+use sub_lib::blockchain_bridge::BlockchainBridgeConfig;
+use sub_lib::blockchain_bridge::BlockchainBridgeSubs;
+use sub_lib::blockchain_bridge::ChannelTerms;
+use sub_lib::blockchain_bridge::CheckChannelMsg;
+use sub_lib::blockchain_bridge::GetBalancesMsg;
+use sub_lib::blockchain_bridge::RequestTransactionMsg;
+use sub_lib::blockchain_bridge::TransactionRequest;
+use sub_lib::cryptde::Key;
+use sub_lib::logger::Logger;
+use sub_lib::peer_actors::BindMessage;
+use sub_lib::utils::NODE_MAILBOX_CAPACITY;
+use sub_lib::wallet::Wallet;
+use blockchain_rpc::BlockchainRpc;
+use blockchain_rpc::IncomingTransfer;
+use raw_transaction::TransactionSigner;
+use tiny_keccak::keccak256;
+
+const NO_WALLET: &str = "No wallet is configured";
+// About once a block
+const CONFIRMATION_CHECK_INTERVAL_MS: u64 = 15000;
+
+struct UnconfirmedTransaction {
+    request: TransactionRequest,
+    transaction_hash: String,
+}
+
+pub struct BlockchainBridge {
+    config: BlockchainBridgeConfig,
+    rpc: BlockchainRpc,
+    signer_opt: Option<Box<TransactionSigner>>,
+    earning_wallet_opt: Option<Wallet>,
+    // the newest block already searched for payments to this Node
+    last_block_watched_opt: Option<u64>,
+    // submitted for the Accountant, and not yet confirmed or failed
+    unconfirmed: Vec<UnconfirmedTransaction>,
+    to_accountant: Option<Recipient<Syn, ReportPaymentReceivedMessage>>,
+    to_accountant_transactions: Option<Recipient<Syn, ReportTransactionMessage>>,
+    to_accountant_channels: Option<Recipient<Syn, ReportChannelMessage>>,
+    logger: Logger,
+}
+
+impl Actor for BlockchainBridge {
+    type Context = Context<Self>;
+}
+
+impl Handler<BindMessage> for BlockchainBridge {
+    type Result = ();
+
+    fn handle(&mut self, msg: BindMessage, ctx: &mut Self::Context) -> Self::Result {
+        ctx.set_mailbox_capacity(NODE_MAILBOX_CAPACITY);
+        self.to_accountant = Some (msg.peer_actors.accountant.report_payment_received);
+        self.to_accountant_transactions = Some (msg.peer_actors.accountant.report_transaction);
+        self.to_accountant_channels = Some (msg.peer_actors.accountant.report_channel);
+        match self.balances () {
+            Ok (balances) => self.logger.info (format! ("Wallet {} holds {} wei and {} SUB", balances.wallet, balances.eth_wei, balances.sub)),
+            Err (e) => self.logger.warning (format! ("Couldn't check wallet balances: {}", e)),
+        }
+        if (self.config.payment_watch_interval_ms > 0) && self.earning_wallet_opt.is_some () {
+            self.watch_for_payments ();
+            ctx.run_interval (Duration::from_millis (self.config.payment_watch_interval_ms), |bridge, _ctx| {
+                bridge.watch_for_payments ()
+            });
+        }
+        ctx.run_interval (Duration::from_millis (CONFIRMATION_CHECK_INTERVAL_MS), |bridge, _ctx| {
+            bridge.check_confirmations ()
+        });
+        ()
+    }
+}
+
+impl Handler<GetBalancesMsg> for BlockchainBridge {
+    type Result = MessageResult<GetBalancesMsg>;
+
+    fn handle(&mut self, _msg: GetBalancesMsg, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult (self.balances ())
+    }
+}
+
+impl Handler<RequestTransactionMsg> for BlockchainBridge {
+    type Result = ();
+
+    fn handle(&mut self, msg: RequestTransactionMsg, _ctx: &mut Self::Context) -> Self::Result {
+        self.submit (msg.request);
+        ()
+    }
+}
+
+impl Handler<CheckChannelMsg> for BlockchainBridge {
+    type Result = ();
+
+    fn handle(&mut self, msg: CheckChannelMsg, _ctx: &mut Self::Context) -> Self::Result {
+        let result = self.check_channel (&msg.channel_id, &msg.payer_key);
+        if let Err (ref e) = result {
+            self.logger.warning (format! ("Channel {} doesn't check out: {}", msg.channel_id, e));
+        }
+        self.to_accountant_channels.as_ref ().expect ("Accountant unbound in BlockchainBridge").try_send (ReportChannelMessage {
+            channel_id: msg.channel_id,
+            result,
+        }).expect ("Accountant is dead");
+        ()
+    }
+}
+
+impl BlockchainBridge {
+    pub fn new (config: BlockchainBridgeConfig, rpc: BlockchainRpc, signer_opt: Option<Box<TransactionSigner>>) -> BlockchainBridge {
+        let earning_wallet_opt = config.earning_wallet_opt.clone ()
+            .or_else (|| signer_opt.as_ref ().map (|signer| signer.wallet ().clone ()));
+        BlockchainBridge {
+            config,
+            rpc,
+            signer_opt,
+            earning_wallet_opt,
+            last_block_watched_opt: None,
+            unconfirmed: vec! (),
+            to_accountant: None,
+            to_accountant_transactions: None,
+            to_accountant_channels: None,
+            logger: Logger::new ("BlockchainBridge"),
+        }
+    }
+
+    pub fn make_subs_from(addr: &Addr<Syn, BlockchainBridge>) -> BlockchainBridgeSubs {
+        BlockchainBridgeSubs {
+            bind: addr.clone ().recipient::<BindMessage>(),
+            get_balances: addr.clone ().recipient::<GetBalancesMsg>(),
+            request_transaction: addr.clone ().recipient::<RequestTransactionMsg>(),
+            check_channel: addr.clone ().recipient::<CheckChannelMsg>(),
+        }
+    }
+
+    fn balances (&self) -> Result<Balances, String> {
+        match self.signer_opt {
+            Some (ref signer) => self.rpc.balances (signer.wallet ()),
+            None => Err (String::from (NO_WALLET))
+        }
+    }
+
+    fn submit (&mut self, request: TransactionRequest) {
+        let result = match request {
+            TransactionRequest::Payment {ref payee, amount, ..} => self.transfer (payee, amount),
+            TransactionRequest::OpenChannel {ref channel_id, ref payer_key, ref payee, deposit, expires, ..} =>
+                self.open_channel (channel_id, payer_key, payee, deposit, expires),
+            TransactionRequest::CloseChannel {ref channel_id, balance, ref signature} => self.close_channel (channel_id, balance, signature),
+        };
+        match result {
+            Ok (transaction_hash) => {
+                self.logger.info (format! ("Submitted {} in transaction {}", request, transaction_hash));
+                self.report_transaction (request.clone (), Some (transaction_hash.clone ()), TransactionStatus::Submitted);
+                self.unconfirmed.push (UnconfirmedTransaction {request, transaction_hash});
+            },
+            Err (e) => {
+                self.logger.warning (format! ("Couldn't submit {}: {}", request, e));
+                self.report_transaction (request, None, TransactionStatus::Failed (e));
+            }
+        }
+    }
+
+    fn transfer (&self, payee: &Wallet, amount: i64) -> Result<String, String> {
+        if amount <= 0 {return Err (format! ("Can't transfer {} SUB", amount))}
+        self.sign_and_send (|rpc, signer, gas_price| rpc.transfer (signer, payee, amount as u64, gas_price))
+    }
+
+    fn open_channel (&self, channel_id: &str, payer_key: &Key, payee: &Wallet, deposit: i64, expires: u64) -> Result<String, String> {
+        if deposit <= 0 {return Err (format! ("Can't deposit {} SUB", deposit))}
+        self.sign_and_send (|rpc, signer, gas_price| rpc.open_channel (signer, channel_id, payee, deposit as u64, expires, &payer_key.data[..], gas_price))
+    }
+
+    fn close_channel (&self, channel_id: &str, balance: i64, signature: &[u8]) -> Result<String, String> {
+        if balance < 0 {return Err (format! ("Can't claim {} SUB", balance))}
+        self.sign_and_send (|rpc, signer, gas_price| rpc.close_channel (signer, channel_id, balance as u64, signature, gas_price))
+    }
+
+    fn sign_and_send<F> (&self, send: F) -> Result<String, String> where F: FnOnce (&BlockchainRpc, &TransactionSigner, u64) -> Result<String, String> {
+        match self.signer_opt {
+            Some (ref signer) => self.affordable_gas_price ().and_then (|gas_price| send (&self.rpc, signer.as_ref (), gas_price)),
+            None => Err (String::from (NO_WALLET))
+        }
+    }
+
+    // A channel only counts once its opening is mined deep enough to stay mined, and only for the Node
+    // whose key it was opened with: anyone can see a channel to this Node on the blockchain and claim it
+    fn check_channel (&self, channel_id: &str, payer_key: &Key) -> Result<ChannelTerms, String> {
+        let earning_wallet = match self.earning_wallet_opt {
+            Some (ref wallet) => wallet,
+            None => return Err (String::from (NO_WALLET))
+        };
+        let block_number = self.rpc.block_number ()?.saturating_sub (self.config.confirmations);
+        let channel = match self.rpc.channel (channel_id, block_number)? {
+            Some (channel) => channel,
+            None => return Err (String::from ("it isn't on the blockchain"))
+        };
+        if &channel.payee != earning_wallet {return Err (format! ("it pays {}, not {}", channel.payee, earning_wallet))}
+        if channel.payer_key_hash != keccak256 (&payer_key.data[..]) {return Err (String::from ("it was opened for another Node"))}
+        if !channel.open {return Err (String::from ("it's closed"))}
+        if channel.deposit > i64::max_value () as u64 {return Err (format! ("its deposit of {} SUB is too big to account for", channel.deposit))}
+        Ok (ChannelTerms {deposit: channel.deposit as i64, expires: channel.expires})
+    }
+
+    // A transaction is reported once it's mined under enough blocks to stay mined, or reverted.
+    // Until it's mined at all it just waits; the Accountant won't ask for it again meanwhile.
+    fn check_confirmations (&mut self) {
+        if self.unconfirmed.is_empty () {return}
+        let block_number = match self.rpc.block_number () {
+            Ok (block_number) => block_number,
+            Err (e) => {
+                self.logger.warning (format! ("Couldn't check for confirmations: {}", e));
+                return
+            }
+        };
+        let unconfirmed = mem::replace (&mut self.unconfirmed, vec! ());
+        for transaction in unconfirmed {
+            let receipt_result = self.rpc.transaction_receipt (&transaction.transaction_hash);
+            let receipt = match receipt_result {
+                Ok (Some (ref receipt)) if receipt.block_number + self.config.confirmations <= block_number => receipt.clone (),
+                Ok (_) => {
+                    self.unconfirmed.push (transaction);
+                    continue
+                },
+                Err (e) => {
+                    self.logger.warning (format! ("Couldn't check transaction {}: {}", transaction.transaction_hash, e));
+                    self.unconfirmed.push (transaction);
+                    continue
+                }
+            };
+            let status = if receipt.succeeded {
+                self.logger.info (format! ("Confirmed {} in transaction {}", transaction.request, transaction.transaction_hash));
+                TransactionStatus::Confirmed
+            }
+            else {
+                self.logger.warning (format! ("Transaction {} for {} was reverted", transaction.transaction_hash, transaction.request));
+                TransactionStatus::Failed (String::from ("Reverted"))
+            };
+            self.report_transaction (transaction.request, Some (transaction.transaction_hash), status);
+        }
+    }
+
+    fn report_transaction (&self, request: TransactionRequest, transaction_hash_opt: Option<String>, status: TransactionStatus) {
+        self.to_accountant_transactions.as_ref ().expect ("Accountant unbound in BlockchainBridge").try_send (ReportTransactionMessage {
+            request,
+            transaction_hash_opt,
+            status,
+        }).expect ("Accountant is dead")
+    }
+
+    // When gas is dearer than the operator will pay, the payment fails and the Accountant tries again later
+    fn affordable_gas_price (&self) -> Result<u64, String> {
+        let gas_price = match self.config.gas_price_opt {
+            Some (gas_price) => gas_price,
+            None => self.rpc.gas_price ()?
+        };
+        if gas_price > self.config.max_gas_price {
+            return Err (format! ("Gas costs {} wei, more than the limit of {}; payment deferred", gas_price, self.config.max_gas_price))
+        }
+        Ok (gas_price)
+    }
+
+    // The first look only notes where the chain is; payments confirmed before the Node started aren't
+    // news. Only blocks under enough others to stay mined are searched, so nothing reported is undone.
+    fn watch_for_payments (&mut self) {
+        let wallet = match self.earning_wallet_opt {
+            Some (ref wallet) => wallet.clone (),
+            None => return
+        };
+        let block_number = match self.rpc.block_number () {
+            Ok (block_number) => block_number.saturating_sub (self.config.confirmations),
+            Err (e) => {
+                self.logger.warning (format! ("Couldn't watch for payments: {}", e));
+                return
+            }
+        };
+        let last_block_watched_opt = self.last_block_watched_opt;
+        let from_block = match last_block_watched_opt {
+            None => {
+                self.last_block_watched_opt = Some (block_number);
+                return
+            },
+            Some (last_block_watched) if last_block_watched >= block_number => return,
+            Some (last_block_watched) => last_block_watched + 1,
+        };
+        match self.rpc.incoming_transfers (&wallet, from_block, block_number) {
+            Ok (transfers) => {
+                transfers.into_iter ().for_each (|transfer| self.report_payment (transfer));
+                self.last_block_watched_opt = Some (block_number);
+            },
+            Err (e) => self.logger.warning (format! ("Couldn't watch for payments: {}", e)),
+        }
+    }
+
+    fn report_payment (&self, transfer: IncomingTransfer) {
+        self.logger.info (format! ("Received {} SUB from {} in transaction {}", transfer.amount, transfer.payer, transfer.transaction_hash));
+        let amount = match transfer.amount.parse::<i64> () {
+            Ok (amount) => amount,
+            Err (_) => {
+                self.logger.warning (format! ("Payment of {} SUB in transaction {} is too big to account for", transfer.amount, transfer.transaction_hash));
+                return
+            }
+        };
+        self.to_accountant.as_ref ().expect ("Accountant unbound in BlockchainBridge").try_send (ReportPaymentReceivedMessage {
+            payer: transfer.payer,
+            amount,
+            transaction_hash: transfer.transaction_hash,
+        }).expect ("Accountant is dead")
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use actix::Arbiter;
+    use actix::msgs;
+    use actix::System;
+    use futures::future::Future;
+    use serde_json::Value;
+    use test_utils::test_utils::Recorder;
+    use json_rpc::JsonRpcTransport;
+    use raw_transaction::Signer;
+    use blockchain_rpc::TRANSFER_EVENT_TOPIC;
+    use hex::encode_hex;
+    use sub_lib::blockchain_bridge::DEFAULT_MAX_GAS_PRICE;
+    use sub_lib::blockchain_bridge::WEI_PER_GWEI;
+
+    struct JsonRpcTransportMock {
+        calls: Arc<Mutex<Vec<(String, Value)>>>,
+        results: RefCell<Vec<Result<Value, String>>>,
+    }
+
+    impl JsonRpcTransport for JsonRpcTransportMock {
+        fn call (&self, method: &str, params: Value) -> Result<Value, String> {
+            self.calls.lock ().unwrap ().push ((String::from (method), params));
+            self.results.borrow_mut ().remove (0)
+        }
+    }
+
+    fn make_subject (results: Vec<Result<Value, String>>, with_wallet: bool, earning_wallet_opt: Option<Wallet>) -> (BlockchainBridge, Arc<Mutex<Vec<(String, Value)>>>) {
+        make_subject_with_gas (results, with_wallet, earning_wallet_opt, None, DEFAULT_MAX_GAS_PRICE)
+    }
+
+    fn make_subject_with_gas (results: Vec<Result<Value, String>>, with_wallet: bool, earning_wallet_opt: Option<Wallet>,
+            gas_price_opt: Option<u64>, max_gas_price: u64) -> (BlockchainBridge, Arc<Mutex<Vec<(String, Value)>>>) {
+        let calls = Arc::new (Mutex::new (vec! ()));
+        let transport = JsonRpcTransportMock {calls: calls.clone (), results: RefCell::new (results)};
+        let contract = Wallet::new ("0x12480e24eb5bec1a9d4369cab6a80cad3c0a377a").unwrap ();
+        let channel_contract = Wallet::new ("0x5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a").unwrap ();
+        let config = BlockchainBridgeConfig {
+            blockchain_service_url_opt: Some (String::from ("http://localhost:8545")),
+            chain_id: 3,
+            sub_contract_address: contract.clone (),
+            channel_contract_opt: Some (channel_contract.clone ()),
+            consuming_private_key_opt: None,
+            signing_service_url_opt: None,
+            signing_wallet_opt: None,
+            earning_wallet_opt,
+            payment_watch_interval_ms: 0,
+            gas_price_opt,
+            max_gas_price,
+            confirmations: 2,
+        };
+        let signer_opt = if with_wallet {
+            let signer: Box<TransactionSigner> = Box::new (Signer::from_private_key ("4646464646464646464646464646464646464646464646464646464646464646").unwrap ());
+            Some (signer)
+        }
+        else {
+            None
+        };
+        (BlockchainBridge::new (config, BlockchainRpc::new (Box::new (transport), 3, contract, Some (channel_contract)), signer_opt), calls)
+    }
+
+    fn methods (calls: &Arc<Mutex<Vec<(String, Value)>>>) -> Vec<String> {
+        calls.lock ().unwrap ().iter ().map (|call| call.0.clone ()).collect ()
+    }
+
+    #[test]
+    fn balances_are_reported_for_the_node_s_wallet () {
+        let system = System::new ("balances_are_reported_for_the_node_s_wallet");
+        let (subject, _) = make_subject (vec! (Ok (json! ("0x400")), Ok (json! ("0x10"))), true, None);
+        let addr: Addr<Syn, BlockchainBridge> = subject.start ();
+        let sub: Recipient<Syn, GetBalancesMsg> = BlockchainBridge::make_subs_from (&addr).get_balances;
+
+        let future = sub.send (GetBalancesMsg {});
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let result = future.wait ().unwrap ();
+        assert_eq! (result, Ok (Balances {
+            wallet: Wallet::new ("0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f").unwrap (),
+            eth_wei: String::from ("1024"),
+            sub: String::from ("16"),
+        }));
+    }
+
+    fn payment (amount: i64) -> TransactionRequest {
+        TransactionRequest::Payment {
+            payee_key: Key::new (b"payee"),
+            payee: Wallet::new ("0x3535353535353535353535353535353535353535").unwrap (),
+            amount,
+        }
+    }
+
+    fn report (request: TransactionRequest, transaction_hash_opt: Option<&str>, status: TransactionStatus) -> ReportTransactionMessage {
+        ReportTransactionMessage {request, transaction_hash_opt: transaction_hash_opt.map (String::from), status}
+    }
+
+    #[test]
+    fn payments_are_sent_from_the_node_s_wallet_and_reported_once_mined_under_enough_blocks () {
+        let system = System::new ("payments_are_sent_from_the_node_s_wallet_and_reported_once_mined_under_enough_blocks");
+        let accountant = Recorder::new ();
+        let accountant_recording_arc = accountant.get_recording ();
+        let accountant_addr: Addr<Syn, Recorder> = accountant.start ();
+        let (mut subject, calls) = make_subject (vec! (
+            Ok (json! ("0x0")),
+            Ok (json! ("0x1")),
+            Ok (json! ("0xfeedface")),
+            Ok (json! ("0x11")),
+            Ok (Value::Null),
+            Ok (json! ("0x12")),
+            Ok (json! ({"transactionHash": "0xfeedface", "blockNumber": "0x11", "status": "0x1"})),
+            Ok (json! ("0x13")),
+            Ok (json! ({"transactionHash": "0xfeedface", "blockNumber": "0x11", "status": "0x1"})),
+        ), true, None);
+        subject.to_accountant_transactions = Some (accountant_addr.recipient::<ReportTransactionMessage> ());
+
+        subject.submit (payment (1024));
+        subject.check_confirmations ();
+        subject.check_confirmations ();
+        subject.check_confirmations ();
+        subject.check_confirmations ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let accountant_recording = accountant_recording_arc.lock ().unwrap ();
+        assert_eq! (accountant_recording.get_record::<ReportTransactionMessage> (0), &report (payment (1024), Some ("0xfeedface"), TransactionStatus::Submitted));
+        assert_eq! (accountant_recording.get_record::<ReportTransactionMessage> (1), &report (payment (1024), Some ("0xfeedface"), TransactionStatus::Confirmed));
+        assert_eq! (accountant_recording.len (), 2);
+        assert_eq! (methods (&calls), vec! (
+            String::from ("eth_gasPrice"), String::from ("eth_getTransactionCount"), String::from ("eth_sendRawTransaction"),
+            String::from ("eth_blockNumber"), String::from ("eth_getTransactionReceipt"),
+            String::from ("eth_blockNumber"), String::from ("eth_getTransactionReceipt"),
+            String::from ("eth_blockNumber"), String::from ("eth_getTransactionReceipt"),
+        ));
+    }
+
+    #[test]
+    fn a_configured_gas_price_is_used_without_asking () {
+        let (subject, calls) = make_subject_with_gas (vec! (Ok (json! ("0x0")), Ok (json! ("0xfeedface"))), true, None,
+            Some (3 * WEI_PER_GWEI), DEFAULT_MAX_GAS_PRICE);
+
+        let result = subject.affordable_gas_price ();
+
+        assert_eq! (result, Ok (3000000000));
+        assert_eq! (methods (&calls), Vec::<String>::new ());
+    }
+
+    #[test]
+    fn payments_are_deferred_while_gas_costs_more_than_the_limit () {
+        let system = System::new ("payments_are_deferred_while_gas_costs_more_than_the_limit");
+        let accountant = Recorder::new ();
+        let accountant_recording_arc = accountant.get_recording ();
+        let accountant_addr: Addr<Syn, Recorder> = accountant.start ();
+        let (mut subject, calls) = make_subject_with_gas (vec! (Ok (json! ("0x4a817c801"))), true, None, None, 20 * WEI_PER_GWEI);
+        subject.to_accountant_transactions = Some (accountant_addr.recipient::<ReportTransactionMessage> ());
+        let addr: Addr<Syn, BlockchainBridge> = subject.start ();
+        let sub: Recipient<Syn, RequestTransactionMsg> = BlockchainBridge::make_subs_from (&addr).request_transaction;
+
+        sub.try_send (RequestTransactionMsg {request: payment (1024)}).unwrap ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let accountant_recording = accountant_recording_arc.lock ().unwrap ();
+        assert_eq! (accountant_recording.get_record::<ReportTransactionMessage> (0), &report (payment (1024), None,
+            TransactionStatus::Failed (String::from ("Gas costs 20000000001 wei, more than the limit of 20000000000; payment deferred"))));
+        assert_eq! (methods (&calls), vec! (String::from ("eth_gasPrice")));
+    }
+
+    fn channel_id () -> String {
+        format! ("0x{}", "c1".repeat (32))
+    }
+
+    fn earning_wallet () -> Wallet {
+        Wallet::new ("0x5353535353535353535353535353535353535353").unwrap ()
+    }
+
+    // What the channel contract's channels function returns
+    fn on_chain_channel (payee: &str, payer_key: &[u8], deposit: u64, open: bool) -> Result<Value, String> {
+        Ok (json! (format! ("0x{:0>64}{:0>64}{:064x}{:064x}{}{:064x}", "3535353535353535353535353535353535353535", payee, deposit, 1200,
+            encode_hex (&keccak256 (payer_key)[..]), if open {1} else {0})))
+    }
+
+    #[test]
+    fn channels_are_opened_with_the_node_s_key_and_reported_like_payments () {
+        let system = System::new ("channels_are_opened_with_the_node_s_key_and_reported_like_payments");
+        let accountant = Recorder::new ();
+        let accountant_recording_arc = accountant.get_recording ();
+        let accountant_addr: Addr<Syn, Recorder> = accountant.start ();
+        let (mut subject, calls) = make_subject (vec! (
+            Ok (json! ("0x0")),
+            Ok (json! ("0x1")),
+            Ok (json! ("0xa9")),
+            Ok (json! ("0xc1")),
+        ), true, None);
+        subject.to_accountant_transactions = Some (accountant_addr.recipient::<ReportTransactionMessage> ());
+        let request = TransactionRequest::OpenChannel {
+            channel_id: channel_id (),
+            payer_key: Key::new (b"payer"),
+            payee_key: Key::new (b"payee"),
+            payee: Wallet::new ("0x3535353535353535353535353535353535353535").unwrap (),
+            deposit: 5000,
+            expires: 1200,
+        };
+
+        subject.submit (request.clone ());
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let accountant_recording = accountant_recording_arc.lock ().unwrap ();
+        assert_eq! (accountant_recording.get_record::<ReportTransactionMessage> (0), &report (request, Some ("0xc1"), TransactionStatus::Submitted));
+        assert_eq! (methods (&calls), vec! (
+            String::from ("eth_gasPrice"), String::from ("eth_getTransactionCount"), String::from ("eth_sendRawTransaction"), String::from ("eth_sendRawTransaction"),
+        ));
+        assert_eq! (subject.unconfirmed[0].transaction_hash, String::from ("0xc1"));
+    }
+
+    #[test]
+    fn channels_check_out_only_if_they_re_open_to_this_node_for_the_payer_s_key_under_enough_blocks () {
+        let earning_address = &earning_wallet ().address[2..];
+        let (subject, calls) = make_subject (vec! (
+            Ok (json! ("0x12")), on_chain_channel (earning_address, b"payer", 5000, true),
+            Ok (json! ("0x12")), Ok (json! (format! ("0x{}", "00".repeat (6 * 32)))),
+            Ok (json! ("0x12")), on_chain_channel ("3535353535353535353535353535353535353535", b"payer", 5000, true),
+            Ok (json! ("0x12")), on_chain_channel (earning_address, b"other", 5000, true),
+            Ok (json! ("0x12")), on_chain_channel (earning_address, b"payer", 5000, false),
+        ), false, Some (earning_wallet ()));
+        let payer_key = Key::new (b"payer");
+
+        assert_eq! (subject.check_channel (&channel_id (), &payer_key), Ok (ChannelTerms {deposit: 5000, expires: 1200}));
+        assert_eq! (subject.check_channel (&channel_id (), &payer_key), Err (String::from ("it isn't on the blockchain")));
+        assert_eq! (subject.check_channel (&channel_id (), &payer_key),
+            Err (String::from ("it pays 0x3535353535353535353535353535353535353535, not 0x5353535353535353535353535353535353535353")));
+        assert_eq! (subject.check_channel (&channel_id (), &payer_key), Err (String::from ("it was opened for another Node")));
+        assert_eq! (subject.check_channel (&channel_id (), &payer_key), Err (String::from ("it's closed")));
+        assert_eq! (calls.lock ().unwrap ()[1], (String::from ("eth_call"), json! ([{
+            "to": "0x5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a",
+            "data": format! ("0x7a7ebd7b{}", "c1".repeat (32))
+        }, "0x10"])));
+    }
+
+    #[test]
+    fn channel_checks_are_reported_to_the_accountant () {
+        let system = System::new ("channel_checks_are_reported_to_the_accountant");
+        let accountant = Recorder::new ();
+        let accountant_recording_arc = accountant.get_recording ();
+        let accountant_addr: Addr<Syn, Recorder> = accountant.start ();
+        let (mut subject, _) = make_subject (vec! (Err (String::from ("eth_blockNumber failed: boom"))), false, Some (earning_wallet ()));
+        subject.to_accountant_channels = Some (accountant_addr.recipient::<ReportChannelMessage> ());
+        let addr: Addr<Syn, BlockchainBridge> = subject.start ();
+        let sub: Recipient<Syn, CheckChannelMsg> = BlockchainBridge::make_subs_from (&addr).check_channel;
+
+        sub.try_send (CheckChannelMsg {channel_id: channel_id (), payer_key: Key::new (b"payer")}).unwrap ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let accountant_recording = accountant_recording_arc.lock ().unwrap ();
+        assert_eq! (accountant_recording.get_record::<ReportChannelMessage> (0), &ReportChannelMessage {
+            channel_id: channel_id (),
+            result: Err (String::from ("eth_blockNumber failed: boom")),
+        });
+    }
+
+    #[test]
+    fn reverted_transactions_are_reported_as_failed () {
+        let system = System::new ("reverted_transactions_are_reported_as_failed");
+        let accountant = Recorder::new ();
+        let accountant_recording_arc = accountant.get_recording ();
+        let accountant_addr: Addr<Syn, Recorder> = accountant.start ();
+        let (mut subject, _) = make_subject (vec! (
+            Ok (json! ("0x20")),
+            Ok (json! ({"transactionHash": "0xfeedface", "blockNumber": "0x1e", "status": "0x0"})),
+        ), true, None);
+        subject.to_accountant_transactions = Some (accountant_addr.recipient::<ReportTransactionMessage> ());
+        subject.unconfirmed.push (UnconfirmedTransaction {request: payment (1024), transaction_hash: String::from ("0xfeedface")});
+
+        subject.check_confirmations ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let accountant_recording = accountant_recording_arc.lock ().unwrap ();
+        assert_eq! (accountant_recording.get_record::<ReportTransactionMessage> (0), &report (payment (1024), Some ("0xfeedface"),
+            TransactionStatus::Failed (String::from ("Reverted"))));
+        assert_eq! (subject.unconfirmed.is_empty (), true);
+    }
+
+    #[test]
+    fn nothing_is_sent_or_checked_without_a_wallet () {
+        let (mut subject, calls) = make_subject (vec! (), false, None);
+
+        assert_eq! (subject.balances (), Err (String::from ("No wallet is configured")));
+        subject.watch_for_payments ();
+
+        assert_eq! (methods (&calls), Vec::<String>::new ());
+    }
+
+    #[test]
+    fn payments_to_the_earning_wallet_are_reported_to_the_accountant () {
+        let system = System::new ("payments_to_the_earning_wallet_are_reported_to_the_accountant");
+        let accountant = Recorder::new ();
+        let accountant_recording_arc = accountant.get_recording ();
+        let accountant_addr: Addr<Syn, Recorder> = accountant.start ();
+        let earning_wallet = Wallet::new ("0x5353535353535353535353535353535353535353").unwrap ();
+        let (mut subject, calls) = make_subject (vec! (
+            Ok (json! ("0x10")),
+            Ok (json! ("0x12")),
+            Ok (json! ([{
+                "topics": [
+                    TRANSFER_EVENT_TOPIC,
+                    "0x0000000000000000000000003535353535353535353535353535353535353535",
+                    "0x0000000000000000000000005353535353535353535353535353535353535353"
+                ],
+                "data": "0x0000000000000000000000000000000000000000000000000000000000000400",
+                "transactionHash": "0xfeedface",
+                "blockNumber": "0x10"
+            }])),
+        ), true, Some (earning_wallet));
+        subject.to_accountant = Some (accountant_addr.recipient::<ReportPaymentReceivedMessage> ());
+
+        subject.watch_for_payments ();
+        subject.watch_for_payments ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let accountant_recording = accountant_recording_arc.lock ().unwrap ();
+        assert_eq! (accountant_recording.get_record::<ReportPaymentReceivedMessage> (0), &ReportPaymentReceivedMessage {
+            payer: Wallet::new ("0x3535353535353535353535353535353535353535").unwrap (),
+            amount: 1024,
+            transaction_hash: String::from ("0xfeedface"),
+        });
+        assert_eq! (accountant_recording.len (), 1);
+        let calls = calls.lock ().unwrap ();
+        assert_eq! (calls[2].1[0]["topics"][2], json! ("0x0000000000000000000000005353535353535353535353535353535353535353"));
+        assert_eq! ((&calls[2].1[0]["fromBlock"], &calls[2].1[0]["toBlock"]), (&json! ("0xf"), &json! ("0x10")));
+    }
+
+    #[test]
+    fn payments_are_watched_for_in_blocks_not_yet_searched_once_they_re_confirmed () {
+        let (mut subject, calls) = make_subject (vec! (
+            Ok (json! ("0x10")),
+            Ok (json! ("0x10")),
+            Ok (json! ("0x12")),
+            Ok (json! ([])),
+            Err (String::from ("eth_blockNumber failed: boom")),
+        ), true, None);
+
+        subject.watch_for_payments ();
+        subject.watch_for_payments ();
+        subject.watch_for_payments ();
+        subject.watch_for_payments ();
+
+        assert_eq! (methods (&calls), vec! (
+            String::from ("eth_blockNumber"),
+            String::from ("eth_blockNumber"),
+            String::from ("eth_blockNumber"),
+            String::from ("eth_getLogs"),
+            String::from ("eth_blockNumber"),
+        ));
+        let calls = calls.lock ().unwrap ();
+        assert_eq! (calls[3].1[0]["fromBlock"], json! ("0xf"));
+        assert_eq! (calls[3].1[0]["toBlock"], json! ("0x10"));
+        assert_eq! (subject.last_block_watched_opt, Some (0x10));
+    }
+}
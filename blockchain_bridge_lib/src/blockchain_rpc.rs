@@ -0,0 +1,483 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use serde_json::Value;
+use sub_lib::blockchain_bridge::Balances;
+use sub_lib::wallet::Wallet;
+use hex::decode_hex;
+use hex::encode_hex;
+use hex::from_quantity;
+use hex::quantity_to_decimal;
+use hex::to_quantity;
+use json_rpc::JsonRpcTransport;
+use raw_transaction::RawTransaction;
+use raw_transaction::TransactionSigner;
+
+// keccak256 ("Transfer(address,address,uint256)"): the event an ERC-20 contract logs for every transfer
+pub const TRANSFER_EVENT_TOPIC: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+// The first four bytes of keccak256 ("transfer(address,uint256)") and keccak256 ("balanceOf(address)")
+pub const TRANSFER_SELECTOR: [u8; 4] = [0xa9, 0x05, 0x9c, 0xbb];
+pub const BALANCE_OF_SELECTOR: [u8; 4] = [0x70, 0xa0, 0x82, 0x31];
+// keccak256 ("approve(address,uint256)"), for the SUB contract to let the channel contract take a deposit
+pub const APPROVE_SELECTOR: [u8; 4] = [0x09, 0x5e, 0xa7, 0xb3];
+// The channel contract's keccak256 ("openChannel(bytes32,address,uint256,uint256,bytes)"),
+// keccak256 ("closeChannel(bytes32,uint256,bytes)") and keccak256 ("channels(bytes32)"). It keeps
+// each payer's public key from openChannel and checks closeChannel's signature against it;
+// channels returns (address payer, address payee, uint256 deposit, uint256 expires,
+// bytes32 keccak256 (payer key), bool open), all zeros for a channel it's never heard of.
+pub const OPEN_CHANNEL_SELECTOR: [u8; 4] = [0xf5, 0x18, 0x6e, 0x2a];
+pub const CLOSE_CHANNEL_SELECTOR: [u8; 4] = [0xba, 0xc0, 0x68, 0xce];
+pub const CHANNELS_SELECTOR: [u8; 4] = [0x7a, 0x7e, 0xbd, 0x7b];
+const TRANSFER_GAS_LIMIT: u64 = 100000;
+const CHANNEL_GAS_LIMIT: u64 = 200000;
+const NO_CHANNEL_CONTRACT: &str = "No channel contract is configured";
+
+#[derive (Clone, Debug, PartialEq)]
+pub struct IncomingTransfer {
+    pub payer: Wallet,
+    // decimal, in the smallest unit of SUB
+    pub amount: String,
+    pub transaction_hash: String,
+    pub block_number: u64,
+}
+
+#[derive (Clone, Debug, PartialEq)]
+pub struct TransactionReceipt {
+    pub block_number: u64,
+    // false if the transaction was reverted
+    pub succeeded: bool,
+}
+
+// A payment channel as the channel contract keeps it
+#[derive (Clone, Debug, PartialEq)]
+pub struct OnChainChannel {
+    pub payer: Wallet,
+    pub payee: Wallet,
+    pub deposit: u64,
+    // seconds since the epoch
+    pub expires: u64,
+    // keccak256 of the public key the payer signs its balance updates with
+    pub payer_key_hash: [u8; 32],
+    pub open: bool,
+}
+
+// The Ethereum JSON-RPC calls the Node needs to hold, send, and receive SUB
+pub struct BlockchainRpc {
+    transport: Box<JsonRpcTransport>,
+    chain_id: u64,
+    contract: Wallet,
+    channel_contract_opt: Option<Wallet>,
+}
+
+impl BlockchainRpc {
+    pub fn new (transport: Box<JsonRpcTransport>, chain_id: u64, contract: Wallet, channel_contract_opt: Option<Wallet>) -> BlockchainRpc {
+        BlockchainRpc {transport, chain_id, contract, channel_contract_opt}
+    }
+
+    pub fn balances (&self, wallet: &Wallet) -> Result<Balances, String> {
+        let eth_wei = self.transport.call ("eth_getBalance", json! ([wallet.address, "latest"]))?;
+        let data = call_data (&BALANCE_OF_SELECTOR, &[address_word (wallet)]);
+        let sub = self.transport.call ("eth_call", json! ([{"to": self.contract.address, "data": data}, "latest"]))?;
+        Ok (Balances {
+            wallet: wallet.clone (),
+            eth_wei: quantity_to_decimal (as_str (&eth_wei)?)?,
+            sub: quantity_to_decimal (as_str (&sub)?)?,
+        })
+    }
+
+    // Wei per unit of gas, as the Ethereum node reckons it from recent blocks
+    pub fn gas_price (&self) -> Result<u64, String> {
+        from_quantity (as_str (&self.transport.call ("eth_gasPrice", json! ([]))?)?)
+    }
+
+    // Returns the hash of the submitted transaction; it still has to be mined to count
+    pub fn transfer (&self, signer: &TransactionSigner, payee: &Wallet, amount: u64, gas_price: u64) -> Result<String, String> {
+        let nonce = self.next_nonce (signer)?;
+        let data = call_data (&TRANSFER_SELECTOR, &[address_word (payee), amount_word (amount)]);
+        self.send_transaction (signer, nonce, &self.contract, &data, TRANSFER_GAS_LIMIT, gas_price)
+    }
+
+    // The SUB contract is told to let the channel contract take the deposit, and the channel is
+    // opened in the next transaction; if the approval doesn't go through, neither does the opening.
+    // Returns the hash of the opening.
+    pub fn open_channel (&self, signer: &TransactionSigner, channel_id: &str, payee: &Wallet, deposit: u64, expires: u64, payer_key: &[u8],
+                         gas_price: u64) -> Result<String, String> {
+        let channel_contract = self.channel_contract ()?;
+        let open_data = call_data (&OPEN_CHANNEL_SELECTOR, &[
+            channel_word (channel_id)?, address_word (payee), amount_word (deposit), amount_word (expires), amount_word (5 * 32), bytes_words (payer_key),
+        ]);
+        let nonce = self.next_nonce (signer)?;
+        let approve_data = call_data (&APPROVE_SELECTOR, &[address_word (channel_contract), amount_word (deposit)]);
+        self.send_transaction (signer, nonce, &self.contract, &approve_data, TRANSFER_GAS_LIMIT, gas_price)?;
+        self.send_transaction (signer, nonce + 1, channel_contract, &open_data, CHANNEL_GAS_LIMIT, gas_price)
+    }
+
+    // Claims the balance the payer signed for; the rest of the deposit goes back to the payer
+    pub fn close_channel (&self, signer: &TransactionSigner, channel_id: &str, balance: u64, signature: &[u8], gas_price: u64) -> Result<String, String> {
+        let channel_contract = self.channel_contract ()?;
+        let data = call_data (&CLOSE_CHANNEL_SELECTOR, &[channel_word (channel_id)?, amount_word (balance), amount_word (3 * 32), bytes_words (signature)]);
+        let nonce = self.next_nonce (signer)?;
+        self.send_transaction (signer, nonce, channel_contract, &data, CHANNEL_GAS_LIMIT, gas_price)
+    }
+
+    // As of the given block; None if the channel contract has never heard of it
+    pub fn channel (&self, channel_id: &str, block_number: u64) -> Result<Option<OnChainChannel>, String> {
+        let data = call_data (&CHANNELS_SELECTOR, &[channel_word (channel_id)?]);
+        let result = self.transport.call ("eth_call", json! ([{"to": self.channel_contract ()?.address, "data": data}, to_quantity (block_number)]))?;
+        channel_from_result (as_str (&result)?)
+    }
+
+    pub fn block_number (&self) -> Result<u64, String> {
+        from_quantity (as_str (&self.transport.call ("eth_blockNumber", json! ([]))?)?)
+    }
+
+    // None until the transaction is mined
+    pub fn transaction_receipt (&self, transaction_hash: &str) -> Result<Option<TransactionReceipt>, String> {
+        let receipt = self.transport.call ("eth_getTransactionReceipt", json! ([transaction_hash]))?;
+        if receipt.is_null () || receipt["blockNumber"].is_null () {return Ok (None)}
+        Ok (Some (TransactionReceipt {
+            block_number: from_quantity (as_str (&receipt["blockNumber"])?)?,
+            succeeded: from_quantity (as_str (&receipt["status"])?)? == 1,
+        }))
+    }
+
+    // SUB transfers into the wallet mined in the given range of blocks, inclusive
+    pub fn incoming_transfers (&self, wallet: &Wallet, from_block: u64, to_block: u64) -> Result<Vec<IncomingTransfer>, String> {
+        let logs = self.transport.call ("eth_getLogs", json! ([{
+            "fromBlock": to_quantity (from_block),
+            "toBlock": to_quantity (to_block),
+            "address": self.contract.address,
+            "topics": [TRANSFER_EVENT_TOPIC, null, format! ("0x{}", address_word (wallet))]
+        }]))?;
+        match logs.as_array () {
+            Some (logs) => logs.iter ().map (transfer_from_log).collect (),
+            None => Err (String::from ("eth_getLogs returned something other than a list of logs"))
+        }
+    }
+
+    fn channel_contract (&self) -> Result<&Wallet, String> {
+        self.channel_contract_opt.as_ref ().ok_or (String::from (NO_CHANNEL_CONTRACT))
+    }
+
+    fn next_nonce (&self, signer: &TransactionSigner) -> Result<u64, String> {
+        let nonce = self.transport.call ("eth_getTransactionCount", json! ([signer.wallet ().address, "pending"]))?;
+        from_quantity (as_str (&nonce)?)
+    }
+
+    fn send_transaction (&self, signer: &TransactionSigner, nonce: u64, to: &Wallet, data: &str, gas_limit: u64, gas_price: u64) -> Result<String, String> {
+        let transaction = RawTransaction {
+            nonce,
+            gas_price,
+            gas_limit,
+            to: to.clone (),
+            value: 0,
+            data: decode_hex (data)?,
+        };
+        let signed = signer.sign_transaction (&transaction, self.chain_id)?;
+        let transaction_hash = self.transport.call ("eth_sendRawTransaction", json! ([format! ("0x{}", encode_hex (&signed[..]))]))?;
+        Ok (String::from (as_str (&transaction_hash)?))
+    }
+}
+
+fn channel_from_result (result: &str) -> Result<Option<OnChainChannel>, String> {
+    let data = decode_hex (result)?;
+    if data.len () != 6 * 32 {return Err (format! ("channels returned {} bytes rather than 6 words", data.len ()))}
+    if word (&data, 0).iter ().all (|byte| *byte == 0) {return Ok (None)}
+    let mut payer_key_hash = [0u8; 32];
+    payer_key_hash.copy_from_slice (word (&data, 4));
+    Ok (Some (OnChainChannel {
+        payer: Wallet::new (&encode_hex (&word (&data, 0)[12..]))?,
+        payee: Wallet::new (&encode_hex (&word (&data, 1)[12..]))?,
+        deposit: word_to_u64 (word (&data, 2))?,
+        expires: word_to_u64 (word (&data, 3))?,
+        payer_key_hash,
+        open: word_to_u64 (word (&data, 5))? != 0,
+    }))
+}
+
+fn word (data: &[u8], index: usize) -> &[u8] {
+    &data[index * 32..(index + 1) * 32]
+}
+
+fn word_to_u64 (word: &[u8]) -> Result<u64, String> {
+    if word[..24].iter ().any (|byte| *byte != 0) {return Err (format! ("0x{} is too big", encode_hex (word)))}
+    Ok (word[24..].iter ().fold (0, |value, byte| (value << 8) | *byte as u64))
+}
+
+fn transfer_from_log (log: &Value) -> Result<IncomingTransfer, String> {
+    let payer_topic = as_str (&log["topics"][1])?;
+    if payer_topic.len () < 40 {return Err (format! ("Transfer log has a malformed payer: '{}'", payer_topic))}
+    Ok (IncomingTransfer {
+        payer: Wallet::new (&payer_topic[payer_topic.len () - 40..])?,
+        amount: quantity_to_decimal (as_str (&log["data"])?)?,
+        transaction_hash: String::from (as_str (&log["transactionHash"])?),
+        block_number: from_quantity (as_str (&log["blockNumber"])?)?,
+    })
+}
+
+fn as_str (value: &Value) -> Result<&str, String> {
+    value.as_str ().ok_or (format! ("Expected a string, not {}", value))
+}
+
+// ABI encoding: the selector, then each argument in a 32-byte word
+fn call_data (selector: &[u8; 4], words: &[String]) -> String {
+    format! ("0x{}{}", encode_hex (&selector[..]), words.concat ())
+}
+
+fn address_word (wallet: &Wallet) -> String {
+    format! ("{:0>64}", &wallet.address[2..])
+}
+
+fn amount_word (amount: u64) -> String {
+    format! ("{:064x}", amount)
+}
+
+// Channel ids are 32 bytes, in hex
+fn channel_word (channel_id: &str) -> Result<String, String> {
+    let bytes = decode_hex (channel_id)?;
+    if bytes.len () != 32 {return Err (format! ("Invalid channel id: '{}'", channel_id))}
+    Ok (encode_hex (&bytes[..]))
+}
+
+// A dynamic bytes argument's tail: its length, then the bytes padded out to whole words
+fn bytes_words (data: &[u8]) -> String {
+    let padding = (32 - data.len () % 32) % 32;
+    format! ("{:064x}{}{}", data.len (), encode_hex (data), "00".repeat (padding))
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use raw_transaction::Signer;
+
+    struct JsonRpcTransportMock {
+        calls: Arc<Mutex<Vec<(String, Value)>>>,
+        results: RefCell<Vec<Result<Value, String>>>,
+    }
+
+    impl JsonRpcTransport for JsonRpcTransportMock {
+        fn call (&self, method: &str, params: Value) -> Result<Value, String> {
+            self.calls.lock ().unwrap ().push ((String::from (method), params));
+            self.results.borrow_mut ().remove (0)
+        }
+    }
+
+    fn make_subject (results: Vec<Result<Value, String>>) -> (BlockchainRpc, Arc<Mutex<Vec<(String, Value)>>>) {
+        let calls = Arc::new (Mutex::new (vec! ()));
+        let transport = JsonRpcTransportMock {calls: calls.clone (), results: RefCell::new (results)};
+        (BlockchainRpc::new (Box::new (transport), 3, contract (), Some (channel_contract ())), calls)
+    }
+
+    fn channel_contract () -> Wallet {
+        Wallet::new ("0x5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a").unwrap ()
+    }
+
+    fn channel_id () -> String {
+        format! ("0x{}", "c1".repeat (32))
+    }
+
+    fn contract () -> Wallet {
+        Wallet::new ("0x12480e24eb5bec1a9d4369cab6a80cad3c0a377a").unwrap ()
+    }
+
+    fn wallet () -> Wallet {
+        Wallet::new ("0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f").unwrap ()
+    }
+
+    #[test]
+    fn balances_come_from_the_node_and_the_contract () {
+        let (subject, calls) = make_subject (vec! (
+            Ok (json! ("0x3635c9adc5dea00000")),
+            Ok (json! ("0x0000000000000000000000000000000000000000000000000000000000000400")),
+        ));
+
+        let result = subject.balances (&wallet ());
+
+        assert_eq! (result, Ok (Balances {wallet: wallet (), eth_wei: String::from ("1000000000000000000000"), sub: String::from ("1024")}));
+        assert_eq! (*calls.lock ().unwrap (), vec! (
+            (String::from ("eth_getBalance"), json! (["0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f", "latest"])),
+            (String::from ("eth_call"), json! ([{
+                "to": "0x12480e24eb5bec1a9d4369cab6a80cad3c0a377a",
+                "data": "0x70a082310000000000000000000000009d8a62f656a8d1615c1294fd71e9cfb3e4855a4f"
+            }, "latest"])),
+        ));
+    }
+
+    #[test]
+    fn transfers_are_signed_and_submitted_to_the_contract () {
+        let signer = Signer::from_private_key ("4646464646464646464646464646464646464646464646464646464646464646").unwrap ();
+        let payee = Wallet::new ("0x3535353535353535353535353535353535353535").unwrap ();
+        let (subject, calls) = make_subject (vec! (
+            Ok (json! ("0x9")),
+            Ok (json! ("0xfeedface")),
+        ));
+
+        let result = subject.transfer (&signer, &payee, 1024, 0x4a817c800);
+
+        assert_eq! (result, Ok (String::from ("0xfeedface")));
+        let calls = calls.lock ().unwrap ();
+        assert_eq! (calls[0], (String::from ("eth_getTransactionCount"), json! (["0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f", "pending"])));
+        assert_eq! (calls[1].0, String::from ("eth_sendRawTransaction"));
+        let raw_transaction = calls[1].1[0].as_str ().unwrap ();
+        assert_eq! (raw_transaction.contains ("098504a817c800830186a09412480e24eb5bec1a9d4369cab6a80cad3c0a377a80b844a9059cbb"), true);
+        assert_eq! (raw_transaction.contains ("0000000000000000000000003535353535353535353535353535353535353535\
+            0000000000000000000000000000000000000000000000000000000000000400"), true);
+    }
+
+    #[test]
+    fn the_gas_price_comes_from_the_node () {
+        let (subject, calls) = make_subject (vec! (Ok (json! ("0x4a817c800"))));
+
+        let result = subject.gas_price ();
+
+        assert_eq! (result, Ok (20000000000));
+        assert_eq! (*calls.lock ().unwrap (), vec! ((String::from ("eth_gasPrice"), json! ([]))));
+    }
+
+    #[test]
+    fn incoming_transfers_are_read_from_the_contract_s_logs () {
+        let (subject, calls) = make_subject (vec! (Ok (json! ([{
+            "topics": [
+                TRANSFER_EVENT_TOPIC,
+                "0x0000000000000000000000003535353535353535353535353535353535353535",
+                "0x0000000000000000000000009d8a62f656a8d1615c1294fd71e9cfb3e4855a4f"
+            ],
+            "data": "0x0000000000000000000000000000000000000000000000000000000000000400",
+            "transactionHash": "0xfeedface",
+            "blockNumber": "0x10"
+        }]))));
+
+        let result = subject.incoming_transfers (&wallet (), 15, 16);
+
+        assert_eq! (result, Ok (vec! (IncomingTransfer {
+            payer: Wallet::new ("0x3535353535353535353535353535353535353535").unwrap (),
+            amount: String::from ("1024"),
+            transaction_hash: String::from ("0xfeedface"),
+            block_number: 16,
+        })));
+        assert_eq! (*calls.lock ().unwrap (), vec! ((String::from ("eth_getLogs"), json! ([{
+            "fromBlock": "0xf",
+            "toBlock": "0x10",
+            "address": "0x12480e24eb5bec1a9d4369cab6a80cad3c0a377a",
+            "topics": [TRANSFER_EVENT_TOPIC, null, "0x0000000000000000000000009d8a62f656a8d1615c1294fd71e9cfb3e4855a4f"]
+        }]))));
+    }
+
+    #[test]
+    fn receipts_say_where_transactions_were_mined_and_whether_they_succeeded () {
+        let (subject, calls) = make_subject (vec! (
+            Ok (json! ({"transactionHash": "0xfeedface", "blockNumber": "0x10", "status": "0x1"})),
+            Ok (json! ({"transactionHash": "0xdeadbeef", "blockNumber": "0x11", "status": "0x0"})),
+            Ok (Value::Null),
+        ));
+
+        assert_eq! (subject.transaction_receipt ("0xfeedface"), Ok (Some (TransactionReceipt {block_number: 16, succeeded: true})));
+        assert_eq! (subject.transaction_receipt ("0xdeadbeef"), Ok (Some (TransactionReceipt {block_number: 17, succeeded: false})));
+        assert_eq! (subject.transaction_receipt ("0xbadcafe"), Ok (None));
+        assert_eq! (calls.lock ().unwrap ()[2], (String::from ("eth_getTransactionReceipt"), json! (["0xbadcafe"])));
+    }
+
+    #[test]
+    fn channels_are_opened_once_the_channel_contract_is_allowed_to_take_the_deposit () {
+        let signer = Signer::from_private_key ("4646464646464646464646464646464646464646464646464646464646464646").unwrap ();
+        let payee = Wallet::new ("0x3535353535353535353535353535353535353535").unwrap ();
+        let (subject, calls) = make_subject (vec! (
+            Ok (json! ("0x9")),
+            Ok (json! ("0xa9")),
+            Ok (json! ("0xc1")),
+        ));
+
+        let result = subject.open_channel (&signer, &channel_id (), &payee, 5000, 1200, b"payer", 0x4a817c800);
+
+        assert_eq! (result, Ok (String::from ("0xc1")));
+        let calls = calls.lock ().unwrap ();
+        assert_eq! (calls.iter ().map (|call| call.0.clone ()).collect::<Vec<String>> (), vec! (
+            String::from ("eth_getTransactionCount"), String::from ("eth_sendRawTransaction"), String::from ("eth_sendRawTransaction"),
+        ));
+        let approval = calls[1].1[0].as_str ().unwrap ();
+        assert_eq! (approval.contains ("098504a817c800830186a09412480e24eb5bec1a9d4369cab6a80cad3c0a377a80b844095ea7b3\
+            0000000000000000000000005a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a\
+            0000000000000000000000000000000000000000000000000000000000001388"), true);
+        let opening = calls[2].1[0].as_str ().unwrap ();
+        assert_eq! (opening.contains ("0a8504a817c80083030d40945a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a80b8e4f5186e2a\
+            c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1c1\
+            0000000000000000000000003535353535353535353535353535353535353535\
+            0000000000000000000000000000000000000000000000000000000000001388\
+            00000000000000000000000000000000000000000000000000000000000004b0\
+            00000000000000000000000000000000000000000000000000000000000000a0\
+            0000000000000000000000000000000000000000000000000000000000000005\
+            7061796572000000000000000000000000000000000000000000000000000000"), true);
+    }
+
+    #[test]
+    fn channels_are_closed_with_the_payer_s_signature_on_the_balance () {
+        let signer = Signer::from_private_key ("4646464646464646464646464646464646464646464646464646464646464646").unwrap ();
+        let (subject, calls) = make_subject (vec! (
+            Ok (json! ("0x9")),
+            Ok (json! ("0xd1")),
+        ));
+
+        let result = subject.close_channel (&signer, &channel_id (), 3000, &[0xAB; 40], 0x4a817c800);
+
+        assert_eq! (result, Ok (String::from ("0xd1")));
+        let closing = calls.lock ().unwrap ()[1].1[0].as_str ().unwrap ().to_string ();
+        assert_eq! (closing.contains (&format! ("945a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a80b8c4bac068ce{}\
+            0000000000000000000000000000000000000000000000000000000000000bb8\
+            0000000000000000000000000000000000000000000000000000000000000060\
+            0000000000000000000000000000000000000000000000000000000000000028\
+            {}{}", "c1".repeat (32), "ab".repeat (40), "00".repeat (24))), true);
+    }
+
+    #[test]
+    fn channels_are_read_from_the_channel_contract_as_of_a_block () {
+        let (subject, calls) = make_subject (vec! (
+            Ok (json! (format! ("0x{}{}{}{}{}{}",
+                "0000000000000000000000009d8a62f656a8d1615c1294fd71e9cfb3e4855a4f",
+                "0000000000000000000000003535353535353535353535353535353535353535",
+                "0000000000000000000000000000000000000000000000000000000000001388",
+                "00000000000000000000000000000000000000000000000000000000000004b0",
+                "77".repeat (32),
+                "0000000000000000000000000000000000000000000000000000000000000001",
+            ))),
+            Ok (json! (format! ("0x{}", "00".repeat (6 * 32)))),
+        ));
+
+        let result = subject.channel (&channel_id (), 0x10);
+        let missing = subject.channel (&channel_id (), 0x10);
+
+        assert_eq! (result, Ok (Some (OnChainChannel {
+            payer: wallet (),
+            payee: Wallet::new ("0x3535353535353535353535353535353535353535").unwrap (),
+            deposit: 5000,
+            expires: 1200,
+            payer_key_hash: [0x77; 32],
+            open: true,
+        })));
+        assert_eq! (missing, Ok (None));
+        assert_eq! (calls.lock ().unwrap ()[0], (String::from ("eth_call"), json! ([{
+            "to": "0x5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a",
+            "data": format! ("0x7a7ebd7b{}", "c1".repeat (32))
+        }, "0x10"])));
+    }
+
+    #[test]
+    fn channels_need_a_channel_contract_and_a_proper_id () {
+        let signer = Signer::from_private_key ("4646464646464646464646464646464646464646464646464646464646464646").unwrap ();
+        let (subject, calls) = make_subject (vec! ());
+        let without_contract = BlockchainRpc::new (Box::new (JsonRpcTransportMock {calls: calls.clone (), results: RefCell::new (vec! ())}),
+            3, contract (), None);
+
+        assert_eq! (without_contract.channel (&channel_id (), 0x10), Err (String::from ("No channel contract is configured")));
+        assert_eq! (without_contract.close_channel (&signer, &channel_id (), 3000, &[], 1), Err (String::from ("No channel contract is configured")));
+        assert_eq! (subject.channel ("0xC1", 0x10), Err (String::from ("Invalid channel id: '0xC1'")));
+        assert_eq! (calls.lock ().unwrap ().len (), 0);
+    }
+
+    #[test]
+    fn node_errors_are_passed_along () {
+        let (subject, _) = make_subject (vec! (Err (String::from ("eth_blockNumber failed: boom"))));
+
+        assert_eq! (subject.block_number (), Err (String::from ("eth_blockNumber failed: boom")));
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use bip39::Language;
+use bip39::Mnemonic;
+use bip39::MnemonicType;
+use hmac::Hmac;
+use hmac::Mac;
+use secp256k1::PublicKey;
+use secp256k1::Secp256k1;
+use secp256k1::SecretKey;
+use sha2::Sha512;
+use hex::encode_hex;
+
+// The first account of the first Ethereum wallet, where MetaMask, MyEtherWallet and hardware wallets look
+pub const DEFAULT_DERIVATION_PATH: &str = "m/44'/60'/0'/0/0";
+
+const HARDENED: u32 = 0x80000000;
+
+// A fresh BIP39 recovery phrase of 12 or 24 English words
+pub fn generate_mnemonic (word_count: usize) -> Result<String, String> {
+    let mnemonic_type = match word_count {
+        12 | 24 => MnemonicType::for_word_count (word_count).expect ("Internal error"),
+        _ => return Err (format! ("A recovery phrase has 12 or 24 words, not {}", word_count))
+    };
+    let mnemonic = Mnemonic::new (mnemonic_type, Language::English, "")
+        .map_err (|e| format! ("Couldn't generate a recovery phrase: {}", e))?;
+    Ok (mnemonic.get_string ())
+}
+
+// The hex private key at a BIP44 path under the seed a BIP39 phrase and its passphrase make
+pub fn private_key_from_mnemonic (phrase: &str, passphrase: &str, derivation_path: &str) -> Result<String, String> {
+    let mnemonic = Mnemonic::from_string (phrase, Language::English, passphrase)
+        .map_err (|e| format! ("Not a valid recovery phrase: {}", e))?;
+    let path = parse_derivation_path (derivation_path)?;
+    let secret_key = derive_private_key (mnemonic.get_seed ().as_bytes (), &path)?;
+    Ok (encode_hex (&secret_key[..]))
+}
+
+// BIP32 private derivation: hardened children hash the parent private key, the others its public key
+fn derive_private_key (seed: &[u8], path: &Vec<u32>) -> Result<SecretKey, String> {
+    let secp = Secp256k1::new ();
+    let master = hmac_sha512 (b"Bitcoin seed", seed);
+    let mut secret_key = SecretKey::from_slice (&secp, &master[..32])
+        .map_err (|_| String::from ("Seed makes an unusable master key"))?;
+    let mut chain_code = master[32..].to_vec ();
+    for &index in path {
+        let mut data = if index & HARDENED != 0 {
+            let mut data = vec! (0u8);
+            data.extend_from_slice (&secret_key[..]);
+            data
+        }
+        else {
+            PublicKey::from_secret_key (&secp, &secret_key).serialize ().to_vec ()
+        };
+        data.extend_from_slice (&[(index >> 24) as u8, (index >> 16) as u8, (index >> 8) as u8, index as u8]);
+        let digest = hmac_sha512 (&chain_code[..], &data[..]);
+        let tweak = SecretKey::from_slice (&secp, &digest[..32])
+            .map_err (|_| format! ("Child key {} is unusable; try another path", index & !HARDENED))?;
+        secret_key.add_assign (&secp, &tweak)
+            .map_err (|_| format! ("Child key {} is unusable; try another path", index & !HARDENED))?;
+        chain_code = digest[32..].to_vec ();
+    }
+    Ok (secret_key)
+}
+
+fn hmac_sha512 (key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha512>::new_varkey (key).expect ("Internal error");
+    mac.input (data);
+    mac.result ().code ().to_vec ()
+}
+
+// Like m/44'/60'/0'/0/0; an apostrophe or h marks a hardened index
+fn parse_derivation_path (derivation_path: &str) -> Result<Vec<u32>, String> {
+    let complaint = || format! ("Not a derivation path: '{}'", derivation_path);
+    let mut pieces = derivation_path.split ('/');
+    if pieces.next () != Some ("m") {return Err (complaint ())}
+    pieces.map (|piece| {
+        let (digits, hardened) = if piece.ends_with ('\'') || piece.ends_with ('h') {
+            (&piece[..piece.len () - 1], true)
+        }
+        else {
+            (piece, false)
+        };
+        match digits.parse::<u32> () {
+            Ok (index) if index < HARDENED => Ok (if hardened {index | HARDENED} else {index}),
+            _ => Err (complaint ())
+        }
+    }).collect ()
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use hex::decode_hex;
+    use raw_transaction::Signer;
+    use sub_lib::wallet::Wallet;
+
+    const PHRASE: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn derivation_matches_the_bip32_test_vectors () {
+        let seed = decode_hex ("000102030405060708090a0b0c0d0e0f").unwrap ();
+
+        let master = derive_private_key (&seed[..], &vec! ()).unwrap ();
+        let child = derive_private_key (&seed[..], &parse_derivation_path ("m/0'").unwrap ()).unwrap ();
+
+        assert_eq! (encode_hex (&master[..]), String::from ("e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35"));
+        assert_eq! (encode_hex (&child[..]), String::from ("edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea"));
+    }
+
+    #[test]
+    fn a_phrase_recovers_the_same_ethereum_wallet_other_wallets_do () {
+        let private_key = private_key_from_mnemonic (PHRASE, "", DEFAULT_DERIVATION_PATH).unwrap ();
+
+        let signer = Signer::from_private_key (&private_key).unwrap ();
+        assert_eq! (signer.wallet (), &Wallet::new ("0x9858EfFD232B4033E47d90003D41EC34EcaEda94").unwrap ());
+    }
+
+    #[test]
+    fn the_passphrase_and_path_each_make_a_different_wallet () {
+        let plain = private_key_from_mnemonic (PHRASE, "", DEFAULT_DERIVATION_PATH).unwrap ();
+        let with_passphrase = private_key_from_mnemonic (PHRASE, "TREZOR", DEFAULT_DERIVATION_PATH).unwrap ();
+        let second_account = private_key_from_mnemonic (PHRASE, "", "m/44'/60'/0'/0/1").unwrap ();
+
+        assert_ne! (plain, with_passphrase);
+        assert_ne! (plain, second_account);
+        assert_ne! (with_passphrase, second_account);
+    }
+
+    #[test]
+    fn generated_phrases_have_the_requested_length_and_can_be_recovered () {
+        let short = generate_mnemonic (12).unwrap ();
+        let long = generate_mnemonic (24).unwrap ();
+
+        assert_eq! (short.split (' ').count (), 12);
+        assert_eq! (long.split (' ').count (), 24);
+        assert_eq! (private_key_from_mnemonic (&long, "", DEFAULT_DERIVATION_PATH).is_ok (), true);
+        assert_eq! (generate_mnemonic (13), Err (String::from ("A recovery phrase has 12 or 24 words, not 13")));
+    }
+
+    #[test]
+    fn bad_phrases_and_paths_are_refused () {
+        assert_eq! (private_key_from_mnemonic ("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon", "", DEFAULT_DERIVATION_PATH).is_err (), true);
+        assert_eq! (private_key_from_mnemonic (PHRASE, "", "44'/60'/0'/0/0"), Err (String::from ("Not a derivation path: '44'/60'/0'/0/0'")));
+        assert_eq! (private_key_from_mnemonic (PHRASE, "", "m/44'/sixty'"), Err (String::from ("Not a derivation path: 'm/44'/sixty''")));
+        assert_eq! (private_key_from_mnemonic (PHRASE, "", "m/2147483648"), Err (String::from ("Not a derivation path: 'm/2147483648'")));
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::char;
+use std::str;
+
+pub fn encode_hex (data: &[u8]) -> String {
+    data.iter ().map (|byte| format! ("{:02x}", byte)).collect ()
+}
+
+// The 0x prefix is optional
+pub fn decode_hex (hex: &str) -> Result<Vec<u8>, String> {
+    let digits = if hex.starts_with ("0x") || hex.starts_with ("0X") {&hex[2..]} else {hex};
+    if digits.len () % 2 != 0 {return Err (format! ("Odd number of hex digits: '{}'", hex))}
+    digits.as_bytes ().chunks (2)
+        .map (|pair| str::from_utf8 (pair).ok ()
+            .and_then (|pair| u8::from_str_radix (pair, 16).ok ())
+            .ok_or (format! ("Invalid hex: '{}'", hex)))
+        .collect ()
+}
+
+// JSON-RPC quantities are hex numbers without leading zeros
+pub fn to_quantity (value: u64) -> String {
+    format! ("0x{:x}", value)
+}
+
+pub fn from_quantity (quantity: &str) -> Result<u64, String> {
+    let digits = quantity.trim_left_matches ("0x");
+    if digits.is_empty () {return Ok (0)}
+    u64::from_str_radix (digits, 16).map_err (|_| format! ("Invalid quantity: '{}'", quantity))
+}
+
+// For quantities like balances that can outgrow 64 bits
+pub fn quantity_to_decimal (quantity: &str) -> Result<String, String> {
+    let digits = quantity.trim_left_matches ("0x");
+    let mut hex_digits: Vec<u32> = vec! ();
+    for c in digits.chars () {
+        match c.to_digit (16) {
+            Some (digit) => hex_digits.push (digit),
+            None => return Err (format! ("Invalid quantity: '{}'", quantity))
+        }
+    }
+    // Long division by ten, least significant decimal digit first
+    let mut decimal_digits: Vec<char> = vec! ();
+    while hex_digits.iter ().any (|digit| *digit != 0) {
+        let mut remainder = 0;
+        for digit in hex_digits.iter_mut () {
+            let value = remainder * 16 + *digit;
+            *digit = value / 10;
+            remainder = value % 10;
+        }
+        decimal_digits.push (char::from_digit (remainder, 10).expect ("Internal error"));
+    }
+    if decimal_digits.is_empty () {return Ok (String::from ("0"))}
+    Ok (decimal_digits.into_iter ().rev ().collect ())
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trips () {
+        assert_eq! (encode_hex (&[0x00, 0x7f, 0xff]), String::from ("007fff"));
+        assert_eq! (decode_hex ("0x007fFF"), Ok (vec! (0x00, 0x7f, 0xff)));
+        assert_eq! (decode_hex ("007"), Err (String::from ("Odd number of hex digits: '007'")));
+        assert_eq! (decode_hex ("0g"), Err (String::from ("Invalid hex: '0g'")));
+    }
+
+    #[test]
+    fn quantities_convert () {
+        assert_eq! (to_quantity (0), String::from ("0x0"));
+        assert_eq! (to_quantity (1024), String::from ("0x400"));
+        assert_eq! (from_quantity ("0x400"), Ok (1024));
+        assert_eq! (from_quantity ("0x"), Ok (0));
+        assert_eq! (quantity_to_decimal ("0x0"), Ok (String::from ("0")));
+        assert_eq! (quantity_to_decimal ("0x400"), Ok (String::from ("1024")));
+        assert_eq! (quantity_to_decimal ("0x3635c9adc5dea00000"), Ok (String::from ("1000000000000000000000")));
+        assert_eq! (quantity_to_decimal ("0xz"), Err (String::from ("Invalid quantity: '0xz'")));
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::cell::Cell;
+use std::cmp;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::str;
+use std::sync::Arc;
+use std::time::Duration;
+use rustls::ClientConfig;
+use rustls::ClientSession;
+use rustls::Stream;
+use serde_json;
+use serde_json::Value;
+use webpki::DNSNameRef;
+
+const RPC_TIMEOUT_MS: u64 = 10000;
+// Where Ethereum nodes usually listen
+const DEFAULT_RPC_PORT: u16 = 8545;
+
+pub trait JsonRpcTransport {
+    fn call (&self, method: &str, params: Value) -> Result<Value, String>;
+}
+
+// One connection per call, over plain HTTP, which is all an Ethereum node on the same machine or
+// network needs
+pub struct JsonRpcHttp {
+    host: String,
+    port: u16,
+    path: String,
+    next_id: Cell<u64>,
+}
+
+impl JsonRpcTransport for JsonRpcHttp {
+    fn call (&self, method: &str, params: Value) -> Result<Value, String> {
+        let id = self.next_id.get ();
+        self.next_id.set (id + 1);
+        let body = json! ({"jsonrpc": "2.0", "id": id, "method": method, "params": params}).to_string ();
+        let response = self.post (&body).map_err (|e| format! ("{} failed: {}", method, e))?;
+        let reply: Value = serde_json::from_slice (&response[..])
+            .map_err (|e| format! ("{} returned unparseable JSON: {}", method, e))?;
+        if let Some (error) = reply.get ("error") {
+            let message = error.get ("message").and_then (|message| message.as_str ()).unwrap_or ("unknown error");
+            return Err (format! ("{} failed: {}", method, message))
+        }
+        match reply.get ("result") {
+            Some (result) => Ok (result.clone ()),
+            None => Err (format! ("{} returned no result", method))
+        }
+    }
+}
+
+impl JsonRpcHttp {
+    pub fn new (url: &str) -> Result<JsonRpcHttp, String> {
+        let (host, port, path) = parse_url (url, DEFAULT_RPC_PORT)?;
+        Ok (JsonRpcHttp {host, port, path, next_id: Cell::new (1)})
+    }
+
+    fn post (&self, body: &str) -> Result<Vec<u8>, String> {
+        http_exchange (&self.host, self.port, &format! ("POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.path, self.host, self.port, body.len (), body))
+    }
+}
+
+// Sends a whole request over a fresh connection and returns the body of a successful response
+pub fn http_exchange (host: &str, port: u16, request: &str) -> Result<Vec<u8>, String> {
+    let mut socket = connect (host, port)?;
+    let response = exchange (&mut socket, request)?;
+    parse_http_response (&response[..])
+}
+
+// The same, over TLS, to a server whose certificate is for host and is trusted by config
+pub fn https_exchange (host: &str, port: u16, request: &str, config: &Arc<ClientConfig>) -> Result<Vec<u8>, String> {
+    let name = DNSNameRef::try_from_ascii_str (host).map_err (|_| format! ("'{}' is not a DNS name a certificate can be for", host))?;
+    let mut session = ClientSession::new (config, name);
+    let mut socket = connect (host, port)?;
+    let response = exchange (&mut Stream::new (&mut session, &mut socket), request)?;
+    parse_http_response (&response[..])
+}
+
+fn connect (host: &str, port: u16) -> Result<TcpStream, String> {
+    let socket_addr = match (host, port).to_socket_addrs ().map_err (|e| format! ("{}", e))?.next () {
+        Some (socket_addr) => socket_addr,
+        None => return Err (format! ("Can't resolve {}", host))
+    };
+    let timeout = Duration::from_millis (RPC_TIMEOUT_MS);
+    let socket = TcpStream::connect_timeout (&socket_addr, timeout).map_err (|e| format! ("{}", e))?;
+    socket.set_read_timeout (Some (timeout)).map_err (|e| format! ("{}", e))?;
+    socket.set_write_timeout (Some (timeout)).map_err (|e| format! ("{}", e))?;
+    Ok (socket)
+}
+
+fn exchange<T> (stream: &mut T, request: &str) -> Result<Vec<u8>, String> where T: Read + Write {
+    stream.write_all (request.as_bytes ()).map_err (|e| format! ("{}", e))?;
+    stream.flush ().map_err (|e| format! ("{}", e))?;
+    let mut response = vec! ();
+    stream.read_to_end (&mut response).map_err (|e| format! ("{}", e))?;
+    Ok (response)
+}
+
+// Only http:// URLs, split into host, port and path
+pub fn parse_url (url: &str, default_port: u16) -> Result<(String, u16, String), String> {
+    parse_url_with_scheme (url, "http://", default_port)
+}
+
+// Only https:// URLs, the same way
+pub fn parse_https_url (url: &str, default_port: u16) -> Result<(String, u16, String), String> {
+    parse_url_with_scheme (url, "https://", default_port)
+}
+
+fn parse_url_with_scheme (url: &str, scheme: &str, default_port: u16) -> Result<(String, u16, String), String> {
+    if !url.starts_with (scheme) {return Err (format! ("Not an {} URL: '{}'", scheme, url))}
+    let rest = &url[scheme.len ()..];
+    let (authority, path) = match rest.find ('/') {
+        Some (index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/")
+    };
+    let (host, port) = match authority.rfind (':') {
+        Some (index) => (&authority[..index], authority[index + 1..].parse::<u16> ()
+            .map_err (|_| format! ("Invalid port in URL: '{}'", url))?),
+        None => (authority, default_port)
+    };
+    if host.is_empty () {return Err (format! ("No host in URL: '{}'", url))}
+    Ok ((String::from (host), port, String::from (path)))
+}
+
+fn parse_http_response (response: &[u8]) -> Result<Vec<u8>, String> {
+    let header_end = match find (response, b"\r\n\r\n") {
+        Some (index) => index,
+        None => return Err (String::from ("Incomplete HTTP response"))
+    };
+    let header = String::from_utf8_lossy (&response[..header_end]).to_lowercase ();
+    let body = &response[header_end + 4..];
+    let status = header.split_whitespace ().nth (1).unwrap_or ("");
+    if status != "200" {return Err (format! ("HTTP status {}", status))}
+    if header.contains ("transfer-encoding: chunked") {dechunk (body)} else {Ok (body.to_vec ())}
+}
+
+fn dechunk (mut body: &[u8]) -> Result<Vec<u8>, String> {
+    let mut result = vec! ();
+    loop {
+        let line_end = find (body, b"\r\n").ok_or (String::from ("Incomplete chunk"))?;
+        let size_text = str::from_utf8 (&body[..line_end]).map_err (|_| String::from ("Invalid chunk size"))?;
+        let size = usize::from_str_radix (size_text.split (';').next ().unwrap_or ("").trim (), 16)
+            .map_err (|_| String::from ("Invalid chunk size"))?;
+        if size == 0 {return Ok (result)}
+        let chunk_start = line_end + 2;
+        if body.len () < chunk_start + size {return Err (String::from ("Incomplete chunk"))}
+        result.extend_from_slice (&body[chunk_start..chunk_start + size]);
+        body = &body[cmp::min (chunk_start + size + 2, body.len ())..];
+    }
+}
+
+fn find (haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows (needle.len ()).position (|window| window == needle)
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_are_parsed () {
+        assert_eq! (parse_url ("http://localhost", 8545), Ok ((String::from ("localhost"), 8545, String::from ("/"))));
+        assert_eq! (parse_url ("http://10.0.0.5:8546/rpc", 8545), Ok ((String::from ("10.0.0.5"), 8546, String::from ("/rpc"))));
+        assert_eq! (parse_url ("https://mainnet.example.com", 8545), Err (String::from ("Not an http:// URL: 'https://mainnet.example.com'")));
+        assert_eq! (parse_url ("http://localhost:port", 8545), Err (String::from ("Invalid port in URL: 'http://localhost:port'")));
+        assert_eq! (parse_https_url ("https://prices.example.com/sub", 443), Ok ((String::from ("prices.example.com"), 443, String::from ("/sub"))));
+        assert_eq! (parse_https_url ("http://prices.example.com/sub", 443), Err (String::from ("Not an https:// URL: 'http://prices.example.com/sub'")));
+    }
+
+    #[test]
+    fn plain_and_chunked_responses_are_read () {
+        let plain = b"HTTP/1.1 200 OK\r\nContent-Length: 7\r\n\r\n{\"a\":1}";
+        let chunked = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\n{\"a\r\n4\r\n\":1}\r\n0\r\n\r\n";
+
+        assert_eq! (parse_http_response (&plain[..]), Ok (b"{\"a\":1}".to_vec ()));
+        assert_eq! (parse_http_response (&chunked[..]), Ok (b"{\"a\":1}".to_vec ()));
+    }
+
+    #[test]
+    fn unsuccessful_responses_are_errors () {
+        assert_eq! (parse_http_response (&b"HTTP/1.1 503 Service Unavailable\r\n\r\n"[..]), Err (String::from ("HTTP status 503")));
+        assert_eq! (parse_http_response (&b"HTTP/1.1 200 OK\r\n"[..]), Err (String::from ("Incomplete HTTP response")));
+    }
+}
@@ -0,0 +1,27 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+extern crate actix;
+extern crate bip39;
+extern crate hmac;
+extern crate rustls;
+extern crate secp256k1;
+#[macro_use]
+extern crate serde_json;
+extern crate sha2;
+extern crate sub_lib;
+extern crate tiny_keccak;
+extern crate webpki;
+extern crate webpki_roots;
+
+#[cfg (test)]
+extern crate futures;
+#[cfg (test)]
+extern crate test_utils;
+
+pub mod blockchain_bridge;
+pub mod blockchain_rpc;
+pub mod hd_wallet;
+pub mod hex;
+pub mod json_rpc;
+pub mod price_oracle;
+pub mod raw_transaction;
+pub mod remote_signer;
@@ -0,0 +1,104 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::sync::Arc;
+use rustls::ClientConfig;
+use serde_json;
+use serde_json::Value;
+use sub_lib::price_oracle::PriceOracle;
+use webpki_roots::TLS_SERVER_ROOTS;
+use json_rpc::https_exchange;
+use json_rpc::parse_https_url;
+
+const DEFAULT_HTTPS_PORT: u16 = 443;
+
+// Gets the price of SUB from any HTTPS source that answers a GET with JSON. The price moves the
+// Node's thresholds, so it only comes from a server with a certificate browsers would trust. The
+// URL's fragment, which is never sent, says where the price is in the answer: "#substratum.usd" for
+// {"substratum": {"usd": 0.05}}. Without one, the answer is the price itself. Either way it can be a
+// number or a string of one.
+pub struct PriceOracleHttps {
+    host: String,
+    port: u16,
+    path: String,
+    field_path: Vec<String>,
+    config: Arc<ClientConfig>,
+}
+
+impl PriceOracle for PriceOracleHttps {
+    // Not unit tested
+    fn price (&self) -> Result<f64, String> {
+        let body = https_exchange (&self.host, self.port, &format! ("GET {} HTTP/1.1\r\nHost: {}:{}\r\nAccept: application/json\r\nConnection: close\r\n\r\n",
+            self.path, self.host, self.port), &self.config).map_err (|e| format! ("Price oracle failed: {}", e))?;
+        price_from_body (&body[..], &self.field_path)
+    }
+}
+
+impl PriceOracleHttps {
+    pub fn new (url: &str) -> Result<PriceOracleHttps, String> {
+        let (url, field_path): (&str, Vec<String>) = match url.find ('#') {
+            Some (index) => (&url[..index], url[index + 1..].split ('.').map (String::from).collect ()),
+            None => (url, vec! ())
+        };
+        let (host, port, path) = parse_https_url (url, DEFAULT_HTTPS_PORT)?;
+        let mut config = ClientConfig::new ();
+        config.root_store.add_server_trust_anchors (&TLS_SERVER_ROOTS);
+        Ok (PriceOracleHttps {host, port, path, field_path, config: Arc::new (config)})
+    }
+}
+
+fn price_from_body (body: &[u8], field_path: &[String]) -> Result<f64, String> {
+    let answer: Value = serde_json::from_slice (body).map_err (|e| format! ("Price oracle returned unparseable JSON: {}", e))?;
+    let mut value = &answer;
+    for field in field_path {
+        value = match value.get (field.as_str ()) {
+            Some (value) => value,
+            None => return Err (format! ("Price oracle returned no {}: {}", field_path.join ("."), answer))
+        };
+    }
+    let price = match (value.as_f64 (), value.as_str ()) {
+        (Some (price), _) => price,
+        (None, Some (text)) => text.trim ().parse::<f64> ().map_err (|_| format! ("Price oracle returned a price that isn't a number: {}", value))?,
+        (None, None) => return Err (format! ("Price oracle returned a price that isn't a number: {}", value))
+    };
+    if !price.is_finite () || (price <= 0.0) {return Err (format! ("Price oracle returned a price that can't be right: {}", price))}
+    Ok (price)
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    fn path (field_path: &str) -> Vec<String> {
+        field_path.split ('.').map (String::from).collect ()
+    }
+
+    #[test]
+    fn the_fragment_says_where_the_price_is () {
+        let subject = PriceOracleHttps::new ("https://prices.example.com/simple/price?ids=substratum&vs_currencies=usd#substratum.usd").unwrap ();
+
+        assert_eq! ((subject.host.as_str (), subject.port, subject.path.as_str ()), ("prices.example.com", 443, "/simple/price?ids=substratum&vs_currencies=usd"));
+        assert_eq! (subject.field_path, path ("substratum.usd"));
+        assert_eq! (PriceOracleHttps::new ("https://localhost:8443/sub").unwrap ().field_path, Vec::<String>::new ());
+    }
+
+    #[test]
+    fn prices_are_not_taken_over_plain_http () {
+        let result = PriceOracleHttps::new ("http://prices.example.com/sub#usd");
+
+        assert_eq! (result.err (), Some (String::from ("Not an https:// URL: 'http://prices.example.com/sub'")));
+    }
+
+    #[test]
+    fn prices_are_numbers_or_strings_of_them_wherever_they_are () {
+        assert_eq! (price_from_body (b"{\"substratum\": {\"usd\": 0.05}}", &path ("substratum.usd")), Ok (0.05));
+        assert_eq! (price_from_body (b"{\"data\": {\"price\": \"0.0625\"}}", &path ("data.price")), Ok (0.0625));
+        assert_eq! (price_from_body (b"1.5", &[]), Ok (1.5));
+    }
+
+    #[test]
+    fn prices_that_are_missing_or_make_no_sense_are_errors () {
+        assert_eq! (price_from_body (b"{\"substratum\": {}}", &path ("substratum.usd")), Err (String::from ("Price oracle returned no substratum.usd: {\"substratum\":{}}")));
+        assert_eq! (price_from_body (b"{\"usd\": \"cheap\"}", &path ("usd")), Err (String::from ("Price oracle returned a price that isn't a number: \"cheap\"")));
+        assert_eq! (price_from_body (b"{\"usd\": 0}", &path ("usd")), Err (String::from ("Price oracle returned a price that can't be right: 0")));
+        assert_eq! (price_from_body (b"<html>", &[]).is_err (), true);
+    }
+}
@@ -0,0 +1,288 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use secp256k1::Message;
+use secp256k1::PublicKey;
+use secp256k1::RecoverableSignature;
+use secp256k1::RecoveryId;
+use secp256k1::Secp256k1;
+use secp256k1::SecretKey;
+use tiny_keccak::keccak256;
+use sub_lib::wallet::Wallet;
+use hex::decode_hex;
+use hex::encode_hex;
+
+// Whatever holds the private key of the wallet this Node pays from: the Node itself, or a hardware
+// wallet or signing daemon off the box. Returns the transaction signed and ready to submit.
+pub trait TransactionSigner {
+    fn wallet (&self) -> &Wallet;
+    fn sign_transaction (&self, transaction: &RawTransaction, chain_id: u64) -> Result<Vec<u8>, String>;
+}
+
+// Holds the private key of the wallet this Node pays from; the key never leaves it
+pub struct Signer {
+    secret_key: SecretKey,
+    wallet: Wallet,
+}
+
+impl Signer {
+    pub fn from_private_key (private_key: &str) -> Result<Signer, String> {
+        let secp = Secp256k1::new ();
+        let secret_key = match decode_hex (private_key) {
+            Ok (ref bytes) if bytes.len () == 32 => SecretKey::from_slice (&secp, &bytes[..])
+                .map_err (|_| String::from ("Private key is out of range"))?,
+            _ => return Err (String::from ("Private key must be 64 hex digits"))
+        };
+        let wallet = wallet_for (&PublicKey::from_secret_key (&secp, &secret_key));
+        Ok (Signer {secret_key, wallet})
+    }
+
+    pub fn wallet (&self) -> &Wallet {
+        &self.wallet
+    }
+
+    // Returns the recovery id and the 64-byte compact signature
+    fn sign (&self, hash: &[u8; 32]) -> (u8, [u8; 64]) {
+        let secp = Secp256k1::new ();
+        let message = Message::from_slice (&hash[..]).expect ("Internal error");
+        let signature = secp.sign_recoverable (&message, &self.secret_key);
+        let (recovery_id, compact) = signature.serialize_compact (&secp);
+        (recovery_id.to_i32 () as u8, compact)
+    }
+}
+
+impl TransactionSigner for Signer {
+    fn wallet (&self) -> &Wallet {
+        &self.wallet
+    }
+
+    fn sign_transaction (&self, transaction: &RawTransaction, chain_id: u64) -> Result<Vec<u8>, String> {
+        Ok (transaction.sign (self, chain_id))
+    }
+}
+
+// The address is the last 20 bytes of the hash of the public key, without its 0x04 tag
+fn wallet_for (public_key: &PublicKey) -> Wallet {
+    let hash = keccak256 (&public_key.serialize_uncompressed ()[1..]);
+    Wallet::new (&encode_hex (&hash[12..])).expect ("Internal error")
+}
+
+#[derive (Clone, Debug, PartialEq)]
+pub struct RawTransaction {
+    pub nonce: u64,
+    pub gas_price: u64,
+    pub gas_limit: u64,
+    pub to: Wallet,
+    pub value: u64,
+    pub data: Vec<u8>,
+}
+
+impl RawTransaction {
+    // Signed as EIP-155 requires, so it can't be replayed on another chain
+    pub fn sign (&self, signer: &Signer, chain_id: u64) -> Vec<u8> {
+        let mut unsigned = self.rlp_fields ();
+        unsigned.extend (vec! (rlp_scalar (chain_id), rlp_scalar (0), rlp_scalar (0)));
+        let (recovery_id, signature) = signer.sign (&keccak256 (&rlp_list (&unsigned)[..]));
+        let mut signed = self.rlp_fields ();
+        signed.extend (vec! (
+            rlp_scalar (recovery_id as u64 + chain_id * 2 + 35),
+            rlp_bytes (strip_leading_zeros (&signature[..32])),
+            rlp_bytes (strip_leading_zeros (&signature[32..])),
+        ));
+        rlp_list (&signed)
+    }
+
+    // Undoes sign: the sender is whoever holds the key the signature recovers
+    pub fn decode_signed (signed: &[u8]) -> Result<SignedTransaction, String> {
+        let items = rlp_decode_list (signed)?;
+        if items.len () != 9 {return Err (format! ("A signed transaction has 9 fields, not {}", items.len ()))}
+        let v = from_big_endian (&items[6])?;
+        if v < 35 {return Err (String::from ("Only transactions signed for one chain are understood"))}
+        let chain_id = (v - 35) / 2;
+        let transaction = RawTransaction {
+            nonce: from_big_endian (&items[0])?,
+            gas_price: from_big_endian (&items[1])?,
+            gas_limit: from_big_endian (&items[2])?,
+            to: Wallet::new (&encode_hex (&items[3][..]))?,
+            value: from_big_endian (&items[4])?,
+            data: items[5].clone (),
+        };
+        if (items[7].len () > 32) || (items[8].len () > 32) {return Err (String::from ("Signature is too long"))}
+        let mut compact = [0u8; 64];
+        compact[32 - items[7].len ()..32].copy_from_slice (&items[7][..]);
+        compact[64 - items[8].len ()..].copy_from_slice (&items[8][..]);
+        let mut unsigned = transaction.rlp_fields ();
+        unsigned.extend (vec! (rlp_scalar (chain_id), rlp_scalar (0), rlp_scalar (0)));
+        let secp = Secp256k1::new ();
+        let message = Message::from_slice (&keccak256 (&rlp_list (&unsigned)[..])[..]).expect ("Internal error");
+        let recovery_id = RecoveryId::from_i32 (((v - 35) % 2) as i32).expect ("Internal error");
+        let signature = RecoverableSignature::from_compact (&secp, &compact[..], recovery_id)
+            .map_err (|e| format! ("Malformed signature: {:?}", e))?;
+        let public_key = secp.recover (&message, &signature)
+            .map_err (|e| format! ("Signature doesn't recover a key: {:?}", e))?;
+        Ok (SignedTransaction {transaction, sender: wallet_for (&public_key), chain_id})
+    }
+
+    fn rlp_fields (&self) -> Vec<Vec<u8>> {
+        vec! (
+            rlp_scalar (self.nonce),
+            rlp_scalar (self.gas_price),
+            rlp_scalar (self.gas_limit),
+            rlp_bytes (&decode_hex (&self.to.address).expect ("Internal error")[..]),
+            rlp_scalar (self.value),
+            rlp_bytes (&self.data[..]),
+        )
+    }
+}
+
+#[derive (Clone, Debug, PartialEq)]
+pub struct SignedTransaction {
+    pub transaction: RawTransaction,
+    pub sender: Wallet,
+    pub chain_id: u64,
+}
+
+fn rlp_scalar (value: u64) -> Vec<u8> {
+    rlp_bytes (&big_endian (value)[..])
+}
+
+fn rlp_bytes (data: &[u8]) -> Vec<u8> {
+    if (data.len () == 1) && (data[0] < 0x80) {return data.to_vec ()}
+    [rlp_length_prefix (data.len (), 0x80), data.to_vec ()].concat ()
+}
+
+fn rlp_list (items: &Vec<Vec<u8>>) -> Vec<u8> {
+    let payload = items.concat ();
+    [rlp_length_prefix (payload.len (), 0xc0), payload].concat ()
+}
+
+fn rlp_length_prefix (length: usize, offset: u8) -> Vec<u8> {
+    if length < 56 {return vec! (offset + length as u8)}
+    let length_bytes = big_endian (length as u64);
+    [vec! (offset + 55 + length_bytes.len () as u8), length_bytes].concat ()
+}
+
+// Only flat lists of strings, which is all a transaction is
+fn rlp_decode_list (data: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    let (is_list, mut payload, rest) = rlp_item (data)?;
+    if !is_list || !rest.is_empty () {return Err (String::from ("Not a single RLP list"))}
+    let mut items = vec! ();
+    while !payload.is_empty () {
+        let (is_list, item, rest) = rlp_item (payload)?;
+        if is_list {return Err (String::from ("Unexpected RLP list inside a transaction"))}
+        items.push (item.to_vec ());
+        payload = rest;
+    }
+    Ok (items)
+}
+
+// Splits off the first item: whether it's a list, its payload, and whatever follows it
+fn rlp_item (data: &[u8]) -> Result<(bool, &[u8], &[u8]), String> {
+    let prefix = match data.first () {
+        Some (prefix) => *prefix,
+        None => return Err (String::from ("RLP item is missing"))
+    };
+    let (is_list, offset, length) = if prefix < 0x80 {
+        return Ok ((false, &data[..1], &data[1..]))
+    }
+    else if prefix < 0xb8 {
+        (false, 1, (prefix - 0x80) as usize)
+    }
+    else if prefix < 0xc0 {
+        let length_length = (prefix - 0xb7) as usize;
+        (false, 1 + length_length, rlp_length (&data[1..], length_length)?)
+    }
+    else if prefix < 0xf8 {
+        (true, 1, (prefix - 0xc0) as usize)
+    }
+    else {
+        let length_length = (prefix - 0xf7) as usize;
+        (true, 1 + length_length, rlp_length (&data[1..], length_length)?)
+    };
+    if data.len () < offset + length {return Err (String::from ("RLP item is truncated"))}
+    Ok ((is_list, &data[offset..offset + length], &data[offset + length..]))
+}
+
+fn rlp_length (data: &[u8], length_length: usize) -> Result<usize, String> {
+    if data.len () < length_length {return Err (String::from ("RLP length is truncated"))}
+    from_big_endian (&data[..length_length]).map (|length| length as usize)
+}
+
+fn from_big_endian (data: &[u8]) -> Result<u64, String> {
+    if data.len () > 8 {return Err (format! ("{}-byte number is too big", data.len ()))}
+    Ok (data.iter ().fold (0, |value, byte| (value << 8) | *byte as u64))
+}
+
+// Without leading zeros; zero itself has no bytes at all
+fn big_endian (value: u64) -> Vec<u8> {
+    (0..8).rev ().map (|index| (value >> (index * 8)) as u8).skip_while (|byte| *byte == 0).collect ()
+}
+
+fn strip_leading_zeros (data: &[u8]) -> &[u8] {
+    let start = data.iter ().position (|byte| *byte != 0).unwrap_or (data.len ());
+    &data[start..]
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rlp_encodes_strings_scalars_and_lists () {
+        assert_eq! (rlp_bytes (b"dog"), vec! (0x83, b'd', b'o', b'g'));
+        assert_eq! (rlp_bytes (b""), vec! (0x80));
+        assert_eq! (rlp_scalar (0), vec! (0x80));
+        assert_eq! (rlp_scalar (15), vec! (0x0f));
+        assert_eq! (rlp_scalar (1024), vec! (0x82, 0x04, 0x00));
+        assert_eq! (rlp_list (&vec! (rlp_bytes (b"cat"), rlp_bytes (b"dog"))), vec! (0xc8, 0x83, b'c', b'a', b't', 0x83, b'd', b'o', b'g'));
+        let long = [b'a'; 56];
+        assert_eq! (rlp_bytes (&long[..])[..2].to_vec (), vec! (0xb8, 56));
+    }
+
+    #[test]
+    fn signer_knows_its_address () {
+        let subject = Signer::from_private_key ("0x4646464646464646464646464646464646464646464646464646464646464646").unwrap ();
+
+        assert_eq! (subject.wallet (), &Wallet::new ("0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f").unwrap ());
+    }
+
+    #[test]
+    fn bad_private_keys_are_refused () {
+        assert_eq! (Signer::from_private_key ("0x46").err ().unwrap (), String::from ("Private key must be 64 hex digits"));
+        assert_eq! (Signer::from_private_key (&"0".repeat (64)).err ().unwrap (), String::from ("Private key is out of range"));
+    }
+
+    // The example from EIP-155
+    #[test]
+    fn transactions_are_signed_for_one_chain () {
+        let signer = Signer::from_private_key ("4646464646464646464646464646464646464646464646464646464646464646").unwrap ();
+        let subject = RawTransaction {
+            nonce: 9,
+            gas_price: 20000000000,
+            gas_limit: 21000,
+            to: Wallet::new ("0x3535353535353535353535353535353535353535").unwrap (),
+            value: 1000000000000000000,
+            data: vec! (),
+        };
+
+        let result = subject.sign (&signer, 1);
+
+        assert_eq! (encode_hex (&result[..]), String::from ("f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"));
+    }
+
+    #[test]
+    fn signed_transactions_decode_to_what_was_signed_and_who_signed_it () {
+        let signer = Signer::from_private_key ("4646464646464646464646464646464646464646464646464646464646464646").unwrap ();
+        let transaction = RawTransaction {
+            nonce: 0,
+            gas_price: 20000000000,
+            gas_limit: 100000,
+            to: Wallet::new ("0x12480e24eb5bec1a9d4369cab6a80cad3c0a377a").unwrap (),
+            value: 0,
+            data: vec! (0xa9, 0x05, 0x9c, 0xbb, 0x00, 0x01),
+        };
+
+        let result = RawTransaction::decode_signed (&transaction.sign (&signer, 3)[..]);
+
+        assert_eq! (result, Ok (SignedTransaction {transaction, sender: signer.wallet ().clone (), chain_id: 3}));
+        assert_eq! (RawTransaction::decode_signed (&[0xc2, 0x01]).err ().unwrap (), String::from ("RLP item is truncated"));
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use serde_json::Value;
+use sub_lib::wallet::Wallet;
+use hex::decode_hex;
+use hex::encode_hex;
+use hex::to_quantity;
+use json_rpc::JsonRpcTransport;
+use raw_transaction::RawTransaction;
+use raw_transaction::TransactionSigner;
+
+// Has transactions signed by a signing service that speaks eth_signTransaction, such as Clef or
+// EthSigner, which in turn can keep the key on a Ledger or Trezor. The key never touches the Node;
+// the service is trusted with nothing but what it signs, and that is checked before it's submitted.
+pub struct RemoteSigner {
+    transport: Box<JsonRpcTransport>,
+    wallet: Wallet,
+}
+
+impl TransactionSigner for RemoteSigner {
+    fn wallet (&self) -> &Wallet {
+        &self.wallet
+    }
+
+    fn sign_transaction (&self, transaction: &RawTransaction, chain_id: u64) -> Result<Vec<u8>, String> {
+        let result = self.transport.call ("eth_signTransaction", json! ([{
+            "from": self.wallet.address,
+            "to": transaction.to.address,
+            "nonce": to_quantity (transaction.nonce),
+            "gas": to_quantity (transaction.gas_limit),
+            "gasPrice": to_quantity (transaction.gas_price),
+            "value": to_quantity (transaction.value),
+            "data": format! ("0x{}", encode_hex (&transaction.data[..])),
+            "chainId": to_quantity (chain_id),
+        }]))?;
+        let signed = decode_hex (raw_from_result (&result)?)?;
+        let decoded = RawTransaction::decode_signed (&signed[..])
+            .map_err (|e| format! ("Signing service returned a malformed transaction: {}", e))?;
+        if decoded.sender != self.wallet {
+            return Err (format! ("Signing service signed for {} rather than {}", decoded.sender, self.wallet))
+        }
+        if (decoded.chain_id != chain_id) || (&decoded.transaction != transaction) {
+            return Err (String::from ("Signing service signed something other than the transaction it was sent"))
+        }
+        Ok (signed)
+    }
+}
+
+impl RemoteSigner {
+    pub fn new (transport: Box<JsonRpcTransport>, wallet: Wallet) -> RemoteSigner {
+        RemoteSigner {transport, wallet}
+    }
+}
+
+// Geth-style services answer with the transaction as well as the raw bytes; others with the bytes alone
+fn raw_from_result (result: &Value) -> Result<&str, String> {
+    match result.get ("raw").unwrap_or (result).as_str () {
+        Some (raw) => Ok (raw),
+        None => Err (format! ("eth_signTransaction returned no signed transaction: {}", result))
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use raw_transaction::Signer;
+
+    struct JsonRpcTransportMock {
+        calls: Arc<Mutex<Vec<(String, Value)>>>,
+        results: RefCell<Vec<Result<Value, String>>>,
+    }
+
+    impl JsonRpcTransport for JsonRpcTransportMock {
+        fn call (&self, method: &str, params: Value) -> Result<Value, String> {
+            self.calls.lock ().unwrap ().push ((String::from (method), params));
+            self.results.borrow_mut ().remove (0)
+        }
+    }
+
+    fn make_subject (results: Vec<Result<Value, String>>) -> (RemoteSigner, Arc<Mutex<Vec<(String, Value)>>>) {
+        let calls = Arc::new (Mutex::new (vec! ()));
+        let transport = JsonRpcTransportMock {calls: calls.clone (), results: RefCell::new (results)};
+        (RemoteSigner::new (Box::new (transport), device ().wallet ().clone ()), calls)
+    }
+
+    // Stands in for the key on the device
+    fn device () -> Signer {
+        Signer::from_private_key ("4646464646464646464646464646464646464646464646464646464646464646").unwrap ()
+    }
+
+    fn transaction () -> RawTransaction {
+        RawTransaction {
+            nonce: 9,
+            gas_price: 20000000000,
+            gas_limit: 100000,
+            to: Wallet::new ("0x12480e24eb5bec1a9d4369cab6a80cad3c0a377a").unwrap (),
+            value: 0,
+            data: vec! (0xa9, 0x05, 0x9c, 0xbb),
+        }
+    }
+
+    fn hex (signed: &[u8]) -> Value {
+        json! (format! ("0x{}", encode_hex (signed)))
+    }
+
+    #[test]
+    fn transactions_are_sent_off_to_be_signed_in_either_form_of_reply () {
+        let signed = transaction ().sign (&device (), 3);
+        let (subject, calls) = make_subject (vec! (
+            Ok (json! ({"raw": hex (&signed[..]), "tx": {}})),
+            Ok (hex (&signed[..])),
+        ));
+
+        assert_eq! (subject.sign_transaction (&transaction (), 3), Ok (signed.clone ()));
+        assert_eq! (subject.sign_transaction (&transaction (), 3), Ok (signed));
+        assert_eq! (calls.lock ().unwrap ()[0], (String::from ("eth_signTransaction"), json! ([{
+            "from": "0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f",
+            "to": "0x12480e24eb5bec1a9d4369cab6a80cad3c0a377a",
+            "nonce": "0x9",
+            "gas": "0x186a0",
+            "gasPrice": "0x4a817c800",
+            "value": "0x0",
+            "data": "0xa9059cbb",
+            "chainId": "0x3",
+        }])));
+    }
+
+    #[test]
+    fn signatures_for_the_wrong_wallet_chain_or_transaction_are_refused () {
+        let stranger = Signer::from_private_key ("5757575757575757575757575757575757575757575757575757575757575757").unwrap ();
+        let other_transaction = RawTransaction {nonce: 10, ..transaction ()};
+        let (subject, _) = make_subject (vec! (
+            Ok (hex (&transaction ().sign (&stranger, 3)[..])),
+            Ok (hex (&transaction ().sign (&device (), 1)[..])),
+            Ok (hex (&other_transaction.sign (&device (), 3)[..])),
+            Ok (json! ({"tx": {}})),
+            Err (String::from ("eth_signTransaction failed: request denied on device")),
+        ));
+
+        assert_eq! (subject.sign_transaction (&transaction (), 3), Err (format! ("Signing service signed for {} rather than {}", stranger.wallet (), device ().wallet ())));
+        assert_eq! (subject.sign_transaction (&transaction (), 3), Err (String::from ("Signing service signed something other than the transaction it was sent")));
+        assert_eq! (subject.sign_transaction (&transaction (), 3), Err (String::from ("Signing service signed something other than the transaction it was sent")));
+        assert_eq! (subject.sign_transaction (&transaction (), 3), Err (String::from ("eth_signTransaction returned no signed transaction: {\"tx\":{}}")));
+        assert_eq! (subject.sign_transaction (&transaction (), 3), Err (String::from ("eth_signTransaction failed: request denied on device")));
+    }
+}
@@ -33,6 +33,24 @@ ci/all.sh
 echo "***                                           NEIGHBORHOOD TAIL                                       ***"
 echo "*********************************************************************************************************"
 echo "*********************************************************************************************************"
+echo "***                                       BLOCKCHAIN BRIDGE HEAD                                      ***"
+cd "$CI_DIR/../blockchain_bridge_lib"
+ci/all.sh
+echo "***                                       BLOCKCHAIN BRIDGE TAIL                                      ***"
+echo "*********************************************************************************************************"
+echo "*********************************************************************************************************"
+echo "***                                        MOCK BLOCKCHAIN HEAD                                       ***"
+cd "$CI_DIR/../mock_blockchain"
+ci/all.sh
+echo "***                                        MOCK BLOCKCHAIN TAIL                                       ***"
+echo "*********************************************************************************************************"
+echo "*********************************************************************************************************"
+echo "***                                            ACCOUNTANT HEAD                                        ***"
+cd "$CI_DIR/../accountant_lib"
+ci/all.sh
+echo "***                                            ACCOUNTANT TAIL                                        ***"
+echo "*********************************************************************************************************"
+echo "*********************************************************************************************************"
 echo "***                                              HOPPER HEAD                                          ***"
 cd "$CI_DIR/../hopper_lib"
 ci/all.sh
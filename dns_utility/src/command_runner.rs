@@ -0,0 +1,96 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::process::Command;
+
+// Some systems' DNS settings can only be changed by asking the program that owns them
+pub trait CommandRunner {
+    // Standard output if the program succeeds; otherwise what it said was wrong
+    fn run (&self, program: &str, args: &[&str]) -> Result<String, String>;
+}
+
+pub struct CommandRunnerReal {}
+
+impl CommandRunner for CommandRunnerReal {
+    fn run (&self, program: &str, args: &[&str]) -> Result<String, String> {
+        let description = describe (program, args);
+        let output = match Command::new (program).args (args).output () {
+            Ok (o) => o,
+            Err (e) => return Err (format! ("Couldn't run {}: {}", description, e))
+        };
+        if output.status.success () {
+            Ok (String::from_utf8_lossy (&output.stdout).into_owned ())
+        }
+        else {
+            let stderr = String::from_utf8_lossy (&output.stderr).trim ().to_string ();
+            match output.status.code () {
+                Some (code) => Err (format! ("{} failed with exit code {}: {}", description, code, stderr)),
+                None => Err (format! ("{} was killed: {}", description, stderr))
+            }
+        }
+    }
+}
+
+impl CommandRunnerReal {
+    pub fn new () -> CommandRunnerReal {
+        CommandRunnerReal {}
+    }
+}
+
+pub fn describe (program: &str, args: &[&str]) -> String {
+    let mut words = vec! (program);
+    words.extend (args.iter ());
+    words.join (" ")
+}
+
+#[cfg (test)]
+pub mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    pub struct CommandRunnerMock {
+        run_parameters: Arc<Mutex<Vec<String>>>,
+        run_results: RefCell<Vec<Result<String, String>>>,
+    }
+
+    impl CommandRunner for CommandRunnerMock {
+        fn run (&self, program: &str, args: &[&str]) -> Result<String, String> {
+            self.run_parameters.lock ().unwrap ().push (describe (program, args));
+            self.run_results.borrow_mut ().remove (0)
+        }
+    }
+
+    impl CommandRunnerMock {
+        pub fn new () -> CommandRunnerMock {
+            CommandRunnerMock {
+                run_parameters: Arc::new (Mutex::new (vec! ())),
+                run_results: RefCell::new (vec! ()),
+            }
+        }
+
+        pub fn run_parameters (mut self, parameters: &Arc<Mutex<Vec<String>>>) -> CommandRunnerMock {
+            self.run_parameters = parameters.clone ();
+            self
+        }
+
+        pub fn run_result (self, result: Result<String, String>) -> CommandRunnerMock {
+            self.run_results.borrow_mut ().push (result);
+            self
+        }
+    }
+
+    #[test]
+    fn commands_are_described_as_they_would_be_typed () {
+        assert_eq! (describe ("resolvectl", &["dns", "eth0", "127.0.0.1"]), String::from ("resolvectl dns eth0 127.0.0.1"));
+    }
+
+    #[cfg (unix)]
+    #[test]
+    fn real_runner_reports_output_failure_and_absence () {
+        let subject = CommandRunnerReal::new ();
+
+        assert_eq! (subject.run ("sh", &["-c", "echo booga"]), Ok (String::from ("booga\n")));
+        assert_eq! (subject.run ("sh", &["-c", "echo bad >&2; exit 3"]), Err (String::from ("sh -c echo bad >&2; exit 3 failed with exit code 3: bad")));
+        assert_eq! (subject.run ("/nonexistent/program", &[]).err ().unwrap ().starts_with ("Couldn't run /nonexistent/program: "), true);
+    }
+}
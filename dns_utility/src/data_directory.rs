@@ -0,0 +1,27 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::path::PathBuf;
+#[cfg (windows)]
+use std::env;
+
+// Where dns_utility keeps what it has to remember from one run to the next. It runs with admin
+// privileges, so this is a system-wide directory rather than one belonging to the user.
+#[cfg (target_os = "linux")]
+pub fn data_directory () -> PathBuf {
+    PathBuf::from ("/var/lib/SubstratumNode")
+}
+
+#[cfg (target_os = "macos")]
+pub fn data_directory () -> PathBuf {
+    PathBuf::from ("/Library/Application Support/SubstratumNode")
+}
+
+#[cfg (windows)]
+pub fn data_directory () -> PathBuf {
+    let program_data = env::var ("ProgramData").unwrap_or (String::from ("C:\\ProgramData"));
+    PathBuf::from (program_data).join ("SubstratumNode")
+}
+
+#[cfg (not (any (target_os = "linux", target_os = "macos", windows)))]
+pub fn data_directory () -> PathBuf {
+    PathBuf::from ("/var/db/SubstratumNode")
+}
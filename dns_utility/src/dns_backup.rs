@@ -0,0 +1,126 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+const BACKUP_FILE: &str = "dns_servers_backup";
+const HEADER: &str = "# DNS servers of each interface before dns_utility subverted it; no servers means automatic\n";
+
+// Where a system won't keep an interface's original DNS servers for us, they're written down in the
+// data directory before the interface is subverted, so that a revert after a reboot or from another
+// run of dns_utility can still find them. One interface per line: "<interface>\t<server> <server>".
+pub struct DnsBackup {
+    path: PathBuf,
+}
+
+impl DnsBackup {
+    pub fn new (data_directory: &Path) -> DnsBackup {
+        DnsBackup {path: data_directory.join (BACKUP_FILE)}
+    }
+
+    pub fn path (&self) -> &Path {
+        &self.path
+    }
+
+    pub fn read (&self) -> Result<HashMap<String, Vec<String>>, String> {
+        let mut contents = String::new ();
+        match File::open (&self.path).and_then (|mut file| file.read_to_string (&mut contents)) {
+            Ok (_) => (),
+            Err (ref e) if e.kind () == ErrorKind::NotFound => return Ok (HashMap::new ()),
+            Err (e) => return Err (format! ("Couldn't read backed-up DNS settings from {:?}: {}", self.path, e))
+        }
+        Ok (contents.lines ()
+            .filter (|line| !line.starts_with ('#'))
+            .flat_map (|line| {
+                let mut fields = line.splitn (2, '\t');
+                let name = fields.next ()?;
+                let servers = fields.next ()?;
+                Some ((String::from (name), servers.split_whitespace ().map (String::from).collect::<Vec<String>> ()))
+            })
+            .collect ())
+    }
+
+    // An entry is only good while its interface is still subverted. If something else has changed the
+    // interface's servers since (a reboot, a new DHCP lease, the user), restoring the entry would undo
+    // that change, so it's left out.
+    pub fn read_fresh (&self, subverted: &Vec<String>) -> Result<HashMap<String, Vec<String>>, String> {
+        Ok (self.read ()?.into_iter ().filter (|&(ref name, _)| subverted.contains (name)).collect ())
+    }
+
+    pub fn write (&self, backup: &HashMap<String, Vec<String>>) -> Result<(), String> {
+        let mut contents = String::from (HEADER);
+        backup.iter ().for_each (|(name, servers)| contents.push_str (&format! ("{}\t{}\n", name, servers.join (" "))));
+        let result: io::Result<()> = self.path.parent ().map (|parent| fs::create_dir_all (parent)).unwrap_or (Ok (()))
+            .and_then (|_| File::create (&self.path))
+            .and_then (|mut file| file.write_all (contents.as_bytes ()));
+        result.map_err (|e| format! ("Couldn't back up DNS settings to {:?}: {}", self.path, e))
+    }
+
+    pub fn remove (&self) -> Result<(), String> {
+        match fs::remove_file (&self.path) {
+            Ok (_) => Ok (()),
+            Err (ref e) if e.kind () == ErrorKind::NotFound => Ok (()),
+            Err (e) => Err (format! ("Couldn't remove backed-up DNS settings from {:?}: {}", self.path, e))
+        }
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn make_subject (test_name: &str) -> DnsBackup {
+        let cur_dir = env::current_dir ().unwrap ();
+        let data_directory = cur_dir.join (Path::new ("generated")).join (Path::new ("DnsBackup")).join (Path::new (test_name));
+        fs::remove_dir_all (data_directory.clone ()).is_ok (); // don't care if it doesn't exist
+        DnsBackup::new (&data_directory)
+    }
+
+    #[test]
+    fn nothing_is_backed_up_until_something_is_written () {
+        let subject = make_subject ("nothing_is_backed_up_until_something_is_written");
+
+        let result = subject.read ();
+
+        assert_eq! (result, Ok (HashMap::new ()));
+    }
+
+    #[test]
+    fn what_is_written_can_be_read_back_until_it_is_removed () {
+        let subject = make_subject ("what_is_written_can_be_read_back_until_it_is_removed");
+        let mut backup = HashMap::new ();
+        backup.insert (String::from ("Wi-Fi"), vec! (String::from ("8.8.8.8"), String::from ("8.8.4.4")));
+        backup.insert (String::from ("USB Ethernet"), vec! ());
+
+        subject.write (&backup).unwrap ();
+        let written = subject.read ();
+        subject.remove ().unwrap ();
+        let removed = subject.read ();
+
+        assert_eq! (written, Ok (backup));
+        assert_eq! (removed, Ok (HashMap::new ()));
+        assert_eq! (subject.path ().exists (), false);
+    }
+
+    #[test]
+    fn entries_for_interfaces_that_are_no_longer_subverted_are_stale () {
+        let subject = make_subject ("entries_for_interfaces_that_are_no_longer_subverted_are_stale");
+        let mut backup = HashMap::new ();
+        backup.insert (String::from ("Wi-Fi"), vec! (String::from ("8.8.8.8")));
+        backup.insert (String::from ("Ethernet"), vec! (String::from ("10.0.0.1")));
+        subject.write (&backup).unwrap ();
+
+        let result = subject.read_fresh (&vec! (String::from ("Ethernet"), String::from ("Bluetooth PAN")));
+
+        let mut expected = HashMap::new ();
+        expected.insert (String::from ("Ethernet"), vec! (String::from ("10.0.0.1")));
+        assert_eq! (result, Ok (expected));
+    }
+}
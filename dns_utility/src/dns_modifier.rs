@@ -2,6 +2,85 @@
 
 pub trait DnsModifier {
     fn type_name (&self) -> &'static str;
-    fn subvert (&self) -> Result<(), String>;
-    fn revert (&self) -> Result<(), String>;
+    fn subvert (&self, filter: &InterfaceFilter) -> Result<(), String>;
+    fn revert (&self, filter: &InterfaceFilter) -> Result<(), String>;
+    fn inspect (&self) -> Result<Vec<InterfaceStatus>, String>;
+}
+
+// What an interface (or whatever else a DnsModifier changes) is doing for DNS. An empty list of
+// servers means they're chosen automatically, by DHCP or the like.
+#[derive (Clone, Debug, PartialEq)]
+pub struct InterfaceStatus {
+    pub name: String,
+    pub subverted: bool,
+    pub active: Vec<String>,
+    // None if nothing is kept, either because nothing is subverted or because the system itself knows
+    // what to go back to
+    pub saved: Option<Vec<String>>,
+}
+
+// Which interfaces subvert and revert change: ordinarily all of them, but the user may name some,
+// for example to leave a corporate VPN adapter's DNS alone
+#[derive (Clone, Debug, PartialEq)]
+pub enum InterfaceFilter {
+    All,
+    Only (Vec<String>),
+}
+
+impl InterfaceFilter {
+    pub fn includes (&self, name: &str) -> bool {
+        match *self {
+            InterfaceFilter::All => true,
+            InterfaceFilter::Only (ref names) => names.iter ().any (|candidate| candidate == name)
+        }
+    }
+
+    // Naming an interface that isn't there is much more likely a typo than a request to do nothing
+    pub fn check (&self, available: &Vec<String>) -> Result<(), String> {
+        if let InterfaceFilter::Only (ref names) = *self {
+            if let Some (missing) = names.iter ().find (|name| !available.contains (*name)) {
+                return Err (format! ("There is no network interface named '{}'; the interfaces are: {}", missing, available.join (", ")))
+            }
+        }
+        Ok (())
+    }
+
+    // Some systems keep one set of DNS settings for every interface at once
+    pub fn require_all (&self) -> Result<(), String> {
+        match *self {
+            InterfaceFilter::All => Ok (()),
+            InterfaceFilter::Only (_) => Err (String::from ("This system's DNS settings aren't kept per network interface, so no interfaces can be named"))
+        }
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_includes_every_interface_and_only_includes_those_named () {
+        let only = InterfaceFilter::Only (vec! (String::from ("Wi-Fi")));
+
+        assert_eq! (InterfaceFilter::All.includes ("utun0"), true);
+        assert_eq! (only.includes ("Wi-Fi"), true);
+        assert_eq! (only.includes ("utun0"), false);
+    }
+
+    #[test]
+    fn named_interfaces_must_exist () {
+        let available = vec! (String::from ("eth0"), String::from ("wlan0"));
+
+        assert_eq! (InterfaceFilter::All.check (&available), Ok (()));
+        assert_eq! (InterfaceFilter::Only (vec! (String::from ("wlan0"))).check (&available), Ok (()));
+        assert_eq! (InterfaceFilter::Only (vec! (String::from ("wlan0"), String::from ("wlan1"))).check (&available),
+            Err (String::from ("There is no network interface named 'wlan1'; the interfaces are: eth0, wlan0")));
+    }
+
+    #[test]
+    fn interfaces_cannot_be_named_where_settings_are_not_kept_per_interface () {
+        assert_eq! (InterfaceFilter::All.require_all (), Ok (()));
+        assert_eq! (InterfaceFilter::Only (vec! (String::from ("eth0"))).require_all (),
+            Err (String::from ("This system's DNS settings aren't kept per network interface, so no interfaces can be named")));
+    }
 }
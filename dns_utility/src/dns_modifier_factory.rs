@@ -8,12 +8,21 @@ use dns_modifier::DnsModifier;
 #[cfg (unix)]
 use resolv_conf_dns_modifier::ResolvConfDnsModifier;
 
+#[cfg (target_os = "linux")]
+use resolved_dns_modifier::ResolvedDnsModifier;
+
+#[cfg (target_os = "linux")]
+use network_manager_dns_modifier::NetworkManagerDnsModifier;
+
 #[cfg (windows)]
 use winreg_dns_modifier::WinRegDnsModifier;
 
 #[cfg (target_os = "macos")]
 use dynamic_store_dns_modifier::DynamicStoreDnsModifier;
 
+#[cfg (target_os = "macos")]
+use network_setup_dns_modifier::NetworkSetupDnsModifier;
+
 pub trait DnsModifierFactory {
     fn make (&self) -> Option<Box<DnsModifier>>;
 }
@@ -35,9 +44,14 @@ impl DnsModifierFactoryReal {
     }
 }
 
-const QUALIFIER_FACTORIES: [&QualifierFactory; 3] = [
+// Where there's a way to change DNS settings that lasts, it's preferred to one that doesn't; and on
+// Linux, whatever manages DNS has to be asked before /etc/resolv.conf is changed by hand
+const QUALIFIER_FACTORIES: [&QualifierFactory; 6] = [
+    &NetworkSetupQualifierFactory {},
     &DynamicStoreQualifierFactory {},
     &WinRegQualifierFactory {},
+    &NetworkManagerQualifierFactory {},
+    &ResolvedQualifierFactory {},
     &ResolvConfQualifierFactory {}
 ];
 
@@ -66,6 +80,46 @@ impl QualifierFactory for ResolvConfQualifierFactory {
     }
 }
 
+struct NetworkManagerQualifierFactory;
+#[cfg (target_os = "linux")]
+impl QualifierFactory for NetworkManagerQualifierFactory {
+    fn system_qualifies(&self) -> bool {
+        NetworkManagerDnsModifier::new ().manages_dns ()
+    }
+    fn make(&self) -> Box<DnsModifier> {
+        Box::new (NetworkManagerDnsModifier::new ())
+    }
+}
+#[cfg (not (target_os = "linux"))]
+impl QualifierFactory for NetworkManagerQualifierFactory {
+    fn system_qualifies(&self) -> bool {
+        false
+    }
+    fn make(&self) -> Box<DnsModifier> {
+        panic!("Should never be called")
+    }
+}
+
+struct ResolvedQualifierFactory;
+#[cfg (target_os = "linux")]
+impl QualifierFactory for ResolvedQualifierFactory {
+    fn system_qualifies(&self) -> bool {
+        ResolvedDnsModifier::new ().manages_dns ()
+    }
+    fn make(&self) -> Box<DnsModifier> {
+        Box::new (ResolvedDnsModifier::new ())
+    }
+}
+#[cfg (not (target_os = "linux"))]
+impl QualifierFactory for ResolvedQualifierFactory {
+    fn system_qualifies(&self) -> bool {
+        false
+    }
+    fn make(&self) -> Box<DnsModifier> {
+        panic!("Should never be called")
+    }
+}
+
 struct WinRegQualifierFactory;
 #[cfg (windows)]
 impl QualifierFactory for WinRegQualifierFactory {
@@ -86,6 +140,26 @@ impl QualifierFactory for WinRegQualifierFactory {
     }
 }
 
+struct NetworkSetupQualifierFactory;
+#[cfg (target_os = "macos")]
+impl QualifierFactory for NetworkSetupQualifierFactory {
+    fn system_qualifies(&self) -> bool {
+        NetworkSetupDnsModifier::new ().is_available ()
+    }
+    fn make(&self) -> Box<DnsModifier> {
+        Box::new (NetworkSetupDnsModifier::new ())
+    }
+}
+#[cfg (not (target_os = "macos"))]
+impl QualifierFactory for NetworkSetupQualifierFactory {
+    fn system_qualifies(&self) -> bool {
+        false
+    }
+    fn make(&self) -> Box<DnsModifier> {
+        panic!("Should never be called")
+    }
+}
+
 struct DynamicStoreQualifierFactory;
 #[cfg (target_os = "macos")]
 impl QualifierFactory for DynamicStoreQualifierFactory {
@@ -132,6 +206,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn network_manager_and_resolved_qualifier_factories_work_on_this_os () {
+        let network_manager_result = NetworkManagerQualifierFactory {}.system_qualifies ();
+        let resolved_result = ResolvedQualifierFactory {}.system_qualifies ();
+
+        #[cfg (target_os = "linux")]
+        {
+            assert_eq! (network_manager_result, NetworkManagerDnsModifier::new ().manages_dns ());
+            assert_eq! (resolved_result, ResolvedDnsModifier::new ().manages_dns ());
+        }
+
+        #[cfg (not (target_os = "linux"))]
+        {
+            assert_eq! ((network_manager_result, resolved_result), (false, false))
+        }
+    }
+
     #[test]
     fn win_reg_qualifier_factory_works_on_this_os () {
         let subject = WinRegQualifierFactory {};
@@ -149,6 +240,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn network_setup_qualifier_factory_works_on_this_os () {
+        let subject = NetworkSetupQualifierFactory {};
+
+        let result = subject.system_qualifies();
+
+        #[cfg (target_os = "macos")]
+        {
+            assert_eq!(result, NetworkSetupDnsModifier::new ().is_available ())
+        }
+
+        #[cfg (not (target_os = "macos"))]
+        {
+            assert_eq! (result, false)
+        }
+    }
+
     #[test]
     fn dynamic_store_qualifier_factory_works_on_this_os () {
         let subject = DynamicStoreQualifierFactory {};
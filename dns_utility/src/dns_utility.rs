@@ -1,24 +1,59 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::path::PathBuf;
 use sub_lib::main_tools::Command;
 use sub_lib::main_tools::StdStreams;
+use dns_modifier::DnsModifier;
+use dns_modifier::InterfaceStatus;
+use dns_modifier::InterfaceFilter;
 use dns_modifier_factory::DnsModifierFactory;
 use dns_modifier_factory::DnsModifierFactoryReal;
+use process_watcher::ProcessWatcher;
+use process_watcher::ProcessWatcherReal;
+use data_directory::data_directory;
+use doctor::Doctor;
+
+// Present while the user wants DNS subverted, naming the interfaces they chose, one per line, if they
+// chose any; a watcher only re-subverts DNS when this says to
+const SUBVERSION_FLAG_FILE: &str = "dns_subversion_wanted";
 
 pub struct DnsUtility {
-    factory: Box<DnsModifierFactory>
+    factory: Box<DnsModifierFactory>,
+    watcher: Box<ProcessWatcher>,
+    data_directory: PathBuf,
+    doctor: Doctor
 }
 
 enum Action {
-    Subvert,
-    Revert
+    Subvert (InterfaceFilter),
+    Revert (InterfaceFilter),
+    Status (bool),
+    Watch (u32),
+    Doctor
 }
 
 impl Command for DnsUtility {
     fn go<'a>(&mut self, streams: &mut StdStreams, args: &Vec<String>) -> u8 {
         let action = match args {
             a if a.len () < 2 => return DnsUtility::usage (streams),
-            a if a[1] == String::from ("subvert") => Action::Subvert,
-            a if a[1] == String::from ("revert") => Action::Revert,
+            a if a[1] == String::from ("subvert") => match parse_filter (&a[2..]) {
+                Some (filter) => Action::Subvert (filter),
+                None => return DnsUtility::usage (streams)
+            },
+            a if a[1] == String::from ("revert") => match parse_filter (&a[2..]) {
+                Some (filter) => Action::Revert (filter),
+                None => return DnsUtility::usage (streams)
+            },
+            a if a[1] == String::from ("status") && a.len () == 2 => Action::Status (false),
+            a if a[1] == String::from ("status") && a.len () == 3 && a[2] == String::from ("--json") => Action::Status (true),
+            a if a[1] == String::from ("doctor") && a.len () == 2 => Action::Doctor,
+            a if a[1] == String::from ("watch") && a.len () == 3 => match a[2].parse::<u32> () {
+                Ok (pid) => Action::Watch (pid),
+                Err (_) => return DnsUtility::usage (streams)
+            },
             _ => return DnsUtility::usage (streams),
         };
         self.perform_action (action, streams)
@@ -28,7 +63,10 @@ impl Command for DnsUtility {
 impl DnsUtility {
     pub fn new () -> DnsUtility {
         DnsUtility {
-            factory: Box::new (DnsModifierFactoryReal::new ())
+            factory: Box::new (DnsModifierFactoryReal::new ()),
+            watcher: Box::new (ProcessWatcherReal::new ()),
+            data_directory: data_directory (),
+            doctor: Doctor::new ()
         }
     }
 
@@ -41,8 +79,11 @@ impl DnsUtility {
             Some (m) => m
         };
         let (result, name) = match action {
-            Action::Subvert => (modifier.subvert (), "subvert"),
-            Action::Revert => (modifier.revert (), "revert")
+            Action::Subvert (filter) => (modifier.subvert (&filter).map (|_| self.want_subversion (&filter)), "subvert"),
+            Action::Revert (filter) => (modifier.revert (&filter).map (|_| self.unwant_subversion (&filter, modifier.as_ref ())), "revert"),
+            Action::Status (json) => (DnsUtility::report_status (modifier.as_ref (), json, streams), "inspect"),
+            Action::Watch (pid) => (self.watch (pid, modifier.as_ref (), streams), "revert"),
+            Action::Doctor => return self.diagnose (modifier.as_ref (), streams)
         };
         match result {
             Ok (_) => 0,
@@ -53,22 +94,228 @@ impl DnsUtility {
         }
     }
 
+    // Waits for the Node to go away, however it goes, and then puts DNS back if it's still subverted,
+    // so that a crashed Node doesn't leave the machine unable to look anything up. Meanwhile, joining
+    // a different network or docking tends to put an interface's DNS settings back the way DHCP says
+    // they should be; if the user still wants DNS subverted, it's subverted again.
+    fn watch (&self, pid: u32, modifier: &DnsModifier, streams: &mut StdStreams) -> Result<(), String> {
+        let mut last_failure: Option<String> = None;
+        while self.watcher.is_running (pid) {
+            self.watcher.pause ();
+            let filter = match self.wanted_subversion () {
+                Some (filter) => filter,
+                None => continue
+            };
+            match DnsUtility::resubvert (modifier, &filter) {
+                Ok (true) => {
+                    writeln! (streams.stdout, "Network settings changed; DNS subverted again").expect ("Could not writeln");
+                    last_failure = None
+                },
+                Ok (false) => last_failure = None,
+                // Until the network comes back, this will fail every time; once is enough to say so
+                Err (msg) => {
+                    if last_failure != Some (msg.clone ()) {
+                        writeln! (streams.stderr, "Cannot subvert DNS again: {}", msg).expect ("Could not writeln");
+                    }
+                    last_failure = Some (msg)
+                }
+            }
+        }
+        let statuses = modifier.inspect ()?;
+        if statuses.iter ().any (|status| status.subverted) {
+            modifier.revert (&InterfaceFilter::All)?;
+            self.remember_subversion (None);
+            writeln! (streams.stdout, "Process {} is gone; DNS reverted", pid).expect ("Could not writeln");
+        }
+        else {
+            writeln! (streams.stdout, "Process {} is gone; DNS was not subverted", pid).expect ("Could not writeln");
+        }
+        Ok (())
+    }
+
+    // Only the chosen interfaces that are still here are subverted again; one that's gone away, as a
+    // dock's adapter does, is no reason not to subvert the rest
+    fn resubvert (modifier: &DnsModifier, filter: &InterfaceFilter) -> Result<bool, String> {
+        let unsubverted: Vec<String> = modifier.inspect ()?.into_iter ()
+            .filter (|status| !status.subverted && filter.includes (&status.name))
+            .map (|status| status.name)
+            .collect ();
+        if unsubverted.is_empty () {return Ok (false)}
+        let target = match *filter {
+            InterfaceFilter::All => InterfaceFilter::All,
+            InterfaceFilter::Only (_) => InterfaceFilter::Only (unsubverted)
+        };
+        modifier.subvert (&target)?;
+        Ok (true)
+    }
+
+    fn wanted_subversion (&self) -> Option<InterfaceFilter> {
+        let mut contents = String::new ();
+        if File::open (self.data_directory.join (SUBVERSION_FLAG_FILE)).and_then (|mut file| file.read_to_string (&mut contents)).is_err () {
+            return None
+        }
+        let names: Vec<String> = contents.lines ().filter (|line| !line.is_empty ()).map (String::from).collect ();
+        Some (if names.is_empty () {InterfaceFilter::All} else {InterfaceFilter::Only (names)})
+    }
+
+    // Interfaces subverted now are wanted along with those subverted before
+    fn want_subversion (&self, filter: &InterfaceFilter) {
+        let wanted = match self.wanted_subversion () {
+            Some (InterfaceFilter::Only (mut names)) => match *filter {
+                InterfaceFilter::All => InterfaceFilter::All,
+                InterfaceFilter::Only (ref more) => {
+                    for name in more {
+                        if !names.contains (name) {names.push (name.clone ())}
+                    }
+                    InterfaceFilter::Only (names)
+                }
+            },
+            Some (InterfaceFilter::All) => InterfaceFilter::All,
+            None => filter.clone ()
+        };
+        self.remember_subversion (Some (&wanted))
+    }
+
+    // Only the interfaces reverted stop being wanted; the rest stay subverted, and a watcher keeps them so
+    fn unwant_subversion (&self, filter: &InterfaceFilter, modifier: &DnsModifier) {
+        let reverted = match *filter {
+            InterfaceFilter::All => return self.remember_subversion (None),
+            InterfaceFilter::Only (ref names) => names
+        };
+        let remaining: Vec<String> = match self.wanted_subversion () {
+            None => return,
+            Some (InterfaceFilter::Only (names)) => names.into_iter ().filter (|name| !reverted.contains (name)).collect (),
+            // Every interface but these can't be written down, but the interfaces still subverted can
+            Some (InterfaceFilter::All) => match modifier.inspect () {
+                Ok (statuses) => statuses.into_iter ().filter (|status| status.subverted).map (|status| status.name).collect (),
+                Err (_) => vec! ()
+            }
+        };
+        if remaining.is_empty () {
+            self.remember_subversion (None)
+        }
+        else {
+            self.remember_subversion (Some (&InterfaceFilter::Only (remaining)))
+        }
+    }
+
+    // Failing to remember this isn't worth failing the subversion or reversion for; the only cost is
+    // that a watcher won't re-subvert DNS after a network change
+    fn remember_subversion (&self, wanted: Option<&InterfaceFilter>) {
+        let path = self.data_directory.join (SUBVERSION_FLAG_FILE);
+        match wanted {
+            Some (filter) => {
+                let contents = match *filter {
+                    InterfaceFilter::All => String::new (),
+                    InterfaceFilter::Only (ref names) => names.iter ().map (|name| format! ("{}\n", name)).collect ()
+                };
+                let _ = fs::create_dir_all (&self.data_directory)
+                    .and_then (|_| File::create (&path))
+                    .and_then (|mut file| file.write_all (contents.as_bytes ()));
+            },
+            None => {let _ = fs::remove_file (&path);}
+        }
+    }
+
+    fn diagnose (&self, modifier: &DnsModifier, streams: &mut StdStreams) -> u8 {
+        let checks = self.doctor.examine (modifier);
+        checks.iter ().for_each (|check| match check.result {
+            Ok (_) => writeln! (streams.stdout, "[ OK ] {}", check.description).expect ("Could not writeln"),
+            Err (ref advice) => writeln! (streams.stdout, "[FAIL] {}\n       {}", check.description, advice).expect ("Could not writeln")
+        });
+        let failures = checks.iter ().filter (|check| check.result.is_err ()).count ();
+        if failures == 0 {
+            writeln! (streams.stdout, "Everything needed to browse through SubstratumNode is working").expect ("Could not writeln");
+            0
+        }
+        else {
+            writeln! (streams.stdout, "{} of {} checks failed", failures, checks.len ()).expect ("Could not writeln");
+            1
+        }
+    }
+
+    fn report_status (modifier: &DnsModifier, json: bool, streams: &mut StdStreams) -> Result<(), String> {
+        let statuses = modifier.inspect ()?;
+        let report = if json {
+            DnsUtility::status_as_json (modifier.type_name (), &statuses)
+        } else {
+            DnsUtility::status_as_text (modifier.type_name (), &statuses)
+        };
+        write! (streams.stdout, "{}", report).expect ("Could not write");
+        Ok (())
+    }
+
+    fn status_as_text (type_name: &str, statuses: &Vec<InterfaceStatus>) -> String {
+        let describe = |servers: &Vec<String>| if servers.is_empty () {String::from ("(automatic)")} else {servers.join (", ")};
+        let mut report = format! ("DNS settings are changed by {}\n", type_name);
+        if statuses.is_empty () {report.push_str ("No network interfaces have DNS settings\n")}
+        statuses.iter ().for_each (|status| {
+            report.push_str (&format! ("{}: {}\n", status.name, if status.subverted {"subverted"} else {"not subverted"}));
+            report.push_str (&format! ("    active: {}\n", describe (&status.active)));
+            if let Some (ref saved) = status.saved {report.push_str (&format! ("    saved: {}\n", describe (saved)))}
+        });
+        report
+    }
+
+    fn status_as_json (type_name: &str, statuses: &Vec<InterfaceStatus>) -> String {
+        let list = |servers: &Vec<String>| format! ("[{}]", servers.iter ().map (|server| json_string (server)).collect::<Vec<String>> ().join (","));
+        let interfaces: Vec<String> = statuses.iter ().map (|status| {
+            format! ("{{\"name\":{},\"subverted\":{},\"active\":{},\"saved\":{}}}", json_string (&status.name), status.subverted,
+                list (&status.active), match status.saved {Some (ref saved) => list (saved), None => String::from ("null")})
+        }).collect ();
+        format! ("{{\"modifier\":{},\"interfaces\":[{}]}}\n", json_string (type_name), interfaces.join (","))
+    }
+
     fn usage (streams: &mut StdStreams) -> u8 {
-        writeln!(streams.stderr, "Usage: dns_utility [ subvert | revert ]").expect("Internal error");
+        writeln!(streams.stderr, "Usage: dns_utility [ subvert [ --interface <name> ]... | revert [ --interface <name> ]... | status [ --json ] | watch <pid> | doctor ]").expect("Internal error");
         1
     }
 }
 
+// Nothing means every interface; otherwise each is named with "--interface <name>"
+fn parse_filter (args: &[String]) -> Option<InterfaceFilter> {
+    if args.is_empty () {return Some (InterfaceFilter::All)}
+    if args.len () % 2 != 0 {return None}
+    let mut names: Vec<String> = vec! ();
+    for pair in args.chunks (2) {
+        if pair[0] != String::from ("--interface") {return None}
+        names.push (pair[1].clone ());
+    }
+    Some (InterfaceFilter::Only (names))
+}
+
+fn json_string (s: &str) -> String {
+    let mut result = String::from ("\"");
+    for c in s.chars () {
+        match c {
+            '"' => result.push_str ("\\\""),
+            '\\' => result.push_str ("\\\\"),
+            c if (c as u32) < 0x20 => result.push_str (&format! ("\\u{:04x}", c as u32)),
+            c => result.push (c)
+        }
+    }
+    result.push ('"');
+    result
+}
+
 #[cfg (test)]
-mod tests {
+pub mod tests {
     use super::*;
     use test_utils::test_utils::FakeStreamHolder;
     use std::cell::RefCell;
-    use dns_modifier::DnsModifier;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use utils::get_parameters_from;
+    use doctor::tests::ProbeMock;
+    use std::env;
+    use std::path::Path;
 
     pub struct DnsModifierMock {
+        subvert_parameters: Arc<Mutex<Vec<InterfaceFilter>>>,
         subvert_results: RefCell<Vec<Result<(), String>>>,
-        revert_results: RefCell<Vec<Result<(), String>>>
+        revert_parameters: Arc<Mutex<Vec<InterfaceFilter>>>,
+        revert_results: RefCell<Vec<Result<(), String>>>,
+        inspect_results: RefCell<Vec<Result<Vec<InterfaceStatus>, String>>>
     }
 
     impl DnsModifier for DnsModifierMock {
@@ -76,32 +323,56 @@ mod tests {
             "DnsModifierMock"
         }
 
-        fn subvert(&self) -> Result<(), String> {
+        fn subvert(&self, filter: &InterfaceFilter) -> Result<(), String> {
+            self.subvert_parameters.lock ().unwrap ().push (filter.clone ());
             self.subvert_results.borrow_mut ().remove (0)
         }
 
-        fn revert(&self) -> Result<(), String> {
+        fn revert(&self, filter: &InterfaceFilter) -> Result<(), String> {
+            self.revert_parameters.lock ().unwrap ().push (filter.clone ());
             self.revert_results.borrow_mut ().remove (0)
         }
+
+        fn inspect(&self) -> Result<Vec<InterfaceStatus>, String> {
+            self.inspect_results.borrow_mut ().remove (0)
+        }
     }
 
     impl DnsModifierMock {
         pub fn new () -> DnsModifierMock {
             DnsModifierMock {
+                subvert_parameters: Arc::new (Mutex::new (vec! ())),
                 subvert_results: RefCell::new (vec! ()),
-                revert_results: RefCell::new (vec! ())
+                revert_parameters: Arc::new (Mutex::new (vec! ())),
+                revert_results: RefCell::new (vec! ()),
+                inspect_results: RefCell::new (vec! ())
             }
         }
 
+        pub fn subvert_parameters (mut self, parameters: &Arc<Mutex<Vec<InterfaceFilter>>>) -> DnsModifierMock {
+            self.subvert_parameters = parameters.clone ();
+            self
+        }
+
         pub fn subvert_result (self, result: Result<(), String>) -> DnsModifierMock {
             self.subvert_results.borrow_mut ().push (result);
             self
         }
 
+        pub fn revert_parameters (mut self, parameters: &Arc<Mutex<Vec<InterfaceFilter>>>) -> DnsModifierMock {
+            self.revert_parameters = parameters.clone ();
+            self
+        }
+
         pub fn revert_result (self, result: Result<(), String>) -> DnsModifierMock {
             self.revert_results.borrow_mut ().push (result);
             self
         }
+
+        pub fn inspect_result (self, result: Result<Vec<InterfaceStatus>, String>) -> DnsModifierMock {
+            self.inspect_results.borrow_mut ().push (result);
+            self
+        }
     }
 
     pub struct DnsModifierFactoryMock {
@@ -127,6 +398,39 @@ mod tests {
         }
     }
 
+    pub struct ProcessWatcherMock {
+        is_running_parameters: Arc<Mutex<Vec<u32>>>,
+        is_running_results: RefCell<Vec<bool>>
+    }
+
+    impl ProcessWatcher for ProcessWatcherMock {
+        fn is_running (&self, pid: u32) -> bool {
+            self.is_running_parameters.lock ().unwrap ().push (pid);
+            self.is_running_results.borrow_mut ().remove (0)
+        }
+
+        fn pause (&self) {}
+    }
+
+    impl ProcessWatcherMock {
+        pub fn new () -> ProcessWatcherMock {
+            ProcessWatcherMock {
+                is_running_parameters: Arc::new (Mutex::new (vec! ())),
+                is_running_results: RefCell::new (vec! ())
+            }
+        }
+
+        pub fn is_running_parameters (mut self, parameters: &Arc<Mutex<Vec<u32>>>) -> ProcessWatcherMock {
+            self.is_running_parameters = parameters.clone ();
+            self
+        }
+
+        pub fn is_running_result (self, result: bool) -> ProcessWatcherMock {
+            self.is_running_results.borrow_mut ().push (result);
+            self
+        }
+    }
+
     #[test]
     fn go_with_no_parameters_prints_usage_to_stderr_and_exits_with_error () {
         let mut holder = FakeStreamHolder::new ();
@@ -136,7 +440,7 @@ mod tests {
 
         assert_eq! (result, 1);
         assert_eq! (holder.stderr.get_string (), String::from (
-            "Usage: dns_utility [ subvert | revert ]\n"
+            "Usage: dns_utility [ subvert [ --interface <name> ]... | revert [ --interface <name> ]... | status [ --json ] | watch <pid> | doctor ]\n"
         ));
     }
 
@@ -149,7 +453,7 @@ mod tests {
 
         assert_eq! (result, 1);
         assert_eq! (holder.stderr.get_string (), String::from (
-            "Usage: dns_utility [ subvert | revert ]\n"
+            "Usage: dns_utility [ subvert [ --interface <name> ]... | revert [ --interface <name> ]... | status [ --json ] | watch <pid> | doctor ]\n"
         ));
     }
 
@@ -194,13 +498,16 @@ mod tests {
             .subvert_result (Ok (()));
         let factory = DnsModifierFactoryMock::new()
             .make_result (Some (Box::new (dns_modifier)));
+        let data_directory = make_data_directory ("go_with_subvert_parameter_makes_dns_modifier_calls_subvert_and_handles_success");
         let mut subject = DnsUtility::new ();
         subject.factory = Box::new (factory);
+        subject.data_directory = data_directory.clone ();
 
         let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("subvert")));
 
         assert_eq! (result, 0);
         assert_eq! (holder.stderr.get_string (), String::new ());
+        assert_eq! (data_directory.join (SUBVERSION_FLAG_FILE).exists (), true);
     }
 
     #[test]
@@ -228,12 +535,441 @@ mod tests {
             .revert_result (Ok (()));
         let factory = DnsModifierFactoryMock::new()
             .make_result (Some (Box::new (dns_modifier)));
+        let data_directory = make_data_directory ("go_with_revert_parameter_makes_dns_modifier_calls_revert_and_handles_success");
+        File::create (data_directory.join (SUBVERSION_FLAG_FILE)).unwrap ();
         let mut subject = DnsUtility::new ();
         subject.factory = Box::new (factory);
+        subject.data_directory = data_directory.clone ();
 
         let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("revert")));
 
         assert_eq! (result, 0);
         assert_eq! (holder.stderr.get_string (), String::new ());
+        assert_eq! (data_directory.join (SUBVERSION_FLAG_FILE).exists (), false);
+    }
+
+    fn make_statuses () -> Vec<InterfaceStatus> {
+        vec! (
+            InterfaceStatus {name: String::from ("Wi-Fi"), subverted: true, active: vec! (String::from ("127.0.0.1")), saved: Some (vec! (String::from ("8.8.8.8"), String::from ("8.8.4.4")))},
+            InterfaceStatus {name: String::from ("USB \"Dock\""), subverted: false, active: vec! (), saved: None},
+        )
+    }
+
+    #[test]
+    fn go_with_status_parameter_reports_each_interface_readably () {
+        let mut holder = FakeStreamHolder::new ();
+        let dns_modifier = DnsModifierMock::new ()
+            .inspect_result (Ok (make_statuses ()));
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (dns_modifier)));
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("status")));
+
+        assert_eq! (result, 0);
+        assert_eq! (holder.stdout.get_string (), String::from (
+            "DNS settings are changed by DnsModifierMock\n\
+             Wi-Fi: subverted\n    active: 127.0.0.1\n    saved: 8.8.8.8, 8.8.4.4\n\
+             USB \"Dock\": not subverted\n    active: (automatic)\n"
+        ));
+    }
+
+    #[test]
+    fn go_with_status_and_json_parameters_reports_each_interface_for_scripts () {
+        let mut holder = FakeStreamHolder::new ();
+        let dns_modifier = DnsModifierMock::new ()
+            .inspect_result (Ok (make_statuses ()));
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (dns_modifier)));
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("status"), String::from ("--json")));
+
+        assert_eq! (result, 0);
+        assert_eq! (holder.stdout.get_string (), String::from (
+            "{\"modifier\":\"DnsModifierMock\",\"interfaces\":[\
+             {\"name\":\"Wi-Fi\",\"subverted\":true,\"active\":[\"127.0.0.1\"],\"saved\":[\"8.8.8.8\",\"8.8.4.4\"]},\
+             {\"name\":\"USB \\\"Dock\\\"\",\"subverted\":false,\"active\":[],\"saved\":null}]}\n"
+        ));
+    }
+
+    #[test]
+    fn go_with_status_parameter_handles_failure () {
+        let mut holder = FakeStreamHolder::new ();
+        let dns_modifier = DnsModifierMock::new ()
+            .inspect_result (Err (String::from ("blooga blooga")));
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (dns_modifier)));
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("status")));
+
+        assert_eq! (result, 1);
+        assert_eq! (holder.stderr.get_string (), String::from (
+            "Cannot inspect DNS: blooga blooga\n"
+        ));
+    }
+
+    #[test]
+    fn go_with_watch_parameter_waits_for_the_process_to_die_and_then_reverts_subverted_dns () {
+        let mut holder = FakeStreamHolder::new ();
+        let is_running_parameters = Arc::new (Mutex::new (vec! ()));
+        let watcher = ProcessWatcherMock::new ()
+            .is_running_parameters (&is_running_parameters)
+            .is_running_result (true)
+            .is_running_result (true)
+            .is_running_result (false);
+        let dns_modifier = DnsModifierMock::new ()
+            .inspect_result (Ok (make_statuses ()))
+            .revert_result (Ok (()));
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (dns_modifier)));
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+        subject.watcher = Box::new (watcher);
+        subject.data_directory = make_data_directory ("go_with_watch_parameter_waits_for_the_process_to_die_and_then_reverts_subverted_dns");
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("watch"), String::from ("1234")));
+
+        assert_eq! (result, 0);
+        assert_eq! (get_parameters_from (is_running_parameters), vec! (1234, 1234, 1234));
+        assert_eq! (holder.stdout.get_string (), String::from ("Process 1234 is gone; DNS reverted\n"));
+    }
+
+    #[test]
+    fn go_with_watch_parameter_leaves_dns_alone_if_it_was_not_subverted () {
+        let mut holder = FakeStreamHolder::new ();
+        let watcher = ProcessWatcherMock::new ()
+            .is_running_result (false);
+        let dns_modifier = DnsModifierMock::new ()
+            .inspect_result (Ok (vec! (InterfaceStatus {name: String::from ("eth0"), subverted: false, active: vec! (), saved: None})));
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (dns_modifier)));
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+        subject.watcher = Box::new (watcher);
+        subject.data_directory = make_data_directory ("go_with_watch_parameter_leaves_dns_alone_if_it_was_not_subverted");
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("watch"), String::from ("1234")));
+
+        assert_eq! (result, 0);
+        assert_eq! (holder.stdout.get_string (), String::from ("Process 1234 is gone; DNS was not subverted\n"));
+    }
+
+    #[test]
+    fn go_with_watch_parameter_handles_revert_failure () {
+        let mut holder = FakeStreamHolder::new ();
+        let watcher = ProcessWatcherMock::new ()
+            .is_running_result (false);
+        let dns_modifier = DnsModifierMock::new ()
+            .inspect_result (Ok (make_statuses ()))
+            .revert_result (Err (String::from ("blooga blooga")));
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (dns_modifier)));
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+        subject.watcher = Box::new (watcher);
+        subject.data_directory = make_data_directory ("go_with_watch_parameter_handles_revert_failure");
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("watch"), String::from ("1234")));
+
+        assert_eq! (result, 1);
+        assert_eq! (holder.stderr.get_string (), String::from ("Cannot revert DNS: blooga blooga\n"));
+    }
+
+    #[test]
+    fn go_with_watch_parameter_and_no_valid_pid_prints_usage () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = DnsUtility::new ();
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("watch"), String::from ("booga")));
+
+        assert_eq! (result, 1);
+        assert_eq! (holder.stderr.get_string (), String::from (
+            "Usage: dns_utility [ subvert [ --interface <name> ]... | revert [ --interface <name> ]... | status [ --json ] | watch <pid> | doctor ]\n"
+        ));
+    }
+
+    #[test]
+    fn go_with_watch_parameter_subverts_dns_again_after_a_network_change_if_subversion_is_wanted () {
+        let mut holder = FakeStreamHolder::new ();
+        let watcher = ProcessWatcherMock::new ()
+            .is_running_result (true)
+            .is_running_result (true)
+            .is_running_result (false);
+        let dns_modifier = DnsModifierMock::new ()
+            .inspect_result (Ok (make_statuses ()))
+            .subvert_result (Ok (()))
+            .inspect_result (Ok (vec! (make_statuses ().remove (0))))
+            .inspect_result (Ok (vec! (make_statuses ().remove (1))));
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (dns_modifier)));
+        let data_directory = make_data_directory ("go_with_watch_parameter_subverts_dns_again_after_a_network_change_if_subversion_is_wanted");
+        File::create (data_directory.join (SUBVERSION_FLAG_FILE)).unwrap ();
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+        subject.watcher = Box::new (watcher);
+        subject.data_directory = data_directory;
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("watch"), String::from ("1234")));
+
+        assert_eq! (result, 0);
+        assert_eq! (holder.stdout.get_string (), String::from (
+            "Network settings changed; DNS subverted again\nProcess 1234 is gone; DNS was not subverted\n"
+        ));
+    }
+
+    #[test]
+    fn go_with_watch_parameter_leaves_dns_alone_after_a_network_change_if_subversion_is_not_wanted () {
+        let mut holder = FakeStreamHolder::new ();
+        let watcher = ProcessWatcherMock::new ()
+            .is_running_result (true)
+            .is_running_result (false);
+        let dns_modifier = DnsModifierMock::new ()
+            .inspect_result (Ok (vec! (make_statuses ().remove (1))));
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (dns_modifier)));
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+        subject.watcher = Box::new (watcher);
+        subject.data_directory = make_data_directory ("go_with_watch_parameter_leaves_dns_alone_after_a_network_change_if_subversion_is_not_wanted");
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("watch"), String::from ("1234")));
+
+        assert_eq! (result, 0);
+        assert_eq! (holder.stdout.get_string (), String::from ("Process 1234 is gone; DNS was not subverted\n"));
+    }
+
+    #[test]
+    fn go_with_watch_parameter_complains_once_about_each_failure_to_subvert_dns_again () {
+        let mut holder = FakeStreamHolder::new ();
+        let watcher = ProcessWatcherMock::new ()
+            .is_running_result (true)
+            .is_running_result (true)
+            .is_running_result (false);
+        let dns_modifier = DnsModifierMock::new ()
+            .inspect_result (Err (String::from ("No network")))
+            .inspect_result (Err (String::from ("No network")))
+            .inspect_result (Ok (vec! ()));
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (dns_modifier)));
+        let data_directory = make_data_directory ("go_with_watch_parameter_complains_once_about_each_failure_to_subvert_dns_again");
+        File::create (data_directory.join (SUBVERSION_FLAG_FILE)).unwrap ();
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+        subject.watcher = Box::new (watcher);
+        subject.data_directory = data_directory;
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("watch"), String::from ("1234")));
+
+        assert_eq! (result, 0);
+        assert_eq! (holder.stderr.get_string (), String::from ("Cannot subvert DNS again: No network\n"));
+    }
+
+    fn make_data_directory (test_name: &str) -> PathBuf {
+        let cur_dir = env::current_dir ().unwrap ();
+        let base_dir = cur_dir.join (Path::new ("generated")).join (Path::new ("DnsUtility")).join (Path::new (test_name));
+        fs::remove_dir_all (base_dir.clone ()).is_ok (); // don't care if it doesn't exist
+        fs::create_dir_all (base_dir.clone ()).unwrap ();
+        base_dir
+    }
+
+    #[test]
+    fn go_with_doctor_parameter_reports_each_check_and_succeeds_if_all_pass () {
+        let mut holder = FakeStreamHolder::new ();
+        let dns_modifier = DnsModifierMock::new ()
+            .inspect_result (Ok (vec! (make_statuses ().remove (0))));
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (dns_modifier)));
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+        subject.doctor = Doctor::with_probe (ProbeMock::healthy ());
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("doctor")));
+
+        assert_eq! (result, 0);
+        assert_eq! (holder.stdout.get_string (), String::from (
+            "[ OK ] DNS is subverted\n\
+             [ OK ] SubstratumNode answers DNS questions on 127.0.0.1:53\n\
+             [ OK ] Looking up substratum.net leads to the Node\n\
+             [ OK ] Looking up example.com leads to the Node\n\
+             [ OK ] The Node's ProxyServer accepts connections on 127.0.0.1:80\n\
+             [ OK ] The Node's ProxyServer accepts connections on 127.0.0.1:443\n\
+             Everything needed to browse through SubstratumNode is working\n"
+        ));
+    }
+
+    #[test]
+    fn go_with_doctor_parameter_explains_failures_and_fails () {
+        let mut holder = FakeStreamHolder::new ();
+        let dns_modifier = DnsModifierMock::new ()
+            .inspect_result (Ok (make_statuses ()));
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (dns_modifier)));
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+        subject.doctor = Doctor::with_probe (ProbeMock::healthy ());
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("doctor")));
+
+        assert_eq! (result, 1);
+        assert_eq! (holder.stdout.get_string ().starts_with (
+            "[FAIL] DNS is subverted\n       DNS is not subverted on USB \"Dock\"; run 'dns_utility subvert' with admin privileges\n"
+        ), true);
+        assert_eq! (holder.stdout.get_string ().ends_with ("1 of 6 checks failed\n"), true);
+    }
+
+    #[test]
+    fn go_with_subvert_and_interface_parameters_subverts_only_those_interfaces_and_remembers_them () {
+        let mut holder = FakeStreamHolder::new ();
+        let subvert_parameters = Arc::new (Mutex::new (vec! ()));
+        let dns_modifier = DnsModifierMock::new ()
+            .subvert_parameters (&subvert_parameters)
+            .subvert_result (Ok (()));
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (dns_modifier)));
+        let data_directory = make_data_directory ("go_with_subvert_and_interface_parameters_subverts_only_those_interfaces_and_remembers_them");
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+        subject.data_directory = data_directory.clone ();
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("subvert"),
+            String::from ("--interface"), String::from ("Wi-Fi"), String::from ("--interface"), String::from ("USB \"Dock\"")));
+
+        assert_eq! (result, 0);
+        let chosen = InterfaceFilter::Only (vec! (String::from ("Wi-Fi"), String::from ("USB \"Dock\"")));
+        assert_eq! (get_parameters_from (subvert_parameters), vec! (chosen.clone ()));
+        assert_eq! (subject.wanted_subversion (), Some (chosen));
+    }
+
+    #[test]
+    fn go_with_revert_and_interface_parameters_reverts_only_those_interfaces () {
+        let mut holder = FakeStreamHolder::new ();
+        let revert_parameters = Arc::new (Mutex::new (vec! ()));
+        let dns_modifier = DnsModifierMock::new ()
+            .revert_parameters (&revert_parameters)
+            .revert_result (Ok (()));
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (dns_modifier)));
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+        subject.data_directory = make_data_directory ("go_with_revert_and_interface_parameters_reverts_only_those_interfaces");
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("revert"), String::from ("--interface"), String::from ("wlan0")));
+
+        assert_eq! (result, 0);
+        assert_eq! (get_parameters_from (revert_parameters), vec! (InterfaceFilter::Only (vec! (String::from ("wlan0")))));
+    }
+
+    #[test]
+    fn go_with_subvert_and_interface_parameters_remembers_those_interfaces_along_with_the_ones_chosen_before () {
+        let mut holder = FakeStreamHolder::new ();
+        let dns_modifier = DnsModifierMock::new ()
+            .subvert_result (Ok (()));
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (dns_modifier)));
+        let data_directory = make_data_directory ("go_with_subvert_and_interface_parameters_remembers_those_interfaces_along_with_the_ones_chosen_before");
+        File::create (data_directory.join (SUBVERSION_FLAG_FILE)).unwrap ().write_all (b"Wi-Fi\neth0\n").unwrap ();
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+        subject.data_directory = data_directory;
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("subvert"),
+            String::from ("--interface"), String::from ("eth0"), String::from ("--interface"), String::from ("wlan0")));
+
+        assert_eq! (result, 0);
+        assert_eq! (subject.wanted_subversion (), Some (InterfaceFilter::Only (vec! (String::from ("Wi-Fi"), String::from ("eth0"), String::from ("wlan0")))));
+    }
+
+    #[test]
+    fn go_with_revert_and_interface_parameters_stops_wanting_only_those_interfaces_subverted () {
+        let mut holder = FakeStreamHolder::new ();
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (DnsModifierMock::new ().revert_result (Ok (())))))
+            .make_result (Some (Box::new (DnsModifierMock::new ().revert_result (Ok (())))));
+        let data_directory = make_data_directory ("go_with_revert_and_interface_parameters_stops_wanting_only_those_interfaces_subverted");
+        File::create (data_directory.join (SUBVERSION_FLAG_FILE)).unwrap ().write_all (b"Wi-Fi\neth0\n").unwrap ();
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+        subject.data_directory = data_directory.clone ();
+
+        let first_result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("revert"), String::from ("--interface"), String::from ("eth0")));
+        let first_wanted = subject.wanted_subversion ();
+        let second_result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("revert"), String::from ("--interface"), String::from ("Wi-Fi")));
+
+        assert_eq! (first_result, 0);
+        assert_eq! (first_wanted, Some (InterfaceFilter::Only (vec! (String::from ("Wi-Fi")))));
+        assert_eq! (second_result, 0);
+        assert_eq! (data_directory.join (SUBVERSION_FLAG_FILE).exists (), false);
+    }
+
+    #[test]
+    fn go_with_revert_and_interface_parameters_after_subverting_every_interface_keeps_wanting_the_others_subverted () {
+        let mut holder = FakeStreamHolder::new ();
+        let dns_modifier = DnsModifierMock::new ()
+            .revert_result (Ok (()))
+            .inspect_result (Ok (vec! (
+                InterfaceStatus {name: String::from ("Wi-Fi"), subverted: true, active: vec! (), saved: None},
+                InterfaceStatus {name: String::from ("eth0"), subverted: false, active: vec! (), saved: None},
+                InterfaceStatus {name: String::from ("utun0"), subverted: true, active: vec! (), saved: None},
+            )));
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (dns_modifier)));
+        let data_directory = make_data_directory ("go_with_revert_and_interface_parameters_after_subverting_every_interface_keeps_wanting_the_others_subverted");
+        File::create (data_directory.join (SUBVERSION_FLAG_FILE)).unwrap ();
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+        subject.data_directory = data_directory;
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("revert"), String::from ("--interface"), String::from ("eth0")));
+
+        assert_eq! (result, 0);
+        assert_eq! (subject.wanted_subversion (), Some (InterfaceFilter::Only (vec! (String::from ("Wi-Fi"), String::from ("utun0")))));
+    }
+
+    #[test]
+    fn go_with_subvert_and_incomplete_interface_parameters_prints_usage () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = DnsUtility::new ();
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("subvert"), String::from ("--interface")));
+
+        assert_eq! (result, 1);
+        assert_eq! (holder.stderr.get_string (), String::from (
+            "Usage: dns_utility [ subvert [ --interface <name> ]... | revert [ --interface <name> ]... | status [ --json ] | watch <pid> | doctor ]\n"
+        ));
+    }
+
+    #[test]
+    fn go_with_watch_parameter_subverts_again_only_the_chosen_interfaces_that_are_still_there () {
+        let mut holder = FakeStreamHolder::new ();
+        let subvert_parameters = Arc::new (Mutex::new (vec! ()));
+        let watcher = ProcessWatcherMock::new ()
+            .is_running_result (true)
+            .is_running_result (false);
+        let dns_modifier = DnsModifierMock::new ()
+            .subvert_parameters (&subvert_parameters)
+            .inspect_result (Ok (vec! (
+                InterfaceStatus {name: String::from ("Wi-Fi"), subverted: false, active: vec! (), saved: None},
+                InterfaceStatus {name: String::from ("utun0"), subverted: false, active: vec! (), saved: None},
+            )))
+            .subvert_result (Ok (()))
+            .inspect_result (Ok (vec! ()));
+        let factory = DnsModifierFactoryMock::new()
+            .make_result (Some (Box::new (dns_modifier)));
+        let data_directory = make_data_directory ("go_with_watch_parameter_subverts_again_only_the_chosen_interfaces_that_are_still_there");
+        File::create (data_directory.join (SUBVERSION_FLAG_FILE)).unwrap ().write_all (b"Wi-Fi\nThunderbolt Ethernet\n").unwrap ();
+        let mut subject = DnsUtility::new ();
+        subject.factory = Box::new (factory);
+        subject.watcher = Box::new (watcher);
+        subject.data_directory = data_directory;
+
+        let result = subject.go (&mut holder.streams (), &vec! (String::new (), String::from ("watch"), String::from ("1234")));
+
+        assert_eq! (result, 0);
+        assert_eq! (get_parameters_from (subvert_parameters), vec! (InterfaceFilter::Only (vec! (String::from ("Wi-Fi")))));
     }
 }
@@ -0,0 +1,337 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::net::UdpSocket;
+use std::time::Duration;
+use dns_modifier::DnsModifier;
+
+const NODE_IP: [u8; 4] = [127, 0, 0, 1];
+const SAMPLE_NAMES: [&str; 2] = ["substratum.net", "example.com"];
+const PROXY_PORTS: [u16; 2] = [80, 443];
+const TIMEOUT_MS: u64 = 2000;
+
+// The network operations the doctor needs, so that they can be faked in tests
+pub trait Probe {
+    // The IPv4 addresses the DNS server at the given address gives for the name
+    fn ask_dns (&self, server: SocketAddr, name: &str) -> Result<Vec<Ipv4Addr>, String>;
+    // The addresses this machine's own resolver gives for the name, wherever it gets them
+    fn look_up (&self, name: &str) -> Result<Vec<IpAddr>, String>;
+    fn connect (&self, addr: SocketAddr) -> Result<(), String>;
+}
+
+pub struct ProbeReal {
+    timeout: Duration,
+}
+
+impl Probe for ProbeReal {
+    fn ask_dns (&self, server: SocketAddr, name: &str) -> Result<Vec<Ipv4Addr>, String> {
+        let socket = UdpSocket::bind ("0.0.0.0:0").map_err (|e| format! ("{}", e))?;
+        socket.set_read_timeout (Some (self.timeout)).map_err (|e| format! ("{}", e))?;
+        let query = make_query (0x5342, name);
+        socket.send_to (&query[..], server).map_err (|e| format! ("{}", e))?;
+        let mut buf: [u8; 512] = [0; 512];
+        let (length, _) = socket.recv_from (&mut buf).map_err (|e| format! ("{}", e))?;
+        parse_addresses (0x5342, &buf[..length])
+    }
+
+    fn look_up (&self, name: &str) -> Result<Vec<IpAddr>, String> {
+        match (name, 80).to_socket_addrs () {
+            Ok (addrs) => Ok (addrs.map (|addr| addr.ip ()).collect ()),
+            Err (e) => Err (format! ("{}", e))
+        }
+    }
+
+    fn connect (&self, addr: SocketAddr) -> Result<(), String> {
+        TcpStream::connect_timeout (&addr, self.timeout).map (|_| ()).map_err (|e| format! ("{}", e))
+    }
+}
+
+impl ProbeReal {
+    pub fn new () -> ProbeReal {
+        ProbeReal {timeout: Duration::from_millis (TIMEOUT_MS)}
+    }
+}
+
+// One thing that has to be right for browsing through the Node to work. If it isn't, the failure
+// says what the user can do about it.
+#[derive (Clone, Debug, PartialEq)]
+pub struct Check {
+    pub description: String,
+    pub result: Result<(), String>,
+}
+
+// "My internet broke" is almost always one of a few things, and they can all be checked from here:
+// DNS has to be subverted, the Node has to be answering DNS questions, this machine's lookups have
+// to be reaching it, and its ProxyServer has to be accepting the connections that follow.
+pub struct Doctor {
+    probe: Box<Probe>,
+}
+
+impl Doctor {
+    pub fn new () -> Doctor {
+        Doctor {probe: Box::new (ProbeReal::new ())}
+    }
+
+    pub fn examine (&self, modifier: &DnsModifier) -> Vec<Check> {
+        let mut checks = vec! (
+            Doctor::check_subversion (modifier),
+            self.check_entry_dns (),
+        );
+        checks.extend (SAMPLE_NAMES.iter ().map (|name| self.check_lookup (name)));
+        checks.extend (PROXY_PORTS.iter ().map (|port| self.check_proxy (*port)));
+        checks
+    }
+
+    fn check_subversion (modifier: &DnsModifier) -> Check {
+        let result = match modifier.inspect () {
+            Err (msg) => Err (format! ("Couldn't read DNS settings: {}", msg)),
+            Ok (ref statuses) if statuses.is_empty () => Err (String::from ("No network interface has DNS settings; is this machine connected to a network?")),
+            Ok (statuses) => {
+                let unsubverted: Vec<String> = statuses.into_iter ().filter (|status| !status.subverted).map (|status| status.name).collect ();
+                if unsubverted.is_empty () {Ok (())}
+                else {Err (format! ("DNS is not subverted on {}; run 'dns_utility subvert' with admin privileges", unsubverted.join (", ")))}
+            }
+        };
+        Check {description: String::from ("DNS is subverted"), result}
+    }
+
+    fn check_entry_dns (&self) -> Check {
+        let node_ip = Ipv4Addr::from (NODE_IP);
+        let server = SocketAddr::new (IpAddr::V4 (node_ip), 53);
+        let result = match self.probe.ask_dns (server, SAMPLE_NAMES[0]) {
+            Err (msg) => Err (format! ("Nothing answered DNS questions on {} ({}); is SubstratumNode running with admin privileges?", server, msg)),
+            Ok (ref addresses) if addresses.contains (&node_ip) => Ok (()),
+            Ok (addresses) => Err (format! ("The Node answered {} with {} instead of {}; is it running in bypass mode, or with a different --dns_target?",
+                SAMPLE_NAMES[0], describe (&addresses), node_ip))
+        };
+        Check {description: format! ("SubstratumNode answers DNS questions on {}", server), result}
+    }
+
+    fn check_lookup (&self, name: &str) -> Check {
+        let node_ip = IpAddr::V4 (Ipv4Addr::from (NODE_IP));
+        let result = match self.probe.look_up (name) {
+            Err (msg) => Err (format! ("Couldn't look up {} ({}); if DNS is subverted, the Node isn't answering", name, msg)),
+            Ok (ref addresses) if addresses.contains (&node_ip) => Ok (()),
+            Ok (addresses) => Err (format! ("{} was looked up as {}, not {}; an old answer may be cached (flush the DNS cache or restart the browser), or another DNS server may be answering first",
+                name, describe (&addresses), node_ip))
+        };
+        Check {description: format! ("Looking up {} leads to the Node", name), result}
+    }
+
+    fn check_proxy (&self, port: u16) -> Check {
+        let addr = SocketAddr::new (IpAddr::V4 (Ipv4Addr::from (NODE_IP)), port);
+        let result = self.probe.connect (addr)
+            .map_err (|msg| format! ("Couldn't connect to {} ({}); is SubstratumNode running, and is another program using port {}?", addr, msg, port));
+        Check {description: format! ("The Node's ProxyServer accepts connections on {}", addr), result}
+    }
+}
+
+fn describe<T> (addresses: &Vec<T>) -> String where T: ToString {
+    if addresses.is_empty () {return String::from ("no addresses")}
+    addresses.iter ().map (|address| address.to_string ()).collect::<Vec<String>> ().join (", ")
+}
+
+// A standard query, recursion desired, for the A records of the name
+fn make_query (transaction_id: u16, name: &str) -> Vec<u8> {
+    let mut query = vec! ((transaction_id >> 8) as u8, transaction_id as u8, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0);
+    name.split ('.').filter (|label| !label.is_empty ()).for_each (|label| {
+        query.push (label.len () as u8);
+        query.extend_from_slice (label.as_bytes ());
+    });
+    query.extend_from_slice (&[0, 0, 1, 0, 1]);
+    query
+}
+
+fn parse_addresses (transaction_id: u16, response: &[u8]) -> Result<Vec<Ipv4Addr>, String> {
+    if response.len () < 12 {return Err (String::from ("Response too short"))}
+    if (((response[0] as u16) << 8) | (response[1] as u16)) != transaction_id {return Err (String::from ("Response doesn't match the question"))}
+    let rcode = response[3] & 0x0F;
+    if rcode != 0 {return Err (format! ("Response code {}", rcode))}
+    let question_count = ((response[4] as usize) << 8) | (response[5] as usize);
+    let answer_count = ((response[6] as usize) << 8) | (response[7] as usize);
+    let mut offset = 12;
+    for _ in 0..question_count {
+        offset = skip_name (response, offset)? + 4;
+    }
+    let mut addresses: Vec<Ipv4Addr> = vec! ();
+    for _ in 0..answer_count {
+        offset = skip_name (response, offset)?;
+        if response.len () < offset + 10 {return Err (String::from ("Response truncated"))}
+        let rtype = ((response[offset] as u16) << 8) | (response[offset + 1] as u16);
+        let rdlength = ((response[offset + 8] as usize) << 8) | (response[offset + 9] as usize);
+        offset += 10;
+        if response.len () < offset + rdlength {return Err (String::from ("Response truncated"))}
+        if (rtype == 1) && (rdlength == 4) {
+            addresses.push (Ipv4Addr::new (response[offset], response[offset + 1], response[offset + 2], response[offset + 3]));
+        }
+        offset += rdlength;
+    }
+    Ok (addresses)
+}
+
+// Names are a series of length-prefixed labels ending either in an empty one or in a two-byte
+// pointer to the rest of the name somewhere earlier in the packet
+fn skip_name (packet: &[u8], start: usize) -> Result<usize, String> {
+    let mut offset = start;
+    loop {
+        if offset >= packet.len () {return Err (String::from ("Response truncated"))}
+        let length = packet[offset] as usize;
+        if length == 0 {return Ok (offset + 1)}
+        if (length & 0xC0) == 0xC0 {return Ok (offset + 2)}
+        offset += length + 1;
+    }
+}
+
+#[cfg (test)]
+pub mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use dns_modifier::InterfaceStatus;
+    use dns_utility::tests::DnsModifierMock;
+
+    pub struct ProbeMock {
+        ask_dns_results: RefCell<Vec<Result<Vec<Ipv4Addr>, String>>>,
+        look_up_results: RefCell<Vec<Result<Vec<IpAddr>, String>>>,
+        connect_results: RefCell<Vec<Result<(), String>>>,
+    }
+
+    impl Probe for ProbeMock {
+        fn ask_dns (&self, _server: SocketAddr, _name: &str) -> Result<Vec<Ipv4Addr>, String> {
+            self.ask_dns_results.borrow_mut ().remove (0)
+        }
+
+        fn look_up (&self, _name: &str) -> Result<Vec<IpAddr>, String> {
+            self.look_up_results.borrow_mut ().remove (0)
+        }
+
+        fn connect (&self, _addr: SocketAddr) -> Result<(), String> {
+            self.connect_results.borrow_mut ().remove (0)
+        }
+    }
+
+    impl ProbeMock {
+        pub fn new () -> ProbeMock {
+            ProbeMock {
+                ask_dns_results: RefCell::new (vec! ()),
+                look_up_results: RefCell::new (vec! ()),
+                connect_results: RefCell::new (vec! ()),
+            }
+        }
+
+        pub fn ask_dns_result (self, result: Result<Vec<Ipv4Addr>, String>) -> ProbeMock {
+            self.ask_dns_results.borrow_mut ().push (result);
+            self
+        }
+
+        pub fn look_up_result (self, result: Result<Vec<IpAddr>, String>) -> ProbeMock {
+            self.look_up_results.borrow_mut ().push (result);
+            self
+        }
+
+        pub fn connect_result (self, result: Result<(), String>) -> ProbeMock {
+            self.connect_results.borrow_mut ().push (result);
+            self
+        }
+
+        // Everything a healthy Node on a subverted machine would say
+        pub fn healthy () -> ProbeMock {
+            let localhost = Ipv4Addr::new (127, 0, 0, 1);
+            ProbeMock::new ()
+                .ask_dns_result (Ok (vec! (localhost)))
+                .look_up_result (Ok (vec! (IpAddr::V4 (localhost))))
+                .look_up_result (Ok (vec! (IpAddr::V4 (localhost))))
+                .connect_result (Ok (()))
+                .connect_result (Ok (()))
+        }
+    }
+
+    impl Doctor {
+        pub fn with_probe (probe: ProbeMock) -> Doctor {
+            Doctor {probe: Box::new (probe)}
+        }
+    }
+
+    fn subverted (name: &str, subverted: bool) -> InterfaceStatus {
+        InterfaceStatus {name: String::from (name), subverted, active: vec! (), saved: None}
+    }
+
+    #[test]
+    fn a_healthy_node_on_a_subverted_machine_passes_every_check () {
+        let modifier = DnsModifierMock::new ().inspect_result (Ok (vec! (subverted ("eth0", true))));
+        let subject = Doctor::with_probe (ProbeMock::healthy ());
+
+        let result = subject.examine (&modifier);
+
+        assert_eq! (result.iter ().map (|check| check.description.clone ()).collect::<Vec<String>> (), vec! (
+            String::from ("DNS is subverted"),
+            String::from ("SubstratumNode answers DNS questions on 127.0.0.1:53"),
+            String::from ("Looking up substratum.net leads to the Node"),
+            String::from ("Looking up example.com leads to the Node"),
+            String::from ("The Node's ProxyServer accepts connections on 127.0.0.1:80"),
+            String::from ("The Node's ProxyServer accepts connections on 127.0.0.1:443"),
+        ));
+        assert_eq! (result.iter ().all (|check| check.result.is_ok ()), true);
+    }
+
+    #[test]
+    fn every_failure_comes_with_advice () {
+        let modifier = DnsModifierMock::new ().inspect_result (Ok (vec! (subverted ("eth0", true), subverted ("tun0", false), subverted ("wlan0", false))));
+        let subject = Doctor::with_probe (ProbeMock::new ()
+            .ask_dns_result (Ok (vec! (Ipv4Addr::new (104, 18, 40, 31))))
+            .look_up_result (Err (String::from ("failed to lookup address information")))
+            .look_up_result (Ok (vec! (IpAddr::V4 (Ipv4Addr::new (93, 184, 216, 34)))))
+            .connect_result (Err (String::from ("Connection refused")))
+            .connect_result (Ok (())));
+
+        let result = subject.examine (&modifier);
+
+        assert_eq! (result.into_iter ().map (|check| check.result).collect::<Vec<Result<(), String>>> (), vec! (
+            Err (String::from ("DNS is not subverted on tun0, wlan0; run 'dns_utility subvert' with admin privileges")),
+            Err (String::from ("The Node answered substratum.net with 104.18.40.31 instead of 127.0.0.1; is it running in bypass mode, or with a different --dns_target?")),
+            Err (String::from ("Couldn't look up substratum.net (failed to lookup address information); if DNS is subverted, the Node isn't answering")),
+            Err (String::from ("example.com was looked up as 93.184.216.34, not 127.0.0.1; an old answer may be cached (flush the DNS cache or restart the browser), or another DNS server may be answering first")),
+            Err (String::from ("Couldn't connect to 127.0.0.1:80 (Connection refused); is SubstratumNode running, and is another program using port 80?")),
+            Ok (()),
+        ));
+    }
+
+    #[test]
+    fn a_node_that_is_not_there_and_settings_that_cannot_be_read_are_explained () {
+        let modifier = DnsModifierMock::new ().inspect_result (Err (String::from ("blooga")));
+        let subject = Doctor::with_probe (ProbeMock::new ()
+            .ask_dns_result (Err (String::from ("timed out")))
+            .look_up_result (Ok (vec! ()))
+            .look_up_result (Ok (vec! ()))
+            .connect_result (Ok (()))
+            .connect_result (Ok (())));
+
+        let result = subject.examine (&modifier);
+
+        assert_eq! (result[0].result, Err (String::from ("Couldn't read DNS settings: blooga")));
+        assert_eq! (result[1].result, Err (String::from ("Nothing answered DNS questions on 127.0.0.1:53 (timed out); is SubstratumNode running with admin privileges?")));
+    }
+
+    #[test]
+    fn queries_ask_for_a_records_and_answers_yield_their_addresses () {
+        let query = make_query (0x1234, "example.com");
+        let mut response = query.clone ();
+        response[2] = 0x81;
+        response[3] = 0x80;
+        response[7] = 2;
+        response.extend_from_slice (&[0xC0, 0x0C, 0, 5, 0, 1, 0, 0, 0, 60, 0, 2, 0xC0, 0x0C]);
+        response.extend_from_slice (&[0xC0, 0x0C, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 127, 0, 0, 1]);
+
+        assert_eq! (query, vec! (0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0,
+            7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0, 0, 1, 0, 1));
+        assert_eq! (parse_addresses (0x1234, &response[..]), Ok (vec! (Ipv4Addr::new (127, 0, 0, 1))));
+        assert_eq! (parse_addresses (0x4321, &response[..]), Err (String::from ("Response doesn't match the question")));
+        response[3] = 0x83;
+        assert_eq! (parse_addresses (0x1234, &response[..]), Err (String::from ("Response code 3")));
+        assert_eq! (parse_addresses (0x1234, &response[..20]), Err (String::from ("Response code 3")));
+        response[3] = 0x80;
+        assert_eq! (parse_addresses (0x1234, &response[..40]), Err (String::from ("Response truncated")));
+    }
+}
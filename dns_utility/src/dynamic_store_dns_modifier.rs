@@ -6,6 +6,8 @@ use std::iter::FromIterator;
 use libc;
 use regex::Regex;
 use dns_modifier::DnsModifier;
+use dns_modifier::InterfaceStatus;
+use dns_modifier::InterfaceFilter;
 
 use core_foundation::array::CFArray;
 use core_foundation::array::FromVoid;
@@ -25,7 +27,8 @@ impl DnsModifier for DynamicStoreDnsModifier {
         "DynamicStoreDnsModifier"
     }
 
-    fn subvert(&self) -> Result<(), String> {
+    fn subvert(&self, filter: &InterfaceFilter) -> Result<(), String> {
+        filter.require_all ()?;
         let (dns_base_path, dns_info) = self.get_dns_info()?;
         let result = match self.subvert_contents (dns_info) {
             Err (e) => return Err (e),
@@ -35,7 +38,8 @@ impl DnsModifier for DynamicStoreDnsModifier {
         self.set_dns_info (dns_base_path, result)
     }
 
-    fn revert(&self) -> Result<(), String> {
+    fn revert(&self, filter: &InterfaceFilter) -> Result<(), String> {
+        filter.require_all ()?;
         let (dns_base_path, dns_info) = self.get_dns_info()?;
         let result = match self.revert_contents (dns_info) {
             Err (e) => return Err (e),
@@ -44,6 +48,18 @@ impl DnsModifier for DynamicStoreDnsModifier {
         };
         self.set_dns_info (dns_base_path, result)
     }
+
+    fn inspect (&self) -> Result<Vec<InterfaceStatus>, String> {
+        let (dns_base_path, dns_info) = self.get_dns_info()?;
+        let active = dns_info.get ("ServerAddresses").cloned ().unwrap_or (vec! ());
+        let subverted = active.first () == Some (&String::from ("127.0.0.1"));
+        Ok (vec! (InterfaceStatus {
+            name: dns_base_path,
+            subverted,
+            active,
+            saved: dns_info.get ("ServerAddressesBak").cloned (),
+        }))
+    }
 }
 
 impl DynamicStoreDnsModifier {
@@ -379,7 +395,7 @@ mod tests {
         let mut subject = DynamicStoreDnsModifier::new ();
         subject.store = Box::new (store);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("Dynamic-Store path State:/Network/Global/IPv4 not found; DNS settings cannot be modified")));
     }
@@ -392,7 +408,7 @@ mod tests {
         let mut subject = DynamicStoreDnsModifier::new ();
         subject.store = Box::new (store);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("Dynamic-Store path State:/Network/Global/IPv4/PrimaryService not found; DNS settings cannot be modified")));
     }
@@ -407,7 +423,7 @@ mod tests {
         let mut subject = DynamicStoreDnsModifier::new ();
         subject.store = Box::new (store);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("Dynamic-Store path State:/Network/Global/IPv4/PrimaryService is not a string; DNS settings cannot be modified")));
     }
@@ -423,7 +439,7 @@ mod tests {
         let mut subject = DynamicStoreDnsModifier::new ();
         subject.store = Box::new (store);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("This system has no DNS settings to modify; aborting")));
     }
@@ -440,7 +456,7 @@ mod tests {
         let mut subject = DynamicStoreDnsModifier::new ();
         subject.store = Box::new (store);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("This system has no DNS settings to modify; aborting")));
     }
@@ -460,7 +476,7 @@ mod tests {
         let mut subject = DynamicStoreDnsModifier::new ();
         subject.store = Box::new (store);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("Dynamic-Store path State:/Network/Service/booga/DNS/ServerAddresses is not an array; DNS settings cannot be modified")));
     }
@@ -481,7 +497,7 @@ mod tests {
         let mut subject = DynamicStoreDnsModifier::new ();
         subject.store = Box::new (store);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("This system does not appear to be connected to a network; DNS settings cannot be modified")));
     }
@@ -505,7 +521,7 @@ mod tests {
         let mut subject = DynamicStoreDnsModifier::new ();
         subject.store = Box::new (store);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("Dynamic-Store path State:/Network/Service/booga/DNS/ServerAddresses is not an array of strings; DNS settings cannot be modified")));
     }
@@ -534,7 +550,7 @@ mod tests {
         let mut subject = DynamicStoreDnsModifier::new ();
         subject.store = Box::new (store);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("This system's DNS settings don't make sense; aborting")));
     }
@@ -563,7 +579,7 @@ mod tests {
         let mut subject = DynamicStoreDnsModifier::new ();
         subject.store = Box::new (store);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Ok (()));
     }
@@ -601,7 +617,7 @@ mod tests {
         let mut subject = DynamicStoreDnsModifier::new ();
         subject.store = Box::new (store);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Ok (()));
         assert_eq! (get_parameters_from (get_dictionary_string_cfpl_parameters), vec! (
@@ -650,7 +666,7 @@ mod tests {
         let mut subject = DynamicStoreDnsModifier::new ();
         subject.store = Box::new (store);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("Error changing DNS settings. Are you sure you ran me with sudo?")));
     }
@@ -673,7 +689,7 @@ mod tests {
         let mut subject = DynamicStoreDnsModifier::new ();
         subject.store = Box::new (store);
 
-        let result = subject.revert ();
+        let result = subject.revert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("This system has no backed-up DNS settings to restore; aborting")));
     }
@@ -696,7 +712,7 @@ mod tests {
         let mut subject = DynamicStoreDnsModifier::new ();
         subject.store = Box::new (store);
 
-        let result = subject.revert ();
+        let result = subject.revert (&InterfaceFilter::All);
 
         assert_eq! (result, Ok (()));
     }
@@ -740,7 +756,7 @@ mod tests {
         let mut subject = DynamicStoreDnsModifier::new ();
         subject.store = Box::new (store);
 
-        let result = subject.revert ();
+        let result = subject.revert (&InterfaceFilter::All);
 
         assert_eq! (result, Ok (()));
         let new_server_addresses = CFArray::from_CFTypes(&[CFString::from_static_string ("1.2.3.4"), CFString::from_static_string ("5.6.7.8")]);
@@ -19,5 +19,13 @@ pub mod dns_modifier;
 pub mod dns_modifier_factory;
 pub mod winreg_dns_modifier;
 pub mod resolv_conf_dns_modifier;
+pub mod resolved_dns_modifier;
+pub mod network_manager_dns_modifier;
 pub mod dynamic_store_dns_modifier;
+pub mod network_setup_dns_modifier;
+pub mod command_runner;
+pub mod process_watcher;
+pub mod data_directory;
+pub mod dns_backup;
+pub mod doctor;
 pub mod utils;
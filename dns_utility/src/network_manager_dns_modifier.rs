@@ -0,0 +1,349 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+#![cfg (target_os = "linux")]
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use dns_modifier::DnsModifier;
+use dns_modifier::InterfaceStatus;
+use dns_modifier::InterfaceFilter;
+use command_runner::CommandRunner;
+use command_runner::CommandRunnerReal;
+
+// Where NetworkManager is in charge, it rewrites /etc/resolv.conf (or tells systemd-resolved what to
+// do) whenever a connection changes, so DNS settings have to be changed through it. Each connected
+// device's settings are changed with nmcli, but only the settings the device is using right now, not
+// the connection profile it got them from; so reverting is just a matter of reapplying the profile,
+// and nothing needs to be backed up.
+pub struct NetworkManagerDnsModifier {
+    runner: Box<CommandRunner>,
+    root: PathBuf,
+}
+
+#[derive (Clone, Debug, PartialEq)]
+struct Device {
+    name: String,
+    servers: Vec<String>,
+}
+
+impl DnsModifier for NetworkManagerDnsModifier {
+    fn type_name (&self) -> &'static str {
+        "NetworkManagerDnsModifier"
+    }
+
+    fn subvert (&self, filter: &InterfaceFilter) -> Result<(), String> {
+        let devices = self.find_chosen_devices (filter)?;
+        if devices.is_empty () {return Err (String::from ("This system does not appear to be connected to a network"))}
+        if devices.iter ().any (|device| makes_no_sense (&device.servers)) {
+            return Err (String::from ("This system's DNS settings don't make sense; aborting"))
+        }
+        let mut subverted: Vec<&Device> = vec! ();
+        for device in devices.iter ().filter (|device| !is_subverted (&device.servers)) {
+            match self.subvert_device (device) {
+                Ok (_) => subverted.push (device),
+                Err (msg) => {
+                    subverted.iter ().for_each (|device| {let _ = self.revert_device (device);});
+                    return Err (msg)
+                }
+            }
+        }
+        Ok (())
+    }
+
+    fn revert (&self, filter: &InterfaceFilter) -> Result<(), String> {
+        let devices = self.find_chosen_devices (filter)?;
+        let mut reverted: Vec<&Device> = vec! ();
+        for device in devices.iter ().filter (|device| is_subverted (&device.servers)) {
+            match self.revert_device (device) {
+                Ok (_) => reverted.push (device),
+                Err (msg) => {
+                    reverted.iter ().for_each (|device| {let _ = self.subvert_device (device);});
+                    return Err (msg)
+                }
+            }
+        }
+        Ok (())
+    }
+
+    fn inspect (&self) -> Result<Vec<InterfaceStatus>, String> {
+        Ok (self.find_devices ()?.into_iter ().map (|device| InterfaceStatus {
+            name: device.name,
+            subverted: is_subverted (&device.servers),
+            active: device.servers,
+            saved: None,
+        }).collect ())
+    }
+}
+
+impl NetworkManagerDnsModifier {
+    pub fn new () -> NetworkManagerDnsModifier {
+        NetworkManagerDnsModifier {
+            runner: Box::new (CommandRunnerReal::new ()),
+            root: PathBuf::from ("/"),
+        }
+    }
+
+    // NetworkManager can be running without being allowed to touch DNS, in which case whatever owns
+    // /etc/resolv.conf had better be dealt with instead
+    pub fn manages_dns (&self) -> bool {
+        match self.runner.run ("nmcli", &["-t", "-f", "RUNNING", "general"]) {
+            Ok (ref output) if output.trim () == "running" => (),
+            _ => return false
+        }
+        let path = Path::new (&self.root).join (Path::new ("etc")).join (Path::new ("resolv.conf"));
+        if let Ok (target) = fs::read_link (&path) {
+            let target = target.to_string_lossy ();
+            return target.contains ("NetworkManager") || target.contains ("systemd/resolve")
+        }
+        let mut contents = String::new ();
+        match File::open (&path).and_then (|mut file| file.read_to_string (&mut contents)) {
+            Ok (_) => contents.contains ("Generated by NetworkManager"),
+            Err (_) => false
+        }
+    }
+
+    // The devices the user wants changed; naming one that doesn't exist is an error
+    fn find_chosen_devices (&self, filter: &InterfaceFilter) -> Result<Vec<Device>, String> {
+        let devices = self.find_devices ()?;
+        filter.check (&devices.iter ().map (|device| device.name.clone ()).collect::<Vec<String>> ())?;
+        Ok (devices.into_iter ().filter (|device| filter.includes (&device.name)).collect ())
+    }
+
+    // Connected devices without DNS servers aren't how this system resolves names, so they're left alone.
+    // IPv6 servers count as much as IPv4 ones; router advertisements (RDNSS) can put them on a link
+    // that has no IPv4 servers at all.
+    fn find_devices (&self) -> Result<Vec<Device>, String> {
+        let output = self.runner.run ("nmcli", &["-t", "-f", "DEVICE,STATE", "device", "status"])?;
+        let mut devices: Vec<Device> = vec! ();
+        for name in connected_device_names (&output) {
+            let servers = parse_servers (&self.runner.run ("nmcli", &["-t", "-f", "IP4.DNS,IP6.DNS", "device", "show", name.as_str ()])?);
+            if !servers.is_empty () {devices.push (Device {name, servers})}
+        }
+        Ok (devices)
+    }
+
+    // The DNS server only listens on IPv4, so IPv6 servers are taken away rather than pointed at it;
+    // left alone, lookups would leak to them
+    fn subvert_device (&self, device: &Device) -> Result<(), String> {
+        self.runner.run ("nmcli", &["device", "modify", device.name.as_str (), "ipv4.ignore-auto-dns", "yes", "ipv4.dns", "127.0.0.1",
+            "ipv6.ignore-auto-dns", "yes", "ipv6.dns", ""]).map (|_| ())
+    }
+
+    fn revert_device (&self, device: &Device) -> Result<(), String> {
+        self.runner.run ("nmcli", &["device", "reapply", device.name.as_str ()]).map (|_| ())
+    }
+}
+
+// "eth0:connected"; in terse output, colons that aren't separators are escaped with backslashes
+fn connected_device_names (output: &str) -> Vec<String> {
+    output.lines ()
+        .flat_map (|line| {
+            let fields = split_terse (line);
+            if fields.len () == 2 && fields[1] == "connected" {Some (fields[0].clone ())} else {None}
+        })
+        .collect ()
+}
+
+// "IP4.DNS[1]:192.168.0.1", then "IP6.DNS[1]:fe80\:\:1"
+fn parse_servers (output: &str) -> Vec<String> {
+    output.lines ()
+        .flat_map (|line| {
+            let fields = split_terse (line);
+            let is_dns = fields[0].starts_with ("IP4.DNS") || fields[0].starts_with ("IP6.DNS");
+            if fields.len () == 2 && is_dns && !fields[1].is_empty () {Some (fields[1].clone ())} else {None}
+        })
+        .collect ()
+}
+
+fn split_terse (line: &str) -> Vec<String> {
+    let mut fields = vec! (String::new ());
+    let mut escaped = false;
+    for c in line.chars () {
+        if escaped {
+            fields.last_mut ().expect ("Internal error").push (c);
+            escaped = false;
+        }
+        else if c == '\\' {
+            escaped = true;
+        }
+        else if c == ':' {
+            fields.push (String::new ());
+        }
+        else {
+            fields.last_mut ().expect ("Internal error").push (c);
+        }
+    }
+    fields
+}
+
+fn is_subverted (servers: &Vec<String>) -> bool {
+    servers.first ().map (|server| server == "127.0.0.1").unwrap_or (false)
+}
+
+fn makes_no_sense (servers: &Vec<String>) -> bool {
+    !is_subverted (servers) && servers.contains (&String::from ("127.0.0.1"))
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Write;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use command_runner::tests::CommandRunnerMock;
+    use utils::get_parameters_from;
+
+    const DEVICE_STATUS: &str = "wlan0:connected\neth0:unavailable\ntun0:connected\nlo:unmanaged\n";
+
+    fn make_subject (runner: CommandRunnerMock) -> NetworkManagerDnsModifier {
+        let mut subject = NetworkManagerDnsModifier::new ();
+        subject.runner = Box::new (runner);
+        subject
+    }
+
+    #[test]
+    fn instance_knows_its_type_name () {
+        let subject = NetworkManagerDnsModifier::new ();
+
+        let result = subject.type_name ();
+
+        assert_eq! (result, "NetworkManagerDnsModifier");
+    }
+
+    #[test]
+    fn terse_output_is_parsed_with_escapes () {
+        assert_eq! (connected_device_names ("we\\:ird:connected\neth0:disconnected\n"), vec! (String::from ("we:ird")));
+        assert_eq! (parse_servers ("IP4.DNS[1]:192.168.1.1\nIP4.DNS[2]:8.8.8.8\n"), vec! (String::from ("192.168.1.1"), String::from ("8.8.8.8")));
+        assert_eq! (parse_servers ("IP4.DNS[1]:192.168.1.1\nIP6.DNS[1]:fe80\\:\\:1\n"), vec! (String::from ("192.168.1.1"), String::from ("fe80::1")));
+        assert_eq! (parse_servers (""), Vec::<String>::new ());
+    }
+
+    #[test]
+    fn manages_dns_only_if_running_and_in_charge_of_resolv_conf () {
+        let root = make_root ("manages_dns_only_if_running_and_in_charge_of_resolv_conf");
+        let etc = root.join ("etc");
+        fs::create_dir_all (&etc).unwrap ();
+        File::create (etc.join ("resolv.conf")).unwrap ().write_all (b"# Generated by NetworkManager\nnameserver 192.168.1.1\n").unwrap ();
+        let mut not_running = make_subject (CommandRunnerMock::new ().run_result (Err (String::from ("Couldn't run nmcli"))));
+        not_running.root = root.clone ();
+        let mut running = make_subject (CommandRunnerMock::new ().run_result (Ok (String::from ("running\n"))).run_result (Ok (String::from ("running\n"))));
+        running.root = root.clone ();
+
+        let not_running_result = not_running.manages_dns ();
+        let generated_result = running.manages_dns ();
+        File::create (etc.join ("resolv.conf")).unwrap ().write_all (b"nameserver 192.168.1.1\n").unwrap ();
+        let hand_made_result = running.manages_dns ();
+
+        assert_eq! ((not_running_result, generated_result, hand_made_result), (false, true, false));
+    }
+
+    #[test]
+    fn subvert_complains_if_no_connected_device_has_dns_servers () {
+        let subject = make_subject (CommandRunnerMock::new ()
+            .run_result (Ok (String::from ("wlan0:connected\n")))
+            .run_result (Ok (String::new ())));
+
+        let result = subject.subvert (&InterfaceFilter::All);
+
+        assert_eq! (result, Err (String::from ("This system does not appear to be connected to a network")));
+    }
+
+    #[test]
+    fn subvert_complains_if_dns_settings_dont_make_sense () {
+        let subject = make_subject (CommandRunnerMock::new ()
+            .run_result (Ok (String::from ("wlan0:connected\n")))
+            .run_result (Ok (String::from ("IP4.DNS[1]:8.8.8.8\nIP4.DNS[2]:127.0.0.1\n"))));
+
+        let result = subject.subvert (&InterfaceFilter::All);
+
+        assert_eq! (result, Err (String::from ("This system's DNS settings don't make sense; aborting")));
+    }
+
+    #[test]
+    fn subvert_points_every_connected_device_at_localhost_except_those_already_there () {
+        let run_parameters = Arc::new (Mutex::new (vec! ()));
+        let subject = make_subject (CommandRunnerMock::new ()
+            .run_parameters (&run_parameters)
+            .run_result (Ok (String::from (DEVICE_STATUS)))
+            .run_result (Ok (String::from ("IP4.DNS[1]:192.168.1.1\n")))
+            .run_result (Ok (String::from ("IP4.DNS[1]:127.0.0.1\n")))
+            .run_result (Ok (String::new ())));
+
+        let result = subject.subvert (&InterfaceFilter::All);
+
+        assert_eq! (result, Ok (()));
+        assert_eq! (get_parameters_from (run_parameters), vec! (
+            String::from ("nmcli -t -f DEVICE,STATE device status"),
+            String::from ("nmcli -t -f IP4.DNS,IP6.DNS device show wlan0"),
+            String::from ("nmcli -t -f IP4.DNS,IP6.DNS device show tun0"),
+            String::from ("nmcli device modify wlan0 ipv4.ignore-auto-dns yes ipv4.dns 127.0.0.1 ipv6.ignore-auto-dns yes ipv6.dns "),
+        ));
+    }
+
+    #[test]
+    fn subvert_takes_away_the_ipv6_servers_of_devices_that_have_only_those () {
+        let run_parameters = Arc::new (Mutex::new (vec! ()));
+        let subject = make_subject (CommandRunnerMock::new ()
+            .run_parameters (&run_parameters)
+            .run_result (Ok (String::from ("wlan0:connected\n")))
+            .run_result (Ok (String::from ("IP6.DNS[1]:2001\\:db8\\:\\:53\n")))
+            .run_result (Ok (String::new ())));
+
+        let result = subject.subvert (&InterfaceFilter::All);
+
+        assert_eq! (result, Ok (()));
+        assert_eq! (get_parameters_from (run_parameters)[2..].to_vec (), vec! (
+            String::from ("nmcli device modify wlan0 ipv4.ignore-auto-dns yes ipv4.dns 127.0.0.1 ipv6.ignore-auto-dns yes ipv6.dns "),
+        ));
+    }
+
+    #[test]
+    fn subvert_backs_out_successes_if_there_is_a_failure () {
+        let run_parameters = Arc::new (Mutex::new (vec! ()));
+        let subject = make_subject (CommandRunnerMock::new ()
+            .run_parameters (&run_parameters)
+            .run_result (Ok (String::from (DEVICE_STATUS)))
+            .run_result (Ok (String::from ("IP4.DNS[1]:192.168.1.1\n")))
+            .run_result (Ok (String::from ("IP4.DNS[1]:10.8.0.1\n")))
+            .run_result (Ok (String::new ()))
+            .run_result (Err (String::from ("Not authorized")))
+            .run_result (Ok (String::new ())));
+
+        let result = subject.subvert (&InterfaceFilter::All);
+
+        assert_eq! (result, Err (String::from ("Not authorized")));
+        assert_eq! (get_parameters_from (run_parameters)[3..].to_vec (), vec! (
+            String::from ("nmcli device modify wlan0 ipv4.ignore-auto-dns yes ipv4.dns 127.0.0.1 ipv6.ignore-auto-dns yes ipv6.dns "),
+            String::from ("nmcli device modify tun0 ipv4.ignore-auto-dns yes ipv4.dns 127.0.0.1 ipv6.ignore-auto-dns yes ipv6.dns "),
+            String::from ("nmcli device reapply wlan0"),
+        ));
+    }
+
+    #[test]
+    fn revert_reapplies_the_connection_profiles_of_subverted_devices () {
+        let run_parameters = Arc::new (Mutex::new (vec! ()));
+        let subject = make_subject (CommandRunnerMock::new ()
+            .run_parameters (&run_parameters)
+            .run_result (Ok (String::from (DEVICE_STATUS)))
+            .run_result (Ok (String::from ("IP4.DNS[1]:127.0.0.1\n")))
+            .run_result (Ok (String::from ("IP4.DNS[1]:10.8.0.1\n")))
+            .run_result (Ok (String::new ())));
+
+        let result = subject.revert (&InterfaceFilter::All);
+
+        assert_eq! (result, Ok (()));
+        assert_eq! (get_parameters_from (run_parameters)[3..].to_vec (), vec! (
+            String::from ("nmcli device reapply wlan0"),
+        ));
+    }
+
+    fn make_root (test_name: &str) -> PathBuf {
+        let cur_dir = env::current_dir ().unwrap ();
+        let base_dir = cur_dir.join (Path::new ("generated")).join (Path::new ("NetworkManagerDnsModifier")).join (Path::new (test_name));
+        fs::remove_dir_all (base_dir.clone ()).is_ok (); // don't care if it doesn't exist
+        fs::create_dir_all (base_dir.clone ()).unwrap ();
+        base_dir
+    }
+}
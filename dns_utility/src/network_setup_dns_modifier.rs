@@ -0,0 +1,354 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+#![cfg (target_os = "macos")]
+use std::collections::HashMap;
+use dns_modifier::DnsModifier;
+use dns_modifier::InterfaceStatus;
+use dns_modifier::InterfaceFilter;
+use command_runner::CommandRunner;
+use command_runner::CommandRunnerReal;
+use data_directory::data_directory;
+use dns_backup::DnsBackup;
+
+// Changes the DNS servers of every enabled network service the way System Preferences does, so that
+// the change lasts until it's reverted rather than until the network next changes. macOS won't say
+// what a service's servers were before they were set, so they're written down beforehand.
+pub struct NetworkSetupDnsModifier {
+    runner: Box<CommandRunner>,
+    backup: DnsBackup,
+}
+
+#[derive (Clone, Debug, PartialEq)]
+struct Service {
+    name: String,
+    servers: Vec<String>,
+}
+
+impl DnsModifier for NetworkSetupDnsModifier {
+    fn type_name (&self) -> &'static str {
+        "NetworkSetupDnsModifier"
+    }
+
+    fn subvert (&self, filter: &InterfaceFilter) -> Result<(), String> {
+        let services = self.find_services ()?;
+        let chosen = choose (&services, filter)?;
+        if chosen.iter ().any (|service| makes_no_sense (&service.servers)) {
+            return Err (String::from ("This system's DNS settings don't make sense; aborting"))
+        }
+        let to_subvert: Vec<&Service> = chosen.into_iter ().filter (|service| !is_subverted (&service.servers)).collect ();
+        if to_subvert.is_empty () {return Ok (())}
+        let mut backup = self.backup.read_fresh (&subverted_names (&services))?;
+        to_subvert.iter ().for_each (|service| {backup.insert (service.name.clone (), service.servers.clone ());});
+        self.backup.write (&backup)?;
+        let mut subverted: Vec<&Service> = vec! ();
+        for service in to_subvert {
+            match self.set_servers (&service.name, &vec! (String::from ("127.0.0.1"))) {
+                Ok (_) => subverted.push (service),
+                Err (msg) => {
+                    subverted.iter ().for_each (|service| {let _ = self.set_servers (&service.name, &service.servers);});
+                    return Err (msg)
+                }
+            }
+        }
+        Ok (())
+    }
+
+    fn revert (&self, filter: &InterfaceFilter) -> Result<(), String> {
+        let services = self.find_services ()?;
+        let backup = self.backup.read_fresh (&subverted_names (&services))?;
+        let to_revert: Vec<&Service> = choose (&services, filter)?.into_iter ().filter (|service| is_subverted (&service.servers)).collect ();
+        if to_revert.iter ().any (|service| !backup.contains_key (&service.name)) {
+            return Err (String::from ("This system has no backed-up DNS settings to restore; aborting"))
+        }
+        let mut reverted: Vec<&Service> = vec! ();
+        for service in to_revert {
+            match self.set_servers (&service.name, &backup[&service.name]) {
+                Ok (_) => reverted.push (service),
+                Err (msg) => {
+                    reverted.iter ().for_each (|service| {let _ = self.set_servers (&service.name, &vec! (String::from ("127.0.0.1")));});
+                    return Err (msg)
+                }
+            }
+        }
+        // Services that weren't chosen are still subverted, and still need what was recorded for them
+        let remaining: HashMap<String, Vec<String>> = backup.into_iter ().filter (|&(ref name, _)| !filter.includes (name)).collect ();
+        if remaining.is_empty () {self.backup.remove ()} else {self.backup.write (&remaining)}
+    }
+
+    fn inspect (&self) -> Result<Vec<InterfaceStatus>, String> {
+        let services = self.find_services ()?;
+        let backup = self.backup.read_fresh (&subverted_names (&services))?;
+        Ok (services.into_iter ().map (|service| InterfaceStatus {
+            subverted: is_subverted (&service.servers),
+            saved: backup.get (&service.name).cloned (),
+            name: service.name,
+            active: service.servers,
+        }).collect ())
+    }
+}
+
+impl NetworkSetupDnsModifier {
+    pub fn new () -> NetworkSetupDnsModifier {
+        NetworkSetupDnsModifier {
+            runner: Box::new (CommandRunnerReal::new ()),
+            backup: DnsBackup::new (&data_directory ()),
+        }
+    }
+
+    pub fn is_available (&self) -> bool {
+        self.runner.run ("networksetup", &["-listallnetworkservices"]).is_ok ()
+    }
+
+    // Disabled services are marked with asterisks, and the list is preceded by a line explaining so
+    fn find_services (&self) -> Result<Vec<Service>, String> {
+        let output = self.run ("networksetup", &["-listallnetworkservices"])?;
+        let names: Vec<String> = output.lines ().skip (1)
+            .filter (|line| !line.is_empty () && !line.starts_with ('*'))
+            .map (String::from)
+            .collect ();
+        if names.is_empty () {return Err (String::from ("This system has no enabled network services; DNS settings cannot be modified"))}
+        let mut services: Vec<Service> = vec! ();
+        for name in names {
+            let servers = parse_servers (&self.run ("networksetup", &["-getdnsservers", name.as_str ()])?);
+            services.push (Service {name, servers});
+        }
+        Ok (services)
+    }
+
+    fn set_servers (&self, service_name: &str, servers: &Vec<String>) -> Result<(), String> {
+        let mut args: Vec<&str> = vec! ("-setdnsservers", service_name);
+        if servers.is_empty () {args.push ("Empty")} else {args.extend (servers.iter ().map (|server| server.as_str ()))}
+        self.run ("networksetup", &args).map (|_| ())
+    }
+
+    // networksetup often reports trouble on standard output and exits happily anyway
+    fn run (&self, program: &str, args: &[&str]) -> Result<String, String> {
+        let output = self.runner.run (program, args)?;
+        if output.starts_with ("** Error") {
+            if output.contains ("requires admin privileges") {return Err (String::from ("Error changing DNS settings. Are you sure you ran me with sudo?"))}
+            return Err (output.trim ().to_string ())
+        }
+        Ok (output)
+    }
+}
+
+// Either one address per line, or "There aren't any DNS Servers set on Wi-Fi."
+fn parse_servers (output: &str) -> Vec<String> {
+    if output.starts_with ("There aren't any") {return vec! ()}
+    output.lines ().map (|line| line.trim ()).filter (|line| !line.is_empty ()).map (String::from).collect ()
+}
+
+fn choose<'a> (services: &'a Vec<Service>, filter: &InterfaceFilter) -> Result<Vec<&'a Service>, String> {
+    filter.check (&services.iter ().map (|service| service.name.clone ()).collect::<Vec<String>> ())?;
+    Ok (services.iter ().filter (|service| filter.includes (&service.name)).collect ())
+}
+
+fn subverted_names (services: &Vec<Service>) -> Vec<String> {
+    services.iter ().filter (|service| is_subverted (&service.servers)).map (|service| service.name.clone ()).collect ()
+}
+
+fn is_subverted (servers: &Vec<String>) -> bool {
+    servers.first ().map (|server| server == "127.0.0.1").unwrap_or (false)
+}
+
+fn makes_no_sense (servers: &Vec<String>) -> bool {
+    !is_subverted (servers) && servers.contains (&String::from ("127.0.0.1"))
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use command_runner::tests::CommandRunnerMock;
+    use utils::get_parameters_from;
+
+    const SERVICES: &str = "An asterisk (*) denotes that a network service is disabled.\nWi-Fi\n*Bluetooth PAN\nUSB Ethernet\n";
+
+    fn make_subject (test_name: &str, runner: CommandRunnerMock) -> NetworkSetupDnsModifier {
+        let cur_dir = env::current_dir ().unwrap ();
+        let root = cur_dir.join (Path::new ("generated")).join (Path::new ("NetworkSetupDnsModifier")).join (Path::new (test_name));
+        fs::remove_dir_all (root.clone ()).is_ok (); // don't care if it doesn't exist
+        fs::create_dir_all (root.clone ()).unwrap ();
+        let mut subject = NetworkSetupDnsModifier::new ();
+        subject.runner = Box::new (runner);
+        subject.backup = DnsBackup::new (&root);
+        subject
+    }
+
+    fn no_servers (service: &str) -> Result<String, String> {
+        Ok (format! ("There aren't any DNS Servers set on {}.\n", service))
+    }
+
+    #[test]
+    fn instance_knows_its_type_name () {
+        let subject = NetworkSetupDnsModifier::new ();
+
+        let result = subject.type_name ();
+
+        assert_eq! (result, "NetworkSetupDnsModifier");
+    }
+
+    #[test]
+    fn subvert_records_every_enabled_services_servers_then_points_them_at_localhost () {
+        let run_parameters = Arc::new (Mutex::new (vec! ()));
+        let subject = make_subject ("subvert_records_every_enabled_services_servers_then_points_them_at_localhost", CommandRunnerMock::new ()
+            .run_parameters (&run_parameters)
+            .run_result (Ok (String::from (SERVICES)))
+            .run_result (Ok (String::from ("8.8.8.8\n8.8.4.4\n")))
+            .run_result (no_servers ("USB Ethernet"))
+            .run_result (Ok (String::new ()))
+            .run_result (Ok (String::new ())));
+
+        let result = subject.subvert (&InterfaceFilter::All);
+
+        assert_eq! (result, Ok (()));
+        assert_eq! (get_parameters_from (run_parameters)[3..].to_vec (), vec! (
+            String::from ("networksetup -setdnsservers Wi-Fi 127.0.0.1"),
+            String::from ("networksetup -setdnsservers USB Ethernet 127.0.0.1"),
+        ));
+        let backup = subject.backup.read ().unwrap ();
+        assert_eq! (backup.get ("Wi-Fi"), Some (&vec! (String::from ("8.8.8.8"), String::from ("8.8.4.4"))));
+        assert_eq! (backup.get ("USB Ethernet"), Some (&vec! ()));
+        assert_eq! (backup.len (), 2);
+    }
+
+    #[test]
+    fn subvert_forgets_servers_recorded_for_services_that_are_no_longer_subverted () {
+        let subject = make_subject ("subvert_forgets_servers_recorded_for_services_that_are_no_longer_subverted", CommandRunnerMock::new ()
+            .run_result (Ok (String::from (SERVICES)))
+            .run_result (Ok (String::from ("10.0.0.1\n")))
+            .run_result (Ok (String::from ("127.0.0.1\n")))
+            .run_result (Ok (String::new ())));
+        let mut stale_backup = HashMap::new ();
+        stale_backup.insert (String::from ("Wi-Fi"), vec! (String::from ("8.8.8.8")));
+        stale_backup.insert (String::from ("USB Ethernet"), vec! ());
+        stale_backup.insert (String::from ("Thunderbolt Bridge"), vec! (String::from ("1.1.1.1")));
+        subject.backup.write (&stale_backup).unwrap ();
+
+        let result = subject.subvert (&InterfaceFilter::All);
+
+        assert_eq! (result, Ok (()));
+        let mut expected = HashMap::new ();
+        expected.insert (String::from ("Wi-Fi"), vec! (String::from ("10.0.0.1")));
+        expected.insert (String::from ("USB Ethernet"), vec! ());
+        assert_eq! (subject.backup.read (), Ok (expected));
+    }
+
+    #[test]
+    fn subvert_complains_if_dns_settings_dont_make_sense () {
+        let subject = make_subject ("subvert_complains_if_dns_settings_dont_make_sense", CommandRunnerMock::new ()
+            .run_result (Ok (String::from (SERVICES)))
+            .run_result (Ok (String::from ("8.8.8.8\n127.0.0.1\n")))
+            .run_result (no_servers ("USB Ethernet")));
+
+        let result = subject.subvert (&InterfaceFilter::All);
+
+        assert_eq! (result, Err (String::from ("This system's DNS settings don't make sense; aborting")));
+    }
+
+    #[test]
+    fn subvert_backs_out_successes_if_there_is_a_failure () {
+        let run_parameters = Arc::new (Mutex::new (vec! ()));
+        let subject = make_subject ("subvert_backs_out_successes_if_there_is_a_failure", CommandRunnerMock::new ()
+            .run_parameters (&run_parameters)
+            .run_result (Ok (String::from (SERVICES)))
+            .run_result (Ok (String::from ("8.8.8.8\n")))
+            .run_result (no_servers ("USB Ethernet"))
+            .run_result (Ok (String::new ()))
+            .run_result (Ok (String::from ("** Error: Command requires admin privileges.\n")))
+            .run_result (Ok (String::new ())));
+
+        let result = subject.subvert (&InterfaceFilter::All);
+
+        assert_eq! (result, Err (String::from ("Error changing DNS settings. Are you sure you ran me with sudo?")));
+        assert_eq! (get_parameters_from (run_parameters)[3..].to_vec (), vec! (
+            String::from ("networksetup -setdnsservers Wi-Fi 127.0.0.1"),
+            String::from ("networksetup -setdnsservers USB Ethernet 127.0.0.1"),
+            String::from ("networksetup -setdnsservers Wi-Fi 8.8.8.8"),
+        ));
+    }
+
+    #[test]
+    fn revert_restores_recorded_servers_and_forgets_them () {
+        let run_parameters = Arc::new (Mutex::new (vec! ()));
+        let subject = make_subject ("revert_restores_recorded_servers_and_forgets_them", CommandRunnerMock::new ()
+            .run_parameters (&run_parameters)
+            .run_result (Ok (String::from (SERVICES)))
+            .run_result (Ok (String::from ("127.0.0.1\n")))
+            .run_result (Ok (String::from ("127.0.0.1\n")))
+            .run_result (Ok (String::new ()))
+            .run_result (Ok (String::new ())));
+        let mut backup = HashMap::new ();
+        backup.insert (String::from ("Wi-Fi"), vec! (String::from ("8.8.8.8"), String::from ("8.8.4.4")));
+        backup.insert (String::from ("USB Ethernet"), vec! ());
+        subject.backup.write (&backup).unwrap ();
+
+        let result = subject.revert (&InterfaceFilter::All);
+
+        assert_eq! (result, Ok (()));
+        assert_eq! (get_parameters_from (run_parameters)[3..].to_vec (), vec! (
+            String::from ("networksetup -setdnsservers Wi-Fi 8.8.8.8 8.8.4.4"),
+            String::from ("networksetup -setdnsservers USB Ethernet Empty"),
+        ));
+        assert_eq! (subject.backup.path ().exists (), false);
+    }
+
+    #[test]
+    fn revert_restores_only_the_named_services_and_keeps_what_was_recorded_for_the_others () {
+        let run_parameters = Arc::new (Mutex::new (vec! ()));
+        let subject = make_subject ("revert_restores_only_the_named_services_and_keeps_what_was_recorded_for_the_others", CommandRunnerMock::new ()
+            .run_parameters (&run_parameters)
+            .run_result (Ok (String::from (SERVICES)))
+            .run_result (Ok (String::from ("127.0.0.1\n")))
+            .run_result (Ok (String::from ("127.0.0.1\n")))
+            .run_result (Ok (String::new ())));
+        let mut backup = HashMap::new ();
+        backup.insert (String::from ("Wi-Fi"), vec! (String::from ("8.8.8.8")));
+        backup.insert (String::from ("USB Ethernet"), vec! ());
+        subject.backup.write (&backup).unwrap ();
+
+        let result = subject.revert (&InterfaceFilter::Only (vec! (String::from ("Wi-Fi"))));
+
+        assert_eq! (result, Ok (()));
+        assert_eq! (get_parameters_from (run_parameters)[3..].to_vec (), vec! (
+            String::from ("networksetup -setdnsservers Wi-Fi 8.8.8.8"),
+        ));
+        let mut expected = HashMap::new ();
+        expected.insert (String::from ("USB Ethernet"), vec! ());
+        assert_eq! (subject.backup.read (), Ok (expected));
+    }
+
+    #[test]
+    fn inspect_reports_each_enabled_service_with_what_was_recorded_for_it () {
+        let subject = make_subject ("inspect_reports_each_enabled_service_with_what_was_recorded_for_it", CommandRunnerMock::new ()
+            .run_result (Ok (String::from (SERVICES)))
+            .run_result (Ok (String::from ("127.0.0.1\n")))
+            .run_result (no_servers ("USB Ethernet")));
+        let mut backup = HashMap::new ();
+        backup.insert (String::from ("Wi-Fi"), vec! ());
+        subject.backup.write (&backup).unwrap ();
+
+        let result = subject.inspect ();
+
+        assert_eq! (result, Ok (vec! (
+            InterfaceStatus {name: String::from ("Wi-Fi"), subverted: true, active: vec! (String::from ("127.0.0.1")), saved: Some (vec! ())},
+            InterfaceStatus {name: String::from ("USB Ethernet"), subverted: false, active: vec! (), saved: None},
+        )));
+    }
+
+    #[test]
+    fn revert_complains_if_a_subverted_service_has_no_recorded_servers () {
+        let subject = make_subject ("revert_complains_if_a_subverted_service_has_no_recorded_servers", CommandRunnerMock::new ()
+            .run_result (Ok (String::from (SERVICES)))
+            .run_result (Ok (String::from ("127.0.0.1\n")))
+            .run_result (no_servers ("USB Ethernet")));
+
+        let result = subject.revert (&InterfaceFilter::All);
+
+        assert_eq! (result, Err (String::from ("This system has no backed-up DNS settings to restore; aborting")));
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::thread;
+use std::time::Duration;
+#[cfg (unix)]
+use std::io;
+#[cfg (unix)]
+use libc;
+#[cfg (windows)]
+use command_runner::CommandRunner;
+#[cfg (windows)]
+use command_runner::CommandRunnerReal;
+
+// If the Node dies with DNS subverted, nothing on the machine can look up a name until somebody
+// reverts it; whoever is keeping an eye on the Node needs to know when it's gone.
+pub trait ProcessWatcher {
+    fn is_running (&self, pid: u32) -> bool;
+    fn pause (&self);
+}
+
+pub struct ProcessWatcherReal {
+    interval: Duration,
+}
+
+impl ProcessWatcher for ProcessWatcherReal {
+    // Signal 0 isn't delivered; it only asks whether the process could be signalled. A process
+    // belonging to somebody else that we aren't allowed to signal is still there.
+    #[cfg (unix)]
+    fn is_running (&self, pid: u32) -> bool {
+        if unsafe {libc::kill (pid as libc::pid_t, 0)} == 0 {return true}
+        io::Error::last_os_error ().raw_os_error () == Some (libc::EPERM)
+    }
+
+    #[cfg (windows)]
+    fn is_running (&self, pid: u32) -> bool {
+        let filter = format! ("PID eq {}", pid);
+        match CommandRunnerReal::new ().run ("tasklist", &["/FI", filter.as_str (), "/NH", "/FO", "CSV"]) {
+            Ok (output) => output.contains (&format! ("\"{}\"", pid)),
+            // If we can't tell, assume it's still there rather than pull DNS out from under it
+            Err (_) => true
+        }
+    }
+
+    fn pause (&self) {
+        thread::sleep (self.interval);
+    }
+}
+
+impl ProcessWatcherReal {
+    pub fn new () -> ProcessWatcherReal {
+        ProcessWatcherReal {interval: Duration::from_secs (1)}
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    #[cfg (unix)]
+    use std::process;
+
+    #[cfg (unix)]
+    #[test]
+    fn real_watcher_can_tell_running_processes_from_finished_ones () {
+        let subject = ProcessWatcherReal::new ();
+        let mut child = process::Command::new ("sh").args (&["-c", "exit 0"]).spawn ().unwrap ();
+        let child_pid = child.id ();
+        child.wait ().unwrap ();
+
+        assert_eq! (subject.is_running (process::id ()), true);
+        assert_eq! (subject.is_running (child_pid), false);
+    }
+}
@@ -13,6 +13,8 @@ use std::path::Path;
 use std::path::PathBuf;
 use regex::Regex;
 use dns_modifier::DnsModifier;
+use dns_modifier::InterfaceStatus;
+use dns_modifier::InterfaceFilter;
 
 pub struct ResolvConfDnsModifier {
     root: PathBuf
@@ -24,18 +26,36 @@ impl DnsModifier for ResolvConfDnsModifier {
     }
 
     #[allow (unused_mut)]
-    fn subvert(&self) -> Result<(), String> {
+    fn subvert(&self, filter: &InterfaceFilter) -> Result<(), String> {
+        filter.require_all ()?;
         let (mut file, contents_before) = self.open_resolv_conf()?;
         let contents_after = self.subvert_contents (contents_before)?;
         self.replace_contents (file, contents_after)
     }
 
     #[allow (unused_mut)]
-    fn revert(&self) -> Result<(), String> {
+    fn revert(&self, filter: &InterfaceFilter) -> Result<(), String> {
+        filter.require_all ()?;
         let (mut file, contents_before) = self.open_resolv_conf()?;
         let contents_after = self.revert_contents (contents_before)?;
         self.replace_contents (file, contents_after)
     }
+
+    // What revert would uncomment is what was saved
+    fn inspect (&self) -> Result<Vec<InterfaceStatus>, String> {
+        let contents = self.read_resolv_conf ()?;
+        let active: Vec<String> = self.active_nameservers (&contents[..]).into_iter ()
+            .flat_map (|(entry, _)| entry.split_whitespace ().nth (1).map (String::from))
+            .collect ();
+        let subverted = active.first () == Some (&String::from ("127.0.0.1"));
+        let saved = if subverted {
+            Some (self.existing_nameservers (&contents[..]).into_iter ()
+                .filter (|&(ref entry, _)| entry.starts_with ('#'))
+                .flat_map (|(entry, _)| entry[1..].split_whitespace ().nth (1).map (String::from))
+                .collect ())
+        } else {None};
+        Ok (vec! (InterfaceStatus {name: String::from ("/etc/resolv.conf"), subverted, active, saved}))
+    }
 }
 
 impl ResolvConfDnsModifier {
@@ -65,6 +85,18 @@ impl ResolvConfDnsModifier {
         Ok ((file, contents))
     }
 
+    // Only reading doesn't take the privileges that changing does
+    fn read_resolv_conf (&self) -> Result<String, String> {
+        let path = Path::new (&self.root).join (Path::new ("etc")).join (Path::new ("resolv.conf"));
+        let mut contents = String::new ();
+        match File::open (path.clone ()).and_then (|mut file| file.read_to_string (&mut contents)) {
+            Ok (_) => Ok (contents),
+            Err (ref e) if e.kind () == ErrorKind::NotFound => Err (String::from ("/etc/resolv.conf was not found")),
+            Err (ref e) if e.kind () == ErrorKind::InvalidData => Err (String::from ("/etc/resolv.conf is not a UTF-8 text file")),
+            Err (e) => Err (format! ("Unexpected error reading {:?}: {}", path, e))
+        }
+    }
+
     fn subvert_contents (&self, contents_before: String) -> Result<String, String> {
         let active_nameservers = self.active_nameservers (&contents_before[..]);
         self.check_disconnected (&active_nameservers)?;
@@ -367,7 +399,7 @@ mod tests {
         let mut subject = ResolvConfDnsModifier::new ();
         subject.root = root;
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result.err ().unwrap (), String::from ("/etc/resolv.conf was not found and could not be modified"))
     }
@@ -379,7 +411,7 @@ mod tests {
         let mut subject = ResolvConfDnsModifier::new ();
         subject.root = root;
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result.err ().unwrap (), String::from ("/etc/resolv.conf is a directory and could not be modified"))
     }
@@ -394,7 +426,7 @@ mod tests {
         let mut subject = ResolvConfDnsModifier::new ();
         subject.root = root;
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result.err ().unwrap (), String::from ("/etc/resolv.conf is not readable and writable and could not be modified"))
     }
@@ -409,7 +441,7 @@ mod tests {
         let mut subject = ResolvConfDnsModifier::new ();
         subject.root = root;
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result.err ().unwrap (), String::from ("/etc/resolv.conf is not readable and writable and could not be modified"))
     }
@@ -423,7 +455,7 @@ mod tests {
         let mut subject = ResolvConfDnsModifier::new ();
         subject.root = root;
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result.err ().unwrap (), String::from ("/etc/resolv.conf is not a UTF-8 text file and could not be modified"))
     }
@@ -435,7 +467,7 @@ mod tests {
         let mut subject = ResolvConfDnsModifier::new ();
         subject.root = root.clone ();
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         let contents = get_resolv_conf (&root);
         assert_eq! (contents, String::from (
@@ -451,7 +483,7 @@ mod tests {
         let mut subject = ResolvConfDnsModifier::new ();
         subject.root = root;
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result.err ().unwrap (), String::from ("This system's DNS settings don't make sense; aborting"))
     }
@@ -463,7 +495,7 @@ mod tests {
         let mut subject = ResolvConfDnsModifier::new ();
         subject.root = root;
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result.err ().unwrap (), String::from ("This system does not appear to be connected to a network"))
     }
@@ -475,7 +507,7 @@ mod tests {
         let mut subject = ResolvConfDnsModifier::new ();
         subject.root = root.clone ();
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         let contents = get_resolv_conf (&root);
         assert_eq! (contents, String::from (
@@ -484,6 +516,31 @@ mod tests {
         assert_eq! (result.is_ok (), true);
     }
 
+    #[test]
+    fn inspect_reports_active_and_commented_out_nameservers () {
+        let root = make_root ("inspect_reports_active_and_commented_out_nameservers");
+        make_resolv_conf (&root, "#comment\n## nameserver 1.1.1.1\n#nameserver 8.8.8.8\n# nameserver 9.9.9.9\nnameserver 127.0.0.1\n");
+        let mut subject = ResolvConfDnsModifier::new ();
+        subject.root = root.clone ();
+
+        let subverted_result = subject.inspect ();
+        make_resolv_conf (&root, "#nameserver 1.1.1.1\nnameserver 8.8.8.8\n");
+        let unsubverted_result = subject.inspect ();
+
+        assert_eq! (subverted_result, Ok (vec! (InterfaceStatus {
+            name: String::from ("/etc/resolv.conf"),
+            subverted: true,
+            active: vec! (String::from ("127.0.0.1")),
+            saved: Some (vec! (String::from ("8.8.8.8"), String::from ("9.9.9.9"))),
+        })));
+        assert_eq! (unsubverted_result, Ok (vec! (InterfaceStatus {
+            name: String::from ("/etc/resolv.conf"),
+            subverted: false,
+            active: vec! (String::from ("8.8.8.8")),
+            saved: None,
+        })));
+    }
+
     #[test]
     fn revert_backs_off_if_dns_is_not_subverted () {
         let root = make_root ("revert_backs_off_if_dns_is_not_subverted");
@@ -491,7 +548,7 @@ mod tests {
         let mut subject = ResolvConfDnsModifier::new ();
         subject.root = root.clone ();
 
-        let result = subject.revert ();
+        let result = subject.revert (&InterfaceFilter::All);
 
         let contents = get_resolv_conf (&root);
         assert_eq! (contents, String::from (
@@ -507,7 +564,7 @@ mod tests {
         let mut subject = ResolvConfDnsModifier::new ();
         subject.root = root;
 
-        let result = subject.revert ();
+        let result = subject.revert (&InterfaceFilter::All);
 
         assert_eq! (result.err ().unwrap (), String::from ("This system's DNS settings don't make sense; aborting"))
     }
@@ -519,7 +576,7 @@ mod tests {
         let mut subject = ResolvConfDnsModifier::new ();
         subject.root = root;
 
-        let result = subject.revert ();
+        let result = subject.revert (&InterfaceFilter::All);
 
         assert_eq! (result.err ().unwrap (), String::from ("There do not appear to be any DNS settings to revert to"))
     }
@@ -531,7 +588,7 @@ mod tests {
         let mut subject = ResolvConfDnsModifier::new ();
         subject.root = root.clone ();
 
-        let result = subject.revert ();
+        let result = subject.revert (&InterfaceFilter::All);
 
         let contents = get_resolv_conf (&root);
         assert_eq! (contents, String::from (
@@ -0,0 +1,331 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+#![cfg (target_os = "linux")]
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use dns_modifier::DnsModifier;
+use dns_modifier::InterfaceStatus;
+use dns_modifier::InterfaceFilter;
+use command_runner::CommandRunner;
+use command_runner::CommandRunnerReal;
+
+// Where systemd-resolved is in charge, /etc/resolv.conf is a link to a file it writes (usually one that
+// points at its own stub resolver), so changing the file would accomplish nothing. Instead each link's
+// DNS servers are changed with resolvectl; resolvectl can also put a link back the way the network
+// configuration says it should be, so nothing needs to be backed up.
+pub struct ResolvedDnsModifier {
+    runner: Box<CommandRunner>,
+    root: PathBuf,
+}
+
+#[derive (Clone, Debug, PartialEq)]
+struct Link {
+    name: String,
+    servers: Vec<String>,
+}
+
+impl DnsModifier for ResolvedDnsModifier {
+    fn type_name (&self) -> &'static str {
+        "ResolvedDnsModifier"
+    }
+
+    fn subvert (&self, filter: &InterfaceFilter) -> Result<(), String> {
+        let links = self.find_chosen_links (filter)?;
+        if links.is_empty () {return Err (String::from ("This system does not appear to be connected to a network"))}
+        if links.iter ().any (|link| makes_no_sense (&link.servers)) {
+            return Err (String::from ("This system's DNS settings don't make sense; aborting"))
+        }
+        let mut subverted: Vec<&Link> = vec! ();
+        for link in links.iter ().filter (|link| !is_subverted (&link.servers)) {
+            match self.runner.run ("resolvectl", &["dns", link.name.as_str (), "127.0.0.1"]) {
+                Ok (_) => subverted.push (link),
+                Err (msg) => {
+                    subverted.iter ().for_each (|link| {let _ = self.runner.run ("resolvectl", &["revert", link.name.as_str ()]);});
+                    return Err (msg)
+                }
+            }
+        }
+        Ok (())
+    }
+
+    fn revert (&self, filter: &InterfaceFilter) -> Result<(), String> {
+        let links = self.find_chosen_links (filter)?;
+        let mut reverted: Vec<&Link> = vec! ();
+        for link in links.iter ().filter (|link| is_subverted (&link.servers)) {
+            match self.runner.run ("resolvectl", &["revert", link.name.as_str ()]) {
+                Ok (_) => reverted.push (link),
+                Err (msg) => {
+                    reverted.iter ().for_each (|link| {let _ = self.runner.run ("resolvectl", &["dns", link.name.as_str (), "127.0.0.1"]);});
+                    return Err (msg)
+                }
+            }
+        }
+        Ok (())
+    }
+
+    fn inspect (&self) -> Result<Vec<InterfaceStatus>, String> {
+        Ok (self.find_links ()?.into_iter ().map (|link| InterfaceStatus {
+            name: link.name,
+            subverted: is_subverted (&link.servers),
+            active: link.servers,
+            saved: None,
+        }).collect ())
+    }
+}
+
+impl ResolvedDnsModifier {
+    pub fn new () -> ResolvedDnsModifier {
+        ResolvedDnsModifier {
+            runner: Box::new (CommandRunnerReal::new ()),
+            root: PathBuf::from ("/"),
+        }
+    }
+
+    pub fn manages_dns (&self) -> bool {
+        let path = Path::new (&self.root).join (Path::new ("etc")).join (Path::new ("resolv.conf"));
+        match fs::read_link (path) {
+            Ok (target) => target.to_string_lossy ().contains ("systemd/resolve"),
+            Err (_) => false
+        }
+    }
+
+    // The links the user wants changed; naming one that doesn't exist is an error
+    fn find_chosen_links (&self, filter: &InterfaceFilter) -> Result<Vec<Link>, String> {
+        let links = self.find_links ()?;
+        filter.check (&links.iter ().map (|link| link.name.clone ()).collect::<Vec<String>> ())?;
+        Ok (links.into_iter ().filter (|link| filter.includes (&link.name)).collect ())
+    }
+
+    // Links without DNS servers aren't how this system resolves names, so they're left alone
+    fn find_links (&self) -> Result<Vec<Link>, String> {
+        let output = self.runner.run ("resolvectl", &["dns"])?;
+        Ok (parse_links (&output).into_iter ().filter (|link| !link.servers.is_empty ()).collect ())
+    }
+}
+
+// "Link 2 (eth0): 192.168.0.1 8.8.8.8"; the global servers, if any, are set in resolved.conf and can't be changed here
+fn parse_links (output: &str) -> Vec<Link> {
+    output.lines ()
+        .filter (|line| line.starts_with ("Link "))
+        .flat_map (|line| {
+            let open = line.find ('(')?;
+            let close = line.find ("):")?;
+            if close < open {return None}
+            Some (Link {
+                name: String::from (&line[(open + 1)..close]),
+                servers: line[(close + 2)..].split_whitespace ().map (String::from).collect (),
+            })
+        })
+        .collect ()
+}
+
+fn is_subverted (servers: &Vec<String>) -> bool {
+    servers.first ().map (|server| server == "127.0.0.1").unwrap_or (false)
+}
+
+fn makes_no_sense (servers: &Vec<String>) -> bool {
+    !is_subverted (servers) && servers.contains (&String::from ("127.0.0.1"))
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::os::unix::fs::symlink;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use command_runner::tests::CommandRunnerMock;
+    use utils::get_parameters_from;
+
+    const RESOLVECTL_DNS: &str = "Global:\nLink 3 (wlan0): 192.168.1.1 fe80::1%3\nLink 2 (eth0): 10.0.2.3\nLink 4 (docker0):\n";
+
+    fn make_subject (runner: CommandRunnerMock) -> ResolvedDnsModifier {
+        let mut subject = ResolvedDnsModifier::new ();
+        subject.runner = Box::new (runner);
+        subject
+    }
+
+    #[test]
+    fn instance_knows_its_type_name () {
+        let subject = ResolvedDnsModifier::new ();
+
+        let result = subject.type_name ();
+
+        assert_eq! (result, "ResolvedDnsModifier");
+    }
+
+    #[test]
+    fn links_are_parsed_with_their_servers () {
+        let result = parse_links (RESOLVECTL_DNS);
+
+        assert_eq! (result, vec! (
+            Link {name: String::from ("wlan0"), servers: vec! (String::from ("192.168.1.1"), String::from ("fe80::1%3"))},
+            Link {name: String::from ("eth0"), servers: vec! (String::from ("10.0.2.3"))},
+            Link {name: String::from ("docker0"), servers: vec! ()},
+        ));
+    }
+
+    #[test]
+    fn manages_dns_only_if_resolv_conf_leads_to_systemd_resolved () {
+        let root = make_root ("manages_dns_only_if_resolv_conf_leads_to_systemd_resolved");
+        let etc = root.join ("etc");
+        fs::create_dir_all (&etc).unwrap ();
+        let mut subject = ResolvedDnsModifier::new ();
+        subject.root = root.clone ();
+
+        let missing = subject.manages_dns ();
+        symlink ("../run/systemd/resolve/stub-resolv.conf", etc.join ("resolv.conf")).unwrap ();
+        let linked = subject.manages_dns ();
+        fs::remove_file (etc.join ("resolv.conf")).unwrap ();
+        symlink ("../run/NetworkManager/resolv.conf", etc.join ("resolv.conf")).unwrap ();
+        let linked_elsewhere = subject.manages_dns ();
+
+        assert_eq! ((missing, linked, linked_elsewhere), (false, true, false));
+    }
+
+    #[test]
+    fn inspect_reports_each_link_with_servers () {
+        let subject = make_subject (CommandRunnerMock::new ()
+            .run_result (Ok (String::from ("Link 3 (wlan0): 127.0.0.1\nLink 2 (eth0): 10.0.2.3\nLink 4 (docker0):\n"))));
+
+        let result = subject.inspect ();
+
+        assert_eq! (result, Ok (vec! (
+            InterfaceStatus {name: String::from ("wlan0"), subverted: true, active: vec! (String::from ("127.0.0.1")), saved: None},
+            InterfaceStatus {name: String::from ("eth0"), subverted: false, active: vec! (String::from ("10.0.2.3")), saved: None},
+        )));
+    }
+
+    #[test]
+    fn subvert_complains_if_no_link_has_dns_servers () {
+        let subject = make_subject (CommandRunnerMock::new ()
+            .run_result (Ok (String::from ("Global:\nLink 1 (lo):\n"))));
+
+        let result = subject.subvert (&InterfaceFilter::All);
+
+        assert_eq! (result, Err (String::from ("This system does not appear to be connected to a network")));
+    }
+
+    #[test]
+    fn subvert_complains_if_dns_settings_dont_make_sense () {
+        let subject = make_subject (CommandRunnerMock::new ()
+            .run_result (Ok (String::from ("Link 2 (eth0): 8.8.8.8 127.0.0.1\n"))));
+
+        let result = subject.subvert (&InterfaceFilter::All);
+
+        assert_eq! (result, Err (String::from ("This system's DNS settings don't make sense; aborting")));
+    }
+
+    #[test]
+    fn subvert_points_every_link_with_servers_at_localhost_except_those_already_there () {
+        let run_parameters = Arc::new (Mutex::new (vec! ()));
+        let subject = make_subject (CommandRunnerMock::new ()
+            .run_parameters (&run_parameters)
+            .run_result (Ok (String::from ("Link 3 (wlan0): 192.168.1.1\nLink 2 (eth0): 127.0.0.1\nLink 5 (tun0): 10.8.0.1\n")))
+            .run_result (Ok (String::new ()))
+            .run_result (Ok (String::new ())));
+
+        let result = subject.subvert (&InterfaceFilter::All);
+
+        assert_eq! (result, Ok (()));
+        assert_eq! (get_parameters_from (run_parameters), vec! (
+            String::from ("resolvectl dns"),
+            String::from ("resolvectl dns wlan0 127.0.0.1"),
+            String::from ("resolvectl dns tun0 127.0.0.1"),
+        ));
+    }
+
+    #[test]
+    fn subvert_points_only_the_named_links_at_localhost () {
+        let run_parameters = Arc::new (Mutex::new (vec! ()));
+        let subject = make_subject (CommandRunnerMock::new ()
+            .run_parameters (&run_parameters)
+            .run_result (Ok (String::from (RESOLVECTL_DNS)))
+            .run_result (Ok (String::new ())));
+
+        let result = subject.subvert (&InterfaceFilter::Only (vec! (String::from ("eth0"))));
+
+        assert_eq! (result, Ok (()));
+        assert_eq! (get_parameters_from (run_parameters), vec! (
+            String::from ("resolvectl dns"),
+            String::from ("resolvectl dns eth0 127.0.0.1"),
+        ));
+    }
+
+    #[test]
+    fn subvert_complains_about_a_named_link_that_has_no_dns_servers () {
+        let subject = make_subject (CommandRunnerMock::new ()
+            .run_result (Ok (String::from (RESOLVECTL_DNS))));
+
+        let result = subject.subvert (&InterfaceFilter::Only (vec! (String::from ("docker0"))));
+
+        assert_eq! (result, Err (String::from ("There is no network interface named 'docker0'; the interfaces are: wlan0, eth0")));
+    }
+
+    #[test]
+    fn subvert_backs_out_successes_if_there_is_a_failure () {
+        let run_parameters = Arc::new (Mutex::new (vec! ()));
+        let subject = make_subject (CommandRunnerMock::new ()
+            .run_parameters (&run_parameters)
+            .run_result (Ok (String::from (RESOLVECTL_DNS)))
+            .run_result (Ok (String::new ()))
+            .run_result (Err (String::from ("Access denied")))
+            .run_result (Ok (String::new ())));
+
+        let result = subject.subvert (&InterfaceFilter::All);
+
+        assert_eq! (result, Err (String::from ("Access denied")));
+        assert_eq! (get_parameters_from (run_parameters), vec! (
+            String::from ("resolvectl dns"),
+            String::from ("resolvectl dns wlan0 127.0.0.1"),
+            String::from ("resolvectl dns eth0 127.0.0.1"),
+            String::from ("resolvectl revert wlan0"),
+        ));
+    }
+
+    #[test]
+    fn revert_returns_subverted_links_to_their_network_configuration () {
+        let run_parameters = Arc::new (Mutex::new (vec! ()));
+        let subject = make_subject (CommandRunnerMock::new ()
+            .run_parameters (&run_parameters)
+            .run_result (Ok (String::from ("Link 3 (wlan0): 127.0.0.1\nLink 2 (eth0): 10.0.2.3\n")))
+            .run_result (Ok (String::new ())));
+
+        let result = subject.revert (&InterfaceFilter::All);
+
+        assert_eq! (result, Ok (()));
+        assert_eq! (get_parameters_from (run_parameters), vec! (
+            String::from ("resolvectl dns"),
+            String::from ("resolvectl revert wlan0"),
+        ));
+    }
+
+    #[test]
+    fn revert_backs_out_successes_if_there_is_a_failure () {
+        let run_parameters = Arc::new (Mutex::new (vec! ()));
+        let subject = make_subject (CommandRunnerMock::new ()
+            .run_parameters (&run_parameters)
+            .run_result (Ok (String::from ("Link 3 (wlan0): 127.0.0.1\nLink 2 (eth0): 127.0.0.1\n")))
+            .run_result (Ok (String::new ()))
+            .run_result (Err (String::from ("Access denied")))
+            .run_result (Ok (String::new ())));
+
+        let result = subject.revert (&InterfaceFilter::All);
+
+        assert_eq! (result, Err (String::from ("Access denied")));
+        assert_eq! (get_parameters_from (run_parameters), vec! (
+            String::from ("resolvectl dns"),
+            String::from ("resolvectl revert wlan0"),
+            String::from ("resolvectl revert eth0"),
+            String::from ("resolvectl dns wlan0 127.0.0.1"),
+        ));
+    }
+
+    fn make_root (test_name: &str) -> PathBuf {
+        let cur_dir = env::current_dir ().unwrap ();
+        let base_dir = cur_dir.join (Path::new ("generated")).join (Path::new ("ResolvedDnsModifier")).join (Path::new (test_name));
+        fs::remove_dir_all (base_dir.clone ()).is_ok (); // don't care if it doesn't exist
+        fs::create_dir_all (base_dir.clone ()).unwrap ();
+        base_dir
+    }
+}
@@ -1,9 +1,12 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
-use std::collections::HashSet;
 use std::fmt::Debug;
 use std::io;
 use dns_modifier::DnsModifier;
+use dns_modifier::InterfaceStatus;
+use dns_modifier::InterfaceFilter;
+use command_runner::CommandRunner;
+use command_runner::CommandRunnerReal;
 
 #[cfg (windows)]
 use winreg::RegKey;
@@ -12,12 +15,15 @@ use winreg::enums::*;
 
 #[cfg (not (windows))]
 const KEY_ALL_ACCESS: u32 = 1234;
+#[cfg (not (windows))]
+const KEY_READ: u32 = 4321;
 
 const NOT_FOUND: i32 = 2;
 const PERMISSION_DENIED: i32 = 5;
 
 pub struct WinRegDnsModifier {
     hive: Box<RegKeyTrait>,
+    runner: Box<CommandRunner>,
 }
 
 impl DnsModifier for WinRegDnsModifier {
@@ -25,8 +31,8 @@ impl DnsModifier for WinRegDnsModifier {
         "WinRegDnsModifier"
     }
 
-    fn subvert(&self) -> Result<(), String> {
-        let interfaces = self.find_interfaces_to_subvert ()?;
+    fn subvert(&self, filter: &InterfaceFilter) -> Result<(), String> {
+        let interfaces = self.find_interfaces_to_subvert (filter)?;
         let begin_overhang: Vec<Box<RegKeyTrait>> = vec! ();
         let begin_error_opt: Option<String> = None;
         let (overhang, error_opt) = interfaces.into_iter ()
@@ -46,12 +52,15 @@ impl DnsModifier for WinRegDnsModifier {
                 overhang.into_iter ().for_each (|interface| {self.roll_back_subvert (&interface)});
                 Err (msg)
             },
-            None => Ok (())
+            None => {
+                if !overhang.is_empty () {self.flush_cache ()}
+                Ok (())
+            }
         }
     }
 
-    fn revert(&self) -> Result<(), String> {
-        let interfaces = self.find_interfaces_to_revert ()?;
+    fn revert(&self, filter: &InterfaceFilter) -> Result<(), String> {
+        let interfaces = self.find_interfaces_to_revert (filter)?;
         let begin_overhang: Vec<Box<RegKeyTrait>> = vec! ();
         let begin_error_opt: Option<String> = None;
         let (overhang, error_opt) = interfaces.into_iter ()
@@ -71,9 +80,43 @@ impl DnsModifier for WinRegDnsModifier {
                 overhang.into_iter ().for_each (|interface| {self.roll_back_revert (&interface)});
                 Err (msg)
             },
-            None => Ok (())
+            None => {
+                if !overhang.is_empty () {self.flush_cache ()}
+                Ok (())
+            }
         }
     }
+
+    // The interfaces subvert would change, and any it has changed. Where NameServer is empty, DHCP's
+    // servers are the ones in use.
+    fn inspect (&self) -> Result<Vec<InterfaceStatus>, String> {
+        let interface_key = self.handle_reg_error(self.hive.open_subkey_with_flags("SYSTEM\\CurrentControlSet\\Services\\Tcpip\\Parameters\\Interfaces", KEY_READ))?;
+        let mut statuses: Vec<InterfaceStatus> = vec! ();
+        for interface_name in interface_key.enum_keys ().into_iter ().flat_map (|k| {k}) {
+            let interface = match interface_key.open_subkey_with_flags (&interface_name[..], KEY_READ) {
+                Ok (i) => i,
+                Err (_) => continue
+            };
+            let name_servers = match interface.get_value ("NameServer") {
+                Ok (ns) => ns,
+                Err (_) => continue
+            };
+            let saved_opt = interface.get_value ("NameServerBak").ok ();
+            if saved_opt.is_none () && WinRegDnsModifier::get_default_gateway (&interface).is_none () {continue}
+            let active = if name_servers.is_empty () {
+                split_name_servers (&interface.get_value ("DhcpNameServer").unwrap_or (String::new ()))
+            } else {
+                split_name_servers (&name_servers)
+            };
+            statuses.push (InterfaceStatus {
+                name: interface_name,
+                subverted: WinRegDnsModifier::is_subverted (&name_servers),
+                active,
+                saved: saved_opt.map (|saved| split_name_servers (&saved)),
+            });
+        }
+        Ok (statuses)
+    }
 }
 
 impl WinRegDnsModifier {
@@ -81,6 +124,7 @@ impl WinRegDnsModifier {
     pub fn new () -> WinRegDnsModifier {
         WinRegDnsModifier {
             hive: Box::new (RegKeyReal::new (RegKey::predef (HKEY_LOCAL_MACHINE))),
+            runner: Box::new (CommandRunnerReal::new ()),
         }
     }
 
@@ -88,13 +132,13 @@ impl WinRegDnsModifier {
     pub fn new () -> WinRegDnsModifier {
         WinRegDnsModifier {
             hive: Box::new (RegKeyReal{}),
+            runner: Box::new (CommandRunnerReal::new ()),
         }
     }
 
-    fn find_interfaces_to_subvert(&self) -> Result<Vec<Box<RegKeyTrait>>, String> {
+    fn find_interfaces_to_subvert(&self, filter: &InterfaceFilter) -> Result<Vec<Box<RegKeyTrait>>, String> {
         let interface_key = self.handle_reg_error(self.hive.open_subkey_with_flags("SYSTEM\\CurrentControlSet\\Services\\Tcpip\\Parameters\\Interfaces", KEY_ALL_ACCESS))?;
-        let gateway_interfaces: Vec<Box<RegKeyTrait>> = interface_key.enum_keys ().into_iter ()
-            .flat_map (|k| {k})
+        let gateway_interfaces: Vec<Box<RegKeyTrait>> = WinRegDnsModifier::chosen_interface_names (interface_key.as_ref (), filter)?.into_iter ()
             .flat_map (| interface_name | {
                 interface_key.open_subkey_with_flags (&interface_name[..], KEY_ALL_ACCESS)
             })
@@ -104,22 +148,14 @@ impl WinRegDnsModifier {
             })
             .collect ();
         if gateway_interfaces.is_empty() { return Err(String::from("This system has no accessible network interfaces configured with default gateways and DNS servers")) }
-        let distinct_gateway_ips: HashSet<String> = gateway_interfaces.iter ()
-            .flat_map (|interface| {
-                WinRegDnsModifier::get_default_gateway (interface)
-            })
-            .collect ();
-        if distinct_gateway_ips.len () > 1 {
-            return Err (String::from (format! ("This system has {} active network interfaces configured with {} different default gateways. Manual configuration required.",
-                gateway_interfaces.len (), distinct_gateway_ips.len ())))
-        }
+        // Unless the user names some, every one of them is subverted, whatever its gateway: Wi-Fi, Ethernet and VPN adapters can all be
+        // active at once, and any one left alone would let DNS queries get past the Node
         Ok (gateway_interfaces)
     }
 
-    fn find_interfaces_to_revert(&self) -> Result<Vec<Box<RegKeyTrait>>, String> {
+    fn find_interfaces_to_revert(&self, filter: &InterfaceFilter) -> Result<Vec<Box<RegKeyTrait>>, String> {
         let interface_key = self.handle_reg_error(self.hive.open_subkey_with_flags("SYSTEM\\CurrentControlSet\\Services\\Tcpip\\Parameters\\Interfaces", KEY_ALL_ACCESS))?;
-        let revertible_interfaces = interface_key.enum_keys ().into_iter ()
-            .flat_map (|k| {k})
+        let revertible_interfaces = WinRegDnsModifier::chosen_interface_names (interface_key.as_ref (), filter)?.into_iter ()
             .flat_map (| interface_name | {
                 interface_key.open_subkey_with_flags (&interface_name[..], KEY_ALL_ACCESS)
             })
@@ -130,6 +166,13 @@ impl WinRegDnsModifier {
         Ok (revertible_interfaces)
     }
 
+    // Interfaces are named by the GUIDs of their registry keys, as status reports them
+    fn chosen_interface_names (interface_key: &RegKeyTrait, filter: &InterfaceFilter) -> Result<Vec<String>, String> {
+        let names: Vec<String> = interface_key.enum_keys ().into_iter ().flat_map (|k| {k}).collect ();
+        filter.check (&names)?;
+        Ok (names.into_iter ().filter (|name| filter.includes (name)).collect ())
+    }
+
     fn subvert_interface(&self, interface: &Box<RegKeyTrait>) -> Result <(), String> {
         let name_servers = interface.get_value ("NameServer").expect ("Interface became unsubvertible. Check your DNS settings manually.");
         if WinRegDnsModifier::is_subverted(&name_servers) {return Ok (())}
@@ -174,6 +217,12 @@ impl WinRegDnsModifier {
         interface.set_value ("NameServer", "127.0.0.1").expect ("Can't reset NameServer to roll back reversion. Check your DNS settings manually.");
     }
 
+    // Answers from the old DNS servers would otherwise go on being believed until they expired. The new
+    // settings are in place whether or not this works, so failure is no reason to report failure.
+    fn flush_cache (&self) {
+        let _ = self.runner.run ("ipconfig", &["/flushdns"]);
+    }
+
     fn handle_reg_error<T> (&self, result: io::Result<T>) -> Result<T, String> {
         match result {
             Ok(retval) => Ok(retval),
@@ -183,12 +232,13 @@ impl WinRegDnsModifier {
         }
     }
 
+    // Windows separates the addresses in NameServer with commas or spaces, depending on who wrote them
     fn is_subverted(name_servers: &String) -> bool {
-        name_servers == "127.0.0.1" || name_servers.starts_with ("127.0.0.1,")
+        name_servers.split (|c: char| c == ',' || c == ' ').next () == Some ("127.0.0.1")
     }
 
     fn makes_no_sense (name_servers: &String) -> bool {
-        name_servers.split(",").collect::<Vec<&str>>().contains(&"127.0.0.1")
+        name_servers.split(|c: char| c == ',' || c == ' ').collect::<Vec<&str>>().contains(&"127.0.0.1")
     }
 
     fn get_default_gateway (interface: &Box<RegKeyTrait>) -> Option<String> {
@@ -206,6 +256,10 @@ impl WinRegDnsModifier {
     }
 }
 
+fn split_name_servers (name_servers: &str) -> Vec<String> {
+    name_servers.split (|c: char| c == ',' || c == ' ').filter (|s| !s.is_empty ()).map (String::from).collect ()
+}
+
 fn plus<T> (mut source: Vec<T>, item: T) -> Vec<T> {
     let mut result = vec! ();
     result.append (&mut source);
@@ -284,6 +338,7 @@ mod tests {
     use std::sync::Arc;
     use utils::get_parameters_from;
     use std::collections::HashMap;
+    use command_runner::tests::CommandRunnerMock;
 
     #[derive (Debug)]
     struct RegKeyMock {
@@ -450,6 +505,12 @@ mod tests {
         assert_eq!(result, true)
     }
 
+    #[test]
+    fn is_already_subverted_understands_space_separated_addresses () {
+        assert_eq! (WinRegDnsModifier::is_subverted (&String::from ("127.0.0.1 1.1.1.1")), true);
+        assert_eq! (WinRegDnsModifier::makes_no_sense (&String::from ("1.1.1.1 127.0.0.1")), true);
+    }
+
     #[test]
     fn get_default_gateway_sees_dhcp_if_both_are_specified () {
         // Many people think this is incorrect behavior, but it seems to be the way Win7+ does things.
@@ -511,7 +572,7 @@ mod tests {
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result.err ().unwrap (), String::from ("You must have administrative privilege to modify your DNS settings"))
     }
@@ -523,7 +584,7 @@ mod tests {
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result.err ().unwrap (), String::from ("Registry contains no DNS information to modify"))
     }
@@ -535,7 +596,7 @@ mod tests {
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         let string_err = result.err ().unwrap ();
         assert_eq! (string_err.starts_with ("Unexpected error: "), true, "{}", &string_err);
@@ -564,7 +625,7 @@ mod tests {
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result.err ().unwrap (), String::from ("This system has no accessible network interfaces configured with default gateways and DNS servers"));
     }
@@ -591,21 +652,29 @@ mod tests {
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result.err ().unwrap (), String::from ("This system has no accessible network interfaces configured with default gateways and DNS servers"));
     }
 
     #[test]
-    fn subvert_complains_if_interfaces_have_different_gateway_values() {
+    fn subvert_subverts_interfaces_with_different_gateway_values_separately() {
+        let one_set_value_parameters_arc = Arc::new (Mutex::new (vec! ()));
         let one_interface = RegKeyMock::new ()
+            .set_value_parameters (&one_set_value_parameters_arc)
             .get_value_result ("DefaultGateway", Ok(String::from("Gateway IP")))
             .get_value_result ("DhcpDefaultGateway", Err (Error::from_raw_os_error(NOT_FOUND)))
-            .get_value_result ("NameServer", Ok (String::from ("8.8.8.8")));
+            .get_value_result ("NameServer", Ok (String::from ("8.8.8.8")))
+            .set_value_result ("NameServerBak", Ok (()))
+            .set_value_result ("NameServer", Ok (()));
+        let another_set_value_parameters_arc = Arc::new (Mutex::new (vec! ()));
         let another_interface = RegKeyMock::new ()
+            .set_value_parameters (&another_set_value_parameters_arc)
             .get_value_result ("DefaultGateway", Err (Error::from_raw_os_error(NOT_FOUND)))
             .get_value_result ("DhcpDefaultGateway", Ok(String::from("DHCP Gateway IP")))
-            .get_value_result ("NameServer", Ok (String::from ("8.8.8.8")));
+            .get_value_result ("NameServer", Ok (String::from ("10.8.0.1")))
+            .set_value_result ("NameServerBak", Ok (()))
+            .set_value_result ("NameServer", Ok (()));
         let interfaces = RegKeyMock::new ()
             .enum_keys_result (vec! (Ok ("one_interface"), Ok ("another_interface")))
             .open_subkey_with_flags_result(Ok (Box::new (one_interface)))
@@ -614,10 +683,19 @@ mod tests {
             .open_subkey_with_flags_result(Ok (Box::new (interfaces)));
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
+        subject.runner = Box::new (CommandRunnerMock::new ().run_result (Ok (String::new ())));
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
-        assert_eq! (result.err ().unwrap (), String::from ("This system has 2 active network interfaces configured with 2 different default gateways. Manual configuration required."));
+        assert_eq! (result, Ok (()));
+        assert_eq! (get_parameters_from (one_set_value_parameters_arc), vec! (
+            (String::from ("NameServerBak"), String::from ("8.8.8.8")),
+            (String::from ("NameServer"), String::from ("127.0.0.1")),
+        ));
+        assert_eq! (get_parameters_from (another_set_value_parameters_arc), vec! (
+            (String::from ("NameServerBak"), String::from ("10.8.0.1")),
+            (String::from ("NameServer"), String::from ("127.0.0.1")),
+        ));
     }
 
     #[test]
@@ -639,7 +717,7 @@ mod tests {
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("This system's DNS settings don't make sense; aborting")));
     }
@@ -661,8 +739,9 @@ mod tests {
             .open_subkey_with_flags_result(Ok (Box::new (interfaces)));
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
+        subject.runner = Box::new (CommandRunnerMock::new ().run_result (Ok (String::new ())));
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Ok (()));
         assert_eq! (get_parameters_from (open_subkey_with_flags_parameters_arc), vec! (
@@ -692,7 +771,7 @@ mod tests {
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("You must have administrative privilege to modify your DNS settings")));
         assert_eq! (get_parameters_from (delete_value_parameters_arc), vec! (
@@ -737,7 +816,7 @@ mod tests {
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("You must have administrative privilege to modify your DNS settings")));
         assert_eq! (get_parameters_from (one_active_set_value_parameters_arc), vec! (
@@ -785,12 +864,17 @@ mod tests {
             .open_subkey_with_flags_result(Ok (Box::new (inactive_interface)));
         let hive = RegKeyMock::new ()
             .open_subkey_with_flags_result(Ok (Box::new (interfaces)));
+        let run_parameters_arc = Arc::new (Mutex::new (vec! ()));
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
+        subject.runner = Box::new (CommandRunnerMock::new ()
+            .run_parameters (&run_parameters_arc)
+            .run_result (Err (String::from ("flush failed; doesn't matter"))));
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Ok (()));
+        assert_eq! (get_parameters_from (run_parameters_arc), vec! (String::from ("ipconfig /flushdns")));
         assert_eq! (get_parameters_from (one_active_set_value_parameters_arc), vec! (
             (String::from ("NameServerBak"), String::from ("8.8.8.8,8.8.8.9")),
             (String::from ("NameServer"), String::from ("127.0.0.1")),
@@ -821,7 +905,7 @@ mod tests {
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
 
-        let result = subject.subvert ();
+        let result = subject.subvert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("This system has no accessible network interfaces configured with default gateways and DNS servers")));
     }
@@ -849,7 +933,7 @@ mod tests {
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
 
-        let result = subject.revert ();
+        let result = subject.revert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("You must have administrative privilege to modify your DNS settings")));
         assert_eq! (get_parameters_from (set_value_parameters_arc), vec! (
@@ -881,7 +965,7 @@ mod tests {
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
 
-        let result = subject.revert ();
+        let result = subject.revert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("You must have administrative privilege to modify your DNS settings")));
         assert_eq! (get_parameters_from (set_value_parameters_arc), vec! (
@@ -924,7 +1008,7 @@ mod tests {
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
 
-        let result = subject.revert ();
+        let result = subject.revert (&InterfaceFilter::All);
 
         assert_eq! (result, Err (String::from ("You must have administrative privilege to modify your DNS settings")));
         assert_eq! (get_parameters_from (one_subverted_set_value_parameters_arc), vec! (
@@ -981,12 +1065,17 @@ mod tests {
             .open_subkey_with_flags_result(Ok (Box::new (unsubverted_interface)));
         let hive = RegKeyMock::new ()
             .open_subkey_with_flags_result(Ok (Box::new (interfaces)));
+        let run_parameters_arc = Arc::new (Mutex::new (vec! ()));
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
+        subject.runner = Box::new (CommandRunnerMock::new ()
+            .run_parameters (&run_parameters_arc)
+            .run_result (Ok (String::new ())));
 
-        let result = subject.revert ();
+        let result = subject.revert (&InterfaceFilter::All);
 
         assert_eq! (result, Ok (()));
+        assert_eq! (get_parameters_from (run_parameters_arc), vec! (String::from ("ipconfig /flushdns")));
         assert_eq! (get_parameters_from (one_subverted_set_value_parameters_arc), vec! (
             (String::from ("NameServer"), String::from ("8.8.8.8"))
         ));
@@ -1003,6 +1092,43 @@ mod tests {
         assert_eq! (get_parameters_from (unsubverted_delete_value_parameters_arc).len (), 0);
     }
 
+    #[test]
+    fn inspect_reports_subverted_and_active_interfaces () {
+        let subverted_interface = RegKeyMock::new ()
+            .get_value_result ("NameServer", Ok (String::from ("127.0.0.1")))
+            .get_value_result ("NameServerBak", Ok (String::new ()));
+        let dhcp_interface = RegKeyMock::new ()
+            .get_value_result ("NameServer", Ok (String::new ()))
+            .get_value_result ("NameServerBak", Err (Error::from_raw_os_error(NOT_FOUND)))
+            .get_value_result ("DefaultGateway", Err (Error::from_raw_os_error(NOT_FOUND)))
+            .get_value_result ("DhcpDefaultGateway", Ok (String::from ("192.168.0.1")))
+            .get_value_result ("DhcpNameServer", Ok (String::from ("192.168.0.1 8.8.8.8")));
+        let inactive_interface = RegKeyMock::new ()
+            .get_value_result ("NameServer", Ok (String::new ()))
+            .get_value_result ("NameServerBak", Err (Error::from_raw_os_error(NOT_FOUND)))
+            .get_value_result ("DefaultGateway", Err (Error::from_raw_os_error(NOT_FOUND)))
+            .get_value_result ("DhcpDefaultGateway", Err (Error::from_raw_os_error(NOT_FOUND)));
+        let open_subkey_with_flags_parameters_arc = Arc::new (Mutex::new (vec! ()));
+        let interfaces = RegKeyMock::new ()
+            .enum_keys_result (vec! (Ok ("subverted_interface"), Ok ("dhcp_interface"), Ok ("inactive_interface")))
+            .open_subkey_with_flags_parameters (&open_subkey_with_flags_parameters_arc)
+            .open_subkey_with_flags_result(Ok (Box::new (subverted_interface)))
+            .open_subkey_with_flags_result(Ok (Box::new (dhcp_interface)))
+            .open_subkey_with_flags_result(Ok (Box::new (inactive_interface)));
+        let hive = RegKeyMock::new ()
+            .open_subkey_with_flags_result(Ok (Box::new (interfaces)));
+        let mut subject = WinRegDnsModifier::new ();
+        subject.hive = Box::new (hive);
+
+        let result = subject.inspect ();
+
+        assert_eq! (result, Ok (vec! (
+            InterfaceStatus {name: String::from ("subverted_interface"), subverted: true, active: vec! (String::from ("127.0.0.1")), saved: Some (vec! ())},
+            InterfaceStatus {name: String::from ("dhcp_interface"), subverted: false, active: vec! (String::from ("192.168.0.1"), String::from ("8.8.8.8")), saved: None},
+        )));
+        assert_eq! (get_parameters_from (open_subkey_with_flags_parameters_arc)[0], (String::from ("subverted_interface"), KEY_READ));
+    }
+
     #[test]
     fn revert_succeeds_with_no_work_if_no_subverted_nic_is_found () {
         let delete_value_parameters_arc = Arc::new (Mutex::new (vec! ()));
@@ -1017,7 +1143,7 @@ mod tests {
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
 
-        let result = subject.revert ();
+        let result = subject.revert (&InterfaceFilter::All);
 
         assert_eq! (result, Ok (()));
         assert_eq! (get_parameters_from (delete_value_parameters_arc).len (), 0);
@@ -1042,8 +1168,9 @@ mod tests {
             .open_subkey_with_flags_result(Ok (Box::new (interfaces)));
         let mut subject = WinRegDnsModifier::new ();
         subject.hive = Box::new (hive);
+        subject.runner = Box::new (CommandRunnerMock::new ().run_result (Ok (String::new ())));
 
-        let result = subject.revert ();
+        let result = subject.revert (&InterfaceFilter::All);
 
         assert_eq! (result, Ok (()));
         assert_eq! (get_parameters_from (delete_value_parameters), vec! (
@@ -0,0 +1,221 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::net::IpAddr;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::str::from_utf8;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::thread;
+use std::time::Duration;
+use sub_lib::logger::Logger;
+
+pub const DEFAULT_BLOCKLIST_REFRESH_SECS: u64 = 86400;
+const HTTP_TIMEOUT_SECS: u64 = 30;
+// Names that hosts files map for the machine's own sake, not to block anything
+const HOSTS_FILE_NAMES: [&str; 5] = ["localhost", "localhost.localdomain", "local", "broadcasthost", "0.0.0.0"];
+
+#[derive (Clone, Copy, Debug, PartialEq)]
+pub enum BlockMode {
+    // The name doesn't exist
+    NxDomain,
+    // The name exists, but at 0.0.0.0 or ::
+    NullAddress,
+}
+
+// Domains whose names aren't to be resolved. A domain on the list takes all its subdomains with it.
+pub struct Blocklist {
+    pub mode: BlockMode,
+    domains: HashSet<String>,
+}
+
+impl Blocklist {
+    pub fn new (mode: BlockMode) -> Blocklist {
+        Blocklist {mode, domains: HashSet::new ()}
+    }
+
+    pub fn set_domains (&mut self, domains: HashSet<String>) {
+        self.domains = domains
+    }
+
+    pub fn len (&self) -> usize {
+        self.domains.len ()
+    }
+
+    pub fn is_empty (&self) -> bool {
+        self.domains.is_empty ()
+    }
+
+    pub fn is_blocked (&self, name: &str) -> bool {
+        let name = normalize (name);
+        let mut suffix: &str = &name;
+        loop {
+            if self.domains.contains (suffix) {return true}
+            match suffix.find ('.') {
+                Some (index) => suffix = &suffix[(index + 1)..],
+                None => return false
+            }
+        }
+    }
+}
+
+// Reads hosts files ("0.0.0.0 ads.example.com tracker.example.com") and plain lists of domains
+// alike, with # comments in either
+pub fn parse_domains (text: &str) -> HashSet<String> {
+    let mut domains = HashSet::new ();
+    for line in text.lines () {
+        let line = match line.find ('#') {
+            Some (index) => &line[..index],
+            None => line
+        };
+        let mut words = line.split_whitespace ().peekable ();
+        let is_hosts_line = match words.peek () {
+            Some (word) => word.parse::<IpAddr> ().is_ok (),
+            None => continue
+        };
+        if is_hosts_line {words.next ();}
+        for word in words {
+            let domain = normalize (word);
+            if !domain.is_empty () && !HOSTS_FILE_NAMES.contains (&domain.as_str ()) {
+                domains.insert (domain);
+            }
+        }
+    }
+    domains
+}
+
+// Each source is a file or an http:// URL
+pub fn load_domains (sources: &[String]) -> Result<HashSet<String>, String> {
+    let mut domains = HashSet::new ();
+    for source in sources {
+        let text = if source.starts_with ("http://") {
+            http_get (source)?
+        } else if source.starts_with ("https://") {
+            return Err (format! ("Can't fetch blocklist {}: only http:// URLs are supported", source))
+        } else {
+            let mut text = String::new ();
+            File::open (source).and_then (|mut file| file.read_to_string (&mut text))
+                .map_err (|e| format! ("Can't read blocklist {}: {}", source, e))?;
+            text
+        };
+        domains.extend (parse_domains (&text));
+    }
+    Ok (domains)
+}
+
+// Loads the blocklist now, and again every so often for as long as the Node runs. If a load fails,
+// the list stays as it was. The sources can be changed in the meantime; they're looked at afresh
+// for every load.
+pub fn start_refreshing (blocklist: Arc<RwLock<Blocklist>>, sources: Arc<RwLock<Vec<String>>>, interval: Duration) {
+    thread::spawn (move || {
+        let logger = Logger::new ("Blocklist");
+        loop {
+            let current_sources = sources.read ().expect ("Blocklist sources poisoned").clone ();
+            if !current_sources.is_empty () {
+                match load_domains (&current_sources) {
+                    Ok (domains) => {
+                        let mut guard = blocklist.write ().expect ("Blocklist poisoned");
+                        guard.set_domains (domains);
+                        logger.info (format! ("Blocking {} domains", guard.len ()));
+                    },
+                    Err (e) => logger.warning (e)
+                }
+            }
+            thread::sleep (interval);
+        }
+    });
+}
+
+fn normalize (name: &str) -> String {
+    name.trim_right_matches ('.').to_lowercase ()
+}
+
+fn http_get (url: &str) -> Result<String, String> {
+    let rest = &url["http://".len ()..];
+    let (authority, path) = match rest.find ('/') {
+        Some (index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/")
+    };
+    let host = match authority.rfind (':') {
+        Some (index) => &authority[..index],
+        None => authority
+    };
+    let address = if authority.contains (':') {String::from (authority)} else {format! ("{}:80", authority)};
+    let fail = |e: String| format! ("Can't fetch blocklist {}: {}", url, e);
+    let socket_addr = address.to_socket_addrs ().map_err (|e| fail (format! ("{}", e)))?
+        .next ().ok_or_else (|| fail (String::from ("host not found")))?;
+    let mut stream = TcpStream::connect_timeout (&socket_addr, Duration::from_secs (HTTP_TIMEOUT_SECS))
+        .map_err (|e| fail (format! ("{}", e)))?;
+    stream.set_read_timeout (Some (Duration::from_secs (HTTP_TIMEOUT_SECS))).map_err (|e| fail (format! ("{}", e)))?;
+    let request = format! ("GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n", path, host);
+    stream.write_all (request.as_bytes ()).map_err (|e| fail (format! ("{}", e)))?;
+    let mut response: Vec<u8> = vec! ();
+    stream.read_to_end (&mut response).map_err (|e| fail (format! ("{}", e)))?;
+    body_from_response (&response).map_err (fail)
+}
+
+fn body_from_response (response: &[u8]) -> Result<String, String> {
+    let text = from_utf8 (response).map_err (|_| String::from ("response is not UTF-8"))?;
+    let header_end = match text.find ("\r\n\r\n") {
+        Some (index) => index,
+        None => return Err (String::from ("malformed HTTP response"))
+    };
+    let status_line = text.lines ().next ().unwrap_or ("");
+    match status_line.split_whitespace ().nth (1) {
+        Some ("200") => Ok (String::from (&text[(header_end + 4)..])),
+        _ => Err (format! ("server said {}", status_line))
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hosts_files_and_domain_lists_are_both_understood () {
+        let text = "# Ad servers\n\
+            0.0.0.0 ads.example.com tracker.example.com # trailing comment\n\
+            127.0.0.1 localhost\n\
+            ::1 localhost\n\
+            \n\
+            Metrics.Example.NET.\n\
+            0.0.0.0 0.0.0.0\n";
+
+        let result = parse_domains (text);
+
+        let mut sorted: Vec<String> = result.into_iter ().collect ();
+        sorted.sort ();
+        assert_eq! (sorted, vec! (String::from ("ads.example.com"), String::from ("metrics.example.net"), String::from ("tracker.example.com")));
+    }
+
+    #[test]
+    fn listed_domains_block_their_subdomains_but_not_their_parents_or_lookalikes () {
+        let mut subject = Blocklist::new (BlockMode::NxDomain);
+        subject.set_domains (parse_domains ("ads.example.com"));
+
+        assert_eq! (subject.is_blocked ("ads.example.com"), true);
+        assert_eq! (subject.is_blocked ("ADS.example.com."), true);
+        assert_eq! (subject.is_blocked ("eu.ads.example.com"), true);
+        assert_eq! (subject.is_blocked ("example.com"), false);
+        assert_eq! (subject.is_blocked ("badads.example.com"), false);
+        assert_eq! (subject.is_blocked ("ads.example.com.evil.net"), false);
+    }
+
+    #[test]
+    fn http_responses_yield_their_bodies_only_when_successful () {
+        assert_eq! (body_from_response (b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\nads.example.com\n"),
+            Ok (String::from ("ads.example.com\n")));
+        assert_eq! (body_from_response (b"HTTP/1.1 404 Not Found\r\n\r\n"), Err (String::from ("server said HTTP/1.1 404 Not Found")));
+        assert_eq! (body_from_response (b"garbage"), Err (String::from ("malformed HTTP response")));
+    }
+
+    #[test]
+    fn https_and_missing_files_are_reported () {
+        assert_eq! (load_domains (&[String::from ("https://example.com/hosts")]),
+            Err (String::from ("Can't fetch blocklist https://example.com/hosts: only http:// URLs are supported")));
+        assert_eq! (load_domains (&[String::from ("/nonexistent/blocklist")]).err ().unwrap ().starts_with ("Can't read blocklist /nonexistent/blocklist: "), true);
+    }
+}
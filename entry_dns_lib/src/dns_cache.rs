@@ -0,0 +1,295 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+pub const DEFAULT_CACHE_SIZE: usize = 1000;
+// How long a name that doesn't exist is remembered when the answer has no SOA record to say
+pub const DEFAULT_NEGATIVE_TTL: u32 = 300;
+// Nothing is kept longer than a day, whatever its TTL
+const MAX_TTL: u32 = 86400;
+const RR_TYPE_SOA: u16 = 6;
+// An OPT pseudo-record's TTL field holds flags, not a time to live
+const RR_TYPE_OPT: u16 = 41;
+const RCODE_NO_ERROR: u8 = 0x0;
+const RCODE_NAME_ERROR: u8 = 0x3;
+
+#[derive (Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    name: String,
+    qtype: u16,
+    qclass: u16,
+}
+
+impl CacheKey {
+    // Names are case-insensitive
+    pub fn new (name: &str, qtype: u16, qclass: u16) -> CacheKey {
+        CacheKey {name: name.to_lowercase (), qtype, qclass}
+    }
+}
+
+struct CacheEntry {
+    response: Vec<u8>,
+    ttl_offsets: Vec<usize>,
+    stored_at: Instant,
+    expires_at: Instant,
+}
+
+// Whole responses from upstream, kept for as long as their TTLs say. Answers that a name or a
+// record doesn't exist are kept too, for as long as the zone's SOA record says. When the cache is
+// full, whatever would expire soonest makes way.
+pub struct DnsCache {
+    entries: HashMap<CacheKey, CacheEntry>,
+    capacity: usize,
+    negative_ttl: u32,
+}
+
+impl DnsCache {
+    pub fn new (capacity: usize, negative_ttl: u32) -> DnsCache {
+        DnsCache {entries: HashMap::new (), capacity, negative_ttl}
+    }
+
+    // The response as it was stored, but with its TTLs counted down by the time it's been kept
+    pub fn lookup (&mut self, key: &CacheKey, now: Instant) -> Option<Vec<u8>> {
+        let expired = match self.entries.get (key) {
+            None => return None,
+            Some (entry) => entry.expires_at <= now
+        };
+        if expired {
+            self.entries.remove (key);
+            return None
+        }
+        let entry = self.entries.get (key).expect ("Internal error");
+        let elapsed = now.duration_since (entry.stored_at).as_secs ();
+        let mut response = entry.response.clone ();
+        for offset in &entry.ttl_offsets {
+            let ttl = u32_from (&response, *offset) as u64;
+            u32_to (if ttl > elapsed {(ttl - elapsed) as u32} else {0}, &mut response, *offset);
+        }
+        Some (response)
+    }
+
+    // Responses that can't be parsed, were truncated, or report a failure aren't kept
+    pub fn store (&mut self, key: CacheKey, response: &[u8], now: Instant) {
+        let scan = match scan_response (response) {
+            Some (scan) => scan,
+            None => return
+        };
+        let ttl = match scan.rcode {
+            RCODE_NO_ERROR if scan.answer_count > 0 => scan.min_answer_ttl,
+            RCODE_NO_ERROR | RCODE_NAME_ERROR => scan.negative_ttl_opt.unwrap_or (self.negative_ttl),
+            _ => return
+        };
+        let ttl = if ttl > MAX_TTL {MAX_TTL} else {ttl};
+        if (ttl == 0) || (self.capacity == 0) {return}
+        if !self.entries.contains_key (&key) && (self.entries.len () >= self.capacity) {
+            self.make_room (now)
+        }
+        self.entries.insert (key, CacheEntry {
+            response: response.to_vec (),
+            ttl_offsets: scan.ttl_offsets,
+            stored_at: now,
+            expires_at: now + Duration::from_secs (ttl as u64),
+        });
+    }
+
+    pub fn len (&self) -> usize {
+        self.entries.len ()
+    }
+
+    fn make_room (&mut self, now: Instant) {
+        self.entries.retain (|_, entry| entry.expires_at > now);
+        if self.entries.len () < self.capacity {return}
+        let soonest_opt = self.entries.iter ()
+            .min_by_key (|&(_, entry)| entry.expires_at)
+            .map (|(key, _)| key.clone ());
+        if let Some (soonest) = soonest_opt {
+            self.entries.remove (&soonest);
+        }
+    }
+}
+
+struct ResponseScan {
+    rcode: u8,
+    answer_count: u16,
+    min_answer_ttl: u32,
+    // from the SOA record in the authority section, if there is one
+    negative_ttl_opt: Option<u32>,
+    ttl_offsets: Vec<usize>,
+}
+
+// Finds the TTL of every record in a response, following nothing but lengths, so names compressed
+// or not are all the same to it
+fn scan_response (response: &[u8]) -> Option<ResponseScan> {
+    if response.len () < 12 {return None}
+    if (response[2] & 0x02) != 0 {return None}
+    let question_count = u16_from (response, 4);
+    let answer_count = u16_from (response, 6);
+    let authority_count = u16_from (response, 8);
+    let additional_count = u16_from (response, 10);
+    let mut offset = 12;
+    for _ in 0..question_count {
+        offset = try_opt! (skip_name (response, offset)) + 4;
+    }
+    if offset > response.len () {return None}
+    let mut scan = ResponseScan {
+        rcode: response[3] & 0x0F,
+        answer_count,
+        min_answer_ttl: MAX_TTL,
+        negative_ttl_opt: None,
+        ttl_offsets: vec! (),
+    };
+    let record_count = answer_count as usize + authority_count as usize + additional_count as usize;
+    for index in 0..record_count {
+        let type_offset = try_opt! (skip_name (response, offset));
+        if type_offset + 10 > response.len () {return None}
+        let record_type = u16_from (response, type_offset);
+        let ttl_offset = type_offset + 4;
+        let ttl = u32_from (response, ttl_offset);
+        let rdata_offset = type_offset + 10;
+        let rdata_end = rdata_offset + u16_from (response, type_offset + 8) as usize;
+        if rdata_end > response.len () {return None}
+        if record_type != RR_TYPE_OPT {
+            scan.ttl_offsets.push (ttl_offset);
+        }
+        if (index < answer_count as usize) && (ttl < scan.min_answer_ttl) {
+            scan.min_answer_ttl = ttl;
+        }
+        let in_authorities = (index >= answer_count as usize) && (index < answer_count as usize + authority_count as usize);
+        if in_authorities && (record_type == RR_TYPE_SOA) && (rdata_end - rdata_offset >= 20) {
+            // The SOA's minimum is the last field in its record
+            let minimum = u32_from (response, rdata_end - 4);
+            scan.negative_ttl_opt = Some (if ttl < minimum {ttl} else {minimum});
+        }
+        offset = rdata_end;
+    }
+    Some (scan)
+}
+
+fn skip_name (buf: &[u8], offset: usize) -> Option<usize> {
+    let mut local_offset = offset;
+    loop {
+        if local_offset >= buf.len () {return None}
+        let length = buf[local_offset] as usize;
+        if length == 0x00 {return Some (local_offset + 1)}
+        if (length & 0xC0) == 0xC0 {return Some (local_offset + 2)}
+        local_offset += length + 1;
+    }
+}
+
+fn u16_from (buf: &[u8], start: usize) -> u16 {
+    ((buf[start] as u16) << 8) | (buf[start + 1] as u16)
+}
+
+fn u32_from (buf: &[u8], start: usize) -> u32 {
+    ((u16_from (buf, start) as u32) << 16) | (u16_from (buf, start + 2) as u32)
+}
+
+fn u32_to (value: u32, buf: &mut [u8], start: usize) {
+    buf[start] = (value >> 24) as u8;
+    buf[start + 1] = (value >> 16) as u8;
+    buf[start + 2] = (value >> 8) as u8;
+    buf[start + 3] = value as u8;
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use packet_facade::PacketFacade;
+
+    fn response (rcode: u8, answers: Vec<(u16, u32, Vec<u8>)>, authorities: Vec<(u16, u32, Vec<u8>)>) -> Vec<u8> {
+        let mut buf: [u8; 512] = [0; 512];
+        let length = {
+            let mut facade = PacketFacade::new (&mut buf, 12);
+            facade.set_transaction_id (0x1234);
+            facade.set_query (false);
+            facade.set_rcode (rcode);
+            facade.add_query ("www.example.com", 0x000F, 0x0001);
+            for (rtype, ttl, rdata) in answers {
+                facade.add_answer ("www.example.com", rtype, 0x0001, ttl, &rdata[..]);
+            }
+            for (rtype, ttl, rdata) in authorities {
+                facade.add_authority ("example.com", rtype, 0x0001, ttl, &rdata[..]);
+            }
+            facade.get_length ()
+        };
+        buf[..length].to_vec ()
+    }
+
+    fn soa (minimum: u32) -> Vec<u8> {
+        let mut rdata = vec! (0xC0, 0x0C, 0xC0, 0x0C, 0, 0, 0, 1, 0, 0, 0x0E, 0x10, 0, 0, 0x07, 0x08, 0, 0x09, 0x3A, 0x80, 0, 0, 0, 0);
+        u32_to (minimum, &mut rdata, 20);
+        rdata
+    }
+
+    fn ttls (response: &[u8]) -> Vec<u32> {
+        scan_response (response).unwrap ().ttl_offsets.iter ().map (|offset| u32_from (response, *offset)).collect ()
+    }
+
+    fn key () -> CacheKey {
+        CacheKey::new ("www.example.com", 0x000F, 0x0001)
+    }
+
+    #[test]
+    fn answers_are_kept_for_their_shortest_ttl_and_counted_down_meanwhile () {
+        let mut subject = DnsCache::new (10, DEFAULT_NEGATIVE_TTL);
+        let start = Instant::now ();
+        let answer = response (RCODE_NO_ERROR, vec! ((0x000F, 600, vec! (0, 10, 0xC0, 0x0C)), (0x000F, 300, vec! (0, 20, 0xC0, 0x0C))), vec! ());
+
+        subject.store (key (), &answer[..], start);
+
+        assert_eq! (subject.lookup (&CacheKey::new ("WWW.Example.COM", 0x000F, 0x0001), start), Some (answer.clone ()));
+        assert_eq! (ttls (&subject.lookup (&key (), start + Duration::from_secs (100)).unwrap ()[..]), vec! (500, 200));
+        assert_eq! (subject.lookup (&CacheKey::new ("www.example.com", 0x0001, 0x0001), start), None);
+        assert_eq! (subject.lookup (&key (), start + Duration::from_secs (300)), None);
+        assert_eq! (subject.len (), 0);
+    }
+
+    #[test]
+    fn names_and_records_that_dont_exist_are_kept_as_long_as_the_soa_says_or_the_default () {
+        let mut subject = DnsCache::new (10, 60);
+        let start = Instant::now ();
+        let nxdomain = response (RCODE_NAME_ERROR, vec! (), vec! ((RR_TYPE_SOA, 3600, soa (900))));
+        let nodata = response (RCODE_NO_ERROR, vec! (), vec! ());
+
+        subject.store (key (), &nxdomain[..], start);
+        subject.store (CacheKey::new ("other.example.com", 0x000F, 0x0001), &nodata[..], start);
+
+        assert_eq! (subject.lookup (&key (), start + Duration::from_secs (899)).is_some (), true);
+        assert_eq! (subject.lookup (&key (), start + Duration::from_secs (900)), None);
+        assert_eq! (subject.lookup (&CacheKey::new ("other.example.com", 0x000F, 0x0001), start + Duration::from_secs (59)).is_some (), true);
+        assert_eq! (subject.lookup (&CacheKey::new ("other.example.com", 0x000F, 0x0001), start + Duration::from_secs (60)), None);
+    }
+
+    #[test]
+    fn failures_truncations_and_zero_ttls_are_not_kept () {
+        let mut subject = DnsCache::new (10, DEFAULT_NEGATIVE_TTL);
+        let start = Instant::now ();
+        let mut truncated = response (RCODE_NO_ERROR, vec! ((0x000F, 600, vec! (0, 10, 0xC0, 0x0C))), vec! ());
+        truncated[2] |= 0x02;
+
+        subject.store (key (), &response (0x2, vec! (), vec! ())[..], start);
+        subject.store (key (), &truncated[..], start);
+        subject.store (key (), &response (RCODE_NO_ERROR, vec! ((0x000F, 0, vec! (0, 10, 0xC0, 0x0C))), vec! ())[..], start);
+        subject.store (key (), &[0x12, 0x34, 0x81, 0x80][..], start);
+
+        assert_eq! (subject.len (), 0);
+    }
+
+    #[test]
+    fn a_full_cache_drops_whatever_would_expire_soonest () {
+        let mut subject = DnsCache::new (2, DEFAULT_NEGATIVE_TTL);
+        let start = Instant::now ();
+        let name_key = |name: &str| CacheKey::new (name, 0x000F, 0x0001);
+        let answer = |ttl: u32| response (RCODE_NO_ERROR, vec! ((0x000F, ttl, vec! (0, 10, 0xC0, 0x0C))), vec! ());
+
+        subject.store (name_key ("long"), &answer (600)[..], start);
+        subject.store (name_key ("short"), &answer (60)[..], start);
+        subject.store (name_key ("new"), &answer (300)[..], start);
+
+        assert_eq! (subject.len (), 2);
+        assert_eq! (subject.lookup (&name_key ("short"), start), None);
+        assert_eq! (subject.lookup (&name_key ("long"), start).is_some (), true);
+        assert_eq! (subject.lookup (&name_key ("new"), start).is_some (), true);
+    }
+}
@@ -1,25 +1,123 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::collections::HashSet;
 use std::net::IpAddr;
 use std::str::FromStr;
 use std::net::IpAddr::V4;
 use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
 use std::net::SocketAddr;
+use std::time::Duration;
+use std::thread;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::path::PathBuf;
 use sub_lib::main_tools::StdStreams;
+use sub_lib::socket_server::Reloader;
 use sub_lib::socket_server::SocketServer;
 use sub_lib::udp_socket_wrapper::UdpSocketWrapperTrait;
 use sub_lib::udp_socket_wrapper::UdpSocketWrapperReal;
 use sub_lib::limiter::Limiter;
+use sub_lib::tcp_wrappers::TcpListenerWrapper;
+use sub_lib::tcp_wrappers::TcpListenerWrapperReal;
 use sub_lib::logger::Logger;
 use processor::ProcessorReal;
+use resolver::ResolverTrait;
+use resolver::CachingResolver;
+use dns_cache::DnsCache;
+use dns_cache::DEFAULT_CACHE_SIZE;
+use dns_cache::DEFAULT_NEGATIVE_TTL;
+use upstream_resolver::UpstreamResolver;
+use upstream_resolver::DEFAULT_UPSTREAM_TIMEOUT_MS;
+use upstream_resolver::parse_upstreams;
+use tls_resolver::TlsResolver;
+use tls_resolver::TlsUpstreams;
+use tls_resolver::make_tls_config;
+use tls_resolver::parse_tls_upstreams;
 use packet_server::PacketServerTrait;
 use packet_server::PacketServerReal;
+use tcp_server::TcpServerReal;
+use tcp_server::start_accepting;
+use blocklist::Blocklist;
+use blocklist::BlockMode;
+use blocklist::DEFAULT_BLOCKLIST_REFRESH_SECS;
+use blocklist::load_domains;
+use blocklist::start_refreshing;
+use overrides::Overrides;
+use overrides::OVERRIDES_FILE_CHECK_SECS;
+use overrides::parse_inline;
+use overrides::start_watching;
+use rate_limiter::RateLimiter;
+use rate_limiter::DEFAULT_RATE_LIMIT_QPS;
+use query_log::QueryLog;
+use query_log::ClientPrivacy;
+use query_log::DEFAULT_QUERY_LOG_MAX_BYTES;
 
 pub struct DnsSocketServer<S> where S: UdpSocketWrapperTrait {
     dns_target: Option<IpAddr>,
+    dns_target_v6: Option<Ipv6Addr>,
+    upstreams: Vec<SocketAddr>,
+    // Used instead of the plain upstreams, if given
+    tls_upstreams_opt: Option<TlsUpstreams>,
+    bypass: bool,
+    blocklist_sources: Vec<String>,
+    block_mode: BlockMode,
+    blocklist_refresh_secs: u64,
+    hosts_file_opt: Option<String>,
+    inline_overrides: Overrides,
+    // Queries a second from any one address over UDP; 0 for no limit
+    rate_limit_qps: u64,
+    // No query log is kept unless the operator names a file for it
+    query_log_opt: Option<String>,
+    client_privacy: ClientPrivacy,
     socket_wrapper: S,
+    tcp_listener_opt: Option<Box<TcpListenerWrapper>>,
+    reloader: DnsReloader,
     pub limiter: Limiter
 }
 
+// The rate limit and the blocklist, as they stand while the server runs; a reload changes them
+// underneath it
+#[derive (Clone)]
+pub struct DnsReloader {
+    rate_limit_qps: Arc<AtomicUsize>,
+    blocklist: Arc<RwLock<Blocklist>>,
+    blocklist_sources: Arc<RwLock<Vec<String>>>,
+}
+
+impl Reloader for DnsReloader {
+    fn reload (&self, args: &Vec<String>) -> Result<(), String> {
+        let rate_limit_qps = get_dns_rate_limit (args);
+        let sources = get_dns_blocklist (args);
+        let mode = get_dns_blocklist_mode (args);
+        let sources_changed = *self.blocklist_sources.read ().expect ("Blocklist sources poisoned") != sources;
+        let domains_opt = if !sources_changed {None}
+            else if sources.is_empty () {Some (HashSet::new ())}
+            else {Some (load_domains (&sources)?)};
+        {
+            let mut blocklist = self.blocklist.write ().expect ("Blocklist poisoned");
+            blocklist.mode = mode;
+            if let Some (domains) = domains_opt {blocklist.set_domains (domains)}
+        }
+        *self.blocklist_sources.write ().expect ("Blocklist sources poisoned") = sources;
+        self.rate_limit_qps.store (rate_limit_qps as usize, Ordering::SeqCst);
+        Ok (())
+    }
+}
+
+impl DnsReloader {
+    pub fn new () -> DnsReloader {
+        DnsReloader {
+            rate_limit_qps: Arc::new (AtomicUsize::new (DEFAULT_RATE_LIMIT_QPS as usize)),
+            blocklist: Arc::new (RwLock::new (Blocklist::new (BlockMode::NxDomain))),
+            blocklist_sources: Arc::new (RwLock::new (vec! ())),
+        }
+    }
+}
+
 impl<S> SocketServer for DnsSocketServer<S> where S: UdpSocketWrapperTrait {
     fn name(&self) -> String {
         String::from("EntryDnsServer")
@@ -27,25 +125,115 @@ impl<S> SocketServer for DnsSocketServer<S> where S: UdpSocketWrapperTrait {
 
     fn initialize_as_root (&mut self, args: &Vec<String>, _streams: &mut StdStreams) {
         self.dns_target = Some (get_dns_target (args));
-        let socket_addr = SocketAddr::new (V4 (Ipv4Addr::from (0)), get_dns_port (args));
+        self.dns_target_v6 = get_dns_target_v6 (args);
+        self.upstreams = get_dns_upstreams (args);
+        self.tls_upstreams_opt = get_dns_upstreams_tls (args);
+        if !self.upstreams.is_empty () && self.tls_upstreams_opt.is_some () {
+            panic! ("--dns_upstream and --dns_upstream_tls can't be used together")
+        }
+        self.bypass = get_dns_bypass (args, !self.upstreams.is_empty () || self.tls_upstreams_opt.is_some ());
+        self.blocklist_sources = get_dns_blocklist (args);
+        self.block_mode = get_dns_blocklist_mode (args);
+        self.blocklist_refresh_secs = get_dns_blocklist_refresh (args);
+        self.hosts_file_opt = get_dns_hosts (args);
+        self.inline_overrides = get_dns_overrides (args);
+        self.rate_limit_qps = get_dns_rate_limit (args);
+        self.query_log_opt = get_dns_query_log (args);
+        self.client_privacy = get_dns_query_log_clients (args);
+        self.reloader.rate_limit_qps.store (self.rate_limit_qps as usize, Ordering::SeqCst);
+        self.reloader.blocklist.write ().expect ("Blocklist poisoned").mode = self.block_mode;
+        *self.reloader.blocklist_sources.write ().expect ("Blocklist sources poisoned") = self.blocklist_sources.clone ();
+        let socket_addr = SocketAddr::new (get_dns_bind_ip (args), get_dns_port (args));
         // The following expect() will cause an appropriate panic if the port can't be opened
         self.socket_wrapper.bind (socket_addr).expect (&format! ("Cannot bind socket to {:?}", socket_addr));
+        if let Some (ref mut tcp_listener) = self.tcp_listener_opt {
+            tcp_listener.bind (socket_addr).expect (&format! ("Cannot bind TCP socket to {:?}", socket_addr));
+        }
     }
 
     fn serve_without_root (&mut self) {
-        let processor = ProcessorReal::new (self.dns_target.expect("Missing dns_target - was initialize_as_root called?"));
+        let dns_target = self.dns_target.expect("Missing dns_target - was initialize_as_root called?");
+        // There's always a blocklist, even an empty one, in case a reload names some sources for it
+        let blocklist = self.reloader.blocklist.clone ();
+        start_refreshing (blocklist.clone (), self.reloader.blocklist_sources.clone (), Duration::from_secs (self.blocklist_refresh_secs));
+        let blocklist_opt = Some (blocklist);
+        let overrides_opt = if self.hosts_file_opt.is_none () && self.inline_overrides.is_empty () {None} else {
+            let overrides = Arc::new (RwLock::new (self.inline_overrides.clone ()));
+            if let Some (ref hosts_file) = self.hosts_file_opt {
+                start_watching (overrides.clone (), hosts_file.clone (), self.inline_overrides.clone (),
+                    Duration::from_secs (OVERRIDES_FILE_CHECK_SECS));
+            }
+            Some (overrides)
+        };
+        let privacy = self.client_privacy;
+        let query_log_opt = self.query_log_opt.as_ref ().map (|path| {
+            Arc::new (Mutex::new (QueryLog::new (PathBuf::from (path), DEFAULT_QUERY_LOG_MAX_BYTES, privacy)))
+        });
+        if let Some (tcp_listener) = self.tcp_listener_opt.take () {
+            let (dns_target_v6, upstreams, tls_upstreams_opt, bypass) = (self.dns_target_v6, self.upstreams.clone (),
+                self.tls_upstreams_opt.clone (), self.bypass);
+            let (blocklist_opt, overrides_opt, query_log_opt) = (blocklist_opt.clone (), overrides_opt.clone (), query_log_opt.clone ());
+            let (request_tx, request_rx) = mpsc::channel ();
+            start_accepting (tcp_listener, request_tx);
+            thread::spawn (move || {
+                // TCP gets a processor of its own, since processors can't be shared between threads;
+                // every TCP connection brings its questions to this one
+                let processor = make_processor (dns_target, dns_target_v6, &upstreams, tls_upstreams_opt, bypass, blocklist_opt,
+                    overrides_opt, query_log_opt);
+                let mut tcp_server = TcpServerReal {logger: Logger::new ("EntryDnsServer"),
+                    requests: &request_rx, processor: &processor};
+                let mut buf: [u8; 65536] = [0; 65536];
+                loop {
+                    tcp_server.serve (&mut buf);
+                }
+            });
+        }
+        let processor = make_processor (dns_target, self.dns_target_v6, &self.upstreams, self.tls_upstreams_opt.clone (), self.bypass,
+            blocklist_opt, overrides_opt, query_log_opt);
         let mut packet_server = PacketServerReal {logger: Logger::new ("EntryDnsServer"),
-            socket: &mut self.socket_wrapper, processor: &processor};
+            socket: &mut self.socket_wrapper, processor: &processor,
+            rate_limiter_opt: if self.rate_limit_qps == 0 {None} else {Some (RateLimiter::new (self.rate_limit_qps))}};
         let mut buf: [u8; 65536] = [0; 65536];
+        let mut rate_limit_qps = self.rate_limit_qps;
         while self.limiter.should_continue () {
+            let reloaded_qps = self.reloader.rate_limit_qps.load (Ordering::SeqCst) as u64;
+            if reloaded_qps != rate_limit_qps {
+                rate_limit_qps = reloaded_qps;
+                packet_server.rate_limiter_opt = if rate_limit_qps == 0 {None} else {Some (RateLimiter::new (rate_limit_qps))};
+            }
             packet_server.serve (&mut buf);
         }
     }
+
+    fn reloader (&self) -> Option<Box<Reloader>> {
+        Some (Box::new (self.reloader.clone ()))
+    }
 }
 
 // TODO: why not use the `::new` convention?
 pub fn new_dns_socket_server() -> DnsSocketServer<UdpSocketWrapperReal> {
-    DnsSocketServer {dns_target: None, socket_wrapper: UdpSocketWrapperReal::new (), limiter: Limiter::new()}
+    DnsSocketServer {dns_target: None, dns_target_v6: None, upstreams: vec! (), tls_upstreams_opt: None, bypass: false,
+        blocklist_sources: vec! (), block_mode: BlockMode::NxDomain, blocklist_refresh_secs: DEFAULT_BLOCKLIST_REFRESH_SECS, hosts_file_opt: None,
+        inline_overrides: Overrides::new (), rate_limit_qps: DEFAULT_RATE_LIMIT_QPS, query_log_opt: None,
+        client_privacy: ClientPrivacy::Masked, socket_wrapper: UdpSocketWrapperReal::new (),
+        tcp_listener_opt: Some (Box::new (TcpListenerWrapperReal::new ())), reloader: DnsReloader::new (), limiter: Limiter::new()}
+}
+
+fn make_processor (dns_target: IpAddr, dns_target_v6: Option<Ipv6Addr>, upstreams: &Vec<SocketAddr>,
+        tls_upstreams_opt: Option<TlsUpstreams>, bypass: bool,
+        blocklist_opt: Option<Arc<RwLock<Blocklist>>>, overrides_opt: Option<Arc<RwLock<Overrides>>>,
+        query_log_opt: Option<Arc<Mutex<QueryLog>>>) -> ProcessorReal {
+    let cache = DnsCache::new (DEFAULT_CACHE_SIZE, DEFAULT_NEGATIVE_TTL);
+    let timeout = Duration::from_millis (DEFAULT_UPSTREAM_TIMEOUT_MS);
+    let resolver_opt: Option<Box<ResolverTrait>> = if let Some (tls_upstreams) = tls_upstreams_opt {
+        let upstream = TlsResolver::new (tls_upstreams, timeout).expect ("Couldn't set up DNS over TLS");
+        Some (Box::new (CachingResolver::new (upstream, cache)))
+    } else if upstreams.is_empty () {None} else {
+        let upstream = UpstreamResolver::new (Box::new (|| UdpSocketWrapperReal::new ()), upstreams.clone (), timeout)
+            .expect ("Couldn't set up upstream DNS");
+        Some (Box::new (CachingResolver::new (upstream, cache)))
+    };
+    ProcessorReal::new (dns_target, dns_target_v6, resolver_opt, bypass, blocklist_opt, overrides_opt, query_log_opt)
 }
 
 fn get_dns_target (args: &Vec<String>) -> IpAddr {
@@ -60,6 +248,130 @@ fn get_dns_target (args: &Vec<String>) -> IpAddr {
     }
 }
 
+fn get_dns_target_v6 (args: &Vec<String>) -> Option<Ipv6Addr> {
+    let finder = ParameterFinder::new (args);
+    match finder.find_value_after ("--dns_target_v6", "must be followed by IPv6 address to redirect AAAA queries to") {
+        Some (s) => match Ipv6Addr::from_str (&s) {
+            Ok (ip_addr) => Some (ip_addr),
+            Err (_) => panic! ("Invalid IPv6 address for --dns_target_v6: {}", s)
+        },
+        None => None
+    }
+}
+
+fn get_dns_upstreams (args: &Vec<String>) -> Vec<SocketAddr> {
+    let finder = ParameterFinder::new (args);
+    match finder.find_value_after ("--dns_upstream", "must be followed by comma-separated IP addresses of DNS servers to forward to") {
+        Some (s) => match parse_upstreams (&s) {
+            Ok (upstreams) => upstreams,
+            Err (e) => panic! ("Invalid value for --dns_upstream: {}", e)
+        },
+        None => vec! ()
+    }
+}
+
+fn get_dns_upstreams_tls (args: &Vec<String>) -> Option<TlsUpstreams> {
+    let finder = ParameterFinder::new (args);
+    let servers = match finder.find_value_after ("--dns_upstream_tls", "must be followed by comma-separated DNS-over-TLS servers, each as address#certificate-name") {
+        Some (s) => match parse_tls_upstreams (&s) {
+            Ok (servers) => servers,
+            Err (e) => panic! ("Invalid value for --dns_upstream_tls: {}", e)
+        },
+        None => return None
+    };
+    let ca_file_opt = finder.find_value_after ("--dns_upstream_ca", "must be followed by a PEM file of extra certificate authorities to trust");
+    match make_tls_config (ca_file_opt.as_ref ().map (|ca_file| ca_file.as_str ())) {
+        Ok (config) => Some (TlsUpstreams {servers, config}),
+        Err (e) => panic! ("Invalid value for --dns_upstream_ca: {}", e)
+    }
+}
+
+fn get_dns_bypass (args: &Vec<String>, have_upstreams: bool) -> bool {
+    let finder = ParameterFinder::new (args);
+    let mode = finder.find_value_after ("--dns_mode", "must be followed by subvert or bypass (default subvert)")
+        .unwrap_or (String::from ("subvert"));
+    match mode.as_str () {
+        "subvert" => false,
+        "bypass" if !have_upstreams => panic! ("--dns_mode bypass needs --dns_upstream or --dns_upstream_tls to forward to"),
+        "bypass" => true,
+        _ => panic! ("Invalid value for --dns_mode: {}", mode)
+    }
+}
+
+fn get_dns_blocklist (args: &Vec<String>) -> Vec<String> {
+    let finder = ParameterFinder::new (args);
+    match finder.find_value_after ("--dns_blocklist", "must be followed by comma-separated files or http:// URLs of domains to block") {
+        Some (s) => s.split (',').map (|source| String::from (source.trim ())).filter (|source| !source.is_empty ()).collect (),
+        None => vec! ()
+    }
+}
+
+fn get_dns_blocklist_mode (args: &Vec<String>) -> BlockMode {
+    let finder = ParameterFinder::new (args);
+    let mode = finder.find_value_after ("--dns_blocklist_mode", "must be followed by nxdomain or null (default nxdomain)")
+        .unwrap_or (String::from ("nxdomain"));
+    match mode.as_str () {
+        "nxdomain" => BlockMode::NxDomain,
+        "null" => BlockMode::NullAddress,
+        _ => panic! ("Invalid value for --dns_blocklist_mode: {}", mode)
+    }
+}
+
+fn get_dns_blocklist_refresh (args: &Vec<String>) -> u64 {
+    let finder = ParameterFinder::new (args);
+    match finder.find_value_after ("--dns_blocklist_refresh", "must be followed by seconds between blocklist reloads (default 86400)") {
+        Some (s) => match s.parse::<u64> () {
+            Ok (secs) if secs > 0 => secs,
+            _ => panic! ("Invalid value for --dns_blocklist_refresh: {}", s)
+        },
+        None => DEFAULT_BLOCKLIST_REFRESH_SECS
+    }
+}
+
+fn get_dns_hosts (args: &Vec<String>) -> Option<String> {
+    let finder = ParameterFinder::new (args);
+    finder.find_value_after ("--dns_hosts", "must be followed by a hosts-style file of names to override (checked for changes every few seconds)")
+}
+
+fn get_dns_overrides (args: &Vec<String>) -> Overrides {
+    let finder = ParameterFinder::new (args);
+    match finder.find_value_after ("--dns_override", "must be followed by comma-separated name=address pairs") {
+        Some (s) => match parse_inline (&s) {
+            Ok (overrides) => overrides,
+            Err (e) => panic! ("Invalid value for --dns_override: {}", e)
+        },
+        None => Overrides::new ()
+    }
+}
+
+fn get_dns_rate_limit (args: &Vec<String>) -> u64 {
+    let finder = ParameterFinder::new (args);
+    match finder.find_value_after ("--dns_rate_limit", "must be followed by queries per second allowed from each client, or 0 for no limit (default 100)") {
+        Some (s) => match s.parse::<u64> () {
+            Ok (qps) => qps,
+            Err (_) => panic! ("Invalid value for --dns_rate_limit: {}", s)
+        },
+        None => DEFAULT_RATE_LIMIT_QPS
+    }
+}
+
+fn get_dns_query_log (args: &Vec<String>) -> Option<String> {
+    let finder = ParameterFinder::new (args);
+    finder.find_value_after ("--dns_query_log", "must be followed by the file to log DNS queries to")
+}
+
+fn get_dns_query_log_clients (args: &Vec<String>) -> ClientPrivacy {
+    let finder = ParameterFinder::new (args);
+    let privacy = finder.find_value_after ("--dns_query_log_clients", "must be followed by full, masked or omitted (default masked)")
+        .unwrap_or (String::from ("masked"));
+    match privacy.as_str () {
+        "full" => ClientPrivacy::Full,
+        "masked" => ClientPrivacy::Masked,
+        "omitted" => ClientPrivacy::Omitted,
+        _ => panic! ("Invalid value for --dns_query_log_clients: {}", privacy)
+    }
+}
+
 fn get_dns_port (args: &Vec<String>) -> u16 {
     let finder = ParameterFinder::new (args);
     let port_str = match finder.find_value_after("--dns_port", "must be followed by port number on which DNS server listens (default 53)") {
@@ -76,6 +388,22 @@ fn get_dns_port (args: &Vec<String>) -> u16 {
     port as u16
 }
 
+// --dns_bind_ip, or else --bind_ip, which is for all of the Node's listeners
+fn get_dns_bind_ip (args: &Vec<String>) -> IpAddr {
+    let finder = ParameterFinder::new (args);
+    let (parameter_tag, value) = match finder.find_value_after ("--dns_bind_ip", "must be followed by the IP address of the local interface the DNS server listens on") {
+        Some (value) => ("--dns_bind_ip", value),
+        None => match finder.find_value_after ("--bind_ip", "must be followed by the IP address of the local interface every listener listens on") {
+            Some (value) => ("--bind_ip", value),
+            None => return V4 (Ipv4Addr::from (0))
+        }
+    };
+    match IpAddr::from_str (&value) {
+        Ok (ip_addr) => ip_addr,
+        Err (_) => panic! ("Invalid IP address for {}: {}", parameter_tag, value)
+    }
+}
+
 struct ParameterFinder<'a> {
     args: &'a Vec<String>
 }
@@ -111,16 +439,42 @@ impl<'a> ParameterFinder<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::env;
+    use std::fs::File;
     use std::io;
+    use std::io::Write;
     use std::time::Duration;
     use std::cell::RefCell;
     use std::ops::DerefMut;
     use std::cmp::min;
+    use std::net::Incoming;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use sub_lib::tcp_wrappers::TcpStreamWrapper;
     use packet_facade::PacketFacade;
     use test_utils::test_utils::FakeStreamHolder;
     use test_utils::test_utils::init_test_logging;
     use test_utils::test_utils::TestLogHandler;
 
+    struct TcpListenerWrapperMock {
+        log: Arc<Mutex<Vec<String>>>
+    }
+
+    impl TcpListenerWrapper for TcpListenerWrapperMock {
+        fn bind (&mut self, addr: SocketAddr) -> io::Result<()> {
+            self.log.lock ().unwrap ().push (format! ("bind ('{:?}')", addr));
+            Ok (())
+        }
+
+        fn local_addr (&self) -> io::Result<SocketAddr> {unimplemented! ()}
+        fn accept (&self) -> io::Result<(Box<TcpStreamWrapper>, SocketAddr)> {unimplemented! ()}
+        fn incoming (&self) -> Incoming {unimplemented! ()}
+        fn set_ttl (&self, _ttl: u32) -> io::Result<()> {unimplemented! ()}
+        fn ttl (&self) -> io::Result<u32> {unimplemented! ()}
+        fn take_error (&self) -> io::Result<Option<io::Error>> {unimplemented! ()}
+        fn set_nonblocking (&self, _nonblocking: bool) -> io::Result<()> {unimplemented! ()}
+    }
+
     struct UdpSocketWrapperMockGuts {
         log: Vec<String>,
         buf: [u8; 12]
@@ -271,6 +625,252 @@ mod tests {
         assert_eq! (subject.dns_target, Some (V4(Ipv4Addr::from_str ("127.0.0.1").unwrap ())));
     }
 
+    #[test]
+    fn accepts_valid_dns_target_v6 () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(String::from ("--dns_target_v6"), String::from ("2001:db8::1234")),
+                                   &mut holder.streams ());
+
+        assert_eq! (subject.dns_target_v6, Some (Ipv6Addr::from_str ("2001:db8::1234").unwrap ()));
+    }
+
+    #[test]
+    fn defaults_unspecified_dns_target_v6 () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(), &mut holder.streams ());
+
+        assert_eq! (subject.dns_target_v6, None);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid IPv6 address for --dns_target_v6: 123.124.125.126")]
+    fn complains_about_dns_target_v6_that_is_not_ipv6 () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(String::from ("--dns_target_v6"), String::from ("123.124.125.126")),
+                                   &mut holder.streams ());
+    }
+
+    #[test]
+    fn accepts_blocklists_with_their_mode_and_refresh_interval () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(String::from ("--dns_blocklist"), String::from ("/etc/blocked, http://example.com/hosts"),
+            String::from ("--dns_blocklist_mode"), String::from ("null"), String::from ("--dns_blocklist_refresh"), String::from ("3600")),
+            &mut holder.streams ());
+
+        assert_eq! (subject.blocklist_sources, vec! (String::from ("/etc/blocked"), String::from ("http://example.com/hosts")));
+        assert_eq! (subject.block_mode, BlockMode::NullAddress);
+        assert_eq! (subject.blocklist_refresh_secs, 3600);
+    }
+
+    #[test]
+    fn defaults_to_no_blocklist () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(), &mut holder.streams ());
+
+        assert_eq! (subject.blocklist_sources.is_empty (), true);
+        assert_eq! (subject.block_mode, BlockMode::NxDomain);
+        assert_eq! (subject.blocklist_refresh_secs, DEFAULT_BLOCKLIST_REFRESH_SECS);
+    }
+
+    #[test]
+    fn a_reload_changes_the_rate_limit_and_the_blocklist_of_a_running_server () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+        subject.initialize_as_root(&vec!(), &mut holder.streams ());
+        let blocklist_file = env::temp_dir ().join ("a_reload_changes_the_rate_limit_and_the_blocklist_of_a_running_server.hosts");
+        File::create (&blocklist_file).unwrap ().write_all (b"0.0.0.0 ads.example.com\n").unwrap ();
+        let reloader = subject.reloader ().unwrap ();
+
+        let result = reloader.reload (&vec! (String::from ("--dns_rate_limit"), String::from ("20"),
+            String::from ("--dns_blocklist"), blocklist_file.to_string_lossy ().to_string (),
+            String::from ("--dns_blocklist_mode"), String::from ("null")));
+
+        assert_eq! (result, Ok (()));
+        assert_eq! (subject.reloader.rate_limit_qps.load (Ordering::SeqCst), 20);
+        let blocklist = subject.reloader.blocklist.read ().unwrap ();
+        assert_eq! (blocklist.mode, BlockMode::NullAddress);
+        assert_eq! (blocklist.is_blocked ("ads.example.com"), true);
+    }
+
+    #[test]
+    fn a_reload_with_an_unreadable_blocklist_changes_nothing () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+        subject.initialize_as_root(&vec!(), &mut holder.streams ());
+        let reloader = subject.reloader ().unwrap ();
+
+        let result = reloader.reload (&vec! (String::from ("--dns_rate_limit"), String::from ("20"),
+            String::from ("--dns_blocklist"), String::from ("/nonexistent/blocklist")));
+
+        assert_eq! (result.err ().unwrap ().starts_with ("Can't read blocklist /nonexistent/blocklist: "), true);
+        assert_eq! (subject.reloader.rate_limit_qps.load (Ordering::SeqCst), DEFAULT_RATE_LIMIT_QPS as usize);
+        assert_eq! (subject.reloader.blocklist_sources.read ().unwrap ().is_empty (), true);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --dns_blocklist_mode: booga")]
+    fn complains_about_bad_blocklist_mode () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(String::from ("--dns_blocklist_mode"), String::from ("booga")), &mut holder.streams ());
+    }
+
+    #[test]
+    fn accepts_a_hosts_file_and_inline_overrides () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(String::from ("--dns_hosts"), String::from ("/etc/substratum/hosts"),
+            String::from ("--dns_override"), String::from ("db.lab=10.0.0.5,api.lab=fd00::5")), &mut holder.streams ());
+
+        assert_eq! (subject.hosts_file_opt, Some (String::from ("/etc/substratum/hosts")));
+        assert_eq! (subject.inline_overrides, parse_inline ("db.lab=10.0.0.5,api.lab=fd00::5").unwrap ());
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --dns_override: 'db.lab=booga' is not name=address")]
+    fn complains_about_bad_inline_overrides () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(String::from ("--dns_override"), String::from ("db.lab=booga")), &mut holder.streams ());
+    }
+
+    #[test]
+    fn accepts_a_rate_limit_and_defaults_to_one () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut limited = make_instrumented_subject ();
+        let mut unlimited = make_instrumented_subject ();
+        let mut defaulted = make_instrumented_subject ();
+
+        limited.initialize_as_root(&vec!(String::from ("--dns_rate_limit"), String::from ("20")), &mut holder.streams ());
+        unlimited.initialize_as_root(&vec!(String::from ("--dns_rate_limit"), String::from ("0")), &mut holder.streams ());
+        defaulted.initialize_as_root(&vec!(), &mut holder.streams ());
+
+        assert_eq! (limited.rate_limit_qps, 20);
+        assert_eq! (unlimited.rate_limit_qps, 0);
+        assert_eq! (defaulted.rate_limit_qps, DEFAULT_RATE_LIMIT_QPS);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --dns_rate_limit: lots")]
+    fn complains_about_bad_rate_limit () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(String::from ("--dns_rate_limit"), String::from ("lots")), &mut holder.streams ());
+    }
+
+    #[test]
+    fn keeps_no_query_log_unless_asked_and_masks_clients_by_default () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut defaulted = make_instrumented_subject ();
+        let mut logging = make_instrumented_subject ();
+
+        defaulted.initialize_as_root(&vec!(), &mut holder.streams ());
+        logging.initialize_as_root(&vec!(String::from ("--dns_query_log"), String::from ("/var/log/queries.log"),
+            String::from ("--dns_query_log_clients"), String::from ("omitted")), &mut holder.streams ());
+
+        assert_eq! (defaulted.query_log_opt, None);
+        assert_eq! (defaulted.client_privacy, ClientPrivacy::Masked);
+        assert_eq! (logging.query_log_opt, Some (String::from ("/var/log/queries.log")));
+        assert_eq! (logging.client_privacy, ClientPrivacy::Omitted);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --dns_query_log_clients: booga")]
+    fn complains_about_bad_query_log_clients () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(String::from ("--dns_query_log_clients"), String::from ("booga")), &mut holder.streams ());
+    }
+
+    #[test]
+    fn accepts_upstreams_and_bypass_mode () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(String::from ("--dns_upstream"), String::from ("8.8.8.8,1.1.1.1:5353"),
+            String::from ("--dns_mode"), String::from ("bypass")), &mut holder.streams ());
+
+        assert_eq! (subject.upstreams, vec! (SocketAddr::from_str ("8.8.8.8:53").unwrap (), SocketAddr::from_str ("1.1.1.1:5353").unwrap ()));
+        assert_eq! (subject.bypass, true);
+    }
+
+    #[test]
+    fn accepts_dns_over_tls_upstreams_for_bypass_mode () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(String::from ("--dns_upstream_tls"), String::from ("1.1.1.1#cloudflare-dns.com"),
+            String::from ("--dns_mode"), String::from ("bypass")), &mut holder.streams ());
+
+        assert_eq! (subject.upstreams.is_empty (), true);
+        assert_eq! (subject.tls_upstreams_opt.as_ref ().unwrap ().servers, parse_tls_upstreams ("1.1.1.1#cloudflare-dns.com").unwrap ());
+        assert_eq! (subject.bypass, true);
+    }
+
+    #[test]
+    #[should_panic (expected = "--dns_upstream and --dns_upstream_tls can't be used together")]
+    fn complains_about_plain_and_tls_upstreams_together () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(String::from ("--dns_upstream"), String::from ("8.8.8.8"),
+            String::from ("--dns_upstream_tls"), String::from ("1.1.1.1#cloudflare-dns.com")), &mut holder.streams ());
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --dns_upstream_ca: Can't read /nonexistent/ca.pem")]
+    fn complains_about_unreadable_certificate_authorities () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(String::from ("--dns_upstream_tls"), String::from ("1.1.1.1#cloudflare-dns.com"),
+            String::from ("--dns_upstream_ca"), String::from ("/nonexistent/ca.pem")), &mut holder.streams ());
+    }
+
+    #[test]
+    fn defaults_to_no_upstreams_and_subversion () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(), &mut holder.streams ());
+
+        assert_eq! (subject.upstreams.is_empty (), true);
+        assert_eq! (subject.bypass, false);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --dns_upstream: 'booga' is not an IP address with an optional port")]
+    fn complains_about_bad_upstream () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(String::from ("--dns_upstream"), String::from ("8.8.8.8,booga")), &mut holder.streams ());
+    }
+
+    #[test]
+    #[should_panic (expected = "--dns_mode bypass needs --dns_upstream or --dns_upstream_tls to forward to")]
+    fn complains_about_bypass_without_upstream () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(String::from ("--dns_mode"), String::from ("bypass")), &mut holder.streams ());
+    }
+
     #[test]
     #[should_panic (expected = "--dns_port must be followed by port number on which DNS server listens (default 53)")]
     fn complains_about_missing_dns_port () {
@@ -328,6 +928,43 @@ mod tests {
         assert_eq! (log[0], "bind ('V4(0.0.0.0:5454)')")
     }
 
+    #[test]
+    fn listens_for_tcp_on_the_same_port () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+        let log = Arc::new (Mutex::new (vec! ()));
+        subject.tcp_listener_opt = Some (Box::new (TcpListenerWrapperMock {log: log.clone ()}));
+
+        subject.initialize_as_root(&vec!(String::from ("--dns_port"), String::from ("5454")),
+                                   &mut holder.streams ());
+
+        assert_eq! (*log.lock ().unwrap (), vec! (String::from ("bind ('V4(0.0.0.0:5454)')")));
+    }
+
+    #[test]
+    fn binds_to_the_dns_bind_address_in_preference_to_the_one_for_all_listeners () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+        let log = Arc::new (Mutex::new (vec! ()));
+        subject.tcp_listener_opt = Some (Box::new (TcpListenerWrapperMock {log: log.clone ()}));
+
+        subject.initialize_as_root(&vec!(String::from ("--bind_ip"), String::from ("10.0.0.5"), String::from ("--dns_bind_ip"), String::from ("127.0.0.1")),
+                                   &mut holder.streams ());
+
+        let socket_wrapper = &subject.socket_wrapper as &UdpSocketWrapperMock;
+        assert_eq! (socket_wrapper.guts.borrow ().log[0], "bind ('V4(127.0.0.1:53)')");
+        assert_eq! (*log.lock ().unwrap (), vec! (String::from ("bind ('V4(127.0.0.1:53)')")));
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid IP address for --bind_ip: booga")]
+    fn complains_about_a_bad_bind_address () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = make_instrumented_subject ();
+
+        subject.initialize_as_root(&vec!(String::from ("--bind_ip"), String::from ("booga")), &mut holder.streams ());
+    }
+
     #[test]
     fn defaults_unspecified_dns_port () {
         let mut holder = FakeStreamHolder::new ();
@@ -348,6 +985,7 @@ mod tests {
             let mut subject = make_instrumented_subject();
             subject.dns_target = Some(V4(Ipv4Addr::from_str("1.2.3.4").unwrap()));
             subject.limiter = Limiter::with_only (1);
+            subject.tcp_listener_opt = None;
 
             subject.serve_without_root();
 
@@ -370,6 +1008,11 @@ mod tests {
         let socket_wrapper = UdpSocketWrapperMock::new (&[
             0x12, 0x34, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
         ]);
-        DnsSocketServer {dns_target: None, socket_wrapper, limiter: Limiter::with_only (1)}
+        DnsSocketServer {dns_target: None, dns_target_v6: None, upstreams: vec! (), tls_upstreams_opt: None, bypass: false,
+            blocklist_sources: vec! (), block_mode: BlockMode::NxDomain, blocklist_refresh_secs: DEFAULT_BLOCKLIST_REFRESH_SECS, hosts_file_opt: None,
+            inline_overrides: Overrides::new (), rate_limit_qps: DEFAULT_RATE_LIMIT_QPS, query_log_opt: None,
+            client_privacy: ClientPrivacy::Masked, socket_wrapper,
+            tcp_listener_opt: Some (Box::new (TcpListenerWrapperMock {log: Arc::new (Mutex::new (vec! ()))})), reloader: DnsReloader::new (),
+            limiter: Limiter::with_only (1)}
     }
 }
\ No newline at end of file
@@ -0,0 +1,130 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::cmp::max;
+use std::cmp::min;
+use packet_facade::PacketFacade;
+use packet_facade::ResourceRecord;
+use packet_server::MAX_UDP_RESPONSE_LENGTH;
+
+pub const RR_TYPE_OPT: u16 = 0x0029;
+// The largest UDP response we'll send, small enough not to be fragmented on any sane path
+pub const MAX_EDNS_PAYLOAD_SIZE: u16 = 1232;
+pub const EDNS_VERSION: u8 = 0;
+// The upper eight bits of BADVERS (16) go in the OPT record; the lower four in the header are zero
+pub const EXTENDED_RCODE_BADVERS: u8 = 0x01;
+
+// What an OPT pseudo-record says. It stands in for a resource record, but its class is the
+// largest UDP payload its sender can take, and its TTL holds the upper bits of the RCODE, the
+// EDNS version, and the DNSSEC OK flag.
+#[derive (Clone, Debug, PartialEq)]
+pub struct Edns {
+    pub udp_payload_size: u16,
+    pub extended_rcode: u8,
+    pub version: u8,
+    pub dnssec_ok: bool,
+}
+
+impl Edns {
+    pub fn from_record (record: &ResourceRecord) -> Edns {
+        let ttl = record.get_time_to_live ();
+        Edns {
+            udp_payload_size: record.get_resource_class (),
+            extended_rcode: (ttl >> 24) as u8,
+            version: (ttl >> 16) as u8,
+            dnssec_ok: (ttl & 0x8000) != 0,
+        }
+    }
+
+    // What we say back to a request that came with this
+    pub fn response (&self, extended_rcode: u8) -> Edns {
+        Edns {udp_payload_size: MAX_EDNS_PAYLOAD_SIZE, extended_rcode, version: EDNS_VERSION, dnssec_ok: self.dnssec_ok}
+    }
+
+    pub fn add_to (&self, facade: &mut PacketFacade) -> bool {
+        let ttl = ((self.extended_rcode as u32) << 24) | ((self.version as u32) << 16) | if self.dnssec_ok {0x8000} else {0};
+        facade.add_additional ("", RR_TYPE_OPT, self.udp_payload_size, ttl, &[])
+    }
+}
+
+// A packet may have one OPT record, and it must be for the root; anything else is a format error
+pub fn find_edns (facade: &PacketFacade) -> Result<Option<Edns>, String> {
+    let additionals = match facade.get_additionals () {
+        Some (additionals) => additionals,
+        None => return Err (String::from ("Unparseable additional records"))
+    };
+    let opts: Vec<&ResourceRecord> = additionals.iter ().filter (|record| record.get_resource_type () == RR_TYPE_OPT).collect ();
+    match opts.len () {
+        0 => Ok (None),
+        1 if opts[0].get_name ().is_empty () => Ok (Some (Edns::from_record (opts[0]))),
+        1 => Err (format! ("OPT record for '{}' rather than the root", opts[0].get_name ())),
+        n => Err (format! ("{} OPT records", n))
+    }
+}
+
+// How long a UDP response to this request may be: what the client says it can take, within limits
+pub fn udp_payload_limit (buf: &mut [u8], length: usize) -> usize {
+    let facade = PacketFacade::new (buf, length);
+    match find_edns (&facade) {
+        Ok (Some (edns)) => max (MAX_UDP_RESPONSE_LENGTH, min (edns.udp_payload_size, MAX_EDNS_PAYLOAD_SIZE) as usize),
+        _ => MAX_UDP_RESPONSE_LENGTH
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    fn make_packet (buf: &mut [u8], opts: Vec<(&str, u16, u32)>) -> usize {
+        let mut facade = PacketFacade::new (buf, 12);
+        facade.add_query ("name", 0x0001, 0x0001);
+        facade.add_additional ("other", 0x0001, 0x0001, 3600, &[1, 2, 3, 4]);
+        for (name, class, ttl) in opts {
+            facade.add_additional (name, RR_TYPE_OPT, class, ttl, &[]);
+        }
+        facade.get_length ()
+    }
+
+    fn edns_of (opts: Vec<(&str, u16, u32)>) -> Result<Option<Edns>, String> {
+        let mut buf: [u8; 200] = [0; 200];
+        let length = make_packet (&mut buf, opts);
+        let facade = PacketFacade::new (&mut buf, length);
+        find_edns (&facade)
+    }
+
+    #[test]
+    fn opt_records_are_read_and_checked () {
+        assert_eq! (edns_of (vec! ()), Ok (None));
+        assert_eq! (edns_of (vec! (("", 4096, 0x01028000))), Ok (Some (Edns {udp_payload_size: 4096, extended_rcode: 1, version: 2, dnssec_ok: true})));
+        assert_eq! (edns_of (vec! (("", 4096, 0))), Ok (Some (Edns {udp_payload_size: 4096, extended_rcode: 0, version: 0, dnssec_ok: false})));
+        assert_eq! (edns_of (vec! (("name", 4096, 0))), Err (String::from ("OPT record for 'name' rather than the root")));
+        assert_eq! (edns_of (vec! (("", 4096, 0), ("", 512, 0))), Err (String::from ("2 OPT records")));
+    }
+
+    #[test]
+    fn responses_say_what_we_can_take_and_echo_the_dnssec_ok_flag () {
+        let request = Edns {udp_payload_size: 4096, extended_rcode: 0, version: 0, dnssec_ok: true};
+        let mut buf: [u8; 200] = [0; 200];
+        let length = {
+            let mut facade = PacketFacade::new (&mut buf, 12);
+            request.response (EXTENDED_RCODE_BADVERS).add_to (&mut facade);
+            facade.get_length ()
+        };
+
+        let facade = PacketFacade::new (&mut buf, length);
+        assert_eq! (find_edns (&facade), Ok (Some (Edns {udp_payload_size: MAX_EDNS_PAYLOAD_SIZE, extended_rcode: 1, version: 0, dnssec_ok: true})));
+    }
+
+    #[test]
+    fn udp_responses_may_be_as_long_as_the_client_says_within_limits () {
+        let limit_for = |opts: Vec<(&str, u16, u32)>| {
+            let mut buf: [u8; 200] = [0; 200];
+            let length = make_packet (&mut buf, opts);
+            udp_payload_limit (&mut buf, length)
+        };
+
+        assert_eq! (limit_for (vec! ()), 512);
+        assert_eq! (limit_for (vec! (("", 1000, 0))), 1000);
+        assert_eq! (limit_for (vec! (("", 100, 0))), 512);
+        assert_eq! (limit_for (vec! (("", 65535, 0))), 1232);
+        assert_eq! (limit_for (vec! (("", 1000, 0), ("", 1000, 0))), 512);
+    }
+}
@@ -1,5 +1,9 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 extern crate sub_lib;
+extern crate rand;
+extern crate rustls;
+extern crate webpki;
+extern crate webpki_roots;
 #[cfg(unix)]
 extern crate daemonize;
 
@@ -13,4 +17,14 @@ pub mod packet_facade;
 
 pub mod packet_server;
 pub mod processor;
+pub mod edns;
+pub mod dns_cache;
+pub mod resolver;
+pub mod upstream_resolver;
+pub mod tls_resolver;
+pub mod tcp_server;
+pub mod blocklist;
+pub mod overrides;
+pub mod rate_limiter;
+pub mod query_log;
 pub mod dns_socket_server;
@@ -0,0 +1,170 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
+use sub_lib::logger::Logger;
+
+// Overrides are answered with a short TTL, so that changes to them take effect soon
+pub const OVERRIDE_TTL: u32 = 60;
+pub const OVERRIDES_FILE_CHECK_SECS: u64 = 5;
+
+// Names pinned to addresses by the operator, like /etc/hosts. An overridden name has no records but
+// these; other names are left alone, subdomains included.
+#[derive (Clone, Debug, PartialEq, Default)]
+pub struct Overrides {
+    entries: HashMap<String, Vec<IpAddr>>,
+}
+
+impl Overrides {
+    pub fn new () -> Overrides {
+        Overrides {entries: HashMap::new ()}
+    }
+
+    pub fn add (&mut self, name: &str, ip_addr: IpAddr) {
+        let addrs = self.entries.entry (normalize (name)).or_insert (vec! ());
+        if !addrs.contains (&ip_addr) {addrs.push (ip_addr)}
+    }
+
+    // Everything in the other set, which takes the place of anything here for the same names
+    pub fn overlay (&mut self, other: &Overrides) {
+        for (name, addrs) in &other.entries {
+            self.entries.insert (name.clone (), addrs.clone ());
+        }
+    }
+
+    pub fn lookup (&self, name: &str) -> Option<&Vec<IpAddr>> {
+        self.entries.get (&normalize (name))
+    }
+
+    pub fn len (&self) -> usize {
+        self.entries.len ()
+    }
+
+    pub fn is_empty (&self) -> bool {
+        self.entries.is_empty ()
+    }
+}
+
+// Lines of "address name [name...]", with # comments
+pub fn parse_hosts (text: &str) -> Result<Overrides, String> {
+    let mut overrides = Overrides::new ();
+    for (index, line) in text.lines ().enumerate () {
+        let line = match line.find ('#') {
+            Some (comment_start) => &line[..comment_start],
+            None => line
+        };
+        let mut words = line.split_whitespace ();
+        let ip_addr = match words.next () {
+            Some (word) => IpAddr::from_str (word).map_err (|_| format! ("Line {}: '{}' is not an IP address", index + 1, word))?,
+            None => continue
+        };
+        let names: Vec<&str> = words.collect ();
+        if names.is_empty () {return Err (format! ("Line {}: no names for {}", index + 1, ip_addr))}
+        for name in names {
+            overrides.add (name, ip_addr);
+        }
+    }
+    Ok (overrides)
+}
+
+// "name=address,name=address"
+pub fn parse_inline (spec: &str) -> Result<Overrides, String> {
+    let mut overrides = Overrides::new ();
+    for pair in spec.split (',').map (|pair| pair.trim ()).filter (|pair| !pair.is_empty ()) {
+        let mut parts = pair.splitn (2, '=');
+        let name = parts.next ().unwrap_or ("").trim ();
+        let address = parts.next ().unwrap_or ("").trim ();
+        if name.is_empty () {return Err (format! ("'{}' has no name", pair))}
+        match IpAddr::from_str (address) {
+            Ok (ip_addr) => overrides.add (name, ip_addr),
+            Err (_) => return Err (format! ("'{}' is not name=address", pair))
+        }
+    }
+    Ok (overrides)
+}
+
+pub fn load_hosts (path: &str) -> Result<Overrides, String> {
+    let mut text = String::new ();
+    File::open (path).and_then (|mut file| file.read_to_string (&mut text))
+        .map_err (|e| format! ("Can't read overrides file {}: {}", path, e))?;
+    parse_hosts (&text).map_err (|e| format! ("Overrides file {}: {}", path, e))
+}
+
+// Reloads the file whenever it changes, for as long as the Node runs. The inline overrides are laid
+// over whatever the file says. If the file can't be read or parsed, the overrides stay as they were.
+pub fn start_watching (overrides: Arc<RwLock<Overrides>>, path: String, inline: Overrides, interval: Duration) {
+    thread::spawn (move || {
+        let logger = Logger::new ("Overrides");
+        let mut loaded_modified_opt: Option<SystemTime> = None;
+        // so that a file that stays broken is only complained about once
+        let mut last_error_opt: Option<String> = None;
+        loop {
+            let modified_opt = fs::metadata (&path).and_then (|metadata| metadata.modified ()).ok ();
+            if modified_opt.is_none () || (modified_opt != loaded_modified_opt) {
+                match load_hosts (&path) {
+                    Ok (mut from_file) => {
+                        from_file.overlay (&inline);
+                        logger.info (format! ("Overriding {} names", from_file.len ()));
+                        *overrides.write ().expect ("Overrides poisoned") = from_file;
+                        loaded_modified_opt = modified_opt;
+                        last_error_opt = None;
+                    },
+                    Err (e) => {
+                        if last_error_opt.as_ref () != Some (&e) {logger.warning (e.clone ())}
+                        last_error_opt = Some (e);
+                    }
+                }
+            }
+            thread::sleep (interval);
+        }
+    });
+}
+
+fn normalize (name: &str) -> String {
+    name.trim_right_matches ('.').to_lowercase ()
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hosts_text_maps_each_name_to_its_addresses () {
+        let text = "# Lab\n\
+            10.0.0.5 db.lab DB2.lab. # two names\n\
+            fd00::5 db.lab\n\
+            \n\
+            10.0.0.6 db.lab\n";
+
+        let result = parse_hosts (text).unwrap ();
+
+        assert_eq! (result.lookup ("DB.lab"), Some (&vec! (IpAddr::from_str ("10.0.0.5").unwrap (),
+            IpAddr::from_str ("fd00::5").unwrap (), IpAddr::from_str ("10.0.0.6").unwrap ())));
+        assert_eq! (result.lookup ("db2.lab"), Some (&vec! (IpAddr::from_str ("10.0.0.5").unwrap ())));
+        assert_eq! (result.lookup ("www.db.lab"), None);
+        assert_eq! (parse_hosts ("10.0.0.5 db.lab\nbooga db.lab"), Err (String::from ("Line 2: 'booga' is not an IP address")));
+        assert_eq! (parse_hosts ("10.0.0.5"), Err (String::from ("Line 1: no names for 10.0.0.5")));
+    }
+
+    #[test]
+    fn inline_overrides_are_parsed_and_take_the_place_of_those_from_the_file () {
+        let inline = parse_inline ("db.lab=10.0.0.9, api.lab=fd00::9").unwrap ();
+        let mut subject = parse_hosts ("10.0.0.5 db.lab\n10.0.0.6 web.lab").unwrap ();
+
+        subject.overlay (&inline);
+
+        assert_eq! (subject.lookup ("db.lab"), Some (&vec! (IpAddr::from_str ("10.0.0.9").unwrap ())));
+        assert_eq! (subject.lookup ("api.lab"), Some (&vec! (IpAddr::from_str ("fd00::9").unwrap ())));
+        assert_eq! (subject.lookup ("web.lab"), Some (&vec! (IpAddr::from_str ("10.0.0.6").unwrap ())));
+        assert_eq! (parse_inline ("db.lab:10.0.0.9"), Err (String::from ("'db.lab:10.0.0.9' is not name=address")));
+        assert_eq! (parse_inline ("=10.0.0.9"), Err (String::from ("'=10.0.0.9' has no name")));
+    }
+}
@@ -369,6 +369,30 @@ impl<'a> PacketFacade<'a> {
         self.length = 12
     }
 
+    // Leaves the questions, answers and authorities alone
+    pub fn remove_additionals (&mut self) -> bool {
+        let count = try_flg! (PacketFacade::u16_from (self.buf, 10, self.length));
+        if count == 0 {return true}
+        self.length = try_flg! (self.find_authorities_end ());
+        PacketFacade::u16_to (0x0000, &mut self.buf, 10)
+    }
+
+    // Drops every resource record, keeping the questions, and sets the TC bit so the client knows
+    // to ask again over TCP
+    pub fn truncate (&mut self) -> usize {
+        match self.find_queries_end () {
+            Some (queries_end) => {
+                PacketFacade::u16_to(0x0000, &mut self.buf, 6);
+                PacketFacade::u16_to(0x0000, &mut self.buf, 8);
+                PacketFacade::u16_to(0x0000, &mut self.buf, 10);
+                self.length = queries_end
+            },
+            None => self.clear ()
+        }
+        self.set_truncated (true);
+        self.length
+    }
+
     fn establish_high_water (&mut self, candidate: usize) {
         self.length = max (self.length, candidate);
     }
@@ -1194,6 +1218,54 @@ mod tests {
         }
     }
 
+    #[test]
+    fn removes_additionals_but_nothing_else() {
+        let mut buf: [u8; 100] = [0; 100];
+        let length = {
+            let mut subject = PacketFacade::new(&mut buf, 12);
+            subject.add_query("name", 0x0001, 0x0001);
+            subject.add_answer("name", 0x0001, 0x0001, 3600, &[1, 2, 3, 4]);
+            subject.add_additional("", 0x0029, 0x1000, 0, &[]);
+            subject.get_length ()
+        };
+
+        let (result, new_length) = {
+            let mut subject = PacketFacade::new(&mut buf, length);
+            (subject.remove_additionals(), subject.get_length())
+        };
+
+        let subject = PacketFacade::new(&mut buf, new_length);
+        assert_eq!(result, true);
+        assert_eq!(new_length, length - 11);
+        assert_eq!(subject.get_answers().unwrap().len(), 1);
+        assert_eq!(subject.get_additionals().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn truncates_to_the_questions_with_the_tc_bit_set() {
+        let mut buf: [u8; 100] = [0; 100];
+        let length = {
+            let mut subject = PacketFacade::new(&mut buf, 12);
+            subject.set_transaction_id(0x1234);
+            subject.add_query("name", 0x0001, 0x0001);
+            subject.add_answer("name", 0x0001, 0x0001, 3600, &[1, 2, 3, 4]);
+            subject.add_authority("name", 0x0002, 0x0001, 3600, &[0]);
+            subject.add_additional("name", 0x0001, 0x0001, 3600, &[1, 2, 3, 4]);
+            subject.get_length ()
+        };
+
+        let result = PacketFacade::new(&mut buf, length).truncate();
+
+        let subject = PacketFacade::new(&mut buf, result);
+        assert_eq!(result, 12 + 6 + 4);
+        assert_eq!(subject.get_transaction_id(), Some (0x1234));
+        assert_eq!(subject.is_truncated(), Some (true));
+        assert_eq!(subject.get_queries().unwrap().len(), 1);
+        assert_eq!(subject.get_answers().unwrap().len(), 0);
+        assert_eq!(subject.get_authorities().unwrap().len(), 0);
+        assert_eq!(subject.get_additionals().unwrap().len(), 0);
+    }
+
     #[test]
     fn returns_none_if_getting_transaction_id_busts_length () {
         let mut buf: [u8; 100] = [0; 100];
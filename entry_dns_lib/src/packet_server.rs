@@ -1,8 +1,17 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
-
+use std::time::Instant;
 use sub_lib::udp_socket_wrapper::UdpSocketWrapperTrait;
 use sub_lib::logger::Logger;
 use processor::ProcessorTrait;
+use packet_facade::PacketFacade;
+use edns::udp_payload_limit;
+use rate_limiter::RateLimiter;
+use rate_limiter::Verdict;
+use rate_limiter::make_refusal;
+
+// Unless the client says otherwise with EDNS, this is as long as a UDP response may be; anything
+// longer has to come over TCP
+pub const MAX_UDP_RESPONSE_LENGTH: usize = 512;
 
 pub trait PacketServerTrait {
     fn serve (&mut self, buf: &mut [u8]);
@@ -11,7 +20,9 @@ pub trait PacketServerTrait {
 pub struct PacketServerReal<'a, S: 'a, P: 'a> where S: UdpSocketWrapperTrait, P: ProcessorTrait {
     pub logger: Logger,
     pub socket: &'a S,
-    pub processor: &'a P
+    pub processor: &'a P,
+    // UDP source addresses are easily forged, so this is where floods are stopped
+    pub rate_limiter_opt: Option<RateLimiter>
 }
 
 impl<'a, S: UdpSocketWrapperTrait, P: ProcessorTrait> PacketServerTrait for PacketServerReal<'a, S, P> {
@@ -21,7 +32,32 @@ impl<'a, S: UdpSocketWrapperTrait, P: ProcessorTrait> PacketServerTrait for Pack
             Ok (size_and_address) => size_and_address,
             Err (e) => {self.logger.error(format! ("Couldn't receive packet: {}", e)); return}
         };
-        let response_length = self.processor.process (buf, request_length, &addr, &self.logger);
+        let verdict = match self.rate_limiter_opt {
+            Some (ref mut rate_limiter) => {
+                let (verdict, episode_opt) = rate_limiter.check (addr.ip (), Instant::now ());
+                if let Some (episode) = episode_opt {
+                    self.logger.warning (format! ("Stopped rate-limiting {}: refused {} and dropped {} of its queries ({} refused and {} dropped in all)",
+                        episode.client, episode.refused, episode.dropped, rate_limiter.refused_total (), rate_limiter.dropped_total ()));
+                }
+                verdict
+            },
+            None => Verdict::Allow
+        };
+        let limit = udp_payload_limit (buf, request_length);
+        let mut response_length = match verdict {
+            Verdict::Allow => self.processor.process (buf, request_length, &addr, &self.logger),
+            Verdict::Refuse => {
+                self.logger.warning (format! ("Refusing queries from {}: too many too fast", addr.ip ()));
+                match make_refusal (buf, request_length) {
+                    0 => return,
+                    length => length
+                }
+            },
+            Verdict::Drop => return
+        };
+        if response_length > limit {
+            response_length = PacketFacade::new (buf, response_length).truncate ();
+        }
         match self.socket.send_to (&buf[0..response_length], addr) {
             Ok (_) => (),
             Err (e) => self.logger.error(format! ("Couldn't respond: {}", e))
@@ -120,7 +156,7 @@ mod tests {
         );
         let processor = ProcessorMock::new (vec![]);
         let mut buf = [0; 0];
-        let mut subject = PacketServerReal { logger: Logger::new ("EntryDnsServer"), socket: &mut socket, processor: &processor };
+        let mut subject = PacketServerReal { logger: Logger::new ("EntryDnsServer"), socket: &mut socket, processor: &processor, rate_limiter_opt: None };
 
         subject.serve(&mut buf);
 
@@ -139,7 +175,7 @@ mod tests {
         let processor = ProcessorMock::new (vec![3]);
         {
             let mut buf = [0; 4];
-            let mut subject = PacketServerReal { logger: Logger::new ("complains_when_packet_cant_be_sent"), socket: &mut socket, processor: &processor };
+            let mut subject = PacketServerReal { logger: Logger::new ("complains_when_packet_cant_be_sent"), socket: &mut socket, processor: &processor, rate_limiter_opt: None };
 
             subject.serve(&mut buf);
         };
@@ -165,7 +201,7 @@ mod tests {
         let processor = ProcessorMock::new (vec![3]);
         {
             let mut buf = [0; 4];
-            let mut subject = PacketServerReal { logger: Logger::new ("EntryDnsServer"), socket: &mut socket, processor: &processor };
+            let mut subject = PacketServerReal { logger: Logger::new ("EntryDnsServer"), socket: &mut socket, processor: &processor, rate_limiter_opt: None };
 
             subject.serve(&mut buf);
         };
@@ -176,4 +212,52 @@ mod tests {
         let tlh = TestLogHandler::new ();
         tlh.exists_log_containing ("INFO: EntryDnsServer: processed");
     }
+
+    #[test]
+    pub fn clients_over_the_rate_limit_are_refused_then_ignored () {
+        init_test_logging();
+        let mut socket = UdpSocketWrapperMock::new (
+            Ok (true),
+            vec![
+                Ok ((12, SocketAddr::new (IpAddr::from ([1, 2, 3, 4]), 123))),
+                Ok ((12, SocketAddr::new (IpAddr::from ([1, 2, 3, 4]), 123))),
+                Ok ((12, SocketAddr::new (IpAddr::from ([1, 2, 3, 4]), 123))),
+                Ok ((12, SocketAddr::new (IpAddr::from ([1, 2, 3, 4]), 123))),
+            ],
+            vec![Ok (12), Ok (12), Ok (12)]
+        );
+        let processor = ProcessorMock::new (vec![12, 12]);
+        {
+            let mut buf = [0; 12];
+            let mut subject = PacketServerReal { logger: Logger::new ("rate_limited"), socket: &mut socket, processor: &processor,
+                rate_limiter_opt: Some (RateLimiter::new (1)) };
+
+            for _ in 0..4 {subject.serve(&mut buf);}
+        };
+
+        assert_eq! (processor.call_log.borrow ().len (), 2);
+        let call_log = socket.call_log.borrow ();
+        assert_eq! (call_log.iter ().filter (|entry| entry.starts_with ("send_to")).count (), 3);
+        assert_eq! (call_log[call_log.len () - 2], String::from ("send_to ([0, 0, 128, 133, 0, 0, 0, 0, 0, 0, 0, 0], V4(1.2.3.4:123))"));
+        TestLogHandler::new ().exists_log_containing ("WARN: rate_limited: Refusing queries from 1.2.3.4: too many too fast");
+    }
+
+    #[test]
+    pub fn responses_too_long_for_udp_are_truncated () {
+        let mut socket = UdpSocketWrapperMock::new (
+            Ok (true),
+            vec![Ok ((2, SocketAddr::new (IpAddr::from ([1, 2, 3, 4]), 123)))],
+            vec![Ok (12)]
+        );
+        let processor = ProcessorMock::new (vec![MAX_UDP_RESPONSE_LENGTH + 1]);
+        {
+            let mut buf = [0; 1000];
+            let mut subject = PacketServerReal { logger: Logger::new ("EntryDnsServer"), socket: &mut socket, processor: &processor, rate_limiter_opt: None };
+
+            subject.serve(&mut buf);
+        };
+
+        let call_log = socket.call_log.borrow ();
+        assert_eq! (call_log[1], String::from ("send_to ([0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0], V4(1.2.3.4:123))"));
+    }
 }
@@ -1,10 +1,25 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use std::time::Instant;
+use std::time::SystemTime;
 use std::net::SocketAddr;
 use std::net::IpAddr;
+use std::net::Ipv6Addr;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::sync::Mutex;
 use packet_facade::PacketFacade;
 use packet_facade::Query;
 use packet_facade::ResourceRecord;
+use resolver::ResolverTrait;
+use edns::Edns;
+use edns::find_edns;
+use edns::EDNS_VERSION;
+use edns::EXTENDED_RCODE_BADVERS;
+use blocklist::Blocklist;
+use blocklist::BlockMode;
+use overrides::Overrides;
+use overrides::OVERRIDE_TTL;
+use query_log::QueryLog;
 use sub_lib::logger::Logger;
 
 pub trait ProcessorTrait {
@@ -12,25 +27,82 @@ pub trait ProcessorTrait {
 }
 
 pub struct ProcessorReal {
-    target_ip: IpAddr
+    target_ip: IpAddr,
+    // AAAA questions are answered with this if there is one, and with nothing at all otherwise
+    target_ipv6_opt: Option<Ipv6Addr>,
+    // Questions other than the A records it redirects go here, if there's somewhere for them to go
+    resolver_opt: Option<Box<ResolverTrait>>,
+    // If set, everything goes to the resolver and nothing is redirected
+    bypass: bool,
+    // Questions about names on this list are answered before anything else, and not helpfully
+    blocklist_opt: Option<Arc<RwLock<Blocklist>>>,
+    // Names the operator has pinned to addresses; these are answered before even the blocklist is consulted
+    overrides_opt: Option<Arc<RwLock<Overrides>>>,
+    // Every question and what became of it is written here, if the operator has asked for that
+    query_log_opt: Option<Arc<Mutex<QueryLog>>>
 }
 
 impl ProcessorReal {
-    pub fn new (target_ip: IpAddr) -> ProcessorReal {
-        ProcessorReal {target_ip}
+    pub fn new (target_ip: IpAddr, target_ipv6_opt: Option<Ipv6Addr>, resolver_opt: Option<Box<ResolverTrait>>, bypass: bool,
+            blocklist_opt: Option<Arc<RwLock<Blocklist>>>, overrides_opt: Option<Arc<RwLock<Overrides>>>,
+            query_log_opt: Option<Arc<Mutex<QueryLog>>>) -> ProcessorReal {
+        ProcessorReal {target_ip, target_ipv6_opt, resolver_opt, bypass, blocklist_opt, overrides_opt, query_log_opt}
     }
 }
 
 impl ProcessorTrait for ProcessorReal {
     fn process (&self, buf: &mut [u8], length: usize, addr: &SocketAddr, logger: &Logger) -> usize {
+        let query_log = match self.query_log_opt {
+            Some (ref query_log) => query_log,
+            None => return self.answer (buf, length, addr, logger).0
+        };
+        let timestamp = SystemTime::now ();
+        // The response may not repeat the questions, so they're noted beforehand
+        let queries = PacketFacade::new (buf, length).get_queries ().unwrap_or (vec! ());
+        let (result, how) = self.answer (buf, length, addr, logger);
+        let rcode = PacketFacade::new (buf, result).get_rcode ().unwrap_or (0xFF);
+        let extended_rcode = find_edns (&PacketFacade::new (buf, result)).ok ().and_then (|edns_opt| edns_opt)
+            .map (|edns| edns.extended_rcode).unwrap_or (0);
+        query_log.lock ().expect ("Query log poisoned").record (&timestamp, addr, &queries, (extended_rcode << 4) | rcode, how, logger);
+        result
+    }
+}
+
+impl ProcessorReal {
+    // The length of the response, and how it was arrived at
+    fn answer (&self, buf: &mut [u8], length: usize, addr: &SocketAddr, logger: &Logger) -> (usize, &'static str) {
+        if let Some (ref overrides) = self.overrides_opt {
+            let addresses_opt = ProcessorReal::overridden_addresses (&overrides.read ().expect ("Overrides poisoned"), buf, length);
+            if let Some (addresses) = addresses_opt {
+                return (ProcessorReal::answer_overridden (addresses, buf, length, addr, logger), "overridden")
+            }
+        }
+        if let Some (ref blocklist) = self.blocklist_opt {
+            let mode_opt = ProcessorReal::blocked_mode (&blocklist.read ().expect ("Blocklist poisoned"), buf, length);
+            if let Some (mode) = mode_opt {
+                return (ProcessorReal::answer_blocked (mode, buf, length, addr, logger), "blocked")
+            }
+        }
+        if let Some (ref resolver) = self.resolver_opt {
+            if self.bypass || ProcessorReal::should_pass_through (buf, length) {
+                return (ProcessorReal::pass_through (resolver.as_ref (), buf, length, addr, logger), "passed through")
+            }
+        }
         let mut facade = PacketFacade::new(buf, length);
         let request_record = RequestRecord {
             timestamp: Instant::now (),
             opcode: facade.get_opcode ().unwrap_or (0xFF),
             queries: facade.get_queries ().unwrap_or (vec![])
         };
-        let result: usize;
+        let mut result: usize;
+        // Whatever the answer, if the request had an OPT record, the response gets one too
+        let mut edns_opt: Option<Edns> = None;
+        let mut extended_rcode: u8 = 0;
         loop {
+            edns_opt = match find_edns (&facade) {
+                Ok (edns_opt) => edns_opt,
+                Err (_) => {result = ProcessorReal::make_format_error(&mut facade); break}
+            };
             if facade.get_opcode().expect("The provided buffer must have more than 0 bytes") != 0x0 {
                 result = ProcessorReal::make_not_implemented_error(&mut facade);
                 break;
@@ -42,37 +114,187 @@ impl ProcessorTrait for ProcessorReal {
                 facade.set_recursion_available(true) &&
                 facade.set_authenticated_data(false) &&
                 facade.set_checking_disabled(false);
-            if !success { result = ProcessorReal::make_format_error(&mut facade); break };
+            if !success || !facade.remove_additionals() { result = ProcessorReal::make_format_error(&mut facade); break };
+            if edns_opt.as_ref ().map (|edns| edns.version > EDNS_VERSION).unwrap_or (false) {
+                result = ProcessorReal::make_bad_version_error(&mut facade);
+                extended_rcode = EXTENDED_RCODE_BADVERS;
+                break
+            }
             let queries = match facade.get_queries() {
                 None => {result = ProcessorReal::make_format_error(&mut facade); break },
                 Some(q) => q
             };
+            if queries.iter ().any (|query| (query.get_query_class() != 0x0001)
+                    || ((query.get_query_type() != 0x0001) && (query.get_query_type() != 0x001C))) {
+                result = ProcessorReal::make_not_implemented_error(&mut facade);
+                break
+            }
             for query in queries {
-                if query.get_query_type() != 0x0001 { return ProcessorReal::make_not_implemented_error(&mut facade) }
-                if query.get_query_class() != 0x0001 { return ProcessorReal::make_not_implemented_error(&mut facade) }
-                let octets = match self.target_ip {
-                    IpAddr::V4 (ipv4) => ipv4.octets (),
-                    // crashpoint - make a card
-                    IpAddr::V6 (_ipv6) => unimplemented!()
-                };
-                facade.add_answer(&query.get_query_name(), 0x0001, 0x0001, 3600, &octets);
+                match query.get_query_type() {
+                    0x0001 => {
+                        let octets = match self.target_ip {
+                            IpAddr::V4 (ipv4) => ipv4.octets (),
+                            // crashpoint - make a card
+                            IpAddr::V6 (_ipv6) => unimplemented!()
+                        };
+                        facade.add_answer(&query.get_query_name(), 0x0001, 0x0001, 3600, &octets);
+                    },
+                    _ => if let Some (ipv6) = self.target_ipv6_opt {
+                        facade.add_answer(&query.get_query_name(), 0x001C, 0x0001, 3600, &ipv6.octets ());
+                    }
+                }
             }
 
             result = facade.get_length();
             break;
         }
+        if let Some (ref edns) = edns_opt {
+            if edns.response (extended_rcode).add_to (&mut facade) {result = facade.get_length ()}
+        }
         let latency = request_record.timestamp.elapsed ();
         let response_record = ResponseRecord {
             latency_ns: ((latency.as_secs () as u64) * 1000000000) + (latency.subsec_nanos() as u64),
-            rcode: facade.get_rcode ().unwrap_or (0xFF),
+            rcode: facade.get_rcode ().map (|rcode| (extended_rcode << 4) | rcode).unwrap_or (0xFF),
             answers: facade.get_answers ().unwrap_or (vec![])
         };
         ProcessorReal::write_log (&request_record, &response_record, addr, logger);
-        return result;
+        (result, "local")
+    }
+
+    // Only standard queries are blocked; anything else is left for the rest of the processor to judge
+    fn blocked_mode (blocklist: &Blocklist, buf: &mut [u8], length: usize) -> Option<BlockMode> {
+        let facade = PacketFacade::new (buf, length);
+        if facade.get_opcode () != Some (0x0) {return None}
+        let queries = try_opt! (facade.get_queries ());
+        if queries.iter ().any (|query| blocklist.is_blocked (query.get_query_name ())) {Some (blocklist.mode)} else {None}
+    }
+
+    fn answer_blocked (mode: BlockMode, buf: &mut [u8], length: usize, addr: &SocketAddr, logger: &Logger) -> usize {
+        ProcessorReal::answer_directly (buf, length, addr, logger, "blocked", |facade, queries| {
+            match mode {
+                BlockMode::NxDomain => {facade.set_rcode (0x3);},
+                BlockMode::NullAddress => {
+                    facade.set_rcode (0x0);
+                    for query in queries.iter () {
+                        match (query.get_query_type (), query.get_query_class ()) {
+                            (0x0001, 0x0001) => {facade.add_answer (query.get_query_name (), 0x0001, 0x0001, 3600, &[0; 4]);},
+                            (0x001C, 0x0001) => {facade.add_answer (query.get_query_name (), 0x001C, 0x0001, 3600, &[0; 16]);},
+                            _ => ()
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    // The addresses for each query in a standard request, but only if every name in it is overridden
+    fn overridden_addresses (overrides: &Overrides, buf: &mut [u8], length: usize) -> Option<Vec<Vec<IpAddr>>> {
+        let facade = PacketFacade::new (buf, length);
+        if facade.get_opcode () != Some (0x0) {return None}
+        let queries = try_opt! (facade.get_queries ());
+        if queries.is_empty () {return None}
+        queries.iter ().map (|query| {
+            if query.get_query_class () != 0x0001 {return None}
+            overrides.lookup (query.get_query_name ()).cloned ()
+        }).collect ()
+    }
+
+    // An overridden name has the records it's been given and no others: A and AAAA questions get the
+    // addresses of their family, and anything else gets an empty answer
+    fn answer_overridden (addresses: Vec<Vec<IpAddr>>, buf: &mut [u8], length: usize, addr: &SocketAddr, logger: &Logger) -> usize {
+        ProcessorReal::answer_directly (buf, length, addr, logger, "overridden", |facade, queries| {
+            facade.set_rcode (0x0);
+            for (query, ip_addrs) in queries.iter ().zip (addresses.iter ()) {
+                for ip_addr in ip_addrs {
+                    match (query.get_query_type (), ip_addr) {
+                        (0x0001, &IpAddr::V4 (ref ipv4)) => {facade.add_answer (query.get_query_name (), 0x0001, 0x0001, OVERRIDE_TTL, &ipv4.octets ());},
+                        (0x001C, &IpAddr::V6 (ref ipv6)) => {facade.add_answer (query.get_query_name (), 0x001C, 0x0001, OVERRIDE_TTL, &ipv6.octets ());},
+                        _ => ()
+                    }
+                }
+            }
+        })
+    }
+
+    // Turns the request into a response, lets the caller answer it, and echoes any OPT record
+    fn answer_directly<F> (buf: &mut [u8], length: usize, addr: &SocketAddr, logger: &Logger, note: &str, answer: F) -> usize
+            where F: FnOnce (&mut PacketFacade, &[Query]) {
+        let timestamp = Instant::now ();
+        let mut facade = PacketFacade::new (buf, length);
+        let queries = facade.get_queries ().expect ("Internal error");
+        let edns_opt = find_edns (&facade).unwrap_or (None);
+        facade.set_query (false);
+        facade.set_authoritative_answer (false);
+        facade.set_truncated (false);
+        facade.set_recursion_available (true);
+        facade.set_authenticated_data (false);
+        facade.set_checking_disabled (false);
+        facade.remove_additionals ();
+        answer (&mut facade, &queries);
+        if let Some (edns) = edns_opt {
+            edns.response (0).add_to (&mut facade);
+        }
+        let latency = timestamp.elapsed ();
+        let query_list = queries.iter ()
+            .map (|query| format! ("{}/{}/{}", query.get_query_type (), query.get_query_class (), query.get_query_name ()))
+            .collect::<Vec<String>> ().join (", ");
+        logger.debug (format! ("{}ns: {} RQ0 ({}) -> RS{:X} ({})",
+            ((latency.as_secs () as u64) * 1000000000) + (latency.subsec_nanos () as u64), addr, query_list,
+            facade.get_rcode ().unwrap_or (0xFF), note));
+        facade.get_length ()
+    }
+
+    fn should_pass_through (buf: &mut [u8], length: usize) -> bool {
+        let facade = PacketFacade::new (buf, length);
+        if facade.get_opcode () != Some (0x0) {return false}
+        match facade.get_queries () {
+            Some (ref queries) if queries.len () == 1 => {
+                let qtype = queries[0].get_query_type ();
+                ((qtype != 0x0001) && (qtype != 0x001C)) || (queries[0].get_query_class () != 0x0001)
+            },
+            _ => false
+        }
+    }
+
+    fn pass_through (resolver: &ResolverTrait, buf: &mut [u8], length: usize, addr: &SocketAddr, logger: &Logger) -> usize {
+        let timestamp = Instant::now ();
+        let (opcode, query_list) = {
+            let facade = PacketFacade::new (buf, length);
+            let query_list = facade.get_queries ().unwrap_or (vec! ()).iter ()
+                .map (|query| format! ("{}/{}/{}", query.get_query_type (), query.get_query_class (), query.get_query_name ()))
+                .collect::<Vec<String>> ().join (", ");
+            (facade.get_opcode ().unwrap_or (0xFF), query_list)
+        };
+        let result = match resolver.resolve (&buf[..length]) {
+            Ok (ref response) if (response.len () >= 12) && (response.len () <= buf.len ()) => {
+                buf[..response.len ()].copy_from_slice (&response[..]);
+                response.len ()
+            },
+            Ok (_) => ProcessorReal::make_server_failure (&mut PacketFacade::new (buf, length)),
+            Err (e) => {
+                logger.warning (format! ("Couldn't pass {} through: {}", query_list, e));
+                ProcessorReal::make_server_failure (&mut PacketFacade::new (buf, length))
+            }
+        };
+        let latency = timestamp.elapsed ();
+        let rcode = PacketFacade::new (buf, result).get_rcode ().unwrap_or (0xFF);
+        logger.debug (format! ("{}ns: {} RQ{:X} ({}) -> RS{:X} (passed through)",
+            ((latency.as_secs () as u64) * 1000000000) + (latency.subsec_nanos () as u64), addr, opcode, query_list, rcode));
+        result
+    }
+
+    fn make_server_failure (facade: &mut PacketFacade) -> usize {
+        facade.set_query (false);
+        facade.set_authoritative_answer (false);
+        facade.set_truncated (false);
+        facade.set_recursion_available (true);
+        facade.set_authenticated_data (false);
+        facade.set_checking_disabled (false);
+        facade.set_rcode (0x2);
+        facade.clear();
+        return 12
     }
-}
 
-impl ProcessorReal {
     fn make_format_error (facade: &mut PacketFacade) -> usize {
         facade.set_query (false);
         facade.set_authoritative_answer (false);
@@ -85,6 +307,19 @@ impl ProcessorReal {
         return 12
     }
 
+    // The header's RCODE is NOERROR; it's the OPT record that says BADVERS
+    fn make_bad_version_error (facade: &mut PacketFacade) -> usize {
+        facade.set_query (false);
+        facade.set_authoritative_answer (false);
+        facade.set_truncated (false);
+        facade.set_recursion_available (true);
+        facade.set_authenticated_data (false);
+        facade.set_checking_disabled (false);
+        facade.set_rcode (0x0);
+        facade.clear();
+        return 12
+    }
+
     fn make_not_implemented_error (facade: &mut PacketFacade) -> usize {
         facade.set_query (false);
         facade.set_authoritative_answer (false);
@@ -97,6 +332,8 @@ impl ProcessorReal {
         return 12
     }
 
+    // Every query, with the full address of whoever asked it, is only for debugging; what's kept at
+    // other levels is the query log, which masks or leaves out clients as the user chooses
     fn write_log (from: &RequestRecord, to: &ResponseRecord, addr: &SocketAddr, logger: &Logger) {
         let mut query_list = String::new ();
         for query in from.queries.as_slice () {
@@ -107,10 +344,17 @@ impl ProcessorReal {
         for answer in to.answers.as_slice () {
             if !answer_list.is_empty () {answer_list += ", "}
             let rdata = answer.get_rdata ();
-            // TODO: What if there aren't four elements in this array?
-            answer_list += &format! ("{}.{}.{}.{}", rdata[0], rdata[1], rdata[2], rdata[3])
+            answer_list += &match rdata.len () {
+                4 => format! ("{}.{}.{}.{}", rdata[0], rdata[1], rdata[2], rdata[3]),
+                16 => {
+                    let mut octets: [u8; 16] = [0; 16];
+                    octets.copy_from_slice (rdata);
+                    format! ("{}", Ipv6Addr::from (octets))
+                },
+                _ => format! ("{} bytes", rdata.len ())
+            }
         }
-        logger.info(format! ("{}ns: {} RQ{:X} ({}) -> RS{:X} ({})",
+        logger.debug(format! ("{}ns: {} RQ{:X} ({}) -> RS{:X} ({})",
             to.latency_ns, addr, from.opcode, &query_list, to.rcode, &answer_list));
     }
 }
@@ -134,6 +378,7 @@ mod tests {
     use std::net::SocketAddrV4;
     use std::net::Ipv4Addr;
     use std::net::IpAddr;
+    use std::net::Ipv6Addr;
     use std::str::FromStr;
     use packet_facade::PacketFacade;
     use packet_facade::Query;
@@ -145,6 +390,25 @@ mod tests {
     use processor::ProcessorReal;
     use processor::RequestRecord;
     use processor::ResponseRecord;
+    use resolver::ResolverTrait;
+    use edns::Edns;
+    use edns::find_edns;
+    use edns::RR_TYPE_OPT;
+    use edns::MAX_EDNS_PAYLOAD_SIZE;
+    use blocklist::Blocklist;
+    use blocklist::BlockMode;
+    use blocklist::parse_domains;
+    use overrides::parse_inline;
+    use query_log::QueryLog;
+    use query_log::ClientPrivacy;
+    use std::env::temp_dir;
+    use std::fs;
+    use std::fs::File;
+    use std::io::Read;
+    use std::sync::RwLock;
+    use std::cell::RefCell;
+    use std::sync::Arc;
+    use std::sync::Mutex;
 
     #[test]
     fn returns_format_error_if_queries_overrun () {
@@ -163,7 +427,7 @@ mod tests {
         let truncated_length = correct_length - 1;
         let truncated_buf = &mut correct_buf[0..truncated_length];
         let addr = SocketAddr::from (SocketAddrV4::new(Ipv4Addr::new(101, 102, 103, 104), 53));
-        let subject = ProcessorReal::new (IpAddr::from_str ("123.124.125.126").unwrap ());
+        let subject = ProcessorReal::new (IpAddr::from_str ("123.124.125.126").unwrap (), None, None, false, None, None, None);
 
         let result = subject.process(truncated_buf, truncated_length, &addr,
             &Logger::new (""));
@@ -186,7 +450,7 @@ mod tests {
             facade.get_length ()
         };
         let addr = SocketAddr::from (SocketAddrV4::new(Ipv4Addr::new(101, 102, 103, 104), 53));
-        let subject = ProcessorReal::new (IpAddr::from_str ("18.52.86.120").unwrap ());
+        let subject = ProcessorReal::new (IpAddr::from_str ("18.52.86.120").unwrap (), None, None, false, None, None, None);
 
         let rsp_length = subject.process (&mut buf, req_length, &addr,
             &Logger::new (""));
@@ -209,7 +473,7 @@ mod tests {
             facade.get_length ()
         };
         let addr = SocketAddr::from (SocketAddrV4::new(Ipv4Addr::new(101, 102, 103, 104), 53));
-        let subject = ProcessorReal::new (IpAddr::from_str ("18.52.86.120").unwrap ());
+        let subject = ProcessorReal::new (IpAddr::from_str ("18.52.86.120").unwrap (), None, None, false, None, None, None);
 
         let rsp_length = subject.process (&mut buf, req_length, &addr,
             &Logger::new (""));
@@ -232,7 +496,7 @@ mod tests {
             facade.get_length ()
         };
         let addr = SocketAddr::from (SocketAddrV4::new(Ipv4Addr::new(101, 102, 103, 104), 53));
-        let subject = ProcessorReal::new (IpAddr::from_str ("18.52.86.120").unwrap ());
+        let subject = ProcessorReal::new (IpAddr::from_str ("18.52.86.120").unwrap (), None, None, false, None, None, None);
 
         let rsp_length = subject.process (&mut buf, req_length, &addr, &Logger::new (""));
 
@@ -240,6 +504,90 @@ mod tests {
         assert_eq! (rsp_length, 12);
     }
 
+    struct ResolverMock {
+        requests: Arc<Mutex<Vec<Vec<u8>>>>,
+        results: RefCell<Vec<Result<Vec<u8>, String>>>,
+    }
+
+    impl ResolverTrait for ResolverMock {
+        fn resolve (&self, request: &[u8]) -> Result<Vec<u8>, String> {
+            self.requests.lock ().unwrap ().push (request.to_vec ());
+            self.results.borrow_mut ().remove (0)
+        }
+    }
+
+    fn make_mx_request (buf: &mut [u8]) -> usize {
+        let mut facade = PacketFacade::new (buf, 12);
+        facade.set_transaction_id (0x1234);
+        facade.set_query (true);
+        facade.set_opcode (0x0);
+        facade.add_query ("name", 0x000F, 0x0001);
+        facade.get_length ()
+    }
+
+    #[test]
+    fn questions_other_than_for_a_records_are_passed_through_when_there_is_a_resolver () {
+        init_test_logging ();
+        let mut response: [u8; 100] = [0; 100];
+        let response_length = {
+            let mut facade = PacketFacade::new (&mut response, 12);
+            facade.set_transaction_id (0x1234);
+            facade.set_query (false);
+            facade.add_query ("name", 0x000F, 0x0001);
+            facade.add_answer ("name", 0x000F, 0x0001, 300, &[0, 10, 0xC0, 0x0C]);
+            facade.get_length ()
+        };
+        let requests = Arc::new (Mutex::new (vec! ()));
+        let resolver = ResolverMock {requests: requests.clone (), results: RefCell::new (vec! (
+            Ok (response[..response_length].to_vec ()),
+            Err (String::from ("Upstream is down")),
+        ))};
+        let subject = ProcessorReal::new (IpAddr::from_str ("18.52.86.120").unwrap (), None, Some (Box::new (resolver)), false, None, None, None);
+        let addr = SocketAddr::from (SocketAddrV4::new (Ipv4Addr::new (101, 102, 103, 104), 53));
+        let mut buf: [u8; 500] = [0; 500];
+        let req_length = make_mx_request (&mut buf);
+        let request = buf[..req_length].to_vec ();
+
+        let rsp_length = subject.process (&mut buf, req_length, &addr, &Logger::new ("passed_through"));
+
+        assert_eq! (&buf[..rsp_length], &response[..response_length]);
+        assert_eq! (*requests.lock ().unwrap (), vec! (request));
+
+        let mut buf: [u8; 500] = [0; 500];
+        let req_length = make_mx_request (&mut buf);
+
+        let rsp_length = subject.process (&mut buf, req_length, &addr, &Logger::new ("passed_through"));
+
+        assert_eq! (rsp_length, 12);
+        assert_eq! (PacketFacade::new (&mut buf, rsp_length).get_rcode (), Some (0x2));
+        let tlh = TestLogHandler::new ();
+        tlh.exists_log_containing ("101.102.103.104:53 RQ0 (15/1/name) -> RS0 (passed through)");
+        tlh.exists_log_containing ("WARN: passed_through: Couldn't pass 15/1/name through: Upstream is down");
+        tlh.exists_log_containing ("101.102.103.104:53 RQ0 (15/1/name) -> RS2 (passed through)");
+    }
+
+    #[test]
+    fn in_bypass_mode_even_a_records_are_passed_through () {
+        let requests = Arc::new (Mutex::new (vec! ()));
+        let resolver = ResolverMock {requests: requests.clone (), results: RefCell::new (vec! (Err (String::from ("Upstream is down"))))};
+        let subject = ProcessorReal::new (IpAddr::from_str ("18.52.86.120").unwrap (), None, Some (Box::new (resolver)), true, None, None, None);
+        let addr = SocketAddr::from (SocketAddrV4::new (Ipv4Addr::new (101, 102, 103, 104), 53));
+        let mut buf: [u8; 500] = [0; 500];
+        let req_length = {
+            let mut facade = PacketFacade::new (&mut buf, 12);
+            facade.set_transaction_id (0x1234);
+            facade.set_query (true);
+            facade.add_query ("name", 0x0001, 0x0001);
+            facade.get_length ()
+        };
+
+        let rsp_length = subject.process (&mut buf, req_length, &addr, &Logger::new ("bypass"));
+
+        assert_eq! (requests.lock ().unwrap ().len (), 1);
+        assert_eq! (rsp_length, 12);
+        assert_eq! (PacketFacade::new (&mut buf, rsp_length).get_rcode (), Some (0x2));
+    }
+
     #[test]
     fn two_queries_are_answered () {
         init_test_logging();
@@ -258,7 +606,7 @@ mod tests {
         };
         let addr = SocketAddr::from (SocketAddrV4::new(Ipv4Addr::new(101, 102, 103, 104), 53));
         let rsp_length = {
-            let subject = ProcessorReal::new (IpAddr::from_str ("18.52.86.120").unwrap ());
+            let subject = ProcessorReal::new (IpAddr::from_str ("18.52.86.120").unwrap (), None, None, false, None, None, None);
 
             subject.process(&mut buf, req_length, &addr, &Logger::new ("two_queries_are_answered"))
         };
@@ -305,6 +653,228 @@ mod tests {
         tlh.exists_log_containing ("101.102.103.104:53 RQ0 (1/1/ooga.com, 1/1/booga.com) -> RS0 (18.52.86.120, 18.52.86.120)");
     }
 
+    #[test]
+    fn opt_records_are_answered_in_kind_and_unknown_edns_versions_are_refused () {
+        init_test_logging();
+        let make_request = |buf: &mut [u8], ttl: u32| {
+            let mut request = PacketFacade::new(buf, 12);
+            request.set_transaction_id(0x4321);
+            request.set_query(true);
+            request.add_query("ooga.com", 0x0001, 0x0001);
+            request.add_additional("", RR_TYPE_OPT, 4096, ttl, &[]);
+            request.get_length ()
+        };
+        let addr = SocketAddr::from (SocketAddrV4::new(Ipv4Addr::new(101, 102, 103, 104), 53));
+        let subject = ProcessorReal::new (IpAddr::from_str ("18.52.86.120").unwrap (), None, None, false, None, None, None);
+        let mut v0_buf: [u8; 500] = [0; 500];
+        let mut v1_buf: [u8; 500] = [0; 500];
+        let v0_req_length = make_request (&mut v0_buf[..], 0x00008000);
+        let v1_req_length = make_request (&mut v1_buf[..], 0x00010000);
+
+        let v0_rsp_length = subject.process (&mut v0_buf, v0_req_length, &addr, &Logger::new ("edns"));
+        let v1_rsp_length = subject.process (&mut v1_buf, v1_req_length, &addr, &Logger::new ("edns"));
+
+        {
+            let response = PacketFacade::new (&mut v0_buf, v0_rsp_length);
+            assert_eq! (response.get_rcode (), Some (0x0));
+            assert_eq! (response.get_answers ().unwrap ().len (), 1);
+            assert_eq! (find_edns (&response), Ok (Some (Edns {udp_payload_size: MAX_EDNS_PAYLOAD_SIZE, extended_rcode: 0, version: 0, dnssec_ok: true})));
+            assert_eq! (response.get_additionals ().unwrap ().len (), 1);
+        }
+        {
+            let response = PacketFacade::new (&mut v1_buf, v1_rsp_length);
+            assert_eq! (response.get_rcode (), Some (0x0));
+            assert_eq! (response.get_queries ().unwrap ().len (), 0);
+            assert_eq! (response.get_answers ().unwrap ().len (), 0);
+            assert_eq! (find_edns (&response), Ok (Some (Edns {udp_payload_size: MAX_EDNS_PAYLOAD_SIZE, extended_rcode: 1, version: 0, dnssec_ok: false})));
+        }
+        TestLogHandler::new ().exists_log_containing ("101.102.103.104:53 RQ0 (1/1/ooga.com) -> RS10 ()");
+    }
+
+    #[test]
+    fn blocked_names_are_answered_according_to_the_block_mode_even_in_bypass_mode () {
+        init_test_logging();
+        let make_request = |buf: &mut [u8], name: &str, qtype: u16| {
+            let mut request = PacketFacade::new(buf, 12);
+            request.set_transaction_id(0x4321);
+            request.set_query(true);
+            request.add_query(name, qtype, 0x0001);
+            request.get_length ()
+        };
+        let make_subject = |mode: BlockMode| {
+            let mut blocklist = Blocklist::new (mode);
+            blocklist.set_domains (parse_domains ("ads.example.com"));
+            let resolver = ResolverMock {requests: Arc::new (Mutex::new (vec! ())), results: RefCell::new (vec! ())};
+            ProcessorReal::new (IpAddr::from_str ("18.52.86.120").unwrap (), None, Some (Box::new (resolver)), true,
+                Some (Arc::new (RwLock::new (blocklist))), None, None)
+        };
+        let addr = SocketAddr::from (SocketAddrV4::new(Ipv4Addr::new(101, 102, 103, 104), 53));
+        let mut nx_buf: [u8; 500] = [0; 500];
+        let mut null_a_buf: [u8; 500] = [0; 500];
+        let mut null_aaaa_buf: [u8; 500] = [0; 500];
+        let nx_req_length = make_request (&mut nx_buf[..], "eu.ads.example.com", 0x0001);
+        let null_a_req_length = make_request (&mut null_a_buf[..], "ads.example.com", 0x0001);
+        let null_aaaa_req_length = make_request (&mut null_aaaa_buf[..], "ads.example.com", 0x001C);
+
+        let nx_rsp_length = make_subject (BlockMode::NxDomain).process (&mut nx_buf, nx_req_length, &addr, &Logger::new ("blocked"));
+        let null_subject = make_subject (BlockMode::NullAddress);
+        let null_a_rsp_length = null_subject.process (&mut null_a_buf, null_a_req_length, &addr, &Logger::new ("blocked"));
+        let null_aaaa_rsp_length = null_subject.process (&mut null_aaaa_buf, null_aaaa_req_length, &addr, &Logger::new ("blocked"));
+
+        {
+            let response = PacketFacade::new (&mut nx_buf, nx_rsp_length);
+            assert_eq! (response.is_query (), Some (false));
+            assert_eq! (response.get_rcode (), Some (0x3));
+            assert_eq! (response.get_answers ().unwrap ().len (), 0);
+        }
+        {
+            let response = PacketFacade::new (&mut null_a_buf, null_a_rsp_length);
+            assert_eq! (response.get_rcode (), Some (0x0));
+            assert_eq! (response.get_answers ().unwrap ()[0].get_rdata (), &[0u8; 4][..]);
+        }
+        {
+            let response = PacketFacade::new (&mut null_aaaa_buf, null_aaaa_rsp_length);
+            assert_eq! (response.get_answers ().unwrap ()[0].get_rdata (), &[0u8; 16][..]);
+        }
+        TestLogHandler::new ().exists_log_containing ("101.102.103.104:53 RQ0 (1/1/eu.ads.example.com) -> RS3 (blocked)");
+    }
+
+    #[test]
+    fn overridden_names_are_answered_with_their_own_addresses_before_the_blocklist_and_the_resolver () {
+        init_test_logging();
+        let make_request = |buf: &mut [u8], name: &str, qtype: u16| {
+            let mut request = PacketFacade::new(buf, 12);
+            request.set_transaction_id(0x4321);
+            request.set_query(true);
+            request.add_query(name, qtype, 0x0001);
+            request.get_length ()
+        };
+        let mut blocklist = Blocklist::new (BlockMode::NxDomain);
+        blocklist.set_domains (parse_domains ("lab"));
+        let requests = Arc::new (Mutex::new (vec! ()));
+        let resolver = ResolverMock {requests: requests.clone (), results: RefCell::new (vec! ())};
+        let overrides = parse_inline ("db.lab=10.0.0.5,DB.lab=10.0.0.6,db.lab=fd00::5").unwrap ();
+        let subject = ProcessorReal::new (IpAddr::from_str ("18.52.86.120").unwrap (), None, Some (Box::new (resolver)), true,
+            Some (Arc::new (RwLock::new (blocklist))), Some (Arc::new (RwLock::new (overrides))), None);
+        let addr = SocketAddr::from (SocketAddrV4::new(Ipv4Addr::new(101, 102, 103, 104), 53));
+        let mut a_buf: [u8; 500] = [0; 500];
+        let mut aaaa_buf: [u8; 500] = [0; 500];
+        let mut mx_buf: [u8; 500] = [0; 500];
+        let mut other_buf: [u8; 500] = [0; 500];
+        let a_req_length = make_request (&mut a_buf[..], "db.lab", 0x0001);
+        let aaaa_req_length = make_request (&mut aaaa_buf[..], "DB.LAB", 0x001C);
+        let mx_req_length = make_request (&mut mx_buf[..], "db.lab", 0x000F);
+        let other_req_length = make_request (&mut other_buf[..], "www.db.lab", 0x0001);
+
+        let a_rsp_length = subject.process (&mut a_buf, a_req_length, &addr, &Logger::new ("overridden"));
+        let aaaa_rsp_length = subject.process (&mut aaaa_buf, aaaa_req_length, &addr, &Logger::new ("overridden"));
+        let mx_rsp_length = subject.process (&mut mx_buf, mx_req_length, &addr, &Logger::new ("overridden"));
+        let other_rsp_length = subject.process (&mut other_buf, other_req_length, &addr, &Logger::new ("overridden"));
+
+        {
+            let response = PacketFacade::new (&mut a_buf, a_rsp_length);
+            assert_eq! (response.is_query (), Some (false));
+            assert_eq! (response.get_rcode (), Some (0x0));
+            let answers = response.get_answers ().unwrap ();
+            assert_eq! (answers.len (), 2);
+            assert_eq! (answers[0].get_time_to_live (), 60);
+            assert_eq! (answers[0].get_rdata (), &[10u8, 0, 0, 5][..]);
+            assert_eq! (answers[1].get_rdata (), &[10u8, 0, 0, 6][..]);
+        }
+        {
+            let response = PacketFacade::new (&mut aaaa_buf, aaaa_rsp_length);
+            let answers = response.get_answers ().unwrap ();
+            assert_eq! (answers.len (), 1);
+            assert_eq! (answers[0].get_rdata (), &Ipv6Addr::from_str ("fd00::5").unwrap ().octets ()[..]);
+        }
+        {
+            let response = PacketFacade::new (&mut mx_buf, mx_rsp_length);
+            assert_eq! (response.get_rcode (), Some (0x0));
+            assert_eq! (response.get_answers ().unwrap ().len (), 0);
+        }
+        {
+            let response = PacketFacade::new (&mut other_buf, other_rsp_length);
+            assert_eq! (response.get_rcode (), Some (0x3));
+        }
+        assert_eq! (requests.lock ().unwrap ().len (), 0);
+        TestLogHandler::new ().exists_log_containing ("101.102.103.104:53 RQ0 (1/1/db.lab) -> RS0 (overridden)");
+    }
+
+    #[test]
+    fn questions_and_what_became_of_them_go_to_the_query_log_if_there_is_one () {
+        let directory = temp_dir ().join ("processor").join ("questions_and_what_became_of_them_go_to_the_query_log_if_there_is_one");
+        let _ = fs::remove_dir_all (&directory);
+        fs::create_dir_all (&directory).unwrap ();
+        let path = directory.join ("queries.log");
+        let make_request = |buf: &mut [u8], name: &str| {
+            let mut request = PacketFacade::new(buf, 12);
+            request.set_transaction_id(0x4321);
+            request.set_query(true);
+            request.add_query(name, 0x0001, 0x0001);
+            request.get_length ()
+        };
+        let mut blocklist = Blocklist::new (BlockMode::NxDomain);
+        blocklist.set_domains (parse_domains ("ads.example.com"));
+        let query_log = QueryLog::new (path.clone (), 1000000, ClientPrivacy::Full);
+        let subject = ProcessorReal::new (IpAddr::from_str ("18.52.86.120").unwrap (), None, None, false,
+            Some (Arc::new (RwLock::new (blocklist))), None, Some (Arc::new (Mutex::new (query_log))));
+        let addr = SocketAddr::from (SocketAddrV4::new(Ipv4Addr::new(101, 102, 103, 104), 53));
+        let mut local_buf: [u8; 500] = [0; 500];
+        let mut blocked_buf: [u8; 500] = [0; 500];
+        let local_req_length = make_request (&mut local_buf[..], "ooga.com");
+        let blocked_req_length = make_request (&mut blocked_buf[..], "ads.example.com");
+
+        subject.process (&mut local_buf, local_req_length, &addr, &Logger::new ("query_log"));
+        subject.process (&mut blocked_buf, blocked_req_length, &addr, &Logger::new ("query_log"));
+
+        let mut text = String::new ();
+        File::open (&path).unwrap ().read_to_string (&mut text).unwrap ();
+        let lines: Vec<&str> = text.lines ().collect ();
+        assert_eq! (lines.len (), 2);
+        assert_eq! (lines[0].ends_with (" 101.102.103.104 ooga.com A -> NOERROR (local)"), true);
+        assert_eq! (lines[1].ends_with (" 101.102.103.104 ads.example.com A -> NXDOMAIN (blocked)"), true);
+    }
+
+    #[test]
+    fn aaaa_queries_are_answered_with_the_ipv6_target_or_with_nothing_without_one () {
+        init_test_logging();
+        let make_request = |buf: &mut [u8]| {
+            let mut request = PacketFacade::new(buf, 12);
+            request.set_transaction_id(0x4321);
+            request.set_query(true);
+            request.set_opcode(0x0);
+            request.add_query("ooga.com", 0x001C, 0x0001);
+            request.get_length ()
+        };
+        let addr = SocketAddr::from (SocketAddrV4::new(Ipv4Addr::new(101, 102, 103, 104), 53));
+        let with_ipv6 = ProcessorReal::new (IpAddr::from_str ("18.52.86.120").unwrap (),
+            Some (Ipv6Addr::from_str ("2001:db8::1234").unwrap ()), None, false, None, None, None);
+        let without_ipv6 = ProcessorReal::new (IpAddr::from_str ("18.52.86.120").unwrap (), None, None, false, None, None, None);
+        let mut with_buf: [u8; 500] = [0; 500];
+        let mut without_buf: [u8; 500] = [0; 500];
+        let with_req_length = make_request (&mut with_buf[..]);
+        let without_req_length = make_request (&mut without_buf[..]);
+
+        let with_rsp_length = with_ipv6.process (&mut with_buf, with_req_length, &addr, &Logger::new ("aaaa"));
+        let without_rsp_length = without_ipv6.process (&mut without_buf, without_req_length, &addr, &Logger::new ("aaaa"));
+
+        {
+            let response = PacketFacade::new (&mut with_buf, with_rsp_length);
+            assert_eq! (response.get_rcode (), Some (0x0));
+            let answers = response.get_answers ().unwrap ();
+            assert_eq! (answers.len (), 1);
+            assert_eq! (answers[0].get_resource_type (), 0x001C);
+            assert_eq! (answers[0].get_time_to_live (), 3600);
+            assert_eq! (answers[0].get_rdata (), &Ipv6Addr::from_str ("2001:db8::1234").unwrap ().octets ()[..]);
+        }
+        {
+            let response = PacketFacade::new (&mut without_buf, without_rsp_length);
+            assert_eq! (response.get_rcode (), Some (0x0));
+            assert_eq! (response.get_answers ().unwrap ().len (), 0);
+        }
+        TestLogHandler::new ().exists_log_containing ("101.102.103.104:53 RQ0 (28/1/ooga.com) -> RS0 (2001:db8::1234)");
+    }
+
     #[test]
     fn write_log_produces_correct_text () {
         init_test_logging();
@@ -331,7 +901,7 @@ mod tests {
             ProcessorReal::write_log(&request_record, &response_record, &addr, &Logger::new("write_log_produces_correct_text"));
         }
 
-        TestLogHandler::new ().exists_log_containing("2345ns: 101.102.103.104:53 RQ2 (4660/9029/first, 13398/17767/second) -> RS3 (123.124.125.126, 124.125.126.127)");
+        TestLogHandler::new ().exists_log_containing("DEBUG: write_log_produces_correct_text: 2345ns: 101.102.103.104:53 RQ2 (4660/9029/first, 13398/17767/second) -> RS3 (123.124.125.126, 124.125.126.127)");
     }
 
     fn check_format_error_message (mut buf: &mut [u8], transaction_id: u16) {
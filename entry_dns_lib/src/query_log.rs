@@ -0,0 +1,229 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use sub_lib::logger::Logger;
+use packet_facade::Query;
+
+pub const DEFAULT_QUERY_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+// Besides the file being written, this many full ones are kept: name.1 is the newest, name.3 the oldest
+pub const QUERY_LOG_OLD_FILES: usize = 3;
+
+// How much of a client's address goes in the log
+#[derive (Clone, Copy, Debug, PartialEq)]
+pub enum ClientPrivacy {
+    Full,
+    // Enough to tell one network from another, but not one device from another: a /24 for IPv4, a /48 for IPv6
+    Masked,
+    Omitted,
+}
+
+// A record of who asked what, and what they were told, kept apart from the Node's own log and only
+// if the operator asks for it. No file grows past the size limit; when the current one would, it's
+// set aside and the oldest set-aside one is deleted.
+pub struct QueryLog {
+    path: PathBuf,
+    max_bytes: u64,
+    privacy: ClientPrivacy,
+    file_opt: Option<File>,
+    written: u64,
+    // so that a log that can't be written is only complained about when it starts failing
+    failing: bool,
+}
+
+impl QueryLog {
+    pub fn new (path: PathBuf, max_bytes: u64, privacy: ClientPrivacy) -> QueryLog {
+        QueryLog {path, max_bytes, privacy, file_opt: None, written: 0, failing: false}
+    }
+
+    // One line for each question: "<timestamp> <client> <name> <type> -> <rcode> (<how it was answered>)"
+    pub fn record (&mut self, timestamp: &SystemTime, client: &SocketAddr, queries: &[Query], rcode: u8, how: &str, logger: &Logger) {
+        let time = Logger::timestamp_as_string (timestamp);
+        let client = self.describe_client (client.ip ());
+        let text = if queries.is_empty () {
+            format! ("{} {} - - -> {} ({})\n", time, client, rcode_name (rcode), how)
+        } else {
+            queries.iter ().map (|query| format! ("{} {} {} {} -> {} ({})\n", time, client, query.get_query_name (),
+                type_name (query.get_query_type ()), rcode_name (rcode), how)).collect::<Vec<String>> ().concat ()
+        };
+        match self.write (text.as_bytes ()) {
+            Ok (()) => self.failing = false,
+            Err (e) => {
+                if !self.failing {logger.warning (format! ("Couldn't write query log {}: {}", self.path.display (), e))}
+                self.failing = true;
+                self.file_opt = None;
+            }
+        }
+    }
+
+    fn write (&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.file_opt.is_none () {
+            let file = OpenOptions::new ().create (true).append (true).open (&self.path)?;
+            self.written = file.metadata ()?.len ();
+            self.file_opt = Some (file);
+        }
+        if (self.written > 0) && ((self.written + bytes.len () as u64) > self.max_bytes) {
+            self.rotate ()?;
+        }
+        {
+            let file = self.file_opt.as_mut ().expect ("Query log disappeared");
+            file.write_all (bytes)?;
+        }
+        self.written += bytes.len () as u64;
+        Ok (())
+    }
+
+    fn rotate (&mut self) -> io::Result<()> {
+        self.file_opt = None;
+        let oldest = self.old_file (QUERY_LOG_OLD_FILES);
+        if oldest.exists () {fs::remove_file (&oldest)?}
+        for index in (1..QUERY_LOG_OLD_FILES).rev () {
+            let older = self.old_file (index);
+            if older.exists () {fs::rename (&older, self.old_file (index + 1))?}
+        }
+        fs::rename (&self.path, self.old_file (1))?;
+        self.file_opt = Some (OpenOptions::new ().create (true).write (true).truncate (true).open (&self.path)?);
+        self.written = 0;
+        Ok (())
+    }
+
+    fn old_file (&self, index: usize) -> PathBuf {
+        let mut name = self.path.file_name ().expect ("Query log path has no file name").to_os_string ();
+        name.push (format! (".{}", index));
+        self.path.with_file_name (name)
+    }
+
+    fn describe_client (&self, ip_addr: IpAddr) -> String {
+        match (self.privacy, ip_addr) {
+            (ClientPrivacy::Full, ip_addr) => format! ("{}", ip_addr),
+            (ClientPrivacy::Masked, IpAddr::V4 (ipv4)) => {
+                let octets = ipv4.octets ();
+                format! ("{}/24", Ipv4Addr::new (octets[0], octets[1], octets[2], 0))
+            },
+            (ClientPrivacy::Masked, IpAddr::V6 (ipv6)) => {
+                let segments = ipv6.segments ();
+                format! ("{}/48", Ipv6Addr::new (segments[0], segments[1], segments[2], 0, 0, 0, 0, 0))
+            },
+            (ClientPrivacy::Omitted, _) => String::from ("-"),
+        }
+    }
+}
+
+fn type_name (query_type: u16) -> String {
+    match query_type {
+        0x0001 => String::from ("A"),
+        0x0002 => String::from ("NS"),
+        0x0005 => String::from ("CNAME"),
+        0x0006 => String::from ("SOA"),
+        0x000C => String::from ("PTR"),
+        0x000F => String::from ("MX"),
+        0x0010 => String::from ("TXT"),
+        0x001C => String::from ("AAAA"),
+        0x0021 => String::from ("SRV"),
+        0x00FF => String::from ("ANY"),
+        other => format! ("TYPE{}", other)
+    }
+}
+
+fn rcode_name (rcode: u8) -> String {
+    match rcode {
+        0x0 => String::from ("NOERROR"),
+        0x1 => String::from ("FORMERR"),
+        0x2 => String::from ("SERVFAIL"),
+        0x3 => String::from ("NXDOMAIN"),
+        0x4 => String::from ("NOTIMP"),
+        0x5 => String::from ("REFUSED"),
+        0x10 => String::from ("BADVERS"),
+        other => format! ("RCODE{}", other)
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::io::Read;
+    use std::str::FromStr;
+    use std::time::Duration;
+    use std::time::UNIX_EPOCH;
+    use packet_facade::PacketFacade;
+
+    fn make_queries (names: &[&str], query_type: u16) -> Vec<Query> {
+        let mut buf: [u8; 500] = [0; 500];
+        let length = {
+            let mut facade = PacketFacade::new (&mut buf, 12);
+            for name in names {facade.add_query (name, query_type, 0x0001);}
+            facade.get_length ()
+        };
+        let facade = PacketFacade::new (&mut buf, length);
+        facade.get_queries ().unwrap ()
+    }
+
+    fn contents_of (path: &PathBuf) -> String {
+        let mut text = String::new ();
+        File::open (path).unwrap ().read_to_string (&mut text).unwrap ();
+        text
+    }
+
+    fn make_directory (test_name: &str) -> PathBuf {
+        let directory = temp_dir ().join ("query_log").join (test_name);
+        let _ = fs::remove_dir_all (&directory);
+        fs::create_dir_all (&directory).unwrap ();
+        directory
+    }
+
+    #[test]
+    fn each_question_gets_a_line_and_clients_are_described_as_discreetly_as_asked () {
+        let directory = make_directory ("each_question_gets_a_line_and_clients_are_described_as_discreetly_as_asked");
+        let path = directory.join ("queries.log");
+        let timestamp = UNIX_EPOCH + Duration::from_secs (1500000000);
+        let logger = Logger::new ("test");
+        let client_v4 = SocketAddr::from_str ("192.168.1.23:5353").unwrap ();
+        let client_v6 = SocketAddr::from_str ("[2001:db8:1234:5678::9]:5353").unwrap ();
+
+        let mut masked = QueryLog::new (path.clone (), DEFAULT_QUERY_LOG_MAX_BYTES, ClientPrivacy::Masked);
+        masked.record (&timestamp, &client_v4, &make_queries (&["example.com", "example.net"], 0x0001), 0x0, "local", &logger);
+        masked.record (&timestamp, &client_v6, &make_queries (&["example.org"], 0x001C), 0x3, "blocked", &logger);
+        let mut full = QueryLog::new (path.clone (), DEFAULT_QUERY_LOG_MAX_BYTES, ClientPrivacy::Full);
+        full.record (&timestamp, &client_v4, &make_queries (&["example.com"], 0x1234), 0x2, "passed through", &logger);
+        let mut omitted = QueryLog::new (path.clone (), DEFAULT_QUERY_LOG_MAX_BYTES, ClientPrivacy::Omitted);
+        omitted.record (&timestamp, &client_v4, &[], 0x1, "local", &logger);
+
+        assert_eq! (contents_of (&path), String::from (
+            "2017-07-14 02:40:00.000 192.168.1.0/24 example.com A -> NOERROR (local)\n\
+             2017-07-14 02:40:00.000 192.168.1.0/24 example.net A -> NOERROR (local)\n\
+             2017-07-14 02:40:00.000 2001:db8:1234::/48 example.org AAAA -> NXDOMAIN (blocked)\n\
+             2017-07-14 02:40:00.000 192.168.1.23 example.com TYPE4660 -> SERVFAIL (passed through)\n\
+             2017-07-14 02:40:00.000 - - - -> FORMERR (local)\n"));
+    }
+
+    #[test]
+    fn full_files_are_set_aside_and_only_a_few_are_kept () {
+        let directory = make_directory ("full_files_are_set_aside_and_only_a_few_are_kept");
+        let path = directory.join ("queries.log");
+        let timestamp = UNIX_EPOCH + Duration::from_secs (1500000000);
+        let logger = Logger::new ("test");
+        let client = SocketAddr::from_str ("192.168.1.23:5353").unwrap ();
+        // Each line is 68 bytes, so each file has room for one
+        let mut subject = QueryLog::new (path.clone (), 100, ClientPrivacy::Masked);
+
+        for name in &["one.com", "two.com", "thr.com", "fou.com", "fiv.com"] {
+            subject.record (&timestamp, &client, &make_queries (&[*name], 0x0001), 0x0, "local", &logger);
+        }
+
+        assert_eq! (contents_of (&path).contains ("fiv.com"), true);
+        assert_eq! (contents_of (&directory.join ("queries.log.1")).contains ("fou.com"), true);
+        assert_eq! (contents_of (&directory.join ("queries.log.2")).contains ("thr.com"), true);
+        assert_eq! (contents_of (&directory.join ("queries.log.3")).contains ("two.com"), true);
+        assert_eq! (directory.join ("queries.log.4").exists (), false);
+        assert_eq! (contents_of (&path).len (), 68);
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+use std::time::Instant;
+use packet_facade::PacketFacade;
+
+pub const DEFAULT_RATE_LIMIT_QPS: u64 = 100;
+// Beyond this many clients, new ones aren't limited until idle ones have been forgotten
+const MAX_TRACKED_CLIENTS: usize = 10000;
+// Tokens are counted in thousandths so that rates needn't be whole numbers of queries per millisecond
+const MILLITOKENS_PER_QUERY: u64 = 1000;
+
+#[derive (Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    Allow,
+    // Tell the client it's over the limit
+    Refuse,
+    // Say nothing. Most queries over the limit get this, so that a flood from spoofed addresses
+    // isn't reflected at its victims.
+    Drop,
+}
+
+// How a client fared during the time it was over the limit
+#[derive (Clone, Copy, Debug, PartialEq)]
+pub struct Episode {
+    pub client: IpAddr,
+    pub refused: u64,
+    pub dropped: u64,
+}
+
+struct Bucket {
+    millitokens: u64,
+    refilled_at: Instant,
+    last_refusal_opt: Option<Instant>,
+    refused: u64,
+    dropped: u64,
+}
+
+// A token bucket for each client address: each query takes a token, and tokens come back at the
+// configured rate, up to a burst of twice that. A client with no tokens left is refused once a
+// second, and otherwise ignored.
+pub struct RateLimiter {
+    queries_per_sec: u64,
+    buckets: HashMap<IpAddr, Bucket>,
+    refused_total: u64,
+    dropped_total: u64,
+}
+
+impl RateLimiter {
+    pub fn new (queries_per_sec: u64) -> RateLimiter {
+        RateLimiter {queries_per_sec, buckets: HashMap::new (), refused_total: 0, dropped_total: 0}
+    }
+
+    // Also returns the episode that just ended, if this query is the first to be allowed after some weren't
+    pub fn check (&mut self, client: IpAddr, now: Instant) -> (Verdict, Option<Episode>) {
+        let capacity = self.capacity ();
+        let rate = self.queries_per_sec;
+        if !self.buckets.contains_key (&client) {
+            if self.buckets.len () >= MAX_TRACKED_CLIENTS {self.forget_idle_clients (now)}
+            if self.buckets.len () >= MAX_TRACKED_CLIENTS {return (Verdict::Allow, None)}
+            self.buckets.insert (client, Bucket {millitokens: capacity, refilled_at: now, last_refusal_opt: None, refused: 0, dropped: 0});
+        }
+        let result = {
+            let bucket = self.buckets.get_mut (&client).expect ("Bucket disappeared");
+            refill (bucket, rate, capacity, now);
+            if bucket.millitokens >= MILLITOKENS_PER_QUERY {
+                bucket.millitokens -= MILLITOKENS_PER_QUERY;
+                let episode_opt = if (bucket.refused + bucket.dropped) > 0 {
+                    Some (Episode {client, refused: bucket.refused, dropped: bucket.dropped})
+                } else {None};
+                bucket.refused = 0;
+                bucket.dropped = 0;
+                (Verdict::Allow, episode_opt)
+            } else {
+                let may_refuse = match bucket.last_refusal_opt {
+                    Some (last_refusal) => now.duration_since (last_refusal) >= Duration::from_secs (1),
+                    None => true
+                };
+                if may_refuse {
+                    bucket.last_refusal_opt = Some (now);
+                    bucket.refused += 1;
+                    (Verdict::Refuse, None)
+                } else {
+                    bucket.dropped += 1;
+                    (Verdict::Drop, None)
+                }
+            }
+        };
+        match result.0 {
+            Verdict::Refuse => self.refused_total += 1,
+            Verdict::Drop => self.dropped_total += 1,
+            Verdict::Allow => ()
+        }
+        result
+    }
+
+    pub fn refused_total (&self) -> u64 {
+        self.refused_total
+    }
+
+    pub fn dropped_total (&self) -> u64 {
+        self.dropped_total
+    }
+
+    fn capacity (&self) -> u64 {
+        self.queries_per_sec * 2 * MILLITOKENS_PER_QUERY
+    }
+
+    // A client whose bucket has filled up again is no different from one we've never seen
+    fn forget_idle_clients (&mut self, now: Instant) {
+        let (rate, capacity) = (self.queries_per_sec, self.capacity ());
+        self.buckets.retain (|_, bucket| {
+            refill (bucket, rate, capacity, now);
+            bucket.millitokens < capacity
+        });
+    }
+}
+
+// Turns a request into a REFUSED response with nothing in it
+pub fn make_refusal (buf: &mut [u8], length: usize) -> usize {
+    if length < 12 {return 0}
+    let mut facade = PacketFacade::new (buf, length);
+    facade.set_query (false);
+    facade.set_authoritative_answer (false);
+    facade.set_truncated (false);
+    facade.set_recursion_available (true);
+    facade.set_authenticated_data (false);
+    facade.set_checking_disabled (false);
+    facade.set_rcode (0x5);
+    facade.clear ();
+    12
+}
+
+fn refill (bucket: &mut Bucket, queries_per_sec: u64, capacity: u64, now: Instant) {
+    if now <= bucket.refilled_at {return}
+    let elapsed = now.duration_since (bucket.refilled_at);
+    let elapsed_ms = (elapsed.as_secs () * 1000) + (elapsed.subsec_nanos () / 1000000) as u64;
+    // queries_per_sec * 1000 millitokens a second is queries_per_sec millitokens a millisecond
+    let gained = elapsed_ms.saturating_mul (queries_per_sec);
+    if gained == 0 {return}
+    bucket.millitokens = if bucket.millitokens.saturating_add (gained) > capacity {capacity} else {bucket.millitokens + gained};
+    bucket.refilled_at = now;
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn ip (s: &str) -> IpAddr {
+        IpAddr::from_str (s).unwrap ()
+    }
+
+    #[test]
+    fn a_client_over_its_limit_is_refused_once_a_second_and_otherwise_ignored () {
+        let mut subject = RateLimiter::new (2);
+        let start = Instant::now ();
+
+        let verdicts: Vec<Verdict> = (0..8).map (|_| subject.check (ip ("10.0.0.1"), start).0).collect ();
+        let other_client = subject.check (ip ("10.0.0.2"), start);
+        let a_second_later = subject.check (ip ("10.0.0.1"), start + Duration::from_millis (1000));
+
+        assert_eq! (verdicts, vec! (Verdict::Allow, Verdict::Allow, Verdict::Allow, Verdict::Allow,
+            Verdict::Refuse, Verdict::Drop, Verdict::Drop, Verdict::Drop));
+        assert_eq! (other_client, (Verdict::Allow, None));
+        assert_eq! (a_second_later, (Verdict::Allow, Some (Episode {client: ip ("10.0.0.1"), refused: 1, dropped: 3})));
+        assert_eq! (subject.refused_total (), 1);
+        assert_eq! (subject.dropped_total (), 3);
+    }
+
+    #[test]
+    fn tokens_come_back_at_the_configured_rate_but_no_faster () {
+        let mut subject = RateLimiter::new (10);
+        let start = Instant::now ();
+        for _ in 0..20 {subject.check (ip ("10.0.0.1"), start);}
+
+        let too_soon = subject.check (ip ("10.0.0.1"), start + Duration::from_millis (50)).0;
+        let soon_enough = subject.check (ip ("10.0.0.1"), start + Duration::from_millis (150)).0;
+        let one_too_many = subject.check (ip ("10.0.0.1"), start + Duration::from_millis (150)).0;
+
+        assert_eq! ((too_soon, soon_enough, one_too_many), (Verdict::Refuse, Verdict::Allow, Verdict::Drop));
+    }
+
+    #[test]
+    fn refusals_keep_the_transaction_id_and_lose_everything_else () {
+        let mut buf: [u8; 100] = [0; 100];
+        let length = {
+            let mut facade = PacketFacade::new (&mut buf, 12);
+            facade.set_transaction_id (0x1234);
+            facade.set_query (true);
+            facade.add_query ("example.com", 0x0001, 0x0001);
+            facade.get_length ()
+        };
+
+        let result = make_refusal (&mut buf, length);
+
+        let facade = PacketFacade::new (&mut buf, result);
+        assert_eq! (result, 12);
+        assert_eq! (facade.get_transaction_id (), Some (0x1234));
+        assert_eq! (facade.is_query (), Some (false));
+        assert_eq! (facade.get_rcode (), Some (0x5));
+        assert_eq! (facade.get_queries ().unwrap ().len (), 0);
+        assert_eq! (make_refusal (&mut buf, 11), 0);
+    }
+}
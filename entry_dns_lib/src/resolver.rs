@@ -0,0 +1,149 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::cell::RefCell;
+use std::time::Instant;
+use packet_facade::PacketFacade;
+use dns_cache::CacheKey;
+use dns_cache::DnsCache;
+
+// Something that can take a whole DNS request and come back with a whole DNS response
+pub trait ResolverTrait {
+    fn resolve (&self, request: &[u8]) -> Result<Vec<u8>, String>;
+}
+
+// Answers from its cache what it can, and passes the rest along to the resolver it wraps
+pub struct CachingResolver<R> where R: ResolverTrait {
+    inner: R,
+    cache: RefCell<DnsCache>,
+}
+
+impl<R> ResolverTrait for CachingResolver<R> where R: ResolverTrait {
+    fn resolve (&self, request: &[u8]) -> Result<Vec<u8>, String> {
+        let key = match cache_key (request) {
+            Some (key) => key,
+            None => return self.inner.resolve (request)
+        };
+        let now = Instant::now ();
+        if let Some (mut response) = self.cache.borrow_mut ().lookup (&key, now) {
+            // The cached response is someone else's; it has to answer this request instead
+            response[0] = request[0];
+            response[1] = request[1];
+            return Ok (response)
+        }
+        let response = self.inner.resolve (request)?;
+        // Whatever's cached goes to everybody who asks, so a reply is only kept if it's plainly
+        // the answer to this very question
+        if (response.len () >= 2) && (&response[..2] == &request[..2]) && (cache_key (&response[..]).as_ref () == Some (&key)) {
+            self.cache.borrow_mut ().store (key, &response[..], now);
+        }
+        Ok (response)
+    }
+}
+
+impl<R> CachingResolver<R> where R: ResolverTrait {
+    pub fn new (inner: R, cache: DnsCache) -> CachingResolver<R> {
+        CachingResolver {inner, cache: RefCell::new (cache)}
+    }
+}
+
+// Only requests with a single question are cached
+fn cache_key (request: &[u8]) -> Option<CacheKey> {
+    let mut copy = request.to_vec ();
+    let facade = PacketFacade::new (&mut copy, request.len ());
+    let queries = try_opt! (facade.get_queries ());
+    if queries.len () != 1 {return None}
+    let query = &queries[0];
+    Some (CacheKey::new (query.get_query_name (), query.get_query_type (), query.get_query_class ()))
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use dns_cache::DEFAULT_NEGATIVE_TTL;
+
+    struct ResolverMock {
+        requests: Arc<Mutex<Vec<Vec<u8>>>>,
+        results: RefCell<Vec<Result<Vec<u8>, String>>>,
+    }
+
+    impl ResolverTrait for ResolverMock {
+        fn resolve (&self, request: &[u8]) -> Result<Vec<u8>, String> {
+            self.requests.lock ().unwrap ().push (request.to_vec ());
+            self.results.borrow_mut ().remove (0)
+        }
+    }
+
+    fn make_subject (results: Vec<Result<Vec<u8>, String>>) -> (CachingResolver<ResolverMock>, Arc<Mutex<Vec<Vec<u8>>>>) {
+        let requests = Arc::new (Mutex::new (vec! ()));
+        let inner = ResolverMock {requests: requests.clone (), results: RefCell::new (results)};
+        (CachingResolver::new (inner, DnsCache::new (10, DEFAULT_NEGATIVE_TTL)), requests)
+    }
+
+    fn packet (transaction_id: u16, names: Vec<&str>, answer: bool) -> Vec<u8> {
+        let mut buf: [u8; 512] = [0; 512];
+        let length = {
+            let mut facade = PacketFacade::new (&mut buf, 12);
+            facade.set_transaction_id (transaction_id);
+            facade.set_query (!answer);
+            for name in &names {
+                facade.add_query (name, 0x0010, 0x0001);
+            }
+            if answer {
+                facade.add_answer (names[0], 0x0010, 0x0001, 600, b"\x05hello");
+            }
+            facade.get_length ()
+        };
+        buf[..length].to_vec ()
+    }
+
+    #[test]
+    fn a_repeated_question_is_answered_from_the_cache_for_whoever_asks_it () {
+        let (subject, requests) = make_subject (vec! (Ok (packet (0x1111, vec! ("www.example.com"), true))));
+
+        let first = subject.resolve (&packet (0x1111, vec! ("www.example.com"), false)[..]);
+        let second = subject.resolve (&packet (0x2222, vec! ("WWW.EXAMPLE.COM"), false)[..]);
+
+        assert_eq! (first, Ok (packet (0x1111, vec! ("www.example.com"), true)));
+        let mut expected = packet (0x1111, vec! ("www.example.com"), true);
+        expected[0] = 0x22;
+        expected[1] = 0x22;
+        assert_eq! (second, Ok (expected));
+        assert_eq! (requests.lock ().unwrap ().len (), 1);
+    }
+
+    #[test]
+    fn replies_with_another_id_or_to_another_question_are_passed_on_but_not_cached () {
+        let (subject, requests) = make_subject (vec! (
+            Ok (packet (0x1111, vec! ("evil.example.com"), true)),
+            Ok (packet (0x9999, vec! ("www.example.com"), true)),
+            Ok (packet (0x1111, vec! ("www.example.com"), true)),
+            Ok (packet (0x1111, vec! ("www.example.com"), true)),
+        ));
+        let request = packet (0x1111, vec! ("www.example.com"), false);
+
+        let results: Vec<Result<Vec<u8>, String>> = (0..4).map (|_| subject.resolve (&request[..])).collect ();
+
+        assert_eq! (results[0], Ok (packet (0x1111, vec! ("evil.example.com"), true)));
+        assert_eq! (results[1], Ok (packet (0x9999, vec! ("www.example.com"), true)));
+        assert_eq! (results[3], Ok (packet (0x1111, vec! ("www.example.com"), true)));
+        assert_eq! (requests.lock ().unwrap ().len (), 3);
+    }
+
+    #[test]
+    fn failures_and_requests_with_other_than_one_question_go_upstream_every_time () {
+        let two_questions = packet (0x3333, vec! ("one.example.com", "two.example.com"), false);
+        let (subject, requests) = make_subject (vec! (
+            Err (String::from ("Upstream is down")),
+            Err (String::from ("Upstream is still down")),
+            Ok (packet (0x3333, vec! ("one.example.com", "two.example.com"), true)),
+            Ok (packet (0x3333, vec! ("one.example.com", "two.example.com"), true)),
+        ));
+
+        assert_eq! (subject.resolve (&packet (0x1111, vec! ("www.example.com"), false)[..]), Err (String::from ("Upstream is down")));
+        assert_eq! (subject.resolve (&packet (0x1111, vec! ("www.example.com"), false)[..]), Err (String::from ("Upstream is still down")));
+        assert_eq! (subject.resolve (&two_questions[..]).is_ok (), true);
+        assert_eq! (subject.resolve (&two_questions[..]).is_ok (), true);
+        assert_eq! (requests.lock ().unwrap ().len (), 4);
+    }
+}
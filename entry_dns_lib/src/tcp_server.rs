@@ -0,0 +1,335 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::cmp::min;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use sub_lib::logger::Logger;
+use sub_lib::tcp_wrappers::TcpListenerWrapper;
+use sub_lib::tcp_wrappers::TcpStreamWrapper;
+use processor::ProcessorTrait;
+use packet_server::PacketServerTrait;
+
+// A connection that goes quiet for this long, either way, is dropped
+pub const TCP_IDLE_TIMEOUT_MS: u64 = 5000;
+// However busy a client keeps it, no connection is served for longer than this
+pub const TCP_CONNECTION_DEADLINE_MS: u64 = 30000;
+// Connections past this many are closed as soon as they're accepted
+pub const MAX_TCP_CONNECTIONS: usize = 100;
+
+// DNS over TCP: each message, each way, is preceded by its length in two bytes. A client may ask
+// any number of questions on a connection before closing it. Each connection is read and written
+// on a thread of its own, so a slow client holds up nobody but itself; the questions all come
+// here, to the one processor, to be answered in turn.
+pub struct TcpServerReal<'a, P: 'a> where P: ProcessorTrait {
+    pub logger: Logger,
+    pub requests: &'a Receiver<TcpRequest>,
+    pub processor: &'a P
+}
+
+// A question read from a connection, and the way back to the connection for its answer
+pub struct TcpRequest {
+    request: Vec<u8>,
+    addr: SocketAddr,
+    response_tx: Sender<Vec<u8>>,
+}
+
+impl<'a, P: ProcessorTrait> PacketServerTrait for TcpServerReal<'a, P> {
+    fn serve (&mut self, buf: &mut [u8]) {
+        let request = self.requests.recv ().expect ("TCP acceptor is dead");
+        let request_length = request.request.len ();
+        buf[..request_length].copy_from_slice (&request.request[..]);
+        let response_length = self.processor.process (buf, request_length, &request.addr, &self.logger);
+        // The connection may have been dropped while its question waited
+        if request.response_tx.send (buf[..response_length].to_vec ()).is_err () {
+            self.logger.debug (format! ("Connection from {} closed before it was answered", request.addr))
+        }
+    }
+}
+
+// Accepts connections on a thread of its own and serves each one on a thread of its own, sending
+// the questions asked on them to the TcpServerReal at the other end of request_tx
+pub fn start_accepting (listener: Box<TcpListenerWrapper>, request_tx: Sender<TcpRequest>) {
+    thread::spawn (move || {
+        let logger = Logger::new ("EntryDnsServer");
+        let limit = ConnectionLimit::new (MAX_TCP_CONNECTIONS);
+        loop {
+            let (stream, addr) = match listener.accept () {
+                Ok (stream_and_address) => stream_and_address,
+                Err (e) => {logger.error (format! ("Couldn't accept connection: {}", e)); continue}
+            };
+            let open_connection = match limit.try_open () {
+                Some (open_connection) => open_connection,
+                None => {
+                    logger.warning (format! ("Refused connection from {}: {} connections already open", addr, MAX_TCP_CONNECTIONS));
+                    continue
+                }
+            };
+            let processor = QueuedProcessor {request_tx: request_tx.clone ()};
+            thread::spawn (move || {
+                let _open_connection = open_connection;
+                let logger = Logger::new ("EntryDnsServer");
+                if let Err (e) = stream.set_write_timeout (Some (Duration::from_millis (TCP_IDLE_TIMEOUT_MS))) {
+                    logger.error (format! ("Couldn't set timeout for {}: {}", addr, e));
+                    return
+                }
+                let deadline = Instant::now () + Duration::from_millis (TCP_CONNECTION_DEADLINE_MS);
+                let mut stream = DeadlineStream {stream, deadline};
+                let mut buf: Vec<u8> = vec! [0; 65536];
+                if let Err (e) = serve_connection (&mut stream, &mut buf[..], &addr, &processor, &logger) {
+                    logger.warning (format! ("Dropped connection from {}: {}", addr, e))
+                }
+            });
+        }
+    });
+}
+
+pub fn serve_connection<T, P> (stream: &mut T, buf: &mut [u8], addr: &SocketAddr, processor: &P, logger: &Logger) -> io::Result<()>
+        where T: Read + Write, P: ProcessorTrait {
+    loop {
+        let mut prefix: [u8; 2] = [0; 2];
+        match stream.read_exact (&mut prefix) {
+            Ok (()) => (),
+            // The client has asked all it's going to
+            Err (ref e) if e.kind () == io::ErrorKind::UnexpectedEof => return Ok (()),
+            Err (e) => return Err (e)
+        }
+        let request_length = ((prefix[0] as usize) << 8) | (prefix[1] as usize);
+        if request_length > buf.len () {
+            return Err (io::Error::new (io::ErrorKind::InvalidData, format! ("{}-byte request is too long", request_length)))
+        }
+        stream.read_exact (&mut buf[..request_length])?;
+        let response_length = processor.process (buf, request_length, addr, logger);
+        if response_length > 0xFFFF {
+            return Err (io::Error::new (io::ErrorKind::InvalidData, format! ("{}-byte response is too long", response_length)))
+        }
+        stream.write_all (&[(response_length >> 8) as u8, response_length as u8])?;
+        stream.write_all (&buf[..response_length])?;
+        stream.flush ()?;
+    }
+}
+
+// Stands in for the processor on a connection's thread, handing each question to the thread that
+// has the real one and waiting for the answer
+struct QueuedProcessor {
+    request_tx: Sender<TcpRequest>,
+}
+
+impl ProcessorTrait for QueuedProcessor {
+    fn process (&self, buf: &mut [u8], length: usize, addr: &SocketAddr, _logger: &Logger) -> usize {
+        let (response_tx, response_rx) = mpsc::channel ();
+        self.request_tx.send (TcpRequest {request: buf[..length].to_vec (), addr: *addr, response_tx}).expect ("TCP server is dead");
+        let response = response_rx.recv ().expect ("TCP server is dead");
+        buf[..response.len ()].copy_from_slice (&response[..]);
+        response.len ()
+    }
+}
+
+// Gives each read only as long as is left before the deadline, so a client can't keep a connection
+// open by dribbling its questions in a byte at a time
+struct DeadlineStream {
+    stream: Box<TcpStreamWrapper>,
+    deadline: Instant,
+}
+
+impl Read for DeadlineStream {
+    fn read (&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let timeout = read_timeout (self.deadline, Instant::now ())?;
+        self.stream.set_read_timeout (Some (timeout))?;
+        self.stream.read (buf)
+    }
+}
+
+impl Write for DeadlineStream {
+    fn write (&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write (buf)
+    }
+
+    fn flush (&mut self) -> io::Result<()> {
+        self.stream.flush ()
+    }
+}
+
+fn read_timeout (deadline: Instant, now: Instant) -> io::Result<Duration> {
+    if now >= deadline {
+        return Err (io::Error::new (io::ErrorKind::TimedOut, format! ("open longer than {}ms", TCP_CONNECTION_DEADLINE_MS)))
+    }
+    Ok (min (deadline - now, Duration::from_millis (TCP_IDLE_TIMEOUT_MS)))
+}
+
+// Counts the connections being served, and won't count past its maximum
+struct ConnectionLimit {
+    open: Arc<AtomicUsize>,
+    max: usize,
+}
+
+impl ConnectionLimit {
+    fn new (max: usize) -> ConnectionLimit {
+        ConnectionLimit {open: Arc::new (AtomicUsize::new (0)), max}
+    }
+
+    fn try_open (&self) -> Option<OpenConnection> {
+        if self.open.fetch_add (1, Ordering::SeqCst) >= self.max {
+            self.open.fetch_sub (1, Ordering::SeqCst);
+            return None
+        }
+        Some (OpenConnection {open: self.open.clone ()})
+    }
+}
+
+// Counts as open until it's dropped, even if the thread serving it panics
+struct OpenConnection {
+    open: Arc<AtomicUsize>,
+}
+
+impl Drop for OpenConnection {
+    fn drop (&mut self) {
+        self.open.fetch_sub (1, Ordering::SeqCst);
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::str::FromStr;
+
+    struct StreamMock {
+        input: Vec<u8>,
+        position: usize,
+        output: Vec<u8>,
+    }
+
+    impl Read for StreamMock {
+        fn read (&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let count = if buf.len () < self.input.len () - self.position {buf.len ()} else {self.input.len () - self.position};
+            buf[..count].copy_from_slice (&self.input[self.position..(self.position + count)]);
+            self.position += count;
+            Ok (count)
+        }
+    }
+
+    impl Write for StreamMock {
+        fn write (&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.extend_from_slice (buf);
+            Ok (buf.len ())
+        }
+
+        fn flush (&mut self) -> io::Result<()> {
+            Ok (())
+        }
+    }
+
+    // Answers by appending 0xAA to each request
+    struct ProcessorMock {
+        requests: RefCell<Vec<Vec<u8>>>,
+    }
+
+    impl ProcessorTrait for ProcessorMock {
+        fn process (&self, buf: &mut [u8], length: usize, _addr: &SocketAddr, _logger: &Logger) -> usize {
+            self.requests.borrow_mut ().push (buf[..length].to_vec ());
+            buf[length] = 0xAA;
+            length + 1
+        }
+    }
+
+    fn make_stream (input: Vec<u8>) -> StreamMock {
+        StreamMock {input, position: 0, output: vec! ()}
+    }
+
+    #[test]
+    fn every_request_on_a_connection_is_answered_with_its_length_in_front () {
+        let mut stream = make_stream (vec! (0, 2, 0x12, 0x34, 0, 3, 0x56, 0x78, 0x9A));
+        let processor = ProcessorMock {requests: RefCell::new (vec! ())};
+        let mut buf: [u8; 100] = [0; 100];
+
+        let result = serve_connection (&mut stream, &mut buf, &SocketAddr::from_str ("1.2.3.4:5678").unwrap (), &processor, &Logger::new ("test"));
+
+        assert_eq! (result.is_ok (), true);
+        assert_eq! (*processor.requests.borrow (), vec! (vec! (0x12, 0x34), vec! (0x56, 0x78, 0x9A)));
+        assert_eq! (stream.output, vec! (0, 3, 0x12, 0x34, 0xAA, 0, 4, 0x56, 0x78, 0x9A, 0xAA));
+    }
+
+    #[test]
+    fn a_connection_that_ends_in_the_middle_of_a_request_is_an_error () {
+        let mut stream = make_stream (vec! (0, 5, 0x12, 0x34));
+        let processor = ProcessorMock {requests: RefCell::new (vec! ())};
+        let mut buf: [u8; 100] = [0; 100];
+
+        let result = serve_connection (&mut stream, &mut buf, &SocketAddr::from_str ("1.2.3.4:5678").unwrap (), &processor, &Logger::new ("test"));
+
+        assert_eq! (result.err ().unwrap ().kind (), io::ErrorKind::UnexpectedEof);
+        assert_eq! (processor.requests.borrow ().len (), 0);
+        assert_eq! (stream.output.len (), 0);
+    }
+
+    #[test]
+    fn a_request_too_long_for_the_buffer_is_an_error () {
+        let mut stream = make_stream (vec! (1, 0));
+        let processor = ProcessorMock {requests: RefCell::new (vec! ())};
+        let mut buf: [u8; 100] = [0; 100];
+
+        let result = serve_connection (&mut stream, &mut buf, &SocketAddr::from_str ("1.2.3.4:5678").unwrap (), &processor, &Logger::new ("test"));
+
+        assert_eq! (format! ("{}", result.err ().unwrap ()), String::from ("256-byte request is too long"));
+    }
+
+    #[test]
+    fn questions_from_connection_threads_are_answered_by_the_server_thread () {
+        let (request_tx, request_rx) = mpsc::channel ();
+        thread::spawn (move || {
+            let processor = ProcessorMock {requests: RefCell::new (vec! ())};
+            let mut subject = TcpServerReal {logger: Logger::new ("test"), requests: &request_rx, processor: &processor};
+            let mut buf: [u8; 100] = [0; 100];
+            subject.serve (&mut buf);
+        });
+        let queued_processor = QueuedProcessor {request_tx};
+        let mut buf: [u8; 100] = [0; 100];
+        buf[0] = 0x12;
+        buf[1] = 0x34;
+
+        let result = queued_processor.process (&mut buf, 2, &SocketAddr::from_str ("1.2.3.4:5678").unwrap (), &Logger::new ("test"));
+
+        assert_eq! (result, 3);
+        assert_eq! (buf[..3].to_vec (), vec! (0x12, 0x34, 0xAA));
+    }
+
+    #[test]
+    fn reads_wait_no_longer_than_the_idle_timeout_or_the_connection_deadline () {
+        let now = Instant::now ();
+        let idle_timeout = Duration::from_millis (TCP_IDLE_TIMEOUT_MS);
+
+        let early = read_timeout (now + Duration::from_millis (TCP_CONNECTION_DEADLINE_MS), now).unwrap ();
+        let late = read_timeout (now + Duration::from_millis (100), now).unwrap ();
+        let expired = read_timeout (now, now);
+
+        assert_eq! (early == idle_timeout, true);
+        assert_eq! (late == Duration::from_millis (100), true);
+        assert_eq! (expired.err ().unwrap ().kind (), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn connections_past_the_limit_are_refused_until_one_closes () {
+        let subject = ConnectionLimit::new (2);
+
+        let first = subject.try_open ();
+        let second = subject.try_open ();
+        let third = subject.try_open ();
+        drop (first);
+        let fourth = subject.try_open ();
+
+        assert_eq! (second.is_some (), true);
+        assert_eq! (third.is_none (), true);
+        assert_eq! (fourth.is_some (), true);
+        assert_eq! (subject.open.load (Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,245 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::Duration;
+use rustls::ClientConfig;
+use rustls::ClientSession;
+use rustls::Stream;
+use webpki::DNSNameRef;
+use webpki_roots::TLS_SERVER_ROOTS;
+use resolver::ResolverTrait;
+
+pub const DEFAULT_TLS_UPSTREAM_PORT: u16 = 853;
+
+// A DNS-over-TLS server: where it is, and the name its certificate must be for
+#[derive (Clone, Debug, PartialEq)]
+pub struct TlsUpstream {
+    pub addr: SocketAddr,
+    pub name: String,
+}
+
+// The servers, and what it takes to trust them
+#[derive (Clone)]
+pub struct TlsUpstreams {
+    pub servers: Vec<TlsUpstream>,
+    pub config: Arc<ClientConfig>,
+}
+
+struct Connection {
+    index: usize,
+    session: ClientSession,
+    socket: TcpStream,
+}
+
+// Asks DNS-over-TLS servers (RFC 7858), so that nobody between here and them can see or change the
+// questions. Servers are tried in turn as UpstreamResolver tries them. The connection to whichever
+// answered last is kept open for the next question, since a TLS handshake for every one would
+// cost more than the question itself.
+pub struct TlsResolver {
+    upstreams: TlsUpstreams,
+    timeout: Duration,
+    preferred: Cell<usize>,
+    connection: RefCell<Option<Connection>>,
+}
+
+impl ResolverTrait for TlsResolver {
+    fn resolve (&self, request: &[u8]) -> Result<Vec<u8>, String> {
+        if request.len () < 12 {return Err (String::from ("Request too short to forward"))}
+        let servers = &self.upstreams.servers;
+        let mut failures: Vec<String> = vec! ();
+        for attempt in 0..servers.len () {
+            let index = (self.preferred.get () + attempt) % servers.len ();
+            match self.ask (index, request) {
+                Ok (response) => {
+                    self.preferred.set (index);
+                    return Ok (response)
+                },
+                Err (e) => failures.push (format! ("{}#{}: {}", servers[index].addr, servers[index].name, e))
+            }
+        }
+        Err (format! ("No DNS-over-TLS server answered ({})", failures.join ("; ")))
+    }
+}
+
+impl TlsResolver {
+    pub fn new (upstreams: TlsUpstreams, timeout: Duration) -> Result<TlsResolver, String> {
+        if upstreams.servers.is_empty () {return Err (String::from ("No DNS-over-TLS servers"))}
+        Ok (TlsResolver {upstreams, timeout, preferred: Cell::new (0), connection: RefCell::new (None)})
+    }
+
+    fn ask (&self, index: usize, request: &[u8]) -> Result<Vec<u8>, String> {
+        let mut connection_opt = self.connection.borrow_mut ();
+        let reusable = match *connection_opt {
+            Some (ref connection) => connection.index == index,
+            None => false
+        };
+        // A kept connection may have been closed by the server since it was last used; if so, a
+        // fresh one gets a try
+        if reusable {
+            let result = {
+                let connection = connection_opt.as_mut ().expect ("Connection disappeared");
+                exchange (&mut Stream::new (&mut connection.session, &mut connection.socket), request)
+            };
+            if result.is_ok () {return result}
+        }
+        *connection_opt = None;
+        let mut connection = self.connect (index)?;
+        let result = exchange (&mut Stream::new (&mut connection.session, &mut connection.socket), request);
+        if result.is_ok () {*connection_opt = Some (connection)}
+        result
+    }
+
+    fn connect (&self, index: usize) -> Result<Connection, String> {
+        let server = &self.upstreams.servers[index];
+        let socket = TcpStream::connect_timeout (&server.addr, self.timeout).map_err (|e| format! ("{}", e))?;
+        socket.set_read_timeout (Some (self.timeout)).map_err (|e| format! ("{}", e))?;
+        socket.set_write_timeout (Some (self.timeout)).map_err (|e| format! ("{}", e))?;
+        let name = DNSNameRef::try_from_ascii_str (&server.name).map_err (|_| format! ("'{}' is not a valid DNS name", server.name))?;
+        let session = ClientSession::new (&self.upstreams.config, name);
+        Ok (Connection {index, session, socket})
+    }
+}
+
+// The certificate authorities browsers trust, and any others in the given PEM file
+pub fn make_tls_config (ca_file_opt: Option<&str>) -> Result<Arc<ClientConfig>, String> {
+    let mut config = ClientConfig::new ();
+    config.root_store.add_server_trust_anchors (&TLS_SERVER_ROOTS);
+    if let Some (ca_file) = ca_file_opt {
+        let file = File::open (ca_file).map_err (|e| format! ("Can't read {}: {}", ca_file, e))?;
+        match config.root_store.add_pem_file (&mut BufReader::new (file)) {
+            Ok ((added, _)) if added > 0 => (),
+            _ => return Err (format! ("No usable certificates in {}", ca_file))
+        }
+    }
+    Ok (Arc::new (config))
+}
+
+// Servers are given as IP addresses, with or without ports, each followed by the name on its
+// certificate: "1.1.1.1#cloudflare-dns.com,[2620:fe::fe]:853#dns.quad9.net"
+pub fn parse_tls_upstreams (spec: &str) -> Result<Vec<TlsUpstream>, String> {
+    spec.split (',').map (|server| {
+        let server = server.trim ();
+        let mut parts = server.splitn (2, '#');
+        let address = parts.next ().unwrap_or ("");
+        let name = match parts.next () {
+            Some (name) if !name.is_empty () => name,
+            _ => return Err (format! ("'{}' needs #name of the server's certificate", server))
+        };
+        if DNSNameRef::try_from_ascii_str (name).is_err () {return Err (format! ("'{}' is not a valid DNS name", name))}
+        let addr = match address.parse::<SocketAddr> () {
+            Ok (addr) => addr,
+            Err (_) => match address.parse::<IpAddr> () {
+                Ok (ip) => SocketAddr::new (ip, DEFAULT_TLS_UPSTREAM_PORT),
+                Err (_) => return Err (format! ("'{}' is not an IP address with an optional port", address))
+            }
+        };
+        Ok (TlsUpstream {addr, name: String::from (name)})
+    }).collect ()
+}
+
+// DNS over TLS is framed as DNS over TCP is: each message is preceded by its length in two bytes
+fn exchange<T> (stream: &mut T, request: &[u8]) -> Result<Vec<u8>, String> where T: Read + Write {
+    if request.len () > 0xFFFF {return Err (format! ("{}-byte request is too long", request.len ()))}
+    let mut framed = vec! ((request.len () >> 8) as u8, request.len () as u8);
+    framed.extend_from_slice (request);
+    stream.write_all (&framed[..]).map_err (|e| format! ("{}", e))?;
+    stream.flush ().map_err (|e| format! ("{}", e))?;
+    let mut prefix: [u8; 2] = [0; 2];
+    stream.read_exact (&mut prefix).map_err (|e| format! ("{}", e))?;
+    let mut response = vec! (0; ((prefix[0] as usize) << 8) | (prefix[1] as usize));
+    stream.read_exact (&mut response[..]).map_err (|e| format! ("{}", e))?;
+    if (response.len () < 12) || (response[0] != request[0]) || (response[1] != request[1]) {
+        return Err (String::from ("Response doesn't match the request"))
+    }
+    Ok (response)
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io;
+    use std::str::FromStr;
+
+    struct StreamMock {
+        input: Vec<u8>,
+        position: usize,
+        output: Vec<u8>,
+    }
+
+    impl Read for StreamMock {
+        fn read (&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let count = if buf.len () < self.input.len () - self.position {buf.len ()} else {self.input.len () - self.position};
+            buf[..count].copy_from_slice (&self.input[self.position..(self.position + count)]);
+            self.position += count;
+            Ok (count)
+        }
+    }
+
+    impl Write for StreamMock {
+        fn write (&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.extend_from_slice (buf);
+            Ok (buf.len ())
+        }
+
+        fn flush (&mut self) -> io::Result<()> {
+            Ok (())
+        }
+    }
+
+    fn packet (transaction_id: u8) -> Vec<u8> {
+        vec! (transaction_id, transaction_id, 0x81, 0x80, 0, 0, 0, 0, 0, 0, 0, 0)
+    }
+
+    fn framed (packet: Vec<u8>) -> Vec<u8> {
+        let mut result = vec! (0, packet.len () as u8);
+        result.extend (packet);
+        result
+    }
+
+    #[test]
+    fn questions_and_answers_are_framed_with_their_lengths_and_must_match () {
+        let mut stream = StreamMock {input: framed (packet (0x11)), position: 0, output: vec! ()};
+        let mut mismatched = StreamMock {input: framed (packet (0x22)), position: 0, output: vec! ()};
+
+        let result = exchange (&mut stream, &packet (0x11)[..]);
+        let mismatched_result = exchange (&mut mismatched, &packet (0x11)[..]);
+
+        assert_eq! (result, Ok (packet (0x11)));
+        assert_eq! (stream.output, framed (packet (0x11)));
+        assert_eq! (mismatched_result, Err (String::from ("Response doesn't match the request")));
+    }
+
+    #[test]
+    fn servers_are_parsed_with_their_certificate_names () {
+        assert_eq! (parse_tls_upstreams ("1.1.1.1#cloudflare-dns.com, [2620:fe::fe]:8853#dns.quad9.net"), Ok (vec! (
+            TlsUpstream {addr: SocketAddr::from_str ("1.1.1.1:853").unwrap (), name: String::from ("cloudflare-dns.com")},
+            TlsUpstream {addr: SocketAddr::from_str ("[2620:fe::fe]:8853").unwrap (), name: String::from ("dns.quad9.net")},
+        )));
+        assert_eq! (parse_tls_upstreams ("1.1.1.1"), Err (String::from ("'1.1.1.1' needs #name of the server's certificate")));
+        assert_eq! (parse_tls_upstreams ("1.1.1.1#bad name"), Err (String::from ("'bad name' is not a valid DNS name")));
+        assert_eq! (parse_tls_upstreams ("booga#dns.quad9.net"), Err (String::from ("'booga' is not an IP address with an optional port")));
+    }
+
+    #[test]
+    fn extra_certificate_authorities_must_be_readable_pem () {
+        let directory = temp_dir ().join ("tls_resolver").join ("extra_certificate_authorities_must_be_readable_pem");
+        fs::create_dir_all (&directory).unwrap ();
+        let not_pem = directory.join ("not_pem.crt");
+        File::create (&not_pem).unwrap ().write_all (b"booga").unwrap ();
+        let not_pem_name = not_pem.to_str ().unwrap ();
+
+        assert_eq! (make_tls_config (None).is_ok (), true);
+        assert_eq! (make_tls_config (Some (not_pem_name)).err (), Some (format! ("No usable certificates in {}", not_pem_name)));
+        assert_eq! (make_tls_config (Some ("/nonexistent/ca.pem")).err ().unwrap ().starts_with ("Can't read /nonexistent/ca.pem: "), true);
+    }
+}
@@ -0,0 +1,310 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::cell::Cell;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::Instant;
+use rand;
+use sub_lib::udp_socket_wrapper::UdpSocketWrapperTrait;
+use packet_facade::PacketFacade;
+use resolver::ResolverTrait;
+
+pub const DEFAULT_UPSTREAM_PORT: u16 = 53;
+pub const DEFAULT_UPSTREAM_TIMEOUT_MS: u64 = 2000;
+
+// Asks real DNS servers over UDP. They're tried in turn until one answers; whichever answered last
+// is asked first next time, so a dead server only costs a timeout once rather than on every question.
+pub struct UpstreamResolver<S> where S: UdpSocketWrapperTrait {
+    make_socket: Box<Fn () -> S + Send>,
+    local_ip: IpAddr,
+    upstreams: Vec<SocketAddr>,
+    timeout: Duration,
+    preferred: Cell<usize>,
+}
+
+impl<S> ResolverTrait for UpstreamResolver<S> where S: UdpSocketWrapperTrait {
+    fn resolve (&self, request: &[u8]) -> Result<Vec<u8>, String> {
+        if request.len () < 12 {return Err (String::from ("Request too short to forward"))}
+        let mut failures: Vec<String> = vec! ();
+        for attempt in 0..self.upstreams.len () {
+            let index = (self.preferred.get () + attempt) % self.upstreams.len ();
+            let upstream = self.upstreams[index];
+            match self.exchange (request, upstream) {
+                Ok (response) => {
+                    self.preferred.set (index);
+                    return Ok (response)
+                },
+                Err (e) => failures.push (format! ("{}: {}", upstream, e))
+            }
+        }
+        Err (format! ("No upstream DNS server answered ({})", failures.join ("; ")))
+    }
+}
+
+impl<S> UpstreamResolver<S> where S: UdpSocketWrapperTrait {
+    pub fn new (make_socket: Box<Fn () -> S + Send>, upstreams: Vec<SocketAddr>, timeout: Duration) -> Result<UpstreamResolver<S>, String> {
+        if upstreams.is_empty () {return Err (String::from ("No upstream DNS servers"))}
+        let local_ip = match upstreams[0].ip () {
+            IpAddr::V4 (_) => IpAddr::V4 (Ipv4Addr::from (0)),
+            IpAddr::V6 (_) => IpAddr::V6 (Ipv6Addr::from ([0; 16])),
+        };
+        if upstreams.iter ().any (|upstream| upstream.is_ipv4 () != local_ip.is_ipv4 ()) {
+            return Err (String::from ("Upstream DNS servers must be all IPv4 or all IPv6"))
+        }
+        Ok (UpstreamResolver {make_socket, local_ip, upstreams, timeout, preferred: Cell::new (0)})
+    }
+
+    // Every question goes out with an ID of its own from a port of its own, so a forged reply has
+    // to guess both. Anything that arrives from elsewhere, with another ID, or with other questions
+    // than were asked is ignored; the reply that's kept gets the client's ID back.
+    fn exchange (&self, request: &[u8], upstream: SocketAddr) -> Result<Vec<u8>, String> {
+        let questions = questions_of (request).ok_or (String::from ("Request has no readable question"))?;
+        let mut socket = (self.make_socket) ();
+        socket.bind (SocketAddr::new (self.local_ip, 0)).map_err (|e| format! ("Couldn't open socket for upstream DNS: {}", e))?;
+        socket.set_read_timeout (Some (self.timeout)).map_err (|e| format! ("Couldn't set upstream DNS timeout: {}", e))?;
+        let transaction_id: u16 = rand::random ();
+        let mut query = request.to_vec ();
+        query[0] = (transaction_id >> 8) as u8;
+        query[1] = (transaction_id & 0xFF) as u8;
+        socket.send_to (&query[..], upstream).map_err (|e| format! ("{}", e))?;
+        let deadline = Instant::now () + self.timeout;
+        let mut buf: [u8; 65536] = [0; 65536];
+        loop {
+            let (length, from) = socket.recv_from (&mut buf).map_err (|e| format! ("{}", e))?;
+            if (from == upstream) && (length >= 12) && (buf[0] == query[0]) && (buf[1] == query[1])
+                    && (questions_of (&buf[..length]).as_ref () == Some (&questions)) {
+                let mut response = buf[..length].to_vec ();
+                response[0] = request[0];
+                response[1] = request[1];
+                return Ok (response)
+            }
+            if Instant::now () >= deadline {return Err (String::from ("Timed out"))}
+        }
+    }
+}
+
+// The name, type and class of each question in a packet; names are compared without case
+fn questions_of (packet: &[u8]) -> Option<Vec<(String, u16, u16)>> {
+    let mut buf = packet.to_vec ();
+    let length = buf.len ();
+    let facade = PacketFacade::new (&mut buf[..], length);
+    let queries = try_opt! (facade.get_queries ());
+    Some (queries.iter ()
+        .map (|query| (query.get_query_name ().to_lowercase (), query.get_query_type (), query.get_query_class ()))
+        .collect::<Vec<(String, u16, u16)>> ())
+}
+
+// Servers are given as IP addresses, with or without ports: "8.8.8.8,1.1.1.1:53,[2001:4860:4860::8888]:53"
+pub fn parse_upstreams (spec: &str) -> Result<Vec<SocketAddr>, String> {
+    spec.split (',').map (|server| {
+        let server = server.trim ();
+        match server.parse::<SocketAddr> () {
+            Ok (addr) => Ok (addr),
+            Err (_) => match server.parse::<IpAddr> () {
+                Ok (ip) => Ok (SocketAddr::new (ip, DEFAULT_UPSTREAM_PORT)),
+                Err (_) => Err (format! ("'{}' is not an IP address with an optional port", server))
+            }
+        }
+    }).collect ()
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::io;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    // What the mock upstream sends back: a reply to the ID it was just sent, or to some other ID
+    enum Reply {
+        Answer (Vec<u8>),
+        Misdirected (Vec<u8>),
+    }
+
+    struct UdpSocketWrapperMock {
+        log: Arc<Mutex<Vec<String>>>,
+        sent_ids: Arc<Mutex<Vec<u16>>>,
+        recv_from_results: Arc<Mutex<Vec<io::Result<(Reply, SocketAddr)>>>>,
+        last_sent: RefCell<Vec<u8>>,
+    }
+
+    impl UdpSocketWrapperTrait for UdpSocketWrapperMock {
+        fn bind (&mut self, addr: SocketAddr) -> io::Result<bool> {
+            self.log.lock ().unwrap ().push (format! ("bind ({})", addr));
+            Ok (true)
+        }
+
+        fn set_read_timeout (&self, dur: Option<Duration>) -> io::Result<()> {
+            let millis = dur.map (|d| (d.as_secs () * 1000) + (d.subsec_nanos () / 1000000) as u64);
+            self.log.lock ().unwrap ().push (format! ("set_read_timeout ({:?})", millis));
+            Ok (())
+        }
+
+        fn recv_from (&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+            let (reply, from) = self.recv_from_results.lock ().unwrap ().remove (0)?;
+            let last_sent = self.last_sent.borrow ();
+            let data = match reply {
+                Reply::Answer (mut data) => {data[0] = last_sent[0]; data[1] = last_sent[1]; data},
+                Reply::Misdirected (mut data) => {data[0] = !last_sent[0]; data[1] = last_sent[1]; data},
+            };
+            buf[..data.len ()].copy_from_slice (&data[..]);
+            Ok ((data.len (), from))
+        }
+
+        fn send_to (&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+            self.log.lock ().unwrap ().push (format! ("send_to ({} bytes, {})", buf.len (), addr));
+            self.sent_ids.lock ().unwrap ().push (((buf[0] as u16) << 8) | (buf[1] as u16));
+            *self.last_sent.borrow_mut () = buf.to_vec ();
+            Ok (buf.len ())
+        }
+    }
+
+    // Every socket the resolver opens draws on the same replies and writes to the same log
+    struct SocketFactoryMock {
+        log: Arc<Mutex<Vec<String>>>,
+        sent_ids: Arc<Mutex<Vec<u16>>>,
+        recv_from_results: Arc<Mutex<Vec<io::Result<(Reply, SocketAddr)>>>>,
+    }
+
+    impl SocketFactoryMock {
+        fn new (recv_from_results: Vec<io::Result<(Reply, SocketAddr)>>) -> SocketFactoryMock {
+            SocketFactoryMock {
+                log: Arc::new (Mutex::new (vec! ())),
+                sent_ids: Arc::new (Mutex::new (vec! ())),
+                recv_from_results: Arc::new (Mutex::new (recv_from_results)),
+            }
+        }
+
+        fn make (&self) -> Box<Fn () -> UdpSocketWrapperMock + Send> {
+            let log = self.log.clone ();
+            let sent_ids = self.sent_ids.clone ();
+            let recv_from_results = self.recv_from_results.clone ();
+            Box::new (move || UdpSocketWrapperMock {
+                log: log.clone (),
+                sent_ids: sent_ids.clone (),
+                recv_from_results: recv_from_results.clone (),
+                last_sent: RefCell::new (vec! ()),
+            })
+        }
+    }
+
+    // A packet with one question, for the name given
+    fn packet_for (transaction_id: u8, name: &str) -> Vec<u8> {
+        let mut packet = vec! (transaction_id, transaction_id, 0x81, 0x80, 0, 1, 0, 0, 0, 0, 0, 0);
+        for label in name.split ('.') {
+            packet.push (label.len () as u8);
+            packet.extend_from_slice (label.as_bytes ());
+        }
+        packet.extend_from_slice (&[0, 0, 1, 0, 1]);
+        packet
+    }
+
+    fn packet (transaction_id: u8) -> Vec<u8> {
+        packet_for (transaction_id, "booga.com")
+    }
+
+    fn addr (s: &str) -> SocketAddr {
+        SocketAddr::from_str (s).unwrap ()
+    }
+
+    fn timeout () -> io::Error {
+        io::Error::from (io::ErrorKind::WouldBlock)
+    }
+
+    #[test]
+    fn a_dead_server_is_skipped_and_the_next_that_answers_is_asked_first_thereafter () {
+        let factory = SocketFactoryMock::new (vec! (
+            Err (timeout ()),
+            Ok ((Reply::Answer (packet (0x11)), addr ("3.3.3.3:53"))),
+            Ok ((Reply::Answer (packet (0x11)), addr ("2.2.2.2:53"))),
+            Ok ((Reply::Answer (packet (0x22)), addr ("2.2.2.2:53"))),
+        ));
+        let subject = UpstreamResolver::new (factory.make (), vec! (addr ("1.1.1.1:53"), addr ("2.2.2.2:53")), Duration::from_millis (500)).unwrap ();
+
+        let first = subject.resolve (&packet (0x11)[..]);
+        let second = subject.resolve (&packet (0x22)[..]);
+
+        assert_eq! (first, Ok (packet (0x11)));
+        assert_eq! (second, Ok (packet (0x22)));
+        assert_eq! (*factory.log.lock ().unwrap (), vec! (
+            String::from ("bind (0.0.0.0:0)"),
+            String::from ("set_read_timeout (Some(500))"),
+            String::from ("send_to (27 bytes, 1.1.1.1:53)"),
+            String::from ("bind (0.0.0.0:0)"),
+            String::from ("set_read_timeout (Some(500))"),
+            String::from ("send_to (27 bytes, 2.2.2.2:53)"),
+            String::from ("bind (0.0.0.0:0)"),
+            String::from ("set_read_timeout (Some(500))"),
+            String::from ("send_to (27 bytes, 2.2.2.2:53)"),
+        ));
+    }
+
+    #[test]
+    fn replies_with_another_id_or_to_another_question_are_ignored () {
+        let factory = SocketFactoryMock::new (vec! (
+            Ok ((Reply::Misdirected (packet (0x11)), addr ("1.1.1.1:53"))),
+            Ok ((Reply::Answer (packet_for (0x11, "evil.com")), addr ("1.1.1.1:53"))),
+            Ok ((Reply::Answer (packet_for (0x11, "BOOGA.com")), addr ("1.1.1.1:53"))),
+        ));
+        let subject = UpstreamResolver::new (factory.make (), vec! (addr ("1.1.1.1:53")), Duration::from_millis (500)).unwrap ();
+
+        let result = subject.resolve (&packet (0x11)[..]);
+
+        assert_eq! (result, Ok (packet_for (0x11, "BOOGA.com")));
+        assert_eq! (factory.recv_from_results.lock ().unwrap ().len (), 0);
+    }
+
+    #[test]
+    fn every_question_goes_out_with_a_random_id_and_the_client_gets_its_own_back () {
+        let replies: Vec<io::Result<(Reply, SocketAddr)>> = (0..20).map (|_| Ok ((Reply::Answer (packet (0x11)), addr ("1.1.1.1:53")))).collect ();
+        let factory = SocketFactoryMock::new (replies);
+        let subject = UpstreamResolver::new (factory.make (), vec! (addr ("1.1.1.1:53")), Duration::from_millis (500)).unwrap ();
+
+        let results: Vec<Result<Vec<u8>, String>> = (0..20).map (|_| subject.resolve (&packet (0x11)[..])).collect ();
+
+        assert_eq! (results.into_iter ().all (|result| result == Ok (packet (0x11))), true);
+        let sent_ids = factory.sent_ids.lock ().unwrap ();
+        assert_eq! (sent_ids.len (), 20);
+        assert_eq! (sent_ids.iter ().all (|id| *id == sent_ids[0]), false);
+        assert_eq! (factory.log.lock ().unwrap ().iter ().filter (|entry| entry.starts_with ("bind")).count (), 20);
+    }
+
+    #[test]
+    fn a_request_without_a_readable_question_isnt_forwarded () {
+        let factory = SocketFactoryMock::new (vec! ());
+        let subject = UpstreamResolver::new (factory.make (), vec! (addr ("1.1.1.1:53")), Duration::from_millis (500)).unwrap ();
+        let mut request = packet (0x11);
+        request.truncate (15);
+
+        let result = subject.resolve (&request[..]);
+
+        assert_eq! (result, Err (String::from ("No upstream DNS server answered (1.1.1.1:53: Request has no readable question)")));
+        assert_eq! (factory.log.lock ().unwrap ().len (), 0);
+    }
+
+    #[test]
+    fn when_no_server_answers_the_failures_are_all_reported () {
+        let factory = SocketFactoryMock::new (vec! (Err (timeout ()), Err (timeout ())));
+        let subject = UpstreamResolver::new (factory.make (), vec! (addr ("1.1.1.1:53"), addr ("2.2.2.2:5353")), Duration::from_millis (500)).unwrap ();
+
+        let result = subject.resolve (&packet (0x11)[..]);
+
+        assert_eq! (result, Err (format! ("No upstream DNS server answered (1.1.1.1:53: {}; 2.2.2.2:5353: {})", timeout (), timeout ())));
+    }
+
+    #[test]
+    fn servers_are_parsed_with_or_without_ports_and_must_not_mix_families () {
+        assert_eq! (parse_upstreams ("8.8.8.8, 1.1.1.1:5353,[2001:db8::1]:53,2001:db8::2"), Ok (vec! (
+            addr ("8.8.8.8:53"), addr ("1.1.1.1:5353"), addr ("[2001:db8::1]:53"), addr ("[2001:db8::2]:53"),
+        )));
+        assert_eq! (parse_upstreams ("8.8.8.8,booga"), Err (String::from ("'booga' is not an IP address with an optional port")));
+        assert_eq! (UpstreamResolver::new (SocketFactoryMock::new (vec! ()).make (), vec! (addr ("8.8.8.8:53"), addr ("[2001:db8::1]:53")), Duration::from_millis (500)).err (),
+            Some (String::from ("Upstream DNS servers must be all IPv4 or all IPv6")));
+        assert_eq! (UpstreamResolver::new (SocketFactoryMock::new (vec! ()).make (), vec! (), Duration::from_millis (500)).err (),
+            Some (String::from ("No upstream DNS servers")));
+    }
+}
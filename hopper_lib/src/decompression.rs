@@ -0,0 +1,126 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+
+// Shortest match an LZ4 sequence can copy; a match length of 0 in a token means this many bytes
+const MIN_MATCH: usize = 4;
+
+#[derive (Clone, Copy, Debug, PartialEq)]
+pub enum DecompressionError {
+    Truncated,
+    BadOffset,
+    TooLong,
+}
+
+// Reads what lz4_compress::compress writes: a raw LZ4 block. A few bytes of LZ4 can stand for
+// hundreds of times as many, so a neighbor's package is only decompressed up to max_length; past
+// that, it's given up on before any more is written.
+pub fn decompress (input: &[u8], max_length: usize) -> Result<Vec<u8>, DecompressionError> {
+    let mut output: Vec<u8> = Vec::new ();
+    let mut position = 0;
+    while position < input.len () {
+        let token = input[position];
+        position += 1;
+        let literal_length = read_length (input, &mut position, (token >> 4) as usize)?;
+        if literal_length > input.len () - position {
+            return Err (DecompressionError::Truncated)
+        }
+        if literal_length > max_length - output.len () {
+            return Err (DecompressionError::TooLong)
+        }
+        output.extend_from_slice (&input[position..(position + literal_length)]);
+        position += literal_length;
+        // The last sequence is literals alone
+        if position == input.len () {break}
+        if input.len () - position < 2 {
+            return Err (DecompressionError::Truncated)
+        }
+        let offset = (input[position] as usize) | ((input[position + 1] as usize) << 8);
+        position += 2;
+        if (offset == 0) || (offset > output.len ()) {
+            return Err (DecompressionError::BadOffset)
+        }
+        let match_length = read_length (input, &mut position, (token & 0x0F) as usize)? + MIN_MATCH;
+        if match_length > max_length - output.len () {
+            return Err (DecompressionError::TooLong)
+        }
+        // A match may overlap what it's copying, so it goes a byte at a time
+        let start = output.len () - offset;
+        for index in start..(start + match_length) {
+            let byte = output[index];
+            output.push (byte);
+        }
+    }
+    Ok (output)
+}
+
+// A nibble of 15 goes on in the bytes that follow, for as long as they're 255
+fn read_length (input: &[u8], position: &mut usize, nibble: usize) -> Result<usize, DecompressionError> {
+    let mut length = nibble;
+    if nibble == 15 {
+        loop {
+            let byte = match input.get (*position) {
+                None => return Err (DecompressionError::Truncated),
+                Some (byte) => *byte
+            };
+            *position += 1;
+            length += byte as usize;
+            if byte != 255 {break}
+        }
+    }
+    Ok (length)
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use lz4_compress;
+
+    #[test]
+    fn decompresses_what_lz4_compress_compresses () {
+        let mut data: Vec<u8> = b"a package that says the same thing, and the same thing, and the same thing again".to_vec ();
+        data.extend ((0..5000).map (|index| ((index * 7919) % 251) as u8));
+        data.extend (vec! (0x42; 3000));
+        let compressed = lz4_compress::compress (&data[..]);
+
+        let result = decompress (&compressed[..], data.len ());
+
+        assert_eq! (result, Ok (data));
+    }
+
+    #[test]
+    fn stops_decompressing_at_the_maximum_length () {
+        let bomb = lz4_compress::compress (&vec! (0u8; 1000000)[..]);
+
+        let result = decompress (&bomb[..], 999999);
+
+        assert_eq! (bomb.len () < 10000, true);
+        assert_eq! (result, Err (DecompressionError::TooLong));
+    }
+
+    #[test]
+    fn rejects_literals_that_run_past_the_end () {
+        let result = decompress (&[0x50, b'a', b'b'], 100);
+
+        assert_eq! (result, Err (DecompressionError::Truncated));
+    }
+
+    #[test]
+    fn rejects_lengths_that_run_past_the_end () {
+        let result = decompress (&[0xF0, 255, 255], 100000);
+
+        assert_eq! (result, Err (DecompressionError::Truncated));
+    }
+
+    #[test]
+    fn rejects_matches_from_before_the_start () {
+        let result = decompress (&[0x20, b'a', b'b', 3, 0], 100);
+
+        assert_eq! (result, Err (DecompressionError::BadOffset));
+    }
+
+    #[test]
+    fn matches_may_overlap_what_they_copy () {
+        let result = decompress (&[0x22, b'a', b'b', 2, 0, 0x00], 100);
+
+        assert_eq! (result, Ok (b"abababab".to_vec ()));
+    }
+}
@@ -1,12 +1,26 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 use actix::Actor;
 use actix::Addr;
+use actix::AsyncContext;
 use actix::Context;
 use actix::Handler;
 use actix::Recipient;
 use actix::Syn;
+use lz4_compress;
 use serde_cbor;
+use sub_lib::accountant::ReportRoutingServiceMessage;
+use sub_lib::accountant::ServiceRefusal;
+use sub_lib::accountant::ServiceStanding;
+use sub_lib::accountant::ServiceStandingMessage;
 use sub_lib::cryptde::CryptData;
 use sub_lib::cryptde::CryptDE;
 use sub_lib::cryptde::CryptdecError;
@@ -17,13 +31,38 @@ use sub_lib::dispatcher::Endpoint;
 use sub_lib::dispatcher::InboundClientData;
 use sub_lib::hop::Hop;
 use sub_lib::hopper::ExpiredCoresPackage;
+use sub_lib::hopper::HopperConfig;
 use sub_lib::hopper::HopperSubs;
 use sub_lib::hopper::HopperTemporaryTransmitDataMsg;
 use sub_lib::hopper::IncipientCoresPackage;
+use sub_lib::hopper::MixDelay;
 use sub_lib::logger::Logger;
+use sub_lib::neighborhood::ExpiredNeighborhoodPackage;
+use sub_lib::neighborhood::MAX_GOSSIP_BYTES;
+use sub_lib::neighborhood::Misbehaver;
+use sub_lib::neighborhood::NeighborMisbehavior;
+use sub_lib::neighborhood::NeighborMisbehaviorMessage;
+use sub_lib::neighborhood::NodeBannedMsg;
+use sub_lib::neighborhood::NodeUnbannedMsg;
 use sub_lib::peer_actors::BindMessage;
+use sub_lib::proxy_client::ClientResponsePayload;
+use sub_lib::proxy_client::ExitFailure;
+use sub_lib::proxy_server::ClientRequestPayload;
 use sub_lib::route::Route;
+use sub_lib::route::RouteSegment;
+use sub_lib::traffic_stats;
 use sub_lib::utils::NODE_MAILBOX_CAPACITY;
+use decompression;
+use mixer::Mixer;
+use replay_window::ReplayWindow;
+
+// Whoever numbered a package: the Node at the other end of the link it came in on once that Node
+// has proved who it is, or else the address it came in from
+#[derive (Clone, Debug, PartialEq, Eq, Hash)]
+enum PackageSender {
+    Node (Key),
+    Address (SocketAddr),
+}
 
 pub struct Hopper {
     cryptde: &'static CryptDE,
@@ -31,6 +70,21 @@ pub struct Hopper {
     to_proxy_client: Option<Recipient<Syn, ExpiredCoresPackage>>,
     // TODO when we are decentralized, change this to a TransmitDataMsg
     to_dispatcher: Option<Recipient<Syn, HopperTemporaryTransmitDataMsg>>,
+    to_neighborhood: Option<Recipient<Syn, ExpiredNeighborhoodPackage>>,
+    to_neighborhood_reports: Option<Recipient<Syn, NeighborMisbehaviorMessage>>,
+    to_accountant: Option<Recipient<Syn, ReportRoutingServiceMessage>>,
+    to_accountant_packages: Option<Recipient<Syn, ExpiredCoresPackage>>,
+    config: HopperConfig,
+    initial_sequence: u64,
+    outgoing_sequences: HashMap<Key, u64>,
+    replay_windows: HashMap<PackageSender, ReplayWindow>,
+    compression_keys: HashSet<Key>,
+    banned_ips: HashSet<IpAddr>,
+    // Consuming Nodes the Accountant has throttled or refused; the rest are in good standing
+    service_standings: HashMap<Key, ServiceStanding>,
+    // When each refused Node was last told it's refused relaying
+    refusals_sent: HashMap<Key, Instant>,
+    mixer: Option<Mixer<Endpoint, HopperTemporaryTransmitDataMsg>>,
     logger: Logger,
 }
 
@@ -46,6 +100,15 @@ impl Handler<BindMessage> for Hopper {
         self.to_proxy_server = Some(msg.peer_actors.proxy_server.from_hopper);
         self.to_proxy_client = Some(msg.peer_actors.proxy_client.from_hopper);
         self.to_dispatcher = Some(msg.peer_actors.dispatcher.from_hopper);
+        self.to_neighborhood = Some(msg.peer_actors.neighborhood.from_hopper);
+        self.to_neighborhood_reports = Some(msg.peer_actors.neighborhood.report_misbehavior);
+        self.to_accountant = Some(msg.peer_actors.accountant.report_routing_service);
+        self.to_accountant_packages = Some(msg.peer_actors.accountant.from_hopper);
+        if self.config.cover_traffic_interval_ms > 0 {
+            ctx.run_interval (Duration::from_millis (self.config.cover_traffic_interval_ms), |hopper, _ctx| {
+                hopper.send_cover_traffic ()
+            });
+        }
         ()
     }
 }
@@ -55,97 +118,227 @@ impl Handler<IncipientCoresPackage> for Hopper {
 
     fn handle(&mut self, msg: IncipientCoresPackage, _ctx: &mut Self::Context) -> Self::Result {
         self.logger.debug (format! ("Received IncipientCoresPackage with {}-byte payload", msg.payload.data.len ()));
-        let (live_package, key) = LiveCoresPackage::from_incipient(msg, self.cryptde.borrow());
+        self.send_incipient (msg)
+    }
+}
 
-        let serialized_package = match serde_cbor::ser::to_vec (&live_package) {
-            Ok(package) => package,
+impl Handler<InboundClientData> for Hopper {
+    type Result = ();
+
+    fn handle(&mut self, msg: InboundClientData, ctx: &mut Self::Context) -> Self::Result {
+        self.logger.debug (format! ("Received {} bytes of InboundClientData from Dispatcher", msg.data.len ()));
+        if self.banned_ips.contains (&msg.socket_addr.ip ()) {
+            self.logger.warning (format! ("Refused {} bytes from banned Node at {}", msg.data.len (), msg.socket_addr));
+            return ()
+        }
+        let misbehaver = match msg.peer_public_key_opt {
+            Some (ref public_key) => Misbehaver::Node (public_key.clone ()),
+            None => Misbehaver::Address (msg.socket_addr),
+        };
+        if msg.data.len () > MAX_PACKAGE_BYTES {
+            self.logger.error (format! ("Dropped {}-byte package from neighbor at {}: nothing legitimate is that big", msg.data.len (), msg.socket_addr));
+            self.report_misbehavior (misbehaver, NeighborMisbehavior::OversizedPackage);
+            return ()
+        }
+        let sealed_package = CryptData::new (&msg.data[..]);
+        let unsealed = match LiveCoresPackage::unseal (&sealed_package, &self.cryptde.private_key (), self.cryptde.borrow ()) {
+            Ok(unsealed) => unsealed,
+            Err(SealError::IntegrityCheckFailed) => {
+                self.logger.error(format!("Rejected tampered package from neighbor at {}", msg.socket_addr));
+                self.report_misbehavior (misbehaver, NeighborMisbehavior::TamperedPackage);
+                return ()
+            },
             Err(_) => {
-                self.logger.error(format! ("Couldn't serialize package"));
-                // TODO what should we do here? (nothing is unbound --so we don't need to blow up-- but we can't send this package)
+                self.logger.error(format!("Couldn't deserialize package from neighbor at {}", msg.socket_addr));
+                self.report_misbehavior (misbehaver, NeighborMisbehavior::MalformedPackage);
                 return ()
             }
         };
 
-        let encrypted_package = match self.cryptde.encode(&key, &PlainData::new(&serialized_package[..])) {
-            Ok(package) => package,
-            Err (_) => {
-                self.logger.error(format! ("Couldn't encode package"));
-                // TODO what should we do here? (nothing is unbound --so we don't need to blow up-- but we can't send this package)
-                return ()
+        let sender = match msg.peer_public_key_opt {
+            Some (ref public_key) => PackageSender::Node (public_key.clone ()),
+            None => PackageSender::Address (msg.socket_addr),
+        };
+        let admitted = self.replay_windows.entry (sender).or_insert_with (ReplayWindow::new).admit (unsealed.sequence);
+        if !admitted {
+            self.logger.warning(format!("Dropped replayed package {} from neighbor at {}", unsealed.sequence, msg.socket_addr));
+            self.report_misbehavior (misbehaver, NeighborMisbehavior::ReplayedPackage);
+            return ()
+        }
+        if let Some (key) = unsealed.compression_key {
+            if self.compression_keys.insert (key) {
+                self.logger.debug (format! ("Neighbor at {} accepts compressed packages", msg.socket_addr));
             }
+        }
+        let live_package = match unsealed.package {
+            None => {
+                self.logger.debug (format! ("Discarded cover traffic from neighbor at {}", msg.socket_addr));
+                return ()
+            },
+            Some (package) => package
         };
 
-        // TODO when we are decentralized, change this to a TransmitDataMsg
-        let transmit_msg = HopperTemporaryTransmitDataMsg {
-            endpoint: Endpoint::Key(key),
-            last_data: false, // Hopper-to-Hopper streams are never remotely killed
-            data: encrypted_package.data,
+        let next_hop = match live_package.next_hop(self.cryptde.borrow()) {
+            Ok (hop) => hop,
+            Err (e) => return self.reject_malformed (misbehaver, msg.socket_addr, e)
         };
-
-        self.logger.debug (format! ("Sending TransmitDataMsg with {}-byte payload to Dispatcher", transmit_msg.data.len ()));
-        self.to_dispatcher.as_ref().expect("Dispatcher unbound in Hopper").try_send(transmit_msg).expect("Dispatcher is dead");
-        ()
-    }
-}
-
-impl Handler<InboundClientData> for Hopper {
-    type Result = ();
-
-    fn handle(&mut self, msg: InboundClientData, _ctx: &mut Self::Context) -> Self::Result {
-        self.logger.debug (format! ("Received {} bytes of InboundClientData from Dispatcher", msg.data.len ()));
-        let decrypted_package = match self.cryptde.decode(&self.cryptde.private_key(), &CryptData::new(&msg.data[..])) {
-            Ok(package) => package,
-            Err (e) => {
-                self.logger.error(format! ("{:?}", e));
-                // TODO what should we do here? (nothing is unbound --so we don't need to blow up-- but we can't send this package)
+        if self.config.originate_only {
+            let refused_role = match next_hop.component {
+                Component::Hopper => Some ("relay"),
+                Component::ProxyClient => Some ("exit"),
+                _ => None
+            };
+            if let Some (role) = refused_role {
+                self.logger.warning (format! ("Refused to {} for neighbor at {}: this Node only originates traffic", role, msg.socket_addr));
                 return ()
             }
+        }
+
+        // Service for Nodes that owe too much is slowed, and then stopped. A relay can't read the
+        // request to answer it, so it tells the consuming Node's Accountant instead; an exit tells
+        // the consuming Node why that request got nothing.
+        let throttled = match (next_hop.component, self.standing_of (&next_hop)) {
+            (Component::Hopper, ServiceStanding::Refused) => {
+                self.logger.warning (format! ("Refused to relay for neighbor at {}: the consuming Node owes too much", msg.socket_addr));
+                if let Some (consuming_node_key) = self.billable_key (&next_hop) {
+                    self.refuse_relay (consuming_node_key)
+                }
+                return ()
+            },
+            (Component::ProxyClient, ServiceStanding::Refused) => {
+                self.logger.warning (format! ("Refused to exit for neighbor at {}: the consuming Node owes too much", msg.socket_addr));
+                let expired_package = match live_package.to_expired(self.cryptde.borrow()) {
+                    Ok (package) => package,
+                    Err (e) => return self.reject_malformed (misbehaver, msg.socket_addr, e)
+                };
+                return self.refuse_exit (expired_package)
+            },
+            (_, standing) => standing == ServiceStanding::Throttled
         };
-        let live_package = match serde_cbor::de::from_slice::<LiveCoresPackage>(&decrypted_package.data[..]) {
-            Ok(package) => package,
-            Err(_) => {
-                self.logger.error(format!("Couldn't deserialize package"));
-                // TODO what should we do here? (nothing is unbound --so we don't need to blow up-- but we can't send this package)
+
+        if next_hop.component == Component::Hopper {
+            if live_package.ttl == 0 {
+                self.logger.warning (format! ("Dropped expired package from neighbor at {}: TTL exhausted", msg.socket_addr));
                 return ()
             }
+            let transmit_msg = match self.to_transmit_msg (live_package, msg.last_data) {
+                Ok (m) => m,
+                Err (e) => return self.reject_malformed (misbehaver, msg.socket_addr, e)
+            };
+            if throttled {
+                ctx.run_later (Duration::from_millis (THROTTLE_DELAY_MS), |hopper, ctx| hopper.relay (transmit_msg, ctx));
+            }
+            else {
+                self.relay (transmit_msg, ctx);
+            }
+            return self.report_routing_service (&next_hop, msg.socket_addr.ip (), msg.data.len ())
+        }
 
+        let mut expired_package = match live_package.to_expired(self.cryptde.borrow()) {
+            Ok (package) => package,
+            Err (e) => return self.reject_malformed (misbehaver, msg.socket_addr, e)
         };
-
-        let next_hop = live_package.next_hop(self.cryptde.borrow());
-
         match next_hop.component {
             Component::ProxyServer => {
-                let expired_package = live_package.to_expired(self.cryptde.borrow());
                 self.logger.debug (format! ("Forwarding ExpiredCoresPackage to Proxy Server: {:?}", expired_package));
                 self.to_proxy_server.as_ref().expect("ProxyServer unbound in Hopper").try_send(expired_package).expect("Proxy Server is dead")
             },
             Component::ProxyClient => {
-                let expired_package = live_package.to_expired(self.cryptde.borrow());
+                expired_package.consuming_key_opt = self.billable_key (&next_hop);
+                expired_package.neighbor_ip_opt = Some (msg.socket_addr.ip ());
                 self.logger.debug (format! ("Forwarding ExpiredCoresPackage to Proxy Client: {:?}", expired_package));
-                self.to_proxy_client.as_ref ().expect ("ProxyClient unbound in Hopper").try_send (expired_package ).expect ("Proxy Client is dead")
+                if throttled {
+                    ctx.run_later (Duration::from_millis (THROTTLE_DELAY_MS), |hopper, _ctx| hopper.send_to_proxy_client (expired_package));
+                }
+                else {
+                    self.send_to_proxy_client (expired_package)
+                }
             },
-            Component::Neighborhood => unimplemented!(),
-            Component::Hopper => {
-                let transmit_msg = match self.to_transmit_msg (live_package, msg.last_data) {
-                    // crashpoint - need to figure out how to bubble up different kinds of errors, or just log and return
-                    Err (_) => unimplemented! (),
-                    Ok (m) => m
-                };
-                self.logger.debug (format! ("Relaying {}-byte LiveCoresPackage Dispatcher inside a TransmitDataMsg", transmit_msg.data.len ()));
-                self.to_dispatcher.as_ref().expect("Dispatcher unbound in Hopper").try_send(transmit_msg).expect("Dispatcher is dead");
-            }
+            Component::Neighborhood => {
+                self.logger.debug (format! ("Forwarding ExpiredCoresPackage to Neighborhood: {:?}", expired_package));
+                self.to_neighborhood.as_ref ().expect ("Neighborhood unbound in Hopper").try_send (ExpiredNeighborhoodPackage {
+                    package: expired_package,
+                    neighbor_addr: msg.socket_addr,
+                }).expect ("Neighborhood is dead")
+            },
+            Component::Accountant => {
+                self.logger.debug (format! ("Forwarding ExpiredCoresPackage to Accountant: {:?}", expired_package));
+                self.to_accountant_packages.as_ref ().expect ("Accountant unbound in Hopper").try_send (expired_package).expect ("Accountant is dead")
+            },
+            Component::Hopper => unreachable! ("Relayed packages don't expire here")
         };
         ()
     }
 }
 
+impl Handler<NodeBannedMsg> for Hopper {
+    type Result = ();
+
+    fn handle(&mut self, msg: NodeBannedMsg, _ctx: &mut Self::Context) -> Self::Result {
+        self.replay_windows.remove (&PackageSender::Node (msg.public_key.clone ()));
+        if let Some (ip_addr) = msg.ip_addr_opt {
+            self.banned_ips.insert (ip_addr);
+            self.replay_windows.retain (|sender, _| match *sender {
+                PackageSender::Address (socket_addr) => socket_addr.ip () != ip_addr,
+                PackageSender::Node (_) => true,
+            });
+        }
+        self.compression_keys.remove (&msg.public_key);
+        self.outgoing_sequences.remove (&msg.public_key);
+        ()
+    }
+}
+
+impl Handler<NodeUnbannedMsg> for Hopper {
+    type Result = ();
+
+    fn handle(&mut self, msg: NodeUnbannedMsg, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some (ip_addr) = msg.ip_addr_opt {
+            self.banned_ips.remove (&ip_addr);
+        }
+        ()
+    }
+}
+
+impl Handler<ServiceStandingMessage> for Hopper {
+    type Result = ();
+
+    fn handle(&mut self, msg: ServiceStandingMessage, _ctx: &mut Self::Context) -> Self::Result {
+        match msg.standing {
+            ServiceStanding::Good => {
+                self.service_standings.remove (&msg.consuming_node_key);
+                self.refusals_sent.remove (&msg.consuming_node_key);
+            },
+            standing => {self.service_standings.insert (msg.consuming_node_key, standing);},
+        }
+        ()
+    }
+}
+
 impl Hopper {
-    pub fn new (cryptde: &'static CryptDE) -> Hopper {
+    pub fn new (cryptde: &'static CryptDE, config: HopperConfig) -> Hopper {
+        let mixer = match config.mix_delay {
+            MixDelay::Off => None,
+            delay => Some (Mixer::new (delay)),
+        };
         Hopper {
             cryptde,
             to_proxy_server: None,
             to_proxy_client: None,
             to_dispatcher: None,
+            to_neighborhood: None,
+            to_neighborhood_reports: None,
+            to_accountant: None,
+            to_accountant_packages: None,
+            config,
+            initial_sequence: Hopper::initial_sequence (),
+            outgoing_sequences: HashMap::new (),
+            replay_windows: HashMap::new (),
+            compression_keys: HashSet::new (),
+            banned_ips: HashSet::new (),
+            service_standings: HashMap::new (),
+            refusals_sent: HashMap::new (),
+            mixer,
             logger: Logger::new ("Hopper"),
         }
     }
@@ -155,24 +348,23 @@ impl Hopper {
             bind: addr.clone ().recipient::<BindMessage>(),
             from_hopper_client: addr.clone ().recipient::<IncipientCoresPackage>(),
             from_dispatcher: addr.clone ().recipient::<InboundClientData>(),
+            node_banned: addr.clone ().recipient::<NodeBannedMsg>(),
+            node_unbanned: addr.clone ().recipient::<NodeUnbannedMsg>(),
+            service_standing: addr.clone ().recipient::<ServiceStandingMessage>(),
         }
     }
 
     // TODO when we are decentralized, change this type to a TransmitDataMsg
-    pub fn to_transmit_msg (&self, live_package: LiveCoresPackage, last_data: bool) -> Result<HopperTemporaryTransmitDataMsg, CryptdecError> {
-        let (next_key, next_live_package) = match live_package.to_next_live (self.cryptde.borrow ()) {
-            // crashpoint - log error and return None?
-            Err (_) => unimplemented! (),
-            Ok (p) => p
-        };
-        let next_live_package_ser = match serde_cbor::ser::to_vec (&next_live_package) {
-            // crashpoint - log error and return None?
-            Err (_) => unimplemented! (),
-            Ok (p) => p
-        };
-        let next_live_package_enc = match self.cryptde.encode (&next_key, &PlainData::new (&next_live_package_ser[..])) {
-            // crashpoint - log error and return None?
-            Err (_) => unimplemented! (),
+    pub fn to_transmit_msg (&mut self, live_package: LiveCoresPackage, last_data: bool) -> Result<HopperTemporaryTransmitDataMsg, CryptdecError> {
+        let (next_key, next_live_package) = live_package.to_next_live (self.cryptde.borrow ())?;
+        let known_key = self.outgoing_sequences.contains_key (&next_key);
+        let sequence = self.next_sequence (&next_key);
+        let next_live_package_enc = match next_live_package.seal (sequence, &self.seal_options_for (&next_key), &next_key, self.cryptde.borrow ()) {
+            Err (e) => {
+                // A next hop whose key can't be sealed for isn't a neighbor to send cover traffic to
+                if !known_key {self.outgoing_sequences.remove (&next_key);}
+                return Err (CryptdecError::InvalidKey (format! ("Couldn't seal package for next hop: {:?}", e)))
+            },
             Ok (p) => p
         };
         // TODO when we are decentralized, change this to a TransmitDataMsg
@@ -182,17 +374,269 @@ impl Hopper {
             data: next_live_package_enc.data
         })
     }
+
+    fn next_sequence (&mut self, key: &Key) -> u64 {
+        let initial_sequence = self.initial_sequence;
+        let sequence = self.outgoing_sequences.entry (key.clone ()).or_insert (initial_sequence);
+        *sequence += 1;
+        *sequence
+    }
+
+    // Starting from the clock keeps a restarted Node's sequence numbers ahead of the ones its
+    // neighbors have already seen from it
+    fn initial_sequence () -> u64 {
+        match SystemTime::now ().duration_since (UNIX_EPOCH) {
+            Ok (d) => d.as_secs () * 1_000_000 + (d.subsec_nanos () / 1_000) as u64,
+            Err (_) => 0
+        }
+    }
+
+    // Cover traffic goes only to neighbors we've already sent real packages to, so it doesn't
+    // reveal links that would otherwise be idle
+    fn send_cover_traffic (&mut self) {
+        let keys: Vec<Key> = self.outgoing_sequences.keys ().cloned ().collect ();
+        for key in keys {
+            let sequence = self.next_sequence (&key);
+            let cover_enc = match seal_cover (sequence, &self.seal_options_for (&key), &key, self.cryptde.borrow ()) {
+                Err (_) => {
+                    self.logger.error (format! ("Couldn't seal cover traffic"));
+                    continue
+                },
+                Ok (c) => c
+            };
+            self.to_dispatcher.as_ref().expect("Dispatcher unbound in Hopper").try_send(HopperTemporaryTransmitDataMsg {
+                endpoint: Endpoint::Key (key),
+                last_data: false,
+                data: cover_enc.data,
+            }).expect("Dispatcher is dead");
+        }
+    }
+
+    // A neighbor only gets compressed packages after it has told us it can read them, so Nodes
+    // that predate compression keep working. The claim isn't authenticated, but a neighbor that
+    // lies about someone else can do no more harm than a neighbor that drops their packages.
+    fn seal_options_for (&self, key: &Key) -> SealOptions {
+        SealOptions {
+            padded: self.config.pad_packages,
+            compressed: self.config.compress_packages && self.compression_keys.contains (key),
+            compression_key: if self.config.compress_packages {Some (self.cryptde.public_key ())} else {None},
+        }
+    }
+
+    fn relay (&mut self, transmit_msg: HopperTemporaryTransmitDataMsg, ctx: &mut Context<Hopper>) {
+        if self.mixer.is_none () {
+            return self.send_relayed (transmit_msg)
+        }
+        // Everything for the same next hop is one stream to the mixer, so no link sees its
+        // packages out of order
+        let next_hop = transmit_msg.endpoint.clone ();
+        let release_delay_opt = self.mixer.as_mut ().expect ("Mixer disappeared").hold (next_hop, transmit_msg);
+        if let Some (release_delay) = release_delay_opt {
+            ctx.run_later (release_delay, |hopper, _ctx| hopper.release_mixed ());
+        }
+    }
+
+    fn release_mixed (&mut self) {
+        let batch = match self.mixer {
+            None => return,
+            Some (ref mut mixer) => mixer.release (),
+        };
+        self.logger.debug (format! ("Releasing a batch of {} mixed packages", batch.len ()));
+        batch.into_iter ().for_each (|transmit_msg| self.send_relayed (transmit_msg));
+    }
+
+    fn send_incipient (&mut self, msg: IncipientCoresPackage) {
+        let (live_package, key) = LiveCoresPackage::from_incipient(msg, self.cryptde.borrow());
+
+        let sequence = self.next_sequence (&key);
+        let encrypted_package = match live_package.seal (sequence, &self.seal_options_for (&key), &key, self.cryptde.borrow ()) {
+            Ok(package) => package,
+            Err (SealError::Encryption) => {
+                self.logger.error(format! ("Couldn't encrypt package"));
+                // TODO what should we do here? (nothing is unbound --so we don't need to blow up-- but we can't send this package)
+                return ()
+            },
+            Err(_) => {
+                self.logger.error(format! ("Couldn't serialize package"));
+                // TODO what should we do here? (nothing is unbound --so we don't need to blow up-- but we can't send this package)
+                return ()
+            }
+        };
+
+        // TODO when we are decentralized, change this to a TransmitDataMsg
+        let transmit_msg = HopperTemporaryTransmitDataMsg {
+            endpoint: Endpoint::Key(key),
+            last_data: false, // Hopper-to-Hopper streams are never remotely killed
+            data: encrypted_package.data,
+        };
+
+        self.logger.debug (format! ("Sending TransmitDataMsg with {}-byte payload to Dispatcher", transmit_msg.data.len ()));
+        traffic_stats::count_bytes_originated (transmit_msg.data.len ());
+        self.to_dispatcher.as_ref().expect("Dispatcher unbound in Hopper").try_send(transmit_msg).expect("Dispatcher is dead");
+    }
+
+    fn send_to_proxy_client (&self, expired_package: ExpiredCoresPackage) {
+        self.to_proxy_client.as_ref ().expect ("ProxyClient unbound in Hopper").try_send (expired_package).expect ("Proxy Client is dead")
+    }
+
+    fn refuse_exit (&mut self, expired_package: ExpiredCoresPackage) {
+        let request = match expired_package.payload::<ClientRequestPayload> () {
+            Ok (request) => request,
+            Err (_) => {
+                self.logger.error (format! ("Couldn't tell the consuming Node its request was refused: ClientRequestPayload is not OK"));
+                return
+            }
+        };
+        let response = ClientResponsePayload {
+            stream_key: request.stream_key,
+            last_response: true,
+            data: PlainData::new (&[]),
+            failure: Some (ExitFailure::ServiceRefused),
+        };
+        self.send_incipient (IncipientCoresPackage::new (expired_package.remaining_route, response, &request.originator_public_key))
+    }
+
+    // Sent no more often than once per REFUSAL_NOTICE_INTERVAL_MS, however many packages are refused
+    fn refuse_relay (&mut self, consuming_node_key: Key) {
+        let now = Instant::now ();
+        let due = match self.refusals_sent.get (&consuming_node_key) {
+            Some (sent_at) => now.duration_since (*sent_at) >= Duration::from_millis (REFUSAL_NOTICE_INTERVAL_MS),
+            None => true
+        };
+        if !due {return}
+        let route = match Route::new (vec! (
+            RouteSegment::new (vec! (&self.cryptde.public_key (), &consuming_node_key), Component::Accountant)
+        ), self.cryptde) {
+            Ok (route) => route,
+            Err (e) => {
+                self.logger.error (format! ("Couldn't tell the consuming Node its packages are refused: {:?}", e));
+                return
+            }
+        };
+        let refusal = ServiceRefusal {refusing_key: self.cryptde.public_key (), refused_key: consuming_node_key.clone ()};
+        self.refusals_sent.insert (consuming_node_key.clone (), now);
+        self.send_incipient (IncipientCoresPackage::new (route, refusal, &consuming_node_key))
+    }
+
+    fn standing_of (&self, hop: &Hop) -> ServiceStanding {
+        match self.billable_key (hop) {
+            Some (ref consuming_node_key) => self.service_standings.get (consuming_node_key).cloned ().unwrap_or (ServiceStanding::Good),
+            None => ServiceStanding::Good
+        }
+    }
+
+    // Service is paid for by the Node that built the route, if it signed the hop to prove it did.
+    // Anybody can put anybody's key in a route; serving ourselves earns nothing.
+    fn billable_key (&self, hop: &Hop) -> Option<Key> {
+        match hop.proven_consuming_key (self.cryptde.borrow ()) {
+            Some (ref consuming_node_key) if consuming_node_key == &self.cryptde.public_key () => None,
+            proven_key_opt => proven_key_opt
+        }
+    }
+
+    fn send_relayed (&self, transmit_msg: HopperTemporaryTransmitDataMsg) {
+        self.logger.debug (format! ("Relaying {}-byte LiveCoresPackage Dispatcher inside a TransmitDataMsg", transmit_msg.data.len ()));
+        traffic_stats::count_bytes_relayed (transmit_msg.data.len ());
+        self.to_dispatcher.as_ref().expect("Dispatcher unbound in Hopper").try_send(transmit_msg).expect("Dispatcher is dead");
+    }
+
+    // Relaying for a Node too old to say who it is, or unable to prove it, earns nothing
+    fn report_routing_service (&self, hop: &Hop, neighbor_ip: IpAddr, payload_size: usize) {
+        if let Some (consuming_node_key) = self.billable_key (hop) {
+            self.to_accountant.as_ref ().expect ("Accountant unbound in Hopper").try_send (ReportRoutingServiceMessage {
+                consuming_node_key,
+                neighbor_ip,
+                payload_size,
+            }).expect ("Accountant is dead")
+        }
+    }
+
+    // A neighbor can seal anything it likes for this Node, so what's inside the seal can still be garbage
+    fn reject_malformed (&self, misbehaver: Misbehaver, socket_addr: SocketAddr, error: CryptdecError) {
+        self.logger.error (format! ("Couldn't read package from neighbor at {}: {:?}", socket_addr, error));
+        self.report_misbehavior (misbehaver, NeighborMisbehavior::MalformedPackage)
+    }
+
+    fn report_misbehavior (&self, misbehaver: Misbehaver, misbehavior: NeighborMisbehavior) {
+        self.to_neighborhood_reports.as_ref().expect("Neighborhood unbound in Hopper").try_send(NeighborMisbehaviorMessage {
+            misbehaver,
+            misbehavior,
+        }).expect("Neighborhood is dead");
+    }
+}
+
+// How long relaying and exiting for a throttled Node is held back
+const THROTTLE_DELAY_MS: u64 = 1000;
+// How often a refused Node whose packages keep coming is told again that they're refused
+const REFUSAL_NOTICE_INTERVAL_MS: u64 = 60000;
+
+// Nothing a neighbor sends is bigger than a full load of Gossip with its route and seal, so anything
+// bigger is dropped before it's decrypted or deserialized
+pub const MAX_PACKAGE_BYTES: usize = MAX_GOSSIP_BYTES + 65536;
+
+// Relays allowed before a package is dropped; no legitimate route comes close to this
+pub const DEFAULT_PACKAGE_TTL: u8 = 32;
+
+// Padded packages grow to the smallest of these sizes that holds them, or to a multiple of the largest
+pub const PADDING_BUCKETS: [usize; 4] = [512, 2048, 8192, 32768];
+
+// Binds the encryption to what it holds, so nothing else encrypted for a Node opens as a package
+const SEAL_ASSOCIATED_DATA: &[u8] = b"LiveCoresPackage";
+const SEAL_SEQUENCE_LENGTH: usize = 8;
+const SEAL_SIZE_LENGTH: usize = 4;
+const SEAL_HEADER_LENGTH: usize = SEAL_SEQUENCE_LENGTH + 1 + SEAL_SIZE_LENGTH;
+const SEALED_PACKAGE: u8 = 0;
+const SEALED_COVER: u8 = 1;
+const SEALED_COMPRESSED_PACKAGE: u8 = 2;
+// Follows the sealed contents, where Nodes that predate it see only padding
+const COMPRESSION_ADVERTISEMENT: u8 = 1;
+// Leads every serialized LiveCoresPackage. Nodes that predate it start right in with a CBOR map,
+// whose first byte is always in 0xA0-0xBF, so versions must stay below that.
+pub const PACKAGE_FORMAT_VERSION: u8 = 1;
+const CBOR_MAJOR_TYPE_MAP: u8 = 5;
+
+#[derive (Clone, Debug, PartialEq)]
+pub struct SealOptions {
+    pub padded: bool,
+    pub compressed: bool,
+    // public key of a sender that can read compressed packages
+    pub compression_key: Option<Key>,
+}
+
+impl SealOptions {
+    pub fn plain () -> SealOptions {
+        SealOptions {padded: false, compressed: false, compression_key: None}
+    }
 }
 
-#[derive (Clone, PartialEq, Serialize, Deserialize)]
+#[derive (Clone, Debug, PartialEq)]
+pub struct Unsealed {
+    pub sequence: u64,
+    // None if the sender sealed cover traffic
+    pub package: Option<LiveCoresPackage>,
+    pub compression_key: Option<Key>,
+}
+
+#[derive (Clone, Debug, PartialEq)]
+pub enum SealError {
+    Serialization,
+    Encryption,
+    IntegrityCheckFailed,
+    Deserialization,
+}
+
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct LiveCoresPackage {
     pub route: Route,
-    pub payload: CryptData
+    pub payload: CryptData,
+    // Nodes that predate the TTL send none
+    #[serde (default = "default_package_ttl")]
+    pub ttl: u8,
 }
 
 impl LiveCoresPackage {
     pub fn new (route: Route, payload: CryptData) -> LiveCoresPackage {
-        LiveCoresPackage { route, payload}
+        LiveCoresPackage { route, payload, ttl: DEFAULT_PACKAGE_TTL}
     }
 
     pub fn from_incipient (incipient: IncipientCoresPackage, cryptde: &CryptDE) -> (LiveCoresPackage, Key) {
@@ -208,59 +652,212 @@ impl LiveCoresPackage {
         (LiveCoresPackage::new (route, encrypted_payload), next_hop.public_key)
     }
 
-    pub fn to_expired (self, cryptde: &CryptDE) -> ExpiredCoresPackage {
-        let payload = match cryptde.decode (&cryptde.private_key (), &self.payload) {
-            Ok (payload) => payload,
-            // crashpoint - should discuss as a team
-            Err (e) => panic! ("{:?}", e)
-        };
-        ExpiredCoresPackage::new (self.route, payload)
+    pub fn to_expired (self, cryptde: &CryptDE) -> Result<ExpiredCoresPackage, CryptdecError> {
+        let payload = cryptde.decode (&cryptde.private_key (), &self.payload)?;
+        Ok (ExpiredCoresPackage::new (self.route, payload))
     }
 
     pub fn to_next_live (mut self, cryptde: &CryptDE) -> Result<(Key, LiveCoresPackage), CryptdecError> {
         let next_hop = match self.route.shift (&cryptde.private_key (), cryptde) {
-            // crashpoint - should discuss as a team
-            None => unimplemented! (),
+            None => return Err (CryptdecError::InvalidData (String::from ("Route has no next hop for this Node"))),
             Some (h) => h
         };
         let next_key = next_hop.public_key;
-        let next_live = LiveCoresPackage::new (self.route, self.payload);
+        let next_live = LiveCoresPackage {route: self.route, payload: self.payload, ttl: self.ttl.saturating_sub (1)};
         Ok ((next_key, next_live))
     }
 
-    pub fn next_hop (&self, cryptde: &CryptDE) -> Hop {
+    // The package is sealed with authenticated encryption for the next hop, so a neighbor that
+    // alters the encrypted bytes in transit can't produce a package that passes unseal. The link
+    // sequence number is inside the encryption too, so replays can't be disguised as new packages.
+    pub fn seal (&self, sequence: u64, options: &SealOptions, key: &Key, cryptde: &CryptDE) -> Result<CryptData, SealError> {
+        let serialized_package = serialize_package (self)?;
+        if options.compressed {
+            let compressed_package = lz4_compress::compress (&serialized_package[..]);
+            if compressed_package.len () < serialized_package.len () {
+                return encrypt_contents (&seal_contents (sequence, SEALED_COMPRESSED_PACKAGE, &compressed_package[..], options), key, cryptde)
+            }
+        }
+        encrypt_contents (&seal_contents (sequence, SEALED_PACKAGE, &serialized_package[..], options), key, cryptde)
+    }
+
+    pub fn unseal (sealed_package: &CryptData, key: &Key, cryptde: &CryptDE) -> Result<Unsealed, SealError> {
+        match cryptde.decrypt (key, SEAL_ASSOCIATED_DATA, sealed_package) {
+            Err (_) => Err (SealError::IntegrityCheckFailed),
+            Ok (contents) => unseal_contents (&contents)
+        }
+    }
+
+    pub fn next_hop (&self, cryptde: &CryptDE) -> Result<Hop, CryptdecError> {
         match self.route.next_hop (&cryptde.private_key (), cryptde) {
-            // crashpoint - should discuss as a team
-            None => unimplemented! (),
-            Some (h) => h
+            None => Err (CryptdecError::InvalidData (String::from ("Route has no next hop for this Node"))),
+            Some (h) => Ok (h)
         }
     }
 }
 
+pub fn seal_cover (sequence: u64, options: &SealOptions, key: &Key, cryptde: &CryptDE) -> Result<CryptData, SealError> {
+    encrypt_contents (&seal_contents (sequence, SEALED_COVER, &[], options), key, cryptde)
+}
+
+fn encrypt_contents (contents: &PlainData, key: &Key, cryptde: &CryptDE) -> Result<CryptData, SealError> {
+    cryptde.encrypt (key, SEAL_ASSOCIATED_DATA, contents).map_err (|_| SealError::Encryption)
+}
+
+fn unseal_contents (sealed_contents: &PlainData) -> Result<Unsealed, SealError> {
+    if sealed_contents.data.len () < SEAL_HEADER_LENGTH {
+        return Err (SealError::Deserialization)
+    }
+    let (sequence_bytes, contents) = sealed_contents.data.split_at (SEAL_SEQUENCE_LENGTH);
+    let (kind_bytes, contents) = contents.split_at (1);
+    let (size_bytes, contents) = contents.split_at (SEAL_SIZE_LENGTH);
+    let sequence = from_big_endian (sequence_bytes);
+    let size = from_big_endian (size_bytes) as usize;
+    if size > contents.len () {
+        return Err (SealError::Deserialization)
+    }
+    let (contents, trailer) = contents.split_at (size);
+    let package = match kind_bytes[0] {
+        SEALED_COVER => None,
+        SEALED_PACKAGE => Some (deserialize_package (contents)?),
+        SEALED_COMPRESSED_PACKAGE => match decompression::decompress (contents, MAX_PACKAGE_BYTES) {
+            Err (_) => return Err (SealError::Deserialization),
+            Ok (decompressed) => Some (deserialize_package (&decompressed[..])?)
+        },
+        _ => return Err (SealError::Deserialization)
+    };
+    Ok (Unsealed {sequence, package, compression_key: read_compression_advertisement (trailer)})
+}
+
+fn seal_contents (sequence: u64, kind: u8, serialized: &[u8], options: &SealOptions) -> PlainData {
+    let mut contents = to_big_endian (sequence, SEAL_SEQUENCE_LENGTH);
+    contents.push (kind);
+    contents.extend (to_big_endian (serialized.len () as u64, SEAL_SIZE_LENGTH));
+    contents.extend_from_slice (serialized);
+    if let Some (ref key) = options.compression_key {
+        contents.push (COMPRESSION_ADVERTISEMENT);
+        contents.extend (to_big_endian (key.data.len () as u64, 2));
+        contents.extend_from_slice (&key.data[..]);
+    }
+    if options.padded {
+        let padded_length = padded_length (contents.len ());
+        contents.resize (padded_length, 0);
+    }
+    PlainData::new (&contents[..])
+}
+
+fn serialize_package (package: &LiveCoresPackage) -> Result<Vec<u8>, SealError> {
+    match serde_cbor::ser::to_vec (package) {
+        Ok (serialized) => Ok ([&[PACKAGE_FORMAT_VERSION][..], &serialized[..]].concat ()),
+        Err (_) => Err (SealError::Serialization)
+    }
+}
+
+// Within a format version, fields are only ever added to a LiveCoresPackage, never changed, so
+// fields this Node doesn't know are skipped. A format version it doesn't know may mean anything.
+fn deserialize_package (serialized: &[u8]) -> Result<LiveCoresPackage, SealError> {
+    let cbor = match serialized.first () {
+        None => return Err (SealError::Deserialization),
+        Some (first) if (first >> 5) == CBOR_MAJOR_TYPE_MAP => serialized,
+        Some (&PACKAGE_FORMAT_VERSION) => &serialized[1..],
+        Some (_) => return Err (SealError::Deserialization),
+    };
+    serde_cbor::de::from_slice::<LiveCoresPackage> (cbor).map_err (|_| SealError::Deserialization)
+}
+
+fn default_package_ttl () -> u8 {
+    DEFAULT_PACKAGE_TTL
+}
+
+fn read_compression_advertisement (trailer: &[u8]) -> Option<Key> {
+    if trailer.len () < 3 || trailer[0] != COMPRESSION_ADVERTISEMENT {
+        return None
+    }
+    let key_length = from_big_endian (&trailer[1..3]) as usize;
+    if key_length == 0 || trailer.len () < 3 + key_length {
+        return None
+    }
+    Some (Key::new (&trailer[3..(3 + key_length)]))
+}
+
+fn padded_length (length: usize) -> usize {
+    match PADDING_BUCKETS.iter ().find (|bucket| **bucket >= length) {
+        Some (bucket) => *bucket,
+        None => {
+            let largest = PADDING_BUCKETS[PADDING_BUCKETS.len () - 1];
+            ((length + largest - 1) / largest) * largest
+        }
+    }
+}
+
+fn to_big_endian (value: u64, length: usize) -> Vec<u8> {
+    (0..length).rev ().map (|i| (value >> (i * 8)) as u8).collect ()
+}
+
+fn from_big_endian (bytes: &[u8]) -> u64 {
+    bytes.iter ().fold (0u64, |sofar, byte| (sofar << 8) | (*byte as u64))
+}
+
 #[cfg (test)]
 mod tests {
     use super::*;
     use std::net::SocketAddr;
     use std::str::FromStr;
     use std::thread;
+    use std::time::Instant;
     use actix::Actor;
     use actix::Arbiter;
     use actix::msgs;
     use actix::System;
     use sub_lib::cryptde::PlainData;
+    use sub_lib::cryptde_null::CryptDENull;
+    use sub_lib::cryptde_real::CryptDEReal;
     use sub_lib::dispatcher::Component;
     use sub_lib::hopper::ExpiredCoresPackage;
     use sub_lib::hopper::HopperTemporaryTransmitDataMsg;
     use sub_lib::hopper::IncipientCoresPackage;
+    use sub_lib::neighborhood::Misbehaver;
+    use sub_lib::neighborhood::NeighborMisbehavior;
+    use sub_lib::neighborhood::NeighborMisbehaviorMessage;
+    use sub_lib::proxy_server::ProxyProtocol;
     use sub_lib::route::Route;
     use sub_lib::route::RouteSegment;
     use test_utils::test_utils::PayloadMock;
     use test_utils::test_utils::Recorder;
     use test_utils::test_utils::cryptde;
+    use test_utils::test_utils::init_test_logging;
     use test_utils::test_utils::make_peer_actors_from;
+    use test_utils::test_utils::TestLogHandler;
     use test_utils::test_utils::route_to_proxy_client;
     use test_utils::test_utils::route_to_proxy_server;
 
+    fn plain_config () -> HopperConfig {
+        HopperConfig {
+            pad_packages: false,
+            cover_traffic_interval_ms: 0,
+            mix_delay: MixDelay::Off,
+            compress_packages: false,
+            originate_only: false,
+        }
+    }
+
+    fn padded_options () -> SealOptions {
+        SealOptions {padded: true, ..SealOptions::plain ()}
+    }
+
+    fn plain_unsealed (sequence: u64, package: Option<LiveCoresPackage>) -> Unsealed {
+        Unsealed {sequence, package, compression_key: None}
+    }
+
+    fn unseal_transmitted (data: &Vec<u8>, key: &Key, cryptde: &CryptDE) -> (u64, Option<LiveCoresPackage>) {
+        let unsealed = LiveCoresPackage::unseal (&CryptData::new (&data[..]), &CryptDENull::other_key (key), cryptde).unwrap ();
+        (unsealed.sequence, unsealed.package)
+    }
+
+    fn opened (sealed: &CryptData) -> PlainData {
+        cryptde ().decrypt (&cryptde ().private_key (), SEAL_ASSOCIATED_DATA, sealed).unwrap ()
+    }
+
     #[test]
     fn live_cores_package_can_be_constructed_from_scratch () {
         let payload = CryptData::new (&[5, 6]);
@@ -299,42 +896,690 @@ mod tests {
     }
 
     #[test]
-    fn converts_incipient_message_to_live_and_sends_to_dispatcher () {
+    fn live_cores_package_survives_seal_and_unseal () {
         let cryptde = cryptde();
-        let dispatcher = Recorder::new ();
-        let dispatcher_recording_arc = dispatcher.get_recording ();
-        let dispatcher_awaiter = dispatcher.get_awaiter();
-        let destination_key = Key::new (&[65, 65, 65]);
-        let route = Route::new (
-            vec! (RouteSegment::new (vec! (&cryptde.public_key (), &destination_key.clone ()), Component::Neighborhood)),
-            cryptde
-        ).unwrap ();
-        let payload = PlainData::new (&b"abcd"[..]);
-        let incipient_cores_package = IncipientCoresPackage::new (route.clone (),
-            payload, &destination_key);
-        let incipient_cores_package_a = incipient_cores_package.clone ();
-        thread::spawn (move || {
-            let system = System::new ("converts_incipient_message_to_live_and_sends_to_dispatcher");
-            let peer_actors = make_peer_actors_from(None, Some(dispatcher), None, None, None);
-            let subject = Hopper::new (cryptde);
-            let subject_addr: Addr<Syn, Hopper> = subject.start ();
-            subject_addr.try_send (BindMessage {peer_actors}).unwrap ();
+        let route = route_to_proxy_client(&cryptde.public_key (), cryptde);
+        let subject = LiveCoresPackage::new (route, CryptData::new (&b"payload"[..]));
 
-            subject_addr.try_send (incipient_cores_package).unwrap ();
+        let sealed = subject.seal (0x0102030405060708, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
 
-            system.run ();
-        });
-        dispatcher_awaiter.await_message_count(1);
+        let result = LiveCoresPackage::unseal (&sealed, &cryptde.private_key (), cryptde).unwrap ();
+
+        assert_eq! (result, plain_unsealed (0x0102030405060708, Some (subject)));
+    }
+
+    #[derive (Serialize)]
+    struct UnversionedLiveCoresPackage {
+        route: Route,
+        payload: CryptData,
+    }
+
+    #[derive (Serialize)]
+    struct FutureLiveCoresPackage {
+        route: Route,
+        payload: CryptData,
+        ttl: u8,
+        priority: u8,
+    }
+
+    #[test]
+    fn serialized_packages_lead_with_the_format_version () {
+        let cryptde = cryptde();
+        let route = route_to_proxy_client(&cryptde.public_key (), cryptde);
+        let subject = LiveCoresPackage::new (route, CryptData::new (&b"payload"[..]));
+
+        let serialized = serialize_package (&subject).unwrap ();
+
+        assert_eq! (serialized[0], PACKAGE_FORMAT_VERSION);
+        assert_eq! (deserialize_package (&serialized[..]), Ok (subject));
+    }
+
+    #[test]
+    fn packages_from_nodes_that_predate_the_format_version_still_deserialize () {
+        let cryptde = cryptde();
+        let route = route_to_proxy_client(&cryptde.public_key (), cryptde);
+        let fixture = serde_cbor::ser::to_vec (&UnversionedLiveCoresPackage {route: route.clone (), payload: CryptData::new (&b"payload"[..])}).unwrap ();
+
+        let result = deserialize_package (&fixture[..]);
+
+        assert_eq! (result, Ok (LiveCoresPackage {route, payload: CryptData::new (&b"payload"[..]), ttl: DEFAULT_PACKAGE_TTL}));
+    }
+
+    #[test]
+    fn packages_deserialize_without_the_fields_this_version_does_not_know () {
+        let cryptde = cryptde();
+        let route = route_to_proxy_client(&cryptde.public_key (), cryptde);
+        let future = FutureLiveCoresPackage {route: route.clone (), payload: CryptData::new (&b"payload"[..]), ttl: 7, priority: 3};
+        let fixture = [&[PACKAGE_FORMAT_VERSION][..], &serde_cbor::ser::to_vec (&future).unwrap ()[..]].concat ();
+
+        let result = deserialize_package (&fixture[..]);
+
+        assert_eq! (result, Ok (LiveCoresPackage {route, payload: CryptData::new (&b"payload"[..]), ttl: 7}));
+    }
+
+    #[test]
+    fn packages_in_format_versions_this_version_does_not_know_are_rejected () {
+        let cryptde = cryptde();
+        let route = route_to_proxy_client(&cryptde.public_key (), cryptde);
+        let future = FutureLiveCoresPackage {route, payload: CryptData::new (&b"payload"[..]), ttl: 7, priority: 3};
+        let serialized = serde_cbor::ser::to_vec (&future).unwrap ();
+        let fixture = |version: u8| [&[version][..], &serialized[..]].concat ();
+
+        assert_eq! (deserialize_package (&fixture (PACKAGE_FORMAT_VERSION + 1)[..]), Err (SealError::Deserialization));
+        assert_eq! (deserialize_package (&fixture (0)[..]), Err (SealError::Deserialization));
+    }
+
+    #[test]
+    fn deserialize_package_rejects_nothing_at_all () {
+        assert_eq! (deserialize_package (&[]), Err (SealError::Deserialization));
+        assert_eq! (deserialize_package (&[PACKAGE_FORMAT_VERSION]), Err (SealError::Deserialization));
+    }
+
+    #[test]
+    fn unseal_rejects_altered_package () {
+        let cryptde = cryptde();
+        let route = route_to_proxy_client(&cryptde.public_key (), cryptde);
+        let subject = LiveCoresPackage::new (route, CryptData::new (&b"payload"[..]));
+        let mut real_cryptde = CryptDEReal::new ();
+        real_cryptde.generate_key_pair ();
+        let mut sealed = subject.seal (1, &SealOptions::plain (), &real_cryptde.public_key (), &real_cryptde).unwrap ();
+        let last_index = sealed.data.len () - 1;
+        sealed.data[last_index] ^= 0x01;
+
+        let result = LiveCoresPackage::unseal (&sealed, &real_cryptde.private_key (), &real_cryptde);
+
+        assert_eq! (result.err ().unwrap (), SealError::IntegrityCheckFailed);
+    }
+
+    #[test]
+    fn unseal_rejects_package_too_short_to_decrypt () {
+        let cryptde = cryptde();
+
+        let result = LiveCoresPackage::unseal (&CryptData::new (&b"short"[..]), &cryptde.private_key (), cryptde);
+
+        assert_eq! (result.err ().unwrap (), SealError::IntegrityCheckFailed);
+    }
+
+    #[test]
+    fn drops_oversized_inbound_package_unread_and_reports_the_neighbor () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let neighborhood = Recorder::new ();
+        let neighborhood_recording_arc = neighborhood.get_recording ();
+        let neighborhood_awaiter = neighborhood.get_awaiter ();
+        let component = Recorder::new ();
+        let component_recording_arc = component.get_recording ();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let inbound_client_data = InboundClientData {
+            socket_addr,
+            origin_port: None,
+            peer_public_key_opt: Some (Key::new (b"neighbor")),
+            component: Component::Hopper,
+            last_data: false,
+            data: vec! (0u8; MAX_PACKAGE_BYTES + 1)
+        };
+        thread::spawn(move || {
+            let system = System::new("drops_oversized_inbound_package_unread_and_reports_the_neighbor");
+            let peer_actors = make_peer_actors_from(None, None, None, Some(component), Some (neighborhood), None);
+            let subject = Hopper::new (cryptde, plain_config ());
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(inbound_client_data ).unwrap ();
+
+            system.run();
+        });
+        neighborhood_awaiter.await_message_count(1);
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        assert_eq! (neighborhood_recording.get_record::<NeighborMisbehaviorMessage>(0), &NeighborMisbehaviorMessage {
+            misbehaver: Misbehaver::Node (Key::new (b"neighbor")),
+            misbehavior: NeighborMisbehavior::OversizedPackage,
+        });
+        assert_eq! (component_recording_arc.lock ().unwrap ().len (), 0);
+        TestLogHandler::new ().exists_log_containing (&format! ("Dropped {}-byte package from neighbor at 1.2.3.4:5678: nothing legitimate is that big", MAX_PACKAGE_BYTES + 1));
+    }
+
+    #[test]
+    fn rejects_tampered_inbound_package_and_reports_the_neighbor () {
+        init_test_logging ();
+        let cryptde = cryptde();
+        let neighborhood = Recorder::new ();
+        let neighborhood_recording_arc = neighborhood.get_recording ();
+        let neighborhood_awaiter = neighborhood.get_awaiter ();
+        let component = Recorder::new ();
+        let component_recording_arc = component.get_recording ();
+        let route = route_to_proxy_client(&cryptde.public_key (), cryptde);
+        let payload = PlainData::new (&b"abcd"[..]);
+        let lcp = LiveCoresPackage::new (route, cryptde.encode (&cryptde.public_key (), &payload).unwrap ());
+        let mut data_enc = lcp.seal (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
+        // CryptDENull authenticates only what follows its key, so that's where the tampering has to go
+        let authenticated_index = cryptde.private_key ().data.len ();
+        data_enc.data[authenticated_index] ^= 0x01;
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let inbound_client_data = InboundClientData {
+            socket_addr,
+            origin_port: None,
+            peer_public_key_opt: None,
+            component: Component::Hopper,
+            last_data: false,
+            data: data_enc.data
+        };
+        thread::spawn(move || {
+            let system = System::new("rejects_tampered_inbound_package_and_reports_the_neighbor");
+            let peer_actors = make_peer_actors_from(None, None, None, Some(component), Some (neighborhood), None);
+            let subject = Hopper::new (cryptde, plain_config ());
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(inbound_client_data ).unwrap ();
+
+            system.run();
+        });
+        neighborhood_awaiter.await_message_count(1);
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        assert_eq! (neighborhood_recording.get_record::<NeighborMisbehaviorMessage>(0), &NeighborMisbehaviorMessage {
+            misbehaver: Misbehaver::Address (socket_addr),
+            misbehavior: NeighborMisbehavior::TamperedPackage,
+        });
+        assert_eq! (component_recording_arc.lock ().unwrap ().len (), 0);
+        TestLogHandler::new ().exists_log_containing ("Rejected tampered package from neighbor at 1.2.3.4:5678");
+    }
+
+    #[test]
+    fn rejects_malformed_inbound_package_and_reports_the_neighbor () {
+        init_test_logging ();
+        let cryptde = cryptde();
+        let neighborhood = Recorder::new ();
+        let neighborhood_recording_arc = neighborhood.get_recording ();
+        let neighborhood_awaiter = neighborhood.get_awaiter ();
+        let component = Recorder::new ();
+        let component_recording_arc = component.get_recording ();
+        let data_sealed = seal_contents (1, SEALED_PACKAGE, &b"not a package"[..], &SealOptions::plain ());
+        let data_enc = encrypt_contents (&data_sealed, &cryptde.public_key (), cryptde).unwrap ();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let inbound_client_data = InboundClientData {
+            socket_addr,
+            origin_port: None,
+            peer_public_key_opt: Some (Key::new (b"neighbor")),
+            component: Component::Hopper,
+            last_data: false,
+            data: data_enc.data
+        };
+        thread::spawn(move || {
+            let system = System::new("rejects_malformed_inbound_package_and_reports_the_neighbor");
+            let peer_actors = make_peer_actors_from(None, None, None, Some(component), Some (neighborhood), None);
+            let subject = Hopper::new (cryptde, plain_config ());
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(inbound_client_data ).unwrap ();
+
+            system.run();
+        });
+        neighborhood_awaiter.await_message_count(1);
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        assert_eq! (neighborhood_recording.get_record::<NeighborMisbehaviorMessage>(0), &NeighborMisbehaviorMessage {
+            misbehaver: Misbehaver::Node (Key::new (b"neighbor")),
+            misbehavior: NeighborMisbehavior::MalformedPackage,
+        });
+        assert_eq! (component_recording_arc.lock ().unwrap ().len (), 0);
+        TestLogHandler::new ().exists_log_containing ("Couldn't deserialize package from neighbor at 1.2.3.4:5678");
+    }
+
+    fn rejects_well_sealed_garbage_and_keeps_running (test_name: &'static str, garbage: LiveCoresPackage) {
+        init_test_logging ();
+        let cryptde = cryptde();
+        let neighborhood = Recorder::new ();
+        let neighborhood_recording_arc = neighborhood.get_recording ();
+        let neighborhood_awaiter = neighborhood.get_awaiter ();
+        let proxy_server = Recorder::new ();
+        let proxy_server_recording_arc = proxy_server.get_recording ();
+        let proxy_server_awaiter = proxy_server.get_awaiter ();
+        let good_package = LiveCoresPackage::new (route_to_proxy_server (&cryptde.public_key (), cryptde),
+            cryptde.encode (&cryptde.public_key (), &PlainData::new (b"abcd")).unwrap ());
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let inbound_client_data = |sequence: u64, package: &LiveCoresPackage| InboundClientData {
+            socket_addr,
+            origin_port: None,
+            peer_public_key_opt: Some (Key::new (b"neighbor")),
+            component: Component::Hopper,
+            last_data: false,
+            data: package.seal (sequence, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ().data
+        };
+        let garbage_data = inbound_client_data (1, &garbage);
+        let good_data = inbound_client_data (2, &good_package);
+        thread::spawn(move || {
+            let system = System::new(test_name);
+            let peer_actors = make_peer_actors_from(Some (proxy_server), None, None, None, Some (neighborhood), None);
+            let subject = Hopper::new (cryptde, plain_config ());
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(garbage_data).unwrap ();
+            subject_addr.try_send(good_data).unwrap ();
+
+            system.run();
+        });
+        neighborhood_awaiter.await_message_count(1);
+        proxy_server_awaiter.await_message_count(1);
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        assert_eq! (neighborhood_recording.len (), 1);
+        assert_eq! (neighborhood_recording.get_record::<NeighborMisbehaviorMessage>(0), &NeighborMisbehaviorMessage {
+            misbehaver: Misbehaver::Node (Key::new (b"neighbor")),
+            misbehavior: NeighborMisbehavior::MalformedPackage,
+        });
+        let proxy_server_recording = proxy_server_recording_arc.lock().unwrap();
+        assert_eq! (proxy_server_recording.get_record::<ExpiredCoresPackage>(0), &good_package.to_expired (cryptde).unwrap ());
+        TestLogHandler::new ().exists_log_containing ("Couldn't read package from neighbor at 1.2.3.4:5678");
+    }
+
+    #[test]
+    fn rejects_well_sealed_package_whose_first_hop_is_not_a_hop () {
+        let cryptde = cryptde();
+        let not_a_hop = cryptde.encode (&cryptde.public_key (), &PlainData::new (b"not a hop")).unwrap ();
+        let garbage = LiveCoresPackage::new (Route {hops: vec! (not_a_hop)}, cryptde.encode (&cryptde.public_key (), &PlainData::new (b"abcd")).unwrap ());
+
+        rejects_well_sealed_garbage_and_keeps_running ("rejects_well_sealed_package_whose_first_hop_is_not_a_hop", garbage);
+    }
+
+    #[test]
+    fn rejects_well_sealed_package_with_an_empty_route () {
+        let cryptde = cryptde();
+        let garbage = LiveCoresPackage::new (Route {hops: vec! ()}, cryptde.encode (&cryptde.public_key (), &PlainData::new (b"abcd")).unwrap ());
+
+        rejects_well_sealed_garbage_and_keeps_running ("rejects_well_sealed_package_with_an_empty_route", garbage);
+    }
+
+    #[test]
+    fn rejects_well_sealed_package_whose_payload_is_not_for_this_node () {
+        let cryptde = cryptde();
+        let garbage = LiveCoresPackage::new (route_to_proxy_server (&cryptde.public_key (), cryptde), CryptData::new (b"not for this Node"));
+
+        rejects_well_sealed_garbage_and_keeps_running ("rejects_well_sealed_package_whose_payload_is_not_for_this_node", garbage);
+    }
+
+    #[test]
+    fn refuses_traffic_from_banned_nodes () {
+        init_test_logging ();
+        let cryptde = cryptde();
+        let component = Recorder::new ();
+        let component_recording_arc = component.get_recording ();
+        let route = route_to_proxy_client(&cryptde.public_key (), cryptde);
+        let payload = PlainData::new (&b"abcd"[..]);
+        let lcp = LiveCoresPackage::new (route, cryptde.encode (&cryptde.public_key (), &payload).unwrap ());
+        let data_enc = lcp.seal (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let inbound_client_data = InboundClientData {
+            socket_addr,
+            origin_port: None,
+            peer_public_key_opt: None,
+            component: Component::Hopper,
+            last_data: false,
+            data: data_enc.data
+        };
+        let system = System::new("refuses_traffic_from_banned_nodes");
+        let peer_actors = make_peer_actors_from(None, None, None, Some(component), None, None);
+        let subject = Hopper::new (cryptde, plain_config ());
+        let subject_addr: Addr<Syn, Hopper> = subject.start();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+        subject_addr.try_send(NodeBannedMsg {public_key: Key::new (b"banned"), ip_addr_opt: Some (socket_addr.ip ())}).unwrap ();
+        subject_addr.try_send(inbound_client_data).unwrap ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run();
+        assert_eq! (component_recording_arc.lock ().unwrap ().len (), 0);
+        TestLogHandler::new ().exists_log_containing ("bytes from banned Node at 1.2.3.4:5678");
+    }
+
+    #[test]
+    fn drops_replayed_inbound_package_and_reports_the_neighbor () {
+        init_test_logging ();
+        let cryptde = cryptde();
+        let neighborhood = Recorder::new ();
+        let neighborhood_recording_arc = neighborhood.get_recording ();
+        let neighborhood_awaiter = neighborhood.get_awaiter ();
+        let component = Recorder::new ();
+        let component_recording_arc = component.get_recording ();
+        let route = route_to_proxy_client(&cryptde.public_key (), cryptde);
+        let payload = PlainData::new (&b"abcd"[..]);
+        let lcp = LiveCoresPackage::new (route, cryptde.encode (&cryptde.public_key (), &payload).unwrap ());
+        let data_enc = lcp.seal (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let inbound_client_data = InboundClientData {
+            socket_addr,
+            origin_port: None,
+            peer_public_key_opt: None,
+            component: Component::Hopper,
+            last_data: false,
+            data: data_enc.data
+        };
+        let replayed_client_data = inbound_client_data.clone ();
+        thread::spawn(move || {
+            let system = System::new("drops_replayed_inbound_package_and_reports_the_neighbor");
+            let peer_actors = make_peer_actors_from(None, None, None, Some(component), Some (neighborhood), None);
+            let subject = Hopper::new (cryptde, plain_config ());
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(inbound_client_data ).unwrap ();
+            subject_addr.try_send(replayed_client_data ).unwrap ();
+
+            system.run();
+        });
+        neighborhood_awaiter.await_message_count(1);
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        assert_eq! (neighborhood_recording.get_record::<NeighborMisbehaviorMessage>(0), &NeighborMisbehaviorMessage {
+            misbehaver: Misbehaver::Address (socket_addr),
+            misbehavior: NeighborMisbehavior::ReplayedPackage,
+        });
+        assert_eq! (component_recording_arc.lock ().unwrap ().len (), 1);
+        TestLogHandler::new ().exists_log_containing ("Dropped replayed package 1 from neighbor at 1.2.3.4:5678");
+    }
+
+    #[test]
+    fn nodes_behind_one_address_number_their_packages_independently () {
+        init_test_logging ();
+        let cryptde = cryptde();
+        let neighborhood = Recorder::new ();
+        let neighborhood_recording_arc = neighborhood.get_recording ();
+        let component = Recorder::new ();
+        let component_recording_arc = component.get_recording ();
+        let component_awaiter = component.get_awaiter ();
+        let route = route_to_proxy_client(&cryptde.public_key (), cryptde);
+        let payload = PlainData::new (&b"abcd"[..]);
+        let lcp = LiveCoresPackage::new (route, cryptde.encode (&cryptde.public_key (), &payload).unwrap ());
+        let data_enc = lcp.seal (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
+        let from_node = |public_key: &[u8]| InboundClientData {
+            socket_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            origin_port: None,
+            peer_public_key_opt: Some (Key::new (public_key)),
+            component: Component::Hopper,
+            last_data: false,
+            data: data_enc.data.clone ()
+        };
+        let from_one = from_node (b"one");
+        let from_other = from_node (b"other");
+        let replayed_from_other = from_node (b"other");
+        thread::spawn(move || {
+            let system = System::new("nodes_behind_one_address_number_their_packages_independently");
+            let peer_actors = make_peer_actors_from(None, None, None, Some(component), Some (neighborhood), None);
+            let subject = Hopper::new (cryptde, plain_config ());
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(from_one).unwrap ();
+            subject_addr.try_send(from_other).unwrap ();
+            subject_addr.try_send(replayed_from_other).unwrap ();
+
+            system.run();
+        });
+        component_awaiter.await_message_count(2);
+        TestLogHandler::new ().await_log_containing ("Dropped replayed package 1 from neighbor at 1.2.3.4:5678", 1000);
+        assert_eq! (component_recording_arc.lock ().unwrap ().len (), 2);
+        assert_eq! (neighborhood_recording_arc.lock ().unwrap ().len (), 1);
+    }
+
+    #[test]
+    fn numbers_outgoing_packages_consecutively_per_neighbor () {
+        let cryptde = cryptde();
+        let dispatcher = Recorder::new ();
+        let dispatcher_recording_arc = dispatcher.get_recording ();
+        let dispatcher_awaiter = dispatcher.get_awaiter();
+        let destination_key = Key::new (&[65, 65, 65]);
+        let route = Route::new (
+            vec! (RouteSegment::new (vec! (&cryptde.public_key (), &destination_key.clone ()), Component::Neighborhood)),
+            cryptde
+        ).unwrap ();
+        let incipient_cores_package = IncipientCoresPackage::new (route, PlainData::new (&b"abcd"[..]), &destination_key);
+        let another_incipient_cores_package = incipient_cores_package.clone ();
+        thread::spawn (move || {
+            let system = System::new ("numbers_outgoing_packages_consecutively_per_neighbor");
+            let peer_actors = make_peer_actors_from(None, Some(dispatcher), None, None, None, None);
+            let subject = Hopper::new (cryptde, plain_config ());
+            let subject_addr: Addr<Syn, Hopper> = subject.start ();
+            subject_addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            subject_addr.try_send (incipient_cores_package).unwrap ();
+            subject_addr.try_send (another_incipient_cores_package).unwrap ();
+
+            system.run ();
+        });
+        dispatcher_awaiter.await_message_count(2);
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        let first_record = dispatcher_recording.get_record::<HopperTemporaryTransmitDataMsg>(0);
+        let second_record = dispatcher_recording.get_record::<HopperTemporaryTransmitDataMsg>(1);
+        let first_sequence = unseal_transmitted (&first_record.data, &destination_key, cryptde).0;
+        let second_sequence = unseal_transmitted (&second_record.data, &destination_key, cryptde).0;
+        assert_eq! (second_sequence, first_sequence + 1);
+    }
+
+    #[test]
+    fn padded_seal_rounds_package_up_to_a_bucket_size () {
+        let cryptde = cryptde();
+        let route = route_to_proxy_client(&cryptde.public_key (), cryptde);
+        let small = LiveCoresPackage::new (route.clone (), CryptData::new (&b"payload"[..]));
+        let large = LiveCoresPackage::new (route, CryptData::new (&[1u8; 40000][..]));
+
+        let small_sealed = small.seal (1, &padded_options (), &cryptde.public_key (), cryptde).unwrap ();
+        let large_sealed = large.seal (2, &padded_options (), &cryptde.public_key (), cryptde).unwrap ();
+
+        assert_eq! (opened (&small_sealed).data.len (), PADDING_BUCKETS[0]);
+        assert_eq! (opened (&large_sealed).data.len (), 2 * PADDING_BUCKETS[PADDING_BUCKETS.len () - 1]);
+        assert_eq! (LiveCoresPackage::unseal (&small_sealed, &cryptde.private_key (), cryptde).unwrap (), plain_unsealed (1, Some (small)));
+        assert_eq! (LiveCoresPackage::unseal (&large_sealed, &cryptde.private_key (), cryptde).unwrap (), plain_unsealed (2, Some (large)));
+    }
+
+    #[test]
+    fn cover_traffic_unseals_to_no_package () {
+        let cryptde = cryptde();
+        let sealed = seal_cover (7, &padded_options (), &cryptde.public_key (), cryptde).unwrap ();
+
+        let result = LiveCoresPackage::unseal (&sealed, &cryptde.private_key (), cryptde).unwrap ();
+
+        assert_eq! (opened (&sealed).data.len (), PADDING_BUCKETS[0]);
+        assert_eq! (result, plain_unsealed (7, None));
+    }
+
+    #[test]
+    fn compressed_seal_is_smaller_and_survives_unseal () {
+        let cryptde = cryptde();
+        let route = route_to_proxy_client(&cryptde.public_key (), cryptde);
+        let subject = LiveCoresPackage::new (route, CryptData::new (&[7u8; 4000][..]));
+
+        let plain_sealed = subject.seal (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
+        let compressed_sealed = subject.seal (1, &SealOptions {compressed: true, ..SealOptions::plain ()}, &cryptde.public_key (), cryptde).unwrap ();
+
+        assert! (compressed_sealed.data.len () < plain_sealed.data.len () / 4, "{}", compressed_sealed.data.len ());
+        assert_eq! (opened (&compressed_sealed).data[SEAL_SEQUENCE_LENGTH], SEALED_COMPRESSED_PACKAGE);
+        assert_eq! (LiveCoresPackage::unseal (&compressed_sealed, &cryptde.private_key (), cryptde).unwrap (), plain_unsealed (1, Some (subject)));
+    }
+
+    #[test]
+    fn unseal_rejects_package_that_decompresses_to_more_than_any_package_can_be () {
+        let cryptde = cryptde();
+        let bomb = lz4_compress::compress (&vec! (0u8; MAX_PACKAGE_BYTES + 1)[..]);
+        let sealed = encrypt_contents (&seal_contents (1, SEALED_COMPRESSED_PACKAGE, &bomb[..], &SealOptions::plain ()), &cryptde.public_key (), cryptde).unwrap ();
+
+        let result = LiveCoresPackage::unseal (&sealed, &cryptde.private_key (), cryptde);
+
+        assert_eq! (result.err ().unwrap (), SealError::Deserialization);
+    }
+
+    #[test]
+    fn compressed_seal_falls_back_to_plain_when_compression_does_not_help () {
+        let cryptde = cryptde();
+        let route = route_to_proxy_client(&cryptde.public_key (), cryptde);
+        let subject = LiveCoresPackage::new (route, CryptData::new (&b"x"[..]));
+
+        let sealed = subject.seal (1, &SealOptions {compressed: true, ..SealOptions::plain ()}, &cryptde.public_key (), cryptde).unwrap ();
+
+        assert_eq! (opened (&sealed).data[SEAL_SEQUENCE_LENGTH], SEALED_PACKAGE);
+        assert_eq! (LiveCoresPackage::unseal (&sealed, &cryptde.private_key (), cryptde).unwrap (), plain_unsealed (1, Some (subject)));
+    }
+
+    #[test]
+    fn compression_advertisement_survives_padding () {
+        let cryptde = cryptde();
+        let options = SealOptions {padded: true, compressed: false, compression_key: Some (Key::new (b"sender"))};
+        let sealed = seal_cover (3, &options, &cryptde.public_key (), cryptde).unwrap ();
+
+        let result = LiveCoresPackage::unseal (&sealed, &cryptde.private_key (), cryptde).unwrap ();
+
+        assert_eq! (opened (&sealed).data.len (), PADDING_BUCKETS[0]);
+        assert_eq! (result, Unsealed {sequence: 3, package: None, compression_key: Some (Key::new (b"sender"))});
+    }
+
+    #[test]
+    fn compresses_packages_only_for_neighbors_that_advertise_compression () {
+        let cryptde = cryptde();
+        let dispatcher = Recorder::new ();
+        let dispatcher_recording_arc = dispatcher.get_recording ();
+        let dispatcher_awaiter = dispatcher.get_awaiter();
+        let modern_key = Key::new (&[65, 65, 65]);
+        let legacy_key = Key::new (&[66, 66, 66]);
+        let advertisement = SealOptions {compression_key: Some (modern_key.clone ()), ..SealOptions::plain ()};
+        let inbound_client_data = InboundClientData {
+            socket_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            origin_port: None,
+            peer_public_key_opt: None,
+            component: Component::Hopper,
+            last_data: false,
+            data: seal_cover (1, &advertisement, &cryptde.public_key (), cryptde).unwrap ().data
+        };
+        let incipient_for = |key: &Key| {
+            let route = Route::new (
+                vec! (RouteSegment::new (vec! (&cryptde.public_key (), key), Component::Neighborhood)),
+                cryptde
+            ).unwrap ();
+            IncipientCoresPackage::new (route, PlainData::new (&[9u8; 4000][..]), key)
+        };
+        let modern_package = incipient_for (&modern_key);
+        let legacy_package = incipient_for (&legacy_key);
+        thread::spawn (move || {
+            let system = System::new ("compresses_packages_only_for_neighbors_that_advertise_compression");
+            let peer_actors = make_peer_actors_from(None, Some(dispatcher), None, None, None, None);
+            let subject = Hopper::new (cryptde, HopperConfig {compress_packages: true, ..plain_config ()});
+            let subject_addr: Addr<Syn, Hopper> = subject.start ();
+            subject_addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            subject_addr.try_send (inbound_client_data).unwrap ();
+            subject_addr.try_send (modern_package).unwrap ();
+            subject_addr.try_send (legacy_package).unwrap ();
+
+            system.run ();
+        });
+        dispatcher_awaiter.await_message_count(2);
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        let kind_of = |index: usize, key: &Key| {
+            let record = dispatcher_recording.get_record::<HopperTemporaryTransmitDataMsg>(index);
+            let sealed = CryptData::new (&record.data[..]);
+            let private_key = CryptDENull::other_key (key);
+            assert_eq! (LiveCoresPackage::unseal (&sealed, &private_key, cryptde).unwrap ().compression_key, Some (cryptde.public_key ()));
+            cryptde.decrypt (&private_key, SEAL_ASSOCIATED_DATA, &sealed).unwrap ().data[SEAL_SEQUENCE_LENGTH]
+        };
+        assert_eq! (kind_of (0, &modern_key), SEALED_COMPRESSED_PACKAGE);
+        assert_eq! (kind_of (1, &legacy_key), SEALED_PACKAGE);
+    }
+
+    #[test]
+    fn discards_inbound_cover_traffic () {
+        init_test_logging ();
+        let cryptde = cryptde();
+        let component = Recorder::new ();
+        let component_recording_arc = component.get_recording ();
+        let data_enc = seal_cover (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
+        let inbound_client_data = InboundClientData {
+            socket_addr: SocketAddr::from_str("3.4.5.6:7890").unwrap(),
+            origin_port: None,
+            peer_public_key_opt: None,
+            component: Component::Hopper,
+            last_data: false,
+            data: data_enc.data
+        };
+        let system = System::new("discards_inbound_cover_traffic");
+        let peer_actors = make_peer_actors_from(Some (component), None, None, None, None, None);
+        let subject = Hopper::new (cryptde, plain_config ());
+        let subject_addr: Addr<Syn, Hopper> = subject.start();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+        subject_addr.try_send(inbound_client_data ).unwrap ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run();
+        TestLogHandler::new ().await_log_containing ("Discarded cover traffic from neighbor at 3.4.5.6:7890", 1000);
+        assert_eq! (component_recording_arc.lock ().unwrap ().len (), 0);
+    }
+
+    #[test]
+    fn sends_cover_traffic_to_neighbors_it_has_links_with () {
+        let cryptde = cryptde();
+        let dispatcher = Recorder::new ();
+        let dispatcher_recording_arc = dispatcher.get_recording ();
+        let dispatcher_awaiter = dispatcher.get_awaiter();
+        let destination_key = Key::new (&[65, 65, 65]);
+        let route = Route::new (
+            vec! (RouteSegment::new (vec! (&cryptde.public_key (), &destination_key.clone ()), Component::Neighborhood)),
+            cryptde
+        ).unwrap ();
+        let incipient_cores_package = IncipientCoresPackage::new (route, PlainData::new (&b"abcd"[..]), &destination_key);
+        thread::spawn (move || {
+            let system = System::new ("sends_cover_traffic_to_neighbors_it_has_links_with");
+            let peer_actors = make_peer_actors_from(None, Some(dispatcher), None, None, None, None);
+            let subject = Hopper::new (cryptde, HopperConfig {pad_packages: true, cover_traffic_interval_ms: 10, ..plain_config ()});
+            let subject_addr: Addr<Syn, Hopper> = subject.start ();
+            subject_addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            subject_addr.try_send (incipient_cores_package).unwrap ();
+
+            system.run ();
+        });
+        dispatcher_awaiter.await_message_count(3);
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        let package_record = dispatcher_recording.get_record::<HopperTemporaryTransmitDataMsg>(0);
+        let cover_record = dispatcher_recording.get_record::<HopperTemporaryTransmitDataMsg>(1);
+        let (package_sequence, package_opt) = unseal_transmitted (&package_record.data, &destination_key, cryptde);
+        let (cover_sequence, cover_opt) = unseal_transmitted (&cover_record.data, &destination_key, cryptde);
+        assert_eq! (package_opt.is_some (), true);
+        assert_eq! (cover_record.endpoint, Endpoint::Key (destination_key.clone ()));
+        assert_eq! (cover_opt, None);
+        assert_eq! (cover_sequence, package_sequence + 1);
+        assert_eq! (cover_record.data.len (), package_record.data.len ());
+    }
+
+    #[test]
+    fn converts_incipient_message_to_live_and_sends_to_dispatcher () {
+        let cryptde = cryptde();
+        let dispatcher = Recorder::new ();
+        let dispatcher_recording_arc = dispatcher.get_recording ();
+        let dispatcher_awaiter = dispatcher.get_awaiter();
+        let destination_key = Key::new (&[65, 65, 65]);
+        let route = Route::new (
+            vec! (RouteSegment::new (vec! (&cryptde.public_key (), &destination_key.clone ()), Component::Neighborhood)),
+            cryptde
+        ).unwrap ();
+        let payload = PlainData::new (&b"abcd"[..]);
+        let incipient_cores_package = IncipientCoresPackage::new (route.clone (),
+            payload, &destination_key);
+        let incipient_cores_package_a = incipient_cores_package.clone ();
+        thread::spawn (move || {
+            let system = System::new ("converts_incipient_message_to_live_and_sends_to_dispatcher");
+            let peer_actors = make_peer_actors_from(None, Some(dispatcher), None, None, None, None);
+            let subject = Hopper::new (cryptde, plain_config ());
+            let subject_addr: Addr<Syn, Hopper> = subject.start ();
+            subject_addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            subject_addr.try_send (incipient_cores_package).unwrap ();
+
+            system.run ();
+        });
+        dispatcher_awaiter.await_message_count(1);
         let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
         let record = dispatcher_recording.get_record::<HopperTemporaryTransmitDataMsg>(0);
         let expected_lcp = LiveCoresPackage::from_incipient (incipient_cores_package_a, cryptde).0;
-        let expected_lcp_ser = PlainData::new (&serde_cbor::ser::to_vec (&expected_lcp).unwrap ());
-        let expected_lcp_enc = cryptde.encode (&destination_key, &expected_lcp_ser).unwrap ();
-        assert_eq! (*record, HopperTemporaryTransmitDataMsg {
-            endpoint: Endpoint::Key (destination_key.clone ()),
-            last_data: false,
-            data: expected_lcp_enc.data
-        });
+        assert_eq! (record.endpoint, Endpoint::Key (destination_key.clone ()));
+        assert_eq! (record.last_data, false);
+        assert_eq! (unseal_transmitted (&record.data, &destination_key, cryptde).1, Some (expected_lcp));
     }
 
     #[test]
@@ -347,19 +1592,63 @@ mod tests {
         let payload = PlainData::new (&b"abcd"[..]);
         let lcp = LiveCoresPackage::new (route, cryptde.encode (&cryptde.public_key (), &payload).unwrap ());
         let lcp_a = lcp.clone ();
-        let data_ser = PlainData::new (&serde_cbor::ser::to_vec (&lcp).unwrap ()[..]);
-        let data_enc = cryptde.encode (&cryptde.public_key (), &data_ser).unwrap ();
+        let data_enc = lcp.seal (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
         let inbound_client_data = InboundClientData {
             socket_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
             origin_port: None,
+            peer_public_key_opt: None,
             component: Component::Hopper,
             last_data: false,
             data: data_enc.data
         };
         thread::spawn(move || {
             let system = System::new("converts_live_message_to_expired_for_proxy_client");
-            let peer_actors = make_peer_actors_from(None, None, None, Some(component), None);
-            let subject = Hopper::new (cryptde);
+            let peer_actors = make_peer_actors_from(None, None, None, Some(component), None, None);
+            let subject = Hopper::new (cryptde, plain_config ());
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(inbound_client_data ).unwrap ();
+
+            system.run();
+        });
+        component_awaiter.await_message_count(1);
+        let component_recording = component_recording_arc.lock().unwrap();
+        let record = component_recording.get_record::<ExpiredCoresPackage>(0);
+        let expected_ecp = lcp_a.to_expired (cryptde).unwrap ();
+        assert_eq! (*record, expected_ecp);
+    }
+
+    #[test]
+    fn tells_the_proxy_client_which_node_proved_it_built_the_route () {
+        let cryptde = cryptde();
+        let mut consumer_cryptde = CryptDENull::new ();
+        consumer_cryptde.generate_key_pair ();
+        let component = Recorder::new ();
+        let component_recording_arc = component.get_recording ();
+        let component_awaiter = component.get_awaiter ();
+        let mut route = Route::new (vec! (
+            RouteSegment::new (vec! (&cryptde.public_key (), &cryptde.public_key ()), Component::ProxyClient),
+            RouteSegment::new (vec! (&cryptde.public_key (), &cryptde.public_key ()), Component::ProxyServer)
+        ), &consumer_cryptde).unwrap ();
+        route.shift (&cryptde.private_key (), cryptde);
+        let payload = PlainData::new (&b"abcd"[..]);
+        let lcp = LiveCoresPackage::new (route, cryptde.encode (&cryptde.public_key (), &payload).unwrap ());
+        let lcp_a = lcp.clone ();
+        let data_enc = lcp.seal (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
+        let inbound_client_data = InboundClientData {
+            socket_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            origin_port: None,
+            peer_public_key_opt: None,
+            component: Component::Hopper,
+            last_data: false,
+            data: data_enc.data
+        };
+        let consuming_node_key = consumer_cryptde.public_key ();
+        thread::spawn(move || {
+            let system = System::new("tells_the_proxy_client_which_node_proved_it_built_the_route");
+            let peer_actors = make_peer_actors_from(None, None, None, Some(component), None, None);
+            let subject = Hopper::new (cryptde, plain_config ());
             let subject_addr: Addr<Syn, Hopper> = subject.start();
             subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
 
@@ -370,7 +1659,9 @@ mod tests {
         component_awaiter.await_message_count(1);
         let component_recording = component_recording_arc.lock().unwrap();
         let record = component_recording.get_record::<ExpiredCoresPackage>(0);
-        let expected_ecp = lcp_a.to_expired (cryptde);
+        let mut expected_ecp = lcp_a.to_expired (cryptde).unwrap ();
+        expected_ecp.consuming_key_opt = Some (consuming_node_key);
+        expected_ecp.neighbor_ip_opt = Some (IpAddr::from_str ("1.2.3.4").unwrap ());
         assert_eq! (*record, expected_ecp);
     }
 
@@ -384,19 +1675,102 @@ mod tests {
         let payload = PlainData::new (&b"abcd"[..]);
         let lcp = LiveCoresPackage::new (route, cryptde.encode (&cryptde.public_key (), &payload).unwrap ());
         let lcp_a = lcp.clone ();
-        let data_ser = PlainData::new (&serde_cbor::ser::to_vec (&lcp).unwrap ()[..]);
-        let data_enc = cryptde.encode (&cryptde.public_key (), &data_ser).unwrap ();
+        let data_enc = lcp.seal (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
         let inbound_client_data = InboundClientData {
             socket_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
             origin_port: None,
+            peer_public_key_opt: None,
             component: Component::Hopper,
             last_data: false,
             data: data_enc.data
         };
         thread::spawn(move || {
             let system = System::new("converts_live_message_to_expired_for_proxy_server");
-            let peer_actors = make_peer_actors_from(Some (component), None, None, None, None);
-            let subject = Hopper::new (cryptde);
+            let peer_actors = make_peer_actors_from(Some (component), None, None, None, None, None);
+            let subject = Hopper::new (cryptde, plain_config ());
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(inbound_client_data ).unwrap ();
+
+            system.run();
+        });
+        component_awaiter.await_message_count(1);
+        let component_recording = component_recording_arc.lock().unwrap();
+        let record = component_recording.get_record::<ExpiredCoresPackage>(0);
+        let expected_ecp = lcp_a.to_expired (cryptde).unwrap ();
+        assert_eq! (*record, expected_ecp);
+    }
+
+    #[test]
+    fn converts_live_message_to_expired_for_neighborhood () {
+        let cryptde = cryptde();
+        let component = Recorder::new ();
+        let component_recording_arc = component.get_recording ();
+        let component_awaiter = component.get_awaiter ();
+        let mut route = Route::new (vec! (
+            RouteSegment::new (vec! (&cryptde.public_key (), &cryptde.public_key ()), Component::Neighborhood)
+        ), cryptde).unwrap ();
+        route.shift (&cryptde.private_key (), cryptde).unwrap ();
+        let payload = PlainData::new (&b"gossip"[..]);
+        let lcp = LiveCoresPackage::new (route, cryptde.encode (&cryptde.public_key (), &payload).unwrap ());
+        let lcp_a = lcp.clone ();
+        let data_enc = lcp.seal (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
+        let inbound_client_data = InboundClientData {
+            socket_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            origin_port: None,
+            peer_public_key_opt: None,
+            component: Component::Hopper,
+            last_data: false,
+            data: data_enc.data
+        };
+        thread::spawn(move || {
+            let system = System::new("converts_live_message_to_expired_for_neighborhood");
+            let peer_actors = make_peer_actors_from(None, None, None, None, Some (component), None);
+            let subject = Hopper::new (cryptde, plain_config ());
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(inbound_client_data ).unwrap ();
+
+            system.run();
+        });
+        component_awaiter.await_message_count(1);
+        let component_recording = component_recording_arc.lock().unwrap();
+        let record = component_recording.get_record::<ExpiredNeighborhoodPackage>(0);
+        let expected_ecp = lcp_a.to_expired (cryptde).unwrap ();
+        assert_eq! (*record, ExpiredNeighborhoodPackage {
+            package: expected_ecp,
+            neighbor_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+        });
+    }
+
+    #[test]
+    fn converts_live_message_to_expired_for_accountant () {
+        let cryptde = cryptde();
+        let component = Recorder::new ();
+        let component_recording_arc = component.get_recording ();
+        let component_awaiter = component.get_awaiter ();
+        let mut route = Route::new (vec! (
+            RouteSegment::new (vec! (&cryptde.public_key (), &cryptde.public_key ()), Component::Accountant)
+        ), cryptde).unwrap ();
+        route.shift (&cryptde.private_key (), cryptde).unwrap ();
+        let payload = PlainData::new (&b"balance update"[..]);
+        let lcp = LiveCoresPackage::new (route, cryptde.encode (&cryptde.public_key (), &payload).unwrap ());
+        let lcp_a = lcp.clone ();
+        let data_enc = lcp.seal (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
+        let inbound_client_data = InboundClientData {
+            socket_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            origin_port: None,
+            peer_public_key_opt: None,
+            component: Component::Hopper,
+            last_data: false,
+            data: data_enc.data
+        };
+        thread::spawn(move || {
+            let system = System::new("converts_live_message_to_expired_for_accountant");
+            let peer_actors = make_peer_actors_from(None, None, None, None, None, Some (component));
+            let subject = Hopper::new (cryptde, plain_config ());
             let subject_addr: Addr<Syn, Hopper> = subject.start();
             subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
 
@@ -407,7 +1781,7 @@ mod tests {
         component_awaiter.await_message_count(1);
         let component_recording = component_recording_arc.lock().unwrap();
         let record = component_recording.get_record::<ExpiredCoresPackage>(0);
-        let expected_ecp = lcp_a.to_expired (cryptde);
+        let expected_ecp = lcp_a.to_expired (cryptde).unwrap ();
         assert_eq! (*record, expected_ecp);
     }
 
@@ -424,19 +1798,19 @@ mod tests {
         let payload = PlainData::new (&b"abcd"[..]);
         let lcp = LiveCoresPackage::new (route, cryptde.encode (&next_key, &payload).unwrap ());
         let lcp_a = lcp.clone ();
-        let data_ser = PlainData::new (&serde_cbor::ser::to_vec (&lcp).unwrap ()[..]);
-        let data_enc = cryptde.encode (&cryptde.public_key (), &data_ser).unwrap ();
+        let data_enc = lcp.seal (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
         let inbound_client_data = InboundClientData {
             socket_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
             origin_port: None,
+            peer_public_key_opt: None,
             component: Component::Hopper,
             last_data: true,
             data: data_enc.data
         };
         thread::spawn(move || {
             let system = System::new("converts_live_message_to_expired_for_proxy_server");
-            let peer_actors = make_peer_actors_from(None, Some (dispatcher), None, None, None);
-            let subject = Hopper::new (cryptde);
+            let peer_actors = make_peer_actors_from(None, Some (dispatcher), None, None, None, None);
+            let subject = Hopper::new (cryptde, plain_config ());
             let subject_addr: Addr<Syn, Hopper> = subject.start();
             subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
 
@@ -448,13 +1822,370 @@ mod tests {
         let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
         let record = dispatcher_recording.get_record::<HopperTemporaryTransmitDataMsg>(0);
         let expected_lcp = lcp_a.to_next_live (cryptde).unwrap ().1;
-        let expected_lcp_ser = PlainData::new (&serde_cbor::ser::to_vec (&expected_lcp).unwrap ());
-        let expected_lcp_enc = cryptde.encode (&next_key, &expected_lcp_ser).unwrap ();
-        assert_eq! (*record, HopperTemporaryTransmitDataMsg {
-            endpoint: Endpoint::Key (next_key.clone ()),
-            last_data: true,
-            data: expected_lcp_enc.data
+        assert_eq! (record.endpoint, Endpoint::Key (next_key.clone ()));
+        assert_eq! (record.last_data, true);
+        assert_eq! (unseal_transmitted (&record.data, &next_key, cryptde).1, Some (expected_lcp));
+    }
+
+    #[test]
+    fn reports_relaying_for_another_node_to_the_accountant () {
+        let cryptde = cryptde();
+        let mut consumer_cryptde = CryptDENull::new ();
+        consumer_cryptde.generate_key_pair ();
+        let accountant = Recorder::new ();
+        let accountant_recording_arc = accountant.get_recording ();
+        let accountant_awaiter = accountant.get_awaiter ();
+        let next_key = Key::new (&[65, 65, 65]);
+        let route = Route::new (vec! (
+            RouteSegment::new (vec! (&cryptde.public_key (), &next_key), Component::Neighborhood)
+        ), &consumer_cryptde).unwrap ();
+        let lcp = LiveCoresPackage::new (route, cryptde.encode (&next_key, &PlainData::new (&b"abcd"[..])).unwrap ());
+        let data_enc = lcp.seal (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
+        let data_len = data_enc.data.len ();
+        let inbound_client_data = InboundClientData {
+            socket_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            origin_port: None,
+            peer_public_key_opt: None,
+            component: Component::Hopper,
+            last_data: false,
+            data: data_enc.data
+        };
+        let consuming_node_key = consumer_cryptde.public_key ();
+        thread::spawn(move || {
+            let system = System::new("reports_relaying_for_another_node_to_the_accountant");
+            let peer_actors = make_peer_actors_from(None, None, None, None, None, Some (accountant));
+            let subject = Hopper::new (cryptde, plain_config ());
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(inbound_client_data).unwrap ();
+
+            system.run();
+        });
+        accountant_awaiter.await_message_count(1);
+        let accountant_recording = accountant_recording_arc.lock().unwrap();
+        assert_eq! (accountant_recording.get_record::<ReportRoutingServiceMessage>(0), &ReportRoutingServiceMessage {
+            consuming_node_key,
+            neighbor_ip: IpAddr::from_str ("1.2.3.4").unwrap (),
+            payload_size: data_len,
+        });
+    }
+
+    #[test]
+    fn originate_only_hopper_refuses_to_relay_or_exit () {
+        init_test_logging ();
+        let cryptde = cryptde();
+        let dispatcher = Recorder::new ();
+        let dispatcher_recording_arc = dispatcher.get_recording ();
+        let proxy_client = Recorder::new ();
+        let proxy_client_recording_arc = proxy_client.get_recording ();
+        let next_key = Key::new (&[65, 65, 65]);
+        let relay_route = Route::new (vec! (
+            RouteSegment::new (vec! (&cryptde.public_key (), &next_key), Component::Neighborhood)
+        ), cryptde).unwrap ();
+        let inbound = |route: Route, sequence: u64| {
+            let lcp = LiveCoresPackage::new (route, cryptde.encode (&next_key, &PlainData::new (&b"abcd"[..])).unwrap ());
+            let data_enc = lcp.seal (sequence, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
+            InboundClientData {
+                socket_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+                origin_port: None,
+                peer_public_key_opt: None,
+                component: Component::Hopper,
+                last_data: false,
+                data: data_enc.data
+            }
+        };
+        let relay_data = inbound (relay_route, 1);
+        let exit_data = inbound (route_to_proxy_client (&cryptde.public_key (), cryptde), 2);
+        thread::spawn(move || {
+            let system = System::new("originate_only_hopper_refuses_to_relay_or_exit");
+            let peer_actors = make_peer_actors_from(None, Some (dispatcher), None, Some (proxy_client), None, None);
+            let subject = Hopper::new (cryptde, HopperConfig {originate_only: true, ..plain_config ()});
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(relay_data).unwrap ();
+            subject_addr.try_send(exit_data).unwrap ();
+
+            system.run();
+        });
+        let tlh = TestLogHandler::new ();
+        tlh.await_log_containing ("Refused to relay for neighbor at 1.2.3.4:5678: this Node only originates traffic", 1000);
+        tlh.await_log_containing ("Refused to exit for neighbor at 1.2.3.4:5678: this Node only originates traffic", 1000);
+        assert_eq! (dispatcher_recording_arc.lock ().unwrap ().len (), 0);
+        assert_eq! (proxy_client_recording_arc.lock ().unwrap ().len (), 0);
+    }
+
+    #[test]
+    fn holds_back_relaying_for_throttled_nodes_and_refuses_exiting_for_refused_ones () {
+        init_test_logging ();
+        let cryptde = cryptde();
+        let mut throttled_cryptde = CryptDENull::new ();
+        throttled_cryptde.generate_key_pair ();
+        let mut refused_cryptde = CryptDENull::new ();
+        refused_cryptde.generate_key_pair ();
+        let dispatcher = Recorder::new ();
+        let dispatcher_recording_arc = dispatcher.get_recording ();
+        let dispatcher_awaiter = dispatcher.get_awaiter ();
+        let proxy_client = Recorder::new ();
+        let proxy_client_recording_arc = proxy_client.get_recording ();
+        let next_key = Key::new (&[65, 65, 65]);
+        let relay_route = Route::new (vec! (
+            RouteSegment::new (vec! (&cryptde.public_key (), &next_key), Component::Neighborhood)
+        ), &throttled_cryptde).unwrap ();
+        let mut exit_route = Route::new (vec! (
+            RouteSegment::new (vec! (&cryptde.public_key (), &cryptde.public_key ()), Component::ProxyClient),
+            RouteSegment::new (vec! (&cryptde.public_key (), &cryptde.public_key ()), Component::ProxyServer)
+        ), &refused_cryptde).unwrap ();
+        exit_route.shift (&cryptde.private_key (), cryptde);
+        let inbound = |route: Route, sequence: u64| {
+            let lcp = LiveCoresPackage::new (route, cryptde.encode (&next_key, &PlainData::new (&b"abcd"[..])).unwrap ());
+            let data_enc = lcp.seal (sequence, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
+            InboundClientData {
+                socket_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+                origin_port: None,
+                peer_public_key_opt: None,
+                component: Component::Hopper,
+                last_data: false,
+                data: data_enc.data
+            }
+        };
+        let exit_data = inbound (exit_route, 1);
+        let relay_data = inbound (relay_route, 2);
+        let throttled_key = throttled_cryptde.public_key ();
+        let refused_key = refused_cryptde.public_key ();
+        let start = Instant::now ();
+        thread::spawn(move || {
+            let system = System::new("holds_back_relaying_for_throttled_nodes_and_refuses_exiting_for_refused_ones");
+            let peer_actors = make_peer_actors_from(None, Some (dispatcher), None, Some (proxy_client), None, None);
+            let subject = Hopper::new (cryptde, plain_config ());
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(ServiceStandingMessage {consuming_node_key: throttled_key, standing: ServiceStanding::Throttled}).unwrap ();
+            subject_addr.try_send(ServiceStandingMessage {consuming_node_key: refused_key, standing: ServiceStanding::Refused}).unwrap ();
+            subject_addr.try_send(exit_data).unwrap ();
+            subject_addr.try_send(relay_data).unwrap ();
+
+            system.run();
+        });
+        dispatcher_awaiter.await_message_count (1);
+        assert_eq! (start.elapsed () >= Duration::from_millis (THROTTLE_DELAY_MS), true);
+        assert_eq! (dispatcher_recording_arc.lock ().unwrap ().len (), 1);
+        assert_eq! (proxy_client_recording_arc.lock ().unwrap ().len (), 0);
+        TestLogHandler::new ().exists_log_containing ("Refused to exit for neighbor at 1.2.3.4:5678: the consuming Node owes too much");
+    }
+
+    #[test]
+    fn tells_a_refused_consuming_node_why_its_request_was_not_exited () {
+        let cryptde = cryptde();
+        let mut refused_cryptde = CryptDENull::new ();
+        refused_cryptde.generate_key_pair ();
+        let dispatcher = Recorder::new ();
+        let dispatcher_recording_arc = dispatcher.get_recording ();
+        let dispatcher_awaiter = dispatcher.get_awaiter ();
+        let proxy_client = Recorder::new ();
+        let proxy_client_recording_arc = proxy_client.get_recording ();
+        let mut route = Route::new (vec! (
+            RouteSegment::new (vec! (&cryptde.public_key (), &cryptde.public_key ()), Component::ProxyClient),
+            RouteSegment::new (vec! (&cryptde.public_key (), &cryptde.public_key ()), Component::ProxyServer)
+        ), &refused_cryptde).unwrap ();
+        route.shift (&cryptde.private_key (), cryptde);
+        let request = ClientRequestPayload {
+            stream_key: SocketAddr::from_str ("1.2.3.4:5678").unwrap (),
+            last_data: false,
+            data: PlainData::new (&b"GET / HTTP/1.1\r\n\r\n"[..]),
+            target_hostname: Some (String::from ("server.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: cryptde.public_key (),
+        };
+        let serialized_request = PlainData::new (&serde_cbor::ser::to_vec (&request).unwrap ()[..]);
+        let lcp = LiveCoresPackage::new (route, cryptde.encode (&cryptde.public_key (), &serialized_request).unwrap ());
+        let data_enc = lcp.seal (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
+        let inbound_client_data = InboundClientData {
+            socket_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            origin_port: None,
+            peer_public_key_opt: None,
+            component: Component::Hopper,
+            last_data: false,
+            data: data_enc.data
+        };
+        let refused_key = refused_cryptde.public_key ();
+        thread::spawn(move || {
+            let system = System::new("tells_a_refused_consuming_node_why_its_request_was_not_exited");
+            let peer_actors = make_peer_actors_from(None, Some (dispatcher), None, Some (proxy_client), None, None);
+            let subject = Hopper::new (cryptde, plain_config ());
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(ServiceStandingMessage {consuming_node_key: refused_key, standing: ServiceStanding::Refused}).unwrap ();
+            subject_addr.try_send(inbound_client_data).unwrap ();
+
+            system.run();
+        });
+        dispatcher_awaiter.await_message_count (1);
+        let dispatcher_recording = dispatcher_recording_arc.lock ().unwrap ();
+        let record = dispatcher_recording.get_record::<HopperTemporaryTransmitDataMsg> (0);
+        assert_eq! (record.endpoint, Endpoint::Key (cryptde.public_key ()));
+        let response_package = unseal_transmitted (&record.data, &cryptde.public_key (), cryptde).1.unwrap ().to_expired (cryptde).unwrap ();
+        assert_eq! (response_package.payload::<ClientResponsePayload> ().unwrap (), ClientResponsePayload {
+            stream_key: SocketAddr::from_str ("1.2.3.4:5678").unwrap (),
+            last_response: true,
+            data: PlainData::new (&[]),
+            failure: Some (ExitFailure::ServiceRefused),
+        });
+        assert_eq! (proxy_client_recording_arc.lock ().unwrap ().len (), 0);
+    }
+
+    #[test]
+    fn tells_a_refused_consuming_node_once_that_its_packages_are_not_relayed () {
+        let cryptde = cryptde();
+        let mut refused_cryptde = CryptDENull::new ();
+        refused_cryptde.generate_key_pair ();
+        let dispatcher = Recorder::new ();
+        let dispatcher_recording_arc = dispatcher.get_recording ();
+        let dispatcher_awaiter = dispatcher.get_awaiter ();
+        let next_key = Key::new (&[65, 65, 65]);
+        let route = Route::new (vec! (
+            RouteSegment::new (vec! (&cryptde.public_key (), &next_key), Component::Neighborhood)
+        ), &refused_cryptde).unwrap ();
+        let lcp = LiveCoresPackage::new (route, cryptde.encode (&next_key, &PlainData::new (&b"abcd"[..])).unwrap ());
+        let inbound_client_data = |sequence: u64| {
+            let data_enc = lcp.seal (sequence, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
+            InboundClientData {
+                socket_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+                origin_port: None,
+                peer_public_key_opt: None,
+                component: Component::Hopper,
+                last_data: false,
+                data: data_enc.data
+            }
+        };
+        let first_data = inbound_client_data (1);
+        let second_data = inbound_client_data (2);
+        let refused_key = refused_cryptde.public_key ();
+        let standing_key = refused_key.clone ();
+        thread::spawn(move || {
+            let system = System::new("tells_a_refused_consuming_node_once_that_its_packages_are_not_relayed");
+            let peer_actors = make_peer_actors_from(None, Some (dispatcher), None, None, None, None);
+            let subject = Hopper::new (cryptde, plain_config ());
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(ServiceStandingMessage {consuming_node_key: standing_key, standing: ServiceStanding::Refused}).unwrap ();
+            subject_addr.try_send(first_data).unwrap ();
+            subject_addr.try_send(second_data).unwrap ();
+
+            system.run();
+        });
+        dispatcher_awaiter.await_message_count (1);
+        thread::sleep (Duration::from_millis (100));
+        let dispatcher_recording = dispatcher_recording_arc.lock ().unwrap ();
+        assert_eq! (dispatcher_recording.len (), 1);
+        let record = dispatcher_recording.get_record::<HopperTemporaryTransmitDataMsg> (0);
+        assert_eq! (record.endpoint, Endpoint::Key (refused_key.clone ()));
+        let refusal_package = unseal_transmitted (&record.data, &refused_key, &refused_cryptde).1.unwrap ().to_expired (&refused_cryptde).unwrap ();
+        assert_eq! (refusal_package.payload::<ServiceRefusal> ().unwrap (), ServiceRefusal {
+            refusing_key: cryptde.public_key (),
+            refused_key,
+        });
+    }
+
+    #[test]
+    fn holds_relayed_packages_for_the_mix_delay_and_releases_them_together () {
+        let cryptde = cryptde();
+        let dispatcher = Recorder::new ();
+        let dispatcher_recording_arc = dispatcher.get_recording ();
+        let dispatcher_awaiter = dispatcher.get_awaiter();
+        let next_key = Key::new (&[65, 65, 65]);
+        let route = Route::new (vec! (
+            RouteSegment::new (vec! (&cryptde.public_key (), &next_key), Component::Neighborhood)
+        ), cryptde).unwrap ();
+        let lcp = LiveCoresPackage::new (route, cryptde.encode (&next_key, &PlainData::new (&b"abcd"[..])).unwrap ());
+        let inbound_client_data = |sequence: u64| {
+            let data_enc = lcp.seal (sequence, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
+            InboundClientData {
+                socket_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+                origin_port: None,
+                peer_public_key_opt: None,
+                component: Component::Hopper,
+                last_data: false,
+                data: data_enc.data
+            }
+        };
+        let first = inbound_client_data (1);
+        let second = inbound_client_data (2);
+        let config = HopperConfig {mix_delay: MixDelay::Uniform {min_ms: 200, max_ms: 200}, ..plain_config ()};
+        let start = Instant::now ();
+        thread::spawn(move || {
+            let system = System::new("holds_relayed_packages_for_the_mix_delay_and_releases_them_together");
+            let peer_actors = make_peer_actors_from(None, Some (dispatcher), None, None, None, None);
+            let subject = Hopper::new (cryptde, config);
+            let subject_addr: Addr<Syn, Hopper> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(first).unwrap ();
+            subject_addr.try_send(second).unwrap ();
+
+            system.run();
         });
+        dispatcher_awaiter.await_message_count(2);
+        assert! (start.elapsed () >= Duration::from_millis (200), "{:?}", start.elapsed ());
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        let first_sequence = unseal_transmitted (&dispatcher_recording.get_record::<HopperTemporaryTransmitDataMsg>(0).data, &next_key, cryptde).0;
+        let second_sequence = unseal_transmitted (&dispatcher_recording.get_record::<HopperTemporaryTransmitDataMsg>(1).data, &next_key, cryptde).0;
+        // Both are bound for the same next hop, so mixing mustn't swap them
+        assert_eq! (second_sequence, first_sequence + 1);
+    }
+
+    #[test]
+    fn to_next_live_decrements_ttl () {
+        let cryptde = cryptde();
+        let next_key = Key::new (&[65, 65, 65]);
+        let route = Route::new (vec! (
+            RouteSegment::new (vec! (&cryptde.public_key (), &next_key), Component::Neighborhood)
+        ), cryptde).unwrap ();
+        let subject = LiveCoresPackage::new (route, CryptData::new (&b"abcd"[..]));
+
+        let result = subject.to_next_live (cryptde).unwrap ().1;
+
+        assert_eq! (result.ttl, DEFAULT_PACKAGE_TTL - 1);
+    }
+
+    #[test]
+    fn drops_relayed_package_whose_ttl_is_exhausted () {
+        init_test_logging ();
+        let cryptde = cryptde();
+        let dispatcher = Recorder::new ();
+        let dispatcher_recording_arc = dispatcher.get_recording ();
+        let next_key = Key::new (&[65, 65, 65]);
+        let route = Route::new (vec! (
+            RouteSegment::new (vec! (&cryptde.public_key (), &next_key), Component::Neighborhood)
+        ), cryptde).unwrap ();
+        let mut lcp = LiveCoresPackage::new (route, CryptData::new (&b"abcd"[..]));
+        lcp.ttl = 0;
+        let data_enc = lcp.seal (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ();
+        let inbound_client_data = InboundClientData {
+            socket_addr: SocketAddr::from_str("2.3.4.5:6789").unwrap(),
+            origin_port: None,
+            peer_public_key_opt: None,
+            component: Component::Hopper,
+            last_data: false,
+            data: data_enc.data
+        };
+        let system = System::new("drops_relayed_package_whose_ttl_is_exhausted");
+        let peer_actors = make_peer_actors_from(None, Some (dispatcher), None, None, None, None);
+        let subject = Hopper::new (cryptde, plain_config ());
+        let subject_addr: Addr<Syn, Hopper> = subject.start();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+        subject_addr.try_send(inbound_client_data ).unwrap ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run();
+        TestLogHandler::new ().await_log_containing ("Dropped expired package from neighbor at 2.3.4.5:6789: TTL exhausted", 1000);
+        assert_eq! (dispatcher_recording_arc.lock ().unwrap ().len (), 0);
     }
 
     #[test]
@@ -466,18 +2197,18 @@ mod tests {
         let serialized_payload = serde_cbor::ser::to_vec (&PayloadMock::new()).unwrap ();
         let data = cryptde.encode(&cryptde.public_key(), &PlainData::new(&serialized_payload[..])).unwrap();
         let live_package = LiveCoresPackage::new(route, data);
-        let live_data = PlainData::new(&serde_cbor::ser::to_vec (&live_package).unwrap ()[..]);
-        let encrypted_package = cryptde.encode(&cryptde.public_key(), &live_data).unwrap().data;
+        let encrypted_package = live_package.seal (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ().data;
 
         let inbound_client_data = InboundClientData {
             socket_addr,
             origin_port: None,
+            peer_public_key_opt: None,
             component: Component::Hopper,
             last_data: false,
             data: encrypted_package,
         };
         let system = System::new("panics_if_proxy_server_is_unbound");
-        let subject = Hopper::new (cryptde);
+        let subject = Hopper::new (cryptde, plain_config ());
         let subject_addr: Addr<Syn, Hopper> = subject.start();
 
         subject_addr.try_send(inbound_client_data ).unwrap ();
@@ -495,18 +2226,18 @@ mod tests {
         let serialized_payload = serde_cbor::ser::to_vec (&PayloadMock::new()).unwrap ();
         let data = cryptde.encode(&cryptde.public_key(), &PlainData::new(&serialized_payload[..])).unwrap();
         let live_package = LiveCoresPackage::new(route, data);
-        let live_data = PlainData::new(&serde_cbor::ser::to_vec (&live_package).unwrap ()[..]);
-        let encrypted_package = cryptde.encode(&cryptde.public_key(), &live_data).unwrap().data;
+        let encrypted_package = live_package.seal (1, &SealOptions::plain (), &cryptde.public_key (), cryptde).unwrap ().data;
 
         let inbound_client_data = InboundClientData {
             socket_addr,
             origin_port: None,
+            peer_public_key_opt: None,
             component: Component::Hopper,
             last_data: false,
             data: encrypted_package,
         };
         let system = System::new("panics_if_proxy_client_is_unbound");
-        let subject = Hopper::new (cryptde);
+        let subject = Hopper::new (cryptde, plain_config ());
         let subject_addr: Addr<Syn, Hopper> = subject.start();
 
         subject_addr.try_send(inbound_client_data ).unwrap ();
@@ -528,7 +2259,7 @@ mod tests {
             PayloadMock::new (), &cryptde.public_key ()
         );
         let system = System::new("panics_if_dispatcher_is_unbound");
-        let subject = Hopper::new (cryptde);
+        let subject = Hopper::new (cryptde, plain_config ());
         let subject_addr: Addr<Syn, Hopper> = subject.start();
 
         subject_addr.try_send(incipient_package ).unwrap ();
@@ -3,10 +3,15 @@ extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_cbor;
+extern crate lz4_compress;
 extern crate sub_lib;
 extern crate actix;
+extern crate rand;
 
 #[cfg (test)]
 extern crate test_utils;
 
+pub mod decompression;
 pub mod hopper;
+pub mod mixer;
+pub mod replay_window;
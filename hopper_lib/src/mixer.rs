@@ -0,0 +1,139 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::time::Duration;
+use rand;
+use rand::Rng;
+use rand::distributions::Exp;
+use sub_lib::hopper::MixDelay;
+
+// Holds items until a randomly chosen moment, then lets them all go at once in a shuffled order,
+// so an observer can't pair what leaves with what came in by timing or position. Only the
+// interleaving of different streams is shuffled: items of the same stream leave in the order they
+// came in, because whoever's at the other end needs them that way.
+pub struct Mixer<K, T> {
+    delay: MixDelay,
+    pool: Vec<(K, T)>,
+}
+
+impl<K: PartialEq + Clone, T> Mixer<K, T> {
+    pub fn new (delay: MixDelay) -> Mixer<K, T> {
+        Mixer {
+            delay,
+            pool: vec! (),
+        }
+    }
+
+    // Returns the delay until the pool should be released if this item starts a new batch
+    pub fn hold (&mut self, stream: K, item: T) -> Option<Duration> {
+        let starts_batch = self.pool.is_empty ();
+        self.pool.push ((stream, item));
+        if starts_batch {Some (sample_delay (&self.delay))} else {None}
+    }
+
+    // Each item gets one of the slots its stream drew in the shuffle, earliest item first
+    pub fn release (&mut self) -> Vec<T> {
+        let mut slots: Vec<Option<K>> = self.pool.iter ().map (|&(ref stream, _)| Some (stream.clone ())).collect ();
+        rand::thread_rng ().shuffle (&mut slots);
+        let mut batch: Vec<Option<T>> = self.pool.iter ().map (|_| None).collect ();
+        for (stream, item) in self.pool.drain (..) {
+            let index = slots.iter ().position (|slot| slot.as_ref () == Some (&stream)).expect ("Stream lost its slot");
+            slots[index] = None;
+            batch[index] = Some (item);
+        }
+        batch.into_iter ().map (|item_opt| item_opt.expect ("Slot left empty")).collect ()
+    }
+
+    pub fn len (&self) -> usize {
+        self.pool.len ()
+    }
+
+    pub fn is_empty (&self) -> bool {
+        self.pool.is_empty ()
+    }
+}
+
+pub fn sample_delay (delay: &MixDelay) -> Duration {
+    let mut rng = rand::thread_rng ();
+    let millis = match *delay {
+        MixDelay::Off => 0,
+        MixDelay::Uniform {min_ms, max_ms} if min_ms >= max_ms => min_ms,
+        MixDelay::Uniform {min_ms, max_ms} => rng.gen_range (min_ms, max_ms + 1),
+        MixDelay::Exponential {mean_ms: 0} => 0,
+        MixDelay::Exponential {mean_ms} => rng.sample (Exp::new (1.0 / mean_ms as f64)).round () as u64,
+    };
+    Duration::from_millis (millis)
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_first_item_of_a_batch_gets_a_release_delay () {
+        let mut subject = Mixer::new (MixDelay::Uniform {min_ms: 10, max_ms: 10});
+
+        assert_eq! (subject.hold ('a', 1), Some (Duration::from_millis (10)));
+        assert_eq! (subject.hold ('b', 2), None);
+        assert_eq! (subject.len (), 2);
+    }
+
+    #[test]
+    fn release_empties_the_pool_and_returns_everything_held () {
+        let mut subject = Mixer::new (MixDelay::Off);
+        (0..20).for_each (|n| {subject.hold (n, n);});
+
+        let mut batch = subject.release ();
+
+        assert_eq! (subject.is_empty (), true);
+        batch.sort ();
+        assert_eq! (batch, (0..20).collect::<Vec<i32>> ());
+        assert_eq! (subject.hold (99, 99).is_some (), true);
+    }
+
+    #[test]
+    fn release_keeps_each_stream_in_order_but_shuffles_the_streams_together () {
+        let mut interleavings: Vec<Vec<char>> = vec! ();
+        for _ in 0..100 {
+            let mut subject = Mixer::new (MixDelay::Off);
+            (0..5).for_each (|n| {subject.hold ('a', ('a', n));});
+            (0..5).for_each (|n| {subject.hold ('b', ('b', n));});
+
+            let batch = subject.release ();
+
+            let a_order: Vec<i32> = batch.iter ().filter (|&&(stream, _)| stream == 'a').map (|&(_, n)| n).collect ();
+            let b_order: Vec<i32> = batch.iter ().filter (|&&(stream, _)| stream == 'b').map (|&(_, n)| n).collect ();
+            assert_eq! (a_order, vec! (0, 1, 2, 3, 4));
+            assert_eq! (b_order, vec! (0, 1, 2, 3, 4));
+            let interleaving: Vec<char> = batch.iter ().map (|&(stream, _)| stream).collect ();
+            if !interleavings.contains (&interleaving) {interleavings.push (interleaving)}
+        }
+        assert! (interleavings.len () > 1, "{:?}", interleavings);
+    }
+
+    #[test]
+    fn uniform_delays_stay_inside_their_bounds () {
+        let delay = MixDelay::Uniform {min_ms: 50, max_ms: 60};
+
+        for _ in 0..1000 {
+            let sample = sample_delay (&delay);
+            assert! (sample >= Duration::from_millis (50) && sample <= Duration::from_millis (60), "{:?}", sample);
+        }
+    }
+
+    #[test]
+    fn exponential_delays_average_near_their_mean () {
+        let delay = MixDelay::Exponential {mean_ms: 100};
+
+        let total: u64 = (0..10000).map (|_| {
+            let sample = sample_delay (&delay);
+            sample.as_secs () * 1000 + u64::from (sample.subsec_millis ())
+        }).sum ();
+
+        let average = total / 10000;
+        assert! (average > 90 && average < 110, "{}", average);
+    }
+
+    #[test]
+    fn off_means_no_delay () {
+        assert_eq! (sample_delay (&MixDelay::Off), Duration::from_millis (0));
+    }
+}
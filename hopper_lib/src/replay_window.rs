@@ -0,0 +1,102 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+
+pub const REPLAY_WINDOW_SIZE: u64 = 64;
+
+// Tracks the sequence numbers a neighbor has used on its link to us. Numbers may arrive out of
+// order, but only within REPLAY_WINDOW_SIZE of the highest one seen, and each only once.
+pub struct ReplayWindow {
+    highest_sequence: u64,
+    // bit n is set if highest_sequence - n has been admitted
+    seen: u64,
+}
+
+impl ReplayWindow {
+    pub fn new () -> ReplayWindow {
+        ReplayWindow {
+            highest_sequence: 0,
+            seen: 0,
+        }
+    }
+
+    pub fn admit (&mut self, sequence: u64) -> bool {
+        if sequence == 0 {
+            return false
+        }
+        if sequence > self.highest_sequence {
+            let advance = sequence - self.highest_sequence;
+            self.seen = if advance >= REPLAY_WINDOW_SIZE {0} else {self.seen << advance};
+            self.seen |= 1;
+            self.highest_sequence = sequence;
+            return true
+        }
+        let age = self.highest_sequence - sequence;
+        if age >= REPLAY_WINDOW_SIZE {
+            return false
+        }
+        let mask = 1u64 << age;
+        if self.seen & mask != 0 {
+            return false
+        }
+        self.seen |= mask;
+        true
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admits_increasing_sequence_numbers () {
+        let mut subject = ReplayWindow::new ();
+
+        assert_eq! (subject.admit (1), true);
+        assert_eq! (subject.admit (2), true);
+        assert_eq! (subject.admit (1000), true);
+    }
+
+    #[test]
+    fn rejects_repeated_sequence_numbers () {
+        let mut subject = ReplayWindow::new ();
+        subject.admit (5);
+        subject.admit (6);
+
+        assert_eq! (subject.admit (6), false);
+        assert_eq! (subject.admit (5), false);
+    }
+
+    #[test]
+    fn admits_late_sequence_numbers_inside_the_window_once () {
+        let mut subject = ReplayWindow::new ();
+        subject.admit (100);
+
+        assert_eq! (subject.admit (100 - REPLAY_WINDOW_SIZE + 1), true);
+        assert_eq! (subject.admit (100 - REPLAY_WINDOW_SIZE + 1), false);
+    }
+
+    #[test]
+    fn rejects_sequence_numbers_older_than_the_window () {
+        let mut subject = ReplayWindow::new ();
+        subject.admit (100);
+
+        assert_eq! (subject.admit (100 - REPLAY_WINDOW_SIZE), false);
+    }
+
+    #[test]
+    fn forgets_seen_numbers_when_the_window_jumps_past_them () {
+        let mut subject = ReplayWindow::new ();
+        subject.admit (1);
+        subject.admit (2);
+        subject.admit (2 + REPLAY_WINDOW_SIZE);
+
+        assert_eq! (subject.admit (1 + REPLAY_WINDOW_SIZE), true);
+        assert_eq! (subject.admit (2), false);
+    }
+
+    #[test]
+    fn rejects_sequence_number_zero () {
+        let mut subject = ReplayWindow::new ();
+
+        assert_eq! (subject.admit (0), false);
+    }
+}
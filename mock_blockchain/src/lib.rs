@@ -0,0 +1,441 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+extern crate blockchain_bridge_lib;
+#[macro_use]
+extern crate serde_json;
+extern crate sub_lib;
+extern crate tiny_keccak;
+
+use std::collections::HashMap;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::thread;
+use serde_json;
+use serde_json::Value;
+use tiny_keccak::keccak256;
+use sub_lib::wallet::Wallet;
+use blockchain_bridge_lib::blockchain_rpc::BALANCE_OF_SELECTOR;
+use blockchain_bridge_lib::blockchain_rpc::TRANSFER_EVENT_TOPIC;
+use blockchain_bridge_lib::blockchain_rpc::TRANSFER_SELECTOR;
+use blockchain_bridge_lib::hex::decode_hex;
+use blockchain_bridge_lib::hex::encode_hex;
+use blockchain_bridge_lib::hex::from_quantity;
+use blockchain_bridge_lib::hex::to_quantity;
+use blockchain_bridge_lib::json_rpc::JsonRpcTransport;
+use blockchain_bridge_lib::raw_transaction::RawTransaction;
+use blockchain_bridge_lib::raw_transaction::Signer;
+
+pub const MOCK_GAS_PRICE: u64 = 1000000000;
+
+// An Ethereum node with the SUB contract on it, in memory, for tests. Balances are whatever the
+// test sets; submitted transfers wait until the test mines a block, and are reverted then if the
+// payer can't cover them; and the test can make any call fail. Gas is free. Served over HTTP, it
+// can stand in for --blockchain_service_url for Nodes in other processes or containers, and, for
+// wallets it's given the keys to, for --signing_service_url as well.
+#[derive (Clone)]
+pub struct MockBlockchain {
+    chain: Arc<Mutex<MockChain>>,
+}
+
+#[derive (Clone, Debug, PartialEq)]
+pub struct MockTransfer {
+    pub payer: Wallet,
+    pub payee: Wallet,
+    pub amount: u64,
+    pub transaction_hash: String,
+    // None until the transfer is mined
+    pub block_number_opt: Option<u64>,
+    // mined, but the payer didn't have the SUB
+    pub reverted: bool,
+}
+
+struct MockChain {
+    chain_id: u64,
+    contract: Wallet,
+    gas_price: u64,
+    eth_wei: HashMap<Wallet, u64>,
+    sub: HashMap<Wallet, u64>,
+    transfers: Vec<MockTransfer>,
+    block_number: u64,
+    // error messages waiting for the next calls of each method
+    failures: HashMap<String, Vec<String>>,
+    // wallets eth_signTransaction signs for
+    signers: HashMap<Wallet, Signer>,
+}
+
+impl JsonRpcTransport for MockBlockchain {
+    fn call (&self, method: &str, params: Value) -> Result<Value, String> {
+        self.answer (method, &params).map_err (|e| format! ("{} failed: {}", method, e))
+    }
+}
+
+impl MockBlockchain {
+    pub fn new (chain_id: u64, contract: Wallet) -> MockBlockchain {
+        MockBlockchain {
+            chain: Arc::new (Mutex::new (MockChain {
+                chain_id,
+                contract,
+                gas_price: MOCK_GAS_PRICE,
+                eth_wei: HashMap::new (),
+                sub: HashMap::new (),
+                transfers: vec! (),
+                block_number: 0,
+                failures: HashMap::new (),
+                signers: HashMap::new (),
+            }))
+        }
+    }
+
+    pub fn set_balances (&self, wallet: &Wallet, eth_wei: u64, sub: u64) {
+        let mut chain = self.lock ();
+        chain.eth_wei.insert (wallet.clone (), eth_wei);
+        chain.sub.insert (wallet.clone (), sub);
+    }
+
+    // Wei and SUB, as of the last block mined
+    pub fn balances (&self, wallet: &Wallet) -> (u64, u64) {
+        let chain = self.lock ();
+        (chain.eth_wei.get (wallet).cloned ().unwrap_or (0), chain.sub.get (wallet).cloned ().unwrap_or (0))
+    }
+
+    pub fn set_gas_price (&self, gas_price: u64) {
+        self.lock ().gas_price = gas_price
+    }
+
+    // The next call of the method fails with the message; queued failures are used up in order
+    pub fn fail_next (&self, method: &str, message: &str) {
+        self.lock ().failures.entry (String::from (method)).or_insert (vec! ()).push (String::from (message))
+    }
+
+    // From now on, transactions from the signer's wallet are signed when eth_signTransaction asks
+    pub fn add_signer (&self, signer: Signer) {
+        self.lock ().signers.insert (signer.wallet ().clone (), signer);
+    }
+
+    // Every transfer waiting is mined into one new block; returns its number
+    pub fn mine_block (&self) -> u64 {
+        let mut chain = self.lock ();
+        chain.block_number += 1;
+        let block_number = chain.block_number;
+        let mut transfers = chain.transfers.clone ();
+        for transfer in transfers.iter_mut ().filter (|transfer| transfer.block_number_opt.is_none ()) {
+            transfer.block_number_opt = Some (block_number);
+            let payer_sub = chain.sub.get (&transfer.payer).cloned ().unwrap_or (0);
+            if payer_sub < transfer.amount {
+                transfer.reverted = true;
+                continue
+            }
+            chain.sub.insert (transfer.payer.clone (), payer_sub - transfer.amount);
+            *chain.sub.entry (transfer.payee.clone ()).or_insert (0) += transfer.amount;
+        }
+        chain.transfers = transfers;
+        block_number
+    }
+
+    pub fn transfers (&self) -> Vec<MockTransfer> {
+        self.lock ().transfers.clone ()
+    }
+
+    // Answers JSON-RPC over HTTP on a thread of its own until the process ends; returns the address
+    // it's listening on, which is the one to bind to unless that one's port is 0
+    pub fn serve (&self, socket_addr: SocketAddr) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind (socket_addr)?;
+        let local_addr = listener.local_addr ()?;
+        let mock = self.clone ();
+        thread::spawn (move || {
+            for stream in listener.incoming () {
+                if let Ok (stream) = stream {
+                    let _ = mock.serve_request (stream);
+                }
+            }
+        });
+        Ok (local_addr)
+    }
+
+    fn serve_request (&self, mut stream: TcpStream) -> io::Result<()> {
+        let request = read_http_request (&mut stream)?;
+        let reply = match serde_json::from_slice::<Value> (&request[..]) {
+            Ok (request) => {
+                let method = request["method"].as_str ().unwrap_or ("").to_string ();
+                match self.answer (&method, &request["params"]) {
+                    Ok (result) => json! ({"jsonrpc": "2.0", "id": request["id"], "result": result}),
+                    Err (e) => json! ({"jsonrpc": "2.0", "id": request["id"], "error": {"code": -32000, "message": e}}),
+                }
+            },
+            Err (e) => json! ({"jsonrpc": "2.0", "id": null, "error": {"code": -32700, "message": format! ("{}", e)}}),
+        }.to_string ();
+        write! (stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", reply.len (), reply)
+    }
+
+    fn answer (&self, method: &str, params: &Value) -> Result<Value, String> {
+        let mut chain = self.lock ();
+        let failure_opt = match chain.failures.get_mut (method) {
+            Some (failures) => if failures.is_empty () {None} else {Some (failures.remove (0))},
+            None => None
+        };
+        if let Some (failure) = failure_opt {return Err (failure)}
+        match method {
+            "eth_getBalance" => {
+                let wallet = wallet_param (&params[0])?;
+                Ok (json! (to_quantity (chain.eth_wei.get (&wallet).cloned ().unwrap_or (0))))
+            },
+            "eth_call" => {
+                if wallet_param (&params[0]["to"])? != chain.contract {return Err (String::from ("Only the SUB contract is here"))}
+                let data = decode_hex (params[0]["data"].as_str ().unwrap_or (""))?;
+                if (data.len () != 36) || (data[..4] != BALANCE_OF_SELECTOR[..]) {return Err (String::from ("Only balanceOf can be called"))}
+                let wallet = Wallet::new (&encode_hex (&data[16..36]))?;
+                Ok (json! (format! ("0x{:064x}", chain.sub.get (&wallet).cloned ().unwrap_or (0))))
+            },
+            "eth_gasPrice" => Ok (json! (to_quantity (chain.gas_price))),
+            "eth_getTransactionCount" => {
+                let wallet = wallet_param (&params[0])?;
+                let with_pending = params[1].as_str () == Some ("pending");
+                Ok (json! (to_quantity (chain.nonce (&wallet, with_pending))))
+            },
+            "eth_sendRawTransaction" => {
+                let signed = decode_hex (params[0].as_str ().unwrap_or (""))?;
+                let transfer = chain.decode_transfer (&signed[..])?;
+                let transaction_hash = transfer.transaction_hash.clone ();
+                chain.transfers.push (transfer);
+                Ok (json! (transaction_hash))
+            },
+            "eth_signTransaction" => {
+                let request = &params[0];
+                let signer = match chain.signers.get (&wallet_param (&request["from"])?) {
+                    Some (signer) => signer,
+                    None => return Err (String::from ("No key here for that wallet"))
+                };
+                let transaction = RawTransaction {
+                    nonce: quantity_param (&request["nonce"])?,
+                    gas_price: quantity_param (&request["gasPrice"])?,
+                    gas_limit: quantity_param (&request["gas"])?,
+                    to: wallet_param (&request["to"])?,
+                    value: quantity_param (&request["value"])?,
+                    data: decode_hex (request["data"].as_str ().unwrap_or (""))?,
+                };
+                let signed = transaction.sign (signer, quantity_param (&request["chainId"])?);
+                Ok (json! (format! ("0x{}", encode_hex (&signed[..]))))
+            },
+            "eth_blockNumber" => Ok (json! (to_quantity (chain.block_number))),
+            "eth_getTransactionReceipt" => {
+                let transaction_hash = params[0].as_str ().unwrap_or ("");
+                match chain.transfers.iter ().find (|transfer| transfer.transaction_hash == transaction_hash) {
+                    Some (&MockTransfer {block_number_opt: Some (block_number), reverted, ..}) => Ok (json! ({
+                        "transactionHash": transaction_hash,
+                        "blockNumber": to_quantity (block_number),
+                        "status": if reverted {"0x0"} else {"0x1"},
+                    })),
+                    _ => Ok (Value::Null)
+                }
+            },
+            "eth_getLogs" => {
+                let filter = &params[0];
+                if wallet_param (&filter["address"])? != chain.contract {return Err (String::from ("Only the SUB contract is here"))}
+                let from_block = from_quantity (filter["fromBlock"].as_str ().unwrap_or ("0x0"))?;
+                let to_block = from_quantity (filter["toBlock"].as_str ().unwrap_or ("0x0"))?;
+                let payee_opt = match filter["topics"][2].as_str () {
+                    Some (topic) if topic.len () >= 40 => Some (Wallet::new (&topic[topic.len () - 40..])?),
+                    _ => None
+                };
+                let logs: Vec<Value> = chain.transfers.iter ()
+                    .filter (|transfer| !transfer.reverted)
+                    .filter (|transfer| match transfer.block_number_opt {
+                        Some (block_number) => (block_number >= from_block) && (block_number <= to_block),
+                        None => false
+                    })
+                    .filter (|transfer| payee_opt.as_ref ().map (|payee| payee == &transfer.payee).unwrap_or (true))
+                    .map (|transfer| json! ({
+                        "address": chain.contract.address,
+                        "topics": [TRANSFER_EVENT_TOPIC, address_topic (&transfer.payer), address_topic (&transfer.payee)],
+                        "data": format! ("0x{:064x}", transfer.amount),
+                        "transactionHash": transfer.transaction_hash,
+                        "blockNumber": to_quantity (transfer.block_number_opt.unwrap_or (0)),
+                    }))
+                    .collect ();
+                Ok (Value::Array (logs))
+            },
+            _ => Err (format! ("{} isn't supported", method))
+        }
+    }
+
+    fn lock (&self) -> MutexGuard<MockChain> {
+        self.chain.lock ().expect ("Mock blockchain is poisoned")
+    }
+}
+
+impl MockChain {
+    // Reverted transfers use up their nonces as well as successful ones
+    fn nonce (&self, wallet: &Wallet, with_pending: bool) -> u64 {
+        self.transfers.iter ()
+            .filter (|transfer| &transfer.payer == wallet)
+            .filter (|transfer| with_pending || transfer.block_number_opt.is_some ())
+            .count () as u64
+    }
+
+    fn decode_transfer (&self, signed: &[u8]) -> Result<MockTransfer, String> {
+        let decoded = RawTransaction::decode_signed (signed)?;
+        if decoded.chain_id != self.chain_id {return Err (format! ("Transaction is for chain {}, not {}", decoded.chain_id, self.chain_id))}
+        let transaction = decoded.transaction;
+        if transaction.to != self.contract {return Err (String::from ("Only the SUB contract is here"))}
+        let expected_nonce = self.nonce (&decoded.sender, true);
+        if transaction.nonce != expected_nonce {return Err (format! ("Nonce {} is wrong; {} is next", transaction.nonce, expected_nonce))}
+        let data = transaction.data;
+        if (data.len () != 68) || (data[..4] != TRANSFER_SELECTOR[..]) {return Err (String::from ("Only transfer can be sent"))}
+        if data[36..60].iter ().any (|byte| *byte != 0) {return Err (String::from ("Amount is too big for the mock"))}
+        let amount = data[60..68].iter ().fold (0, |amount, byte| (amount << 8) | *byte as u64);
+        Ok (MockTransfer {
+            payer: decoded.sender,
+            payee: Wallet::new (&encode_hex (&data[16..36]))?,
+            amount,
+            transaction_hash: format! ("0x{}", encode_hex (&keccak256 (signed)[..])),
+            block_number_opt: None,
+            reverted: false,
+        })
+    }
+}
+
+fn wallet_param (param: &Value) -> Result<Wallet, String> {
+    Wallet::new (param.as_str ().unwrap_or (""))
+}
+
+fn quantity_param (param: &Value) -> Result<u64, String> {
+    from_quantity (param.as_str ().unwrap_or ("0x0"))
+}
+
+fn address_topic (wallet: &Wallet) -> String {
+    format! ("0x{:0>64}", &wallet.address[2..])
+}
+
+// Enough HTTP for one JSON-RPC request: headers, then as much body as Content-Length says
+fn read_http_request (stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut request = vec! ();
+    let mut buf = [0u8; 4096];
+    loop {
+        if let Some (header_end) = request.windows (4).position (|window| window == b"\r\n\r\n") {
+            let header = String::from_utf8_lossy (&request[..header_end]).to_lowercase ();
+            let content_length = header.lines ()
+                .filter (|line| line.starts_with ("content-length:"))
+                .filter_map (|line| line["content-length:".len ()..].trim ().parse::<usize> ().ok ())
+                .next ().unwrap_or (0);
+            if request.len () >= header_end + 4 + content_length {
+                return Ok (request[header_end + 4..header_end + 4 + content_length].to_vec ())
+            }
+        }
+        let length = stream.read (&mut buf)?;
+        if length == 0 {return Err (io::Error::new (io::ErrorKind::UnexpectedEof, "Request ended early"))}
+        request.extend_from_slice (&buf[..length]);
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use blockchain_bridge_lib::blockchain_rpc::BlockchainRpc;
+    use blockchain_bridge_lib::blockchain_rpc::IncomingTransfer;
+    use blockchain_bridge_lib::blockchain_rpc::TransactionReceipt;
+    use blockchain_bridge_lib::json_rpc::JsonRpcHttp;
+    use blockchain_bridge_lib::raw_transaction::TransactionSigner;
+    use blockchain_bridge_lib::remote_signer::RemoteSigner;
+
+    fn contract () -> Wallet {
+        Wallet::new ("0x12480e24eb5bec1a9d4369cab6a80cad3c0a377a").unwrap ()
+    }
+
+    fn signer () -> Signer {
+        Signer::from_private_key ("4646464646464646464646464646464646464646464646464646464646464646").unwrap ()
+    }
+
+    #[test]
+    fn transfers_wait_to_be_mined_and_those_the_payer_cannot_cover_are_reverted () {
+        let mock = MockBlockchain::new (3, contract ());
+        let subject = BlockchainRpc::new (Box::new (mock.clone ()), 3, contract (), None);
+        let signer = signer ();
+        let payee = Wallet::new (&"5".repeat (40)).unwrap ();
+        mock.set_balances (signer.wallet (), 1000, 5000);
+
+        let first_hash = subject.transfer (&signer, &payee, 3000, MOCK_GAS_PRICE).unwrap ();
+        let second_hash = subject.transfer (&signer, &payee, 3000, MOCK_GAS_PRICE).unwrap ();
+
+        assert_eq! (subject.balances (&payee).unwrap ().sub, String::from ("0"));
+        assert_eq! (mock.mine_block (), 1);
+        assert_eq! (subject.balances (signer.wallet ()).unwrap ().sub, String::from ("2000"));
+        assert_eq! (subject.balances (signer.wallet ()).unwrap ().eth_wei, String::from ("1000"));
+        assert_eq! (mock.balances (&payee), (0, 3000));
+        assert_eq! (mock.transfers ()[1], MockTransfer {
+            payer: signer.wallet ().clone (),
+            payee: payee.clone (),
+            amount: 3000,
+            transaction_hash: second_hash,
+            block_number_opt: Some (1),
+            reverted: true,
+        });
+        assert_eq! (subject.block_number ().unwrap (), 1);
+        assert_eq! (subject.incoming_transfers (&payee, 1, 1).unwrap (), vec! (IncomingTransfer {
+            payer: signer.wallet ().clone (),
+            amount: String::from ("3000"),
+            transaction_hash: first_hash,
+            block_number: 1,
+        }));
+        assert_eq! (subject.incoming_transfers (&payee, 2, 2).unwrap (), vec! ());
+        assert_eq! (subject.transaction_receipt (&mock.transfers ()[0].transaction_hash).unwrap (), Some (TransactionReceipt {block_number: 1, succeeded: true}));
+        assert_eq! (subject.transaction_receipt (&mock.transfers ()[1].transaction_hash).unwrap (), Some (TransactionReceipt {block_number: 1, succeeded: false}));
+    }
+
+    #[test]
+    fn calls_fail_when_the_test_says_and_then_recover () {
+        let mock = MockBlockchain::new (3, contract ());
+        let subject = BlockchainRpc::new (Box::new (mock.clone ()), 3, contract (), None);
+        mock.set_gas_price (5);
+        mock.fail_next ("eth_gasPrice", "node is syncing");
+
+        assert_eq! (subject.gas_price (), Err (String::from ("eth_gasPrice failed: node is syncing")));
+        assert_eq! (subject.gas_price (), Ok (5));
+    }
+
+    #[test]
+    fn the_mock_serves_json_rpc_over_http () {
+        let mock = MockBlockchain::new (3, contract ());
+        let local_addr = mock.serve (SocketAddr::from_str ("127.0.0.1:0").unwrap ()).unwrap ();
+        let transport = JsonRpcHttp::new (&format! ("http://{}/", local_addr)).unwrap ();
+        let subject = BlockchainRpc::new (Box::new (transport), 3, contract (), None);
+        let signer = signer ();
+        mock.set_balances (signer.wallet (), 0, 100);
+        mock.fail_next ("eth_blockNumber", "node is down");
+
+        subject.transfer (&signer, &contract (), 40, MOCK_GAS_PRICE).unwrap ();
+        mock.mine_block ();
+
+        assert_eq! (subject.balances (signer.wallet ()).unwrap ().sub, String::from ("60"));
+        assert_eq! (subject.block_number (), Err (String::from ("eth_blockNumber failed: node is down")));
+        assert_eq! (subject.block_number (), Ok (1));
+    }
+
+    #[test]
+    fn the_mock_signs_for_wallets_it_has_the_keys_to_and_no_others () {
+        let mock = MockBlockchain::new (3, contract ());
+        let local_addr = mock.serve (SocketAddr::from_str ("127.0.0.1:0").unwrap ()).unwrap ();
+        let url = format! ("http://{}/", local_addr);
+        let subject = BlockchainRpc::new (Box::new (JsonRpcHttp::new (&url).unwrap ()), 3, contract (), None);
+        let signer = signer ();
+        let wallet = signer.wallet ().clone ();
+        let remote_signer = RemoteSigner::new (Box::new (JsonRpcHttp::new (&url).unwrap ()), wallet.clone ());
+        let stranger = Wallet::new (&"7".repeat (40)).unwrap ();
+        let stranger_signer = RemoteSigner::new (Box::new (JsonRpcHttp::new (&url).unwrap ()), stranger);
+        mock.set_balances (&wallet, 0, 100);
+        mock.add_signer (signer);
+
+        subject.transfer (&remote_signer, &contract (), 40, MOCK_GAS_PRICE).unwrap ();
+        mock.mine_block ();
+
+        assert_eq! (mock.balances (&wallet), (0, 60));
+        let transaction = RawTransaction {nonce: 1, gas_price: MOCK_GAS_PRICE, gas_limit: 60000, to: contract (), value: 0, data: vec! ()};
+        assert_eq! (stranger_signer.sign_transaction (&transaction, 3), Err (String::from ("eth_signTransaction failed: No key here for that wallet")));
+    }
+}
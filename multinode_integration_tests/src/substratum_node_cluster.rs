@@ -16,13 +16,20 @@ use regex::Regex;
 use command::Command;
 
 pub struct NodeStartupConfig {
-    port_numbers: Vec<u16>
+    port_numbers: Vec<u16>,
+    node_args: Vec<String>,
 }
 
 impl NodeStartupConfig {
     pub fn new (port_numbers: Vec<u16>) -> NodeStartupConfig {
+        NodeStartupConfig::with_node_args (port_numbers, vec! ())
+    }
+
+    // The arguments are added to the ones every test Node starts with; none may contain a space or a comma
+    pub fn with_node_args (port_numbers: Vec<u16>, node_args: Vec<String>) -> NodeStartupConfig {
         NodeStartupConfig {
-            port_numbers
+            port_numbers,
+            node_args
         }
     }
 
@@ -30,10 +37,17 @@ impl NodeStartupConfig {
         &self.port_numbers
     }
 
-    pub fn as_command_line_parameter<'a> (&'a self) -> &'a str {
+    pub fn get_node_args (&self) -> &Vec<String> {
+        &self.node_args
+    }
+
+    pub fn as_command_line_parameter (&self) -> String {
         // We don't know what these parameter clusters will look like eventually;
-        // right now they are not looked at except to ensure that they contain no spaces.
-        "booga"
+        // right now the first piece is not looked at except to ensure that it contains no spaces,
+        // and the rest are the Node's arguments.
+        let mut pieces = vec! (String::from ("booga"));
+        pieces.extend (self.node_args.iter ().cloned ());
+        pieces.join (",")
     }
 }
 
@@ -68,6 +82,18 @@ impl SubstratumNode {
         let socket_addr = SocketAddr::new(self.ip_address, port);
         SubstratumNodeClient::new(socket_addr)
     }
+
+    // What other Nodes need in --neighbor to reach this one; only Nodes started with --ip print it
+    pub fn node_descriptor (&self) -> String {
+        let mut logs_command = Command::new ("docker", vec! ("logs", self.name.as_str ()));
+        if logs_command.wait_for_exit () != 0 {panic! ("Couldn't get logs of {}", self.name)}
+        let logs = logs_command.stdout_as_string ();
+        let regex = Regex::new ("Substratum Node descriptor: (\\S+)").unwrap ();
+        match regex.captures (&logs) {
+            Some (captures) => String::from (captures.get (1).unwrap ().as_str ()),
+            None => panic! ("{} didn't print its descriptor:\n{}", self.name, logs)
+        }
+    }
 }
 
 pub struct SubstratumNodeClient {
@@ -103,6 +129,8 @@ impl SubstratumNodeClient {
 
 pub struct SubstratumNodeCluster {
     nodes: HashMap<String, SubstratumNode>,
+    // including those since stopped, whose names and addresses aren't reused
+    started_count: usize,
 }
 
 impl SubstratumNodeCluster {
@@ -110,6 +138,7 @@ impl SubstratumNodeCluster {
         start_nodes(&configs);
         let mut cluster = SubstratumNodeCluster {
             nodes: HashMap::new (),
+            started_count: configs.len (),
         };
         for idx in 0..configs.len() {
             let config = configs.remove (0);
@@ -119,6 +148,19 @@ impl SubstratumNodeCluster {
         cluster
     }
 
+    // Starts one more Node, after the ones already running, on the same network
+    pub fn start_node (&mut self, config: NodeStartupConfig) -> &SubstratumNode {
+        self.started_count += 1;
+        let index = self.started_count;
+        let index_parameter = index.to_string ();
+        let config_parameter = config.as_command_line_parameter ();
+        run_docker_script ("start_node.sh", vec! (index_parameter.as_str (), config_parameter.as_str ()));
+        let node = SubstratumNode::new (config, index);
+        let name = node.get_name ().to_string ();
+        self.nodes.insert (name.clone (), node);
+        self.nodes.get (&name).expect ("Internal error")
+    }
+
     pub fn running_node_names(&self) -> HashSet<String> {
         self.nodes.keys ().map (|key_ref| {key_ref.clone ()}).collect()
     }
@@ -150,8 +192,17 @@ impl SubstratumNodeCluster {
 }
 
 fn start_nodes (configs: &Vec<NodeStartupConfig>) {
-    let parameters = configs.iter().map(|config| { config.as_command_line_parameter() }).collect();
-    run_docker_script("start_nodes.sh", parameters);
+    let parameters: Vec<String> = configs.iter().map(|config| { config.as_command_line_parameter() }).collect();
+    run_docker_script("start_nodes.sh", parameters.iter().map(|parameter| { parameter.as_str() }).collect());
+}
+
+// Where Nodes on the test network can reach services the test itself runs, such as a mock blockchain:
+// the Docker host, or, when the tests run in the subjenkins container, that container (see start_nodes.sh)
+pub fn test_host_ip_address () -> IpAddr {
+    match env::var ("HOST_NODE_PARENT_DIR") {
+        Ok (ref dir) if !dir.is_empty () => IpAddr::from_str ("172.18.0.2").unwrap (),
+        _ => IpAddr::from_str ("172.18.0.1").unwrap (),
+    }
 }
 
 fn run_docker_script(script_name: &str, parameters: Vec<&str>) {
@@ -0,0 +1,82 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+extern crate blockchain_bridge_lib;
+extern crate mock_blockchain;
+extern crate sub_lib;
+extern crate multinode_integration_tests_lib;
+
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use blockchain_bridge_lib::raw_transaction::Signer;
+use mock_blockchain::MockBlockchain;
+use mock_blockchain::MockTransfer;
+use sub_lib::utils::index_of;
+use sub_lib::wallet::Wallet;
+use multinode_integration_tests_lib::substratum_node_cluster::NodeStartupConfig;
+use multinode_integration_tests_lib::substratum_node_cluster::SubstratumNodeCluster;
+use multinode_integration_tests_lib::substratum_node_cluster::test_host_ip_address;
+
+const CHAIN_ID: u64 = 3;
+const CONTRACT: &str = "0x12480e24eb5bec1a9d4369cab6a80cad3c0a377a";
+const EARNING_WALLET: &str = "0x5555555555555555555555555555555555555555";
+const CONSUMING_PRIVATE_KEY: &str = "4646464646464646464646464646464646464646464646464646464646464646";
+
+#[test]
+fn a_node_that_uses_another_for_an_exit_pays_it_on_the_mock_blockchain () {
+    let contract = Wallet::new (CONTRACT).unwrap ();
+    let earning_wallet = Wallet::new (EARNING_WALLET).unwrap ();
+    let signer = Signer::from_private_key (CONSUMING_PRIVATE_KEY).unwrap ();
+    let consuming_wallet = signer.wallet ().clone ();
+    let mock = MockBlockchain::new (CHAIN_ID, contract.clone ());
+    mock.set_balances (&consuming_wallet, 1000000000000000000, 1000000000);
+    mock.add_signer (signer);
+    let local_addr = mock.serve (SocketAddr::from_str ("0.0.0.0:0").unwrap ()).unwrap ();
+    let url = format! ("http://{}:{}/", test_host_ip_address (), local_addr.port ());
+    let blockchain_args = vec! (
+        "--blockchain_service_url", url.as_str (),
+        "--chain_id", "3",
+        "--sub_contract_address", CONTRACT,
+    );
+
+    let mut cluster = SubstratumNodeCluster::new (vec! (NodeStartupConfig::with_node_args (vec! (1234), node_args (
+        vec! ("--ip", "172.18.1.1", "--clandestine_port", "1234", "--earning_wallet", EARNING_WALLET,
+            "--payment_watch_interval", "1000"),
+        &blockchain_args,
+    ))));
+    let serving_descriptor = cluster.get_node ("test_node_1").unwrap ().node_descriptor ();
+    let mut client = cluster.start_node (NodeStartupConfig::with_node_args (vec! (1234), node_args (
+        vec! ("--ip", "172.18.1.2", "--clandestine_port", "1234", "--mode", "originate_only",
+            "--neighbor", serving_descriptor.as_str (),
+            "--signing_service_url", url.as_str (), "--signing_wallet", consuming_wallet.address.as_str (),
+            "--payment_threshold", "1", "--payable_scan_interval", "1000"),
+        &blockchain_args,
+    ))).make_client (80);
+
+    client.send_chunk (Vec::from (&b"GET /html HTTP/1.1\r\nHost: httpbin.org\r\n\r\n"[..]));
+    let response = client.wait_for_chunk ();
+    assert_eq! (index_of (&response[..], b"It was the Bottle Conjuror!").is_some (), true,
+        "Did not contain 'It was the Bottle Conjuror!': '{}'", String::from_utf8_lossy (&response[..]));
+    let payment = wait_for_payment (&mock, &consuming_wallet, &earning_wallet);
+
+    cluster.stop_all ();
+    assert_eq! (payment.reverted, false);
+    assert_eq! (payment.amount > 0, true);
+    assert_eq! (mock.balances (&earning_wallet).1, payment.amount);
+}
+
+fn node_args (args: Vec<&str>, more_args: &Vec<&str>) -> Vec<String> {
+    args.into_iter ().chain (more_args.iter ().cloned ()).map (String::from).collect ()
+}
+
+// Mines a block a second, as a real network would, until the payment is in one
+fn wait_for_payment (mock: &MockBlockchain, payer: &Wallet, payee: &Wallet) -> MockTransfer {
+    for _ in 0..60 {
+        thread::sleep (Duration::from_millis (1000));
+        mock.mine_block ();
+        let payment_opt = mock.transfers ().into_iter ()
+            .find (|transfer| (&transfer.payer == payer) && (&transfer.payee == payee) && transfer.block_number_opt.is_some ());
+        if let Some (payment) = payment_opt {return payment}
+    }
+    panic! ("{} never paid {}: {:?}", payer, payee, mock.transfers ())
+}
@@ -0,0 +1,149 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::collections::HashMap;
+use serde_cbor;
+use sub_lib::cryptde::CryptData;
+use sub_lib::cryptde::CryptDE;
+use sub_lib::cryptde::Key;
+use sub_lib::cryptde::PlainData;
+
+// One Node's signed statement that another Node is abusive. It travels in Gossip unaltered, so
+// whoever receives it can check who made the accusation.
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BanRecord {
+    pub banned_key: Key,
+    pub reason: String,
+    pub banner_key: Key,
+    pub signature: CryptData,
+}
+
+impl BanRecord {
+    pub fn new (banned_key: &Key, reason: &str, cryptde: &CryptDE) -> BanRecord {
+        let mut record = BanRecord {
+            banned_key: banned_key.clone (),
+            reason: String::from (reason),
+            banner_key: cryptde.public_key (),
+            signature: CryptData::new (&[]),
+        };
+        record.signature = cryptde.sign (&record.signed_data ()).expect ("Couldn't sign BanRecord");
+        record
+    }
+
+    pub fn signed_data (&self) -> PlainData {
+        let contents = (&self.banned_key, &self.reason, &self.banner_key);
+        PlainData::new (&serde_cbor::ser::to_vec (&contents).expect ("Serialization failure")[..])
+    }
+
+    pub fn has_valid_signature (&self, cryptde: &CryptDE) -> bool {
+        cryptde.verify_signature (&self.signed_data (), &self.signature, &self.banner_key)
+    }
+}
+
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ban {
+    pub record: BanRecord,
+    // whether the record goes out in Gossip
+    pub shared: bool,
+}
+
+pub struct BanList {
+    bans: HashMap<Key, Ban>,
+}
+
+impl BanList {
+    pub fn new () -> BanList {
+        BanList {
+            bans: HashMap::new (),
+        }
+    }
+
+    pub fn is_banned (&self, public_key: &Key) -> bool {
+        self.bans.contains_key (public_key)
+    }
+
+    // Returns false if the Node was already banned
+    pub fn ban (&mut self, ban: Ban) -> bool {
+        if self.is_banned (&ban.record.banned_key) {return false}
+        self.bans.insert (ban.record.banned_key.clone (), ban);
+        true
+    }
+
+    // Only the Node that imposed a ban can lift it; returns the ban that was lifted, if any
+    pub fn unban (&mut self, banned_key: &Key, banner_key: &Key) -> Option<Ban> {
+        match self.bans.get (banned_key) {
+            Some (ref ban) if &ban.record.banner_key == banner_key => (),
+            _ => return None
+        }
+        self.bans.remove (banned_key)
+    }
+
+    pub fn bans (&self) -> Vec<&Ban> {
+        let mut bans: Vec<&Ban> = self.bans.values ().collect ();
+        bans.sort_by (|a, b| a.record.banned_key.data.cmp (&b.record.banned_key.data));
+        bans
+    }
+
+    pub fn shared_records (&self) -> Vec<&BanRecord> {
+        self.bans ().into_iter ()
+            .filter (|ban| ban.shared)
+            .map (|ban| &ban.record)
+            .collect ()
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use sub_lib::cryptde_null::CryptDENull;
+
+    #[test]
+    fn ban_records_verify_against_the_banner_s_key () {
+        let mut banner = CryptDENull::new ();
+        banner.generate_key_pair ();
+
+        let subject = BanRecord::new (&Key::new (b"villain"), "Forged Gossip", &banner);
+
+        assert_eq! (subject.banner_key, banner.public_key ());
+        assert_eq! (subject.has_valid_signature (&banner), true);
+    }
+
+    #[test]
+    fn altered_ban_records_do_not_verify () {
+        let mut banner = CryptDENull::new ();
+        banner.generate_key_pair ();
+        let mut subject = BanRecord::new (&Key::new (b"villain"), "Forged Gossip", &banner);
+
+        subject.banned_key = Key::new (b"innocent");
+
+        assert_eq! (subject.has_valid_signature (&banner), false);
+    }
+
+    #[test]
+    fn only_shared_bans_are_offered_for_gossip () {
+        let banner = CryptDENull::new ();
+        let shared = BanRecord::new (&Key::new (b"shared"), "", &banner);
+        let private = BanRecord::new (&Key::new (b"private"), "", &banner);
+        let mut subject = BanList::new ();
+
+        assert_eq! (subject.ban (Ban {record: shared.clone (), shared: true}), true);
+        assert_eq! (subject.ban (Ban {record: private, shared: false}), true);
+        assert_eq! (subject.ban (Ban {record: shared.clone (), shared: false}), false);
+
+        assert_eq! (subject.is_banned (&Key::new (b"private")), true);
+        assert_eq! (subject.shared_records (), vec! (&shared));
+    }
+
+    #[test]
+    fn only_the_banner_can_lift_a_ban () {
+        let mut banner = CryptDENull::new ();
+        banner.generate_key_pair ();
+        let record = BanRecord::new (&Key::new (b"debtor"), "Unpaid debt", &banner);
+        let mut subject = BanList::new ();
+        subject.ban (Ban {record: record.clone (), shared: false});
+
+        assert_eq! (subject.unban (&Key::new (b"debtor"), &Key::new (b"stranger")), None);
+        assert_eq! (subject.is_banned (&Key::new (b"debtor")), true);
+        assert_eq! (subject.unban (&Key::new (b"debtor"), &banner.public_key ()), Some (Ban {record, shared: false}));
+        assert_eq! (subject.is_banned (&Key::new (b"debtor")), false);
+        assert_eq! (subject.unban (&Key::new (b"debtor"), &banner.public_key ()), None);
+    }
+}
@@ -0,0 +1,136 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::fs::File;
+use std::io::Read;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+struct IpRange {
+    first: IpAddr,
+    last: IpAddr,
+    country: String,
+    asn_opt: Option<u32>,
+    operator_opt: Option<String>,
+}
+
+// Maps IP addresses to the countries they're assigned to, and optionally to the autonomous system
+// and organization that announce them. The source is a CSV file with one range per line: first
+// address, last address, two-letter country code, then optionally the AS number and the operator's
+// name. Blank lines and lines starting with '#' are skipped.
+pub struct GeolocationTable {
+    ranges: Vec<IpRange>,
+}
+
+impl GeolocationTable {
+    pub fn new () -> GeolocationTable {
+        GeolocationTable {ranges: vec! ()}
+    }
+
+    pub fn load (path: &Path) -> Result<GeolocationTable, String> {
+        let mut csv = String::new ();
+        match File::open (path).and_then (|mut file| file.read_to_string (&mut csv)) {
+            Err (e) => Err (format! ("Couldn't read geolocation database {:?}: {}", path, e)),
+            Ok (_) => GeolocationTable::from_csv (&csv)
+        }
+    }
+
+    pub fn from_csv (csv: &str) -> Result<GeolocationTable, String> {
+        let mut ranges = vec! ();
+        for (index, line) in csv.lines ().enumerate () {
+            let line = line.trim ();
+            if line.is_empty () || line.starts_with ('#') {continue}
+            let fields: Vec<&str> = line.split (',').map (|field| field.trim ().trim_matches ('"')).collect ();
+            let invalid = || format! ("Invalid geolocation range on line {}: '{}'", index + 1, line);
+            let (first, last) = match (fields.len (), IpAddr::from_str (fields[0]), fields.get (1).map (|field| IpAddr::from_str (field))) {
+                (3...5, Ok (first), Some (Ok (last))) if (first <= last) && (fields[2].len () == 2) => (first, last),
+                _ => return Err (invalid ())
+            };
+            let asn_opt = match fields.get (3) {
+                None | Some (&"") => None,
+                Some (asn) => match u32::from_str (asn.trim_left_matches ("AS")) {
+                    Ok (asn) => Some (asn),
+                    Err (_) => return Err (invalid ())
+                }
+            };
+            let operator_opt = match fields.get (4) {
+                None | Some (&"") => None,
+                Some (operator) => Some (String::from (*operator))
+            };
+            let range = IpRange {first, last, country: fields[2].to_uppercase (), asn_opt, operator_opt};
+            ranges.push (range);
+        }
+        ranges.sort_by (|a, b| a.first.cmp (&b.first));
+        Ok (GeolocationTable {ranges})
+    }
+
+    pub fn country_of (&self, ip_addr: &IpAddr) -> Option<&str> {
+        self.range_of (ip_addr).map (|range| range.country.as_str ())
+    }
+
+    pub fn asn_of (&self, ip_addr: &IpAddr) -> Option<u32> {
+        self.range_of (ip_addr).and_then (|range| range.asn_opt)
+    }
+
+    pub fn operator_of (&self, ip_addr: &IpAddr) -> Option<&str> {
+        self.range_of (ip_addr).and_then (|range| range.operator_opt.as_ref ().map (|operator| operator.as_str ()))
+    }
+
+    fn range_of (&self, ip_addr: &IpAddr) -> Option<&IpRange> {
+        let candidate_count = match self.ranges.binary_search_by (|range| range.first.cmp (ip_addr)) {
+            Ok (index) => index + 1,
+            Err (index) => index
+        };
+        self.ranges[..candidate_count].iter ().rev ()
+            .find (|range| &range.last >= ip_addr)
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    fn ip (text: &str) -> IpAddr {
+        IpAddr::from_str (text).unwrap ()
+    }
+
+    #[test]
+    fn addresses_are_located_by_the_range_that_contains_them () {
+        let subject = GeolocationTable::from_csv ("# first,last,country\n\
+            \"5.0.0.0\",\"5.255.255.255\",\"de\"\n\
+            \n\
+            1.0.0.0,1.0.0.255,AU\n\
+            2001:db8::,2001:db8::ffff,NL\n").unwrap ();
+
+        assert_eq! (subject.country_of (&ip ("1.0.0.0")), Some ("AU"));
+        assert_eq! (subject.country_of (&ip ("1.0.0.255")), Some ("AU"));
+        assert_eq! (subject.country_of (&ip ("5.6.7.8")), Some ("DE"));
+        assert_eq! (subject.country_of (&ip ("2001:db8::1")), Some ("NL"));
+        assert_eq! (subject.country_of (&ip ("1.0.1.0")), None);
+        assert_eq! (subject.country_of (&ip ("0.255.255.255")), None);
+        assert_eq! (GeolocationTable::new ().country_of (&ip ("1.0.0.0")), None);
+    }
+
+    #[test]
+    fn ranges_can_name_their_autonomous_system_and_operator () {
+        let subject = GeolocationTable::from_csv ("8.8.8.0,8.8.8.255,US,AS15169,Google LLC\n\
+            9.9.9.0,9.9.9.255,CH,19281\n\
+            1.0.0.0,1.0.0.255,AU,,\n").unwrap ();
+
+        assert_eq! (subject.asn_of (&ip ("8.8.8.8")), Some (15169));
+        assert_eq! (subject.operator_of (&ip ("8.8.8.8")), Some ("Google LLC"));
+        assert_eq! (subject.asn_of (&ip ("9.9.9.9")), Some (19281));
+        assert_eq! (subject.operator_of (&ip ("9.9.9.9")), None);
+        assert_eq! (subject.asn_of (&ip ("1.0.0.1")), None);
+        assert_eq! (subject.operator_of (&ip ("1.0.0.1")), None);
+    }
+
+    #[test]
+    fn malformed_ranges_are_rejected () {
+        assert_eq! (GeolocationTable::from_csv ("1.0.0.0,1.0.0.255,AU\n1.0.1.0,booga,CN").err (),
+            Some (String::from ("Invalid geolocation range on line 2: '1.0.1.0,booga,CN'")));
+        assert_eq! (GeolocationTable::from_csv ("1.0.0.255,1.0.0.0,AU").is_err (), true);
+        assert_eq! (GeolocationTable::from_csv ("1.0.0.0,1.0.0.255,Australia").is_err (), true);
+        assert_eq! (GeolocationTable::from_csv ("1.0.0.0,1.0.0.255,AU,ASbooga").is_err (), true);
+        assert_eq! (GeolocationTable::from_csv ("1.0.0.0,1.0.0.255,AU,1,Operator,extra").is_err (), true);
+    }
+}
@@ -0,0 +1,386 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::time::Duration;
+use std::time::Instant;
+use serde_cbor;
+use sub_lib::cryptde::CryptDE;
+use sub_lib::cryptde::CryptData;
+use sub_lib::cryptde::Key;
+use sub_lib::cryptde::PlainData;
+use ban::BanRecord;
+use neighborhood_database::NodeRecord;
+
+// Far more than an honest network of this size needs; anything bigger is dropped unread
+pub const MAX_GOSSIP_NODE_RECORDS: usize = 4096;
+pub const MAX_GOSSIP_BANS: usize = 1024;
+
+// Each neighbor gets this many Gossip packages per window; the rest are dropped
+pub const MAX_GOSSIP_PER_WINDOW: usize = 20;
+pub const GOSSIP_RATE_WINDOW_MS: u64 = 10000;
+
+// How many introductions received are remembered, so that a recorded one can't be played back
+pub const MAX_REMEMBERED_INTRODUCTIONS: usize = 4096;
+
+// Everything the sending Node knows about the network, delivered to a neighbor's Neighborhood
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Gossip {
+    pub node_records: Vec<NodeRecord>,
+    // Nodes that predate bans send none
+    #[serde (default)]
+    pub bans: Vec<BanRecord>,
+}
+
+impl Gossip {
+    pub fn is_oversized (&self) -> bool {
+        (self.node_records.len () > MAX_GOSSIP_NODE_RECORDS) || (self.bans.len () > MAX_GOSSIP_BANS)
+    }
+}
+
+// Gossip as it travels between neighbors: encrypted so only the neighbor it's meant for can read
+// it, and signed by the sender, whose public key is authenticated as associated data. Anybody
+// watching the wire sees who is talking to whom, but not what either knows about the network.
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SealedGossip {
+    pub sender_public_key: Key,
+    pub sealed: CryptData,
+}
+
+#[derive (Serialize, Deserialize)]
+struct SignedGossip {
+    gossip: PlainData,
+    signature: CryptData,
+}
+
+impl SealedGossip {
+    pub fn seal (gossip: &Gossip, recipient_public_key: &Key, cryptde: &CryptDE) -> Result<SealedGossip, String> {
+        let serialized = match serde_cbor::ser::to_vec (gossip) {
+            Ok (serialized) => PlainData::new (&serialized[..]),
+            Err (e) => return Err (format! ("Couldn't serialize Gossip: {:?}", e))
+        };
+        let signature = match cryptde.sign (&serialized) {
+            Ok (signature) => signature,
+            Err (e) => return Err (format! ("Couldn't sign Gossip: {:?}", e))
+        };
+        let signed = serde_cbor::ser::to_vec (&SignedGossip {gossip: serialized, signature}).expect ("Internal error: couldn't serialize SignedGossip");
+        let sender_public_key = cryptde.public_key ();
+        match cryptde.encrypt (recipient_public_key, &sender_public_key.data[..], &PlainData::new (&signed[..])) {
+            Ok (sealed) => Ok (SealedGossip {sender_public_key, sealed}),
+            Err (e) => Err (format! ("Couldn't encrypt Gossip: {:?}", e))
+        }
+    }
+
+    pub fn open (&self, cryptde: &CryptDE) -> Result<Gossip, String> {
+        let signed_data = match cryptde.decrypt (&cryptde.private_key (), &self.sender_public_key.data[..], &self.sealed) {
+            Ok (signed_data) => signed_data,
+            Err (e) => return Err (format! ("Couldn't decrypt Gossip: {:?}", e))
+        };
+        let signed = match serde_cbor::de::from_slice::<SignedGossip> (&signed_data.data[..]) {
+            Ok (signed) => signed,
+            Err (e) => return Err (format! ("Couldn't read decrypted Gossip: {:?}", e))
+        };
+        if !cryptde.verify_signature (&signed.gossip, &signed.signature, &self.sender_public_key) {
+            return Err (String::from ("Gossip is not signed by its sender"))
+        }
+        match serde_cbor::de::from_slice::<Gossip> (&signed.gossip.data[..]) {
+            Ok (gossip) => Ok (gossip),
+            Err (e) => Err (format! ("Couldn't read decrypted Gossip: {:?}", e))
+        }
+    }
+}
+
+// How Nodes that want more neighbors meet Nodes they've only heard about in Gossip. The nonce
+// is picked fresh by the requester for each request, and the answer carries it back.
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Introduction {
+    // The sender, whose record this is, asks to become a neighbor
+    Request {record: NodeRecord, nonce: u64},
+    // The sender, whose record this is, has made the requester a neighbor
+    Accepted {record: NodeRecord, nonce: u64},
+    // The sender already has as many neighbors as it will take
+    Declined {nonce: u64},
+}
+
+// An introduction as it travels between Nodes: encrypted for the Node it's meant for, and signed by
+// the sender together with the recipient's public key, so the recipient can't pass it along to
+// some other Node as if it had been sent there. Its fields aren't named like SealedGossip's, so
+// neither can pass for the other.
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SealedIntroduction {
+    pub sender_public_key: Key,
+    pub sealed_introduction: CryptData,
+}
+
+#[derive (Serialize, Deserialize)]
+struct SignedIntroduction {
+    introduction: PlainData,
+    signature: CryptData,
+}
+
+impl SealedIntroduction {
+    pub fn seal (introduction: &Introduction, recipient_public_key: &Key, cryptde: &CryptDE) -> Result<SealedIntroduction, String> {
+        let serialized = match serde_cbor::ser::to_vec (introduction) {
+            Ok (serialized) => PlainData::new (&serialized[..]),
+            Err (e) => return Err (format! ("Couldn't serialize introduction: {:?}", e))
+        };
+        let signature = match cryptde.sign (&SealedIntroduction::signed_data (&serialized, recipient_public_key)) {
+            Ok (signature) => signature,
+            Err (e) => return Err (format! ("Couldn't sign introduction: {:?}", e))
+        };
+        let signed = serde_cbor::ser::to_vec (&SignedIntroduction {introduction: serialized, signature}).expect ("Internal error: couldn't serialize SignedIntroduction");
+        let sender_public_key = cryptde.public_key ();
+        match cryptde.encrypt (recipient_public_key, &sender_public_key.data[..], &PlainData::new (&signed[..])) {
+            Ok (sealed_introduction) => Ok (SealedIntroduction {sender_public_key, sealed_introduction}),
+            Err (e) => Err (format! ("Couldn't encrypt introduction: {:?}", e))
+        }
+    }
+
+    pub fn open (&self, cryptde: &CryptDE) -> Result<Introduction, String> {
+        let signed_data = match cryptde.decrypt (&cryptde.private_key (), &self.sender_public_key.data[..], &self.sealed_introduction) {
+            Ok (signed_data) => signed_data,
+            Err (e) => return Err (format! ("Couldn't decrypt introduction: {:?}", e))
+        };
+        let signed = match serde_cbor::de::from_slice::<SignedIntroduction> (&signed_data.data[..]) {
+            Ok (signed) => signed,
+            Err (e) => return Err (format! ("Couldn't read decrypted introduction: {:?}", e))
+        };
+        let signed_for_us = SealedIntroduction::signed_data (&signed.introduction, &cryptde.public_key ());
+        if !cryptde.verify_signature (&signed_for_us, &signed.signature, &self.sender_public_key) {
+            return Err (String::from ("Introduction is not signed by its sender for this Node"))
+        }
+        match serde_cbor::de::from_slice::<Introduction> (&signed.introduction.data[..]) {
+            Ok (introduction) => Ok (introduction),
+            Err (e) => Err (format! ("Couldn't read decrypted introduction: {:?}", e))
+        }
+    }
+
+    fn signed_data (serialized: &PlainData, recipient_public_key: &Key) -> PlainData {
+        let mut data = recipient_public_key.data.clone ();
+        data.extend_from_slice (&serialized.data[..]);
+        PlainData::new (&data[..])
+    }
+}
+
+// The nonces of the latest introductions from each sender; a request seen once is a replay the
+// second time. The oldest are forgotten first.
+pub struct IntroductionReplayGuard {
+    max_remembered: usize,
+    order: VecDeque<(Key, u64)>,
+    seen: HashSet<(Key, u64)>,
+}
+
+impl IntroductionReplayGuard {
+    pub fn new (max_remembered: usize) -> IntroductionReplayGuard {
+        IntroductionReplayGuard {
+            max_remembered,
+            order: VecDeque::new (),
+            seen: HashSet::new (),
+        }
+    }
+
+    // Returns false if this sender has used this nonce before
+    pub fn first_sight (&mut self, sender_public_key: &Key, nonce: u64) -> bool {
+        let entry = (sender_public_key.clone (), nonce);
+        if self.seen.contains (&entry) {return false}
+        if self.order.len () >= self.max_remembered {
+            if let Some (oldest) = self.order.pop_front () {
+                self.seen.remove (&oldest);
+            }
+        }
+        self.order.push_back (entry.clone ());
+        self.seen.insert (entry);
+        true
+    }
+}
+
+pub struct GossipRateLimiter {
+    max_per_window: usize,
+    window: Duration,
+    arrivals: HashMap<IpAddr, VecDeque<Instant>>,
+}
+
+impl GossipRateLimiter {
+    pub fn new (max_per_window: usize, window: Duration) -> GossipRateLimiter {
+        GossipRateLimiter {
+            max_per_window,
+            window,
+            arrivals: HashMap::new (),
+        }
+    }
+
+    // Returns false if the neighbor has already used up its allowance for the window
+    pub fn allow (&mut self, ip_addr: IpAddr, now: Instant) -> bool {
+        let window = self.window;
+        let arrivals = self.arrivals.entry (ip_addr).or_insert_with (|| VecDeque::new ());
+        while arrivals.front ().map (|arrival| now.duration_since (*arrival) >= window).unwrap_or (false) {
+            arrivals.pop_front ();
+        }
+        if arrivals.len () >= self.max_per_window {return false}
+        arrivals.push_back (now);
+        true
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use sub_lib::cryptde_null::CryptDENull;
+    use heartbeat::Heartbeat;
+
+    #[test]
+    fn neighbors_get_a_limited_number_of_gossip_packages_per_window () {
+        let mut subject = GossipRateLimiter::new (2, Duration::from_millis (1000));
+        let flooder = IpAddr::from_str ("1.2.3.4").unwrap ();
+        let bystander = IpAddr::from_str ("2.3.4.5").unwrap ();
+        let now = Instant::now ();
+
+        assert_eq! (subject.allow (flooder, now), true);
+        assert_eq! (subject.allow (flooder, now + Duration::from_millis (10)), true);
+        assert_eq! (subject.allow (flooder, now + Duration::from_millis (20)), false);
+        assert_eq! (subject.allow (bystander, now + Duration::from_millis (20)), true);
+        assert_eq! (subject.allow (flooder, now + Duration::from_millis (1000)), true);
+        assert_eq! (subject.allow (flooder, now + Duration::from_millis (1005)), false);
+    }
+
+    #[test]
+    fn gossip_with_too_many_records_is_oversized () {
+        let record = NodeRecord::new (&Key::new (b"node"), None, 1);
+        let subject = Gossip {node_records: vec! (record; MAX_GOSSIP_NODE_RECORDS + 1), bans: vec! ()};
+
+        assert_eq! (subject.is_oversized (), true);
+        assert_eq! (Gossip {node_records: vec! (), bans: vec! ()}.is_oversized (), false);
+    }
+
+    fn make_cryptde () -> CryptDENull {
+        let mut cryptde = CryptDENull::new ();
+        cryptde.generate_key_pair ();
+        cryptde
+    }
+
+    #[test]
+    fn sealed_gossip_is_opened_only_by_its_recipient () {
+        let sender = make_cryptde ();
+        let recipient = make_cryptde ();
+        let bystander = make_cryptde ();
+        let gossip = Gossip {node_records: vec! (NodeRecord::new (&Key::new (b"node"), None, 1)), bans: vec! ()};
+
+        let subject = SealedGossip::seal (&gossip, &recipient.public_key (), &sender).unwrap ();
+
+        assert_eq! (subject.sender_public_key, sender.public_key ());
+        assert_eq! (subject.open (&recipient), Ok (gossip));
+        assert_eq! (subject.open (&bystander).is_err (), true);
+    }
+
+    #[test]
+    fn sealed_gossip_is_not_opened_if_it_claims_somebody_else_sent_it () {
+        let sender = make_cryptde ();
+        let recipient = make_cryptde ();
+        let gossip = Gossip {node_records: vec! (), bans: vec! ()};
+        let mut subject = SealedGossip::seal (&gossip, &recipient.public_key (), &sender).unwrap ();
+
+        subject.sender_public_key = make_cryptde ().public_key ();
+
+        assert_eq! (subject.open (&recipient).is_err (), true);
+    }
+
+    #[test]
+    fn sealed_gossip_is_not_opened_if_the_signature_is_not_the_sender_s () {
+        let sender = make_cryptde ();
+        let forger = make_cryptde ();
+        let recipient = make_cryptde ();
+        let serialized = PlainData::new (&serde_cbor::ser::to_vec (&Gossip {node_records: vec! (), bans: vec! ()}).unwrap ()[..]);
+        let signed = serde_cbor::ser::to_vec (&SignedGossip {gossip: serialized.clone (), signature: forger.sign (&serialized).unwrap ()}).unwrap ();
+        let subject = SealedGossip {
+            sender_public_key: sender.public_key (),
+            sealed: forger.encrypt (&recipient.public_key (), &sender.public_key ().data[..], &PlainData::new (&signed[..])).unwrap (),
+        };
+
+        assert_eq! (subject.open (&recipient), Err (String::from ("Gossip is not signed by its sender")));
+    }
+
+    #[test]
+    fn sealed_gossip_cannot_be_mistaken_for_anything_else () {
+        let sealed = SealedGossip::seal (&Gossip {node_records: vec! (), bans: vec! ()}, &make_cryptde ().public_key (), &make_cryptde ()).unwrap ();
+        let serialized = serde_cbor::ser::to_vec (&sealed).unwrap ();
+
+        assert_eq! (serde_cbor::de::from_slice::<Gossip> (&serialized[..]).is_err (), true);
+        assert_eq! (serde_cbor::de::from_slice::<Heartbeat> (&serialized[..]).is_err (), true);
+        assert_eq! (serde_cbor::de::from_slice::<Introduction> (&serialized[..]).is_err (), true);
+        assert_eq! (serde_cbor::de::from_slice::<SealedGossip> (&serialized[..]).unwrap (), sealed);
+    }
+
+    #[test]
+    fn introductions_cannot_be_mistaken_for_gossip_or_heartbeats () {
+        let record = NodeRecord::new (&Key::new (b"node"), None, 1);
+        for introduction in vec! (Introduction::Request {record: record.clone (), nonce: 1}, Introduction::Accepted {record, nonce: 1}, Introduction::Declined {nonce: 1}) {
+            let serialized = serde_cbor::ser::to_vec (&introduction).unwrap ();
+
+            assert_eq! (serde_cbor::de::from_slice::<Gossip> (&serialized[..]).is_err (), true);
+            assert_eq! (serde_cbor::de::from_slice::<Heartbeat> (&serialized[..]).is_err (), true);
+            assert_eq! (serde_cbor::de::from_slice::<Introduction> (&serialized[..]).unwrap (), introduction);
+        }
+        let gossip = serde_cbor::ser::to_vec (&Gossip {node_records: vec! (), bans: vec! ()}).unwrap ();
+        assert_eq! (serde_cbor::de::from_slice::<Introduction> (&gossip[..]).is_err (), true);
+        let ping = serde_cbor::ser::to_vec (&Heartbeat::Ping {nonce: 1}).unwrap ();
+        assert_eq! (serde_cbor::de::from_slice::<Introduction> (&ping[..]).is_err (), true);
+    }
+
+    #[test]
+    fn a_sealed_introduction_is_opened_only_by_its_recipient () {
+        let sender = make_cryptde ();
+        let recipient = make_cryptde ();
+        let bystander = make_cryptde ();
+        let introduction = Introduction::Declined {nonce: 42};
+
+        let subject = SealedIntroduction::seal (&introduction, &recipient.public_key (), &sender).unwrap ();
+
+        assert_eq! (subject.sender_public_key, sender.public_key ());
+        assert_eq! (subject.open (&recipient), Ok (introduction));
+        assert_eq! (subject.open (&bystander).is_err (), true);
+    }
+
+    #[test]
+    fn a_sealed_introduction_passed_along_to_another_node_is_not_opened () {
+        let sender = make_cryptde ();
+        let recipient = make_cryptde ();
+        let victim = make_cryptde ();
+        let introduction = Introduction::Request {record: NodeRecord::new (&sender.public_key (), None, 1), nonce: 42};
+        let opened = SealedIntroduction::seal (&introduction, &recipient.public_key (), &sender).unwrap ()
+            .sealed_introduction;
+        let signed_data = recipient.decrypt (&recipient.private_key (), &sender.public_key ().data[..], &opened).unwrap ();
+
+        let subject = SealedIntroduction {
+            sender_public_key: sender.public_key (),
+            sealed_introduction: recipient.encrypt (&victim.public_key (), &sender.public_key ().data[..], &signed_data).unwrap (),
+        };
+
+        assert_eq! (subject.open (&victim), Err (String::from ("Introduction is not signed by its sender for this Node")));
+    }
+
+    #[test]
+    fn sealed_introductions_and_sealed_gossip_cannot_be_mistaken_for_each_other () {
+        let sender = make_cryptde ();
+        let recipient_key = make_cryptde ().public_key ();
+        let introduction = serde_cbor::ser::to_vec (&SealedIntroduction::seal (&Introduction::Declined {nonce: 1}, &recipient_key, &sender).unwrap ()).unwrap ();
+        let gossip = serde_cbor::ser::to_vec (&SealedGossip::seal (&Gossip {node_records: vec! (), bans: vec! ()}, &recipient_key, &sender).unwrap ()).unwrap ();
+
+        assert_eq! (serde_cbor::de::from_slice::<SealedGossip> (&introduction[..]).is_err (), true);
+        assert_eq! (serde_cbor::de::from_slice::<SealedIntroduction> (&gossip[..]).is_err (), true);
+    }
+
+    #[test]
+    fn an_introduction_nonce_is_accepted_once_per_sender () {
+        let mut subject = IntroductionReplayGuard::new (2);
+        let sender = Key::new (b"sender");
+        let other = Key::new (b"other");
+
+        assert_eq! (subject.first_sight (&sender, 1), true);
+        assert_eq! (subject.first_sight (&sender, 1), false);
+        assert_eq! (subject.first_sight (&other, 1), true);
+        assert_eq! (subject.first_sight (&sender, 2), true);
+        assert_eq! (subject.first_sight (&sender, 1), true);
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+use sub_lib::cryptde::Key;
+
+// A neighbor that leaves this many Pings in a row unanswered is treated as gone
+pub const DEAD_AFTER_MISSED_PINGS: u32 = 3;
+
+// Travels between neighboring Neighborhoods alongside Gossip
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Heartbeat {
+    Ping {nonce: u64},
+    Pong {nonce: u64},
+}
+
+pub struct LatencyTable {
+    next_nonce: u64,
+    pending: HashMap<u64, (Key, Instant)>,
+    latencies: HashMap<Key, Duration>,
+    missed_pings: HashMap<Key, u32>,
+}
+
+impl LatencyTable {
+    pub fn new () -> LatencyTable {
+        LatencyTable {
+            next_nonce: 0,
+            pending: HashMap::new (),
+            latencies: HashMap::new (),
+            missed_pings: HashMap::new (),
+        }
+    }
+
+    // Returns the nonce to put in the Ping
+    pub fn ping_sent (&mut self, public_key: &Key, now: Instant) -> u64 {
+        let nonce = self.next_nonce;
+        self.next_nonce = self.next_nonce.wrapping_add (1);
+        self.pending.insert (nonce, (public_key.clone (), now));
+        nonce
+    }
+
+    // Returns the neighbor that answered and how long it took, unless the Pong wasn't for us
+    pub fn pong_received (&mut self, nonce: u64, now: Instant) -> Option<(Key, Duration)> {
+        let (public_key, sent_at) = match self.pending.remove (&nonce) {
+            None => return None,
+            Some (pending) => pending
+        };
+        let round_trip = now.duration_since (sent_at);
+        self.latencies.insert (public_key.clone (), round_trip);
+        self.missed_pings.remove (&public_key);
+        Some ((public_key, round_trip))
+    }
+
+    // Gives up on Pings sent longer than timeout ago; returns the neighbors that just went dead
+    pub fn expire (&mut self, now: Instant, timeout: Duration) -> Vec<Key> {
+        let expired: Vec<u64> = self.pending.iter ()
+            .filter (|&(_, &(_, sent_at))| now.duration_since (sent_at) >= timeout)
+            .map (|(nonce, _)| *nonce)
+            .collect ();
+        let mut newly_dead = vec! ();
+        for nonce in expired {
+            let (public_key, _) = self.pending.remove (&nonce).expect ("Pending Ping disappeared");
+            let missed = self.missed_pings.entry (public_key.clone ()).or_insert (0);
+            *missed += 1;
+            if *missed == DEAD_AFTER_MISSED_PINGS {
+                self.latencies.remove (&public_key);
+                newly_dead.push (public_key);
+            }
+        }
+        newly_dead.sort_by (|a, b| a.data.cmp (&b.data));
+        newly_dead
+    }
+
+    pub fn latency_of (&self, public_key: &Key) -> Option<Duration> {
+        self.latencies.get (public_key).cloned ()
+    }
+
+    pub fn is_dead (&self, public_key: &Key) -> bool {
+        self.missed_pings.get (public_key).map (|missed| *missed >= DEAD_AFTER_MISSED_PINGS).unwrap_or (false)
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pongs_measure_round_trip_time () {
+        let mut subject = LatencyTable::new ();
+        let key = Key::new (b"neighbor");
+        let now = Instant::now ();
+        let nonce = subject.ping_sent (&key, now);
+
+        let result = subject.pong_received (nonce, now + Duration::from_millis (30));
+
+        assert_eq! (result, Some ((key.clone (), Duration::from_millis (30))));
+        assert_eq! (subject.latency_of (&key), Some (Duration::from_millis (30)));
+    }
+
+    #[test]
+    fn unsolicited_and_repeated_pongs_are_ignored () {
+        let mut subject = LatencyTable::new ();
+        let now = Instant::now ();
+        let nonce = subject.ping_sent (&Key::new (b"neighbor"), now);
+        subject.pong_received (nonce, now);
+
+        assert_eq! (subject.pong_received (nonce, now), None);
+        assert_eq! (subject.pong_received (nonce + 1, now), None);
+    }
+
+    #[test]
+    fn neighbors_that_miss_enough_pings_are_dead_until_they_answer () {
+        let mut subject = LatencyTable::new ();
+        let key = Key::new (b"neighbor");
+        let timeout = Duration::from_millis (1000);
+        let mut now = Instant::now ();
+        subject.ping_sent (&key, now);
+        subject.pong_received (0, now + Duration::from_millis (10));
+
+        for _ in 0..(DEAD_AFTER_MISSED_PINGS - 1) {
+            subject.ping_sent (&key, now);
+            now += timeout;
+            assert_eq! (subject.expire (now, timeout), vec! ());
+        }
+        assert_eq! (subject.is_dead (&key), false);
+        subject.ping_sent (&key, now);
+        now += timeout;
+        assert_eq! (subject.expire (now, timeout), vec! (key.clone ()));
+        assert_eq! (subject.is_dead (&key), true);
+        assert_eq! (subject.latency_of (&key), None);
+
+        let nonce = subject.ping_sent (&key, now);
+        subject.pong_received (nonce, now);
+
+        assert_eq! (subject.is_dead (&key), false);
+    }
+
+    #[test]
+    fn pings_younger_than_the_timeout_are_still_pending () {
+        let mut subject = LatencyTable::new ();
+        let now = Instant::now ();
+        let nonce = subject.ping_sent (&Key::new (b"neighbor"), now);
+
+        subject.expire (now + Duration::from_millis (999), Duration::from_millis (1000));
+
+        assert_eq! (subject.pong_received (nonce, now + Duration::from_millis (999)).is_some (), true);
+    }
+}
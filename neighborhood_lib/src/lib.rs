@@ -2,9 +2,20 @@
 extern crate actix;
 extern crate futures;
 extern crate regex;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_cbor;
 extern crate sub_lib;
 
 #[cfg(test)]
 extern crate test_utils;
 
+pub mod ban;
+pub mod geolocation;
+pub mod gossip;
+pub mod heartbeat;
 pub mod neighborhood;
+pub mod neighborhood_database;
+pub mod neighborhood_store;
+pub mod reputation;
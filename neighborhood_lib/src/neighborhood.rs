@@ -1,33 +1,178 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use actix::Actor;
 use actix::Addr;
+use actix::AsyncContext;
 use actix::Context;
 use actix::Handler;
+use actix::Recipient;
 use actix::Syn;
+use sub_lib::accountant::ReportConsumingWalletMessage;
+use sub_lib::accountant::ReportEarningWalletMessage;
+use sub_lib::accountant::ReportRatesMessage;
 use sub_lib::dispatcher::Component;
 use sub_lib::node_addr::NodeAddr;
 use sub_lib::route::Route;
+use sub_lib::route::RouteSegment;
 use sub_lib::cryptde::Key;
 use sub_lib::neighborhood::NeighborhoodSubs;
 use sub_lib::peer_actors::BindMessage;
 use sub_lib::cryptde::CryptDE;
+use sub_lib::hopper::IncipientCoresPackage;
 use sub_lib::neighborhood::NodeQueryMessage;
 use sub_lib::neighborhood::NodeDescriptor;
+use sub_lib::neighborhood::RouteQueryMessage;
+use sub_lib::neighborhood::RouteQueryResponse;
+use sub_lib::neighborhood::Misbehaver;
+use sub_lib::neighborhood::NeighborMisbehavior;
+use sub_lib::neighborhood::NeighborMisbehaviorMessage;
+use sub_lib::neighborhood::ExpiredNeighborhoodPackage;
+use sub_lib::neighborhood::NeighborhoodConfig;
+use sub_lib::neighborhood::BanNodeMsg;
+use sub_lib::neighborhood::UnbanNodeMsg;
+use sub_lib::neighborhood::NodeBannedMsg;
+use sub_lib::neighborhood::NodeUnbannedMsg;
+use sub_lib::neighborhood::NewPublicIpMsg;
+use sub_lib::neighborhood::NewPublicKeyMsg;
+use sub_lib::neighborhood::RetiredPublicKeyMsg;
+use sub_lib::neighborhood::SetExitLocationMsg;
+use sub_lib::neighborhood::GetTopologyMsg;
+use sub_lib::neighborhood::NeighborhoodTopology;
+use sub_lib::neighborhood::TopologyEdge;
+use sub_lib::neighborhood::TopologyNode;
+use sub_lib::neighborhood::ExitLocation;
+use sub_lib::neighborhood::RouteCost;
+use sub_lib::neighborhood::RouteDiversity;
+use sub_lib::logger::Logger;
+use sub_lib::utils::to_string;
 use actix::MessageResult;
+use serde::Serialize;
+use std::cmp;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use ban::Ban;
+use ban::BanList;
+use ban::BanRecord;
+use geolocation::GeolocationTable;
+use gossip::Gossip;
+use gossip::GossipRateLimiter;
+use gossip::Introduction;
+use gossip::IntroductionReplayGuard;
+use gossip::SealedGossip;
+use gossip::SealedIntroduction;
+use gossip::GOSSIP_RATE_WINDOW_MS;
+use gossip::MAX_GOSSIP_PER_WINDOW;
+use gossip::MAX_REMEMBERED_INTRODUCTIONS;
+use heartbeat::Heartbeat;
+use heartbeat::LatencyTable;
+use heartbeat::DEAD_AFTER_MISSED_PINGS;
+use neighborhood_database::NeighborhoodDatabase;
+use neighborhood_database::NodeRecord;
+use neighborhood_database::NodeRecordError;
+use neighborhood_store::NeighborhoodSnapshot;
+use neighborhood_store::NeighborhoodStore;
+use reputation::ReputationTable;
+use reputation::INITIAL_REPUTATION;
+
+// Until a configured neighbor answers, Gossip goes out to all of them again after this long, and
+// twice as long each time after that, up to the maximum
+const BOOTSTRAP_RETRY_MS: u64 = 10000;
+const MAX_BOOTSTRAP_RETRY_MS: u64 = 320000;
 
 pub struct Neighborhood {
-    _cryptde: &'static CryptDE,
+    cryptde: &'static CryptDE,
     neighboring_nodes: Vec<NodeDescriptor>,
+    min_hops: usize,
+    max_hops: usize,
+    clandestine_ports: Vec<u16>,
+    gossip_interval_ms: u64,
+    heartbeat_interval_ms: u64,
+    stale_node_window_ms: u64,
+    database: NeighborhoodDatabase,
+    store_opt: Option<NeighborhoodStore>,
+    to_hopper: Option<Recipient<Syn, IncipientCoresPackage>>,
+    to_hopper_bans: Option<Recipient<Syn, NodeBannedMsg>>,
+    to_dispatcher_bans: Option<Recipient<Syn, NodeBannedMsg>>,
+    to_hopper_unbans: Option<Recipient<Syn, NodeUnbannedMsg>>,
+    to_dispatcher_unbans: Option<Recipient<Syn, NodeUnbannedMsg>>,
+    to_accountant_wallets: Option<Recipient<Syn, ReportEarningWalletMessage>>,
+    to_accountant_consuming_wallets: Option<Recipient<Syn, ReportConsumingWalletMessage>>,
+    to_accountant_rates: Option<Recipient<Syn, ReportRatesMessage>>,
+    reputation: ReputationTable,
+    latencies: LatencyTable,
+    gossip_limiter: GossipRateLimiter,
+    bans: BanList,
+    geolocation: GeolocationTable,
+    exit_location: ExitLocation,
+    route_diversity: RouteDiversity,
+    max_route_cost_opt: Option<RouteCost>,
+    min_neighbors: usize,
+    target_neighbors: usize,
+    max_neighbors: usize,
+    // Nodes asked to become neighbors since the last time stale Nodes were pruned, and the nonces
+    // they were asked with
+    introductions_requested: HashMap<Key, u64>,
+    introduction_replay_guard: IntroductionReplayGuard,
+    bootstrapped: bool,
+    bootstrap_retry_ms: u64,
+    logger: Logger,
 }
 
 impl Actor for Neighborhood {
     type Context = Context<Self>;
+
+    // Whatever's changed since the last save is kept for the next run
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.save ();
+    }
 }
 
 impl Handler<BindMessage> for Neighborhood {
     type Result = ();
 
-    fn handle(&mut self, _msg: BindMessage, _ctx: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: BindMessage, ctx: &mut Self::Context) -> Self::Result {
+        self.to_hopper = Some (msg.peer_actors.hopper.from_hopper_client);
+        self.to_hopper_bans = Some (msg.peer_actors.hopper.node_banned);
+        self.to_dispatcher_bans = Some (msg.peer_actors.dispatcher.node_banned);
+        self.to_hopper_unbans = Some (msg.peer_actors.hopper.node_unbanned);
+        self.to_dispatcher_unbans = Some (msg.peer_actors.dispatcher.node_unbanned);
+        self.to_accountant_wallets = Some (msg.peer_actors.accountant.report_earning_wallet);
+        self.to_accountant_consuming_wallets = Some (msg.peer_actors.accountant.report_consuming_wallet);
+        self.to_accountant_rates = Some (msg.peer_actors.accountant.report_rates);
+        for ban in self.bans.bans () {
+            self.announce_ban (&ban.record.banned_key);
+        }
+        let restored_keys: Vec<Key> = self.database.records ().into_iter ().map (|record| record.public_key.clone ()).collect ();
+        for public_key in restored_keys {self.report_payment_terms (&public_key)}
+        self.save ();
+        // Every neighbor hears from us at once; whichever answers first bootstraps us
+        let neighbor_count = self.database.root ().neighbors.len ();
+        if neighbor_count > 0 {
+            self.logger.info (format! ("Bootstrapping from {} neighbors", neighbor_count));
+            Neighborhood::schedule_bootstrap_retry (self.bootstrap_retry_ms, ctx);
+        }
+        self.send_gossip ();
+        self.seek_neighbors ();
+        if self.gossip_interval_ms > 0 {
+            ctx.run_interval (Duration::from_millis (self.gossip_interval_ms), |neighborhood, _ctx| {
+                neighborhood.send_gossip ();
+                neighborhood.seek_neighbors ()
+            });
+        }
+        if self.heartbeat_interval_ms > 0 {
+            ctx.run_interval (Duration::from_millis (self.heartbeat_interval_ms), |neighborhood, _ctx| {
+                neighborhood.send_heartbeats ()
+            });
+        }
+        if self.stale_node_window_ms > 0 {
+            ctx.run_interval (Duration::from_millis (self.stale_node_window_ms / 2), |neighborhood, _ctx| {
+                neighborhood.prune_stale_nodes ()
+            });
+        }
         ()
     }
 }
@@ -40,39 +185,933 @@ impl Handler<NodeQueryMessage> for Neighborhood {
             .find(|node_ref_ref| {
                 self.matches(node_ref_ref, &msg)
             })
-            .map(|r| r.clone());
+            .map(|r| r.clone())
+            .or_else (|| match msg {
+                NodeQueryMessage::PublicKey (ref public_key) => self.database.descriptor_by_key (public_key),
+                NodeQueryMessage::IpAddress (ref ip_addr) => self.database.descriptor_by_ip (ip_addr),
+            });
 
         MessageResult(result_opt)
     }
 }
 
+impl Handler<RouteQueryMessage> for Neighborhood {
+    type Result = MessageResult<RouteQueryMessage>;
+
+    fn handle(&mut self, msg: RouteQueryMessage, _ctx: &mut Self::Context) -> <Self as Handler<RouteQueryMessage>>::Result {
+        MessageResult(self.route_round_trip (&msg.excluded_exit_keys))
+    }
+}
+
+impl Handler<ExpiredNeighborhoodPackage> for Neighborhood {
+    type Result = ();
+
+    fn handle(&mut self, msg: ExpiredNeighborhoodPackage, _ctx: &mut Self::Context) -> Self::Result {
+        self.heard_from (&msg.neighbor_addr.ip ());
+        // Heartbeats and Gossip are different enough shapes that neither can pass for the other
+        if let Ok (heartbeat) = msg.package.payload::<Heartbeat> () {
+            self.receive_heartbeat (heartbeat, msg.neighbor_addr);
+            return ()
+        }
+        if !self.gossip_limiter.allow (msg.neighbor_addr.ip (), Instant::now ()) {
+            self.logger.warning (format! ("Dropped Gossip from neighbor at {}: more than {} in {}ms", msg.neighbor_addr, MAX_GOSSIP_PER_WINDOW, GOSSIP_RATE_WINDOW_MS));
+            self.penalize (Misbehaver::Address (msg.neighbor_addr), NeighborMisbehavior::GossipFlood);
+            return ()
+        }
+        if let Ok (sealed_introduction) = msg.package.payload::<SealedIntroduction> () {
+            self.receive_sealed_introduction (sealed_introduction, msg.neighbor_addr);
+            return ()
+        }
+        let sealed_gossip = match msg.package.payload::<SealedGossip> () {
+            Ok (sealed_gossip) => sealed_gossip,
+            Err (e) => {
+                self.logger.error (format! ("Received unintelligible Gossip: {:?}", e));
+                return ()
+            }
+        };
+        if self.bans.is_banned (&sealed_gossip.sender_public_key) {
+            self.logger.debug (format! ("Ignored Gossip from banned Node {}", to_string (&sealed_gossip.sender_public_key.data)));
+            return ()
+        }
+        let gossip = match sealed_gossip.open (self.cryptde) {
+            Ok (gossip) => gossip,
+            Err (e) => {
+                self.logger.warning (format! ("Dropped Gossip from neighbor at {}: {}", msg.neighbor_addr, e));
+                self.penalize (Misbehaver::Address (msg.neighbor_addr), NeighborMisbehavior::ForgedGossip);
+                return ()
+            }
+        };
+        if gossip.is_oversized () {
+            self.logger.warning (format! ("Dropped Gossip from neighbor at {} with {} Node records and {} bans", msg.neighbor_addr, gossip.node_records.len (), gossip.bans.len ()));
+            self.penalize (Misbehaver::Address (msg.neighbor_addr), NeighborMisbehavior::GossipFlood);
+            return ()
+        }
+        self.receive_gossip (gossip, &sealed_gossip.sender_public_key, msg.neighbor_addr);
+        if !self.bootstrapped && self.is_neighbor_ip (&msg.neighbor_addr.ip ()) {
+            self.logger.info (format! ("Bootstrapped from neighbor at {}", msg.neighbor_addr));
+            self.bootstrapped = true;
+        }
+        ()
+    }
+}
+
+impl Handler<NeighborMisbehaviorMessage> for Neighborhood {
+    type Result = ();
+
+    fn handle(&mut self, msg: NeighborMisbehaviorMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.penalize (msg.misbehaver, msg.misbehavior);
+        ()
+    }
+}
+
+impl Handler<BanNodeMsg> for Neighborhood {
+    type Result = ();
+
+    fn handle(&mut self, msg: BanNodeMsg, _ctx: &mut Self::Context) -> Self::Result {
+        let record = BanRecord::new (&msg.public_key, &msg.reason, self.cryptde);
+        if self.impose_ban (Ban {record, shared: msg.share}) {
+            self.save ();
+            if msg.share {self.send_gossip ()}
+        }
+        ()
+    }
+}
+
+impl Handler<UnbanNodeMsg> for Neighborhood {
+    type Result = ();
+
+    // Other Nodes that adopted a shared ban keep it; they have their own reasons to lift it or not
+    fn handle(&mut self, msg: UnbanNodeMsg, _ctx: &mut Self::Context) -> Self::Result {
+        if self.lift_ban (&msg.public_key) {
+            self.save ();
+        }
+        ()
+    }
+}
+
+impl Handler<NewPublicIpMsg> for Neighborhood {
+    type Result = ();
+
+    // Neighbors hear about the move right away, rather than at the next regular Gossip
+    fn handle(&mut self, msg: NewPublicIpMsg, _ctx: &mut Self::Context) -> Self::Result {
+        let old_ip_addr_opt = self.database.root ().node_addr_opt.as_ref ().map (|node_addr| node_addr.ip_addr ());
+        if !self.database.relocate_root (&NodeAddr::new (&msg.ip_addr, &self.clandestine_ports)) {return ()}
+        match old_ip_addr_opt {
+            None => self.logger.info (format! ("Public IP address is {}", msg.ip_addr)),
+            Some (old_ip_addr) => self.logger.info (format! ("Public IP address changed from {} to {}", old_ip_addr, msg.ip_addr)),
+        }
+        self.save ();
+        self.send_gossip ();
+        ()
+    }
+}
+
+impl Handler<NewPublicKeyMsg> for Neighborhood {
+    type Result = ();
+
+    // Neighbors hear about the new key right away, so they can move us over to it while the old
+    // key still works
+    fn handle(&mut self, msg: NewPublicKeyMsg, _ctx: &mut Self::Context) -> Self::Result {
+        if !self.database.rekey_root (&msg.previous_public_key, &msg.endorsement) {return ()}
+        self.logger.info (format! ("Public key rotated from {} to {}", to_string (&msg.previous_public_key.data), to_string (&self.cryptde.public_key ().data)));
+        self.save ();
+        self.send_gossip ();
+        ()
+    }
+}
+
+impl Handler<RetiredPublicKeyMsg> for Neighborhood {
+    type Result = ();
+
+    fn handle(&mut self, msg: RetiredPublicKeyMsg, _ctx: &mut Self::Context) -> Self::Result {
+        if self.database.retire_root_predecessor () {
+            self.logger.info (format! ("Public key {} retired", to_string (&msg.public_key.data)));
+        }
+        ()
+    }
+}
+
+impl Handler<SetExitLocationMsg> for Neighborhood {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetExitLocationMsg, _ctx: &mut Self::Context) -> Self::Result {
+        if msg.exit_location == self.exit_location {return ()}
+        self.logger.info (format! ("Routes will exit {}", if msg.exit_location.is_anywhere () {String::from ("anywhere")} else {format! ("in {}", msg.exit_location)}));
+        self.exit_location = msg.exit_location;
+        ()
+    }
+}
+
+impl Handler<GetTopologyMsg> for Neighborhood {
+    type Result = MessageResult<GetTopologyMsg>;
+
+    fn handle(&mut self, _msg: GetTopologyMsg, _ctx: &mut Self::Context) -> <Self as Handler<GetTopologyMsg>>::Result {
+        MessageResult(self.topology (Instant::now ()))
+    }
+}
+
 impl Neighborhood {
-    pub fn new(cryptde: &'static CryptDE, config: Vec<(Key, NodeAddr)>) -> Self {
+    pub fn new(cryptde: &'static CryptDE, config: NeighborhoodConfig) -> Self {
+        let logger = Logger::new ("Neighborhood");
+        let local_node_addr_opt = config.local_ip_addr_opt.map (|ip_addr| NodeAddr::new (&ip_addr, &config.clandestine_ports));
+        let mut database = NeighborhoodDatabase::new (local_node_addr_opt.as_ref (), Neighborhood::initial_version (), cryptde);
+        let store_opt = config.data_directory_opt.as_ref ().map (|data_directory| NeighborhoodStore::new (data_directory));
+        let mut bans = BanList::new ();
+        if let Some (ref store) = store_opt {
+            Neighborhood::restore (&mut database, &mut bans, store, &logger);
+        }
+        config.neighbor_configs.iter ().for_each (|&(ref key, ref node_addr)| database.add_neighbor (key, node_addr));
+        config.bans.iter ().for_each (|ban| {
+            bans.ban (Ban {record: BanRecord::new (&ban.public_key, &ban.reason, cryptde), shared: ban.share});
+        });
+        bans.bans ().into_iter ().for_each (|ban| database.remove_neighbor (&ban.record.banned_key));
+        database.set_originate_only (config.originate_only);
+        database.set_earning_wallet (config.earning_wallet_opt.clone ());
+        database.set_consuming_wallet (config.consuming_wallet_opt.clone ());
+        database.set_rates (config.rates);
+        let geolocation = match config.geolocation_database_opt {
+            None => GeolocationTable::new (),
+            Some (ref path) => match GeolocationTable::load (path) {
+                Ok (geolocation) => geolocation,
+                Err (e) => {
+                    logger.error (format! ("Node locations will be unknown: {}", e));
+                    GeolocationTable::new ()
+                }
+            }
+        };
         Neighborhood {
-            _cryptde: cryptde,
-            neighboring_nodes: config.into_iter().map(|(key, node_addr)| {
+            cryptde,
+            neighboring_nodes: config.neighbor_configs.into_iter().map(|(key, node_addr)| {
                 NodeDescriptor::new (key, Some (node_addr))
-            }).collect ()
+            }).collect (),
+            min_hops: config.min_hops,
+            max_hops: config.max_hops,
+            clandestine_ports: config.clandestine_ports,
+            gossip_interval_ms: config.gossip_interval_ms,
+            heartbeat_interval_ms: config.heartbeat_interval_ms,
+            stale_node_window_ms: config.stale_node_window_ms,
+            database,
+            store_opt,
+            to_hopper: None,
+            to_hopper_bans: None,
+            to_dispatcher_bans: None,
+            to_hopper_unbans: None,
+            to_dispatcher_unbans: None,
+            to_accountant_wallets: None,
+            to_accountant_consuming_wallets: None,
+            to_accountant_rates: None,
+            reputation: ReputationTable::new (),
+            latencies: LatencyTable::new (),
+            gossip_limiter: GossipRateLimiter::new (MAX_GOSSIP_PER_WINDOW, Duration::from_millis (GOSSIP_RATE_WINDOW_MS)),
+            bans,
+            geolocation,
+            exit_location: config.exit_location,
+            route_diversity: config.route_diversity,
+            max_route_cost_opt: config.max_route_cost_opt,
+            min_neighbors: config.min_neighbors,
+            target_neighbors: config.target_neighbors,
+            max_neighbors: config.max_neighbors,
+            introductions_requested: HashMap::new (),
+            introduction_replay_guard: IntroductionReplayGuard::new (MAX_REMEMBERED_INTRODUCTIONS),
+            bootstrapped: false,
+            bootstrap_retry_ms: BOOTSTRAP_RETRY_MS,
+            logger,
         }
     }
 
     pub fn make_subs_from(addr: &Addr<Syn, Neighborhood>) -> NeighborhoodSubs {
         NeighborhoodSubs {
             bind: addr.clone ().recipient::<BindMessage>(),
+            route_query: addr.clone ().recipient::<RouteQueryMessage>(),
+            report_misbehavior: addr.clone ().recipient::<NeighborMisbehaviorMessage>(),
+            from_hopper: addr.clone ().recipient::<ExpiredNeighborhoodPackage>(),
+            ban_node: addr.clone ().recipient::<BanNodeMsg>(),
+            unban_node: addr.clone ().recipient::<UnbanNodeMsg>(),
+            new_public_ip: addr.clone ().recipient::<NewPublicIpMsg>(),
+            new_public_key: addr.clone ().recipient::<NewPublicKeyMsg>(),
+            retired_public_key: addr.clone ().recipient::<RetiredPublicKeyMsg>(),
+            set_exit_location: addr.clone ().recipient::<SetExitLocationMsg>(),
+            get_topology: addr.clone ().recipient::<GetTopologyMsg>(),
+        }
+    }
+
+    // Starting from the clock means a restarted Node's record supersedes the one it gossiped
+    // before it went down
+    fn initial_version () -> u64 {
+        match SystemTime::now ().duration_since (UNIX_EPOCH) {
+            Ok (d) => d.as_secs (),
+            Err (_) => 1
+        }
+    }
+
+    // A neighbor that passes along a record its author didn't sign is either lying or careless.
+    // Bans are taken only firsthand from neighbors in good standing, and go no further from here.
+    // The sender signed the Gossip, so it answers for what's in it
+    fn receive_gossip (&mut self, gossip: Gossip, sender_key: &Key, neighbor_addr: SocketAddr) {
+        let record_count = gossip.node_records.len () + gossip.bans.len ();
+        let mut changed_count = 0;
+        let mut forged_count = 0;
+        for record in gossip.node_records {
+            // A new key doesn't get a banned Node out from under its ban
+            let predecessor_banned = match record.predecessor_opt {
+                Some (ref predecessor) => self.bans.is_banned (&predecessor.public_key),
+                None => false
+            };
+            if self.bans.is_banned (&record.public_key) || predecessor_banned {continue}
+            let public_key = record.public_key.clone ();
+            match self.database.merge (record) {
+                Ok (true) => {
+                    changed_count += 1;
+                    self.report_payment_terms (&public_key)
+                },
+                Ok (false) => (),
+                Err (NodeRecordError::InvalidSignature) => forged_count += 1,
+            }
+        }
+        for record in gossip.bans {
+            if self.bans.is_banned (&record.banned_key) {continue}
+            if !record.has_valid_signature (self.cryptde) {
+                forged_count += 1;
+                continue
+            }
+            if !self.is_trusted_banner (&record.banner_key, neighbor_addr) {
+                self.logger.debug (format! ("Ignored ban of {} by Node {}, which isn't a neighbor in good standing speaking for itself",
+                    to_string (&record.banned_key.data), to_string (&record.banner_key.data)));
+                continue
+            }
+            if self.impose_ban (Ban {record, shared: false}) {changed_count += 1}
+        }
+        self.logger.debug (format! ("Received Gossip with {} records; {} were news", record_count, changed_count));
+        if forged_count > 0 {
+            self.logger.warning (format! ("Discarded {} forged records in Gossip from neighbor at {}", forged_count, neighbor_addr));
+            self.penalize (Misbehaver::Node (sender_key.clone ()), NeighborMisbehavior::ForgedGossip);
+        }
+        else if record_count > 0 {
+            self.reputation.commend (sender_key);
+        }
+        if changed_count > 0 {
+            self.save ();
+            self.send_gossip ();
+            self.seek_neighbors ()
+        }
+    }
+
+    // Below the target, the most reputable Nodes we know of but aren't neighbors with yet are
+    // asked to become neighbors, each of them only once between prunings
+    fn seek_neighbors (&mut self) {
+        let neighbor_count = self.database.root ().neighbors.len ();
+        if neighbor_count < self.min_neighbors {
+            self.logger.warning (format! ("Has {} neighbors, fewer than the minimum of {}", neighbor_count, self.min_neighbors));
+        }
+        if neighbor_count >= self.target_neighbors {return}
+        let candidates: Vec<Key> = {
+            let neighbors = &self.database.root ().neighbors;
+            let strangers: Vec<&Key> = self.database.reachable_keys ().into_iter ()
+                .filter (|key| !neighbors.contains (*key) && !self.introductions_requested.contains_key (*key))
+                .collect ();
+            self.rank_by_reputation (strangers).into_iter ()
+                .take (self.target_neighbors - neighbor_count)
+                .cloned ()
+                .collect ()
+        };
+        if candidates.is_empty () {
+            self.logger.debug (format! ("Want {} more neighbors, but know of nobody new to ask", self.target_neighbors - neighbor_count));
+            return
+        }
+        for candidate in candidates {
+            self.logger.debug (format! ("Asking Node {} to become a neighbor", to_string (&candidate.data)));
+            let nonce = make_nonce (self.cryptde);
+            self.send_introduction (&candidate, &Introduction::Request {record: self.database.root ().clone (), nonce});
+            self.introductions_requested.insert (candidate, nonce);
+        }
+    }
+
+    fn send_introduction (&self, recipient_key: &Key, introduction: &Introduction) {
+        match SealedIntroduction::seal (introduction, recipient_key, self.cryptde) {
+            Ok (sealed_introduction) => self.send_to_neighbor (recipient_key, sealed_introduction),
+            Err (e) => self.logger.error (format! ("Couldn't send introduction to Node {}: {}", to_string (&recipient_key.data), e)),
+        }
+    }
+
+    // Only the Node a record describes can ask to be introduced with it or accept with it, and only
+    // once per nonce
+    fn receive_sealed_introduction (&mut self, sealed_introduction: SealedIntroduction, neighbor_addr: SocketAddr) {
+        let sender_key = sealed_introduction.sender_public_key.clone ();
+        if self.bans.is_banned (&sender_key) {
+            self.logger.debug (format! ("Ignored introduction from banned Node {}", to_string (&sender_key.data)));
+            return
+        }
+        let introduction = match sealed_introduction.open (self.cryptde) {
+            Ok (introduction) => introduction,
+            Err (e) => {
+                self.logger.warning (format! ("Dropped introduction from neighbor at {}: {}", neighbor_addr, e));
+                self.penalize (Misbehaver::Address (neighbor_addr), NeighborMisbehavior::ForgedGossip);
+                return
+            }
+        };
+        let record_key_opt = match introduction {
+            Introduction::Request {ref record, ..} => Some (record.public_key.clone ()),
+            Introduction::Accepted {ref record, ..} => Some (record.public_key.clone ()),
+            Introduction::Declined {..} => None
+        };
+        if record_key_opt.map (|record_key| record_key != sender_key).unwrap_or (false) {
+            self.logger.warning (format! ("Dropped introduction from neighbor at {}: it carries some other Node's record", neighbor_addr));
+            self.penalize (Misbehaver::Node (sender_key), NeighborMisbehavior::ForgedGossip);
+            return
+        }
+        self.receive_introduction (introduction, neighbor_addr);
+    }
+
+    fn receive_introduction (&mut self, introduction: Introduction, neighbor_addr: SocketAddr) {
+        match introduction {
+            Introduction::Request {record, nonce} => {
+                let requester_key = record.public_key.clone ();
+                if !self.introduction_replay_guard.first_sight (&requester_key, nonce) {
+                    self.logger.warning (format! ("Dropped replayed introduction from neighbor at {}", neighbor_addr));
+                    return
+                }
+                if !self.admit_introduced_record (record, neighbor_addr) {return}
+                if self.database.root ().neighbors.contains (&requester_key) {
+                    let accepted = Introduction::Accepted {record: self.database.root ().clone (), nonce};
+                    return self.send_introduction (&requester_key, &accepted)
+                }
+                let neighbor_count = self.database.root ().neighbors.len ();
+                if neighbor_count >= self.max_neighbors {
+                    self.logger.info (format! ("Declined introduction from neighbor at {}: already have {} neighbors", neighbor_addr, neighbor_count));
+                    return self.send_introduction (&requester_key, &Introduction::Declined {nonce})
+                }
+                self.adopt_neighbor (&requester_key, neighbor_addr);
+                let accepted = Introduction::Accepted {record: self.database.root ().clone (), nonce};
+                self.send_introduction (&requester_key, &accepted);
+                self.send_gossip ()
+            },
+            Introduction::Accepted {record, nonce} => {
+                let accepter_key = record.public_key.clone ();
+                if self.introductions_requested.get (&accepter_key) != Some (&nonce) {
+                    self.logger.debug (format! ("Ignored unsolicited acceptance from neighbor at {}", neighbor_addr));
+                    return
+                }
+                if !self.admit_introduced_record (record, neighbor_addr) {return}
+                if self.database.root ().neighbors.len () >= self.max_neighbors {return}
+                self.adopt_neighbor (&accepter_key, neighbor_addr);
+                self.send_gossip ()
+            },
+            Introduction::Declined {..} => self.logger.debug (format! ("Neighbor at {} declined an introduction", neighbor_addr)),
+        }
+    }
+
+    // Returns false if the Node on the other end of an introduction can't be a neighbor
+    fn admit_introduced_record (&mut self, record: NodeRecord, neighbor_addr: SocketAddr) -> bool {
+        if self.bans.is_banned (&record.public_key) || record.node_addr_opt.is_none () {return false}
+        let public_key = record.public_key.clone ();
+        match self.database.merge (record) {
+            Ok (true) => self.report_payment_terms (&public_key),
+            Ok (false) => (),
+            Err (NodeRecordError::InvalidSignature) => {
+                self.logger.warning (format! ("Discarded forged record in introduction from neighbor at {}", neighbor_addr));
+                self.penalize (Misbehaver::Node (public_key), NeighborMisbehavior::ForgedGossip);
+                return false
+            }
+        }
+        !self.is_quarantined (&public_key)
+    }
+
+    // The Accountant pays other Nodes wherever their latest signed records say to, credits them with
+    // payments from the wallets those records say they pay from, and holds their invoices to the
+    // rates those records advertise
+    fn report_payment_terms (&self, public_key: &Key) {
+        if public_key == &self.cryptde.public_key () {return}
+        if let Some (earning_wallet) = self.database.earning_wallet_of (public_key) {
+            self.to_accountant_wallets.as_ref ().expect ("Accountant unbound in Neighborhood").try_send (ReportEarningWalletMessage {
+                public_key: public_key.clone (),
+                earning_wallet: earning_wallet.clone (),
+            }).expect ("Accountant is dead")
+        }
+        if let Some (consuming_wallet) = self.database.consuming_wallet_of (public_key) {
+            self.to_accountant_consuming_wallets.as_ref ().expect ("Accountant unbound in Neighborhood").try_send (ReportConsumingWalletMessage {
+                public_key: public_key.clone (),
+                consuming_wallet: consuming_wallet.clone (),
+            }).expect ("Accountant is dead")
+        }
+        if let Some (rates) = self.database.node_by_key (public_key).and_then (|record| record.rates_opt) {
+            self.to_accountant_rates.as_ref ().expect ("Accountant unbound in Neighborhood").try_send (ReportRatesMessage {
+                public_key: public_key.clone (),
+                rates,
+            }).expect ("Accountant is dead")
+        }
+    }
+
+    fn adopt_neighbor (&mut self, public_key: &Key, neighbor_addr: SocketAddr) {
+        let node_addr = match self.database.node_addr_of (public_key) {
+            None => return,
+            Some (node_addr) => node_addr
+        };
+        self.database.add_neighbor (public_key, &node_addr);
+        self.logger.info (format! ("Node {} at {} is now a neighbor", to_string (&public_key.data), neighbor_addr));
+        self.save ();
+    }
+
+    // Returns false if the Node was already banned or can't be
+    fn impose_ban (&mut self, ban: Ban) -> bool {
+        let banned_key = ban.record.banned_key.clone ();
+        if banned_key == self.cryptde.public_key () {
+            self.logger.warning (format! ("Ignored ban of the local Node: {}", ban.record.reason));
+            return false
+        }
+        let reason = ban.record.reason.clone ();
+        if !self.bans.ban (ban) {return false}
+        self.logger.warning (format! ("Banned Node {}: {}", to_string (&banned_key.data), reason));
+        self.database.remove_neighbor (&banned_key);
+        self.announce_ban (&banned_key);
+        true
+    }
+
+    fn announce_ban (&self, banned_key: &Key) {
+        let msg = NodeBannedMsg {
+            public_key: banned_key.clone (),
+            ip_addr_opt: self.database.node_addr_of (banned_key).map (|node_addr| node_addr.ip_addr ()),
+        };
+        self.to_hopper_bans.as_ref ().expect ("Hopper unbound in Neighborhood").try_send (msg.clone ()).expect ("Hopper is dead");
+        self.to_dispatcher_bans.as_ref ().expect ("Dispatcher unbound in Neighborhood").try_send (msg).expect ("Dispatcher is dead");
+    }
+
+    fn lift_ban (&mut self, banned_key: &Key) -> bool {
+        let ban = match self.bans.unban (banned_key, &self.cryptde.public_key ()) {
+            Some (ban) => ban,
+            None => return false
+        };
+        self.logger.info (format! ("Lifted ban of Node {}: {}", to_string (&banned_key.data), ban.record.reason));
+        let msg = NodeUnbannedMsg {
+            public_key: banned_key.clone (),
+            ip_addr_opt: self.database.node_addr_of (banned_key).map (|node_addr| node_addr.ip_addr ()),
+        };
+        self.to_hopper_unbans.as_ref ().expect ("Hopper unbound in Neighborhood").try_send (msg.clone ()).expect ("Hopper is dead");
+        self.to_dispatcher_unbans.as_ref ().expect ("Dispatcher unbound in Neighborhood").try_send (msg).expect ("Dispatcher is dead");
+        true
+    }
+
+    fn is_neighbor_ip (&self, ip_addr: &IpAddr) -> bool {
+        self.database.root ().neighbors.iter ()
+            .any (|key| self.database.node_addr_of (key).map (|node_addr| &node_addr.ip_addr () == ip_addr).unwrap_or (false))
+    }
+
+    // Anybody who has gossiped is known here, so knowing a Node isn't enough to take its word that
+    // another Node should be banned: it has to be a neighbor that hasn't misbehaved, and the ban has
+    // to come from its own address rather than be passed along by somebody else
+    fn is_trusted_banner (&self, banner_key: &Key, neighbor_addr: SocketAddr) -> bool {
+        let banner_ip_opt = self.database.node_addr_of (banner_key).map (|node_addr| node_addr.ip_addr ());
+        self.database.root ().neighbors.contains (banner_key)
+            && (banner_ip_opt == Some (neighbor_addr.ip ()))
+            && !self.bans.is_banned (banner_key)
+            && (self.reputation_of (banner_key) >= INITIAL_REPUTATION)
+    }
+
+    // Misbehavior seen only at an address is held against a Node only if it's the one Node known
+    // there; behind NAT, the rest would answer for it
+    fn penalize (&mut self, misbehaver: Misbehaver, misbehavior: NeighborMisbehavior) {
+        let public_key = match misbehaver {
+            Misbehaver::Node (public_key) => public_key,
+            Misbehaver::Address (socket_addr) => {
+                let keys: Vec<Key> = self.database.keys_by_ip (&socket_addr.ip ()).into_iter ().cloned ().collect ();
+                if keys.len () != 1 {
+                    self.logger.warning (format! ("Neighbor at {} reported for {:?}, but {} Nodes are known there; holding none of them to account",
+                        socket_addr.ip (), misbehavior, keys.len ()));
+                    return
+                }
+                keys[0].clone ()
+            }
+        };
+        let node = to_string (&public_key.data);
+        let was_quarantined = self.reputation.is_quarantined (&public_key);
+        let score = self.reputation.penalize (&public_key, misbehavior);
+        self.logger.warning (format! ("Node {} reported for {:?}; reputation now {}", node, misbehavior, score));
+        if !was_quarantined && self.reputation.is_quarantined (&public_key) {
+            self.logger.warning (format! ("Quarantined Node {}: it will be left out of routes", node));
+        }
+    }
+
+    fn reputation_of (&self, public_key: &Key) -> i64 {
+        self.reputation.score (public_key)
+    }
+
+    fn is_quarantined (&self, public_key: &Key) -> bool {
+        self.reputation.is_quarantined (public_key)
+    }
+
+    // Best reputation first, then the quickest link, leaving out banned, quarantined and
+    // unresponsive Nodes, and Nodes (the local one included) that only originate traffic; ties
+    // keep their original order
+    fn rank_by_reputation<'a> (&self, keys: Vec<&'a Key>) -> Vec<&'a Key> {
+        let mut ranked: Vec<&Key> = keys.into_iter ()
+            .filter (|key| !self.bans.is_banned (key) && !self.is_quarantined (key) && !self.latencies.is_dead (key)
+                && !self.database.is_originate_only (key))
+            .collect ();
+        ranked.sort_by (|a, b| self.reputation_of (b).cmp (&self.reputation_of (a))
+            .then_with (|| self.latency_rank (a).cmp (&self.latency_rank (b))));
+        ranked
+    }
+
+    fn country_of (&self, public_key: &Key) -> Option<&str> {
+        match self.database.node_addr_of (public_key) {
+            None => None,
+            Some (node_addr) => self.geolocation.country_of (&node_addr.ip_addr ())
+        }
+    }
+
+    fn is_acceptable_exit (&self, public_key: &Key) -> bool {
+        self.exit_location.allows (self.country_of (public_key))
+    }
+
+    fn is_affordable_exit (&self, public_key: &Key) -> bool {
+        self.is_affordable (&self.route_cost (&[], public_key, &[]))
+    }
+
+    fn is_affordable (&self, cost: &RouteCost) -> bool {
+        match self.max_route_cost_opt {
+            None => true,
+            Some (ref max_route_cost) => !cost.exceeds (max_route_cost)
+        }
+    }
+
+    // What the relays and exit charge by the rates they advertise; the local Node doesn't charge itself
+    fn route_cost (&self, over_relay_keys: &[&Key], exit_key: &Key, back_relay_keys: &[&Key]) -> RouteCost {
+        let mut cost = RouteCost::default ();
+        for relay_key in over_relay_keys.iter ().chain (back_relay_keys.iter ()) {
+            let rates = self.database.rates_of (relay_key);
+            cost.per_package += rates.routing_service_rate;
+            cost.per_byte += rates.routing_byte_rate;
+        }
+        if exit_key != &self.cryptde.public_key () {
+            let rates = self.database.rates_of (exit_key);
+            cost.per_package += rates.exit_service_rate;
+            cost.per_byte += rates.exit_byte_rate;
+        }
+        cost
+    }
+
+    // The local Node is as close as it gets; Nodes we haven't timed come after those we have
+    fn latency_rank (&self, public_key: &Key) -> Duration {
+        if public_key == &self.cryptde.public_key () {return Duration::from_millis (0)}
+        self.latencies.latency_of (public_key).unwrap_or (Duration::new (u64::max_value (), 0))
+    }
+
+    // Our own record comes back only as the list of neighbors we had, since everything else
+    // about it is issued fresh
+    fn restore (database: &mut NeighborhoodDatabase, bans: &mut BanList, store: &NeighborhoodStore, logger: &Logger) {
+        let snapshot = match store.load () {
+            Ok (Some (snapshot)) => snapshot,
+            Ok (None) => return,
+            Err (e) => {
+                logger.error (format! ("Starting with an empty neighborhood database: {}", e));
+                return
+            }
+        };
+        let root_key = database.root ().public_key.clone ();
+        let mut former_neighbors = vec! ();
+        let record_count = snapshot.node_records.len ();
+        for record in snapshot.node_records {
+            if record.public_key == root_key {
+                former_neighbors = record.neighbors
+            }
+            else if let Err (e) = database.merge (record) {
+                logger.warning (format! ("Ignoring saved Node record: {:?}", e));
+            }
+        }
+        for key in former_neighbors {
+            let node_addr_opt = database.node_addr_of (&key);
+            if let Some (node_addr) = node_addr_opt {
+                database.add_neighbor (&key, &node_addr)
+            }
+        }
+        let ban_count = snapshot.bans.len ();
+        snapshot.bans.into_iter ().for_each (|ban| {bans.ban (ban);});
+        logger.info (format! ("Restored {} Node records and {} bans from the neighborhood database", record_count, ban_count));
+    }
+
+    fn save (&self) {
+        if let Some (ref store) = self.store_opt {
+            let snapshot = NeighborhoodSnapshot {
+                node_records: self.database.records ().into_iter ().cloned ().collect (),
+                bans: self.bans.bans ().into_iter ().cloned ().collect (),
+            };
+            if let Err (e) = store.save (&snapshot) {
+                self.logger.error (e)
+            }
+        }
+    }
+
+    // Every direct neighbor hears everything we know; anything that's news to them, they pass on
+    fn schedule_bootstrap_retry (delay_ms: u64, ctx: &mut Context<Neighborhood>) {
+        ctx.run_later (Duration::from_millis (delay_ms), move |neighborhood, ctx| {
+            neighborhood.retry_bootstrap (delay_ms, ctx)
+        });
+    }
+
+    fn retry_bootstrap (&mut self, delay_ms: u64, ctx: &mut Context<Neighborhood>) {
+        if self.bootstrapped {return}
+        let next_delay_ms = cmp::min (delay_ms * 2, MAX_BOOTSTRAP_RETRY_MS);
+        self.logger.warning (format! ("No configured neighbor has answered in {}ms; Gossiping to them again, and again in {}ms if none answers",
+            delay_ms, next_delay_ms));
+        self.send_gossip ();
+        Neighborhood::schedule_bootstrap_retry (next_delay_ms, ctx);
+    }
+
+    fn send_gossip (&self) {
+        let gossip = Gossip {
+            node_records: self.database.gossip_records ().into_iter ().cloned ().collect (),
+            bans: self.bans.shared_records ().into_iter ().cloned ().collect (),
+        };
+        for neighbor_key in self.database.root ().neighbors.iter () {
+            match SealedGossip::seal (&gossip, neighbor_key, self.cryptde) {
+                Ok (sealed_gossip) => self.send_to_neighbor (neighbor_key, sealed_gossip),
+                Err (e) => self.logger.error (format! ("Couldn't send Gossip to neighbor {}: {}", to_string (&neighbor_key.data), e)),
+            }
+        }
+    }
+
+    // A Ping nobody answered by the time the next one goes out counts as missed
+    fn send_heartbeats (&mut self) {
+        let now = Instant::now ();
+        for public_key in self.latencies.expire (now, Duration::from_millis (self.heartbeat_interval_ms)) {
+            self.logger.warning (format! ("Neighbor {} missed {} Pings in a row: it will be left out of routes until it answers", to_string (&public_key.data), DEAD_AFTER_MISSED_PINGS));
+        }
+        let neighbor_keys = self.database.root ().neighbors.clone ();
+        for neighbor_key in neighbor_keys {
+            let nonce = self.latencies.ping_sent (&neighbor_key, now);
+            self.send_to_neighbor (&neighbor_key, Heartbeat::Ping {nonce});
+        }
+    }
+
+    fn receive_heartbeat (&mut self, heartbeat: Heartbeat, neighbor_addr: SocketAddr) {
+        match heartbeat {
+            Heartbeat::Ping {nonce} => match self.database.descriptor_by_ip (&neighbor_addr.ip ()) {
+                None => self.logger.debug (format! ("Ignored Ping from unknown Node at {}", neighbor_addr)),
+                Some (descriptor) => self.send_to_neighbor (&descriptor.public_key, Heartbeat::Pong {nonce}),
+            },
+            Heartbeat::Pong {nonce} => match self.latencies.pong_received (nonce, Instant::now ()) {
+                None => self.logger.debug (format! ("Ignored unsolicited Pong from {}", neighbor_addr)),
+                Some ((_, round_trip)) => self.logger.debug (format! ("Round trip to neighbor at {} took {}ms",
+                    neighbor_addr, round_trip.as_secs () * 1000 + (round_trip.subsec_nanos () / 1_000_000) as u64)),
+            },
+        }
+    }
+
+    fn heard_from (&mut self, ip_addr: &IpAddr) {
+        if let Some (descriptor) = self.database.descriptor_by_ip (ip_addr) {
+            self.database.touch (&descriptor.public_key, Instant::now ())
+        }
+    }
+
+    // Runs twice per window, so nobody lingers much past it. Reissuing our own record each time
+    // keeps everyone else from forgetting us in turn.
+    fn prune_stale_nodes (&mut self) {
+        let pruned = self.database.prune (Instant::now (), Duration::from_millis (self.stale_node_window_ms));
+        if !pruned.is_empty () {
+            self.logger.info (format! ("Forgot {} Nodes not heard from in {}ms", pruned.len (), self.stale_node_window_ms));
         }
+        self.database.refresh_root ();
+        self.save ();
+        self.send_gossip ();
+        self.introductions_requested.clear ();
+        self.seek_neighbors ();
+    }
+
+    fn send_to_neighbor<T> (&self, neighbor_key: &Key, payload: T) where T: Serialize {
+        let local_key = self.cryptde.public_key ();
+        let route = match Route::new (vec! (
+            RouteSegment::new (vec! (&local_key, neighbor_key), Component::Neighborhood)
+        ), self.cryptde) {
+            Err (e) => {
+                self.logger.error (format! ("Couldn't route to neighbor {:?}: {:?}", neighbor_key, e));
+                return
+            },
+            Ok (route) => route
+        };
+        let package = IncipientCoresPackage::new (route, payload, neighbor_key);
+        self.to_hopper.as_ref ().expect ("Hopper unbound in Neighborhood").try_send (package).expect ("Hopper is dead");
     }
 
-    // TODO: Turn these into actor messages
+    // TODO: Turn this into an actor message
     // crashpoint - unused so far
     #[allow (dead_code)]
     fn route_one_way(&self, _remote_recipient: Component) -> Result<(Route, Key), ()> {
         unimplemented!()
     }
 
-    // crashpoint - unused so far
-    #[allow (dead_code)]
-    fn route_round_trip(&self, _remote_recipient: Component, _local_recipient: Component) -> Result<(Route, Key), ()> {
-        unimplemented!()
+    // The local Node is preferred as the exit unless relays are required; neighbors are used
+    // when it has been excluded, the most reputable first. Responses return through different
+    // relays than requests took whenever there are enough neighbors to keep the two paths apart.
+    // Exits outside the configured exit location are never used, and neither are routes that cost
+    // more than the most this Node will pay.
+    fn route_round_trip(&self, excluded_exit_keys: &Vec<Key>) -> Option<RouteQueryResponse> {
+        let local_key = self.cryptde.public_key ();
+        let exit_key = {
+            let mut candidates = vec! ();
+            if self.min_hops == 0 {candidates.push (&local_key)}
+            candidates.extend (self.neighboring_nodes.iter ().map (|node| &node.public_key));
+            candidates.extend (self.database.reachable_keys ());
+            let available: Vec<&Key> = self.rank_by_reputation (candidates).into_iter ()
+                .filter (|key| !excluded_exit_keys.contains (*key))
+                .collect ();
+            match available.iter ().find (|key| self.is_acceptable_exit (key) && self.is_affordable_exit (key)) {
+                Some (key) => (*key).clone (),
+                None if available.is_empty () => return None,
+                None if available.iter ().any (|key| self.is_acceptable_exit (key)) => {
+                    self.logger.warning (format! ("Every exit Node in an acceptable location charges more than this Node will pay for a route ({})",
+                        self.max_route_cost_opt.unwrap_or_default ()));
+                    return None
+                },
+                None => {
+                    self.logger.warning (format! ("None of the {} available exit Nodes is in an acceptable location ({})", available.len (), self.exit_location));
+                    return None
+                }
+            }
+        };
+        let (over_relay_keys, back_relay_keys) = if exit_key == local_key {
+            (vec! (), vec! ())
+        }
+        else {
+            match self.choose_relays (&exit_key) {
+                None => {
+                    self.logger.warning (format! ("Can't build a route with at least {} hops from {} neighbors", self.min_hops, self.neighboring_nodes.len ()));
+                    return None
+                },
+                Some (relays) => relays
+            }
+        };
+        let cost = self.route_cost (&over_relay_keys, &exit_key, &back_relay_keys);
+        if !self.is_affordable (&cost) {
+            self.logger.warning (format! ("The best route available costs {}, more than this Node will pay ({})",
+                cost, self.max_route_cost_opt.unwrap_or_default ()));
+            return None
+        }
+        self.logger.debug (format! ("Built a route through {} relays expected to cost {}", over_relay_keys.len () + back_relay_keys.len (), cost));
+        let mut over_keys = vec! (&local_key);
+        over_keys.extend (over_relay_keys);
+        over_keys.push (&exit_key);
+        let mut back_keys = vec! (&exit_key);
+        back_keys.extend (back_relay_keys);
+        back_keys.push (&local_key);
+        let route = match Route::new (vec! (
+            RouteSegment::new (over_keys, Component::ProxyClient),
+            RouteSegment::new (back_keys, Component::ProxyServer)
+        ), self.cryptde) {
+            Err (_) => return None,
+            Ok (route) => route
+        };
+        Some (RouteQueryResponse {route, exit_key})
+    }
+
+    // Returns relays in travel order: over from the local Node to the exit, back from the exit.
+    // Relays related to other Nodes on the same path are avoided as route_diversity demands.
+    fn choose_relays (&self, exit_key: &Key) -> Option<(Vec<&Key>, Vec<&Key>)> {
+        let local_key = self.cryptde.public_key ();
+        let mut candidates: Vec<&Key> = vec! ();
+        let known_keys = self.neighboring_nodes.iter ().map (|node| &node.public_key)
+            .chain (self.database.reachable_keys ().into_iter ());
+        for key in known_keys {
+            if (key != exit_key) && !candidates.contains (&key) {
+                candidates.push (key)
+            }
+        }
+        let candidates = self.rank_by_reputation (candidates);
+        if candidates.len () < self.min_hops {return None}
+        let over_count = cmp::min (self.max_hops, cmp::max (self.min_hops, (candidates.len () + 1) / 2));
+        let over_relay_keys = self.pick_diverse (&[&local_key, exit_key], &candidates, over_count);
+        if over_relay_keys.len () < self.min_hops {
+            self.logger.warning (format! ("Only {} Nodes are unrelated enough to relay a route needing {} hops", over_relay_keys.len (), self.min_hops));
+            return None
+        }
+        let unused_keys: Vec<&Key> = candidates.iter ().filter (|key| !over_relay_keys.contains (key)).map (|key| *key).collect ();
+        let mut back_relay_keys = self.pick_diverse (&[exit_key, &local_key], &unused_keys, self.max_hops);
+        // Not enough unused neighbors: share some of the request path's relays
+        if back_relay_keys.len () < self.min_hops {
+            let needed = self.min_hops - back_relay_keys.len ();
+            let reused_keys = {
+                let mut back_path = vec! (exit_key, &local_key);
+                back_path.extend (back_relay_keys.iter ().map (|key| *key));
+                let reusable_keys: Vec<&Key> = over_relay_keys.iter ().rev ().map (|key| *key).collect ();
+                self.pick_diverse (&back_path, &reusable_keys, needed)
+            };
+            if reused_keys.len () < needed {return None}
+            back_relay_keys.extend (reused_keys);
+        }
+        Some ((over_relay_keys, back_relay_keys))
+    }
+
+    // Takes up to count candidates, best first, passing over those related to Nodes already on
+    // the path. Under Prefer, the least related are taken once the unrelated run out.
+    fn pick_diverse<'a> (&self, path: &[&Key], candidates: &[&'a Key], count: usize) -> Vec<&'a Key> {
+        let mut on_path: Vec<&Key> = path.to_vec ();
+        let mut remaining: Vec<&'a Key> = candidates.to_vec ();
+        let mut picked: Vec<&'a Key> = vec! ();
+        while (picked.len () < count) && !remaining.is_empty () {
+            let index = {
+                let relation_counts: Vec<usize> = remaining.iter ()
+                    .map (|key| on_path.iter ().filter (|other| self.are_related (key, other)).count ())
+                    .collect ();
+                let fewest = *relation_counts.iter ().min ().expect ("No candidates remain");
+                if (fewest > 0) && (self.route_diversity == RouteDiversity::Require) {break}
+                relation_counts.iter ().position (|relation_count| *relation_count == fewest).expect ("Minimum disappeared")
+            };
+            let key = remaining.remove (index);
+            on_path.push (key);
+            picked.push (key);
+        }
+        picked
+    }
+
+    // Related Nodes share a /16 (a /32 for IPv6), an autonomous system, or an operator, so one
+    // party might be watching both
+    fn are_related (&self, a: &Key, b: &Key) -> bool {
+        if self.route_diversity == RouteDiversity::Off {return false}
+        let (a_ip, b_ip) = match (self.database.node_addr_of (a), self.database.node_addr_of (b)) {
+            (Some (a_addr), Some (b_addr)) => (a_addr.ip_addr (), b_addr.ip_addr ()),
+            _ => return false
+        };
+        let same_network = match (&a_ip, &b_ip) {
+            (&IpAddr::V4 (ref a_v4), &IpAddr::V4 (ref b_v4)) => &a_v4.octets ()[..2] == &b_v4.octets ()[..2],
+            (&IpAddr::V6 (ref a_v6), &IpAddr::V6 (ref b_v6)) => &a_v6.segments ()[..2] == &b_v6.segments ()[..2],
+            _ => false
+        };
+        let same_asn = match (self.geolocation.asn_of (&a_ip), self.geolocation.asn_of (&b_ip)) {
+            (Some (a_asn), Some (b_asn)) => a_asn == b_asn,
+            _ => false
+        };
+        let same_operator = match (self.geolocation.operator_of (&a_ip), self.geolocation.operator_of (&b_ip)) {
+            (Some (a_operator), Some (b_operator)) => a_operator == b_operator,
+            _ => false
+        };
+        same_network || same_asn || same_operator
+    }
+
+    // Every Node the database knows of, and the neighbor links their records claim between them.
+    // Links to Nodes we have no record or address for would have nothing to point at, so they're
+    // left out.
+    fn topology (&self, now: Instant) -> NeighborhoodTopology {
+        let keys = self.database.known_keys ();
+        let root_key = &self.database.root ().public_key;
+        let nodes: Vec<TopologyNode> = keys.iter ().map (|key| {
+            let node_addr_opt = self.database.node_addr_of (key);
+            let ip_addr_opt = node_addr_opt.as_ref ().map (|node_addr| node_addr.ip_addr ());
+            TopologyNode {
+                public_key: (*key).clone (),
+                is_local: *key == root_key,
+                reputation: self.reputation_of (key),
+                last_seen_ms_ago_opt: self.database.last_seen (key)
+                    .map (|last_seen| if last_seen > now {0} else {millis (now.duration_since (last_seen))}),
+                latency_ms_opt: self.latencies.latency_of (key).map (millis),
+                country_opt: ip_addr_opt.and_then (|ip_addr| self.geolocation.country_of (&ip_addr).map (String::from)),
+                node_addr_opt,
+            }
+        }).collect ();
+        let mut edges = vec! ();
+        for record in self.database.records () {
+            for neighbor in record.neighbors.iter ().filter (|neighbor| keys.contains (neighbor)) {
+                edges.push (TopologyEdge {from: record.public_key.clone (), to: neighbor.clone ()});
+            }
+        }
+        NeighborhoodTopology {nodes, edges}
     }
 
     fn matches (&self, node_ref_ref: &&NodeDescriptor, query: &NodeQueryMessage) -> bool {
@@ -86,24 +1125,151 @@ impl Neighborhood {
     }
 }
 
+fn millis (duration: Duration) -> u64 {
+    duration.as_secs () * 1000 + (duration.subsec_nanos () / 1_000_000) as u64
+}
+
+fn make_nonce (cryptde: &CryptDE) -> u64 {
+    let mut bytes = [0u8; 8];
+    cryptde.random (&mut bytes);
+    bytes.iter ().fold (0u64, |nonce, byte| (nonce << 8) | (*byte as u64))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::str::FromStr;
     use std::net::IpAddr;
+    use std::net::SocketAddr;
     use actix::Arbiter;
     use actix::Recipient;
     use actix::System;
     use actix::msgs;
     use futures::future::Future;
+    use std::env::temp_dir;
+    use std::fs;
+    use std::fs::File;
+    use std::io::Write;
+    use std::thread;
+    use neighborhood_store::NEIGHBORHOOD_DATABASE_FILENAME;
+    use serde_cbor;
+    use sub_lib::cryptde::PlainData;
+    use sub_lib::cryptde_null::CryptDENull;
+    use sub_lib::cryptde_rotating::CryptDERotating;
+    use sub_lib::hopper::ExpiredCoresPackage;
+    use neighborhood_database::NodeRecord;
+    use gossip::MAX_GOSSIP_NODE_RECORDS;
+    use sub_lib::accountant::DEFAULT_RATES;
+    use sub_lib::accountant::EXIT_BYTE_RATE;
+    use sub_lib::accountant::EXIT_SERVICE_RATE;
+    use sub_lib::accountant::RateSchedule;
+    use sub_lib::neighborhood::DEFAULT_MAX_NEIGHBORS;
+    use sub_lib::wallet::Wallet;
     use test_utils::test_utils::cryptde;
+    use test_utils::test_utils::make_meaningless_route;
+    use test_utils::test_utils::make_peer_actors_from;
+    use test_utils::test_utils::Recorder;
+    use test_utils::test_utils::init_test_logging;
+    use test_utils::test_utils::TestLogHandler;
+
+    fn direct_config (neighbor_configs: Vec<(Key, NodeAddr)>) -> NeighborhoodConfig {
+        NeighborhoodConfig {
+            neighbor_configs,
+            min_hops: 0,
+            max_hops: 0,
+            gossip_interval_ms: 0,
+            heartbeat_interval_ms: 0,
+            stale_node_window_ms: 0,
+            data_directory_opt: None,
+            local_ip_addr_opt: None,
+            clandestine_ports: vec! (),
+            bans: vec! (),
+            geolocation_database_opt: None,
+            exit_location: ExitLocation::default (),
+            route_diversity: RouteDiversity::Prefer,
+            originate_only: false,
+            min_neighbors: 0,
+            target_neighbors: 0,
+            max_neighbors: DEFAULT_MAX_NEIGHBORS,
+            earning_wallet_opt: None,
+            consuming_wallet_opt: None,
+            rates: DEFAULT_RATES,
+            max_route_cost_opt: None,
+        }
+    }
+
+    // Opens Gossip the way the neighbor it's meant for would
+    fn gossip_in (package: &IncipientCoresPackage) -> Gossip {
+        let sealed_gossip: SealedGossip = serde_cbor::de::from_slice (&package.payload.data[..]).unwrap ();
+        let mut neighbor = CryptDENull::new ();
+        neighbor.adopt_private_key (&CryptDENull::other_key (&package.payload_destination_key)).unwrap ();
+        sealed_gossip.open (&neighbor).unwrap ()
+    }
+
+    fn gossip_package (node_records: Vec<NodeRecord>) -> ExpiredNeighborhoodPackage {
+        gossip_package_with_bans (node_records, vec! ())
+    }
+
+    fn gossip_package_with_bans (node_records: Vec<NodeRecord>, bans: Vec<BanRecord>) -> ExpiredNeighborhoodPackage {
+        let sender = make_signer ();
+        let sealed_gossip = SealedGossip::seal (&Gossip {node_records, bans}, &cryptde ().public_key (), &sender).unwrap ();
+        let payload = serde_cbor::ser::to_vec (&sealed_gossip).unwrap ();
+        ExpiredNeighborhoodPackage {
+            package: ExpiredCoresPackage::new (make_meaningless_route (), PlainData::new (&payload[..])),
+            neighbor_addr: SocketAddr::from_str ("1.2.3.4:5678").unwrap (),
+        }
+    }
+
+    fn make_signer () -> CryptDENull {
+        let mut signer = CryptDENull::new ();
+        signer.generate_key_pair ();
+        signer
+    }
+
+    fn signed_record (signer: &CryptDENull, node_addr_opt: Option<&NodeAddr>, version: u64) -> NodeRecord {
+        let mut record = NodeRecord::new (&signer.public_key (), node_addr_opt, version);
+        record.sign (signer);
+        record
+    }
 
 
+    #[test]
+    fn topology_shows_known_nodes_with_what_is_known_of_them_and_the_links_between_them () {
+        let cryptde = cryptde ();
+        let neighbor = make_signer ();
+        let distant = make_signer ();
+        let neighbor_addr = NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap (), &vec! (1234));
+        let mut subject = Neighborhood::new (cryptde, direct_config (vec! ((neighbor.public_key (), neighbor_addr.clone ()))));
+        let mut neighbor_record = NodeRecord::new (&neighbor.public_key (), Some (&neighbor_addr), 1);
+        neighbor_record.neighbors = vec! (cryptde.public_key (), distant.public_key ());
+        neighbor_record.sign (&neighbor);
+        subject.database.merge (neighbor_record).unwrap ();
+        subject.penalize (Misbehaver::Node (neighbor.public_key ()), NeighborMisbehavior::FailedRelay);
+
+        let result = subject.topology (Instant::now () + Duration::from_millis (1500));
+
+        assert_eq! (result.nodes.len (), 2);
+        let local = result.nodes.iter ().find (|node| node.public_key == cryptde.public_key ()).unwrap ();
+        assert_eq! (local.is_local, true);
+        assert_eq! (local.reputation, INITIAL_REPUTATION);
+        assert_eq! (local.last_seen_ms_ago_opt, None);
+        let remote = result.nodes.iter ().find (|node| node.public_key == neighbor.public_key ()).unwrap ();
+        assert_eq! (remote.is_local, false);
+        assert_eq! (remote.node_addr_opt, Some (neighbor_addr));
+        assert_eq! (remote.reputation, INITIAL_REPUTATION - 5);
+        assert_eq! (remote.last_seen_ms_ago_opt.map (|ms| (ms >= 1500) && (ms < 60000)), Some (true));
+        assert_eq! (remote.latency_ms_opt, None);
+        assert_eq! (remote.country_opt, None);
+        assert_eq! (result.edges.len (), 2);
+        assert_eq! (result.edges.contains (&TopologyEdge {from: cryptde.public_key (), to: neighbor.public_key ()}), true);
+        assert_eq! (result.edges.contains (&TopologyEdge {from: neighbor.public_key (), to: cryptde.public_key ()}), true);
+    }
+
     #[test]
     fn responds_with_none_when_initially_configured_with_no_data () {
         let cryptde = cryptde ();
         let system = System::new ("responds_with_none_when_initially_configured_with_no_data");
-        let subject = Neighborhood::new (cryptde, vec! ());
+        let subject = Neighborhood::new (cryptde, direct_config (vec! ()));
         let addr: Addr<Syn, Neighborhood> = subject.start ();
         let sub: Recipient<Syn, NodeQueryMessage> = addr.recipient::<NodeQueryMessage> ();
 
@@ -119,9 +1285,9 @@ mod tests {
     fn responds_with_none_when_key_query_matches_no_configured_data () {
         let cryptde = cryptde ();
         let system = System::new ("responds_with_none_when_initially_configured_with_no_data");
-        let subject = Neighborhood::new (cryptde, vec! (
+        let subject = Neighborhood::new (cryptde, direct_config (vec! (
             (Key::new (&b"booga"[..]), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234, 2345))),
-        ));
+        )));
         let addr: Addr<Syn, Neighborhood> = subject.start ();
         let sub: Recipient<Syn, NodeQueryMessage> = addr.recipient::<NodeQueryMessage> ();
 
@@ -140,10 +1306,10 @@ mod tests {
         let public_key = Key::new (&b"booga"[..]);
         let node_addr = NodeAddr::new(&IpAddr::from_str("1.2.3.4").unwrap(), &vec!(1234, 2345));
         let another_node_addr = NodeAddr::new(&IpAddr::from_str("2.3.4.5").unwrap(), &vec!(1234, 2345));
-        let subject = Neighborhood::new (cryptde, vec! (
+        let subject = Neighborhood::new (cryptde, direct_config (vec! (
             (public_key.clone (), node_addr.clone ()),
             (public_key.clone (), another_node_addr.clone ()),
-        ));
+        )));
         let addr: Addr<Syn, Neighborhood> = subject.start ();
         let sub: Recipient<Syn, NodeQueryMessage> = addr.recipient::<NodeQueryMessage> ();
 
@@ -159,9 +1325,9 @@ mod tests {
     fn responds_with_none_when_ip_address_query_matches_no_configured_data () {
         let cryptde = cryptde ();
         let system = System::new ("responds_with_none_when_initially_configured_with_no_data");
-        let subject = Neighborhood::new (cryptde, vec! (
+        let subject = Neighborhood::new (cryptde, direct_config (vec! (
             (Key::new (&b"booga"[..]), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234, 2345))),
-        ));
+        )));
         let addr: Addr<Syn, Neighborhood> = subject.start ();
         let sub: Recipient<Syn, NodeQueryMessage> = addr.recipient::<NodeQueryMessage> ();
 
@@ -180,10 +1346,10 @@ mod tests {
         let public_key = Key::new (&b"booga"[..]);
         let another_public_key = Key::new (&b"gooba"[..]);
         let node_addr = NodeAddr::new(&IpAddr::from_str("1.2.3.4").unwrap(), &vec!(1234, 2345));
-        let subject = Neighborhood::new (cryptde, vec! (
+        let subject = Neighborhood::new (cryptde, direct_config (vec! (
             (public_key.clone (), node_addr.clone ()),
             (another_public_key.clone (), node_addr.clone ()),
-        ));
+        )));
         let addr: Addr<Syn, Neighborhood> = subject.start ();
         let sub: Recipient<Syn, NodeQueryMessage> = addr.recipient::<NodeQueryMessage> ();
 
@@ -194,4 +1360,1546 @@ mod tests {
         let result = future.wait ().unwrap ();
         assert_eq! (result.unwrap (), NodeDescriptor::new (public_key, Some (node_addr)));
     }
+
+    #[test]
+    fn route_query_prefers_local_node_as_exit () {
+        let cryptde = cryptde ();
+        let system = System::new ("route_query_prefers_local_node_as_exit");
+        let subject = Neighborhood::new (cryptde, direct_config (vec! (
+            (Key::new (&b"booga"[..]), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234, 2345))),
+        )));
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+        let sub: Recipient<Syn, RouteQueryMessage> = addr.recipient::<RouteQueryMessage> ();
+
+        let future = sub.send(RouteQueryMessage::new (vec! ()));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let result = future.wait ().unwrap ().unwrap ();
+        let local_key = cryptde.public_key ();
+        assert_eq! (result, RouteQueryResponse {
+            route: Route::new (vec! (
+                RouteSegment::new (vec! (&local_key, &local_key), Component::ProxyClient),
+                RouteSegment::new (vec! (&local_key, &local_key), Component::ProxyServer)
+            ), cryptde).unwrap (),
+            exit_key: local_key.clone (),
+        });
+    }
+
+    #[test]
+    fn route_query_uses_neighbor_as_exit_when_local_node_is_excluded () {
+        let cryptde = cryptde ();
+        let system = System::new ("route_query_uses_neighbor_as_exit_when_local_node_is_excluded");
+        let excluded_key = Key::new (&b"booga"[..]);
+        let exit_key = Key::new (&b"gooba"[..]);
+        let subject = Neighborhood::new (cryptde, direct_config (vec! (
+            (excluded_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234))),
+            (exit_key.clone (), NodeAddr::new (&IpAddr::from_str ("2.3.4.5").unwrap(), &vec! (1234))),
+        )));
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+        let sub: Recipient<Syn, RouteQueryMessage> = addr.recipient::<RouteQueryMessage> ();
+
+        let future = sub.send(RouteQueryMessage::new (vec! (cryptde.public_key (), excluded_key)));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let result = future.wait ().unwrap ().unwrap ();
+        let local_key = cryptde.public_key ();
+        assert_eq! (result, RouteQueryResponse {
+            route: Route::new (vec! (
+                RouteSegment::new (vec! (&local_key, &exit_key), Component::ProxyClient),
+                RouteSegment::new (vec! (&exit_key, &local_key), Component::ProxyServer)
+            ), cryptde).unwrap (),
+            exit_key,
+        });
+    }
+
+    #[test]
+    fn route_query_passes_over_exits_that_charge_more_than_the_most_this_node_will_pay () {
+        let cryptde = cryptde ();
+        let system = System::new ("route_query_passes_over_exits_that_charge_more_than_the_most_this_node_will_pay");
+        let cheap_key = Key::new (&b"cheap"[..]);
+        let pricey_signer = make_signer ();
+        let mut pricey = NodeRecord::new (&pricey_signer.public_key (), Some (&NodeAddr::new (&IpAddr::from_str ("5.6.7.8").unwrap(), &vec! (1234))), 1);
+        pricey.rates_opt = Some (RateSchedule {exit_service_rate: 5000, ..DEFAULT_RATES});
+        pricey.sign (&pricey_signer);
+        let subject = Neighborhood::new (cryptde, NeighborhoodConfig {
+            max_route_cost_opt: Some (RouteCost {per_package: EXIT_SERVICE_RATE, per_byte: EXIT_BYTE_RATE}),
+            ..direct_config (vec! ((cheap_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234)))))
+        });
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+        addr.try_send (BindMessage {peer_actors: make_peer_actors_from (None, None, None, None, None, None)}).unwrap ();
+        addr.try_send (gossip_package (vec! (pricey))).unwrap ();
+
+        let cheap_future = addr.clone ().recipient::<RouteQueryMessage> ().send (RouteQueryMessage::new (vec! (cryptde.public_key ())));
+        let pricey_future = addr.recipient::<RouteQueryMessage> ().send (RouteQueryMessage::new (vec! (cryptde.public_key (), cheap_key.clone ())));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        assert_eq! (cheap_future.wait ().unwrap ().unwrap ().exit_key, cheap_key);
+        assert_eq! (pricey_future.wait ().unwrap (), None);
+    }
+
+    #[test]
+    fn route_query_responds_with_none_when_every_exit_is_excluded () {
+        let cryptde = cryptde ();
+        let system = System::new ("route_query_responds_with_none_when_every_exit_is_excluded");
+        let subject = Neighborhood::new (cryptde, direct_config (vec! ()));
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+        let sub: Recipient<Syn, RouteQueryMessage> = addr.recipient::<RouteQueryMessage> ();
+
+        let future = sub.send(RouteQueryMessage::new (vec! (cryptde.public_key ())));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let result = future.wait ().unwrap ();
+        assert_eq! (result, None);
+    }
+
+    fn geolocated_config (test_name: &str, exit_location: ExitLocation, neighbor_configs: Vec<(Key, NodeAddr)>) -> NeighborhoodConfig {
+        let directory = temp_dir ().join ("neighborhood").join (test_name);
+        fs::create_dir_all (&directory).unwrap ();
+        let path = directory.join ("geolocation.csv");
+        File::create (&path).unwrap ().write_all (b"1.0.0.0,1.255.255.255,US\n2.0.0.0,2.255.255.255,DE\n3.0.0.0,3.255.255.255,CN\n").unwrap ();
+        NeighborhoodConfig {
+            local_ip_addr_opt: Some (IpAddr::from_str ("3.3.3.3").unwrap ()),
+            geolocation_database_opt: Some (path),
+            exit_location,
+            ..direct_config (neighbor_configs)
+        }
+    }
+
+    #[test]
+    fn route_query_uses_an_exit_in_a_requested_country () {
+        let cryptde = cryptde ();
+        let system = System::new ("route_query_uses_an_exit_in_a_requested_country");
+        let us_key = Key::new (&b"american"[..]);
+        let de_key = Key::new (&b"german"[..]);
+        let subject = Neighborhood::new (cryptde, geolocated_config ("route_query_uses_an_exit_in_a_requested_country",
+            ExitLocation {countries: vec! (String::from ("DE")), avoided_countries: vec! ()}, vec! (
+                (us_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234))),
+                (de_key.clone (), NodeAddr::new (&IpAddr::from_str ("2.3.4.5").unwrap(), &vec! (1234))),
+            )));
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+
+        let future = addr.recipient::<RouteQueryMessage> ().send (RouteQueryMessage::new (vec! ()));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let result = future.wait ().unwrap ().unwrap ();
+        assert_eq! (result.exit_key, de_key);
+    }
+
+    #[test]
+    fn route_query_follows_a_changed_exit_location () {
+        let cryptde = cryptde ();
+        let system = System::new ("route_query_follows_a_changed_exit_location");
+        let us_key = Key::new (&b"american"[..]);
+        let de_key = Key::new (&b"german"[..]);
+        let subject = Neighborhood::new (cryptde, geolocated_config ("route_query_follows_a_changed_exit_location",
+            ExitLocation {countries: vec! (String::from ("DE")), avoided_countries: vec! ()}, vec! (
+                (us_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234))),
+                (de_key.clone (), NodeAddr::new (&IpAddr::from_str ("2.3.4.5").unwrap(), &vec! (1234))),
+            )));
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+
+        addr.try_send (SetExitLocationMsg {exit_location: ExitLocation {countries: vec! (String::from ("US")), avoided_countries: vec! ()}}).unwrap ();
+        let future = addr.recipient::<RouteQueryMessage> ().send (RouteQueryMessage::new (vec! ()));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let result = future.wait ().unwrap ().unwrap ();
+        assert_eq! (result.exit_key, us_key);
+    }
+
+    #[test]
+    fn route_query_fails_clearly_when_no_exit_is_in_an_acceptable_location () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let system = System::new ("route_query_fails_clearly_when_no_exit_is_in_an_acceptable_location");
+        let subject = Neighborhood::new (cryptde, geolocated_config ("route_query_fails_clearly_when_no_exit_is_in_an_acceptable_location",
+            ExitLocation {countries: vec! (), avoided_countries: vec! (String::from ("CN"), String::from ("US"))}, vec! (
+                (Key::new (&b"american"[..]), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234))),
+            )));
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+
+        let future = addr.recipient::<RouteQueryMessage> ().send (RouteQueryMessage::new (vec! ()));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        assert_eq! (future.wait ().unwrap (), None);
+        TestLogHandler::new ().exists_log_containing ("None of the 2 available exit Nodes is in an acceptable location (!CN,!US)");
+    }
+
+    #[test]
+    fn route_query_returns_through_different_relays_than_it_goes_out_through () {
+        let cryptde = cryptde ();
+        let system = System::new ("route_query_returns_through_different_relays_than_it_goes_out_through");
+        let exit_key = Key::new (&b"exit"[..]);
+        let first_over_key = Key::new (&b"first over"[..]);
+        let second_over_key = Key::new (&b"second over"[..]);
+        let first_back_key = Key::new (&b"first back"[..]);
+        let second_back_key = Key::new (&b"second back"[..]);
+        let subject = Neighborhood::new (cryptde, NeighborhoodConfig {
+            neighbor_configs: vec! (
+                (exit_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234))),
+                (first_over_key.clone (), NodeAddr::new (&IpAddr::from_str ("2.3.4.5").unwrap(), &vec! (1234))),
+                (second_over_key.clone (), NodeAddr::new (&IpAddr::from_str ("3.4.5.6").unwrap(), &vec! (1234))),
+                (first_back_key.clone (), NodeAddr::new (&IpAddr::from_str ("4.5.6.7").unwrap(), &vec! (1234))),
+                (second_back_key.clone (), NodeAddr::new (&IpAddr::from_str ("5.6.7.8").unwrap(), &vec! (1234))),
+                (Key::new (&b"unused"[..]), NodeAddr::new (&IpAddr::from_str ("6.7.8.9").unwrap(), &vec! (1234))),
+            ),
+            min_hops: 1,
+            max_hops: 2,
+            gossip_interval_ms: 0,
+            heartbeat_interval_ms: 0,
+            stale_node_window_ms: 0,
+            data_directory_opt: None,
+            local_ip_addr_opt: None,
+            clandestine_ports: vec! (),
+            bans: vec! (),
+            geolocation_database_opt: None,
+            exit_location: ExitLocation::default (),
+            route_diversity: RouteDiversity::Prefer,
+            originate_only: false,
+            min_neighbors: 0,
+            target_neighbors: 0,
+            max_neighbors: DEFAULT_MAX_NEIGHBORS,
+            earning_wallet_opt: None,
+            consuming_wallet_opt: None,
+            rates: DEFAULT_RATES,
+            max_route_cost_opt: None,
+        });
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+        let sub: Recipient<Syn, RouteQueryMessage> = addr.recipient::<RouteQueryMessage> ();
+
+        let future = sub.send(RouteQueryMessage::new (vec! ()));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let result = future.wait ().unwrap ().unwrap ();
+        let local_key = cryptde.public_key ();
+        assert_eq! (result, RouteQueryResponse {
+            route: Route::new (vec! (
+                RouteSegment::new (vec! (&local_key, &first_over_key, &second_over_key, &exit_key), Component::ProxyClient),
+                RouteSegment::new (vec! (&exit_key, &first_back_key, &second_back_key, &local_key), Component::ProxyServer)
+            ), cryptde).unwrap (),
+            exit_key,
+        });
+    }
+
+    #[test]
+    fn route_query_shares_relays_between_directions_only_to_reach_min_hops () {
+        let cryptde = cryptde ();
+        let system = System::new ("route_query_shares_relays_between_directions_only_to_reach_min_hops");
+        let exit_key = Key::new (&b"exit"[..]);
+        let first_relay_key = Key::new (&b"first"[..]);
+        let second_relay_key = Key::new (&b"second"[..]);
+        let third_relay_key = Key::new (&b"third"[..]);
+        let subject = Neighborhood::new (cryptde, NeighborhoodConfig {
+            neighbor_configs: vec! (
+                (exit_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234))),
+                (first_relay_key.clone (), NodeAddr::new (&IpAddr::from_str ("2.3.4.5").unwrap(), &vec! (1234))),
+                (second_relay_key.clone (), NodeAddr::new (&IpAddr::from_str ("3.4.5.6").unwrap(), &vec! (1234))),
+                (third_relay_key.clone (), NodeAddr::new (&IpAddr::from_str ("4.5.6.7").unwrap(), &vec! (1234))),
+            ),
+            min_hops: 2,
+            max_hops: 2,
+            gossip_interval_ms: 0,
+            heartbeat_interval_ms: 0,
+            stale_node_window_ms: 0,
+            data_directory_opt: None,
+            local_ip_addr_opt: None,
+            clandestine_ports: vec! (),
+            bans: vec! (),
+            geolocation_database_opt: None,
+            exit_location: ExitLocation::default (),
+            route_diversity: RouteDiversity::Prefer,
+            originate_only: false,
+            min_neighbors: 0,
+            target_neighbors: 0,
+            max_neighbors: DEFAULT_MAX_NEIGHBORS,
+            earning_wallet_opt: None,
+            consuming_wallet_opt: None,
+            rates: DEFAULT_RATES,
+            max_route_cost_opt: None,
+        });
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+        let sub: Recipient<Syn, RouteQueryMessage> = addr.recipient::<RouteQueryMessage> ();
+
+        let future = sub.send(RouteQueryMessage::new (vec! ()));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let result = future.wait ().unwrap ().unwrap ();
+        let local_key = cryptde.public_key ();
+        assert_eq! (result, RouteQueryResponse {
+            route: Route::new (vec! (
+                RouteSegment::new (vec! (&local_key, &first_relay_key, &second_relay_key, &exit_key), Component::ProxyClient),
+                RouteSegment::new (vec! (&exit_key, &third_relay_key, &second_relay_key, &local_key), Component::ProxyServer)
+            ), cryptde).unwrap (),
+            exit_key,
+        });
+    }
+
+    #[test]
+    fn route_query_responds_with_none_when_too_few_neighbors_for_min_hops () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let system = System::new ("route_query_responds_with_none_when_too_few_neighbors_for_min_hops");
+        let subject = Neighborhood::new (cryptde, NeighborhoodConfig {
+            neighbor_configs: vec! (
+                (Key::new (&b"exit"[..]), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234))),
+                (Key::new (&b"relay"[..]), NodeAddr::new (&IpAddr::from_str ("2.3.4.5").unwrap(), &vec! (1234))),
+            ),
+            min_hops: 2,
+            max_hops: 3,
+            gossip_interval_ms: 0,
+            heartbeat_interval_ms: 0,
+            stale_node_window_ms: 0,
+            data_directory_opt: None,
+            local_ip_addr_opt: None,
+            clandestine_ports: vec! (),
+            bans: vec! (),
+            geolocation_database_opt: None,
+            exit_location: ExitLocation::default (),
+            route_diversity: RouteDiversity::Prefer,
+            originate_only: false,
+            min_neighbors: 0,
+            target_neighbors: 0,
+            max_neighbors: DEFAULT_MAX_NEIGHBORS,
+            earning_wallet_opt: None,
+            consuming_wallet_opt: None,
+            rates: DEFAULT_RATES,
+            max_route_cost_opt: None,
+        });
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+        let sub: Recipient<Syn, RouteQueryMessage> = addr.recipient::<RouteQueryMessage> ();
+
+        let future = sub.send(RouteQueryMessage::new (vec! ()));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let result = future.wait ().unwrap ();
+        assert_eq! (result, None);
+        TestLogHandler::new ().exists_log_containing ("Can't build a route with at least 2 hops from 2 neighbors");
+    }
+
+    fn diverse_route_query (test_name: &str, route_diversity: RouteDiversity, neighbor_configs: Vec<(Key, NodeAddr)>) -> Option<RouteQueryResponse> {
+        let cryptde = cryptde ();
+        let system = System::new (test_name);
+        let subject = Neighborhood::new (cryptde, NeighborhoodConfig {
+            min_hops: 1,
+            max_hops: 1,
+            route_diversity,
+            ..direct_config (neighbor_configs)
+        });
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+
+        let future = addr.recipient::<RouteQueryMessage> ().send (RouteQueryMessage::new (vec! ()));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        future.wait ().unwrap ()
+    }
+
+    #[test]
+    fn route_query_keeps_nodes_in_the_same_network_apart_when_it_can () {
+        let cryptde = cryptde ();
+        let exit_key = Key::new (&b"exit"[..]);
+        let sibling_key = Key::new (&b"sibling"[..]);
+        let stranger_key = Key::new (&b"stranger"[..]);
+
+        let result = diverse_route_query ("route_query_keeps_nodes_in_the_same_network_apart_when_it_can", RouteDiversity::Prefer, vec! (
+            (exit_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.0.1").unwrap(), &vec! (1234))),
+            (sibling_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.0.2").unwrap(), &vec! (1234))),
+            (stranger_key.clone (), NodeAddr::new (&IpAddr::from_str ("5.6.7.8").unwrap(), &vec! (1234))),
+        )).unwrap ();
+
+        let local_key = cryptde.public_key ();
+        assert_eq! (result, RouteQueryResponse {
+            route: Route::new (vec! (
+                RouteSegment::new (vec! (&local_key, &stranger_key, &exit_key), Component::ProxyClient),
+                RouteSegment::new (vec! (&exit_key, &sibling_key, &local_key), Component::ProxyServer)
+            ), cryptde).unwrap (),
+            exit_key,
+        });
+    }
+
+    #[test]
+    fn route_query_reuses_an_unrelated_relay_rather_than_a_related_one_when_diversity_is_required () {
+        let cryptde = cryptde ();
+        let exit_key = Key::new (&b"exit"[..]);
+        let stranger_key = Key::new (&b"stranger"[..]);
+
+        let result = diverse_route_query ("route_query_reuses_an_unrelated_relay_rather_than_a_related_one_when_diversity_is_required", RouteDiversity::Require, vec! (
+            (exit_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.0.1").unwrap(), &vec! (1234))),
+            (Key::new (&b"sibling"[..]), NodeAddr::new (&IpAddr::from_str ("1.2.0.2").unwrap(), &vec! (1234))),
+            (stranger_key.clone (), NodeAddr::new (&IpAddr::from_str ("5.6.7.8").unwrap(), &vec! (1234))),
+        )).unwrap ();
+
+        let local_key = cryptde.public_key ();
+        assert_eq! (result, RouteQueryResponse {
+            route: Route::new (vec! (
+                RouteSegment::new (vec! (&local_key, &stranger_key, &exit_key), Component::ProxyClient),
+                RouteSegment::new (vec! (&exit_key, &stranger_key, &local_key), Component::ProxyServer)
+            ), cryptde).unwrap (),
+            exit_key,
+        });
+    }
+
+    #[test]
+    fn route_query_responds_with_none_when_required_diversity_is_impossible () {
+        init_test_logging ();
+
+        let result = diverse_route_query ("route_query_responds_with_none_when_required_diversity_is_impossible", RouteDiversity::Require, vec! (
+            (Key::new (&b"exit"[..]), NodeAddr::new (&IpAddr::from_str ("1.2.0.1").unwrap(), &vec! (1234))),
+            (Key::new (&b"sibling"[..]), NodeAddr::new (&IpAddr::from_str ("1.2.0.2").unwrap(), &vec! (1234))),
+        ));
+
+        assert_eq! (result, None);
+        TestLogHandler::new ().exists_log_containing ("Only 0 Nodes are unrelated enough to relay a route needing 1 hops");
+    }
+
+    #[test]
+    fn nodes_run_by_the_same_operator_are_related () {
+        let directory = temp_dir ().join ("neighborhood").join ("nodes_run_by_the_same_operator_are_related");
+        fs::create_dir_all (&directory).unwrap ();
+        let path = directory.join ("geolocation.csv");
+        File::create (&path).unwrap ().write_all (b"1.0.0.0,1.255.255.255,US,64500,Acme\n2.0.0.0,2.255.255.255,US,64501,Acme\n\
+            3.0.0.0,3.255.255.255,US,64502,Initech\n4.0.0.0,4.255.255.255,US,64502,Globex\n").unwrap ();
+        let keys: Vec<Key> = vec! (Key::new (b"one"), Key::new (b"two"), Key::new (b"three"), Key::new (b"four"));
+        let subject = Neighborhood::new (cryptde (), NeighborhoodConfig {
+            geolocation_database_opt: Some (path),
+            ..direct_config (keys.iter ().enumerate ().map (|(index, key)| {
+                (key.clone (), NodeAddr::new (&IpAddr::from_str (&format! ("{}.0.0.1", index + 1)).unwrap(), &vec! (1234)))
+            }).collect ())
+        });
+
+        assert_eq! (subject.are_related (&keys[0], &keys[1]), true);
+        assert_eq! (subject.are_related (&keys[2], &keys[3]), true);
+        assert_eq! (subject.are_related (&keys[1], &keys[2]), false);
+    }
+
+    #[test]
+    fn misbehavior_reports_cost_the_neighbor_reputation () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let system = System::new ("misbehavior_reports_cost_the_neighbor_reputation");
+        let subject = Neighborhood::new (cryptde, direct_config (vec! (
+            (Key::new (b"neighbor"), NodeAddr::new (&IpAddr::from_str ("3.4.5.6").unwrap (), &vec! (1234)))
+        )));
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+        let sub: Recipient<Syn, NeighborMisbehaviorMessage> = addr.recipient::<NeighborMisbehaviorMessage> ();
+        let report = NeighborMisbehaviorMessage {
+            misbehaver: Misbehaver::Address (SocketAddr::from_str ("3.4.5.6:7890").unwrap ()),
+            misbehavior: NeighborMisbehavior::TamperedPackage,
+        };
+
+        sub.try_send (report.clone ()).unwrap ();
+        sub.try_send (report).unwrap ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        TestLogHandler::new ().await_log_containing ("Node neighbor reported for TamperedPackage; reputation now 60", 1000);
+    }
+
+    #[test]
+    fn misbehavior_at_an_address_shared_by_several_nodes_is_held_against_none_of_them () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let mut subject = Neighborhood::new (cryptde, direct_config (vec! (
+            (Key::new (b"behind NAT 1"), NodeAddr::new (&IpAddr::from_str ("3.4.5.7").unwrap (), &vec! (1234))),
+            (Key::new (b"behind NAT 2"), NodeAddr::new (&IpAddr::from_str ("3.4.5.7").unwrap (), &vec! (2345))),
+        )));
+
+        subject.penalize (Misbehaver::Address (SocketAddr::from_str ("3.4.5.7:7890").unwrap ()), NeighborMisbehavior::ForgedGossip);
+        subject.penalize (Misbehaver::Node (Key::new (b"behind NAT 2")), NeighborMisbehavior::ForgedGossip);
+
+        assert_eq! (subject.reputation_of (&Key::new (b"behind NAT 1")), INITIAL_REPUTATION);
+        assert_eq! (subject.reputation_of (&Key::new (b"behind NAT 2")), INITIAL_REPUTATION - 25);
+        TestLogHandler::new ().exists_log_containing ("Neighbor at 3.4.5.7 reported for ForgedGossip, but 2 Nodes are known there; holding none of them to account");
+    }
+
+    #[test]
+    fn originate_only_node_says_so_in_its_record_and_never_exits_its_own_traffic () {
+        let cryptde = cryptde ();
+        let neighbor_key = Key::new (&b"neighbor"[..]);
+        let subject = Neighborhood::new (cryptde, NeighborhoodConfig {
+            originate_only: true,
+            ..direct_config (vec! ((neighbor_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234)))))
+        });
+
+        let result = subject.route_round_trip (&vec! ()).unwrap ();
+
+        assert_eq! (subject.database.root ().originate_only, true);
+        assert_eq! (subject.database.root ().has_valid_signature (cryptde), true);
+        assert_eq! (result.exit_key, neighbor_key);
+    }
+
+    #[test]
+    fn route_query_leaves_out_gossiped_nodes_that_only_originate_traffic () {
+        let cryptde = cryptde ();
+        let consumer_signer = make_signer ();
+        let mut consumer_record = NodeRecord::new (&consumer_signer.public_key (), Some (&NodeAddr::new (&IpAddr::from_str ("5.6.7.8").unwrap(), &vec! (1234))), 1);
+        consumer_record.originate_only = true;
+        consumer_record.sign (&consumer_signer);
+        let provider_signer = make_signer ();
+        let mut subject = Neighborhood::new (cryptde, direct_config (vec! ()));
+        subject.receive_gossip (Gossip {node_records: vec! (consumer_record), bans: vec! ()}, &Key::new (b"neighbor"), SocketAddr::from_str ("1.2.3.4:5678").unwrap ());
+
+        assert_eq! (subject.route_round_trip (&vec! (cryptde.public_key ())), None);
+
+        subject.receive_gossip (Gossip {
+            node_records: vec! (signed_record (&provider_signer, Some (&NodeAddr::new (&IpAddr::from_str ("6.7.8.9").unwrap(), &vec! (1234))), 1)),
+            bans: vec! (),
+        }, &Key::new (b"neighbor"), SocketAddr::from_str ("1.2.3.4:5678").unwrap ());
+
+        assert_eq! (subject.route_round_trip (&vec! (cryptde.public_key ())).unwrap ().exit_key, provider_signer.public_key ());
+    }
+
+    #[test]
+    fn gossips_its_own_record_to_configured_neighbors_when_bound () {
+        let cryptde = cryptde ();
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_awaiter = hopper.get_awaiter ();
+        let neighbor_key = Key::new (&b"neighbor"[..]);
+        let neighbor_addr = NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234));
+        let config = direct_config (vec! ((neighbor_key.clone (), neighbor_addr)));
+        thread::spawn (move || {
+            let system = System::new ("gossips_its_own_record_to_configured_neighbors_when_bound");
+            let subject = Neighborhood::new (cryptde, config);
+            let peer_actors = make_peer_actors_from (None, None, Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            system.run ();
+        });
+        hopper_awaiter.await_message_count (1);
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        let package = hopper_recording.get_record::<IncipientCoresPackage> (0);
+        assert_eq! (package.route, Route::new (vec! (
+            RouteSegment::new (vec! (&cryptde.public_key (), &neighbor_key), Component::Neighborhood)
+        ), cryptde).unwrap ());
+        assert_eq! (package.payload_destination_key, neighbor_key);
+        let gossip = gossip_in (package);
+        let local_record = gossip.node_records.iter ().find (|record| record.public_key == cryptde.public_key ()).unwrap ();
+        assert_eq! (local_record.neighbors, vec! (neighbor_key.clone ()));
+        assert_eq! (local_record.has_valid_signature (cryptde), true);
+        assert_eq! (gossip.node_records.len (), 1);
+    }
+
+    #[test]
+    fn passes_news_from_gossip_along_to_neighbors () {
+        let cryptde = cryptde ();
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_awaiter = hopper.get_awaiter ();
+        let neighbor_key = Key::new (&b"neighbor"[..]);
+        let stranger_signer = make_signer ();
+        let mut stranger = NodeRecord::new (&stranger_signer.public_key (), Some (&NodeAddr::new (&IpAddr::from_str ("5.6.7.8").unwrap(), &vec! (1234))), 3);
+        stranger.neighbors = vec! (neighbor_key.clone ());
+        stranger.sign (&stranger_signer);
+        let news = gossip_package (vec! (stranger.clone ()));
+        let old_news = gossip_package (vec! (stranger.clone ()));
+        let config = direct_config (vec! (
+            (neighbor_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234)))
+        ));
+        thread::spawn (move || {
+            let system = System::new ("passes_news_from_gossip_along_to_neighbors");
+            let subject = Neighborhood::new (cryptde, config);
+            let peer_actors = make_peer_actors_from (None, None, Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            addr.try_send (news).unwrap ();
+            addr.try_send (old_news).unwrap ();
+
+            system.run ();
+        });
+        hopper_awaiter.await_message_count (2);
+        thread::sleep (Duration::from_millis (100));
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        assert_eq! (hopper_recording.len (), 2);
+        let gossip = gossip_in (hopper_recording.get_record::<IncipientCoresPackage> (1));
+        assert_eq! (gossip.node_records.contains (&stranger), true);
+    }
+
+    #[test]
+    fn payment_terms_from_gossip_are_reported_to_the_accountant_once_per_version () {
+        let cryptde = cryptde ();
+        let accountant = Recorder::new ();
+        let accountant_recording_arc = accountant.get_recording ();
+        let accountant_awaiter = accountant.get_awaiter ();
+        let neighbor_key = Key::new (&b"neighbor"[..]);
+        let stranger_signer = make_signer ();
+        let mut stranger = NodeRecord::new (&stranger_signer.public_key (), Some (&NodeAddr::new (&IpAddr::from_str ("5.6.7.8").unwrap(), &vec! (1234))), 3);
+        stranger.earning_wallet_opt = Some (Wallet::new ("0x2222222222222222222222222222222222222222").unwrap ());
+        stranger.consuming_wallet_opt = Some (Wallet::new ("0x3333333333333333333333333333333333333333").unwrap ());
+        stranger.rates_opt = Some (RateSchedule {exit_byte_rate: 7, ..DEFAULT_RATES});
+        stranger.sign (&stranger_signer);
+        let news = gossip_package (vec! (stranger.clone ()));
+        let old_news = gossip_package (vec! (stranger.clone ()));
+        let config = NeighborhoodConfig {
+            earning_wallet_opt: Some (Wallet::new ("0x1111111111111111111111111111111111111111").unwrap ()),
+            ..direct_config (vec! (
+                (neighbor_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234)))
+            ))
+        };
+        thread::spawn (move || {
+            let system = System::new ("payment_terms_from_gossip_are_reported_to_the_accountant_once_per_version");
+            let subject = Neighborhood::new (cryptde, config);
+            let peer_actors = make_peer_actors_from (None, None, None, None, None, Some (accountant));
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            addr.try_send (news).unwrap ();
+            addr.try_send (old_news).unwrap ();
+
+            system.run ();
+        });
+        accountant_awaiter.await_message_count (3);
+        thread::sleep (Duration::from_millis (100));
+        let accountant_recording = accountant_recording_arc.lock ().unwrap ();
+        assert_eq! (accountant_recording.len (), 3);
+        assert_eq! (accountant_recording.get_record::<ReportEarningWalletMessage> (0), &ReportEarningWalletMessage {
+            public_key: stranger_signer.public_key (),
+            earning_wallet: Wallet::new ("0x2222222222222222222222222222222222222222").unwrap (),
+        });
+        assert_eq! (accountant_recording.get_record::<ReportConsumingWalletMessage> (1), &ReportConsumingWalletMessage {
+            public_key: stranger_signer.public_key (),
+            consuming_wallet: Wallet::new ("0x3333333333333333333333333333333333333333").unwrap (),
+        });
+        assert_eq! (accountant_recording.get_record::<ReportRatesMessage> (2), &ReportRatesMessage {
+            public_key: stranger_signer.public_key (),
+            rates: RateSchedule {exit_byte_rate: 7, ..DEFAULT_RATES},
+        });
+    }
+
+    #[test]
+    fn gossip_cannot_rewrite_the_local_record () {
+        let cryptde = cryptde ();
+        let system = System::new ("gossip_cannot_rewrite_the_local_record");
+        let subject = Neighborhood::new (cryptde, direct_config (vec! ()));
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+        let mut impostor = NodeRecord::new (&cryptde.public_key (), Some (&NodeAddr::new (&IpAddr::from_str ("6.6.6.6").unwrap(), &vec! (1234))), u64::max_value ());
+        impostor.sign (cryptde);
+        addr.try_send (gossip_package (vec! (impostor))).unwrap ();
+
+        let future = addr.recipient::<NodeQueryMessage> ().send (NodeQueryMessage::IpAddress (IpAddr::from_str ("6.6.6.6").unwrap ()));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        assert_eq! (future.wait ().unwrap (), None);
+    }
+
+    #[test]
+    fn answers_node_queries_and_builds_routes_with_gossiped_nodes () {
+        let cryptde = cryptde ();
+        let system = System::new ("answers_node_queries_and_builds_routes_with_gossiped_nodes");
+        let exit_key = Key::new (&b"exit"[..]);
+        let relay_signer = make_signer ();
+        let relay_key = relay_signer.public_key ();
+        let relay_addr = NodeAddr::new (&IpAddr::from_str ("5.6.7.8").unwrap(), &vec! (1234));
+        let subject = Neighborhood::new (cryptde, NeighborhoodConfig {
+            neighbor_configs: vec! ((exit_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234)))),
+            min_hops: 1,
+            max_hops: 1,
+            gossip_interval_ms: 0,
+            heartbeat_interval_ms: 0,
+            stale_node_window_ms: 0,
+            data_directory_opt: None,
+            local_ip_addr_opt: None,
+            clandestine_ports: vec! (),
+            bans: vec! (),
+            geolocation_database_opt: None,
+            exit_location: ExitLocation::default (),
+            route_diversity: RouteDiversity::Prefer,
+            originate_only: false,
+            min_neighbors: 0,
+            target_neighbors: 0,
+            max_neighbors: DEFAULT_MAX_NEIGHBORS,
+            earning_wallet_opt: None,
+            consuming_wallet_opt: None,
+            rates: DEFAULT_RATES,
+            max_route_cost_opt: None,
+        });
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+        addr.try_send (BindMessage {peer_actors: make_peer_actors_from (None, None, None, None, None, None)}).unwrap ();
+        addr.try_send (gossip_package (vec! (signed_record (&relay_signer, Some (&relay_addr), 1)))).unwrap ();
+
+        let node_future = addr.clone ().recipient::<NodeQueryMessage> ().send (NodeQueryMessage::IpAddress (IpAddr::from_str ("5.6.7.8").unwrap ()));
+        let route_future = addr.recipient::<RouteQueryMessage> ().send (RouteQueryMessage::new (vec! ()));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        assert_eq! (node_future.wait ().unwrap (), Some (NodeDescriptor::new (relay_key.clone (), Some (relay_addr))));
+        let local_key = cryptde.public_key ();
+        assert_eq! (route_future.wait ().unwrap ().unwrap (), RouteQueryResponse {
+            route: Route::new (vec! (
+                RouteSegment::new (vec! (&local_key, &relay_key, &exit_key), Component::ProxyClient),
+                RouteSegment::new (vec! (&exit_key, &relay_key, &local_key), Component::ProxyServer)
+            ), cryptde).unwrap (),
+            exit_key,
+        });
+    }
+
+    #[test]
+    fn remembers_gossiped_nodes_across_restarts () {
+        let cryptde = cryptde ();
+        let data_directory = temp_dir ().join ("neighborhood").join ("remembers_gossiped_nodes_across_restarts");
+        fs::create_dir_all (&data_directory).unwrap ();
+        let _ = fs::remove_file (data_directory.join (NEIGHBORHOOD_DATABASE_FILENAME));
+        let config = NeighborhoodConfig {data_directory_opt: Some (data_directory), ..direct_config (vec! ())};
+        let stranger_signer = make_signer ();
+        let stranger_key = stranger_signer.public_key ();
+        let stranger_addr = NodeAddr::new (&IpAddr::from_str ("5.6.7.8").unwrap(), &vec! (1234));
+        Neighborhood::new (cryptde, config.clone ()).receive_gossip (Gossip {
+            node_records: vec! (signed_record (&stranger_signer, Some (&stranger_addr), 1)),
+            bans: vec! (),
+        }, &Key::new (b"neighbor"), SocketAddr::from_str ("1.2.3.4:5678").unwrap ());
+        let system = System::new ("remembers_gossiped_nodes_across_restarts");
+        let addr: Addr<Syn, Neighborhood> = Neighborhood::new (cryptde, config).start ();
+
+        let future = addr.recipient::<NodeQueryMessage> ().send (NodeQueryMessage::PublicKey (stranger_key.clone ()));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        assert_eq! (future.wait ().unwrap (), Some (NodeDescriptor::new (stranger_key, Some (stranger_addr))));
+    }
+
+    #[test]
+    fn discards_forged_records_and_strikes_the_neighbor_that_relayed_them () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let system = System::new ("discards_forged_records_and_strikes_the_neighbor_that_relayed_them");
+        let subject = Neighborhood::new (cryptde, direct_config (vec! ()));
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+        let victim = make_signer ();
+        let mut forged = NodeRecord::new (&victim.public_key (), Some (&NodeAddr::new (&IpAddr::from_str ("6.6.6.6").unwrap(), &vec! (1234))), 1);
+        forged.sign (&make_signer ());
+        let honest_signer = make_signer ();
+        let honest = signed_record (&honest_signer, Some (&NodeAddr::new (&IpAddr::from_str ("5.6.7.8").unwrap(), &vec! (1234))), 1);
+        let mut package = gossip_package (vec! (forged, honest));
+        package.neighbor_addr = SocketAddr::from_str ("4.3.2.1:5678").unwrap ();
+        addr.try_send (package).unwrap ();
+
+        let forged_future = addr.clone ().recipient::<NodeQueryMessage> ().send (NodeQueryMessage::PublicKey (victim.public_key ()));
+        let honest_future = addr.recipient::<NodeQueryMessage> ().send (NodeQueryMessage::PublicKey (honest_signer.public_key ()));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        assert_eq! (forged_future.wait ().unwrap (), None);
+        assert_eq! (honest_future.wait ().unwrap ().is_some (), true);
+        let tlh = TestLogHandler::new ();
+        tlh.exists_log_containing ("Discarded 1 forged records in Gossip from neighbor at 4.3.2.1:5678");
+        tlh.exists_log_containing ("reported for ForgedGossip; reputation now 75");
+    }
+
+    #[test]
+    fn drops_gossip_sealed_for_somebody_else_and_strikes_the_neighbor_that_sent_it () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let system = System::new ("drops_gossip_sealed_for_somebody_else_and_strikes_the_neighbor_that_sent_it");
+        let subject = Neighborhood::new (cryptde, direct_config (vec! ()));
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+        let stranger_signer = make_signer ();
+        let gossip = Gossip {
+            node_records: vec! (signed_record (&stranger_signer, Some (&NodeAddr::new (&IpAddr::from_str ("5.6.7.8").unwrap(), &vec! (1234))), 1)),
+            bans: vec! (),
+        };
+        let sealed_gossip = SealedGossip::seal (&gossip, &make_signer ().public_key (), &make_signer ()).unwrap ();
+        addr.try_send (ExpiredNeighborhoodPackage {
+            package: ExpiredCoresPackage::new (make_meaningless_route (), PlainData::new (&serde_cbor::ser::to_vec (&sealed_gossip).unwrap ()[..])),
+            neighbor_addr: SocketAddr::from_str ("4.3.2.2:5678").unwrap (),
+        }).unwrap ();
+
+        let future = addr.recipient::<NodeQueryMessage> ().send (NodeQueryMessage::PublicKey (stranger_signer.public_key ()));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        assert_eq! (future.wait ().unwrap (), None);
+        let tlh = TestLogHandler::new ();
+        tlh.exists_log_containing ("Dropped Gossip from neighbor at 4.3.2.2:5678: Couldn't decrypt Gossip");
+        tlh.exists_log_containing ("Neighbor at 4.3.2.2 reported for ForgedGossip, but 0 Nodes are known there; holding none of them to account");
+    }
+
+    #[test]
+    fn route_query_prefers_reputable_relays_and_leaves_out_quarantined_exits () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let system = System::new ("route_query_prefers_reputable_relays_and_leaves_out_quarantined_exits");
+        let quarantined_key = Key::new (&b"quarantined"[..]);
+        let exit_key = Key::new (&b"exit"[..]);
+        let suspect_key = Key::new (&b"suspect"[..]);
+        let trusted_key = Key::new (&b"trusted"[..]);
+        let subject = Neighborhood::new (cryptde, NeighborhoodConfig {
+            neighbor_configs: vec! (
+                (quarantined_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234))),
+                (exit_key.clone (), NodeAddr::new (&IpAddr::from_str ("2.3.4.5").unwrap(), &vec! (1234))),
+                (suspect_key.clone (), NodeAddr::new (&IpAddr::from_str ("3.4.5.6").unwrap(), &vec! (1234))),
+                (trusted_key.clone (), NodeAddr::new (&IpAddr::from_str ("4.5.6.7").unwrap(), &vec! (1234))),
+            ),
+            min_hops: 1,
+            max_hops: 1,
+            gossip_interval_ms: 0,
+            heartbeat_interval_ms: 0,
+            stale_node_window_ms: 0,
+            data_directory_opt: None,
+            local_ip_addr_opt: None,
+            clandestine_ports: vec! (),
+            bans: vec! (),
+            geolocation_database_opt: None,
+            exit_location: ExitLocation::default (),
+            route_diversity: RouteDiversity::Prefer,
+            originate_only: false,
+            min_neighbors: 0,
+            target_neighbors: 0,
+            max_neighbors: DEFAULT_MAX_NEIGHBORS,
+            earning_wallet_opt: None,
+            consuming_wallet_opt: None,
+            rates: DEFAULT_RATES,
+            max_route_cost_opt: None,
+        });
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+        let report_sub: Recipient<Syn, NeighborMisbehaviorMessage> = addr.clone ().recipient::<NeighborMisbehaviorMessage> ();
+        let report = |key: &Key, misbehavior: NeighborMisbehavior| NeighborMisbehaviorMessage {
+            misbehaver: Misbehaver::Node (key.clone ()),
+            misbehavior,
+        };
+        report_sub.try_send (report (&quarantined_key, NeighborMisbehavior::UnpaidDebt)).unwrap ();
+        report_sub.try_send (report (&quarantined_key, NeighborMisbehavior::UnpaidDebt)).unwrap ();
+        report_sub.try_send (report (&suspect_key, NeighborMisbehavior::FailedRelay)).unwrap ();
+
+        let future = addr.recipient::<RouteQueryMessage> ().send (RouteQueryMessage::new (vec! ()));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        let result = future.wait ().unwrap ().unwrap ();
+        let local_key = cryptde.public_key ();
+        assert_eq! (result, RouteQueryResponse {
+            route: Route::new (vec! (
+                RouteSegment::new (vec! (&local_key, &trusted_key, &exit_key), Component::ProxyClient),
+                RouteSegment::new (vec! (&exit_key, &suspect_key, &local_key), Component::ProxyServer)
+            ), cryptde).unwrap (),
+            exit_key,
+        });
+        TestLogHandler::new ().exists_log_containing ("Quarantined Node quarantined: it will be left out of routes");
+    }
+
+    #[test]
+    fn nodes_banned_at_startup_are_left_out_of_routes () {
+        let cryptde = cryptde ();
+        let system = System::new ("nodes_banned_at_startup_are_left_out_of_routes");
+        let villain_key = Key::new (&b"villain"[..]);
+        let exit_key = Key::new (&b"exit"[..]);
+        let config = NeighborhoodConfig {
+            bans: vec! (BanNodeMsg {public_key: villain_key.clone (), reason: String::from ("Banned by operator"), share: false}),
+            ..direct_config (vec! (
+                (villain_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234))),
+                (exit_key.clone (), NodeAddr::new (&IpAddr::from_str ("2.3.4.5").unwrap(), &vec! (1234))),
+            ))
+        };
+        let subject = Neighborhood::new (cryptde, config);
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+
+        let future = addr.recipient::<RouteQueryMessage> ().send (RouteQueryMessage::new (vec! (cryptde.public_key ())));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        assert_eq! (future.wait ().unwrap ().unwrap ().exit_key, exit_key);
+    }
+
+    #[test]
+    fn banning_a_node_tells_the_hopper_and_dispatcher_to_cut_it_off () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_awaiter = hopper.get_awaiter ();
+        let dispatcher = Recorder::new ();
+        let dispatcher_recording_arc = dispatcher.get_recording ();
+        let dispatcher_awaiter = dispatcher.get_awaiter ();
+        let villain_key = Key::new (&b"villain"[..]);
+        let config = direct_config (vec! (
+            (villain_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234)))
+        ));
+        let ban = BanNodeMsg {public_key: villain_key.clone (), reason: String::from ("Unpaid debt"), share: false};
+        thread::spawn (move || {
+            let system = System::new ("banning_a_node_tells_the_hopper_and_dispatcher_to_cut_it_off");
+            let subject = Neighborhood::new (cryptde, config);
+            let peer_actors = make_peer_actors_from (None, Some (dispatcher), Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            addr.try_send (ban).unwrap ();
+
+            system.run ();
+        });
+        let expected = NodeBannedMsg {public_key: villain_key, ip_addr_opt: Some (IpAddr::from_str ("1.2.3.4").unwrap ())};
+        hopper_awaiter.await_message_count (2);
+        dispatcher_awaiter.await_message_count (1);
+        thread::sleep (Duration::from_millis (100));
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        assert_eq! (hopper_recording.len (), 2);
+        assert_eq! (hopper_recording.get_record::<NodeBannedMsg> (1), &expected);
+        assert_eq! (dispatcher_recording_arc.lock ().unwrap ().get_record::<NodeBannedMsg> (0), &expected);
+        TestLogHandler::new ().exists_log_containing ("Banned Node villain: Unpaid debt");
+    }
+
+    #[test]
+    fn lifting_a_ban_tells_the_hopper_and_dispatcher_to_carry_the_node_again () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_awaiter = hopper.get_awaiter ();
+        let dispatcher = Recorder::new ();
+        let dispatcher_recording_arc = dispatcher.get_recording ();
+        let dispatcher_awaiter = dispatcher.get_awaiter ();
+        let debtor_key = Key::new (&b"debtor"[..]);
+        let config = direct_config (vec! (
+            (debtor_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234)))
+        ));
+        let ban = BanNodeMsg {public_key: debtor_key.clone (), reason: String::from ("Unpaid debt"), share: false};
+        let unban = UnbanNodeMsg {public_key: debtor_key.clone ()};
+        thread::spawn (move || {
+            let system = System::new ("lifting_a_ban_tells_the_hopper_and_dispatcher_to_carry_the_node_again");
+            let subject = Neighborhood::new (cryptde, config);
+            let peer_actors = make_peer_actors_from (None, Some (dispatcher), Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            addr.try_send (ban).unwrap ();
+            addr.try_send (unban.clone ()).unwrap ();
+            addr.try_send (unban).unwrap ();
+
+            system.run ();
+        });
+        let expected = NodeUnbannedMsg {public_key: debtor_key, ip_addr_opt: Some (IpAddr::from_str ("1.2.3.4").unwrap ())};
+        hopper_awaiter.await_message_count (3);
+        dispatcher_awaiter.await_message_count (2);
+        thread::sleep (Duration::from_millis (100));
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        assert_eq! (hopper_recording.len (), 3);
+        assert_eq! (hopper_recording.get_record::<NodeUnbannedMsg> (2), &expected);
+        let dispatcher_recording = dispatcher_recording_arc.lock ().unwrap ();
+        assert_eq! (dispatcher_recording.len (), 2);
+        assert_eq! (dispatcher_recording.get_record::<NodeUnbannedMsg> (1), &expected);
+        TestLogHandler::new ().exists_log_containing ("Lifted ban of Node debtor: Unpaid debt");
+    }
+
+    #[test]
+    fn shared_bans_go_out_in_gossip () {
+        let cryptde = cryptde ();
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_awaiter = hopper.get_awaiter ();
+        let villain_key = Key::new (&b"villain"[..]);
+        let config = direct_config (vec! (
+            (Key::new (&b"neighbor"[..]), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234)))
+        ));
+        let ban = BanNodeMsg {public_key: villain_key.clone (), reason: String::from ("Forged Gossip"), share: true};
+        thread::spawn (move || {
+            let system = System::new ("shared_bans_go_out_in_gossip");
+            let subject = Neighborhood::new (cryptde, config);
+            let peer_actors = make_peer_actors_from (None, None, Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            addr.try_send (ban).unwrap ();
+
+            system.run ();
+        });
+        hopper_awaiter.await_message_count (3);
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        assert_eq! (hopper_recording.get_record::<NodeBannedMsg> (1), &NodeBannedMsg {public_key: villain_key.clone (), ip_addr_opt: None});
+        let gossip = gossip_in (hopper_recording.get_record::<IncipientCoresPackage> (2));
+        assert_eq! (gossip.bans, vec! (BanRecord::new (&villain_key, "Forged Gossip", cryptde)));
+    }
+
+    #[test]
+    fn adopts_gossiped_bans_only_from_the_neighbor_that_imposed_them_and_does_not_pass_them_on () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let dispatcher = Recorder::new ();
+        let dispatcher_recording_arc = dispatcher.get_recording ();
+        let dispatcher_awaiter = dispatcher.get_awaiter ();
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_awaiter = hopper.get_awaiter ();
+        let accuser = make_signer ();
+        let accuser_addr = NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234));
+        let accuser_record = signed_record (&accuser, Some (&accuser_addr), 1);
+        let bystander = make_signer ();
+        let bystander_record = signed_record (&bystander, Some (&NodeAddr::new (&IpAddr::from_str ("5.6.7.8").unwrap(), &vec! (1234))), 1);
+        let credible_ban = BanRecord::new (&Key::new (&b"villain"[..]), "Replayed packages", &accuser);
+        let hearsay_ban = BanRecord::new (&Key::new (&b"honest"[..]), "Replayed packages", &bystander);
+        let stranger_ban = BanRecord::new (&Key::new (&b"rival"[..]), "Just because", &make_signer ());
+        let mut forged_ban = BanRecord::new (&Key::new (&b"suspect"[..]), "Replayed packages", &accuser);
+        forged_ban.banned_key = Key::new (&b"innocent"[..]);
+        let package = gossip_package_with_bans (vec! (accuser_record, bystander_record),
+            vec! (credible_ban, hearsay_ban, stranger_ban, forged_ban));
+        thread::spawn (move || {
+            let system = System::new ("adopts_gossiped_bans_only_from_the_neighbor_that_imposed_them_and_does_not_pass_them_on");
+            let subject = Neighborhood::new (cryptde, direct_config (vec! ((accuser.public_key (), accuser_addr))));
+            let peer_actors = make_peer_actors_from (None, Some (dispatcher), Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            addr.try_send (package).unwrap ();
+
+            system.run ();
+        });
+        dispatcher_awaiter.await_message_count (1);
+        hopper_awaiter.await_message_count (3);
+        thread::sleep (Duration::from_millis (100));
+        let dispatcher_recording = dispatcher_recording_arc.lock ().unwrap ();
+        assert_eq! (dispatcher_recording.len (), 1);
+        assert_eq! (dispatcher_recording.get_record::<NodeBannedMsg> (0).public_key, Key::new (&b"villain"[..]));
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        assert_eq! (hopper_recording.get_record::<NodeBannedMsg> (1).public_key, Key::new (&b"villain"[..]));
+        assert_eq! (gossip_in (hopper_recording.get_record::<IncipientCoresPackage> (2)).bans, vec! ());
+        TestLogHandler::new ().exists_log_containing ("Discarded 1 forged records in Gossip from neighbor at 1.2.3.4:5678");
+    }
+
+    #[test]
+    fn a_neighbor_that_has_misbehaved_cannot_get_a_node_banned () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let accuser = make_signer ();
+        let accuser_addr = NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234));
+        let mut subject = Neighborhood::new (cryptde, direct_config (vec! ((accuser.public_key (), accuser_addr.clone ()))));
+        subject.database.merge (signed_record (&accuser, Some (&accuser_addr), 1)).unwrap ();
+        let neighbor_addr = SocketAddr::from_str ("1.2.3.4:5678").unwrap ();
+
+        let before = subject.is_trusted_banner (&accuser.public_key (), neighbor_addr);
+        subject.penalize (Misbehaver::Address (SocketAddr::from_str ("1.2.3.4:5678").unwrap ()), NeighborMisbehavior::FailedRelay);
+        let after = subject.is_trusted_banner (&accuser.public_key (), neighbor_addr);
+
+        assert_eq! (before, true);
+        assert_eq! (after, false);
+        assert_eq! (subject.is_trusted_banner (&accuser.public_key (), SocketAddr::from_str ("5.6.7.8:5678").unwrap ()), false);
+    }
+
+    #[test]
+    fn bootstraps_from_whichever_configured_neighbor_answers_first () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_awaiter = hopper.get_awaiter ();
+        let config = direct_config (vec! (
+            (Key::new (&b"first"[..]), NodeAddr::new (&IpAddr::from_str ("7.7.7.1").unwrap(), &vec! (1234))),
+            (Key::new (&b"second"[..]), NodeAddr::new (&IpAddr::from_str ("7.7.7.2").unwrap(), &vec! (1234))),
+            (Key::new (&b"third"[..]), NodeAddr::new (&IpAddr::from_str ("7.7.7.3").unwrap(), &vec! (1234))),
+        ));
+        let mut first_answer = gossip_package (vec! ());
+        first_answer.neighbor_addr = SocketAddr::from_str ("7.7.7.2:5678").unwrap ();
+        let mut second_answer = gossip_package (vec! ());
+        second_answer.neighbor_addr = SocketAddr::from_str ("7.7.7.3:5678").unwrap ();
+        thread::spawn (move || {
+            let system = System::new ("bootstraps_from_whichever_configured_neighbor_answers_first");
+            let subject = Neighborhood::new (cryptde, config);
+            let peer_actors = make_peer_actors_from (None, None, Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            addr.try_send (first_answer).unwrap ();
+            addr.try_send (second_answer).unwrap ();
+
+            system.run ();
+        });
+        hopper_awaiter.await_message_count (3);
+        let tlh = TestLogHandler::new ();
+        tlh.await_log_containing ("Bootstrapped from neighbor at 7.7.7.2:5678", 1000);
+        thread::sleep (Duration::from_millis (100));
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        let destinations: Vec<Key> = (0..3).map (|index| hopper_recording.get_record::<IncipientCoresPackage> (index).payload_destination_key.clone ()).collect ();
+        assert_eq! (destinations, vec! (Key::new (&b"first"[..]), Key::new (&b"second"[..]), Key::new (&b"third"[..])));
+        tlh.exists_log_containing ("Bootstrapping from 3 neighbors");
+        tlh.exists_no_log_containing ("Bootstrapped from neighbor at 7.7.7.3");
+    }
+
+    #[test]
+    fn gossips_to_configured_neighbors_again_until_one_answers () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let hopper = Recorder::new ();
+        let hopper_awaiter = hopper.get_awaiter ();
+        let config = direct_config (vec! (
+            (Key::new (&b"silent"[..]), NodeAddr::new (&IpAddr::from_str ("7.7.8.1").unwrap(), &vec! (1234))),
+        ));
+        thread::spawn (move || {
+            let system = System::new ("gossips_to_configured_neighbors_again_until_one_answers");
+            let mut subject = Neighborhood::new (cryptde, config);
+            subject.bootstrap_retry_ms = 10;
+            let peer_actors = make_peer_actors_from (None, None, Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            system.run ();
+        });
+        hopper_awaiter.await_message_count (3);
+        let tlh = TestLogHandler::new ();
+        tlh.exists_log_containing ("No configured neighbor has answered in 10ms; Gossiping to them again, and again in 20ms if none answers");
+        tlh.await_log_containing ("No configured neighbor has answered in 20ms; Gossiping to them again, and again in 40ms if none answers", 1000);
+    }
+
+    #[test]
+    fn stops_gossiping_to_configured_neighbors_again_once_one_answers () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_awaiter = hopper.get_awaiter ();
+        let config = direct_config (vec! (
+            (Key::new (&b"prompt"[..]), NodeAddr::new (&IpAddr::from_str ("7.7.9.1").unwrap(), &vec! (1234))),
+        ));
+        let mut answer = gossip_package (vec! ());
+        answer.neighbor_addr = SocketAddr::from_str ("7.7.9.1:5678").unwrap ();
+        thread::spawn (move || {
+            let system = System::new ("stops_gossiping_to_configured_neighbors_again_once_one_answers");
+            let mut subject = Neighborhood::new (cryptde, config);
+            subject.bootstrap_retry_ms = 50;
+            let peer_actors = make_peer_actors_from (None, None, Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            addr.try_send (answer).unwrap ();
+
+            system.run ();
+        });
+        hopper_awaiter.await_message_count (1);
+        TestLogHandler::new ().await_log_containing ("Bootstrapped from neighbor at 7.7.9.1:5678", 1000);
+        let gossip_count = hopper_recording_arc.lock ().unwrap ().len ();
+        thread::sleep (Duration::from_millis (200));
+        assert_eq! (hopper_recording_arc.lock ().unwrap ().len (), gossip_count);
+    }
+
+    fn introduction_package (introduction: Introduction, sender: &CryptDENull, neighbor_addr: &str) -> ExpiredNeighborhoodPackage {
+        let sealed_introduction = SealedIntroduction::seal (&introduction, &cryptde ().public_key (), sender).unwrap ();
+        let payload = serde_cbor::ser::to_vec (&sealed_introduction).unwrap ();
+        ExpiredNeighborhoodPackage {
+            package: ExpiredCoresPackage::new (make_meaningless_route (), PlainData::new (&payload[..])),
+            neighbor_addr: SocketAddr::from_str (neighbor_addr).unwrap (),
+        }
+    }
+
+    fn introduction_in (package: &IncipientCoresPackage) -> Introduction {
+        let sealed_introduction: SealedIntroduction = serde_cbor::de::from_slice (&package.payload.data[..]).unwrap ();
+        let mut recipient = CryptDENull::new ();
+        recipient.adopt_private_key (&CryptDENull::other_key (&package.payload_destination_key)).unwrap ();
+        sealed_introduction.open (&recipient).unwrap ()
+    }
+
+    #[test]
+    fn asks_nodes_heard_about_in_gossip_to_become_neighbors_until_it_has_enough () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_awaiter = hopper.get_awaiter ();
+        let neighbor_key = Key::new (&b"neighbor"[..]);
+        let stranger_signer = make_signer ();
+        let stranger_addr = NodeAddr::new (&IpAddr::from_str ("5.6.7.8").unwrap (), &vec! (1234));
+        let news = gossip_package (vec! (signed_record (&stranger_signer, Some (&stranger_addr), 3)));
+        // The local Node's CryptDENull makes the same nonce every time
+        let acceptance = introduction_package (Introduction::Accepted {record: signed_record (&stranger_signer, Some (&stranger_addr), 4), nonce: make_nonce (cryptde)},
+            &stranger_signer, "5.6.7.8:1234");
+        let config = NeighborhoodConfig {
+            min_neighbors: 2,
+            target_neighbors: 2,
+            max_neighbors: 3,
+            ..direct_config (vec! ((neighbor_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234)))))
+        };
+        thread::spawn (move || {
+            let system = System::new ("asks_nodes_heard_about_in_gossip_to_become_neighbors_until_it_has_enough");
+            let subject = Neighborhood::new (cryptde, config);
+            let peer_actors = make_peer_actors_from (None, None, Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            addr.try_send (news).unwrap ();
+            addr.try_send (acceptance).unwrap ();
+
+            system.run ();
+        });
+        hopper_awaiter.await_message_count (5);
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        let request = hopper_recording.get_record::<IncipientCoresPackage> (2);
+        assert_eq! (request.payload_destination_key, stranger_signer.public_key ());
+        match introduction_in (request) {
+            Introduction::Request {record, nonce} => {
+                assert_eq! (record.public_key, cryptde.public_key ());
+                assert_eq! (nonce, make_nonce (cryptde));
+            },
+            other => panic! ("Expected an introduction request, not {:?}", other),
+        }
+        let mut gossip_destinations: Vec<Key> = (3..5).map (|index| hopper_recording.get_record::<IncipientCoresPackage> (index).payload_destination_key.clone ()).collect ();
+        gossip_destinations.sort_by (|a, b| a.data.cmp (&b.data));
+        let mut expected_destinations = vec! (neighbor_key, stranger_signer.public_key ());
+        expected_destinations.sort_by (|a, b| a.data.cmp (&b.data));
+        assert_eq! (gossip_destinations, expected_destinations);
+        let tlh = TestLogHandler::new ();
+        tlh.exists_log_containing ("WARN: Neighborhood: Has 1 neighbors, fewer than the minimum of 2");
+        tlh.exists_log_containing ("at 5.6.7.8:1234 is now a neighbor");
+    }
+
+    #[test]
+    fn accepts_introductions_until_it_has_the_maximum_number_of_neighbors () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_awaiter = hopper.get_awaiter ();
+        let first_signer = make_signer ();
+        let second_signer = make_signer ();
+        let first_request = introduction_package (Introduction::Request {
+            record: signed_record (&first_signer, Some (&NodeAddr::new (&IpAddr::from_str ("6.6.6.1").unwrap (), &vec! (1234))), 1), nonce: 11
+        }, &first_signer, "6.6.6.1:1234");
+        let second_request = introduction_package (Introduction::Request {
+            record: signed_record (&second_signer, Some (&NodeAddr::new (&IpAddr::from_str ("6.6.6.2").unwrap (), &vec! (1234))), 1), nonce: 22
+        }, &second_signer, "6.6.6.2:1234");
+        let config = NeighborhoodConfig {
+            max_neighbors: 1,
+            ..direct_config (vec! ())
+        };
+        thread::spawn (move || {
+            let system = System::new ("accepts_introductions_until_it_has_the_maximum_number_of_neighbors");
+            let subject = Neighborhood::new (cryptde, config);
+            let peer_actors = make_peer_actors_from (None, None, Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            addr.try_send (first_request).unwrap ();
+            addr.try_send (second_request).unwrap ();
+
+            system.run ();
+        });
+        hopper_awaiter.await_message_count (3);
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        let acceptance = hopper_recording.get_record::<IncipientCoresPackage> (0);
+        assert_eq! (acceptance.payload_destination_key, first_signer.public_key ());
+        match introduction_in (acceptance) {
+            Introduction::Accepted {record, nonce} => {
+                assert_eq! (record.public_key, cryptde.public_key ());
+                assert_eq! (record.neighbors, vec! (first_signer.public_key ()));
+                assert_eq! (nonce, 11);
+            },
+            other => panic! ("Expected an acceptance, not {:?}", other),
+        }
+        assert_eq! (hopper_recording.get_record::<IncipientCoresPackage> (1).payload_destination_key, first_signer.public_key ());
+        let refusal = hopper_recording.get_record::<IncipientCoresPackage> (2);
+        assert_eq! (refusal.payload_destination_key, second_signer.public_key ());
+        assert_eq! (introduction_in (refusal), Introduction::Declined {nonce: 22});
+        TestLogHandler::new ().exists_log_containing ("Declined introduction from neighbor at 6.6.6.2:1234: already have 1 neighbors");
+    }
+
+    #[test]
+    fn introductions_carrying_somebody_else_s_record_or_played_back_are_dropped () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_awaiter = hopper.get_awaiter ();
+        let requester_signer = make_signer ();
+        let impostor_signer = make_signer ();
+        let requester_record = signed_record (&requester_signer, Some (&NodeAddr::new (&IpAddr::from_str ("6.6.6.1").unwrap (), &vec! (1234))), 1);
+        let impostor_request = introduction_package (Introduction::Request {record: requester_record.clone (), nonce: 11}, &impostor_signer, "6.6.6.3:1234");
+        let genuine_request = introduction_package (Introduction::Request {record: requester_record, nonce: 11}, &requester_signer, "6.6.6.1:1234");
+        let replayed_request = genuine_request.clone ();
+        thread::spawn (move || {
+            let system = System::new ("introductions_carrying_somebody_else_s_record_or_played_back_are_dropped");
+            let subject = Neighborhood::new (cryptde, direct_config (vec! ()));
+            let peer_actors = make_peer_actors_from (None, None, Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            addr.try_send (impostor_request).unwrap ();
+            addr.try_send (genuine_request).unwrap ();
+            addr.try_send (replayed_request).unwrap ();
+
+            system.run ();
+        });
+        let tlh = TestLogHandler::new ();
+        tlh.await_log_containing ("Dropped introduction from neighbor at 6.6.6.3:1234: it carries some other Node's record", 1000);
+        tlh.await_log_containing ("Dropped replayed introduction from neighbor at 6.6.6.1:1234", 1000);
+        hopper_awaiter.await_message_count (2);
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        let introductions: Vec<Introduction> = (0..hopper_recording.len ())
+            .map (|index| hopper_recording.get_record::<IncipientCoresPackage> (index))
+            .filter (|package| serde_cbor::de::from_slice::<SealedIntroduction> (&package.payload.data[..]).is_ok ())
+            .map (|package| introduction_in (package))
+            .collect ();
+        assert_eq! (introductions.len (), 1);
+        match introductions[0] {
+            Introduction::Accepted {ref record, nonce} => {
+                assert_eq! (record.neighbors, vec! (requester_signer.public_key ()));
+                assert_eq! (nonce, 11);
+            },
+            ref other => panic! ("Expected an acceptance, not {:?}", other),
+        }
+    }
+
+    #[test]
+    fn an_acceptance_with_the_wrong_nonce_is_ignored () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let hopper = Recorder::new ();
+        let stranger_signer = make_signer ();
+        let stranger_addr = NodeAddr::new (&IpAddr::from_str ("5.6.7.9").unwrap (), &vec! (1234));
+        let news = gossip_package (vec! (signed_record (&stranger_signer, Some (&stranger_addr), 3)));
+        let acceptance = introduction_package (Introduction::Accepted {record: signed_record (&stranger_signer, Some (&stranger_addr), 4), nonce: make_nonce (cryptde) ^ 1},
+            &stranger_signer, "5.6.7.9:1234");
+        let config = NeighborhoodConfig {
+            target_neighbors: 2,
+            ..direct_config (vec! ((Key::new (&b"neighbor"[..]), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234)))))
+        };
+        thread::spawn (move || {
+            let system = System::new ("an_acceptance_with_the_wrong_nonce_is_ignored");
+            let subject = Neighborhood::new (cryptde, config);
+            let peer_actors = make_peer_actors_from (None, None, Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            addr.try_send (news).unwrap ();
+            addr.try_send (acceptance).unwrap ();
+
+            system.run ();
+        });
+        let tlh = TestLogHandler::new ();
+        tlh.await_log_containing ("Ignored unsolicited acceptance from neighbor at 5.6.7.9:1234", 1000);
+        tlh.exists_no_log_containing ("at 5.6.7.9:1234 is now a neighbor");
+    }
+
+    fn heartbeat_package (heartbeat: Heartbeat, neighbor_addr: &str) -> ExpiredNeighborhoodPackage {
+        let payload = serde_cbor::ser::to_vec (&heartbeat).unwrap ();
+        ExpiredNeighborhoodPackage {
+            package: ExpiredCoresPackage::new (make_meaningless_route (), PlainData::new (&payload[..])),
+            neighbor_addr: SocketAddr::from_str (neighbor_addr).unwrap (),
+        }
+    }
+
+    #[test]
+    fn answers_pings_and_pings_neighbors_on_the_heartbeat_interval () {
+        let cryptde = cryptde ();
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_awaiter = hopper.get_awaiter ();
+        let neighbor_key = Key::new (&b"neighbor"[..]);
+        let config = NeighborhoodConfig {
+            heartbeat_interval_ms: 100,
+            ..direct_config (vec! ((neighbor_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234)))))
+        };
+        thread::spawn (move || {
+            let system = System::new ("answers_pings_and_pings_neighbors_on_the_heartbeat_interval");
+            let subject = Neighborhood::new (cryptde, config);
+            let peer_actors = make_peer_actors_from (None, None, Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            addr.try_send (heartbeat_package (Heartbeat::Ping {nonce: 7}, "1.2.3.4:5678")).unwrap ();
+
+            system.run ();
+        });
+        hopper_awaiter.await_message_count (3);
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        let heartbeat_in = |index: usize| -> Heartbeat {
+            let package = hopper_recording.get_record::<IncipientCoresPackage> (index);
+            assert_eq! (package.payload_destination_key, neighbor_key);
+            serde_cbor::de::from_slice (&package.payload.data[..]).unwrap ()
+        };
+        assert_eq! (heartbeat_in (1), Heartbeat::Pong {nonce: 7});
+        assert_eq! (heartbeat_in (2), Heartbeat::Ping {nonce: 0});
+    }
+
+    #[test]
+    fn pongs_are_timed_and_unsolicited_ones_ignored () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let neighbor_key = Key::new (&b"neighbor"[..]);
+        let mut subject = Neighborhood::new (cryptde, direct_config (vec! ()));
+        let nonce = subject.latencies.ping_sent (&neighbor_key, Instant::now ());
+
+        subject.receive_heartbeat (Heartbeat::Pong {nonce}, SocketAddr::from_str ("1.2.3.4:5678").unwrap ());
+        subject.receive_heartbeat (Heartbeat::Pong {nonce}, SocketAddr::from_str ("1.2.3.4:5679").unwrap ());
+
+        assert_eq! (subject.latencies.latency_of (&neighbor_key).is_some (), true);
+        let tlh = TestLogHandler::new ();
+        tlh.exists_log_containing ("Round trip to neighbor at 1.2.3.4:5678 took");
+        tlh.exists_log_containing ("Ignored unsolicited Pong from 1.2.3.4:5679");
+    }
+
+    #[test]
+    fn routes_prefer_quick_links_and_leave_out_dead_ones () {
+        let cryptde = cryptde ();
+        let slow_key = Key::new (&b"slow"[..]);
+        let fast_key = Key::new (&b"fast"[..]);
+        let dead_key = Key::new (&b"dead"[..]);
+        let mut subject = Neighborhood::new (cryptde, direct_config (vec! ()));
+        let now = Instant::now ();
+        let timeout = Duration::from_millis (1000);
+        let slow_nonce = subject.latencies.ping_sent (&slow_key, now);
+        let fast_nonce = subject.latencies.ping_sent (&fast_key, now);
+        subject.latencies.pong_received (slow_nonce, now + Duration::from_millis (200));
+        subject.latencies.pong_received (fast_nonce, now + Duration::from_millis (20));
+        for _ in 0..DEAD_AFTER_MISSED_PINGS {
+            subject.latencies.ping_sent (&dead_key, now);
+            subject.latencies.expire (now + timeout, timeout);
+        }
+        let unmeasured_key = Key::new (&b"unmeasured"[..]);
+
+        let result = subject.rank_by_reputation (vec! (&unmeasured_key, &slow_key, &dead_key, &fast_key));
+
+        assert_eq! (result, vec! (&fast_key, &slow_key, &unmeasured_key));
+    }
+
+    #[test]
+    fn stale_nodes_are_forgotten_and_the_local_record_is_reissued () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_awaiter = hopper.get_awaiter ();
+        let neighbor_key = Key::new (&b"neighbor"[..]);
+        let ghost_signer = make_signer ();
+        let ghost_key = ghost_signer.public_key ();
+        let config = NeighborhoodConfig {
+            stale_node_window_ms: 200,
+            ..direct_config (vec! ((neighbor_key.clone (), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234)))))
+        };
+        let mut ghost_gossip = gossip_package (vec! (signed_record (&ghost_signer, Some (&NodeAddr::new (&IpAddr::from_str ("6.6.6.7").unwrap(), &vec! (1234))), 1)));
+        ghost_gossip.neighbor_addr = SocketAddr::from_str ("6.6.6.7:5678").unwrap ();
+        thread::spawn (move || {
+            let system = System::new ("stale_nodes_are_forgotten_and_the_local_record_is_reissued");
+            let subject = Neighborhood::new (cryptde, config);
+            let peer_actors = make_peer_actors_from (None, None, Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            addr.try_send (ghost_gossip).unwrap ();
+
+            system.run ();
+        });
+        TestLogHandler::new ().await_log_containing ("Forgot 1 Nodes not heard from in 200ms", 2000);
+        hopper_awaiter.await_message_count (3);
+        thread::sleep (Duration::from_millis (50));
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        let initial_gossip = gossip_in (hopper_recording.get_record::<IncipientCoresPackage> (0));
+        let latest_gossip = gossip_in (hopper_recording.get_record::<IncipientCoresPackage> (hopper_recording.len () - 1));
+        let local_version = |gossip: &Gossip| gossip.node_records.iter ().find (|record| record.public_key == cryptde.public_key ()).unwrap ().version;
+        assert_eq! (local_version (&latest_gossip) > local_version (&initial_gossip), true);
+        assert_eq! (latest_gossip.node_records.iter ().any (|record| record.public_key == ghost_key), false);
+    }
+
+    #[test]
+    fn drops_and_penalizes_gossip_floods () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let system = System::new ("drops_and_penalizes_gossip_floods");
+        let subject = Neighborhood::new (cryptde, direct_config (vec! ()));
+        let addr: Addr<Syn, Neighborhood> = subject.start ();
+        let from = |package: ExpiredNeighborhoodPackage, neighbor_addr: &str| ExpiredNeighborhoodPackage {
+            neighbor_addr: SocketAddr::from_str (neighbor_addr).unwrap (),
+            ..package
+        };
+        for _ in 0..(MAX_GOSSIP_PER_WINDOW + 1) {
+            addr.try_send (from (gossip_package (vec! ()), "4.4.4.4:5678")).unwrap ();
+        }
+        let record = NodeRecord::new (&Key::new (&b"node"[..]), None, 1);
+        addr.try_send (from (gossip_package (vec! (record; MAX_GOSSIP_NODE_RECORDS + 1)), "4.4.4.6:5678")).unwrap ();
+
+        let future = addr.recipient::<NodeQueryMessage> ().send (NodeQueryMessage::PublicKey (Key::new (&b"node"[..])));
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        assert_eq! (future.wait ().unwrap (), None);
+        let tlh = TestLogHandler::new ();
+        tlh.exists_log_containing ("Dropped Gossip from neighbor at 4.4.4.4:5678: more than 20 in 10000ms");
+        tlh.exists_log_containing ("Neighbor at 4.4.4.4 reported for GossipFlood");
+        tlh.exists_log_containing ("Dropped Gossip from neighbor at 4.4.4.6:5678 with 4097 Node records and 0 bans");
+    }
+
+    #[test]
+    fn a_new_public_ip_address_is_signed_into_the_local_record_and_gossiped_at_once () {
+        init_test_logging ();
+        let cryptde = cryptde ();
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_awaiter = hopper.get_awaiter ();
+        let config = NeighborhoodConfig {
+            local_ip_addr_opt: Some (IpAddr::from_str ("3.3.3.3").unwrap ()),
+            clandestine_ports: vec! (443, 80),
+            ..direct_config (vec! ((Key::new (&b"neighbor"[..]), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234)))))
+        };
+        thread::spawn (move || {
+            let system = System::new ("a_new_public_ip_address_is_signed_into_the_local_record_and_gossiped_at_once");
+            let subject = Neighborhood::new (cryptde, config);
+            let peer_actors = make_peer_actors_from (None, None, Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            addr.try_send (NewPublicIpMsg {ip_addr: IpAddr::from_str ("3.3.3.3").unwrap ()}).unwrap ();
+            addr.try_send (NewPublicIpMsg {ip_addr: IpAddr::from_str ("4.4.4.4").unwrap ()}).unwrap ();
+
+            system.run ();
+        });
+        hopper_awaiter.await_message_count (2);
+        thread::sleep (Duration::from_millis (100));
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        assert_eq! (hopper_recording.len (), 2);
+        let local_record_in = |index: usize| {
+            gossip_in (hopper_recording.get_record::<IncipientCoresPackage> (index)).node_records.into_iter ()
+                .find (|record| record.public_key == cryptde.public_key ()).unwrap ()
+        };
+        let before = local_record_in (0);
+        let after = local_record_in (1);
+        assert_eq! (before.node_addr_opt, Some (NodeAddr::new (&IpAddr::from_str ("3.3.3.3").unwrap (), &vec! (80, 443))));
+        assert_eq! (after.node_addr_opt, Some (NodeAddr::new (&IpAddr::from_str ("4.4.4.4").unwrap (), &vec! (80, 443))));
+        assert_eq! (after.version, before.version + 1);
+        assert_eq! (after.has_valid_signature (cryptde), true);
+        TestLogHandler::new ().exists_log_containing ("Public IP address changed from 3.3.3.3 to 4.4.4.4");
+    }
+
+    fn make_boxed_signer () -> Box<CryptDE> {
+        Box::new (CryptDENull::new ())
+    }
+
+    #[test]
+    fn a_rotated_key_goes_out_in_gossip_at_once_alongside_the_old_one_until_it_retires () {
+        init_test_logging ();
+        let rotating: &'static CryptDERotating = Box::leak (Box::new (CryptDERotating::new (Box::new (make_signer ()), make_boxed_signer)));
+        let old_key = rotating.public_key ();
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        let hopper_awaiter = hopper.get_awaiter ();
+        let config = direct_config (vec! ((Key::new (&b"neighbor"[..]), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap(), &vec! (1234)))));
+        thread::spawn (move || {
+            let system = System::new ("a_rotated_key_goes_out_in_gossip_at_once_alongside_the_old_one_until_it_retires");
+            let subject = Neighborhood::new (rotating, config);
+            let peer_actors = make_peer_actors_from (None, None, Some (hopper), None, None, None);
+            let addr: Addr<Syn, Neighborhood> = subject.start ();
+            addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            let (previous_public_key, endorsement) = rotating.rotate ();
+            addr.try_send (NewPublicKeyMsg {previous_public_key: previous_public_key.clone (), endorsement}).unwrap ();
+            addr.try_send (RetiredPublicKeyMsg {public_key: previous_public_key}).unwrap ();
+
+            system.run ();
+        });
+        hopper_awaiter.await_message_count (2);
+        let hopper_recording = hopper_recording_arc.lock ().unwrap ();
+        let gossip = gossip_in (hopper_recording.get_record::<IncipientCoresPackage> (1));
+        let new_record = gossip.node_records.iter ().find (|record| record.public_key == rotating.public_key ()).unwrap ();
+        assert_eq! (new_record.predecessor_opt.as_ref ().unwrap ().public_key, old_key);
+        assert_eq! (new_record.neighbors, vec! (Key::new (&b"neighbor"[..])));
+        assert_eq! (new_record.has_valid_signature (rotating), true);
+        assert_eq! (gossip.node_records.iter ().any (|record| record.public_key == old_key), true);
+        let tlh = TestLogHandler::new ();
+        tlh.await_log_containing (&format! ("Public key {} retired", to_string (&old_key.data)), 1000);
+        tlh.exists_log_containing (&format! ("Public key rotated from {} to {}", to_string (&old_key.data), to_string (&rotating.public_key ().data)));
+    }
 }
@@ -0,0 +1,819 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+use std::time::Instant;
+use serde_cbor;
+use sub_lib::accountant::DEFAULT_RATES;
+use sub_lib::accountant::RateSchedule;
+use sub_lib::cryptde::CryptData;
+use sub_lib::cryptde::CryptDE;
+use sub_lib::cryptde::Key;
+use sub_lib::cryptde::PlainData;
+use sub_lib::neighborhood::NodeDescriptor;
+use sub_lib::node_addr::NodeAddr;
+use sub_lib::wallet::Wallet;
+
+// How many stale-Node windows a forgotten Node stays forgotten, whatever Gossip says
+const TOMBSTONE_WINDOWS: u32 = 4;
+
+// What one Node says about itself. Only the Node a record describes can sign a new version of
+// it; everybody else just passes the latest version they've seen along, unaltered.
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NodeRecord {
+    pub public_key: Key,
+    pub node_addr_opt: Option<NodeAddr>,
+    pub neighbors: Vec<Key>,
+    pub version: u64,
+    // The Node uses routes but won't relay or exit for anybody else
+    #[serde (default)]
+    pub originate_only: bool,
+    // The key this Node used before it rotated to this one
+    #[serde (default)]
+    pub predecessor_opt: Option<Predecessor>,
+    // Where the Node wants to be paid for relaying and exiting
+    #[serde (default)]
+    pub earning_wallet_opt: Option<Wallet>,
+    // What the Node charges, if not the default rates
+    #[serde (default)]
+    pub rates_opt: Option<RateSchedule>,
+    // Where the Node's payments come from
+    #[serde (default)]
+    pub consuming_wallet_opt: Option<Wallet>,
+    pub signature: CryptData,
+}
+
+// A rotated-away-from public key, and its signature on the key that replaced it
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Predecessor {
+    pub public_key: Key,
+    pub endorsement: CryptData,
+}
+
+#[derive (Clone, Debug, PartialEq)]
+pub enum NodeRecordError {
+    InvalidSignature,
+}
+
+impl NodeRecord {
+    pub fn new (public_key: &Key, node_addr_opt: Option<&NodeAddr>, version: u64) -> NodeRecord {
+        NodeRecord {
+            public_key: public_key.clone (),
+            node_addr_opt: node_addr_opt.cloned (),
+            neighbors: vec! (),
+            version,
+            originate_only: false,
+            predecessor_opt: None,
+            earning_wallet_opt: None,
+            rates_opt: None,
+            consuming_wallet_opt: None,
+            signature: CryptData::new (&[]),
+        }
+    }
+
+    // Everything but the signature itself. The originate-only flag, predecessor, wallets and rates
+    // are left out unless they're set, so records signed before there were such things still verify.
+    // Signing the wallets and rates keeps anybody passing the record along from redirecting payments,
+    // claiming them, or misquoting prices.
+    pub fn signed_data (&self) -> PlainData {
+        let serialized = match (self.originate_only, &self.predecessor_opt) {
+            _ if self.consuming_wallet_opt.is_some () => serde_cbor::ser::to_vec (&(&self.public_key, &self.node_addr_opt, &self.neighbors, self.version, self.originate_only, &self.predecessor_opt, &self.earning_wallet_opt, &self.rates_opt, &self.consuming_wallet_opt)),
+            _ if self.rates_opt.is_some () => serde_cbor::ser::to_vec (&(&self.public_key, &self.node_addr_opt, &self.neighbors, self.version, self.originate_only, &self.predecessor_opt, &self.earning_wallet_opt, &self.rates_opt)),
+            _ if self.earning_wallet_opt.is_some () => serde_cbor::ser::to_vec (&(&self.public_key, &self.node_addr_opt, &self.neighbors, self.version, self.originate_only, &self.predecessor_opt, &self.earning_wallet_opt)),
+            (originate_only, &Some (ref predecessor)) => serde_cbor::ser::to_vec (&(&self.public_key, &self.node_addr_opt, &self.neighbors, self.version, originate_only, predecessor)),
+            (true, &None) => serde_cbor::ser::to_vec (&(&self.public_key, &self.node_addr_opt, &self.neighbors, self.version, true)),
+            (false, &None) => serde_cbor::ser::to_vec (&(&self.public_key, &self.node_addr_opt, &self.neighbors, self.version)),
+        };
+        PlainData::new (&serialized.expect ("Serialization failure")[..])
+    }
+
+    pub fn sign (&mut self, cryptde: &CryptDE) {
+        self.signature = cryptde.sign (&self.signed_data ()).expect ("Couldn't sign NodeRecord");
+    }
+
+    // A record that names a predecessor must also carry the predecessor's endorsement of its key
+    pub fn has_valid_signature (&self, cryptde: &CryptDE) -> bool {
+        cryptde.verify_signature (&self.signed_data (), &self.signature, &self.public_key) && match self.predecessor_opt {
+            None => true,
+            Some (ref predecessor) => cryptde.verify_signature (&PlainData::new (&self.public_key.data[..]), &predecessor.endorsement, &predecessor.public_key)
+        }
+    }
+}
+
+pub struct NeighborhoodDatabase {
+    cryptde: &'static CryptDE,
+    root_key: Key,
+    records: HashMap<Key, NodeRecord>,
+    // Addresses we were given out of band, for neighbors that haven't told us (or don't know) their own
+    known_addrs: HashMap<Key, NodeAddr>,
+    // When each Node other than the root last issued a new record or spoke to us directly
+    last_seen: HashMap<Key, Instant>,
+    // The version each forgotten Node was at, and when it was forgotten, so Gossip from neighbors
+    // that haven't forgotten it yet can't bring it back
+    tombstones: HashMap<Key, (u64, Instant)>,
+    // Our own record under the key we're rotating away from, gossiped until that key is retired
+    retiring_root_opt: Option<NodeRecord>,
+    // Keys Nodes have rotated away from, and the keys that replaced them
+    successors: HashMap<Key, Key>,
+}
+
+impl NeighborhoodDatabase {
+    pub fn new (node_addr_opt: Option<&NodeAddr>, version: u64, cryptde: &'static CryptDE) -> NeighborhoodDatabase {
+        let root_key = cryptde.public_key ();
+        let mut root = NodeRecord::new (&root_key, node_addr_opt, version);
+        root.sign (cryptde);
+        let mut records = HashMap::new ();
+        records.insert (root_key.clone (), root);
+        NeighborhoodDatabase {cryptde, root_key, records, known_addrs: HashMap::new (), last_seen: HashMap::new (),
+            tombstones: HashMap::new (), retiring_root_opt: None, successors: HashMap::new ()}
+    }
+
+    pub fn root (&self) -> &NodeRecord {
+        self.records.get (&self.root_key).expect ("Root record disappeared")
+    }
+
+    pub fn node_by_key (&self, public_key: &Key) -> Option<&NodeRecord> {
+        self.records.get (public_key)
+    }
+
+    pub fn node_addr_of (&self, public_key: &Key) -> Option<NodeAddr> {
+        self.records.get (public_key)
+            .and_then (|record| record.node_addr_opt.as_ref ())
+            .or_else (|| self.known_addrs.get (public_key))
+            .cloned ()
+    }
+
+    pub fn descriptor_by_key (&self, public_key: &Key) -> Option<NodeDescriptor> {
+        if !self.records.contains_key (public_key) && !self.known_addrs.contains_key (public_key) {
+            return None
+        }
+        Some (NodeDescriptor::new (public_key.clone (), self.node_addr_of (public_key)))
+    }
+
+    pub fn descriptor_by_ip (&self, ip_addr: &IpAddr) -> Option<NodeDescriptor> {
+        self.known_keys ().into_iter ()
+            .find (|key| match self.node_addr_of (key) {
+                None => false,
+                Some (node_addr) => &node_addr.ip_addr () == ip_addr
+            })
+            .and_then (|key| self.descriptor_by_key (key))
+    }
+
+    // Every Node known to be at an address; behind NAT, there can be several
+    pub fn keys_by_ip (&self, ip_addr: &IpAddr) -> Vec<&Key> {
+        self.known_keys ().into_iter ()
+            .filter (|key| match self.node_addr_of (key) {
+                None => false,
+                Some (node_addr) => &node_addr.ip_addr () == ip_addr
+            })
+            .collect ()
+    }
+
+    // Signed records only, so everything here can be gossiped as is
+    pub fn records (&self) -> Vec<&NodeRecord> {
+        let mut records: Vec<&NodeRecord> = self.records.values ().collect ();
+        records.sort_by (|a, b| a.public_key.data.cmp (&b.public_key.data));
+        records
+    }
+
+    // What neighbors should hear: the signed records, plus our record under a retiring key
+    pub fn gossip_records (&self) -> Vec<&NodeRecord> {
+        let mut records = self.records ();
+        records.extend (self.retiring_root_opt.iter ());
+        records
+    }
+
+    // Nodes other than the root whose addresses we know, in a stable order
+    pub fn reachable_keys (&self) -> Vec<&Key> {
+        self.known_keys ().into_iter ()
+            .filter (|key| (*key != &self.root_key) && self.node_addr_of (key).is_some ())
+            .collect ()
+    }
+
+    // Adds a direct neighbor we were told about out of band
+    pub fn add_neighbor (&mut self, public_key: &Key, node_addr: &NodeAddr) {
+        if public_key == &self.root_key {return}
+        self.known_addrs.entry (public_key.clone ()).or_insert_with (|| node_addr.clone ());
+        let cryptde = self.cryptde;
+        let root = self.records.get_mut (&self.root_key).expect ("Root record disappeared");
+        if !root.neighbors.contains (public_key) {
+            root.neighbors.push (public_key.clone ());
+            root.neighbors.sort_by (|a, b| a.data.cmp (&b.data));
+            root.version += 1;
+            root.sign (cryptde);
+        }
+    }
+
+    pub fn remove_neighbor (&mut self, public_key: &Key) {
+        let cryptde = self.cryptde;
+        let root = self.records.get_mut (&self.root_key).expect ("Root record disappeared");
+        if root.neighbors.contains (public_key) {
+            root.neighbors.retain (|key| key != public_key);
+            root.version += 1;
+            root.sign (cryptde);
+        }
+    }
+
+    // Returns true if the database changed. Nobody else gets to tell us about ourselves, and a
+    // record can only be replaced by a newer version of itself signed by the Node it describes.
+    // A record under a key its Node has rotated away from is old news, and so is a record no newer
+    // than the one a forgotten Node was forgotten at.
+    pub fn merge (&mut self, incoming: NodeRecord) -> Result<bool, NodeRecordError> {
+        if !incoming.has_valid_signature (self.cryptde) {return Err (NodeRecordError::InvalidSignature)}
+        if (incoming.public_key == self.root_key) || self.is_retiring_root (&incoming.public_key) {return Ok (false)}
+        if self.successors.contains_key (&incoming.public_key) {return Ok (false)}
+        if let Some (existing) = self.records.get (&incoming.public_key) {
+            if existing.version >= incoming.version {return Ok (false)}
+        }
+        if let Some (&(pruned_version, _)) = self.tombstones.get (&incoming.public_key) {
+            if pruned_version >= incoming.version {return Ok (false)}
+        }
+        self.tombstones.remove (&incoming.public_key);
+        if let Some (ref predecessor) = incoming.predecessor_opt {
+            self.succeed (&predecessor.public_key, &incoming.public_key);
+        }
+        self.last_seen.insert (incoming.public_key.clone (), Instant::now ());
+        self.records.insert (incoming.public_key.clone (), incoming);
+        Ok (true)
+    }
+
+    pub fn successor_of (&self, public_key: &Key) -> Option<&Key> {
+        self.successors.get (public_key)
+    }
+
+    // Moves what we know about a Node from its old key to its new one. If it was our neighbor
+    // under the old key, it's our neighbor under the new one.
+    fn succeed (&mut self, predecessor_key: &Key, successor_key: &Key) {
+        self.successors.insert (predecessor_key.clone (), successor_key.clone ());
+        self.records.remove (predecessor_key);
+        self.last_seen.remove (predecessor_key);
+        if let Some (node_addr) = self.known_addrs.remove (predecessor_key) {
+            self.known_addrs.entry (successor_key.clone ()).or_insert (node_addr);
+        }
+        let cryptde = self.cryptde;
+        let root = self.records.get_mut (&self.root_key).expect ("Root record disappeared");
+        if root.neighbors.contains (predecessor_key) {
+            root.neighbors.retain (|key| key != predecessor_key);
+            if !root.neighbors.contains (successor_key) {
+                root.neighbors.push (successor_key.clone ());
+                root.neighbors.sort_by (|a, b| a.data.cmp (&b.data));
+            }
+            root.version += 1;
+            root.sign (cryptde);
+        }
+    }
+
+    // Reissues the root record under the CryptDE's new public key, naming the old one as its
+    // predecessor. The old record keeps going out in Gossip until the old key is retired.
+    // Returns false if the key hasn't changed.
+    pub fn rekey_root (&mut self, predecessor_key: &Key, endorsement: &CryptData) -> bool {
+        let successor_key = self.cryptde.public_key ();
+        if successor_key == self.root_key {return false}
+        let old_root = self.records.remove (&self.root_key).expect ("Root record disappeared");
+        let mut root = old_root.clone ();
+        root.public_key = successor_key.clone ();
+        root.version += 1;
+        root.predecessor_opt = Some (Predecessor {public_key: predecessor_key.clone (), endorsement: endorsement.clone ()});
+        root.sign (self.cryptde);
+        self.records.insert (successor_key.clone (), root);
+        self.retiring_root_opt = Some (old_root);
+        self.root_key = successor_key;
+        true
+    }
+
+    // Stops gossiping our record under the retired key; returns false if there wasn't one
+    pub fn retire_root_predecessor (&mut self) -> bool {
+        self.retiring_root_opt.take ().is_some ()
+    }
+
+    fn is_retiring_root (&self, public_key: &Key) -> bool {
+        self.retiring_root_opt.as_ref ().map (|record| &record.public_key == public_key).unwrap_or (false)
+    }
+
+    pub fn last_seen (&self, public_key: &Key) -> Option<Instant> {
+        self.last_seen.get (public_key).cloned ()
+    }
+
+    pub fn touch (&mut self, public_key: &Key, now: Instant) {
+        if self.records.contains_key (public_key) && (public_key != &self.root_key) {
+            self.last_seen.insert (public_key.clone (), now);
+        }
+    }
+
+    // Returns false if the root record already had this address
+    pub fn relocate_root (&mut self, node_addr: &NodeAddr) -> bool {
+        let cryptde = self.cryptde;
+        let root = self.records.get_mut (&self.root_key).expect ("Root record disappeared");
+        if root.node_addr_opt.as_ref () == Some (node_addr) {return false}
+        root.node_addr_opt = Some (node_addr.clone ());
+        root.version += 1;
+        root.sign (cryptde);
+        true
+    }
+
+    // Returns false if the root record already said so
+    pub fn set_originate_only (&mut self, originate_only: bool) -> bool {
+        let cryptde = self.cryptde;
+        let root = self.records.get_mut (&self.root_key).expect ("Root record disappeared");
+        if root.originate_only == originate_only {return false}
+        root.originate_only = originate_only;
+        root.version += 1;
+        root.sign (cryptde);
+        true
+    }
+
+    pub fn is_originate_only (&self, public_key: &Key) -> bool {
+        self.records.get (public_key).map (|record| record.originate_only).unwrap_or (false)
+    }
+
+    // Returns false if the root record already said so
+    pub fn set_earning_wallet (&mut self, earning_wallet_opt: Option<Wallet>) -> bool {
+        let cryptde = self.cryptde;
+        let root = self.records.get_mut (&self.root_key).expect ("Root record disappeared");
+        if root.earning_wallet_opt == earning_wallet_opt {return false}
+        root.earning_wallet_opt = earning_wallet_opt;
+        root.version += 1;
+        root.sign (cryptde);
+        true
+    }
+
+    pub fn earning_wallet_of (&self, public_key: &Key) -> Option<&Wallet> {
+        self.records.get (public_key).and_then (|record| record.earning_wallet_opt.as_ref ())
+    }
+
+    // Returns false if the root record already said so
+    pub fn set_consuming_wallet (&mut self, consuming_wallet_opt: Option<Wallet>) -> bool {
+        let cryptde = self.cryptde;
+        let root = self.records.get_mut (&self.root_key).expect ("Root record disappeared");
+        if root.consuming_wallet_opt == consuming_wallet_opt {return false}
+        root.consuming_wallet_opt = consuming_wallet_opt;
+        root.version += 1;
+        root.sign (cryptde);
+        true
+    }
+
+    pub fn consuming_wallet_of (&self, public_key: &Key) -> Option<&Wallet> {
+        self.records.get (public_key).and_then (|record| record.consuming_wallet_opt.as_ref ())
+    }
+
+    // Returns false if the root record already said so. The default rates go unsaid.
+    pub fn set_rates (&mut self, rates: RateSchedule) -> bool {
+        let rates_opt = if rates == DEFAULT_RATES {None} else {Some (rates)};
+        let cryptde = self.cryptde;
+        let root = self.records.get_mut (&self.root_key).expect ("Root record disappeared");
+        if root.rates_opt == rates_opt {return false}
+        root.rates_opt = rates_opt;
+        root.version += 1;
+        root.sign (cryptde);
+        true
+    }
+
+    // Nodes we know nothing about, or that don't say, are taken to charge the default rates
+    pub fn rates_of (&self, public_key: &Key) -> RateSchedule {
+        self.records.get (public_key).and_then (|record| record.rates_opt).unwrap_or (DEFAULT_RATES)
+    }
+
+    // Issues a new version of the root record with nothing changed but the version, so the rest
+    // of the network knows we're still here
+    pub fn refresh_root (&mut self) {
+        let cryptde = self.cryptde;
+        let root = self.records.get_mut (&self.root_key).expect ("Root record disappeared");
+        root.version += 1;
+        root.sign (cryptde);
+    }
+
+    // Forgets Nodes nobody has heard from within the window; direct neighbors are never
+    // forgotten. A forgotten Node is remembered as forgotten for a few windows more: long enough
+    // for every other Node to forget it too, since it stopped issuing new records as long ago for
+    // them as for us. Returns the keys of the forgotten Nodes.
+    pub fn prune (&mut self, now: Instant, window: Duration) -> Vec<Key> {
+        self.tombstones.retain (|_, &mut (_, pruned_at)| (pruned_at > now) || (now.duration_since (pruned_at) < window * TOMBSTONE_WINDOWS));
+        let mut stale: Vec<Key> = {
+            let neighbors = &self.root ().neighbors;
+            self.last_seen.iter ()
+                .filter (|&(key, last_seen)| !neighbors.contains (key)
+                    && (*last_seen <= now) && (now.duration_since (*last_seen) >= window))
+                .map (|(key, _)| key.clone ())
+                .collect ()
+        };
+        stale.sort_by (|a, b| a.data.cmp (&b.data));
+        for key in stale.iter () {
+            if let Some (record) = self.records.remove (key) {
+                self.tombstones.insert (key.clone (), (record.version, now));
+            }
+            self.last_seen.remove (key);
+        }
+        stale
+    }
+
+    // Nodes we have records for or addresses of, the root included, in a stable order
+    pub fn known_keys (&self) -> Vec<&Key> {
+        let mut keys: Vec<&Key> = self.records.keys ().collect ();
+        keys.extend (self.known_addrs.keys ().filter (|key| !self.records.contains_key (*key)));
+        keys.sort_by (|a, b| a.data.cmp (&b.data));
+        keys
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use sub_lib::cryptde_null::CryptDENull;
+    use sub_lib::cryptde_rotating::CryptDERotating;
+    use test_utils::test_utils::cryptde;
+
+    fn node_addr (ip: &str) -> NodeAddr {
+        NodeAddr::new (&IpAddr::from_str (ip).unwrap (), &vec! (1234))
+    }
+
+    fn signed_record (signer: &CryptDENull, node_addr_opt: Option<&NodeAddr>, version: u64) -> NodeRecord {
+        let mut record = NodeRecord::new (&signer.public_key (), node_addr_opt, version);
+        record.sign (signer);
+        record
+    }
+
+    fn make_signer () -> CryptDENull {
+        let mut signer = CryptDENull::new ();
+        signer.generate_key_pair ();
+        signer
+    }
+
+    fn make_boxed_signer () -> Box<CryptDE> {
+        Box::new (CryptDENull::new ())
+    }
+
+    #[test]
+    fn the_root_record_is_signed_by_the_local_node () {
+        let subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+
+        assert_eq! (subject.root ().public_key, cryptde ().public_key ());
+        assert_eq! (subject.root ().has_valid_signature (cryptde ()), true);
+    }
+
+    #[test]
+    fn adding_a_neighbor_links_it_to_the_root_and_re_signs_the_root () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+
+        subject.add_neighbor (&Key::new (b"neighbor"), &node_addr ("1.2.3.4"));
+        subject.add_neighbor (&Key::new (b"neighbor"), &node_addr ("1.2.3.4"));
+
+        assert_eq! (subject.root ().neighbors, vec! (Key::new (b"neighbor")));
+        assert_eq! (subject.root ().version, 101);
+        assert_eq! (subject.root ().has_valid_signature (cryptde ()), true);
+        assert_eq! (subject.node_by_key (&Key::new (b"neighbor")), None);
+        assert_eq! (subject.descriptor_by_ip (&IpAddr::from_str ("1.2.3.4").unwrap ()),
+            Some (NodeDescriptor::new (Key::new (b"neighbor"), Some (node_addr ("1.2.3.4")))));
+        assert_eq! (subject.records ().len (), 1);
+    }
+
+    #[test]
+    fn keys_by_ip_finds_every_node_known_at_an_address () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+        subject.add_neighbor (&Key::new (b"behind NAT 1"), &node_addr ("1.2.3.4"));
+        subject.add_neighbor (&Key::new (b"behind NAT 2"), &node_addr ("1.2.3.4"));
+        subject.add_neighbor (&Key::new (b"elsewhere"), &node_addr ("2.3.4.5"));
+
+        assert_eq! (subject.keys_by_ip (&IpAddr::from_str ("1.2.3.4").unwrap ()), vec! (&Key::new (b"behind NAT 1"), &Key::new (b"behind NAT 2")));
+        assert_eq! (subject.keys_by_ip (&IpAddr::from_str ("2.3.4.5").unwrap ()), vec! (&Key::new (b"elsewhere")));
+        assert_eq! (subject.keys_by_ip (&IpAddr::from_str ("3.4.5.6").unwrap ()), Vec::<&Key>::new ());
+    }
+
+    #[test]
+    fn removing_a_neighbor_unlinks_it_from_the_root_and_re_signs_the_root () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+        subject.add_neighbor (&Key::new (b"neighbor"), &node_addr ("1.2.3.4"));
+
+        subject.remove_neighbor (&Key::new (b"neighbor"));
+        subject.remove_neighbor (&Key::new (b"neighbor"));
+
+        assert_eq! (subject.root ().neighbors, Vec::<Key>::new ());
+        assert_eq! (subject.root ().version, 102);
+        assert_eq! (subject.root ().has_valid_signature (cryptde ()), true);
+    }
+
+    #[test]
+    fn merge_accepts_new_records_and_newer_versions_only () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+        let signer = make_signer ();
+
+        assert_eq! (subject.merge (signed_record (&signer, Some (&node_addr ("2.3.4.5")), 5)), Ok (true));
+        assert_eq! (subject.merge (signed_record (&signer, Some (&node_addr ("2.3.4.5")), 5)), Ok (false));
+        assert_eq! (subject.merge (signed_record (&signer, Some (&node_addr ("2.3.4.5")), 4)), Ok (false));
+        let newer = signed_record (&signer, Some (&node_addr ("3.4.5.6")), 6);
+        assert_eq! (subject.merge (newer.clone ()), Ok (true));
+        assert_eq! (subject.node_by_key (&signer.public_key ()), Some (&newer));
+    }
+
+    #[test]
+    fn merge_rejects_records_not_signed_by_the_node_they_describe () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+        let signer = make_signer ();
+        let mut forged = NodeRecord::new (&signer.public_key (), Some (&node_addr ("6.6.6.6")), 5);
+        forged.sign (&make_signer ());
+        let mut altered = signed_record (&signer, Some (&node_addr ("2.3.4.5")), 5);
+        altered.version = 6;
+
+        assert_eq! (subject.merge (forged), Err (NodeRecordError::InvalidSignature));
+        assert_eq! (subject.merge (altered), Err (NodeRecordError::InvalidSignature));
+        assert_eq! (subject.node_by_key (&signer.public_key ()), None);
+    }
+
+    #[test]
+    fn an_out_of_band_address_fills_in_for_a_record_without_one () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+        let signer = make_signer ();
+        subject.add_neighbor (&signer.public_key (), &node_addr ("1.2.3.4"));
+
+        subject.merge (signed_record (&signer, None, 1)).unwrap ();
+
+        assert_eq! (subject.node_by_key (&signer.public_key ()).unwrap ().node_addr_opt, None);
+        assert_eq! (subject.descriptor_by_key (&signer.public_key ()), Some (NodeDescriptor::new (signer.public_key (), Some (node_addr ("1.2.3.4")))));
+    }
+
+    #[test]
+    fn merge_never_replaces_the_root_record () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+        let root_before = subject.root ().clone ();
+        let mut impostor = NodeRecord::new (&cryptde ().public_key (), Some (&node_addr ("6.6.6.6")), 1000);
+        impostor.sign (cryptde ());
+
+        let result = subject.merge (impostor);
+
+        assert_eq! (result, Ok (false));
+        assert_eq! (subject.root (), &root_before);
+    }
+
+    #[test]
+    fn reachable_keys_skip_the_root_and_nodes_without_addresses () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+        let addressed = make_signer ();
+        let hidden = make_signer ();
+        subject.merge (signed_record (&addressed, Some (&node_addr ("2.3.4.5")), 1)).unwrap ();
+        subject.merge (signed_record (&hidden, None, 1)).unwrap ();
+        subject.add_neighbor (&Key::new (b"configured"), &node_addr ("3.4.5.6"));
+
+        let addressed_key = addressed.public_key ();
+        let configured_key = Key::new (b"configured");
+        let mut expected = vec! (&addressed_key, &configured_key);
+        expected.sort_by (|a, b| a.data.cmp (&b.data));
+        assert_eq! (subject.reachable_keys (), expected);
+    }
+
+    #[test]
+    fn nodes_not_heard_from_within_the_window_are_pruned_but_neighbors_are_kept () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+        let window = Duration::from_millis (1000);
+        let ghost_signer = make_signer ();
+        let live_signer = make_signer ();
+        let neighbor_signer = make_signer ();
+        subject.merge (signed_record (&ghost_signer, Some (&node_addr ("1.2.3.4")), 1)).unwrap ();
+        subject.merge (signed_record (&live_signer, Some (&node_addr ("2.3.4.5")), 1)).unwrap ();
+        subject.merge (signed_record (&neighbor_signer, Some (&node_addr ("3.4.5.6")), 1)).unwrap ();
+        subject.add_neighbor (&neighbor_signer.public_key (), &node_addr ("3.4.5.6"));
+        let later = Instant::now () + window;
+        subject.touch (&live_signer.public_key (), later);
+
+        let result = subject.prune (later, window);
+
+        assert_eq! (result, vec! (ghost_signer.public_key ()));
+        assert_eq! (subject.node_by_key (&ghost_signer.public_key ()), None);
+        assert_eq! (subject.node_by_key (&live_signer.public_key ()).is_some (), true);
+        assert_eq! (subject.node_by_key (&neighbor_signer.public_key ()).is_some (), true);
+        assert_eq! (subject.root ().has_valid_signature (cryptde ()), true);
+    }
+
+    #[test]
+    fn a_pruned_node_is_not_brought_back_by_its_old_record_but_is_by_a_newer_one () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+        let window = Duration::from_millis (1000);
+        let ghost_signer = make_signer ();
+        subject.merge (signed_record (&ghost_signer, Some (&node_addr ("1.2.3.4")), 1)).unwrap ();
+        subject.prune (Instant::now () + window, window);
+
+        let old_result = subject.merge (signed_record (&ghost_signer, Some (&node_addr ("1.2.3.4")), 1));
+
+        assert_eq! (old_result, Ok (false));
+        assert_eq! (subject.node_by_key (&ghost_signer.public_key ()), None);
+
+        let new_result = subject.merge (signed_record (&ghost_signer, Some (&node_addr ("1.2.3.4")), 2));
+
+        assert_eq! (new_result, Ok (true));
+        assert_eq! (subject.node_by_key (&ghost_signer.public_key ()).map (|record| record.version), Some (2));
+    }
+
+    #[test]
+    fn a_pruned_node_is_remembered_as_pruned_for_a_few_windows_only () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+        let window = Duration::from_millis (1000);
+        let ghost_signer = make_signer ();
+        subject.merge (signed_record (&ghost_signer, Some (&node_addr ("1.2.3.4")), 1)).unwrap ();
+        let pruned_at = Instant::now () + window;
+        subject.prune (pruned_at, window);
+        subject.prune (pruned_at + (window * (TOMBSTONE_WINDOWS - 1)), window);
+
+        assert_eq! (subject.merge (signed_record (&ghost_signer, Some (&node_addr ("1.2.3.4")), 1)), Ok (false));
+
+        subject.prune (pruned_at + (window * TOMBSTONE_WINDOWS), window);
+
+        assert_eq! (subject.merge (signed_record (&ghost_signer, Some (&node_addr ("1.2.3.4")), 1)), Ok (true));
+    }
+
+    #[test]
+    fn refreshing_the_root_issues_a_new_signed_version () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+
+        subject.refresh_root ();
+
+        assert_eq! (subject.root ().version, 101);
+        assert_eq! (subject.root ().has_valid_signature (cryptde ()), true);
+    }
+
+    #[test]
+    fn relocating_the_root_issues_a_new_signed_version_only_when_the_address_changes () {
+        let mut subject = NeighborhoodDatabase::new (Some (&node_addr ("1.2.3.4")), 100, cryptde ());
+
+        assert_eq! (subject.relocate_root (&node_addr ("1.2.3.4")), false);
+        assert_eq! (subject.relocate_root (&node_addr ("5.6.7.8")), true);
+
+        assert_eq! (subject.root ().node_addr_opt, Some (node_addr ("5.6.7.8")));
+        assert_eq! (subject.root ().version, 101);
+        assert_eq! (subject.root ().has_valid_signature (cryptde ()), true);
+    }
+
+    #[test]
+    fn originate_only_flag_is_covered_by_the_signature () {
+        let signer = make_signer ();
+        let mut record = signed_record (&signer, Some (&node_addr ("1.2.3.4")), 1);
+
+        record.originate_only = true;
+
+        assert_eq! (record.has_valid_signature (&signer), false);
+        record.sign (&signer);
+        assert_eq! (record.has_valid_signature (&signer), true);
+    }
+
+    #[test]
+    fn earning_wallet_is_covered_by_the_signature () {
+        let signer = make_signer ();
+        let mut record = signed_record (&signer, Some (&node_addr ("1.2.3.4")), 1);
+        record.earning_wallet_opt = Some (Wallet::new ("0x3535353535353535353535353535353535353535").unwrap ());
+        record.sign (&signer);
+
+        record.earning_wallet_opt = Some (Wallet::new ("0x5353535353535353535353535353535353535353").unwrap ());
+
+        assert_eq! (record.has_valid_signature (&signer), false);
+    }
+
+    #[test]
+    fn consuming_wallet_is_covered_by_the_signature () {
+        let signer = make_signer ();
+        let mut record = signed_record (&signer, Some (&node_addr ("1.2.3.4")), 1);
+        record.consuming_wallet_opt = Some (Wallet::new ("0x3535353535353535353535353535353535353535").unwrap ());
+        record.sign (&signer);
+        assert_eq! (record.has_valid_signature (&signer), true);
+
+        record.consuming_wallet_opt = Some (Wallet::new ("0x5353535353535353535353535353535353535353").unwrap ());
+
+        assert_eq! (record.has_valid_signature (&signer), false);
+    }
+
+    #[test]
+    fn rates_are_covered_by_the_signature () {
+        let signer = make_signer ();
+        let mut record = signed_record (&signer, Some (&node_addr ("1.2.3.4")), 1);
+        record.rates_opt = Some (RateSchedule {routing_byte_rate: 5, ..DEFAULT_RATES});
+        record.sign (&signer);
+        assert_eq! (record.has_valid_signature (&signer), true);
+
+        record.rates_opt = Some (RateSchedule {routing_byte_rate: 1, ..DEFAULT_RATES});
+
+        assert_eq! (record.has_valid_signature (&signer), false);
+    }
+
+    fn successor_record (predecessor: &CryptDENull, successor: &CryptDENull, node_addr_opt: Option<&NodeAddr>, version: u64) -> NodeRecord {
+        let mut record = NodeRecord::new (&successor.public_key (), node_addr_opt, version);
+        record.predecessor_opt = Some (Predecessor {
+            public_key: predecessor.public_key (),
+            endorsement: predecessor.sign (&PlainData::new (&successor.public_key ().data[..])).unwrap (),
+        });
+        record.sign (successor);
+        record
+    }
+
+    #[test]
+    fn a_node_that_rotates_its_key_stays_our_neighbor_under_the_new_one () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+        let old_signer = make_signer ();
+        let new_signer = make_signer ();
+        subject.add_neighbor (&old_signer.public_key (), &node_addr ("1.2.3.4"));
+        subject.merge (signed_record (&old_signer, Some (&node_addr ("1.2.3.4")), 1)).unwrap ();
+
+        let result = subject.merge (successor_record (&old_signer, &new_signer, Some (&node_addr ("1.2.3.4")), 2));
+
+        assert_eq! (result, Ok (true));
+        assert_eq! (subject.root ().neighbors, vec! (new_signer.public_key ()));
+        assert_eq! (subject.root ().version, 102);
+        assert_eq! (subject.root ().has_valid_signature (cryptde ()), true);
+        assert_eq! (subject.node_by_key (&old_signer.public_key ()), None);
+        assert_eq! (subject.node_by_key (&new_signer.public_key ()).is_some (), true);
+        assert_eq! (subject.successor_of (&old_signer.public_key ()), Some (&new_signer.public_key ()));
+        assert_eq! (subject.merge (signed_record (&old_signer, Some (&node_addr ("1.2.3.4")), 3)), Ok (false));
+        assert_eq! (subject.node_by_key (&old_signer.public_key ()), None);
+    }
+
+    #[test]
+    fn a_successor_without_its_predecessors_endorsement_is_a_forgery () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+        let victim = make_signer ();
+        let hijacker = make_signer ();
+        subject.add_neighbor (&victim.public_key (), &node_addr ("1.2.3.4"));
+        let mut hijack = successor_record (&hijacker, &hijacker, None, 1);
+        hijack.predecessor_opt.as_mut ().unwrap ().public_key = victim.public_key ();
+        hijack.sign (&hijacker);
+
+        let result = subject.merge (hijack);
+
+        assert_eq! (result, Err (NodeRecordError::InvalidSignature));
+        assert_eq! (subject.root ().neighbors, vec! (victim.public_key ()));
+    }
+
+    #[test]
+    fn rekeying_the_root_reissues_it_under_the_new_key_and_gossips_the_old_one_until_retired () {
+        let rotating: &'static CryptDERotating = Box::leak (Box::new (CryptDERotating::new (Box::new (make_signer ()), make_boxed_signer)));
+        let mut subject = NeighborhoodDatabase::new (Some (&node_addr ("1.2.3.4")), 100, rotating);
+        subject.add_neighbor (&Key::new (b"neighbor"), &node_addr ("2.3.4.5"));
+        let old_root = subject.root ().clone ();
+
+        assert_eq! (subject.rekey_root (&old_root.public_key, &CryptData::new (b"irrelevant")), false);
+        let (predecessor_key, endorsement) = rotating.rotate ();
+        assert_eq! (subject.rekey_root (&predecessor_key, &endorsement), true);
+
+        assert_eq! (subject.root ().public_key, rotating.public_key ());
+        assert_eq! (subject.root ().node_addr_opt, old_root.node_addr_opt);
+        assert_eq! (subject.root ().neighbors, old_root.neighbors);
+        assert_eq! (subject.root ().version, old_root.version + 1);
+        assert_eq! (subject.root ().predecessor_opt.as_ref ().unwrap ().public_key, old_root.public_key);
+        assert_eq! (subject.root ().has_valid_signature (rotating), true);
+        assert_eq! (subject.records (), vec! (subject.root ()));
+        assert_eq! (subject.gossip_records ().contains (&&old_root), true);
+        assert_eq! (subject.merge (old_root.clone ()), Ok (false));
+
+        assert_eq! (subject.retire_root_predecessor (), true);
+
+        assert_eq! (subject.gossip_records (), vec! (subject.root ()));
+        assert_eq! (subject.retire_root_predecessor (), false);
+    }
+
+    #[test]
+    fn marking_the_root_originate_only_issues_a_new_signed_version () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+
+        assert_eq! (subject.set_originate_only (false), false);
+        assert_eq! (subject.set_originate_only (true), true);
+
+        assert_eq! (subject.is_originate_only (&cryptde ().public_key ()), true);
+        assert_eq! (subject.root ().version, 101);
+        assert_eq! (subject.root ().has_valid_signature (cryptde ()), true);
+    }
+
+    #[test]
+    fn setting_the_root_s_earning_wallet_issues_a_new_signed_version () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+        let wallet = Wallet::new ("0x3535353535353535353535353535353535353535").unwrap ();
+
+        assert_eq! (subject.set_earning_wallet (None), false);
+        assert_eq! (subject.set_earning_wallet (Some (wallet.clone ())), true);
+
+        assert_eq! (subject.earning_wallet_of (&cryptde ().public_key ()), Some (&wallet));
+        assert_eq! (subject.root ().version, 101);
+        assert_eq! (subject.root ().has_valid_signature (cryptde ()), true);
+    }
+
+    #[test]
+    fn setting_the_root_s_consuming_wallet_issues_a_new_signed_version () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+        let wallet = Wallet::new ("0x3535353535353535353535353535353535353535").unwrap ();
+
+        assert_eq! (subject.set_consuming_wallet (None), false);
+        assert_eq! (subject.set_consuming_wallet (Some (wallet.clone ())), true);
+
+        assert_eq! (subject.consuming_wallet_of (&cryptde ().public_key ()), Some (&wallet));
+        assert_eq! (subject.root ().version, 101);
+        assert_eq! (subject.root ().has_valid_signature (cryptde ()), true);
+    }
+
+    #[test]
+    fn setting_the_root_s_rates_issues_a_new_signed_version_unless_they_re_the_defaults () {
+        let mut subject = NeighborhoodDatabase::new (None, 100, cryptde ());
+        let rates = RateSchedule {exit_byte_rate: 3, ..DEFAULT_RATES};
+
+        assert_eq! (subject.set_rates (DEFAULT_RATES), false);
+        assert_eq! (subject.set_rates (rates), true);
+
+        assert_eq! (subject.rates_of (&cryptde ().public_key ()), rates);
+        assert_eq! (subject.rates_of (&Key::new (b"stranger")), DEFAULT_RATES);
+        assert_eq! (subject.root ().version, 101);
+        assert_eq! (subject.root ().has_valid_signature (cryptde ()), true);
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::fs;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use serde_cbor;
+use ban::Ban;
+use neighborhood_database::NodeRecord;
+
+pub const NEIGHBORHOOD_DATABASE_FILENAME: &str = "neighborhood.db";
+
+// Everything the Neighborhood knows that's worth keeping across a restart
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NeighborhoodSnapshot {
+    pub node_records: Vec<NodeRecord>,
+    #[serde (default)]
+    pub bans: Vec<Ban>,
+}
+
+pub struct NeighborhoodStore {
+    path: PathBuf,
+}
+
+impl NeighborhoodStore {
+    pub fn new (data_directory: &Path) -> NeighborhoodStore {
+        NeighborhoodStore {
+            path: data_directory.join (NEIGHBORHOOD_DATABASE_FILENAME),
+        }
+    }
+
+    // Ok (None) means there's nothing saved yet
+    pub fn load (&self) -> Result<Option<NeighborhoodSnapshot>, String> {
+        let mut file = match File::open (&self.path) {
+            Ok (file) => file,
+            Err (ref e) if e.kind () == ErrorKind::NotFound => return Ok (None),
+            Err (e) => return Err (format! ("Couldn't open {:?}: {}", self.path, e))
+        };
+        let mut contents = vec! ();
+        if let Err (e) = file.read_to_end (&mut contents) {
+            return Err (format! ("Couldn't read {:?}: {}", self.path, e))
+        }
+        match serde_cbor::de::from_slice::<NeighborhoodSnapshot> (&contents[..]) {
+            Ok (snapshot) => Ok (Some (snapshot)),
+            Err (e) => Err (format! ("{:?} is not a neighborhood database: {:?}", self.path, e))
+        }
+    }
+
+    // Writes beside the old file and then replaces it, so a crash mid-save leaves the last good copy
+    pub fn save (&self, snapshot: &NeighborhoodSnapshot) -> Result<(), String> {
+        let contents = match serde_cbor::ser::to_vec (snapshot) {
+            Ok (contents) => contents,
+            Err (e) => return Err (format! ("Couldn't serialize neighborhood database: {:?}", e))
+        };
+        let temporary_path = self.path.with_extension ("tmp");
+        let written = File::create (&temporary_path)
+            .and_then (|mut file| file.write_all (&contents[..]).and_then (|_| file.sync_all ()))
+            .and_then (|_| fs::rename (&temporary_path, &self.path));
+        match written {
+            Ok (_) => Ok (()),
+            Err (e) => Err (format! ("Couldn't save {:?}: {}", self.path, e))
+        }
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+    use sub_lib::cryptde::Key;
+    use sub_lib::node_addr::NodeAddr;
+
+    fn make_data_directory (name: &str) -> PathBuf {
+        let data_directory = temp_dir ().join ("neighborhood_store").join (name);
+        fs::create_dir_all (&data_directory).unwrap ();
+        let _ = fs::remove_file (data_directory.join (NEIGHBORHOOD_DATABASE_FILENAME));
+        data_directory
+    }
+
+    #[test]
+    fn load_finds_nothing_before_the_first_save () {
+        let subject = NeighborhoodStore::new (&make_data_directory ("load_finds_nothing_before_the_first_save"));
+
+        assert_eq! (subject.load (), Ok (None));
+    }
+
+    #[test]
+    fn load_returns_what_was_saved () {
+        let data_directory = make_data_directory ("load_returns_what_was_saved");
+        let mut record = NodeRecord::new (&Key::new (b"somebody"), Some (&NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap (), &vec! (1234))), 7);
+        record.neighbors = vec! (Key::new (b"somebody else"));
+        let snapshot = NeighborhoodSnapshot {node_records: vec! (record), bans: vec! ()};
+
+        NeighborhoodStore::new (&data_directory).save (&snapshot).unwrap ();
+        let result = NeighborhoodStore::new (&data_directory).load ();
+
+        assert_eq! (result, Ok (Some (snapshot)));
+    }
+
+    #[test]
+    fn load_complains_about_a_file_that_is_not_a_snapshot () {
+        let data_directory = make_data_directory ("load_complains_about_a_file_that_is_not_a_snapshot");
+        File::create (data_directory.join (NEIGHBORHOOD_DATABASE_FILENAME)).unwrap ().write_all (b"garbage").unwrap ();
+
+        let result = NeighborhoodStore::new (&data_directory).load ();
+
+        assert_eq! (result.err ().unwrap ().contains ("is not a neighborhood database"), true);
+    }
+}
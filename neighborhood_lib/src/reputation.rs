@@ -0,0 +1,117 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::cmp;
+use std::collections::HashMap;
+use sub_lib::cryptde::Key;
+use sub_lib::neighborhood::NeighborMisbehavior;
+
+// Every Node starts out as trustworthy as it will ever be; it can only lose reputation by
+// misbehaving and win some of it back by behaving well afterward.
+pub const INITIAL_REPUTATION: i64 = 100;
+// At or below this, a Node is left out of routes
+pub const QUARANTINE_THRESHOLD: i64 = 50;
+pub const GOOD_BEHAVIOR_CREDIT: i64 = 1;
+
+pub fn penalty_for (misbehavior: NeighborMisbehavior) -> i64 {
+    match misbehavior {
+        NeighborMisbehavior::TamperedPackage => 20,
+        NeighborMisbehavior::ReplayedPackage => 10,
+        NeighborMisbehavior::MalformedPackage => 10,
+        NeighborMisbehavior::OversizedPackage => 20,
+        NeighborMisbehavior::FailedRelay => 5,
+        NeighborMisbehavior::ForgedGossip => 25,
+        NeighborMisbehavior::GossipFlood => 10,
+        NeighborMisbehavior::UnpaidDebt => 30,
+    }
+}
+
+// Kept by Node rather than by address, so Nodes that share an address behind NAT don't answer
+// for each other
+pub struct ReputationTable {
+    scores: HashMap<Key, i64>,
+}
+
+impl ReputationTable {
+    pub fn new () -> ReputationTable {
+        ReputationTable {
+            scores: HashMap::new (),
+        }
+    }
+
+    pub fn score (&self, public_key: &Key) -> i64 {
+        *self.scores.get (public_key).unwrap_or (&INITIAL_REPUTATION)
+    }
+
+    pub fn is_quarantined (&self, public_key: &Key) -> bool {
+        self.score (public_key) <= QUARANTINE_THRESHOLD
+    }
+
+    // Returns the new score
+    pub fn penalize (&mut self, public_key: &Key, misbehavior: NeighborMisbehavior) -> i64 {
+        let score = self.scores.entry (public_key.clone ()).or_insert (INITIAL_REPUTATION);
+        *score = cmp::max (0, *score - penalty_for (misbehavior));
+        *score
+    }
+
+    // Returns the new score
+    pub fn commend (&mut self, public_key: &Key) -> i64 {
+        let score = self.scores.entry (public_key.clone ()).or_insert (INITIAL_REPUTATION);
+        *score = cmp::min (INITIAL_REPUTATION, *score + GOOD_BEHAVIOR_CREDIT);
+        *score
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strangers_start_with_the_initial_reputation () {
+        let subject = ReputationTable::new ();
+
+        assert_eq! (subject.score (&Key::new (b"stranger")), INITIAL_REPUTATION);
+        assert_eq! (subject.is_quarantined (&Key::new (b"stranger")), false);
+    }
+
+    #[test]
+    fn misbehavior_costs_reputation_until_the_neighbor_is_quarantined () {
+        let mut subject = ReputationTable::new ();
+        let public_key = Key::new (b"misbehaver");
+
+        assert_eq! (subject.penalize (&public_key, NeighborMisbehavior::ForgedGossip), 75);
+        assert_eq! (subject.is_quarantined (&public_key), false);
+        assert_eq! (subject.penalize (&public_key, NeighborMisbehavior::ForgedGossip), 50);
+        assert_eq! (subject.is_quarantined (&public_key), true);
+        assert_eq! (subject.score (&Key::new (b"bystander")), INITIAL_REPUTATION);
+    }
+
+    #[test]
+    fn an_oversized_package_costs_more_than_a_malformed_one () {
+        let mut subject = ReputationTable::new ();
+        let public_key = Key::new (b"misbehaver");
+
+        assert_eq! (subject.penalize (&public_key, NeighborMisbehavior::OversizedPackage), 80);
+        assert_eq! (penalty_for (NeighborMisbehavior::OversizedPackage) > penalty_for (NeighborMisbehavior::MalformedPackage), true);
+    }
+
+    #[test]
+    fn reputation_never_drops_below_zero () {
+        let mut subject = ReputationTable::new ();
+        let public_key = Key::new (b"misbehaver");
+
+        (0..10).for_each (|_| {subject.penalize (&public_key, NeighborMisbehavior::UnpaidDebt);});
+
+        assert_eq! (subject.score (&public_key), 0);
+    }
+
+    #[test]
+    fn good_behavior_wins_back_reputation_but_never_more_than_the_initial_amount () {
+        let mut subject = ReputationTable::new ();
+        let public_key = Key::new (b"misbehaver");
+        subject.penalize (&public_key, NeighborMisbehavior::FailedRelay);
+
+        assert_eq! (subject.commend (&public_key), INITIAL_REPUTATION - 4);
+        (0..10).for_each (|_| {subject.commend (&public_key);});
+
+        assert_eq! (subject.score (&public_key), INITIAL_REPUTATION);
+    }
+}
@@ -1,28 +1,68 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::sync::mpsc;
 use std::thread;
+use std::time::Duration;
+use accountant_lib::accountant::Accountant;
+use accountant_lib::ledger::LedgerReal;
+use blockchain_bridge_lib::blockchain_bridge::BlockchainBridge;
+use blockchain_bridge_lib::blockchain_rpc::BlockchainRpc;
+use blockchain_bridge_lib::json_rpc::JsonRpcHttp;
+use blockchain_bridge_lib::price_oracle::PriceOracleHttps;
+use blockchain_bridge_lib::raw_transaction::Signer;
+use blockchain_bridge_lib::raw_transaction::TransactionSigner;
+use blockchain_bridge_lib::remote_signer::RemoteSigner;
 use actix::Actor;
 use actix::Addr;
+use actix::Arbiter;
+use actix::msgs;
 use actix::Recipient;
 use actix::Syn;
 use actix::System;
 use bootstrapper::BootstrapperConfig;
+use bootstrapper::NodeMode;
 use dispatcher::Dispatcher;
 use hopper_lib::hopper::Hopper;
+use key_rotator::KeyRotator;
 use neighborhood_lib::neighborhood::Neighborhood;
 use proxy_client_lib::proxy_client::ProxyClient;
 use proxy_server_lib::proxy_server::ProxyServer;
+use port_mapping::make_port_mapper;
+use port_mapping::PortMappingKeeper;
+use port_mapping::PORT_MAPPING_LIFETIME_SECS;
+use price_monitor::PriceMonitor;
+use price_monitor::PRICE_CHECK_INTERVAL_MS;
+use public_ip_discovery::public_ip_finder;
+use public_ip_monitor::HostnamePublicIpFinder;
+use public_ip_monitor::PublicIpFinder;
+use public_ip_monitor::PublicIpMonitor;
+use stream_handler_pool::ConnectToPeerMsg;
 use stream_handler_pool::PoolBindMessage;
 use stream_handler_pool::StreamHandlerPool;
 use stream_handler_pool::StreamHandlerPoolSubs;
+use ui_gateway::start_ui_listener;
+use ui_gateway::UiGateway;
+use ui_gateway::UiGatewayConfig;
+use ui_gateway::UiGatewaySubs;
+use sub_lib::accountant::AccountantConfig;
+use sub_lib::accountant::AccountantSubs;
+use sub_lib::blockchain_bridge::BlockchainBridgeConfig;
+use sub_lib::blockchain_bridge::BlockchainBridgeSubs;
+use sub_lib::blockchain_interface::BlockchainInterface;
+use sub_lib::blockchain_interface::BlockchainInterfaceBridge;
+use sub_lib::blockchain_interface::BlockchainInterfaceNull;
 use sub_lib::cryptde::CryptDE;
-use sub_lib::cryptde::Key;
-use sub_lib::cryptde_null::CryptDENull;
+use sub_lib::cryptde_rotating::CryptDERotating;
 use sub_lib::dispatcher::DispatcherSubs;
+use sub_lib::hopper::HopperConfig;
+use sub_lib::identity_store::IdentityStore;
+use sub_lib::link_handshake::RekeyPolicy;
 use sub_lib::hopper::HopperSubs;
+use sub_lib::neighborhood::NeighborhoodConfig;
 use sub_lib::neighborhood::NeighborhoodSubs;
-use sub_lib::node_addr::NodeAddr;
 use sub_lib::peer_actors::BindMessage;
 use sub_lib::peer_actors::PeerActors;
 use sub_lib::proxy_client::ProxyClientSubs;
@@ -30,29 +70,128 @@ use sub_lib::proxy_server::ProxyServerSubs;
 use bootstrapper;
 
 pub trait ActorSystemFactory: Send {
-    fn make_and_start_actors(&self, config: BootstrapperConfig) -> StreamHandlerPoolSubs;
+    fn make_and_start_actors(&self, config: BootstrapperConfig) -> (StreamHandlerPoolSubs, PeerActors, ActorSystemHandle);
+}
+
+// For taking the actor system down from outside it. Each actor saves whatever it needs to as it stops.
+pub struct ActorSystemHandle {
+    system: Addr<Syn, System>,
+    stopped_rx: mpsc::Receiver<()>,
+}
+
+impl ActorSystemHandle {
+    // stopped_rx hears from the actor system's thread once System::run has returned
+    pub fn new (system: Addr<Syn, System>, stopped_rx: mpsc::Receiver<()>) -> ActorSystemHandle {
+        ActorSystemHandle {system, stopped_rx}
+    }
+
+    pub fn stop (self, timeout_ms: u64) -> Result<(), String> {
+        if self.system.try_send (msgs::SystemExit (0)).is_err () {
+            return Err (String::from ("The actor system is already gone"))
+        }
+        self.stopped_rx.recv_timeout (Duration::from_millis (timeout_ms))
+            .map_err (|_| format! ("The actors didn't all stop within {}ms", timeout_ms))
+    }
 }
 
 pub struct ActorSystemFactoryReal {}
 
 impl ActorSystemFactory for ActorSystemFactoryReal {
     // THIS CODE HAS NO UNIT TESTS
-    fn make_and_start_actors(&self, config: BootstrapperConfig) -> StreamHandlerPoolSubs {
-        let cryptde: &'static CryptDENull = unsafe {
+    fn make_and_start_actors(&self, config: BootstrapperConfig) -> (StreamHandlerPoolSubs, PeerActors, ActorSystemHandle) {
+        let cryptde: &'static CryptDERotating = unsafe {
             bootstrapper::CRYPT_DE_OPT.as_ref().expect("Internal error")
         };
         let (tx, rx) = mpsc::channel();
+        let (stopped_tx, stopped_rx) = mpsc::channel();
 
         thread::spawn(move || {
             let system = System::new("SubstratumNode");
 
             // make all the actors
             let (dispatcher_subs, pool_bind_sub) = ActorSystemFactoryReal::make_and_start_dispatcher();
-            let proxy_server_subs = ActorSystemFactoryReal::make_and_start_proxy_server(cryptde);
-            let proxy_client_subs = ActorSystemFactoryReal::make_and_start_proxy_client(cryptde, config.dns_servers);
-            let hopper_subs = ActorSystemFactoryReal::make_and_start_hopper(cryptde);
-            let neighborhood_subs = ActorSystemFactoryReal::make_and_start_neighborhood(cryptde, config.neighbor_configs);
-            let stream_handler_pool_subs = ActorSystemFactoryReal::make_and_start_stream_handler_pool();
+            let zero_hop = config.mode == NodeMode::ZeroHop;
+            let proxy_server_subs = ActorSystemFactoryReal::make_and_start_proxy_server(cryptde, zero_hop);
+            let proxy_client_subs = ActorSystemFactoryReal::make_and_start_proxy_client(cryptde, config.dns_servers, config.max_response_size, zero_hop);
+            let originate_only = config.mode == NodeMode::OriginateOnly;
+            let hopper_subs = ActorSystemFactoryReal::make_and_start_hopper(cryptde, HopperConfig {
+                pad_packages: config.pad_packages,
+                cover_traffic_interval_ms: config.cover_traffic_interval_ms,
+                mix_delay: config.mix_delay,
+                compress_packages: config.compress_packages,
+                originate_only,
+            });
+            // Other Nodes are told to pay this one at its consuming wallet unless it names another
+            let signer_opt = ActorSystemFactoryReal::make_signer (&config.blockchain_bridge_config);
+            let consuming_wallet_opt = signer_opt.as_ref ().map (|signer| signer.wallet ().clone ());
+            let earning_wallet_opt = config.blockchain_bridge_config.earning_wallet_opt.clone ().or_else (|| consuming_wallet_opt.clone ());
+            let neighbor_configs = config.neighbor_configs.clone ();
+            let neighborhood_subs = ActorSystemFactoryReal::make_and_start_neighborhood(cryptde, NeighborhoodConfig {
+                neighbor_configs: config.neighbor_configs,
+                local_ip_addr_opt: config.ip_addr_opt,
+                clandestine_ports: config.clandestine_ports.clone (),
+                min_hops: config.min_hops,
+                max_hops: config.max_hops,
+                gossip_interval_ms: config.gossip_interval_ms,
+                heartbeat_interval_ms: config.heartbeat_interval_ms,
+                stale_node_window_ms: config.stale_node_window_ms,
+                data_directory_opt: config.data_directory_opt.clone (),
+                bans: config.bans,
+                geolocation_database_opt: config.geolocation_database_opt,
+                exit_location: config.exit_location,
+                route_diversity: config.route_diversity,
+                originate_only,
+                min_neighbors: config.min_neighbors,
+                target_neighbors: config.target_neighbors,
+                max_neighbors: config.max_neighbors,
+                earning_wallet_opt,
+                consuming_wallet_opt,
+                rates: config.accountant_config.rates,
+                max_route_cost_opt: config.max_route_cost_opt,
+            });
+            // A discovered address is as likely to move as a hostname's, so it's watched the same way
+            let public_ip_finder_opt: Option<Box<PublicIpFinder>> = match (&config.public_hostname_opt, &config.ip_discovery_opt) {
+                (&Some (ref hostname), _) => Some (Box::new (HostnamePublicIpFinder::new (hostname))),
+                (&None, &Some (ref ip_discovery)) if config.ip_addr_discovered => Some (public_ip_finder (ip_discovery)),
+                _ => None
+            };
+            if let Some (public_ip_finder) = public_ip_finder_opt {
+                PublicIpMonitor::new (public_ip_finder, config.ip_check_interval_ms,
+                    config.ip_addr_opt, neighborhood_subs.new_public_ip.clone ()).start ();
+            }
+            if config.key_rotation_interval_ms > 0 {
+                let identity_store_opt = match (&config.data_directory_opt, &config.identity_passphrase_opt) {
+                    (&Some (ref data_directory), &Some (ref passphrase)) => Some (IdentityStore::in_data_directory (data_directory, passphrase)),
+                    _ => None
+                };
+                let rotator = KeyRotator::new (cryptde, config.key_rotation_interval_ms, config.key_overlap_ms, identity_store_opt,
+                    neighborhood_subs.new_public_key.clone (), neighborhood_subs.retired_public_key.clone ());
+                let _: Addr<Syn, KeyRotator> = rotator.start ();
+            }
+            if let Some (protocol) = config.port_mapping_opt {
+                let keeper = PortMappingKeeper::new (make_port_mapper (protocol), config.clandestine_ports.clone (),
+                    PORT_MAPPING_LIFETIME_SECS);
+                let _: Addr<Syn, PortMappingKeeper> = keeper.start ();
+            }
+            let blockchain_bridge_subs_opt = ActorSystemFactoryReal::make_and_start_blockchain_bridge (config.blockchain_bridge_config.clone (), signer_opt);
+            let blockchain_interface: Box<BlockchainInterface> = match blockchain_bridge_subs_opt {
+                Some (ref blockchain_bridge_subs) => Box::new (BlockchainInterfaceBridge::new (blockchain_bridge_subs.request_transaction.clone (),
+                    blockchain_bridge_subs.check_channel.clone ())),
+                None => Box::new (BlockchainInterfaceNull::new ())
+            };
+            // Channels can't be opened without a contract to open them with
+            let accountant_config = match config.blockchain_bridge_config.channel_contract_opt {
+                Some (_) => config.accountant_config.clone (),
+                None => AccountantConfig {channel_deposit: 0, ..config.accountant_config.clone ()}
+            };
+            let accountant_subs = ActorSystemFactoryReal::make_and_start_accountant(cryptde, accountant_config, config.data_directory_opt.clone (),
+                blockchain_interface);
+            if let Some (ref url) = config.accountant_config.price_oracle_url_opt {
+                let oracle = PriceOracleHttps::new (url).unwrap_or_else (|e| panic! ("Invalid value for --price_oracle <url>: {}", e));
+                PriceMonitor::new (Box::new (oracle), PRICE_CHECK_INTERVAL_MS, accountant_subs.report_fiat_price.clone ()).start ();
+            }
+            let stream_handler_pool_subs = ActorSystemFactoryReal::make_and_start_stream_handler_pool(cryptde, config.clandestine_ports.clone (), config.rekey_policy);
+            let ui_gateway_subs = ActorSystemFactoryReal::make_and_start_ui_gateway(&config.ui_gateway_config);
 
             // collect all the subs
             let peer_actors = PeerActors {
@@ -60,7 +199,8 @@ impl ActorSystemFactory for ActorSystemFactoryReal {
                 proxy_server: proxy_server_subs,
                 proxy_client: proxy_client_subs,
                 hopper: hopper_subs,
-                neighborhood: neighborhood_subs
+                neighborhood: neighborhood_subs,
+                accountant: accountant_subs,
             };
 
             //bind all the actors
@@ -69,17 +209,32 @@ impl ActorSystemFactory for ActorSystemFactoryReal {
             peer_actors.proxy_client.bind.try_send(BindMessage { peer_actors: peer_actors.clone() }).expect("Proxy Client is dead");
             peer_actors.hopper.bind.try_send(BindMessage { peer_actors: peer_actors.clone() }).expect("Hopper is dead");
             peer_actors.neighborhood.bind.try_send(BindMessage { peer_actors: peer_actors.clone() }).expect("Neighborhood is dead");
+            peer_actors.accountant.bind.try_send(BindMessage { peer_actors: peer_actors.clone() }).expect("Accountant is dead");
+            if let Some (ref blockchain_bridge_subs) = blockchain_bridge_subs_opt {
+                blockchain_bridge_subs.bind.try_send(BindMessage { peer_actors: peer_actors.clone() }).expect("Blockchain Bridge is dead");
+            }
+            ui_gateway_subs.bind.try_send(BindMessage { peer_actors: peer_actors.clone() }).expect("UI Gateway is dead");
             stream_handler_pool_subs.bind.try_send(PoolBindMessage { dispatcher_subs: dispatcher_subs.clone(), stream_handler_pool_subs: stream_handler_pool_subs.clone() }).expect("Stream Handler Pool is dead");
             pool_bind_sub.try_send(PoolBindMessage { dispatcher_subs, stream_handler_pool_subs: stream_handler_pool_subs.clone() }).expect("Dispatcher is dead");
+            // The neighbors named at startup are known by key already, so this Node opens its links to them
+            for (peer_public_key, node_addr) in neighbor_configs {
+                let socket_addrs: Vec<SocketAddr> = node_addr.into ();
+                if let Some (socket_addr) = socket_addrs.first () {
+                    stream_handler_pool_subs.connect_to_peer.try_send (ConnectToPeerMsg {peer_public_key, socket_addr: *socket_addr})
+                        .expect ("Stream Handler Pool is dead");
+                }
+            }
 
-            //send out the stream handler pool subs (to be bound to listeners)
-            tx.send(stream_handler_pool_subs).ok();
+            //send out the stream handler pool subs (to be bound to listeners) and the peer actors (to be reconfigured)
+            tx.send((stream_handler_pool_subs, peer_actors, Arbiter::system())).ok();
 
-            //run the actor system
-            system.run()
+            //run the actor system, and say so once it's over
+            system.run();
+            stopped_tx.send(()).ok();
         });
 
-        rx.recv().expect("Internal error: actor-system init thread died before initializing StreamHandlerPool subscribers")
+        let (stream_handler_pool_subs, peer_actors, system) = rx.recv().expect("Internal error: actor-system init thread died before initializing StreamHandlerPool subscribers");
+        (stream_handler_pool_subs, peer_actors, ActorSystemHandle::new (system, stopped_rx))
     }
 }
 
@@ -90,32 +245,88 @@ impl ActorSystemFactoryReal {
         (Dispatcher::make_subs_from(&addr), addr.recipient::<PoolBindMessage> ())
     }
 
-    fn make_and_start_proxy_server(cryptde: &'static CryptDE) -> ProxyServerSubs {
-        let proxy_server = ProxyServer::new(cryptde);
+    fn make_and_start_proxy_server(cryptde: &'static CryptDE, zero_hop: bool) -> ProxyServerSubs {
+        let proxy_server = ProxyServer::new(cryptde, zero_hop);
         let addr: Addr<Syn, ProxyServer> = proxy_server.start();
         ProxyServer::make_subs_from(&addr)
     }
 
-    fn make_and_start_hopper(cryptde: &'static CryptDE) -> HopperSubs {
-        let hopper = Hopper::new(cryptde);
+    fn make_and_start_hopper(cryptde: &'static CryptDE, config: HopperConfig) -> HopperSubs {
+        let hopper = Hopper::new(cryptde, config);
         let addr: Addr<Syn, Hopper> = hopper.start();
         Hopper::make_subs_from(&addr)
     }
 
-    fn make_and_start_neighborhood(cryptde: &'static CryptDE, config: Vec<(Key, NodeAddr)>) -> NeighborhoodSubs {
+    fn make_and_start_neighborhood(cryptde: &'static CryptDE, config: NeighborhoodConfig) -> NeighborhoodSubs {
         let neighborhood = Neighborhood::new (cryptde, config);
         let addr: Addr<Syn, Neighborhood> = neighborhood.start ();
         Neighborhood::make_subs_from (&addr)
     }
 
-    fn make_and_start_stream_handler_pool() -> StreamHandlerPoolSubs {
-        let pool = StreamHandlerPool::new();
+    fn make_and_start_accountant(cryptde: &'static CryptDE, config: AccountantConfig, data_directory_opt: Option<PathBuf>,
+                                 blockchain_interface: Box<BlockchainInterface>) -> AccountantSubs {
+        let ledger_result = match data_directory_opt {
+            Some (ref data_directory) => LedgerReal::in_data_directory (data_directory),
+            None => LedgerReal::in_memory (),
+        };
+        let ledger = match ledger_result {
+            Ok (ledger) => ledger,
+            Err (e) => panic! ("Accountant can't keep accounts: {}", e)
+        };
+        let accountant = Accountant::new (cryptde, config, Box::new (ledger), blockchain_interface);
+        let addr: Addr<Syn, Accountant> = accountant.start ();
+        Accountant::make_subs_from (&addr)
+    }
+
+    // The consuming wallet's key is either here or behind a signing service, never both
+    fn make_signer (config: &BlockchainBridgeConfig) -> Option<Box<TransactionSigner>> {
+        let signer: Box<TransactionSigner> = match (&config.signing_service_url_opt, &config.signing_wallet_opt, &config.consuming_private_key_opt) {
+            (&Some (ref url), &Some (ref wallet), _) => {
+                let transport = JsonRpcHttp::new (url).unwrap_or_else (|e| panic! ("Invalid value for --signing_service_url <url>: {}", e));
+                Box::new (RemoteSigner::new (Box::new (transport), wallet.clone ()))
+            },
+            (_, _, &Some (ref private_key)) => Box::new (Signer::from_private_key (private_key)
+                .unwrap_or_else (|e| panic! ("Invalid private key for the consuming wallet: {}", e))),
+            _ => return None
+        };
+        Some (signer)
+    }
+
+    // Without a blockchain service, the Node stays off the blockchain altogether
+    fn make_and_start_blockchain_bridge(config: BlockchainBridgeConfig, signer_opt: Option<Box<TransactionSigner>>) -> Option<BlockchainBridgeSubs> {
+        let url = match config.blockchain_service_url_opt {
+            Some (ref url) => url.clone (),
+            None => return None
+        };
+        let transport = JsonRpcHttp::new (&url).unwrap_or_else (|e| panic! ("Invalid value for --blockchain_service_url <url>: {}", e));
+        let rpc = BlockchainRpc::new (Box::new (transport), config.chain_id, config.sub_contract_address.clone (), config.channel_contract_opt.clone ());
+        let blockchain_bridge = BlockchainBridge::new (config, rpc, signer_opt);
+        let addr: Addr<Syn, BlockchainBridge> = blockchain_bridge.start ();
+        Some (BlockchainBridge::make_subs_from (&addr))
+    }
+
+    fn make_and_start_stream_handler_pool(cryptde: &'static CryptDE, clandestine_ports: Vec<u16>, rekey_policy: RekeyPolicy) -> StreamHandlerPoolSubs {
+        let pool = StreamHandlerPool::new(cryptde, clandestine_ports, rekey_policy);
         let addr: Addr<Syn, StreamHandlerPool> = pool.start();
         StreamHandlerPool::make_subs_from(&addr)
     }
 
-    fn make_and_start_proxy_client(cryptde: &'static CryptDE, dns_servers: Vec<SocketAddr>) -> ProxyClientSubs {
-        let proxy_client = ProxyClient::new(cryptde, dns_servers);
+    // Only for UIs that have the token
+    fn make_and_start_ui_gateway(config: &UiGatewayConfig) -> UiGatewaySubs {
+        let token = config.token_opt.clone ().expect ("UI token not established - call initialize_as_root first");
+        let tls_opt = config.certificate_opt.as_ref ().map (|certificate| Arc::new (certificate.server_config ()
+            .unwrap_or_else (|e| panic! ("Could not serve the UI over TLS: {}", e))));
+        let ui_gateway = UiGateway::new();
+        let addr: Addr<Syn, UiGateway> = ui_gateway.start();
+        let subs = UiGateway::make_subs_from(&addr);
+        let listener = TcpListener::bind (SocketAddr::new (config.ui_bind_ip, config.ui_port))
+            .unwrap_or_else (|e| panic! ("Could not listen for the UI on port {} at {}: {}", config.ui_port, config.ui_bind_ip, e));
+        start_ui_listener (listener, tls_opt, token, subs.clone ());
+        subs
+    }
+
+    fn make_and_start_proxy_client(cryptde: &'static CryptDE, dns_servers: Vec<SocketAddr>, max_response_size: usize, zero_hop: bool) -> ProxyClientSubs {
+        let proxy_client = ProxyClient::new(cryptde, dns_servers, max_response_size, zero_hop);
         let addr: Addr<Syn, ProxyClient> = proxy_client.start();
         ProxyClient::make_subs_from(&addr)
     }
@@ -1,30 +1,175 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::cmp;
 use std::net::IpAddr;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::sync::Mutex;
 use std::thread;
+use std::time::Duration;
+use actix::Recipient;
+use actix::Syn;
 use actor_system_factory::ActorSystemFactory;
 use actor_system_factory::ActorSystemFactoryReal;
+use actor_system_factory::ActorSystemHandle;
 use base64;
+use blockchain_bridge_lib::hd_wallet::generate_mnemonic;
+use blockchain_bridge_lib::hd_wallet::private_key_from_mnemonic;
+use blockchain_bridge_lib::hd_wallet::DEFAULT_DERIVATION_PATH;
+use blockchain_bridge_lib::raw_transaction::Signer;
+use clandestine_port::ClandestinePortStore;
+use clandestine_port::random_clandestine_port;
 use configuration::Configuration;
 use listener_handler::ListenerHandler;
 use listener_handler::ListenerHandlerFactory;
 use listener_handler::ListenerHandlerFactoryReal;
+use port_mapping::PortMappingProtocol;
+use public_ip_discovery::public_ip_finder;
+use public_ip_discovery::IpDiscoveryConfig;
+use public_ip_discovery::PublicIpFinderFactory;
+use public_ip_discovery::PublicIpFinderFactoryReal;
+use public_ip_discovery::DEFAULT_HTTP_ECHO_HOST;
+use public_ip_discovery::DEFAULT_STUN_SERVER;
+use stream_handler_pool::StopStreamsMsg;
 use stream_handler_pool::StreamHandlerPoolSubs;
+use terminal::Terminal;
+use terminal::TerminalReal;
+use ui_gateway::UiGatewayConfig;
+use ui_gateway::DEFAULT_UI_PORT;
+use ui_gateway::default_ui_bind_ip;
+use ui_certificate::UiCertificate;
+use ui_certificate::UiCertificateStore;
+use ui_certificate::UiTls;
+use ui_token::random_ui_token;
+use ui_token::UiTokenStore;
+use sub_lib::accountant::AccountantConfig;
+use sub_lib::accountant::DEFAULT_CHANNEL_DEPOSIT;
+use sub_lib::accountant::DEFAULT_CHANNEL_LIFETIME_MS;
+use sub_lib::accountant::DEFAULT_DEBT_CEILING;
+use sub_lib::accountant::DEFAULT_CHANNEL_MIN_PAYMENTS;
+use sub_lib::accountant::DEFAULT_DELINQUENCY_CURVE;
+use sub_lib::accountant::DEFAULT_FIAT_THRESHOLDS;
+use sub_lib::accountant::DEFAULT_FREE_TIER;
+use sub_lib::accountant::DEFAULT_INVOICE_INTERVAL_MS;
+use sub_lib::accountant::DEFAULT_PAYABLE_SCAN_INTERVAL_MS;
+use sub_lib::accountant::DEFAULT_PAYMENT_AGE_THRESHOLD_MS;
+use sub_lib::accountant::DEFAULT_PAYMENT_RETRY_MS;
+use sub_lib::accountant::DEFAULT_PAYMENT_THRESHOLD;
+use sub_lib::accountant::DEFAULT_RATES;
+use sub_lib::accountant::DEFAULT_RECEIVABLE_SCAN_INTERVAL_MS;
+use sub_lib::accountant::FiatThresholds;
+use sub_lib::accountant::FreeTier;
+use sub_lib::accountant::RateSchedule;
+use sub_lib::blockchain_bridge::BlockchainBridgeConfig;
+use sub_lib::blockchain_bridge::DEFAULT_CONFIRMATIONS;
+use sub_lib::blockchain_bridge::DEFAULT_MAX_GAS_PRICE;
+use sub_lib::blockchain_bridge::DEFAULT_PAYMENT_WATCH_INTERVAL_MS;
+use sub_lib::blockchain_bridge::MAINNET_CHAIN_ID;
+use sub_lib::blockchain_bridge::SUB_CONTRACT_ADDRESS;
+use sub_lib::blockchain_bridge::WEI_PER_GWEI;
 use sub_lib::cryptde::Key;
+use sub_lib::hopper::MixDelay;
 use sub_lib::main_tools::StdStreams;
+use sub_lib::neighborhood::BanNodeMsg;
+use sub_lib::neighborhood::ExitLocation;
+use sub_lib::neighborhood::RouteCost;
+use sub_lib::neighborhood::RouteDiversity;
+use sub_lib::neighborhood::SetExitLocationMsg;
+use sub_lib::neighborhood::DEFAULT_ROUTE_DIVERSITY;
+use sub_lib::neighborhood::DEFAULT_IP_CHECK_INTERVAL_MS;
+use sub_lib::neighborhood::DEFAULT_GOSSIP_INTERVAL_MS;
+use sub_lib::neighborhood::DEFAULT_HEARTBEAT_INTERVAL_MS;
+use sub_lib::neighborhood::DEFAULT_STALE_NODE_WINDOW_MS;
+use sub_lib::neighborhood::DEFAULT_MAX_HOPS;
+use sub_lib::neighborhood::DEFAULT_MIN_HOPS;
+use sub_lib::neighborhood::DEFAULT_MIN_NEIGHBORS;
+use sub_lib::neighborhood::DEFAULT_TARGET_NEIGHBORS;
+use sub_lib::neighborhood::DEFAULT_MAX_NEIGHBORS;
 use sub_lib::node_addr::NodeAddr;
 use sub_lib::parameter_finder::ParameterFinder;
+use sub_lib::socket_server::Reloader;
 use sub_lib::socket_server::SocketServer;
+use sub_lib::socket_server::Stopper;
 use sub_lib::cryptde::CryptDE;
 use sub_lib::cryptde_null::CryptDENull;
-
-pub static mut CRYPT_DE_OPT: Option<CryptDENull> = None;
+use sub_lib::cryptde_real::CryptDEReal;
+use sub_lib::cryptde_rotating::CryptDERotating;
+use sub_lib::cryptde_rotating::DEFAULT_KEY_OVERLAP_MS;
+use sub_lib::identity_store::IdentityStore;
+use sub_lib::link_handshake::RekeyPolicy;
+use sub_lib::link_handshake::DEFAULT_REKEY_POLICY;
+use sub_lib::wallet_store::WalletStore;
+use sub_lib::wallet_store::WALLET_FILENAME;
+use sub_lib::wallet::Wallet;
+
+pub static mut CRYPT_DE_OPT: Option<CryptDERotating> = None;
+
+#[derive (Clone, Copy, Debug, PartialEq)]
+pub enum NodeMode {
+    // Originates, relays, and exits traffic
+    Standard,
+    // Uses the network without relaying or exiting for anybody else
+    OriginateOnly,
+    // Relays and exits for others, but has no DNS subversion or ProxyServer front end of its own
+    ServeOnly,
+    // ProxyServer hands requests straight to the local ProxyClient; for development and testing
+    ZeroHop,
+}
 
 #[derive (Clone)]
 pub struct BootstrapperConfig {
+    pub mode: NodeMode,
     pub dns_servers: Vec<SocketAddr>,
-    pub neighbor_configs: Vec<(Key, NodeAddr)>
+    pub neighbor_configs: Vec<(Key, NodeAddr)>,
+    pub ip_addr_opt: Option<IpAddr>,
+    pub ip_addr_discovered: bool,
+    // None when --ip_discovery is off, so nobody outside is asked for this Node's address
+    pub ip_discovery_opt: Option<IpDiscoveryConfig>,
+    pub public_hostname_opt: Option<String>,
+    pub ip_check_interval_ms: u64,
+    pub clandestine_ports: Vec<u16>,
+    pub clandestine_port_opt: Option<u16>,
+    pub port_mapping_opt: Option<PortMappingProtocol>,
+    pub max_response_size: usize,
+    pub min_hops: usize,
+    pub max_hops: usize,
+    pub min_neighbors: usize,
+    pub target_neighbors: usize,
+    pub max_neighbors: usize,
+    pub gossip_interval_ms: u64,
+    pub heartbeat_interval_ms: u64,
+    pub stale_node_window_ms: u64,
+    pub data_directory_opt: Option<PathBuf>,
+    // Asked for at startup, never given as a setting, where ps and shell history would show it
+    pub identity_passphrase_opt: Option<String>,
+    pub identity_backup_opt: Option<PathBuf>,
+    pub identity_restore_opt: Option<PathBuf>,
+    pub bans: Vec<BanNodeMsg>,
+    pub geolocation_database_opt: Option<PathBuf>,
+    pub exit_location: ExitLocation,
+    pub route_diversity: RouteDiversity,
+    pub max_route_cost_opt: Option<RouteCost>,
+    pub pad_packages: bool,
+    pub cover_traffic_interval_ms: u64,
+    pub mix_delay: MixDelay,
+    pub compress_packages: bool,
+    pub rekey_policy: RekeyPolicy,
+    pub null_cryptde: bool,
+    pub key_rotation_interval_ms: u64,
+    pub key_overlap_ms: u64,
+    pub accountant_config: AccountantConfig,
+    pub blockchain_bridge_config: BlockchainBridgeConfig,
+    pub ui_gateway_config: UiGatewayConfig,
+    // recovery phrase of a consuming wallet made up for this run, which the operator has to be shown
+    pub generated_mnemonic_opt: Option<String>,
+    // the recovery phrase of the consuming wallet is to be asked for at startup
+    pub recover_consuming_wallet: bool,
+    // the private key of the consuming wallet is to be asked for at startup
+    pub import_consuming_wallet: bool,
+    // where the consuming wallet lies under its recovery phrase
+    pub derivation_path: String,
 }
 
 // TODO: Consider splitting this into a piece that's meant for being root and a piece that's not.
@@ -34,7 +179,75 @@ pub struct Bootstrapper {
     actor_system_factory: Box<ActorSystemFactory>,
     #[allow (dead_code)]
     stream_handler_pool_subs: Option<StreamHandlerPoolSubs>,
+    public_ip_finder_factory: Box<PublicIpFinderFactory>,
+    terminal: Box<Terminal>,
     config: Option<BootstrapperConfig>,
+    reloader: BootstrapperReloader,
+    stopper: BootstrapperStopper,
+}
+
+// Of the Bootstrapper's settings, only the exit location can change while the Node runs. The
+// Neighborhood it goes to doesn't exist until serve_without_root has started the actors.
+#[derive (Clone)]
+pub struct BootstrapperReloader {
+    set_exit_location_opt: Arc<Mutex<Option<Recipient<Syn, SetExitLocationMsg>>>>,
+}
+
+impl Reloader for BootstrapperReloader {
+    fn reload (&self, args: &Vec<String>) -> Result<(), String> {
+        let exit_location = Bootstrapper::parse_exit_location (&ParameterFinder::new (args.clone ()));
+        match *self.set_exit_location_opt.lock ().expect ("Reloader poisoned") {
+            Some (ref set_exit_location) => set_exit_location.try_send (SetExitLocationMsg {exit_location})
+                .map_err (|_| String::from ("Neighborhood is dead")),
+            None => Err (String::from ("The Node hasn't finished starting up"))
+        }
+    }
+}
+
+impl BootstrapperReloader {
+    pub fn new () -> BootstrapperReloader {
+        BootstrapperReloader {set_exit_location_opt: Arc::new (Mutex::new (None))}
+    }
+}
+
+// How long each step of a shutdown may take before the next one goes ahead anyway
+const STOP_TIMEOUT_MS: u64 = 5000;
+
+// The streams are closed first, so nothing new arrives while the actors are on their way down. Like
+// the reloader, it has nothing to stop until serve_without_root has started the actors.
+#[derive (Clone)]
+pub struct BootstrapperStopper {
+    stop_streams_opt: Arc<Mutex<Option<Recipient<Syn, StopStreamsMsg>>>>,
+    actor_system_opt: Arc<Mutex<Option<ActorSystemHandle>>>,
+}
+
+impl Stopper for BootstrapperStopper {
+    fn stop (&self) -> Result<(), String> {
+        let streams_result = match *self.stop_streams_opt.lock ().expect ("Stopper poisoned") {
+            Some (ref stop_streams) => {
+                let (done_tx, done_rx) = mpsc::channel ();
+                stop_streams.try_send (StopStreamsMsg {done: done_tx})
+                    .map_err (|_| String::from ("Stream Handler Pool is dead"))
+                    .and_then (|_| done_rx.recv_timeout (Duration::from_millis (STOP_TIMEOUT_MS))
+                        .map_err (|_| format! ("The streams weren't all closed within {}ms", STOP_TIMEOUT_MS)))
+            },
+            None => Ok (())
+        };
+        let actors_result = match self.actor_system_opt.lock ().expect ("Stopper poisoned").take () {
+            Some (actor_system) => actor_system.stop (STOP_TIMEOUT_MS),
+            None => Ok (())
+        };
+        streams_result.and (actors_result)
+    }
+}
+
+impl BootstrapperStopper {
+    pub fn new () -> BootstrapperStopper {
+        BootstrapperStopper {
+            stop_streams_opt: Arc::new (Mutex::new (None)),
+            actor_system_opt: Arc::new (Mutex::new (None)),
+        }
+    }
 }
 
 impl SocketServer for Bootstrapper {
@@ -43,27 +256,62 @@ impl SocketServer for Bootstrapper {
     }
 
     fn initialize_as_root(&mut self, args: &Vec<String>, streams: &mut StdStreams) {
+        let mut config = Bootstrapper::parse_args (args);
         let mut configuration = Configuration::new ();
         configuration.establish (args);
+        // A zero-hop Node doesn't use the network, so other Nodes have no reason to reach it
+        if config.mode != NodeMode::ZeroHop {
+            let clandestine_port = Bootstrapper::establish_clandestine_port (&config);
+            configuration.add_clandestine_port (clandestine_port);
+            config.clandestine_ports = configuration.clandestine_ports ();
+        }
+        let ui_token = Bootstrapper::establish_ui_token (streams, &config);
+        config.ui_gateway_config.token_opt = Some (ui_token);
+        config.ui_gateway_config.certificate_opt = Bootstrapper::establish_ui_certificate (&config);
         self.listener_handlers = configuration.ports ().iter ().map (|port_ref| {
             let mut listener_handler =
                 self.listener_handler_factory.make ();
             let discriminator_factories = configuration.take_discriminator_factories_for (*port_ref);
-            match listener_handler.bind_port_and_discriminator_factories (*port_ref, discriminator_factories) {
+            let socket_addr = configuration.bind_address_for (*port_ref);
+            match listener_handler.bind_address_and_discriminator_factories (socket_addr, discriminator_factories) {
                 Ok(()) => (),
-                Err(e) => panic! ("Could not listen on port {}: {}", port_ref, e.to_string ())
+                Err(e) => panic! ("Could not listen on port {} at {}: {}", port_ref, socket_addr.ip (), e.to_string ())
             }
             listener_handler
         }).collect ();
-        self.config = Some(Bootstrapper::parse_args (args));
-        Bootstrapper::initialize_and_report_cryptde (streams);
+        if config.ip_addr_opt.is_none () {
+            let ip_addr_opt = match config.ip_discovery_opt {
+                Some (ref ip_discovery) => self.public_ip_finder_factory.make (ip_discovery).find_public_ip (),
+                None => None
+            };
+            match (ip_addr_opt, config.ip_discovery_opt.is_some ()) {
+                (Some (ip_addr), _) => {
+                    config.ip_addr_opt = Some (ip_addr);
+                    config.ip_addr_discovered = true;
+                },
+                (None, true) => writeln! (streams.stderr, "Couldn't discover this Node's public IP address; supply it with --ip").expect ("Internal error"),
+                (None, false) => writeln! (streams.stderr, "--ip_discovery is off; supply this Node's public IP address with --ip").expect ("Internal error"),
+            }
+        }
+        Bootstrapper::establish_new_consuming_wallet (streams, self.terminal.as_ref (), &mut config);
+        Bootstrapper::establish_consuming_wallet (streams, self.terminal.as_ref (), &mut config);
+        Bootstrapper::establish_identity_passphrase (streams, self.terminal.as_ref (), &mut config);
+        let cryptde = Bootstrapper::initialize_and_report_cryptde (streams, self.terminal.as_ref (), &config);
+        if let (Some (ip_addr), false) = (config.ip_addr_opt, config.clandestine_ports.is_empty ()) {
+            let descriptor = Bootstrapper::node_descriptor (&cryptde.public_key (), &NodeAddr::new (&ip_addr, &config.clandestine_ports));
+            writeln! (streams.stdout, "Substratum Node descriptor: {}", descriptor).expect ("Internal error");
+        }
+        self.config = Some(config);
     }
 
     fn serve_without_root(&mut self) {
-        let stream_handler_pool_subs =
+        let (stream_handler_pool_subs, peer_actors, actor_system) =
             self.actor_system_factory.make_and_start_actors(
                 self.config.as_ref().expect("Missing BootstrapperConfig - call initialize_as_root first").clone(),
             );
+        *self.reloader.set_exit_location_opt.lock ().expect ("Reloader poisoned") = Some (peer_actors.neighborhood.set_exit_location);
+        *self.stopper.stop_streams_opt.lock ().expect ("Stopper poisoned") = Some (stream_handler_pool_subs.stop_streams.clone ());
+        *self.stopper.actor_system_opt.lock ().expect ("Stopper poisoned") = Some (actor_system);
 
         while self.listener_handlers.len () > 0 {
             let mut listener_handler = self.listener_handlers.remove (0);
@@ -71,6 +319,14 @@ impl SocketServer for Bootstrapper {
             self.start_listener_thread (listener_handler);
         }
     }
+
+    fn reloader (&self) -> Option<Box<Reloader>> {
+        Some (Box::new (self.reloader.clone ()))
+    }
+
+    fn stopper (&self) -> Option<Box<Stopper>> {
+        Some (Box::new (self.stopper.clone ()))
+    }
 }
 
 impl Bootstrapper {
@@ -81,7 +337,11 @@ impl Bootstrapper {
 
             actor_system_factory: Box::new (ActorSystemFactoryReal {}),
             stream_handler_pool_subs: None,
+            public_ip_finder_factory: Box::new (PublicIpFinderFactoryReal::new ()),
+            terminal: Box::new (TerminalReal::new ()),
             config: None,
+            reloader: BootstrapperReloader::new (),
+            stopper: BootstrapperStopper::new (),
         }
     }
 
@@ -93,9 +353,74 @@ impl Bootstrapper {
 
     fn parse_args (args: &Vec<String>) -> BootstrapperConfig {
         let finder = ParameterFinder::new(args.clone ());
+        let (min_hops, max_hops) = Bootstrapper::parse_hop_range (&finder);
+        let (min_neighbors, target_neighbors, max_neighbors) = Bootstrapper::parse_neighbor_counts (&finder);
+        let (key_rotation_interval_ms, key_overlap_ms) = Bootstrapper::parse_key_rotation (&finder);
+        let generated_mnemonic_opt = Bootstrapper::parse_generate_consuming_wallet (&finder);
+        let recover_consuming_wallet = Bootstrapper::parse_recover_consuming_wallet (&finder);
+        let import_consuming_wallet = Bootstrapper::parse_import_consuming_wallet (&finder);
+        let blockchain_bridge_config = Bootstrapper::parse_blockchain_bridge_config (&finder);
+        Bootstrapper::check_consuming_wallet_sources (&blockchain_bridge_config, generated_mnemonic_opt.is_some (), recover_consuming_wallet, import_consuming_wallet);
+        Bootstrapper::check_clandestine_transport (&finder);
         BootstrapperConfig {
+            mode: Bootstrapper::parse_mode (&finder),
             dns_servers: Bootstrapper::parse_dns_servers (&finder),
             neighbor_configs: Bootstrapper::parse_neighbor_configs (&finder),
+            ip_addr_opt: Bootstrapper::parse_ip_addr (&finder),
+            ip_addr_discovered: false,
+            ip_discovery_opt: Bootstrapper::parse_ip_discovery (&finder),
+            public_hostname_opt: finder.find_value_for ("--public_hostname", "--public_hostname <hostname> that always resolves to this Node's public IP address"),
+            ip_check_interval_ms: Bootstrapper::parse_ip_check_interval (&finder),
+            clandestine_ports: vec! (),
+            clandestine_port_opt: Bootstrapper::parse_clandestine_port (&finder),
+            port_mapping_opt: Bootstrapper::parse_port_mapping (&finder),
+            max_response_size: Bootstrapper::parse_max_response_size (&finder),
+            min_hops,
+            max_hops,
+            min_neighbors,
+            target_neighbors,
+            max_neighbors,
+            gossip_interval_ms: Bootstrapper::parse_gossip_interval (&finder),
+            heartbeat_interval_ms: Bootstrapper::parse_heartbeat_interval (&finder),
+            stale_node_window_ms: Bootstrapper::parse_stale_node_window (&finder),
+            data_directory_opt: Bootstrapper::parse_data_directory (&finder),
+            identity_passphrase_opt: None,
+            identity_backup_opt: finder.find_value_for ("--backup_identity", "--backup_identity <path> where a copy of this Node's encrypted identity should be written").map (PathBuf::from),
+            identity_restore_opt: finder.find_value_for ("--restore_identity", "--restore_identity <path> of an identity backup to restore into the data directory").map (PathBuf::from),
+            bans: Bootstrapper::parse_bans (&finder),
+            geolocation_database_opt: Bootstrapper::parse_geolocation_database (&finder),
+            exit_location: Bootstrapper::parse_exit_location (&finder),
+            route_diversity: Bootstrapper::parse_route_diversity (&finder),
+            max_route_cost_opt: Bootstrapper::parse_max_route_cost (&finder),
+            pad_packages: Bootstrapper::parse_padding (&finder),
+            cover_traffic_interval_ms: Bootstrapper::parse_cover_traffic_interval (&finder),
+            mix_delay: Bootstrapper::parse_mix_delay (&finder),
+            compress_packages: Bootstrapper::parse_compression (&finder),
+            rekey_policy: Bootstrapper::parse_rekey_policy (&finder),
+            null_cryptde: Bootstrapper::parse_null_cryptde (&finder),
+            key_rotation_interval_ms,
+            key_overlap_ms,
+            accountant_config: Bootstrapper::parse_accountant_config (&finder),
+            blockchain_bridge_config,
+            ui_gateway_config: Bootstrapper::parse_ui_gateway_config (&finder),
+            generated_mnemonic_opt,
+            recover_consuming_wallet,
+            import_consuming_wallet,
+            derivation_path: finder.find_value_for ("--derivation_path", "--derivation_path <path> of the consuming wallet under its recovery phrase, such as m/44'/60'/0'/0/0")
+                .unwrap_or (String::from (DEFAULT_DERIVATION_PATH)),
+        }
+    }
+
+    pub fn parse_mode (finder: &ParameterFinder) -> NodeMode {
+        let parameter_tag = "--mode";
+        let usage = "--mode <standard|originate_only|serve_only|zero_hop> where 'originate_only' uses the network without relaying or exiting for other Nodes, 'serve_only' relays and exits without a local front end, and 'zero_hop' exits all local traffic directly without using the network";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => NodeMode::Standard,
+            Some (ref value) if value == "standard" => NodeMode::Standard,
+            Some (ref value) if value == "originate_only" => NodeMode::OriginateOnly,
+            Some (ref value) if value == "serve_only" => NodeMode::ServeOnly,
+            Some (ref value) if value == "zero_hop" => NodeMode::ZeroHop,
+            Some (value) => panic! ("Invalid value for --mode <standard|originate_only|serve_only|zero_hop>: '{}'", value)
         }
     }
 
@@ -124,26 +449,914 @@ impl Bootstrapper {
     }
 
     fn parse_neighbor_config (string: String) -> (Key, NodeAddr) {
+        Bootstrapper::parse_node_descriptor (&string).unwrap_or_else (|e| panic! ("{}", e))
+    }
+
+    // The reverse of node_descriptor
+    pub fn parse_node_descriptor (string: &str) -> Result<(Key, NodeAddr), String> {
+        let pieces: Vec<&str> = string.split (";").collect ();
+        if pieces.len () != 3 {return Err (String::from ("--neighbor <public key>;<IP address>;<port>,<port>,..."))}
+        let public_key = match base64::decode (pieces[0]) {
+            Ok (data) => Key::new (&data[..]),
+            Err (_) => return Err (format! ("Invalid Base64 for --neighbor <public key>: '{}'", pieces[0]))
+        };
+        let ip_addr = match IpAddr::from_str (&pieces[1]) {
+            Ok (ip_addr) => ip_addr,
+            Err (_) => return Err (format! ("Invalid IP address for --neighbor <IP address>: '{}'", pieces[1]))
+        };
+        let ports = pieces[2].split (",").map (|s| s.parse::<u16>()
+            .map_err (|_| format! ("Neighbor port numbers must be 0-65535, not {}", s))).collect::<Result<Vec<u16>, String>> ()?;
+        Ok ((public_key, NodeAddr::new (&ip_addr, &ports)))
+    }
+
+    // The descriptor a Node started with these arguments would report, worked out without starting it.
+    // Only a Node that keeps its identity in its data directory has the same descriptor from one run
+    // to the next.
+    pub fn descriptor_for (streams: &mut StdStreams, terminal: &Terminal, args: &Vec<String>) -> Result<String, String> {
+        let mut config = Bootstrapper::parse_args (args);
+        Bootstrapper::establish_identity_passphrase (streams, terminal, &mut config);
+        let (data_directory, passphrase) = match (&config.data_directory_opt, &config.identity_passphrase_opt) {
+            (&Some (ref data_directory), &Some (ref passphrase)) => (data_directory, passphrase),
+            _ => return Err (String::from ("Without --data_directory and an identity passphrase, this Node gets a new identity, and so a new descriptor, every time it starts"))
+        };
+        let store = IdentityStore::in_data_directory (data_directory, passphrase);
+        let private_key = match store.load ()? {
+            Some (private_key) => private_key,
+            None => return Err (format! ("There's no identity in {:?} yet; start the Node once to make one", store.path ()))
+        };
+        let mut cryptde = Bootstrapper::cryptde_factory (config.null_cryptde) ();
+        cryptde.adopt_private_key (&private_key)
+            .map_err (|e| format! ("{:?} does not hold a usable identity: {:?}", store.path (), e))?;
+        if config.mode == NodeMode::ZeroHop {
+            return Err (String::from ("A zero-hop Node doesn't use the network, so it has no descriptor"))
+        }
+        let clandestine_port = match Bootstrapper::configured_clandestine_port (&config)? {
+            Some (clandestine_port) => clandestine_port,
+            None => return Err (format! ("There's no clandestine port in {:?} yet; start the Node once to choose one, or give --clandestine_port", data_directory))
+        };
+        let discovered_ip_addr_opt = match config.ip_discovery_opt {
+            Some (ref ip_discovery) => public_ip_finder (ip_discovery).find_public_ip (),
+            None => None
+        };
+        let ip_addr = match config.ip_addr_opt.or (discovered_ip_addr_opt) {
+            Some (ip_addr) => ip_addr,
+            None => return Err (String::from ("Couldn't discover this Node's public IP address; supply it with --ip"))
+        };
+        let mut configuration = Configuration::new ();
+        configuration.establish (args);
+        configuration.add_clandestine_port (clandestine_port);
+        Ok (Bootstrapper::node_descriptor (&cryptde.public_key (), &NodeAddr::new (&ip_addr, &configuration.clandestine_ports ())))
+    }
+
+    // The port given with --clandestine_port, or else the one kept in the data directory, if there is one
+    pub fn known_clandestine_port (args: &Vec<String>) -> Result<Option<u16>, String> {
+        Bootstrapper::configured_clandestine_port (&Bootstrapper::parse_args (args))
+    }
+
+    fn configured_clandestine_port (config: &BootstrapperConfig) -> Result<Option<u16>, String> {
+        match (config.clandestine_port_opt, &config.data_directory_opt) {
+            (Some (port), _) => Ok (Some (port)),
+            (None, &Some (ref data_directory)) => ClandestinePortStore::in_data_directory (data_directory).load (),
+            (None, &None) => Ok (None)
+        }
+    }
+
+    // A port picked at random or given with --clandestine_port is kept in the data directory, so that
+    // the Node's descriptor doesn't change when it restarts. Without a data directory, it does.
+    fn establish_clandestine_port (config: &BootstrapperConfig) -> u16 {
+        let saved_opt = match config.data_directory_opt {
+            Some (ref data_directory) => ClandestinePortStore::in_data_directory (data_directory).load ().unwrap_or_else (|e| panic! ("{}", e)),
+            None => None
+        };
+        let port = config.clandestine_port_opt.or (saved_opt).unwrap_or_else (random_clandestine_port);
+        if let Some (ref data_directory) = config.data_directory_opt {
+            if saved_opt != Some (port) {
+                ClandestinePortStore::in_data_directory (data_directory).save (port).unwrap_or_else (|e| panic! ("{}", e));
+            }
+        }
+        port
+    }
+
+    // The UI reads the token out of the data directory, where it stays from one run to the next.
+    // Without a data directory there's nowhere for the UI to find it, so it's shown instead.
+    fn establish_ui_token (streams: &mut StdStreams, config: &BootstrapperConfig) -> String {
+        match config.data_directory_opt {
+            Some (ref data_directory) => {
+                let store = UiTokenStore::in_data_directory (data_directory);
+                match store.load ().unwrap_or_else (|e| panic! ("{}", e)) {
+                    Some (token) => token,
+                    None => {
+                        let token = random_ui_token ();
+                        store.save (&token).unwrap_or_else (|e| panic! ("{}", e));
+                        token
+                    }
+                }
+            },
+            None => {
+                let token = random_ui_token ();
+                writeln! (streams.stdout, "UI token for this run: {}", token).expect ("Internal error");
+                token
+            }
+        }
+    }
+
+    // Read while the Node is still root, since an operator's private key may well be readable only by root
+    fn establish_ui_certificate (config: &BootstrapperConfig) -> Option<UiCertificate> {
+        match config.ui_gateway_config.ui_tls {
+            UiTls::Off => None,
+            UiTls::Supplied {ref certificate_file, ref private_key_file} => Some (UiCertificate::from_pem_files (certificate_file, private_key_file)
+                .unwrap_or_else (|e| panic! ("Invalid value for --ui_certificate or --ui_private_key: {}", e))),
+            UiTls::Generated => match config.data_directory_opt {
+                Some (ref data_directory) => {
+                    let store = UiCertificateStore::in_data_directory (data_directory);
+                    match store.load ().unwrap_or_else (|e| panic! ("{}", e)) {
+                        Some (ui_certificate) => Some (ui_certificate),
+                        None => {
+                            let ui_certificate = UiCertificate::generate ();
+                            store.save (&ui_certificate).unwrap_or_else (|e| panic! ("{}", e));
+                            Some (ui_certificate)
+                        }
+                    }
+                },
+                None => Some (UiCertificate::generate ())
+            }
+        }
+    }
+
+    // The same format --neighbor accepts, so operators can hand it straight to their peers
+    pub fn node_descriptor (public_key: &Key, node_addr: &NodeAddr) -> String {
+        let ports: Vec<String> = node_addr.ports ().iter ().map (|port| port.to_string ()).collect ();
+        format! ("{};{};{}", base64::encode (&public_key.data), node_addr.ip_addr (), ports.join (","))
+    }
+
+    fn parse_ip_addr (finder: &ParameterFinder) -> Option<IpAddr> {
+        let parameter_tag = "--ip";
+        let usage = "--ip <IP address> where neighbors can reach this Node";
+        finder.find_value_for (parameter_tag, usage).map (|value| IpAddr::from_str (&value)
+            .expect (format! ("Invalid value for --ip <IP address>: '{}'", value).as_str ()))
+    }
+
+    fn parse_ip_discovery (finder: &ParameterFinder) -> Option<IpDiscoveryConfig> {
+        let parameter_tag = "--ip_discovery";
+        let usage = "--ip_discovery <on|off> where 'off' keeps this Node from asking outside services for its public IP address";
+        let on = match finder.find_value_for (parameter_tag, usage) {
+            None => true,
+            Some (ref value) if value == "on" => true,
+            Some (ref value) if value == "off" => false,
+            Some (value) => panic! ("Invalid value for --ip_discovery <on|off>: '{}'", value)
+        };
+        let stun_server_opt = finder.find_value_for ("--stun_server", "--stun_server <host:port> of the STUN server asked for this Node's public IP address");
+        let http_echo_host_opt = finder.find_value_for ("--ip_echo_host", "--ip_echo_host <host> whose HTTP service answers with the asker's IP address");
+        if !on {
+            if stun_server_opt.is_some () || http_echo_host_opt.is_some () {
+                panic! ("--stun_server and --ip_echo_host are only used with --ip_discovery on")
+            }
+            return None
+        }
+        Some (IpDiscoveryConfig {
+            stun_server: stun_server_opt.unwrap_or (String::from (DEFAULT_STUN_SERVER)),
+            http_echo_host: http_echo_host_opt.unwrap_or (String::from (DEFAULT_HTTP_ECHO_HOST)),
+        })
+    }
+
+    fn parse_ip_check_interval (finder: &ParameterFinder) -> u64 {
+        let parameter_tag = "--ip_check_interval";
+        let usage = "--ip_check_interval <milliseconds> between checks of --public_hostname for a new IP address";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => DEFAULT_IP_CHECK_INTERVAL_MS,
+            Some (value) => value.parse::<u64> ()
+                .expect (format! ("Invalid value for --ip_check_interval <milliseconds>: '{}'", value).as_str ())
+        }
+    }
+
+    fn parse_max_response_size (finder: &ParameterFinder) -> usize {
+        let parameter_tag = "--max_response_size";
+        let usage = "--max_response_size <bytes> where 'bytes' is the largest response an exit request may return (0 for unlimited)";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => 0,
+            Some (value) => value.parse::<usize> ()
+                .expect (format! ("Invalid value for --max_response_size <bytes>: '{}'", value).as_str ())
+        }
+    }
+
+    fn parse_hop_range (finder: &ParameterFinder) -> (usize, usize) {
+        let min_hops = Bootstrapper::parse_count (finder, "--min_hops",
+            "--min_hops <count> where 'count' is the fewest relays a route may use between this Node and the exit Node");
+        let max_hops = Bootstrapper::parse_count (finder, "--max_hops",
+            "--max_hops <count> where 'count' is the most relays a route may use between this Node and the exit Node");
+        match (min_hops, max_hops) {
+            (None, None) => (DEFAULT_MIN_HOPS, DEFAULT_MAX_HOPS),
+            (Some (min), None) => (min, cmp::max (min, DEFAULT_MAX_HOPS)),
+            (None, Some (max)) => (cmp::min (max, DEFAULT_MIN_HOPS), max),
+            (Some (min), Some (max)) => {
+                if min > max {panic! ("--min_hops ({}) must not be greater than --max_hops ({})", min, max)}
+                (min, max)
+            }
+        }
+    }
+
+    // Defaults give way to whatever the operator did specify
+    fn parse_neighbor_counts (finder: &ParameterFinder) -> (usize, usize, usize) {
+        let min_opt = Bootstrapper::parse_count (finder, "--min_neighbors",
+            "--min_neighbors <count> where 'count' is the fewest neighbors this Node can have without complaining");
+        let target_opt = Bootstrapper::parse_count (finder, "--target_neighbors",
+            "--target_neighbors <count> where 'count' is how many neighbors this Node seeks introductions to");
+        let max_opt = Bootstrapper::parse_count (finder, "--max_neighbors",
+            "--max_neighbors <count> where 'count' is the most neighbors this Node accepts introductions from");
+        let floor = min_opt.unwrap_or (0);
+        let ceiling = max_opt.unwrap_or (usize::max_value ());
+        let target = target_opt.unwrap_or (cmp::max (floor, cmp::min (ceiling, DEFAULT_TARGET_NEIGHBORS)));
+        let min = min_opt.unwrap_or (cmp::min (target, DEFAULT_MIN_NEIGHBORS));
+        let max = max_opt.unwrap_or (cmp::max (target, DEFAULT_MAX_NEIGHBORS));
+        if min > target {panic! ("--min_neighbors ({}) must not be greater than --target_neighbors ({})", min, target)}
+        if target > max {panic! ("--target_neighbors ({}) must not be greater than --max_neighbors ({})", target, max)}
+        (min, target, max)
+    }
+
+    fn parse_count (finder: &ParameterFinder, parameter_tag: &str, usage: &str) -> Option<usize> {
+        match finder.find_value_for (parameter_tag, usage) {
+            None => None,
+            Some (value) => Some (value.parse::<usize> ()
+                .expect (format! ("Invalid value for {} <count>: '{}'", parameter_tag, value).as_str ()))
+        }
+    }
+
+    fn parse_gossip_interval (finder: &ParameterFinder) -> u64 {
+        let parameter_tag = "--gossip_interval";
+        let usage = "--gossip_interval <milliseconds> between Gossip to each neighbor when nothing has changed (0 to gossip only about changes)";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => DEFAULT_GOSSIP_INTERVAL_MS,
+            Some (value) => value.parse::<u64> ()
+                .expect (format! ("Invalid value for --gossip_interval <milliseconds>: '{}'", value).as_str ())
+        }
+    }
+
+    fn parse_heartbeat_interval (finder: &ParameterFinder) -> u64 {
+        let parameter_tag = "--heartbeat_interval";
+        let usage = "--heartbeat_interval <milliseconds> between Pings to each neighbor (0 for none)";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => DEFAULT_HEARTBEAT_INTERVAL_MS,
+            Some (value) => value.parse::<u64> ()
+                .expect (format! ("Invalid value for --heartbeat_interval <milliseconds>: '{}'", value).as_str ())
+        }
+    }
+
+    fn parse_stale_node_window (finder: &ParameterFinder) -> u64 {
+        let parameter_tag = "--stale_node_window";
+        let usage = "--stale_node_window <milliseconds> a Node can go unheard from before it's forgotten (0 to remember Nodes forever)";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => DEFAULT_STALE_NODE_WINDOW_MS,
+            Some (value) => value.parse::<u64> ()
+                .expect (format! ("Invalid value for --stale_node_window <milliseconds>: '{}'", value).as_str ())
+        }
+    }
+
+    fn parse_data_directory (finder: &ParameterFinder) -> Option<PathBuf> {
+        let parameter_tag = "--data_directory";
+        let usage = "--data_directory <path> where the Node keeps what it learns about the network between runs";
+        finder.find_value_for (parameter_tag, usage).map (PathBuf::from)
+    }
+
+    fn parse_geolocation_database (finder: &ParameterFinder) -> Option<PathBuf> {
+        let parameter_tag = "--geolocation_database";
+        let usage = "--geolocation_database <path> to a CSV file of IP address ranges and the countries they're in";
+        finder.find_value_for (parameter_tag, usage).map (PathBuf::from)
+    }
+
+    // Countries are two-letter codes; a leading '!' means routes must not exit there
+    fn parse_exit_location (finder: &ParameterFinder) -> ExitLocation {
+        let parameter_tag = "--exit_location";
+        let usage = "--exit_location <countries> where 'countries' is a comma-separated list of country codes, each prefixed with '!' to avoid it";
+        let value = match finder.find_value_for (parameter_tag, usage) {
+            None => return ExitLocation::default (),
+            Some (value) => value
+        };
+        if Bootstrapper::parse_geolocation_database (finder).is_none () {
+            panic! ("--exit_location requires --geolocation_database")
+        }
+        let mut exit_location = ExitLocation::default ();
+        for term in value.split (',') {
+            let (avoided, country) = if term.starts_with ('!') {(true, &term[1..])} else {(false, term)};
+            if (country.len () != 2) || !country.chars ().all (|c| c.is_ascii_alphabetic ()) {
+                panic! ("Invalid value for --exit_location <countries>: '{}'", value)
+            }
+            if avoided {
+                exit_location.avoided_countries.push (country.to_uppercase ())
+            }
+            else {
+                exit_location.countries.push (country.to_uppercase ())
+            }
+        }
+        exit_location
+    }
+
+    fn parse_route_diversity (finder: &ParameterFinder) -> RouteDiversity {
+        let parameter_tag = "--route_diversity";
+        let usage = "--route_diversity <off|prefer|require> to keep Nodes in the same network, autonomous system, or operator off the same route";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => DEFAULT_ROUTE_DIVERSITY,
+            Some (ref value) if value == "off" => RouteDiversity::Off,
+            Some (ref value) if value == "prefer" => RouteDiversity::Prefer,
+            Some (ref value) if value == "require" => RouteDiversity::Require,
+            Some (value) => panic! ("Invalid value for --route_diversity <off|prefer|require>: '{}'", value)
+        }
+    }
+
+    fn parse_max_route_cost (finder: &ParameterFinder) -> Option<RouteCost> {
+        let parameter_tag = "--max_route_cost";
+        let usage = "--max_route_cost <per package>,<per byte> where each is the most SUB the Nodes on a route may charge, all told, for a request and its response and for each byte in them";
+        finder.find_value_for (parameter_tag, usage).map (|value| {
+            let amounts: Vec<i64> = value.split (',').map (|amount| amount.parse::<i64> ()
+                .unwrap_or_else (|_| panic! ("Invalid value for --max_route_cost <per package>,<per byte>: '{}'", value)))
+                .collect ();
+            if amounts.len () != 2 {panic! ("Invalid value for --max_route_cost <per package>,<per byte>: '{}'", value)}
+            RouteCost {per_package: amounts[0], per_byte: amounts[1]}
+        })
+    }
+
+    fn parse_bans (finder: &ParameterFinder) -> Vec<BanNodeMsg> {
+        let parameter_tag = "--ban";
+        let usage = "--ban <public key>[;share] where 'share' tells neighbors about the ban too";
+        finder.find_values_for (parameter_tag, usage).into_iter ()
+            .map (|s| Bootstrapper::parse_ban (s, usage))
+            .collect ()
+    }
+
+    fn parse_ban (string: String, usage: &str) -> BanNodeMsg {
         let pieces: Vec<&str> = string.split (";").collect ();
-        if pieces.len () != 3 {panic! ("--neighbor <public key>;<IP address>;<port>,<port>,...")}
+        let share = match pieces.len () {
+            1 => false,
+            2 if pieces[1] == "share" => true,
+            _ => panic! ("{}", usage)
+        };
         let public_key = Key::new (&base64::decode (pieces[0])
-            .expect (format! ("Invalid Base64 for --neighbor <public key>: '{}'", pieces[0]).as_str ())[..]);
-        let ip_addr = IpAddr::from_str (&pieces[1])
-            .expect (format! ("Invalid IP address for --neighbor <IP address>: '{}'", pieces[1]).as_str ());
-        let ports: Vec<u16> = pieces[2].split (",").map (|s| s.parse::<u16>()
-            .expect(format! ("Neighbor port numbers must be 0-65535, not {}", s).as_str ())).collect ();
-        (public_key, NodeAddr::new (&ip_addr, &ports))
-    }
-
-    fn initialize_and_report_cryptde (streams: &mut StdStreams) {
-        let mut exemplar = CryptDENull::new ();
-        exemplar.generate_key_pair();
-        let cryptde: &'static CryptDENull = unsafe {
+            .expect (format! ("Invalid Base64 for --ban <public key>: '{}'", pieces[0]).as_str ())[..]);
+        BanNodeMsg {public_key, reason: String::from ("Banned by operator"), share}
+    }
+
+    fn parse_port_mapping (finder: &ParameterFinder) -> Option<PortMappingProtocol> {
+        let parameter_tag = "--port_mapping";
+        let usage = "--port_mapping <off|upnp|natpmp> to have the gateway forward the clandestine ports to this Node";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => None,
+            Some (ref value) if value == "off" => None,
+            Some (ref value) if value == "upnp" => Some (PortMappingProtocol::Upnp),
+            Some (ref value) if value == "natpmp" => Some (PortMappingProtocol::NatPmp),
+            Some (value) => panic! ("Invalid value for --port_mapping <off|upnp|natpmp>: '{}'", value)
+        }
+    }
+
+    fn parse_clandestine_port (finder: &ParameterFinder) -> Option<u16> {
+        let parameter_tag = "--clandestine_port";
+        let usage = "--clandestine_port <port> where other Nodes reach this one; picked at random and kept in the data directory if not given";
+        finder.find_value_for (parameter_tag, usage).map (|value| match value.parse::<u16> () {
+            Ok (port) if port > 0 => port,
+            _ => panic! ("Invalid value for --clandestine_port <port>: '{}'", value)
+        })
+    }
+
+    // Every UI has to have the token, but beyond this machine the token itself would go over the
+    // network, so listening anywhere but loopback is only allowed over TLS
+    fn parse_ui_gateway_config (finder: &ParameterFinder) -> UiGatewayConfig {
+        let ui_bind_ip = Bootstrapper::parse_ui_bind_ip (finder);
+        let ui_tls = Bootstrapper::parse_ui_tls (finder);
+        if !ui_bind_ip.is_loopback () && ui_tls == UiTls::Off {
+            panic! ("--ui_bind_ip {} would let other machines reach the UI; that needs --ui_tls on", ui_bind_ip)
+        }
+        UiGatewayConfig {
+            ui_bind_ip,
+            ui_port: Bootstrapper::parse_ui_port (finder),
+            ui_tls,
+            token_opt: None,
+            certificate_opt: None,
+        }
+    }
+
+    fn parse_ui_bind_ip (finder: &ParameterFinder) -> IpAddr {
+        let parameter_tag = "--ui_bind_ip";
+        let usage = "--ui_bind_ip <IP address> of the local interface where a UI can connect (default 127.0.0.1)";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => default_ui_bind_ip (),
+            Some (value) => IpAddr::from_str (&value).unwrap_or_else (|_| panic! ("Invalid value for --ui_bind_ip <IP address>: '{}'", value))
+        }
+    }
+
+    fn parse_ui_tls (finder: &ParameterFinder) -> UiTls {
+        let on = match finder.find_value_for ("--ui_tls", "--ui_tls <on|off> where 'on' serves the UI over TLS") {
+            None => false,
+            Some (ref value) if value == "on" => true,
+            Some (ref value) if value == "off" => false,
+            Some (value) => panic! ("Invalid value for --ui_tls <on|off>: '{}'", value)
+        };
+        let certificate_opt = finder.find_value_for ("--ui_certificate", "--ui_certificate <PEM file> of the certificate chain to serve the UI with");
+        let private_key_opt = finder.find_value_for ("--ui_private_key", "--ui_private_key <PEM file> of the private key for --ui_certificate");
+        match (on, certificate_opt, private_key_opt) {
+            (false, None, None) => UiTls::Off,
+            (false, _, _) => panic! ("--ui_certificate and --ui_private_key need --ui_tls on"),
+            (true, None, None) => UiTls::Generated,
+            (true, Some (certificate_file), Some (private_key_file)) => UiTls::Supplied {
+                certificate_file: PathBuf::from (certificate_file),
+                private_key_file: PathBuf::from (private_key_file),
+            },
+            (true, _, _) => panic! ("--ui_certificate and --ui_private_key have to be given together")
+        }
+    }
+
+    fn parse_ui_port (finder: &ParameterFinder) -> u16 {
+        let parameter_tag = "--ui_port";
+        let usage = "--ui_port <port> where a UI can connect to control the Node";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => DEFAULT_UI_PORT,
+            Some (value) => match value.parse::<u16> () {
+                Ok (port) if port > 0 => port,
+                _ => panic! ("Invalid value for --ui_port <port>: '{}'", value)
+            }
+        }
+    }
+
+    // Links to neighbors are plain TCP, disguised only by the masqueraders. Asking for TLS is an
+    // error rather than quietly getting plain TCP instead.
+    fn check_clandestine_transport (finder: &ParameterFinder) {
+        let parameter_tag = "--clandestine_transport";
+        let usage = "--clandestine_transport <plain>";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => (),
+            Some (ref value) if value == "plain" => (),
+            Some (ref value) if value == "tls" => panic! ("--clandestine_transport tls is not supported yet: links to neighbors can only be plain"),
+            Some (value) => panic! ("Invalid value for --clandestine_transport <plain>: '{}'", value)
+        }
+    }
+
+    fn parse_padding (finder: &ParameterFinder) -> bool {
+        let parameter_tag = "--padding";
+        let usage = "--padding <on|off> where 'on' pads CORES packages to fixed sizes to disguise their contents";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => false,
+            Some (ref value) if value == "on" => true,
+            Some (ref value) if value == "off" => false,
+            Some (value) => panic! ("Invalid value for --padding <on|off>: '{}'", value)
+        }
+    }
+
+    fn parse_cover_traffic_interval (finder: &ParameterFinder) -> u64 {
+        let parameter_tag = "--cover_traffic_interval";
+        let usage = "--cover_traffic_interval <milliseconds> between cover packages sent to each neighbor (0 for none)";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => 0,
+            Some (value) => value.parse::<u64> ()
+                .expect (format! ("Invalid value for --cover_traffic_interval <milliseconds>: '{}'", value).as_str ())
+        }
+    }
+
+    fn parse_rekey_policy (finder: &ParameterFinder) -> RekeyPolicy {
+        let max_bytes = match finder.find_value_for ("--rekey_bytes", "--rekey_bytes <bytes> a link key carries before it's replaced (0 for no limit)") {
+            None => DEFAULT_REKEY_POLICY.max_bytes,
+            Some (value) => value.parse::<u64> ()
+                .expect (format! ("Invalid value for --rekey_bytes <bytes>: '{}'", value).as_str ())
+        };
+        let max_interval_ms = match finder.find_value_for ("--rekey_interval", "--rekey_interval <milliseconds> a link key is used before it's replaced (0 for no limit)") {
+            None => DEFAULT_REKEY_POLICY.max_interval_ms,
+            Some (value) => value.parse::<u64> ()
+                .expect (format! ("Invalid value for --rekey_interval <milliseconds>: '{}'", value).as_str ())
+        };
+        if (max_bytes == 0) && (max_interval_ms == 0) {
+            panic! ("--rekey_bytes and --rekey_interval can't both be 0: link keys have to be replaced sometime")
+        }
+        RekeyPolicy {max_bytes, max_interval_ms}
+    }
+
+    fn parse_mix_delay (finder: &ParameterFinder) -> MixDelay {
+        let parameter_tag = "--mix_delay";
+        let usage = "--mix_delay <off|uniform:<min>-<max>|exponential:<mean>> in milliseconds to hold relayed packages before releasing them in shuffled batches";
+        let value = match finder.find_value_for (parameter_tag, usage) {
+            None => return MixDelay::Off,
+            Some (value) => value
+        };
+        let pieces: Vec<&str> = value.splitn (2, ":").collect ();
+        match (pieces[0], pieces.get (1).cloned ()) {
+            ("off", None) => MixDelay::Off,
+            ("uniform", Some (range)) => {
+                let bounds: Vec<&str> = range.splitn (2, "-").collect ();
+                if bounds.len () != 2 {Bootstrapper::complain_about_mix_delay (&value)}
+                let min_ms = Bootstrapper::parse_mix_delay_millis (&value, bounds[0]);
+                let max_ms = Bootstrapper::parse_mix_delay_millis (&value, bounds[1]);
+                if min_ms > max_ms {Bootstrapper::complain_about_mix_delay (&value)}
+                MixDelay::Uniform {min_ms, max_ms}
+            },
+            ("exponential", Some (mean)) => MixDelay::Exponential {mean_ms: Bootstrapper::parse_mix_delay_millis (&value, mean)},
+            _ => Bootstrapper::complain_about_mix_delay (&value)
+        }
+    }
+
+    fn parse_mix_delay_millis (value: &str, millis: &str) -> u64 {
+        millis.parse::<u64> ().unwrap_or_else (|_| Bootstrapper::complain_about_mix_delay (value))
+    }
+
+    fn complain_about_mix_delay (value: &str) -> ! {
+        panic! ("Invalid value for --mix_delay <off|uniform:<min>-<max>|exponential:<mean>>: '{}'", value)
+    }
+
+    fn parse_compression (finder: &ParameterFinder) -> bool {
+        let parameter_tag = "--compression";
+        let usage = "--compression <on|off> where 'on' compresses CORES packages for neighbors that can read them";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => false,
+            Some (ref value) if value == "on" => true,
+            Some (ref value) if value == "off" => false,
+            Some (value) => panic! ("Invalid value for --compression <on|off>: '{}'", value)
+        }
+    }
+
+    fn parse_null_cryptde (finder: &ParameterFinder) -> bool {
+        let parameter_tag = "--null_cryptde";
+        let usage = "--null_cryptde <on|off> where 'on' replaces real cryptography with a transparent stand-in for testing; never use it on a real network";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => false,
+            Some (ref value) if value == "on" => true,
+            Some (ref value) if value == "off" => false,
+            Some (value) => panic! ("Invalid value for --null_cryptde <on|off>: '{}'", value)
+        }
+    }
+
+    // A rotation interval of 0 means the key pair is never rotated
+    fn parse_key_rotation (finder: &ParameterFinder) -> (u64, u64) {
+        let interval = match finder.find_value_for ("--key_rotation_interval", "--key_rotation_interval <milliseconds> between new key pairs for this Node (0 to keep one key pair)") {
+            None => 0,
+            Some (value) => value.parse::<u64> ()
+                .expect (format! ("Invalid value for --key_rotation_interval <milliseconds>: '{}'", value).as_str ())
+        };
+        let overlap = match finder.find_value_for ("--key_overlap", "--key_overlap <milliseconds> an old key pair keeps working after it's rotated away from") {
+            None => cmp::min (DEFAULT_KEY_OVERLAP_MS, interval / 2),
+            Some (value) => value.parse::<u64> ()
+                .expect (format! ("Invalid value for --key_overlap <milliseconds>: '{}'", value).as_str ())
+        };
+        if (interval > 0) && (overlap >= interval) {
+            panic! ("--key_overlap ({}) must be shorter than --key_rotation_interval ({})", overlap, interval)
+        }
+        (interval, overlap)
+    }
+
+    fn parse_accountant_config (finder: &ParameterFinder) -> AccountantConfig {
+        fn parse<T: FromStr> (finder: &ParameterFinder, parameter_tag: &str, unit: &str, usage: &str, default: T) -> T {
+            match finder.find_value_for (parameter_tag, usage) {
+                None => default,
+                Some (value) => value.parse::<T> ().ok ()
+                    .expect (format! ("Invalid value for {} <{}>: '{}'", parameter_tag, unit, value).as_str ())
+            }
+        }
+        let price_oracle_url_opt = finder.find_value_for ("--price_oracle",
+            "--price_oracle <url> that answers with the price of SUB in a fiat currency, its #fragment saying where in the JSON, such as https://prices.example.com/sub#usd");
+        // Fiat thresholds mean nothing without a price to convert them at
+        let fiat_thresholds_opt = price_oracle_url_opt.as_ref ().map (|_| FiatThresholds {
+            payment_threshold: parse (finder, "--fiat_payment_threshold", "hundredths",
+                "--fiat_payment_threshold <hundredths> of a fiat currency, such as cents, owed to one Node that gets paid at the next check", DEFAULT_FIAT_THRESHOLDS.payment_threshold),
+            max_debt: parse (finder, "--fiat_max_debt", "hundredths",
+                "--fiat_max_debt <hundredths> of a fiat currency another Node can owe once its grace period is over before it's banned", DEFAULT_FIAT_THRESHOLDS.max_debt),
+            min_debt: parse (finder, "--fiat_min_debt", "hundredths",
+                "--fiat_min_debt <hundredths> of a fiat currency another Node can owe, however long it has owed it, before it's banned", DEFAULT_FIAT_THRESHOLDS.min_debt),
+        });
+        AccountantConfig {
+            payable_scan_interval_ms: parse (finder, "--payable_scan_interval", "milliseconds",
+                "--payable_scan_interval <milliseconds> between checks for debts this Node should pay (0 to never pay)", DEFAULT_PAYABLE_SCAN_INTERVAL_MS),
+            payment_threshold: parse (finder, "--payment_threshold", "amount",
+                "--payment_threshold <amount> of SUB owed to one Node that gets paid at the next check", DEFAULT_PAYMENT_THRESHOLD),
+            payment_age_threshold_ms: parse (finder, "--payment_age_threshold", "milliseconds",
+                "--payment_age_threshold <milliseconds> a debt of any amount can go unpaid before it gets paid at the next check", DEFAULT_PAYMENT_AGE_THRESHOLD_MS),
+            payment_retry_ms: parse (finder, "--payment_retry", "milliseconds",
+                "--payment_retry <milliseconds> before a failed payment is tried again, doubling with each failure in a row", DEFAULT_PAYMENT_RETRY_MS),
+            receivable_scan_interval_ms: parse (finder, "--receivable_scan_interval", "milliseconds",
+                "--receivable_scan_interval <milliseconds> between checks for Nodes that owe too much for too long (0 to never ban them)", DEFAULT_RECEIVABLE_SCAN_INTERVAL_MS),
+            delinquency_curve: DEFAULT_DELINQUENCY_CURVE,
+            free_tier: FreeTier {
+                bytes: parse (finder, "--free_bytes", "bytes",
+                    "--free_bytes <bytes> of service a Node that has never paid gets for nothing", DEFAULT_FREE_TIER.bytes),
+                period_ms: parse (finder, "--free_period", "milliseconds",
+                    "--free_period <milliseconds> after its first service that a Node that has never paid gets it for nothing", DEFAULT_FREE_TIER.period_ms),
+                throttle_debt: parse (finder, "--free_throttle_debt", "amount",
+                    "--free_throttle_debt <amount> of SUB a Node that has never paid can owe before its traffic is slowed", DEFAULT_FREE_TIER.throttle_debt),
+                refusal_debt: parse (finder, "--free_refusal_debt", "amount",
+                    "--free_refusal_debt <amount> of SUB a Node that has never paid can owe before its traffic is refused", DEFAULT_FREE_TIER.refusal_debt),
+            },
+            debt_ceiling: parse (finder, "--debt_ceiling", "amount",
+                "--debt_ceiling <amount> of SUB any Node can owe before its traffic is refused until it pays", DEFAULT_DEBT_CEILING),
+            channel_deposit: parse (finder, "--channel_deposit", "amount",
+                "--channel_deposit <amount> of SUB to lock up in a payment channel to a Node this Node pays often (0 for no channels)", DEFAULT_CHANNEL_DEPOSIT),
+            channel_min_payments: parse (finder, "--channel_min_payments", "count",
+                "--channel_min_payments <count> of on-chain payments to a Node before a payment channel is opened to it", DEFAULT_CHANNEL_MIN_PAYMENTS),
+            channel_lifetime_ms: parse (finder, "--channel_lifetime", "milliseconds",
+                "--channel_lifetime <milliseconds> a payment channel is paid through before it's closed", DEFAULT_CHANNEL_LIFETIME_MS),
+            invoice_interval_ms: parse (finder, "--invoice_interval", "milliseconds",
+                "--invoice_interval <milliseconds> between signed invoices to each Node this Node charges (0 for no invoices)", DEFAULT_INVOICE_INTERVAL_MS),
+            rates: RateSchedule {
+                routing_service_rate: parse (finder, "--routing_service_rate", "amount",
+                    "--routing_service_rate <amount> of SUB this Node charges to relay a CORES package", DEFAULT_RATES.routing_service_rate),
+                routing_byte_rate: parse (finder, "--routing_byte_rate", "amount",
+                    "--routing_byte_rate <amount> of SUB this Node charges for each byte of a CORES package it relays", DEFAULT_RATES.routing_byte_rate),
+                exit_service_rate: parse (finder, "--exit_service_rate", "amount",
+                    "--exit_service_rate <amount> of SUB this Node charges to send a request out onto the Internet", DEFAULT_RATES.exit_service_rate),
+                exit_byte_rate: parse (finder, "--exit_byte_rate", "amount",
+                    "--exit_byte_rate <amount> of SUB this Node charges for each byte of a request it sends out onto the Internet", DEFAULT_RATES.exit_byte_rate),
+            },
+            price_oracle_url_opt,
+            fiat_thresholds_opt,
+        }
+    }
+
+    fn parse_generate_consuming_wallet (finder: &ParameterFinder) -> Option<String> {
+        finder.find_value_for ("--generate_consuming_wallet", "--generate_consuming_wallet <12 or 24> words of recovery phrase for a new consuming wallet").map (|value| {
+            let word_count = value.parse::<usize> ()
+                .unwrap_or_else (|_| panic! ("Invalid value for --generate_consuming_wallet <12 or 24>: '{}'", value));
+            generate_mnemonic (word_count).unwrap_or_else (|e| panic! ("Invalid value for --generate_consuming_wallet <12 or 24>: {}", e))
+        })
+    }
+
+    // A recovery phrase is never taken on the command line, where other users and shell history
+    // can see it; it's asked for at startup instead
+    fn parse_recover_consuming_wallet (finder: &ParameterFinder) -> bool {
+        let parameter_tag = "--recover_consuming_wallet";
+        let usage = "--recover_consuming_wallet <on|off> where 'on' asks for the consuming wallet's recovery phrase at startup and seals the wallet into the data directory";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => false,
+            Some (ref value) if value == "on" => true,
+            Some (ref value) if value == "off" => false,
+            Some (value) => panic! ("Invalid value for --recover_consuming_wallet <on|off>: '{}'", value)
+        }
+    }
+
+    // A private key is never taken on the command line either
+    fn parse_import_consuming_wallet (finder: &ParameterFinder) -> bool {
+        let parameter_tag = "--import_consuming_wallet";
+        let usage = "--import_consuming_wallet <on|off> where 'on' asks for the consuming wallet's private key at startup and seals the wallet into the data directory";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => false,
+            Some (ref value) if value == "on" => true,
+            Some (ref value) if value == "off" => false,
+            Some (value) => panic! ("Invalid value for --import_consuming_wallet <on|off>: '{}'", value)
+        }
+    }
+
+    fn check_consuming_wallet_sources (config: &BlockchainBridgeConfig, generate: bool, recover: bool, import: bool) {
+        let given = vec! (generate, recover, import).into_iter ().filter (|given| *given).count ();
+        if given > 1 {
+            panic! ("Only one of --generate_consuming_wallet, --recover_consuming_wallet and --import_consuming_wallet can be given")
+        }
+        if (given > 0) && config.signing_service_url_opt.is_some () {
+            panic! ("A consuming wallet can't be kept here and by a --signing_service_url both")
+        }
+    }
+
+    // A consuming wallet kept by a signing service, such as one in front of a hardware wallet, is known
+    // here only by its address
+    fn parse_signing_service (finder: &ParameterFinder) -> (Option<String>, Option<Wallet>) {
+        let url_opt = finder.find_value_for ("--signing_service_url", "--signing_service_url <url> of a service that signs for the consuming wallet off this machine, such as http://localhost:8550");
+        let wallet_opt = finder.find_value_for ("--signing_wallet", "--signing_wallet <address> of the consuming wallet the signing service signs for").map (|value| {
+            Wallet::new (&value).unwrap_or_else (|_| panic! ("Invalid value for --signing_wallet <address>: '{}'", value))
+        });
+        if url_opt.is_some () != wallet_opt.is_some () {
+            panic! ("--signing_service_url and --signing_wallet go together")
+        }
+        (url_opt, wallet_opt)
+    }
+
+    fn parse_blockchain_bridge_config (finder: &ParameterFinder) -> BlockchainBridgeConfig {
+        let chain_id = match finder.find_value_for ("--chain_id", "--chain_id <number> of the Ethereum network to transact on (1 for the main network)") {
+            None => MAINNET_CHAIN_ID,
+            Some (value) => value.parse::<u64> ()
+                .expect (format! ("Invalid value for --chain_id <number>: '{}'", value).as_str ())
+        };
+        let sub_contract_address = match finder.find_value_for ("--sub_contract_address", "--sub_contract_address <address> of the SUB token contract, for test networks") {
+            None => Wallet::new (SUB_CONTRACT_ADDRESS).expect ("Internal error"),
+            Some (value) => Wallet::new (&value)
+                .unwrap_or_else (|_| panic! ("Invalid value for --sub_contract_address <address>: '{}'", value))
+        };
+        let channel_contract_opt = finder.find_value_for ("--channel_contract", "--channel_contract <address> of the payment channel contract; without one, debts are all paid on-chain").map (|value| {
+            Wallet::new (&value).unwrap_or_else (|_| panic! ("Invalid value for --channel_contract <address>: '{}'", value))
+        });
+        let payment_watch_interval_ms = match finder.find_value_for ("--payment_watch_interval", "--payment_watch_interval <milliseconds> between checks for SUB paid to this Node (0 to never check)") {
+            None => DEFAULT_PAYMENT_WATCH_INTERVAL_MS,
+            Some (value) => value.parse::<u64> ()
+                .expect (format! ("Invalid value for --payment_watch_interval <milliseconds>: '{}'", value).as_str ())
+        };
+        let gas_price_opt = Bootstrapper::parse_gas_price (finder, "--gas_price", "--gas_price <gwei> to offer per unit of gas, instead of what the Ethereum node says is going");
+        let max_gas_price = Bootstrapper::parse_gas_price (finder, "--max_gas_price", "--max_gas_price <gwei> to offer per unit of gas before payments wait for gas to get cheaper")
+            .unwrap_or (DEFAULT_MAX_GAS_PRICE);
+        if gas_price_opt.map (|gas_price| gas_price > max_gas_price).unwrap_or (false) {
+            panic! ("--gas_price can't be more than --max_gas_price ({} gwei)", max_gas_price / WEI_PER_GWEI)
+        }
+        let confirmations = match finder.find_value_for ("--confirmations", "--confirmations <blocks> mined after a transaction before it counts") {
+            None => DEFAULT_CONFIRMATIONS,
+            Some (value) => value.parse::<u64> ()
+                .expect (format! ("Invalid value for --confirmations <blocks>: '{}'", value).as_str ())
+        };
+        let earning_wallet_opt = finder.find_value_for ("--earning_wallet", "--earning_wallet <address> other Nodes should pay this Node at, if not the consuming wallet").map (|value| {
+            Wallet::new (&value).unwrap_or_else (|_| panic! ("Invalid value for --earning_wallet <address>: '{}'", value))
+        });
+        let (signing_service_url_opt, signing_wallet_opt) = Bootstrapper::parse_signing_service (finder);
+        BlockchainBridgeConfig {
+            blockchain_service_url_opt: finder.find_value_for ("--blockchain_service_url", "--blockchain_service_url <url> of an Ethereum node's JSON-RPC interface, such as http://localhost:8545"),
+            chain_id,
+            sub_contract_address,
+            channel_contract_opt,
+            // unlocked or sealed at startup; never given on the command line
+            consuming_private_key_opt: None,
+            signing_service_url_opt,
+            signing_wallet_opt,
+            earning_wallet_opt,
+            payment_watch_interval_ms,
+            gas_price_opt,
+            max_gas_price,
+            confirmations,
+        }
+    }
+
+    // Operators think in gwei; transactions want wei
+    fn parse_gas_price (finder: &ParameterFinder, parameter_tag: &str, usage: &str) -> Option<u64> {
+        finder.find_value_for (parameter_tag, usage).map (|value| {
+            match value.parse::<u64> ().ok ().and_then (|gwei| gwei.checked_mul (WEI_PER_GWEI)) {
+                Some (wei) => wei,
+                None => panic! ("Invalid value for {} <gwei>: '{}'", parameter_tag, value)
+            }
+        })
+    }
+
+    // A new consuming wallet, whether made from a recovery phrase or imported by its private key, is
+    // sealed into the data directory straight away, since neither is ever taken again. Secrets are
+    // only shown, or asked for, at a terminal, so they can't end up in a log or in shell history.
+    fn establish_new_consuming_wallet (streams: &mut StdStreams, terminal: &Terminal, config: &mut BootstrapperConfig) {
+        let generated_phrase_opt = config.generated_mnemonic_opt.take ();
+        if generated_phrase_opt.is_none () && !config.recover_consuming_wallet && !config.import_consuming_wallet {return}
+        let data_directory = match config.data_directory_opt {
+            Some (ref data_directory) => data_directory.clone (),
+            None => panic! ("--generate_consuming_wallet, --recover_consuming_wallet and --import_consuming_wallet need a --data_directory to seal the wallet into")
+        };
+        if WalletStore::exists_in (&data_directory) {
+            panic! ("{:?} already holds a consuming wallet; move it aside to replace it", data_directory.join (WALLET_FILENAME))
+        }
+        if !terminal.is_interactive () {
+            panic! ("A consuming wallet's secrets are only shown or asked for at a terminal; start the Node from one, without --daemon")
+        }
+        let private_key = match generated_phrase_opt {
+            Some (ref phrase) => {
+                let private_key = private_key_from_mnemonic (phrase, "", &config.derivation_path)
+                    .unwrap_or_else (|e| panic! ("Can't recover the consuming wallet: {}", e));
+                Bootstrapper::report_generated_consuming_wallet (streams, phrase, &private_key);
+                private_key
+            },
+            None if config.import_consuming_wallet => {
+                let private_key = Bootstrapper::prompt_secret (streams, terminal, "Private key of the consuming wallet, in hex: ");
+                Signer::from_private_key (&private_key).unwrap_or_else (|e| panic! ("Can't import the consuming wallet: {}", e));
+                private_key
+            },
+            None => {
+                let phrase = Bootstrapper::prompt_secret (streams, terminal, "Recovery phrase of the consuming wallet: ");
+                let passphrase = Bootstrapper::prompt_secret (streams, terminal, "Passphrase set along with the recovery phrase (blank if none): ");
+                private_key_from_mnemonic (&phrase, &passphrase, &config.derivation_path)
+                    .unwrap_or_else (|e| panic! ("Can't recover the consuming wallet: {}", e))
+            }
+        };
+        let password = Bootstrapper::prompt_secret (streams, terminal, "Password to seal the consuming wallet with: ");
+        if password.is_empty () {panic! ("The consuming wallet has to be sealed with a password")}
+        let store = WalletStore::in_data_directory (&data_directory, &password);
+        store.save (&private_key).unwrap_or_else (|e| panic! ("{}", e));
+        writeln! (streams.stdout, "Sealed the consuming wallet into {:?}; from now on its password alone unlocks it", store.path ()).expect ("Internal error");
+        config.blockchain_bridge_config.consuming_private_key_opt = Some (private_key);
+    }
+
+    fn report_generated_consuming_wallet (streams: &mut StdStreams, phrase: &str, private_key: &str) {
+        let signer = Signer::from_private_key (private_key).expect ("Internal error");
+        writeln! (streams.stdout, "New consuming wallet: {}", signer.wallet ()).expect ("Internal error");
+        writeln! (streams.stdout, "Its recovery phrase, which won't be shown again: {}", phrase).expect ("Internal error");
+        writeln! (streams.stdout, "Write the phrase down; if the data directory is lost, --recover_consuming_wallet and the phrase are the only way back to the wallet").expect ("Internal error");
+    }
+
+    // A consuming wallet sealed into the data directory is unlocked by a password asked for at startup
+    fn establish_consuming_wallet (streams: &mut StdStreams, terminal: &Terminal, config: &mut BootstrapperConfig) {
+        // Just sealed, and so already unlocked
+        if config.blockchain_bridge_config.consuming_private_key_opt.is_some () {return}
+        let data_directory = match config.data_directory_opt {
+            Some (ref data_directory) => data_directory.clone (),
+            None => return
+        };
+        // The signing service has the key; any sealed here is left locked
+        if config.blockchain_bridge_config.signing_service_url_opt.is_some () {return}
+        if !WalletStore::exists_in (&data_directory) {return}
+        let password = Bootstrapper::prompt_secret (streams, terminal, "Password to unlock the consuming wallet (blank to leave it locked): ");
+        if password.is_empty () {
+            writeln! (streams.stderr, "Consuming wallet left locked; this Node can't pay other Nodes").expect ("Internal error");
+            return
+        }
+        match WalletStore::in_data_directory (&data_directory, &password).load () {
+            Ok (private_key_opt) => config.blockchain_bridge_config.consuming_private_key_opt = private_key_opt,
+            Err (e) => panic! ("{}", e)
+        }
+    }
+
+    // The identity in the data directory is opened with a passphrase asked for at startup. Left
+    // blank, the Node has a new identity every time it starts.
+    fn establish_identity_passphrase (streams: &mut StdStreams, terminal: &Terminal, config: &mut BootstrapperConfig) {
+        if config.data_directory_opt.is_none () {return}
+        let passphrase = Bootstrapper::prompt_secret (streams, terminal, "Passphrase for this Node's identity (blank for a new identity every time it starts): ");
+        if !passphrase.is_empty () {
+            config.identity_passphrase_opt = Some (passphrase)
+        }
+    }
+
+    // One line from standard input, without its line ending
+    fn prompt (streams: &mut StdStreams, question: &str) -> String {
+        write! (streams.stdout, "{}", question).expect ("Internal error");
+        streams.stdout.flush ().expect ("Internal error");
+        let mut answer = vec! ();
+        let mut byte = [0u8; 1];
+        while let Ok (1) = streams.stdin.read (&mut byte) {
+            if byte[0] == b'\n' {break}
+            answer.push (byte[0]);
+        }
+        String::from_utf8 (answer).expect ("Answer is not UTF-8").trim_right_matches ('\r').to_string ()
+    }
+
+    // As prompt (), but what's typed isn't shown; the line ending that isn't echoed either is
+    // written in its place
+    fn prompt_secret (streams: &mut StdStreams, terminal: &Terminal, question: &str) -> String {
+        terminal.set_echo (false);
+        let answer = Bootstrapper::prompt (streams, question);
+        terminal.set_echo (true);
+        writeln! (streams.stdout).expect ("Internal error");
+        answer
+    }
+
+    fn initialize_and_report_cryptde (streams: &mut StdStreams, terminal: &Terminal, config: &BootstrapperConfig) -> &'static CryptDE {
+        let exemplar = CryptDERotating::new (Bootstrapper::make_cryptde (streams, terminal, config), Bootstrapper::cryptde_factory (config.null_cryptde));
+        let cryptde: &'static CryptDE = unsafe {
             CRYPT_DE_OPT = Some(exemplar);
             CRYPT_DE_OPT.as_ref().expect("Internal error")
         };
         let public_key_base64 = base64::encode (&cryptde.public_key ().data);
         writeln! (streams.stdout, "Substratum Node public key: {}", public_key_base64).expect ("Internal error");
+        cryptde
+    }
+
+    fn cryptde_factory (null_cryptde: bool) -> fn () -> Box<CryptDE> {
+        fn make_null () -> Box<CryptDE> {Box::new (CryptDENull::new ())}
+        fn make_real () -> Box<CryptDE> {Box::new (CryptDEReal::new ())}
+        if null_cryptde {make_null as fn () -> Box<CryptDE>} else {make_real}
+    }
+
+    fn make_cryptde (streams: &mut StdStreams, terminal: &Terminal, config: &BootstrapperConfig) -> Box<CryptDE> {
+        if config.null_cryptde {
+            writeln! (streams.stderr, "WARNING: --null_cryptde is on; this Node's traffic is neither encrypted nor authenticated").expect ("Internal error");
+        }
+        let mut cryptde = Bootstrapper::cryptde_factory (config.null_cryptde) ();
+        let store = match (&config.data_directory_opt, &config.identity_passphrase_opt) {
+            (&Some (ref data_directory), &Some (ref passphrase)) => IdentityStore::in_data_directory (data_directory, passphrase),
+            _ => {
+                if config.identity_backup_opt.is_some () || config.identity_restore_opt.is_some () {
+                    panic! ("--backup_identity and --restore_identity need --data_directory and an identity passphrase")
+                }
+                if config.data_directory_opt.is_some () {
+                    writeln! (streams.stderr, "No identity passphrase; this Node will have a new identity every time it starts").expect ("Internal error");
+                }
+                cryptde.generate_key_pair ();
+                return cryptde
+            }
+        };
+        let passphrase = config.identity_passphrase_opt.as_ref ().expect ("Internal error");
+        if let Some (ref restore_path) = config.identity_restore_opt {
+            let private_key = match IdentityStore::new (restore_path, passphrase).load () {
+                Ok (Some (private_key)) => private_key,
+                Ok (None) => panic! ("No identity backup at {:?}", restore_path),
+                Err (e) => panic! ("{}", e)
+            };
+            Bootstrapper::confirm_identity_replacement (streams, terminal, &store, &private_key, restore_path);
+            store.save (&private_key).unwrap_or_else (|e| panic! ("{}", e));
+            writeln! (streams.stdout, "Restored this Node's identity from {:?}", restore_path).expect ("Internal error");
+        }
+        match store.load () {
+            Ok (Some (private_key)) => cryptde.adopt_private_key (&private_key)
+                .unwrap_or_else (|e| panic! ("{:?} does not hold a usable identity: {:?}", store.path (), e)),
+            Ok (None) => {
+                cryptde.generate_key_pair ();
+                store.save (&cryptde.private_key ()).unwrap_or_else (|e| panic! ("{}", e));
+            },
+            Err (e) => panic! ("{}", e)
+        }
+        if let Some (ref backup_path) = config.identity_backup_opt {
+            IdentityStore::new (backup_path, passphrase).save (&cryptde.private_key ()).unwrap_or_else (|e| panic! ("{}", e));
+            writeln! (streams.stdout, "Backed up this Node's identity to {:?}", backup_path).expect ("Internal error");
+        }
+        cryptde
+    }
+
+    // Restoring over a different identity loses that one for good, so it takes a yes typed at a terminal
+    fn confirm_identity_replacement (streams: &mut StdStreams, terminal: &Terminal, store: &IdentityStore, private_key: &Key, restore_path: &PathBuf) {
+        match store.load () {
+            Ok (Some (ref existing)) if existing != private_key => (),
+            Ok (_) => return,
+            Err (e) => panic! ("{}", e)
+        }
+        let question = format! ("{:?} already holds another identity, which restoring {:?} replaces for good; type yes to go ahead: ", store.path (), restore_path);
+        if !terminal.is_interactive () || (Bootstrapper::prompt (streams, &question) != "yes") {
+            panic! ("Didn't restore {:?}: {:?} already holds another identity", restore_path, store.path ())
+        }
     }
 }
 
@@ -151,6 +1364,8 @@ impl Bootstrapper {
 mod tests {
     use super::*;
     use std::cell::RefCell;
+    use std::env::temp_dir;
+    use std::fs;
     use std::io;
     use std::io::Error;
     use std::io::ErrorKind;
@@ -161,15 +1376,20 @@ mod tests {
     use std::sync::Arc;
     use std::sync::mpsc;
     use std::sync::Mutex;
+    use actix::Arbiter;
     use actix::Recipient;
     use actix::Syn;
     use actix::System;
     use discriminator::DiscriminatorFactory;
+    use public_ip_monitor::PublicIpFinder;
     use node_test_utils::extract_log;
     use node_test_utils::make_stream_handler_pool_subs_from;
     use node_test_utils::TcpStreamWrapperMock;
     use node_test_utils::TestLogOwner;
     use stream_handler_pool::AddStreamMsg;
+    use sub_lib::peer_actors::PeerActors;
+    use test_utils::test_utils::make_peer_actors_from;
+    use test_utils::test_utils::ByteArrayReader;
     use test_utils::test_utils::FakeStreamHolder;
     use test_utils::test_utils::RecordAwaiter;
     use test_utils::test_utils::Recorder;
@@ -177,6 +1397,7 @@ mod tests {
     use test_utils::test_utils::TestLog;
     use regex::Regex;
     use sub_lib::cryptde::PlainData;
+    use sub_lib::identity_store::IDENTITY_FILENAME;
 
     struct ListenerHandlerFactoryMock {
         log: TestLog,
@@ -186,9 +1407,12 @@ mod tests {
     unsafe impl Sync for ListenerHandlerFactoryMock {}
 
     impl ListenerHandlerFactory for ListenerHandlerFactoryMock {
+        // Listeners a test didn't ask for bind without complaint
         fn make(&self) -> Box<ListenerHandler> {
             self.log.log (format! ("make ()"));
-            Box::new (self.mocks.borrow_mut ().remove (0))
+            let mut mocks = self.mocks.borrow_mut ();
+            if mocks.is_empty () {return Box::new (ListenerHandlerNull::new (vec! ()).bind_port_result (Ok (())))}
+            Box::new (mocks.remove (0))
         }
     }
 
@@ -207,17 +1431,17 @@ mod tests {
 
     struct ListenerHandlerNull {
         log: Arc<Mutex<TestLog>>,
-        bind_port_and_discriminator_factories_result: Option<io::Result<()>>,
+        bind_address_and_discriminator_factories_result: Option<io::Result<()>>,
         discriminator_factories_parameter: Option<Vec<Box<DiscriminatorFactory>>>,
         add_stream_sub: Option<Recipient<Syn, AddStreamMsg>>,
         add_stream_msgs: Arc<Mutex<Vec<AddStreamMsg>>>
     }
 
     impl ListenerHandler for ListenerHandlerNull {
-        fn bind_port_and_discriminator_factories (&mut self, port: u16, discriminator_factories: Vec<Box<DiscriminatorFactory>>) -> io::Result<()> {
-            self.log.lock ().unwrap ().log (format! ("bind_port_and_discriminator_factories ({}, ...)", port));
+        fn bind_address_and_discriminator_factories (&mut self, socket_addr: SocketAddr, discriminator_factories: Vec<Box<DiscriminatorFactory>>) -> io::Result<()> {
+            self.log.lock ().unwrap ().log (format! ("bind_address_and_discriminator_factories ({}, ...)", socket_addr));
             self.discriminator_factories_parameter = Some (discriminator_factories);
-            self.bind_port_and_discriminator_factories_result.take ().unwrap ()
+            self.bind_address_and_discriminator_factories_result.take ().unwrap ()
         }
 
         fn bind_subs (&mut self, add_stream_sub: Recipient<Syn, AddStreamMsg>) {
@@ -244,7 +1468,7 @@ mod tests {
         fn new (add_stream_msgs: Vec<AddStreamMsg>) -> ListenerHandlerNull {
             ListenerHandlerNull {
                 log: Arc::new (Mutex::new (TestLog::new ())),
-                bind_port_and_discriminator_factories_result: None,
+                bind_address_and_discriminator_factories_result: None,
                 discriminator_factories_parameter: None,
                 add_stream_sub: None,
                 add_stream_msgs: Arc::new (Mutex::new (add_stream_msgs))
@@ -252,7 +1476,7 @@ mod tests {
         }
 
         fn bind_port_result(mut self, result: io::Result<()>) -> ListenerHandlerNull {
-            self.bind_port_and_discriminator_factories_result = Some (result);
+            self.bind_address_and_discriminator_factories_result = Some (result);
             self
         }
     }
@@ -346,19 +1570,1198 @@ mod tests {
         let args: Vec<String> = vec! (
             "--irrelevant", "irrelevant",
             "--dns_servers", "12.34.56.78,23.45.67.89",
+            "--mode", "originate_only",
             "--irrelevant", "irrelevant",
             "--neighbor", "QmlsbA;1.2.3.4;1234,2345",
             "--neighbor", "VGVk;2.3.4.5;3456,4567",
+            "--ip", "4.3.2.1",
+            "--public_hostname", "node.example.com",
+            "--ip_check_interval", "60000",
+            "--port_mapping", "natpmp",
+            "--clandestine_transport", "plain",
+            "--max_response_size", "1048576",
+            "--min_hops", "2",
+            "--max_hops", "4",
+            "--min_neighbors", "3",
+            "--target_neighbors", "6",
+            "--max_neighbors", "12",
+            "--gossip_interval", "15000",
+            "--heartbeat_interval", "2500",
+            "--stale_node_window", "600000",
+            "--data_directory", "/var/lib/substratum",
+            "--backup_identity", "/media/backup/identity.key",
+            "--restore_identity", "/media/old/identity.key",
+            "--ban", "QmFk",
+            "--ban", "VWdseQ;share",
+            "--geolocation_database", "/usr/share/substratum/geolocation.csv",
+            "--exit_location", "de,NL,!us",
+            "--route_diversity", "require",
+            "--padding", "on",
+            "--cover_traffic_interval", "500",
+            "--mix_delay", "uniform:20-80",
+            "--compression", "on",
+            "--null_cryptde", "on",
+            "--key_rotation_interval", "86400000",
+            "--key_overlap", "3600000",
+            "--payable_scan_interval", "600000",
+            "--payment_threshold", "5000000",
+            "--payment_age_threshold", "86400000",
+            "--payment_retry", "30000",
+            "--receivable_scan_interval", "900000",
+            "--free_bytes", "5000000",
+            "--free_period", "43200000",
+            "--free_throttle_debt", "50000",
+            "--free_refusal_debt", "500000",
+            "--debt_ceiling", "20000000",
+            "--channel_deposit", "20000000",
+            "--channel_min_payments", "5",
+            "--channel_lifetime", "604800000",
+            "--invoice_interval", "900000",
+            "--routing_service_rate", "150",
+            "--routing_byte_rate", "2",
+            "--exit_service_rate", "250",
+            "--exit_byte_rate", "3",
+            "--price_oracle", "https://prices.example.com/sub#usd",
+            "--fiat_payment_threshold", "2000",
+            "--fiat_max_debt", "3000",
+            "--max_route_cost", "1000,10",
+            "--blockchain_service_url", "http://localhost:8545",
+            "--chain_id", "3",
+            "--sub_contract_address", "0x3535353535353535353535353535353535353535",
+            "--channel_contract", "0x5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a",
+            "--import_consuming_wallet", "on",
+            "--earning_wallet", "0x5757575757575757575757575757575757575757",
+            "--payment_watch_interval", "15000",
+            "--gas_price", "4",
+            "--max_gas_price", "20",
+            "--confirmations", "6",
             "--irrelevant", "irrelevant"
         ).into_iter ().map (String::from).collect ();
 
         let config = Bootstrapper::parse_args (&args);
 
+        assert_eq! (config.mode, NodeMode::OriginateOnly);
         assert_eq! (config.dns_servers, vec! (SocketAddr::from_str ("12.34.56.78:53").unwrap (), SocketAddr::from_str ("23.45.67.89:53").unwrap ()));
         assert_eq! (config.neighbor_configs, vec! (
             (Key::new (b"Bill"), NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap (), &vec! (1234, 2345))),
             (Key::new (b"Ted"), NodeAddr::new (&IpAddr::from_str ("2.3.4.5").unwrap (), &vec! (3456, 4567))),
         ));
+        assert_eq! (config.ip_addr_opt, Some (IpAddr::from_str ("4.3.2.1").unwrap ()));
+        assert_eq! (config.public_hostname_opt, Some (String::from ("node.example.com")));
+        assert_eq! (config.ip_check_interval_ms, 60000);
+        assert_eq! (config.port_mapping_opt, Some (PortMappingProtocol::NatPmp));
+        assert_eq! (config.max_response_size, 1048576);
+        assert_eq! (config.min_hops, 2);
+        assert_eq! (config.max_hops, 4);
+        assert_eq! (config.min_neighbors, 3);
+        assert_eq! (config.target_neighbors, 6);
+        assert_eq! (config.max_neighbors, 12);
+        assert_eq! (config.gossip_interval_ms, 15000);
+        assert_eq! (config.heartbeat_interval_ms, 2500);
+        assert_eq! (config.stale_node_window_ms, 600000);
+        assert_eq! (config.data_directory_opt, Some (PathBuf::from ("/var/lib/substratum")));
+        assert_eq! (config.identity_passphrase_opt, None);
+        assert_eq! (config.identity_backup_opt, Some (PathBuf::from ("/media/backup/identity.key")));
+        assert_eq! (config.identity_restore_opt, Some (PathBuf::from ("/media/old/identity.key")));
+        assert_eq! (config.import_consuming_wallet, true);
+        assert_eq! (config.bans, vec! (
+            BanNodeMsg {public_key: Key::new (b"Bad"), reason: String::from ("Banned by operator"), share: false},
+            BanNodeMsg {public_key: Key::new (b"Ugly"), reason: String::from ("Banned by operator"), share: true},
+        ));
+        assert_eq! (config.geolocation_database_opt, Some (PathBuf::from ("/usr/share/substratum/geolocation.csv")));
+        assert_eq! (config.exit_location, ExitLocation {
+            countries: vec! (String::from ("DE"), String::from ("NL")),
+            avoided_countries: vec! (String::from ("US")),
+        });
+        assert_eq! (config.route_diversity, RouteDiversity::Require);
+        assert_eq! (config.max_route_cost_opt, Some (RouteCost {per_package: 1000, per_byte: 10}));
+        assert_eq! (config.pad_packages, true);
+        assert_eq! (config.cover_traffic_interval_ms, 500);
+        assert_eq! (config.mix_delay, MixDelay::Uniform {min_ms: 20, max_ms: 80});
+        assert_eq! (config.compress_packages, true);
+        assert_eq! (config.null_cryptde, true);
+        assert_eq! (config.key_rotation_interval_ms, 86400000);
+        assert_eq! (config.key_overlap_ms, 3600000);
+        assert_eq! (config.accountant_config, AccountantConfig {
+            payable_scan_interval_ms: 600000,
+            payment_threshold: 5000000,
+            payment_age_threshold_ms: 86400000,
+            payment_retry_ms: 30000,
+            receivable_scan_interval_ms: 900000,
+            delinquency_curve: DEFAULT_DELINQUENCY_CURVE,
+            free_tier: FreeTier {bytes: 5000000, period_ms: 43200000, throttle_debt: 50000, refusal_debt: 500000},
+            debt_ceiling: 20000000,
+            channel_deposit: 20000000,
+            channel_min_payments: 5,
+            channel_lifetime_ms: 604800000,
+            invoice_interval_ms: 900000,
+            rates: RateSchedule {routing_service_rate: 150, routing_byte_rate: 2, exit_service_rate: 250, exit_byte_rate: 3},
+            price_oracle_url_opt: Some (String::from ("https://prices.example.com/sub#usd")),
+            fiat_thresholds_opt: Some (FiatThresholds {payment_threshold: 2000, max_debt: 3000, min_debt: DEFAULT_FIAT_THRESHOLDS.min_debt}),
+        });
+        assert_eq! (config.blockchain_bridge_config, BlockchainBridgeConfig {
+            blockchain_service_url_opt: Some (String::from ("http://localhost:8545")),
+            chain_id: 3,
+            sub_contract_address: Wallet::new ("0x3535353535353535353535353535353535353535").unwrap (),
+            channel_contract_opt: Some (Wallet::new ("0x5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a").unwrap ()),
+            consuming_private_key_opt: None,
+            signing_service_url_opt: None,
+            signing_wallet_opt: None,
+            earning_wallet_opt: Some (Wallet::new ("0x5757575757575757575757575757575757575757").unwrap ()),
+            payment_watch_interval_ms: 15000,
+            gas_price_opt: Some (4000000000),
+            max_gas_price: 20000000000,
+            confirmations: 6,
+        });
+    }
+
+    #[test]
+    fn accountant_config_has_defaults () {
+        let finder = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+
+        assert_eq! (Bootstrapper::parse_accountant_config (&finder), AccountantConfig {
+            payable_scan_interval_ms: DEFAULT_PAYABLE_SCAN_INTERVAL_MS,
+            payment_threshold: DEFAULT_PAYMENT_THRESHOLD,
+            payment_age_threshold_ms: DEFAULT_PAYMENT_AGE_THRESHOLD_MS,
+            payment_retry_ms: DEFAULT_PAYMENT_RETRY_MS,
+            receivable_scan_interval_ms: DEFAULT_RECEIVABLE_SCAN_INTERVAL_MS,
+            delinquency_curve: DEFAULT_DELINQUENCY_CURVE,
+            free_tier: DEFAULT_FREE_TIER,
+            debt_ceiling: DEFAULT_DEBT_CEILING,
+            channel_deposit: DEFAULT_CHANNEL_DEPOSIT,
+            channel_min_payments: DEFAULT_CHANNEL_MIN_PAYMENTS,
+            channel_lifetime_ms: DEFAULT_CHANNEL_LIFETIME_MS,
+            invoice_interval_ms: DEFAULT_INVOICE_INTERVAL_MS,
+            rates: DEFAULT_RATES,
+            price_oracle_url_opt: None,
+            fiat_thresholds_opt: None,
+        });
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --payment_threshold <amount>: 'lots'")]
+    fn parse_accountant_config_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--payment_threshold"), String::from ("lots")));
+
+        Bootstrapper::parse_accountant_config (&finder);
+    }
+
+    #[test]
+    fn blockchain_bridge_config_defaults_to_the_main_network_without_a_blockchain_service () {
+        let finder = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+
+        assert_eq! (Bootstrapper::parse_blockchain_bridge_config (&finder), BlockchainBridgeConfig {
+            blockchain_service_url_opt: None,
+            chain_id: MAINNET_CHAIN_ID,
+            sub_contract_address: Wallet::new (SUB_CONTRACT_ADDRESS).unwrap (),
+            channel_contract_opt: None,
+            consuming_private_key_opt: None,
+            signing_service_url_opt: None,
+            signing_wallet_opt: None,
+            earning_wallet_opt: None,
+            payment_watch_interval_ms: DEFAULT_PAYMENT_WATCH_INTERVAL_MS,
+            gas_price_opt: None,
+            max_gas_price: DEFAULT_MAX_GAS_PRICE,
+            confirmations: DEFAULT_CONFIRMATIONS,
+        });
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --sub_contract_address <address>: 'SUB'")]
+    fn parse_blockchain_bridge_config_complains_about_bad_contract_addresses () {
+        let finder = ParameterFinder::new (vec! (String::from ("--sub_contract_address"), String::from ("SUB")));
+
+        Bootstrapper::parse_blockchain_bridge_config (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --earning_wallet <address>: 'mattress'")]
+    fn parse_blockchain_bridge_config_complains_about_bad_earning_wallets () {
+        let finder = ParameterFinder::new (vec! (String::from ("--earning_wallet"), String::from ("mattress")));
+
+        Bootstrapper::parse_blockchain_bridge_config (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --max_gas_price <gwei>: 'cheap'")]
+    fn parse_blockchain_bridge_config_complains_about_bad_gas_prices () {
+        let finder = ParameterFinder::new (vec! (String::from ("--max_gas_price"), String::from ("cheap")));
+
+        Bootstrapper::parse_blockchain_bridge_config (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "--gas_price can't be more than --max_gas_price (50 gwei)")]
+    fn parse_blockchain_bridge_config_refuses_a_gas_price_over_the_limit () {
+        let finder = ParameterFinder::new (vec! (String::from ("--gas_price"), String::from ("51")));
+
+        Bootstrapper::parse_blockchain_bridge_config (&finder);
+    }
+
+    const PHRASE: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    struct TerminalMock {
+        interactive: bool,
+        echoes: RefCell<Vec<bool>>,
+    }
+
+    impl Terminal for TerminalMock {
+        fn is_interactive (&self) -> bool {
+            self.interactive
+        }
+
+        fn set_echo (&self, on: bool) {
+            self.echoes.borrow_mut ().push (on);
+        }
+    }
+
+    impl TerminalMock {
+        fn new (interactive: bool) -> TerminalMock {
+            TerminalMock {interactive, echoes: RefCell::new (vec! ())}
+        }
+    }
+
+    fn establish_new_consuming_wallet_from_config (holder: &mut FakeStreamHolder, terminal: &TerminalMock, config: &mut BootstrapperConfig) {
+        Bootstrapper::establish_new_consuming_wallet (&mut holder.streams (), terminal, config);
+        Bootstrapper::establish_consuming_wallet (&mut holder.streams (), terminal, config);
+    }
+
+    #[test]
+    fn the_consuming_wallet_is_recovered_from_a_recovery_phrase_asked_for_at_startup_and_sealed () {
+        let directory = make_identity_directory ("the_consuming_wallet_is_recovered_from_a_recovery_phrase_asked_for_at_startup_and_sealed");
+        let mut holder = FakeStreamHolder::new ();
+        holder.stdin = ByteArrayReader::new (format! ("{}\nTREZOR\ncorrect horse\n", PHRASE).as_bytes ());
+        let terminal = TerminalMock::new (true);
+        let mut config = Bootstrapper::parse_args (&vec! ("--data_directory", directory.to_str ().unwrap (),
+            "--recover_consuming_wallet", "on", "--derivation_path", "m/44'/60'/0'/0/3").into_iter ().map (String::from).collect ());
+
+        establish_new_consuming_wallet_from_config (&mut holder, &terminal, &mut config);
+
+        let private_key = private_key_from_mnemonic (PHRASE, "TREZOR", "m/44'/60'/0'/0/3").unwrap ();
+        assert_eq! (config.blockchain_bridge_config.consuming_private_key_opt, Some (private_key.clone ()));
+        assert_eq! (WalletStore::in_data_directory (&directory, "correct horse").load (), Ok (Some (private_key)));
+        let stdout = holder.stdout.get_string ();
+        assert_eq! (stdout.starts_with ("Recovery phrase of the consuming wallet: \nPassphrase set along with the recovery phrase (blank if none): \nPassword to seal the consuming wallet with: \n"), true);
+        assert_eq! (stdout.contains (PHRASE), false);
+        assert_eq! (*terminal.echoes.borrow (), vec! (false, true, false, true, false, true));
+    }
+
+    #[test]
+    fn the_consuming_wallet_is_imported_from_a_private_key_asked_for_at_startup_and_sealed () {
+        let directory = make_identity_directory ("the_consuming_wallet_is_imported_from_a_private_key_asked_for_at_startup_and_sealed");
+        let mut holder = FakeStreamHolder::new ();
+        holder.stdin = ByteArrayReader::new (format! ("{}\ncorrect horse\n", PRIVATE_KEY).as_bytes ());
+        let terminal = TerminalMock::new (true);
+        let mut config = Bootstrapper::parse_args (&vec! ("--data_directory", directory.to_str ().unwrap (),
+            "--import_consuming_wallet", "on").into_iter ().map (String::from).collect ());
+
+        establish_new_consuming_wallet_from_config (&mut holder, &terminal, &mut config);
+
+        assert_eq! (config.blockchain_bridge_config.consuming_private_key_opt, Some (String::from (PRIVATE_KEY)));
+        assert_eq! (WalletStore::in_data_directory (&directory, "correct horse").load (), Ok (Some (String::from (PRIVATE_KEY))));
+        let stdout = holder.stdout.get_string ();
+        assert_eq! (stdout.starts_with ("Private key of the consuming wallet, in hex: \nPassword to seal the consuming wallet with: \n"), true);
+        assert_eq! (stdout.contains (PRIVATE_KEY), false);
+        assert_eq! (*terminal.echoes.borrow (), vec! (false, true, false, true));
+    }
+
+    #[test]
+    #[should_panic (expected = "Can't import the consuming wallet")]
+    fn an_imported_private_key_has_to_be_one () {
+        let directory = make_identity_directory ("an_imported_private_key_has_to_be_one");
+        let mut holder = FakeStreamHolder::new ();
+        holder.stdin = ByteArrayReader::new (b"booga\ncorrect horse\n");
+        let mut config = Bootstrapper::parse_args (&vec! ("--data_directory", directory.to_str ().unwrap (),
+            "--import_consuming_wallet", "on").into_iter ().map (String::from).collect ());
+
+        establish_new_consuming_wallet_from_config (&mut holder, &TerminalMock::new (true), &mut config);
+    }
+
+    #[test]
+    fn a_generated_recovery_phrase_makes_the_consuming_wallet_which_is_sealed () {
+        let directory = make_identity_directory ("a_generated_recovery_phrase_makes_the_consuming_wallet_which_is_sealed");
+        let mut holder = FakeStreamHolder::new ();
+        holder.stdin = ByteArrayReader::new (b"correct horse\n");
+        let mut config = Bootstrapper::parse_args (&vec! ("--data_directory", directory.to_str ().unwrap (),
+            "--generate_consuming_wallet", "12").into_iter ().map (String::from).collect ());
+        let phrase = config.generated_mnemonic_opt.clone ().unwrap ();
+
+        establish_new_consuming_wallet_from_config (&mut holder, &TerminalMock::new (true), &mut config);
+
+        let private_key = private_key_from_mnemonic (&phrase, "", DEFAULT_DERIVATION_PATH).unwrap ();
+        assert_eq! (phrase.split (' ').count (), 12);
+        assert_eq! (config.blockchain_bridge_config.consuming_private_key_opt, Some (private_key.clone ()));
+        assert_eq! (WalletStore::in_data_directory (&directory, "correct horse").load (), Ok (Some (private_key)));
+        let stdout = holder.stdout.get_string ();
+        assert_eq! (stdout.contains (&format! ("Its recovery phrase, which won't be shown again: {}\n", phrase)), true);
+        assert_eq! (stdout.contains ("Sealed the consuming wallet into"), true);
+    }
+
+    #[test]
+    #[should_panic (expected = "A consuming wallet's secrets are only shown or asked for at a terminal; start the Node from one, without --daemon")]
+    fn a_recovery_phrase_is_not_shown_away_from_a_terminal () {
+        let directory = make_identity_directory ("a_recovery_phrase_is_not_shown_away_from_a_terminal");
+        let mut holder = FakeStreamHolder::new ();
+        let mut config = Bootstrapper::parse_args (&vec! ("--data_directory", directory.to_str ().unwrap (),
+            "--generate_consuming_wallet", "12").into_iter ().map (String::from).collect ());
+
+        establish_new_consuming_wallet_from_config (&mut holder, &TerminalMock::new (false), &mut config);
+    }
+
+    #[test]
+    #[should_panic (expected = "--generate_consuming_wallet, --recover_consuming_wallet and --import_consuming_wallet need a --data_directory to seal the wallet into")]
+    fn a_wallet_from_a_recovery_phrase_needs_somewhere_to_be_sealed () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut config = Bootstrapper::parse_args (&vec! ("--recover_consuming_wallet", "on").into_iter ().map (String::from).collect ());
+
+        establish_new_consuming_wallet_from_config (&mut holder, &TerminalMock::new (true), &mut config);
+    }
+
+    #[test]
+    #[should_panic (expected = "already holds a consuming wallet; move it aside to replace it")]
+    fn a_sealed_consuming_wallet_is_not_overwritten () {
+        let directory = make_identity_directory ("a_sealed_consuming_wallet_is_not_overwritten");
+        WalletStore::in_data_directory (&directory, "correct horse").save (PRIVATE_KEY).unwrap ();
+        let mut holder = FakeStreamHolder::new ();
+        holder.stdin = ByteArrayReader::new (b"5757575757575757575757575757575757575757575757575757575757575757\ncorrect horse\n");
+        let mut config = Bootstrapper::parse_args (&vec! ("--data_directory", directory.to_str ().unwrap (),
+            "--import_consuming_wallet", "on").into_iter ().map (String::from).collect ());
+
+        establish_new_consuming_wallet_from_config (&mut holder, &TerminalMock::new (true), &mut config);
+    }
+
+    #[test]
+    #[should_panic (expected = "The consuming wallet has to be sealed with a password")]
+    fn a_new_consuming_wallet_needs_a_password () {
+        let directory = make_identity_directory ("a_new_consuming_wallet_needs_a_password");
+        let mut holder = FakeStreamHolder::new ();
+        holder.stdin = ByteArrayReader::new (format! ("{}\n\n", PRIVATE_KEY).as_bytes ());
+        let mut config = Bootstrapper::parse_args (&vec! ("--data_directory", directory.to_str ().unwrap (),
+            "--import_consuming_wallet", "on").into_iter ().map (String::from).collect ());
+
+        establish_new_consuming_wallet_from_config (&mut holder, &TerminalMock::new (true), &mut config);
+    }
+
+    #[test]
+    fn the_generated_consuming_wallet_is_reported_with_its_recovery_phrase () {
+        let mut holder = FakeStreamHolder::new ();
+        let private_key = private_key_from_mnemonic (PHRASE, "", DEFAULT_DERIVATION_PATH).unwrap ();
+
+        Bootstrapper::report_generated_consuming_wallet (&mut holder.streams (), PHRASE, &private_key);
+
+        let stdout = holder.stdout.get_string ();
+        assert_eq! (stdout.contains ("New consuming wallet: 0x9858effd232b4033e47d90003d41ec34ecaeda94\n"), true);
+        assert_eq! (stdout.contains (PHRASE), true);
+    }
+
+    #[test]
+    fn a_consuming_wallet_can_be_signed_for_off_this_machine () {
+        let finder = ParameterFinder::new (vec! (
+            "--signing_service_url", "http://localhost:8550",
+            "--signing_wallet", "0x9D8A62F656A8D1615C1294FD71E9CFB3E4855A4F",
+        ).into_iter ().map (String::from).collect ());
+
+        let config = Bootstrapper::parse_blockchain_bridge_config (&finder);
+
+        assert_eq! (config.signing_service_url_opt, Some (String::from ("http://localhost:8550")));
+        assert_eq! (config.signing_wallet_opt, Some (Wallet::new ("0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f").unwrap ()));
+        assert_eq! (config.consuming_private_key_opt, None);
+    }
+
+    #[test]
+    #[should_panic (expected = "--signing_service_url and --signing_wallet go together")]
+    fn a_signing_service_needs_to_be_told_the_wallet () {
+        let finder = ParameterFinder::new (vec! (String::from ("--signing_service_url"), String::from ("http://localhost:8550")));
+
+        Bootstrapper::parse_blockchain_bridge_config (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "A consuming wallet can't be kept here and by a --signing_service_url both")]
+    fn a_consuming_wallet_is_not_kept_here_and_off_this_machine_both () {
+        Bootstrapper::parse_args (&vec! (
+            "--signing_service_url", "http://localhost:8550",
+            "--signing_wallet", "0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f",
+            "--import_consuming_wallet", "on",
+        ).into_iter ().map (String::from).collect ());
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --generate_consuming_wallet <12 or 24>: A recovery phrase has 12 or 24 words, not 13")]
+    fn parse_generate_consuming_wallet_complains_about_odd_lengths () {
+        let finder = ParameterFinder::new (vec! (String::from ("--generate_consuming_wallet"), String::from ("13")));
+
+        Bootstrapper::parse_generate_consuming_wallet (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "Only one of --generate_consuming_wallet, --recover_consuming_wallet and --import_consuming_wallet can be given")]
+    fn parse_args_refuses_two_consuming_wallets () {
+        Bootstrapper::parse_args (&vec! (
+            "--import_consuming_wallet", "on",
+            "--recover_consuming_wallet", "on",
+        ).into_iter ().map (String::from).collect ());
+    }
+
+    #[test]
+    #[should_panic (expected = "Can't recover the consuming wallet: Not a derivation path: 'm/x'")]
+    fn establish_new_consuming_wallet_complains_about_bad_derivation_paths () {
+        let directory = make_identity_directory ("establish_new_consuming_wallet_complains_about_bad_derivation_paths");
+        let mut holder = FakeStreamHolder::new ();
+        let mut config = Bootstrapper::parse_args (&vec! ("--data_directory", directory.to_str ().unwrap (),
+            "--generate_consuming_wallet", "12", "--derivation_path", "m/x").into_iter ().map (String::from).collect ());
+
+        establish_new_consuming_wallet_from_config (&mut holder, &TerminalMock::new (true), &mut config);
+    }
+
+    #[test]
+    fn gossip_interval_has_a_default () {
+        let finder = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+
+        assert_eq! (Bootstrapper::parse_gossip_interval (&finder), DEFAULT_GOSSIP_INTERVAL_MS);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --gossip_interval <milliseconds>: 'hourly'")]
+    fn parse_gossip_interval_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--gossip_interval"), String::from ("hourly")));
+
+        Bootstrapper::parse_gossip_interval (&finder);
+    }
+
+    #[test]
+    fn heartbeat_interval_has_a_default () {
+        let finder = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+
+        assert_eq! (Bootstrapper::parse_heartbeat_interval (&finder), DEFAULT_HEARTBEAT_INTERVAL_MS);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --heartbeat_interval <milliseconds>: 'often'")]
+    fn parse_heartbeat_interval_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--heartbeat_interval"), String::from ("often")));
+
+        Bootstrapper::parse_heartbeat_interval (&finder);
+    }
+
+    #[test]
+    fn stale_node_window_has_a_default () {
+        let finder = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+
+        assert_eq! (Bootstrapper::parse_stale_node_window (&finder), DEFAULT_STALE_NODE_WINDOW_MS);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --stale_node_window <milliseconds>: 'forever'")]
+    fn parse_stale_node_window_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--stale_node_window"), String::from ("forever")));
+
+        Bootstrapper::parse_stale_node_window (&finder);
+    }
+
+    #[test]
+    fn nothing_is_persisted_without_a_data_directory () {
+        let finder = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+
+        assert_eq! (Bootstrapper::parse_data_directory (&finder), None);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid Base64 for --ban <public key>: 'bad_key'")]
+    fn parse_bans_complains_about_bad_base_64 () {
+        let finder = ParameterFinder::new (vec! (String::from ("--ban"), String::from ("bad_key")));
+
+        Bootstrapper::parse_bans (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "--ban <public key>[;share] where 'share' tells neighbors about the ban too")]
+    fn parse_bans_complains_about_unknown_options () {
+        let finder = ParameterFinder::new (vec! (String::from ("--ban"), String::from ("QmFk;tell_everyone")));
+
+        Bootstrapper::parse_bans (&finder);
+    }
+
+    #[test]
+    fn padding_and_cover_traffic_are_off_by_default () {
+        let finder = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+
+        assert_eq! (Bootstrapper::parse_padding (&finder), false);
+        assert_eq! (Bootstrapper::parse_cover_traffic_interval (&finder), 0);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --padding <on|off>: 'sometimes'")]
+    fn parse_padding_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--padding"), String::from ("sometimes")));
+
+        Bootstrapper::parse_padding (&finder);
+    }
+
+    #[test]
+    fn exit_location_is_anywhere_by_default () {
+        let finder = ParameterFinder::new (vec! ());
+
+        assert_eq! (Bootstrapper::parse_exit_location (&finder).is_anywhere (), true);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --exit_location <countries>: 'US,Canada'")]
+    fn parse_exit_location_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--exit_location"), String::from ("US,Canada"),
+            String::from ("--geolocation_database"), String::from ("geolocation.csv")));
+
+        Bootstrapper::parse_exit_location (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "--exit_location requires --geolocation_database")]
+    fn parse_exit_location_requires_a_geolocation_database () {
+        let finder = ParameterFinder::new (vec! (String::from ("--exit_location"), String::from ("US")));
+
+        Bootstrapper::parse_exit_location (&finder);
+    }
+
+    #[test]
+    fn mode_is_standard_by_default () {
+        let finder = ParameterFinder::new (vec! ());
+
+        assert_eq! (Bootstrapper::parse_mode (&finder), NodeMode::Standard);
+    }
+
+    #[test]
+    fn mode_can_be_serve_only () {
+        let finder = ParameterFinder::new (vec! (String::from ("--mode"), String::from ("serve_only")));
+
+        assert_eq! (Bootstrapper::parse_mode (&finder), NodeMode::ServeOnly);
+    }
+
+    #[test]
+    fn mode_can_be_zero_hop () {
+        let finder = ParameterFinder::new (vec! (String::from ("--mode"), String::from ("zero_hop")));
+
+        assert_eq! (Bootstrapper::parse_mode (&finder), NodeMode::ZeroHop);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --mode <standard|originate_only|serve_only|zero_hop>: 'leech'")]
+    fn parse_mode_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--mode"), String::from ("leech")));
+
+        Bootstrapper::parse_mode (&finder);
+    }
+
+    #[test]
+    fn routes_cost_whatever_they_cost_unless_there_s_a_maximum () {
+        let finder = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+
+        assert_eq! (Bootstrapper::parse_max_route_cost (&finder), None);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --max_route_cost <per package>,<per byte>: '1000'")]
+    fn parse_max_route_cost_complains_about_a_missing_amount () {
+        let finder = ParameterFinder::new (vec! (String::from ("--max_route_cost"), String::from ("1000")));
+
+        Bootstrapper::parse_max_route_cost (&finder);
+    }
+
+    #[test]
+    fn route_diversity_has_a_default () {
+        let finder = ParameterFinder::new (vec! ());
+
+        assert_eq! (Bootstrapper::parse_route_diversity (&finder), DEFAULT_ROUTE_DIVERSITY);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --route_diversity <off|prefer|require>: 'always'")]
+    fn parse_route_diversity_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--route_diversity"), String::from ("always")));
+
+        Bootstrapper::parse_route_diversity (&finder);
+    }
+
+    #[test]
+    fn port_mapping_is_off_by_default () {
+        let finder = ParameterFinder::new (vec! ());
+
+        assert_eq! (Bootstrapper::parse_port_mapping (&finder), None);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --port_mapping <off|upnp|natpmp>: 'pcp'")]
+    fn parse_port_mapping_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--port_mapping"), String::from ("pcp")));
+
+        Bootstrapper::parse_port_mapping (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "--clandestine_transport tls is not supported yet: links to neighbors can only be plain")]
+    fn check_clandestine_transport_refuses_tls_rather_than_ignoring_it () {
+        let finder = ParameterFinder::new (vec! (String::from ("--clandestine_transport"), String::from ("tls")));
+
+        Bootstrapper::check_clandestine_transport (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --clandestine_transport <plain>: 'ssl'")]
+    fn check_clandestine_transport_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--clandestine_transport"), String::from ("ssl")));
+
+        Bootstrapper::check_clandestine_transport (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --cover_traffic_interval <milliseconds>: 'often'")]
+    fn parse_cover_traffic_interval_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--cover_traffic_interval"), String::from ("often")));
+
+        Bootstrapper::parse_cover_traffic_interval (&finder);
+    }
+
+    #[test]
+    fn compression_is_off_by_default () {
+        let finder = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+
+        assert_eq! (Bootstrapper::parse_compression (&finder), false);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --compression <on|off>: 'lz4'")]
+    fn parse_compression_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--compression"), String::from ("lz4")));
+
+        Bootstrapper::parse_compression (&finder);
+    }
+
+    #[test]
+    fn links_re_key_on_the_default_limits_unless_told_otherwise () {
+        let none = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+        let bytes_only = ParameterFinder::new (vec! (String::from ("--rekey_bytes"), String::from ("1048576"),
+            String::from ("--rekey_interval"), String::from ("0")));
+        let interval_only = ParameterFinder::new (vec! (String::from ("--rekey_interval"), String::from ("60000")));
+
+        assert_eq! (Bootstrapper::parse_rekey_policy (&none), DEFAULT_REKEY_POLICY);
+        assert_eq! (Bootstrapper::parse_rekey_policy (&bytes_only), RekeyPolicy {max_bytes: 1048576, max_interval_ms: 0});
+        assert_eq! (Bootstrapper::parse_rekey_policy (&interval_only), RekeyPolicy {max_bytes: DEFAULT_REKEY_POLICY.max_bytes, max_interval_ms: 60000});
+    }
+
+    #[test]
+    #[should_panic (expected = "--rekey_bytes and --rekey_interval can't both be 0")]
+    fn parse_rekey_policy_complains_about_never_re_keying () {
+        let finder = ParameterFinder::new (vec! (String::from ("--rekey_bytes"), String::from ("0"),
+            String::from ("--rekey_interval"), String::from ("0")));
+
+        Bootstrapper::parse_rekey_policy (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --rekey_bytes <bytes>: '1GB'")]
+    fn parse_rekey_policy_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--rekey_bytes"), String::from ("1GB")));
+
+        Bootstrapper::parse_rekey_policy (&finder);
+    }
+
+    #[test]
+    fn keys_are_not_rotated_by_default_and_overlap_by_default_when_they_are () {
+        let none = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+        let daily = ParameterFinder::new (vec! (String::from ("--key_rotation_interval"), String::from ("86400000")));
+        let often = ParameterFinder::new (vec! (String::from ("--key_rotation_interval"), String::from ("60000")));
+
+        assert_eq! (Bootstrapper::parse_key_rotation (&none), (0, 0));
+        assert_eq! (Bootstrapper::parse_key_rotation (&daily), (86400000, DEFAULT_KEY_OVERLAP_MS));
+        assert_eq! (Bootstrapper::parse_key_rotation (&often), (60000, 30000));
+    }
+
+    #[test]
+    #[should_panic (expected = "--key_overlap (60000) must be shorter than --key_rotation_interval (60000)")]
+    fn parse_key_rotation_complains_about_an_overlap_as_long_as_the_interval () {
+        let finder = ParameterFinder::new (vec! (String::from ("--key_rotation_interval"), String::from ("60000"),
+            String::from ("--key_overlap"), String::from ("60000")));
+
+        Bootstrapper::parse_key_rotation (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --key_rotation_interval <milliseconds>: 'weekly'")]
+    fn parse_key_rotation_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--key_rotation_interval"), String::from ("weekly")));
+
+        Bootstrapper::parse_key_rotation (&finder);
+    }
+
+    #[test]
+    fn real_cryptography_is_used_by_default () {
+        let finder = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+
+        assert_eq! (Bootstrapper::parse_null_cryptde (&finder), false);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --null_cryptde <on|off>: 'yes'")]
+    fn parse_null_cryptde_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--null_cryptde"), String::from ("yes")));
+
+        Bootstrapper::parse_null_cryptde (&finder);
+    }
+
+    #[test]
+    fn mixing_is_off_by_default () {
+        let finder = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+
+        assert_eq! (Bootstrapper::parse_mix_delay (&finder), MixDelay::Off);
+    }
+
+    #[test]
+    fn parse_mix_delay_understands_each_distribution () {
+        let parse = |value: &str| Bootstrapper::parse_mix_delay (&ParameterFinder::new (vec! (String::from ("--mix_delay"), String::from (value))));
+
+        assert_eq! (parse ("off"), MixDelay::Off);
+        assert_eq! (parse ("uniform:10-10"), MixDelay::Uniform {min_ms: 10, max_ms: 10});
+        assert_eq! (parse ("exponential:150"), MixDelay::Exponential {mean_ms: 150});
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --mix_delay <off|uniform:<min>-<max>|exponential:<mean>>: 'uniform:80-20'")]
+    fn parse_mix_delay_complains_about_backward_ranges () {
+        let finder = ParameterFinder::new (vec! (String::from ("--mix_delay"), String::from ("uniform:80-20")));
+
+        Bootstrapper::parse_mix_delay (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --mix_delay <off|uniform:<min>-<max>|exponential:<mean>>: 'gaussian:50'")]
+    fn parse_mix_delay_complains_about_unknown_distributions () {
+        let finder = ParameterFinder::new (vec! (String::from ("--mix_delay"), String::from ("gaussian:50")));
+
+        Bootstrapper::parse_mix_delay (&finder);
+    }
+
+    #[test]
+    fn parse_hop_range_defaults_when_unspecified () {
+        let finder = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+
+        let result = Bootstrapper::parse_hop_range (&finder);
+
+        assert_eq! (result, (DEFAULT_MIN_HOPS, DEFAULT_MAX_HOPS));
+    }
+
+    #[test]
+    fn parse_hop_range_raises_max_to_meet_a_larger_min () {
+        let finder = ParameterFinder::new (vec! (String::from ("--min_hops"), String::from ("5")));
+
+        let result = Bootstrapper::parse_hop_range (&finder);
+
+        assert_eq! (result, (5, 5));
+    }
+
+    #[test]
+    #[should_panic (expected = "--min_hops (4) must not be greater than --max_hops (2)")]
+    fn parse_hop_range_complains_about_inverted_range () {
+        let finder = ParameterFinder::new (vec! (
+            "--min_hops", "4",
+            "--max_hops", "2"
+        ).into_iter ().map (String::from).collect ());
+
+        Bootstrapper::parse_hop_range (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --max_hops <count>: 'booga'")]
+    fn parse_hop_range_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--max_hops"), String::from ("booga")));
+
+        Bootstrapper::parse_hop_range (&finder);
+    }
+
+    #[test]
+    fn parse_neighbor_counts_defaults_when_unspecified () {
+        let finder = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+
+        let result = Bootstrapper::parse_neighbor_counts (&finder);
+
+        assert_eq! (result, (DEFAULT_MIN_NEIGHBORS, DEFAULT_TARGET_NEIGHBORS, DEFAULT_MAX_NEIGHBORS));
+    }
+
+    #[test]
+    fn parse_neighbor_counts_moves_defaults_out_of_the_way () {
+        let finder = ParameterFinder::new (vec! (String::from ("--max_neighbors"), String::from ("1")));
+
+        assert_eq! (Bootstrapper::parse_neighbor_counts (&finder), (1, 1, 1));
+
+        let finder = ParameterFinder::new (vec! (String::from ("--min_neighbors"), String::from ("20")));
+
+        assert_eq! (Bootstrapper::parse_neighbor_counts (&finder), (20, 20, 20));
+    }
+
+    #[test]
+    #[should_panic (expected = "--target_neighbors (8) must not be greater than --max_neighbors (4)")]
+    fn parse_neighbor_counts_complains_about_a_target_above_the_maximum () {
+        let finder = ParameterFinder::new (vec! (
+            "--target_neighbors", "8",
+            "--max_neighbors", "4"
+        ).into_iter ().map (String::from).collect ());
+
+        Bootstrapper::parse_neighbor_counts (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --min_neighbors <count>: 'few'")]
+    fn parse_neighbor_counts_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--min_neighbors"), String::from ("few")));
+
+        Bootstrapper::parse_neighbor_counts (&finder);
+    }
+
+    #[test]
+    fn parse_max_response_size_defaults_to_unlimited () {
+        let finder = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+
+        let result = Bootstrapper::parse_max_response_size (&finder);
+
+        assert_eq! (result, 0);
+    }
+
+    #[test]
+    fn node_descriptors_can_be_read_back_as_neighbor_configs () {
+        let public_key = Key::new (b"Bill");
+        let node_addr = NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap (), &vec! (443, 80));
+
+        let result = Bootstrapper::node_descriptor (&public_key, &node_addr);
+
+        assert_eq! (result, String::from ("QmlsbA==;1.2.3.4;80,443"));
+        assert_eq! (Bootstrapper::parse_neighbor_config (result), (public_key, node_addr));
+    }
+
+    #[test]
+    fn descriptor_for_reports_the_descriptor_of_the_identity_in_the_data_directory () {
+        let data_directory = make_identity_directory ("descriptor_for_reports_the_descriptor_of_the_identity_in_the_data_directory");
+        let mut cryptde = CryptDEReal::new ();
+        cryptde.generate_key_pair ();
+        IdentityStore::in_data_directory (&data_directory, "secret").save (&cryptde.private_key ()).unwrap ();
+        ClandestinePortStore::in_data_directory (&data_directory).save (4321).unwrap ();
+        let args: Vec<String> = vec! ("--data_directory", data_directory.to_str ().unwrap (), "--ip", "4.3.2.1")
+            .into_iter ().map (String::from).collect ();
+        let mut holder = FakeStreamHolder::new ();
+        holder.stdin = ByteArrayReader::new (b"secret\n");
+        let terminal = TerminalMock::new (true);
+
+        let result = Bootstrapper::descriptor_for (&mut holder.streams (), &terminal, &args);
+
+        assert_eq! (result, Ok (Bootstrapper::node_descriptor (&cryptde.public_key (),
+            &NodeAddr::new (&IpAddr::from_str ("4.3.2.1").unwrap (), &vec! (4321)))));
+        assert_eq! (holder.stdout.get_string (), String::from ("Passphrase for this Node's identity (blank for a new identity every time it starts): \n"));
+        assert_eq! (*terminal.echoes.borrow (), vec! (false, true));
+    }
+
+    #[test]
+    fn a_random_clandestine_port_is_kept_in_the_data_directory_until_another_is_given () {
+        let data_directory = make_identity_directory ("a_random_clandestine_port_is_kept_in_the_data_directory_until_another_is_given");
+        let data_directory_string = data_directory.to_string_lossy ().to_string ();
+        let config = Bootstrapper::parse_args (&vec! (String::from ("--data_directory"), data_directory_string.clone ()));
+        let given_config = Bootstrapper::parse_args (&vec! (String::from ("--data_directory"), data_directory_string,
+            String::from ("--clandestine_port"), String::from ("4321")));
+
+        let first_port = Bootstrapper::establish_clandestine_port (&config);
+        let second_port = Bootstrapper::establish_clandestine_port (&config);
+        let given_port = Bootstrapper::establish_clandestine_port (&given_config);
+        let last_port = Bootstrapper::establish_clandestine_port (&config);
+
+        assert_eq! (second_port, first_port);
+        assert_eq! (given_port, 4321);
+        assert_eq! (last_port, 4321);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --clandestine_port <port>: '0'")]
+    fn parse_clandestine_port_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--clandestine_port"), String::from ("0")));
+
+        Bootstrapper::parse_clandestine_port (&finder);
+    }
+
+    #[test]
+    fn descriptor_for_needs_an_identity_that_lasts () {
+        let args: Vec<String> = vec! ("--ip", "4.3.2.1").into_iter ().map (String::from).collect ();
+        let mut holder = FakeStreamHolder::new ();
+
+        let result = Bootstrapper::descriptor_for (&mut holder.streams (), &TerminalMock::new (true), &args);
+
+        assert_eq! (result, Err (String::from ("Without --data_directory and an identity passphrase, this Node gets a new identity, and so a new descriptor, every time it starts")));
+        assert_eq! (holder.stdout.get_string (), String::new ());
+    }
+
+    #[test]
+    fn ip_check_interval_has_a_default () {
+        let finder = ParameterFinder::new (vec! (String::from ("--irrelevant"), String::from ("irrelevant")));
+
+        assert_eq! (Bootstrapper::parse_ip_check_interval (&finder), DEFAULT_IP_CHECK_INTERVAL_MS);
+    }
+
+    #[test]
+    fn the_ui_port_has_a_default () {
+        let default_config = Bootstrapper::parse_args (&vec! ());
+        let given_config = Bootstrapper::parse_args (&vec! (String::from ("--ui_port"), String::from ("4444")));
+
+        assert_eq! (default_config.ui_gateway_config, UiGatewayConfig {
+            ui_bind_ip: IpAddr::from_str ("127.0.0.1").unwrap (),
+            ui_port: DEFAULT_UI_PORT,
+            ui_tls: UiTls::Off,
+            token_opt: None,
+            certificate_opt: None,
+        });
+        assert_eq! (given_config.ui_gateway_config.ui_port, 4444);
+    }
+
+    #[test]
+    fn the_ui_can_be_served_over_tls_with_a_generated_or_supplied_certificate () {
+        let generated_config = Bootstrapper::parse_args (&vec! (String::from ("--ui_tls"), String::from ("on")));
+        let supplied_config = Bootstrapper::parse_args (&vec! ("--ui_tls", "on", "--ui_certificate", "cert.pem", "--ui_private_key", "key.pem")
+            .into_iter ().map (String::from).collect ());
+
+        assert_eq! (generated_config.ui_gateway_config.ui_tls, UiTls::Generated);
+        assert_eq! (supplied_config.ui_gateway_config.ui_tls, UiTls::Supplied {
+            certificate_file: PathBuf::from ("cert.pem"),
+            private_key_file: PathBuf::from ("key.pem"),
+        });
+    }
+
+    #[test]
+    #[should_panic (expected = "--ui_certificate and --ui_private_key need --ui_tls on")]
+    fn a_ui_certificate_without_tls_is_refused () {
+        Bootstrapper::parse_args (&vec! ("--ui_certificate", "cert.pem", "--ui_private_key", "key.pem").into_iter ().map (String::from).collect ());
+    }
+
+    #[test]
+    #[should_panic (expected = "--ui_certificate and --ui_private_key have to be given together")]
+    fn a_ui_certificate_without_its_private_key_is_refused () {
+        Bootstrapper::parse_args (&vec! ("--ui_tls", "on", "--ui_certificate", "cert.pem").into_iter ().map (String::from).collect ());
+    }
+
+    #[test]
+    fn the_ui_can_listen_everywhere_over_tls () {
+        let config = Bootstrapper::parse_args (&vec! ("--ui_bind_ip", "0.0.0.0", "--ui_tls", "on").into_iter ().map (String::from).collect ());
+
+        assert_eq! (config.ui_gateway_config.ui_bind_ip, IpAddr::from_str ("0.0.0.0").unwrap ());
+    }
+
+    #[test]
+    #[should_panic (expected = "--ui_bind_ip :: would let other machines reach the UI; that needs --ui_tls on")]
+    fn the_ui_cannot_listen_everywhere_without_tls () {
+        Bootstrapper::parse_args (&vec! (String::from ("--ui_bind_ip"), String::from ("::")));
+    }
+
+    #[test]
+    #[should_panic (expected = "--ui_bind_ip 192.168.1.10 would let other machines reach the UI; that needs --ui_tls on")]
+    fn the_ui_cannot_listen_on_a_network_address_without_tls () {
+        Bootstrapper::parse_args (&vec! (String::from ("--ui_bind_ip"), String::from ("192.168.1.10")));
+    }
+
+    #[test]
+    fn the_ui_can_listen_on_any_loopback_address_without_tls () {
+        let config = Bootstrapper::parse_args (&vec! (String::from ("--ui_bind_ip"), String::from ("::1")));
+
+        assert_eq! (config.ui_gateway_config.ui_bind_ip, IpAddr::from_str ("::1").unwrap ());
+    }
+
+    #[test]
+    fn a_generated_ui_certificate_is_kept_in_the_data_directory () {
+        let data_directory = make_identity_directory ("a_generated_ui_certificate_is_kept_in_the_data_directory");
+        let config = Bootstrapper::parse_args (&vec! (String::from ("--data_directory"), data_directory.to_string_lossy ().to_string (),
+            String::from ("--ui_tls"), String::from ("on")));
+
+        let first_certificate = Bootstrapper::establish_ui_certificate (&config);
+        let second_certificate = Bootstrapper::establish_ui_certificate (&config);
+
+        assert_eq! (first_certificate.is_some (), true);
+        assert_eq! (second_certificate, first_certificate);
+        assert_eq! (UiCertificateStore::in_data_directory (&data_directory).load (), Ok (first_certificate));
+    }
+
+    #[test]
+    fn the_ui_token_is_made_once_and_kept_in_the_data_directory () {
+        let data_directory = make_identity_directory ("the_ui_token_is_made_once_and_kept_in_the_data_directory");
+        let config = Bootstrapper::parse_args (&vec! (String::from ("--data_directory"), data_directory.to_string_lossy ().to_string ()));
+        let mut holder = FakeStreamHolder::new ();
+
+        let first_token = Bootstrapper::establish_ui_token (&mut holder.streams (), &config);
+        let second_token = Bootstrapper::establish_ui_token (&mut holder.streams (), &config);
+
+        assert_eq! (second_token, first_token);
+        assert_eq! (UiTokenStore::in_data_directory (&data_directory).load (), Ok (Some (first_token)));
+        assert_eq! (holder.stdout.get_string (), String::new ());
+    }
+
+    #[test]
+    fn without_a_data_directory_the_ui_token_is_shown_and_lasts_one_run () {
+        let config = Bootstrapper::parse_args (&vec! ());
+        let mut holder = FakeStreamHolder::new ();
+
+        let first_token = Bootstrapper::establish_ui_token (&mut holder.streams (), &config);
+        let second_token = Bootstrapper::establish_ui_token (&mut holder.streams (), &config);
+
+        assert_ne! (second_token, first_token);
+        assert_eq! (holder.stdout.get_string (), format! ("UI token for this run: {}\nUI token for this run: {}\n", first_token, second_token));
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --ui_port <port>: '0'")]
+    fn parse_ui_port_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--ui_port"), String::from ("0")));
+
+        Bootstrapper::parse_ui_port (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --ip_check_interval <milliseconds>: 'daily'")]
+    fn parse_ip_check_interval_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--ip_check_interval"), String::from ("daily")));
+
+        Bootstrapper::parse_ip_check_interval (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --ip <IP address>: 'booga'")]
+    fn parse_ip_addr_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--ip"), String::from ("booga")));
+
+        Bootstrapper::parse_ip_addr (&finder);
+    }
+
+    #[test]
+    fn initialize_as_root_reports_the_node_descriptor_when_the_ip_address_is_known () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = DispatcherBuilder::new ()
+            .add_listener_handler (ListenerHandlerNull::new (vec! ()).bind_port_result(Ok (())))
+            .add_listener_handler (ListenerHandlerNull::new (vec! ()).bind_port_result(Ok (())))
+            .build ();
+
+        subject.initialize_as_root(&vec! (String::from ("--dns_servers"), String::from ("1.1.1.1"), String::from ("--ip"), String::from ("4.3.2.1"),
+                                          String::from ("--clandestine_port"), String::from ("4321")),
+                                   &mut holder.streams ());
+
+        let stdout_dump = holder.stdout.get_string ();
+        let regex = Regex::new(r"Substratum Node descriptor: (.+?)\n").unwrap();
+        let descriptor = regex.captures (stdout_dump.as_str ()).unwrap ().get (1).unwrap ().as_str ();
+        let (_, node_addr) = Bootstrapper::parse_neighbor_config (String::from (descriptor));
+        assert_eq! (node_addr, NodeAddr::new (&IpAddr::from_str ("4.3.2.1").unwrap (), &vec! (4321)));
+    }
+
+    #[test]
+    fn initialize_as_root_reports_no_node_descriptor_without_an_ip_address () {
+        let mut holder = FakeStreamHolder::new ();
+        let mut subject = DispatcherBuilder::new ()
+            .add_listener_handler (ListenerHandlerNull::new (vec! ()).bind_port_result(Ok (())))
+            .add_listener_handler (ListenerHandlerNull::new (vec! ()).bind_port_result(Ok (())))
+            .build ();
+
+        subject.initialize_as_root(&meaningless_dns_servers(), &mut holder.streams ());
+
+        assert_eq! (holder.stdout.get_string ().contains ("Substratum Node descriptor"), false);
+        assert_eq! (holder.stderr.get_string ().contains ("Couldn't discover this Node's public IP address; supply it with --ip"), true);
+    }
+
+    #[test]
+    fn initialize_as_root_discovers_the_ip_address_when_it_is_not_supplied () {
+        let mut holder = FakeStreamHolder::new ();
+        let public_ip_finder_factory = PublicIpFinderFactoryMock::new (Some (IpAddr::from_str ("5.6.7.8").unwrap ()));
+        let make_parameters_arc = public_ip_finder_factory.make_parameters.clone ();
+        let mut subject = DispatcherBuilder::new ()
+            .add_listener_handler (ListenerHandlerNull::new (vec! ()).bind_port_result(Ok (())))
+            .add_listener_handler (ListenerHandlerNull::new (vec! ()).bind_port_result(Ok (())))
+            .public_ip_finder_factory (public_ip_finder_factory)
+            .build ();
+
+        subject.initialize_as_root(&vec! (String::from ("--dns_servers"), String::from ("1.1.1.1"),
+                                          String::from ("--stun_server"), String::from ("stun.example.com:3478"),
+                                          String::from ("--ip_echo_host"), String::from ("echo.example.com")),
+                                   &mut holder.streams ());
+
+        let config = subject.config.unwrap ();
+        assert_eq! (*make_parameters_arc.lock ().unwrap (), vec! (IpDiscoveryConfig {
+            stun_server: String::from ("stun.example.com:3478"),
+            http_echo_host: String::from ("echo.example.com"),
+        }));
+        assert_eq! (config.ip_addr_opt, Some (IpAddr::from_str ("5.6.7.8").unwrap ()));
+        assert_eq! (config.ip_addr_discovered, true);
+        assert_eq! (holder.stdout.get_string ().contains ("Substratum Node descriptor: "), true);
+        assert_eq! (holder.stdout.get_string ().contains (&format! (";5.6.7.8;{}\n", config.clandestine_ports[0])), true);
+    }
+
+    #[test]
+    fn initialize_as_root_does_not_look_for_an_ip_address_that_was_supplied () {
+        let mut subject = DispatcherBuilder::new ()
+            .add_listener_handler (ListenerHandlerNull::new (vec! ()).bind_port_result(Ok (())))
+            .add_listener_handler (ListenerHandlerNull::new (vec! ()).bind_port_result(Ok (())))
+            .public_ip_finder_factory (PublicIpFinderFactoryMock::new (Some (IpAddr::from_str ("5.6.7.8").unwrap ())))
+            .build ();
+
+        subject.initialize_as_root(&vec! (String::from ("--dns_servers"), String::from ("1.1.1.1"), String::from ("--ip"), String::from ("4.3.2.1")),
+                                   &mut FakeStreamHolder::new ().streams ());
+
+        let config = subject.config.unwrap ();
+        assert_eq! (config.ip_addr_opt, Some (IpAddr::from_str ("4.3.2.1").unwrap ()));
+        assert_eq! (config.ip_addr_discovered, false);
+    }
+
+    #[test]
+    fn initialize_as_root_asks_nobody_for_the_ip_address_when_ip_discovery_is_off () {
+        let mut holder = FakeStreamHolder::new ();
+        let public_ip_finder_factory = PublicIpFinderFactoryMock::new (Some (IpAddr::from_str ("5.6.7.8").unwrap ()));
+        let make_parameters_arc = public_ip_finder_factory.make_parameters.clone ();
+        let mut subject = DispatcherBuilder::new ()
+            .add_listener_handler (ListenerHandlerNull::new (vec! ()).bind_port_result(Ok (())))
+            .add_listener_handler (ListenerHandlerNull::new (vec! ()).bind_port_result(Ok (())))
+            .public_ip_finder_factory (public_ip_finder_factory)
+            .build ();
+
+        subject.initialize_as_root(&vec! (String::from ("--dns_servers"), String::from ("1.1.1.1"), String::from ("--ip_discovery"), String::from ("off")),
+                                   &mut holder.streams ());
+
+        let config = subject.config.unwrap ();
+        assert_eq! (make_parameters_arc.lock ().unwrap ().is_empty (), true);
+        assert_eq! (config.ip_addr_opt, None);
+        assert_eq! (config.ip_addr_discovered, false);
+        assert_eq! (holder.stderr.get_string ().contains ("--ip_discovery is off; supply this Node's public IP address with --ip"), true);
+    }
+
+    #[test]
+    fn parse_ip_discovery_defaults_to_the_well_known_endpoints () {
+        let finder = ParameterFinder::new (vec! ());
+
+        let result = Bootstrapper::parse_ip_discovery (&finder);
+
+        assert_eq! (result, Some (IpDiscoveryConfig {
+            stun_server: String::from (DEFAULT_STUN_SERVER),
+            http_echo_host: String::from (DEFAULT_HTTP_ECHO_HOST),
+        }));
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --ip_discovery <on|off>: 'sometimes'")]
+    fn parse_ip_discovery_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--ip_discovery"), String::from ("sometimes")));
+
+        Bootstrapper::parse_ip_discovery (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "--stun_server and --ip_echo_host are only used with --ip_discovery on")]
+    fn parse_ip_discovery_complains_about_endpoints_when_discovery_is_off () {
+        let finder = ParameterFinder::new (vec! (String::from ("--ip_discovery"), String::from ("off"),
+            String::from ("--stun_server"), String::from ("stun.example.com:3478")));
+
+        Bootstrapper::parse_ip_discovery (&finder);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --max_response_size <bytes>: 'booga'")]
+    fn parse_max_response_size_complains_about_bad_values () {
+        let finder = ParameterFinder::new (vec! (String::from ("--max_response_size"), String::from ("booga")));
+
+        Bootstrapper::parse_max_response_size (&finder);
     }
 
     #[test]
@@ -372,15 +2775,71 @@ mod tests {
             .add_listener_handler (third_handler)
             .build ();
 
-        subject.initialize_as_root(&meaningless_dns_servers(), &mut FakeStreamHolder::new ().streams ());
+        subject.initialize_as_root(&vec! (String::from ("--dns_servers"), String::from ("222.222.222.222"), String::from ("--clandestine_port"), String::from ("4321")),
+                                   &mut FakeStreamHolder::new ().streams ());
 
         let mut all_calls = vec! ();
         all_calls.extend (first_handler_log.lock ().unwrap ().dump ());
         all_calls.extend (second_handler_log.lock ().unwrap ().dump ());
         all_calls.extend (third_handler_log.lock ().unwrap ().dump ());
-        assert_eq! (all_calls.contains (&String::from ("bind_port_and_discriminator_factories (80, ...)")), true, "{:?}", all_calls);
-        assert_eq! (all_calls.contains (&String::from ("bind_port_and_discriminator_factories (443, ...)")), true, "{:?}", all_calls);
-        assert_eq! (all_calls.len (), 2, "{:?}", all_calls);
+        assert_eq! (all_calls.contains (&String::from ("bind_address_and_discriminator_factories (0.0.0.0:80, ...)")), true, "{:?}", all_calls);
+        assert_eq! (all_calls.contains (&String::from ("bind_address_and_discriminator_factories (0.0.0.0:443, ...)")), true, "{:?}", all_calls);
+        assert_eq! (all_calls.contains (&String::from ("bind_address_and_discriminator_factories (0.0.0.0:4321, ...)")), true, "{:?}", all_calls);
+        assert_eq! (all_calls.len (), 3, "{:?}", all_calls);
+    }
+
+    #[test]
+    fn a_reload_sends_the_exit_location_to_the_neighborhood_once_it_is_running () {
+        let mut actor_system_factory = ActorSystemFactoryMock::new();
+        let neighborhood_recording_arc = actor_system_factory.neighborhood_recording.clone ();
+        let neighborhood_awaiter = actor_system_factory.neighborhood_awaiter.take ().unwrap ();
+        let mut subject = DispatcherBuilder::new ()
+            .actor_system_factory (Box::new (actor_system_factory))
+            .add_listener_handler (ListenerHandlerNull::new (vec! ()).bind_port_result(Ok (())))
+            .add_listener_handler (ListenerHandlerNull::new (vec! ()).bind_port_result(Ok (())))
+            .build ();
+        subject.initialize_as_root(&vec! (String::from ("--dns_servers"), String::from ("1.2.3.4")),
+                                   &mut FakeStreamHolder::new ().streams ());
+        let reloader = subject.reloader ().unwrap ();
+        let args = vec! (String::from ("--dns_servers"), String::from ("1.2.3.4"), String::from ("--exit_location"), String::from ("de,!us"),
+            String::from ("--geolocation_database"), String::from ("geolocation.csv"));
+        let early_result = reloader.reload (&args);
+
+        subject.serve_without_root();
+        let result = reloader.reload (&args);
+
+        assert_eq! (early_result, Err (String::from ("The Node hasn't finished starting up")));
+        assert_eq! (result, Ok (()));
+        neighborhood_awaiter.await_message_count (1);
+        let neighborhood_recording = neighborhood_recording_arc.lock ().unwrap ();
+        assert_eq! (neighborhood_recording.get_record::<SetExitLocationMsg> (0), &SetExitLocationMsg {
+            exit_location: ExitLocation {countries: vec! (String::from ("DE")), avoided_countries: vec! (String::from ("US"))}
+        });
+    }
+
+    #[test]
+    fn stopping_closes_the_streams_and_then_takes_down_the_actors_once_they_are_running () {
+        let mut actor_system_factory = ActorSystemFactoryMock::new();
+        let awaiter = actor_system_factory.stream_handler_pool_cluster.awaiter.take ().unwrap ();
+        let recording_arc = actor_system_factory.stream_handler_pool_cluster.recording.take ().unwrap ();
+        let mut subject = DispatcherBuilder::new ()
+            .actor_system_factory (Box::new (actor_system_factory))
+            .add_listener_handler (ListenerHandlerNull::new (vec! ()).bind_port_result(Ok (())))
+            .add_listener_handler (ListenerHandlerNull::new (vec! ()).bind_port_result(Ok (())))
+            .build ();
+        subject.initialize_as_root(&meaningless_dns_servers(), &mut FakeStreamHolder::new ().streams ());
+        let stopper = subject.stopper ().unwrap ();
+        let early_result = stopper.stop ();
+
+        subject.serve_without_root();
+        let result = stopper.stop ();
+
+        assert_eq! (early_result, Ok (()));
+        assert_eq! (result, Ok (()));
+        awaiter.await_message_count (1);
+        let recording = recording_arc.lock ().unwrap ();
+        assert_eq! (recording.len (), 1);
+        recording.get_record::<StopStreamsMsg> (0);
     }
 
     #[test]
@@ -431,26 +2890,247 @@ mod tests {
     fn initialize_and_report_cryptde () {
         let mut holder = FakeStreamHolder::new ();
 
-        {
+        let cryptde = {
             let mut streams = holder.streams ();
-            Bootstrapper::initialize_and_report_cryptde(&mut streams);
-        }
-
-        let cryptde = unsafe {
-            CRYPT_DE_OPT.as_ref().expect("Internal error")
+            Bootstrapper::initialize_and_report_cryptde(&mut streams, &TerminalMock::new (true), &Bootstrapper::parse_args (&vec! ()))
         };
-        assert_ne! (cryptde.private_key ().data, b"uninitialized".to_vec ());
+
+        assert_eq! (cryptde.public_key ().data.len (), 64);
         let expected_public_key = base64::encode (&cryptde.public_key ().data);
         let stdout_dump = holder.stdout.get_string ();
         let regex = Regex::new(r"Substratum Node public key: (.+?)\n").unwrap();
         let captured_public_key = regex.captures (stdout_dump.as_str ()).unwrap ().get (1).unwrap ().as_str ();
         assert_eq! (captured_public_key, expected_public_key);
+        assert_eq! (holder.stderr.get_string (), String::new ());
         let expected_data = PlainData::new (b"ho'q ;iaerh;frjhvs;lkjerre");
-        let crypt_data = cryptde.encode (&cryptde.private_key (), &expected_data).unwrap ();
-        let decrypted_data = cryptde.decode (&cryptde.public_key (), &crypt_data).unwrap ();
+        let crypt_data = cryptde.encode (&cryptde.public_key (), &expected_data).unwrap ();
+        assert_ne! (crypt_data.data, expected_data.data);
+        let decrypted_data = cryptde.decode (&cryptde.private_key (), &crypt_data).unwrap ();
         assert_eq! (decrypted_data, expected_data)
     }
 
+    fn make_cryptde_from_args (holder: &mut FakeStreamHolder, args: Vec<&str>) -> Box<CryptDE> {
+        make_cryptde_at_terminal (holder, &TerminalMock::new (true), args)
+    }
+
+    fn make_cryptde_at_terminal (holder: &mut FakeStreamHolder, terminal: &TerminalMock, args: Vec<&str>) -> Box<CryptDE> {
+        let mut config = Bootstrapper::parse_args (&args.into_iter ().map (String::from).collect ());
+        Bootstrapper::establish_identity_passphrase (&mut holder.streams (), terminal, &mut config);
+        Bootstrapper::make_cryptde (&mut holder.streams (), terminal, &config)
+    }
+
+    fn make_identity_directory (name: &str) -> PathBuf {
+        let directory = temp_dir ().join ("bootstrapper").join (name);
+        let _ = fs::remove_dir_all (&directory);
+        fs::create_dir_all (&directory).unwrap ();
+        directory
+    }
+
+    #[test]
+    fn make_cryptde_warns_about_null_cryptde () {
+        let mut holder = FakeStreamHolder::new ();
+
+        let cryptde = make_cryptde_from_args (&mut holder, vec! ("--null_cryptde", "on"));
+
+        assert_ne! (cryptde.private_key ().data, b"uninitialized".to_vec ());
+        assert_eq! (cryptde.public_key (), CryptDENull::other_key (&cryptde.private_key ()));
+        assert_eq! (holder.stderr.get_string (), String::from ("WARNING: --null_cryptde is on; this Node's traffic is neither encrypted nor authenticated\n"));
+    }
+
+    #[test]
+    fn make_cryptde_keeps_the_same_identity_across_restarts () {
+        let directory = make_identity_directory ("make_cryptde_keeps_the_same_identity_across_restarts");
+        let data_directory = directory.to_str ().unwrap ();
+        let mut holder = FakeStreamHolder::new ();
+        holder.stdin = ByteArrayReader::new (b"open sesame\nopen sesame\n");
+        let terminal = TerminalMock::new (true);
+
+        let first = make_cryptde_at_terminal (&mut holder, &terminal, vec! ("--data_directory", data_directory));
+        let second = make_cryptde_at_terminal (&mut holder, &terminal, vec! ("--data_directory", data_directory));
+
+        assert_eq! (second.public_key (), first.public_key ());
+        assert_eq! (second.private_key (), first.private_key ());
+        assert_eq! (holder.stderr.get_string (), String::new ());
+        assert_eq! (holder.stdout.get_string ().contains ("open sesame"), false);
+        assert_eq! (*terminal.echoes.borrow (), vec! (false, true, false, true));
+    }
+
+    #[test]
+    fn make_cryptde_warns_that_the_identity_is_not_kept_without_a_passphrase () {
+        let directory = make_identity_directory ("make_cryptde_warns_that_the_identity_is_not_kept_without_a_passphrase");
+        let mut holder = FakeStreamHolder::new ();
+
+        let first = make_cryptde_from_args (&mut holder, vec! ("--data_directory", directory.to_str ().unwrap ()));
+        let second = make_cryptde_from_args (&mut holder, vec! ("--data_directory", directory.to_str ().unwrap ()));
+
+        assert_ne! (second.public_key (), first.public_key ());
+        assert_eq! (holder.stderr.get_string ().contains ("No identity passphrase; this Node will have a new identity every time it starts"), true);
+    }
+
+    #[test]
+    #[should_panic (expected = "wrong passphrase")]
+    fn make_cryptde_complains_about_the_wrong_passphrase () {
+        let directory = make_identity_directory ("make_cryptde_complains_about_the_wrong_passphrase");
+        let data_directory = directory.to_str ().unwrap ();
+        let mut holder = FakeStreamHolder::new ();
+        holder.stdin = ByteArrayReader::new (b"open sesame\nopen barley\n");
+        make_cryptde_from_args (&mut holder, vec! ("--data_directory", data_directory));
+
+        make_cryptde_from_args (&mut holder, vec! ("--data_directory", data_directory));
+    }
+
+    #[test]
+    fn an_identity_can_be_backed_up_and_restored_elsewhere () {
+        let old_directory = make_identity_directory ("an_identity_can_be_backed_up_and_restored_elsewhere_old");
+        let new_directory = make_identity_directory ("an_identity_can_be_backed_up_and_restored_elsewhere_new");
+        let backup = old_directory.join ("backup.key");
+        let mut holder = FakeStreamHolder::new ();
+        holder.stdin = ByteArrayReader::new (b"open sesame\nopen sesame\nopen sesame\n");
+
+        let original = make_cryptde_from_args (&mut holder, vec! ("--data_directory", old_directory.to_str ().unwrap (),
+            "--backup_identity", backup.to_str ().unwrap ()));
+        let restored = make_cryptde_from_args (&mut holder, vec! ("--data_directory", new_directory.to_str ().unwrap (),
+            "--restore_identity", backup.to_str ().unwrap ()));
+        let restarted = make_cryptde_from_args (&mut holder, vec! ("--data_directory", new_directory.to_str ().unwrap ()));
+
+        assert_eq! (restored.public_key (), original.public_key ());
+        assert_eq! (restarted.public_key (), original.public_key ());
+        let stdout_dump = holder.stdout.get_string ();
+        assert_eq! (stdout_dump.contains (&format! ("Backed up this Node's identity to {:?}", backup)), true);
+        assert_eq! (stdout_dump.contains (&format! ("Restored this Node's identity from {:?}", backup)), true);
+    }
+
+    fn make_identity_backup (directory: &PathBuf, passphrase: &str) -> (PathBuf, Key) {
+        let backup = directory.join ("backup.key");
+        let mut cryptde = CryptDEReal::new ();
+        cryptde.generate_key_pair ();
+        IdentityStore::new (&backup, passphrase).save (&cryptde.private_key ()).unwrap ();
+        (backup, cryptde.public_key ())
+    }
+
+    #[test]
+    fn restoring_over_another_identity_goes_ahead_once_confirmed () {
+        let directory = make_identity_directory ("restoring_over_another_identity_goes_ahead_once_confirmed");
+        let (backup, backed_up_public_key) = make_identity_backup (&directory, "open sesame");
+        let mut holder = FakeStreamHolder::new ();
+        holder.stdin = ByteArrayReader::new (b"open sesame\nopen sesame\nyes\n");
+        make_cryptde_from_args (&mut holder, vec! ("--data_directory", directory.to_str ().unwrap ()));
+
+        let restored = make_cryptde_from_args (&mut holder, vec! ("--data_directory", directory.to_str ().unwrap (),
+            "--restore_identity", backup.to_str ().unwrap ()));
+
+        assert_eq! (restored.public_key (), backed_up_public_key);
+        assert_eq! (holder.stdout.get_string ().contains (&format! ("{:?} already holds another identity, which restoring {:?} replaces for good; type yes to go ahead: ",
+            directory.join (IDENTITY_FILENAME), backup)), true);
+    }
+
+    #[test]
+    fn restoring_over_another_identity_is_refused_without_confirmation () {
+        let directory = make_identity_directory ("restoring_over_another_identity_is_refused_without_confirmation");
+        let (backup, _) = make_identity_backup (&directory, "open sesame");
+        let mut holder = FakeStreamHolder::new ();
+        holder.stdin = ByteArrayReader::new (b"open sesame\n");
+        let original = make_cryptde_from_args (&mut holder, vec! ("--data_directory", directory.to_str ().unwrap ()));
+        let attempt = |answer: &'static [u8], terminal: TerminalMock| {
+            let directory = directory.clone ();
+            let backup = backup.clone ();
+            thread::spawn (move || {
+                let mut holder = FakeStreamHolder::new ();
+                holder.stdin = ByteArrayReader::new (&[&b"open sesame\n"[..], answer].concat ()[..]);
+                make_cryptde_at_terminal (&mut holder, &terminal, vec! ("--data_directory", directory.to_str ().unwrap (),
+                    "--restore_identity", backup.to_str ().unwrap ()));
+            }).join ()
+        };
+
+        let declined = attempt (b"no\n", TerminalMock::new (true));
+        let unattended = attempt (b"yes\n", TerminalMock::new (false));
+
+        assert_eq! (declined.is_err (), true);
+        assert_eq! (unattended.is_err (), true);
+        assert_eq! (IdentityStore::in_data_directory (&directory, "open sesame").load (), Ok (Some (original.private_key ())));
+    }
+
+    #[test]
+    #[should_panic (expected = "--backup_identity and --restore_identity need --data_directory and an identity passphrase")]
+    fn backing_up_an_identity_requires_somewhere_to_keep_it () {
+        let mut holder = FakeStreamHolder::new ();
+
+        make_cryptde_from_args (&mut holder, vec! ("--backup_identity", "/tmp/identity.key"));
+    }
+
+    fn establish_consuming_wallet_from_args (holder: &mut FakeStreamHolder, terminal: &TerminalMock, args: Vec<&str>) -> Option<String> {
+        let mut config = Bootstrapper::parse_args (&args.into_iter ().map (String::from).collect ());
+        Bootstrapper::establish_consuming_wallet (&mut holder.streams (), terminal, &mut config);
+        config.blockchain_bridge_config.consuming_private_key_opt
+    }
+
+    const PRIVATE_KEY: &str = "4646464646464646464646464646464646464646464646464646464646464646";
+
+    #[test]
+    fn a_sealed_consuming_wallet_is_unlocked_by_a_password_asked_for_without_echo () {
+        let directory = make_identity_directory ("a_sealed_consuming_wallet_is_unlocked_by_a_password_asked_for_without_echo");
+        WalletStore::in_data_directory (&directory, "correct horse").save (PRIVATE_KEY).unwrap ();
+        let mut holder = FakeStreamHolder::new ();
+        holder.stdin = ByteArrayReader::new (b"correct horse\r\n");
+        let terminal = TerminalMock::new (true);
+
+        let result = establish_consuming_wallet_from_args (&mut holder, &terminal, vec! ("--data_directory", directory.to_str ().unwrap ()));
+
+        assert_eq! (result, Some (String::from (PRIVATE_KEY)));
+        assert_eq! (holder.stdout.get_string (), String::from ("Password to unlock the consuming wallet (blank to leave it locked): \n"));
+        assert_eq! (*terminal.echoes.borrow (), vec! (false, true));
+    }
+
+    #[test]
+    fn a_blank_wallet_password_leaves_the_consuming_wallet_locked () {
+        let directory = make_identity_directory ("a_blank_wallet_password_leaves_the_consuming_wallet_locked");
+        WalletStore::in_data_directory (&directory, "correct horse").save (PRIVATE_KEY).unwrap ();
+        let mut holder = FakeStreamHolder::new ();
+        holder.stdin = ByteArrayReader::new (b"\n");
+
+        let result = establish_consuming_wallet_from_args (&mut holder, &TerminalMock::new (true), vec! ("--data_directory", directory.to_str ().unwrap ()));
+
+        assert_eq! (result, None);
+        assert_eq! (holder.stderr.get_string (), String::from ("Consuming wallet left locked; this Node can't pay other Nodes\n"));
+    }
+
+    #[test]
+    fn nothing_is_asked_for_without_a_sealed_consuming_wallet () {
+        let directory = make_identity_directory ("nothing_is_asked_for_without_a_sealed_consuming_wallet");
+        let mut holder = FakeStreamHolder::new ();
+        let terminal = TerminalMock::new (true);
+
+        let result = establish_consuming_wallet_from_args (&mut holder, &terminal, vec! ("--data_directory", directory.to_str ().unwrap ()));
+
+        assert_eq! (result, None);
+        assert_eq! (holder.stdout.get_string (), String::new ());
+        assert_eq! (terminal.echoes.borrow ().is_empty (), true);
+    }
+
+    #[test]
+    fn a_sealed_consuming_wallet_is_left_locked_when_a_signing_service_signs () {
+        let directory = make_identity_directory ("a_sealed_consuming_wallet_is_left_locked_when_a_signing_service_signs");
+        WalletStore::in_data_directory (&directory, "correct horse").save (PRIVATE_KEY).unwrap ();
+        let mut holder = FakeStreamHolder::new ();
+
+        let result = establish_consuming_wallet_from_args (&mut holder, &TerminalMock::new (true), vec! ("--data_directory", directory.to_str ().unwrap (),
+            "--signing_service_url", "http://localhost:8550", "--signing_wallet", "0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f"));
+
+        assert_eq! (result, None);
+        assert_eq! (holder.stdout.get_string (), String::new ());
+    }
+
+    #[test]
+    #[should_panic (expected = "wrong passphrase")]
+    fn the_consuming_wallet_does_not_unlock_with_the_wrong_password () {
+        let directory = make_identity_directory ("the_consuming_wallet_does_not_unlock_with_the_wrong_password");
+        WalletStore::in_data_directory (&directory, "correct horse").save (PRIVATE_KEY).unwrap ();
+        let mut holder = FakeStreamHolder::new ();
+        holder.stdin = ByteArrayReader::new (b"battery staple\n");
+
+        establish_consuming_wallet_from_args (&mut holder, &TerminalMock::new (true), vec! ("--data_directory", directory.to_str ().unwrap ()));
+    }
+
     #[test]
     fn serve_without_root_moves_streams_from_listener_handlers_to_stream_handler_pool () {
         let first_message = AddStreamMsg {
@@ -514,22 +3194,28 @@ mod tests {
 
     struct ActorSystemFactoryMock {
         stream_handler_pool_cluster: StreamHandlerPoolCluster,
+        peer_actors: PeerActors,
+        neighborhood_recording: Arc<Mutex<Recording>>,
+        neighborhood_awaiter: Option<RecordAwaiter>,
         dnss: Arc<Mutex<Option<Vec<SocketAddr>>>>,
+        actor_system: Mutex<Option<ActorSystemHandle>>,
     }
 
     impl ActorSystemFactory for ActorSystemFactoryMock {
-        fn make_and_start_actors(&self, config: BootstrapperConfig) -> StreamHandlerPoolSubs {
+        fn make_and_start_actors(&self, config: BootstrapperConfig) -> (StreamHandlerPoolSubs, PeerActors, ActorSystemHandle) {
             let mut parameter_guard = self.dnss.lock ().unwrap ();
             let parameter_ref = parameter_guard.deref_mut ();
             *parameter_ref = Some (config.dns_servers);
 
-            self.stream_handler_pool_cluster.subs.clone ()
+            let actor_system = self.actor_system.lock ().unwrap ().take ().expect ("Actors already started");
+            (self.stream_handler_pool_cluster.subs.clone (), self.peer_actors.clone (), actor_system)
         }
     }
 
     impl ActorSystemFactoryMock {
         fn new() -> ActorSystemFactoryMock {
             let (tx, rx) = mpsc::channel ();
+            let (stopped_tx, stopped_rx) = mpsc::channel ();
             thread::spawn (move || {
                 let system = System::new ("test");
 
@@ -544,13 +3230,23 @@ mod tests {
                     }
                 };
 
-                tx.send (stream_handler_pool_cluster).unwrap ();
+                let neighborhood = Recorder::new ();
+                let neighborhood_recording = neighborhood.get_recording ();
+                let neighborhood_awaiter = neighborhood.get_awaiter ();
+                let peer_actors = make_peer_actors_from (None, None, None, None, Some (neighborhood), None);
+
+                tx.send ((stream_handler_pool_cluster, peer_actors, neighborhood_recording, neighborhood_awaiter, Arbiter::system ())).unwrap ();
                 system.run ();
+                stopped_tx.send (()).ok ();
             });
-            let stream_handler_pool_cluster = rx.recv ().unwrap ();
+            let (stream_handler_pool_cluster, peer_actors, neighborhood_recording, neighborhood_awaiter, system) = rx.recv ().unwrap ();
             ActorSystemFactoryMock {
                 stream_handler_pool_cluster,
+                peer_actors,
+                neighborhood_recording,
+                neighborhood_awaiter: Some (neighborhood_awaiter),
                 dnss: Arc::new(Mutex::new(None)),
+                actor_system: Mutex::new (Some (ActorSystemHandle::new (system, stopped_rx))),
             }
         }
     }
@@ -560,6 +3256,35 @@ mod tests {
         actor_system_factory: Box<ActorSystemFactory>,
         stream_handler_pool_cluster: Option<StreamHandlerPoolCluster>,
         listener_handler_factory: ListenerHandlerFactoryMock,
+        public_ip_finder_factory: PublicIpFinderFactoryMock,
+    }
+
+    struct PublicIpFinderMock {
+        result: Option<IpAddr>,
+    }
+
+    impl PublicIpFinder for PublicIpFinderMock {
+        fn find_public_ip (&self) -> Option<IpAddr> {
+            self.result
+        }
+    }
+
+    struct PublicIpFinderFactoryMock {
+        result: Option<IpAddr>,
+        make_parameters: Arc<Mutex<Vec<IpDiscoveryConfig>>>,
+    }
+
+    impl PublicIpFinderFactory for PublicIpFinderFactoryMock {
+        fn make (&self, config: &IpDiscoveryConfig) -> Box<PublicIpFinder> {
+            self.make_parameters.lock ().unwrap ().push (config.clone ());
+            Box::new (PublicIpFinderMock {result: self.result})
+        }
+    }
+
+    impl PublicIpFinderFactoryMock {
+        fn new (result: Option<IpAddr>) -> PublicIpFinderFactoryMock {
+            PublicIpFinderFactoryMock {result, make_parameters: Arc::new (Mutex::new (vec! ()))}
+        }
     }
 
     impl DispatcherBuilder {
@@ -570,6 +3295,7 @@ mod tests {
                 stream_handler_pool_cluster: None,
                 // Don't modify this line unless you've already looked at DispatcherBuilder::add_listener_handler().
                 listener_handler_factory: ListenerHandlerFactoryMock::new (),
+                public_ip_finder_factory: PublicIpFinderFactoryMock::new (None),
             }
         }
 
@@ -589,6 +3315,11 @@ mod tests {
             self
         }
 
+        fn public_ip_finder_factory (mut self, public_ip_finder_factory: PublicIpFinderFactoryMock) -> DispatcherBuilder {
+            self.public_ip_finder_factory = public_ip_finder_factory;
+            self
+        }
+
         fn build (self) -> Bootstrapper {
             let stream_handler_pool_subs = match &self.stream_handler_pool_cluster {
                 &Some (ref shpc) => Some (shpc.subs.clone ()),
@@ -599,7 +3330,11 @@ mod tests {
                 stream_handler_pool_subs,
                 listener_handler_factory: Box::new (self.listener_handler_factory),
                 listener_handlers: vec! (),
+                public_ip_finder_factory: Box::new (self.public_ip_finder_factory),
+                terminal: Box::new (TerminalMock::new (false)),
                 config: None,
+                reloader: BootstrapperReloader::new (),
+                stopper: BootstrapperStopper::new (),
             }
         }
     }
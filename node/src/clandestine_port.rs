@@ -0,0 +1,105 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use sodiumoxide::randombytes::randombytes;
+
+pub const CLANDESTINE_PORT_FILENAME: &str = "clandestine_port";
+
+// A port picked at random comes from here: above the privileged ports, below the ephemeral ones
+// operating systems hand out for outgoing connections
+const LOWEST_RANDOM_PORT: u16 = 1025;
+const HIGHEST_RANDOM_PORT: u16 = 9999;
+
+// Neighbors know a Node by its descriptor, which includes the port its clandestine traffic arrives
+// on, so the port is kept in the data directory and stays the same from one run to the next.
+pub struct ClandestinePortStore {
+    path: PathBuf,
+}
+
+impl ClandestinePortStore {
+    pub fn in_data_directory (data_directory: &Path) -> ClandestinePortStore {
+        ClandestinePortStore {path: data_directory.join (CLANDESTINE_PORT_FILENAME)}
+    }
+
+    pub fn path (&self) -> &Path {
+        &self.path
+    }
+
+    // Ok (None) means no port has been saved yet
+    pub fn load (&self) -> Result<Option<u16>, String> {
+        let mut contents = String::new ();
+        match File::open (&self.path).and_then (|mut file| file.read_to_string (&mut contents)) {
+            Ok (_) => (),
+            Err (ref e) if e.kind () == ErrorKind::NotFound => return Ok (None),
+            Err (e) => return Err (format! ("Couldn't read the clandestine port from {:?}: {}", self.path, e))
+        }
+        match contents.trim ().parse::<u16> () {
+            Ok (port) if port > 0 => Ok (Some (port)),
+            _ => Err (format! ("{:?} should hold a port number, not '{}'", self.path, contents.trim ()))
+        }
+    }
+
+    pub fn save (&self, port: u16) -> Result<(), String> {
+        let result: io::Result<()> = self.path.parent ().map (|parent| fs::create_dir_all (parent)).unwrap_or (Ok (()))
+            .and_then (|_| File::create (&self.path))
+            .and_then (|mut file| file.write_all (format! ("{}\n", port).as_bytes ()));
+        result.map_err (|e| format! ("Couldn't save the clandestine port to {:?}: {}", self.path, e))
+    }
+}
+
+pub fn random_clandestine_port () -> u16 {
+    let bytes = randombytes (2);
+    let random = ((bytes[0] as u32) << 8) | (bytes[1] as u32);
+    let span = (HIGHEST_RANDOM_PORT - LOWEST_RANDOM_PORT) as u32 + 1;
+    LOWEST_RANDOM_PORT + (random % span) as u16
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+
+    fn make_subject (name: &str) -> ClandestinePortStore {
+        let data_directory = temp_dir ().join ("clandestine_port").join (name);
+        let _ = fs::remove_dir_all (&data_directory);
+        ClandestinePortStore::in_data_directory (&data_directory)
+    }
+
+    #[test]
+    fn a_saved_port_can_be_loaded_back () {
+        let subject = make_subject ("a_saved_port_can_be_loaded_back");
+        let before = subject.load ();
+
+        subject.save (4321).unwrap ();
+        let after = subject.load ();
+
+        assert_eq! (before, Ok (None));
+        assert_eq! (after, Ok (Some (4321)));
+    }
+
+    #[test]
+    fn a_file_without_a_port_number_is_an_error () {
+        let subject = make_subject ("a_file_without_a_port_number_is_an_error");
+        fs::create_dir_all (subject.path ().parent ().unwrap ()).unwrap ();
+        File::create (subject.path ()).unwrap ().write_all (b"booga\n").unwrap ();
+
+        let result = subject.load ();
+
+        assert_eq! (result, Err (format! ("{:?} should hold a port number, not 'booga'", subject.path ())));
+    }
+
+    #[test]
+    fn random_ports_stay_in_range () {
+        for _ in 0..100 {
+            let port = random_clandestine_port ();
+
+            assert_eq! (port >= LOWEST_RANDOM_PORT && port <= HIGHEST_RANDOM_PORT, true, "{}", port);
+        }
+    }
+}
@@ -0,0 +1,428 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::env;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use clap::App;
+use clap::AppSettings;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::ErrorKind;
+use clap::SubCommand;
+use log::LevelFilter;
+use blockchain_bridge_lib::hd_wallet::generate_mnemonic;
+use blockchain_bridge_lib::hd_wallet::private_key_from_mnemonic;
+use blockchain_bridge_lib::hd_wallet::DEFAULT_DERIVATION_PATH;
+use blockchain_bridge_lib::raw_transaction::Signer;
+use sub_lib::identity_store::IDENTITY_FILENAME;
+use sub_lib::main_tools::Command;
+use sub_lib::main_tools::StdStreams;
+use sub_lib::parameter_finder::ParameterFinder;
+use sub_lib::wallet::Wallet;
+use bootstrapper::Bootstrapper;
+use config_file;
+use config_file::PARAMETERS;
+use configuration::Configuration;
+use server_initializer::ServerInitializer;
+use terminal::Terminal;
+use terminal::TerminalReal;
+
+// Parameters that may be given more than once
+const REPEATABLE_PARAMETERS: &[&str] = &["ban", "extra_port", "neighbor"];
+
+const STATUS_CONNECT_TIMEOUT_MS: u64 = 1000;
+
+type Validator = fn (String) -> Result<(), String>;
+
+// The Node's command line: `run` starts the Node, and is assumed when no subcommand is given, so
+// that the flags that used to be the whole command line still work on their own. The other
+// subcommands do one job and exit.
+pub struct Cli {
+    terminal: Box<Terminal>,
+}
+
+impl Command for Cli {
+    fn go<'b> (&mut self, streams: &'b mut StdStreams<'b>, args: &Vec<String>) -> u8 {
+        let args = with_subcommand (args);
+        let matches = match app ().get_matches_from_safe (args.clone ()) {
+            Ok (matches) => matches,
+            Err (ref e) if e.kind == ErrorKind::HelpDisplayed || e.kind == ErrorKind::VersionDisplayed => {
+                writeln! (streams.stdout, "{}", e.message).expect ("Internal error");
+                return 0
+            },
+            Err (e) => {
+                writeln! (streams.stderr, "{}", e.message).expect ("Internal error");
+                return 1
+            }
+        };
+        // Everything after the subcommand goes on as the Node's own argument list
+        let mut node_args = vec! (args[0].clone ());
+        node_args.extend (args.iter ().skip (2).cloned ());
+        let env_vars: Vec<(String, String)> = env::vars ().collect ();
+        match matches.subcommand () {
+            ("generate-wallet", Some (sub_matches)) => generate_wallet (streams, sub_matches),
+            ("dump-descriptor", _) => dump_descriptor (streams, self.terminal.as_ref (), &node_args, &env_vars),
+            ("status", _) => status (streams, &node_args, &env_vars),
+            _ => ServerInitializer::new ().go (streams, &node_args)
+        }
+    }
+}
+
+impl Cli {
+    pub fn new () -> Cli {
+        Cli {
+            terminal: Box::new (TerminalReal::new ()),
+        }
+    }
+}
+
+// Settings from the environment and the config file are checked as carefully as those on the
+// command line, so this is run over the merged argument list.
+pub fn validate (args: &Vec<String>) -> Result<(), String> {
+    let program = String::from ("SubstratumNode");
+    let mut node_args = vec! (program.clone (), String::from ("run"));
+    node_args.extend (args.iter ().skip_while (|arg| !arg.starts_with ("--")).cloned ());
+    match app ().get_matches_from_safe (node_args) {
+        Ok (_) => Ok (()),
+        Err (e) => Err (e.message)
+    }
+}
+
+fn with_subcommand (args: &Vec<String>) -> Vec<String> {
+    let needs_run = match args.get (1) {
+        None => true,
+        Some (arg) => arg.starts_with ("--") && (arg != "--help") && (arg != "--version")
+    };
+    let mut result = args.clone ();
+    if result.is_empty () {result.push (String::from ("SubstratumNode"))}
+    if needs_run {result.insert (1, String::from ("run"))}
+    result
+}
+
+fn app () -> App<'static, 'static> {
+    App::new ("SubstratumNode")
+        .version (env! ("CARGO_PKG_VERSION"))
+        .about ("A Node on the Substratum Network")
+        .setting (AppSettings::VersionlessSubcommands)
+        .subcommand (with_node_parameters (SubCommand::with_name ("run")
+            .about ("Starts the Node (the default when no subcommand is given)")))
+        .subcommand (SubCommand::with_name ("generate-wallet")
+            .about ("Makes a new consuming wallet and shows its address and recovery phrase")
+            .arg (Arg::with_name ("words").long ("words").takes_value (true).value_name ("12|24")
+                .possible_values (&["12", "24"]).default_value ("24")
+                .help ("How many words the recovery phrase has"))
+            .arg (Arg::with_name ("derivation_path").long ("derivation_path").takes_value (true).value_name ("PATH")
+                .default_value (DEFAULT_DERIVATION_PATH)
+                .help ("Where the wallet lies under the recovery phrase")))
+        .subcommand (with_node_parameters (SubCommand::with_name ("dump-descriptor")
+            .about ("Shows the descriptor a Node started with these settings would have, for its neighbors' --neighbor")))
+        .subcommand (with_node_parameters (SubCommand::with_name ("status")
+            .about ("Checks these settings and whether a Node started with them is running")))
+}
+
+fn with_node_parameters (subcommand: App<'static, 'static>) -> App<'static, 'static> {
+    let subcommand = subcommand.arg (Arg::with_name ("config").long ("config").takes_value (true).value_name ("FILE")
+        .help ("TOML file of settings; defaults to config.toml in the data directory"));
+    PARAMETERS.iter ().fold (subcommand, |subcommand, parameter| subcommand.arg (node_parameter (parameter)))
+}
+
+fn node_parameter (parameter: &'static str) -> Arg<'static, 'static> {
+    let arg = Arg::with_name (parameter).long (parameter).takes_value (true).allow_hyphen_values (true).value_name ("VALUE");
+    let arg = if REPEATABLE_PARAMETERS.contains (&parameter) {arg.multiple (true).number_of_values (1)} else {arg};
+    match validator_for (parameter) {
+        Some (validator) => arg.validator (validator),
+        None => arg
+    }
+}
+
+fn validator_for (parameter: &str) -> Option<Validator> {
+    match parameter {
+        "ip" | "bind_ip" | "http_bind_ip" | "tls_bind_ip" | "dns_bind_ip" | "clandestine_bind_ip" | "ui_bind_ip" => Some (validate_ip_address as Validator),
+        "dns_target" => Some (validate_ipv4_address as Validator),
+        "dns_target_v6" => Some (validate_ipv6_address as Validator),
+        "dns_servers" => Some (validate_ip_address_list as Validator),
+        "dns_port" | "clandestine_port" | "ui_port" => Some (validate_port as Validator),
+        "neighbor" => Some (validate_node_descriptor as Validator),
+        "extra_port" => Some (validate_extra_port as Validator),
+        "earning_wallet" | "signing_wallet" => Some (validate_wallet_address as Validator),
+        "mode" => Some (validate_mode as Validator),
+        "log_level" => Some (validate_log_level as Validator),
+        "rekey_bytes" | "rekey_interval" => Some (validate_whole_number as Validator),
+        "daemon" | "ui_tls" | "recover_consuming_wallet" | "import_consuming_wallet" | "ip_discovery" => Some (validate_on_off as Validator),
+        _ => None
+    }
+}
+
+fn validate_ip_address (value: String) -> Result<(), String> {
+    IpAddr::from_str (&value).map (|_| ()).map_err (|_| format! ("'{}' isn't an IP address", value))
+}
+
+fn validate_ipv4_address (value: String) -> Result<(), String> {
+    Ipv4Addr::from_str (&value).map (|_| ()).map_err (|_| format! ("'{}' isn't an IPv4 address", value))
+}
+
+fn validate_ipv6_address (value: String) -> Result<(), String> {
+    Ipv6Addr::from_str (&value).map (|_| ()).map_err (|_| format! ("'{}' isn't an IPv6 address", value))
+}
+
+fn validate_ip_address_list (value: String) -> Result<(), String> {
+    value.split (",").map (|address| validate_ip_address (String::from (address))).collect::<Result<Vec<()>, String>> ().map (|_| ())
+}
+
+fn validate_port (value: String) -> Result<(), String> {
+    match value.parse::<u16> () {
+        Ok (port) if port > 0 => Ok (()),
+        _ => Err (format! ("'{}' isn't a port number between 1 and 65535", value))
+    }
+}
+
+fn validate_node_descriptor (value: String) -> Result<(), String> {
+    Bootstrapper::parse_node_descriptor (&value).map (|_| ())
+}
+
+fn validate_extra_port (value: String) -> Result<(), String> {
+    Configuration::parse_extra_port (&value).map (|_| ())
+}
+
+fn validate_wallet_address (value: String) -> Result<(), String> {
+    Wallet::new (&value).map (|_| ())
+}
+
+fn validate_mode (value: String) -> Result<(), String> {
+    match value.as_str () {
+        "standard" | "originate_only" | "serve_only" | "zero_hop" => Ok (()),
+        _ => Err (format! ("'{}' isn't one of standard, originate_only, serve_only or zero_hop", value))
+    }
+}
+
+fn validate_on_off (value: String) -> Result<(), String> {
+    match value.as_str () {
+        "on" | "off" => Ok (()),
+        _ => Err (format! ("'{}' isn't on or off", value))
+    }
+}
+
+fn validate_whole_number (value: String) -> Result<(), String> {
+    value.parse::<u64> ().map (|_| ()).map_err (|_| format! ("'{}' isn't a whole number", value))
+}
+
+fn validate_log_level (value: String) -> Result<(), String> {
+    LevelFilter::from_str (&value).map (|_| ()).map_err (|_| format! ("'{}' isn't one of trace, debug, info, warn, error or off", value))
+}
+
+fn merged_and_validated (args: &Vec<String>, env_vars: &Vec<(String, String)>) -> Result<Vec<String>, String> {
+    let merged_args = config_file::merge_args (args, env_vars)?;
+    validate (&merged_args)?;
+    Ok (merged_args)
+}
+
+fn generate_wallet (streams: &mut StdStreams, matches: &ArgMatches) -> u8 {
+    let word_count = matches.value_of ("words").expect ("Internal error").parse::<usize> ().expect ("Internal error");
+    let derivation_path = matches.value_of ("derivation_path").expect ("Internal error");
+    let result = generate_mnemonic (word_count).and_then (|phrase| {
+        let private_key = private_key_from_mnemonic (&phrase, "", derivation_path)?;
+        let signer = Signer::from_private_key (&private_key)?;
+        Ok ((phrase, signer.wallet ()))
+    });
+    match result {
+        Ok ((phrase, wallet)) => {
+            writeln! (streams.stdout, "New consuming wallet: {}", wallet).expect ("Internal error");
+            writeln! (streams.stdout, "Its recovery phrase: {}", phrase).expect ("Internal error");
+            writeln! (streams.stdout, "Write the phrase down, or the wallet is lost; start the Node once with --recover_consuming_wallet on to seal the wallet into its data directory").expect ("Internal error");
+            0
+        },
+        Err (e) => {
+            writeln! (streams.stderr, "Couldn't make a wallet: {}", e).expect ("Internal error");
+            1
+        }
+    }
+}
+
+fn dump_descriptor (streams: &mut StdStreams, terminal: &Terminal, args: &Vec<String>, env_vars: &Vec<(String, String)>) -> u8 {
+    let result = merged_and_validated (args, env_vars).and_then (|merged_args| Bootstrapper::descriptor_for (streams, terminal, &merged_args));
+    match result {
+        Ok (descriptor) => {
+            writeln! (streams.stdout, "{}", descriptor).expect ("Internal error");
+            0
+        },
+        Err (e) => {
+            writeln! (streams.stderr, "{}", e).expect ("Internal error");
+            1
+        }
+    }
+}
+
+// Exits with 0 if the Node seems to be running and 3 if it doesn't, the way init scripts do
+fn status (streams: &mut StdStreams, args: &Vec<String>, env_vars: &Vec<(String, String)>) -> u8 {
+    let merged_args = match merged_and_validated (args, env_vars) {
+        Ok (merged_args) => merged_args,
+        Err (e) => {
+            writeln! (streams.stderr, "{}", e).expect ("Internal error");
+            return 1
+        }
+    };
+    writeln! (streams.stdout, "Settings: OK").expect ("Internal error");
+    let finder = ParameterFinder::new (merged_args.clone ());
+    match finder.find_value_for ("--data_directory", "--data_directory <directory>") {
+        Some (data_directory) => {
+            let identity_path = PathBuf::from (&data_directory).join (IDENTITY_FILENAME);
+            let identity = if identity_path.exists () {"kept there"} else {"not kept yet"};
+            writeln! (streams.stdout, "Data directory: {} (identity {})", data_directory, identity).expect ("Internal error");
+        },
+        None => writeln! (streams.stdout, "Data directory: none; the identity is new every time the Node starts").expect ("Internal error")
+    }
+    let mut configuration = Configuration::new ();
+    configuration.establish (&merged_args);
+    match Bootstrapper::known_clandestine_port (&merged_args) {
+        Ok (Some (port)) => {
+            writeln! (streams.stdout, "Clandestine port: {}", port).expect ("Internal error");
+            configuration.add_clandestine_port (port)
+        },
+        Ok (None) => writeln! (streams.stdout, "Clandestine port: none chosen yet").expect ("Internal error"),
+        Err (e) => writeln! (streams.stdout, "Clandestine port: {}", e).expect ("Internal error")
+    }
+    let listening: Vec<String> = configuration.ports ().into_iter ()
+        .filter (|port| is_listening (configuration.bind_address_for (*port)))
+        .map (|port| port.to_string ())
+        .collect ();
+    if listening.is_empty () {
+        writeln! (streams.stdout, "Running: no; nothing is listening on the Node's ports").expect ("Internal error");
+        3
+    }
+    else {
+        writeln! (streams.stdout, "Running: yes; listening on port(s) {}", listening.join (", ")).expect ("Internal error");
+        0
+    }
+}
+
+// A listener bound to every address can be reached on the loopback one
+fn is_listening (bind_address: SocketAddr) -> bool {
+    let address = if bind_address.ip ().is_unspecified () {
+        SocketAddr::new (IpAddr::V4 (Ipv4Addr::new (127, 0, 0, 1)), bind_address.port ())
+    } else {
+        bind_address
+    };
+    TcpStream::connect_timeout (&address, Duration::from_millis (STATUS_CONNECT_TIMEOUT_MS)).is_ok ()
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use test_utils::test_utils::FakeStreamHolder;
+
+    fn strings (strs: Vec<&str>) -> Vec<String> {
+        strs.into_iter ().map (String::from).collect ()
+    }
+
+    #[test]
+    fn bare_flags_are_taken_as_the_run_subcommand () {
+        let result = with_subcommand (&strings (vec! ("SubstratumNode", "--dns_servers", "1.1.1.1")));
+
+        assert_eq! (result, strings (vec! ("SubstratumNode", "run", "--dns_servers", "1.1.1.1")));
+        assert_eq! (with_subcommand (&strings (vec! ("SubstratumNode"))), strings (vec! ("SubstratumNode", "run")));
+        assert_eq! (with_subcommand (&strings (vec! ("SubstratumNode", "--help"))), strings (vec! ("SubstratumNode", "--help")));
+        assert_eq! (with_subcommand (&strings (vec! ("SubstratumNode", "status"))), strings (vec! ("SubstratumNode", "status")));
+    }
+
+    #[test]
+    fn well_formed_settings_are_valid () {
+        let result = validate (&strings (vec! ("SubstratumNode",
+            "--ip", "1.2.3.4",
+            "--dns_servers", "1.1.1.1,2001:4860:4860::8888",
+            "--dns_port", "5353",
+            "--neighbor", "QmlsbA==;1.2.3.4;80,443",
+            "--neighbor", "QmVu;2.3.4.5;443",
+            "--earning_wallet", "0x5757575757575757575757575757575757575757",
+            "--mode", "zero_hop",
+            "--log_level", "debug",
+            "--gossip_interval", "5000",
+            "--rekey_interval", "600000",
+        )));
+
+        assert_eq! (result, Ok (()));
+    }
+
+    #[test]
+    fn a_bad_address_is_named () {
+        let result = validate (&strings (vec! ("--dns_servers", "1.1.1.1,1.2.3.256")));
+
+        let message = result.unwrap_err ();
+        assert_eq! (message.contains ("--dns_servers"), true, "{}", message);
+        assert_eq! (message.contains ("'1.2.3.256' isn't an IP address"), true, "{}", message);
+    }
+
+    #[test]
+    fn a_bad_port_is_named () {
+        let message = validate (&strings (vec! ("--dns_port", "0"))).unwrap_err ();
+
+        assert_eq! (message.contains ("'0' isn't a port number between 1 and 65535"), true, "{}", message);
+    }
+
+    #[test]
+    fn a_bad_rekey_limit_is_named () {
+        let message = validate (&strings (vec! ("--rekey_bytes", "1GB"))).unwrap_err ();
+
+        assert_eq! (message.contains ("'1GB' isn't a whole number"), true, "{}", message);
+    }
+
+    #[test]
+    fn a_bad_descriptor_is_named () {
+        let message = validate (&strings (vec! ("--neighbor", "QmlsbA==;1.2.3.4;65536"))).unwrap_err ();
+
+        assert_eq! (message.contains ("Neighbor port numbers must be 0-65535, not 65536"), true, "{}", message);
+    }
+
+    #[test]
+    fn unknown_parameters_and_missing_values_are_caught () {
+        let unknown = validate (&strings (vec! ("--dns_server", "1.1.1.1"))).unwrap_err ();
+        let missing = validate (&strings (vec! ("--ip"))).unwrap_err ();
+
+        assert_eq! (unknown.contains ("--dns_server"), true, "{}", unknown);
+        assert_eq! (missing.contains ("--ip"), true, "{}", missing);
+    }
+
+    #[test]
+    fn parameters_that_are_not_repeatable_can_only_be_given_once () {
+        let message = validate (&strings (vec! ("--ip", "1.2.3.4", "--ip", "2.3.4.5"))).unwrap_err ();
+
+        assert_eq! (message.contains ("--ip"), true, "{}", message);
+    }
+
+    #[test]
+    fn bad_flags_are_reported_without_starting_anything () {
+        let mut holder = FakeStreamHolder::new ();
+
+        let result = Cli::new ().go (&mut holder.streams (), &strings (vec! ("SubstratumNode", "--ip", "booga")));
+
+        assert_eq! (result, 1);
+        assert_eq! (holder.stderr.get_string ().contains ("'booga' isn't an IP address"), true);
+    }
+
+    #[test]
+    fn generate_wallet_shows_a_new_wallet_and_its_recovery_phrase () {
+        let mut holder = FakeStreamHolder::new ();
+
+        let result = Cli::new ().go (&mut holder.streams (), &strings (vec! ("SubstratumNode", "generate-wallet", "--words", "12")));
+
+        assert_eq! (result, 0);
+        let stdout = holder.stdout.get_string ();
+        assert_eq! (stdout.contains ("New consuming wallet: 0x"), true, "{}", stdout);
+        let phrase_line = stdout.lines ().find (|line| line.starts_with ("Its recovery phrase: ")).unwrap ();
+        assert_eq! (phrase_line["Its recovery phrase: ".len ()..].split (" ").count (), 12);
+    }
+
+    #[test]
+    fn dump_descriptor_explains_why_there_is_no_descriptor () {
+        let mut holder = FakeStreamHolder::new ();
+
+        let result = Cli::new ().go (&mut holder.streams (), &strings (vec! ("SubstratumNode", "dump-descriptor", "--ip", "1.2.3.4")));
+
+        assert_eq! (result, 1);
+        assert_eq! (holder.stderr.get_string ().contains ("Without --data_directory and an identity passphrase"), true);
+    }
+}
@@ -0,0 +1,292 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::path::PathBuf;
+use toml;
+
+pub const CONFIG_FILE: &str = "config.toml";
+pub const ENV_PREFIX: &str = "SUB_";
+
+// Every parameter the Node understands, without its leading "--". Each one can be given on the
+// command line, as SUB_<PARAMETER> in the environment, or as a key in the config file.
+pub const PARAMETERS: &[&str] = &[
+    "backup_identity", "ban", "bind_ip", "blockchain_service_url", "chain_id", "channel_contract", "channel_deposit", "channel_lifetime",
+    "channel_min_payments", "clandestine_bind_ip", "clandestine_port", "clandestine_transport", "compression", "confirmations",
+    "cover_traffic_interval", "daemon", "data_directory", "debt_ceiling", "derivation_path", "dns_bind_ip", "dns_blocklist",
+    "dns_blocklist_mode", "dns_blocklist_refresh", "dns_hosts", "dns_mode", "dns_override", "dns_port",
+    "dns_query_log", "dns_query_log_clients", "dns_rate_limit", "dns_servers", "dns_target", "dns_target_v6",
+    "dns_upstream", "dns_upstream_ca", "dns_upstream_tls", "earning_wallet", "exit_byte_rate", "exit_location",
+    "exit_service_rate", "extra_port", "fiat_max_debt", "fiat_min_debt", "fiat_payment_threshold", "free_bytes", "free_period",
+    "free_refusal_debt", "free_throttle_debt", "gas_price", "generate_consuming_wallet", "geolocation_database",
+    "gossip_interval", "group", "heartbeat_interval", "http_bind_ip", "import_consuming_wallet", "invoice_interval", "ip", "ip_check_interval",
+    "ip_discovery", "ip_echo_host", "key_overlap", "key_rotation_interval", "log_level", "max_gas_price", "max_hops", "max_neighbors",
+    "max_response_size", "max_route_cost", "min_hops", "min_neighbors", "mix_delay", "mode",
+    "neighbor", "null_cryptde", "padding", "payable_scan_interval", "payment_age_threshold", "payment_retry",
+    "payment_threshold", "payment_watch_interval", "pid_file", "port_mapping", "price_oracle", "public_hostname",
+    "receivable_scan_interval", "recover_consuming_wallet", "rekey_bytes", "rekey_interval", "restore_identity", "route_diversity", "routing_byte_rate", "routing_service_rate",
+    "signing_service_url", "signing_wallet", "stale_node_window", "stun_server", "sub_contract_address", "target_neighbors",
+    "tls_bind_ip", "ui_bind_ip", "ui_certificate", "ui_port", "ui_private_key", "ui_tls", "user",
+];
+
+// The only settings a restart from the UI can change. A restart is run by the supervisor, which is
+// still root, so nothing here may touch privileges, paths, ports or secrets.
+pub const RESTARTABLE_PARAMETERS: &[&str] = &[
+    "ban", "compression", "cover_traffic_interval", "dns_rate_limit", "gossip_interval", "heartbeat_interval",
+    "key_overlap", "key_rotation_interval", "log_level", "max_hops", "max_neighbors", "max_response_size",
+    "max_route_cost", "min_hops", "min_neighbors", "mix_delay", "neighbor", "padding", "route_diversity",
+    "stale_node_window", "target_neighbors",
+];
+
+// Everything downstream reads its settings from the argument list, so settings from the environment
+// and the config file are tacked onto the end of it as if they'd been typed there. A parameter only
+// comes from one place: the command line beats the environment, which beats the file, and anything
+// in none of them keeps its default. Repeatable parameters like --neighbor aren't mixed across places.
+pub fn merge_args (args: &Vec<String>, env_vars: &Vec<(String, String)>) -> Result<Vec<String>, String> {
+    let cli_tags = tags_in (args);
+    let env_args = env_args (env_vars, &cli_tags);
+    let mut given_tags = cli_tags;
+    given_tags.extend (tags_in (&env_args));
+    let file_args = match config_path (args, env_vars) {
+        None => vec! (),
+        Some (location) => file_args (&location, &given_tags)?
+    };
+    let mut merged = args.clone ();
+    merged.extend (env_args);
+    merged.extend (file_args);
+    Ok (merged)
+}
+
+// For a restart with new settings: every value of a parameter the replacements mention is taken out,
+// so a repeatable parameter like --neighbor is replaced as a whole, and the replacements go on the end
+pub fn replace_args (args: &Vec<String>, replacements: &Vec<String>) -> Vec<String> {
+    let replaced_tags = setting_tags (replacements);
+    let mut result = vec! ();
+    let mut index = 0;
+    while index < args.len () {
+        if replaced_tags.contains (&args[index]) {
+            index += 2;
+            continue
+        }
+        result.push (args[index].clone ());
+        index += 1;
+    }
+    result.extend (replacements.iter ().cloned ());
+    result
+}
+
+// Settings for a restart come as tag and value pairs, and every tag has to be one a restart may change
+pub fn check_restartable (settings: &Vec<String>) -> Result<(), String> {
+    if settings.len () % 2 != 0 {
+        return Err (String::from ("Restart settings have to come in pairs"))
+    }
+    for tag in setting_tags (settings) {
+        if !RESTARTABLE_PARAMETERS.iter ().any (|parameter| tag == format! ("--{}", parameter)) {
+            return Err (format! ("Setting '{}' can't be changed by a restart", tag.trim_left_matches ("--")))
+        }
+    }
+    Ok (())
+}
+
+// Only every other one is a tag; a value that starts with -- is still a value
+fn setting_tags (settings: &Vec<String>) -> HashSet<String> {
+    settings.iter ().enumerate ().filter (|&(index, _)| index % 2 == 0).map (|(_, tag)| tag.clone ()).collect ()
+}
+
+fn tags_in (args: &Vec<String>) -> HashSet<String> {
+    args.iter ().filter (|arg| arg.starts_with ("--")).cloned ().collect ()
+}
+
+fn env_args (env_vars: &Vec<(String, String)>, cli_tags: &HashSet<String>) -> Vec<String> {
+    PARAMETERS.iter ()
+        .filter (|parameter| !cli_tags.contains (&format! ("--{}", parameter)))
+        .flat_map (|parameter| {
+            let name = format! ("{}{}", ENV_PREFIX, parameter.to_uppercase ());
+            env_vars.iter ()
+                .find (|&&(ref key, _)| key == &name)
+                .map (|&(_, ref value)| vec! (format! ("--{}", parameter), value.clone ()))
+                .unwrap_or (vec! ())
+        })
+        .collect ()
+}
+
+// An explicitly named config file has to be there; the default one in the data directory is optional.
+fn config_path (args: &Vec<String>, env_vars: &Vec<(String, String)>) -> Option<(PathBuf, bool)> {
+    let find = |tag: &str| -> Option<String> {
+        let from_cli = args.iter ().position (|arg| arg == &format! ("--{}", tag))
+            .and_then (|index| args.get (index + 1).cloned ());
+        let name = format! ("{}{}", ENV_PREFIX, tag.to_uppercase ());
+        from_cli.or (env_vars.iter ().find (|&&(ref key, _)| key == &name).map (|&(_, ref value)| value.clone ()))
+    };
+    match (find ("config"), find ("data_directory")) {
+        (Some (path), _) => Some ((PathBuf::from (path), true)),
+        (None, Some (data_directory)) => Some ((PathBuf::from (data_directory).join (CONFIG_FILE), false)),
+        (None, None) => None
+    }
+}
+
+fn file_args (location: &(PathBuf, bool), given_tags: &HashSet<String>) -> Result<Vec<String>, String> {
+    let (ref path, required) = *location;
+    let mut contents = String::new ();
+    match File::open (path).and_then (|mut file| file.read_to_string (&mut contents)) {
+        Ok (_) => (),
+        Err (ref e) if e.kind () == ErrorKind::NotFound && !required => return Ok (vec! ()),
+        Err (e) => return Err (format! ("Couldn't read config file {:?}: {}", path, e))
+    }
+    let table = match contents.parse::<toml::Value> () {
+        Ok (toml::Value::Table (table)) => table,
+        Ok (_) => return Err (format! ("Config file {:?} isn't a table of settings", path)),
+        Err (e) => return Err (format! ("Config file {:?} isn't valid TOML: {}", path, e))
+    };
+    let mut args = vec! ();
+    for (key, value) in table {
+        if !PARAMETERS.contains (&key.as_str ()) {
+            return Err (format! ("Unknown key '{}' in config file {:?}", key, path))
+        }
+        let values = match value {
+            toml::Value::Array (elements) => elements.into_iter ().map (|element| scalar (&key, element, path)).collect::<Result<Vec<String>, String>> ()?,
+            other => vec! (scalar (&key, other, path)?)
+        };
+        let tag = format! ("--{}", key);
+        if given_tags.contains (&tag) {continue}
+        values.into_iter ().for_each (|value| {
+            args.push (tag.clone ());
+            args.push (value);
+        });
+    }
+    Ok (args)
+}
+
+fn scalar (key: &str, value: toml::Value, path: &PathBuf) -> Result<String, String> {
+    match value {
+        toml::Value::String (string) => Ok (string),
+        toml::Value::Integer (integer) => Ok (integer.to_string ()),
+        toml::Value::Float (float) => Ok (float.to_string ()),
+        toml::Value::Boolean (true) => Ok (String::from ("on")),
+        toml::Value::Boolean (false) => Ok (String::from ("off")),
+        _ => Err (format! ("Invalid value for key '{}' in config file {:?}: expected a string, number, boolean, or list of them", key, path))
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs;
+    use std::io::Write;
+
+    fn make_data_directory (name: &str, contents: Option<&str>) -> PathBuf {
+        let data_directory = temp_dir ().join ("config_file").join (name);
+        let _ = fs::remove_dir_all (&data_directory);
+        fs::create_dir_all (&data_directory).unwrap ();
+        if let Some (contents) = contents {
+            File::create (data_directory.join (CONFIG_FILE)).unwrap ().write_all (contents.as_bytes ()).unwrap ();
+        }
+        data_directory
+    }
+
+    fn strings (strs: Vec<&str>) -> Vec<String> {
+        strs.into_iter ().map (String::from).collect ()
+    }
+
+    fn pairs (strs: Vec<(&str, &str)>) -> Vec<(String, String)> {
+        strs.into_iter ().map (|(key, value)| (String::from (key), String::from (value))).collect ()
+    }
+
+    #[test]
+    fn without_a_config_file_or_environment_the_arguments_are_left_alone () {
+        let args = strings (vec! ("SubstratumNode", "--dns_servers", "1.1.1.1"));
+
+        let result = merge_args (&args, &pairs (vec! (("PATH", "/usr/bin"))));
+
+        assert_eq! (result, Ok (args));
+    }
+
+    #[test]
+    fn the_command_line_beats_the_environment_which_beats_the_config_file () {
+        let data_directory = make_data_directory ("the_command_line_beats_the_environment_which_beats_the_config_file",
+            Some ("dns_servers = \"8.8.8.8\"\nlog_level = \"info\"\ngossip_interval = 5000\npadding = true\nneighbor = [\"first\", \"second\"]\n"));
+        let data_directory_string = data_directory.to_string_lossy ().to_string ();
+        let args = strings (vec! ("SubstratumNode", "--data_directory", data_directory_string.as_str (), "--dns_servers", "1.1.1.1"));
+        let env_vars = pairs (vec! (("SUB_LOG_LEVEL", "debug"), ("SUB_DNS_SERVERS", "9.9.9.9")));
+
+        let result = merge_args (&args, &env_vars).unwrap ();
+
+        let mut expected = args.clone ();
+        expected.extend (strings (vec! (
+            "--log_level", "debug",
+            "--gossip_interval", "5000",
+            "--neighbor", "first", "--neighbor", "second",
+            "--padding", "on",
+        )));
+        assert_eq! (result, expected);
+    }
+
+    #[test]
+    fn an_explicit_config_file_must_exist () {
+        let data_directory = make_data_directory ("an_explicit_config_file_must_exist", None);
+        let config = data_directory.join (CONFIG_FILE).to_string_lossy ().to_string ();
+
+        let result = merge_args (&strings (vec! ("SubstratumNode", "--config", config.as_str ())), &vec! ());
+
+        assert_eq! (result.unwrap_err ().starts_with ("Couldn't read config file"), true);
+    }
+
+    #[test]
+    fn the_config_file_can_be_named_in_the_environment () {
+        let data_directory = make_data_directory ("the_config_file_can_be_named_in_the_environment", Some ("mode = \"zero_hop\"\n"));
+        let config = data_directory.join (CONFIG_FILE).to_string_lossy ().to_string ();
+
+        let result = merge_args (&strings (vec! ("SubstratumNode")), &pairs (vec! (("SUB_CONFIG", config.as_str ()))));
+
+        assert_eq! (result, Ok (strings (vec! ("SubstratumNode", "--mode", "zero_hop"))));
+    }
+
+    #[test]
+    fn unknown_keys_are_named () {
+        let data_directory = make_data_directory ("unknown_keys_are_named", Some ("dns_server = \"1.1.1.1\"\n"));
+        let data_directory_string = data_directory.to_string_lossy ().to_string ();
+
+        let result = merge_args (&strings (vec! ("--data_directory", data_directory_string.as_str ())), &vec! ());
+
+        assert_eq! (result.unwrap_err ().starts_with ("Unknown key 'dns_server' in config file"), true);
+    }
+
+    #[test]
+    fn replacement_arguments_take_the_place_of_every_value_they_name () {
+        let args = strings (vec! ("SubstratumNode", "--neighbor", "first", "--log_level", "warn", "--neighbor", "second", "--dns_servers", "1.1.1.1"));
+
+        let result = replace_args (&args, &strings (vec! ("--neighbor", "third", "--log_level", "debug")));
+
+        assert_eq! (result, strings (vec! ("SubstratumNode", "--dns_servers", "1.1.1.1", "--neighbor", "third", "--log_level", "debug")));
+    }
+
+    #[test]
+    fn a_restart_cannot_change_privileges_paths_or_secrets () {
+        assert_eq! (check_restartable (&strings (vec! ("--log_level", "debug", "--ban", "--user"))), Ok (()));
+        assert_eq! (check_restartable (&strings (vec! ("--log_level", "debug", "--data_directory", "/etc"))),
+            Err (String::from ("Setting 'data_directory' can't be changed by a restart")));
+        assert_eq! (check_restartable (&strings (vec! ("--user", "root"))), Err (String::from ("Setting 'user' can't be changed by a restart")));
+        assert_eq! (check_restartable (&strings (vec! ("--log_level"))), Err (String::from ("Restart settings have to come in pairs")));
+    }
+
+    #[test]
+    fn a_replacement_value_that_looks_like_a_tag_replaces_nothing () {
+        let args = strings (vec! ("SubstratumNode", "--user", "nobody", "--ban", "first"));
+
+        let result = replace_args (&args, &strings (vec! ("--ban", "--user")));
+
+        assert_eq! (result, strings (vec! ("SubstratumNode", "--user", "nobody", "--ban", "--user")));
+    }
+
+    #[test]
+    fn values_that_cannot_be_arguments_are_named () {
+        let data_directory = make_data_directory ("values_that_cannot_be_arguments_are_named", Some ("[dns_servers]\nfirst = \"1.1.1.1\"\n"));
+        let data_directory_string = data_directory.to_string_lossy ().to_string ();
+
+        let result = merge_args (&strings (vec! ("--data_directory", data_directory_string.as_str ())), &vec! ());
+
+        assert_eq! (result.unwrap_err ().starts_with ("Invalid value for key 'dns_servers' in config file"), true);
+    }
+}
@@ -0,0 +1,237 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::any::Any;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::sync::Mutex;
+#[cfg (unix)]
+use std::sync::atomic::AtomicBool;
+#[cfg (unix)]
+use std::sync::atomic::Ordering;
+#[cfg (unix)]
+use std::sync::atomic::ATOMIC_BOOL_INIT;
+#[cfg (unix)]
+use std::thread;
+#[cfg (unix)]
+use std::time::Duration;
+#[cfg (unix)]
+use libc;
+use config_file;
+use config_file::PARAMETERS;
+use sub_lib::logger::Logger;
+use sub_lib::socket_server::Reloader;
+
+// Settings that can change while the Node runs. A change to anything else waits for a restart.
+pub const RELOADABLE_PARAMETERS: &[&str] = &["log_level", "dns_rate_limit", "dns_blocklist", "dns_blocklist_mode", "exit_location"];
+
+#[cfg (unix)]
+const SIGHUP_CHECK_MS: u64 = 500;
+
+// Reads the settings again from wherever they came from at startup, and hands the new ones to
+// everything that can use them without a restart
+pub struct ConfigReloader {
+    cli_args: Vec<String>,
+    env_vars: Vec<(String, String)>,
+    current_args: Vec<String>,
+    reloaders: Vec<Box<Reloader>>,
+    logger: Logger,
+}
+
+impl ConfigReloader {
+    pub fn new (cli_args: &Vec<String>, env_vars: &Vec<(String, String)>, current_args: &Vec<String>, reloaders: Vec<Box<Reloader>>) -> ConfigReloader {
+        ConfigReloader {
+            cli_args: cli_args.clone (),
+            env_vars: env_vars.clone (),
+            current_args: current_args.clone (),
+            reloaders,
+            logger: Logger::new ("ConfigReloader"),
+        }
+    }
+
+    // Either everything that's changed is applied or none of it is; the names of the changed
+    // settings come back
+    pub fn reload (&mut self) -> Result<Vec<String>, String> {
+        let result = self.try_reload ();
+        match result {
+            Ok (ref changed) if changed.is_empty () => self.logger.info (String::from ("Configuration reloaded; nothing has changed")),
+            Ok (ref changed) => self.logger.info (format! ("Configuration reloaded; changed {}", changed.join (", "))),
+            Err (ref e) => self.logger.error (format! ("Configuration not reloaded: {}", e))
+        }
+        result
+    }
+
+    fn try_reload (&mut self) -> Result<Vec<String>, String> {
+        let new_args = config_file::merge_args (&self.cli_args, &self.env_vars)?;
+        let changed: Vec<String> = PARAMETERS.iter ()
+            .filter (|parameter| values_of (&self.current_args, parameter) != values_of (&new_args, parameter))
+            .map (|parameter| String::from (*parameter))
+            .collect ();
+        let fixed: Vec<String> = changed.iter ()
+            .filter (|parameter| !RELOADABLE_PARAMETERS.contains (&parameter.as_str ()))
+            .cloned ()
+            .collect ();
+        if !fixed.is_empty () {
+            return Err (format! ("Can't change {} without restarting the Node", fixed.join (", ")))
+        }
+        if changed.is_empty () {return Ok (changed)}
+        for reloader in &self.reloaders {
+            apply (reloader.as_ref (), &new_args)?;
+        }
+        self.current_args = new_args;
+        Ok (changed)
+    }
+}
+
+fn values_of (args: &Vec<String>, parameter: &str) -> Vec<String> {
+    let tag = format! ("--{}", parameter);
+    args.iter ().zip (args.iter ().skip (1))
+        .filter (|&(name, _)| *name == tag)
+        .map (|(_, value)| value.clone ())
+        .collect ()
+}
+
+// Settings are parsed the way they are at startup, where a bad value is a panic. A bad value in a
+// reload mustn't take the running Node down with it.
+fn apply (reloader: &Reloader, args: &Vec<String>) -> Result<(), String> {
+    match panic::catch_unwind (AssertUnwindSafe (|| reloader.reload (args))) {
+        Ok (result) => result,
+        Err (payload) => Err (panic_message (payload))
+    }
+}
+
+fn panic_message (payload: Box<Any + Send>) -> String {
+    match payload.downcast::<String> () {
+        Ok (message) => *message,
+        Err (payload) => match payload.downcast::<&'static str> () {
+            Ok (message) => String::from (*message),
+            Err (_) => String::from ("Invalid setting")
+        }
+    }
+}
+
+#[cfg (unix)]
+static RELOAD_WANTED: AtomicBool = ATOMIC_BOOL_INIT;
+
+#[cfg (unix)]
+extern "C" fn on_sighup (_signal: libc::c_int) {
+    RELOAD_WANTED.store (true, Ordering::SeqCst);
+}
+
+// A signal handler can't safely do anything much besides set a flag, so the reload happens on a
+// thread that keeps an eye on the flag
+#[cfg (unix)]
+pub fn reload_on_sighup (config_reloader: Arc<Mutex<ConfigReloader>>) {
+    unsafe {libc::signal (libc::SIGHUP, on_sighup as libc::sighandler_t);}
+    thread::spawn (move || {
+        loop {
+            thread::sleep (Duration::from_millis (SIGHUP_CHECK_MS));
+            if RELOAD_WANTED.swap (false, Ordering::SeqCst) {
+                let _ = config_reloader.lock ().expect ("ConfigReloader poisoned").reload ();
+            }
+        }
+    });
+}
+
+// Windows has no SIGHUP
+#[cfg (windows)]
+pub fn reload_on_sighup (_config_reloader: Arc<Mutex<ConfigReloader>>) {
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs;
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use config_file::CONFIG_FILE;
+
+    struct ReloaderMock {
+        reload_parameters: Arc<Mutex<Vec<Vec<String>>>>,
+        panic_message_opt: Option<String>,
+    }
+
+    impl Reloader for ReloaderMock {
+        fn reload (&self, args: &Vec<String>) -> Result<(), String> {
+            self.reload_parameters.lock ().unwrap ().push (args.clone ());
+            match self.panic_message_opt {
+                Some (ref message) => panic! ("{}", message),
+                None => Ok (())
+            }
+        }
+    }
+
+    fn make_data_directory (name: &str, contents: &str) -> PathBuf {
+        let data_directory = temp_dir ().join ("config_reloader").join (name);
+        let _ = fs::remove_dir_all (&data_directory);
+        fs::create_dir_all (&data_directory).unwrap ();
+        write_config (&data_directory, contents);
+        data_directory
+    }
+
+    fn write_config (data_directory: &PathBuf, contents: &str) {
+        File::create (data_directory.join (CONFIG_FILE)).unwrap ().write_all (contents.as_bytes ()).unwrap ();
+    }
+
+    fn make_subject (data_directory: &PathBuf, panic_message_opt: Option<&str>) -> (ConfigReloader, Arc<Mutex<Vec<Vec<String>>>>) {
+        let cli_args = vec! (String::from ("SubstratumNode"), String::from ("--data_directory"), data_directory.to_string_lossy ().to_string ());
+        let current_args = config_file::merge_args (&cli_args, &vec! ()).unwrap ();
+        let reload_parameters = Arc::new (Mutex::new (vec! ()));
+        let reloader = ReloaderMock {reload_parameters: reload_parameters.clone (), panic_message_opt: panic_message_opt.map (String::from)};
+        (ConfigReloader::new (&cli_args, &vec! (), &current_args, vec! (Box::new (reloader))), reload_parameters)
+    }
+
+    #[test]
+    fn nothing_is_reloaded_if_nothing_has_changed () {
+        let data_directory = make_data_directory ("nothing_is_reloaded_if_nothing_has_changed", "log_level = \"warn\"\n");
+        let (mut subject, reload_parameters) = make_subject (&data_directory, None);
+
+        let result = subject.reload ();
+
+        assert_eq! (result, Ok (vec! ()));
+        assert_eq! (reload_parameters.lock ().unwrap ().is_empty (), true);
+    }
+
+    #[test]
+    fn changed_settings_that_can_be_reloaded_are_handed_to_the_reloaders () {
+        let data_directory = make_data_directory ("changed_settings_that_can_be_reloaded_are_handed_to_the_reloaders", "log_level = \"warn\"\n");
+        let (mut subject, reload_parameters) = make_subject (&data_directory, None);
+        write_config (&data_directory, "log_level = \"debug\"\ndns_rate_limit = 20\n");
+
+        let result = subject.reload ();
+        let second_result = subject.reload ();
+
+        assert_eq! (result, Ok (vec! (String::from ("dns_rate_limit"), String::from ("log_level"))));
+        assert_eq! (second_result, Ok (vec! ()));
+        let reload_parameters = reload_parameters.lock ().unwrap ();
+        assert_eq! (reload_parameters.len (), 1);
+        assert_eq! (values_of (&reload_parameters[0], "log_level"), vec! (String::from ("debug")));
+        assert_eq! (values_of (&reload_parameters[0], "dns_rate_limit"), vec! (String::from ("20")));
+    }
+
+    #[test]
+    fn changes_to_settings_that_need_a_restart_are_refused_along_with_everything_else () {
+        let data_directory = make_data_directory ("changes_to_settings_that_need_a_restart_are_refused_along_with_everything_else",
+            "log_level = \"warn\"\nneighbor = [\"first\"]\n");
+        let (mut subject, reload_parameters) = make_subject (&data_directory, None);
+        write_config (&data_directory, "log_level = \"debug\"\nneighbor = [\"first\", \"second\"]\nmin_hops = 2\n");
+
+        let result = subject.reload ();
+
+        assert_eq! (result, Err (String::from ("Can't change min_hops, neighbor without restarting the Node")));
+        assert_eq! (reload_parameters.lock ().unwrap ().is_empty (), true);
+    }
+
+    #[test]
+    fn a_bad_value_is_reported_without_taking_the_node_down_and_can_be_fixed_later () {
+        let data_directory = make_data_directory ("a_bad_value_is_reported_without_taking_the_node_down_and_can_be_fixed_later", "");
+        let (mut subject, _) = make_subject (&data_directory, Some ("Invalid value for --dns_rate_limit: fast"));
+        write_config (&data_directory, "dns_rate_limit = \"fast\"\n");
+
+        let result = subject.reload ();
+
+        assert_eq! (result, Err (String::from ("Invalid value for --dns_rate_limit: fast")));
+        assert_eq! (values_of (&subject.current_args, "dns_rate_limit").is_empty (), true);
+    }
+}
@@ -1,25 +1,159 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use std::collections::HashMap;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::str::FromStr;
 use discriminator::DiscriminatorFactory;
 use http_request_start_finder::HttpRequestDiscriminatorFactory;
 use tls_discriminator::TlsDiscriminatorFactory;
+use json_masquerader::JsonDiscriminatorFactory;
+use bootstrapper::Bootstrapper;
+use bootstrapper::NodeMode;
+use sub_lib::parameter_finder::ParameterFinder;
+
+// What a listening port expects to hear: browser traffic for the ProxyServer in the clear or in TLS,
+// or clandestine traffic from other Nodes
+#[derive (Clone, Copy, Debug, PartialEq)]
+pub enum ListenerProtocol {
+    Http,
+    Tls,
+    Clandestine,
+}
+
+impl ListenerProtocol {
+    fn from_str (string: &str) -> Option<ListenerProtocol> {
+        match string {
+            "http" => Some (ListenerProtocol::Http),
+            "tls" => Some (ListenerProtocol::Tls),
+            "clandestine" => Some (ListenerProtocol::Clandestine),
+            _ => None
+        }
+    }
+
+    fn discriminator_factory (&self) -> Box<DiscriminatorFactory> {
+        match *self {
+            ListenerProtocol::Http => Box::new (HttpRequestDiscriminatorFactory::new ()),
+            ListenerProtocol::Tls => Box::new (TlsDiscriminatorFactory::new ()),
+            ListenerProtocol::Clandestine => Box::new (JsonDiscriminatorFactory::new ()),
+        }
+    }
+}
 
 pub struct Configuration {
-    port_discriminator_factories: HashMap<u16, Vec<Box<DiscriminatorFactory>>>
+    port_discriminator_factories: HashMap<u16, Vec<Box<DiscriminatorFactory>>>,
+    port_bind_ips: HashMap<u16, IpAddr>,
+    http_bind_ip: IpAddr,
+    tls_bind_ip: IpAddr,
+    clandestine_bind_ip: IpAddr,
+    clandestine_ports: Vec<u16>,
 }
 
 impl Configuration {
     pub fn new () -> Configuration {
         Configuration {
-            port_discriminator_factories: HashMap::new ()
+            port_discriminator_factories: HashMap::new (),
+            port_bind_ips: HashMap::new (),
+            http_bind_ip: IpAddr::V4 (Ipv4Addr::from (0)),
+            tls_bind_ip: IpAddr::V4 (Ipv4Addr::from (0)),
+            clandestine_bind_ip: IpAddr::V4 (Ipv4Addr::from (0)),
+            clandestine_ports: vec! (),
+        }
+    }
+
+    pub fn establish (&mut self, args: &Vec<String>) {
+        let finder = ParameterFinder::new (args.clone ());
+        self.http_bind_ip = Configuration::parse_bind_ip (&finder, "--http_bind_ip");
+        self.tls_bind_ip = Configuration::parse_bind_ip (&finder, "--tls_bind_ip");
+        self.clandestine_bind_ip = Configuration::parse_bind_ip (&finder, "--clandestine_bind_ip");
+        let mode = Bootstrapper::parse_mode (&finder);
+        // Ports 80 and 443 are the ProxyServer front end, which a serve-only Node doesn't have
+        if mode != NodeMode::ServeOnly {
+            self.add_port (80, &vec! (ListenerProtocol::Http));
+            self.add_port (443, &vec! (ListenerProtocol::Tls));
+        }
+        for value in finder.find_values_for ("--extra_port", "--extra_port <port>:<protocol>,... where each protocol is http, tls or clandestine") {
+            let (port, protocols) = Configuration::parse_extra_port (&value).unwrap_or_else (|e| panic! ("{}", e));
+            Configuration::check_protocols_for_mode (mode, port, &protocols);
+            self.add_port (port, &protocols);
+            if protocols.contains (&ListenerProtocol::Clandestine) {
+                self.clandestine_ports.push (port);
+            }
+        }
+    }
+
+    // Other Nodes reach this one on its clandestine port
+    pub fn add_clandestine_port (&mut self, port: u16) {
+        self.add_port (port, &vec! (ListenerProtocol::Clandestine));
+        self.clandestine_ports.insert (0, port);
+    }
+
+    // The clandestine port, followed by any extra ports that take clandestine traffic
+    pub fn clandestine_ports (&self) -> Vec<u16> {
+        self.clandestine_ports.clone ()
+    }
+
+    pub fn bind_address_for (&self, port: u16) -> SocketAddr {
+        match self.port_bind_ips.get (&port) {
+            Some (ip_addr) => SocketAddr::new (*ip_addr, port),
+            None => SocketAddr::new (IpAddr::V4 (Ipv4Addr::from (0)), port)
         }
     }
 
-    pub fn establish (&mut self, _args: &Vec<String>) {
-        self.port_discriminator_factories.insert (80,
-            vec! (Box::new (HttpRequestDiscriminatorFactory::new ())));
-        self.port_discriminator_factories.insert (443,
-            vec! (Box::new (TlsDiscriminatorFactory::new ())));
+    // <port>:<protocol>,<protocol>,... such as 8080:http
+    pub fn parse_extra_port (value: &str) -> Result<(u16, Vec<ListenerProtocol>), String> {
+        let pieces: Vec<&str> = value.splitn (2, ':').collect ();
+        if pieces.len () != 2 {
+            return Err (format! ("Invalid value for --extra_port <port>:<protocol>,...: '{}'", value))
+        }
+        let port = match pieces[0].parse::<u16> () {
+            Ok (port) if port > 0 => port,
+            _ => return Err (format! ("Invalid port for --extra_port <port>:<protocol>,...: '{}'", pieces[0]))
+        };
+        let protocols = pieces[1].split (',')
+            .map (|name| ListenerProtocol::from_str (name)
+                .ok_or (format! ("Invalid protocol for --extra_port <port>:<protocol>,...: '{}' isn't http, tls or clandestine", name)))
+            .collect::<Result<Vec<ListenerProtocol>, String>> ()?;
+        Ok ((port, protocols))
+    }
+
+    fn check_protocols_for_mode (mode: NodeMode, port: u16, protocols: &Vec<ListenerProtocol>) {
+        let front_end = protocols.iter ().any (|protocol| *protocol != ListenerProtocol::Clandestine);
+        if (mode == NodeMode::ServeOnly) && front_end {
+            panic! ("A serve-only Node has no front end to put on --extra_port {}", port)
+        }
+        if (mode == NodeMode::ZeroHop) && protocols.contains (&ListenerProtocol::Clandestine) {
+            panic! ("A zero-hop Node takes no clandestine traffic on --extra_port {}", port)
+        }
+    }
+
+    // A port that takes more than one protocol listens on the address of the first of them
+    fn add_port (&mut self, port: u16, protocols: &Vec<ListenerProtocol>) {
+        if self.port_discriminator_factories.contains_key (&port) {
+            panic! ("Port {} can't be used for more than one listener", port)
+        }
+        self.port_discriminator_factories.insert (port,
+            protocols.iter ().map (|protocol| protocol.discriminator_factory ()).collect ());
+        let bind_ip = match protocols[0] {
+            ListenerProtocol::Http => self.http_bind_ip,
+            ListenerProtocol::Tls => self.tls_bind_ip,
+            ListenerProtocol::Clandestine => self.clandestine_bind_ip,
+        };
+        self.port_bind_ips.insert (port, bind_ip);
+    }
+
+    // A listener binds to the address given for it, or else to the one given for all of them, or else
+    // to every address the machine has
+    fn parse_bind_ip (finder: &ParameterFinder, parameter_tag: &str) -> IpAddr {
+        let usage = format! ("{} <IP address> of the local interface to listen on", parameter_tag);
+        let (tag, value) = match finder.find_value_for (parameter_tag, &usage) {
+            Some (value) => (parameter_tag, value),
+            None => match finder.find_value_for ("--bind_ip", "--bind_ip <IP address> of the local interface every listener should listen on") {
+                Some (value) => ("--bind_ip", value),
+                None => return IpAddr::V4 (Ipv4Addr::from (0))
+            }
+        };
+        IpAddr::from_str (&value).unwrap_or_else (|_| panic! ("Invalid value for {} <IP address>: '{}'", tag, value))
     }
 
     pub fn ports (&self) -> Vec<u16> {
@@ -75,6 +209,108 @@ mod tests {
                                                    Component::ProxyServer, true));
     }
 
+    #[test]
+    fn serve_only_mode_produces_no_front_end_ports () {
+        let args = vec! (String::from ("command"), String::from ("--mode"), String::from ("serve_only"));
+        let mut subject = Configuration::new ();
+
+        subject.establish (&args);
+
+        assert_eq! (subject.ports (), Vec::<u16>::new ());
+    }
+
+    #[test]
+    fn listeners_bind_to_every_address_unless_told_otherwise () {
+        let mut subject = Configuration::new ();
+
+        subject.establish (&vec! (String::from ("command")));
+        subject.add_clandestine_port (4321);
+
+        assert_eq! (subject.bind_address_for (80), SocketAddr::from_str ("0.0.0.0:80").unwrap ());
+        assert_eq! (subject.bind_address_for (443), SocketAddr::from_str ("0.0.0.0:443").unwrap ());
+        assert_eq! (subject.bind_address_for (4321), SocketAddr::from_str ("0.0.0.0:4321").unwrap ());
+    }
+
+    #[test]
+    fn each_listener_can_have_an_address_of_its_own_or_share_one () {
+        let args = vec! ("command", "--bind_ip", "10.0.0.5", "--tls_bind_ip", "127.0.0.1", "--clandestine_bind_ip", "192.168.1.5")
+            .into_iter ().map (String::from).collect ();
+        let mut subject = Configuration::new ();
+
+        subject.establish (&args);
+        subject.add_clandestine_port (4321);
+
+        assert_eq! (subject.bind_address_for (80), SocketAddr::from_str ("10.0.0.5:80").unwrap ());
+        assert_eq! (subject.bind_address_for (443), SocketAddr::from_str ("127.0.0.1:443").unwrap ());
+        assert_eq! (subject.bind_address_for (4321), SocketAddr::from_str ("192.168.1.5:4321").unwrap ());
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --bind_ip <IP address>: 'booga'")]
+    fn a_bad_bind_address_is_named () {
+        let args = vec! ("command", "--bind_ip", "booga").into_iter ().map (String::from).collect ();
+
+        Configuration::new ().establish (&args);
+    }
+
+    #[test]
+    fn extra_ports_get_the_protocols_they_are_given () {
+        let args = vec! ("command", "--extra_port", "8080:http", "--extra_port", "8443:tls,clandestine", "--tls_bind_ip", "127.0.0.1")
+            .into_iter ().map (String::from).collect ();
+        let mut subject = Configuration::new ();
+
+        subject.establish (&args);
+        subject.add_clandestine_port (4321);
+
+        let mut ports = subject.ports ();
+        ports.sort ();
+        assert_eq! (ports, vec! (80, 443, 4321, 8080, 8443));
+        assert_eq! (subject.clandestine_ports (), vec! (4321, 8443));
+        assert_eq! (subject.bind_address_for (8443), SocketAddr::from_str ("127.0.0.1:8443").unwrap ());
+        let mut http_discriminator = subject.take_discriminator_factories_for (8080).remove (0).make ();
+        http_discriminator.add_data ("GET http://url.com HTTP/1.1\r\n\r\n".as_bytes ());
+        assert_eq! (http_discriminator.take_chunk ().unwrap ().component, Component::ProxyServer);
+        assert_eq! (subject.take_discriminator_factories_for (8443).len (), 2);
+    }
+
+    #[test]
+    fn extra_ports_are_checked () {
+        assert_eq! (Configuration::parse_extra_port ("8080"), Err (String::from ("Invalid value for --extra_port <port>:<protocol>,...: '8080'")));
+        assert_eq! (Configuration::parse_extra_port ("0:http"), Err (String::from ("Invalid port for --extra_port <port>:<protocol>,...: '0'")));
+        assert_eq! (Configuration::parse_extra_port ("8080:gopher"),
+            Err (String::from ("Invalid protocol for --extra_port <port>:<protocol>,...: 'gopher' isn't http, tls or clandestine")));
+    }
+
+    #[test]
+    #[should_panic (expected = "Port 80 can't be used for more than one listener")]
+    fn an_extra_port_cannot_take_over_another_listener () {
+        let args = vec! ("command", "--extra_port", "80:tls").into_iter ().map (String::from).collect ();
+
+        Configuration::new ().establish (&args);
+    }
+
+    #[test]
+    #[should_panic (expected = "A serve-only Node has no front end to put on --extra_port 8080")]
+    fn a_serve_only_node_has_no_extra_front_end_ports () {
+        let args = vec! ("command", "--mode", "serve_only", "--extra_port", "8080:http").into_iter ().map (String::from).collect ();
+
+        Configuration::new ().establish (&args);
+    }
+
+    #[test]
+    fn a_clandestine_port_gets_clandestine_traffic () {
+        let mut subject = Configuration::new ();
+
+        subject.add_clandestine_port (4321);
+
+        assert_eq! (subject.ports (), vec! (4321));
+        let mut factories = subject.take_discriminator_factories_for (4321);
+        assert_eq! (factories.len (), 1);
+        let mut discriminator = factories.remove (0).make ();
+        discriminator.add_data (b"{\"component\": \"HOPR\", \"bodyText\": \"booga\"}");
+        assert_eq! (discriminator.take_chunk (), Some (UnmaskedChunk::new (b"booga".to_vec (), Component::Hopper, true)));
+    }
+
     #[test]
     fn ports_returns_list_of_ports () {
         let mut subject = Configuration::new ();
@@ -42,6 +42,12 @@ impl Discriminator {
         }
     }
 
+    // Data already added but not yet taken is unmasked by the new Masqueraders
+    pub fn replace_masqueraders (&mut self, masqueraders: Vec<Box<Masquerader>>) {
+        if masqueraders.is_empty () {panic! ("Discriminator must be given at least one Masquerader");}
+        self.masqueraders = masqueraders;
+    }
+
     pub fn add_data(&mut self, data: &[u8]) {
         self.framer.add_data (data);
     }
@@ -140,6 +146,33 @@ mod tests {
         Discriminator::new (Box::new (FramerMock::new ()), vec! ());
     }
 
+    #[test]
+    #[should_panic (expected = "Discriminator must be given at least one Masquerader")]
+    fn complains_if_masqueraders_are_replaced_with_none () {
+        let mut subject = Discriminator::new (Box::new (FramerMock::new ()),
+                                              vec! (Box::new (MasqueraderMock::new ())));
+
+        subject.replace_masqueraders (vec! ());
+    }
+
+    #[test]
+    fn replacement_masqueraders_unmask_data_that_was_added_before_them () {
+        let mut framer = FramerMock::new ();
+        framer.add_data (&b"booga"[..]);
+        let mut old_try_unmask_parameters: Arc<Mutex<Vec<Vec<u8>>>> = Arc::new (Mutex::new (vec! ()));
+        let old_masquerader = MasqueraderMock::new ()
+            .try_unmask_parameters (&mut old_try_unmask_parameters);
+        let new_masquerader = MasqueraderMock::new ()
+            .try_unmask_result (Some (UnmaskedChunk::new (Vec::from (&b"unmasked"[..]), Component::Hopper, true)));
+        let mut subject = Discriminator::new (Box::new (framer), vec! (Box::new (old_masquerader)));
+
+        subject.replace_masqueraders (vec! (Box::new (new_masquerader)));
+        let result = subject.take_chunk ();
+
+        assert_eq! (result, Some (UnmaskedChunk::new (Vec::from (&b"unmasked"[..]), Component::Hopper, true)));
+        assert_eq! (old_try_unmask_parameters.lock ().unwrap ().len (), 0);
+    }
+
     #[test]
     fn returns_none_if_no_data_has_been_added () {
         let mut subject = Discriminator::new (Box::new (FramerMock::new ()),
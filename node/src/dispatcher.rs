@@ -10,8 +10,11 @@ use actix::Syn;
 use sub_lib::dispatcher::Component;
 use sub_lib::dispatcher::InboundClientData;
 use sub_lib::dispatcher::DispatcherSubs;
+use sub_lib::dispatcher::Endpoint;
 use sub_lib::hopper::HopperTemporaryTransmitDataMsg;
 use sub_lib::logger::Logger;
+use sub_lib::neighborhood::NodeBannedMsg;
+use sub_lib::neighborhood::NodeUnbannedMsg;
 use sub_lib::peer_actors::BindMessage;
 use sub_lib::stream_handler_pool::TransmitDataMsg;
 use sub_lib::utils::NODE_MAILBOX_CAPACITY;
@@ -21,6 +24,8 @@ pub struct Dispatcher {
     to_proxy_server: Option<Recipient<Syn, InboundClientData>>,
     to_hopper: Option<Recipient<Syn, InboundClientData>>,
     to_stream: Option<Recipient<Syn, TransmitDataMsg>>,
+    to_stream_bans: Option<Recipient<Syn, NodeBannedMsg>>,
+    to_stream_unbans: Option<Recipient<Syn, NodeUnbannedMsg>>,
     logger: Logger,
 }
 
@@ -43,6 +48,8 @@ impl Handler<PoolBindMessage> for Dispatcher {
 
     fn handle(&mut self, msg: PoolBindMessage, _ctx: &mut Self::Context) {
         self.to_stream = Some(msg.stream_handler_pool_subs.transmit_sub);
+        self.to_stream_bans = Some(msg.stream_handler_pool_subs.node_banned);
+        self.to_stream_unbans = Some(msg.stream_handler_pool_subs.node_unbanned);
     }
 }
 
@@ -67,12 +74,19 @@ impl Handler<HopperTemporaryTransmitDataMsg> for Dispatcher {
 
     fn handle(&mut self, msg: HopperTemporaryTransmitDataMsg, _ctx: &mut Self::Context) {
         self.logger.debug (format! ("Echoing {} bytes from Hopper to Hopper", msg.data.len ()));
+        // Everything echoed comes from this Node, which numbers its packages per destination, so
+        // each destination counts as a sender of its own
+        let peer_public_key_opt = match msg.endpoint {
+            Endpoint::Key (ref key) => Some (key.clone ()),
+            _ => None
+        };
         let ibcd = InboundClientData {
             last_data: msg.last_data,
             data: msg.data,
             socket_addr: SocketAddr::from_str("1.2.3.4:5678").expect("Couldn't create SocketAddr from 1.2.3.4:5678"),
             component: Component::Hopper,
             origin_port: None,
+            peer_public_key_opt,
         };
         self.to_hopper.as_ref().expect("Hopper unbound in Dispatcher").try_send(ibcd).expect("Hopper is dead");
     }
@@ -87,12 +101,30 @@ impl Handler<TransmitDataMsg> for Dispatcher {
     }
 }
 
+impl Handler<NodeBannedMsg> for Dispatcher {
+    type Result = ();
+
+    fn handle(&mut self, msg: NodeBannedMsg, _ctx: &mut Self::Context) {
+        self.to_stream_bans.as_ref().expect("StreamHandlerPool unbound in Dispatcher").try_send(msg).expect("StreamHandlerPool is dead");
+    }
+}
+
+impl Handler<NodeUnbannedMsg> for Dispatcher {
+    type Result = ();
+
+    fn handle(&mut self, msg: NodeUnbannedMsg, _ctx: &mut Self::Context) {
+        self.to_stream_unbans.as_ref().expect("StreamHandlerPool unbound in Dispatcher").try_send(msg).expect("StreamHandlerPool is dead");
+    }
+}
+
 impl Dispatcher {
     pub fn new () -> Dispatcher {
         Dispatcher {
             to_proxy_server: None,
             to_stream: None,
             to_hopper: None,
+            to_stream_bans: None,
+            to_stream_unbans: None,
             logger: Logger::new ("Dispatcher"),
         }
     }
@@ -103,6 +135,8 @@ impl Dispatcher {
             bind: addr.clone ().recipient::<BindMessage>(),
             from_proxy_server: addr.clone ().recipient::<TransmitDataMsg>(),
             from_hopper: addr.clone ().recipient::<HopperTemporaryTransmitDataMsg>(),
+            node_banned: addr.clone ().recipient::<NodeBannedMsg>(),
+            node_unbanned: addr.clone ().recipient::<NodeUnbannedMsg>(),
         }
     }
 }
@@ -115,6 +149,8 @@ mod tests {
     use actix::Arbiter;
     use actix::msgs;
     use actix::System;
+    use std::net::IpAddr;
+    use sub_lib::cryptde::Key;
     use sub_lib::dispatcher::Endpoint;
     use test_utils::test_utils::Recorder;
     use test_utils::test_utils::make_peer_actors;
@@ -138,11 +174,12 @@ mod tests {
         let ibcd_in = InboundClientData {
             socket_addr,
             origin_port,
+            peer_public_key_opt: None,
             component,
             last_data: false,
             data: data.clone ()
         };
-        let mut peer_actors = make_peer_actors_from(Some(proxy_server), None, None, None, None);
+        let mut peer_actors = make_peer_actors_from(Some(proxy_server), None, None, None, None, None);
         peer_actors.dispatcher = Dispatcher::make_subs_from(&subject_addr);
         subject_addr.try_send( BindMessage { peer_actors }).unwrap ();
 
@@ -181,6 +218,7 @@ mod tests {
         let ibcd_in = InboundClientData {
             socket_addr,
             origin_port,
+            peer_public_key_opt: None,
             component,
             last_data: false,
             data: data.clone ()
@@ -209,6 +247,7 @@ mod tests {
         let ibcd_in = InboundClientData {
             socket_addr,
             origin_port,
+            peer_public_key_opt: None,
             component,
             last_data: false,
             data: data.clone ()
@@ -237,6 +276,7 @@ mod tests {
         let ibcd_in = InboundClientData {
             socket_addr,
             origin_port,
+            peer_public_key_opt: None,
             component,
             last_data: false,
             data: data.clone ()
@@ -305,7 +345,7 @@ mod tests {
             last_data: false,
             data: data.clone ()
         };
-        let mut peer_actors = make_peer_actors_from(None, None, None, None, None);
+        let mut peer_actors = make_peer_actors_from(None, None, None, None, None, None);
         peer_actors.dispatcher = Dispatcher::make_subs_from(&subject_addr);
         let stream_handler_pool_subs = make_stream_handler_pool_subs_from (Some (stream_handler_pool));
         subject_addr.try_send( PoolBindMessage { dispatcher_subs: peer_actors.dispatcher.clone (), stream_handler_pool_subs}).unwrap ();
@@ -331,6 +371,64 @@ mod tests {
         assert_eq! (recording.len (), 1);
     }
 
+    #[test]
+    fn forwards_bans_to_stream_handler_pool() {
+        let system = System::new ("test");
+        let subject = Dispatcher::new ();
+        let subject_addr: Addr<Syn, Dispatcher> = subject.start ();
+        let stream_handler_pool = Recorder::new();
+        let recording_arc = stream_handler_pool.get_recording();
+        let awaiter = stream_handler_pool.get_awaiter();
+        let ban = NodeBannedMsg {
+            public_key: Key::new (b"banned"),
+            ip_addr_opt: Some (IpAddr::from_str ("1.2.3.4").unwrap ()),
+        };
+        let mut peer_actors = make_peer_actors_from(None, None, None, None, None, None);
+        peer_actors.dispatcher = Dispatcher::make_subs_from(&subject_addr);
+        let stream_handler_pool_subs = make_stream_handler_pool_subs_from (Some (stream_handler_pool));
+        subject_addr.try_send( PoolBindMessage { dispatcher_subs: peer_actors.dispatcher.clone (), stream_handler_pool_subs}).unwrap ();
+        subject_addr.try_send( BindMessage { peer_actors }).unwrap ();
+
+        subject_addr.try_send (ban.clone ()).unwrap ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+
+        awaiter.await_message_count (1);
+        let recording = recording_arc.lock ().unwrap ();
+        assert_eq! (recording.get_record::<NodeBannedMsg>(0), &ban);
+        assert_eq! (recording.len (), 1);
+    }
+
+    #[test]
+    fn forwards_unbans_to_stream_handler_pool() {
+        let system = System::new ("test");
+        let subject = Dispatcher::new ();
+        let subject_addr: Addr<Syn, Dispatcher> = subject.start ();
+        let stream_handler_pool = Recorder::new();
+        let recording_arc = stream_handler_pool.get_recording();
+        let awaiter = stream_handler_pool.get_awaiter();
+        let unban = NodeUnbannedMsg {
+            public_key: Key::new (b"reformed"),
+            ip_addr_opt: Some (IpAddr::from_str ("1.2.3.4").unwrap ()),
+        };
+        let mut peer_actors = make_peer_actors_from(None, None, None, None, None, None);
+        peer_actors.dispatcher = Dispatcher::make_subs_from(&subject_addr);
+        let stream_handler_pool_subs = make_stream_handler_pool_subs_from (Some (stream_handler_pool));
+        subject_addr.try_send( PoolBindMessage { dispatcher_subs: peer_actors.dispatcher.clone (), stream_handler_pool_subs}).unwrap ();
+        subject_addr.try_send( BindMessage { peer_actors }).unwrap ();
+
+        subject_addr.try_send (unban.clone ()).unwrap ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+
+        awaiter.await_message_count (1);
+        let recording = recording_arc.lock ().unwrap ();
+        assert_eq! (recording.get_record::<NodeUnbannedMsg>(0), &unban);
+        assert_eq! (recording.len (), 1);
+    }
+
     #[test]
     fn converts_nonterminal_hopper_temporary_transmit_data_msg_to_inbound_client_data_for_hopper() {
         let system = System::new ("test");
@@ -347,7 +445,7 @@ mod tests {
             last_data: false,
             data: data.clone ()
         };
-        let mut peer_actors = make_peer_actors_from(None, None, Some(hopper), None, None);
+        let mut peer_actors = make_peer_actors_from(None, None, Some(hopper), None, None, None);
         peer_actors.dispatcher = Dispatcher::make_subs_from(&subject_addr);
         let stream_handler_pool_subs = make_stream_handler_pool_subs_from (Some (stream_handler_pool));
         subject_addr.try_send( PoolBindMessage { dispatcher_subs: peer_actors.dispatcher.clone (), stream_handler_pool_subs}).unwrap ();
@@ -389,7 +487,7 @@ mod tests {
             last_data: true,
             data: data.clone ()
         };
-        let mut peer_actors = make_peer_actors_from(None, None, Some(hopper), None, None);
+        let mut peer_actors = make_peer_actors_from(None, None, Some(hopper), None, None, None);
         peer_actors.dispatcher = Dispatcher::make_subs_from(&subject_addr);
         let stream_handler_pool_subs = make_stream_handler_pool_subs_from (Some (stream_handler_pool));
         subject_addr.try_send( PoolBindMessage { dispatcher_subs: peer_actors.dispatcher.clone (), stream_handler_pool_subs}).unwrap ();
@@ -5,7 +5,10 @@ use sub_lib::dispatcher::Component;
 use sub_lib::logger::Logger;
 use masquerader::Masquerader;
 use masquerader::MasqueradeError;
+use discriminator::Discriminator;
+use discriminator::DiscriminatorFactory;
 use discriminator::UnmaskedChunk;
+use json_framer::JsonFramer;
 
 pub struct JsonMasquerader {
     logger: Logger
@@ -37,7 +40,6 @@ impl Masquerader for JsonMasquerader {
 }
 
 impl JsonMasquerader {
-    #[allow (dead_code)] // Remove this after Release 2
     pub fn new () -> JsonMasquerader {
         JsonMasquerader {
             logger: Logger::new ("JsonMasquerader")
@@ -127,12 +129,42 @@ struct JsonMasqueraderUnmaskStructure {
     bodyData: Option<String>
 }
 
+// Clandestine traffic from other Nodes
+pub struct JsonDiscriminatorFactory {}
+
+impl DiscriminatorFactory for JsonDiscriminatorFactory {
+    fn make (&self) -> Box<Discriminator> {
+        Box::new (Discriminator::new (Box::new (JsonFramer::new ()), vec! (Box::new (JsonMasquerader::new ()))))
+    }
+
+    fn duplicate (&self) -> Box<DiscriminatorFactory> {
+        Box::new (JsonDiscriminatorFactory {})
+    }
+}
+
+impl JsonDiscriminatorFactory {
+    pub fn new () -> JsonDiscriminatorFactory {
+        JsonDiscriminatorFactory {}
+    }
+}
+
 #[cfg (test)]
 mod tests {
     use super::*;
     use test_utils::test_utils::TestLogHandler;
     use test_utils::test_utils::init_test_logging;
 
+    #[test]
+    fn json_discriminator_factory_makes_discriminators_that_find_masked_chunks () {
+        let masked = JsonMasquerader::new ().mask (Component::Hopper, b"Fourscore and seven years ago").unwrap ();
+        let mut subject = JsonDiscriminatorFactory::new ().make ();
+
+        subject.add_data (&masked[..]);
+        let result = subject.take_chunk ();
+
+        assert_eq! (result, Some (UnmaskedChunk::new (b"Fourscore and seven years ago".to_vec (), Component::Hopper, true)));
+    }
+
     #[test]
     fn json_masquerader_can_mask_and_unmask_bodytext () {
         let subject = JsonMasquerader::new ();
@@ -0,0 +1,118 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::time::Duration;
+use actix::Actor;
+use actix::AsyncContext;
+use actix::Context;
+use actix::Recipient;
+use actix::Syn;
+use sub_lib::cryptde::CryptDE;
+use sub_lib::cryptde_rotating::CryptDERotating;
+use sub_lib::identity_store::IdentityStore;
+use sub_lib::logger::Logger;
+use sub_lib::neighborhood::NewPublicKeyMsg;
+use sub_lib::neighborhood::RetiredPublicKeyMsg;
+
+// Rotates the Node's key pair now and then. Each old key pair keeps decoding for the overlap, so
+// routes built with it have time to drain while the Neighborhood spreads the word about the new one.
+pub struct KeyRotator {
+    cryptde: &'static CryptDERotating,
+    rotation_interval_ms: u64,
+    overlap_ms: u64,
+    identity_store_opt: Option<IdentityStore>,
+    to_neighborhood: Recipient<Syn, NewPublicKeyMsg>,
+    to_neighborhood_retired: Recipient<Syn, RetiredPublicKeyMsg>,
+    logger: Logger,
+}
+
+impl Actor for KeyRotator {
+    type Context = Context<Self>;
+
+    fn started (&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval (Duration::from_millis (self.rotation_interval_ms), |rotator, ctx| {
+            rotator.rotate (ctx)
+        });
+    }
+}
+
+impl KeyRotator {
+    pub fn new (cryptde: &'static CryptDERotating, rotation_interval_ms: u64, overlap_ms: u64, identity_store_opt: Option<IdentityStore>,
+                to_neighborhood: Recipient<Syn, NewPublicKeyMsg>, to_neighborhood_retired: Recipient<Syn, RetiredPublicKeyMsg>) -> KeyRotator {
+        KeyRotator {
+            cryptde,
+            rotation_interval_ms,
+            overlap_ms,
+            identity_store_opt,
+            to_neighborhood,
+            to_neighborhood_retired,
+            logger: Logger::new ("KeyRotator"),
+        }
+    }
+
+    fn rotate (&mut self, ctx: &mut Context<Self>) {
+        let (previous_public_key, endorsement) = self.cryptde.rotate ();
+        self.logger.info (format! ("Rotated to a new key pair; the old one retires in {}ms", self.overlap_ms));
+        if let Some (ref store) = self.identity_store_opt {
+            if let Err (e) = store.save (&self.cryptde.private_key ()) {
+                self.logger.error (format! ("The new key pair won't survive a restart: {}", e));
+            }
+        }
+        self.to_neighborhood.try_send (NewPublicKeyMsg {previous_public_key, endorsement}).expect ("Neighborhood is dead");
+        ctx.run_later (Duration::from_millis (self.overlap_ms), |rotator, _ctx| {
+            rotator.retire ()
+        });
+    }
+
+    fn retire (&mut self) {
+        if let Some (public_key) = self.cryptde.retire () {
+            self.to_neighborhood_retired.try_send (RetiredPublicKeyMsg {public_key}).expect ("Neighborhood is dead");
+        }
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs;
+    use std::thread;
+    use actix::Addr;
+    use actix::System;
+    use sub_lib::cryptde::PlainData;
+    use sub_lib::cryptde_null::CryptDENull;
+    use test_utils::test_utils::Recorder;
+
+    fn make_null () -> Box<CryptDE> {
+        Box::new (CryptDENull::new ())
+    }
+
+    #[test]
+    fn rotates_the_key_pair_tells_the_neighborhood_and_retires_the_old_one_after_the_overlap () {
+        let mut cryptde = CryptDERotating::new (make_null (), make_null);
+        cryptde.generate_key_pair ();
+        let cryptde: &'static CryptDERotating = Box::leak (Box::new (cryptde));
+        let original_public_key = cryptde.public_key ();
+        let data_directory = temp_dir ().join ("key_rotator").join ("rotates_the_key_pair_tells_the_neighborhood_and_retires_the_old_one_after_the_overlap");
+        fs::create_dir_all (&data_directory).unwrap ();
+        let store = IdentityStore::in_data_directory (&data_directory, "passphrase");
+        let neighborhood = Recorder::new ();
+        let recording_arc = neighborhood.get_recording ();
+        let awaiter = neighborhood.get_awaiter ();
+        thread::spawn (move || {
+            let system = System::new ("rotates_the_key_pair_tells_the_neighborhood_and_retires_the_old_one_after_the_overlap");
+            let neighborhood_addr: Addr<Syn, Recorder> = neighborhood.start ();
+            let subject = KeyRotator::new (cryptde, 200, 20, Some (store),
+                neighborhood_addr.clone ().recipient::<NewPublicKeyMsg> (), neighborhood_addr.recipient::<RetiredPublicKeyMsg> ());
+            let _: Addr<Syn, KeyRotator> = subject.start ();
+
+            system.run ();
+        });
+        awaiter.await_message_count (2);
+        let recording = recording_arc.lock ().unwrap ();
+        let rotation = recording.get_record::<NewPublicKeyMsg> (0);
+        assert_eq! (rotation.previous_public_key, original_public_key);
+        assert_eq! (cryptde.verify_signature (&PlainData::new (&cryptde.public_key ().data[..]), &rotation.endorsement, &original_public_key), true);
+        assert_eq! (recording.get_record::<RetiredPublicKeyMsg> (1), &RetiredPublicKeyMsg {public_key: original_public_key});
+        assert_eq! (cryptde.retiring_public_key_opt (), None);
+        assert_eq! (IdentityStore::in_data_directory (&data_directory, "passphrase").load (), Ok (Some (cryptde.private_key ())));
+    }
+}
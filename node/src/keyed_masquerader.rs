@@ -0,0 +1,99 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use sodiumoxide;
+use sodiumoxide::crypto::stream::xsalsa20;
+use sub_lib::dispatcher::Component;
+use masquerader::Masquerader;
+use masquerader::MasqueradeError;
+use discriminator::UnmaskedChunk;
+
+// Scrambles data with a key negotiated for one link before handing it to another Masquerader, so
+// the same data looks different on every link and nobody holding a masquerader of their own can
+// strip the masking off. The scrambled data still comes out looking like whatever the inner
+// Masquerader makes it look like.
+pub struct KeyedMasquerader {
+    inner: Box<Masquerader>,
+    masking_key: xsalsa20::Key,
+}
+
+impl Masquerader for KeyedMasquerader {
+    fn try_unmask (&self, item: &[u8]) -> Option<UnmaskedChunk> {
+        let chunk = match self.inner.try_unmask (item) {
+            Some (chunk) => chunk,
+            None => return None
+        };
+        if chunk.chunk.len () < xsalsa20::NONCEBYTES {return None}
+        let (nonce_data, scrambled) = chunk.chunk.split_at (xsalsa20::NONCEBYTES);
+        let nonce = xsalsa20::Nonce::from_slice (nonce_data).expect ("Internal error");
+        Some (UnmaskedChunk::new (xsalsa20::stream_xor (scrambled, &nonce, &self.masking_key), chunk.component, chunk.last_chunk))
+    }
+
+    fn mask (&self, component: Component, data: &[u8]) -> Result<Vec<u8>, MasqueradeError> {
+        let nonce = xsalsa20::gen_nonce ();
+        let scrambled = xsalsa20::stream_xor (data, &nonce, &self.masking_key);
+        self.inner.mask (component, &[&nonce.0[..], &scrambled[..]].concat ()[..])
+    }
+}
+
+impl KeyedMasquerader {
+    pub fn new (inner: Box<Masquerader>, masking_key: &[u8]) -> Result<KeyedMasquerader, String> {
+        sodiumoxide::init ().expect ("Couldn't initialize libsodium");
+        match xsalsa20::Key::from_slice (masking_key) {
+            Some (masking_key) => Ok (KeyedMasquerader {inner, masking_key}),
+            None => Err (format! ("{}-byte masking key should be {} bytes", masking_key.len (), xsalsa20::KEYBYTES))
+        }
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use json_masquerader::JsonMasquerader;
+
+    fn make_subject (masking_key: &[u8]) -> KeyedMasquerader {
+        KeyedMasquerader::new (Box::new (JsonMasquerader::new ()), masking_key).unwrap ()
+    }
+
+    #[test]
+    fn data_masked_for_a_link_is_unmasked_with_the_same_key () {
+        let subject = make_subject (&[1; 32]);
+
+        let masked = subject.mask (Component::Hopper, b"Fourscore and seven years ago").unwrap ();
+
+        assert_eq! (subject.try_unmask (&masked[..]), Some (UnmaskedChunk::new (b"Fourscore and seven years ago".to_vec (), Component::Hopper, true)));
+        assert_eq! (String::from_utf8 (masked.clone ()).unwrap ().contains ("Fourscore"), false);
+        assert_eq! (String::from_utf8 (masked).unwrap ().starts_with ("{"), true);
+    }
+
+    #[test]
+    fn the_same_data_looks_different_every_time_it_is_masked () {
+        let subject = make_subject (&[1; 32]);
+
+        let first = subject.mask (Component::Hopper, b"data").unwrap ();
+        let second = subject.mask (Component::Hopper, b"data").unwrap ();
+
+        assert_ne! (first, second);
+    }
+
+    #[test]
+    fn data_masked_for_one_link_is_garbage_with_another_link_s_key () {
+        let subject = make_subject (&[1; 32]);
+        let other_link = make_subject (&[2; 32]);
+        let unkeyed = JsonMasquerader::new ();
+        let masked = subject.mask (Component::Hopper, b"Fourscore and seven years ago").unwrap ();
+
+        let result = other_link.try_unmask (&masked[..]).unwrap ();
+
+        assert_ne! (result.chunk, b"Fourscore and seven years ago".to_vec ());
+        assert_ne! (unkeyed.try_unmask (&masked[..]).unwrap ().chunk, b"Fourscore and seven years ago".to_vec ());
+    }
+
+    #[test]
+    fn short_chunks_and_bad_keys_are_refused () {
+        let subject = make_subject (&[1; 32]);
+        let unkeyed = JsonMasquerader::new ();
+        let too_short = unkeyed.mask (Component::Hopper, b"short").unwrap ();
+
+        assert_eq! (subject.try_unmask (&too_short[..]), None);
+        assert_eq! (KeyedMasquerader::new (Box::new (JsonMasquerader::new ()), b"short").err ().unwrap (), String::from ("5-byte masking key should be 32 bytes"));
+    }
+}
@@ -1,43 +1,75 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 #[macro_use]
+extern crate accountant_lib;
+extern crate blockchain_bridge_lib;
 extern crate actix;
 extern crate base64;
 extern crate chrono;
+extern crate clap;
 extern crate entry_dns_lib;
 extern crate flexi_logger;
 extern crate hopper_lib;
+#[macro_use]
+extern crate lazy_static;
 extern crate log;
 extern crate neighborhood_lib;
 extern crate proxy_server_lib;
 extern crate proxy_client_lib;
+extern crate rcgen;
 extern crate regex;
+extern crate rustls;
 extern crate serde_cbor;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate sha1;
+extern crate sodiumoxide;
 extern crate sub_lib;
+extern crate toml;
+extern crate webpki;
 
 #[cfg (test)]
 extern crate test_utils;
 
 #[cfg(unix)]
 extern crate daemonize;
+#[cfg(unix)]
+extern crate libc;
 
 mod actor_system_factory;
 mod bootstrapper;
+mod clandestine_port;
+pub mod cli;
+mod config_file;
+mod config_reloader;
 mod configuration;
 mod discriminator;
 mod dispatcher;
 mod http_request_start_finder;
 mod json_framer;
 mod json_masquerader;
+mod key_rotator;
+mod keyed_masquerader;
 mod listener_handler;
 mod masquerader;
 mod null_masquerader;
+mod pid_file;
+mod port_mapping;
+mod price_monitor;
 mod privilege_drop;
+mod public_ip_discovery;
+mod public_ip_monitor;
 pub mod server_initializer;
+mod shutdown;
 mod stream_handler_pool;
+mod supervisor;
+mod terminal;
 mod tls_discriminator;
+mod tls_transport;
+mod ui_certificate;
+mod ui_gateway;
+mod ui_token;
+mod websocket;
 
 #[cfg (test)]
 mod node_test_utils;
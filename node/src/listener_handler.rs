@@ -1,8 +1,6 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use std::io;
 use std::marker::Send;
-use std::net::IpAddr;
-use std::net::Ipv4Addr;
 use std::net::SocketAddr;
 use actix::Recipient;
 use actix::Syn;
@@ -14,7 +12,7 @@ use discriminator::DiscriminatorFactory;
 use stream_handler_pool::AddStreamMsg;
 
 pub trait ListenerHandler: Send {
-    fn bind_port_and_discriminator_factories (&mut self, port: u16, discriminator_factories: Vec<Box<DiscriminatorFactory>>) -> io::Result<()>;
+    fn bind_address_and_discriminator_factories (&mut self, socket_addr: SocketAddr, discriminator_factories: Vec<Box<DiscriminatorFactory>>) -> io::Result<()>;
     fn bind_subs (&mut self, add_stream_sub: Recipient<Syn, AddStreamMsg>);
     fn handle_traffic (&mut self);
 }
@@ -32,10 +30,10 @@ pub struct ListenerHandlerReal {
 }
 
 impl ListenerHandler for ListenerHandlerReal {
-    fn bind_port_and_discriminator_factories (&mut self, port: u16, discriminator_factories: Vec<Box<DiscriminatorFactory>>) -> io::Result<()> {
-        self.port = Some (port);
+    fn bind_address_and_discriminator_factories (&mut self, socket_addr: SocketAddr, discriminator_factories: Vec<Box<DiscriminatorFactory>>) -> io::Result<()> {
+        self.port = Some (socket_addr.port ());
         self.discriminator_factories = discriminator_factories;
-        self.listener.bind (SocketAddr::new (IpAddr::V4 (Ipv4Addr::from (0)), port))
+        self.listener.bind (socket_addr)
     }
 
     fn bind_subs (&mut self, add_stream_sub: Recipient<Syn, AddStreamMsg>) {
@@ -157,21 +155,21 @@ mod tests {
     }
 
     #[test]
-    fn handles_bind_port_and_discriminator_factories_failure () {
+    fn handles_bind_address_and_discriminator_factories_failure () {
         let mut listener = TcpListenerWrapperMock::new ();
         listener.bind_result = Some (Err (Error::from (ErrorKind::AddrNotAvailable)));
         let discriminator_factory = NullDiscriminatorFactory::new ();
         let mut subject = ListenerHandlerReal::new ();
         subject.listener = Box::new (listener);
 
-        let result = subject.bind_port_and_discriminator_factories (1234,
+        let result = subject.bind_address_and_discriminator_factories (SocketAddr::from_str ("0.0.0.0:1234").unwrap (),
             vec! (Box::new (discriminator_factory)));
 
         assert_eq! (result.err ().unwrap ().kind (), ErrorKind::AddrNotAvailable);
     }
 
     #[test]
-    fn handles_bind_port_and_discriminator_factories_success () {
+    fn handles_bind_address_and_discriminator_factories_success () {
         let mut listener = TcpListenerWrapperMock::new ();
         listener.bind_result = Some (Ok (()));
         let listener_log = listener.log.clone ();
@@ -180,11 +178,11 @@ mod tests {
         let mut subject = ListenerHandlerReal::new ();
         subject.listener = Box::new (listener);
 
-        let result = subject.bind_port_and_discriminator_factories (2345,
+        let result = subject.bind_address_and_discriminator_factories (SocketAddr::from_str ("1.2.3.4:2345").unwrap (),
             vec! (Box::new (discriminator_factory)));
 
         assert_eq! (result.unwrap (), ());
-        assert_eq! (listener_log.dump (), vec! (format! ("bind (V4(0.0.0.0:2345))")));
+        assert_eq! (listener_log.dump (), vec! (format! ("bind (V4(1.2.3.4:2345))")));
         assert_eq! (subject.port, Some (2345));
         let factory = subject.discriminator_factories.remove (0);
         let mut discriminator = factory.make ();
@@ -241,7 +239,7 @@ mod tests {
             let mut subject = ListenerHandlerReal::new();
             subject.listener = Box::new(listener);
             subject.limiter = Limiter::with_only(2);
-            subject.bind_port_and_discriminator_factories(1234,
+            subject.bind_address_and_discriminator_factories(SocketAddr::from_str ("0.0.0.0:1234").unwrap (),
                 vec! (Box::new (discriminator_factory))).unwrap ();
             subject.bind_subs(add_stream_sub);
 
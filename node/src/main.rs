@@ -6,7 +6,7 @@ extern crate node_lib;
 use std::io;
 use sub_lib::main_tools::StdStreams;
 use sub_lib::main_tools::Command;
-use node_lib::server_initializer::ServerInitializer;
+use node_lib::cli::Cli;
 
 pub fn main() {
     let mut streams: StdStreams = StdStreams {
@@ -15,7 +15,7 @@ pub fn main() {
         stderr: &mut io::stderr ()
     };
 
-    let mut command = ServerInitializer::new ();
+    let mut command = Cli::new ();
     let streams_ref: &mut StdStreams = &mut streams;
     let exit_code = command.go (streams_ref, &std::env::args ().collect ());
     ::std::process::exit (exit_code as i32);
@@ -22,6 +22,8 @@ use sub_lib::tcp_wrappers::TcpStreamWrapper;
 use sub_lib::dispatcher::Component;
 use sub_lib::framer::Framer;
 use sub_lib::framer::FramedChunk;
+use sub_lib::neighborhood::NodeBannedMsg;
+use sub_lib::neighborhood::NodeUnbannedMsg;
 use sub_lib::stream_handler_pool::TransmitDataMsg;
 use test_utils::test_utils::Recorder;
 use test_utils::test_utils::TestLog;
@@ -35,6 +37,10 @@ use stream_handler_pool::AddStreamMsg;
 use stream_handler_pool::RemoveStreamMsg;
 use stream_handler_pool::StreamHandlerPoolSubs;
 use stream_handler_pool::PoolBindMessage;
+use stream_handler_pool::StopStreamsMsg;
+use stream_handler_pool::LinkEstablishedMsg;
+use stream_handler_pool::ConnectToPeerMsg;
+use ui_gateway::FromUiMessage;
 
 pub trait TestLogOwner {
     fn get_test_log (&self) -> Arc<Mutex<TestLog>>;
@@ -307,6 +313,22 @@ impl Handler<RemoveStreamMsg> for Recorder {
     }
 }
 
+impl Handler<LinkEstablishedMsg> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: LinkEstablishedMsg, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<ConnectToPeerMsg> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: ConnectToPeerMsg, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
 impl Handler<PoolBindMessage> for Recorder {
     type Result = ();
 
@@ -315,6 +337,24 @@ impl Handler<PoolBindMessage> for Recorder {
     }
 }
 
+// The sender isn't kept waiting
+impl Handler<StopStreamsMsg> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: StopStreamsMsg, _ctx: &mut Self::Context) {
+        msg.done.send (()).ok ();
+        self.record (msg);
+    }
+}
+
+impl Handler<FromUiMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: FromUiMessage, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
 pub fn make_stream_handler_pool_subs_from(stream_handler_pool_opt: Option<Recorder>) -> StreamHandlerPoolSubs {
     let stream_handler_pool = match stream_handler_pool_opt {
         Some(stream_handler_pool) => stream_handler_pool,
@@ -328,5 +368,10 @@ pub fn make_stream_handler_pool_subs_from(stream_handler_pool_opt: Option<Record
         transmit_sub: addr.clone ().recipient::<TransmitDataMsg>(),
         remove_sub: addr.clone ().recipient::<RemoveStreamMsg>(),
         bind: addr.clone ().recipient::<PoolBindMessage>(),
+        node_banned: addr.clone ().recipient::<NodeBannedMsg>(),
+        node_unbanned: addr.clone ().recipient::<NodeUnbannedMsg>(),
+        stop_streams: addr.clone ().recipient::<StopStreamsMsg>(),
+        link_established: addr.clone ().recipient::<LinkEstablishedMsg>(),
+        connect_to_peer: addr.clone ().recipient::<ConnectToPeerMsg>(),
     }
 }
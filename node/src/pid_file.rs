@@ -0,0 +1,161 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::env::temp_dir;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+#[cfg (unix)]
+use std::os::unix::io::AsRawFd;
+#[cfg (unix)]
+use libc;
+use sub_lib::parameter_finder::ParameterFinder;
+
+pub const PID_FILENAME: &str = "SubstratumNode.pid";
+
+// Two Nodes on one machine would fight over its ports and its DNS settings, so a Node holds a lock
+// on its PID file for as long as it runs. The operating system lets go of the lock when the process
+// ends, however it ends, so a file left behind by a Node that died stands in nobody's way.
+pub struct PidFile {
+    path: PathBuf,
+    file: File,
+}
+
+impl PidFile {
+    pub fn acquire (path: &Path) -> Result<PidFile, String> {
+        let mut file = match path.parent ().map (|parent| fs::create_dir_all (parent)).unwrap_or (Ok (()))
+                .and_then (|_| OpenOptions::new ().read (true).write (true).create (true).open (path)) {
+            Ok (file) => file,
+            Err (e) => return Err (format! ("Couldn't open PID file {:?}: {}", path, e))
+        };
+        if !lock (&file) {
+            let mut contents = String::new ();
+            let _ = file.read_to_string (&mut contents);
+            return Err (format! ("Another Node is already running as process {}; its PID file is {:?}", contents.trim (), path))
+        }
+        let mut pid_file = PidFile {path: path.to_path_buf (), file};
+        pid_file.write_pid ().map_err (|e| format! ("Couldn't write PID file {:?}: {}", path, e))?;
+        Ok (pid_file)
+    }
+
+    pub fn path (&self) -> &Path {
+        &self.path
+    }
+
+    // The lock goes along with the file through a fork, but the process ID doesn't
+    pub fn refresh (&mut self) -> Result<(), String> {
+        let path = self.path.clone ();
+        self.write_pid ().map_err (|e| format! ("Couldn't write PID file {:?}: {}", path, e))
+    }
+
+    fn write_pid (&mut self) -> io::Result<()> {
+        self.file.set_len (0)?;
+        self.file.seek (SeekFrom::Start (0))?;
+        self.file.write_all (format! ("{}\n", process::id ()).as_bytes ())?;
+        self.file.flush ()
+    }
+}
+
+impl Drop for PidFile {
+    fn drop (&mut self) {
+        let _ = fs::remove_file (&self.path);
+    }
+}
+
+// The default doesn't depend on the data directory: Nodes with different ones would still fight
+// over the same ports
+pub fn pid_file_path (args: &Vec<String>) -> PathBuf {
+    match ParameterFinder::new (args.clone ()).find_value_for ("--pid_file", "--pid_file <path> where the Node writes its process ID") {
+        Some (pid_file) => PathBuf::from (pid_file),
+        None => temp_dir ().join (PID_FILENAME)
+    }
+}
+
+#[cfg (unix)]
+fn lock (file: &File) -> bool {
+    unsafe {libc::flock (file.as_raw_fd (), libc::LOCK_EX | libc::LOCK_NB) == 0}
+}
+
+// Windows won't let a second process open a file the first one is writing anyway
+#[cfg (windows)]
+fn lock (_file: &File) -> bool {
+    true
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    fn make_path (name: &str) -> PathBuf {
+        let directory = temp_dir ().join ("pid_file").join (name);
+        let _ = fs::remove_dir_all (&directory);
+        directory.join (PID_FILENAME)
+    }
+
+    fn contents_of (path: &Path) -> String {
+        let mut contents = String::new ();
+        File::open (path).unwrap ().read_to_string (&mut contents).unwrap ();
+        contents
+    }
+
+    #[test]
+    fn the_pid_is_written_and_the_file_is_gone_afterward () {
+        let path = make_path ("the_pid_is_written_and_the_file_is_gone_afterward");
+
+        let subject = PidFile::acquire (&path).unwrap ();
+        let contents = contents_of (&path);
+        drop (subject);
+
+        assert_eq! (contents, format! ("{}\n", process::id ()));
+        assert_eq! (path.exists (), false);
+    }
+
+    #[cfg (unix)]
+    #[test]
+    fn a_second_node_is_refused_while_the_first_holds_the_file () {
+        let path = make_path ("a_second_node_is_refused_while_the_first_holds_the_file");
+        let first = PidFile::acquire (&path).unwrap ();
+
+        let result = PidFile::acquire (&path);
+
+        assert_eq! (result.err (), Some (format! ("Another Node is already running as process {}; its PID file is {:?}", process::id (), path)));
+        drop (first);
+        assert_eq! (PidFile::acquire (&path).is_ok (), true);
+    }
+
+    #[test]
+    fn refreshing_writes_the_pid_again () {
+        let path = make_path ("refreshing_writes_the_pid_again");
+        let mut subject = PidFile::acquire (&path).unwrap ();
+        File::create (&path).unwrap ().write_all (b"1234567890\n").unwrap ();
+
+        subject.refresh ().unwrap ();
+
+        assert_eq! (contents_of (&path), format! ("{}\n", process::id ()));
+    }
+
+    #[test]
+    fn a_file_left_behind_by_a_dead_node_is_taken_over () {
+        let path = make_path ("a_file_left_behind_by_a_dead_node_is_taken_over");
+        fs::create_dir_all (path.parent ().unwrap ()).unwrap ();
+        File::create (&path).unwrap ().write_all (b"1234567890\n").unwrap ();
+
+        let _subject = PidFile::acquire (&path).unwrap ();
+
+        assert_eq! (contents_of (&path), format! ("{}\n", process::id ()));
+    }
+
+    #[test]
+    fn the_pid_file_goes_where_its_told_or_with_the_log () {
+        let args = vec! (String::from ("--pid_file"), String::from ("/var/run/node.pid"), String::from ("--data_directory"), String::from ("/var/lib/node"));
+
+        assert_eq! (pid_file_path (&args), PathBuf::from ("/var/run/node.pid"));
+        assert_eq! (pid_file_path (&vec! (String::from ("--data_directory"), String::from ("/var/lib/node"))), temp_dir ().join (PID_FILENAME));
+    }
+}
@@ -0,0 +1,511 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::cell::RefCell;
+use std::cmp;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::net::UdpSocket;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+use actix::Actor;
+use actix::Context;
+use sub_lib::logger::Logger;
+
+// Gateways may grant less; either way the mapping is renewed halfway through the lifetime granted
+pub const PORT_MAPPING_LIFETIME_SECS: u32 = 3600;
+const PORT_MAPPING_TIMEOUT_MS: u64 = 3000;
+
+const NAT_PMP_PORT: u16 = 5351;
+const NAT_PMP_MAP_TCP: u8 = 2;
+const NAT_PMP_ATTEMPTS: u32 = 3;
+
+const SSDP_ADDRESS: &str = "239.255.255.250:1900";
+const WAN_IP_CONNECTION: &str = "urn:schemas-upnp-org:service:WANIPConnection:1";
+
+#[derive (Clone, Copy, Debug, PartialEq)]
+pub enum PortMappingProtocol {
+    Upnp,
+    NatPmp,
+}
+
+pub trait PortMapper: Send {
+    // Returns the lifetime the gateway actually granted, in seconds
+    fn add_mapping (&self, port: u16, lifetime_secs: u32) -> Result<u32, String>;
+    fn delete_mapping (&self, port: u16) -> Result<(), String>;
+}
+
+pub fn make_port_mapper (protocol: PortMappingProtocol) -> Box<PortMapper> {
+    match protocol {
+        PortMappingProtocol::Upnp => Box::new (UpnpPortMapper::new ()),
+        PortMappingProtocol::NatPmp => Box::new (NatPmpPortMapper::new (find_default_gateway ())),
+    }
+}
+
+// Maps the clandestine ports on the gateway at startup, keeps the leases alive, and removes them
+// when the actor system shuts down. Talking to the gateway means SSDP, HTTP and NAT-PMP round trips,
+// so that's done on a thread of its own rather than holding up the actors; the actor only marks
+// when the mappings start and stop.
+pub struct PortMappingKeeper {
+    mappings_opt: Option<PortMappings>,
+    stop_tx_opt: Option<Sender<()>>,
+}
+
+impl Actor for PortMappingKeeper {
+    type Context = Context<Self>;
+
+    fn started (&mut self, _ctx: &mut Self::Context) {
+        let mappings = self.mappings_opt.take ().expect ("PortMappingKeeper started twice");
+        let (stop_tx, stop_rx) = mpsc::channel ();
+        thread::spawn (move || mappings.keep (stop_rx));
+        self.stop_tx_opt = Some (stop_tx);
+    }
+
+    fn stopped (&mut self, _ctx: &mut Self::Context) {
+        // The thread removes the mappings once it hears its sender has gone
+        self.stop_tx_opt = None;
+    }
+}
+
+impl PortMappingKeeper {
+    pub fn new (mapper: Box<PortMapper>, ports: Vec<u16>, lifetime_secs: u32) -> PortMappingKeeper {
+        PortMappingKeeper {
+            mappings_opt: Some (PortMappings {
+                mapper,
+                ports,
+                lifetime_secs,
+                logger: Logger::new ("PortMappingKeeper"),
+            }),
+            stop_tx_opt: None,
+        }
+    }
+}
+
+struct PortMappings {
+    mapper: Box<PortMapper>,
+    ports: Vec<u16>,
+    lifetime_secs: u32,
+    logger: Logger,
+}
+
+impl PortMappings {
+    // Renews halfway through the shortest lifetime the gateway granted, until told to stop
+    fn keep (mut self, stop_rx: Receiver<()>) {
+        loop {
+            let renewal_secs = cmp::max (self.add_mappings (), 1);
+            match stop_rx.recv_timeout (Duration::from_millis ((renewal_secs as u64) * 500)) {
+                Err (RecvTimeoutError::Timeout) => (),
+                _ => break
+            }
+        }
+        self.delete_mappings ();
+    }
+
+    // Returns the shortest lifetime granted, or the one asked for if no mapping was made
+    fn add_mappings (&mut self) -> u32 {
+        let mut shortest_granted_secs_opt: Option<u32> = None;
+        for port in &self.ports {
+            match self.mapper.add_mapping (*port, self.lifetime_secs) {
+                Ok (granted_secs) => {
+                    self.logger.debug (format! ("Mapped port {} on the gateway for {}s", port, granted_secs));
+                    shortest_granted_secs_opt = Some (match shortest_granted_secs_opt {
+                        None => granted_secs,
+                        Some (shortest_granted_secs) => cmp::min (shortest_granted_secs, granted_secs)
+                    });
+                },
+                Err (e) => self.logger.warning (format! ("Couldn't map port {} on the gateway: {}", port, e)),
+            }
+        }
+        shortest_granted_secs_opt.unwrap_or (self.lifetime_secs)
+    }
+
+    fn delete_mappings (&mut self) {
+        for port in &self.ports {
+            match self.mapper.delete_mapping (*port) {
+                Ok (()) => self.logger.debug (format! ("Removed mapping for port {} from the gateway", port)),
+                Err (e) => self.logger.warning (format! ("Couldn't remove mapping for port {} from the gateway: {}", port, e)),
+            }
+        }
+    }
+}
+
+pub struct NatPmpPortMapper {
+    gateway: IpAddr,
+}
+
+impl PortMapper for NatPmpPortMapper {
+    fn add_mapping (&self, port: u16, lifetime_secs: u32) -> Result<u32, String> {
+        self.exchange (&make_nat_pmp_request (port, port, lifetime_secs), port)
+    }
+
+    fn delete_mapping (&self, port: u16) -> Result<(), String> {
+        self.exchange (&make_nat_pmp_request (port, 0, 0), port).map (|_| ())
+    }
+}
+
+impl NatPmpPortMapper {
+    pub fn new (gateway: IpAddr) -> NatPmpPortMapper {
+        NatPmpPortMapper {gateway}
+    }
+
+    // NAT-PMP runs over UDP, so the request is retried with a doubling timeout
+    fn exchange (&self, request: &[u8], port: u16) -> Result<u32, String> {
+        let socket = UdpSocket::bind ("0.0.0.0:0").map_err (|e| e.to_string ())?;
+        let gateway_addr = SocketAddr::new (self.gateway, NAT_PMP_PORT);
+        let mut buf = [0u8; 16];
+        for attempt in 0..NAT_PMP_ATTEMPTS {
+            socket.set_read_timeout (Some (Duration::from_millis (250 << attempt))).map_err (|e| e.to_string ())?;
+            socket.send_to (request, gateway_addr).map_err (|e| e.to_string ())?;
+            match socket.recv_from (&mut buf) {
+                Ok ((length, from)) if from == gateway_addr => return parse_nat_pmp_response (&buf[..length], port),
+                Ok (_) => (),
+                Err (ref e) if (e.kind () == io::ErrorKind::WouldBlock) || (e.kind () == io::ErrorKind::TimedOut) => (),
+                Err (e) => return Err (e.to_string ()),
+            }
+        }
+        Err (format! ("No NAT-PMP answer from {}", self.gateway))
+    }
+}
+
+pub struct UpnpPortMapper {
+    // Control endpoint of the gateway's WANIPConnection service, found the first time it's needed
+    control_opt: RefCell<Option<(SocketAddr, String)>>,
+}
+
+impl PortMapper for UpnpPortMapper {
+    fn add_mapping (&self, port: u16, lifetime_secs: u32) -> Result<u32, String> {
+        let (control_addr, control_path) = self.control ()?;
+        let internal_client = local_ip_toward (&control_addr).map_err (|e| e.to_string ())?;
+        let arguments = format! ("<NewRemoteHost></NewRemoteHost><NewExternalPort>{}</NewExternalPort><NewProtocol>TCP</NewProtocol>\
+            <NewInternalPort>{}</NewInternalPort><NewInternalClient>{}</NewInternalClient><NewEnabled>1</NewEnabled>\
+            <NewPortMappingDescription>SubstratumNode</NewPortMappingDescription><NewLeaseDuration>{}</NewLeaseDuration>",
+            port, port, internal_client, lifetime_secs);
+        soap_call (&control_addr, &control_path, "AddPortMapping", &arguments).map (|_| lifetime_secs)
+    }
+
+    fn delete_mapping (&self, port: u16) -> Result<(), String> {
+        let (control_addr, control_path) = self.control ()?;
+        let arguments = format! ("<NewRemoteHost></NewRemoteHost><NewExternalPort>{}</NewExternalPort><NewProtocol>TCP</NewProtocol>", port);
+        soap_call (&control_addr, &control_path, "DeletePortMapping", &arguments)
+    }
+}
+
+impl UpnpPortMapper {
+    pub fn new () -> UpnpPortMapper {
+        UpnpPortMapper {control_opt: RefCell::new (None)}
+    }
+
+    fn control (&self) -> Result<(SocketAddr, String), String> {
+        if let Some (ref control) = *self.control_opt.borrow () {
+            return Ok (control.clone ())
+        }
+        let control = discover_upnp_control ()?;
+        *self.control_opt.borrow_mut () = Some (control.clone ());
+        Ok (control)
+    }
+}
+
+fn discover_upnp_control () -> Result<(SocketAddr, String), String> {
+    let socket = UdpSocket::bind ("0.0.0.0:0").map_err (|e| e.to_string ())?;
+    socket.set_read_timeout (Some (Duration::from_millis (PORT_MAPPING_TIMEOUT_MS))).map_err (|e| e.to_string ())?;
+    let search = format! ("M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {}\r\n\r\n",
+        SSDP_ADDRESS, WAN_IP_CONNECTION);
+    socket.send_to (search.as_bytes (), SSDP_ADDRESS).map_err (|e| e.to_string ())?;
+    let mut buf = [0u8; 2048];
+    let (length, _) = socket.recv_from (&mut buf).map_err (|_| String::from ("No UPnP gateway answered"))?;
+    let location = match find_header (&String::from_utf8_lossy (&buf[..length]), "location") {
+        None => return Err (String::from ("UPnP gateway gave no description location")),
+        Some (location) => location
+    };
+    let (description_addr, description_path) = parse_http_url (&location)?;
+    let description = http_exchange (&description_addr,
+        &format! ("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", description_path, description_addr))
+        .map_err (|e| e.to_string ())?;
+    match find_control_url (&description) {
+        None => Err (String::from ("UPnP gateway offers no WANIPConnection service")),
+        Some (ref control_url) if control_url.starts_with ("http://") => parse_http_url (control_url),
+        Some (control_path) => Ok ((description_addr, control_path)),
+    }
+}
+
+fn soap_call (control_addr: &SocketAddr, control_path: &str, action: &str, arguments: &str) -> Result<(), String> {
+    let body = format! ("<?xml version=\"1.0\"?><s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+        s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\"><s:Body><u:{} xmlns:u=\"{}\">{}</u:{}></s:Body></s:Envelope>",
+        action, WAN_IP_CONNECTION, arguments, action);
+    let request = format! ("POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/xml; charset=\"utf-8\"\r\n\
+        SOAPAction: \"{}#{}\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        control_path, control_addr, WAN_IP_CONNECTION, action, body.len (), body);
+    let response = http_exchange (control_addr, &request).map_err (|e| e.to_string ())?;
+    let status_line = response.lines ().next ().unwrap_or ("");
+    if status_line.split_whitespace ().nth (1) == Some ("200") {
+        Ok (())
+    }
+    else {
+        Err (format! ("UPnP {} failed: {}", action, status_line))
+    }
+}
+
+fn http_exchange (addr: &SocketAddr, request: &str) -> io::Result<String> {
+    let timeout = Duration::from_millis (PORT_MAPPING_TIMEOUT_MS);
+    let mut stream = TcpStream::connect_timeout (addr, timeout)?;
+    stream.set_read_timeout (Some (timeout))?;
+    stream.write_all (request.as_bytes ())?;
+    let mut response = String::new ();
+    stream.read_to_string (&mut response)?;
+    Ok (response)
+}
+
+fn local_ip_toward (addr: &SocketAddr) -> io::Result<IpAddr> {
+    let socket = UdpSocket::bind ("0.0.0.0:0")?;
+    socket.connect (addr)?;
+    Ok (socket.local_addr ()?.ip ())
+}
+
+fn make_nat_pmp_request (internal_port: u16, external_port: u16, lifetime_secs: u32) -> Vec<u8> {
+    vec! (0, NAT_PMP_MAP_TCP, 0, 0,
+        (internal_port >> 8) as u8, internal_port as u8,
+        (external_port >> 8) as u8, external_port as u8,
+        (lifetime_secs >> 24) as u8, (lifetime_secs >> 16) as u8, (lifetime_secs >> 8) as u8, lifetime_secs as u8)
+}
+
+fn parse_nat_pmp_response (data: &[u8], port: u16) -> Result<u32, String> {
+    if (data.len () < 16) || (data[0] != 0) || (data[1] != 128 + NAT_PMP_MAP_TCP) {
+        return Err (String::from ("Malformed NAT-PMP answer"))
+    }
+    let result_code = ((data[2] as u16) << 8) | (data[3] as u16);
+    if result_code != 0 {return Err (format! ("NAT-PMP result code {}", result_code))}
+    let internal_port = ((data[8] as u16) << 8) | (data[9] as u16);
+    let external_port = ((data[10] as u16) << 8) | (data[11] as u16);
+    let lifetime_secs = ((data[12] as u32) << 24) | ((data[13] as u32) << 16) | ((data[14] as u32) << 8) | (data[15] as u32);
+    if internal_port != port {return Err (format! ("NAT-PMP answer was for port {}", internal_port))}
+    // A deletion reports external port 0
+    if (lifetime_secs > 0) && (external_port != port) {
+        return Err (format! ("Gateway would only map external port {}", external_port))
+    }
+    Ok (lifetime_secs)
+}
+
+fn find_header (response: &str, name: &str) -> Option<String> {
+    response.lines ()
+        .filter_map (|line| {
+            let mut pieces = line.splitn (2, ':');
+            match (pieces.next (), pieces.next ()) {
+                (Some (header), Some (value)) if header.trim ().to_lowercase () == name => Some (String::from (value.trim ())),
+                _ => None
+            }
+        })
+        .next ()
+}
+
+fn parse_http_url (url: &str) -> Result<(SocketAddr, String), String> {
+    let rest = match url.find ("://") {
+        Some (index) if &url[..index] == "http" => &url[(index + 3)..],
+        _ => return Err (format! ("Not an http URL: '{}'", url))
+    };
+    let (authority, path) = match rest.find ('/') {
+        None => (rest, "/"),
+        Some (index) => (&rest[..index], &rest[index..])
+    };
+    let authority_with_port = if authority.contains (':') {String::from (authority)} else {format! ("{}:80", authority)};
+    match authority_with_port.to_socket_addrs ().ok ().and_then (|mut addrs| addrs.next ()) {
+        None => Err (format! ("Can't resolve '{}'", authority)),
+        Some (addr) => Ok ((addr, String::from (path)))
+    }
+}
+
+// The description is XML, but the only thing needed from it is the controlURL that follows
+// the WANIPConnection service type.
+fn find_control_url (description: &str) -> Option<String> {
+    let service_index = match description.find (WAN_IP_CONNECTION) {
+        None => return None,
+        Some (index) => index
+    };
+    let after_service = &description[service_index..];
+    let start = match after_service.find ("<controlURL>") {
+        None => return None,
+        Some (index) => index + "<controlURL>".len ()
+    };
+    match after_service[start..].find ("</controlURL>") {
+        None => None,
+        Some (length) => Some (String::from (after_service[start..(start + length)].trim ()))
+    }
+}
+
+// Linux publishes its routing table; elsewhere, the gateway is usually .1 on the local network
+fn find_default_gateway () -> IpAddr {
+    let mut route_table = String::new ();
+    let from_route_table = match File::open ("/proc/net/route").and_then (|mut file| file.read_to_string (&mut route_table)) {
+        Err (_) => None,
+        Ok (_) => parse_default_gateway (&route_table)
+    };
+    match from_route_table {
+        Some (gateway) => IpAddr::V4 (gateway),
+        None => {
+            let local_ip = local_ip_toward (&SocketAddr::from (([8, 8, 8, 8], 53))).unwrap_or (IpAddr::V4 (Ipv4Addr::new (192, 168, 1, 2)));
+            match local_ip {
+                IpAddr::V4 (ip) => IpAddr::V4 (Ipv4Addr::new (ip.octets ()[0], ip.octets ()[1], ip.octets ()[2], 1)),
+                IpAddr::V6 (_) => IpAddr::V4 (Ipv4Addr::new (192, 168, 1, 1)),
+            }
+        }
+    }
+}
+
+// Addresses in /proc/net/route are hex in host (little-endian) byte order
+fn parse_default_gateway (route_table: &str) -> Option<Ipv4Addr> {
+    route_table.lines ().skip (1)
+        .filter_map (|line| {
+            let fields: Vec<&str> = line.split_whitespace ().collect ();
+            if (fields.len () < 3) || (fields[1] != "00000000") {return None}
+            match u32::from_str_radix (fields[2], 16) {
+                Ok (0) | Err (_) => None,
+                Ok (gateway) => Some (Ipv4Addr::new (gateway as u8, (gateway >> 8) as u8, (gateway >> 16) as u8, (gateway >> 24) as u8))
+            }
+        })
+        .next ()
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::thread;
+    use actix::Addr;
+    use actix::Syn;
+    use actix::System;
+    use test_utils::test_utils::init_test_logging;
+    use test_utils::test_utils::TestLogHandler;
+
+    struct PortMapperMock {
+        log: Arc<Mutex<Vec<String>>>,
+        add_result: Result<u32, String>,
+    }
+
+    impl PortMapper for PortMapperMock {
+        fn add_mapping (&self, port: u16, lifetime_secs: u32) -> Result<u32, String> {
+            self.log.lock ().unwrap ().push (format! ("add_mapping ({}, {})", port, lifetime_secs));
+            self.add_result.clone ()
+        }
+
+        fn delete_mapping (&self, port: u16) -> Result<(), String> {
+            self.log.lock ().unwrap ().push (format! ("delete_mapping ({})", port));
+            Ok (())
+        }
+    }
+
+    #[test]
+    fn nat_pmp_request_is_well_formed () {
+        let result = make_nat_pmp_request (443, 443, 3600);
+
+        assert_eq! (result, vec! (0, 2, 0, 0, 0x01, 0xBB, 0x01, 0xBB, 0x00, 0x00, 0x0E, 0x10));
+    }
+
+    #[test]
+    fn nat_pmp_response_yields_the_granted_lifetime () {
+        let data = [0, 130, 0, 0, 0, 0, 0, 1, 0x01, 0xBB, 0x01, 0xBB, 0x00, 0x00, 0x07, 0x08];
+
+        assert_eq! (parse_nat_pmp_response (&data, 443), Ok (1800));
+    }
+
+    #[test]
+    fn nat_pmp_failures_are_reported () {
+        let refused = [0, 130, 0, 2, 0, 0, 0, 1, 0x01, 0xBB, 0x01, 0xBB, 0x00, 0x00, 0x07, 0x08];
+        let moved = [0, 130, 0, 0, 0, 0, 0, 1, 0x01, 0xBB, 0x01, 0xBC, 0x00, 0x00, 0x07, 0x08];
+
+        assert_eq! (parse_nat_pmp_response (&refused, 443), Err (String::from ("NAT-PMP result code 2")));
+        assert_eq! (parse_nat_pmp_response (&moved, 443), Err (String::from ("Gateway would only map external port 444")));
+        assert_eq! (parse_nat_pmp_response (&refused[..12], 443), Err (String::from ("Malformed NAT-PMP answer")));
+    }
+
+    #[test]
+    fn ssdp_location_and_control_url_are_found () {
+        let ssdp_response = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=120\r\nLocation: http://192.168.1.1:5000/rootDesc.xml\r\n\r\n";
+        let description = "<root><service><serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>\
+            <controlURL>/ctl/L3F</controlURL></service><service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>\
+            <controlURL> /ctl/IPConn </controlURL></service></root>";
+
+        let location = find_header (ssdp_response, "location").unwrap ();
+
+        assert_eq! (parse_http_url (&location), Ok ((SocketAddr::from_str ("192.168.1.1:5000").unwrap (), String::from ("/rootDesc.xml"))));
+        assert_eq! (find_control_url (description), Some (String::from ("/ctl/IPConn")));
+        assert_eq! (find_control_url ("<root></root>"), None);
+    }
+
+    #[test]
+    fn default_gateway_comes_from_the_route_table () {
+        let route_table = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\n\
+            eth0\t0000A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\n\
+            eth0\t00000000\t0101A8C0\t0003\t0\t0\t0\t00000000\n";
+
+        assert_eq! (parse_default_gateway (route_table), Some (Ipv4Addr::new (192, 168, 1, 1)));
+        assert_eq! (parse_default_gateway ("Iface\tDestination\tGateway\n"), None);
+    }
+
+    fn make_mappings (mapper: PortMapperMock, ports: Vec<u16>, lifetime_secs: u32) -> PortMappings {
+        PortMappings {mapper: Box::new (mapper), ports, lifetime_secs, logger: Logger::new ("PortMappingKeeper")}
+    }
+
+    #[test]
+    fn keeper_maps_every_port_and_renews_halfway_through_the_granted_lifetime () {
+        init_test_logging ();
+        let log = Arc::new (Mutex::new (vec! ()));
+        let mapper = PortMapperMock {log: log.clone (), add_result: Ok (1)};
+        thread::spawn (move || {
+            let system = System::new ("keeper_maps_every_port_and_renews_halfway_through_the_granted_lifetime");
+            let subject = PortMappingKeeper::new (Box::new (mapper), vec! (80, 443), 3600);
+            let _: Addr<Syn, PortMappingKeeper> = subject.start ();
+
+            system.run ();
+        });
+        thread::sleep (Duration::from_millis (750));
+        let calls = log.lock ().unwrap ().clone ();
+        assert_eq! (&calls[..4], &[
+            String::from ("add_mapping (80, 3600)"), String::from ("add_mapping (443, 3600)"),
+            String::from ("add_mapping (80, 3600)"), String::from ("add_mapping (443, 3600)"),
+        ][..]);
+    }
+
+    #[test]
+    fn mappings_report_the_shortest_lifetime_granted () {
+        let log = Arc::new (Mutex::new (vec! ()));
+        let mut subject = make_mappings (PortMapperMock {log: log.clone (), add_result: Ok (1800)}, vec! (80, 443), 3600);
+
+        assert_eq! (subject.add_mappings (), 1800);
+    }
+
+    #[test]
+    fn mappings_are_removed_once_the_keeper_stops () {
+        let log = Arc::new (Mutex::new (vec! ()));
+        let subject = make_mappings (PortMapperMock {log: log.clone (), add_result: Ok (3600)}, vec! (8443), 3600);
+        let (stop_tx, stop_rx) = mpsc::channel ();
+        drop (stop_tx);
+
+        subject.keep (stop_rx);
+
+        assert_eq! (*log.lock ().unwrap (), vec! (String::from ("add_mapping (8443, 3600)"), String::from ("delete_mapping (8443)")));
+    }
+
+    #[test]
+    fn keeper_logs_mappings_that_fail_and_removes_its_mappings () {
+        init_test_logging ();
+        let log = Arc::new (Mutex::new (vec! ()));
+        let mapper = PortMapperMock {log: log.clone (), add_result: Err (String::from ("No UPnP gateway answered"))};
+        let mut subject = make_mappings (mapper, vec! (8443), 3600);
+
+        assert_eq! (subject.add_mappings (), 3600);
+        subject.delete_mappings ();
+
+        assert_eq! (*log.lock ().unwrap (), vec! (String::from ("add_mapping (8443, 3600)"), String::from ("delete_mapping (8443)")));
+        TestLogHandler::new ().exists_log_containing ("WARN: PortMappingKeeper: Couldn't map port 8443 on the gateway: No UPnP gateway answered");
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::thread;
+use std::time::Duration;
+use actix::Recipient;
+use actix::Syn;
+use sub_lib::accountant::ReportFiatPriceMessage;
+use sub_lib::logger::Logger;
+use sub_lib::price_oracle::PriceOracle;
+
+// How often the price of SUB is asked after; thresholds in fiat don't need to follow it any closer
+pub const PRICE_CHECK_INTERVAL_MS: u64 = 600000;
+
+// Asks the price oracle what SUB is worth now and then, and tells the Accountant. Asking means a
+// network round trip that can take as long as its timeouts, so it's done on a thread of its own
+// rather than in the middle of the Accountant's scans.
+pub struct PriceMonitor {
+    oracle: Box<PriceOracle>,
+    check_interval_ms: u64,
+    to_accountant: Recipient<Syn, ReportFiatPriceMessage>,
+    logger: Logger,
+}
+
+impl PriceMonitor {
+    pub fn new (oracle: Box<PriceOracle>, check_interval_ms: u64, to_accountant: Recipient<Syn, ReportFiatPriceMessage>) -> PriceMonitor {
+        PriceMonitor {
+            oracle,
+            check_interval_ms,
+            to_accountant,
+            logger: Logger::new ("PriceMonitor"),
+        }
+    }
+
+    // Checks straight away, and then once per interval for as long as the Node runs
+    pub fn start (self) {
+        thread::spawn (move || {
+            loop {
+                self.check ();
+                thread::sleep (Duration::from_millis (self.check_interval_ms));
+            }
+        });
+    }
+
+    fn check (&self) {
+        match self.oracle.price () {
+            Ok (price) => self.to_accountant.try_send (ReportFiatPriceMessage {price}).expect ("Accountant is dead"),
+            Err (e) => self.logger.warning (format! ("Couldn't get the price of SUB; will try again later: {}", e))
+        }
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use actix::Actor;
+    use actix::Addr;
+    use actix::System;
+    use test_utils::test_utils::init_test_logging;
+    use test_utils::test_utils::Recorder;
+    use test_utils::test_utils::TestLogHandler;
+
+    struct PriceOracleMock {
+        prices: RefCell<Vec<Result<f64, String>>>,
+    }
+
+    impl PriceOracle for PriceOracleMock {
+        fn price (&self) -> Result<f64, String> {
+            let mut prices = self.prices.borrow_mut ();
+            if prices.is_empty () {Err (String::from ("No more prices"))} else {prices.remove (0)}
+        }
+    }
+
+    #[test]
+    fn reports_each_price_the_oracle_gives_and_logs_its_failures () {
+        init_test_logging ();
+        let accountant = Recorder::new ();
+        let recording_arc = accountant.get_recording ();
+        let awaiter = accountant.get_awaiter ();
+        thread::spawn (move || {
+            let system = System::new ("reports_each_price_the_oracle_gives_and_logs_its_failures");
+            let accountant_addr: Addr<Syn, Recorder> = accountant.start ();
+            let oracle = PriceOracleMock {prices: RefCell::new (vec! (
+                Ok (0.25),
+                Err (String::from ("Price oracle failed: HTTP status 503")),
+                Ok (0.3),
+            ))};
+            let subject = PriceMonitor::new (Box::new (oracle), 10, accountant_addr.recipient::<ReportFiatPriceMessage> ());
+            subject.start ();
+
+            system.run ();
+        });
+        awaiter.await_message_count (2);
+        let recording = recording_arc.lock ().unwrap ();
+        assert_eq! (recording.get_record::<ReportFiatPriceMessage> (0), &ReportFiatPriceMessage {price: 0.25});
+        assert_eq! (recording.get_record::<ReportFiatPriceMessage> (1), &ReportFiatPriceMessage {price: 0.3});
+        TestLogHandler::new ().exists_log_containing ("Couldn't get the price of SUB; will try again later: Price oracle failed: HTTP status 503");
+    }
+}
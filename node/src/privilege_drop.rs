@@ -12,12 +12,43 @@ extern {
 }
 
 use std::env::var;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+#[cfg(unix)]
+use std::ffi::CString;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+#[cfg(unix)]
+use libc;
+use accountant_lib::ledger::LEDGER_FILENAME;
+use neighborhood_lib::neighborhood_store::NEIGHBORHOOD_DATABASE_FILENAME;
+use sub_lib::identity_store::IDENTITY_FILENAME;
+use sub_lib::parameter_finder::ParameterFinder;
+use sub_lib::wallet_store::WALLET_FILENAME;
+use clandestine_port::CLANDESTINE_PORT_FILENAME;
+use pid_file;
+use pid_file::PID_FILENAME;
+use ui_certificate::UI_CERTIFICATE_FILENAME;
+use ui_certificate::UI_PRIVATE_KEY_FILENAME;
+use ui_token::UI_TOKEN_FILENAME;
 
 pub trait IdWrapper {
     fn getuid (&self) -> i32;
     fn getgid (&self) -> i32;
     fn setuid (&self, uid: i32) -> i32;
     fn setgid (&self, gid: i32) -> i32;
+    // Leaves gid as the only supplementary group; otherwise root's groups outlive root
+    fn setgroups (&self, gid: i32) -> i32;
+    // The user's uid and primary gid
+    fn user_ids (&self, name: &str) -> Option<(i32, i32)>;
+    fn group_id (&self, name: &str) -> Option<i32>;
+    // Changes a link itself, never what it points to
+    fn lchown (&self, path: &Path, uid: i32, gid: i32) -> i32;
+    // Who owns path itself, again without following a link
+    fn owner (&self, path: &Path) -> Option<i32>;
 }
 
 pub struct IdWrapperReal;
@@ -43,6 +74,34 @@ impl IdWrapper for IdWrapperReal {
     fn getgid (&self) -> i32  {unsafe {getgid ()}}
     fn setuid (&self, uid: i32) -> i32 {unsafe {setuid (uid)}}
     fn setgid (&self, gid: i32) -> i32  {unsafe {setgid (gid)}}
+
+    fn setgroups (&self, gid: i32) -> i32 {
+        let groups = [gid as libc::gid_t];
+        unsafe {libc::setgroups (1, groups.as_ptr ())}
+    }
+
+    fn user_ids (&self, name: &str) -> Option<(i32, i32)> {
+        let name = match CString::new (name) {Ok (name) => name, Err (_) => return None};
+        let passwd = unsafe {libc::getpwnam (name.as_ptr ())};
+        if passwd.is_null () {return None}
+        unsafe {Some (((*passwd).pw_uid as i32, (*passwd).pw_gid as i32))}
+    }
+
+    fn group_id (&self, name: &str) -> Option<i32> {
+        let name = match CString::new (name) {Ok (name) => name, Err (_) => return None};
+        let group = unsafe {libc::getgrnam (name.as_ptr ())};
+        if group.is_null () {return None}
+        unsafe {Some ((*group).gr_gid as i32)}
+    }
+
+    fn lchown (&self, path: &Path, uid: i32, gid: i32) -> i32 {
+        let path = match CString::new (path.as_os_str ().as_bytes ()) {Ok (path) => path, Err (_) => return -1};
+        unsafe {libc::lchown (path.as_ptr (), uid as libc::uid_t, gid as libc::gid_t)}
+    }
+
+    fn owner (&self, path: &Path) -> Option<i32> {
+        fs::symlink_metadata (path).ok ().map (|metadata| metadata.uid () as i32)
+    }
 }
 
 #[cfg(windows)]
@@ -55,10 +114,20 @@ impl IdWrapper for IdWrapperReal {
     fn setuid (&self, _uid: i32) -> i32 { !unimplemented!() }
     // crashpoint
     fn setgid (&self, _gid: i32) -> i32  { !unimplemented!() }
+    // crashpoint
+    fn setgroups (&self, _gid: i32) -> i32 { !unimplemented!() }
+    // crashpoint
+    fn user_ids (&self, _name: &str) -> Option<(i32, i32)> { unimplemented!() }
+    // crashpoint
+    fn group_id (&self, _name: &str) -> Option<i32> { unimplemented!() }
+    // crashpoint
+    fn lchown (&self, _path: &Path, _uid: i32, _gid: i32) -> i32 { !unimplemented!() }
+    // crashpoint
+    fn owner (&self, _path: &Path) -> Option<i32> { unimplemented!() }
 }
 
 pub trait PrivilegeDropper {
-    fn drop_privileges (&self);
+    fn drop_privileges (&self, args: &Vec<String>);
 }
 
 pub struct PrivilegeDropperReal {
@@ -68,20 +137,35 @@ pub struct PrivilegeDropperReal {
 
 impl PrivilegeDropper for PrivilegeDropperReal {
 
-    fn drop_privileges (&self) {
+    // Root is only needed to bind the low ports and to read and write the data directory while the
+    // Node starts up. Everything after that runs as --user and --group, or as whoever ran sudo.
+    fn drop_privileges (&self, args: &Vec<String>) {
         #[cfg(unix)]
         {
-            let sudo_gid = self.id_from_env("SUDO_GID");
-            let sudo_uid = self.id_from_env("SUDO_UID");
-            let gid = sudo_gid.unwrap_or(self.id_wrapper.getgid());
+            let finder = ParameterFinder::new (args.clone ());
+            let (uid, gid) = self.target_ids (&finder);
+            if self.id_wrapper.getuid () == 0 {
+                if uid == 0 { panic!("Running as root without sudo: give --user <name or uid> for the Node to run as once it's started") }
+                if let Some (data_directory) = PrivilegeDropperReal::parse_data_directory (&finder) {
+                    self.hand_over (&data_directory, uid, gid);
+                }
+                // Otherwise the Node couldn't remove its PID file on the way out
+                let pid_file_path = pid_file::pid_file_path (args);
+                if is_plain_file (&pid_file_path) {
+                    self.lchown (&pid_file_path, uid, gid);
+                }
+                let groups_result = self.id_wrapper.setgroups (gid);
+                if groups_result != 0 { panic!("Error code {} resetting supplementary groups", groups_result) }
+            }
+
             let gid_result = self.id_wrapper.setgid(gid);
             if gid_result != 0 { panic!("Error code {} resetting group id", gid_result) }
             if self.id_wrapper.getgid() == 0 { panic!("Attempt to drop group privileges failed: still root") }
 
-            let uid = sudo_uid.unwrap_or(self.id_wrapper.getuid());
             let uid_result = self.id_wrapper.setuid(uid);
             if uid_result != 0 { panic!("Error code {} resetting user id", uid_result) }
             if self.id_wrapper.getuid() == 0 { panic!("Attempt to drop user privileges failed: still root") }
+            if self.id_wrapper.setuid(0) == 0 { panic!("Attempt to drop user privileges failed: root can be regained") }
         }
     }
 }
@@ -96,6 +180,68 @@ impl PrivilegeDropperReal {
         }
     }
 
+    // A named user brings their own primary group along, unless --group says otherwise
+    fn target_ids (&self, finder: &ParameterFinder) -> (i32, i32) {
+        let user_opt = finder.find_value_for ("--user", "--user <name or uid> the Node runs as once it's started");
+        let group_opt = finder.find_value_for ("--group", "--group <name or gid> the Node runs as once it's started");
+        let (uid, user_gid_opt) = match user_opt {
+            Some (user) => self.user_ids_for (&user),
+            None => (self.id_from_env ("SUDO_UID").unwrap_or (self.id_wrapper.getuid ()), self.id_from_env ("SUDO_GID"))
+        };
+        let gid = match group_opt {
+            Some (group) => self.group_id_for (&group),
+            None => user_gid_opt.unwrap_or (self.id_wrapper.getgid ())
+        };
+        (uid, gid)
+    }
+
+    fn user_ids_for (&self, user: &str) -> (i32, Option<i32>) {
+        match (self.id_wrapper.user_ids (user), user.parse::<i32> ()) {
+            (Some ((uid, gid)), _) => (uid, Some (gid)),
+            (None, Ok (uid)) if uid >= 0 => (uid, None),
+            _ => panic! ("Invalid value for --user <name or uid>: '{}'", user)
+        }
+    }
+
+    fn group_id_for (&self, group: &str) -> i32 {
+        match (self.id_wrapper.group_id (group), group.parse::<i32> ()) {
+            (Some (gid), _) => gid,
+            (None, Ok (gid)) if gid >= 0 => gid,
+            _ => panic! ("Invalid value for --group <name or gid>: '{}'", group)
+        }
+    }
+
+    // Whatever the Node wrote into its data directory while it was root has to stay writable
+    // once it isn't. After the first run the Node's user owns the directory and could leave anything
+    // in it, so only the Node's own files are handed over, and never through a link.
+    fn hand_over (&self, data_directory: &Path, uid: i32, gid: i32) {
+        if !fs::symlink_metadata (data_directory).map (|metadata| metadata.file_type ().is_dir ()).unwrap_or (false) {
+            panic! ("Data directory {:?} isn't a directory of its own", data_directory)
+        }
+        match self.id_wrapper.owner (data_directory) {
+            Some (owner) if (owner == 0) || (owner == uid) => (),
+            Some (owner) => panic! ("Data directory {:?} belongs to user {}, not to root or the Node's user", data_directory, owner),
+            None => panic! ("Can't tell who owns data directory {:?}", data_directory)
+        }
+        self.lchown (data_directory, uid, gid);
+        node_filenames ().iter ()
+            .map (|filename| data_directory.join (filename))
+            .filter (|path| is_plain_file (path))
+            .for_each (|path| self.lchown (&path, uid, gid));
+    }
+
+    fn lchown (&self, path: &Path, uid: i32, gid: i32) {
+        let result = self.id_wrapper.lchown (path, uid, gid);
+        if result != 0 { panic!("Error code {} handing {:?} over to the Node's user", result, path) }
+    }
+
+    fn parse_data_directory (finder: &ParameterFinder) -> Option<PathBuf> {
+        match finder.find_value_for ("--data_directory", "--data_directory <path> where the Node keeps what it learns about the network between runs") {
+            Some (ref path) if Path::new (path).exists () => Some (PathBuf::from (path)),
+            _ => None
+        }
+    }
+
     fn id_from_env (&self, name: &str) -> Option<i32> {
         match self.environment_wrapper.var (name) {
             Some (s) => match s.parse::<i32> () {Ok(n) => Some (n), Err(_) => None},
@@ -104,44 +250,100 @@ impl PrivilegeDropperReal {
     }
 }
 
+// Every file the Node itself keeps in its data directory, along with the ones SQLite and the
+// Neighborhood's store keep beside theirs
+fn node_filenames () -> Vec<String> {
+    let mut filenames: Vec<String> = vec! (IDENTITY_FILENAME, WALLET_FILENAME, CLANDESTINE_PORT_FILENAME, UI_TOKEN_FILENAME,
+        UI_CERTIFICATE_FILENAME, UI_PRIVATE_KEY_FILENAME, PID_FILENAME, NEIGHBORHOOD_DATABASE_FILENAME, LEDGER_FILENAME)
+        .into_iter ().map (String::from).collect ();
+    filenames.push (Path::new (NEIGHBORHOOD_DATABASE_FILENAME).with_extension ("tmp").to_string_lossy ().to_string ());
+    filenames.extend (vec! ("-journal", "-wal", "-shm").into_iter ().map (|suffix| format! ("{}{}", LEDGER_FILENAME, suffix)));
+    filenames
+}
+
+fn is_plain_file (path: &Path) -> bool {
+    fs::symlink_metadata (path).map (|metadata| metadata.file_type ().is_file ()).unwrap_or (false)
+}
+
 #[cfg (test)]
 mod tests {
     use super::*;
+    use std::cell::Cell;
     use std::cell::RefCell;
+    use std::env::temp_dir;
+    use std::fs::File;
+    use std::rc::Rc;
 
+    // Keeps track of its ids the way the kernel would, so a drop can be checked by where it ends up
     struct IdWrapperMock {
-        pub uids: RefCell<Vec<i32>>,
-        pub uid_results: RefCell<Vec<i32>>,
-        pub gids: RefCell<Vec<i32>>,
-        pub gid_results: RefCell<Vec<i32>>,
-        pub log: RefCell<Vec<String>>
+        uid: Cell<i32>,
+        gid: Cell<i32>,
+        setuid_result: i32,
+        setgid_result: i32,
+        setgroups_result: i32,
+        uid_sticks: bool,
+        gid_sticks: bool,
+        root_regainable: bool,
+        users: Vec<(&'static str, i32, i32)>,
+        groups: Vec<(&'static str, i32)>,
+        owner: Option<i32>,
+        log: Rc<RefCell<Vec<String>>>
     }
 
     impl IdWrapper for IdWrapperMock {
         fn getuid (&self) -> i32 {
-            self.uids.borrow_mut ().pop ().unwrap ()
+            self.uid.get ()
         }
         fn getgid (&self) -> i32  {
-            self.gids.borrow_mut ().pop ().unwrap ()
+            self.gid.get ()
         }
         fn setuid (&self, uid: i32) -> i32 {
             self.log.borrow_mut ().push (format! ("setuid ({})", uid));
-            self.uid_results.borrow_mut ().pop ().unwrap ()
+            if self.setuid_result != 0 {return self.setuid_result}
+            if self.uid.get () != 0 && uid != self.uid.get () && !self.root_regainable {return -1}
+            if self.uid_sticks {self.uid.set (uid)}
+            0
         }
         fn setgid (&self, gid: i32) -> i32  {
             self.log.borrow_mut ().push (format! ("setgid ({})", gid));
-            self.gid_results.borrow_mut ().pop ().unwrap ()
+            if self.setgid_result != 0 {return self.setgid_result}
+            if self.gid_sticks {self.gid.set (gid)}
+            0
+        }
+        fn setgroups (&self, gid: i32) -> i32 {
+            self.log.borrow_mut ().push (format! ("setgroups ({})", gid));
+            self.setgroups_result
+        }
+        fn user_ids (&self, name: &str) -> Option<(i32, i32)> {
+            self.users.iter ().find (|&&(user, _, _)| user == name).map (|&(_, uid, gid)| (uid, gid))
+        }
+        fn group_id (&self, name: &str) -> Option<i32> {
+            self.groups.iter ().find (|&&(group, _)| group == name).map (|&(_, gid)| gid)
+        }
+        fn lchown (&self, path: &Path, uid: i32, gid: i32) -> i32 {
+            self.log.borrow_mut ().push (format! ("lchown ({}, {}, {})", path.file_name ().unwrap ().to_string_lossy (), uid, gid));
+            0
+        }
+        fn owner (&self, _path: &Path) -> Option<i32> {
+            self.owner
         }
     }
 
     impl IdWrapperMock {
-        fn new (uid_initial: i32, uid_result: i32, uid_final: i32, gid_initial: i32, gid_result: i32, gid_final: i32) -> IdWrapperMock {
+        fn new (uid: i32, gid: i32) -> IdWrapperMock {
             IdWrapperMock {
-                uids: RefCell::new (vec![uid_final, uid_initial]),
-                uid_results: RefCell::new (vec![uid_result]),
-                gids: RefCell::new (vec![gid_final, gid_initial]),
-                gid_results: RefCell::new (vec![gid_result]),
-                log: RefCell::new (vec![])
+                uid: Cell::new (uid),
+                gid: Cell::new (gid),
+                setuid_result: 0,
+                setgid_result: 0,
+                setgroups_result: 0,
+                uid_sticks: true,
+                gid_sticks: true,
+                root_regainable: false,
+                users: vec! (("substratum", 1001, 1002)),
+                groups: vec! (("daemon", 1)),
+                owner: Some (0),
+                log: Rc::new (RefCell::new (vec! ()))
             }
         }
     }
@@ -170,66 +372,215 @@ mod tests {
         }
     }
 
+    fn make_subject (id_wrapper: IdWrapperMock, environment_wrapper: EnvironmentWrapperMock) -> (PrivilegeDropperReal, Rc<RefCell<Vec<String>>>) {
+        let log = id_wrapper.log.clone ();
+        (PrivilegeDropperReal {id_wrapper: Box::new (id_wrapper), environment_wrapper: Box::new (environment_wrapper)}, log)
+    }
+
+    fn strings (strs: Vec<&str>) -> Vec<String> {
+        strs.into_iter ().map (String::from).collect ()
+    }
+
     #[cfg(unix)]
     #[test]
     #[should_panic (expected = "Error code 47 resetting group id")]
     fn gid_error_code_causes_panic () {
-        let id_wrapper = IdWrapperMock::new (0, 0, 1000, 0, 47, 0);
-        let environment_wrapper = EnvironmentWrapperMock::new (Some ("1000"), Some ("1000"));
-        let subject = PrivilegeDropperReal {id_wrapper: Box::new (id_wrapper), environment_wrapper: Box::new (environment_wrapper)};
+        let mut id_wrapper = IdWrapperMock::new (0, 0);
+        id_wrapper.setgid_result = 47;
+        let (subject, _) = make_subject (id_wrapper, EnvironmentWrapperMock::new (Some ("1000"), Some ("1000")));
 
-        subject.drop_privileges ();
+        subject.drop_privileges (&vec! ());
     }
 
     #[cfg(unix)]
     #[test]
     #[should_panic (expected = "Error code 47 resetting user id")]
     fn uid_error_code_causes_panic () {
-        let id_wrapper = IdWrapperMock::new (0, 47, 0, 0, 0, 1000);
-        let environment_wrapper = EnvironmentWrapperMock::new (Some ("1000"), Some ("1000"));
-        let subject = PrivilegeDropperReal {id_wrapper: Box::new (id_wrapper), environment_wrapper: Box::new (environment_wrapper)};
+        let mut id_wrapper = IdWrapperMock::new (0, 0);
+        id_wrapper.setuid_result = 47;
+        let (subject, _) = make_subject (id_wrapper, EnvironmentWrapperMock::new (Some ("1000"), Some ("1000")));
 
-        subject.drop_privileges ();
+        subject.drop_privileges (&vec! ());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[should_panic (expected = "Error code 47 resetting supplementary groups")]
+    fn setgroups_error_code_causes_panic () {
+        let mut id_wrapper = IdWrapperMock::new (0, 0);
+        id_wrapper.setgroups_result = 47;
+        let (subject, _) = make_subject (id_wrapper, EnvironmentWrapperMock::new (Some ("1000"), Some ("1000")));
+
+        subject.drop_privileges (&vec! ());
     }
 
     #[cfg(unix)]
     #[test]
     #[should_panic (expected = "Attempt to drop group privileges failed: still root")]
     fn final_gid_of_0_causes_panic () {
-        let id_wrapper = IdWrapperMock::new (0, 0, 1000, 0, 0, 0);
-        let environment_wrapper = EnvironmentWrapperMock::new (Some ("1000"), Some ("1000"));
-        let subject = PrivilegeDropperReal {id_wrapper: Box::new (id_wrapper), environment_wrapper: Box::new (environment_wrapper)};
+        let mut id_wrapper = IdWrapperMock::new (0, 0);
+        id_wrapper.gid_sticks = false;
+        let (subject, _) = make_subject (id_wrapper, EnvironmentWrapperMock::new (Some ("1000"), Some ("1000")));
 
-        subject.drop_privileges ();
+        subject.drop_privileges (&vec! ());
     }
 
     #[cfg(unix)]
     #[test]
     #[should_panic (expected = "Attempt to drop user privileges failed: still root")]
     fn final_uid_of_0_causes_panic () {
-        let id_wrapper = IdWrapperMock::new (0, 0, 0, 0, 0, 1000);
-        let environment_wrapper = EnvironmentWrapperMock::new (Some ("1000"), Some ("1000"));
-        let subject = PrivilegeDropperReal {id_wrapper: Box::new (id_wrapper), environment_wrapper: Box::new (environment_wrapper)};
-
-        subject.drop_privileges ();
-    }
-
-    // TODO: Figure out how to make this test compile
-//    #[test]
-//    fn works_okay_as_root_with_environment_variables () {
-//        let mut id_wrapper = IdWrapperMock::new (0, 0, 1000, 0, 0, 1000);
-//        let environment_wrapper = EnvironmentWrapperMock::new (Some ("1000"), Some ("1000"));
-//        let subject = PrivilegeDropper {
-//            id_wrapper: Box::new (id_wrapper),
-//            environment_wrapper: Box::new (environment_wrapper)
-//        };
-//
-//        subject.drop_privileges ();
-//
-//        let log = id_wrapper.log.get_mut ();
-//        assert_eq! (log, &vec![
-//            String::from ("setgid (1000)"),
-//            String::from ("setuid (1000)")
-//        ])
-//    }
-}
\ No newline at end of file
+        let mut id_wrapper = IdWrapperMock::new (0, 0);
+        id_wrapper.uid_sticks = false;
+        let (subject, _) = make_subject (id_wrapper, EnvironmentWrapperMock::new (Some ("1000"), Some ("1000")));
+
+        subject.drop_privileges (&vec! ());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[should_panic (expected = "Attempt to drop user privileges failed: root can be regained")]
+    fn regainable_root_causes_panic () {
+        let mut id_wrapper = IdWrapperMock::new (0, 0);
+        id_wrapper.root_regainable = true;
+        let (subject, _) = make_subject (id_wrapper, EnvironmentWrapperMock::new (Some ("1000"), Some ("1000")));
+
+        subject.drop_privileges (&vec! ());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[should_panic (expected = "Running as root without sudo: give --user <name or uid> for the Node to run as once it's started")]
+    fn root_without_sudo_or_user_causes_panic () {
+        let (subject, _) = make_subject (IdWrapperMock::new (0, 0), EnvironmentWrapperMock::new (None, None));
+
+        subject.drop_privileges (&vec! ());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn works_okay_as_root_with_environment_variables () {
+        let (subject, log) = make_subject (IdWrapperMock::new (0, 0), EnvironmentWrapperMock::new (Some ("1000"), Some ("1000")));
+
+        subject.drop_privileges (&vec! ());
+
+        assert_eq! (*log.borrow (), strings (vec! ("setgroups (1000)", "setgid (1000)", "setuid (1000)", "setuid (0)")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_named_user_is_dropped_to_along_with_their_own_group () {
+        let (subject, log) = make_subject (IdWrapperMock::new (0, 0), EnvironmentWrapperMock::new (Some ("1000"), Some ("1000")));
+
+        subject.drop_privileges (&strings (vec! ("--user", "substratum")));
+
+        assert_eq! (*log.borrow (), strings (vec! ("setgroups (1002)", "setgid (1002)", "setuid (1001)", "setuid (0)")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_named_group_beats_the_users_own () {
+        let (subject, log) = make_subject (IdWrapperMock::new (0, 0), EnvironmentWrapperMock::new (None, None));
+
+        subject.drop_privileges (&strings (vec! ("--user", "substratum", "--group", "daemon")));
+
+        assert_eq! (*log.borrow (), strings (vec! ("setgroups (1)", "setgid (1)", "setuid (1001)", "setuid (0)")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn users_and_groups_can_be_given_by_number () {
+        let (subject, log) = make_subject (IdWrapperMock::new (0, 0), EnvironmentWrapperMock::new (None, None));
+
+        subject.drop_privileges (&strings (vec! ("--user", "1003", "--group", "1004")));
+
+        assert_eq! (*log.borrow (), strings (vec! ("setgroups (1004)", "setgid (1004)", "setuid (1003)", "setuid (0)")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[should_panic (expected = "Invalid value for --user <name or uid>: 'booga'")]
+    fn an_unknown_user_causes_panic () {
+        let (subject, _) = make_subject (IdWrapperMock::new (0, 0), EnvironmentWrapperMock::new (None, None));
+
+        subject.drop_privileges (&strings (vec! ("--user", "booga")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[should_panic (expected = "Invalid value for --group <name or gid>: 'booga'")]
+    fn an_unknown_group_causes_panic () {
+        let (subject, _) = make_subject (IdWrapperMock::new (0, 0), EnvironmentWrapperMock::new (None, None));
+
+        subject.drop_privileges (&strings (vec! ("--user", "substratum", "--group", "booga")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_data_directory_is_handed_over_before_root_is_given_up () {
+        let data_directory = temp_dir ().join ("privilege_drop").join ("the_data_directory_is_handed_over_before_root_is_given_up");
+        let _ = fs::remove_dir_all (&data_directory);
+        fs::create_dir_all (&data_directory).unwrap ();
+        File::create (data_directory.join (IDENTITY_FILENAME)).unwrap ();
+        File::create (data_directory.join ("somebody_elses")).unwrap ();
+        let pid_file = temp_dir ().join ("privilege_drop").join ("node.pid");
+        File::create (&pid_file).unwrap ();
+        let data_directory_string = data_directory.to_string_lossy ().to_string ();
+        let pid_file_string = pid_file.to_string_lossy ().to_string ();
+        let (subject, log) = make_subject (IdWrapperMock::new (0, 0), EnvironmentWrapperMock::new (None, None));
+
+        subject.drop_privileges (&strings (vec! ("--user", "substratum", "--data_directory", data_directory_string.as_str (), "--pid_file", pid_file_string.as_str ())));
+
+        assert_eq! (*log.borrow (), strings (vec! (
+            "lchown (the_data_directory_is_handed_over_before_root_is_given_up, 1001, 1002)",
+            "lchown (identity.key, 1001, 1002)",
+            "lchown (node.pid, 1001, 1002)",
+            "setgroups (1002)", "setgid (1002)", "setuid (1001)", "setuid (0)"
+        )));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_in_the_data_directory_are_not_followed_or_handed_over () {
+        use std::os::unix::fs::symlink;
+        let data_directory = temp_dir ().join ("privilege_drop").join ("links_in_the_data_directory_are_not_followed_or_handed_over");
+        let _ = fs::remove_dir_all (&data_directory);
+        fs::create_dir_all (&data_directory).unwrap ();
+        let target = temp_dir ().join ("privilege_drop").join ("links_in_the_data_directory_are_not_followed_or_handed_over.target");
+        File::create (&target).unwrap ();
+        symlink (&target, data_directory.join (IDENTITY_FILENAME)).unwrap ();
+        let data_directory_string = data_directory.to_string_lossy ().to_string ();
+        let (subject, log) = make_subject (IdWrapperMock::new (0, 0), EnvironmentWrapperMock::new (None, None));
+
+        subject.drop_privileges (&strings (vec! ("--user", "substratum", "--data_directory", data_directory_string.as_str ())));
+
+        assert_eq! (*log.borrow (), strings (vec! (
+            "lchown (links_in_the_data_directory_are_not_followed_or_handed_over, 1001, 1002)",
+            "setgroups (1002)", "setgid (1002)", "setuid (1001)", "setuid (0)"
+        )));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[should_panic (expected = "belongs to user 1003, not to root or the Node's user")]
+    fn a_data_directory_that_belongs_to_somebody_else_is_refused () {
+        let data_directory = temp_dir ().join ("privilege_drop").join ("a_data_directory_that_belongs_to_somebody_else_is_refused");
+        fs::create_dir_all (&data_directory).unwrap ();
+        let data_directory_string = data_directory.to_string_lossy ().to_string ();
+        let mut id_wrapper = IdWrapperMock::new (0, 0);
+        id_wrapper.owner = Some (1003);
+        let (subject, _) = make_subject (id_wrapper, EnvironmentWrapperMock::new (None, None));
+
+        subject.drop_privileges (&strings (vec! ("--user", "substratum", "--data_directory", data_directory_string.as_str ())));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_node_started_without_root_stays_who_it_is () {
+        let data_directory_string = temp_dir ().to_string_lossy ().to_string ();
+        let (subject, log) = make_subject (IdWrapperMock::new (1000, 1000), EnvironmentWrapperMock::new (None, None));
+
+        subject.drop_privileges (&strings (vec! ("--data_directory", data_directory_string.as_str ())));
+
+        assert_eq! (*log.borrow (), strings (vec! ("setgid (1000)", "setuid (1000)", "setuid (0)")));
+    }
+}
@@ -0,0 +1,356 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::cmp;
+use std::io;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::net::UdpSocket;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use public_ip_monitor::PublicIpFinder;
+
+pub const DEFAULT_STUN_SERVER: &str = "stun.l.google.com:19302";
+pub const DEFAULT_HTTP_ECHO_HOST: &str = "api.ipify.org";
+const DISCOVERY_TIMEOUT_MS: u64 = 3000;
+
+const STUN_MAGIC_COOKIE: [u8; 4] = [0x21, 0x12, 0xA4, 0x42];
+const STUN_BINDING_REQUEST: u16 = 0x0001;
+const STUN_BINDING_RESPONSE: u16 = 0x0101;
+const STUN_MAPPED_ADDRESS: u16 = 0x0001;
+const STUN_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+
+// Asks each finder in turn, stopping at the first that answers
+pub struct FirstAnswerPublicIpFinder {
+    finders: Vec<Box<PublicIpFinder>>,
+}
+
+impl PublicIpFinder for FirstAnswerPublicIpFinder {
+    fn find_public_ip (&self) -> Option<IpAddr> {
+        self.finders.iter ().filter_map (|finder| finder.find_public_ip ()).next ()
+    }
+}
+
+impl FirstAnswerPublicIpFinder {
+    pub fn new (finders: Vec<Box<PublicIpFinder>>) -> FirstAnswerPublicIpFinder {
+        FirstAnswerPublicIpFinder {finders}
+    }
+}
+
+// The outside services asked for this Node's public IP address when --ip doesn't give it
+#[derive (Clone, Debug, PartialEq)]
+pub struct IpDiscoveryConfig {
+    pub stun_server: String,
+    pub http_echo_host: String,
+}
+
+// The local interfaces are checked first, since that doesn't involve anybody else; the echo
+// services are only needed when the Node is behind NAT.
+pub fn public_ip_finder (config: &IpDiscoveryConfig) -> Box<PublicIpFinder> {
+    Box::new (FirstAnswerPublicIpFinder::new (vec! (
+        Box::new (InterfacePublicIpFinder::new ()),
+        Box::new (StunPublicIpFinder::new (&config.stun_server)),
+        Box::new (HttpEchoPublicIpFinder::new (&config.http_echo_host)),
+    )))
+}
+
+pub trait PublicIpFinderFactory: Send {
+    fn make (&self, config: &IpDiscoveryConfig) -> Box<PublicIpFinder>;
+}
+
+pub struct PublicIpFinderFactoryReal {}
+
+impl PublicIpFinderFactory for PublicIpFinderFactoryReal {
+    fn make (&self, config: &IpDiscoveryConfig) -> Box<PublicIpFinder> {
+        public_ip_finder (config)
+    }
+}
+
+impl PublicIpFinderFactoryReal {
+    pub fn new () -> PublicIpFinderFactoryReal {
+        PublicIpFinderFactoryReal {}
+    }
+}
+
+// Finds the address of the interface outgoing traffic leaves through, if that address is public.
+// Connecting a UDP socket sends nothing; it just makes the OS choose a route.
+pub struct InterfacePublicIpFinder {}
+
+impl PublicIpFinder for InterfacePublicIpFinder {
+    fn find_public_ip (&self) -> Option<IpAddr> {
+        let socket = match UdpSocket::bind ("0.0.0.0:0") {
+            Err (_) => return None,
+            Ok (socket) => socket
+        };
+        if socket.connect ("8.8.8.8:53").is_err () {return None}
+        match socket.local_addr () {
+            Ok (ref local_addr) if is_public (&local_addr.ip ()) => Some (local_addr.ip ()),
+            _ => None
+        }
+    }
+}
+
+impl InterfacePublicIpFinder {
+    pub fn new () -> InterfacePublicIpFinder {
+        InterfacePublicIpFinder {}
+    }
+}
+
+pub struct StunPublicIpFinder {
+    server: String,
+}
+
+impl PublicIpFinder for StunPublicIpFinder {
+    fn find_public_ip (&self) -> Option<IpAddr> {
+        self.query ().unwrap_or (None)
+    }
+}
+
+impl StunPublicIpFinder {
+    pub fn new (server: &str) -> StunPublicIpFinder {
+        StunPublicIpFinder {server: String::from (server)}
+    }
+
+    fn query (&self) -> io::Result<Option<IpAddr>> {
+        let socket = UdpSocket::bind ("0.0.0.0:0")?;
+        socket.set_read_timeout (Some (Duration::from_millis (DISCOVERY_TIMEOUT_MS)))?;
+        let transaction_id = make_transaction_id ();
+        socket.send_to (&make_stun_request (&transaction_id)[..], self.server.as_str ())?;
+        let mut buf = [0u8; 512];
+        let (length, _) = socket.recv_from (&mut buf)?;
+        Ok (parse_stun_response (&buf[..length], &transaction_id))
+    }
+}
+
+pub struct HttpEchoPublicIpFinder {
+    host: String,
+}
+
+impl PublicIpFinder for HttpEchoPublicIpFinder {
+    fn find_public_ip (&self) -> Option<IpAddr> {
+        self.query ().unwrap_or (None)
+    }
+}
+
+impl HttpEchoPublicIpFinder {
+    pub fn new (host: &str) -> HttpEchoPublicIpFinder {
+        HttpEchoPublicIpFinder {host: String::from (host)}
+    }
+
+    fn query (&self) -> io::Result<Option<IpAddr>> {
+        let socket_addr = match (self.host.as_str (), 80).to_socket_addrs ()?.next () {
+            None => return Err (io::Error::from (ErrorKind::NotFound)),
+            Some (socket_addr) => socket_addr
+        };
+        let timeout = Duration::from_millis (DISCOVERY_TIMEOUT_MS);
+        let mut stream = TcpStream::connect_timeout (&socket_addr, timeout)?;
+        stream.set_read_timeout (Some (timeout))?;
+        write! (stream, "GET / HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", self.host)?;
+        let mut response = String::new ();
+        stream.read_to_string (&mut response)?;
+        Ok (parse_http_echo_response (&response))
+    }
+}
+
+fn is_public (ip_addr: &IpAddr) -> bool {
+    match ip_addr {
+        &IpAddr::V4 (ref ip) => !(ip.is_private () || ip.is_loopback () || ip.is_link_local () || ip.is_unspecified ()
+            || ip.is_broadcast () || ip.is_documentation () || is_shared_v4 (ip)),
+        &IpAddr::V6 (ref ip) => !(ip.is_loopback () || ip.is_unspecified () || is_local_v6 (ip)),
+    }
+}
+
+// 100.64.0.0/10, handed out by carrier-grade NAT
+fn is_shared_v4 (ip: &Ipv4Addr) -> bool {
+    (ip.octets ()[0] == 100) && ((ip.octets ()[1] & 0xC0) == 64)
+}
+
+// fc00::/7 unique local and fe80::/10 link local
+fn is_local_v6 (ip: &Ipv6Addr) -> bool {
+    ((ip.segments ()[0] & 0xFE00) == 0xFC00) || ((ip.segments ()[0] & 0xFFC0) == 0xFE80)
+}
+
+fn make_transaction_id () -> [u8; 12] {
+    let since_epoch = SystemTime::now ().duration_since (UNIX_EPOCH).expect ("Clock is before 1970");
+    let seed = since_epoch.as_secs () ^ ((since_epoch.subsec_nanos () as u64) << 32);
+    let mut transaction_id = [0u8; 12];
+    for index in 0..12 {
+        transaction_id[index] = (seed.rotate_left (index as u32 * 5) & 0xFF) as u8;
+    }
+    transaction_id
+}
+
+fn make_stun_request (transaction_id: &[u8; 12]) -> Vec<u8> {
+    let mut request = vec! ((STUN_BINDING_REQUEST >> 8) as u8, STUN_BINDING_REQUEST as u8, 0, 0);
+    request.extend_from_slice (&STUN_MAGIC_COOKIE);
+    request.extend_from_slice (transaction_id);
+    request
+}
+
+fn parse_stun_response (data: &[u8], transaction_id: &[u8; 12]) -> Option<IpAddr> {
+    if data.len () < 20 {return None}
+    if read_u16 (data, 0) != STUN_BINDING_RESPONSE {return None}
+    if (&data[4..8] != &STUN_MAGIC_COOKIE[..]) || (&data[8..20] != &transaction_id[..]) {return None}
+    let end = cmp::min (data.len (), 20 + read_u16 (data, 2) as usize);
+    let mut mapped_opt = None;
+    let mut offset = 20;
+    while offset + 4 <= end {
+        let attribute_type = read_u16 (data, offset);
+        let length = read_u16 (data, offset + 2) as usize;
+        let value_start = offset + 4;
+        if value_start + length > end {return mapped_opt}
+        let value = &data[value_start..(value_start + length)];
+        match attribute_type {
+            STUN_XOR_MAPPED_ADDRESS => {
+                let mut mask = STUN_MAGIC_COOKIE.to_vec ();
+                mask.extend_from_slice (transaction_id);
+                if let Some (ip_addr) = parse_stun_address (value, &mask[..]) {return Some (ip_addr)}
+            },
+            STUN_MAPPED_ADDRESS => mapped_opt = mapped_opt.or (parse_stun_address (value, &[0u8; 16][..])),
+            _ => ()
+        }
+        offset = value_start + ((length + 3) & !3);
+    }
+    mapped_opt
+}
+
+// Attribute value: reserved byte, family, port, address; the address is XORed with mask
+fn parse_stun_address (value: &[u8], mask: &[u8]) -> Option<IpAddr> {
+    if value.len () < 4 {return None}
+    let address_length = match value[1] {
+        0x01 => 4,
+        0x02 => 16,
+        _ => return None
+    };
+    if value.len () < 4 + address_length {return None}
+    let address: Vec<u8> = value[4..(4 + address_length)].iter ().zip (mask.iter ())
+        .map (|(byte, mask_byte)| byte ^ mask_byte)
+        .collect ();
+    if address_length == 4 {
+        Some (IpAddr::V4 (Ipv4Addr::new (address[0], address[1], address[2], address[3])))
+    }
+    else {
+        let mut octets = [0u8; 16];
+        octets.copy_from_slice (&address[..]);
+        Some (IpAddr::V6 (Ipv6Addr::from (octets)))
+    }
+}
+
+fn parse_http_echo_response (response: &str) -> Option<IpAddr> {
+    let status_line = match response.lines ().next () {
+        None => return None,
+        Some (line) => line
+    };
+    if !status_line.contains (" 200 ") {return None}
+    match response.find ("\r\n\r\n") {
+        None => None,
+        Some (index) => response[(index + 4)..].trim ().parse::<IpAddr> ().ok ()
+    }
+}
+
+fn read_u16 (data: &[u8], offset: usize) -> u16 {
+    ((data[offset] as u16) << 8) | (data[offset + 1] as u16)
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    struct PublicIpFinderMock {
+        result: Option<IpAddr>,
+    }
+
+    impl PublicIpFinder for PublicIpFinderMock {
+        fn find_public_ip (&self) -> Option<IpAddr> {
+            self.result
+        }
+    }
+
+    fn stun_response (transaction_id: &[u8; 12], attributes: Vec<u8>) -> Vec<u8> {
+        let mut response = vec! (0x01, 0x01, (attributes.len () >> 8) as u8, attributes.len () as u8);
+        response.extend_from_slice (&STUN_MAGIC_COOKIE);
+        response.extend_from_slice (transaction_id);
+        response.extend (attributes);
+        response
+    }
+
+    #[test]
+    fn the_first_finder_with_an_answer_wins () {
+        let subject = FirstAnswerPublicIpFinder::new (vec! (
+            Box::new (PublicIpFinderMock {result: None}),
+            Box::new (PublicIpFinderMock {result: Some (IpAddr::from_str ("1.2.3.4").unwrap ())}),
+            Box::new (PublicIpFinderMock {result: Some (IpAddr::from_str ("5.6.7.8").unwrap ())}),
+        ));
+
+        assert_eq! (subject.find_public_ip (), Some (IpAddr::from_str ("1.2.3.4").unwrap ()));
+    }
+
+    #[test]
+    fn private_and_local_addresses_are_not_public () {
+        vec! ("10.1.2.3", "172.16.0.1", "192.168.1.1", "127.0.0.1", "169.254.1.1", "100.64.0.1", "0.0.0.0", "::1", "fe80::1", "fd00::1")
+            .into_iter ().for_each (|ip| assert_eq! (is_public (&IpAddr::from_str (ip).unwrap ()), false, "{}", ip));
+        vec! ("1.2.3.4", "100.128.0.1", "2001:4860::1")
+            .into_iter ().for_each (|ip| assert_eq! (is_public (&IpAddr::from_str (ip).unwrap ()), true, "{}", ip));
+    }
+
+    #[test]
+    fn stun_requests_are_binding_requests () {
+        let transaction_id = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+
+        let result = make_stun_request (&transaction_id);
+
+        assert_eq! (result, vec! (0x00, 0x01, 0x00, 0x00, 0x21, 0x12, 0xA4, 0x42, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12));
+    }
+
+    #[test]
+    fn stun_responses_yield_the_xor_mapped_address () {
+        let transaction_id = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        // 1.2.3.4 XOR 21 12 A4 42, after an unrelated attribute
+        let response = stun_response (&transaction_id, vec! (
+            0x80, 0x22, 0x00, 0x03, b'a', b'b', b'c', 0x00,
+            0x00, 0x20, 0x00, 0x08, 0x00, 0x01, 0x00, 0x00, 0x20, 0x10, 0xA7, 0x46,
+        ));
+
+        assert_eq! (parse_stun_response (&response[..], &transaction_id), Some (IpAddr::from_str ("1.2.3.4").unwrap ()));
+    }
+
+    #[test]
+    fn stun_responses_fall_back_on_the_plain_mapped_address () {
+        let transaction_id = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let response = stun_response (&transaction_id, vec! (
+            0x00, 0x01, 0x00, 0x08, 0x00, 0x01, 0x00, 0x00, 5, 6, 7, 8,
+        ));
+
+        assert_eq! (parse_stun_response (&response[..], &transaction_id), Some (IpAddr::from_str ("5.6.7.8").unwrap ()));
+    }
+
+    #[test]
+    fn stun_responses_to_somebody_else_are_ignored () {
+        let response = stun_response (&[9; 12], vec! (
+            0x00, 0x01, 0x00, 0x08, 0x00, 0x01, 0x00, 0x00, 5, 6, 7, 8,
+        ));
+
+        assert_eq! (parse_stun_response (&response[..], &[1; 12]), None);
+        assert_eq! (parse_stun_response (&response[..10], &[9; 12]), None);
+    }
+
+    #[test]
+    fn http_echo_responses_yield_the_address_in_the_body () {
+        let response = "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 8\r\n\r\n1.2.3.4\n";
+
+        assert_eq! (parse_http_echo_response (response), Some (IpAddr::from_str ("1.2.3.4").unwrap ()));
+    }
+
+    #[test]
+    fn http_echo_failures_yield_nothing () {
+        assert_eq! (parse_http_echo_response ("HTTP/1.1 503 Service Unavailable\r\n\r\n1.2.3.4"), None);
+        assert_eq! (parse_http_echo_response ("HTTP/1.1 200 OK\r\n\r\n<html>nope</html>"), None);
+        assert_eq! (parse_http_echo_response (""), None);
+    }
+}
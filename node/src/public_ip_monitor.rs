@@ -0,0 +1,150 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::net::IpAddr;
+use std::net::ToSocketAddrs;
+use std::thread;
+use std::time::Duration;
+use actix::Recipient;
+use actix::Syn;
+use sub_lib::logger::Logger;
+use sub_lib::neighborhood::NewPublicIpMsg;
+
+pub trait PublicIpFinder: Send {
+    fn find_public_ip (&self) -> Option<IpAddr>;
+}
+
+// For operators with a dynamic DNS name that follows their connection around
+pub struct HostnamePublicIpFinder {
+    hostname: String,
+}
+
+impl PublicIpFinder for HostnamePublicIpFinder {
+    fn find_public_ip (&self) -> Option<IpAddr> {
+        match (self.hostname.as_str (), 0).to_socket_addrs () {
+            Err (_) => None,
+            Ok (mut socket_addrs) => socket_addrs.next ().map (|socket_addr| socket_addr.ip ())
+        }
+    }
+}
+
+impl HostnamePublicIpFinder {
+    pub fn new (hostname: &str) -> HostnamePublicIpFinder {
+        HostnamePublicIpFinder {hostname: String::from (hostname)}
+    }
+}
+
+// Checks the public IP address now and then, and tells the Neighborhood whenever it moves. Finding
+// the address means DNS lookups and network round trips, so it's done on a thread of its own rather
+// than holding up the actors.
+pub struct PublicIpMonitor {
+    finder: Box<PublicIpFinder>,
+    check_interval_ms: u64,
+    last_ip_addr_opt: Option<IpAddr>,
+    to_neighborhood: Recipient<Syn, NewPublicIpMsg>,
+    logger: Logger,
+}
+
+impl PublicIpMonitor {
+    pub fn new (finder: Box<PublicIpFinder>, check_interval_ms: u64, last_ip_addr_opt: Option<IpAddr>,
+                to_neighborhood: Recipient<Syn, NewPublicIpMsg>) -> PublicIpMonitor {
+        PublicIpMonitor {
+            finder,
+            check_interval_ms,
+            last_ip_addr_opt,
+            to_neighborhood,
+            logger: Logger::new ("PublicIpMonitor"),
+        }
+    }
+
+    // Checks straight away, and then once per interval for as long as the Node runs
+    pub fn start (mut self) {
+        thread::spawn (move || {
+            loop {
+                self.check ();
+                thread::sleep (Duration::from_millis (self.check_interval_ms));
+            }
+        });
+    }
+
+    fn check (&mut self) {
+        let ip_addr = match self.finder.find_public_ip () {
+            None => {
+                self.logger.warning (String::from ("Couldn't determine the public IP address; will try again later"));
+                return
+            },
+            Some (ip_addr) => ip_addr
+        };
+        if self.last_ip_addr_opt == Some (ip_addr) {return}
+        self.last_ip_addr_opt = Some (ip_addr);
+        self.to_neighborhood.try_send (NewPublicIpMsg {ip_addr}).expect ("Neighborhood is dead");
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::str::FromStr;
+    use std::thread;
+    use actix::Addr;
+    use actix::System;
+    use test_utils::test_utils::init_test_logging;
+    use test_utils::test_utils::Recorder;
+    use test_utils::test_utils::TestLogHandler;
+
+    struct PublicIpFinderMock {
+        results: RefCell<Vec<Option<IpAddr>>>,
+    }
+
+    impl PublicIpFinder for PublicIpFinderMock {
+        fn find_public_ip (&self) -> Option<IpAddr> {
+            let mut results = self.results.borrow_mut ();
+            if results.is_empty () {None} else {results.remove (0)}
+        }
+    }
+
+    #[test]
+    fn hostname_finder_resolves_the_hostname () {
+        let subject = HostnamePublicIpFinder::new ("localhost");
+
+        let result = subject.find_public_ip ();
+
+        assert_eq! (result.map (|ip_addr| ip_addr.is_loopback ()), Some (true));
+    }
+
+    #[test]
+    fn hostname_finder_finds_nothing_for_an_unresolvable_hostname () {
+        let subject = HostnamePublicIpFinder::new ("no such host.invalid");
+
+        assert_eq! (subject.find_public_ip (), None);
+    }
+
+    #[test]
+    fn reports_only_changes_to_the_public_ip_address () {
+        init_test_logging ();
+        let neighborhood = Recorder::new ();
+        let recording_arc = neighborhood.get_recording ();
+        let awaiter = neighborhood.get_awaiter ();
+        thread::spawn (move || {
+            let system = System::new ("reports_only_changes_to_the_public_ip_address");
+            let neighborhood_addr: Addr<Syn, Recorder> = neighborhood.start ();
+            let finder = PublicIpFinderMock {results: RefCell::new (vec! (
+                Some (IpAddr::from_str ("1.2.3.4").unwrap ()),
+                None,
+                Some (IpAddr::from_str ("1.2.3.4").unwrap ()),
+                Some (IpAddr::from_str ("5.6.7.8").unwrap ()),
+            ))};
+            let subject = PublicIpMonitor::new (Box::new (finder), 10, Some (IpAddr::from_str ("9.9.9.9").unwrap ()),
+                neighborhood_addr.recipient::<NewPublicIpMsg> ());
+            subject.start ();
+
+            system.run ();
+        });
+        awaiter.await_message_count (2);
+        thread::sleep (Duration::from_millis (100));
+        let recording = recording_arc.lock ().unwrap ();
+        assert_eq! (recording.len (), 2);
+        assert_eq! (recording.get_record::<NewPublicIpMsg> (0), &NewPublicIpMsg {ip_addr: IpAddr::from_str ("1.2.3.4").unwrap ()});
+        assert_eq! (recording.get_record::<NewPublicIpMsg> (1), &NewPublicIpMsg {ip_addr: IpAddr::from_str ("5.6.7.8").unwrap ()});
+        TestLogHandler::new ().exists_log_containing ("Couldn't determine the public IP address; will try again later");
+    }
+}
@@ -1,21 +1,49 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::env;
 use std::env::temp_dir;
+#[cfg(unix)]
+use std::fs::File;
+#[cfg(unix)]
+use std::fs::OpenOptions;
+#[cfg(unix)]
+use std::io;
+#[cfg(unix)]
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::thread;
 use std::time::Duration;
 use flexi_logger::LevelFilter;
 use flexi_logger::Logger;
 use flexi_logger::LogSpecification;
+use sub_lib::logger;
 use sub_lib::main_tools::StdStreams;
 use sub_lib::main_tools::Command;
 use sub_lib::parameter_finder::ParameterFinder;
+use sub_lib::socket_server::Reloader;
 use sub_lib::socket_server::SocketServer;
+use sub_lib::socket_server::Stopper;
 use entry_dns_lib::dns_socket_server::new_dns_socket_server;
 use bootstrapper::Bootstrapper;
+use bootstrapper::NodeMode;
+use cli;
+use config_file;
+use config_reloader;
+use config_reloader::ConfigReloader;
+use pid_file;
+use pid_file::PidFile;
 use privilege_drop::PrivilegeDropper;
 use privilege_drop::PrivilegeDropperReal;
-//#[cfg(unix)]
-//use daemonize::Daemonize;
+use shutdown;
+use supervisor::Supervisor;
+use supervisor::SupervisorReal;
+#[cfg(unix)]
+use libc;
 
 pub struct ServerInitializer<P, D> where P: PrivilegeDropper, D: Daemonizer {
     dns_socket_server: Option<Box<SocketServer>>,
@@ -23,27 +51,90 @@ pub struct ServerInitializer<P, D> where P: PrivilegeDropper, D: Daemonizer {
     privilege_dropper: P,
     daemonizer: D,
     logger_initializer_wrapper: Box<LoggerInitializerWrapper>,
+    pid_file_wrapper: Box<PidFileWrapper>,
+    supervisor: Box<Supervisor>,
     lifetime_secs: u64
 }
 
 impl<P, D> Command for ServerInitializer<P, D> where P: PrivilegeDropper, D: Daemonizer {
     fn go<'b> (&mut self, streams: &'b mut StdStreams<'b>, args: &Vec<String>) -> u8 {
+        let env_vars: Vec<(String, String)> = env::vars ().collect ();
+        let merged_args = match config_file::merge_args (args, &env_vars).and_then (|merged_args| {
+            cli::validate (&merged_args)?;
+            Ok (merged_args)
+        }) {
+            Ok (merged_args) => merged_args,
+            Err (e) => {
+                writeln! (streams.stderr, "{}", e).expect ("Internal error");
+                return 1
+            }
+        };
+        let cli_args = args;
+        let args = &merged_args;
+        // Before anything is bound, so a second Node says why it can't start instead of which port it couldn't get
+        if let Err (e) = self.pid_file_wrapper.acquire (args) {
+            writeln! (streams.stderr, "{}", e).expect ("Internal error");
+            return 1
+        }
         self.logger_initializer_wrapper.init (args);
         let mut dns_socket_server_box = self.dns_socket_server.take ().expect ("DNS Socket Server missing");
-        dns_socket_server_box.as_mut ().initialize_as_root (args, streams);
+        // A serve-only Node has no local browsers, so it has no DNS to subvert
+        let serve_only = Bootstrapper::parse_mode (&ParameterFinder::new (args.clone ())) == NodeMode::ServeOnly;
+        if !serve_only {
+            dns_socket_server_box.as_mut ().initialize_as_root (args, streams);
+        }
         let mut bootstrapper_box = self.bootstrapper.take ().expect ("Bootstrapper missing");
         bootstrapper_box.as_mut ().initialize_as_root (args, streams);
-        self.privilege_dropper.drop_privileges();
-        self.daemonizer.daemonize();
-        thread::spawn (move || {
-            dns_socket_server_box.as_mut ().serve_without_root();
-        });
+        let mut reloaders: Vec<Box<Reloader>> = vec! (Box::new (LogLevelReloader {}));
+        if !serve_only {
+            reloaders.extend (dns_socket_server_box.reloader ());
+        }
+        reloaders.extend (bootstrapper_box.reloader ());
+        let mut stoppers: Vec<Box<Stopper>> = vec! ();
+        if !serve_only {
+            stoppers.extend (dns_socket_server_box.stopper ());
+        }
+        stoppers.extend (bootstrapper_box.stopper ());
+        // While still root, so the log can be opened for output; and before any threads start,
+        // since they'd be left behind in the parent
+        self.daemonizer.daemonize (args);
+        // The supervisor stays behind with root, to start the Node again if it's asked to restart;
+        // the PID file is for the Node
+        self.supervisor.supervise (cli_args);
+        if let Err (e) = self.pid_file_wrapper.refresh () {
+            writeln! (streams.stderr, "{}", e).expect ("Internal error");
+            return 1
+        }
+        self.privilege_dropper.drop_privileges (args);
+        let config_reloader = Arc::new (Mutex::new (ConfigReloader::new (cli_args, &env_vars, args, reloaders)));
+        config_reloader::reload_on_sighup (config_reloader);
+        shutdown::shut_down_on_signals ();
+        if !serve_only {
+            thread::spawn (move || {
+                dns_socket_server_box.as_mut ().serve_without_root();
+            });
+        }
         thread::spawn (move || {
             bootstrapper_box.as_mut ().serve_without_root();
         });
 
-        // Don't kill my child threads
-        thread::sleep (Duration::from_secs (self.lifetime_secs));
+        // Don't kill my child threads until it's time
+        let logger = logger::Logger::new ("ServerInitializer");
+        if shutdown::wait_for_shutdown (Duration::from_secs (self.lifetime_secs), shutdown::shutdown_requested) {
+            logger.info (String::from ("Shutting down"));
+        }
+        for stopper in stoppers {
+            if let Err (e) = stopper.stop () {
+                logger.error (format! ("Couldn't shut down cleanly: {}", e));
+            }
+        }
+        if let Some (settings) = shutdown::restart_settings () {
+            logger.info (String::from ("Restarting"));
+            if let Err (e) = self.supervisor.restart (&settings) {
+                logger.error (format! ("Couldn't restart: {}", e));
+                return 1
+            }
+        }
 
         return 0
     }
@@ -58,6 +149,8 @@ impl ServerInitializer<PrivilegeDropperReal, DaemonizerReal> {
             privilege_dropper: PrivilegeDropperReal::new (),
             daemonizer: DaemonizerReal::new (),
             logger_initializer_wrapper: Box::new (LoggerInitializerWrapperReal {}),
+            pid_file_wrapper: Box::new (PidFileWrapperReal {pid_file_opt: None}),
+            supervisor: Box::new (SupervisorReal::new ()),
             lifetime_secs: 0xFFFFFFFFFFFFFFFF
         }
     }
@@ -69,9 +162,10 @@ trait LoggerInitializerWrapper: Send {
 
 struct LoggerInitializerWrapperReal {}
 
+// The logger itself lets everything through, so that the level can be raised later without restarting it
 impl LoggerInitializerWrapper for LoggerInitializerWrapperReal {
     fn init(&mut self, args: &Vec<String>) -> bool {
-        match Logger::with(LogSpecification::default(LoggerInitializerWrapperReal::get_log_level(args)).finalize())
+        let result = match Logger::with(LogSpecification::default(LevelFilter::Trace).finalize())
             .log_to_file()
             .directory(&temp_dir ().to_str ().expect ("Bad temporary filename")[..])
             .print_message ()
@@ -80,10 +174,46 @@ impl LoggerInitializerWrapper for LoggerInitializerWrapperReal {
             .start() {
             Ok (_) => true,
             Err (_) => false
+        };
+        logger::set_log_level (LoggerInitializerWrapperReal::get_log_level (args));
+        result
+    }
+}
+
+trait PidFileWrapper: Send {
+    fn acquire (&mut self, args: &Vec<String>) -> Result<(), String>;
+    // A daemonized Node has a new process ID
+    fn refresh (&mut self) -> Result<(), String>;
+}
+
+// The PID file is held until the ServerInitializer goes away
+struct PidFileWrapperReal {
+    pid_file_opt: Option<PidFile>
+}
+
+impl PidFileWrapper for PidFileWrapperReal {
+    fn acquire (&mut self, args: &Vec<String>) -> Result<(), String> {
+        self.pid_file_opt = Some (PidFile::acquire (&pid_file::pid_file_path (args))?);
+        Ok (())
+    }
+
+    fn refresh (&mut self) -> Result<(), String> {
+        match self.pid_file_opt {
+            Some (ref mut pid_file) => pid_file.refresh (),
+            None => Ok (())
         }
     }
 }
 
+struct LogLevelReloader {}
+
+impl Reloader for LogLevelReloader {
+    fn reload (&self, args: &Vec<String>) -> Result<(), String> {
+        logger::set_log_level (LoggerInitializerWrapperReal::get_log_level (args));
+        Ok (())
+    }
+}
+
 impl LoggerInitializerWrapperReal {
     fn get_log_level(args: &Vec<String>) -> LevelFilter {
         let parameter_tag = "--log_level";
@@ -101,8 +231,15 @@ impl LoggerInitializerWrapperReal {
     }
 }
 
+// The logger names its file for the program, with no timestamp
+fn log_file_path () -> PathBuf {
+    let program = env::args ().next ().unwrap_or (String::from ("SubstratumNode"));
+    let stem = Path::new (&program).file_stem ().map (|stem| stem.to_string_lossy ().to_string ()).unwrap_or (String::from ("SubstratumNode"));
+    temp_dir ().join (format! ("{}.log", stem))
+}
+
 pub trait Daemonizer {
-    fn daemonize (&self);
+    fn daemonize (&self, args: &Vec<String>);
 }
 
 #[cfg(unix)]
@@ -113,26 +250,61 @@ pub struct DaemonizerReal;
 
 #[cfg(unix)]
 impl Daemonizer for DaemonizerReal {
-    // Not unit tested
-    fn daemonize(&self) {
-//        match Daemonize::new ()
-//            .working_directory ("/tmp")
-//            .user ("nobody")
-//            .group ("daemon")
-//            .start () {
-//            Ok (_) => (),
-//            Err (e) => panic! ("Couldn't daemonize: {}", e.to_string ())
-//        }
+    // Not unit tested. The Node stays in the directory it was started in, since its settings may
+    // name paths relative to it.
+    fn daemonize(&self, args: &Vec<String>) {
+        if !DaemonizerReal::parse_daemon (&ParameterFinder::new (args.clone ())) {return}
+        let log_file_path = log_file_path ();
+        let log_file = OpenOptions::new ().append (true).create (true).open (&log_file_path)
+            .unwrap_or_else (|e| panic! ("Couldn't open log file {:?} for output: {}", log_file_path, e));
+        let dev_null = File::open ("/dev/null").unwrap_or_else (|e| panic! ("Couldn't open /dev/null: {}", e));
+        // Anything still buffered would come out of both processes
+        let _ = io::stdout ().flush ();
+        let _ = io::stderr ().flush ();
+        DaemonizerReal::fork_and_leave_parent ();
+        if unsafe {libc::setsid ()} < 0 {panic! ("Couldn't detach from the terminal: {}", io::Error::last_os_error ())}
+        // A session leader could pick up a new controlling terminal; its child can't
+        DaemonizerReal::fork_and_leave_parent ();
+        unsafe {
+            libc::dup2 (dev_null.as_raw_fd (), libc::STDIN_FILENO);
+            libc::dup2 (log_file.as_raw_fd (), libc::STDOUT_FILENO);
+            libc::dup2 (log_file.as_raw_fd (), libc::STDERR_FILENO);
+        }
+    }
+}
+
+#[cfg(unix)]
+impl DaemonizerReal {
+    fn fork_and_leave_parent () {
+        match unsafe {libc::fork ()} {
+            -1 => panic! ("Couldn't fork: {}", io::Error::last_os_error ()),
+            0 => (),
+            // Not exit (): the parent mustn't clean up anything the child is still using, like the PID file
+            _ => unsafe {libc::_exit (0)}
+        }
     }
 }
 
 #[cfg(windows)]
 impl Daemonizer for DaemonizerReal {
-    fn daemonize(&self) {
+    fn daemonize(&self, _args: &Vec<String>) {
         // No daemonization for Windows yet
     }
 }
 
+impl DaemonizerReal {
+    fn parse_daemon (finder: &ParameterFinder) -> bool {
+        let parameter_tag = "--daemon";
+        let usage = "--daemon <on|off> where 'on' runs the Node in the background, with its output in its log";
+        match finder.find_value_for (parameter_tag, usage) {
+            None => false,
+            Some (ref value) if value == "on" => true,
+            Some (ref value) if value == "off" => false,
+            Some (value) => panic! ("Invalid value for --daemon <on|off>: '{}'", value)
+        }
+    }
+}
+
 #[cfg(unix)]
 impl DaemonizerReal {
     fn new () -> DaemonizerReal {
@@ -170,6 +342,17 @@ mod tests {
         limiter: Limiter
     }
 
+    struct StopperMock {
+        name: String
+    }
+
+    impl Stopper for StopperMock {
+        fn stop (&self) -> Result<(), String> {
+            logger::Logger::new (&self.name[..]).log (String::from ("stopped"));
+            Ok (())
+        }
+    }
+
     impl SocketServer for SocketServerMock {
         fn name (&self) -> String {
             self.name.clone ()
@@ -195,6 +378,10 @@ mod tests {
                 logger.log (format! ("{}", request));
             }
         }
+
+        fn stopper (&self) -> Option<Box<Stopper>> {
+            Some (Box::new (StopperMock {name: self.name.clone ()}))
+        }
     }
 
     impl SocketServerMock {
@@ -213,7 +400,7 @@ mod tests {
     }
 
     impl PrivilegeDropper for PrivilegeDropperMock {
-        fn drop_privileges(&self) {
+        fn drop_privileges(&self, _args: &Vec<String>) {
             self.tx.send (String::from ("privileges dropped")).unwrap ();
         }
     }
@@ -223,11 +410,26 @@ mod tests {
     }
 
     impl Daemonizer for DaemonizerMock {
-        fn daemonize(&self) {
+        fn daemonize(&self, _args: &Vec<String>) {
             self.tx.send (String::from ("daemonized")).unwrap ();
         }
     }
 
+    struct SupervisorMock {
+        tx: Sender<String>
+    }
+
+    impl Supervisor for SupervisorMock {
+        fn supervise (&mut self, _args: &Vec<String>) {
+            self.tx.send (String::from ("supervised")).unwrap ();
+        }
+
+        fn restart (&mut self, settings: &Vec<String>) -> Result<(), String> {
+            self.tx.send (format! ("restarted with {:?}", settings)).unwrap ();
+            Ok (())
+        }
+    }
+
     struct LoggerInitializerWrapperMock {
         init_parameters: Arc<Mutex<Vec<Vec<String>>>>
     }
@@ -249,6 +451,26 @@ mod tests {
         }
     }
 
+    struct PidFileWrapperMock {
+        result: Result<(), String>
+    }
+
+    impl PidFileWrapper for PidFileWrapperMock {
+        fn acquire (&mut self, _args: &Vec<String>) -> Result<(), String> {
+            self.result.clone ()
+        }
+
+        fn refresh (&mut self) -> Result<(), String> {
+            Ok (())
+        }
+    }
+
+    impl PidFileWrapperMock {
+        pub fn new () -> PidFileWrapperMock {
+            PidFileWrapperMock {result: Ok (())}
+        }
+    }
+
     #[test]
     fn exits_after_all_socket_servers_exit () {
         let (tx, _rx) = mpsc::channel ();
@@ -262,7 +484,9 @@ mod tests {
             bootstrapper: Some (Box::new (bootstrapper)),
             privilege_dropper,
             daemonizer,
+            supervisor: Box::new (SupervisorMock {tx: tx.clone ()}),
             logger_initializer_wrapper: Box::new (LoggerInitializerWrapperMock::new ()),
+            pid_file_wrapper: Box::new (PidFileWrapperMock::new ()),
             lifetime_secs: 0
         };
 
@@ -298,7 +522,9 @@ mod tests {
             bootstrapper: Some (Box::new (bootstrapper)),
             privilege_dropper,
             daemonizer,
+            supervisor: Box::new (SupervisorMock {tx: tx.clone ()}),
             logger_initializer_wrapper: Box::new (logger_initializer_wrapper),
+            pid_file_wrapper: Box::new (PidFileWrapperMock::new ()),
             lifetime_secs: 0
         };
         let holder = FakeStreamHolder {
@@ -321,8 +547,9 @@ mod tests {
         bootstrapper_tx.send (String::from ("two - second request")).unwrap ();
         handle.join ().unwrap ();
 
-        assert_eq! (rx.recv_timeout(Duration::from_millis(50)).unwrap (), String::from ("privileges dropped"));
         assert_eq! (rx.recv_timeout(Duration::from_millis(50)).unwrap (), String::from ("daemonized"));
+        assert_eq! (rx.recv_timeout(Duration::from_millis(50)).unwrap (), String::from ("supervised"));
+        assert_eq! (rx.recv_timeout(Duration::from_millis(50)).unwrap (), String::from ("privileges dropped"));
         let holder_ref = holder_m.lock ().unwrap ();
         let stdout_string = holder_ref.stdout.get_string ();
         assert_contains (&stdout_string, "first2....second2...");
@@ -331,6 +558,8 @@ mod tests {
         let tlh = TestLogHandler::new ();
         tlh.await_log_containing ("one - second request", 5000);
         tlh.await_log_containing ("two - second request", 5000);
+        tlh.exists_log_containing ("EntryDnsServerMock2: stopped");
+        tlh.exists_log_containing ("BootstrapperMock2: stopped");
         tlh.assert_logs_match_in_order(vec! (
             "EntryDnsServerMock2: initialize_as_root: \\[\"glorp\"\\]",
             "EntryDnsServerMock2: serve_without_root",
@@ -355,10 +584,115 @@ mod tests {
         assert_eq!(logger_init_parameters.lock().unwrap().get(0).unwrap(), &args);
     }
 
+    #[test]
+    fn serve_only_mode_runs_no_dns_socket_server () {
+        let (tx, _rx) = mpsc::channel ();
+        let (dns_socket_server, _dns_tx) = SocketServerMock::make("EntryDnsServerMock3", 1);
+        let (bootstrapper, bootstrapper_tx) = SocketServerMock::make("BootstrapperMock3", 1);
+        let privilege_dropper = PrivilegeDropperMock {tx: tx.clone ()};
+        let daemonizer = DaemonizerMock {tx: tx.clone ()};
+        let args = vec! (String::from ("--mode"), String::from ("serve_only"));
+        let mut subject = ServerInitializer {
+            dns_socket_server: Some (Box::new (dns_socket_server)),
+            bootstrapper: Some (Box::new (bootstrapper)),
+            privilege_dropper,
+            daemonizer,
+            supervisor: Box::new (SupervisorMock {tx: tx.clone ()}),
+            logger_initializer_wrapper: Box::new (LoggerInitializerWrapperMock::new ()),
+            pid_file_wrapper: Box::new (PidFileWrapperMock::new ()),
+            lifetime_secs: 0
+        };
+
+        let handle = thread::spawn (move || {
+            let mut holder = FakeStreamHolder {
+                stdin: ByteArrayReader::new ("first3....".as_bytes ()),
+                stdout: ByteArrayWriter::new (),
+                stderr: ByteArrayWriter::new ()
+            };
+            subject.go(&mut holder.streams(), &args);
+        });
+        bootstrapper_tx.send (String::from ("three - first request")).unwrap ();
+        handle.join ().unwrap ();
+
+        let tlh = TestLogHandler::new ();
+        tlh.await_log_containing ("BootstrapperMock3: three - first request", 5000);
+        tlh.exists_no_log_containing ("EntryDnsServerMock3");
+    }
+
+    #[test]
+    fn a_bad_config_file_is_reported_before_anything_starts () {
+        let (tx, rx) = mpsc::channel ();
+        let (dns_socket_server, _dns_tx) = SocketServerMock::make("EntryDnsServerMock4", 1);
+        let (bootstrapper, _bootstrapper_tx) = SocketServerMock::make("BootstrapperMock4", 1);
+        let privilege_dropper = PrivilegeDropperMock {tx: tx.clone ()};
+        let daemonizer = DaemonizerMock {tx: tx.clone ()};
+        let config = temp_dir ().join ("server_initializer").join ("nonexistent.toml").to_string_lossy ().to_string ();
+        let args = vec! (String::from ("--config"), config);
+        let mut subject = ServerInitializer {
+            dns_socket_server: Some (Box::new (dns_socket_server)),
+            bootstrapper: Some (Box::new (bootstrapper)),
+            privilege_dropper,
+            daemonizer,
+            supervisor: Box::new (SupervisorMock {tx: tx.clone ()}),
+            logger_initializer_wrapper: Box::new (LoggerInitializerWrapperMock::new ()),
+            pid_file_wrapper: Box::new (PidFileWrapperMock::new ()),
+            lifetime_secs: 0
+        };
+        let mut holder = FakeStreamHolder::new ();
+
+        let result = subject.go (&mut holder.streams (), &args);
+
+        assert_eq! (result, 1);
+        assert_contains (&holder.stderr.get_string (), "Couldn't read config file");
+        assert_eq! (rx.try_recv ().is_err (), true);
+    }
+
+    #[test]
+    fn a_node_that_is_already_running_keeps_a_second_one_from_starting () {
+        let (tx, rx) = mpsc::channel ();
+        let (dns_socket_server, _dns_tx) = SocketServerMock::make("EntryDnsServerMock5", 1);
+        let (bootstrapper, _bootstrapper_tx) = SocketServerMock::make("BootstrapperMock5", 1);
+        let privilege_dropper = PrivilegeDropperMock {tx: tx.clone ()};
+        let daemonizer = DaemonizerMock {tx: tx.clone ()};
+        let mut subject = ServerInitializer {
+            dns_socket_server: Some (Box::new (dns_socket_server)),
+            bootstrapper: Some (Box::new (bootstrapper)),
+            privilege_dropper,
+            daemonizer,
+            supervisor: Box::new (SupervisorMock {tx: tx.clone ()}),
+            logger_initializer_wrapper: Box::new (LoggerInitializerWrapperMock::new ()),
+            pid_file_wrapper: Box::new (PidFileWrapperMock {result: Err (String::from ("Another Node is already running as process 1234"))}),
+            lifetime_secs: 0
+        };
+        let mut holder = FakeStreamHolder::new ();
+
+        let result = subject.go (&mut holder.streams (), &vec! ());
+
+        assert_eq! (result, 1);
+        assert_contains (&holder.stderr.get_string (), "Another Node is already running as process 1234");
+        assert_eq! (rx.try_recv ().is_err (), true);
+        TestLogHandler::new ().exists_no_log_containing ("EntryDnsServerMock5");
+    }
+
     fn assert_contains (string: &str, substring: &str) {
         assert_eq! (string.contains (substring), true, "'{}' is not contained in:\n'{}'\n", substring, string);
     }
 
+    #[test]
+    fn the_node_stays_in_the_foreground_unless_told_otherwise () {
+        let finder = |strs: Vec<&str>| ParameterFinder::new (strs.into_iter ().map (String::from).collect ());
+
+        assert_eq! (DaemonizerReal::parse_daemon (&finder (vec! ())), false);
+        assert_eq! (DaemonizerReal::parse_daemon (&finder (vec! ("--daemon", "off"))), false);
+        assert_eq! (DaemonizerReal::parse_daemon (&finder (vec! ("--daemon", "on"))), true);
+    }
+
+    #[test]
+    #[should_panic (expected = "Invalid value for --daemon <on|off>: 'yes'")]
+    fn a_bad_daemon_value_causes_panic () {
+        DaemonizerReal::parse_daemon (&ParameterFinder::new (vec! (String::from ("--daemon"), String::from ("yes"))));
+    }
+
     #[test]
     fn get_log_level_returns_warn_by_default() {
         let args: Vec<String> = vec!();
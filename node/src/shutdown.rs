@@ -0,0 +1,99 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::atomic::ATOMIC_BOOL_INIT;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+#[cfg (unix)]
+use libc;
+
+pub const SHUTDOWN_CHECK_MS: u64 = 100;
+
+static SHUTDOWN_WANTED: AtomicBool = ATOMIC_BOOL_INIT;
+
+lazy_static! {
+    // Set if the Node is to be started again once it's down
+    static ref RESTART_SETTINGS: Mutex<Option<Vec<String>>> = Mutex::new (None);
+}
+
+// Anything that wants the Node to stop says so here; the main thread notices and takes it down in order
+pub fn request_shutdown () {
+    SHUTDOWN_WANTED.store (true, Ordering::SeqCst);
+}
+
+pub fn shutdown_requested () -> bool {
+    SHUTDOWN_WANTED.load (Ordering::SeqCst)
+}
+
+// The settings, as arguments, replace the ones the Node was started with; the rest stay as they were
+pub fn request_restart (settings: Vec<String>) {
+    *RESTART_SETTINGS.lock ().expect ("Restart settings poisoned") = Some (settings);
+    request_shutdown ();
+}
+
+pub fn restart_settings () -> Option<Vec<String>> {
+    RESTART_SETTINGS.lock ().expect ("Restart settings poisoned").clone ()
+}
+
+#[cfg (unix)]
+extern "C" fn on_termination_signal (_signal: libc::c_int) {
+    request_shutdown ();
+}
+
+// As with SIGHUP, the handler only sets a flag; the shutdown itself happens on the main thread
+#[cfg (unix)]
+pub fn shut_down_on_signals () {
+    unsafe {
+        libc::signal (libc::SIGTERM, on_termination_signal as libc::sighandler_t);
+        libc::signal (libc::SIGINT, on_termination_signal as libc::sighandler_t);
+    }
+}
+
+// Windows has no SIGTERM; Ctrl-C still ends the Node, just not in order
+#[cfg (windows)]
+pub fn shut_down_on_signals () {
+}
+
+// Returns true if a shutdown was asked for, false if the lifetime ran out first
+pub fn wait_for_shutdown<F> (lifetime: Duration, requested: F) -> bool where F: Fn () -> bool {
+    let start = Instant::now ();
+    loop {
+        if requested () {return true}
+        let elapsed = start.elapsed ();
+        if elapsed >= lifetime {return false}
+        let check = Duration::from_millis (SHUTDOWN_CHECK_MS);
+        let remaining = lifetime - elapsed;
+        thread::sleep (if remaining < check {remaining} else {check});
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn waiting_ends_when_a_shutdown_is_requested () {
+        let checks = Cell::new (0);
+
+        let result = wait_for_shutdown (Duration::from_secs (0xFFFFFFFF), || {
+            checks.set (checks.get () + 1);
+            checks.get () == 3
+        });
+
+        assert_eq! (result, true);
+        assert_eq! (checks.get (), 3);
+    }
+
+    #[test]
+    fn waiting_ends_when_the_lifetime_runs_out () {
+        let start = Instant::now ();
+
+        let result = wait_for_shutdown (Duration::from_millis (SHUTDOWN_CHECK_MS / 2), || false);
+
+        assert_eq! (result, false);
+        assert_eq! (start.elapsed () < Duration::from_millis (SHUTDOWN_CHECK_MS * 10), true);
+    }
+}
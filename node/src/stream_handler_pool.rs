@@ -1,12 +1,18 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt;
 use std::fmt::Debug;
 use std::fmt::Formatter;
 use std::io;
+use std::io::ErrorKind;
+use std::net::IpAddr;
 use std::net::Shutdown;
 use std::net::SocketAddr;
 use std::string::ToString;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc::Sender;
 use std::thread;
 use std::time::Duration;
 use actix::Actor;
@@ -17,18 +23,33 @@ use actix::Recipient;
 use actix::Syn;
 use discriminator::Discriminator;
 use discriminator::DiscriminatorFactory;
+use json_masquerader::JsonDiscriminatorFactory;
+use json_masquerader::JsonMasquerader;
+use keyed_masquerader::KeyedMasquerader;
+use masquerader::Masquerader;
+use sub_lib::cryptde::CryptDE;
+use sub_lib::cryptde::Key;
 use sub_lib::cryptde::StreamKey;
 use sub_lib::dispatcher;
 use sub_lib::dispatcher::Component;
 use sub_lib::dispatcher::DispatcherSubs;
 use sub_lib::dispatcher::Endpoint;
 use sub_lib::dispatcher::InboundClientData;
+use sub_lib::link_handshake::LinkHandshake;
+use sub_lib::link_handshake::LinkSession;
+use sub_lib::link_handshake::RekeyPolicy;
 use sub_lib::logger::Logger;
+use sub_lib::neighborhood::NodeBannedMsg;
+use sub_lib::neighborhood::NodeUnbannedMsg;
 use sub_lib::node_addr::NodeAddr;
 use sub_lib::stream_handler_pool::TransmitDataMsg;
 use sub_lib::tcp_wrappers::TcpStreamWrapper;
+use sub_lib::tcp_wrappers::TcpStreamWrapperFactory;
+use sub_lib::tcp_wrappers::TcpStreamWrapperFactoryReal;
+use sub_lib::traffic_stats;
 use sub_lib::utils::indicates_dead_stream;
 use sub_lib::utils::indicates_timeout;
+use sub_lib::utils::to_string;
 use sub_lib::utils::NODE_MAILBOX_CAPACITY;
 
 trait StreamReader {
@@ -52,11 +73,38 @@ pub struct RemoveStreamMsg {
     pub socket_addr: SocketAddr
 }
 
+// Sent once the Node at the other end of a clandestine stream has proven which Node it is
+#[derive (Debug, Message)]
+pub struct LinkEstablishedMsg {
+    pub socket_addr: SocketAddr,
+    pub peer_public_key: Key,
+}
+
+// Sent to open a clandestine stream to a Node whose public key is already known from gossip. Nothing
+// goes out on it until the Node at the other end has proven it holds that key.
+#[derive (Debug, Message)]
+pub struct ConnectToPeerMsg {
+    pub peer_public_key: Key,
+    pub socket_addr: SocketAddr,
+}
+
+// Sent when the Node is shutting down. Data already on its way out goes first, since it's ahead in
+// the mailbox; then every stream is closed and no new ones are let in. The sender hears when it's done.
+#[derive (Message)]
+pub struct StopStreamsMsg {
+    pub done: Sender<()>
+}
+
 pub struct StreamHandlerPoolSubs {
     pub add_sub: Recipient<Syn, AddStreamMsg>,
     pub transmit_sub: Recipient<Syn, TransmitDataMsg>,
     pub remove_sub: Recipient<Syn, RemoveStreamMsg>,
     pub bind: Recipient<Syn, PoolBindMessage>,
+    pub node_banned: Recipient<Syn, NodeBannedMsg>,
+    pub node_unbanned: Recipient<Syn, NodeUnbannedMsg>,
+    pub stop_streams: Recipient<Syn, StopStreamsMsg>,
+    pub link_established: Recipient<Syn, LinkEstablishedMsg>,
+    pub connect_to_peer: Recipient<Syn, ConnectToPeerMsg>,
 }
 
 impl Clone for StreamHandlerPoolSubs {
@@ -66,6 +114,11 @@ impl Clone for StreamHandlerPoolSubs {
             transmit_sub: self.transmit_sub.clone (),
             remove_sub: self.remove_sub.clone (),
             bind: self.bind.clone(),
+            node_banned: self.node_banned.clone(),
+            node_unbanned: self.node_unbanned.clone(),
+            stop_streams: self.stop_streams.clone(),
+            link_established: self.link_established.clone(),
+            connect_to_peer: self.connect_to_peer.clone(),
         }
     }
 }
@@ -77,6 +130,7 @@ struct StreamReaderReal {
     ibcd_sub: Recipient<Syn, dispatcher::InboundClientData>,
     remove_sub: Recipient<Syn, RemoveStreamMsg>,
     discriminators: Vec<Box<Discriminator>>,
+    link_opt: Option<ClandestineLink>,
     logger: Logger
 }
 
@@ -93,7 +147,13 @@ impl StreamReader for StreamReaderReal {
                         thread::sleep (Duration::from_millis (100));
                     } else {
                         self.logger.debug (format! ("Read {}-byte chunk from port {}", length, port));
-                        self.wrangle_discriminators(&buf, length)
+                        traffic_stats::count_bytes_in (length);
+                        if let Err (e) = self.wrangle_discriminators(&buf, length) {
+                            self.logger.warning (format! ("Dropping stream on port {}: {}", port, e));
+                            self.remove_sub.try_send (RemoveStreamMsg {socket_addr: self.stream_key}).expect ("StreamHandlerPool is dead");
+                            self.stream.shutdown (Shutdown::Both).ok (); // can't do anything about failure
+                            break;
+                        }
                     }
                 },
                 Err(e) => {
@@ -108,6 +168,7 @@ impl StreamReader for StreamReaderReal {
                         self.ibcd_sub.try_send(InboundClientData {
                             socket_addr: self.stream_key,
                             origin_port: self.origin_port,
+                            peer_public_key_opt: None,
                             component: Component::ProxyServer,
                             last_data: true,
                             data: Vec::new(),
@@ -126,7 +187,8 @@ impl StreamReader for StreamReaderReal {
 
 impl StreamReaderReal {
     fn new (stream: Box<TcpStreamWrapper>, origin_port: Option<u16>, ibcd_sub: Recipient<Syn, dispatcher::InboundClientData>,
-            remove_sub: Recipient<Syn, RemoveStreamMsg>, discriminator_factories: Vec<Box<DiscriminatorFactory>>) -> StreamReaderReal {
+            remove_sub: Recipient<Syn, RemoveStreamMsg>, discriminator_factories: Vec<Box<DiscriminatorFactory>>,
+            link_opt: Option<ClandestineLink>) -> StreamReaderReal {
         let socket_addr = stream.peer_addr ().expect ("Internal error: no peer address creating StreamReaderReal");
         let name = format! ("Dispatcher for {:?}", socket_addr);
         if discriminator_factories.is_empty () {panic! ("Internal error: no Discriminator factories!")}
@@ -138,36 +200,173 @@ impl StreamReaderReal {
             remove_sub,
             // Skinny implementation
             discriminators: vec! (discriminator_factories[0].make ()),
+            link_opt,
             logger: Logger::new (&name)
         }
     }
 
-    fn wrangle_discriminators (&mut self, buf: &[u8], length: usize) {
+    // Fails if the stream is clandestine and the Node at the other end can't prove who it is
+    fn wrangle_discriminators (&mut self, buf: &[u8], length: usize) -> Result<(), String> {
         // Skinny implementation
         if self.discriminators.is_empty () {panic! ("Internal error: no Discriminator factories!")}
-        let discriminator = self.discriminators[0].as_mut ();
         self.logger.debug (format! ("Adding {} bytes to discriminator", length));
-        discriminator.add_data (&buf[..length]);
+        self.discriminators[0].add_data (&buf[..length]);
         loop {
-            match discriminator.take_chunk() {
-                Some(unmasked_chunk) => {
-                    let msg = dispatcher::InboundClientData {
-                        socket_addr: self.stream_key,
-                        origin_port: self.origin_port,
-                        component: unmasked_chunk.component,
-                        last_data: false,
-                        data: unmasked_chunk.chunk.clone ()
-                    };
-                    self.logger.debug (format! ("Discriminator framed and unmasked {} bytes for {}; transmitting to {:?} via Hopper",
-                                                 unmasked_chunk.chunk.len (), msg.socket_addr, unmasked_chunk.component));
-                    self.ibcd_sub.try_send(msg).expect("Dispatcher is dead");
-                }
+            let unmasked_chunk_opt = self.discriminators[0].take_chunk ();
+            let unmasked_chunk = match unmasked_chunk_opt {
+                Some (unmasked_chunk) => unmasked_chunk,
                 None => {
                     self.logger.debug (format!("Discriminator has no more data framed"));
                     break
                 }
-            }
+            };
+            let (data, peer_public_key_opt) = match self.link_opt {
+                Some (ref mut link) => {
+                    let data_opt = link.receive (&unmasked_chunk.chunk[..])?;
+                    // Everything after the handshake is masked with the link's own key
+                    if let Some (masquerader) = link.take_keyed_masquerader () {
+                        self.discriminators[0].replace_masqueraders (vec! (masquerader));
+                    }
+                    match data_opt {
+                        Some (data) => (data, link.peer_public_key_opt.clone ()),
+                        None => continue
+                    }
+                },
+                None => (unmasked_chunk.chunk, None)
+            };
+            let msg = dispatcher::InboundClientData {
+                socket_addr: self.stream_key,
+                origin_port: self.origin_port,
+                peer_public_key_opt,
+                component: unmasked_chunk.component,
+                last_data: false,
+                data
+            };
+            self.logger.debug (format! ("Discriminator framed and unmasked {} bytes for {}; transmitting to {:?} via Hopper",
+                                         msg.data.len (), msg.socket_addr, msg.component));
+            self.ibcd_sub.try_send(msg).expect("Dispatcher is dead");
         }
+        Ok (())
+    }
+}
+
+// What a clandestine stream's StreamReader and StreamWriter share once the handshake is over
+struct EstablishedLink {
+    session: LinkSession,
+    masquerader: KeyedMasquerader,
+}
+
+// The Node's end of a clandestine stream to another Node, whichever of them opened it. Until that Node
+// has proven which Node it is, everything it sends is part of the link handshake and goes no further
+// than here; after that, everything it sends has to decrypt under the session the handshake produced.
+struct ClandestineLink {
+    stream_key: StreamKey,
+    handshake_opt: Option<LinkHandshake<'static>>,
+    established_arc: Arc<Mutex<Option<EstablishedLink>>>,
+    reply_stream: Box<TcpStreamWrapper>,
+    link_established_sub: Recipient<Syn, LinkEstablishedMsg>,
+    masquerader: JsonMasquerader,
+    keyed_masquerader_opt: Option<Box<Masquerader>>,
+    peer_public_key_opt: Option<Key>,
+    rekey_policy: RekeyPolicy,
+}
+
+impl ClandestineLink {
+    // For a stream another Node opened: it speaks first
+    fn responder (cryptde: &'static CryptDE, stream_key: StreamKey, established_arc: Arc<Mutex<Option<EstablishedLink>>>,
+            reply_stream: Box<TcpStreamWrapper>, link_established_sub: Recipient<Syn, LinkEstablishedMsg>,
+            rekey_policy: RekeyPolicy) -> ClandestineLink {
+        ClandestineLink::new (LinkHandshake::responder (cryptde), stream_key, established_arc, reply_stream,
+            link_established_sub, rekey_policy)
+    }
+
+    // For a stream this Node opened to a Node it knows by key: this Node speaks first, and nobody but
+    // the holder of that key will do at the other end
+    fn initiator (cryptde: &'static CryptDE, peer_public_key: Key, stream_key: StreamKey,
+            established_arc: Arc<Mutex<Option<EstablishedLink>>>, reply_stream: Box<TcpStreamWrapper>,
+            link_established_sub: Recipient<Syn, LinkEstablishedMsg>, rekey_policy: RekeyPolicy) -> Result<ClandestineLink, String> {
+        let mut handshake = LinkHandshake::initiator (cryptde, Some (peer_public_key));
+        let first = handshake.start ().map_err (|e| format! ("Link handshake failed: {:?}", e))?;
+        let mut link = ClandestineLink::new (handshake, stream_key, established_arc, reply_stream,
+            link_established_sub, rekey_policy);
+        link.send_handshake_reply (&first[..])?;
+        Ok (link)
+    }
+
+    fn new (handshake: LinkHandshake<'static>, stream_key: StreamKey, established_arc: Arc<Mutex<Option<EstablishedLink>>>,
+            reply_stream: Box<TcpStreamWrapper>, link_established_sub: Recipient<Syn, LinkEstablishedMsg>,
+            rekey_policy: RekeyPolicy) -> ClandestineLink {
+        ClandestineLink {
+            stream_key,
+            handshake_opt: Some (handshake),
+            established_arc,
+            reply_stream,
+            link_established_sub,
+            masquerader: JsonMasquerader::new (),
+            keyed_masquerader_opt: None,
+            peer_public_key_opt: None,
+            rekey_policy,
+        }
+    }
+
+    // Returns the data to pass along, if there is any
+    fn receive (&mut self, data: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        let mut handshake = match self.handshake_opt.take () {
+            Some (handshake) => handshake,
+            None => return self.decrypt (data).map (Some)
+        };
+        let reply_opt = handshake.receive (data).map_err (|e| format! ("Link handshake failed: {:?}", e))?;
+        if let Some (reply) = reply_opt {
+            self.send_handshake_reply (&reply[..])?;
+        }
+        if handshake.is_finished () {
+            let mut session = handshake.session ().map_err (|e| format! ("Link handshake failed: {:?}", e))?;
+            session.set_rekey_policy (self.rekey_policy);
+            let peer_public_key = session.peer_public_key ().clone ();
+            let masquerader = ClandestineLink::make_keyed_masquerader (session.masking_key ())?;
+            self.keyed_masquerader_opt = Some (Box::new (ClandestineLink::make_keyed_masquerader (session.masking_key ())?));
+            *self.established_arc.lock ().expect ("Internal error: link session is poisoned") = Some (EstablishedLink {session, masquerader});
+            self.peer_public_key_opt = Some (peer_public_key.clone ());
+            self.link_established_sub.try_send (LinkEstablishedMsg {socket_addr: self.stream_key, peer_public_key})
+                .expect ("StreamHandlerPool is dead");
+        }
+        else {
+            self.handshake_opt = Some (handshake);
+        }
+        Ok (None)
+    }
+
+    // Once, right after the handshake: what the Discriminator should unmask with from then on
+    fn take_keyed_masquerader (&mut self) -> Option<Box<Masquerader>> {
+        self.keyed_masquerader_opt.take ()
+    }
+
+    fn make_keyed_masquerader (masking_key: &[u8]) -> Result<KeyedMasquerader, String> {
+        KeyedMasquerader::new (Box::new (JsonMasquerader::new ()), masking_key)
+            .map_err (|e| format! ("Link handshake failed: {}", e))
+    }
+
+    // Nothing else can be going out yet: the StreamWriter sends nothing until the handshake is over.
+    // The initiator's first message goes out this way too.
+    fn send_handshake_reply (&mut self, reply: &[u8]) -> Result<(), String> {
+        let masked = self.masquerader.mask (Component::Hopper, reply)
+            .map_err (|e| format! ("Couldn't mask link handshake reply: {}", e))?;
+        match self.reply_stream.write_all (&masked[..]) {
+            Ok (()) => {
+                traffic_stats::count_bytes_out (masked.len ());
+                Ok (())
+            },
+            Err (e) => Err (format! ("Couldn't send link handshake reply: {}", e))
+        }
+    }
+
+    fn decrypt (&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        let mut established_opt = self.established_arc.lock ().expect ("Internal error: link session is poisoned");
+        let result = match *established_opt {
+            Some (ref mut link) => link.session.decrypt (data).map_err (|e| format! ("Link traffic didn't decrypt: {:?}", e)),
+            None => Err (String::from ("Link handshake never finished"))
+        };
+        result
     }
 }
 
@@ -175,13 +374,21 @@ struct StreamWriterReal {
     stream: Box<TcpStreamWrapper>,
     stream_key: StreamKey,
     remove_sub: Recipient<Syn, RemoveStreamMsg>,
+    established_arc_opt: Option<Arc<Mutex<Option<EstablishedLink>>>>,
     logger: Logger
 }
 
 impl StreamWriter for StreamWriterReal {
     fn transmit(&mut self, data: &[u8]) -> io::Result<usize> {
-        match self.stream.write (data) {
-            Ok (size) => Ok (size),
+        let result = match self.seal (data) {
+            Ok (outgoing) => self.stream.write (&outgoing[..]),
+            Err (e) => Err (e)
+        };
+        match result {
+            Ok (size) => {
+                traffic_stats::count_bytes_out (size);
+                Ok (size)
+            },
             Err (e) => {
                 if indicates_dead_stream (e.kind ()) {
                     self.stream.shutdown (Shutdown::Both).ok (); // can't do anything about failure
@@ -199,7 +406,8 @@ impl StreamWriter for StreamWriterReal {
 }
 
 impl StreamWriterReal {
-    fn new (stream: Box<TcpStreamWrapper>, remove_sub: Recipient<Syn, RemoveStreamMsg>) -> StreamWriterReal {
+    fn new (stream: Box<TcpStreamWrapper>, remove_sub: Recipient<Syn, RemoveStreamMsg>,
+            established_arc_opt: Option<Arc<Mutex<Option<EstablishedLink>>>>) -> StreamWriterReal {
         let socket_addr = stream.peer_addr ().expect ("Internal error: no peer address creating StreamWriterReal");
         let name = format! ("Dispatcher for {:?}", socket_addr);
         let logger = Logger::new (&name[..]);
@@ -207,15 +415,40 @@ impl StreamWriterReal {
             stream,
             stream_key: socket_addr,
             remove_sub,
+            established_arc_opt,
             logger
         }
     }
+
+    // Nothing goes out on a clandestine stream until the Node at the other end has proven which Node
+    // it is, and then only encrypted for it
+    fn seal (&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let established_arc = match self.established_arc_opt {
+            Some (ref established_arc) => established_arc,
+            None => return Ok (data.to_vec ())
+        };
+        let mut established_opt = established_arc.lock ().expect ("Internal error: link session is poisoned");
+        let link = match *established_opt {
+            Some (ref mut link) => link,
+            None => return Err (io::Error::new (ErrorKind::NotConnected, "link handshake isn't finished"))
+        };
+        let sealed = link.session.encrypt (data);
+        link.masquerader.mask (Component::Hopper, &sealed[..])
+            .map_err (|e| io::Error::new (ErrorKind::InvalidData, format! ("{}", e)))
+    }
 }
 
 pub struct StreamHandlerPool {
     stream_writers: HashMap<SocketAddr, Box<StreamWriter>>,
+    peer_streams: HashMap<Key, SocketAddr>,
     dispatcher_subs: Option<DispatcherSubs>,
     self_subs: Option<StreamHandlerPoolSubs>,
+    banned_ips: HashSet<IpAddr>,
+    stopped: bool,
+    cryptde: &'static CryptDE,
+    clandestine_ports: Vec<u16>,
+    rekey_policy: RekeyPolicy,
+    tcp_stream_wrapper_factory: Box<TcpStreamWrapperFactory>,
     logger: Logger
 }
 
@@ -225,11 +458,19 @@ impl Actor for StreamHandlerPool {
 
 impl StreamHandlerPool {
 
-    pub fn new() -> StreamHandlerPool {
+    // Streams that come in on the clandestine ports are from other Nodes, which have to prove who they are
+    pub fn new(cryptde: &'static CryptDE, clandestine_ports: Vec<u16>, rekey_policy: RekeyPolicy) -> StreamHandlerPool {
         StreamHandlerPool {
             stream_writers: HashMap::new (),
+            peer_streams: HashMap::new (),
             dispatcher_subs: None,
             self_subs: None,
+            banned_ips: HashSet::new (),
+            stopped: false,
+            cryptde,
+            clandestine_ports,
+            rekey_policy,
+            tcp_stream_wrapper_factory: Box::new (TcpStreamWrapperFactoryReal {}),
             logger: Logger::new ("Dispatcher"),
         }
     }
@@ -240,11 +481,16 @@ impl StreamHandlerPool {
             transmit_sub: pool_addr.clone ().recipient::<TransmitDataMsg>(),
             remove_sub: pool_addr.clone ().recipient::<RemoveStreamMsg>(),
             bind: pool_addr.clone ().recipient::<PoolBindMessage>(),
+            node_banned: pool_addr.clone ().recipient::<NodeBannedMsg>(),
+            node_unbanned: pool_addr.clone ().recipient::<NodeUnbannedMsg>(),
+            stop_streams: pool_addr.clone ().recipient::<StopStreamsMsg>(),
+            link_established: pool_addr.clone ().recipient::<LinkEstablishedMsg>(),
+            connect_to_peer: pool_addr.clone ().recipient::<ConnectToPeerMsg>(),
         }
     }
 
     fn set_up_stream_reader (&mut self, read_stream: Box<TcpStreamWrapper>, origin_port: Option<u16>,
-            discriminator_factories: Vec<Box<DiscriminatorFactory>>) {
+            discriminator_factories: Vec<Box<DiscriminatorFactory>>, link_opt: Option<ClandestineLink>) {
         let ibcd_sub: Recipient<Syn, dispatcher::InboundClientData> =
             self.dispatcher_subs.as_ref().expect("StreamHandlerPool is unbound").ibcd_sub.clone ();
         let remove_sub: Recipient<Syn, RemoveStreamMsg> =
@@ -253,18 +499,35 @@ impl StreamHandlerPool {
             let ibcd_sub = ibcd_sub.clone ();
             let remove_sub = remove_sub.clone();
             let mut stream_reader = StreamReaderReal::new(read_stream, origin_port,
-                ibcd_sub, remove_sub, discriminator_factories);
+                ibcd_sub, remove_sub, discriminator_factories, link_opt);
             stream_reader.handle_traffic();
         });
     }
 
-    fn set_up_stream_writer (&mut self, write_stream: Box<TcpStreamWrapper>) {
-        let socket_addr = write_stream.peer_addr ().expect ("Internal error: no peer address preparing StreamWriter");
+    fn set_up_stream_writer (&mut self, write_stream: Box<TcpStreamWrapper>, socket_addr: SocketAddr,
+            established_arc_opt: Option<Arc<Mutex<Option<EstablishedLink>>>>) {
         let stream_writer = StreamWriterReal::new (
             write_stream,
             self.self_subs.as_ref().expect("StreamHandlerPool is unbound").remove_sub.clone (),
+            established_arc_opt,
         );
         self.stream_writers.insert (socket_addr, Box::new (stream_writer));
+        self.report_active_streams ();
+    }
+
+    fn report_active_streams (&self) {
+        traffic_stats::set_active_streams (self.stream_writers.len ());
+    }
+
+    fn is_clandestine (&self, origin_port: Option<u16>) -> bool {
+        match origin_port {
+            Some (port) => self.clandestine_ports.contains (&port),
+            None => false
+        }
+    }
+
+    fn forget_stream (&mut self, socket_addr: SocketAddr) {
+        self.peer_streams.retain (|_, peer_socket_addr| *peer_socket_addr != socket_addr);
     }
 }
 
@@ -288,8 +551,81 @@ impl Handler<AddStreamMsg> for StreamHandlerPool {
             }
         };
 
-        self.set_up_stream_writer(write_stream);
-        self.set_up_stream_reader(read_stream, msg.origin_port, msg.discriminator_factories);
+        let socket_addr = write_stream.peer_addr ().expect ("Internal error: no peer address preparing StreamWriter");
+        if self.banned_ips.contains (&socket_addr.ip ()) {
+            self.logger.warning (format! ("Refused connection from banned Node at {}", socket_addr));
+            write_stream.shutdown (Shutdown::Both).ok (); // can't do anything about failure
+            return
+        }
+        if self.stopped {
+            self.logger.debug (format! ("Refused connection from {} while shutting down", socket_addr));
+            write_stream.shutdown (Shutdown::Both).ok (); // can't do anything about failure
+            return
+        }
+        let (established_arc_opt, link_opt) = if self.is_clandestine (msg.origin_port) {
+            let reply_stream = match stream_ref.try_clone() {
+                Ok(stream) => stream,
+                Err(e) => {
+                    self.logger.error (format! ("Could not clone reply stream: giving up: {:?}", e));
+                    return
+                }
+            };
+            let established_arc = Arc::new (Mutex::new (None));
+            let link = ClandestineLink::responder (self.cryptde, socket_addr, established_arc.clone (), reply_stream,
+                self.self_subs.as_ref().expect("StreamHandlerPool is unbound").link_established.clone (), self.rekey_policy);
+            (Some (established_arc), Some (link))
+        }
+        else {
+            (None, None)
+        };
+        self.set_up_stream_writer(write_stream, socket_addr, established_arc_opt);
+        self.set_up_stream_reader(read_stream, msg.origin_port, msg.discriminator_factories, link_opt);
+    }
+}
+
+impl Handler<ConnectToPeerMsg> for StreamHandlerPool {
+    type Result = ();
+
+    fn handle(&mut self, msg: ConnectToPeerMsg, _ctx: &mut Self::Context) {
+        let peer_name = to_string (&msg.peer_public_key.data);
+        if self.stopped {
+            self.logger.debug (format! ("Won't connect to Node {} at {} while shutting down", peer_name, msg.socket_addr));
+            return
+        }
+        if self.banned_ips.contains (&msg.socket_addr.ip ()) {
+            self.logger.warning (format! ("Won't connect to banned Node {} at {}", peer_name, msg.socket_addr));
+            return
+        }
+        if self.peer_streams.contains_key (&msg.peer_public_key) {
+            self.logger.debug (format! ("Already linked to Node {}", peer_name));
+            return
+        }
+        let mut stream = self.tcp_stream_wrapper_factory.make ();
+        if let Err (e) = stream.connect (msg.socket_addr) {
+            self.logger.warning (format! ("Couldn't connect to Node {} at {}: {}", peer_name, msg.socket_addr, e));
+            return
+        }
+        let (read_stream, reply_stream) = match (stream.try_clone (), stream.try_clone ()) {
+            (Ok (read_stream), Ok (reply_stream)) => (read_stream, reply_stream),
+            (Err (e), _) | (_, Err (e)) => {
+                self.logger.error (format! ("Could not clone stream to {}: giving up: {:?}", msg.socket_addr, e));
+                stream.shutdown (Shutdown::Both).ok (); // can't do anything about failure
+                return
+            }
+        };
+        let established_arc = Arc::new (Mutex::new (None));
+        let link = match ClandestineLink::initiator (self.cryptde, msg.peer_public_key, msg.socket_addr,
+                established_arc.clone (), reply_stream,
+                self.self_subs.as_ref().expect("StreamHandlerPool is unbound").link_established.clone (), self.rekey_policy) {
+            Ok (link) => link,
+            Err (e) => {
+                self.logger.warning (format! ("Dropping stream to Node {} at {}: {}", peer_name, msg.socket_addr, e));
+                stream.shutdown (Shutdown::Both).ok (); // can't do anything about failure
+                return
+            }
+        };
+        self.set_up_stream_writer (stream, msg.socket_addr, Some (established_arc));
+        self.set_up_stream_reader (read_stream, None, vec! (Box::new (JsonDiscriminatorFactory::new ())), Some (link));
     }
 }
 
@@ -298,6 +634,19 @@ impl Handler<RemoveStreamMsg> for StreamHandlerPool {
 
     fn handle(&mut self, msg: RemoveStreamMsg, _ctx: &mut Self::Context) {
         self.stream_writers.remove (&msg.socket_addr).is_some (); // can't do anything if it fails
+        self.forget_stream (msg.socket_addr);
+        self.report_active_streams ();
+    }
+}
+
+impl Handler<LinkEstablishedMsg> for StreamHandlerPool {
+    type Result = ();
+
+    fn handle(&mut self, msg: LinkEstablishedMsg, _ctx: &mut Self::Context) {
+        // The stream may have been dropped while its handshake was under way
+        if !self.stream_writers.contains_key (&msg.socket_addr) {return}
+        self.logger.debug (format! ("Node {} proved its identity on {}", to_string (&msg.peer_public_key.data), msg.socket_addr));
+        self.peer_streams.insert (msg.peer_public_key, msg.socket_addr);
     }
 }
 
@@ -305,14 +654,23 @@ impl Handler<TransmitDataMsg> for StreamHandlerPool {
     type Result = ();
 
     fn handle(&mut self, msg: TransmitDataMsg, _ctx: &mut Self::Context) {
-        let node_addr = match msg.endpoint {
-            Endpoint::Key (_) => unimplemented!(),
+        let socket_addr = match msg.endpoint {
+            // Only a Node that has proven its identity on a stream can be reached by its key
+            Endpoint::Key (ref key) => match self.peer_streams.get (key) {
+                Some (socket_addr) => *socket_addr,
+                None => {
+                    self.logger.log (format! ("Cannot transmit {} bytes to {}: no link to that Node",
+                        msg.data.len (), to_string (&key.data)));
+                    return
+                }
+            },
             Endpoint::Ip (_) => unimplemented!(),
-            Endpoint::Socket (socket_addr) => NodeAddr::from (&socket_addr)
+            Endpoint::Socket (socket_addr) => {
+                // TODO: Taking just the first address should be eliminated when this moves into the StreamHandlerPool.
+                let mut socket_addrs: Vec<SocketAddr> = NodeAddr::from (&socket_addr).into ();
+                socket_addrs.remove (0)
+            }
         };
-        // TODO: Taking just the first address should be eliminated when this moves into the StreamHandlerPool.
-        let mut socket_addrs: Vec<SocketAddr> = node_addr.into ();
-        let socket_addr = socket_addrs.remove (0);
 
         match self.stream_writers.get_mut (&socket_addr) {
             Some (stream_writer_box) => {
@@ -329,6 +687,56 @@ impl Handler<TransmitDataMsg> for StreamHandlerPool {
     }
 }
 
+impl Handler<NodeBannedMsg> for StreamHandlerPool {
+    type Result = ();
+
+    fn handle(&mut self, msg: NodeBannedMsg, _ctx: &mut Self::Context) {
+        let ip_addr = match msg.ip_addr_opt {
+            Some (ip_addr) => ip_addr,
+            None => return
+        };
+        self.banned_ips.insert (ip_addr);
+        let banned_socket_addrs: Vec<SocketAddr> = self.stream_writers.keys ()
+            .filter (|socket_addr| socket_addr.ip () == ip_addr)
+            .cloned ()
+            .collect ();
+        for socket_addr in banned_socket_addrs {
+            if let Some (mut stream_writer) = self.stream_writers.remove (&socket_addr) {
+                stream_writer.shutdown (Shutdown::Both).ok (); // can't do anything about failure
+                self.logger.warning (format! ("Dropped stream to banned Node at {}", socket_addr));
+            }
+            self.forget_stream (socket_addr);
+        }
+        self.report_active_streams ();
+    }
+}
+
+impl Handler<NodeUnbannedMsg> for StreamHandlerPool {
+    type Result = ();
+
+    fn handle(&mut self, msg: NodeUnbannedMsg, _ctx: &mut Self::Context) {
+        if let Some (ip_addr) = msg.ip_addr_opt {
+            self.banned_ips.remove (&ip_addr);
+        }
+    }
+}
+
+impl Handler<StopStreamsMsg> for StreamHandlerPool {
+    type Result = ();
+
+    fn handle(&mut self, msg: StopStreamsMsg, _ctx: &mut Self::Context) {
+        self.stopped = true;
+        let stream_count = self.stream_writers.len ();
+        for (_, mut stream_writer) in self.stream_writers.drain () {
+            stream_writer.shutdown (Shutdown::Both).ok (); // can't do anything about failure
+        }
+        self.peer_streams.clear ();
+        self.logger.info (format! ("Closed {} streams", stream_count));
+        self.report_active_streams ();
+        msg.done.send (()).ok (); // nobody may be waiting any more
+    }
+}
+
 #[derive (Message)]
 pub struct PoolBindMessage {
     pub dispatcher_subs: DispatcherSubs,
@@ -357,6 +765,9 @@ mod tests {
     use std::cell::RefCell;
     use std::io::Error;
     use std::io::ErrorKind;
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::TcpStream;
     use std::ops::Deref;
     use std::str::FromStr;
     use std::sync::mpsc;
@@ -364,12 +775,20 @@ mod tests {
     use actix::msgs;
     use actix::System;
     use http_request_start_finder::HttpRequestDiscriminatorFactory;
+    use json_framer::JsonFramer;
     use node_test_utils::make_stream_handler_pool_subs_from;
     use node_test_utils::TcpStreamWrapperMock;
     use node_test_utils::TestLogOwner;
     use node_test_utils::wait_until;
+    use sub_lib::cryptde::Key;
+    use sub_lib::cryptde_null::CryptDENull;
     use sub_lib::dispatcher::Component;
     use sub_lib::dispatcher::InboundClientData;
+    use sub_lib::framer::Framer;
+    use sub_lib::link_handshake::DEFAULT_REKEY_POLICY;
+    use sub_lib::tcp_wrappers::TcpListenerWrapper;
+    use sub_lib::tcp_wrappers::TcpListenerWrapperReal;
+    use test_utils::test_utils::cryptde;
     use test_utils::test_utils::init_test_logging;
     use test_utils::test_utils::make_peer_actors;
     use test_utils::test_utils::make_peer_actors_from;
@@ -390,7 +809,7 @@ mod tests {
         let discriminator_factory = HttpRequestDiscriminatorFactory {};
 
         let subject = StreamReaderReal::new (Box::new (stream),
-                                             None, ibcd_sub, remove_sub, vec! (Box::new (discriminator_factory)));
+                                             None, ibcd_sub, remove_sub, vec! (Box::new (discriminator_factory)), None);
 
         assert_eq! (subject.stream_key, SocketAddr::from_str ("12.34.56.78:9101").unwrap ());
     }
@@ -404,7 +823,7 @@ mod tests {
         let remove_addr: Addr<Syn, Recorder> = remove.start ();
         let remove_sub: Recipient<Syn, RemoveStreamMsg> = remove_addr.recipient ();
 
-        let subject = StreamWriterReal::new (Box::new (stream), remove_sub);
+        let subject = StreamWriterReal::new (Box::new (stream), remove_sub, None);
 
         assert_eq! (subject.stream_key, SocketAddr::from_str ("12.34.56.78:9101").unwrap ());
     }
@@ -443,10 +862,10 @@ mod tests {
                 .peer_addr_result (Ok (socket_addr));
             let mut stream = TcpStreamWrapperMock::new();
             stream.try_clone_results = RefCell::new(vec!(Ok(Box::new(read_stream)), Ok(Box::new(write_stream))));
-            let subject = StreamHandlerPool::new();
+            let subject = StreamHandlerPool::new(cryptde (), vec! (), DEFAULT_REKEY_POLICY);
             let subject_addr: Addr<Syn, StreamHandlerPool> = subject.start();
             let subject_subs = StreamHandlerPool::make_subs_from(&subject_addr);
-            let peer_actors = make_peer_actors_from(None, Some(dispatcher), None, None, None);
+            let peer_actors = make_peer_actors_from(None, Some(dispatcher), None, None, None, None);
 
             subject_subs.bind.try_send(PoolBindMessage { dispatcher_subs: peer_actors.dispatcher, stream_handler_pool_subs: subject_subs.clone ()}).unwrap ();
             subject_subs.add_sub.try_send(AddStreamMsg {
@@ -463,6 +882,7 @@ mod tests {
         assert_eq! (dispatcher_recording.get_record::<dispatcher::InboundClientData> (0), &dispatcher::InboundClientData {
             socket_addr,
             origin_port,
+            peer_public_key_opt: None,
             component: Component::ProxyServer,
             last_data: false,
             data: one_http_req_a
@@ -470,6 +890,7 @@ mod tests {
         assert_eq! (dispatcher_recording.get_record::<dispatcher::InboundClientData> (1), &dispatcher::InboundClientData {
             socket_addr,
             origin_port,
+            peer_public_key_opt: None,
             component: Component::ProxyServer,
             last_data: false,
             data: another_http_req_a
@@ -477,6 +898,7 @@ mod tests {
         assert_eq! (dispatcher_recording.get_record::<dispatcher::InboundClientData> (2), &dispatcher::InboundClientData {
             socket_addr,
             origin_port,
+            peer_public_key_opt: None,
             component: Component::ProxyServer,
             last_data: false,
             data: a_third_http_req_a
@@ -484,6 +906,7 @@ mod tests {
         assert_eq! (dispatcher_recording.get_record::<dispatcher::InboundClientData> (3), &dispatcher::InboundClientData {
             socket_addr,
             origin_port,
+            peer_public_key_opt: None,
             component: Component::ProxyServer,
             last_data: true,
             data: Vec::new ()
@@ -517,10 +940,10 @@ mod tests {
         stream.try_clone_results = RefCell::new(vec!(Ok(Box::new(read_stream)), Ok(Box::new(write_stream))));
         thread::spawn (move || {
             let system = System::new("test");
-            let subject = StreamHandlerPool::new();
+            let subject = StreamHandlerPool::new(cryptde (), vec! (), DEFAULT_REKEY_POLICY);
             let subject_addr: Addr<Syn, StreamHandlerPool> = subject.start();
             let subject_subs = StreamHandlerPool::make_subs_from(&subject_addr);
-            let peer_actors = make_peer_actors_from(None, Some(dispatcher), None, None, None);
+            let peer_actors = make_peer_actors_from(None, Some(dispatcher), None, None, None, None);
 
             subject_subs.bind.try_send(PoolBindMessage { dispatcher_subs: peer_actors.dispatcher, stream_handler_pool_subs: subject_subs.clone ()}).unwrap ();
 
@@ -539,6 +962,7 @@ mod tests {
         assert_eq! (recording.get_record::<dispatcher::InboundClientData> (0), &dispatcher::InboundClientData {
             socket_addr,
             origin_port,
+            peer_public_key_opt: None,
             component: Component::ProxyServer,
             last_data: false,
             data: http_req_a
@@ -563,7 +987,7 @@ mod tests {
         let (sub_tx, sub_rx) = mpsc::channel ();
         thread::spawn (move || {
             let system = System::new("test");
-            let subject = StreamHandlerPool::new();
+            let subject = StreamHandlerPool::new(cryptde (), vec! (), DEFAULT_REKEY_POLICY);
             let subject_addr: Addr<Syn, StreamHandlerPool> = subject.start();
             let subject_subs = StreamHandlerPool::make_subs_from(&subject_addr);
             let peer_actors = make_peer_actors();
@@ -610,7 +1034,7 @@ mod tests {
             .peer_addr_result (Ok(socket_addr));
         let mut stream = TcpStreamWrapperMock::new();
         stream.try_clone_results = RefCell::new(vec!(Ok(Box::new(read_stream)), Ok(Box::new(write_stream))));
-        let subject = StreamHandlerPool::new();
+        let subject = StreamHandlerPool::new(cryptde (), vec! (), DEFAULT_REKEY_POLICY);
         let subject_addr: Addr<Syn, StreamHandlerPool> = subject.start();
         let subject_subs = StreamHandlerPool::make_subs_from(&subject_addr);
         let peer_actors = make_peer_actors();
@@ -650,7 +1074,7 @@ mod tests {
             .peer_addr_result (Ok(socket_addr));
         let mut stream = TcpStreamWrapperMock::new();
         stream.try_clone_results = RefCell::new(vec!(Ok(Box::new(read_stream)), Ok(Box::new(write_stream))));
-        let subject = StreamHandlerPool::new();
+        let subject = StreamHandlerPool::new(cryptde (), vec! (), DEFAULT_REKEY_POLICY);
         let subject_addr: Addr<Syn, StreamHandlerPool> = subject.start();
         let subject_subs = StreamHandlerPool::make_subs_from(&subject_addr);
         let peer_actors = make_peer_actors();
@@ -697,7 +1121,7 @@ mod tests {
 
         thread::spawn (move || {
             let system = System::new("test");
-            let subject = StreamHandlerPool::new();
+            let subject = StreamHandlerPool::new(cryptde (), vec! (), DEFAULT_REKEY_POLICY);
             let subject_addr: Addr<Syn, StreamHandlerPool> = subject.start();
             let subject_subs = StreamHandlerPool::make_subs_from(&subject_addr);
             let peer_actors = make_peer_actors();
@@ -734,13 +1158,135 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn banning_a_node_drops_its_streams_and_refuses_new_ones () {
+        init_test_logging();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5681").unwrap();
+        let later_socket_addr = SocketAddr::from_str("1.2.3.4:5682").unwrap();
+        let mut write_stream = TcpStreamWrapperMock::new()
+            .peer_addr_result (Ok (socket_addr));
+        write_stream.shutdown_results = RefCell::new (vec! (Ok (())));
+        let write_stream_log_arc = write_stream.get_test_log ();
+        let mut stream = TcpStreamWrapperMock::new();
+        stream.try_clone_results = RefCell::new(vec!(
+            Ok(Box::new(TcpStreamWrapperMock::new().peer_addr_result (Ok(socket_addr)))),
+            Ok(Box::new(write_stream))
+        ));
+        let mut later_write_stream = TcpStreamWrapperMock::new()
+            .peer_addr_result (Ok (later_socket_addr));
+        later_write_stream.shutdown_results = RefCell::new (vec! (Ok (())));
+        let later_write_stream_log_arc = later_write_stream.get_test_log ();
+        let mut later_stream = TcpStreamWrapperMock::new();
+        later_stream.try_clone_results = RefCell::new(vec!(
+            Ok(Box::new(TcpStreamWrapperMock::new().peer_addr_result (Ok(later_socket_addr)))),
+            Ok(Box::new(later_write_stream))
+        ));
+        let system = System::new("test");
+        let subject = StreamHandlerPool::new(cryptde (), vec! (), DEFAULT_REKEY_POLICY);
+        let subject_addr: Addr<Syn, StreamHandlerPool> = subject.start();
+        let subject_subs = StreamHandlerPool::make_subs_from(&subject_addr);
+        let peer_actors = make_peer_actors();
+        subject_subs.bind.try_send(PoolBindMessage { dispatcher_subs: peer_actors.dispatcher, stream_handler_pool_subs: subject_subs.clone ()}).unwrap ();
+        subject_subs.add_sub.try_send(AddStreamMsg {
+            stream: Box::new(stream),
+            origin_port: None,
+            discriminator_factories: vec! ()
+        }).unwrap ();
+
+        subject_subs.node_banned.try_send(NodeBannedMsg {
+            public_key: Key::new (b"banned"),
+            ip_addr_opt: Some (socket_addr.ip ()),
+        }).unwrap ();
+        subject_subs.add_sub.try_send(AddStreamMsg {
+            stream: Box::new(later_stream),
+            origin_port: None,
+            discriminator_factories: vec! ()
+        }).unwrap ();
+        subject_subs.transmit_sub.try_send(TransmitDataMsg {
+            endpoint: Endpoint::Socket(socket_addr),
+            last_data: false,
+            data: vec!(0x12, 0x34)
+        }).unwrap ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        assert_eq! (write_stream_log_arc.lock ().unwrap ().dump (), vec! ("shutdown (Both)"));
+        assert_eq! (later_write_stream_log_arc.lock ().unwrap ().dump (), vec! ("shutdown (Both)"));
+        let tlh = TestLogHandler::new ();
+        tlh.exists_log_containing ("Dropped stream to banned Node at 1.2.3.4:5681");
+        tlh.exists_log_containing ("Refused connection from banned Node at 1.2.3.4:5682");
+        tlh.exists_log_containing ("Cannot transmit 2 bytes to V4(1.2.3.4:5681): nonexistent stream");
+    }
+
+    #[test]
+    fn stopping_sends_what_was_already_queued_then_closes_every_stream_and_refuses_new_ones () {
+        init_test_logging();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5683").unwrap();
+        let later_socket_addr = SocketAddr::from_str("1.2.3.4:5684").unwrap();
+        let mut write_stream = TcpStreamWrapperMock::new()
+            .peer_addr_result (Ok (socket_addr));
+        write_stream.write_results = vec! (Ok (2));
+        write_stream.shutdown_results = RefCell::new (vec! (Ok (())));
+        let write_stream_params_arc = write_stream.write_params.clone ();
+        let write_stream_log_arc = write_stream.get_test_log ();
+        let mut stream = TcpStreamWrapperMock::new();
+        stream.try_clone_results = RefCell::new(vec!(
+            Ok(Box::new(TcpStreamWrapperMock::new().peer_addr_result (Ok(socket_addr)))),
+            Ok(Box::new(write_stream))
+        ));
+        let mut later_write_stream = TcpStreamWrapperMock::new()
+            .peer_addr_result (Ok (later_socket_addr));
+        later_write_stream.shutdown_results = RefCell::new (vec! (Ok (())));
+        let later_write_stream_log_arc = later_write_stream.get_test_log ();
+        let mut later_stream = TcpStreamWrapperMock::new();
+        later_stream.try_clone_results = RefCell::new(vec!(
+            Ok(Box::new(TcpStreamWrapperMock::new().peer_addr_result (Ok(later_socket_addr)))),
+            Ok(Box::new(later_write_stream))
+        ));
+        let (done_tx, done_rx) = mpsc::channel ();
+        let system = System::new("test");
+        let subject = StreamHandlerPool::new(cryptde (), vec! (), DEFAULT_REKEY_POLICY);
+        let subject_addr: Addr<Syn, StreamHandlerPool> = subject.start();
+        let subject_subs = StreamHandlerPool::make_subs_from(&subject_addr);
+        let peer_actors = make_peer_actors();
+        subject_subs.bind.try_send(PoolBindMessage { dispatcher_subs: peer_actors.dispatcher, stream_handler_pool_subs: subject_subs.clone ()}).unwrap ();
+        subject_subs.add_sub.try_send(AddStreamMsg {
+            stream: Box::new(stream),
+            origin_port: None,
+            discriminator_factories: vec! ()
+        }).unwrap ();
+        subject_subs.transmit_sub.try_send(TransmitDataMsg {
+            endpoint: Endpoint::Socket(socket_addr),
+            last_data: false,
+            data: vec!(0x12, 0x34)
+        }).unwrap ();
+
+        subject_subs.stop_streams.try_send(StopStreamsMsg {done: done_tx}).unwrap ();
+        subject_subs.add_sub.try_send(AddStreamMsg {
+            stream: Box::new(later_stream),
+            origin_port: None,
+            discriminator_factories: vec! ()
+        }).unwrap ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        assert_eq! (done_rx.try_recv (), Ok (()));
+        assert_eq! (write_stream_params_arc.lock ().unwrap ().deref (), &vec! (vec! (0x12, 0x34)));
+        let write_stream_log = write_stream_log_arc.lock ().unwrap ();
+        assert_eq! (write_stream_log.dump ().contains (&String::from ("shutdown (Both)")), true, "{:?}", write_stream_log.dump ());
+        assert_eq! (later_write_stream_log_arc.lock ().unwrap ().dump (), vec! ("shutdown (Both)"));
+        let tlh = TestLogHandler::new ();
+        tlh.exists_log_containing ("Closed 1 streams");
+        tlh.exists_log_containing ("Refused connection from 1.2.3.4:5684 while shutting down");
+    }
+
     #[test]
     fn transmitting_on_an_unknown_socket_addr_produces_an_error_log () {
         init_test_logging();
         thread::spawn (move || {
             let system = System::new("test");
             let socket_addr = SocketAddr::from_str("1.2.3.4:5677").unwrap();
-            let subject = StreamHandlerPool::new();
+            let subject = StreamHandlerPool::new(cryptde (), vec! (), DEFAULT_REKEY_POLICY);
             let subject_addr: Addr<Syn, StreamHandlerPool> = subject.start();
             let subject_subs = StreamHandlerPool::make_subs_from(&subject_addr);
             let peer_actors = make_peer_actors();
@@ -761,6 +1307,284 @@ mod tests {
         TestLogHandler::new ().await_log_containing("ERROR: Dispatcher: Cannot transmit 2 bytes to V4(1.2.3.4:5677): nonexistent stream", 5000);
     }
 
+    // Another Node's end of a clandestine stream to a pool that takes the stream's port as clandestine
+    struct PeerEnd {
+        socket: TcpStream,
+        framer: JsonFramer,
+        masquerader: Box<Masquerader>,
+        port: u16,
+    }
+
+    impl PeerEnd {
+        fn connect (dispatcher: Recorder, rekey_policy: RekeyPolicy) -> (PeerEnd, StreamHandlerPoolSubs) {
+            let listener = listen ();
+            let local_addr = listener.local_addr ().unwrap ();
+            let subject_subs = start_pool (cryptde (), vec! (local_addr.port ()), dispatcher, rekey_policy);
+            let socket = TcpStream::connect (local_addr).unwrap ();
+            socket.set_read_timeout (Some (Duration::from_millis (5000))).unwrap ();
+            let (stream, _) = listener.accept ().unwrap ();
+            subject_subs.add_sub.try_send (AddStreamMsg {
+                stream,
+                origin_port: Some (local_addr.port ()),
+                discriminator_factories: vec! (Box::new (JsonDiscriminatorFactory::new ()))
+            }).unwrap ();
+            (PeerEnd {socket, framer: JsonFramer::new (), masquerader: Box::new (JsonMasquerader::new ()), port: local_addr.port ()}, subject_subs)
+        }
+
+        fn key (&mut self, masking_key: &[u8]) {
+            self.masquerader = Box::new (KeyedMasquerader::new (Box::new (JsonMasquerader::new ()), masking_key).unwrap ());
+        }
+
+        fn send (&mut self, data: &[u8]) {
+            let masked = self.masquerader.mask (Component::Hopper, data).unwrap ();
+            self.socket.write_all (&masked[..]).unwrap ();
+        }
+
+        // None once the pool has closed the stream
+        fn receive (&mut self) -> Option<Vec<u8>> {
+            loop {
+                if let Some (frame) = self.framer.take_frame () {
+                    return Some (self.masquerader.try_unmask (&frame.chunk[..]).unwrap ().chunk)
+                }
+                let mut buf = [0u8; 0x10000];
+                let length = self.socket.read (&mut buf).unwrap ();
+                if length == 0 {return None}
+                self.framer.add_data (&buf[..length]);
+            }
+        }
+    }
+
+    fn listen () -> TcpListenerWrapperReal {
+        let mut listener = TcpListenerWrapperReal::new ();
+        listener.bind (SocketAddr::from_str ("127.0.0.1:0").unwrap ()).unwrap ();
+        listener
+    }
+
+    fn start_pool (cryptde: &'static CryptDE, clandestine_ports: Vec<u16>, dispatcher: Recorder, rekey_policy: RekeyPolicy) -> StreamHandlerPoolSubs {
+        let (sub_tx, sub_rx) = mpsc::channel ();
+        thread::spawn (move || {
+            let system = System::new("test");
+            let subject = StreamHandlerPool::new(cryptde, clandestine_ports, rekey_policy);
+            let subject_addr: Addr<Syn, StreamHandlerPool> = subject.start();
+            let subject_subs = StreamHandlerPool::make_subs_from(&subject_addr);
+            let peer_actors = make_peer_actors_from(None, Some(dispatcher), None, None, None, None);
+            subject_subs.bind.try_send(PoolBindMessage { dispatcher_subs: peer_actors.dispatcher, stream_handler_pool_subs: subject_subs.clone ()}).unwrap ();
+            sub_tx.send (subject_subs).unwrap ();
+            system.run ();
+        });
+        sub_rx.recv ().unwrap ()
+    }
+
+    // The far end of an outbound link accepts it the way its ListenerHandler would
+    fn accept_on (listener: &TcpListenerWrapperReal, pool_subs: &StreamHandlerPoolSubs) {
+        let (stream, _) = listener.accept ().unwrap ();
+        pool_subs.add_sub.try_send (AddStreamMsg {
+            stream,
+            origin_port: Some (listener.local_addr ().unwrap ().port ()),
+            discriminator_factories: vec! (Box::new (JsonDiscriminatorFactory::new ()))
+        }).unwrap ();
+    }
+
+    fn make_peer_cryptde () -> CryptDENull {
+        let mut peer_cryptde = CryptDENull::new ();
+        peer_cryptde.generate_key_pair ();
+        peer_cryptde
+    }
+
+    #[test]
+    fn a_node_that_proves_its_identity_on_a_clandestine_stream_is_heard_and_reached_by_its_key () {
+        let dispatcher = Recorder::new ();
+        let dispatcher_recording_arc = dispatcher.get_recording ();
+        let awaiter = dispatcher.get_awaiter ();
+        let peer_cryptde = make_peer_cryptde ();
+        let (mut peer_end, subject_subs) = PeerEnd::connect (dispatcher, DEFAULT_REKEY_POLICY);
+        let mut handshake = LinkHandshake::initiator (&peer_cryptde, Some (cryptde ().public_key ()));
+
+        let first = handshake.start ().unwrap ();
+        peer_end.send (&first[..]);
+        let second = peer_end.receive ().unwrap ();
+        let third = handshake.receive (&second[..]).unwrap ().unwrap ();
+        peer_end.send (&third[..]);
+        let mut session = handshake.session ().unwrap ();
+        peer_end.key (session.masking_key ());
+        let sealed = session.encrypt (b"from the peer");
+        peer_end.send (&sealed[..]);
+
+        awaiter.await_message_count (1);
+        let record = dispatcher_recording_arc.lock ().unwrap ().get_record::<InboundClientData> (0).clone ();
+        assert_eq! (record.data, b"from the peer".to_vec ());
+        assert_eq! (record.component, Component::Hopper);
+        assert_eq! (record.origin_port, Some (peer_end.port));
+        subject_subs.transmit_sub.try_send (TransmitDataMsg {
+            endpoint: Endpoint::Key (peer_cryptde.public_key ()),
+            last_data: false,
+            data: b"to the peer".to_vec ()
+        }).unwrap ();
+        let reply = peer_end.receive ().unwrap ();
+        assert_ne! (reply, b"to the peer".to_vec ());
+        assert_eq! (session.decrypt (&reply[..]), Ok (b"to the peer".to_vec ()));
+    }
+
+    #[test]
+    fn links_re_key_on_the_limits_the_pool_is_given () {
+        let dispatcher = Recorder::new ();
+        let awaiter = dispatcher.get_awaiter ();
+        let peer_cryptde = make_peer_cryptde ();
+        let (mut peer_end, subject_subs) = PeerEnd::connect (dispatcher, RekeyPolicy {max_bytes: 10, max_interval_ms: 0});
+        let mut handshake = LinkHandshake::initiator (&peer_cryptde, Some (cryptde ().public_key ()));
+        let first = handshake.start ().unwrap ();
+        peer_end.send (&first[..]);
+        let second = peer_end.receive ().unwrap ();
+        let third = handshake.receive (&second[..]).unwrap ().unwrap ();
+        peer_end.send (&third[..]);
+        let mut session = handshake.session ().unwrap ();
+        peer_end.key (session.masking_key ());
+        let sealed = session.encrypt (b"hello");
+        peer_end.send (&sealed[..]);
+        awaiter.await_message_count (1);
+
+        for data in vec! (b"first ten!".to_vec (), b"second ten".to_vec (), b"third ten!".to_vec ()) {
+            subject_subs.transmit_sub.try_send (TransmitDataMsg {
+                endpoint: Endpoint::Key (peer_cryptde.public_key ()),
+                last_data: false,
+                data: data.clone (),
+            }).unwrap ();
+            let reply = peer_end.receive ().unwrap ();
+            assert_eq! (session.decrypt (&reply[..]), Ok (data));
+        }
+
+        assert_eq! (session.key_generations (), (0, 3));
+    }
+
+    #[test]
+    fn a_node_that_connects_to_a_peer_it_knows_by_key_establishes_a_link_both_can_use () {
+        init_test_logging ();
+        let near_dispatcher = Recorder::new ();
+        let near_recording_arc = near_dispatcher.get_recording ();
+        let near_awaiter = near_dispatcher.get_awaiter ();
+        let far_dispatcher = Recorder::new ();
+        let far_recording_arc = far_dispatcher.get_recording ();
+        let far_awaiter = far_dispatcher.get_awaiter ();
+        let far_cryptde: &'static CryptDENull = Box::leak (Box::new (make_peer_cryptde ()));
+        let listener = listen ();
+        let far_addr = listener.local_addr ().unwrap ();
+        let near_subs = start_pool (cryptde (), vec! (), near_dispatcher, DEFAULT_REKEY_POLICY);
+        let far_subs = start_pool (far_cryptde, vec! (far_addr.port ()), far_dispatcher, DEFAULT_REKEY_POLICY);
+
+        near_subs.connect_to_peer.try_send (ConnectToPeerMsg {peer_public_key: far_cryptde.public_key (), socket_addr: far_addr}).unwrap ();
+        accept_on (&listener, &far_subs);
+
+        TestLogHandler::new ().await_log_containing (&format! ("Node {} proved its identity on {}",
+            to_string (&far_cryptde.public_key ().data), far_addr), 5000);
+        near_subs.transmit_sub.try_send (TransmitDataMsg {
+            endpoint: Endpoint::Key (far_cryptde.public_key ()),
+            last_data: false,
+            data: b"from the near end".to_vec ()
+        }).unwrap ();
+        far_awaiter.await_message_count (1);
+        let far_record = far_recording_arc.lock ().unwrap ().get_record::<InboundClientData> (0).clone ();
+        assert_eq! (far_record.data, b"from the near end".to_vec ());
+        assert_eq! (far_record.component, Component::Hopper);
+        assert_eq! (far_record.peer_public_key_opt, Some (cryptde ().public_key ()));
+        far_subs.transmit_sub.try_send (TransmitDataMsg {
+            endpoint: Endpoint::Key (cryptde ().public_key ()),
+            last_data: false,
+            data: b"from the far end".to_vec ()
+        }).unwrap ();
+        near_awaiter.await_message_count (1);
+        let near_record = near_recording_arc.lock ().unwrap ().get_record::<InboundClientData> (0).clone ();
+        assert_eq! (near_record.data, b"from the far end".to_vec ());
+        assert_eq! (near_record.socket_addr, far_addr);
+        assert_eq! (near_record.peer_public_key_opt, Some (far_cryptde.public_key ()));
+    }
+
+    #[test]
+    fn a_node_that_connects_to_a_peer_it_knows_by_key_drops_the_stream_if_some_other_node_answers () {
+        init_test_logging ();
+        let near_dispatcher = Recorder::new ();
+        let near_recording_arc = near_dispatcher.get_recording ();
+        let far_dispatcher = Recorder::new ();
+        let far_cryptde: &'static CryptDENull = Box::leak (Box::new (make_peer_cryptde ()));
+        let expected_cryptde = make_peer_cryptde ();
+        let listener = listen ();
+        let far_addr = listener.local_addr ().unwrap ();
+        let near_subs = start_pool (cryptde (), vec! (), near_dispatcher, DEFAULT_REKEY_POLICY);
+        let far_subs = start_pool (far_cryptde, vec! (far_addr.port ()), far_dispatcher, DEFAULT_REKEY_POLICY);
+
+        near_subs.connect_to_peer.try_send (ConnectToPeerMsg {peer_public_key: expected_cryptde.public_key (), socket_addr: far_addr}).unwrap ();
+        accept_on (&listener, &far_subs);
+
+        TestLogHandler::new ().await_log_containing ("Link handshake failed: UnexpectedPeer", 5000);
+        near_subs.transmit_sub.try_send (TransmitDataMsg {
+            endpoint: Endpoint::Key (far_cryptde.public_key ()),
+            last_data: false,
+            data: b"for the wrong Node".to_vec ()
+        }).unwrap ();
+        TestLogHandler::new ().await_log_containing (&format! ("Cannot transmit 18 bytes to {}: no link to that Node",
+            to_string (&far_cryptde.public_key ().data)), 5000);
+        assert_eq! (near_recording_arc.lock ().unwrap ().len (), 0);
+    }
+
+    #[test]
+    fn a_clandestine_stream_whose_peer_cannot_prove_its_identity_is_dropped_before_anything_reaches_the_dispatcher () {
+        init_test_logging ();
+        let dispatcher = Recorder::new ();
+        let dispatcher_recording_arc = dispatcher.get_recording ();
+        let (mut peer_end, _subject_subs) = PeerEnd::connect (dispatcher, DEFAULT_REKEY_POLICY);
+
+        peer_end.send (b"Trust me, I'm a Node");
+
+        assert_eq! (peer_end.receive (), None);
+        TestLogHandler::new ().await_log_containing (&format! ("Dropping stream on port {}: Link handshake failed: Malformed", peer_end.port), 5000);
+        assert_eq! (dispatcher_recording_arc.lock ().unwrap ().len (), 0);
+    }
+
+    #[test]
+    fn nothing_goes_out_on_a_clandestine_stream_before_its_handshake_is_over () {
+        init_test_logging();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5685").unwrap();
+        let mut read_stream = TcpStreamWrapperMock::new()
+            .peer_addr_result (Ok(socket_addr));
+        read_stream.set_read_timeout_results = RefCell::new (vec! (Ok (())));
+        read_stream.read_results = vec! ((Vec::from ("block".as_bytes ()), Ok(5)));
+        let write_stream = TcpStreamWrapperMock::new()
+            .peer_addr_result (Ok (socket_addr));
+        let write_stream_params_arc = write_stream.write_params.clone ();
+        let reply_stream = TcpStreamWrapperMock::new()
+            .peer_addr_result (Ok (socket_addr));
+        let mut stream = TcpStreamWrapperMock::new();
+        stream.try_clone_results = RefCell::new(vec!(Ok(Box::new(read_stream)), Ok(Box::new(write_stream)), Ok(Box::new(reply_stream))));
+        let system = System::new("test");
+        let subject = StreamHandlerPool::new(cryptde (), vec! (4567), DEFAULT_REKEY_POLICY);
+        let subject_addr: Addr<Syn, StreamHandlerPool> = subject.start();
+        let subject_subs = StreamHandlerPool::make_subs_from(&subject_addr);
+        let peer_actors = make_peer_actors();
+        subject_subs.bind.try_send(PoolBindMessage { dispatcher_subs: peer_actors.dispatcher, stream_handler_pool_subs: subject_subs.clone ()}).unwrap ();
+        subject_subs.add_sub.try_send(AddStreamMsg {
+            stream: Box::new(stream),
+            origin_port: Some (4567),
+            discriminator_factories: vec! (Box::new (JsonDiscriminatorFactory::new ()))
+        }).unwrap ();
+
+        subject_subs.transmit_sub.try_send(TransmitDataMsg {
+            endpoint: Endpoint::Socket(socket_addr),
+            last_data: false,
+            data: vec!(0x12, 0x34)
+        }).unwrap ();
+        subject_subs.transmit_sub.try_send(TransmitDataMsg {
+            endpoint: Endpoint::Key(Key::new (b"stranger")),
+            last_data: false,
+            data: vec!(0x56, 0x78)
+        }).unwrap ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run ();
+        assert_eq! (write_stream_params_arc.lock ().unwrap ().is_empty (), true);
+        let tlh = TestLogHandler::new ();
+        tlh.exists_log_containing ("ERROR: Dispatcher for V4(1.2.3.4:5685): Cannot transmit 2 bytes: link handshake isn't finished");
+        tlh.exists_log_containing ("Cannot transmit 2 bytes to stranger: no link to that Node");
+    }
+
     #[test]
     fn indicates_dead_stream_identifies_dead_stream_errors () {
         vec! (ErrorKind::BrokenPipe, ErrorKind::ConnectionRefused, ErrorKind::ConnectionReset,
@@ -0,0 +1,243 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::env;
+#[cfg (unix)]
+use std::ffi::CString;
+#[cfg (unix)]
+use std::fs::File;
+#[cfg (unix)]
+use std::io;
+#[cfg (unix)]
+use std::io::Read;
+#[cfg (unix)]
+use std::io::Write;
+#[cfg (unix)]
+use std::os::unix::ffi::OsStrExt;
+#[cfg (unix)]
+use std::os::unix::io::FromRawFd;
+#[cfg (unix)]
+use std::ptr;
+#[cfg (unix)]
+use std::sync::atomic::AtomicUsize;
+#[cfg (unix)]
+use std::sync::atomic::Ordering;
+#[cfg (unix)]
+use std::sync::atomic::ATOMIC_USIZE_INIT;
+#[cfg (windows)]
+use std::process::Command;
+#[cfg (unix)]
+use libc;
+use config_file;
+
+// A Node started as root can't get root back once it has dropped its privileges, so it can't start
+// itself over. Instead, the process it forks itself off from stays behind with root and does
+// nothing but wait; when the Node exits after handing it new settings, the supervisor runs the
+// program again with them. The supervisor keeps the command line it was started with, and the Node,
+// which by then runs as somebody else, can only change the settings a restart is allowed to change.
+pub trait Supervisor: Send {
+    // Only the Node comes back; args is the command line as given, program name first
+    fn supervise (&mut self, args: &Vec<String>);
+    // Once the Node has shut down; settings are tag and value pairs
+    fn restart (&mut self, settings: &Vec<String>) -> Result<(), String>;
+}
+
+#[cfg (unix)]
+pub struct SupervisorReal {
+    to_supervisor_opt: Option<File>,
+}
+
+#[cfg (windows)]
+pub struct SupervisorReal {
+    args: Vec<String>,
+}
+
+// Starts every message from the Node, so that a restart with no new settings isn't mistaken for none
+#[cfg (any (unix, test))]
+const RESTART_HEADER: &str = "restart";
+
+#[cfg (unix)]
+static NODE_PID: AtomicUsize = ATOMIC_USIZE_INIT;
+
+#[cfg (unix)]
+extern "C" fn pass_on_signal (signal: libc::c_int) {
+    let node_pid = NODE_PID.load (Ordering::SeqCst);
+    if node_pid != 0 {
+        unsafe {libc::kill (node_pid as libc::pid_t, signal);}
+    }
+}
+
+#[cfg (unix)]
+impl Supervisor for SupervisorReal {
+    // Not unit tested
+    fn supervise (&mut self, args: &Vec<String>) {
+        let mut fds: [libc::c_int; 2] = [0, 0];
+        if unsafe {libc::pipe (fds.as_mut_ptr ())} != 0 {
+            panic! ("Couldn't make a pipe to the supervisor: {}", io::Error::last_os_error ())
+        }
+        // Nothing the Node runs should be holding the pipe open after the Node has gone
+        unsafe {
+            libc::fcntl (fds[0], libc::F_SETFD, libc::FD_CLOEXEC);
+            libc::fcntl (fds[1], libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+        let (from_node, to_supervisor) = unsafe {(File::from_raw_fd (fds[0]), File::from_raw_fd (fds[1]))};
+        // Anything still buffered would come out of both processes
+        let _ = io::stdout ().flush ();
+        let _ = io::stderr ().flush ();
+        match unsafe {libc::fork ()} {
+            -1 => panic! ("Couldn't fork: {}", io::Error::last_os_error ()),
+            0 => {
+                drop (from_node);
+                self.to_supervisor_opt = Some (to_supervisor);
+            },
+            node_pid => {
+                drop (to_supervisor);
+                SupervisorReal::wait_for (node_pid, from_node, args)
+            }
+        }
+    }
+
+    fn restart (&mut self, settings: &Vec<String>) -> Result<(), String> {
+        config_file::check_restartable (settings)?;
+        match self.to_supervisor_opt.take () {
+            Some (mut to_supervisor) => to_supervisor.write_all (&encode_settings (settings))
+                .map_err (|e| format! ("Couldn't hand the new settings to the supervisor: {}", e)),
+            None => Err (String::from ("Nothing is supervising the Node"))
+        }
+    }
+}
+
+#[cfg (unix)]
+impl SupervisorReal {
+    pub fn new () -> SupervisorReal {
+        SupervisorReal {to_supervisor_opt: None}
+    }
+
+    // A Ctrl-C or a SIGHUP reaches the Node by itself; a SIGTERM meant for the Node may have gone
+    // to the supervisor instead. The supervisor leaves with _exit (), since anything it might clean
+    // up, like the PID file, belongs to the Node. What the Node sends is checked again here, since
+    // the Node no longer has root and can't be trusted with it.
+    fn wait_for (node_pid: libc::pid_t, mut from_node: File, args: &Vec<String>) -> ! {
+        NODE_PID.store (node_pid as usize, Ordering::SeqCst);
+        unsafe {
+            libc::signal (libc::SIGINT, libc::SIG_IGN);
+            libc::signal (libc::SIGHUP, libc::SIG_IGN);
+            libc::signal (libc::SIGTERM, pass_on_signal as libc::sighandler_t);
+        }
+        // Ends when the Node does
+        let mut message = vec! ();
+        let _ = from_node.read_to_end (&mut message);
+        let mut status: libc::c_int = 0;
+        while (unsafe {libc::waitpid (node_pid, &mut status, 0)} < 0) && (io::Error::last_os_error ().kind () == io::ErrorKind::Interrupted) {}
+        if let Some (settings) = decode_settings (&message) {
+            match config_file::check_restartable (&settings) {
+                Ok (()) => SupervisorReal::run_again (&config_file::replace_args (args, &settings)),
+                Err (e) => {let _ = writeln! (io::stderr (), "Couldn't restart the Node: {}", e);}
+            }
+        }
+        let exit_code = if unsafe {libc::WIFEXITED (status)} {unsafe {libc::WEXITSTATUS (status)}} else {1};
+        unsafe {libc::_exit (exit_code)}
+    }
+
+    fn run_again (args: &Vec<String>) -> ! {
+        let program = match env::current_exe () {
+            Ok (program) => CString::new (program.as_os_str ().as_bytes ()).expect ("Program path contains NUL"),
+            Err (e) => {
+                let _ = writeln! (io::stderr (), "Couldn't restart the Node: couldn't find its program: {}", e);
+                unsafe {libc::_exit (1)}
+            }
+        };
+        let c_args: Vec<CString> = args.iter ().map (|arg| CString::new (arg.as_bytes ()).expect ("Argument contains NUL")).collect ();
+        let mut argv: Vec<*const libc::c_char> = c_args.iter ().map (|arg| arg.as_ptr ()).collect ();
+        argv.push (ptr::null ());
+        unsafe {
+            libc::signal (libc::SIGINT, libc::SIG_DFL);
+            libc::signal (libc::SIGHUP, libc::SIG_DFL);
+            libc::execv (program.as_ptr (), argv.as_ptr ());
+        }
+        let _ = writeln! (io::stderr (), "Couldn't restart the Node: {}", io::Error::last_os_error ());
+        unsafe {libc::_exit (1)}
+    }
+}
+
+// Windows has no privileges to drop, so the Node can start its own replacement
+#[cfg (windows)]
+impl Supervisor for SupervisorReal {
+    fn supervise (&mut self, args: &Vec<String>) {
+        self.args = args.clone ();
+    }
+
+    fn restart (&mut self, settings: &Vec<String>) -> Result<(), String> {
+        config_file::check_restartable (settings)?;
+        let args = config_file::replace_args (&self.args, settings);
+        let program = env::current_exe ().map_err (|e| format! ("Couldn't find the Node's program: {}", e))?;
+        match Command::new (program).args (args.iter ().skip (1)).spawn () {
+            Ok (_) => Ok (()),
+            Err (e) => Err (format! ("Couldn't start the Node again: {}", e))
+        }
+    }
+}
+
+#[cfg (windows)]
+impl SupervisorReal {
+    pub fn new () -> SupervisorReal {
+        SupervisorReal {args: vec! ()}
+    }
+}
+
+// The header and each setting are followed by a NUL, which no argument can contain
+#[cfg (any (unix, test))]
+fn encode_settings (settings: &Vec<String>) -> Vec<u8> {
+    let mut message = vec! ();
+    message.extend_from_slice (RESTART_HEADER.as_bytes ());
+    message.push (0);
+    for setting in settings {
+        message.extend_from_slice (setting.as_bytes ());
+        message.push (0);
+    }
+    message
+}
+
+// Anything short of a whole message, such as nothing at all, means the Node isn't coming back
+#[cfg (any (unix, test))]
+fn decode_settings (message: &[u8]) -> Option<Vec<String>> {
+    if message.last () != Some (&0) {return None}
+    let pieces: Vec<&[u8]> = message[..(message.len () - 1)].split (|byte| *byte == 0).collect ();
+    match pieces.iter ().map (|piece| String::from_utf8 (piece.to_vec ())).collect::<Result<Vec<String>, _>> () {
+        Ok (ref pieces) if (pieces.len () > 0) && (pieces[0] == RESTART_HEADER) => Some (pieces[1..].to_vec ()),
+        _ => None
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_come_through_the_pipe_as_they_went_in () {
+        let settings = vec! (String::from ("--neighbor"), String::from ("a b;1.2.3.4:1234"), String::from ("--ban"), String::from (""));
+
+        let result = decode_settings (&encode_settings (&settings));
+
+        assert_eq! (result, Some (settings));
+    }
+
+    #[test]
+    fn a_restart_with_no_new_settings_is_still_a_restart () {
+        assert_eq! (decode_settings (&encode_settings (&vec! ())), Some (vec! ()));
+    }
+
+    #[test]
+    fn a_node_that_hands_over_nothing_is_not_restarted () {
+        assert_eq! (decode_settings (&[]), None);
+        assert_eq! (decode_settings (b"restart\0--log_le"), None);
+        assert_eq! (decode_settings (b"SubstratumNode\0--log_level\0debug\0"), None);
+    }
+
+    #[test]
+    fn the_node_cannot_hand_over_settings_a_restart_may_not_change () {
+        let mut subject = SupervisorReal::new ();
+
+        let result = subject.restart (&vec! (String::from ("--data_directory"), String::from ("/etc")));
+
+        assert_eq! (result, Err (String::from ("Setting 'data_directory' can't be changed by a restart")));
+    }
+}
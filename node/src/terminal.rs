@@ -0,0 +1,52 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+#[cfg(unix)]
+use libc;
+#[cfg(unix)]
+use std::mem;
+
+// Secrets like recovery phrases are only shown to, and taken from, a person at a terminal; output
+// that's going to a file or a pipe may be kept where others can read it
+pub trait Terminal: Send {
+    // Whether standard input and standard output are both a terminal
+    fn is_interactive (&self) -> bool;
+    // Whether what's typed at standard input shows up on the screen, for asking after passwords
+    fn set_echo (&self, on: bool);
+}
+
+pub struct TerminalReal;
+
+#[cfg(unix)]
+impl Terminal for TerminalReal {
+    // Not unit tested
+    fn is_interactive (&self) -> bool {
+        unsafe {(libc::isatty (libc::STDIN_FILENO) == 1) && (libc::isatty (libc::STDOUT_FILENO) == 1)}
+    }
+
+    // Not unit tested; standard input that isn't a terminal has no echo to turn off
+    fn set_echo (&self, on: bool) {
+        unsafe {
+            let mut termios: libc::termios = mem::zeroed ();
+            if libc::tcgetattr (libc::STDIN_FILENO, &mut termios) != 0 {return}
+            if on {termios.c_lflag |= libc::ECHO} else {termios.c_lflag &= !libc::ECHO}
+            libc::tcsetattr (libc::STDIN_FILENO, libc::TCSANOW, &termios);
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Terminal for TerminalReal {
+    fn is_interactive (&self) -> bool {
+        // No terminal detection for Windows yet, and no --daemon to send output anywhere else
+        true
+    }
+
+    fn set_echo (&self, _on: bool) {
+        // No console mode control for Windows yet
+    }
+}
+
+impl TerminalReal {
+    pub fn new () -> TerminalReal {
+        TerminalReal {}
+    }
+}
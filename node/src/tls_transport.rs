@@ -0,0 +1,85 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::Arc;
+use rustls::ServerConfig;
+use rustls::ServerSession;
+use rustls::Stream;
+
+// The name in every certificate the Node generates for itself
+pub const CERTIFICATE_HOSTNAME: &str = "localhost";
+
+// The server's end of a TLS connection, read and written like the socket under it
+pub struct TlsServerStream {
+    session: ServerSession,
+    socket: TcpStream,
+}
+
+impl TlsServerStream {
+    pub fn new (config: &Arc<ServerConfig>, socket: TcpStream) -> TlsServerStream {
+        TlsServerStream {session: ServerSession::new (config), socket}
+    }
+
+    pub fn socket (&self) -> &TcpStream {
+        &self.socket
+    }
+}
+
+impl Read for TlsServerStream {
+    fn read (&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Stream::new (&mut self.session, &mut self.socket).read (buf)
+    }
+}
+
+impl Write for TlsServerStream {
+    fn write (&mut self, buf: &[u8]) -> io::Result<usize> {
+        Stream::new (&mut self.session, &mut self.socket).write (buf)
+    }
+
+    fn flush (&mut self) -> io::Result<()> {
+        Stream::new (&mut self.session, &mut self.socket).flush ()
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use rustls::Certificate;
+    use rustls::ClientConfig;
+    use rustls::ClientSession;
+    use webpki;
+    use ui_certificate::UiCertificate;
+
+    #[test]
+    fn a_tls_server_stream_carries_data_both_ways () {
+        let certificate = UiCertificate::generate ();
+        let server_config = Arc::new (certificate.server_config ().unwrap ());
+        let mut client_config = ClientConfig::new ();
+        client_config.root_store.add (&Certificate (certificate.certificate_chain[0].clone ())).unwrap ();
+        let listener = TcpListener::bind ("127.0.0.1:0").unwrap ();
+        let addr = listener.local_addr ().unwrap ();
+        let server = thread::spawn (move || {
+            let (socket, _) = listener.accept ().unwrap ();
+            let mut subject = TlsServerStream::new (&server_config, socket);
+            let mut received = [0u8; 5];
+            subject.read_exact (&mut received).unwrap ();
+            subject.write_all (b"world").unwrap ();
+            subject.flush ().unwrap ();
+            received
+        });
+        let mut socket = TcpStream::connect (addr).unwrap ();
+        let mut session = ClientSession::new (&Arc::new (client_config), webpki::DNSNameRef::try_from_ascii_str (CERTIFICATE_HOSTNAME).unwrap ());
+
+        let mut client = Stream::new (&mut session, &mut socket);
+        client.write_all (b"hello").unwrap ();
+        let mut answer = [0u8; 5];
+        client.read_exact (&mut answer).unwrap ();
+
+        assert_eq! (&answer, b"world");
+        assert_eq! (&server.join ().unwrap (), b"hello");
+    }
+}
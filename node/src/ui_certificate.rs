@@ -0,0 +1,197 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str;
+use base64;
+use rcgen;
+use rustls::Certificate;
+use rustls::internal::pemfile;
+use rustls::NoClientAuth;
+use rustls::PrivateKey;
+use rustls::ServerConfig;
+use sub_lib::private_file::create_private_file;
+use tls_transport::CERTIFICATE_HOSTNAME;
+
+pub const UI_CERTIFICATE_FILENAME: &str = "ui_certificate.pem";
+pub const UI_PRIVATE_KEY_FILENAME: &str = "ui_private_key.pem";
+
+#[derive (Clone, Debug, PartialEq)]
+pub enum UiTls {
+    Off,
+    // The Node makes its own certificate, and keeps it in the data directory if it has one
+    Generated,
+    // PEM files from the operator, presumably signed by someone the UI trusts
+    Supplied {certificate_file: PathBuf, private_key_file: PathBuf},
+}
+
+// What the UI port serves TLS with. Certificates and keys are DER.
+#[derive (Clone, PartialEq)]
+pub struct UiCertificate {
+    pub certificate_chain: Vec<Vec<u8>>,
+    pub private_key: Vec<u8>,
+}
+
+// Configurations get logged; the private key shouldn't go along with them
+impl fmt::Debug for UiCertificate {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write! (f, "UiCertificate {{ {} certificate(s), private key withheld }}", self.certificate_chain.len ())
+    }
+}
+
+impl UiCertificate {
+    // Self-signed, so a UI has to be told to trust it; it's saved as PEM for that reason
+    pub fn generate () -> UiCertificate {
+        let generated = rcgen::generate_simple_self_signed (vec! (String::from (CERTIFICATE_HOSTNAME)));
+        UiCertificate {
+            certificate_chain: vec! (generated.serialize_der ()),
+            private_key: generated.serialize_private_key_der (),
+        }
+    }
+
+    // The key may be PKCS#8 or RSA. The pair is tried out here, so that a bad one is reported at
+    // startup rather than when the first UI connects.
+    pub fn from_pem_files (certificate_file: &Path, private_key_file: &Path) -> Result<UiCertificate, String> {
+        let certificates = read_pem (certificate_file, |reader| pemfile::certs (reader))?;
+        if certificates.is_empty () {
+            return Err (format! ("There are no certificates in {:?}", certificate_file))
+        }
+        let mut keys = read_pem (private_key_file, |reader| pemfile::pkcs8_private_keys (reader))?;
+        if keys.is_empty () {
+            keys = read_pem (private_key_file, |reader| pemfile::rsa_private_keys (reader))?;
+        }
+        if keys.is_empty () {
+            return Err (format! ("There's no private key in {:?}", private_key_file))
+        }
+        let ui_certificate = UiCertificate {
+            certificate_chain: certificates.into_iter ().map (|certificate| certificate.0).collect (),
+            private_key: keys.remove (0).0,
+        };
+        ui_certificate.server_config ()?;
+        Ok (ui_certificate)
+    }
+
+    pub fn server_config (&self) -> Result<ServerConfig, String> {
+        let mut config = ServerConfig::new (NoClientAuth::new ());
+        let chain: Vec<Certificate> = self.certificate_chain.iter ().map (|certificate| Certificate (certificate.clone ())).collect ();
+        match config.set_single_cert (chain, PrivateKey (self.private_key.clone ())) {
+            Ok (()) => Ok (config),
+            Err (e) => Err (format! ("Couldn't use the UI certificate: {:?}", e))
+        }
+    }
+}
+
+// A generated certificate is kept, so that a UI told to trust it once doesn't have to be told again
+// every time the Node starts.
+pub struct UiCertificateStore {
+    certificate_path: PathBuf,
+    private_key_path: PathBuf,
+}
+
+impl UiCertificateStore {
+    pub fn in_data_directory (data_directory: &Path) -> UiCertificateStore {
+        UiCertificateStore {
+            certificate_path: data_directory.join (UI_CERTIFICATE_FILENAME),
+            private_key_path: data_directory.join (UI_PRIVATE_KEY_FILENAME),
+        }
+    }
+
+    pub fn certificate_path (&self) -> &Path {
+        &self.certificate_path
+    }
+
+    // Ok (None) means no certificate has been saved yet
+    pub fn load (&self) -> Result<Option<UiCertificate>, String> {
+        if !self.certificate_path.exists () || !self.private_key_path.exists () {
+            return Ok (None)
+        }
+        UiCertificate::from_pem_files (&self.certificate_path, &self.private_key_path).map (Some)
+    }
+
+    pub fn save (&self, ui_certificate: &UiCertificate) -> Result<(), String> {
+        let certificates: Vec<String> = ui_certificate.certificate_chain.iter ().map (|certificate| pem ("CERTIFICATE", certificate)).collect ();
+        let result: io::Result<()> = self.certificate_path.parent ().map (|parent| fs::create_dir_all (parent)).unwrap_or (Ok (()))
+            .and_then (|_| File::create (&self.certificate_path))
+            .and_then (|mut file| file.write_all (certificates.join ("").as_bytes ()))
+            .and_then (|_| create_private_file (&self.private_key_path))
+            .and_then (|mut file| file.write_all (pem ("PRIVATE KEY", &ui_certificate.private_key).as_bytes ()));
+        result.map_err (|e| format! ("Couldn't save the UI certificate to {:?}: {}", self.certificate_path, e))
+    }
+}
+
+fn read_pem<T, F> (path: &Path, parse: F) -> Result<Vec<T>, String> where F: Fn (&mut BufRead) -> Result<Vec<T>, ()> {
+    let file = File::open (path).map_err (|e| format! ("Couldn't read {:?}: {}", path, e))?;
+    parse (&mut BufReader::new (file)).map_err (|_| format! ("{:?} isn't a PEM file", path))
+}
+
+fn pem (label: &str, der: &[u8]) -> String {
+    let encoded = base64::encode (der);
+    let lines: Vec<&str> = encoded.as_bytes ().chunks (64).map (|line| str::from_utf8 (line).expect ("Base64 isn't ASCII")).collect ();
+    format! ("-----BEGIN {}-----\n{}\n-----END {}-----\n", label, lines.join ("\n"), label)
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+
+    fn make_subject (name: &str) -> UiCertificateStore {
+        let data_directory = temp_dir ().join ("ui_certificate").join (name);
+        let _ = fs::remove_dir_all (&data_directory);
+        UiCertificateStore::in_data_directory (&data_directory)
+    }
+
+    #[test]
+    fn a_generated_certificate_can_be_served () {
+        let subject = UiCertificate::generate ();
+
+        assert_eq! (subject.server_config ().is_ok (), true);
+        assert_eq! (subject.certificate_chain.len (), 1);
+    }
+
+    #[test]
+    fn a_saved_certificate_can_be_loaded_back () {
+        let subject = make_subject ("a_saved_certificate_can_be_loaded_back");
+        let ui_certificate = UiCertificate::generate ();
+        let before = subject.load ();
+
+        subject.save (&ui_certificate).unwrap ();
+        let after = subject.load ();
+
+        assert_eq! (before, Ok (None));
+        assert_eq! (after, Ok (Some (ui_certificate)));
+    }
+
+    #[test]
+    fn a_file_that_is_not_pem_has_no_certificates () {
+        let subject = make_subject ("a_file_that_is_not_pem_has_no_certificates");
+        subject.save (&UiCertificate::generate ()).unwrap ();
+        File::create (subject.certificate_path ()).unwrap ().write_all (b"booga\n").unwrap ();
+
+        let result = subject.load ();
+
+        assert_eq! (result, Err (format! ("There are no certificates in {:?}", subject.certificate_path ())));
+    }
+
+    #[test]
+    fn a_missing_file_is_named () {
+        let subject = make_subject ("a_missing_file_is_named");
+
+        let result = UiCertificate::from_pem_files (subject.certificate_path (), &subject.private_key_path);
+
+        assert_eq! (result.unwrap_err ().starts_with (&format! ("Couldn't read {:?}: ", subject.certificate_path ())), true);
+    }
+
+    #[test]
+    fn the_private_key_is_not_shown () {
+        let subject = UiCertificate {certificate_chain: vec! (vec! (1, 2, 3)), private_key: vec! (4, 5, 6)};
+
+        assert_eq! (format! ("{:?}", subject), String::from ("UiCertificate { 1 certificate(s), private key withheld }"));
+    }
+}
@@ -0,0 +1,921 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::mpsc::TryRecvError;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use actix::Actor;
+use actix::ActorFuture;
+use actix::Addr;
+use actix::AsyncContext;
+use actix::Context;
+use actix::fut;
+use actix::Handler;
+use actix::Recipient;
+use actix::Syn;
+use actix::WrapFuture;
+use base64;
+use flexi_logger::LevelFilter;
+use rustls::ServerConfig;
+use serde_json;
+use serde_json::Value;
+use sub_lib::accountant::GetNodeStatsMsg;
+use sub_lib::accountant::NodeStats;
+use sub_lib::logger;
+use sub_lib::logger::Logger;
+use sub_lib::neighborhood::GetTopologyMsg;
+use sub_lib::neighborhood::NeighborhoodTopology;
+use sub_lib::peer_actors::BindMessage;
+use sub_lib::traffic_stats::traffic_totals;
+use sub_lib::traffic_stats::TrafficTotals;
+use cli;
+use config_file::PARAMETERS;
+use config_file::RESTARTABLE_PARAMETERS;
+use shutdown;
+use tls_transport::TlsServerStream;
+use ui_certificate::UiCertificate;
+use ui_certificate::UiTls;
+use ui_token::tokens_match;
+use websocket;
+use websocket::Frame;
+use websocket::Handshake;
+
+pub const DEFAULT_UI_PORT: u16 = 5333;
+// A client that hasn't finished its handshake by then is dropped
+const HANDSHAKE_TIMEOUT_MS: u64 = 5000;
+// How long a connection waits for something from its client before looking for something to send it
+const POLL_MS: u64 = 100;
+// How often UIs that have subscribed hear about the Node's traffic
+const TRAFFIC_STATS_INTERVAL_MS: u64 = 1000;
+
+// Unless told otherwise, only UIs on the same machine can reach the Node
+pub fn default_ui_bind_ip () -> IpAddr {
+    IpAddr::V4 (Ipv4Addr::new (127, 0, 0, 1))
+}
+
+#[derive (Clone, Debug, PartialEq)]
+pub struct UiGatewayConfig {
+    pub ui_bind_ip: IpAddr,
+    pub ui_port: u16,
+    pub ui_tls: UiTls,
+    // Filled in by the Bootstrapper once it has found or made the token
+    pub token_opt: Option<String>,
+    // Filled in by the Bootstrapper if ui_tls isn't Off
+    pub certificate_opt: Option<UiCertificate>,
+}
+
+// What a UI can ask of the Node, one JSON object per WebSocket text frame, named by its "opcode"
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde (tag = "opcode", rename_all = "snake_case")]
+pub enum UiRequest {
+    Shutdown,
+    // Settings are named as they are in the config file; any not given stay as they were
+    Restart {#[serde (default)] settings: BTreeMap<String, Value>},
+    GetNodeStats,
+    GetTopology,
+    // With a module, only for the Loggers logging under its name; level "default" puts it back under
+    // the Node's level
+    SetLogLevel {level: String, #[serde (default)] module: Option<String>},
+    SubscribeTrafficStats,
+    UnsubscribeTrafficStats,
+}
+
+// What the Node tells a UI: answers go to the UI that asked, and news goes to every UI
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde (tag = "opcode", rename_all = "snake_case")]
+pub enum UiEvent {
+    NodeStats {bytes_relayed: u64, packages_relayed: u64, bytes_exited: u64, requests_served: u64, uptime_ms: u64},
+    Topology {nodes: Vec<UiTopologyNode>, edges: Vec<UiTopologyEdge>},
+    TrafficStats {bytes_in_per_sec: u64, bytes_out_per_sec: u64, bytes_relayed_per_sec: u64, bytes_originated_per_sec: u64,
+        active_streams: u64, routes_in_use: u64},
+    LogLevel {level: String, #[serde (default, skip_serializing_if = "Option::is_none")] module: Option<String>},
+    ShuttingDown,
+    Restarting,
+    Error {message: String},
+}
+
+// Public keys are base64, as they are in neighbor descriptors. The local Node has no last_seen_ms_ago,
+// and neither does a neighbor that hasn't been heard from yet.
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UiTopologyNode {
+    pub public_key: String,
+    pub ip_addr: Option<String>,
+    pub ports: Vec<u16>,
+    pub local: bool,
+    pub reputation: i64,
+    pub last_seen_ms_ago: Option<u64>,
+    pub latency_ms: Option<u64>,
+    pub country: Option<String>,
+}
+
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UiTopologyEdge {
+    pub from: String,
+    pub to: String,
+}
+
+impl UiEvent {
+    fn from_node_stats (stats: NodeStats) -> UiEvent {
+        UiEvent::NodeStats {
+            bytes_relayed: stats.bytes_relayed,
+            packages_relayed: stats.packages_relayed,
+            bytes_exited: stats.bytes_exited,
+            requests_served: stats.requests_served,
+            uptime_ms: stats.uptime_ms,
+        }
+    }
+
+    fn from_topology (topology: NeighborhoodTopology) -> UiEvent {
+        UiEvent::Topology {
+            nodes: topology.nodes.into_iter ().map (|node| UiTopologyNode {
+                public_key: base64::encode (&node.public_key.data),
+                ip_addr: node.node_addr_opt.as_ref ().map (|node_addr| node_addr.ip_addr ().to_string ()),
+                ports: node.node_addr_opt.as_ref ().map (|node_addr| node_addr.ports ()).unwrap_or (vec! ()),
+                local: node.is_local,
+                reputation: node.reputation,
+                last_seen_ms_ago: node.last_seen_ms_ago_opt,
+                latency_ms: node.latency_ms_opt,
+                country: node.country_opt,
+            }).collect (),
+            edges: topology.edges.into_iter ().map (|edge| UiTopologyEdge {
+                from: base64::encode (&edge.from.data),
+                to: base64::encode (&edge.to.data),
+            }).collect (),
+        }
+    }
+
+    // Rates are worked out over however long it really was between the two snapshots
+    fn from_traffic_totals (before: &TrafficTotals, after: &TrafficTotals, elapsed: Duration) -> UiEvent {
+        let elapsed_ms = elapsed.as_secs () * 1000 + (elapsed.subsec_nanos () / 1000000) as u64;
+        let per_sec = |before: u64, after: u64| if elapsed_ms == 0 {0} else {after.saturating_sub (before) * 1000 / elapsed_ms};
+        UiEvent::TrafficStats {
+            bytes_in_per_sec: per_sec (before.bytes_in, after.bytes_in),
+            bytes_out_per_sec: per_sec (before.bytes_out, after.bytes_out),
+            bytes_relayed_per_sec: per_sec (before.bytes_relayed, after.bytes_relayed),
+            bytes_originated_per_sec: per_sec (before.bytes_originated, after.bytes_originated),
+            active_streams: after.active_streams,
+            routes_in_use: after.routes_in_use,
+        }
+    }
+}
+
+// A UI has connected; whatever is sent down to_client goes out to it
+#[derive (Message)]
+pub struct UiClientArrivedMsg {
+    pub client_id: u64,
+    pub to_client: Sender<String>,
+}
+
+#[derive (Message)]
+pub struct FromUiMessage {
+    pub client_id: u64,
+    pub json: String,
+}
+
+#[derive (Message)]
+pub struct UiClientGoneMsg {
+    pub client_id: u64,
+}
+
+#[derive (Clone)]
+pub struct UiGatewaySubs {
+    pub bind: Recipient<Syn, BindMessage>,
+    pub ui_client_arrived: Recipient<Syn, UiClientArrivedMsg>,
+    pub from_ui: Recipient<Syn, FromUiMessage>,
+    pub ui_client_gone: Recipient<Syn, UiClientGoneMsg>,
+}
+
+// Stands between the Node's actors and the UIs connected to its control port, on localhost, so
+// that a desktop app can drive the Node.
+pub struct UiGateway {
+    clients: HashMap<u64, Sender<String>>,
+    traffic_subscribers: HashSet<u64>,
+    traffic_stats_interval_ms: u64,
+    last_traffic: (Instant, TrafficTotals),
+    get_node_stats_opt: Option<Recipient<Syn, GetNodeStatsMsg>>,
+    get_topology_opt: Option<Recipient<Syn, GetTopologyMsg>>,
+    logger: Logger,
+}
+
+impl Actor for UiGateway {
+    type Context = Context<Self>;
+
+    fn started (&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval (Duration::from_millis (self.traffic_stats_interval_ms), |ui_gateway, _ctx| {
+            ui_gateway.publish_traffic_stats ()
+        });
+    }
+}
+
+impl Handler<BindMessage> for UiGateway {
+    type Result = ();
+
+    fn handle(&mut self, msg: BindMessage, _ctx: &mut Self::Context) {
+        self.get_node_stats_opt = Some (msg.peer_actors.accountant.get_node_stats);
+        self.get_topology_opt = Some (msg.peer_actors.neighborhood.get_topology);
+    }
+}
+
+impl Handler<UiClientArrivedMsg> for UiGateway {
+    type Result = ();
+
+    fn handle(&mut self, msg: UiClientArrivedMsg, _ctx: &mut Self::Context) {
+        self.logger.info (format! ("UI client {} connected", msg.client_id));
+        self.clients.insert (msg.client_id, msg.to_client);
+    }
+}
+
+impl Handler<UiClientGoneMsg> for UiGateway {
+    type Result = ();
+
+    fn handle(&mut self, msg: UiClientGoneMsg, _ctx: &mut Self::Context) {
+        self.logger.info (format! ("UI client {} disconnected", msg.client_id));
+        self.clients.remove (&msg.client_id);
+        self.traffic_subscribers.remove (&msg.client_id);
+    }
+}
+
+impl Handler<FromUiMessage> for UiGateway {
+    type Result = ();
+
+    fn handle(&mut self, msg: FromUiMessage, ctx: &mut Self::Context) {
+        let request = match serde_json::from_str::<UiRequest> (&msg.json) {
+            Ok (request) => request,
+            Err (e) => {
+                self.logger.warning (format! ("UI client {} sent something that isn't a request: {}", msg.client_id, e));
+                return self.send_to (msg.client_id, &UiEvent::Error {message: format! ("Not a request: {}", e)})
+            }
+        };
+        match request {
+            UiRequest::Shutdown => {
+                self.logger.info (format! ("UI client {} asked the Node to shut down", msg.client_id));
+                self.broadcast (&UiEvent::ShuttingDown);
+                shutdown::request_shutdown ();
+            },
+            UiRequest::Restart {settings} => match restart_args (&settings) {
+                Ok (args) => {
+                    self.logger.info (format! ("UI client {} asked the Node to restart with {:?}", msg.client_id, settings.keys ().collect::<Vec<&String>> ()));
+                    self.broadcast (&UiEvent::Restarting);
+                    shutdown::request_restart (args);
+                },
+                Err (e) => self.send_to (msg.client_id, &UiEvent::Error {message: e})
+            },
+            UiRequest::GetNodeStats => self.request_node_stats (msg.client_id, ctx),
+            UiRequest::GetTopology => self.request_topology (msg.client_id, ctx),
+            UiRequest::SetLogLevel {level, module: None} => match LevelFilter::from_str (&level) {
+                Ok (level_filter) => {
+                    logger::set_log_level (level_filter);
+                    self.broadcast (&UiEvent::LogLevel {level: level_filter.to_string ().to_lowercase (), module: None});
+                },
+                Err (_) => self.send_to (msg.client_id, &UiEvent::Error {
+                    message: format! ("'{}' isn't one of trace, debug, info, warn, error or off", level)
+                })
+            },
+            UiRequest::SetLogLevel {level, module: Some (module)} => {
+                let level_opt_result = if level == "default" {Ok (None)} else {LevelFilter::from_str (&level).map (|level_filter| Some (level_filter))};
+                match level_opt_result {
+                    Ok (level_opt) => {
+                        self.logger.info (format! ("UI client {} set the log level for {} to {}", msg.client_id, module, level.to_lowercase ()));
+                        logger::set_module_log_level (&module, level_opt);
+                        let level = match level_opt {
+                            Some (level_filter) => level_filter.to_string ().to_lowercase (),
+                            None => String::from ("default")
+                        };
+                        self.broadcast (&UiEvent::LogLevel {level, module: Some (module)});
+                    },
+                    Err (_) => self.send_to (msg.client_id, &UiEvent::Error {
+                        message: format! ("'{}' isn't one of trace, debug, info, warn, error, off or default", level)
+                    })
+                }
+            },
+            UiRequest::SubscribeTrafficStats => {self.traffic_subscribers.insert (msg.client_id);},
+            UiRequest::UnsubscribeTrafficStats => {self.traffic_subscribers.remove (&msg.client_id);},
+        }
+    }
+}
+
+impl UiGateway {
+    pub fn new () -> UiGateway {
+        UiGateway {
+            clients: HashMap::new (),
+            traffic_subscribers: HashSet::new (),
+            traffic_stats_interval_ms: TRAFFIC_STATS_INTERVAL_MS,
+            last_traffic: (Instant::now (), traffic_totals ()),
+            get_node_stats_opt: None,
+            get_topology_opt: None,
+            logger: Logger::new ("UiGateway"),
+        }
+    }
+
+    pub fn make_subs_from (addr: &Addr<Syn, UiGateway>) -> UiGatewaySubs {
+        UiGatewaySubs {
+            bind: addr.clone ().recipient::<BindMessage>(),
+            ui_client_arrived: addr.clone ().recipient::<UiClientArrivedMsg>(),
+            from_ui: addr.clone ().recipient::<FromUiMessage>(),
+            ui_client_gone: addr.clone ().recipient::<UiClientGoneMsg>(),
+        }
+    }
+
+    fn request_node_stats (&mut self, client_id: u64, ctx: &mut Context<UiGateway>) {
+        let future = self.get_node_stats_opt.as_ref ().expect ("Accountant unbound in UiGateway")
+            .send (GetNodeStatsMsg {})
+            .into_actor (self)
+            .then (move |stats_result, ui_gateway, _ctx| {
+                match stats_result {
+                    Ok (stats) => ui_gateway.send_to (client_id, &UiEvent::from_node_stats (stats)),
+                    Err (e) => {
+                        ui_gateway.logger.error (format! ("Accountant failed to answer for node stats: {:?}", e));
+                        ui_gateway.send_to (client_id, &UiEvent::Error {message: String::from ("Node stats are unavailable")})
+                    }
+                }
+                fut::ok (())
+            });
+        ctx.spawn (future);
+    }
+
+    fn request_topology (&mut self, client_id: u64, ctx: &mut Context<UiGateway>) {
+        let future = self.get_topology_opt.as_ref ().expect ("Neighborhood unbound in UiGateway")
+            .send (GetTopologyMsg {})
+            .into_actor (self)
+            .then (move |topology_result, ui_gateway, _ctx| {
+                match topology_result {
+                    Ok (topology) => ui_gateway.send_to (client_id, &UiEvent::from_topology (topology)),
+                    Err (e) => {
+                        ui_gateway.logger.error (format! ("Neighborhood failed to answer for the topology: {:?}", e));
+                        ui_gateway.send_to (client_id, &UiEvent::Error {message: String::from ("The network map is unavailable")})
+                    }
+                }
+                fut::ok (())
+            });
+        ctx.spawn (future);
+    }
+
+    // The snapshot is taken whether or not anybody's listening, so that a new subscriber's first
+    // rates cover one interval rather than everything since the last subscriber left
+    fn publish_traffic_stats (&mut self) {
+        let now = (Instant::now (), traffic_totals ());
+        let event = UiEvent::from_traffic_totals (&self.last_traffic.1, &now.1, now.0.duration_since (self.last_traffic.0));
+        self.last_traffic = now;
+        let subscribers: Vec<u64> = self.traffic_subscribers.iter ().cloned ().collect ();
+        subscribers.into_iter ().for_each (|client_id| self.send_to (client_id, &event));
+    }
+
+    fn send_to (&mut self, client_id: u64, event: &UiEvent) {
+        let json = serde_json::to_string (event).expect ("UiEvent won't serialize");
+        let gone = match self.clients.get (&client_id) {
+            Some (to_client) => to_client.send (json).is_err (),
+            None => false
+        };
+        if gone {
+            self.clients.remove (&client_id);
+            self.traffic_subscribers.remove (&client_id);
+        }
+    }
+
+    fn broadcast (&mut self, event: &UiEvent) {
+        let json = serde_json::to_string (event).expect ("UiEvent won't serialize");
+        self.clients.retain (|_, to_client| to_client.send (json.clone ()).is_ok ());
+        let clients = &self.clients;
+        self.traffic_subscribers.retain (|client_id| clients.contains_key (client_id));
+    }
+}
+
+// Settings are taken the way the config file takes them and checked the way the command line is, so
+// that a bad one is turned down now rather than keeping the Node from coming back up
+fn restart_args (settings: &BTreeMap<String, Value>) -> Result<Vec<String>, String> {
+    let mut args = vec! ();
+    for (name, value) in settings {
+        if !PARAMETERS.contains (&name.as_str ()) {
+            return Err (format! ("Unknown setting '{}'", name))
+        }
+        if !RESTARTABLE_PARAMETERS.contains (&name.as_str ()) {
+            return Err (format! ("Setting '{}' can't be changed by a restart", name))
+        }
+        let values = match *value {
+            Value::Array (ref elements) => elements.iter ().map (|element| setting_value (name, element)).collect::<Result<Vec<String>, String>> ()?,
+            ref other => vec! (setting_value (name, other)?)
+        };
+        for value in values {
+            args.push (format! ("--{}", name));
+            args.push (value);
+        }
+    }
+    let mut command_line = vec! (String::from ("SubstratumNode"));
+    command_line.extend (args.iter ().cloned ());
+    cli::validate (&command_line)?;
+    Ok (args)
+}
+
+fn setting_value (name: &str, value: &Value) -> Result<String, String> {
+    match *value {
+        Value::String (ref string) => Ok (string.clone ()),
+        Value::Number (ref number) => Ok (number.to_string ()),
+        Value::Bool (true) => Ok (String::from ("on")),
+        Value::Bool (false) => Ok (String::from ("off")),
+        _ => Err (format! ("Invalid value for setting '{}': expected a string, number, boolean, or list of them", name))
+    }
+}
+
+// Each UI gets its own thread, which hands what it sends to the UiGateway and sends it whatever
+// the UiGateway has for it. Not unit tested beyond UiConnection.
+pub fn start_ui_listener (listener: TcpListener, tls_opt: Option<Arc<ServerConfig>>, token: String, subs: UiGatewaySubs) {
+    let token = Arc::new (token);
+    thread::spawn (move || {
+        let logger = Logger::new ("UiGateway");
+        let mut next_client_id: u64 = 1;
+        for stream_result in listener.incoming () {
+            match stream_result {
+                Ok (stream) => {
+                    let client_id = next_client_id;
+                    next_client_id += 1;
+                    let tls_opt = tls_opt.clone ();
+                    let token = token.clone ();
+                    let subs = subs.clone ();
+                    thread::spawn (move || match tls_opt {
+                        Some (ref tls) => serve_ui_client (TlsServerStream::new (tls, stream), client_id, token, subs),
+                        None => serve_ui_client (stream, client_id, token, subs)
+                    });
+                },
+                Err (e) => logger.warning (format! ("Couldn't accept a UI connection: {}", e))
+            }
+        }
+    });
+}
+
+// Whatever a UI's connection runs over: plain TCP, or TLS on top of it
+trait UiStream: Read + Write {
+    fn socket (&self) -> &TcpStream;
+}
+
+impl UiStream for TcpStream {
+    fn socket (&self) -> &TcpStream {
+        self
+    }
+}
+
+impl UiStream for TlsServerStream {
+    fn socket (&self) -> &TcpStream {
+        TlsServerStream::socket (self)
+    }
+}
+
+fn serve_ui_client<S> (mut stream: S, client_id: u64, token: Arc<String>, subs: UiGatewaySubs) where S: UiStream {
+    let logger = Logger::new ("UiGateway");
+    stream.socket ().set_read_timeout (Some (Duration::from_millis (HANDSHAKE_TIMEOUT_MS))).ok ();
+    let handshake = match websocket::read_handshake (&mut stream) {
+        Ok (handshake) => handshake,
+        Err (e) => {
+            logger.warning (format! ("Refused UI connection: {}", e));
+            stream.write_all (websocket::rejection ("400 Bad Request").as_bytes ()).ok ();
+            return
+        }
+    };
+    if !authorized (&handshake, &token) {
+        let peer = stream.socket ().peer_addr ().map (|addr| addr.to_string ()).unwrap_or (String::from ("an unknown address"));
+        logger.warning (format! ("Refused UI connection from {}: it didn't present the UI token", peer));
+        stream.write_all (websocket::rejection ("401 Unauthorized").as_bytes ()).ok ();
+        return
+    }
+    if stream.write_all (handshake.acceptance ().as_bytes ()).is_err () {return}
+    stream.socket ().set_read_timeout (Some (Duration::from_millis (POLL_MS))).ok ();
+    let (to_client_tx, to_client_rx) = mpsc::channel ();
+    if subs.ui_client_arrived.try_send (UiClientArrivedMsg {client_id, to_client: to_client_tx}).is_err () {return}
+    UiConnection::new (stream, client_id, subs.from_ui.clone (), to_client_rx).run ();
+    subs.ui_client_gone.try_send (UiClientGoneMsg {client_id}).ok ();
+}
+
+// A browser can't add headers to a WebSocket handshake, so a UI can send the token either as
+// "Authorization: Bearer <token>" or as "?token=<token>" on the end of the path.
+fn authorized (handshake: &Handshake, token: &str) -> bool {
+    presented_token (handshake).map (|presented| tokens_match (presented, token)).unwrap_or (false)
+}
+
+fn presented_token (handshake: &Handshake) -> Option<&str> {
+    if let Some (authorization) = handshake.header ("Authorization") {
+        if authorization.starts_with ("Bearer ") {
+            return Some (authorization["Bearer ".len ()..].trim ())
+        }
+    }
+    let query = match handshake.path.find ('?') {
+        Some (index) => &handshake.path[(index + 1)..],
+        None => return None
+    };
+    query.split ('&').find (|pair| pair.starts_with ("token=")).map (|pair| &pair["token=".len ()..])
+}
+
+// One UI's connection once its handshake is done. Reads from the stream time out now and then, so
+// that messages for the UI don't wait for the UI to say something first.
+struct UiConnection<S> where S: Read + Write {
+    stream: S,
+    client_id: u64,
+    buffer: Vec<u8>,
+    from_ui: Recipient<Syn, FromUiMessage>,
+    to_client_rx: Receiver<String>,
+    logger: Logger,
+}
+
+impl<S> UiConnection<S> where S: Read + Write {
+    fn new (stream: S, client_id: u64, from_ui: Recipient<Syn, FromUiMessage>, to_client_rx: Receiver<String>) -> UiConnection<S> {
+        UiConnection {stream, client_id, buffer: vec! (), from_ui, to_client_rx, logger: Logger::new ("UiGateway")}
+    }
+
+    fn run (mut self) {
+        while self.send_waiting () && self.receive () {}
+    }
+
+    // false once the connection is over
+    fn send_waiting (&mut self) -> bool {
+        loop {
+            match self.to_client_rx.try_recv () {
+                Ok (json) => if !self.send (Frame::Text (json)) {return false},
+                Err (TryRecvError::Empty) => return true,
+                Err (TryRecvError::Disconnected) => {
+                    self.send (Frame::Close);
+                    return false
+                }
+            }
+        }
+    }
+
+    // false once the connection is over
+    fn receive (&mut self) -> bool {
+        let mut chunk = [0u8; 4096];
+        match self.stream.read (&mut chunk) {
+            Ok (0) => return false,
+            Ok (length) => self.buffer.extend_from_slice (&chunk[..length]),
+            Err (ref e) if (e.kind () == io::ErrorKind::WouldBlock) || (e.kind () == io::ErrorKind::TimedOut)
+                || (e.kind () == io::ErrorKind::Interrupted) => return true,
+            Err (e) => {
+                self.logger.debug (format! ("UI client {} connection failed: {}", self.client_id, e));
+                return false
+            }
+        }
+        loop {
+            match websocket::take_frame (&mut self.buffer) {
+                Ok (None) => return true,
+                Ok (Some (Frame::Text (json))) => {
+                    if self.from_ui.try_send (FromUiMessage {client_id: self.client_id, json}).is_err () {return false}
+                },
+                Ok (Some (Frame::Ping (data))) => if !self.send (Frame::Pong (data)) {return false},
+                Ok (Some (Frame::Pong (_))) => (),
+                Ok (Some (Frame::Binary (_))) => {
+                    self.logger.warning (format! ("UI client {} sent binary data, which means nothing to the Node", self.client_id));
+                },
+                Ok (Some (Frame::Close)) => {
+                    self.send (Frame::Close);
+                    return false
+                },
+                Err (e) => {
+                    self.logger.warning (format! ("Dropping UI client {}: {}", self.client_id, e));
+                    self.send (Frame::Close);
+                    return false
+                }
+            }
+        }
+    }
+
+    fn send (&mut self, frame: Frame) -> bool {
+        self.stream.write_all (&frame.to_bytes ()).is_ok ()
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use actix::Arbiter;
+    use actix::msgs;
+    use actix::System;
+    use sub_lib::cryptde::Key;
+    use sub_lib::neighborhood::TopologyEdge;
+    use sub_lib::neighborhood::TopologyNode;
+    use sub_lib::node_addr::NodeAddr;
+    use test_utils::test_utils::make_peer_actors;
+    use test_utils::test_utils::make_peer_actors_from;
+    use test_utils::test_utils::Recorder;
+
+    struct StreamMock {
+        reads: Vec<io::Result<Vec<u8>>>,
+        written: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Read for StreamMock {
+        fn read (&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.reads.is_empty () {return Ok (0)}
+            let data = self.reads.remove (0)?;
+            buf[..data.len ()].copy_from_slice (&data);
+            Ok (data.len ())
+        }
+    }
+
+    impl Write for StreamMock {
+        fn write (&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.lock ().unwrap ().extend_from_slice (buf);
+            Ok (buf.len ())
+        }
+
+        fn flush (&mut self) -> io::Result<()> {
+            Ok (())
+        }
+    }
+
+    fn masked_frame (first_byte: u8, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = vec! (first_byte, 0x80 | payload.len () as u8, 0, 0, 0, 0);
+        bytes.extend_from_slice (payload);
+        bytes
+    }
+
+    fn start_subject (to_client: Sender<String>) -> UiGatewaySubs {
+        let addr: Addr<Syn, UiGateway> = UiGateway::new ().start ();
+        let subs = UiGateway::make_subs_from (&addr);
+        subs.bind.try_send (BindMessage {peer_actors: make_peer_actors ()}).unwrap ();
+        subs.ui_client_arrived.try_send (UiClientArrivedMsg {client_id: 1, to_client}).unwrap ();
+        subs
+    }
+
+    fn from_ui (client_id: u64, json: &str) -> FromUiMessage {
+        FromUiMessage {client_id, json: String::from (json)}
+    }
+
+    #[test]
+    fn requests_and_events_are_json_named_by_opcode () {
+        assert_eq! (serde_json::from_str::<UiRequest> ("{\"opcode\":\"shutdown\"}").unwrap (), UiRequest::Shutdown);
+        assert_eq! (serde_json::from_str::<UiRequest> ("{\"opcode\":\"set_log_level\",\"level\":\"debug\"}").unwrap (),
+            UiRequest::SetLogLevel {level: String::from ("debug"), module: None});
+        assert_eq! (serde_json::from_str::<UiRequest> ("{\"opcode\":\"set_log_level\",\"level\":\"debug\",\"module\":\"Dispatcher\"}").unwrap (),
+            UiRequest::SetLogLevel {level: String::from ("debug"), module: Some (String::from ("Dispatcher"))});
+        assert_eq! (serde_json::to_string (&UiEvent::ShuttingDown).unwrap (), String::from ("{\"opcode\":\"shutting_down\"}"));
+    }
+
+    #[test]
+    fn node_stats_come_from_the_accountant_and_go_only_to_the_ui_that_asked () {
+        let (asker_tx, asker_rx) = mpsc::channel ();
+        let (bystander_tx, bystander_rx) = mpsc::channel ();
+        let accountant = Recorder::new ();
+        let accountant_recording_arc = accountant.get_recording ();
+        thread::spawn (move || {
+            let system = System::new ("node_stats_come_from_the_accountant_and_go_only_to_the_ui_that_asked");
+            let addr: Addr<Syn, UiGateway> = UiGateway::new ().start ();
+            let subs = UiGateway::make_subs_from (&addr);
+            subs.bind.try_send (BindMessage {peer_actors: make_peer_actors_from (None, None, None, None, None, Some (accountant))}).unwrap ();
+            subs.ui_client_arrived.try_send (UiClientArrivedMsg {client_id: 1, to_client: asker_tx}).unwrap ();
+            subs.ui_client_arrived.try_send (UiClientArrivedMsg {client_id: 2, to_client: bystander_tx}).unwrap ();
+
+            subs.from_ui.try_send (from_ui (1, "{\"opcode\":\"get_node_stats\"}")).unwrap ();
+
+            system.run ();
+        });
+
+        let json = asker_rx.recv_timeout (Duration::from_millis (1000)).unwrap ();
+        assert_eq! (json, String::from ("{\"opcode\":\"node_stats\",\"bytes_relayed\":0,\"packages_relayed\":0,\"bytes_exited\":0,\"requests_served\":0,\"uptime_ms\":0}"));
+        assert_eq! (bystander_rx.recv_timeout (Duration::from_millis (100)).is_err (), true);
+        accountant_recording_arc.lock ().unwrap ().get_record::<GetNodeStatsMsg> (0);
+    }
+
+    #[test]
+    fn a_shutdown_request_tells_every_ui_and_asks_for_a_shutdown () {
+        let (first_tx, first_rx) = mpsc::channel ();
+        let (second_tx, second_rx) = mpsc::channel ();
+        let system = System::new ("a_shutdown_request_tells_every_ui_and_asks_for_a_shutdown");
+        let subs = start_subject (first_tx);
+        subs.ui_client_arrived.try_send (UiClientArrivedMsg {client_id: 2, to_client: second_tx}).unwrap ();
+
+        subs.from_ui.try_send (from_ui (2, "{\"opcode\":\"shutdown\"}")).unwrap ();
+
+        Arbiter::system ().try_send (msgs::SystemExit (0)).unwrap ();
+        system.run ();
+        assert_eq! (first_rx.try_recv (), Ok (String::from ("{\"opcode\":\"shutting_down\"}")));
+        assert_eq! (second_rx.try_recv (), Ok (String::from ("{\"opcode\":\"shutting_down\"}")));
+        assert_eq! (shutdown::shutdown_requested (), true);
+    }
+
+    #[test]
+    fn the_log_level_can_be_changed_and_bad_requests_are_answered_with_errors () {
+        let (to_client_tx, to_client_rx) = mpsc::channel ();
+        let system = System::new ("the_log_level_can_be_changed_and_bad_requests_are_answered_with_errors");
+        let subs = start_subject (to_client_tx);
+
+        // Trace is what the tests run at anyway
+        subs.from_ui.try_send (from_ui (1, "{\"opcode\":\"set_log_level\",\"level\":\"trace\"}")).unwrap ();
+        subs.from_ui.try_send (from_ui (1, "{\"opcode\":\"set_log_level\",\"level\":\"loud\"}")).unwrap ();
+        subs.from_ui.try_send (from_ui (1, "{\"opcode\":\"reboot\"}")).unwrap ();
+
+        Arbiter::system ().try_send (msgs::SystemExit (0)).unwrap ();
+        system.run ();
+        assert_eq! (to_client_rx.try_recv (), Ok (String::from ("{\"opcode\":\"log_level\",\"level\":\"trace\"}")));
+        assert_eq! (logger::log_level (), LevelFilter::Trace);
+        assert_eq! (to_client_rx.try_recv (), Ok (String::from ("{\"opcode\":\"error\",\"message\":\"'loud' isn't one of trace, debug, info, warn, error or off\"}")));
+        assert_eq! (to_client_rx.try_recv ().unwrap ().starts_with ("{\"opcode\":\"error\",\"message\":\"Not a request: "), true);
+    }
+
+    #[test]
+    fn the_log_level_can_be_changed_for_one_module_and_put_back () {
+        let (to_client_tx, to_client_rx) = mpsc::channel ();
+        let system = System::new ("the_log_level_can_be_changed_for_one_module_and_put_back");
+        let subs = start_subject (to_client_tx);
+        let module = "the_log_level_can_be_changed_for_one_module_and_put_back";
+
+        subs.from_ui.try_send (from_ui (1, &format! ("{{\"opcode\":\"set_log_level\",\"level\":\"error\",\"module\":\"{}\"}}", module))).unwrap ();
+        subs.from_ui.try_send (from_ui (1, &format! ("{{\"opcode\":\"set_log_level\",\"level\":\"loud\",\"module\":\"{}\"}}", module))).unwrap ();
+
+        Arbiter::system ().try_send (msgs::SystemExit (0)).unwrap ();
+        system.run ();
+        assert_eq! (to_client_rx.try_recv (), Ok (format! ("{{\"opcode\":\"log_level\",\"level\":\"error\",\"module\":\"{}\"}}", module)));
+        assert_eq! (logger::module_log_level (module), LevelFilter::Error);
+        assert_eq! (to_client_rx.try_recv (), Ok (String::from ("{\"opcode\":\"error\",\"message\":\"'loud' isn't one of trace, debug, info, warn, error, off or default\"}")));
+
+        let (to_client_tx, to_client_rx) = mpsc::channel ();
+        let system = System::new ("the_log_level_can_be_changed_for_one_module_and_put_back");
+        let subs = start_subject (to_client_tx);
+
+        subs.from_ui.try_send (from_ui (1, &format! ("{{\"opcode\":\"set_log_level\",\"level\":\"default\",\"module\":\"{}\"}}", module))).unwrap ();
+
+        Arbiter::system ().try_send (msgs::SystemExit (0)).unwrap ();
+        system.run ();
+        assert_eq! (to_client_rx.try_recv (), Ok (format! ("{{\"opcode\":\"log_level\",\"level\":\"default\",\"module\":\"{}\"}}", module)));
+        assert_eq! (logger::module_log_level (module), logger::log_level ());
+    }
+
+    #[test]
+    fn subscribed_uis_hear_about_traffic_until_they_unsubscribe () {
+        let (subscriber_tx, subscriber_rx) = mpsc::channel ();
+        let (bystander_tx, bystander_rx) = mpsc::channel ();
+        let (subs_tx, subs_rx) = mpsc::channel ();
+        thread::spawn (move || {
+            let system = System::new ("subscribed_uis_hear_about_traffic_until_they_unsubscribe");
+            let mut subject = UiGateway::new ();
+            subject.traffic_stats_interval_ms = 10;
+            let addr: Addr<Syn, UiGateway> = subject.start ();
+            let subs = UiGateway::make_subs_from (&addr);
+            subs.bind.try_send (BindMessage {peer_actors: make_peer_actors ()}).unwrap ();
+            subs.ui_client_arrived.try_send (UiClientArrivedMsg {client_id: 1, to_client: subscriber_tx}).unwrap ();
+            subs.ui_client_arrived.try_send (UiClientArrivedMsg {client_id: 2, to_client: bystander_tx}).unwrap ();
+            subs_tx.send (subs).unwrap ();
+            system.run ();
+        });
+        let subs = subs_rx.recv ().unwrap ();
+
+        subs.from_ui.try_send (from_ui (1, "{\"opcode\":\"subscribe_traffic_stats\"}")).unwrap ();
+        let first = subscriber_rx.recv_timeout (Duration::from_millis (1000)).unwrap ();
+        let second = subscriber_rx.recv_timeout (Duration::from_millis (1000)).unwrap ();
+        subs.from_ui.try_send (from_ui (1, "{\"opcode\":\"unsubscribe_traffic_stats\"}")).unwrap ();
+        thread::sleep (Duration::from_millis (100));
+        while subscriber_rx.try_recv ().is_ok () {}
+        thread::sleep (Duration::from_millis (100));
+
+        assert_eq! (first.starts_with ("{\"opcode\":\"traffic_stats\",\"bytes_in_per_sec\":"), true, "{}", first);
+        assert_eq! (second.starts_with ("{\"opcode\":\"traffic_stats\","), true, "{}", second);
+        assert_eq! (subscriber_rx.try_recv (), Err (TryRecvError::Empty));
+        assert_eq! (bystander_rx.try_recv (), Err (TryRecvError::Empty));
+    }
+
+    #[test]
+    fn restart_settings_become_arguments_the_way_config_file_settings_do () {
+        let settings: BTreeMap<String, Value> = serde_json::from_str ("{\"log_level\":\"debug\",\"ban\":[\"first\",\"second\"],\"padding\":true,\"gossip_interval\":5000}").unwrap ();
+
+        let result = restart_args (&settings);
+
+        assert_eq! (result, Ok (vec! ("--ban", "first", "--ban", "second", "--gossip_interval", "5000", "--log_level", "debug", "--padding", "on")
+            .into_iter ().map (String::from).collect::<Vec<String>> ()));
+    }
+
+    #[test]
+    fn bad_restart_settings_are_turned_down_without_a_restart () {
+        let (to_client_tx, to_client_rx) = mpsc::channel ();
+        let system = System::new ("bad_restart_settings_are_turned_down_without_a_restart");
+        let subs = start_subject (to_client_tx);
+
+        subs.from_ui.try_send (from_ui (1, "{\"opcode\":\"restart\",\"settings\":{\"dns_server\":\"1.1.1.1\"}}")).unwrap ();
+        subs.from_ui.try_send (from_ui (1, "{\"opcode\":\"restart\",\"settings\":{\"neighbor\":{\"first\":1}}}")).unwrap ();
+        subs.from_ui.try_send (from_ui (1, "{\"opcode\":\"restart\",\"settings\":{\"log_level\":\"loud\"}}")).unwrap ();
+        subs.from_ui.try_send (from_ui (1, "{\"opcode\":\"restart\",\"settings\":{\"data_directory\":\"/etc\"}}")).unwrap ();
+
+        Arbiter::system ().try_send (msgs::SystemExit (0)).unwrap ();
+        system.run ();
+        assert_eq! (to_client_rx.try_recv ().unwrap (), String::from ("{\"opcode\":\"error\",\"message\":\"Unknown setting 'dns_server'\"}"));
+        assert_eq! (to_client_rx.try_recv ().unwrap (), String::from ("{\"opcode\":\"error\",\"message\":\"Invalid value for setting 'neighbor': expected a string, number, boolean, or list of them\"}"));
+        assert_eq! (to_client_rx.try_recv ().unwrap ().starts_with ("{\"opcode\":\"error\",\"message\":\""), true);
+        assert_eq! (to_client_rx.try_recv ().unwrap (), String::from ("{\"opcode\":\"error\",\"message\":\"Setting 'data_directory' can't be changed by a restart\"}"));
+        assert_eq! (to_client_rx.try_recv (), Err (TryRecvError::Empty));
+        assert_eq! (shutdown::restart_settings (), None);
+    }
+
+    #[test]
+    fn the_topology_goes_to_the_ui_with_keys_in_base64 () {
+        let local_key = Key::new (&b"local"[..]);
+        let remote_key = Key::new (&b"remote"[..]);
+        let topology = NeighborhoodTopology {
+            nodes: vec! (
+                TopologyNode {public_key: local_key.clone (), node_addr_opt: None, is_local: true, reputation: 100,
+                    last_seen_ms_ago_opt: None, latency_ms_opt: None, country_opt: None},
+                TopologyNode {public_key: remote_key.clone (), node_addr_opt: Some (NodeAddr::new (&IpAddr::from_str ("1.2.3.4").unwrap (), &vec! (1234, 2345))),
+                    is_local: false, reputation: 95, last_seen_ms_ago_opt: Some (1500), latency_ms_opt: Some (40), country_opt: Some (String::from ("CA"))},
+            ),
+            edges: vec! (TopologyEdge {from: local_key, to: remote_key}),
+        };
+
+        let result = serde_json::to_string (&UiEvent::from_topology (topology)).unwrap ();
+
+        assert_eq! (result, String::from ("{\"opcode\":\"topology\",\"nodes\":[\
+            {\"public_key\":\"bG9jYWw=\",\"ip_addr\":null,\"ports\":[],\"local\":true,\"reputation\":100,\"last_seen_ms_ago\":null,\"latency_ms\":null,\"country\":null},\
+            {\"public_key\":\"cmVtb3Rl\",\"ip_addr\":\"1.2.3.4\",\"ports\":[1234,2345],\"local\":false,\"reputation\":95,\"last_seen_ms_ago\":1500,\"latency_ms\":40,\"country\":\"CA\"}],\
+            \"edges\":[{\"from\":\"bG9jYWw=\",\"to\":\"cmVtb3Rl\"}]}"));
+    }
+
+    #[test]
+    fn traffic_rates_are_per_second_over_the_time_between_snapshots () {
+        let before = TrafficTotals {bytes_in: 1000, bytes_out: 2000, bytes_relayed: 500, bytes_originated: 0, active_streams: 7, routes_in_use: 3};
+        let after = TrafficTotals {bytes_in: 4000, bytes_out: 2000, bytes_relayed: 1100, bytes_originated: 300, active_streams: 5, routes_in_use: 4};
+
+        let result = UiEvent::from_traffic_totals (&before, &after, Duration::from_millis (1500));
+        let instantly = UiEvent::from_traffic_totals (&before, &after, Duration::from_millis (0));
+
+        assert_eq! (result, UiEvent::TrafficStats {bytes_in_per_sec: 2000, bytes_out_per_sec: 0, bytes_relayed_per_sec: 400,
+            bytes_originated_per_sec: 200, active_streams: 5, routes_in_use: 4});
+        assert_eq! (instantly, UiEvent::TrafficStats {bytes_in_per_sec: 0, bytes_out_per_sec: 0, bytes_relayed_per_sec: 0,
+            bytes_originated_per_sec: 0, active_streams: 5, routes_in_use: 4});
+    }
+
+    #[test]
+    fn a_ui_that_has_gone_hears_nothing_more () {
+        let (to_client_tx, to_client_rx) = mpsc::channel ();
+        let system = System::new ("a_ui_that_has_gone_hears_nothing_more");
+        let subs = start_subject (to_client_tx);
+
+        subs.ui_client_gone.try_send (UiClientGoneMsg {client_id: 1}).unwrap ();
+        subs.from_ui.try_send (from_ui (2, "{\"opcode\":\"set_log_level\",\"level\":\"trace\"}")).unwrap ();
+
+        Arbiter::system ().try_send (msgs::SystemExit (0)).unwrap ();
+        system.run ();
+        assert_eq! (to_client_rx.try_recv (), Err (TryRecvError::Disconnected));
+    }
+
+    fn handshake (path: &str, extra_header: &str) -> Handshake {
+        Handshake::parse (&format! ("GET {} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n{}\r\n",
+            path, extra_header)).unwrap ()
+    }
+
+    #[test]
+    fn a_ui_can_present_the_token_in_the_query_or_an_authorization_header () {
+        assert_eq! (authorized (&handshake ("/?token=0123abcd", ""), "0123abcd"), true);
+        assert_eq! (authorized (&handshake ("/?client=gui&token=0123abcd", ""), "0123abcd"), true);
+        assert_eq! (authorized (&handshake ("/", "Authorization: Bearer 0123abcd\r\n"), "0123abcd"), true);
+    }
+
+    #[test]
+    fn a_ui_without_the_right_token_is_not_authorized () {
+        assert_eq! (authorized (&handshake ("/", ""), "0123abcd"), false);
+        assert_eq! (authorized (&handshake ("/?token=", ""), "0123abcd"), false);
+        assert_eq! (authorized (&handshake ("/?token=0123abce", ""), "0123abcd"), false);
+        assert_eq! (authorized (&handshake ("/", "Authorization: Basic 0123abcd\r\n"), "0123abcd"), false);
+        assert_eq! (authorized (&handshake ("/", "Authorization: Bearer 0123abce\r\n"), "0123abcd"), false);
+    }
+
+    #[test]
+    fn a_connection_passes_requests_along_sends_events_answers_pings_and_closes_when_asked () {
+        let written_arc = Arc::new (Mutex::new (vec! ()));
+        let stream = StreamMock {
+            reads: vec! (
+                Ok (masked_frame (0x81, b"{\"opcode\":\"shutdown\"}")),
+                Err (io::Error::from (io::ErrorKind::WouldBlock)),
+                Ok (masked_frame (0x89, b"ping")),
+                Ok (masked_frame (0x88, b"")),
+            ),
+            written: written_arc.clone (),
+        };
+        let (to_client_tx, to_client_rx) = mpsc::channel ();
+        to_client_tx.send (String::from ("{\"opcode\":\"shutting_down\"}")).unwrap ();
+        let system = System::new ("a_connection_passes_requests_along_sends_events_answers_pings_and_closes_when_asked");
+        let gateway = Recorder::new ();
+        let recording_arc = gateway.get_recording ();
+        let gateway_addr: Addr<Syn, Recorder> = gateway.start ();
+        let subject = UiConnection::new (stream, 3, gateway_addr.recipient::<FromUiMessage> (), to_client_rx);
+
+        subject.run ();
+
+        Arbiter::system ().try_send (msgs::SystemExit (0)).unwrap ();
+        system.run ();
+        let mut expected = Frame::Text (String::from ("{\"opcode\":\"shutting_down\"}")).to_bytes ();
+        expected.extend (Frame::Pong (b"ping".to_vec ()).to_bytes ());
+        expected.extend (Frame::Close.to_bytes ());
+        assert_eq! (written_arc.lock ().unwrap ().clone (), expected);
+        let recording = recording_arc.lock ().unwrap ();
+        let record = recording.get_record::<FromUiMessage> (0);
+        assert_eq! ((record.client_id, record.json.clone ()), (3, String::from ("{\"opcode\":\"shutdown\"}")));
+    }
+}
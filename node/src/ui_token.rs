@@ -0,0 +1,132 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use sodiumoxide::randombytes::randombytes;
+use sub_lib::private_file::create_private_file;
+
+pub const UI_TOKEN_FILENAME: &str = "ui_token";
+
+const UI_TOKEN_BYTES: usize = 32;
+
+// Anything running on the machine can reach the UI port, so a UI has to show this token before the
+// Node will listen to it. It's made up the first time the Node runs with a data directory and kept
+// there, readable only by the Node's user, for the UI to pick up.
+pub struct UiTokenStore {
+    path: PathBuf,
+}
+
+impl UiTokenStore {
+    pub fn in_data_directory (data_directory: &Path) -> UiTokenStore {
+        UiTokenStore {path: data_directory.join (UI_TOKEN_FILENAME)}
+    }
+
+    pub fn path (&self) -> &Path {
+        &self.path
+    }
+
+    // Ok (None) means no token has been saved yet
+    pub fn load (&self) -> Result<Option<String>, String> {
+        let mut contents = String::new ();
+        match File::open (&self.path).and_then (|mut file| file.read_to_string (&mut contents)) {
+            Ok (_) => (),
+            Err (ref e) if e.kind () == ErrorKind::NotFound => return Ok (None),
+            Err (e) => return Err (format! ("Couldn't read the UI token from {:?}: {}", self.path, e))
+        }
+        let token = contents.trim ();
+        if token.is_empty () {
+            return Err (format! ("{:?} should hold a UI token, but it's empty", self.path))
+        }
+        Ok (Some (String::from (token)))
+    }
+
+    pub fn save (&self, token: &str) -> Result<(), String> {
+        let result: io::Result<()> = self.path.parent ().map (|parent| fs::create_dir_all (parent)).unwrap_or (Ok (()))
+            .and_then (|_| create_private_file (&self.path))
+            .and_then (|mut file| file.write_all (format! ("{}\n", token).as_bytes ()));
+        result.map_err (|e| format! ("Couldn't save the UI token to {:?}: {}", self.path, e))
+    }
+}
+
+pub fn random_ui_token () -> String {
+    randombytes (UI_TOKEN_BYTES).iter ().map (|byte| format! ("{:02x}", byte)).collect::<Vec<String>> ().join ("")
+}
+
+// Takes as long to say no to a nearly-right token as to a completely wrong one
+pub fn tokens_match (presented: &str, token: &str) -> bool {
+    if presented.len () != token.len () {return false}
+    presented.bytes ().zip (token.bytes ()).fold (0u8, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    #[cfg (unix)]
+    use std::os::unix::fs::PermissionsExt;
+
+    fn make_subject (name: &str) -> UiTokenStore {
+        let data_directory = temp_dir ().join ("ui_token").join (name);
+        let _ = fs::remove_dir_all (&data_directory);
+        UiTokenStore::in_data_directory (&data_directory)
+    }
+
+    #[test]
+    fn a_saved_token_can_be_loaded_back () {
+        let subject = make_subject ("a_saved_token_can_be_loaded_back");
+        let before = subject.load ();
+
+        subject.save ("0123abcd").unwrap ();
+        let after = subject.load ();
+
+        assert_eq! (before, Ok (None));
+        assert_eq! (after, Ok (Some (String::from ("0123abcd"))));
+    }
+
+    #[test]
+    fn an_empty_file_is_an_error () {
+        let subject = make_subject ("an_empty_file_is_an_error");
+        fs::create_dir_all (subject.path ().parent ().unwrap ()).unwrap ();
+        File::create (subject.path ()).unwrap ().write_all (b"\n").unwrap ();
+
+        let result = subject.load ();
+
+        assert_eq! (result, Err (format! ("{:?} should hold a UI token, but it's empty", subject.path ())));
+    }
+
+    #[cfg (unix)]
+    #[test]
+    fn only_the_owner_can_read_a_saved_token () {
+        let subject = make_subject ("only_the_owner_can_read_a_saved_token");
+        fs::create_dir_all (subject.path ().parent ().unwrap ()).unwrap ();
+        File::create (subject.path ()).unwrap ();
+        fs::set_permissions (subject.path (), fs::Permissions::from_mode (0o644)).unwrap ();
+
+        subject.save ("0123abcd").unwrap ();
+
+        assert_eq! (fs::metadata (subject.path ()).unwrap ().permissions ().mode () & 0o777, 0o600);
+    }
+
+    #[test]
+    fn random_tokens_are_long_hex_strings_that_differ () {
+        let first = random_ui_token ();
+        let second = random_ui_token ();
+
+        assert_eq! (first.len (), UI_TOKEN_BYTES * 2);
+        assert_eq! (first.chars ().all (|c| c.is_digit (16)), true, "{}", first);
+        assert_ne! (first, second);
+    }
+
+    #[test]
+    fn tokens_match_only_when_they_are_the_same () {
+        assert_eq! (tokens_match ("0123abcd", "0123abcd"), true);
+        assert_eq! (tokens_match ("0123abce", "0123abcd"), false);
+        assert_eq! (tokens_match ("0123abc", "0123abcd"), false);
+        assert_eq! (tokens_match ("", "0123abcd"), false);
+    }
+}
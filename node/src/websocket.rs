@@ -0,0 +1,284 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::io;
+use std::io::Read;
+use base64;
+use sha1::Digest;
+use sha1::Sha1;
+
+// Just enough of RFC 6455 for the UI: the server's side of the handshake, and unfragmented frames.
+// Frames from clients are always masked; frames from the server never are.
+
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const MAX_HANDSHAKE_SIZE: usize = 8192;
+pub const MAX_FRAME_PAYLOAD: usize = 65536;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+#[derive (Clone, Debug, PartialEq)]
+pub struct Handshake {
+    pub path: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Handshake {
+    // Header names are compared without regard to case
+    pub fn header (&self, name: &str) -> Option<&str> {
+        self.headers.iter ()
+            .find (|&&(ref header_name, _)| header_name.to_lowercase () == name.to_lowercase ())
+            .map (|&(_, ref value)| value.as_str ())
+    }
+
+    pub fn parse (request: &str) -> Result<Handshake, String> {
+        let mut lines = request.split ("\r\n");
+        let request_line = lines.next ().unwrap_or ("");
+        let words: Vec<&str> = request_line.split (' ').collect ();
+        if words.len () != 3 || words[0] != "GET" || !words[2].starts_with ("HTTP/1.") {
+            return Err (format! ("Not a WebSocket handshake: '{}'", request_line))
+        }
+        let path = String::from (words[1]);
+        let mut headers = vec! ();
+        for line in lines.take_while (|line| !line.is_empty ()) {
+            match line.find (':') {
+                Some (index) => headers.push ((String::from (line[..index].trim ()), String::from (line[(index + 1)..].trim ()))),
+                None => return Err (format! ("Malformed header in WebSocket handshake: '{}'", line))
+            }
+        }
+        let handshake = Handshake {path, headers};
+        match handshake.header ("Upgrade") {
+            Some (ref upgrade) if upgrade.to_lowercase () == "websocket" => (),
+            _ => return Err (String::from ("WebSocket handshake doesn't ask for an upgrade to websocket"))
+        }
+        if handshake.header ("Sec-WebSocket-Key").is_none () {
+            return Err (String::from ("WebSocket handshake has no Sec-WebSocket-Key"))
+        }
+        Ok (handshake)
+    }
+
+    pub fn acceptance (&self) -> String {
+        let key = self.header ("Sec-WebSocket-Key").expect ("Handshake parsed without a key");
+        format! ("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key (key))
+    }
+}
+
+// Byte by byte, so nothing that comes after the handshake is read along with it
+pub fn read_handshake<S> (stream: &mut S) -> Result<Handshake, String> where S: Read {
+    let mut request: Vec<u8> = vec! ();
+    let mut byte = [0u8; 1];
+    while !request.ends_with (b"\r\n\r\n") {
+        if request.len () >= MAX_HANDSHAKE_SIZE {
+            return Err (format! ("WebSocket handshake is longer than {} bytes", MAX_HANDSHAKE_SIZE))
+        }
+        match stream.read (&mut byte) {
+            Ok (0) => return Err (String::from ("Connection closed during the WebSocket handshake")),
+            Ok (_) => request.push (byte[0]),
+            Err (ref e) if e.kind () == io::ErrorKind::Interrupted => (),
+            Err (e) => return Err (format! ("Couldn't read the WebSocket handshake: {}", e))
+        }
+    }
+    match String::from_utf8 (request) {
+        Ok (request) => Handshake::parse (&request),
+        Err (_) => Err (String::from ("WebSocket handshake isn't text"))
+    }
+}
+
+pub fn rejection (status: &str) -> String {
+    format! ("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)
+}
+
+pub fn accept_key (key: &str) -> String {
+    base64::encode (&Sha1::digest (format! ("{}{}", key, HANDSHAKE_GUID).as_bytes ())[..])
+}
+
+#[derive (Clone, Debug, PartialEq)]
+pub enum Frame {
+    Text (String),
+    Binary (Vec<u8>),
+    Ping (Vec<u8>),
+    Pong (Vec<u8>),
+    Close,
+}
+
+impl Frame {
+    pub fn to_bytes (&self) -> Vec<u8> {
+        match *self {
+            Frame::Text (ref text) => frame_bytes (OPCODE_TEXT, text.as_bytes ()),
+            Frame::Binary (ref data) => frame_bytes (OPCODE_BINARY, data),
+            Frame::Ping (ref data) => frame_bytes (OPCODE_PING, data),
+            Frame::Pong (ref data) => frame_bytes (OPCODE_PONG, data),
+            Frame::Close => frame_bytes (OPCODE_CLOSE, &[]),
+        }
+    }
+}
+
+// Takes the first whole frame off the front of what's been read so far. Ok (None) means it isn't
+// all there yet; Err means the client has broken the protocol, and the connection should be dropped.
+pub fn take_frame (buffer: &mut Vec<u8>) -> Result<Option<Frame>, String> {
+    if buffer.len () < 2 {return Ok (None)}
+    let fin = (buffer[0] & 0x80) != 0;
+    let opcode = buffer[0] & 0x0F;
+    if (buffer[1] & 0x80) == 0 {
+        return Err (String::from ("Client sent an unmasked frame"))
+    }
+    let (payload_length, mut offset) = match buffer[1] & 0x7F {
+        126 => {
+            if buffer.len () < 4 {return Ok (None)}
+            (((buffer[2] as u64) << 8) | (buffer[3] as u64), 4)
+        },
+        127 => {
+            if buffer.len () < 10 {return Ok (None)}
+            ((2..10).fold (0u64, |length, index| (length << 8) | (buffer[index] as u64)), 10)
+        },
+        length => (length as u64, 2)
+    };
+    if payload_length > MAX_FRAME_PAYLOAD as u64 {
+        return Err (format! ("Client sent a {}-byte frame; the limit is {}", payload_length, MAX_FRAME_PAYLOAD))
+    }
+    let payload_length = payload_length as usize;
+    if buffer.len () < offset + 4 + payload_length {return Ok (None)}
+    let mask = [buffer[offset], buffer[offset + 1], buffer[offset + 2], buffer[offset + 3]];
+    offset += 4;
+    let payload: Vec<u8> = buffer[offset..(offset + payload_length)].iter ().enumerate ()
+        .map (|(index, byte)| byte ^ mask[index % 4])
+        .collect ();
+    buffer.drain (0..(offset + payload_length));
+    if !fin || opcode == OPCODE_CONTINUATION {
+        return Err (String::from ("Client sent a fragmented message"))
+    }
+    match opcode {
+        OPCODE_TEXT => match String::from_utf8 (payload) {
+            Ok (text) => Ok (Some (Frame::Text (text))),
+            Err (_) => Err (String::from ("Client sent a text frame that isn't UTF-8"))
+        },
+        OPCODE_BINARY => Ok (Some (Frame::Binary (payload))),
+        OPCODE_CLOSE => Ok (Some (Frame::Close)),
+        OPCODE_PING => Ok (Some (Frame::Ping (payload))),
+        OPCODE_PONG => Ok (Some (Frame::Pong (payload))),
+        other => Err (format! ("Client sent a frame with unknown opcode {}", other))
+    }
+}
+
+fn frame_bytes (opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = vec! (0x80 | opcode);
+    let length = payload.len ();
+    if length < 126 {
+        bytes.push (length as u8);
+    }
+    else if length <= 0xFFFF {
+        bytes.push (126);
+        bytes.push ((length >> 8) as u8);
+        bytes.push (length as u8);
+    }
+    else {
+        bytes.push (127);
+        let length = length as u64;
+        for shift in 0..8 {
+            bytes.push ((length >> ((7 - shift) * 8)) as u8);
+        }
+    }
+    bytes.extend_from_slice (payload);
+    bytes
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    fn masked_frame (first_byte: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut bytes = vec! (first_byte);
+        if payload.len () < 126 {
+            bytes.push (0x80 | payload.len () as u8);
+        }
+        else {
+            bytes.push (0x80 | 126);
+            bytes.push ((payload.len () >> 8) as u8);
+            bytes.push (payload.len () as u8);
+        }
+        bytes.extend_from_slice (&mask);
+        bytes.extend (payload.iter ().enumerate ().map (|(index, byte)| byte ^ mask[index % 4]));
+        bytes
+    }
+
+    #[test]
+    fn the_accept_key_is_the_one_in_the_rfc () {
+        assert_eq! (accept_key ("dGhlIHNhbXBsZSBub25jZQ=="), String::from ("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+    }
+
+    #[test]
+    fn a_handshake_is_read_up_to_its_end_and_no_further () {
+        let request = "GET /ui HTTP/1.1\r\nHost: localhost:5333\r\nupgrade: WebSocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\nleftover";
+        let mut stream = request.as_bytes ();
+
+        let result = read_handshake (&mut stream).unwrap ();
+
+        assert_eq! (result.path, String::from ("/ui"));
+        assert_eq! (result.header ("sec-websocket-version"), Some ("13"));
+        assert_eq! (result.acceptance (), String::from ("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n"));
+        assert_eq! (stream, &b"leftover"[..]);
+    }
+
+    #[test]
+    fn a_request_that_is_not_an_upgrade_is_refused () {
+        let result = Handshake::parse ("GET / HTTP/1.1\r\nHost: localhost\r\nSec-WebSocket-Key: abc\r\n\r\n");
+
+        assert_eq! (result, Err (String::from ("WebSocket handshake doesn't ask for an upgrade to websocket")));
+    }
+
+    #[test]
+    fn a_connection_closed_partway_through_the_handshake_is_an_error () {
+        let mut stream = &b"GET / HTTP/1.1\r\n"[..];
+
+        let result = read_handshake (&mut stream);
+
+        assert_eq! (result, Err (String::from ("Connection closed during the WebSocket handshake")));
+    }
+
+    #[test]
+    fn frames_are_taken_only_once_they_are_all_there () {
+        let frame = masked_frame (0x81, b"{\"opcode\":\"shutdown\"}");
+        let mut buffer = frame[..5].to_vec ();
+
+        let early_result = take_frame (&mut buffer);
+        buffer.extend_from_slice (&frame[5..]);
+        buffer.extend_from_slice (&masked_frame (0x89, b"ping"));
+        let first_result = take_frame (&mut buffer);
+        let second_result = take_frame (&mut buffer);
+
+        assert_eq! (early_result, Ok (None));
+        assert_eq! (first_result, Ok (Some (Frame::Text (String::from ("{\"opcode\":\"shutdown\"}")))));
+        assert_eq! (second_result, Ok (Some (Frame::Ping (b"ping".to_vec ()))));
+        assert_eq! (buffer.is_empty (), true);
+    }
+
+    #[test]
+    fn longer_frames_carry_their_length_in_two_more_bytes () {
+        let payload: Vec<u8> = (0..300).map (|index| (index % 256) as u8).collect ();
+        let mut buffer = masked_frame (0x82, &payload);
+
+        let result = take_frame (&mut buffer);
+
+        assert_eq! (result, Ok (Some (Frame::Binary (payload.clone ()))));
+        assert_eq! (&Frame::Binary (payload.clone ()).to_bytes ()[..4], &[0x82, 126, 0x01, 0x2C]);
+    }
+
+    #[test]
+    fn unmasked_and_fragmented_frames_are_refused () {
+        let mut unmasked = vec! (0x81, 0x02, b'h', b'i');
+        let mut fragmented = masked_frame (0x01, b"hi");
+
+        assert_eq! (take_frame (&mut unmasked), Err (String::from ("Client sent an unmasked frame")));
+        assert_eq! (take_frame (&mut fragmented), Err (String::from ("Client sent a fragmented message")));
+    }
+
+    #[test]
+    fn server_frames_are_unmasked () {
+        assert_eq! (Frame::Text (String::from ("hi")).to_bytes (), vec! (0x81, 0x02, b'h', b'i'));
+        assert_eq! (Frame::Close.to_bytes (), vec! (0x88, 0x00));
+    }
+}
@@ -4,6 +4,11 @@ export SUDO_UID=$1
 export SUDO_GID=$2
 shift 2
 echo PID=$$
-$@ > /dev/null # ignore stdout to avoid overflowing the buffer
-
-
+$@ > /dev/null & # ignore stdout to avoid overflowing the buffer
+NODE_PID=$!
+# if the node dies with DNS subverted, the watcher puts DNS back
+DNS_UTILITY="$(dirname "$0")/../binaries/dns_utility"
+if [ -x "$DNS_UTILITY" ]; then
+  "$DNS_UTILITY" watch $NODE_PID > /dev/null 2>&1 &
+fi
+wait $NODE_PID
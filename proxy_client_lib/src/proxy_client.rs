@@ -12,12 +12,14 @@ use resolver_wrapper::ResolverWrapperFactoryReal;
 use stream_handler_pool::StreamHandlerPool;
 use stream_handler_pool::StreamHandlerPoolFactory;
 use stream_handler_pool::StreamHandlerPoolFactoryReal;
+use sub_lib::accountant::ReportExitServiceMessage;
 use sub_lib::cryptde::CryptDE;
 use sub_lib::hopper::ExpiredCoresPackage;
 use sub_lib::hopper::IncipientCoresPackage;
 use sub_lib::logger::Logger;
 use sub_lib::peer_actors::BindMessage;
 use sub_lib::proxy_client::ProxyClientSubs;
+use sub_lib::proxy_server::ClientRequestPayload;
 use sub_lib::tcp_wrappers::TcpStreamWrapperFactory;
 use sub_lib::tcp_wrappers::TcpStreamWrapperFactoryReal;
 use sub_lib::utils::NODE_MAILBOX_CAPACITY;
@@ -28,11 +30,15 @@ use trust_dns_resolver::config::ResolverOpts;
 
 pub struct ProxyClient {
     dns_servers: Vec<SocketAddr>,
+    max_response_size: usize,
+    // answer the local ProxyServer directly instead of sending responses back through the Hopper
+    zero_hop: bool,
     tcp_stream_wrapper_factory: Box<TcpStreamWrapperFactory>,
     resolver_wrapper_factory: Box<ResolverWrapperFactory>,
     stream_handler_pool_factory: Box<StreamHandlerPoolFactory>,
     _cryptde: &'static CryptDE,  // This is not used now, but a version of it may be used in the future when ser/de and en/decrypt are combined.
     to_hopper: Option<Recipient<Syn, IncipientCoresPackage>>,
+    to_accountant: Option<Recipient<Syn, ReportExitServiceMessage>>,
     pool: Option<Box<StreamHandlerPool>>,
     logger: Logger,
 }
@@ -48,6 +54,7 @@ impl Handler<BindMessage> for ProxyClient {
         self.logger.debug (format!("Handling BindMessage"));
         ctx.set_mailbox_capacity(NODE_MAILBOX_CAPACITY);
         self.to_hopper = Some(msg.peer_actors.hopper.from_hopper_client.clone ());
+        self.to_accountant = Some(msg.peer_actors.accountant.report_exit_service.clone ());
         let mut config = ResolverConfig::new ();
         for dns_server_ref in &self.dns_servers {
             self.logger.info (format! ("Adding DNS server: {}", dns_server_ref.ip ()));
@@ -58,8 +65,16 @@ impl Handler<BindMessage> for ProxyClient {
         }
         let opts = ResolverOpts::default ();
         let resolver = self.resolver_wrapper_factory.make(config, opts, Arbiter::handle ());
+        let response_sub = if self.zero_hop {
+            msg.peer_actors.proxy_server.from_proxy_client
+        }
+        else {
+            msg.peer_actors.hopper.from_hopper_client
+        };
         self.pool = Some (self.stream_handler_pool_factory.make (resolver,
-                                                                 self._cryptde, msg.peer_actors.hopper.from_hopper_client));
+                                                                 self._cryptde, response_sub,
+                                                                 msg.peer_actors.accountant.report_exit_service,
+                                                                 self.max_response_size));
         ()
     }
 }
@@ -68,25 +83,32 @@ impl Handler<ExpiredCoresPackage> for ProxyClient {
     type Result = ();
 
     fn handle(&mut self, msg: ExpiredCoresPackage, _ctx: &mut Self::Context) -> Self::Result {
+        let report_opt = self.exit_service_report (&msg);
         let pool = self.pool.as_mut ().expect ("StreamHandlerPool unbound");
         pool.process_package (msg);
+        if let Some (report) = report_opt {
+            self.to_accountant.as_ref ().expect ("Accountant unbound in Proxy Client").try_send (report).expect ("Accountant is dead");
+        }
         self.logger.debug (format! ("ExpiredCoresPackage handled"));
         ()
     }
 }
 
 impl ProxyClient {
-    pub fn new(cryptde: &'static CryptDE, dns_servers: Vec<SocketAddr>) -> ProxyClient {
+    pub fn new(cryptde: &'static CryptDE, dns_servers: Vec<SocketAddr>, max_response_size: usize, zero_hop: bool) -> ProxyClient {
         if dns_servers.is_empty () {
             panic! ("Proxy Client requires at least one DNS server IP address after the --dns_servers parameter")
         }
         ProxyClient {
             dns_servers,
+            max_response_size,
+            zero_hop,
             tcp_stream_wrapper_factory: Box::new(TcpStreamWrapperFactoryReal {}),
             resolver_wrapper_factory: Box::new (ResolverWrapperFactoryReal {}),
             stream_handler_pool_factory: Box::new (StreamHandlerPoolFactoryReal {}),
             _cryptde: cryptde,
             to_hopper: None,
+            to_accountant: None,
             pool: None,
             logger: Logger::new ("Proxy Client")
         }
@@ -98,6 +120,19 @@ impl ProxyClient {
             from_hopper: addr.clone ().recipient::<ExpiredCoresPackage>(),
         }
     }
+
+    // Exiting is paid for by the Node the Hopper found had signed the route. The originator key in
+    // the request proves nothing, so it's never billed. The StreamReader bills for the response.
+    fn exit_service_report (&self, package: &ExpiredCoresPackage) -> Option<ReportExitServiceMessage> {
+        match (&package.consuming_key_opt, package.neighbor_ip_opt, package.payload::<ClientRequestPayload> ()) {
+            (&Some (ref consuming_node_key), Some (neighbor_ip), Ok (ref request)) => Some (ReportExitServiceMessage {
+                consuming_node_key: consuming_node_key.clone (),
+                neighbor_ip,
+                payload_size: request.data.data.len (),
+            }),
+            _ => None
+        }
+    }
 }
 
 #[cfg(test)]
@@ -110,6 +145,7 @@ mod tests {
     use std::str::FromStr;
     use std::sync::Arc;
     use std::sync::Mutex;
+    use std::thread;
     use std::time::Duration;
     use actix::Arbiter;
     use actix::msgs;
@@ -161,15 +197,42 @@ mod tests {
         }
     }
 
+    // Answers every package through whatever response recipient ProxyClient gave it
+    struct EchoingStreamHandlerPool {
+        response_sub: Recipient<Syn, IncipientCoresPackage>,
+    }
+
+    impl StreamHandlerPool for EchoingStreamHandlerPool {
+        fn process_package(&mut self, package: ExpiredCoresPackage) {
+            self.response_sub.try_send (IncipientCoresPackage {
+                route: package.remaining_route,
+                payload: package.payload,
+                payload_destination_key: Key::new (&b"originator"[..]),
+            }).unwrap ();
+        }
+    }
+
+    struct EchoingStreamHandlerPoolFactory {}
+
+    impl StreamHandlerPoolFactory for EchoingStreamHandlerPoolFactory {
+        fn make(&self, _resolver: Box<ResolverWrapper>, _cryptde: &'static CryptDE,
+                hopper_sub: Recipient<Syn, IncipientCoresPackage>, _accountant_sub: Recipient<Syn, ReportExitServiceMessage>,
+                _max_response_size: usize) -> Box<StreamHandlerPool> {
+            Box::new (EchoingStreamHandlerPool {response_sub: hopper_sub})
+        }
+    }
+
     pub struct StreamHandlerPoolFactoryMock {
-        make_parameters: Arc<Mutex<Vec<(Box<ResolverWrapper>, &'static CryptDE, Recipient<Syn, IncipientCoresPackage>)>>>,
+        make_parameters: Arc<Mutex<Vec<(Box<ResolverWrapper>, &'static CryptDE, Recipient<Syn, IncipientCoresPackage>,
+            Recipient<Syn, ReportExitServiceMessage>, usize)>>>,
         make_results: RefCell<Vec<Box<StreamHandlerPool>>>
     }
 
     impl StreamHandlerPoolFactory for StreamHandlerPoolFactoryMock {
         fn make(&self, resolver: Box<ResolverWrapper>, cryptde: &'static CryptDE,
-                hopper_sub: Recipient<Syn, IncipientCoresPackage>) -> Box<StreamHandlerPool> {
-            self.make_parameters.lock ().unwrap ().push ((resolver, cryptde, hopper_sub));
+                hopper_sub: Recipient<Syn, IncipientCoresPackage>, accountant_sub: Recipient<Syn, ReportExitServiceMessage>,
+                max_response_size: usize) -> Box<StreamHandlerPool> {
+            self.make_parameters.lock ().unwrap ().push ((resolver, cryptde, hopper_sub, accountant_sub, max_response_size));
             self.make_results.borrow_mut ().remove (0)
         }
     }
@@ -183,7 +246,7 @@ mod tests {
         }
 
         pub fn make_parameters (self, parameters: &mut Arc<Mutex<Vec<(Box<ResolverWrapper>, &'static CryptDE,
-                Recipient<Syn, IncipientCoresPackage>)>>>) -> StreamHandlerPoolFactoryMock {
+                Recipient<Syn, IncipientCoresPackage>, Recipient<Syn, ReportExitServiceMessage>, usize)>>>) -> StreamHandlerPoolFactoryMock {
             *parameters = self.make_parameters.clone ();
             self
         }
@@ -197,7 +260,7 @@ mod tests {
     #[test]
     #[should_panic (expected = "Proxy Client requires at least one DNS server IP address after the --dns_servers parameter")]
     fn at_least_one_dns_server_must_be_provided () {
-        ProxyClient::new (cryptde(), vec! ());
+        ProxyClient::new (cryptde(), vec! (), 0, false);
     }
 
     #[test]
@@ -217,7 +280,7 @@ mod tests {
         let mut subject = ProxyClient::new (cryptde(), vec! (
             SocketAddr::from_str ("4.3.2.1:4321").unwrap (),
             SocketAddr::from_str ("5.4.3.2:5432").unwrap ()
-        ));
+        ), 1234567, false);
         subject.resolver_wrapper_factory = Box::new (resolver_wrapper_factory);
         subject.stream_handler_pool_factory = Box::new (pool_factory);
         let subject_addr: Addr<Syn, ProxyClient> = subject.start();
@@ -237,6 +300,7 @@ mod tests {
         ]);
         assert_eq! (opts, ResolverOpts::default ());
         assert_eq! (new_parameters_guard.is_empty (), true);
+        assert_eq! (pool_factory_make_parameters.lock ().unwrap ()[0].4, 1234567);
     }
 
     #[test]
@@ -277,7 +341,7 @@ mod tests {
         let tcp_stream_wrapper_factory = TcpStreamWrapperFactoryMock::new ()
             .tcp_stream_wrapper (stream);
         let system = System::new("panics_if_hopper_is_unbound");
-        let mut subject = ProxyClient::new(cryptde, dnss ());
+        let mut subject = ProxyClient::new(cryptde, dnss (), 0, false);
         subject.tcp_stream_wrapper_factory = Box::new(tcp_stream_wrapper_factory);
         let subject_addr: Addr<Syn, ProxyClient> = subject.start();
 
@@ -305,7 +369,7 @@ mod tests {
         let hopper = Recorder::new();
 
         let system = System::new("unparseable_request_results_in_log_and_no_response");
-        let peer_actors = make_peer_actors_from(None, None, Some(hopper), None, None);
+        let peer_actors = make_peer_actors_from(None, None, Some(hopper), None, None, None);
         let mut process_package_parameters = Arc::new (Mutex::new (vec! ()));
         let pool = Box::new (StreamHandlerPoolMock::new ()
                                  .process_package_parameters (&mut process_package_parameters));
@@ -315,7 +379,7 @@ mod tests {
             .lookup_ip_success (vec! (IpAddr::from_str ("4.3.2.1").unwrap ()));
         let resolver_factory = ResolverWrapperFactoryMock::new ()
             .new_result (Box::new (resolver));
-        let mut subject = ProxyClient::new(cryptde(), dnss());
+        let mut subject = ProxyClient::new(cryptde(), dnss(), 0, false);
         subject.resolver_wrapper_factory = Box::new (resolver_factory);
         subject.stream_handler_pool_factory = Box::new (pool_factory);
         let subject_addr: Addr<Syn, ProxyClient> = subject.start();
@@ -329,6 +393,136 @@ mod tests {
         assert_eq! (parameter, ExpiredCoresPackage {
             remaining_route: test_utils::make_meaningless_route(),
             payload: PlainData::new(&serde_cbor::ser::to_vec(&request.clone()).unwrap()[..]),
+            consuming_key_opt: None,
+            neighbor_ip_opt: None,
+        });
+    }
+
+    #[test]
+    fn reports_exiting_for_another_node_to_the_accountant () {
+        let request = ClientRequestPayload {
+            stream_key: SocketAddr::from_str ("1.2.3.4:5678").unwrap (),
+            last_data: false,
+            data: PlainData::new (&b"inbound data"[..]),
+            target_hostname: None,
+            target_port: 0,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: Key::new (&b"originator"[..]),
+        };
+        let mut package = ExpiredCoresPackage::new(
+            test_utils::make_meaningless_route (),
+            PlainData::new(&serde_cbor::ser::to_vec(&request).unwrap()[..])
+        );
+        package.consuming_key_opt = Some (Key::new (&b"consumer"[..]));
+        package.neighbor_ip_opt = Some (IpAddr::from_str ("2.3.4.5").unwrap ());
+        let accountant = Recorder::new ();
+        let accountant_awaiter = accountant.get_awaiter ();
+        let accountant_recording_arc = accountant.get_recording ();
+        thread::spawn (move || {
+            let system = System::new ("reports_exiting_for_another_node_to_the_accountant");
+            let peer_actors = make_peer_actors_from (None, None, None, None, None, Some (accountant));
+            let mut subject = ProxyClient::new (cryptde (), dnss (), 0, false);
+            subject.resolver_wrapper_factory = Box::new (ResolverWrapperFactoryMock::new ()
+                .new_result (Box::new (ResolverWrapperMock::new ())));
+            subject.stream_handler_pool_factory = Box::new (EchoingStreamHandlerPoolFactory {});
+            let subject_addr: Addr<Syn, ProxyClient> = subject.start ();
+            subject_addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            subject_addr.try_send (package).unwrap ();
+
+            system.run ();
+        });
+        accountant_awaiter.await_message_count (1);
+        let accountant_recording = accountant_recording_arc.lock ().unwrap ();
+        assert_eq! (accountant_recording.get_record::<ReportExitServiceMessage> (0), &ReportExitServiceMessage {
+            consuming_node_key: Key::new (&b"consumer"[..]),
+            neighbor_ip: IpAddr::from_str ("2.3.4.5").unwrap (),
+            payload_size: 12,
+        });
+    }
+
+    #[test]
+    fn does_not_bill_the_originator_named_in_a_request_nobody_proved_they_sent () {
+        let request = |data: &[u8]| ClientRequestPayload {
+            stream_key: SocketAddr::from_str ("1.2.3.4:5678").unwrap (),
+            last_data: false,
+            data: PlainData::new (data),
+            target_hostname: None,
+            target_port: 0,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: Key::new (&b"third party"[..]),
+        };
+        let unproven_package = ExpiredCoresPackage::new(
+            test_utils::make_meaningless_route (),
+            PlainData::new(&serde_cbor::ser::to_vec(&request (b"unproven")).unwrap()[..])
+        );
+        let mut proven_package = ExpiredCoresPackage::new(
+            test_utils::make_meaningless_route (),
+            PlainData::new(&serde_cbor::ser::to_vec(&request (b"proven request")).unwrap()[..])
+        );
+        proven_package.consuming_key_opt = Some (Key::new (&b"consumer"[..]));
+        proven_package.neighbor_ip_opt = Some (IpAddr::from_str ("2.3.4.5").unwrap ());
+        let accountant = Recorder::new ();
+        let accountant_awaiter = accountant.get_awaiter ();
+        let accountant_recording_arc = accountant.get_recording ();
+        thread::spawn (move || {
+            let system = System::new ("does_not_bill_the_originator_named_in_a_request_nobody_proved_they_sent");
+            let peer_actors = make_peer_actors_from (None, None, None, None, None, Some (accountant));
+            let mut subject = ProxyClient::new (cryptde (), dnss (), 0, false);
+            subject.resolver_wrapper_factory = Box::new (ResolverWrapperFactoryMock::new ()
+                .new_result (Box::new (ResolverWrapperMock::new ())));
+            subject.stream_handler_pool_factory = Box::new (EchoingStreamHandlerPoolFactory {});
+            let subject_addr: Addr<Syn, ProxyClient> = subject.start ();
+            subject_addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            subject_addr.try_send (unproven_package).unwrap ();
+            subject_addr.try_send (proven_package).unwrap ();
+
+            system.run ();
+        });
+        accountant_awaiter.await_message_count (1);
+        let accountant_recording = accountant_recording_arc.lock ().unwrap ();
+        assert_eq! (accountant_recording.len (), 1);
+        assert_eq! (accountant_recording.get_record::<ReportExitServiceMessage> (0), &ReportExitServiceMessage {
+            consuming_node_key: Key::new (&b"consumer"[..]),
+            neighbor_ip: IpAddr::from_str ("2.3.4.5").unwrap (),
+            payload_size: 14,
+        });
+    }
+
+    #[test]
+    fn zero_hop_proxy_client_answers_the_proxy_server_directly () {
+        let package = ExpiredCoresPackage::new(
+            test_utils::make_meaningless_route (),
+            PlainData::new (&b"request"[..])
+        );
+        let expected_response = IncipientCoresPackage {
+            route: test_utils::make_meaningless_route (),
+            payload: PlainData::new (&b"request"[..]),
+            payload_destination_key: Key::new (&b"originator"[..]),
+        };
+        let proxy_server = Recorder::new ();
+        let proxy_server_awaiter = proxy_server.get_awaiter ();
+        let proxy_server_recording_arc = proxy_server.get_recording ();
+        let hopper = Recorder::new ();
+        let hopper_recording_arc = hopper.get_recording ();
+        thread::spawn (move || {
+            let system = System::new ("zero_hop_proxy_client_answers_the_proxy_server_directly");
+            let peer_actors = make_peer_actors_from (Some (proxy_server), None, Some (hopper), None, None, None);
+            let mut subject = ProxyClient::new (cryptde (), dnss (), 0, true);
+            subject.resolver_wrapper_factory = Box::new (ResolverWrapperFactoryMock::new ()
+                .new_result (Box::new (ResolverWrapperMock::new ())));
+            subject.stream_handler_pool_factory = Box::new (EchoingStreamHandlerPoolFactory {});
+            let subject_addr: Addr<Syn, ProxyClient> = subject.start ();
+            subject_addr.try_send (BindMessage {peer_actors}).unwrap ();
+
+            subject_addr.try_send (package).unwrap ();
+
+            system.run ();
         });
+        proxy_server_awaiter.await_message_count (1);
+        let proxy_server_recording = proxy_server_recording_arc.lock ().unwrap ();
+        assert_eq! (proxy_server_recording.get_record::<IncipientCoresPackage> (0), &expected_response);
+        assert_eq! (hopper_recording_arc.lock ().unwrap ().len (), 0);
     }
 }
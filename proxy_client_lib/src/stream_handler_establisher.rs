@@ -9,12 +9,16 @@ use actix::Syn;
 use trust_dns_resolver::error::ResolveError;
 use trust_dns_resolver::lookup_ip::LookupIp;
 use stream_handler_pool::StreamHandlerPoolReal;
+use stream_reader::ResponseMeter;
+use stream_reader::ResponseWindow;
 use stream_reader::StreamReader;
 use stream_writer::StreamWriter;
+use sub_lib::accountant::ReportExitServiceMessage;
 use sub_lib::cryptde::StreamKey;
 use sub_lib::hopper::ExpiredCoresPackage;
 use sub_lib::hopper::IncipientCoresPackage;
 use sub_lib::logger::Logger;
+use sub_lib::proxy_client::RESPONSE_WINDOW_CHUNKS;
 use sub_lib::proxy_server::ClientRequestPayload;
 use sub_lib::tcp_wrappers::TcpStreamWrapper;
 use sub_lib::tcp_wrappers::TcpStreamWrapperFactory;
@@ -22,8 +26,10 @@ use sub_lib::tcp_wrappers::TcpStreamWrapperFactory;
 pub struct StreamHandlerEstablisher {
     pub tcp_stream_wrapper_factory: Box<TcpStreamWrapperFactory>,
     pub hopper_sub: Recipient<Syn, IncipientCoresPackage>,
-    pub stream_adder_tx: Sender<(StreamKey, StreamWriter)>,
+    pub accountant_sub_opt: Option<Recipient<Syn, ReportExitServiceMessage>>,
+    pub stream_adder_tx: Sender<(StreamKey, StreamWriter, ResponseWindow)>,
     pub stream_killer_tx: Sender<StreamKey>,
+    pub max_response_size: usize,
     pub logger: Logger
 }
 
@@ -32,8 +38,10 @@ impl StreamHandlerEstablisher {
         StreamHandlerEstablisher {
             tcp_stream_wrapper_factory: pool.tcp_stream_wrapper_factory.dup (),
             hopper_sub: pool.hopper_sub.clone (),
+            accountant_sub_opt: pool.accountant_sub_opt.clone (),
             stream_adder_tx: pool.stream_adder_tx.clone (),
             stream_killer_tx: pool.stream_killer_tx.clone (),
+            max_response_size: pool.max_response_size,
             logger: Logger::new ("Proxy Client")
         }
     }
@@ -65,23 +73,34 @@ impl StreamHandlerEstablisher {
             Ok (()) => ()
         }
         self.logger.debug (format! ("New stream set to block for reads"));
-        match self.spawn_stream_reader (package, payload, &stored_write_stream) {
+        let window = ResponseWindow::new (RESPONSE_WINDOW_CHUNKS);
+        match self.spawn_stream_reader (package, payload, &stored_write_stream, window.clone ()) {
             Err (e) => return Err (e),
             Ok (_) => ()
         }
         let stream_writer = StreamWriter::new (stored_write_stream);
         let returned_write_stream = stream_writer.clone ();
-        self.stream_adder_tx.send ((payload.stream_key, stream_writer)).expect("StreamHandlerPool died");
+        self.stream_adder_tx.send ((payload.stream_key, stream_writer, window)).expect("StreamHandlerPool died");
         Ok (returned_write_stream)
     }
 
-    fn spawn_stream_reader (&self, package: &ExpiredCoresPackage, payload: &ClientRequestPayload, write_stream: &Box<TcpStreamWrapper>) -> io::Result<()> {
+    fn spawn_stream_reader (&self, package: &ExpiredCoresPackage, payload: &ClientRequestPayload, write_stream: &Box<TcpStreamWrapper>,
+                            window: ResponseWindow) -> io::Result<()> {
         let read_stream = match write_stream.try_clone () {
             Err (e) => {self.logger.error (format! ("Could not clone stream: {}", e)); return Err (e)},
             Ok (s) => s
         };
         let framer = StreamHandlerPoolReal::framer_from_protocol (payload.protocol);
         let peer_addr = match (&read_stream).peer_addr () {Ok (a) => format! ("{}", a), Err (_) => format! ("<unknown>")};
+        // The Node that proved it opened the stream pays for what comes back on it
+        let meter_opt = match (&self.accountant_sub_opt, &package.consuming_key_opt, package.neighbor_ip_opt) {
+            (&Some (ref accountant_sub), &Some (ref consuming_node_key), Some (neighbor_ip)) => Some (ResponseMeter {
+                accountant_sub: accountant_sub.clone (),
+                consuming_node_key: consuming_node_key.clone (),
+                neighbor_ip,
+            }),
+            _ => None
+        };
         let mut stream_reader = StreamReader::new (
             payload.stream_key,
             self.hopper_sub.clone (),
@@ -91,6 +110,9 @@ impl StreamHandlerEstablisher {
             package.remaining_route.clone (),
             framer,
             payload.originator_public_key.clone (),
+            self.max_response_size,
+            window,
+            meter_opt,
         );
         self.logger.debug (format! ("Spawning StreamReader for {}", peer_addr));
         thread::spawn(move || {
@@ -129,7 +151,7 @@ mod tests {
         let (tx, rx) = mpsc::channel::<io::Result<()>> ();
         thread::spawn(move || {
             let system = System::new ("test");
-            let hopper_sub = test_utils::make_peer_actors_from (None, None, Some (hopper), None, None).hopper.from_hopper_client;
+            let hopper_sub = test_utils::make_peer_actors_from (None, None, Some (hopper), None, None, None).hopper.from_hopper_client;
             let read_stream = Box::new (TcpStreamWrapperMock::new ()
                 .peer_addr_result (Ok (SocketAddr::from_str ("1.2.3.4:5678").unwrap ()))
                 .read_buffer (vec! (0x16, 0x03, 0x03, 0x00, 0x00))
@@ -140,7 +162,7 @@ mod tests {
             let stored_write_stream: Box<TcpStreamWrapper> = Box::new(TcpStreamWrapperMock::new ()
                 .try_clone_result (Ok (read_stream)));
             let pool = StreamHandlerPoolReal::new(Box::new(ResolverWrapperMock::new()),
-                                                  cryptde(), hopper_sub);
+                                                  cryptde(), hopper_sub, 0);
             let subject = StreamHandlerEstablisher::new(&pool);
 
             let result = subject.spawn_stream_reader(
@@ -154,7 +176,8 @@ mod tests {
                     protocol: ProxyProtocol::HTTP,
                     originator_public_key: Key::new(&[]),
                 },
-                &stored_write_stream
+                &stored_write_stream,
+                ResponseWindow::new(RESPONSE_WINDOW_CHUNKS)
             );
             tx.send (result).is_ok ();
             system.run ();
@@ -176,7 +199,7 @@ mod tests {
         let (tx, rx) = mpsc::channel::<io::Result<()>> ();
         thread::spawn(move || {
             let system = System::new ("test");
-            let hopper_sub = test_utils::make_peer_actors_from (None, None, Some (hopper), None, None).hopper.from_hopper_client;
+            let hopper_sub = test_utils::make_peer_actors_from (None, None, Some (hopper), None, None, None).hopper.from_hopper_client;
             let read_stream = Box::new (TcpStreamWrapperMock::new ()
                 .peer_addr_result (Ok (SocketAddr::from_str ("1.2.3.4:5678").unwrap ()))
                 .read_buffer (b"HTTP/1.1 200 OK\r\n\r\n".to_vec ())
@@ -187,7 +210,7 @@ mod tests {
             let stored_write_stream: Box<TcpStreamWrapper> = Box::new(TcpStreamWrapperMock::new ()
                 .try_clone_result (Ok (read_stream)));
             let pool = StreamHandlerPoolReal::new(Box::new(ResolverWrapperMock::new()),
-                                                  cryptde(), hopper_sub);
+                                                  cryptde(), hopper_sub, 0);
             let subject = StreamHandlerEstablisher::new(&pool);
 
             let result = subject.spawn_stream_reader(
@@ -201,7 +224,8 @@ mod tests {
                     protocol: ProxyProtocol::TLS,
                     originator_public_key: Key::new(&[]),
                 },
-                &stored_write_stream
+                &stored_write_stream,
+                ResponseWindow::new(RESPONSE_WINDOW_CHUNKS)
             );
             tx.send (result).is_ok ();
             system.run ();
@@ -13,6 +13,7 @@ use std::sync::mpsc::Receiver;
 use actix::Arbiter;
 use futures::future::Executor;
 use futures::future::Future;
+use sub_lib::accountant::ReportExitServiceMessage;
 use sub_lib::cryptde::CryptDE;
 use sub_lib::cryptde::PlainData;
 use sub_lib::cryptde::StreamKey;
@@ -23,32 +24,42 @@ use sub_lib::http_packet_framer::HttpPacketFramer;
 use sub_lib::http_response_start_finder::HttpResponseStartFinder;
 use sub_lib::logger::Logger;
 use sub_lib::proxy_client::ClientResponsePayload;
+use sub_lib::proxy_client::ExitFailure;
 use sub_lib::proxy_server::ClientRequestPayload;
 use sub_lib::proxy_server::ProxyProtocol;
+use sub_lib::proxy_server::ResponseCreditPayload;
 use sub_lib::route::Route;
 use sub_lib::tcp_wrappers::TcpStreamWrapper;
 use sub_lib::tcp_wrappers::TcpStreamWrapperFactory;
 use sub_lib::tcp_wrappers::TcpStreamWrapperFactoryReal;
 use sub_lib::tls_framer::TlsFramer;
 use resolver_wrapper::ResolverWrapper;
+use stream_reader::ResponseWindow;
 use stream_writer::StreamWriter;
 use stream_handler_establisher::StreamHandlerEstablisher;
 use std::net::Shutdown;
 use actix::Recipient;
 use actix::Syn;
 
+// Response bodies are relayed back toward the originator in pieces no larger than this
+pub const RESPONSE_STREAMING_WINDOW: usize = 16384;
+
 pub trait StreamHandlerPool {
     fn process_package (&mut self, package: ExpiredCoresPackage);
 }
 
 pub struct StreamHandlerPoolReal {
     pub hopper_sub: Recipient<Syn, IncipientCoresPackage>,
+    // Where StreamReaders bill consuming Nodes for the responses they relay; responses go unbilled without it
+    pub accountant_sub_opt: Option<Recipient<Syn, ReportExitServiceMessage>>,
     pub stream_writers: HashMap<StreamKey, StreamWriter>,
-    pub stream_adder_tx: Sender<(StreamKey, StreamWriter)>,
-    pub stream_adder_rx: Receiver<(StreamKey, StreamWriter)>,
+    pub response_windows: HashMap<StreamKey, ResponseWindow>,
+    pub stream_adder_tx: Sender<(StreamKey, StreamWriter, ResponseWindow)>,
+    pub stream_adder_rx: Receiver<(StreamKey, StreamWriter, ResponseWindow)>,
     pub stream_killer_tx: Sender<StreamKey>,
     pub stream_killer_rx: Receiver<StreamKey>,
     pub tcp_stream_wrapper_factory: Box<TcpStreamWrapperFactory>,
+    pub max_response_size: usize,
     resolver: Box<ResolverWrapper>,
     _cryptde: &'static CryptDE, // This is not used now, but a version of it may be used in the future when ser/de and en/decrypt are combined.
     logger: Logger,
@@ -59,6 +70,9 @@ impl StreamHandlerPool for StreamHandlerPoolReal {
     fn process_package (&mut self, package: ExpiredCoresPackage) {
         self.logger.debug (format! ("Received ExpiredCoresPackage with {}-byte payload", package.payload.data.len ()));
         self.do_housekeeping ();
+        if let Ok (credit) = package.payload::<ResponseCreditPayload> () {
+            return self.grant_response_credit (credit)
+        }
         let payload = match self.extract_payload (&package) {
             Ok (p) => p,
             Err (_) => {
@@ -75,7 +89,7 @@ impl StreamHandlerPool for StreamHandlerPoolReal {
                 match StreamHandlerPoolReal::perform_write (&payload, writer_ref) {
                     Ok (_) => (),
                     Err (_) => {
-                        StreamHandlerPoolReal::send_terminating_package(package.remaining_route, &payload, &hopper_sub)
+                        StreamHandlerPoolReal::send_terminating_package(package.remaining_route, &payload, &hopper_sub, None)
                     }
                 }
             },
@@ -87,7 +101,8 @@ impl StreamHandlerPool for StreamHandlerPoolReal {
                 let mut fqdn = match &payload.target_hostname {
                     &None => {
                         self.logger.error (format! ("Cannot open new stream with key {}: no hostname supplied", payload.stream_key));
-                        StreamHandlerPoolReal::send_terminating_package(package.remaining_route, &payload, &hopper_sub);
+                        StreamHandlerPoolReal::send_terminating_package(package.remaining_route, &payload, &hopper_sub,
+                            Some (ExitFailure::MissingHostname));
                         return
                     },
                     &Some (ref s) => s.clone ()
@@ -95,13 +110,15 @@ impl StreamHandlerPool for StreamHandlerPoolReal {
                 fqdn.push('.');
                 let future = self.resolver.lookup_ip(&fqdn[..]).then(move |lookup_result| {
                     establisher.logger.debug (format! ("Resolution closure beginning"));
+                    let resolution_failed = lookup_result.is_err ();
                     let write_result = establisher.after_resolution (&payload, &package, lookup_result).and_then (|mut stream_writer| {
                         StreamHandlerPoolReal::perform_write (&payload, &mut stream_writer)
                     });
                     match write_result {
                         Ok (_) => (),
-                        Err (_) => {
-                            StreamHandlerPoolReal::send_terminating_package(package.remaining_route, &payload, &establisher.hopper_sub)
+                        Err (e) => {
+                            let failure = if resolution_failed {ExitFailure::DnsResolution} else {ExitFailure::from_error_kind (e.kind ())};
+                            StreamHandlerPoolReal::send_terminating_package(package.remaining_route, &payload, &establisher.hopper_sub, Some (failure))
                         }
                     }
                     let result: Result<(), ()> = Ok (());
@@ -116,17 +133,21 @@ impl StreamHandlerPool for StreamHandlerPoolReal {
 }
 
 impl StreamHandlerPoolReal {
-    pub fn new (resolver: Box<ResolverWrapper>, cryptde: &'static CryptDE, hopper_sub: Recipient<Syn, IncipientCoresPackage>) -> StreamHandlerPoolReal {
+    pub fn new (resolver: Box<ResolverWrapper>, cryptde: &'static CryptDE, hopper_sub: Recipient<Syn, IncipientCoresPackage>,
+                max_response_size: usize) -> StreamHandlerPoolReal {
         let (stream_killer_tx, stream_killer_rx) = mpsc::channel ();
         let (stream_adder_tx, stream_adder_rx) = mpsc::channel ();
         StreamHandlerPoolReal {
             hopper_sub,
+            accountant_sub_opt: None,
             stream_writers: HashMap::new (),
+            response_windows: HashMap::new (),
             stream_adder_tx,
             stream_adder_rx,
             stream_killer_tx,
             stream_killer_rx,
             tcp_stream_wrapper_factory: Box::new (TcpStreamWrapperFactoryReal {}),
+            max_response_size,
             resolver,
             _cryptde: cryptde,
             logger: Logger::new ("Proxy Client")
@@ -143,6 +164,7 @@ impl StreamHandlerPoolReal {
             match self.stream_killer_rx.try_recv () {
                 Err (_) => break,
                 Ok (stream_key) => {
+                    self.response_windows.remove (&stream_key);
                     match self.stream_writers.remove (&stream_key) {
                         Some (writer_ref) => self.logger.debug (format! ("Killed StreamWriter for stream to {} under key {}", writer_ref.peer_addr (), stream_key)),
                         None => self.logger.debug (format! ("Tried to kill StreamWriter for key {}, but it was not found", stream_key))
@@ -156,14 +178,22 @@ impl StreamHandlerPoolReal {
         loop {
             match self.stream_adder_rx.try_recv () {
                 Err (_) => break,
-                Ok ((stream_key, stream_writer)) => {
+                Ok ((stream_key, stream_writer, window)) => {
                     self.logger.debug (format! ("Persisting StreamWriter to {} under key {}", stream_writer.peer_addr (), stream_key));
-                    self.stream_writers.insert (stream_key, stream_writer)
+                    self.stream_writers.insert (stream_key, stream_writer);
+                    self.response_windows.insert (stream_key, window);
                 }
             };
         }
     }
 
+    fn grant_response_credit (&self, credit: ResponseCreditPayload) {
+        match self.response_windows.get (&credit.stream_key) {
+            Some (window) => window.grant (credit.credit_chunks),
+            None => self.logger.debug (format! ("Dropped response credit for unknown stream {}", credit.stream_key))
+        }
+    }
+
     fn extract_payload (&self, package: &ExpiredCoresPackage) -> io::Result<ClientRequestPayload> {
         match package.payload::<ClientRequestPayload> () {
             Err(e) => {
@@ -218,16 +248,18 @@ impl StreamHandlerPoolReal {
 
     pub fn framer_from_protocol (protocol: ProxyProtocol) -> Box<Framer> {
         match protocol {
-            ProxyProtocol::HTTP => Box::new (HttpPacketFramer::new (Box::new (HttpResponseStartFinder{}))),
+            ProxyProtocol::HTTP => Box::new (HttpPacketFramer::new_streaming (Box::new (HttpResponseStartFinder{}), RESPONSE_STREAMING_WINDOW)),
             ProxyProtocol::TLS => Box::new (TlsFramer::new ())
         }
     }
 
-    fn send_terminating_package(route: Route, request: &ClientRequestPayload, hopper_sub: &Recipient<Syn, IncipientCoresPackage>) {
+    fn send_terminating_package(route: Route, request: &ClientRequestPayload, hopper_sub: &Recipient<Syn, IncipientCoresPackage>,
+                                failure: Option<ExitFailure>) {
         let response = ClientResponsePayload {
             stream_key: request.stream_key,
             last_response: true,
             data: PlainData::new (&[]),
+            failure,
         };
         let package = IncipientCoresPackage::new (route, response,
             &request.originator_public_key);
@@ -239,15 +271,19 @@ impl StreamHandlerPoolReal {
 
 pub trait StreamHandlerPoolFactory {
     fn make (&self, resolver: Box<ResolverWrapper>, cryptde: &'static CryptDE,
-        hopper_sub: Recipient<Syn, IncipientCoresPackage>) -> Box<StreamHandlerPool>;
+        hopper_sub: Recipient<Syn, IncipientCoresPackage>, accountant_sub: Recipient<Syn, ReportExitServiceMessage>,
+        max_response_size: usize) -> Box<StreamHandlerPool>;
 }
 
 pub struct StreamHandlerPoolFactoryReal {}
 
 impl StreamHandlerPoolFactory for StreamHandlerPoolFactoryReal {
     fn make(&self, resolver: Box<ResolverWrapper>, cryptde: &'static CryptDE,
-            hopper_sub: Recipient<Syn, IncipientCoresPackage>) -> Box<StreamHandlerPool> {
-        Box::new(StreamHandlerPoolReal::new (resolver, cryptde, hopper_sub))
+            hopper_sub: Recipient<Syn, IncipientCoresPackage>, accountant_sub: Recipient<Syn, ReportExitServiceMessage>,
+            max_response_size: usize) -> Box<StreamHandlerPool> {
+        let mut pool = StreamHandlerPoolReal::new (resolver, cryptde, hopper_sub, max_response_size);
+        pool.accountant_sub_opt = Some (accountant_sub);
+        Box::new(pool)
     }
 }
 
@@ -260,6 +296,7 @@ mod tests {
     use std::sync::Arc;
     use std::sync::Mutex;
     use std::thread;
+    use std::time::Duration;
     use actix::System;
     use serde_cbor;
     use trust_dns_resolver::error::ResolveError;
@@ -285,11 +322,11 @@ mod tests {
         thread::spawn (move || {
             let system = System::new("test");
             let hopper_sub =
-                test_utils::make_peer_actors_from(None, None, Some (hopper), None, None).hopper.from_hopper_client;
+                test_utils::make_peer_actors_from(None, None, Some (hopper), None, None, None).hopper.from_hopper_client;
             let package = ExpiredCoresPackage::new (test_utils::make_meaningless_route (),
                 PlainData::new (&b"invalid"[..]));
             let mut subject = StreamHandlerPoolReal::new (Box::new (ResolverWrapperMock::new ()),
-                                                         cryptde(), hopper_sub);
+                                                         cryptde(), hopper_sub, 0);
 
             subject.process_package(package);
 
@@ -316,7 +353,7 @@ mod tests {
         let _system = System::new("test");
         let hopper = Recorder::new ();
         let hopper_sub =
-            test_utils::make_peer_actors_from(None, None, Some (hopper), None, None).hopper.from_hopper_client;
+            test_utils::make_peer_actors_from(None, None, Some (hopper), None, None, None).hopper.from_hopper_client;
         let mut write_parameters = Arc::new (Mutex::new (vec! ()));
         let mut shutdown_parameters = Arc::new (Mutex::new (vec! ()));
         let write_stream = TcpStreamWrapperMock::new ()
@@ -325,7 +362,7 @@ mod tests {
             .write_result (Ok (123))
             .shutdown_parameters (&mut shutdown_parameters);
         let mut subject = StreamHandlerPoolReal::new (Box::new (ResolverWrapperMock::new ()),
-                                                      cryptde(), hopper_sub);
+                                                      cryptde(), hopper_sub, 0);
         subject.stream_writers.insert (client_request_payload.stream_key,
                                        StreamWriter::new (Box::new (write_stream)));
 
@@ -335,6 +372,28 @@ mod tests {
         assert_eq! (shutdown_parameters.lock ().unwrap ().len (), 0);
     }
 
+    #[test]
+    fn response_credit_opens_the_response_window_of_its_stream () {
+        let stream_key = SocketAddr::from_str ("1.2.3.4:5678").unwrap ();
+        let credit = ResponseCreditPayload {stream_key, credit_chunks: 2};
+        let package = ExpiredCoresPackage::new (test_utils::make_meaningless_route (),
+                                                PlainData::new (&(serde_cbor::ser::to_vec (&credit).unwrap ())[..]));
+        let _system = System::new("test");
+        let hopper = Recorder::new ();
+        let hopper_sub =
+            test_utils::make_peer_actors_from(None, None, Some (hopper), None, None, None).hopper.from_hopper_client;
+        let window = ResponseWindow::new (0);
+        let mut subject = StreamHandlerPoolReal::new (Box::new (ResolverWrapperMock::new ()),
+                                                      cryptde(), hopper_sub, 0);
+        subject.response_windows.insert (stream_key, window.clone ());
+
+        subject.process_package(package);
+
+        assert_eq! (window.take (Duration::from_millis (10)), true);
+        assert_eq! (window.take (Duration::from_millis (10)), true);
+        assert_eq! (window.take (Duration::from_millis (10)), false);
+    }
+
     #[test]
     fn terminal_payload_will_close_existing_connection () {
         let client_request_payload = ClientRequestPayload {
@@ -351,7 +410,7 @@ mod tests {
         let _system = System::new("test");
         let hopper = Recorder::new ();
         let hopper_sub =
-            test_utils::make_peer_actors_from(None, None, Some (hopper), None, None).hopper.from_hopper_client;
+            test_utils::make_peer_actors_from(None, None, Some (hopper), None, None, None).hopper.from_hopper_client;
         let mut write_parameters = Arc::new (Mutex::new (vec! ()));
         let mut shutdown_parameters = Arc::new (Mutex::new (vec! ()));
         let write_stream = TcpStreamWrapperMock::new ()
@@ -361,7 +420,7 @@ mod tests {
             .shutdown_parameters (&mut shutdown_parameters)
             .shutdown_result (Ok (()));
         let mut subject = StreamHandlerPoolReal::new (Box::new (ResolverWrapperMock::new ()),
-                                                      cryptde(), hopper_sub);
+                                                      cryptde(), hopper_sub, 0);
         subject.stream_writers.insert (client_request_payload.stream_key,
            StreamWriter::new (Box::new (write_stream)));
 
@@ -391,12 +450,12 @@ mod tests {
                                                    PlainData::new(&(serde_cbor::ser::to_vec(&client_request_payload).unwrap())[..]));
             let system = System::new("test");
             let hopper_sub =
-                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None).hopper.from_hopper_client;
+                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None, None).hopper.from_hopper_client;
             let stream = TcpStreamWrapperMock::new()
                 .peer_addr_result(Ok(SocketAddr::from_str("2.3.4.5:80").unwrap()))
                 .write_result(Err(Error::from(ErrorKind::BrokenPipe)));
             let mut subject = StreamHandlerPoolReal::new(Box::new(ResolverWrapperMock::new()),
-                                                         cryptde(), hopper_sub);
+                                                         cryptde(), hopper_sub, 0);
             subject.stream_writers.insert(client_request_payload.stream_key,
                                           StreamWriter::new(Box::new(stream)));
 
@@ -432,7 +491,7 @@ mod tests {
                                                    PlainData::new(&(serde_cbor::ser::to_vec(&client_request_payload).unwrap())[..]));
             let system = System::new("test");
             let hopper_sub =
-                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None)
+                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None, None)
                     .hopper.from_hopper_client;
             let resolver = ResolverWrapperMock::new()
                 .lookup_ip_success(vec!(IpAddr::from_str("2.3.4.5").unwrap()));
@@ -450,7 +509,7 @@ mod tests {
             let stream_factory = TcpStreamWrapperFactoryMock::new()
                 .tcp_stream_wrapper(write_stream);
             let mut subject = StreamHandlerPoolReal::new(Box::new(resolver),
-                                                         cryptde(), hopper_sub);
+                                                         cryptde(), hopper_sub, 0);
             subject.tcp_stream_wrapper_factory = Box::new(stream_factory);
 
             subject.process_package(package);
@@ -485,7 +544,7 @@ mod tests {
                                                    PlainData::new(&(serde_cbor::ser::to_vec(&client_request_payload).unwrap())[..]));
             let system = System::new("test");
             let hopper_sub =
-                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None)
+                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None, None)
                     .hopper.from_hopper_client;
             let resolver = ResolverWrapperMock::new()
                 .lookup_ip_success(vec!(IpAddr::from_str("2.3.4.5").unwrap()));
@@ -494,7 +553,7 @@ mod tests {
             let stream_factory = TcpStreamWrapperFactoryMock::new()
                 .tcp_stream_wrapper(stream);
             let mut subject = StreamHandlerPoolReal::new(Box::new(resolver),
-                                                         cryptde(), hopper_sub);
+                                                         cryptde(), hopper_sub, 0);
             subject.tcp_stream_wrapper_factory = Box::new(stream_factory);
 
             subject.process_package(package);
@@ -536,7 +595,7 @@ mod tests {
                                                    PlainData::new(&(serde_cbor::ser::to_vec(&client_request_payload).unwrap())[..]));
             let system = System::new("test");
             let hopper_sub =
-                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None)
+                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None, None)
                     .hopper.from_hopper_client;
             let resolver = ResolverWrapperMock::new()
                 .lookup_ip_parameters(&lookup_ip_parameters)
@@ -559,7 +618,7 @@ mod tests {
             let stream_factory = TcpStreamWrapperFactoryMock::new()
                 .tcp_stream_wrapper(stream);
             let mut subject = StreamHandlerPoolReal::new(Box::new(resolver),
-                                                         cryptde(), hopper_sub);
+                                                         cryptde(), hopper_sub, 0);
             subject.tcp_stream_wrapper_factory = Box::new(stream_factory);
 
             subject.process_package(package);
@@ -580,6 +639,7 @@ mod tests {
                 stream_key: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
                 last_response: false,
                 data: PlainData::new (&b"HTTP/1.1 200 OK\r\n\r\n"[..]),
+                failure: None,
             },
             &Key::new(&b"men's souls"[..])
         ));
@@ -605,7 +665,7 @@ mod tests {
                                                    PlainData::new(&(serde_cbor::ser::to_vec(&client_request_payload).unwrap())[..]));
             let system = System::new("test");
             let hopper_sub =
-                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None)
+                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None, None)
                     .hopper.from_hopper_client;
             let resolver = ResolverWrapperMock::new()
                 .lookup_ip_success(vec!(IpAddr::from_str("2.3.4.5").unwrap(), IpAddr::from_str("3.4.5.6").unwrap()));
@@ -615,7 +675,7 @@ mod tests {
             let stream_factory = TcpStreamWrapperFactoryMock::new()
                 .tcp_stream_wrapper(write_stream);
             let mut subject = StreamHandlerPoolReal::new(Box::new(resolver),
-                                                         cryptde(), hopper_sub);
+                                                         cryptde(), hopper_sub, 0);
             subject.tcp_stream_wrapper_factory = Box::new(stream_factory);
 
             subject.process_package(package);
@@ -627,6 +687,7 @@ mod tests {
         let record = hopper_recording.get_record::<IncipientCoresPackage> (0);
         let client_response_payload = serde_cbor::de::from_slice::<ClientResponsePayload> (&record.payload.data[..]).unwrap ();
         assert_eq! (client_response_payload.last_response, true);
+        assert_eq! (client_response_payload.failure, Some (ExitFailure::ConnectionFailed));
         TestLogHandler::new ().await_log_containing ("ERROR: Proxy Client: Could not connect to any of the IP addresses supplied for that.try: [\"2.3.4.5:80\", \"3.4.5.6:80\"]", 1000);
     }
 
@@ -650,7 +711,7 @@ mod tests {
                                                    PlainData::new(&(serde_cbor::ser::to_vec(&client_request_payload).unwrap())[..]));
             let system = System::new("test");
             let hopper_sub =
-                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None)
+                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None, None)
                     .hopper.from_hopper_client;
             let resolver = ResolverWrapperMock::new()
                 .lookup_ip_success(vec!(IpAddr::from_str("2.3.4.5").unwrap()));
@@ -661,7 +722,7 @@ mod tests {
             let stream_factory = TcpStreamWrapperFactoryMock::new()
                 .tcp_stream_wrapper(write_stream);
             let mut subject = StreamHandlerPoolReal::new(Box::new(resolver),
-                                                         cryptde(), hopper_sub);
+                                                         cryptde(), hopper_sub, 0);
             subject.tcp_stream_wrapper_factory = Box::new(stream_factory);
 
             subject.process_package(package);
@@ -696,7 +757,7 @@ mod tests {
                                                    PlainData::new(&(serde_cbor::ser::to_vec(&client_request_payload).unwrap())[..]));
             let system = System::new("test");
             let hopper_sub =
-                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None)
+                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None, None)
                     .hopper.from_hopper_client;
             let resolver = ResolverWrapperMock::new()
                 .lookup_ip_success(vec!(IpAddr::from_str("2.3.4.5").unwrap()));
@@ -707,7 +768,7 @@ mod tests {
             let stream_factory = TcpStreamWrapperFactoryMock::new()
                 .tcp_stream_wrapper(write_stream);
             let mut subject = StreamHandlerPoolReal::new(Box::new(resolver),
-                                                         cryptde(), hopper_sub);
+                                                         cryptde(), hopper_sub, 0);
             subject.tcp_stream_wrapper_factory = Box::new(stream_factory);
 
             subject.process_package(package);
@@ -743,14 +804,14 @@ mod tests {
                                                    PlainData::new(&(serde_cbor::ser::to_vec(&client_request_payload).unwrap())[..]));
             let system = System::new("test");
             let hopper_sub =
-                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None)
+                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None, None)
                     .hopper.from_hopper_client;
             let mut lookup_ip_parameters = Arc::new(Mutex::new(vec!()));
             let resolver = ResolverWrapperMock::new()
                 .lookup_ip_parameters(&mut lookup_ip_parameters)
                 .lookup_ip_failure(ResolveError::from(ResolveErrorKind::Io));
             let mut subject = StreamHandlerPoolReal::new(Box::new(resolver),
-                                                         cryptde(), hopper_sub);
+                                                         cryptde(), hopper_sub, 0);
 
             subject.process_package(package);
 
@@ -765,6 +826,7 @@ mod tests {
             stream_key,
             last_response: true,
             data: PlainData::new (&[]),
+            failure: Some (ExitFailure::DnsResolution),
         });
     }
 
@@ -789,7 +851,7 @@ mod tests {
                                                    PlainData::new(&(serde_cbor::ser::to_vec(&client_request_payload).unwrap())[..]));
             let system = System::new("test");
             let hopper_sub =
-                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None)
+                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None, None)
                     .hopper.from_hopper_client;
             let resolver = ResolverWrapperMock::new()
                 .lookup_ip_success(vec!(IpAddr::from_str("2.3.4.5").unwrap()));
@@ -802,7 +864,7 @@ mod tests {
             let stream_factory = TcpStreamWrapperFactoryMock::new()
                 .tcp_stream_wrapper(write_stream);
             let mut subject = StreamHandlerPoolReal::new(Box::new(resolver),
-                                                         cryptde, hopper_sub);
+                                                         cryptde, hopper_sub, 0);
             subject.tcp_stream_wrapper_factory = Box::new(stream_factory);
 
             subject.process_package(package);
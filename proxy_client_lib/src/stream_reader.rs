@@ -1,23 +1,75 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::net::IpAddr;
 use std::net::Shutdown;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
 use std::sync::mpsc::Sender;
 use std::thread;
 use std::time::Duration;
+use std::time::Instant;
 use actix::Recipient;
 use actix::Syn;
+use sub_lib::accountant::ReportExitServiceMessage;
 use sub_lib::cryptde::Key;
 use sub_lib::cryptde::PlainData;
 use sub_lib::cryptde::StreamKey;
+use sub_lib::framer::FramedChunk;
 use sub_lib::framer::Framer;
 use sub_lib::hopper::IncipientCoresPackage;
 use sub_lib::logger::Logger;
 use sub_lib::proxy_client::ClientResponsePayload;
+use sub_lib::proxy_client::ExitFailure;
 use sub_lib::route::Route;
 use sub_lib::tcp_wrappers::TcpStreamWrapper;
 use sub_lib::utils::indicates_dead_stream;
 use sub_lib::utils::indicates_timeout;
 use sub_lib::utils::to_string;
 
+// An originating Node that has returned no credit for this long has gone away or stopped reading
+pub const RESPONSE_CREDIT_TIMEOUT_MS: u64 = 60000;
+
+// The response chunks a stream's StreamReader may still send before it has to wait for the
+// originating Node to return credit. Shared with the StreamHandlerPool, which hands the credit in.
+#[derive (Clone)]
+pub struct ResponseWindow {
+    credit: Arc<(Mutex<u32>, Condvar)>,
+}
+
+impl ResponseWindow {
+    pub fn new (credit_chunks: u32) -> ResponseWindow {
+        ResponseWindow {credit: Arc::new ((Mutex::new (credit_chunks), Condvar::new ()))}
+    }
+
+    pub fn grant (&self, credit_chunks: u32) {
+        let &(ref credit, ref credit_arrived) = &*self.credit;
+        *credit.lock ().expect ("ResponseWindow poisoned") += credit_chunks;
+        credit_arrived.notify_one ();
+    }
+
+    // Waits for credit to send one chunk; false if none came within the timeout
+    pub fn take (&self, timeout: Duration) -> bool {
+        let &(ref credit, ref credit_arrived) = &*self.credit;
+        let deadline = Instant::now () + timeout;
+        let mut available = credit.lock ().expect ("ResponseWindow poisoned");
+        while *available == 0 {
+            let now = Instant::now ();
+            if now >= deadline {return false}
+            available = credit_arrived.wait_timeout (available, deadline - now).expect ("ResponseWindow poisoned").0;
+        }
+        *available -= 1;
+        true
+    }
+}
+
+// Bills the Node that proved it opened a stream for the response bytes relayed back to it
+#[derive (Clone)]
+pub struct ResponseMeter {
+    pub accountant_sub: Recipient<Syn, ReportExitServiceMessage>,
+    pub consuming_node_key: Key,
+    pub neighbor_ip: IpAddr,
+}
+
 pub struct StreamReader {
     stream_key: StreamKey,
     hopper_sub: Recipient<Syn, IncipientCoresPackage>,
@@ -27,6 +79,10 @@ pub struct StreamReader {
     remaining_route: Route,
     framer: Box<Framer>,
     originator_public_key: Key,
+    max_response_size: usize,
+    bytes_relayed: usize,
+    window: ResponseWindow,
+    meter_opt: Option<ResponseMeter>,
     logger: Logger,
 }
 
@@ -34,7 +90,8 @@ impl StreamReader {
 
     pub fn new (stream_key: StreamKey, hopper_sub: Recipient<Syn, IncipientCoresPackage>,
         stream: Box<TcpStreamWrapper>, stream_killer: Sender<StreamKey>, peer_addr: String,
-        remaining_route: Route, framer: Box<Framer>, originator_public_key: Key) -> StreamReader {
+        remaining_route: Route, framer: Box<Framer>, originator_public_key: Key, max_response_size: usize,
+        window: ResponseWindow, meter_opt: Option<ResponseMeter>) -> StreamReader {
         StreamReader {
             stream_key,
             hopper_sub,
@@ -44,6 +101,10 @@ impl StreamReader {
             remaining_route,
             framer,
             originator_public_key,
+            max_response_size,
+            bytes_relayed: 0,
+            window,
+            meter_opt,
             logger: Logger::new ("Proxy Client"),
         }
     }
@@ -80,7 +141,7 @@ impl StreamReader {
                 }
                 if indicates_dead_stream(e.kind ()) {
                     self.logger.debug (format! ("Stream from {} was closed: {}", self.peer_addr, e));
-                    self.send_cores_response (self.stream_key, PlainData::new (&[]), true);
+                    self.send_cores_response (self.stream_key, PlainData::new (&[]), true, None);
                     self.stream_killer.send (self.stream_key).is_ok ();
                     false
                 }
@@ -98,12 +159,23 @@ impl StreamReader {
                     self.logger.debug (format! ("Framed {}-byte {} response chunk, '{}'", response_chunk.chunk.len (),
                                                 if response_chunk.last_chunk {"final"} else {"non-final"},
                                                 to_string (&response_chunk.chunk)));
+                    let (chunk, last_chunk, truncated) = self.apply_size_limit (response_chunk);
+                    // Until credit comes back, the server's data waits in the TCP connection
+                    if !last_chunk && !self.window.take (Duration::from_millis (RESPONSE_CREDIT_TIMEOUT_MS)) {
+                        self.logger.warning (format! ("No response credit came back for the stream from {} within {}ms; closing it",
+                                                      self.peer_addr, RESPONSE_CREDIT_TIMEOUT_MS));
+                        self.stream.shutdown (Shutdown::Both).is_ok ();
+                        self.stream_killer.send (self.stream_key).is_ok ();
+                        return false;
+                    }
                     self.send_cores_response(
                         self.stream_key,
-                        PlainData::new (&response_chunk.chunk[..]),
-                        response_chunk.last_chunk
+                        PlainData::new (&chunk[..]),
+                        last_chunk,
+                        if truncated {Some (ExitFailure::ResponseTruncated)} else {None}
                     );
-                    if response_chunk.last_chunk {
+                    self.report_exit_service (chunk.len ());
+                    if last_chunk {
                         self.stream.shutdown (Shutdown::Both).is_ok ();
                         self.stream_killer.send (self.stream_key).is_ok ();
                         return false;
@@ -116,11 +188,39 @@ impl StreamReader {
         }
     }
 
-    fn send_cores_response(&self, stream_key: StreamKey, response_data: PlainData, last_response: bool) {
+    // The last chunk of a truncated response says so, so the originator knows it didn't get it all
+    fn apply_size_limit (&mut self, response_chunk: FramedChunk) -> (Vec<u8>, bool, bool) {
+        let mut chunk = response_chunk.chunk;
+        let mut last_chunk = response_chunk.last_chunk;
+        let mut truncated = false;
+        if (self.max_response_size > 0) && (self.bytes_relayed + chunk.len () > self.max_response_size) {
+            self.logger.warning (format! ("Response from {} exceeded the maximum response size of {} bytes; truncating",
+                                          self.peer_addr, self.max_response_size));
+            chunk.truncate (self.max_response_size - self.bytes_relayed);
+            last_chunk = true;
+            truncated = true;
+        }
+        self.bytes_relayed += chunk.len ();
+        (chunk, last_chunk, truncated)
+    }
+
+    fn report_exit_service (&self, payload_size: usize) {
+        if payload_size == 0 {return}
+        if let Some (ref meter) = self.meter_opt {
+            meter.accountant_sub.try_send (ReportExitServiceMessage {
+                consuming_node_key: meter.consuming_node_key.clone (),
+                neighbor_ip: meter.neighbor_ip,
+                payload_size,
+            }).expect ("Accountant is dead");
+        }
+    }
+
+    fn send_cores_response(&self, stream_key: StreamKey, response_data: PlainData, last_response: bool, failure: Option<ExitFailure>) {
         let response_payload = ClientResponsePayload {
             stream_key,
             last_response,
-            data: response_data
+            data: response_data,
+            failure
         };
         let incipient_cores_package =
             IncipientCoresPackage::new (self.remaining_route.clone (),
@@ -140,11 +240,13 @@ mod tests {
     use std::sync::mpsc;
     use actix::System;
     use serde_cbor;
-    use sub_lib::framer::FramedChunk;
     use sub_lib::http_packet_framer::HttpPacketFramer;
     use sub_lib::http_response_start_finder::HttpResponseStartFinder;
+    use sub_lib::proxy_client::RESPONSE_WINDOW_CHUNKS;
     use test_utils::test_utils;
+    use test_utils::test_utils::init_test_logging;
     use test_utils::test_utils::Recorder;
+    use test_utils::test_utils::TestLogHandler;
     use local_test_utils::TcpStreamWrapperMock;
     use std::io::ErrorKind;
 
@@ -177,7 +279,7 @@ mod tests {
 
         thread::spawn(move || {
             let system = System::new("test");
-            let hopper_sub = test_utils::make_peer_actors_from(None, None, Some(hopper), None, None).hopper.from_hopper_client;
+            let hopper_sub = test_utils::make_peer_actors_from(None, None, Some(hopper), None, None, None).hopper.from_hopper_client;
             let mut subject = StreamReader {
                 stream_key,
                 hopper_sub,
@@ -187,6 +289,10 @@ mod tests {
                 remaining_route,
                 framer,
                 originator_public_key,
+                max_response_size: 0,
+                bytes_relayed: 0,
+                window: ResponseWindow::new(RESPONSE_WINDOW_CHUNKS),
+                meter_opt: None,
                 logger
             };
 
@@ -208,6 +314,7 @@ mod tests {
                 stream_key,
                 last_response: true,
                 data: PlainData::new(&[]),
+                failure: None,
             }).unwrap()[..]),
             payload_destination_key: Key::new(&b"men's souls"[..]),
         });
@@ -221,7 +328,7 @@ mod tests {
         thread::spawn(move || {
             let system = System::new("test");
             let hopper_sub =
-                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None)
+                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None, None)
                     .hopper.from_hopper_client;
             let stream = TcpStreamWrapperMock::new()
                 .peer_addr_result(Ok(SocketAddr::from_str("2.3.4.5:80").unwrap()))
@@ -242,6 +349,10 @@ mod tests {
                 remaining_route: test_utils::make_meaningless_route(),
                 framer: Box::new(HttpPacketFramer::new(Box::new(HttpResponseStartFinder {}))),
                 originator_public_key: Key::new(&b"abcd"[..]),
+                max_response_size: 0,
+                bytes_relayed: 0,
+                window: ResponseWindow::new(RESPONSE_WINDOW_CHUNKS),
+                meter_opt: None,
                 logger: Logger::new("test"),
             };
 
@@ -258,6 +369,7 @@ mod tests {
                 stream_key: SocketAddr::from_str("1.2.3.4:80").unwrap(),
                 last_response: false,
                 data: PlainData::new(&b"HTTP/1.1 200 OK\r\n\r\n"[..]),
+                failure: None,
             },
             &Key::new(&b"abcd"[..])
         ));
@@ -267,6 +379,7 @@ mod tests {
                 stream_key: SocketAddr::from_str("1.2.3.4:80").unwrap(),
                 last_response: false,
                 data: PlainData::new(&b"HTTP/1.1 404 File not found\r\n\r\n"[..]),
+                failure: None,
             },
             &Key::new(&b"abcd"[..])
         ));
@@ -276,6 +389,7 @@ mod tests {
                 stream_key: SocketAddr::from_str("1.2.3.4:80").unwrap(),
                 last_response: false,
                 data: PlainData::new(&b"HTTP/1.1 503 Server error\r\n\r\n"[..]),
+                failure: None,
             },
             &Key::new(&b"abcd"[..])
         ));
@@ -285,8 +399,195 @@ mod tests {
                 stream_key: SocketAddr::from_str("1.2.3.4:80").unwrap(),
                 last_response: true,
                 data: PlainData::new(&b""[..]),
+                failure: None,
             },
             &Key::new(&b"abcd"[..])
         ));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn stream_reader_truncates_response_that_exceeds_maximum_size_and_closes_stream() {
+        init_test_logging();
+        let hopper = Recorder::new();
+        let awaiter = hopper.get_awaiter();
+        let hopper_recording_arc = hopper.get_recording();
+        let mut shutdown_parameters = Arc::new(Mutex::new(vec!()));
+        let stream = TcpStreamWrapperMock::new()
+            .read_buffer(Vec::from(&b"HTTP/1.1 200 OK\r\n\r\nHTTP/1.1 404 File not found\r\n\r\n"[..]))
+            .read_result(Ok(b"HTTP/1.1 200 OK\r\n\r\nHTTP/1.1 404 File not found\r\n\r\n".len()))
+            .shutdown_parameters(&mut shutdown_parameters)
+            .shutdown_result(Ok(()));
+        let (stream_killer, rx) = mpsc::channel::<StreamKey>();
+        thread::spawn(move || {
+            let system = System::new("test");
+            let hopper_sub =
+                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None, None)
+                    .hopper.from_hopper_client;
+            let mut subject = StreamReader::new(
+                SocketAddr::from_str("1.2.3.4:80").unwrap(),
+                hopper_sub,
+                Box::new(stream),
+                stream_killer,
+                String::from("Peer Address"),
+                test_utils::make_meaningless_route(),
+                Box::new(HttpPacketFramer::new(Box::new(HttpResponseStartFinder {}))),
+                Key::new(&b"abcd"[..]),
+                24,
+                ResponseWindow::new(RESPONSE_WINDOW_CHUNKS),
+                None,
+            );
+
+            subject.run();
+
+            system.run();
+        });
+
+        awaiter.await_message_count(2);
+        let kill_stream_key = rx.recv().unwrap();
+        assert_eq!(kill_stream_key, SocketAddr::from_str("1.2.3.4:80").unwrap());
+        assert_eq!(shutdown_parameters.lock().unwrap()[0], Shutdown::Both);
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(hopper_recording.get_record::<IncipientCoresPackage>(0), &IncipientCoresPackage::new(
+            test_utils::make_meaningless_route(),
+            ClientResponsePayload {
+                stream_key: SocketAddr::from_str("1.2.3.4:80").unwrap(),
+                last_response: false,
+                data: PlainData::new(&b"HTTP/1.1 200 OK\r\n\r\n"[..]),
+                failure: None,
+            },
+            &Key::new(&b"abcd"[..])
+        ));
+        assert_eq!(hopper_recording.get_record::<IncipientCoresPackage>(1), &IncipientCoresPackage::new(
+            test_utils::make_meaningless_route(),
+            ClientResponsePayload {
+                stream_key: SocketAddr::from_str("1.2.3.4:80").unwrap(),
+                last_response: true,
+                data: PlainData::new(&b"HTTP/"[..]),
+                failure: Some(ExitFailure::ResponseTruncated),
+            },
+            &Key::new(&b"abcd"[..])
+        ));
+        assert_eq!(hopper_recording.len(), 2);
+        TestLogHandler::new().exists_log_containing("WARN: Proxy Client: Response from Peer Address exceeded the maximum response size of 24 bytes; truncating");
+    }
+
+    #[test]
+    fn stream_reader_bills_the_consuming_node_for_the_response_bytes_it_relays() {
+        let accountant = Recorder::new();
+        let awaiter = accountant.get_awaiter();
+        let accountant_recording_arc = accountant.get_recording();
+        thread::spawn(move || {
+            let system = System::new("test");
+            let peer_actors = test_utils::make_peer_actors_from(None, None, None, None, None, Some(accountant));
+            let stream = TcpStreamWrapperMock::new()
+                .read_buffer(Vec::from(&b"HTTP/1.1 200 OK\r\n\r\nHTTP/1.1 404 File not found\r\n\r\n"[..]))
+                .read_result(Ok(b"HTTP/1.1 200 OK\r\n\r\nHTTP/1.1 404 File not found\r\n\r\n".len()))
+                .read_result(Err(Error::from(ErrorKind::BrokenPipe)));
+            let (stream_killer, _) = mpsc::channel::<StreamKey>();
+            let mut subject = StreamReader::new(
+                SocketAddr::from_str("1.2.3.4:80").unwrap(),
+                peer_actors.hopper.from_hopper_client,
+                Box::new(stream),
+                stream_killer,
+                String::from("Peer Address"),
+                test_utils::make_meaningless_route(),
+                Box::new(HttpPacketFramer::new(Box::new(HttpResponseStartFinder {}))),
+                Key::new(&b"abcd"[..]),
+                0,
+                ResponseWindow::new(RESPONSE_WINDOW_CHUNKS),
+                Some(ResponseMeter {
+                    accountant_sub: peer_actors.accountant.report_exit_service,
+                    consuming_node_key: Key::new(&b"consumer"[..]),
+                    neighbor_ip: IpAddr::from_str("2.3.4.5").unwrap(),
+                }),
+            );
+
+            subject.run();
+
+            system.run();
+        });
+
+        awaiter.await_message_count(2);
+        thread::sleep(Duration::from_millis(100));
+        let accountant_recording = accountant_recording_arc.lock().unwrap();
+        assert_eq!(accountant_recording.get_record::<ReportExitServiceMessage>(0), &ReportExitServiceMessage {
+            consuming_node_key: Key::new(&b"consumer"[..]),
+            neighbor_ip: IpAddr::from_str("2.3.4.5").unwrap(),
+            payload_size: 19,
+        });
+        assert_eq!(accountant_recording.get_record::<ReportExitServiceMessage>(1), &ReportExitServiceMessage {
+            consuming_node_key: Key::new(&b"consumer"[..]),
+            neighbor_ip: IpAddr::from_str("2.3.4.5").unwrap(),
+            payload_size: 31,
+        });
+        assert_eq!(accountant_recording.len(), 2);
+    }
+
+    #[test]
+    fn response_window_hands_out_only_the_credit_it_was_granted() {
+        let subject = ResponseWindow::new(1);
+
+        assert_eq!(subject.take(Duration::from_millis(10)), true);
+        assert_eq!(subject.take(Duration::from_millis(10)), false);
+        subject.grant(2);
+        assert_eq!(subject.take(Duration::from_millis(10)), true);
+        assert_eq!(subject.take(Duration::from_millis(10)), true);
+        assert_eq!(subject.take(Duration::from_millis(10)), false);
+    }
+
+    #[test]
+    fn stream_reader_holds_back_response_chunks_until_credit_comes_back() {
+        let hopper = Recorder::new();
+        let awaiter = hopper.get_awaiter();
+        let hopper_recording_arc = hopper.get_recording();
+        let window = ResponseWindow::new(1);
+        let reader_window = window.clone();
+        thread::spawn(move || {
+            let system = System::new("test");
+            let hopper_sub =
+                test_utils::make_peer_actors_from(None, None, Some(hopper), None, None, None)
+                    .hopper.from_hopper_client;
+            let stream = TcpStreamWrapperMock::new()
+                .read_buffer(Vec::from(&b"HTTP/1.1 200 OK\r\n\r\nHTTP/1.1 404 File not found\r\n\r\nHTTP/1.1 503 Server error\r\n\r\n"[..]))
+                .read_result(Ok(b"HTTP/1.1 200 OK\r\n\r\nHTTP/1.1 404 File not found\r\n\r\nHTTP/1.1 503 Server error\r\n\r\n".len()))
+                .read_result(Err(Error::from(ErrorKind::BrokenPipe)));
+            let (stream_killer, _) = mpsc::channel::<StreamKey>();
+            let mut subject = StreamReader::new(
+                SocketAddr::from_str("1.2.3.4:80").unwrap(),
+                hopper_sub,
+                Box::new(stream),
+                stream_killer,
+                String::from("Peer Address"),
+                test_utils::make_meaningless_route(),
+                Box::new(HttpPacketFramer::new(Box::new(HttpResponseStartFinder {}))),
+                Key::new(&b"abcd"[..]),
+                0,
+                reader_window,
+                None,
+            );
+
+            subject.run();
+
+            system.run();
+        });
+
+        awaiter.await_message_count(1);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(hopper_recording_arc.lock().unwrap().len(), 1);
+
+        window.grant(2);
+
+        awaiter.await_message_count(4);
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(hopper_recording.get_record::<IncipientCoresPackage>(2), &IncipientCoresPackage::new(
+            test_utils::make_meaningless_route(),
+            ClientResponsePayload {
+                stream_key: SocketAddr::from_str("1.2.3.4:80").unwrap(),
+                last_response: false,
+                data: PlainData::new(&b"HTTP/1.1 503 Server error\r\n\r\n"[..]),
+                failure: None,
+            },
+            &Key::new(&b"abcd"[..])
+        ));
+    }
+}
@@ -63,6 +63,7 @@ mod tests {
         let ibcd = InboundClientData {
             socket_addr: SocketAddr::from_str ("1.2.3.4:5678").unwrap (),
             origin_port: Some (80),
+            peer_public_key_opt: None,
             component: Component::ProxyServer,
             last_data: false,
             data: data.data.clone (),
@@ -107,6 +108,7 @@ mod tests {
         let ibcd = InboundClientData {
             socket_addr: SocketAddr::from_str ("1.2.3.4:5678").unwrap (),
             origin_port: Some (443),
+            peer_public_key_opt: None,
             component: Component::ProxyServer,
             last_data: false,
             data: data.data.clone (),
@@ -145,6 +147,7 @@ mod tests {
         let ibcd = InboundClientData {
             socket_addr: SocketAddr::from_str ("1.2.3.4:5678").unwrap (),
             origin_port: Some (443),
+            peer_public_key_opt: None,
             component: Component::ProxyServer,
             last_data: true,
             data: data.data.clone (),
@@ -172,6 +175,7 @@ mod tests {
         let ibcd = InboundClientData {
             socket_addr: SocketAddr::from_str ("1.2.3.4:5678").unwrap (),
             origin_port: None,
+            peer_public_key_opt: None,
             component: Component::ProxyServer,
             last_data: false,
             data: vec!(0x10, 0x11, 0x12),
@@ -192,6 +196,7 @@ mod tests {
         let ibcd = InboundClientData {
             socket_addr: SocketAddr::from_str ("1.2.3.4:5678").unwrap (),
             origin_port: Some (1234),
+            peer_public_key_opt: None,
             component: Component::ProxyServer,
             last_data: false,
             data: vec!(0x10, 0x11, 0x12),
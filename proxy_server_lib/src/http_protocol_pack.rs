@@ -1,3 +1,4 @@
+use sub_lib::proxy_client::ExitFailure;
 use sub_lib::proxy_server::ProxyProtocol;
 use sub_lib::cryptde::PlainData;
 use sub_lib::utils::index_of;
@@ -15,6 +16,32 @@ impl ProtocolPack for HttpProtocolPack {
         }
         HttpProtocolPack::find_url_host_name (&data.data[..])
     }
+
+    fn failure_response (&self, failure: ExitFailure, target_hostname: &Option<String>) -> Option<Vec<u8>> {
+        let (status, reason) = match failure {
+            ExitFailure::Timeout => (504, "Gateway Timeout"),
+            ExitFailure::ServiceRefused => (402, "Payment Required"),
+            _ => (502, "Bad Gateway")
+        };
+        let host = match target_hostname {
+            &Some (ref hostname) => HttpProtocolPack::escape_html (hostname),
+            &None => String::from ("the server")
+        };
+        let explanation = match failure {
+            ExitFailure::MissingHostname => String::from ("The request didn't say which server it was meant for."),
+            ExitFailure::DnsResolution => format! ("The exit Node couldn't find an IP address for {}.", host),
+            ExitFailure::ConnectionRefused => format! ("{} refused the exit Node's connection.", host),
+            ExitFailure::Timeout => format! ("The exit Node timed out trying to reach {}.", host),
+            ExitFailure::ConnectionFailed => format! ("The exit Node couldn't connect to {}.", host),
+            ExitFailure::ServiceRefused => String::from ("The exit Node won't serve this Node until it pays what it owes."),
+            ExitFailure::ResponseTruncated => format! ("The response from {} was bigger than the exit Node will carry.", host),
+        };
+        let body = format! ("<html><head><title>Substratum Error: {} {}</title></head>\
+            <body><h1>Substratum Network Error</h1><h2>{} {}</h2><p>{}</p></body></html>",
+            status, reason, status, reason, explanation);
+        Some (format! ("HTTP/1.1 {} {}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status, reason, body.len (), body).into_bytes ())
+    }
 }
 
 impl HttpProtocolPack {
@@ -50,6 +77,10 @@ impl HttpProtocolPack {
         }
     }
 
+    fn escape_html (text: &str) -> String {
+        text.replace ("&", "&amp;").replace ("<", "&lt;").replace (">", "&gt;")
+    }
+
     fn index_of_multi<'a> (haystack: &'a [u8], needles: Vec<&'a [u8]>) -> Option<(usize, &'a [u8])> {
         for needle in needles {
             match index_of (haystack, needle) {
@@ -143,4 +174,35 @@ mod tests {
 
         assert_eq! (result, Some (String::from ("top.host.com")));
     }
+
+    #[test]
+    fn failure_response_for_timeout_is_a_504_substratum_page () {
+        let result = HttpProtocolPack{}.failure_response (ExitFailure::Timeout, &Some (String::from ("slow.com"))).unwrap ();
+
+        let string = String::from_utf8 (result).unwrap ();
+        assert_eq! (string.starts_with ("HTTP/1.1 504 Gateway Timeout\r\n"), true, "{}", string);
+        assert_eq! (string.contains ("<h1>Substratum Network Error</h1>"), true, "{}", string);
+        assert_eq! (string.contains ("The exit Node timed out trying to reach slow.com."), true, "{}", string);
+    }
+
+    #[test]
+    fn failure_response_for_dns_failure_is_a_502_with_accurate_content_length () {
+        let result = HttpProtocolPack{}.failure_response (ExitFailure::DnsResolution, &Some (String::from ("<nowhere>.com"))).unwrap ();
+
+        let string = String::from_utf8 (result).unwrap ();
+        assert_eq! (string.starts_with ("HTTP/1.1 502 Bad Gateway\r\n"), true, "{}", string);
+        assert_eq! (string.contains ("couldn't find an IP address for &lt;nowhere&gt;.com."), true, "{}", string);
+        let body_start = string.find ("\r\n\r\n").unwrap () + 4;
+        let body_length = string.len () - body_start;
+        assert_eq! (string.contains (&format! ("Content-Length: {}\r\n", body_length)), true, "{}", string);
+    }
+
+    #[test]
+    fn failure_response_for_refused_service_is_a_402_substratum_page () {
+        let result = HttpProtocolPack{}.failure_response (ExitFailure::ServiceRefused, &Some (String::from ("server.com"))).unwrap ();
+
+        let string = String::from_utf8 (result).unwrap ();
+        assert_eq! (string.starts_with ("HTTP/1.1 402 Payment Required\r\n"), true, "{}", string);
+        assert_eq! (string.contains ("The exit Node won't serve this Node until it pays what it owes."), true, "{}", string);
+    }
 }
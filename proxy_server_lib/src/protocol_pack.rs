@@ -1,7 +1,29 @@
+use sub_lib::proxy_client::ExitFailure;
 use sub_lib::proxy_server::ProxyProtocol;
 use sub_lib::cryptde::PlainData;
+use http_protocol_pack::HttpProtocolPack;
+use tls_protocol_pack::TlsProtocolPack;
 
 pub trait ProtocolPack {
     fn proxy_protocol (&self) -> ProxyProtocol;
     fn find_host_name (&self, data: &PlainData) -> Option<String>;
+    fn failure_response (&self, failure: ExitFailure, target_hostname: &Option<String>) -> Option<Vec<u8>>;
+}
+
+pub fn protocol_pack_for (protocol: ProxyProtocol) -> Box<ProtocolPack> {
+    match protocol {
+        ProxyProtocol::HTTP => Box::new (HttpProtocolPack {}),
+        ProxyProtocol::TLS => Box::new (TlsProtocolPack {})
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_pack_for_produces_pack_for_requested_protocol () {
+        assert_eq! (protocol_pack_for (ProxyProtocol::HTTP).proxy_protocol (), ProxyProtocol::HTTP);
+        assert_eq! (protocol_pack_for (ProxyProtocol::TLS).proxy_protocol (), ProxyProtocol::TLS);
+    }
 }
@@ -1,35 +1,113 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
 use actix::Actor;
+use actix::ActorFuture;
 use actix::Addr;
+use actix::AsyncContext;
 use actix::Context;
+use actix::fut;
 use actix::Handler;
+use actix::MailboxError;
 use actix::Recipient;
 use actix::Syn;
-use sub_lib::cryptde_null::CryptDENull;
+use actix::WrapFuture;
 use sub_lib::cryptde::CryptDE;
+use sub_lib::cryptde::Key;
+use sub_lib::cryptde::StreamKey;
 use sub_lib::dispatcher::Component;
 use sub_lib::dispatcher::Endpoint;
 use sub_lib::dispatcher::InboundClientData;
 use sub_lib::hopper::IncipientCoresPackage;
 use sub_lib::hopper::ExpiredCoresPackage;
 use sub_lib::logger::Logger;
+use sub_lib::neighborhood::Misbehaver;
+use sub_lib::neighborhood::NeighborMisbehavior;
+use sub_lib::neighborhood::NeighborMisbehaviorMessage;
+use sub_lib::neighborhood::RouteQueryMessage;
+use sub_lib::neighborhood::RouteQueryResponse;
 use sub_lib::peer_actors::BindMessage;
 use sub_lib::proxy_client::ClientResponsePayload;
+use sub_lib::proxy_client::ExitFailure;
+use sub_lib::proxy_client::RESPONSE_WINDOW_CHUNKS;
+use sub_lib::proxy_server::ClientRequestPayload;
+use sub_lib::proxy_server::ProxyProtocol;
 use sub_lib::proxy_server::ProxyServerSubs;
+use sub_lib::proxy_server::ResponseCreditPayload;
 use sub_lib::route::Route;
 use sub_lib::route::RouteSegment;
 use sub_lib::stream_handler_pool::TransmitDataMsg;
+use sub_lib::traffic_stats;
 use sub_lib::utils::NODE_MAILBOX_CAPACITY;
 use client_request_payload_factory::ClientRequestPayloadFactory;
+use protocol_pack::protocol_pack_for;
+
+// A request whose exit Node can't resolve the target hostname is retried through at most this many other exit Nodes
+pub const DNS_FAILURE_RETRIES: usize = 3;
+
+// A route that produces no response this long after a request is presumed broken and replaced,
+// at most ROUTE_FAILURE_RETRIES times
+pub const ROUTE_RESPONSE_TIMEOUT_MS: u64 = 30000;
+pub const ROUTE_FAILURE_RETRIES: usize = 3;
+// Credit for relayed response chunks goes back to the exit Node in batches this size, well before
+// its window runs dry
+pub const RESPONSE_CREDIT_BATCH: u32 = RESPONSE_WINDOW_CHUNKS / 2;
 
 pub struct ProxyServer {
     dispatcher: Option<Recipient<Syn, TransmitDataMsg>>,
     hopper: Option<Recipient<Syn, IncipientCoresPackage>>,
+    neighborhood: Option<Recipient<Syn, RouteQueryMessage>>,
+    neighborhood_reports: Option<Recipient<Syn, NeighborMisbehaviorMessage>>,
+    proxy_client: Option<Recipient<Syn, ExpiredCoresPackage>>,
+    // hand requests straight to the local ProxyClient instead of routing them through the network
+    zero_hop: bool,
     client_request_payload_factory: ClientRequestPayloadFactory,
+    streams: HashMap<StreamKey, StreamInfo>,
+    route_response_timeout: Duration,
     cryptde: &'static CryptDE,
     logger: Logger
 }
 
+struct StreamInfo {
+    protocol: ProxyProtocol,
+    target_hostname: Option<String>,
+    route_opt: Option<RouteQueryResponse>,
+    // waiting for a route from the Neighborhood
+    pending_requests: Vec<ClientRequestPayload>,
+    // sent, but not yet answered by the exit Node; replayed if the stream is rerouted
+    unanswered_requests: Vec<ClientRequestPayload>,
+    // Once the exit Node has answered, the stream stays with it: another exit Node couldn't pick it
+    // up part way through, since later requests (TLS records, say) carry no hostname to connect to
+    answered: bool,
+    // when the oldest unanswered request went out
+    awaiting_since: Option<Instant>,
+    tried_exit_keys: Vec<Key>,
+    last_failure: Option<ExitFailure>,
+    // the client has hung up; the stream is forgotten once its last requests are on their way
+    client_closed: bool,
+    // response chunks relayed to the client that the exit Node hasn't had credit back for yet
+    uncredited_chunks: u32,
+}
+
+impl StreamInfo {
+    fn new (payload: &ClientRequestPayload) -> StreamInfo {
+        StreamInfo {
+            protocol: payload.protocol,
+            target_hostname: payload.target_hostname.clone (),
+            route_opt: None,
+            pending_requests: vec! (),
+            unanswered_requests: vec! (),
+            answered: false,
+            awaiting_since: None,
+            tried_exit_keys: vec! (),
+            last_failure: None,
+            client_closed: false,
+            uncredited_chunks: 0,
+        }
+    }
+}
+
 impl Actor for ProxyServer {
     type Context = Context<Self>;
 }
@@ -41,6 +119,9 @@ impl Handler<BindMessage> for ProxyServer {
         ctx.set_mailbox_capacity(NODE_MAILBOX_CAPACITY);
         self.dispatcher = Some(msg.peer_actors.dispatcher.from_proxy_server);
         self.hopper = Some(msg.peer_actors.hopper.from_hopper_client);
+        self.neighborhood = Some(msg.peer_actors.neighborhood.route_query);
+        self.neighborhood_reports = Some(msg.peer_actors.neighborhood.report_misbehavior);
+        self.proxy_client = Some(msg.peer_actors.proxy_client.from_hopper);
         ()
     }
 }
@@ -48,49 +129,60 @@ impl Handler<BindMessage> for ProxyServer {
 impl Handler<InboundClientData> for ProxyServer {
     type Result = ();
 
-    fn handle(&mut self, msg: InboundClientData, _ctx: &mut Self::Context) -> Self::Result {
-        let hopper = self.hopper.as_ref ().expect ("Hopper unbound in ProxyServer");
+    fn handle(&mut self, msg: InboundClientData, ctx: &mut Self::Context) -> Self::Result {
+        self.hopper.as_ref ().expect ("Hopper unbound in ProxyServer");
         let payload = match self.client_request_payload_factory.make (&msg, self.cryptde, &self.logger) {
             None => { self.logger.error(format! ("Couldn't create ClientRequestPayload")); return (); },
             Some (payload) => payload
         };
-        // TODO this should come from the Neighborhood
-        let route = Route::new(vec! (
-                RouteSegment::new(vec! (&self.cryptde.public_key(), &self.cryptde.public_key ()), Component::ProxyClient),
-                RouteSegment::new(vec! (&self.cryptde.public_key(), &self.cryptde.public_key()), Component::ProxyServer)
-            ), self.cryptde).expect("Couldn't create route");
-        let pkg = IncipientCoresPackage::new(route, payload, &self.cryptde.public_key());
-        hopper.try_send(pkg ).expect ("Hopper is dead")
+        let stream_key = payload.stream_key;
+        let new_stream = !self.streams.contains_key (&stream_key);
+        if new_stream {
+            self.streams.insert (stream_key, StreamInfo::new (&payload));
+        }
+        {
+            let stream = self.streams.get_mut (&stream_key).expect ("Stream disappeared");
+            stream.client_closed = payload.last_data;
+            stream.pending_requests.push (payload);
+        }
+        if new_stream {
+            self.request_route (stream_key, ctx)
+        }
+        else {
+            self.send_pending_requests (&stream_key, ctx)
+        }
     }
 }
 
 impl Handler<ExpiredCoresPackage> for ProxyServer {
     type Result = ();
 
-    fn handle(&mut self, msg: ExpiredCoresPackage, _ctx: &mut Self::Context) -> Self::Result {
-        match msg.payload::<ClientResponsePayload>() {
-            Ok(payload) => {
-                self.logger.debug (format! ("Relaying {}-byte ExpiredCoresPackage payload from Hopper to Dispatcher", payload.data.data.len ()));
-                self.dispatcher.as_ref().expect("Dispatcher unbound in ProxyServer")
-                    .try_send(TransmitDataMsg {
-                        endpoint: Endpoint::Socket(payload.stream_key),
-                        last_data: payload.last_response,
-                        data: payload.data.data.clone()
-                    }).expect ("Dispatcher is dead");
-                ()
-            },
-            Err(_) => { self.logger.error(format! ("ClientResponsePayload is not OK")); return (); },
-        }
-        ()
+    fn handle(&mut self, msg: ExpiredCoresPackage, ctx: &mut Self::Context) -> Self::Result {
+        self.receive_response (msg, ctx)
+    }
+}
+
+// Zero-hop mode: responses come straight from the local ProxyClient, without going through the Hopper
+impl Handler<IncipientCoresPackage> for ProxyServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: IncipientCoresPackage, ctx: &mut Self::Context) -> Self::Result {
+        self.receive_response (ExpiredCoresPackage::new (msg.route, msg.payload), ctx)
     }
 }
 
 impl ProxyServer {
-    pub fn new(cryptde: &'static CryptDE) -> ProxyServer {
+    pub fn new(cryptde: &'static CryptDE, zero_hop: bool) -> ProxyServer {
         ProxyServer {
             dispatcher: None,
             hopper: None,
+            neighborhood: None,
+            neighborhood_reports: None,
+            proxy_client: None,
+            zero_hop,
             client_request_payload_factory: ClientRequestPayloadFactory::new (),
+            streams: HashMap::new (),
+            route_response_timeout: Duration::from_millis (ROUTE_RESPONSE_TIMEOUT_MS),
             cryptde,
             logger: Logger::new ("Proxy Server"),
         }
@@ -101,6 +193,274 @@ impl ProxyServer {
             bind: addr.clone ().recipient::<BindMessage>(),
             from_dispatcher: addr.clone ().recipient::<InboundClientData>(),
             from_hopper: addr.clone ().recipient::<ExpiredCoresPackage>(),
+            from_proxy_client: addr.clone ().recipient::<IncipientCoresPackage>(),
+        }
+    }
+
+    fn receive_response (&mut self, msg: ExpiredCoresPackage, ctx: &mut Context<ProxyServer>) {
+        match msg.payload::<ClientResponsePayload>() {
+            Ok(payload) => {
+                if (payload.failure == Some (ExitFailure::DnsResolution)) && self.reroute_stream (&payload.stream_key, ExitFailure::DnsResolution, ctx) {
+                    return ()
+                }
+                let data = match payload.failure {
+                    None => payload.data.data.clone (),
+                    // Too late for an error page: the browser already has the start of the response
+                    Some (ExitFailure::ResponseTruncated) => {
+                        self.logger.warning (format! ("Exit Node cut the response on stream {} short at its maximum response size", payload.stream_key));
+                        payload.data.data.clone ()
+                    },
+                    Some (failure) => self.failure_response (&payload.stream_key, failure)
+                };
+                // A failure ends the stream whether or not the exit Node said so
+                if payload.last_response || payload.failure.is_some () {
+                    self.forget_stream (&payload.stream_key);
+                }
+                else if let Some (stream) = self.streams.get_mut (&payload.stream_key) {
+                    stream.unanswered_requests.clear ();
+                    stream.answered = true;
+                    stream.awaiting_since = None;
+                }
+                self.logger.debug (format! ("Relaying {}-byte ExpiredCoresPackage payload from Hopper to Dispatcher", data.len ()));
+                self.dispatcher.as_ref().expect("Dispatcher unbound in ProxyServer")
+                    .try_send(TransmitDataMsg {
+                        endpoint: Endpoint::Socket(payload.stream_key),
+                        last_data: payload.last_response,
+                        data
+                    }).expect ("Dispatcher is dead");
+                if !payload.last_response && payload.failure.is_none () {
+                    self.return_response_credit (&payload.stream_key)
+                }
+                ()
+            },
+            Err(_) => { self.logger.error(format! ("ClientResponsePayload is not OK")); return (); },
+        }
+    }
+
+    fn request_route (&mut self, stream_key: StreamKey, ctx: &mut Context<ProxyServer>) {
+        if self.zero_hop {
+            let response = self.zero_hop_route ();
+            return self.route_arrived (stream_key, Ok (Some (response)), ctx)
+        }
+        let excluded_exit_keys = match self.streams.get (&stream_key) {
+            None => return,
+            Some (stream) => stream.tried_exit_keys.clone ()
+        };
+        let future = self.neighborhood.as_ref ().expect ("Neighborhood unbound in ProxyServer")
+            .send (RouteQueryMessage::new (excluded_exit_keys))
+            .into_actor (self)
+            .then (move |route_result, proxy_server, ctx| {
+                proxy_server.route_arrived (stream_key, route_result, ctx);
+                fut::ok (())
+            });
+        ctx.spawn (future);
+    }
+
+    fn route_arrived (&mut self, stream_key: StreamKey, route_result: Result<Option<RouteQueryResponse>, MailboxError>, ctx: &mut Context<ProxyServer>) {
+        let response_opt = match route_result {
+            Ok (response_opt) => response_opt,
+            Err (e) => {
+                self.logger.error (format! ("Neighborhood failed to answer route query for stream {}: {:?}", stream_key, e));
+                None
+            }
+        };
+        match response_opt {
+            None => {
+                self.logger.error (format! ("No route available for stream {}", stream_key));
+                self.abandon_stream (&stream_key)
+            },
+            Some (response) => {
+                match self.streams.get_mut (&stream_key) {
+                    None => return,
+                    Some (stream) => {
+                        stream.tried_exit_keys.push (response.exit_key.clone ());
+                        stream.route_opt = Some (response);
+                    }
+                }
+                self.report_routes_in_use ();
+                self.send_pending_requests (&stream_key, ctx)
+            }
+        }
+    }
+
+    // This Node is both the origin and the exit, with nobody in between
+    fn zero_hop_route (&self) -> RouteQueryResponse {
+        let key = self.cryptde.public_key ();
+        let route = Route::new (vec! (
+            RouteSegment::new (vec! (&key), Component::ProxyClient),
+            RouteSegment::new (vec! (&key, &key), Component::ProxyServer)
+        ), self.cryptde).expect ("Couldn't make zero-hop route");
+        RouteQueryResponse {route, exit_key: key}
+    }
+
+    fn send_pending_requests (&mut self, stream_key: &StreamKey, ctx: &mut Context<ProxyServer>) {
+        let client_closed = {
+            let hopper = self.hopper.as_ref ().expect ("Hopper unbound in ProxyServer");
+            let zero_hop = self.zero_hop;
+            let proxy_client = &self.proxy_client;
+            let stream = match self.streams.get_mut (stream_key) {
+                None => return,
+                Some (stream) => stream
+            };
+            let (route, exit_key) = match stream.route_opt {
+                None => return,
+                Some (ref response) => (response.route.clone (), response.exit_key.clone ())
+            };
+            let requests: Vec<ClientRequestPayload> = stream.pending_requests.drain (..).collect ();
+            if requests.is_empty () {return}
+            for request in requests {
+                let pkg = IncipientCoresPackage::new (route.clone (), request.clone (), &exit_key);
+                if zero_hop {
+                    proxy_client.as_ref ().expect ("ProxyClient unbound in ProxyServer")
+                        .try_send (ExpiredCoresPackage::new (pkg.route, pkg.payload)).expect ("ProxyClient is dead");
+                }
+                else {
+                    hopper.try_send (pkg).expect ("Hopper is dead");
+                }
+                if !stream.answered {
+                    stream.unanswered_requests.push (request);
+                }
+            }
+            if !stream.answered && stream.awaiting_since.is_none () {
+                stream.awaiting_since = Some (Instant::now ());
+                ProxyServer::schedule_response_check (*stream_key, self.route_response_timeout, ctx);
+            }
+            stream.client_closed
+        };
+        // Nobody is left to reroute for or to show an error page to
+        if client_closed {
+            self.forget_stream (stream_key);
+        }
+    }
+
+    fn return_response_credit (&mut self, stream_key: &StreamKey) {
+        let (route, exit_key, credit_chunks) = match self.streams.get_mut (stream_key) {
+            None => return,
+            Some (stream) => {
+                stream.uncredited_chunks += 1;
+                if stream.uncredited_chunks < RESPONSE_CREDIT_BATCH {return}
+                let credit_chunks = stream.uncredited_chunks;
+                stream.uncredited_chunks = 0;
+                match stream.route_opt {
+                    None => return,
+                    Some (ref response) => (response.route.clone (), response.exit_key.clone (), credit_chunks)
+                }
+            }
+        };
+        let pkg = IncipientCoresPackage::new (route, ResponseCreditPayload {stream_key: *stream_key, credit_chunks}, &exit_key);
+        if self.zero_hop {
+            self.proxy_client.as_ref ().expect ("ProxyClient unbound in ProxyServer")
+                .try_send (ExpiredCoresPackage::new (pkg.route, pkg.payload)).expect ("ProxyClient is dead");
+        }
+        else {
+            self.hopper.as_ref ().expect ("Hopper unbound in ProxyServer").try_send (pkg).expect ("Hopper is dead");
+        }
+    }
+
+    fn schedule_response_check (stream_key: StreamKey, delay: Duration, ctx: &mut Context<ProxyServer>) {
+        ctx.run_later (delay, move |proxy_server, ctx| {
+            proxy_server.check_for_response (stream_key, ctx)
+        });
+    }
+
+    fn check_for_response (&mut self, stream_key: StreamKey, ctx: &mut Context<ProxyServer>) {
+        let (waited, exit_key) = match self.streams.get (&stream_key) {
+            Some (&StreamInfo {route_opt: Some (ref response), awaiting_since: Some (since), ..}) => (since.elapsed (), response.exit_key.clone ()),
+            // answered, closed, or already being rerouted
+            _ => return
+        };
+        if waited < self.route_response_timeout {
+            ProxyServer::schedule_response_check (stream_key, self.route_response_timeout - waited, ctx);
+            return
+        }
+        // Which Node on the route dropped the request can't be told from here; the exit Node took the
+        // stream on, so it's the one held to account
+        if exit_key != self.cryptde.public_key () {
+            self.neighborhood_reports.as_ref ().expect ("Neighborhood unbound in ProxyServer").try_send (NeighborMisbehaviorMessage {
+                misbehaver: Misbehaver::Node (exit_key),
+                misbehavior: NeighborMisbehavior::FailedRelay,
+            }).expect ("Neighborhood is dead");
+        }
+        if !self.reroute_stream (&stream_key, ExitFailure::Timeout, ctx) {
+            self.abandon_stream (&stream_key)
+        }
+    }
+
+    fn reroute_stream (&mut self, stream_key: &StreamKey, failure: ExitFailure, ctx: &mut Context<ProxyServer>) -> bool {
+        {
+            let stream = match self.streams.get_mut (stream_key) {
+                None => return false,
+                Some (stream) => stream
+            };
+            // A reroute is already under way; this is a straggler from the abandoned exit Node
+            if stream.route_opt.is_none () {return true}
+            if stream.answered {return false}
+            let (retries, problem) = match failure {
+                ExitFailure::DnsResolution => (DNS_FAILURE_RETRIES, "failed to resolve it"),
+                _ => (ROUTE_FAILURE_RETRIES, "failed to answer"),
+            };
+            if stream.tried_exit_keys.len () > retries {
+                self.logger.warning (format! ("Giving up on stream {} to {:?} after {} exit Nodes {}",
+                    stream_key, stream.target_hostname, stream.tried_exit_keys.len (), problem));
+                stream.last_failure = Some (failure);
+                return false
+            }
+            match failure {
+                ExitFailure::DnsResolution => self.logger.warning (format! ("Exit Node couldn't resolve {:?} for stream {}; rerouting through another exit Node",
+                    stream.target_hostname, stream_key)),
+                _ => self.logger.warning (format! ("Route for stream {} to {:?} stopped answering; rerouting through another exit Node",
+                    stream_key, stream.target_hostname)),
+            }
+            stream.last_failure = Some (failure);
+            stream.route_opt = None;
+            stream.awaiting_since = None;
+            let mut replay: Vec<ClientRequestPayload> = stream.unanswered_requests.drain (..).collect ();
+            replay.extend (stream.pending_requests.drain (..));
+            stream.pending_requests = replay;
+        }
+        self.report_routes_in_use ();
+        self.request_route (*stream_key, ctx);
+        true
+    }
+
+    fn abandon_stream (&mut self, stream_key: &StreamKey) {
+        let data = match self.streams.get (stream_key) {
+            None => return,
+            Some (stream) => match stream.last_failure {
+                None => vec! (),
+                Some (failure) => self.failure_response (stream_key, failure)
+            }
+        };
+        self.forget_stream (stream_key);
+        self.dispatcher.as_ref().expect("Dispatcher unbound in ProxyServer")
+            .try_send(TransmitDataMsg {
+                endpoint: Endpoint::Socket(*stream_key),
+                last_data: true,
+                data
+            }).expect ("Dispatcher is dead");
+    }
+
+    fn forget_stream (&mut self, stream_key: &StreamKey) {
+        self.streams.remove (stream_key);
+        self.report_routes_in_use ();
+    }
+
+    fn report_routes_in_use (&self) {
+        traffic_stats::set_routes_in_use (self.streams.values ().filter (|stream| stream.route_opt.is_some ()).count ());
+    }
+
+    fn failure_response (&self, stream_key: &StreamKey, failure: ExitFailure) -> Vec<u8> {
+        let stream = match self.streams.get (stream_key) {
+            None => {
+                self.logger.warning (format! ("Exit Node reported {:?} for unknown stream {}", failure, stream_key));
+                return vec! ()
+            },
+            Some (stream) => stream
+        };
+        self.logger.warning (format! ("Exit Node reported {:?} for stream {} to {:?}", failure, stream_key, stream.target_hostname));
+        match protocol_pack_for (stream.protocol).failure_response (failure, &stream.target_hostname) {
+            None => vec! (),
+            Some (response) => response
         }
     }
 }
@@ -110,6 +470,8 @@ mod tests {
     use super::*;
     use std::net::SocketAddr;
     use std::str::FromStr;
+    use std::sync::mpsc;
+    use std::thread;
     use actix::msgs;
     use actix::Arbiter;
     use actix::System;
@@ -121,25 +483,28 @@ mod tests {
     use sub_lib::proxy_server::ClientRequestPayload;
     use sub_lib::proxy_server::ProxyProtocol;
     use test_utils::test_utils::make_peer_actors_from;
+    use test_utils::test_utils::init_test_logging;
     use test_utils::test_utils::Recorder;
+    use test_utils::test_utils::TestLogHandler;
     use test_utils::test_utils::cryptde;
     use test_utils::test_utils::route_from_proxy_server;
     use test_utils::test_utils::route_to_proxy_server;
+    use sub_lib::route::Route;
+    use sub_lib::route::RouteSegment;
 
     #[test]
     fn proxy_server_receives_http_request_from_dispatcher_then_sends_cores_package_to_hopper() {
-        let system = System::new("proxy_server_receives_http_request_from_dispatcher_then_sends_cores_package_to_hopper");
         let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
         let hopper_mock = Recorder::new();
         let hopper_log_arc = hopper_mock.get_recording();
         let hopper_awaiter = hopper_mock.get_awaiter();
         let cryptde = cryptde();
-        let subject = ProxyServer::new(cryptde);
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
         let expected_data = http_request.to_vec();
         let msg_from_dispatcher = InboundClientData {
             socket_addr: socket_addr.clone(),
             origin_port: Some (80),
+            peer_public_key_opt: None,
             component: Component::ProxyServer,
             last_data: true,
             data: expected_data.clone()
@@ -157,15 +522,20 @@ mod tests {
             originator_public_key: key.clone()
         };
         let expected_pkg = IncipientCoresPackage::new(route.clone(), expected_payload, &key);
-        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-        let mut peer_actors = make_peer_actors_from(None, None, Some(hopper_mock), None, None);
-        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
-        subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+        let neighborhood_mock = Recorder::new()
+            .route_query_response(Some (RouteQueryResponse {route: route.clone(), exit_key: key.clone()}));
+        thread::spawn (move || {
+            let system = System::new("proxy_server_receives_http_request_from_dispatcher_then_sends_cores_package_to_hopper");
+            let subject = ProxyServer::new(cryptde, false);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = make_peer_actors_from(None, None, Some(hopper_mock), None, Some(neighborhood_mock), None);
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
 
-        subject_addr.try_send(msg_from_dispatcher).unwrap ();
+            subject_addr.try_send(msg_from_dispatcher).unwrap ();
 
-        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
-        system.run();
+            system.run();
+        });
 
         hopper_awaiter.await_message_count(1);
         let recording = hopper_log_arc.lock().unwrap();
@@ -175,7 +545,6 @@ mod tests {
 
     #[test]
     fn proxy_server_receives_tls_client_hello_from_dispatcher_then_sends_cores_package_to_hopper() {
-        let system = System::new("proxy_server_receives_tls_client_hello_from_dispatcher_then_sends_cores_package_to_hopper");
         let tls_request = &[
             0x16, // content_type: Handshake
             0x00, 0x00, 0x00, 0x00, // version, length: don't care
@@ -198,12 +567,12 @@ mod tests {
         let hopper_log_arc = hopper_mock.get_recording();
         let hopper_awaiter = hopper_mock.get_awaiter();
         let cryptde = cryptde();
-        let subject = ProxyServer::new(cryptde);
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
         let expected_data = tls_request.to_vec();
         let msg_from_dispatcher = InboundClientData {
             socket_addr: socket_addr.clone(),
             origin_port: Some (443),
+            peer_public_key_opt: None,
             component: Component::ProxyServer,
             last_data: false,
             data: expected_data.clone()
@@ -221,15 +590,20 @@ mod tests {
             originator_public_key: key.clone()
         };
         let expected_pkg = IncipientCoresPackage::new(route.clone(), expected_payload, &key);
-        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-        let mut peer_actors = make_peer_actors_from(None, None, Some(hopper_mock), None, None);
-        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
-        subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+        let neighborhood_mock = Recorder::new()
+            .route_query_response(Some (RouteQueryResponse {route: route.clone(), exit_key: key.clone()}));
+        thread::spawn (move || {
+            let system = System::new("proxy_server_receives_tls_client_hello_from_dispatcher_then_sends_cores_package_to_hopper");
+            let subject = ProxyServer::new(cryptde, false);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = make_peer_actors_from(None, None, Some(hopper_mock), None, Some(neighborhood_mock), None);
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
 
-        subject_addr.try_send(msg_from_dispatcher).unwrap ();
+            subject_addr.try_send(msg_from_dispatcher).unwrap ();
 
-        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
-        system.run();
+            system.run();
+        });
 
         hopper_awaiter.await_message_count(1);
         let recording = hopper_log_arc.lock().unwrap();
@@ -239,7 +613,6 @@ mod tests {
 
     #[test]
     fn proxy_server_receives_tls_handshake_packet_other_than_client_hello_from_dispatcher_then_sends_cores_package_to_hopper() {
-        let system = System::new("proxy_server_receives_tls_client_hello_from_dispatcher_then_sends_cores_package_to_hopper");
         let tls_request = &[
             0x16, // content_type: Handshake
             0x00, 0x00, 0x00, 0x00, // version, length: don't care
@@ -250,12 +623,12 @@ mod tests {
         let hopper_log_arc = hopper_mock.get_recording();
         let hopper_awaiter = hopper_mock.get_awaiter();
         let cryptde = cryptde();
-        let subject = ProxyServer::new(cryptde);
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
         let expected_data = tls_request.to_vec();
         let msg_from_dispatcher = InboundClientData {
             socket_addr: socket_addr.clone(),
             origin_port: Some (443),
+            peer_public_key_opt: None,
             component: Component::ProxyServer,
             last_data: false,
             data: expected_data.clone()
@@ -273,15 +646,20 @@ mod tests {
             originator_public_key: key.clone()
         };
         let expected_pkg = IncipientCoresPackage::new(route.clone(), expected_payload, &key);
-        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-        let mut peer_actors = make_peer_actors_from(None, None, Some(hopper_mock), None, None);
-        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
-        subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+        let neighborhood_mock = Recorder::new()
+            .route_query_response(Some (RouteQueryResponse {route: route.clone(), exit_key: key.clone()}));
+        thread::spawn (move || {
+            let system = System::new("proxy_server_receives_tls_client_hello_from_dispatcher_then_sends_cores_package_to_hopper");
+            let subject = ProxyServer::new(cryptde, false);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = make_peer_actors_from(None, None, Some(hopper_mock), None, Some(neighborhood_mock), None);
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
 
-        subject_addr.try_send(msg_from_dispatcher).unwrap ();
+            subject_addr.try_send(msg_from_dispatcher).unwrap ();
 
-        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
-        system.run();
+            system.run();
+        });
 
         hopper_awaiter.await_message_count(1);
         let recording = hopper_log_arc.lock().unwrap();
@@ -291,7 +669,6 @@ mod tests {
 
     #[test]
     fn proxy_server_receives_tls_packet_other_than_handshake_from_dispatcher_then_sends_cores_package_to_hopper() {
-        let system = System::new("proxy_server_receives_tls_client_hello_from_dispatcher_then_sends_cores_package_to_hopper");
         let tls_request = &[
             0xFF, // content_type: don't care, just not Handshake
             0x00, 0x00, 0x00, 0x00, // version, length: don't care
@@ -300,12 +677,12 @@ mod tests {
         let hopper_log_arc = hopper_mock.get_recording();
         let hopper_awaiter = hopper_mock.get_awaiter();
         let cryptde = cryptde();
-        let subject = ProxyServer::new(cryptde);
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
         let expected_data = tls_request.to_vec();
         let msg_from_dispatcher = InboundClientData {
             socket_addr: socket_addr.clone(),
             origin_port: Some (443),
+            peer_public_key_opt: None,
             component: Component::ProxyServer,
             last_data: true,
             data: expected_data.clone()
@@ -323,20 +700,118 @@ mod tests {
             originator_public_key: key.clone()
         };
         let expected_pkg = IncipientCoresPackage::new(route.clone(), expected_payload, &key);
+        let neighborhood_mock = Recorder::new()
+            .route_query_response(Some (RouteQueryResponse {route: route.clone(), exit_key: key.clone()}));
+        thread::spawn (move || {
+            let system = System::new("proxy_server_receives_tls_client_hello_from_dispatcher_then_sends_cores_package_to_hopper");
+            let subject = ProxyServer::new(cryptde, false);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = make_peer_actors_from(None, None, Some(hopper_mock), None, Some(neighborhood_mock), None);
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap ();
+
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(1);
+        let recording = hopper_log_arc.lock().unwrap();
+        let record = recording.get_record::<IncipientCoresPackage>(0);
+        assert_eq!(record, &expected_pkg);
+    }
+
+    #[test]
+    fn zero_hop_proxy_server_sends_requests_straight_to_the_proxy_client() {
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let proxy_client_mock = Recorder::new();
+        let proxy_client_log_arc = proxy_client_mock.get_recording();
+        let proxy_client_awaiter = proxy_client_mock.get_awaiter();
+        let hopper_mock = Recorder::new();
+        let hopper_log_arc = hopper_mock.get_recording();
+        let neighborhood_mock = Recorder::new();
+        let neighborhood_log_arc = neighborhood_mock.get_recording();
+        let cryptde = cryptde();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let msg_from_dispatcher = InboundClientData {
+            socket_addr: socket_addr.clone(),
+            origin_port: Some (80),
+            peer_public_key_opt: None,
+            component: Component::ProxyServer,
+            last_data: true,
+            data: http_request.to_vec()
+        };
+        let key = cryptde.public_key();
+        let route = Route::new(vec! (
+            RouteSegment::new(vec! (&key), Component::ProxyClient),
+            RouteSegment::new(vec! (&key, &key), Component::ProxyServer)
+        ), cryptde).unwrap();
+        let expected_payload = ClientRequestPayload {
+            stream_key: socket_addr.clone(),
+            last_data: true,
+            data: PlainData::new(http_request),
+            target_hostname: Some (String::from("nowhere.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: key.clone()
+        };
+        let incipient_pkg = IncipientCoresPackage::new(route, expected_payload, &key);
+        let expected_pkg = ExpiredCoresPackage::new(incipient_pkg.route, incipient_pkg.payload);
+        thread::spawn (move || {
+            let system = System::new("zero_hop_proxy_server_sends_requests_straight_to_the_proxy_client");
+            let subject = ProxyServer::new(cryptde, true);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = make_peer_actors_from(None, None, Some(hopper_mock), Some(proxy_client_mock), Some(neighborhood_mock), None);
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap ();
+
+            system.run();
+        });
+
+        proxy_client_awaiter.await_message_count(1);
+        let recording = proxy_client_log_arc.lock().unwrap();
+        let record = recording.get_record::<ExpiredCoresPackage>(0);
+        assert_eq!(record, &expected_pkg);
+        assert_eq!(hopper_log_arc.lock().unwrap().len(), 0);
+        assert_eq!(neighborhood_log_arc.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn zero_hop_proxy_server_relays_responses_straight_from_the_proxy_client() {
+        let system = System::new("zero_hop_proxy_server_relays_responses_straight_from_the_proxy_client");
+        let dispatcher_mock = Recorder::new();
+        let dispatcher_log_arc = dispatcher_mock.get_recording();
+        let dispatcher_awaiter = dispatcher_mock.get_awaiter();
+        let cryptde = cryptde();
+        let subject = ProxyServer::new(cryptde, true);
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let key = cryptde.public_key();
         let subject_addr: Addr<Syn, ProxyServer> = subject.start();
-        let mut peer_actors = make_peer_actors_from(None, None, Some(hopper_mock), None, None);
+        let client_response_payload = ClientResponsePayload {
+            stream_key: socket_addr.clone(),
+            last_response: true,
+            data: PlainData::new(b"data"),
+            failure: None
+        };
+        let incipient_cores_package = IncipientCoresPackage::new(route_to_proxy_server(&key, cryptde), client_response_payload, &key);
+        let mut peer_actors = make_peer_actors_from(None, Some(dispatcher_mock), None, None, None, None);
         peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
         subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
 
-        subject_addr.try_send(msg_from_dispatcher).unwrap ();
+        subject_addr.try_send(incipient_cores_package).unwrap ();
 
         Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
         system.run();
 
-        hopper_awaiter.await_message_count(1);
-        let recording = hopper_log_arc.lock().unwrap();
-        let record = recording.get_record::<IncipientCoresPackage>(0);
-        assert_eq!(record, &expected_pkg);
+        dispatcher_awaiter.await_message_count(1);
+
+        let recording = dispatcher_log_arc.lock().unwrap();
+        let record = recording.get_record::<TransmitDataMsg>(0);
+        assert_eq!(record.endpoint, Endpoint::Socket(socket_addr));
+        assert_eq!(record.last_data, true);
+        assert_eq!(record.data, b"data".to_vec());
     }
 
     #[test]
@@ -346,7 +821,7 @@ mod tests {
         let dispatcher_log_arc = dispatcher_mock.get_recording();
         let dispatcher_awaiter = dispatcher_mock.get_awaiter();
         let cryptde = cryptde();
-        let subject = ProxyServer::new(cryptde);
+        let subject = ProxyServer::new(cryptde, false);
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
         let key = cryptde.public_key();
         let subject_addr: Addr<Syn, ProxyServer> = subject.start();
@@ -354,11 +829,12 @@ mod tests {
         let client_response_payload = ClientResponsePayload {
             stream_key: socket_addr.clone(),
             last_response: true,
-            data: PlainData::new(b"data")
+            data: PlainData::new(b"data"),
+            failure: None
         };
         let incipient_cores_package = IncipientCoresPackage::new(remaining_route.clone(), client_response_payload, &key);
         let expired_cores_package = ExpiredCoresPackage::new(remaining_route, incipient_cores_package.payload);
-        let mut peer_actors = make_peer_actors_from(None, Some(dispatcher_mock), None, None, None);
+        let mut peer_actors = make_peer_actors_from(None, Some(dispatcher_mock), None, None, None, None);
         peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
         subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
 
@@ -376,6 +852,46 @@ mod tests {
         assert_eq!(record.data, b"data".to_vec());
     }
 
+    #[test]
+    fn proxy_server_passes_on_the_data_of_a_truncated_response_and_says_it_was_truncated() {
+        init_test_logging();
+        let system = System::new("proxy_server_passes_on_the_data_of_a_truncated_response_and_says_it_was_truncated");
+        let dispatcher_mock = Recorder::new();
+        let dispatcher_log_arc = dispatcher_mock.get_recording();
+        let dispatcher_awaiter = dispatcher_mock.get_awaiter();
+        let cryptde = cryptde();
+        let subject = ProxyServer::new(cryptde, false);
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let key = cryptde.public_key();
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let remaining_route = route_to_proxy_server(&key, cryptde);
+        let client_response_payload = ClientResponsePayload {
+            stream_key: socket_addr.clone(),
+            last_response: true,
+            data: PlainData::new(b"da"),
+            failure: Some(ExitFailure::ResponseTruncated)
+        };
+        let incipient_cores_package = IncipientCoresPackage::new(remaining_route.clone(), client_response_payload, &key);
+        let expired_cores_package = ExpiredCoresPackage::new(remaining_route, incipient_cores_package.payload);
+        let mut peer_actors = make_peer_actors_from(None, Some(dispatcher_mock), None, None, None, None);
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+        subject_addr.try_send(expired_cores_package).unwrap ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run();
+
+        dispatcher_awaiter.await_message_count(1);
+
+        let recording = dispatcher_log_arc.lock().unwrap();
+        let record = recording.get_record::<TransmitDataMsg>(0);
+        assert_eq!(record.endpoint, Endpoint::Socket(socket_addr));
+        assert_eq!(record.last_data, true);
+        assert_eq!(record.data, b"da".to_vec());
+        TestLogHandler::new().exists_log_containing("Exit Node cut the response on stream 1.2.3.4:5678 short at its maximum response size");
+    }
+
     #[test]
     fn proxy_server_receives_nonterminal_response_from_hopper() {
         let system = System::new("proxy_server_receives_response_from_hopper");
@@ -383,7 +899,7 @@ mod tests {
         let dispatcher_log_arc = dispatcher_mock.get_recording();
         let dispatcher_awaiter = dispatcher_mock.get_awaiter();
         let cryptde = cryptde();
-        let subject = ProxyServer::new(cryptde);
+        let subject = ProxyServer::new(cryptde, false);
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
         let key = cryptde.public_key();
         let subject_addr: Addr<Syn, ProxyServer> = subject.start();
@@ -391,11 +907,12 @@ mod tests {
         let client_response_payload = ClientResponsePayload {
             stream_key: socket_addr.clone(),
             last_response: false,
-            data: PlainData::new(b"data")
+            data: PlainData::new(b"data"),
+            failure: None
         };
         let incipient_cores_package = IncipientCoresPackage::new(remaining_route.clone(), client_response_payload, &key);
         let expired_cores_package = ExpiredCoresPackage::new(remaining_route, incipient_cores_package.payload);
-        let mut peer_actors = make_peer_actors_from(None, Some(dispatcher_mock), None, None, None);
+        let mut peer_actors = make_peer_actors_from(None, Some(dispatcher_mock), None, None, None, None);
         peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
         subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
 
@@ -413,12 +930,558 @@ mod tests {
         assert_eq!(record.data, b"data".to_vec());
     }
 
+    #[test]
+    fn proxy_server_returns_response_credit_to_the_exit_in_batches() {
+        let system = System::new("proxy_server_returns_response_credit_to_the_exit_in_batches");
+        let hopper_mock = Recorder::new();
+        let hopper_log_arc = hopper_mock.get_recording();
+        let hopper_awaiter = hopper_mock.get_awaiter();
+        let cryptde = cryptde();
+        let key = cryptde.public_key();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let request = ClientRequestPayload {
+            stream_key: socket_addr.clone(),
+            last_data: false,
+            data: PlainData::new(b"GET /big.iso HTTP/1.1\r\nHost: nowhere.com\r\n\r\n"),
+            target_hostname: Some (String::from("nowhere.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: key.clone()
+        };
+        let mut stream = StreamInfo::new(&request);
+        stream.route_opt = Some (RouteQueryResponse {route: route_from_proxy_server(&key, cryptde), exit_key: key.clone()});
+        let mut subject = ProxyServer::new(cryptde, false);
+        subject.streams.insert(socket_addr.clone(), stream);
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let remaining_route = route_to_proxy_server(&key, cryptde);
+        let mut peer_actors = make_peer_actors_from(None, Some(Recorder::new()), Some(hopper_mock), None, None, None);
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+        for _ in 0..(RESPONSE_CREDIT_BATCH + 1) {
+            let client_response_payload = ClientResponsePayload {
+                stream_key: socket_addr.clone(),
+                last_response: false,
+                data: PlainData::new(b"data"),
+                failure: None
+            };
+            let incipient_cores_package = IncipientCoresPackage::new(remaining_route.clone(), client_response_payload, &key);
+            subject_addr.try_send(ExpiredCoresPackage::new(remaining_route.clone(), incipient_cores_package.payload)).unwrap ();
+        }
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run();
+
+        hopper_awaiter.await_message_count(1);
+        let hopper_recording = hopper_log_arc.lock().unwrap();
+        assert_eq!(hopper_recording.len(), 1);
+        assert_eq!(hopper_recording.get_record::<IncipientCoresPackage>(0), &IncipientCoresPackage::new(
+            route_from_proxy_server(&key, cryptde),
+            ResponseCreditPayload {stream_key: socket_addr, credit_chunks: RESPONSE_CREDIT_BATCH},
+            &key
+        ));
+    }
+
+    #[test]
+    fn proxy_server_synthesizes_error_page_when_exit_reports_http_failure() {
+        let system = System::new("proxy_server_synthesizes_error_page_when_exit_reports_http_failure");
+        let dispatcher_mock = Recorder::new();
+        let dispatcher_log_arc = dispatcher_mock.get_recording();
+        let dispatcher_awaiter = dispatcher_mock.get_awaiter();
+        let cryptde = cryptde();
+        let subject = ProxyServer::new(cryptde, false);
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let key = cryptde.public_key();
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let msg_from_dispatcher = InboundClientData {
+            socket_addr: socket_addr.clone(),
+            origin_port: Some (80),
+            peer_public_key_opt: None,
+            component: Component::ProxyServer,
+            last_data: false,
+            data: b"GET /index.html HTTP/1.1\r\nHost: slow.com\r\n\r\n".to_vec()
+        };
+        let remaining_route = route_to_proxy_server(&key, cryptde);
+        let client_response_payload = ClientResponsePayload {
+            stream_key: socket_addr.clone(),
+            last_response: true,
+            data: PlainData::new(b""),
+            failure: Some (ExitFailure::Timeout)
+        };
+        let incipient_cores_package = IncipientCoresPackage::new(remaining_route.clone(), client_response_payload, &key);
+        let expired_cores_package = ExpiredCoresPackage::new(remaining_route, incipient_cores_package.payload);
+        let neighborhood_mock = Recorder::new()
+            .route_query_response(Some (RouteQueryResponse {route: route_from_proxy_server(&key, cryptde), exit_key: key.clone()}));
+        let mut peer_actors = make_peer_actors_from(None, Some(dispatcher_mock), Some(Recorder::new()), None, Some(neighborhood_mock), None);
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+        subject_addr.try_send(msg_from_dispatcher).unwrap ();
+        subject_addr.try_send(expired_cores_package).unwrap ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run();
+
+        dispatcher_awaiter.await_message_count(1);
+
+        let recording = dispatcher_log_arc.lock().unwrap();
+        let record = recording.get_record::<TransmitDataMsg>(0);
+        assert_eq!(record.endpoint, Endpoint::Socket(socket_addr));
+        assert_eq!(record.last_data, true);
+        let expected_data = protocol_pack_for (ProxyProtocol::HTTP).failure_response (ExitFailure::Timeout,
+            &Some (String::from ("slow.com"))).unwrap ();
+        assert_eq!(record.data, expected_data);
+    }
+
+    #[test]
+    fn proxy_server_closes_stream_when_exit_reports_failure_for_unknown_stream() {
+        let system = System::new("proxy_server_closes_stream_when_exit_reports_failure_for_unknown_stream");
+        let dispatcher_mock = Recorder::new();
+        let dispatcher_log_arc = dispatcher_mock.get_recording();
+        let dispatcher_awaiter = dispatcher_mock.get_awaiter();
+        let cryptde = cryptde();
+        let subject = ProxyServer::new(cryptde, false);
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let key = cryptde.public_key();
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let remaining_route = route_to_proxy_server(&key, cryptde);
+        let client_response_payload = ClientResponsePayload {
+            stream_key: socket_addr.clone(),
+            last_response: true,
+            data: PlainData::new(b""),
+            failure: Some (ExitFailure::DnsResolution)
+        };
+        let incipient_cores_package = IncipientCoresPackage::new(remaining_route.clone(), client_response_payload, &key);
+        let expired_cores_package = ExpiredCoresPackage::new(remaining_route, incipient_cores_package.payload);
+        let mut peer_actors = make_peer_actors_from(None, Some(dispatcher_mock), None, None, None, None);
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+        subject_addr.try_send(expired_cores_package).unwrap ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run();
+
+        dispatcher_awaiter.await_message_count(1);
+
+        let recording = dispatcher_log_arc.lock().unwrap();
+        let record = recording.get_record::<TransmitDataMsg>(0);
+        assert_eq!(record.endpoint, Endpoint::Socket(socket_addr));
+        assert_eq!(record.last_data, true);
+        assert_eq!(record.data, Vec::<u8>::new());
+    }
+
+    #[test]
+    fn proxy_server_forgets_stream_once_the_client_has_closed_it() {
+        let system = System::new("proxy_server_forgets_stream_once_the_client_has_closed_it");
+        let dispatcher_mock = Recorder::new();
+        let dispatcher_log_arc = dispatcher_mock.get_recording();
+        let dispatcher_awaiter = dispatcher_mock.get_awaiter();
+        let hopper_mock = Recorder::new();
+        let hopper_log_arc = hopper_mock.get_recording();
+        let neighborhood_mock = Recorder::new();
+        let neighborhood_log_arc = neighborhood_mock.get_recording();
+        let cryptde = cryptde();
+        let key = cryptde.public_key();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let request = ClientRequestPayload {
+            stream_key: socket_addr.clone(),
+            last_data: false,
+            data: PlainData::new(http_request),
+            target_hostname: Some (String::from("nowhere.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: key.clone()
+        };
+        let mut stream = StreamInfo::new(&request);
+        stream.route_opt = Some (RouteQueryResponse {route: route_from_proxy_server(&key, cryptde), exit_key: key.clone()});
+        stream.tried_exit_keys = vec! (key.clone());
+        let msg_from_dispatcher = InboundClientData {
+            socket_addr: socket_addr.clone(),
+            origin_port: Some (80),
+            peer_public_key_opt: None,
+            component: Component::ProxyServer,
+            last_data: true,
+            data: http_request.to_vec()
+        };
+        let mut subject = ProxyServer::new(cryptde, false);
+        subject.streams.insert(socket_addr.clone(), stream);
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let remaining_route = route_to_proxy_server(&key, cryptde);
+        let client_response_payload = ClientResponsePayload {
+            stream_key: socket_addr.clone(),
+            last_response: true,
+            data: PlainData::new(b""),
+            failure: Some (ExitFailure::DnsResolution)
+        };
+        let incipient_cores_package = IncipientCoresPackage::new(remaining_route.clone(), client_response_payload, &key);
+        let expired_cores_package = ExpiredCoresPackage::new(remaining_route, incipient_cores_package.payload);
+        let mut peer_actors = make_peer_actors_from(None, Some(dispatcher_mock), Some(hopper_mock), None, Some(neighborhood_mock), None);
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+        subject_addr.try_send(msg_from_dispatcher).unwrap ();
+        subject_addr.try_send(expired_cores_package).unwrap ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run();
+
+        dispatcher_awaiter.await_message_count(1);
+        let recording = dispatcher_log_arc.lock().unwrap();
+        let record = recording.get_record::<TransmitDataMsg>(0);
+        assert_eq!(record.endpoint, Endpoint::Socket(socket_addr));
+        assert_eq!(record.data, Vec::<u8>::new());
+        assert_eq!(hopper_log_arc.lock().unwrap().len(), 1);
+        assert_eq!(neighborhood_log_arc.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn proxy_server_reroutes_dns_failure_through_different_exit() {
+        let cryptde = cryptde();
+        let key = cryptde.public_key();
+        let other_exit_key = Key::new(&b"other exit"[..]);
+        let first_route = route_from_proxy_server(&key, cryptde);
+        let second_route = Route::new(vec! (
+            RouteSegment::new(vec! (&key, &other_exit_key), Component::ProxyClient),
+            RouteSegment::new(vec! (&other_exit_key, &key), Component::ProxyServer)
+        ), cryptde).unwrap();
+        let hopper_mock = Recorder::new();
+        let hopper_log_arc = hopper_mock.get_recording();
+        let hopper_awaiter = hopper_mock.get_awaiter();
+        let neighborhood_mock = Recorder::new()
+            .route_query_response(Some (RouteQueryResponse {route: first_route.clone(), exit_key: key.clone()}))
+            .route_query_response(Some (RouteQueryResponse {route: second_route.clone(), exit_key: other_exit_key.clone()}));
+        let neighborhood_log_arc = neighborhood_mock.get_recording();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let msg_from_dispatcher = InboundClientData {
+            socket_addr: socket_addr.clone(),
+            origin_port: Some (80),
+            peer_public_key_opt: None,
+            component: Component::ProxyServer,
+            last_data: false,
+            data: http_request.to_vec()
+        };
+        let expected_payload = ClientRequestPayload {
+            stream_key: socket_addr.clone(),
+            last_data: false,
+            data: PlainData::new(http_request),
+            target_hostname: Some (String::from("nowhere.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: key.clone()
+        };
+        let (tx, rx) = mpsc::channel();
+        thread::spawn (move || {
+            let system = System::new("proxy_server_reroutes_dns_failure_through_different_exit");
+            let subject = ProxyServer::new(cryptde, false);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = make_peer_actors_from(None, None, Some(hopper_mock), None, Some(neighborhood_mock), None);
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+            subject_addr.try_send(msg_from_dispatcher).unwrap ();
+            tx.send(subject_addr).unwrap();
+
+            system.run();
+        });
+        let subject_addr = rx.recv().unwrap();
+        hopper_awaiter.await_message_count(1);
+        let remaining_route = route_to_proxy_server(&key, cryptde);
+        let client_response_payload = ClientResponsePayload {
+            stream_key: socket_addr.clone(),
+            last_response: true,
+            data: PlainData::new(b""),
+            failure: Some (ExitFailure::DnsResolution)
+        };
+        let incipient_cores_package = IncipientCoresPackage::new(remaining_route.clone(), client_response_payload, &key);
+
+        subject_addr.try_send(ExpiredCoresPackage::new(remaining_route, incipient_cores_package.payload)).unwrap ();
+
+        hopper_awaiter.await_message_count(2);
+        let hopper_recording = hopper_log_arc.lock().unwrap();
+        assert_eq!(hopper_recording.get_record::<IncipientCoresPackage>(0),
+            &IncipientCoresPackage::new(first_route, expected_payload.clone(), &key));
+        assert_eq!(hopper_recording.get_record::<IncipientCoresPackage>(1),
+            &IncipientCoresPackage::new(second_route, expected_payload, &other_exit_key));
+        let neighborhood_recording = neighborhood_log_arc.lock().unwrap();
+        assert_eq!(neighborhood_recording.get_record::<RouteQueryMessage>(1), &RouteQueryMessage::new(vec! (key)));
+    }
+
+    #[test]
+    fn proxy_server_gives_up_on_dns_failure_after_retry_limit() {
+        let system = System::new("proxy_server_gives_up_on_dns_failure_after_retry_limit");
+        let dispatcher_mock = Recorder::new();
+        let dispatcher_log_arc = dispatcher_mock.get_recording();
+        let dispatcher_awaiter = dispatcher_mock.get_awaiter();
+        let cryptde = cryptde();
+        let key = cryptde.public_key();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let request = ClientRequestPayload {
+            stream_key: socket_addr.clone(),
+            last_data: false,
+            data: PlainData::new(b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n"),
+            target_hostname: Some (String::from("nowhere.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: key.clone()
+        };
+        let mut stream = StreamInfo::new(&request);
+        stream.route_opt = Some (RouteQueryResponse {route: route_from_proxy_server(&key, cryptde), exit_key: key.clone()});
+        stream.unanswered_requests.push(request);
+        stream.tried_exit_keys = (0..(DNS_FAILURE_RETRIES + 1)).map(|index| Key::new(&[index as u8])).collect();
+        let mut subject = ProxyServer::new(cryptde, false);
+        subject.streams.insert(socket_addr.clone(), stream);
+        let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+        let remaining_route = route_to_proxy_server(&key, cryptde);
+        let client_response_payload = ClientResponsePayload {
+            stream_key: socket_addr.clone(),
+            last_response: true,
+            data: PlainData::new(b""),
+            failure: Some (ExitFailure::DnsResolution)
+        };
+        let incipient_cores_package = IncipientCoresPackage::new(remaining_route.clone(), client_response_payload, &key);
+        let expired_cores_package = ExpiredCoresPackage::new(remaining_route, incipient_cores_package.payload);
+        let mut peer_actors = make_peer_actors_from(None, Some(dispatcher_mock), None, None, None, None);
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+        subject_addr.try_send(expired_cores_package).unwrap ();
+
+        Arbiter::system().try_send(msgs::SystemExit(0)).unwrap ();
+        system.run();
+
+        dispatcher_awaiter.await_message_count(1);
+        let recording = dispatcher_log_arc.lock().unwrap();
+        let record = recording.get_record::<TransmitDataMsg>(0);
+        assert_eq!(record.endpoint, Endpoint::Socket(socket_addr));
+        assert_eq!(record.last_data, true);
+        let expected_data = protocol_pack_for (ProxyProtocol::HTTP).failure_response (ExitFailure::DnsResolution,
+            &Some (String::from ("nowhere.com"))).unwrap ();
+        assert_eq!(record.data, expected_data);
+    }
+
+    #[test]
+    fn proxy_server_reroutes_stream_whose_route_stops_answering() {
+        let cryptde = cryptde();
+        let key = cryptde.public_key();
+        let first_exit_key = Key::new(&b"first exit"[..]);
+        let other_exit_key = Key::new(&b"other exit"[..]);
+        let first_route = Route::new(vec! (
+            RouteSegment::new(vec! (&key, &first_exit_key), Component::ProxyClient),
+            RouteSegment::new(vec! (&first_exit_key, &key), Component::ProxyServer)
+        ), cryptde).unwrap();
+        let second_route = Route::new(vec! (
+            RouteSegment::new(vec! (&key, &other_exit_key), Component::ProxyClient),
+            RouteSegment::new(vec! (&other_exit_key, &key), Component::ProxyServer)
+        ), cryptde).unwrap();
+        let hopper_mock = Recorder::new();
+        let hopper_log_arc = hopper_mock.get_recording();
+        let hopper_awaiter = hopper_mock.get_awaiter();
+        let neighborhood_mock = Recorder::new()
+            .route_query_response(Some (RouteQueryResponse {route: first_route.clone(), exit_key: first_exit_key.clone()}))
+            .route_query_response(Some (RouteQueryResponse {route: second_route.clone(), exit_key: other_exit_key.clone()}));
+        let neighborhood_log_arc = neighborhood_mock.get_recording();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let msg_from_dispatcher = InboundClientData {
+            socket_addr: socket_addr.clone(),
+            origin_port: Some (80),
+            peer_public_key_opt: None,
+            component: Component::ProxyServer,
+            last_data: false,
+            data: http_request.to_vec()
+        };
+        let expected_payload = ClientRequestPayload {
+            stream_key: socket_addr.clone(),
+            last_data: false,
+            data: PlainData::new(http_request),
+            target_hostname: Some (String::from("nowhere.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: key.clone()
+        };
+        thread::spawn (move || {
+            let system = System::new("proxy_server_reroutes_stream_whose_route_stops_answering");
+            let mut subject = ProxyServer::new(cryptde, false);
+            subject.route_response_timeout = Duration::from_millis(10);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = make_peer_actors_from(None, None, Some(hopper_mock), None, Some(neighborhood_mock), None);
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap ();
+
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(2);
+        let hopper_recording = hopper_log_arc.lock().unwrap();
+        assert_eq!(hopper_recording.get_record::<IncipientCoresPackage>(0),
+            &IncipientCoresPackage::new(first_route, expected_payload.clone(), &first_exit_key));
+        assert_eq!(hopper_recording.get_record::<IncipientCoresPackage>(1),
+            &IncipientCoresPackage::new(second_route, expected_payload, &other_exit_key));
+        let neighborhood_recording = neighborhood_log_arc.lock().unwrap();
+        assert_eq!(neighborhood_recording.get_record::<NeighborMisbehaviorMessage>(1), &NeighborMisbehaviorMessage {
+            misbehaver: Misbehaver::Node (first_exit_key.clone ()),
+            misbehavior: NeighborMisbehavior::FailedRelay,
+        });
+        assert_eq!(neighborhood_recording.get_record::<RouteQueryMessage>(2), &RouteQueryMessage::new(vec! (first_exit_key)));
+    }
+
+    #[test]
+    fn proxy_server_does_not_reroute_stream_that_has_been_answered() {
+        let cryptde = cryptde();
+        let key = cryptde.public_key();
+        let route = route_from_proxy_server(&key, cryptde);
+        let hopper_mock = Recorder::new();
+        let hopper_log_arc = hopper_mock.get_recording();
+        let hopper_awaiter = hopper_mock.get_awaiter();
+        let neighborhood_mock = Recorder::new();
+        let neighborhood_log_arc = neighborhood_mock.get_recording();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let tls_record = vec! (0x17, 0x03, 0x03, 0x00, 0x01, 0x42);
+        let first_request = ClientRequestPayload {
+            stream_key: socket_addr.clone(),
+            last_data: false,
+            data: PlainData::new(&[0x16, 0x03, 0x01, 0x00, 0x00]),
+            target_hostname: Some (String::from("server.com")),
+            target_port: 443,
+            protocol: ProxyProtocol::TLS,
+            originator_public_key: key.clone()
+        };
+        let mut stream = StreamInfo::new(&first_request);
+        stream.route_opt = Some (RouteQueryResponse {route: route.clone(), exit_key: key.clone()});
+        stream.tried_exit_keys = vec! (key.clone());
+        stream.answered = true;
+        let msg_from_dispatcher = InboundClientData {
+            socket_addr: socket_addr.clone(),
+            origin_port: Some (443),
+            peer_public_key_opt: None,
+            component: Component::ProxyServer,
+            last_data: false,
+            data: tls_record
+        };
+        thread::spawn (move || {
+            let system = System::new("proxy_server_does_not_reroute_stream_that_has_been_answered");
+            let mut subject = ProxyServer::new(cryptde, false);
+            subject.route_response_timeout = Duration::from_millis(10);
+            subject.streams.insert(socket_addr.clone(), stream);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = make_peer_actors_from(None, None, Some(hopper_mock), None, Some(neighborhood_mock), None);
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap ();
+
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(1);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(hopper_log_arc.lock().unwrap().len(), 1);
+        assert_eq!(neighborhood_log_arc.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn proxy_server_gives_up_on_unanswering_routes_after_retry_limit() {
+        let dispatcher_mock = Recorder::new();
+        let dispatcher_log_arc = dispatcher_mock.get_recording();
+        let dispatcher_awaiter = dispatcher_mock.get_awaiter();
+        let hopper_mock = Recorder::new();
+        let cryptde = cryptde();
+        let key = cryptde.public_key();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let request = ClientRequestPayload {
+            stream_key: socket_addr.clone(),
+            last_data: false,
+            data: PlainData::new(http_request),
+            target_hostname: Some (String::from("nowhere.com")),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: key.clone()
+        };
+        let mut stream = StreamInfo::new(&request);
+        stream.route_opt = Some (RouteQueryResponse {route: route_from_proxy_server(&key, cryptde), exit_key: key.clone()});
+        stream.tried_exit_keys = (0..(ROUTE_FAILURE_RETRIES + 1)).map(|index| Key::new(&[index as u8])).collect();
+        let msg_from_dispatcher = InboundClientData {
+            socket_addr: socket_addr.clone(),
+            origin_port: Some (80),
+            peer_public_key_opt: None,
+            component: Component::ProxyServer,
+            last_data: false,
+            data: http_request.to_vec()
+        };
+        thread::spawn (move || {
+            let system = System::new("proxy_server_gives_up_on_unanswering_routes_after_retry_limit");
+            let mut subject = ProxyServer::new(cryptde, false);
+            subject.route_response_timeout = Duration::from_millis(10);
+            subject.streams.insert(socket_addr.clone(), stream);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = make_peer_actors_from(None, Some(dispatcher_mock), Some(hopper_mock), None, None, None);
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap ();
+
+            system.run();
+        });
+
+        dispatcher_awaiter.await_message_count(1);
+        let recording = dispatcher_log_arc.lock().unwrap();
+        let record = recording.get_record::<TransmitDataMsg>(0);
+        assert_eq!(record.endpoint, Endpoint::Socket(socket_addr));
+        assert_eq!(record.last_data, true);
+        let expected_data = protocol_pack_for (ProxyProtocol::HTTP).failure_response (ExitFailure::Timeout,
+            &Some (String::from ("nowhere.com"))).unwrap ();
+        assert_eq!(record.data, expected_data);
+    }
+
+    #[test]
+    fn proxy_server_closes_stream_when_no_route_is_available() {
+        let dispatcher_mock = Recorder::new();
+        let dispatcher_log_arc = dispatcher_mock.get_recording();
+        let dispatcher_awaiter = dispatcher_mock.get_awaiter();
+        let hopper_mock = Recorder::new();
+        let hopper_log_arc = hopper_mock.get_recording();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let msg_from_dispatcher = InboundClientData {
+            socket_addr: socket_addr.clone(),
+            origin_port: Some (80),
+            peer_public_key_opt: None,
+            component: Component::ProxyServer,
+            last_data: false,
+            data: b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n".to_vec()
+        };
+        thread::spawn (move || {
+            let system = System::new("proxy_server_closes_stream_when_no_route_is_available");
+            let subject = ProxyServer::new(cryptde(), false);
+            let subject_addr: Addr<Syn, ProxyServer> = subject.start();
+            let mut peer_actors = make_peer_actors_from(None, Some(dispatcher_mock), Some(hopper_mock), None, None, None);
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap ();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap ();
+
+            system.run();
+        });
+
+        dispatcher_awaiter.await_message_count(1);
+        let recording = dispatcher_log_arc.lock().unwrap();
+        let record = recording.get_record::<TransmitDataMsg>(0);
+        assert_eq!(record.endpoint, Endpoint::Socket(socket_addr));
+        assert_eq!(record.last_data, true);
+        assert_eq!(record.data, Vec::<u8>::new());
+        assert_eq!(hopper_log_arc.lock().unwrap().len(), 0);
+    }
+
     #[test]
     #[should_panic (expected = "Dispatcher unbound in ProxyServer")]
     fn panics_if_dispatcher_is_unbound() {
         let system = System::new("panics_if_dispatcher_is_unbound");
         let cryptde = cryptde();
-        let subject = ProxyServer::new(cryptde);
+        let subject = ProxyServer::new(cryptde, false);
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
         let key = cryptde.public_key();
         let subject_addr: Addr<Syn, ProxyServer> = subject.start();
@@ -426,7 +1489,8 @@ mod tests {
         let client_response_payload = ClientResponsePayload {
             stream_key: socket_addr,
             last_response: true,
-            data: PlainData::new(b"data")
+            data: PlainData::new(b"data"),
+            failure: None
         };
         let incipient_cores_package = IncipientCoresPackage::new(remaining_route.clone(), client_response_payload, &key);
         let expired_cores_package = ExpiredCoresPackage::new(remaining_route, incipient_cores_package.payload);
@@ -442,12 +1506,13 @@ mod tests {
     fn panics_if_hopper_is_unbound() {
         let system = System::new("panics_if_hopper_is_unbound");
         let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
-        let subject = ProxyServer::new(cryptde());
+        let subject = ProxyServer::new(cryptde(), false);
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
         let expected_data = http_request.to_vec();
         let msg_from_dispatcher = InboundClientData {
             socket_addr: socket_addr.clone(),
             origin_port: Some (53),
+            peer_public_key_opt: None,
             component: Component::ProxyServer,
             last_data: false,
             data: expected_data.clone()
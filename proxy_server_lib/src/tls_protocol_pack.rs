@@ -1,5 +1,6 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use protocol_pack::ProtocolPack;
+use sub_lib::proxy_client::ExitFailure;
 use sub_lib::proxy_server::ProxyProtocol;
 use sub_lib::cryptde::PlainData;
 
@@ -13,6 +14,11 @@ impl ProtocolPack for TlsProtocolPack {
         if !TlsProtocolPack::is_client_hello (&data) {return None}
         TlsProtocolPack::find_host_name(&data)
     }
+
+    fn failure_response (&self, _failure: ExitFailure, _target_hostname: &Option<String>) -> Option<Vec<u8>> {
+        // There's no way to inject an error page into an encrypted session; the browser will just see the stream close
+        None
+    }
 }
 
 impl TlsProtocolPack {
@@ -87,6 +93,13 @@ mod tests {
         assert_eq! (result, ProxyProtocol::TLS);
     }
 
+    #[test]
+    fn has_no_failure_response () {
+        let result = TlsProtocolPack{}.failure_response (ExitFailure::Timeout, &Some (String::from ("server.com")));
+
+        assert_eq! (result, None);
+    }
+
     #[test]
     fn rejects_non_empty_packet_that_is_not_handshake () {
         vec! (0x14u8, 0x015u8, 0x17u8).iter ().for_each (|content_type| {
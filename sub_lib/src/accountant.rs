@@ -0,0 +1,521 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use actix::Message;
+use actix::Recipient;
+use actix::Syn;
+use blockchain_bridge::ChannelTerms;
+use blockchain_bridge::TransactionRequest;
+use cryptde::Key;
+use hopper::ExpiredCoresPackage;
+use peer_actors::BindMessage;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+use utils::to_string;
+use wallet::Wallet;
+
+// What a Node charges, in the smallest unit of SUB, for each CORES package and for each byte in it,
+// unless its operator sets other rates
+pub const ROUTING_SERVICE_RATE: i64 = 100;
+pub const ROUTING_BYTE_RATE: i64 = 1;
+pub const EXIT_SERVICE_RATE: i64 = 200;
+pub const EXIT_BYTE_RATE: i64 = 2;
+
+pub const DEFAULT_RATES: RateSchedule = RateSchedule {
+    routing_service_rate: ROUTING_SERVICE_RATE,
+    routing_byte_rate: ROUTING_BYTE_RATE,
+    exit_service_rate: EXIT_SERVICE_RATE,
+    exit_byte_rate: EXIT_BYTE_RATE,
+};
+
+pub const DEFAULT_PAYABLE_SCAN_INTERVAL_MS: u64 = 3600000;
+pub const DEFAULT_PAYMENT_THRESHOLD: i64 = 10000000;
+pub const DEFAULT_PAYMENT_AGE_THRESHOLD_MS: u64 = 604800000;
+pub const DEFAULT_PAYMENT_RETRY_MS: u64 = 60000;
+pub const DEFAULT_RECEIVABLE_SCAN_INTERVAL_MS: u64 = 3600000;
+pub const DEFAULT_CHANNEL_DEPOSIT: i64 = 100000000;
+pub const DEFAULT_CHANNEL_MIN_PAYMENTS: usize = 3;
+pub const DEFAULT_CHANNEL_LIFETIME_MS: u64 = 2592000000;
+pub const DEFAULT_DEBT_CEILING: i64 = 50000000;
+pub const DEFAULT_INVOICE_INTERVAL_MS: u64 = 3600000;
+// The last hour, day and week
+pub const DEFAULT_FINANCIALS_WINDOWS_MS: [u64; 3] = [3600000, 86400000, 604800000];
+
+pub const DEFAULT_DELINQUENCY_CURVE: DelinquencyCurve = DelinquencyCurve {
+    grace_period_ms: 864000000,
+    max_debt: 10000000,
+    decline_period_ms: 1728000000,
+    min_debt: 100000,
+};
+
+// Ten dollars, say, owed to one Node gets paid; a debt tolerated falls from ten to a dime
+pub const DEFAULT_FIAT_THRESHOLDS: FiatThresholds = FiatThresholds {
+    payment_threshold: 1000,
+    max_debt: 1000,
+    min_debt: 10,
+};
+
+// A whole SUB is this many of the smallest units amounts are kept in
+pub const SUB_UNITS_PER_TOKEN: i64 = 100;
+
+pub const DEFAULT_FREE_TIER: FreeTier = FreeTier {
+    bytes: 10000000,
+    period_ms: 86400000,
+    throttle_debt: 100000,
+    refusal_debt: 1000000,
+};
+
+// How much another Node can owe, and for how long, before it's a deadbeat. Debts younger than the
+// grace period are never held against anybody. After that, the debt tolerated falls in a straight
+// line from max_debt to min_debt over the decline period, and stays at min_debt from then on.
+#[derive (Clone, Debug, PartialEq)]
+pub struct DelinquencyCurve {
+    pub grace_period_ms: u64,
+    pub max_debt: i64,
+    pub decline_period_ms: u64,
+    pub min_debt: i64,
+}
+
+impl DelinquencyCurve {
+    pub fn is_delinquent (&self, debt: i64, age: Duration) -> bool {
+        let age_ms = age.as_secs () * 1000 + (age.subsec_nanos () / 1000000) as u64;
+        if age_ms < self.grace_period_ms {return false}
+        let declined_ms = age_ms - self.grace_period_ms;
+        let tolerated_debt = if declined_ms >= self.decline_period_ms {
+            self.min_debt
+        }
+        else {
+            self.max_debt - (self.max_debt - self.min_debt) * declined_ms as i64 / self.decline_period_ms as i64
+        };
+        debt > tolerated_debt
+    }
+}
+
+// For a Node with a price oracle: thresholds in hundredths of a fiat currency, such as cents, that
+// take the place of the SUB ones. They're converted at the going price each time a scan uses them,
+// so what's owed is judged by what it's worth rather than by how many SUB it comes to.
+#[derive (Clone, Debug, PartialEq)]
+pub struct FiatThresholds {
+    pub payment_threshold: i64,
+    // what the delinquency curve tolerates at first and in the end
+    pub max_debt: i64,
+    pub min_debt: i64,
+}
+
+// Hundredths of a fiat currency in the smallest units of SUB, at a price per whole SUB
+pub fn fiat_to_sub (hundredths: i64, price: f64) -> i64 {
+    ((hundredths as f64 / 100.0) / price * SUB_UNITS_PER_TOKEN as f64).round () as i64
+}
+
+// What a Node that has never paid gets for nothing, so a brand-new Node can get going before it has
+// any SUB: its first bytes, or whatever it uses in its first period, aren't charged. Nodes whose
+// packages come in from the same neighbor get no more than those bytes between them each period,
+// since new Nodes cost nothing to make. After that, a
+// Node that still hasn't paid is throttled once it owes more than throttle_debt, and refused once
+// it owes more than refusal_debt. Its first payment puts it back in good standing for good.
+#[derive (Clone, Debug, PartialEq)]
+pub struct FreeTier {
+    pub bytes: u64,
+    pub period_ms: u64,
+    pub throttle_debt: i64,
+    pub refusal_debt: i64,
+}
+
+// What one Node charges for relaying a CORES package and for exiting a request, each so much for
+// the package and so much per byte in it. Every Node advertises its own in Gossip; one that
+// doesn't is taken to charge the defaults.
+#[derive (Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RateSchedule {
+    pub routing_service_rate: i64,
+    pub routing_byte_rate: i64,
+    pub exit_service_rate: i64,
+    pub exit_byte_rate: i64,
+}
+
+impl Default for RateSchedule {
+    fn default () -> RateSchedule {
+        DEFAULT_RATES
+    }
+}
+
+impl RateSchedule {
+    pub fn routing_charge (&self, payload_size: usize) -> i64 {
+        self.routing_service_rate + self.routing_byte_rate * payload_size as i64
+    }
+
+    pub fn exit_charge (&self, payload_size: usize) -> i64 {
+        self.exit_service_rate + self.exit_byte_rate * payload_size as i64
+    }
+}
+
+#[derive (Clone, Debug, PartialEq)]
+pub struct AccountantConfig {
+    // milliseconds between scans for debts this Node should pay; 0 for no scans
+    pub payable_scan_interval_ms: u64,
+    // a debt to one Node that reaches this amount is paid at the next scan
+    pub payment_threshold: i64,
+    // milliseconds a debt of any amount can go unpaid before it's paid at the next scan
+    pub payment_age_threshold_ms: u64,
+    // milliseconds before a failed payment is tried again; doubled for each failure in a row
+    pub payment_retry_ms: u64,
+    // milliseconds between scans for Nodes that owe this Node too much for too long; 0 for no scans
+    pub receivable_scan_interval_ms: u64,
+    pub delinquency_curve: DelinquencyCurve,
+    pub free_tier: FreeTier,
+    // a Node that owes more than this is refused service, however well it has paid before, until
+    // it pays enough to come back under
+    pub debt_ceiling: i64,
+    // SUB locked up in a payment channel to a Node this Node pays often; 0 for no channels
+    pub channel_deposit: i64,
+    // on-chain payments to a Node before a channel is opened to it
+    pub channel_min_payments: usize,
+    // milliseconds a channel is paid through before its payee closes it and claims its balance
+    pub channel_lifetime_ms: u64,
+    // milliseconds between signed invoices to each Node this Node has charged; 0 for no invoices
+    pub invoice_interval_ms: u64,
+    // what this Node charges other Nodes, as it advertises in Gossip
+    pub rates: RateSchedule,
+    // where to get the price of SUB, if thresholds are in fiat
+    pub price_oracle_url_opt: Option<String>,
+    // if there's a price oracle; the SUB thresholds stand in when it can't say
+    pub fiat_thresholds_opt: Option<FiatThresholds>,
+}
+
+// This Node relayed a CORES package on a route built by another Node, handed over by the neighbor
+// at neighbor_ip
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct ReportRoutingServiceMessage {
+    pub consuming_node_key: Key,
+    pub neighbor_ip: IpAddr,
+    pub payload_size: usize,
+}
+
+// This Node sent a request out onto the Internet for another Node, handed over by the neighbor at
+// neighbor_ip
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct ReportExitServiceMessage {
+    pub consuming_node_key: Key,
+    pub neighbor_ip: IpAddr,
+    pub payload_size: usize,
+}
+
+// Another Node paid this Node, as seen on the blockchain; whose wallet it was is up to the Accountant
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct ReportPaymentReceivedMessage {
+    pub payer: Wallet,
+    pub amount: i64,
+    pub transaction_hash: String,
+}
+
+#[derive (Clone, Debug, PartialEq)]
+pub enum TransactionStatus {
+    // sent to the Ethereum node, but not yet mined deep enough to count
+    Submitted,
+    // mined, and under enough blocks that it's there to stay
+    Confirmed,
+    // never sent, or mined and reverted
+    Failed (String),
+}
+
+// What has become of a transaction the Accountant asked the BlockchainBridge for
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct ReportTransactionMessage {
+    pub request: TransactionRequest,
+    // None if it was never sent
+    pub transaction_hash_opt: Option<String>,
+    pub status: TransactionStatus,
+}
+
+// The channel's terms if it checked out, or why it didn't
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct ReportChannelMessage {
+    pub channel_id: String,
+    pub result: Result<ChannelTerms, String>,
+}
+
+// Another Node says, in its Gossip, where it wants to be paid
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct ReportEarningWalletMessage {
+    pub public_key: Key,
+    pub earning_wallet: Wallet,
+}
+
+// Another Node says, in its Gossip, where its payments will come from
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct ReportConsumingWalletMessage {
+    pub public_key: Key,
+    pub consuming_wallet: Wallet,
+}
+
+// A relay that won't carry a Node's packages until it's paid tells that Node's Accountant so,
+// straight from its own, since it can't read the packages to answer any one request
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ServiceRefusal {
+    pub refusing_key: Key,
+    pub refused_key: Key,
+}
+
+// The price oracle says what one SUB is worth, in the currency the fiat thresholds are in
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct ReportFiatPriceMessage {
+    pub price: f64,
+}
+
+// Another Node says, in its Gossip, what it charges
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct ReportRatesMessage {
+    pub public_key: Key,
+    pub rates: RateSchedule,
+}
+
+#[derive (Clone, Copy, Debug, PartialEq)]
+pub enum ServiceStanding {
+    Good,
+    Throttled,
+    Refused,
+}
+
+// The Accountant has changed its mind about how much service a consuming Node should get
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct ServiceStandingMessage {
+    pub consuming_node_key: Key,
+    pub standing: ServiceStanding,
+}
+
+// Service one way between this Node and another, and what it was charged
+#[derive (Clone, Debug, PartialEq, Default)]
+pub struct ServiceTotals {
+    pub bytes_routed: u64,
+    pub bytes_exited: u64,
+    pub amount: i64,
+}
+
+// How this Node stands with another Node
+#[derive (Clone, Debug, PartialEq)]
+pub struct NeighborStats {
+    pub public_key: Key,
+    // what this Node did for the other Node, and earned for it
+    pub served: ServiceTotals,
+    // what the other Node did for this Node, and charged for it
+    pub consumed: ServiceTotals,
+    // what the other Node owes this Node now, whatever the window
+    pub receivable_balance: i64,
+    // what this Node owes the other Node now
+    pub payable_balance: i64,
+}
+
+// For the UI: service both ways over the last window_ms, counted from the start of the hour it
+// begins in, or since the ledger began if there's no window. Every Node with service in the window
+// or a balance either way is listed, those that have earned this Node the most first.
+#[derive (Clone, Debug, PartialEq)]
+pub struct GetNeighborStatsMsg {
+    pub window_ms_opt: Option<u64>,
+}
+
+impl Message for GetNeighborStatsMsg {
+    type Result = Result<Vec<NeighborStats>, String>;
+}
+
+// What this Node has done for other Nodes over its whole life, not just since it last started
+#[derive (Clone, Debug, PartialEq, Default)]
+pub struct NodeStats {
+    pub bytes_relayed: u64,
+    pub packages_relayed: u64,
+    pub bytes_exited: u64,
+    pub requests_served: u64,
+    pub uptime_ms: u64,
+}
+
+// For the UI
+#[derive (Clone, Debug, PartialEq)]
+pub struct GetNodeStatsMsg {}
+
+impl Message for GetNodeStatsMsg {
+    type Result = NodeStats;
+}
+
+// Payments each way over a window of time ending now
+#[derive (Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct PaymentTotals {
+    pub window_ms: u64,
+    // what this Node paid other Nodes
+    pub paid: i64,
+    // what other Nodes paid this Node
+    pub received: i64,
+}
+
+// Money that has yet to change hands on the blockchain
+#[derive (Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PendingTransaction {
+    // A payment that failed, to be tried again once its backoff is up
+    PaymentRetry {payee: Key, amount: i64, failures: u32},
+    // A payment on its way to the blockchain, with its transaction once it's submitted; it isn't
+    // paid until it's confirmed
+    PaymentUnconfirmed {payee: Key, amount: i64, transaction_hash_opt: Option<String>},
+    // What's been paid through an open channel so far, which goes on-chain when the channel closes
+    ChannelOut {channel_id: String, payee: Key, balance: i64, deposit: i64},
+    ChannelIn {channel_id: String, payer: Key, balance: i64, deposit: i64},
+}
+
+impl fmt::Display for PendingTransaction {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PendingTransaction::PaymentRetry {ref payee, amount, failures} =>
+                write! (f, "Payment of {} to Node {}, failed {} time(s) so far", amount, to_string (&payee.data), failures),
+            PendingTransaction::PaymentUnconfirmed {ref payee, amount, transaction_hash_opt: Some (ref transaction_hash)} =>
+                write! (f, "Payment of {} to Node {} in transaction {}, awaiting confirmation", amount, to_string (&payee.data), transaction_hash),
+            PendingTransaction::PaymentUnconfirmed {ref payee, amount, transaction_hash_opt: None} =>
+                write! (f, "Payment of {} to Node {}, waiting to be sent", amount, to_string (&payee.data)),
+            PendingTransaction::ChannelOut {ref channel_id, ref payee, balance, deposit} =>
+                write! (f, "{} of {} paid to Node {} through channel {}, settled when it closes", balance, deposit, to_string (&payee.data), channel_id),
+            PendingTransaction::ChannelIn {ref channel_id, ref payer, balance, deposit} =>
+                write! (f, "{} of {} received from Node {} through channel {}, settled when it closes", balance, deposit, to_string (&payer.data), channel_id),
+        }
+    }
+}
+
+// Where this Node stands with everybody at once. Only balances actually owed count as unpaid;
+// prepayments don't offset them.
+#[derive (Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct Financials {
+    pub unpaid_payables: i64,
+    pub unpaid_receivables: i64,
+    // one for each window asked for, in the order asked
+    pub payment_totals: Vec<PaymentTotals>,
+    pub pending_transactions: Vec<PendingTransaction>,
+}
+
+// The report the "financials" command prints
+impl fmt::Display for Financials {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln! (f, "Unpaid payables:    {}", self.unpaid_payables)?;
+        writeln! (f, "Unpaid receivables: {}", self.unpaid_receivables)?;
+        for totals in &self.payment_totals {
+            writeln! (f, "Last {}: paid {}, received {}", describe_window (totals.window_ms), totals.paid, totals.received)?;
+        }
+        if self.pending_transactions.is_empty () {
+            return writeln! (f, "Nothing pending on the blockchain")
+        }
+        writeln! (f, "Pending on the blockchain:")?;
+        for pending in &self.pending_transactions {
+            writeln! (f, "    {}", pending)?;
+        }
+        Ok (())
+    }
+}
+
+fn describe_window (window_ms: u64) -> String {
+    let units: [(u64, &str); 4] = [(86400000, "d"), (3600000, "h"), (60000, "m"), (1000, "s")];
+    match units.iter ().find (|&&(unit_ms, _)| (window_ms >= unit_ms) && (window_ms % unit_ms == 0)) {
+        Some (&(unit_ms, suffix)) => format! ("{}{}", window_ms / unit_ms, suffix),
+        None => format! ("{}ms", window_ms)
+    }
+}
+
+// For the UI gateway and the "financials" command: payments are totalled over each of the windows
+// given, such as DEFAULT_FINANCIALS_WINDOWS_MS
+#[derive (Clone, Debug, PartialEq)]
+pub struct GetFinancialsMsg {
+    pub windows_ms: Vec<u64>,
+}
+
+impl Message for GetFinancialsMsg {
+    type Result = Result<Financials, String>;
+}
+
+#[derive (Clone)]
+pub struct AccountantSubs {
+    pub bind: Recipient<Syn, BindMessage>,
+    pub report_routing_service: Recipient<Syn, ReportRoutingServiceMessage>,
+    pub report_exit_service: Recipient<Syn, ReportExitServiceMessage>,
+    pub report_payment_received: Recipient<Syn, ReportPaymentReceivedMessage>,
+    pub report_transaction: Recipient<Syn, ReportTransactionMessage>,
+    pub report_channel: Recipient<Syn, ReportChannelMessage>,
+    pub report_earning_wallet: Recipient<Syn, ReportEarningWalletMessage>,
+    pub report_consuming_wallet: Recipient<Syn, ReportConsumingWalletMessage>,
+    pub report_rates: Recipient<Syn, ReportRatesMessage>,
+    pub report_fiat_price: Recipient<Syn, ReportFiatPriceMessage>,
+    pub from_hopper: Recipient<Syn, ExpiredCoresPackage>,
+    pub get_neighbor_stats: Recipient<Syn, GetNeighborStatsMsg>,
+    pub get_node_stats: Recipient<Syn, GetNodeStatsMsg>,
+    pub get_financials: Recipient<Syn, GetFinancialsMsg>,
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    fn curve () -> DelinquencyCurve {
+        DelinquencyCurve {grace_period_ms: 1000, max_debt: 10000, decline_period_ms: 2000, min_debt: 2000}
+    }
+
+    #[test]
+    fn nobody_is_delinquent_during_the_grace_period () {
+        assert_eq! (curve ().is_delinquent (1000000, Duration::from_millis (999)), false);
+    }
+
+    #[test]
+    fn tolerated_debt_declines_after_the_grace_period () {
+        let subject = curve ();
+
+        assert_eq! (subject.is_delinquent (10000, Duration::from_millis (1000)), false);
+        assert_eq! (subject.is_delinquent (10001, Duration::from_millis (1000)), true);
+        assert_eq! (subject.is_delinquent (6000, Duration::from_millis (2000)), false);
+        assert_eq! (subject.is_delinquent (6001, Duration::from_millis (2000)), true);
+        assert_eq! (subject.is_delinquent (2000, Duration::from_millis (3000)), false);
+        assert_eq! (subject.is_delinquent (2001, Duration::from_millis (3000)), true);
+        assert_eq! (subject.is_delinquent (2001, Duration::from_millis (1000000)), true);
+    }
+
+    #[test]
+    fn charges_are_so_much_for_the_package_and_so_much_per_byte () {
+        let subject = RateSchedule {routing_service_rate: 10, routing_byte_rate: 2, exit_service_rate: 30, exit_byte_rate: 4};
+
+        assert_eq! (subject.routing_charge (100), 210);
+        assert_eq! (subject.exit_charge (100), 430);
+        assert_eq! (RateSchedule::default ().routing_charge (0), ROUTING_SERVICE_RATE);
+    }
+
+    #[test]
+    fn fiat_amounts_come_to_more_sub_the_cheaper_it_is () {
+        assert_eq! (fiat_to_sub (1000, 0.05), 200 * SUB_UNITS_PER_TOKEN);
+        assert_eq! (fiat_to_sub (1000, 2.0), 5 * SUB_UNITS_PER_TOKEN);
+        assert_eq! (fiat_to_sub (1, 3.0), 0);
+    }
+
+    #[test]
+    fn financials_are_reported_window_by_window_with_whatever_is_pending () {
+        let subject = Financials {
+            unpaid_payables: 1200,
+            unpaid_receivables: 3400,
+            payment_totals: vec! (
+                PaymentTotals {window_ms: 3600000, paid: 500, received: 0},
+                PaymentTotals {window_ms: 5400000, paid: 700, received: 200},
+            ),
+            pending_transactions: vec! (
+                PendingTransaction::PaymentRetry {payee: Key::new (b"supplier"), amount: 400, failures: 2},
+                PendingTransaction::PaymentUnconfirmed {payee: Key::new (b"exit"), amount: 600, transaction_hash_opt: Some (String::from ("0xA1"))},
+                PendingTransaction::PaymentUnconfirmed {payee: Key::new (b"hop"), amount: 200, transaction_hash_opt: None},
+                PendingTransaction::ChannelOut {channel_id: String::from ("0xC1"), payee: Key::new (b"relay"), balance: 300, deposit: 1000},
+                PendingTransaction::ChannelIn {channel_id: String::from ("0xC2"), payer: Key::new (b"customer"), balance: 100, deposit: 900},
+            ),
+        };
+
+        assert_eq! (format! ("{}", subject), String::from ("\
+Unpaid payables:    1200
+Unpaid receivables: 3400
+Last 1h: paid 500, received 0
+Last 90m: paid 700, received 200
+Pending on the blockchain:
+    Payment of 400 to Node supplier, failed 2 time(s) so far
+    Payment of 600 to Node exit in transaction 0xA1, awaiting confirmation
+    Payment of 200 to Node hop, waiting to be sent
+    300 of 1000 paid to Node relay through channel 0xC1, settled when it closes
+    100 of 900 received from Node customer through channel 0xC2, settled when it closes
+"));
+        assert_eq! (format! ("{}", Financials::default ()), String::from ("\
+Unpaid payables:    0
+Unpaid receivables: 0
+Nothing pending on the blockchain
+"));
+    }
+}
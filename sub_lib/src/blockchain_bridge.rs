@@ -0,0 +1,121 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use actix::Message;
+use actix::Recipient;
+use actix::Syn;
+use cryptde::Key;
+use peer_actors::BindMessage;
+use std::fmt;
+use wallet::Wallet;
+
+// The SUB token contract on the Ethereum main network
+pub const SUB_CONTRACT_ADDRESS: &str = "0x12480e24eb5bec1a9d4369cab6a80cad3c0a377a";
+pub const MAINNET_CHAIN_ID: u64 = 1;
+pub const DEFAULT_PAYMENT_WATCH_INTERVAL_MS: u64 = 60000;
+pub const WEI_PER_GWEI: u64 = 1000000000;
+pub const DEFAULT_MAX_GAS_PRICE: u64 = 50 * WEI_PER_GWEI;
+// About three minutes of blocks, past which a reorganization is too unlikely to worry about
+pub const DEFAULT_CONFIRMATIONS: u64 = 12;
+
+#[derive (Clone, Debug, PartialEq)]
+pub struct BlockchainBridgeConfig {
+    // http:// URL of the Ethereum node's JSON-RPC interface; without one, the Node stays off the blockchain
+    pub blockchain_service_url_opt: Option<String>,
+    pub chain_id: u64,
+    pub sub_contract_address: Wallet,
+    // the contract payment channels are opened and closed with; without one, debts are all paid on-chain
+    // and channels other Nodes claim to have opened to this one aren't credited
+    pub channel_contract_opt: Option<Wallet>,
+    // hex private key of the wallet this Node pays from
+    pub consuming_private_key_opt: Option<String>,
+    // http:// URL of a service that signs with a key kept off this machine, such as on a hardware
+    // wallet; used instead of a consuming private key
+    pub signing_service_url_opt: Option<String>,
+    // the wallet the signing service pays from
+    pub signing_wallet_opt: Option<Wallet>,
+    // where this Node is paid; the consuming wallet if there's no other
+    pub earning_wallet_opt: Option<Wallet>,
+    // milliseconds between checks for SUB sent to this Node's earning wallet; 0 for no checks
+    pub payment_watch_interval_ms: u64,
+    // wei per unit of gas to offer; None to offer whatever the Ethereum node says is going
+    pub gas_price_opt: Option<u64>,
+    // payments wait rather than offer more than this many wei per unit of gas
+    pub max_gas_price: u64,
+    // blocks mined after the one a transaction is in before it counts, so a reorganization of the
+    // chain can't take it back
+    pub confirmations: u64,
+}
+
+// Amounts are decimal, in wei and in the smallest unit of SUB, since they can outgrow 64 bits
+#[derive (Clone, Debug, PartialEq)]
+pub struct Balances {
+    pub wallet: Wallet,
+    pub eth_wei: String,
+    pub sub: String,
+}
+
+// What the Node's consuming wallet holds
+#[derive (Clone, Debug, PartialEq)]
+pub struct GetBalancesMsg {}
+
+impl Message for GetBalancesMsg {
+    type Result = Result<Balances, String>;
+}
+
+// A transaction the Accountant wants on the blockchain. Every report on it carries it back
+// unchanged, so the Accountant can tell what the report is about.
+#[derive (Clone, Debug, PartialEq)]
+pub enum TransactionRequest {
+    // SUB from the Node's consuming wallet, to pay off what it owes another Node
+    Payment {payee_key: Key, payee: Wallet, amount: i64},
+    // a deposit locked up in a payment channel to a Node this Node pays often. The payer names the
+    // channel, so it's known before the transaction is mined; expires is in seconds since the epoch.
+    // The channel contract keeps the payer's key, so only updates this Node signs can settle it.
+    OpenChannel {channel_id: String, payer_key: Key, payee_key: Key, payee: Wallet, deposit: i64, expires: u64},
+    // claims the balance the payer last signed for from a channel to this Node
+    CloseChannel {channel_id: String, balance: i64, signature: Vec<u8>},
+}
+
+// For the log: "Submitted payment of 1024 SUB to 0x..."
+impl fmt::Display for TransactionRequest {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TransactionRequest::Payment {ref payee, amount, ..} =>
+                write! (f, "payment of {} SUB to {}", amount, payee),
+            TransactionRequest::OpenChannel {ref channel_id, ref payee, deposit, ..} =>
+                write! (f, "opening of channel {} to {} with a deposit of {} SUB", channel_id, payee, deposit),
+            TransactionRequest::CloseChannel {ref channel_id, balance, ..} =>
+                write! (f, "closing of channel {} at a balance of {} SUB", channel_id, balance),
+        }
+    }
+}
+
+// Answered with ReportTransactionMessages to the Accountant rather than with a result: a
+// transaction takes minutes to be mined deep enough to count, and nobody should wait for it
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct RequestTransactionMsg {
+    pub request: TransactionRequest,
+}
+
+// A channel as the channel contract has it, once it's mined deep enough to count
+#[derive (Clone, Debug, PartialEq)]
+pub struct ChannelTerms {
+    pub deposit: i64,
+    // seconds since the epoch
+    pub expires: u64,
+}
+
+// Whether a channel another Node says it's paying this one through is open on the blockchain, to
+// this Node's earning wallet, for that Node's key. Answered with a ReportChannelMessage.
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct CheckChannelMsg {
+    pub channel_id: String,
+    pub payer_key: Key,
+}
+
+#[derive (Clone)]
+pub struct BlockchainBridgeSubs {
+    pub bind: Recipient<Syn, BindMessage>,
+    pub get_balances: Recipient<Syn, GetBalancesMsg>,
+    pub request_transaction: Recipient<Syn, RequestTransactionMsg>,
+    pub check_channel: Recipient<Syn, CheckChannelMsg>,
+}
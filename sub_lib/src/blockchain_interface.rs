@@ -0,0 +1,59 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use actix::Recipient;
+use actix::Syn;
+use blockchain_bridge::CheckChannelMsg;
+use blockchain_bridge::RequestTransactionMsg;
+use blockchain_bridge::TransactionRequest;
+use cryptde::Key;
+
+// Puts the Accountant's transactions on the blockchain without keeping it waiting. A request that's
+// taken is answered later with ReportTransactionMessages to the Accountant: one when it's submitted,
+// and one when it's confirmed or has failed. A request that can't be taken fails right away, and
+// can be tried again later. Channel checks work the same way, answered with a ReportChannelMessage.
+pub trait BlockchainInterface {
+    fn request (&self, request: TransactionRequest) -> Result<(), String>;
+    fn check_channel (&self, channel_id: &str, payer_key: &Key) -> Result<(), String>;
+}
+
+// For Nodes that have no way to pay yet: every request fails, so debts stay on the books
+pub struct BlockchainInterfaceNull {}
+
+impl BlockchainInterface for BlockchainInterfaceNull {
+    fn request (&self, _request: TransactionRequest) -> Result<(), String> {
+        Err (String::from ("No blockchain is available"))
+    }
+
+    fn check_channel (&self, _channel_id: &str, _payer_key: &Key) -> Result<(), String> {
+        Err (String::from ("No blockchain is available"))
+    }
+}
+
+impl BlockchainInterfaceNull {
+    pub fn new () -> BlockchainInterfaceNull {
+        BlockchainInterfaceNull {}
+    }
+}
+
+// Hands requests to the BlockchainBridge, which answers them as its Ethereum node does
+pub struct BlockchainInterfaceBridge {
+    request_transaction: Recipient<Syn, RequestTransactionMsg>,
+    check_channel: Recipient<Syn, CheckChannelMsg>,
+}
+
+impl BlockchainInterface for BlockchainInterfaceBridge {
+    fn request (&self, request: TransactionRequest) -> Result<(), String> {
+        self.request_transaction.try_send (RequestTransactionMsg {request})
+            .map_err (|_| String::from ("BlockchainBridge isn't taking requests"))
+    }
+
+    fn check_channel (&self, channel_id: &str, payer_key: &Key) -> Result<(), String> {
+        self.check_channel.try_send (CheckChannelMsg {channel_id: String::from (channel_id), payer_key: payer_key.clone ()})
+            .map_err (|_| String::from ("BlockchainBridge isn't taking requests"))
+    }
+}
+
+impl BlockchainInterfaceBridge {
+    pub fn new (request_transaction: Recipient<Syn, RequestTransactionMsg>, check_channel: Recipient<Syn, CheckChannelMsg>) -> BlockchainInterfaceBridge {
+        BlockchainInterfaceBridge {request_transaction, check_channel}
+    }
+}
@@ -10,7 +10,7 @@ use serde::de::Visitor;
 
 // TODO: Consider generating each of these three with a single macro
 
-#[derive (Clone, Debug, PartialEq, Eq)]
+#[derive (Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Key {
     pub data: Vec<u8>
 }
@@ -132,13 +132,24 @@ pub enum CryptdecError {
     EmptyKey,
     EmptyData,
     InvalidKey (String),
+    WrongAssociatedData,
+    // Decrypted, but not into what was expected
+    InvalidData (String),
 }
 
 pub trait CryptDE: Send + Sync {
     fn generate_key_pair (&mut self);
+    // For restoring a key pair that was generated earlier; the public key is derived from the private one
+    fn adopt_private_key (&mut self, private_key: &Key) -> Result<(), CryptdecError>;
     fn encode(&self, key: &Key, data: &PlainData) -> Result<CryptData, CryptdecError>;
     fn decode(&self, key: &Key, data: &CryptData) -> Result<PlainData, CryptdecError>;
+    // Like encode and decode, but the associated data, which travels in the clear, is authenticated
+    // along with the encrypted data: decryption fails unless it's given the same associated data
+    fn encrypt(&self, key: &Key, associated_data: &[u8], data: &PlainData) -> Result<CryptData, CryptdecError>;
+    fn decrypt(&self, key: &Key, associated_data: &[u8], data: &CryptData) -> Result<PlainData, CryptdecError>;
     fn random(&self, dest: &mut [u8]);
+    fn sign(&self, data: &PlainData) -> Result<CryptData, CryptdecError>;
+    fn verify_signature(&self, data: &PlainData, signature: &CryptData, public_key: &Key) -> bool;
     // TODO: Would be really nice if these could return &Key instead of Key
     fn private_key(&self) -> Key;
     fn public_key(&self) -> Key;
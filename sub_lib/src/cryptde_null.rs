@@ -1,5 +1,7 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use rand::prelude::*;
+use sha2::Digest;
+use sha2::Sha256;
 use cryptde::CryptDE;
 use cryptde::CryptdecError;
 use cryptde::Key;
@@ -22,6 +24,13 @@ impl CryptDE for CryptDENull {
         self.public_key = CryptDENull::other_key (&self.private_key ())
     }
 
+    fn adopt_private_key (&mut self, private_key: &Key) -> Result<(), CryptdecError> {
+        if private_key.data.is_empty () {return Err (CryptdecError::EmptyKey)}
+        self.private_key = private_key.clone ();
+        self.public_key = CryptDENull::other_key (private_key);
+        Ok (())
+    }
+
     fn encode(&self, key: &Key, data: &PlainData) -> Result<CryptData, CryptdecError> {
         if key.data.is_empty() {
             Err(CryptdecError::EmptyKey)
@@ -50,12 +59,40 @@ impl CryptDE for CryptDENull {
         }
     }
 
+    // The digest of the associated data rides between the key and the data
+    fn encrypt (&self, key: &Key, associated_data: &[u8], data: &PlainData) -> Result<CryptData, CryptdecError> {
+        if data.data.is_empty () {return Err (CryptdecError::EmptyData)}
+        let with_digest = [&Sha256::digest (associated_data)[..], &data.data[..]].concat ();
+        self.encode (key, &PlainData::new (&with_digest[..]))
+    }
+
+    fn decrypt (&self, key: &Key, associated_data: &[u8], data: &CryptData) -> Result<PlainData, CryptdecError> {
+        let with_digest = self.decode (key, data)?;
+        let digest = Sha256::digest (associated_data);
+        if (with_digest.data.len () < digest.len ()) || (&with_digest.data[..digest.len ()] != &digest[..]) {
+            return Err (CryptdecError::WrongAssociatedData)
+        }
+        Ok (PlainData::new (&with_digest.data[digest.len ()..]))
+    }
+
     fn random (&self, dest: &mut [u8]) {
         for i in 0..dest.len () {
             dest[i] = '4' as u8
         }
     }
 
+    // A null signature is the digest of the data, encoded with the private key
+    fn sign (&self, data: &PlainData) -> Result<CryptData, CryptdecError> {
+        self.encode (&self.private_key, &PlainData::new (&Sha256::digest (&data.data[..])[..]))
+    }
+
+    fn verify_signature (&self, data: &PlainData, signature: &CryptData, public_key: &Key) -> bool {
+        match self.decode (public_key, signature) {
+            Ok (digest) => &digest.data[..] == &Sha256::digest (&data.data[..])[..],
+            Err (_) => false
+        }
+    }
+
     fn private_key (&self) -> Key {
         self.private_key.clone ()
     }
@@ -163,6 +200,19 @@ mod tests {
         assert_eq!(result.err().unwrap(), CryptdecError::InvalidKey (String::from ("Could not decrypt with [105, 110, 118, 97, 108, 105, 100, 107, 101, 121] data beginning with [107, 101, 121, 100, 97, 116, 97]")));
     }
 
+    #[test]
+    fn encrypt_and_decrypt_with_associated_data () {
+        let subject = CryptDENull::new ();
+        let key = Key::new (b"key");
+
+        let encrypted = subject.encrypt (&CryptDENull::other_key (&key), b"header", &PlainData::new (b"data")).unwrap ();
+
+        assert_eq! (subject.decrypt (&key, b"header", &encrypted), Ok (PlainData::new (b"data")));
+        assert_eq! (subject.decrypt (&key, b"other header", &encrypted), Err (CryptdecError::WrongAssociatedData));
+        assert_eq! (subject.decrypt (&Key::new (b"badKey"), b"header", &encrypted).is_err (), true);
+        assert_eq! (subject.encrypt (&key, b"header", &PlainData::new (b"")), Err (CryptdecError::EmptyData));
+    }
+
     #[test]
     fn random_is_pretty_predictable () {
         let subject = CryptDENull::new ();
@@ -221,6 +271,32 @@ mod tests {
         assert_eq! (decrypted_data, expected_data);
     }
 
+    #[test]
+    fn signatures_verify_against_the_signer_s_public_key_only () {
+        let mut signer = CryptDENull::new ();
+        signer.generate_key_pair ();
+        let mut somebody_else = CryptDENull::new ();
+        somebody_else.generate_key_pair ();
+        let data = PlainData::new (&b"I solemnly swear"[..]);
+
+        let signature = signer.sign (&data).unwrap ();
+
+        assert_eq! (somebody_else.verify_signature (&data, &signature, &signer.public_key ()), true);
+        assert_eq! (somebody_else.verify_signature (&data, &signature, &somebody_else.public_key ()), false);
+        assert_eq! (somebody_else.verify_signature (&PlainData::new (&b"I solemnly lie"[..]), &signature, &signer.public_key ()), false);
+    }
+
+    #[test]
+    fn adopting_a_private_key_derives_the_public_key () {
+        let mut subject = CryptDENull::new ();
+
+        subject.adopt_private_key (&Key::new (b"private")).unwrap ();
+
+        assert_eq! (subject.private_key (), Key::new (b"private"));
+        assert_eq! (subject.public_key (), CryptDENull::other_key (&Key::new (b"private")));
+        assert_eq! (subject.adopt_private_key (&Key::new (b"")), Err (CryptdecError::EmptyKey));
+    }
+
     #[test]
     fn other_key_works () {
         let one_key = Key::new (b"The quick brown fox jumps over the lazy dog");
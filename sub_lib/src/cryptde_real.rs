@@ -0,0 +1,313 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use sha2::Digest;
+use sha2::Sha256;
+use sodiumoxide;
+use sodiumoxide::crypto::box_;
+use sodiumoxide::crypto::scalarmult::curve25519;
+use sodiumoxide::crypto::sealedbox;
+use sodiumoxide::crypto::secretbox;
+use sodiumoxide::crypto::sign;
+use sodiumoxide::randombytes;
+use cryptde::CryptDE;
+use cryptde::CryptdecError;
+use cryptde::Key;
+use cryptde::PlainData;
+use cryptde::CryptData;
+
+// A public key is an X25519 encryption key followed by an Ed25519 verification key; a private key is
+// the matching X25519 secret key followed by the Ed25519 signing key. Data is encoded as a libsodium
+// sealed box (an ephemeral X25519 key agreement, then XSalsa20-Poly1305), so only the holder of the
+// recipient's private key can decode it, and any tampering makes decoding fail.
+//
+// Encrypting with associated data works the same way, except that the XSalsa20-Poly1305 key is
+// derived from the X25519 shared secret together with a digest of the associated data, so the data
+// only decrypts alongside the very same associated data.
+pub struct CryptDEReal {
+    private_key: Key,
+    public_key: Key
+}
+
+const BOX_PUBLIC_KEY_LEN: usize = box_::PUBLICKEYBYTES;
+const BOX_SECRET_KEY_LEN: usize = box_::SECRETKEYBYTES;
+const PUBLIC_KEY_LEN: usize = box_::PUBLICKEYBYTES + sign::PUBLICKEYBYTES;
+const PRIVATE_KEY_LEN: usize = box_::SECRETKEYBYTES + sign::SECRETKEYBYTES;
+
+impl CryptDE for CryptDEReal {
+    fn generate_key_pair(&mut self) {
+        let (box_public_key, box_secret_key) = box_::gen_keypair ();
+        let (sign_public_key, sign_secret_key) = sign::gen_keypair ();
+        self.private_key = Key::new (&[&box_secret_key.0[..], &sign_secret_key.0[..]].concat ()[..]);
+        self.public_key = Key::new (&[&box_public_key.0[..], &sign_public_key.0[..]].concat ()[..]);
+    }
+
+    // An Ed25519 signing key carries its verification key in its last half
+    fn adopt_private_key (&mut self, private_key: &Key) -> Result<(), CryptdecError> {
+        if private_key.data.is_empty () {
+            return Err (CryptdecError::EmptyKey)
+        } else if private_key.data.len () != PRIVATE_KEY_LEN {
+            return Err (CryptdecError::InvalidKey (String::from ("Can't adopt something that is not a private key")))
+        }
+        let box_public_key = CryptDEReal::box_public_key_of (&private_key.data[..BOX_SECRET_KEY_LEN]);
+        let sign_public_key = &private_key.data[(PRIVATE_KEY_LEN - sign::PUBLICKEYBYTES)..];
+        self.private_key = private_key.clone ();
+        self.public_key = Key::new (&[&box_public_key.0[..], sign_public_key].concat ()[..]);
+        Ok (())
+    }
+
+    fn encode(&self, key: &Key, data: &PlainData) -> Result<CryptData, CryptdecError> {
+        if key.data.is_empty() {
+            Err(CryptdecError::EmptyKey)
+        } else if data.data.is_empty() {
+            Err(CryptdecError::EmptyData)
+        } else if key.data.len () != PUBLIC_KEY_LEN {
+            Err(CryptdecError::InvalidKey (format! ("{:?} is not a public key", key.data)))
+        } else {
+            let box_public_key = box_::PublicKey::from_slice (&key.data[..BOX_PUBLIC_KEY_LEN]).expect ("Internal error");
+            Ok(CryptData::new (&sealedbox::seal (&data.data[..], &box_public_key)[..]))
+        }
+    }
+
+    fn decode(&self, key: &Key, data: &CryptData) -> Result<PlainData, CryptdecError> {
+        if key.data.is_empty() {
+            Err(CryptdecError::EmptyKey)
+        } else if data.data.is_empty() {
+            Err(CryptdecError::EmptyData)
+        } else if key.data.len () != PRIVATE_KEY_LEN {
+            Err(CryptdecError::InvalidKey (String::from ("Could not decrypt with something that is not a private key")))
+        } else {
+            let box_secret_key = box_::SecretKey::from_slice (&key.data[..BOX_SECRET_KEY_LEN]).expect ("Internal error");
+            let box_public_key = CryptDEReal::box_public_key_of (&key.data[..BOX_SECRET_KEY_LEN]);
+            match sealedbox::open (&data.data[..], &box_public_key, &box_secret_key) {
+                Ok (plain) => Ok (PlainData::new (&plain[..])),
+                Err (()) => Err (CryptdecError::InvalidKey (format! ("Could not decrypt {} bytes of data with this private key", data.data.len ())))
+            }
+        }
+    }
+
+    fn encrypt (&self, key: &Key, associated_data: &[u8], data: &PlainData) -> Result<CryptData, CryptdecError> {
+        if key.data.is_empty() {
+            return Err(CryptdecError::EmptyKey)
+        } else if data.data.is_empty() {
+            return Err(CryptdecError::EmptyData)
+        } else if key.data.len () != PUBLIC_KEY_LEN {
+            return Err(CryptdecError::InvalidKey (format! ("{:?} is not a public key", key.data)))
+        }
+        let recipient_public_key = box_::PublicKey::from_slice (&key.data[..BOX_PUBLIC_KEY_LEN]).expect ("Internal error");
+        let (ephemeral_public_key, ephemeral_secret_key) = box_::gen_keypair ();
+        let message_key = CryptDEReal::message_key (&box_::precompute (&recipient_public_key, &ephemeral_secret_key),
+            &ephemeral_public_key, associated_data);
+        let sealed = secretbox::seal (&data.data[..], &secretbox::Nonce ([0; secretbox::NONCEBYTES]), &message_key);
+        Ok (CryptData::new (&[&ephemeral_public_key.0[..], &sealed[..]].concat ()[..]))
+    }
+
+    fn decrypt (&self, key: &Key, associated_data: &[u8], data: &CryptData) -> Result<PlainData, CryptdecError> {
+        if key.data.is_empty() {
+            return Err(CryptdecError::EmptyKey)
+        } else if data.data.is_empty() {
+            return Err(CryptdecError::EmptyData)
+        } else if key.data.len () != PRIVATE_KEY_LEN {
+            return Err(CryptdecError::InvalidKey (String::from ("Could not decrypt with something that is not a private key")))
+        } else if data.data.len () < BOX_PUBLIC_KEY_LEN + secretbox::MACBYTES {
+            return Err(CryptdecError::InvalidKey (format! ("Could not decrypt {} bytes of data: too short", data.data.len ())))
+        }
+        let box_secret_key = box_::SecretKey::from_slice (&key.data[..BOX_SECRET_KEY_LEN]).expect ("Internal error");
+        let ephemeral_public_key = box_::PublicKey::from_slice (&data.data[..BOX_PUBLIC_KEY_LEN]).expect ("Internal error");
+        let message_key = CryptDEReal::message_key (&box_::precompute (&ephemeral_public_key, &box_secret_key),
+            &ephemeral_public_key, associated_data);
+        match secretbox::open (&data.data[BOX_PUBLIC_KEY_LEN..], &secretbox::Nonce ([0; secretbox::NONCEBYTES]), &message_key) {
+            Ok (plain) => Ok (PlainData::new (&plain[..])),
+            Err (()) => Err (CryptdecError::InvalidKey (format! ("Could not decrypt {} bytes of data with this private key and associated data", data.data.len ())))
+        }
+    }
+
+    fn random (&self, dest: &mut [u8]) {
+        randombytes::randombytes_into (dest)
+    }
+
+    fn sign (&self, data: &PlainData) -> Result<CryptData, CryptdecError> {
+        if self.private_key.data.len () != PRIVATE_KEY_LEN {
+            return Err (CryptdecError::InvalidKey (String::from ("No key pair has been generated")))
+        }
+        let sign_secret_key = sign::SecretKey::from_slice (&self.private_key.data[BOX_SECRET_KEY_LEN..]).expect ("Internal error");
+        Ok (CryptData::new (&sign::sign_detached (&data.data[..], &sign_secret_key).0[..]))
+    }
+
+    fn verify_signature (&self, data: &PlainData, signature: &CryptData, public_key: &Key) -> bool {
+        if public_key.data.len () != PUBLIC_KEY_LEN {return false}
+        let sign_public_key = sign::PublicKey::from_slice (&public_key.data[BOX_PUBLIC_KEY_LEN..]).expect ("Internal error");
+        match sign::Signature::from_slice (&signature.data[..]) {
+            Some (signature) => sign::verify_detached (&signature, &data.data[..], &sign_public_key),
+            None => false
+        }
+    }
+
+    fn private_key (&self) -> Key {
+        self.private_key.clone ()
+    }
+
+    fn public_key (&self) -> Key {
+        self.public_key.clone ()
+    }
+}
+
+impl CryptDEReal {
+    pub fn new () -> CryptDEReal {
+        sodiumoxide::init ().expect ("Couldn't initialize libsodium");
+        CryptDEReal {
+            private_key: Key::new (b""),
+            public_key: Key::new (b"")
+        }
+    }
+
+    // Every message has its own ephemeral key, so its message key is never used twice and a zero nonce will do
+    fn message_key (shared_secret: &box_::PrecomputedKey, ephemeral_public_key: &box_::PublicKey, associated_data: &[u8]) -> secretbox::Key {
+        let digest = Sha256::digest (&[&shared_secret.0[..], &ephemeral_public_key.0[..], &Sha256::digest (associated_data)[..]].concat ()[..]);
+        secretbox::Key::from_slice (&digest[..]).expect ("Internal error")
+    }
+
+    fn box_public_key_of (box_secret_key: &[u8]) -> box_::PublicKey {
+        let scalar = curve25519::Scalar::from_slice (box_secret_key).expect ("Internal error");
+        box_::PublicKey (curve25519::scalarmult_base (&scalar).0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_subject () -> CryptDEReal {
+        let mut subject = CryptDEReal::new ();
+        subject.generate_key_pair ();
+        subject
+    }
+
+    #[test]
+    fn generated_keys_have_room_for_encryption_and_signing () {
+        let subject = make_subject ();
+
+        assert_eq! (subject.public_key ().data.len (), 64);
+        assert_eq! (subject.private_key ().data.len (), 96);
+        assert_ne! (subject.public_key (), make_subject ().public_key ());
+    }
+
+    #[test]
+    fn data_encoded_for_a_public_key_can_be_decoded_only_with_the_matching_private_key () {
+        let subject = make_subject ();
+        let eavesdropper = make_subject ();
+        let data = PlainData::new (b"Meet me at the docks at midnight");
+
+        let encoded = subject.encode (&subject.public_key (), &data).unwrap ();
+
+        assert_ne! (&encoded.data[..], &data.data[..]);
+        assert_eq! (subject.decode (&subject.private_key (), &encoded), Ok (data));
+        assert_eq! (eavesdropper.decode (&eavesdropper.private_key (), &encoded).is_err (), true);
+    }
+
+    #[test]
+    fn encoding_the_same_data_twice_produces_different_results () {
+        let subject = make_subject ();
+        let data = PlainData::new (b"data");
+
+        let first = subject.encode (&subject.public_key (), &data).unwrap ();
+        let second = subject.encode (&subject.public_key (), &data).unwrap ();
+
+        assert_ne! (first, second);
+    }
+
+    #[test]
+    fn tampered_data_is_not_decoded () {
+        let subject = make_subject ();
+        let mut encoded = subject.encode (&subject.public_key (), &PlainData::new (b"data")).unwrap ();
+        let last = encoded.data.len () - 1;
+        encoded.data[last] ^= 1;
+
+        let result = subject.decode (&subject.private_key (), &encoded);
+
+        assert_eq! (result.is_err (), true);
+    }
+
+    #[test]
+    fn encode_and_decode_complain_about_bad_keys_and_data () {
+        let subject = make_subject ();
+
+        assert_eq! (subject.encode (&Key::new (b""), &PlainData::new (b"data")).err ().unwrap (), CryptdecError::EmptyKey);
+        assert_eq! (subject.encode (&subject.public_key (), &PlainData::new (b"")).err ().unwrap (), CryptdecError::EmptyData);
+        assert_eq! (subject.encode (&Key::new (b"key"), &PlainData::new (b"data")).err ().unwrap (),
+            CryptdecError::InvalidKey (String::from ("[107, 101, 121] is not a public key")));
+        assert_eq! (subject.decode (&Key::new (b""), &CryptData::new (b"data")).err ().unwrap (), CryptdecError::EmptyKey);
+        assert_eq! (subject.decode (&subject.private_key (), &CryptData::new (b"")).err ().unwrap (), CryptdecError::EmptyData);
+        assert_eq! (subject.decode (&subject.public_key (), &CryptData::new (b"data")).is_err (), true);
+    }
+
+    #[test]
+    fn data_encrypted_with_associated_data_decrypts_only_with_the_same_associated_data () {
+        let subject = make_subject ();
+        let eavesdropper = make_subject ();
+        let data = PlainData::new (b"Meet me at the docks at midnight");
+
+        let encrypted = subject.encrypt (&subject.public_key (), b"routing header", &data).unwrap ();
+
+        assert_eq! (encrypted.data.windows (data.data.len ()).any (|window| window == &data.data[..]), false);
+        assert_eq! (subject.decrypt (&subject.private_key (), b"routing header", &encrypted), Ok (data.clone ()));
+        assert_eq! (subject.decrypt (&subject.private_key (), b"routing headex", &encrypted).is_err (), true);
+        assert_eq! (eavesdropper.decrypt (&eavesdropper.private_key (), b"routing header", &encrypted).is_err (), true);
+        assert_ne! (subject.encrypt (&subject.public_key (), b"routing header", &data).unwrap (), encrypted);
+    }
+
+    #[test]
+    fn encrypt_and_decrypt_complain_about_bad_keys_and_data () {
+        let subject = make_subject ();
+
+        assert_eq! (subject.encrypt (&Key::new (b""), b"", &PlainData::new (b"data")).err ().unwrap (), CryptdecError::EmptyKey);
+        assert_eq! (subject.encrypt (&subject.public_key (), b"", &PlainData::new (b"")).err ().unwrap (), CryptdecError::EmptyData);
+        assert_eq! (subject.encrypt (&Key::new (b"key"), b"", &PlainData::new (b"data")).is_err (), true);
+        assert_eq! (subject.decrypt (&subject.private_key (), b"", &CryptData::new (b"short")).is_err (), true);
+        assert_eq! (subject.decrypt (&subject.public_key (), b"", &CryptData::new (b"data")).is_err (), true);
+    }
+
+    #[test]
+    fn signatures_are_verified_only_against_the_signer_and_the_signed_data () {
+        let subject = make_subject ();
+        let impostor = make_subject ();
+        let data = PlainData::new (b"I am who I say I am");
+
+        let signature = subject.sign (&data).unwrap ();
+
+        assert_eq! (impostor.verify_signature (&data, &signature, &subject.public_key ()), true);
+        assert_eq! (subject.verify_signature (&data, &signature, &impostor.public_key ()), false);
+        assert_eq! (subject.verify_signature (&PlainData::new (b"I am somebody else"), &signature, &subject.public_key ()), false);
+        assert_eq! (subject.verify_signature (&data, &CryptData::new (b"signature"), &subject.public_key ()), false);
+        assert_eq! (subject.verify_signature (&data, &signature, &Key::new (b"key")), false);
+    }
+
+    #[test]
+    fn an_adopted_private_key_brings_its_public_key_along () {
+        let original = make_subject ();
+        let mut subject = CryptDEReal::new ();
+
+        subject.adopt_private_key (&original.private_key ()).unwrap ();
+
+        assert_eq! (subject.public_key (), original.public_key ());
+        let signature = subject.sign (&PlainData::new (b"data")).unwrap ();
+        assert_eq! (original.verify_signature (&PlainData::new (b"data"), &signature, &original.public_key ()), true);
+        assert_eq! (subject.adopt_private_key (&Key::new (b"")).err ().unwrap (), CryptdecError::EmptyKey);
+        assert_eq! (subject.adopt_private_key (&original.public_key ()).is_err (), true);
+    }
+
+    #[test]
+    fn signing_requires_a_key_pair () {
+        let subject = CryptDEReal::new ();
+
+        assert_eq! (subject.sign (&PlainData::new (b"data")).is_err (), true);
+    }
+
+    #[test]
+    fn random_bytes_are_not_all_the_same () {
+        let subject = CryptDEReal::new ();
+        let mut dest = [0u8; 32];
+
+        subject.random (&mut dest);
+
+        assert_eq! (dest.iter ().all (|b| *b == dest[0]), false);
+    }
+}
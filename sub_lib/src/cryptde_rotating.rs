@@ -0,0 +1,202 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::sync::RwLock;
+use cryptde::CryptDE;
+use cryptde::CryptdecError;
+use cryptde::Key;
+use cryptde::PlainData;
+use cryptde::CryptData;
+
+pub const DEFAULT_KEY_OVERLAP_MS: u64 = 600000;
+
+struct KeyPairs {
+    current: Box<CryptDE>,
+    retiring_opt: Option<Box<CryptDE>>,
+}
+
+// Wraps another CryptDE so its key pair can be replaced while the Node is running. After a
+// rotation, the retiring key pair can still decode whatever was encoded for it until it's
+// retired, so routes built before the rotation keep working through the overlap.
+pub struct CryptDERotating {
+    key_pairs: RwLock<KeyPairs>,
+    factory: fn () -> Box<CryptDE>,
+}
+
+impl CryptDE for CryptDERotating {
+    fn generate_key_pair (&mut self) {
+        self.key_pairs.get_mut ().expect ("Key pairs poisoned").current.generate_key_pair ()
+    }
+
+    fn adopt_private_key (&mut self, private_key: &Key) -> Result<(), CryptdecError> {
+        self.key_pairs.get_mut ().expect ("Key pairs poisoned").current.adopt_private_key (private_key)
+    }
+
+    fn encode (&self, key: &Key, data: &PlainData) -> Result<CryptData, CryptdecError> {
+        self.key_pairs.read ().expect ("Key pairs poisoned").current.encode (key, data)
+    }
+
+    // Either of our own private keys opens whatever was encoded for either of our public keys
+    fn decode (&self, key: &Key, data: &CryptData) -> Result<PlainData, CryptdecError> {
+        self.with_either_key_pair (key, |cryptde, private_key| cryptde.decode (private_key, data))
+    }
+
+    fn encrypt (&self, key: &Key, associated_data: &[u8], data: &PlainData) -> Result<CryptData, CryptdecError> {
+        self.key_pairs.read ().expect ("Key pairs poisoned").current.encrypt (key, associated_data, data)
+    }
+
+    fn decrypt (&self, key: &Key, associated_data: &[u8], data: &CryptData) -> Result<PlainData, CryptdecError> {
+        self.with_either_key_pair (key, |cryptde, private_key| cryptde.decrypt (private_key, associated_data, data))
+    }
+
+    fn random (&self, dest: &mut [u8]) {
+        self.key_pairs.read ().expect ("Key pairs poisoned").current.random (dest)
+    }
+
+    fn sign (&self, data: &PlainData) -> Result<CryptData, CryptdecError> {
+        self.key_pairs.read ().expect ("Key pairs poisoned").current.sign (data)
+    }
+
+    fn verify_signature (&self, data: &PlainData, signature: &CryptData, public_key: &Key) -> bool {
+        self.key_pairs.read ().expect ("Key pairs poisoned").current.verify_signature (data, signature, public_key)
+    }
+
+    fn private_key (&self) -> Key {
+        self.key_pairs.read ().expect ("Key pairs poisoned").current.private_key ()
+    }
+
+    fn public_key (&self) -> Key {
+        self.key_pairs.read ().expect ("Key pairs poisoned").current.public_key ()
+    }
+}
+
+impl CryptDERotating {
+    // The factory makes the CryptDE that holds each new key pair
+    pub fn new (current: Box<CryptDE>, factory: fn () -> Box<CryptDE>) -> CryptDERotating {
+        CryptDERotating {
+            key_pairs: RwLock::new (KeyPairs {current, retiring_opt: None}),
+            factory,
+        }
+    }
+
+    // Replaces the current key pair with a new one and sets the old one to retire. Returns the old
+    // public key, and the old key pair's signature on the new public key, which is how the rest of
+    // the network can tell the new key belongs to the same Node. A key pair that was already
+    // retiring is retired at once.
+    pub fn rotate (&self) -> (Key, CryptData) {
+        let mut successor = (self.factory) ();
+        successor.generate_key_pair ();
+        let mut key_pairs = self.key_pairs.write ().expect ("Key pairs poisoned");
+        let predecessor_public_key = key_pairs.current.public_key ();
+        let endorsement = key_pairs.current.sign (&PlainData::new (&successor.public_key ().data[..]))
+            .expect ("Couldn't endorse new public key");
+        let predecessor = ::std::mem::replace (&mut key_pairs.current, successor);
+        key_pairs.retiring_opt = Some (predecessor);
+        (predecessor_public_key, endorsement)
+    }
+
+    // Forgets the retiring key pair, if any, and returns its public key
+    pub fn retire (&self) -> Option<Key> {
+        let mut key_pairs = self.key_pairs.write ().expect ("Key pairs poisoned");
+        key_pairs.retiring_opt.take ().map (|retiring| retiring.public_key ())
+    }
+
+    fn with_either_key_pair<F> (&self, key: &Key, open: F) -> Result<PlainData, CryptdecError>
+            where F: Fn (&CryptDE, &Key) -> Result<PlainData, CryptdecError> {
+        let key_pairs = self.key_pairs.read ().expect ("Key pairs poisoned");
+        let retiring = match key_pairs.retiring_opt {
+            Some (ref retiring) => retiring,
+            None => return open (key_pairs.current.as_ref (), key)
+        };
+        let current_private_key = key_pairs.current.private_key ();
+        let retiring_private_key = retiring.private_key ();
+        if (key != &current_private_key) && (key != &retiring_private_key) {
+            return open (key_pairs.current.as_ref (), key)
+        }
+        open (key_pairs.current.as_ref (), &current_private_key)
+            .or_else (|_| open (retiring.as_ref (), &retiring_private_key))
+    }
+
+    pub fn retiring_public_key_opt (&self) -> Option<Key> {
+        let key_pairs = self.key_pairs.read ().expect ("Key pairs poisoned");
+        key_pairs.retiring_opt.as_ref ().map (|retiring| retiring.public_key ())
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use cryptde_null::CryptDENull;
+
+    fn make_null () -> Box<CryptDE> {
+        Box::new (CryptDENull::new ())
+    }
+
+    fn make_subject () -> CryptDERotating {
+        let mut subject = CryptDERotating::new (make_null (), make_null);
+        subject.generate_key_pair ();
+        subject
+    }
+
+    #[test]
+    fn rotation_brings_a_new_key_pair_endorsed_by_the_old_one () {
+        let subject = make_subject ();
+        let old_public_key = subject.public_key ();
+
+        let (predecessor_public_key, endorsement) = subject.rotate ();
+
+        assert_eq! (predecessor_public_key, old_public_key);
+        assert_ne! (subject.public_key (), old_public_key);
+        assert_eq! (subject.retiring_public_key_opt (), Some (old_public_key.clone ()));
+        assert_eq! (subject.verify_signature (&PlainData::new (&subject.public_key ().data[..]), &endorsement, &old_public_key), true);
+        let signature = subject.sign (&PlainData::new (b"data")).unwrap ();
+        assert_eq! (subject.verify_signature (&PlainData::new (b"data"), &signature, &subject.public_key ()), true);
+    }
+
+    #[test]
+    fn data_for_either_key_is_decoded_until_the_old_one_retires () {
+        let subject = make_subject ();
+        let data = PlainData::new (b"data");
+        let for_old_key = subject.encode (&subject.public_key (), &data).unwrap ();
+        subject.rotate ();
+        let for_new_key = subject.encode (&subject.public_key (), &data).unwrap ();
+
+        assert_eq! (subject.decode (&subject.private_key (), &for_old_key), Ok (data.clone ()));
+        assert_eq! (subject.decode (&subject.private_key (), &for_new_key), Ok (data.clone ()));
+
+        let retired = subject.retire ();
+
+        assert_eq! (retired.is_some (), true);
+        assert_eq! (subject.retiring_public_key_opt (), None);
+        assert_eq! (subject.decode (&subject.private_key (), &for_old_key).is_err (), true);
+        assert_eq! (subject.decode (&subject.private_key (), &for_new_key), Ok (data));
+        assert_eq! (subject.retire (), None);
+    }
+
+    #[test]
+    fn associated_data_for_either_key_is_decrypted_until_the_old_one_retires () {
+        let subject = make_subject ();
+        let data = PlainData::new (b"data");
+        let for_old_key = subject.encrypt (&subject.public_key (), b"header", &data).unwrap ();
+        subject.rotate ();
+
+        assert_eq! (subject.decrypt (&subject.private_key (), b"header", &for_old_key), Ok (data));
+        assert_eq! (subject.decrypt (&subject.private_key (), b"other header", &for_old_key).is_err (), true);
+
+        subject.retire ();
+
+        assert_eq! (subject.decrypt (&subject.private_key (), b"header", &for_old_key).is_err (), true);
+    }
+
+    #[test]
+    fn rotating_again_retires_the_key_pair_that_was_already_retiring () {
+        let subject = make_subject ();
+        let first_public_key = subject.public_key ();
+        subject.rotate ();
+        let second_public_key = subject.public_key ();
+
+        let (predecessor_public_key, _) = subject.rotate ();
+
+        assert_eq! (predecessor_public_key, second_public_key);
+        assert_eq! (subject.retiring_public_key_opt (), Some (second_public_key));
+        assert_ne! (subject.public_key (), first_public_key);
+    }
+}
@@ -14,6 +14,8 @@ use serde::Deserializer;
 use serde::de::Visitor;
 use cryptde::Key;
 use hopper::HopperTemporaryTransmitDataMsg;
+use neighborhood::NodeBannedMsg;
+use neighborhood::NodeUnbannedMsg;
 use peer_actors::BindMessage;
 use stream_handler_pool::TransmitDataMsg;
 use utils::to_string;
@@ -24,6 +26,7 @@ pub enum Component {
     Hopper,
     ProxyServer,
     ProxyClient,
+    Accountant,
 }
 
 impl Serialize for Component {
@@ -32,7 +35,8 @@ impl Serialize for Component {
             Component::Neighborhood => 0,
             Component::Hopper => 1,
             Component::ProxyServer => 2,
-            Component::ProxyClient => 3
+            Component::ProxyClient => 3,
+            Component::Accountant => 4
         };
         serializer.serialize_u8(index)
     }
@@ -59,6 +63,7 @@ impl<'a> Visitor<'a> for ComponentVisitor {
             1 => Ok (Component::Hopper),
             2 => Ok (Component::ProxyServer),
             3 => Ok (Component::ProxyClient),
+            4 => Ok (Component::Accountant),
             _ => Err(serde::de::Error::invalid_value(serde::de::Unexpected::Unsigned(v as u64), &self))
         }
     }
@@ -87,7 +92,8 @@ impl Component {
             &Component::Neighborhood => "NBHD",
             &Component::Hopper => "HOPR",
             &Component::ProxyServer => "PXSV",
-            &Component::ProxyClient => "PXCL"
+            &Component::ProxyClient => "PXCL",
+            &Component::Accountant => "ACCT"
         }
     }
 
@@ -96,7 +102,8 @@ impl Component {
             Component::Neighborhood,
             Component::Hopper,
             Component::ProxyServer,
-            Component::ProxyClient
+            Component::ProxyClient,
+            Component::Accountant
         )
     }
 
@@ -123,6 +130,8 @@ pub enum DispatcherError {
 pub struct InboundClientData {
     pub socket_addr: SocketAddr,
     pub origin_port: Option<u16>,
+    // The Node at the other end of a clandestine stream, once it's proved who it is
+    pub peer_public_key_opt: Option<Key>,
     pub component: Component,
     pub last_data: bool,
     pub data: Vec<u8>
@@ -134,8 +143,8 @@ impl Debug for InboundClientData {
             Ok (string) => string,
             Err (_) => format! ("{:?}", &self.data[..])
         };
-        write! (f, "InboundClientData {{ socket_addr: {:?}, origin_port: {:?}, component: {:?}, last_data: {}, data: {} }}",
-                self.socket_addr, self.origin_port, self.component, self.last_data, data_string)
+        write! (f, "InboundClientData {{ socket_addr: {:?}, origin_port: {:?}, peer_public_key_opt: {:?}, component: {:?}, last_data: {}, data: {} }}",
+                self.socket_addr, self.origin_port, self.peer_public_key_opt, self.component, self.last_data, data_string)
     }
 }
 
@@ -146,6 +155,8 @@ pub struct DispatcherSubs {
     pub from_proxy_server: Recipient<Syn, TransmitDataMsg>,
     // TODO when we are decentralized, remove this
     pub from_hopper: Recipient<Syn, HopperTemporaryTransmitDataMsg>,
+    pub node_banned: Recipient<Syn, NodeBannedMsg>,
+    pub node_unbanned: Recipient<Syn, NodeUnbannedMsg>,
 }
 
 impl Clone for DispatcherSubs {
@@ -155,6 +166,8 @@ impl Clone for DispatcherSubs {
             bind: self.bind.clone(),
             from_proxy_server: self.from_proxy_server.clone(),
             from_hopper: self.from_hopper.clone(),
+            node_banned: self.node_banned.clone(),
+            node_unbanned: self.node_unbanned.clone(),
         }
     }
 }
@@ -170,7 +183,8 @@ mod tests {
         assert_eq!(Component::Neighborhood.as_str(), "NBHD");
         assert_eq!(Component::Hopper.as_str(), "HOPR");
         assert_eq!(Component::ProxyServer.as_str(), "PXSV");
-        assert_eq!(Component::ProxyClient.as_str(), "PXCL")
+        assert_eq!(Component::ProxyClient.as_str(), "PXCL");
+        assert_eq!(Component::Accountant.as_str(), "ACCT")
     }
 
     #[test]
@@ -179,6 +193,7 @@ mod tests {
         assert_eq!(Component::from_str("HOPR"), Some(Component::Hopper));
         assert_eq!(Component::from_str("PXSV"), Some(Component::ProxyServer));
         assert_eq!(Component::from_str("PXCL"), Some(Component::ProxyClient));
+        assert_eq!(Component::from_str("ACCT"), Some(Component::Accountant));
         assert_eq!(Component::from_str("BOOGA"), None);
     }
 
@@ -224,21 +239,24 @@ mod tests {
         let hopper_data = serde_cbor::ser::to_vec (&Component::Hopper).unwrap ();
         let proxy_server_data = serde_cbor::ser::to_vec (&Component::ProxyServer).unwrap ();
         let proxy_client_data = serde_cbor::ser::to_vec (&Component::ProxyClient).unwrap ();
+        let accountant_data = serde_cbor::ser::to_vec (&Component::Accountant).unwrap ();
 
         let neighborhood_result = serde_cbor::de::from_slice::<Component> (&neighborhood_data[..]).unwrap ();
         let hopper_result = serde_cbor::de::from_slice::<Component> (&hopper_data[..]).unwrap ();
         let proxy_server_result = serde_cbor::de::from_slice::<Component> (&proxy_server_data[..]).unwrap ();
         let proxy_client_result = serde_cbor::de::from_slice::<Component> (&proxy_client_data[..]).unwrap ();
+        let accountant_result = serde_cbor::de::from_slice::<Component> (&accountant_data[..]).unwrap ();
 
         assert_eq! (neighborhood_result, Component::Neighborhood);
         assert_eq! (hopper_result, Component::Hopper);
         assert_eq! (proxy_server_result, Component::ProxyServer);
         assert_eq! (proxy_client_result, Component::ProxyClient);
+        assert_eq! (accountant_result, Component::Accountant);
     }
 
     #[test]
     fn component_deserializer_handles_unrecognized_component () {
-        let unrecognized_data: &[u8] = &[4];
+        let unrecognized_data: &[u8] = &[5];
 
         let unrecognized_result = serde_cbor::de::from_slice::<Component> (unrecognized_data);
 
@@ -10,23 +10,57 @@ use serde_cbor;
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Hop {
     pub public_key: Key,
-    pub component: Component
+    pub component: Component,
+    // The Node that built the route, and so the one that owes for relaying along it. Hops from
+    // Nodes that predate it have none.
+    #[serde (default)]
+    pub consuming_key_opt: Option<Key>,
+    // The consuming Node's signature over the rest of the hop. A key without one could be anybody's,
+    // so nobody is billed for it.
+    #[serde (default)]
+    pub consuming_signature_opt: Option<CryptData>,
 }
 
 impl Hop {
     pub fn new(key: &Key, component: Component) -> Self {
         Hop {
             public_key: key.clone (),
-            component
+            component,
+            consuming_key_opt: None,
+            consuming_signature_opt: None,
         }
     }
 
+    pub fn consumed_by (mut self, cryptde: &CryptDE) -> Self {
+        self.consuming_key_opt = Some (cryptde.public_key ());
+        self.consuming_signature_opt = match cryptde.sign (&self.signed_data ()) {
+            Ok (signature) => Some (signature),
+            // crashpoint - a CryptDE that can't sign can't build routes either
+            Err (e) => panic! ("Couldn't sign Hop: {:?}", e)
+        };
+        self
+    }
+
+    // The consuming key, if the Node it belongs to signed this hop
+    pub fn proven_consuming_key (&self, cryptde: &CryptDE) -> Option<Key> {
+        match (&self.consuming_key_opt, &self.consuming_signature_opt) {
+            (&Some (ref consuming_key), &Some (ref signature)) if cryptde.verify_signature (&self.signed_data (), signature, consuming_key) => {
+                Some (consuming_key.clone ())
+            },
+            _ => None
+        }
+    }
+
+    fn signed_data (&self) -> PlainData {
+        let signed = (&self.public_key, &self.component, &self.consuming_key_opt);
+        PlainData::new (&serde_cbor::ser::to_vec (&signed).expect ("Internal error: couldn't serialize Hop")[..])
+    }
+
     pub fn decode (key: &Key, cryptde: &CryptDE, crypt_data: &CryptData) -> Result<Self, CryptdecError> {
         let plain_data = cryptde.decode (key, crypt_data)?;
         match serde_cbor::de::from_slice::<Hop> (&plain_data.data[..]) {
             Ok (hop) => Ok (hop),
-            // crashpoint - need to figure out how to return deserialize error
-            Err (_) => unimplemented!()
+            Err (e) => Err (CryptdecError::InvalidData (format! ("Couldn't deserialize Hop: {}", e)))
         }
     }
 
@@ -51,6 +85,68 @@ mod tests {
 
         assert_eq!(subject.public_key, Key::new("key".as_bytes()));
         assert_eq!(subject.component, Component::Neighborhood);
+        assert_eq!(subject.consuming_key_opt, None);
+        assert_eq!(subject.consuming_signature_opt, None);
+    }
+
+    #[test]
+    fn consuming_key_survives_encode_and_decode () {
+        let cryptde = CryptDENull::new ();
+        let mut consumer = CryptDENull::new ();
+        consumer.generate_key_pair ();
+        let encode_key = Key::new (b"waffle");
+        let subject = Hop::new (&Key::new (&[4, 3, 2, 1]), Component::Hopper).consumed_by (&consumer);
+
+        let result = Hop::decode (&CryptDENull::other_key (&encode_key), &cryptde, &subject.encode (&encode_key, &cryptde).unwrap ()).unwrap ();
+
+        assert_eq! (result.consuming_key_opt, Some (consumer.public_key ()));
+        assert_eq! (result.proven_consuming_key (&cryptde), Some (consumer.public_key ()));
+        assert_eq! (result, subject);
+    }
+
+    #[test]
+    fn consuming_key_without_a_signature_is_not_proven () {
+        let cryptde = CryptDENull::new ();
+        let mut subject = Hop::new (&Key::new (&[4, 3, 2, 1]), Component::Hopper);
+        subject.consuming_key_opt = Some (Key::new (b"third party"));
+
+        assert_eq! (subject.proven_consuming_key (&cryptde), None);
+    }
+
+    #[test]
+    fn consuming_key_signed_by_another_node_is_not_proven () {
+        let cryptde = CryptDENull::new ();
+        let mut consumer = CryptDENull::new ();
+        consumer.generate_key_pair ();
+        let mut third_party = CryptDENull::new ();
+        third_party.generate_key_pair ();
+        let mut subject = Hop::new (&Key::new (&[4, 3, 2, 1]), Component::ProxyClient).consumed_by (&consumer);
+        subject.consuming_key_opt = Some (third_party.public_key ());
+
+        assert_eq! (subject.proven_consuming_key (&cryptde), None);
+    }
+
+    #[test]
+    fn signed_hop_is_not_proven_once_it_is_altered () {
+        let cryptde = CryptDENull::new ();
+        let mut subject = Hop::new (&Key::new (&[4, 3, 2, 1]), Component::Hopper).consumed_by (&cryptde);
+        subject.component = Component::ProxyClient;
+
+        assert_eq! (subject.proven_consuming_key (&cryptde), None);
+    }
+
+    #[test]
+    fn decode_rejects_data_that_is_not_a_hop () {
+        let cryptde = CryptDENull::new ();
+        let encode_key = Key::new (b"waffle");
+        let not_a_hop = cryptde.encode (&encode_key, &PlainData::new (b"not a hop")).unwrap ();
+
+        let result = Hop::decode (&CryptDENull::other_key (&encode_key), &cryptde, &not_a_hop);
+
+        match result {
+            Err (CryptdecError::InvalidData (ref message)) if message.starts_with ("Couldn't deserialize Hop") => (),
+            other => panic! ("Expected InvalidData, got {:?}", other)
+        }
     }
 
     #[test]
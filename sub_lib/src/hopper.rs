@@ -1,13 +1,17 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::net::IpAddr;
 use actix::Recipient;
 use actix::Syn;
 use serde::de::Deserialize;
 use serde::ser::Serialize;
 use serde_cbor;
+use accountant::ServiceStandingMessage;
 use cryptde::Key;
 use cryptde::PlainData;
 use dispatcher::Endpoint;
 use dispatcher::InboundClientData;
+use neighborhood::NodeBannedMsg;
+use neighborhood::NodeUnbannedMsg;
 use peer_actors::BindMessage;
 use route::Route;
 
@@ -43,12 +47,17 @@ impl IncipientCoresPackage {
 #[derive (Clone, Debug, PartialEq, Message)]
 pub struct ExpiredCoresPackage {
     pub remaining_route: Route,
-    pub payload: PlainData
+    pub payload: PlainData,
+    // Another Node that proved it built the route, and so owes for exiting the package; set only
+    // for packages the Hopper hands to the ProxyClient
+    pub consuming_key_opt: Option<Key>,
+    // The neighbor it came in from, which shares that Node's free tier; set alongside it
+    pub neighbor_ip_opt: Option<IpAddr>,
 }
 
 impl ExpiredCoresPackage {
     pub fn new (remaining_route: Route, payload: PlainData) -> ExpiredCoresPackage {
-        ExpiredCoresPackage {remaining_route, payload}
+        ExpiredCoresPackage {remaining_route, payload, consuming_key_opt: None, neighbor_ip_opt: None}
     }
 
     /// This method is exquisitely dangerous: hacked data might be deserialized to anything. In
@@ -63,11 +72,35 @@ impl ExpiredCoresPackage {
     }
 }
 
+#[derive (Clone, Debug, PartialEq)]
+pub struct HopperConfig {
+    // pad sealed packages up to fixed size buckets so their lengths say less about their contents
+    pub pad_packages: bool,
+    // milliseconds between cover packages to each neighbor Hopper has a link with; 0 for none
+    pub cover_traffic_interval_ms: u64,
+    // how long relayed packages are held so they leave in a different order than they arrived
+    pub mix_delay: MixDelay,
+    // compress packages for neighbors that say they can read them
+    pub compress_packages: bool,
+    // refuse to relay or exit for other Nodes
+    pub originate_only: bool,
+}
+
+#[derive (Clone, Copy, Debug, PartialEq)]
+pub enum MixDelay {
+    Off,
+    Uniform {min_ms: u64, max_ms: u64},
+    Exponential {mean_ms: u64},
+}
+
 #[derive(Clone)]
 pub struct HopperSubs {
     pub bind: Recipient<Syn, BindMessage>,
     pub from_hopper_client: Recipient<Syn, IncipientCoresPackage>,
     pub from_dispatcher: Recipient<Syn, InboundClientData>,
+    pub node_banned: Recipient<Syn, NodeBannedMsg>,
+    pub node_unbanned: Recipient<Syn, NodeUnbannedMsg>,
+    pub service_standing: Recipient<Syn, ServiceStandingMessage>,
 }
 
 #[cfg (test)]
@@ -1,4 +1,6 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::cmp::min;
+use std::mem;
 use std::usize;
 use std::fmt;
 use std::fmt::Debug;
@@ -70,6 +72,7 @@ pub trait HttpPacketStartFinder: Send {
 pub struct HttpPacketFramer {
     framer_state: HttpFramerState,
     start_finder: Box<HttpPacketStartFinder>,
+    streaming_window: Option<usize>,
     logger: Logger
 }
 
@@ -102,10 +105,20 @@ impl HttpPacketFramer {
                 lines: Vec::new()
             },
             start_finder,
+            streaming_window: None,
             logger: Logger::new("HttpRequestFramer")
         }
     }
 
+    /// A streaming framer doesn't wait for a Content-Length body to arrive in its entirety: it
+    /// relays headers and body data as soon as they're available, never more than window_size
+    /// bytes of body at a time.
+    pub fn new_streaming (start_finder: Box<HttpPacketStartFinder>, window_size: usize) -> HttpPacketFramer {
+        let mut framer = HttpPacketFramer::new (start_finder);
+        framer.streaming_window = Some (window_size);
+        framer
+    }
+
     fn take_packet_frame (&mut self) -> Option<FramedChunk> {
         if self.framer_state.packet_progress_state == PacketProgressState::SeekingPacketStart {
             if !self.start_finder.seek_packet_start (&mut self.framer_state) {return None}
@@ -114,6 +127,11 @@ impl HttpPacketFramer {
             if !self.seek_body_start() { return None }
         }
         if self.framer_state.packet_progress_state == PacketProgressState::SeekingBodyEnd {
+            if let Some (window_size) = self.streaming_window {
+                if let Some (partial) = self.take_partial_body (window_size) {
+                    return Some (partial)
+                }
+            }
             match self.seek_body_end() {
                 Some(request) => {
                     Some(FramedChunk {chunk: request, last_chunk: false})
@@ -174,6 +192,23 @@ impl HttpPacketFramer {
         }
     }
 
+    fn take_partial_body (&mut self, window_size: usize) -> Option<FramedChunk> {
+        // Chunked bodies are already relayed chunk by chunk
+        if self.framer_state.transfer_encoding_chunked != ChunkExistenceState::Standard {return None}
+        let available = min (self.framer_state.data_so_far.len (), window_size);
+        if available >= self.framer_state.content_length {return None}
+        if (available == 0) && self.framer_state.lines.is_empty () {return None}
+        let remainder = self.framer_state.data_so_far.split_off (available);
+        let body_piece = mem::replace (&mut self.framer_state.data_so_far, remainder);
+        self.framer_state.content_length -= available;
+        let mut partial = vec! ();
+        while self.framer_state.lines.len () > 0 {
+            partial.extend (self.framer_state.lines.remove (0))
+        }
+        partial.extend (body_piece);
+        Some (FramedChunk {chunk: partial, last_chunk: false})
+    }
+
     fn check_for_content_length (&mut self, line: &Vec<u8>) {
         if !line.starts_with ("Content-Length:".as_bytes ()) {return}
         let string = match String::from_utf8 (line.clone ()) {
@@ -870,4 +905,39 @@ GOOD_FIRST_LINE\r\nContent-Length: 5\r\n\r\ngooba".as_bytes();
         assert_eq! (to_string (&actual_chunk.chunk), to_string_s (&data[..]));
         assert_eq! (actual_chunk.last_chunk, false);
     }
+    #[test]
+    fn streaming_framer_relays_headers_and_body_as_they_arrive () {
+        let mut subject = HttpPacketFramer::new_streaming (Box::new (TameStartFinder {}), 100);
+
+        subject.add_data ("GOOD_FIRST_LINE\r\nContent-Length: 10\r\n\r\nabc".as_bytes ());
+        let first_result = subject.take_frame ().unwrap ();
+        let second_result = subject.take_frame ();
+        subject.add_data ("defg".as_bytes ());
+        let third_result = subject.take_frame ().unwrap ();
+        subject.add_data ("hijGOOD_FIRST_LINE\r\n\r\n".as_bytes ());
+        let fourth_result = subject.take_frame ().unwrap ();
+        let fifth_result = subject.take_frame ().unwrap ();
+
+        assert_eq! (to_string (&first_result.chunk), "GOOD_FIRST_LINE\r\nContent-Length: 10\r\n\r\nabc");
+        assert_eq! (second_result, None);
+        assert_eq! (to_string (&third_result.chunk), "defg");
+        assert_eq! (to_string (&fourth_result.chunk), "hij");
+        assert_eq! (to_string (&fifth_result.chunk), "GOOD_FIRST_LINE\r\n\r\n");
+        assert_eq! (subject.take_frame (), None);
+    }
+
+    #[test]
+    fn streaming_framer_never_relays_more_body_than_the_window_at_once () {
+        let mut subject = HttpPacketFramer::new_streaming (Box::new (TameStartFinder {}), 4);
+        subject.add_data ("GOOD_FIRST_LINE\r\nContent-Length: 10\r\n\r\n0123456789".as_bytes ());
+
+        let first_result = subject.take_frame ().unwrap ();
+        let second_result = subject.take_frame ().unwrap ();
+        let third_result = subject.take_frame ().unwrap ();
+
+        assert_eq! (to_string (&first_result.chunk), "GOOD_FIRST_LINE\r\nContent-Length: 10\r\n\r\n0123");
+        assert_eq! (to_string (&second_result.chunk), "4567");
+        assert_eq! (to_string (&third_result.chunk), "89");
+        assert_eq! (subject.take_frame (), None);
+    }
 }
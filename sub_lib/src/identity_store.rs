@@ -0,0 +1,110 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::path::Path;
+use cryptde::Key;
+use sealed_file::SealedFile;
+
+pub const IDENTITY_FILENAME: &str = "identity.key";
+
+// Keeps a Node's private key on disk, encrypted with a passphrase, so the Node keeps its identity
+// across restarts. A backup is just another IdentityStore somewhere else.
+pub struct IdentityStore {
+    file: SealedFile,
+}
+
+impl IdentityStore {
+    pub fn new (path: &Path, passphrase: &str) -> IdentityStore {
+        IdentityStore {
+            file: SealedFile::new (path, passphrase, "identity"),
+        }
+    }
+
+    pub fn in_data_directory (data_directory: &Path, passphrase: &str) -> IdentityStore {
+        IdentityStore::new (&data_directory.join (IDENTITY_FILENAME), passphrase)
+    }
+
+    pub fn path (&self) -> &Path {
+        self.file.path ()
+    }
+
+    // Ok (None) means no identity has been saved yet
+    pub fn load (&self) -> Result<Option<Key>, String> {
+        self.file.load ().map (|private_key_opt| private_key_opt.map (|private_key| Key::new (&private_key[..])))
+    }
+
+    pub fn save (&self, private_key: &Key) -> Result<(), String> {
+        self.file.save (&private_key.data[..])
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs;
+    use std::fs::File;
+    use std::io::Read;
+    use std::io::Write;
+    #[cfg (unix)]
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    fn make_data_directory (name: &str) -> PathBuf {
+        let data_directory = temp_dir ().join ("identity_store").join (name);
+        fs::create_dir_all (&data_directory).unwrap ();
+        let _ = fs::remove_file (data_directory.join (IDENTITY_FILENAME));
+        data_directory
+    }
+
+    #[test]
+    fn load_finds_nothing_before_the_first_save () {
+        let subject = IdentityStore::in_data_directory (&make_data_directory ("load_finds_nothing_before_the_first_save"), "passphrase");
+
+        assert_eq! (subject.load (), Ok (None));
+    }
+
+    #[test]
+    fn load_returns_what_was_saved_without_storing_it_in_the_clear () {
+        let data_directory = make_data_directory ("load_returns_what_was_saved_without_storing_it_in_the_clear");
+        let private_key = Key::new (b"a very private key indeed");
+
+        IdentityStore::in_data_directory (&data_directory, "passphrase").save (&private_key).unwrap ();
+        let result = IdentityStore::in_data_directory (&data_directory, "passphrase").load ();
+
+        assert_eq! (result, Ok (Some (private_key)));
+        let mut contents = vec! ();
+        File::open (data_directory.join (IDENTITY_FILENAME)).unwrap ().read_to_end (&mut contents).unwrap ();
+        assert_eq! (contents.windows (b"very private".len ()).any (|window| window == b"very private"), false);
+    }
+
+    #[test]
+    fn load_complains_about_the_wrong_passphrase () {
+        let data_directory = make_data_directory ("load_complains_about_the_wrong_passphrase");
+        IdentityStore::in_data_directory (&data_directory, "passphrase").save (&Key::new (b"private key")).unwrap ();
+
+        let result = IdentityStore::in_data_directory (&data_directory, "guess").load ();
+
+        assert_eq! (result.err ().unwrap ().contains ("wrong passphrase"), true);
+    }
+
+    #[test]
+    fn load_complains_about_a_file_that_is_not_an_identity () {
+        let data_directory = make_data_directory ("load_complains_about_a_file_that_is_not_an_identity");
+        File::create (data_directory.join (IDENTITY_FILENAME)).unwrap ().write_all (b"garbage").unwrap ();
+
+        let result = IdentityStore::in_data_directory (&data_directory, "passphrase").load ();
+
+        assert_eq! (result.err ().unwrap ().contains ("is not an identity file"), true);
+    }
+
+    #[cfg (unix)]
+    #[test]
+    fn only_the_owner_can_read_a_saved_identity () {
+        let data_directory = make_data_directory ("only_the_owner_can_read_a_saved_identity");
+        File::create (data_directory.join (IDENTITY_FILENAME)).unwrap ();
+        fs::set_permissions (data_directory.join (IDENTITY_FILENAME), fs::Permissions::from_mode (0o644)).unwrap ();
+
+        IdentityStore::in_data_directory (&data_directory, "passphrase").save (&Key::new (b"private key")).unwrap ();
+
+        assert_eq! (fs::metadata (data_directory.join (IDENTITY_FILENAME)).unwrap ().permissions ().mode () & 0o777, 0o600);
+    }
+}
@@ -2,6 +2,8 @@
 #[macro_use]
 extern crate actix;
 extern crate chrono;
+#[macro_use]
+extern crate lazy_static;
 extern crate log;
 extern crate rand;
 extern crate regex;
@@ -9,6 +11,8 @@ extern crate serde;
 extern crate serde_cbor;
 #[macro_use]
 extern crate serde_derive;
+extern crate sha2;
+extern crate sodiumoxide;
 
 #[cfg (test)]
 extern crate test_utils;
@@ -16,9 +20,14 @@ extern crate test_utils;
 #[cfg(unix)]
 extern crate daemonize;
 
+pub mod accountant;
+pub mod blockchain_bridge;
+pub mod blockchain_interface;
 pub mod cores_package;
 pub mod cryptde;
 pub mod cryptde_null;
+pub mod cryptde_real;
+pub mod cryptde_rotating;
 pub mod dispatcher;
 pub mod framer;
 pub mod framer_utils;
@@ -26,19 +35,27 @@ pub mod hop;
 pub mod hopper;
 pub mod http_packet_framer;
 pub mod http_response_start_finder;
+pub mod identity_store;
 pub mod limiter;
+pub mod link_handshake;
 pub mod logger;
 pub mod main_tools;
 pub mod neighborhood;
 pub mod node_addr;
 pub mod parameter_finder;
 pub mod peer_actors;
+pub mod price_oracle;
+pub mod private_file;
 pub mod proxy_client;
 pub mod proxy_server;
 pub mod route;
+pub mod sealed_file;
 pub mod socket_server;
 pub mod stream_handler_pool;
 pub mod tcp_wrappers;
 pub mod tls_framer;
+pub mod traffic_stats;
 pub mod udp_socket_wrapper;
 pub mod utils;
+pub mod wallet;
+pub mod wallet_store;
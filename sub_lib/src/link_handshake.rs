@@ -0,0 +1,601 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::time::Duration;
+use std::time::Instant;
+use serde_cbor;
+use sha2::Digest;
+use sha2::Sha256;
+use sodiumoxide;
+use sodiumoxide::crypto::box_;
+use sodiumoxide::crypto::secretbox;
+use cryptde::CryptDE;
+use cryptde::CryptData;
+use cryptde::Key;
+use cryptde::PlainData;
+
+const PROTOCOL_NAME: &[u8] = b"Substratum_XX_25519_XSalsa20Poly1305_SHA256";
+
+#[derive (Clone, Debug, PartialEq)]
+pub enum HandshakeError {
+    Malformed (String),
+    Unauthenticated (String),
+    UnexpectedPeer (Key),
+    OutOfTurn,
+}
+
+#[derive (Clone, Copy, Debug, PartialEq)]
+pub enum HandshakeRole {
+    Initiator,
+    Responder,
+}
+
+impl HandshakeRole {
+    fn label (&self) -> &'static [u8] {
+        match self {
+            &HandshakeRole::Initiator => b"initiator",
+            &HandshakeRole::Responder => b"responder",
+        }
+    }
+
+    fn other (&self) -> HandshakeRole {
+        match self {
+            &HandshakeRole::Initiator => HandshakeRole::Responder,
+            &HandshakeRole::Responder => HandshakeRole::Initiator,
+        }
+    }
+}
+
+// Each side's proof that it holds the private key behind the public key it gossips under
+#[derive (Serialize, Deserialize)]
+struct IdentityProof {
+    public_key: Key,
+    signature: CryptData,
+}
+
+// A three-message handshake in the shape of Noise XX, run when a clandestine stream is established:
+//
+//     initiator -> responder: e
+//     responder -> initiator: e, ee, {responder's public key and signature}
+//     initiator -> responder: {initiator's public key and signature}
+//
+// The static keys are the Nodes' gossip identities, which can sign but not do key agreement, so each
+// side proves its identity by signing the transcript (which covers both ephemeral keys and its role)
+// instead of mixing in a static-key DH. An initiator that already knows the responder's public key
+// from gossip pins it, as in Noise IK, and refuses anybody else. Both directions then get their own
+// session key, derived from the ephemeral DH and everything said during the handshake.
+//
+// The handshake does no I/O of its own: whatever start () and receive () return goes to the peer.
+pub struct LinkHandshake<'a> {
+    cryptde: &'a CryptDE,
+    role: HandshakeRole,
+    expected_peer_key_opt: Option<Key>,
+    ephemeral_public_key: box_::PublicKey,
+    ephemeral_secret_key: box_::SecretKey,
+    peer_public_key_opt: Option<Key>,
+    chaining_key: Vec<u8>,
+    transcript_hash: Vec<u8>,
+    messages_handled: usize,
+}
+
+impl<'a> LinkHandshake<'a> {
+    pub fn initiator (cryptde: &'a CryptDE, expected_peer_key_opt: Option<Key>) -> LinkHandshake<'a> {
+        LinkHandshake::new (cryptde, HandshakeRole::Initiator, expected_peer_key_opt)
+    }
+
+    pub fn responder (cryptde: &'a CryptDE) -> LinkHandshake<'a> {
+        LinkHandshake::new (cryptde, HandshakeRole::Responder, None)
+    }
+
+    pub fn role (&self) -> HandshakeRole {
+        self.role
+    }
+
+    // The initiator's first message; the responder has nothing to say until it hears from the initiator
+    pub fn start (&mut self) -> Result<Vec<u8>, HandshakeError> {
+        if (self.role != HandshakeRole::Initiator) || (self.messages_handled != 0) {
+            return Err (HandshakeError::OutOfTurn)
+        }
+        let ephemeral_public_key = self.ephemeral_public_key.0.to_vec ();
+        self.mix_hash (&ephemeral_public_key[..]);
+        self.messages_handled = 1;
+        Ok (ephemeral_public_key)
+    }
+
+    // Takes the peer's next message and returns our reply, if the handshake calls for one
+    pub fn receive (&mut self, message: &[u8]) -> Result<Option<Vec<u8>>, HandshakeError> {
+        match (self.role, self.messages_handled) {
+            (HandshakeRole::Responder, 0) => self.respond_to_ephemeral_key (message).map (Some),
+            (HandshakeRole::Initiator, 1) => self.respond_to_responder_identity (message).map (Some),
+            (HandshakeRole::Responder, 2) => self.accept_initiator_identity (message).map (|_| None),
+            _ => Err (HandshakeError::OutOfTurn)
+        }
+    }
+
+    pub fn is_finished (&self) -> bool {
+        self.messages_handled == 3
+    }
+
+    // The session keys are only worth having once both sides have proven who they are
+    pub fn session (self) -> Result<LinkSession, HandshakeError> {
+        if !self.is_finished () {return Err (HandshakeError::OutOfTurn)}
+        let initiator_to_responder = LinkHandshake::derive_key (&[&self.chaining_key[..], b"initiator_to_responder"].concat ()[..]);
+        let responder_to_initiator = LinkHandshake::derive_key (&[&self.chaining_key[..], b"responder_to_initiator"].concat ()[..]);
+        let masking_key = Sha256::digest (&[&self.chaining_key[..], b"masking"].concat ()[..]).to_vec ();
+        let (sending_key, receiving_key) = match self.role {
+            HandshakeRole::Initiator => (initiator_to_responder, responder_to_initiator),
+            HandshakeRole::Responder => (responder_to_initiator, initiator_to_responder),
+        };
+        Ok (LinkSession {
+            peer_public_key: self.peer_public_key_opt.expect ("Internal error: finished handshake without a peer"),
+            sending: LinkKey::new (sending_key),
+            receiving: LinkKey::new (receiving_key),
+            rekey_policy: DEFAULT_REKEY_POLICY,
+            masking_key,
+        })
+    }
+
+    fn new (cryptde: &'a CryptDE, role: HandshakeRole, expected_peer_key_opt: Option<Key>) -> LinkHandshake<'a> {
+        sodiumoxide::init ().expect ("Couldn't initialize libsodium");
+        let (ephemeral_public_key, ephemeral_secret_key) = box_::gen_keypair ();
+        let protocol_hash = Sha256::digest (PROTOCOL_NAME).to_vec ();
+        LinkHandshake {
+            cryptde,
+            role,
+            expected_peer_key_opt,
+            ephemeral_public_key,
+            ephemeral_secret_key,
+            peer_public_key_opt: None,
+            chaining_key: protocol_hash.clone (),
+            transcript_hash: protocol_hash,
+            messages_handled: 0,
+        }
+    }
+
+    fn respond_to_ephemeral_key (&mut self, message: &[u8]) -> Result<Vec<u8>, HandshakeError> {
+        let peer_ephemeral_key = LinkHandshake::parse_ephemeral_key (message)?;
+        self.mix_hash (&peer_ephemeral_key.0[..]);
+        let ephemeral_public_key = self.ephemeral_public_key.0.to_vec ();
+        self.mix_hash (&ephemeral_public_key[..]);
+        self.mix_key (&peer_ephemeral_key);
+        let proof = self.prove_identity ()?;
+        let encrypted_proof = self.encrypt_and_hash (&proof[..]);
+        self.messages_handled = 2;
+        Ok ([&ephemeral_public_key[..], &encrypted_proof[..]].concat ())
+    }
+
+    fn respond_to_responder_identity (&mut self, message: &[u8]) -> Result<Vec<u8>, HandshakeError> {
+        if message.len () < box_::PUBLICKEYBYTES {
+            return Err (HandshakeError::Malformed (format! ("{}-byte handshake message is too short", message.len ())))
+        }
+        let (peer_ephemeral_key_data, encrypted_proof) = message.split_at (box_::PUBLICKEYBYTES);
+        let peer_ephemeral_key = LinkHandshake::parse_ephemeral_key (peer_ephemeral_key_data)?;
+        self.mix_hash (&peer_ephemeral_key.0[..]);
+        self.mix_key (&peer_ephemeral_key);
+        self.check_identity (encrypted_proof)?;
+        let proof = self.prove_identity ()?;
+        let encrypted_proof = self.encrypt_and_hash (&proof[..]);
+        self.messages_handled = 3;
+        Ok (encrypted_proof)
+    }
+
+    fn accept_initiator_identity (&mut self, message: &[u8]) -> Result<(), HandshakeError> {
+        self.check_identity (message)?;
+        self.messages_handled = 3;
+        Ok (())
+    }
+
+    fn prove_identity (&self) -> Result<Vec<u8>, HandshakeError> {
+        let public_key = self.cryptde.public_key ();
+        let signature = match self.cryptde.sign (&self.signed_data (self.role, &public_key)) {
+            Ok (signature) => signature,
+            Err (e) => return Err (HandshakeError::Unauthenticated (format! ("Couldn't sign the handshake: {:?}", e)))
+        };
+        let proof = IdentityProof {public_key, signature};
+        Ok (serde_cbor::ser::to_vec (&proof).expect ("Internal error: couldn't serialize IdentityProof"))
+    }
+
+    // The peer signed before it encrypted, so its signature covers the transcript as it was then
+    fn check_identity (&mut self, encrypted_proof: &[u8]) -> Result<(), HandshakeError> {
+        let proof_data = self.decrypt (encrypted_proof)?;
+        let proof = match serde_cbor::de::from_slice::<IdentityProof> (&proof_data[..]) {
+            Ok (proof) => proof,
+            Err (e) => return Err (HandshakeError::Malformed (format! ("Peer's identity is unreadable: {:?}", e)))
+        };
+        let signed_data = self.signed_data (self.role.other (), &proof.public_key);
+        if !self.cryptde.verify_signature (&signed_data, &proof.signature, &proof.public_key) {
+            return Err (HandshakeError::Unauthenticated (String::from ("Peer couldn't prove it holds its private key")))
+        }
+        if let Some (ref expected_peer_key) = self.expected_peer_key_opt {
+            if expected_peer_key != &proof.public_key {
+                return Err (HandshakeError::UnexpectedPeer (proof.public_key))
+            }
+        }
+        self.mix_hash (encrypted_proof);
+        self.peer_public_key_opt = Some (proof.public_key);
+        Ok (())
+    }
+
+    // What each side signs: the transcript so far, its role, and the public key it's proving
+    fn signed_data (&self, role: HandshakeRole, public_key: &Key) -> PlainData {
+        PlainData::new (&[&self.transcript_hash[..], role.label (), &public_key.data[..]].concat ()[..])
+    }
+
+    fn encrypt_and_hash (&mut self, plain: &[u8]) -> Vec<u8> {
+        let sealed = secretbox::seal (plain, &secretbox::Nonce ([0; secretbox::NONCEBYTES]), &self.handshake_key ());
+        self.mix_hash (&sealed[..]);
+        sealed
+    }
+
+    fn decrypt (&self, sealed: &[u8]) -> Result<Vec<u8>, HandshakeError> {
+        match secretbox::open (sealed, &secretbox::Nonce ([0; secretbox::NONCEBYTES]), &self.handshake_key ()) {
+            Ok (plain) => Ok (plain),
+            Err (()) => Err (HandshakeError::Unauthenticated (String::from ("Peer's handshake message was tampered with")))
+        }
+    }
+
+    // Every key is used for a single message: the transcript changes between messages
+    fn handshake_key (&self) -> secretbox::Key {
+        LinkHandshake::derive_key (&[&self.chaining_key[..], &self.transcript_hash[..]].concat ()[..])
+    }
+
+    fn mix_hash (&mut self, data: &[u8]) {
+        let transcript_hash = Sha256::digest (&[&self.transcript_hash[..], data].concat ()[..]).to_vec ();
+        self.transcript_hash = transcript_hash;
+    }
+
+    fn mix_key (&mut self, peer_ephemeral_key: &box_::PublicKey) {
+        let shared_secret = box_::precompute (peer_ephemeral_key, &self.ephemeral_secret_key);
+        let chaining_key = Sha256::digest (&[&self.chaining_key[..], &shared_secret.0[..]].concat ()[..]).to_vec ();
+        self.chaining_key = chaining_key;
+    }
+
+    fn derive_key (data: &[u8]) -> secretbox::Key {
+        secretbox::Key::from_slice (&Sha256::digest (data)[..]).expect ("Internal error: SHA-256 digest is not a key")
+    }
+
+    fn parse_ephemeral_key (data: &[u8]) -> Result<box_::PublicKey, HandshakeError> {
+        match box_::PublicKey::from_slice (data) {
+            Some (key) => Ok (key),
+            None => Err (HandshakeError::Malformed (format! ("{}-byte handshake message is not an ephemeral key", data.len ())))
+        }
+    }
+}
+
+// Zero for either limit means that limit never triggers a re-key
+#[derive (Clone, Copy, Debug, PartialEq)]
+pub struct RekeyPolicy {
+    pub max_bytes: u64,
+    pub max_interval_ms: u64,
+}
+
+// Links re-key on these limits unless the Node is told otherwise
+pub const DEFAULT_REKEY_POLICY: RekeyPolicy = RekeyPolicy {
+    max_bytes: 0x40000000,
+    max_interval_ms: 3600000,
+};
+
+// The first byte of every decrypted message says whether the sender's next message is under a new key
+const FLAG_DATA: u8 = 0;
+const FLAG_KEY_UPDATE: u8 = 1;
+
+// One direction of a link: its current key and how much that key has been used
+struct LinkKey {
+    key: secretbox::Key,
+    message_count: u64,
+    byte_count: u64,
+    keyed_at: Instant,
+    generation: u64,
+}
+
+impl LinkKey {
+    fn new (key: secretbox::Key) -> LinkKey {
+        LinkKey {
+            key,
+            message_count: 0,
+            byte_count: 0,
+            keyed_at: Instant::now (),
+            generation: 0,
+        }
+    }
+
+    fn is_due (&self, policy: &RekeyPolicy, length: usize) -> bool {
+        let bytes_due = (policy.max_bytes > 0) && (self.byte_count + (length as u64) >= policy.max_bytes);
+        let time_due = (policy.max_interval_ms > 0) && (self.keyed_at.elapsed () >= Duration::from_millis (policy.max_interval_ms));
+        bytes_due || time_due
+    }
+
+    fn nonce (&self) -> secretbox::Nonce {
+        let mut nonce = [0; secretbox::NONCEBYTES];
+        for idx in 0..8 {
+            nonce[idx] = (self.message_count >> (8 * idx)) as u8;
+        }
+        secretbox::Nonce (nonce)
+    }
+
+    fn count (&mut self, length: usize) {
+        self.message_count += 1;
+        self.byte_count += length as u64;
+    }
+
+    // Both ends can work out the next key from the current one, so the key itself never goes on the
+    // wire; and the current key can't be worked out from the next one, so it's gone for good.
+    fn advance (&mut self) {
+        let next_key = LinkHandshake::derive_key (&[&self.key.0[..], b"rekey"].concat ()[..]);
+        self.key = next_key;
+        self.message_count = 0;
+        self.byte_count = 0;
+        self.keyed_at = Instant::now ();
+        self.generation += 1;
+    }
+}
+
+// The two session keys for a link, one for each direction. Every message is sealed with
+// XSalsa20-Poly1305 under a nonce counting the messages sent so far, so nothing can be replayed,
+// dropped, or reordered without the receiving side noticing.
+//
+// Each direction re-keys on its own once its key has carried enough bytes or been in use long
+// enough: the sender flags its last message under the old key, and both ends move to the next key
+// right after that message. No traffic has to wait for the re-key.
+pub struct LinkSession {
+    peer_public_key: Key,
+    sending: LinkKey,
+    receiving: LinkKey,
+    rekey_policy: RekeyPolicy,
+    masking_key: Vec<u8>,
+}
+
+impl LinkSession {
+    // The gossip identity the peer proved during the handshake
+    pub fn peer_public_key (&self) -> &Key {
+        &self.peer_public_key
+    }
+
+    // Both ends of the link, and nobody else, get the same masking key out of the handshake
+    pub fn masking_key (&self) -> &[u8] {
+        &self.masking_key[..]
+    }
+
+    pub fn set_rekey_policy (&mut self, rekey_policy: RekeyPolicy) {
+        self.rekey_policy = rekey_policy;
+    }
+
+    // How many times the sending and receiving keys have been replaced
+    pub fn key_generations (&self) -> (u64, u64) {
+        (self.sending.generation, self.receiving.generation)
+    }
+
+    pub fn encrypt (&mut self, data: &[u8]) -> Vec<u8> {
+        let rekey = self.sending.is_due (&self.rekey_policy, data.len ());
+        let flag = if rekey {FLAG_KEY_UPDATE} else {FLAG_DATA};
+        let sealed = secretbox::seal (&[&[flag][..], data].concat ()[..], &self.sending.nonce (), &self.sending.key);
+        self.sending.count (data.len ());
+        if rekey {self.sending.advance ()}
+        sealed
+    }
+
+    pub fn decrypt (&mut self, data: &[u8]) -> Result<Vec<u8>, HandshakeError> {
+        let plain = match secretbox::open (data, &self.receiving.nonce (), &self.receiving.key) {
+            Ok (plain) => plain,
+            Err (()) => return Err (HandshakeError::Unauthenticated (format! ("Message {} under key {} on the link didn't decrypt",
+                self.receiving.message_count, self.receiving.generation)))
+        };
+        let flag = match plain.first () {
+            Some (flag) => *flag,
+            None => return Err (HandshakeError::Malformed (String::from ("Message on the link has no flag")))
+        };
+        self.receiving.count (plain.len () - 1);
+        match flag {
+            FLAG_DATA => (),
+            FLAG_KEY_UPDATE => self.receiving.advance (),
+            _ => return Err (HandshakeError::Malformed (format! ("Message on the link has unknown flag {}", flag)))
+        }
+        Ok (plain[1..].to_vec ())
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use cryptde_real::CryptDEReal;
+
+    fn make_cryptde () -> CryptDEReal {
+        let mut cryptde = CryptDEReal::new ();
+        cryptde.generate_key_pair ();
+        cryptde
+    }
+
+    fn shake_hands (initiator: &mut LinkHandshake, responder: &mut LinkHandshake) -> Result<(), HandshakeError> {
+        let first = initiator.start ()?;
+        let second = responder.receive (&first[..])?.expect ("Responder had no reply");
+        let third = initiator.receive (&second[..])?.expect ("Initiator had no reply");
+        match responder.receive (&third[..])? {
+            None => Ok (()),
+            Some (_) => panic! ("Responder replied to the last message")
+        }
+    }
+
+    #[test]
+    fn a_completed_handshake_gives_each_side_the_other_s_identity_and_matching_session_keys () {
+        let initiator_cryptde = make_cryptde ();
+        let responder_cryptde = make_cryptde ();
+        let mut initiator = LinkHandshake::initiator (&initiator_cryptde, Some (responder_cryptde.public_key ()));
+        let mut responder = LinkHandshake::responder (&responder_cryptde);
+
+        shake_hands (&mut initiator, &mut responder).unwrap ();
+
+        let mut initiator_session = initiator.session ().unwrap ();
+        let mut responder_session = responder.session ().unwrap ();
+        assert_eq! (initiator_session.peer_public_key (), &responder_cryptde.public_key ());
+        assert_eq! (responder_session.peer_public_key (), &initiator_cryptde.public_key ());
+        let outbound = initiator_session.encrypt (b"there and");
+        let inbound = responder_session.encrypt (b"back again");
+        assert_eq! (responder_session.decrypt (&outbound[..]), Ok (b"there and".to_vec ()));
+        assert_eq! (initiator_session.decrypt (&inbound[..]), Ok (b"back again".to_vec ()));
+        assert_ne! (&outbound[..], b"there and");
+        assert_eq! (initiator_session.masking_key (), responder_session.masking_key ());
+    }
+
+    #[test]
+    fn session_keys_differ_from_link_to_link_and_direction_to_direction () {
+        let initiator_cryptde = make_cryptde ();
+        let responder_cryptde = make_cryptde ();
+        let mut first_initiator = LinkHandshake::initiator (&initiator_cryptde, None);
+        let mut first_responder = LinkHandshake::responder (&responder_cryptde);
+        shake_hands (&mut first_initiator, &mut first_responder).unwrap ();
+        let mut second_initiator = LinkHandshake::initiator (&initiator_cryptde, None);
+        let mut second_responder = LinkHandshake::responder (&responder_cryptde);
+        shake_hands (&mut second_initiator, &mut second_responder).unwrap ();
+        let mut first_session = first_initiator.session ().unwrap ();
+        let mut second_session = second_responder.session ().unwrap ();
+        let mut second_initiator_session = second_initiator.session ().unwrap ();
+
+        let outbound = first_session.encrypt (b"data");
+
+        assert_eq! (second_session.decrypt (&outbound[..]).is_err (), true);
+        let own_message = second_initiator_session.encrypt (b"data");
+        assert_eq! (second_initiator_session.decrypt (&own_message[..]).is_err (), true);
+        assert_ne! (first_session.masking_key (), second_session.masking_key ());
+    }
+
+    #[test]
+    fn replayed_or_tampered_messages_are_refused () {
+        let initiator_cryptde = make_cryptde ();
+        let responder_cryptde = make_cryptde ();
+        let mut initiator = LinkHandshake::initiator (&initiator_cryptde, None);
+        let mut responder = LinkHandshake::responder (&responder_cryptde);
+        shake_hands (&mut initiator, &mut responder).unwrap ();
+        let mut initiator_session = initiator.session ().unwrap ();
+        let mut responder_session = responder.session ().unwrap ();
+        let message = initiator_session.encrypt (b"once only");
+        let mut tampered = initiator_session.encrypt (b"untouched");
+        tampered[0] ^= 1;
+
+        assert_eq! (responder_session.decrypt (&message[..]), Ok (b"once only".to_vec ()));
+        assert_eq! (responder_session.decrypt (&message[..]).is_err (), true);
+        assert_eq! (responder_session.decrypt (&tampered[..]).is_err (), true);
+    }
+
+    fn make_sessions () -> (LinkSession, LinkSession) {
+        let initiator_cryptde = make_cryptde ();
+        let responder_cryptde = make_cryptde ();
+        let mut initiator = LinkHandshake::initiator (&initiator_cryptde, None);
+        let mut responder = LinkHandshake::responder (&responder_cryptde);
+        shake_hands (&mut initiator, &mut responder).unwrap ();
+        (initiator.session ().unwrap (), responder.session ().unwrap ())
+    }
+
+    #[test]
+    fn a_sending_key_that_has_carried_enough_bytes_is_replaced_without_interrupting_traffic () {
+        let (mut initiator_session, mut responder_session) = make_sessions ();
+        initiator_session.set_rekey_policy (RekeyPolicy {max_bytes: 10, max_interval_ms: 0});
+
+        let messages: Vec<Vec<u8>> = (0..5).map (|idx| initiator_session.encrypt (&[idx; 4])).collect ();
+
+        let received: Vec<Vec<u8>> = messages.iter ().map (|message| responder_session.decrypt (&message[..]).unwrap ()).collect ();
+        assert_eq! (received, (0..5).map (|idx| vec! (idx; 4)).collect::<Vec<Vec<u8>>> ());
+        assert_eq! (initiator_session.key_generations (), (1, 0));
+        assert_eq! (responder_session.key_generations (), (0, 1));
+        let reply = responder_session.encrypt (b"still on the first key");
+        assert_eq! (initiator_session.decrypt (&reply[..]), Ok (b"still on the first key".to_vec ()));
+    }
+
+    #[test]
+    fn a_sending_key_that_has_been_in_use_long_enough_is_replaced () {
+        let (mut initiator_session, mut responder_session) = make_sessions ();
+        initiator_session.set_rekey_policy (RekeyPolicy {max_bytes: 0, max_interval_ms: 10});
+        let early = initiator_session.encrypt (b"early");
+        thread::sleep (Duration::from_millis (20));
+
+        let late = initiator_session.encrypt (b"late");
+        let later = initiator_session.encrypt (b"later");
+
+        assert_eq! (responder_session.decrypt (&early[..]), Ok (b"early".to_vec ()));
+        assert_eq! (responder_session.decrypt (&late[..]), Ok (b"late".to_vec ()));
+        assert_eq! (responder_session.decrypt (&later[..]), Ok (b"later".to_vec ()));
+        assert_eq! (responder_session.key_generations (), (0, 1));
+    }
+
+    #[test]
+    fn a_message_under_the_old_key_is_refused_once_the_key_has_been_replaced () {
+        let (mut initiator_session, mut responder_session) = make_sessions ();
+        initiator_session.set_rekey_policy (RekeyPolicy {max_bytes: 1, max_interval_ms: 0});
+        let last_under_old_key = initiator_session.encrypt (b"data");
+        responder_session.decrypt (&last_under_old_key[..]).unwrap ();
+
+        let result = responder_session.decrypt (&last_under_old_key[..]);
+
+        assert_eq! (result, Err (HandshakeError::Unauthenticated (String::from ("Message 0 under key 1 on the link didn't decrypt"))));
+    }
+
+    #[test]
+    fn an_initiator_refuses_a_responder_other_than_the_one_it_expected () {
+        let initiator_cryptde = make_cryptde ();
+        let responder_cryptde = make_cryptde ();
+        let expected_cryptde = make_cryptde ();
+        let mut initiator = LinkHandshake::initiator (&initiator_cryptde, Some (expected_cryptde.public_key ()));
+        let mut responder = LinkHandshake::responder (&responder_cryptde);
+
+        let result = shake_hands (&mut initiator, &mut responder);
+
+        assert_eq! (result, Err (HandshakeError::UnexpectedPeer (responder_cryptde.public_key ())));
+        assert_eq! (initiator.is_finished (), false);
+        assert_eq! (initiator.session ().is_err (), true);
+    }
+
+    #[test]
+    fn a_peer_cannot_claim_an_identity_whose_private_key_it_does_not_hold () {
+        let initiator_cryptde = make_cryptde ();
+        let impostor_cryptde = make_cryptde ();
+        let victim_cryptde = make_cryptde ();
+        let mut initiator = LinkHandshake::initiator (&initiator_cryptde, Some (victim_cryptde.public_key ()));
+        let first = initiator.start ().unwrap ();
+        // The impostor presents the victim's public key, but can only sign with its own private key
+        let forged_reply = {
+            let mut forger = LinkHandshake::responder (&impostor_cryptde);
+            let peer_ephemeral_key = LinkHandshake::parse_ephemeral_key (&first[..]).unwrap ();
+            forger.mix_hash (&first[..]);
+            let ephemeral_public_key = forger.ephemeral_public_key.0.to_vec ();
+            forger.mix_hash (&ephemeral_public_key[..]);
+            forger.mix_key (&peer_ephemeral_key);
+            let victim_public_key = victim_cryptde.public_key ();
+            let signature = impostor_cryptde.sign (&forger.signed_data (HandshakeRole::Responder, &victim_public_key)).unwrap ();
+            let proof = serde_cbor::ser::to_vec (&IdentityProof {public_key: victim_public_key, signature}).unwrap ();
+            let encrypted_proof = forger.encrypt_and_hash (&proof[..]);
+            [&ephemeral_public_key[..], &encrypted_proof[..]].concat ()
+        };
+
+        let result = initiator.receive (&forged_reply[..]);
+
+        assert_eq! (result.err ().unwrap (), HandshakeError::Unauthenticated (String::from ("Peer couldn't prove it holds its private key")));
+    }
+
+    #[test]
+    fn tampering_with_the_handshake_stops_it () {
+        let initiator_cryptde = make_cryptde ();
+        let responder_cryptde = make_cryptde ();
+        let mut initiator = LinkHandshake::initiator (&initiator_cryptde, None);
+        let mut responder = LinkHandshake::responder (&responder_cryptde);
+        let first = initiator.start ().unwrap ();
+        let mut second = responder.receive (&first[..]).unwrap ().unwrap ();
+        let last = second.len () - 1;
+        second[last] ^= 1;
+
+        let result = initiator.receive (&second[..]);
+
+        assert_eq! (result.err ().unwrap (), HandshakeError::Unauthenticated (String::from ("Peer's handshake message was tampered with")));
+    }
+
+    #[test]
+    fn messages_out_of_turn_and_malformed_messages_are_refused () {
+        let cryptde = make_cryptde ();
+        let mut initiator = LinkHandshake::initiator (&cryptde, None);
+        let mut responder = LinkHandshake::responder (&cryptde);
+
+        assert_eq! (responder.start (), Err (HandshakeError::OutOfTurn));
+        assert_eq! (initiator.receive (b"hello"), Err (HandshakeError::OutOfTurn));
+        assert_eq! (responder.receive (b"hello"), Err (HandshakeError::Malformed (String::from ("5-byte handshake message is not an ephemeral key"))));
+        initiator.start ().unwrap ();
+        assert_eq! (initiator.start (), Err (HandshakeError::OutOfTurn));
+        assert_eq! (initiator.receive (b"hello"), Err (HandshakeError::Malformed (String::from ("5-byte handshake message is too short"))));
+    }
+}
@@ -3,11 +3,85 @@ use std::time::UNIX_EPOCH;
 use std::time::SystemTime;
 use chrono::NaiveDateTime;
 use chrono::format::strftime::StrftimeItems;
+use log;
 use log::Level;
+use log::LevelFilter;
 use log::Record;
 use log::logger;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::atomic::ATOMIC_BOOL_INIT;
+use std::sync::atomic::ATOMIC_USIZE_INIT;
 use std::thread;
 
+// One more than the LevelFilter in force, so that before anybody sets it, everything is logged
+static LOG_LEVEL: AtomicUsize = ATOMIC_USIZE_INIT;
+const LEVEL_FILTERS: [LevelFilter; 6] = [LevelFilter::Off, LevelFilter::Error, LevelFilter::Warn, LevelFilter::Info,
+    LevelFilter::Debug, LevelFilter::Trace];
+
+lazy_static! {
+    // Levels that override LOG_LEVEL for some modules, by the name their Loggers log under
+    static ref MODULE_LOG_LEVELS: Mutex<HashMap<String, LevelFilter>> = Mutex::new (HashMap::new ());
+}
+// So that while no module has a level of its own, logging never waits for the lock
+static MODULE_LOG_LEVELS_SET: AtomicBool = ATOMIC_BOOL_INIT;
+
+// Can be changed at any time; messages less important than this aren't logged from then on
+pub fn set_log_level (level: LevelFilter) {
+    LOG_LEVEL.store (level as usize + 1, Ordering::SeqCst);
+    update_max_level ();
+}
+
+// Like set_log_level, but only for the Loggers named module, or named module followed by a space and
+// more, such as "Dispatcher for 1.2.3.4:80" for "Dispatcher"; where more than one module matches, the
+// longest wins. None puts the module back under the global level.
+pub fn set_module_log_level (module: &str, level_opt: Option<LevelFilter>) {
+    let mut module_levels = MODULE_LOG_LEVELS.lock ().expect ("Module log levels poisoned");
+    match level_opt {
+        Some (level) => {module_levels.insert (String::from (module), level);},
+        None => {module_levels.remove (module);}
+    }
+    MODULE_LOG_LEVELS_SET.store (!module_levels.is_empty (), Ordering::SeqCst);
+    drop (module_levels);
+    update_max_level ();
+}
+
+// The level a Logger with this name logs at
+pub fn module_log_level (name: &str) -> LevelFilter {
+    if !MODULE_LOG_LEVELS_SET.load (Ordering::SeqCst) {return log_level ()}
+    let module_levels = MODULE_LOG_LEVELS.lock ().expect ("Module log levels poisoned");
+    let mut best_opt: Option<(usize, LevelFilter)> = None;
+    for (module, level) in module_levels.iter () {
+        let matches = (name == module) || (name.starts_with (module.as_str ()) && name[module.len ()..].starts_with (" "));
+        let longer = match best_opt {
+            Some ((best_len, _)) => module.len () > best_len,
+            None => true
+        };
+        if matches && longer {best_opt = Some ((module.len (), *level))}
+    }
+    match best_opt {
+        Some ((_, level)) => level,
+        None => log_level ()
+    }
+}
+
+// The log crate filters what other crates log by the most any module might want
+fn update_max_level () {
+    let module_levels = MODULE_LOG_LEVELS.lock ().expect ("Module log levels poisoned");
+    let max_level = module_levels.values ().fold (log_level (), |sofar, level| if *level > sofar {*level} else {sofar});
+    log::set_max_level (max_level);
+}
+
+pub fn log_level () -> LevelFilter {
+    match LOG_LEVEL.load (Ordering::SeqCst) {
+        0 => LevelFilter::max (),
+        n => LEVEL_FILTERS[n - 1]
+    }
+}
+
 pub struct Logger {
     name: String
 }
@@ -55,6 +129,7 @@ impl Logger {
     }
 
     fn generic_log (&self, level: Level, string: String) {
+        if level > module_log_level (&self.name) {return}
         let logger = logger ();
         logger.log (&Record::builder ()
             .args (format_args! ("{} {:?}: {}: {}: {}", Logger::timestamp_as_string (&SystemTime::now ()),
@@ -92,6 +167,39 @@ mod tests {
         assert_between (&another_log[..prefix_len], &before_str, &after_str);
     }
 
+    // Module names are this test's own, so other tests' Loggers aren't affected
+    #[test]
+    fn a_module_can_log_at_a_level_of_its_own () {
+        set_module_log_level ("a_module_can_log_at_a_level_of_its_own", Some (LevelFilter::Error));
+        set_module_log_level ("a_module_can_log_at_a_level_of_its_own for", Some (LevelFilter::Warn));
+
+        assert_eq! (module_log_level ("a_module_can_log_at_a_level_of_its_own"), LevelFilter::Error);
+        assert_eq! (module_log_level ("a_module_can_log_at_a_level_of_its_own for 1.2.3.4:80"), LevelFilter::Warn);
+        assert_eq! (module_log_level ("a_module_can_log_at_a_level_of_its_own again"), LevelFilter::Error);
+        assert_eq! (module_log_level ("a_module_can_log_at_a_level_of_its_own_too"), log_level ());
+
+        set_module_log_level ("a_module_can_log_at_a_level_of_its_own", None);
+        set_module_log_level ("a_module_can_log_at_a_level_of_its_own for", None);
+
+        assert_eq! (module_log_level ("a_module_can_log_at_a_level_of_its_own"), log_level ());
+        assert_eq! (module_log_level ("a_module_can_log_at_a_level_of_its_own for 1.2.3.4:80"), log_level ());
+    }
+
+    #[test]
+    fn a_module_below_its_level_logs_nothing () {
+        init_test_logging();
+        set_module_log_level ("a_module_below_its_level_logs_nothing", Some (LevelFilter::Off));
+        let subject = Logger::new ("a_module_below_its_level_logs_nothing");
+
+        subject.error (String::from ("unheard"));
+
+        set_module_log_level ("a_module_below_its_level_logs_nothing", None);
+        subject.error (String::from ("heard"));
+        let tlh = TestLogHandler::new ();
+        tlh.exists_log_containing ("a_module_below_its_level_logs_nothing: heard");
+        tlh.exists_no_log_containing ("a_module_below_its_level_logs_nothing: unheard");
+    }
+
     fn assert_between (candidate: &str, before: &str, after: &str) {
         assert_eq! (candidate >= before, true, "{} is not equal to or after {}", candidate, before);
         assert_eq! (candidate <= after, true, "{} is not before or equal to {}", candidate, after);
@@ -2,14 +2,157 @@
 use actix::Message;
 use actix::Recipient;
 use actix::Syn;
+use accountant::RateSchedule;
+use cryptde::CryptData;
 use cryptde::Key;
+use hopper::ExpiredCoresPackage;
 use node_addr::NodeAddr;
 use peer_actors::BindMessage;
+use route::Route;
+use std::fmt;
 use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use wallet::Wallet;
 
 #[derive(Clone)]
 pub struct NeighborhoodSubs {
     pub bind: Recipient<Syn, BindMessage>,
+    pub route_query: Recipient<Syn, RouteQueryMessage>,
+    pub report_misbehavior: Recipient<Syn, NeighborMisbehaviorMessage>,
+    pub from_hopper: Recipient<Syn, ExpiredNeighborhoodPackage>,
+    pub ban_node: Recipient<Syn, BanNodeMsg>,
+    pub unban_node: Recipient<Syn, UnbanNodeMsg>,
+    pub new_public_ip: Recipient<Syn, NewPublicIpMsg>,
+    pub new_public_key: Recipient<Syn, NewPublicKeyMsg>,
+    pub retired_public_key: Recipient<Syn, RetiredPublicKeyMsg>,
+    pub set_exit_location: Recipient<Syn, SetExitLocationMsg>,
+    pub get_topology: Recipient<Syn, GetTopologyMsg>,
+}
+
+// Hop counts are relays between the originating Node and the exit Node
+pub const DEFAULT_MIN_HOPS: usize = 0;
+pub const DEFAULT_MAX_HOPS: usize = 0;
+
+pub const DEFAULT_GOSSIP_INTERVAL_MS: u64 = 60000;
+
+pub const DEFAULT_HEARTBEAT_INTERVAL_MS: u64 = 5000;
+
+// Far more than an honest network of this size needs. Gossip is the largest thing a neighbor
+// sends, so the Hopper drops anything much bigger unread.
+pub const MAX_GOSSIP_BYTES: usize = 1048576;
+
+pub const DEFAULT_STALE_NODE_WINDOW_MS: u64 = 3600000;
+
+pub const DEFAULT_IP_CHECK_INTERVAL_MS: u64 = 300000;
+
+pub const DEFAULT_ROUTE_DIVERSITY: RouteDiversity = RouteDiversity::Prefer;
+
+pub const DEFAULT_MIN_NEIGHBORS: usize = 2;
+pub const DEFAULT_TARGET_NEIGHBORS: usize = 5;
+pub const DEFAULT_MAX_NEIGHBORS: usize = 10;
+
+#[derive (Clone, Debug, PartialEq)]
+pub struct NeighborhoodConfig {
+    pub neighbor_configs: Vec<(Key, NodeAddr)>,
+    // where neighbors can reach this Node, if it's known yet
+    pub local_ip_addr_opt: Option<IpAddr>,
+    pub clandestine_ports: Vec<u16>,
+    pub min_hops: usize,
+    pub max_hops: usize,
+    // milliseconds between unprompted Gossip to direct neighbors; 0 to gossip only about changes
+    pub gossip_interval_ms: u64,
+    // milliseconds between Pings to each direct neighbor; 0 for none
+    pub heartbeat_interval_ms: u64,
+    // milliseconds a Node can go unheard from before it's forgotten; 0 to remember Nodes forever
+    pub stale_node_window_ms: u64,
+    // where the neighborhood database is kept between runs; None to start fresh every time
+    pub data_directory_opt: Option<PathBuf>,
+    // Nodes the operator has banned at startup
+    pub bans: Vec<BanNodeMsg>,
+    // CSV of IP ranges and the countries they're in; None if Node locations aren't needed
+    pub geolocation_database_opt: Option<PathBuf>,
+    pub exit_location: ExitLocation,
+    pub route_diversity: RouteDiversity,
+    // consume routes without ever relaying or exiting for other Nodes
+    pub originate_only: bool,
+    // fewer neighbors than this is worth a warning
+    pub min_neighbors: usize,
+    // below this many neighbors, Nodes heard about in Gossip are asked to become neighbors
+    pub target_neighbors: usize,
+    // at this many neighbors, other Nodes' introductions are declined
+    pub max_neighbors: usize,
+    // where other Nodes should pay this one, announced in Gossip
+    pub earning_wallet_opt: Option<Wallet>,
+    // where this Node pays from, announced in Gossip
+    pub consuming_wallet_opt: Option<Wallet>,
+    // what this Node charges, announced in Gossip
+    pub rates: RateSchedule,
+    // the most a route this Node builds may cost; None for no limit
+    pub max_route_cost_opt: Option<RouteCost>,
+}
+
+// What the Nodes on a route charge, all told, to carry a request and its response: per_package
+// once for the pair, and per_byte for each byte of payload
+#[derive (Clone, Copy, Debug, Default, PartialEq)]
+pub struct RouteCost {
+    pub per_package: i64,
+    pub per_byte: i64,
+}
+
+impl RouteCost {
+    pub fn exceeds (&self, limit: &RouteCost) -> bool {
+        (self.per_package > limit.per_package) || (self.per_byte > limit.per_byte)
+    }
+}
+
+impl fmt::Display for RouteCost {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write! (f, "{} per package and {} per byte", self.per_package, self.per_byte)
+    }
+}
+
+// How hard route building tries to keep Nodes in the same /16, autonomous system, or operator
+// from sharing a route
+#[derive (Clone, Copy, Debug, PartialEq)]
+pub enum RouteDiversity {
+    // Relays are chosen on reputation and latency alone
+    Off,
+    // Related Nodes share a route only when there aren't enough unrelated ones
+    Prefer,
+    // Related Nodes never share a route, even if that means there's no route
+    Require,
+}
+
+// Countries routes may and may not leave the network from; no countries at all means anywhere
+#[derive (Clone, Debug, Default, PartialEq)]
+pub struct ExitLocation {
+    pub countries: Vec<String>,
+    pub avoided_countries: Vec<String>,
+}
+
+impl ExitLocation {
+    pub fn is_anywhere (&self) -> bool {
+        self.countries.is_empty () && self.avoided_countries.is_empty ()
+    }
+
+    // A Node whose country is unknown can't be shown to be in a requested country, but it isn't
+    // known to be in an avoided one either
+    pub fn allows (&self, country_opt: Option<&str>) -> bool {
+        match country_opt {
+            None => self.countries.is_empty (),
+            Some (country) => (self.countries.is_empty () || self.countries.iter ().any (|c| c == country))
+                && !self.avoided_countries.iter ().any (|c| c == country)
+        }
+    }
+}
+
+impl fmt::Display for ExitLocation {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut terms: Vec<String> = self.countries.clone ();
+        terms.extend (self.avoided_countries.iter ().map (|country| format! ("!{}", country)));
+        write! (f, "{}", terms.join (","))
+    }
 }
 
 #[derive (Clone, Debug, PartialEq)]
@@ -34,3 +177,174 @@ pub enum NodeQueryMessage {
 impl Message for NodeQueryMessage {
     type Result = Option<NodeDescriptor>;
 }
+
+#[derive (Clone, Debug, PartialEq)]
+pub struct RouteQueryMessage {
+    pub excluded_exit_keys: Vec<Key>,
+}
+
+impl Message for RouteQueryMessage {
+    type Result = Option<RouteQueryResponse>;
+}
+
+impl RouteQueryMessage {
+    pub fn new (excluded_exit_keys: Vec<Key>) -> RouteQueryMessage {
+        RouteQueryMessage {
+            excluded_exit_keys
+        }
+    }
+}
+
+#[derive (Clone, Debug, PartialEq)]
+pub struct RouteQueryResponse {
+    pub route: Route,
+    pub exit_key: Key,
+}
+
+#[derive (Clone, Copy, Debug, PartialEq)]
+pub enum NeighborMisbehavior {
+    TamperedPackage,
+    ReplayedPackage,
+    MalformedPackage,
+    // bigger than any package a Node would send; it isn't even read
+    OversizedPackage,
+    FailedRelay,
+    ForgedGossip,
+    GossipFlood,
+    UnpaidDebt,
+}
+
+// Neighborhood packages travel a single link, so the Hopper can say who delivered them
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct ExpiredNeighborhoodPackage {
+    pub package: ExpiredCoresPackage,
+    pub neighbor_addr: SocketAddr,
+}
+
+// Reputation is kept by Node. A misbehaving Node whose key the reporter doesn't know is reported
+// by the address it misbehaved from, and held to account only if it's the one Node known there.
+#[derive (Clone, Debug, PartialEq)]
+pub enum Misbehaver {
+    Node (Key),
+    Address (SocketAddr),
+}
+
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct NeighborMisbehaviorMessage {
+    pub misbehaver: Misbehaver,
+    pub misbehavior: NeighborMisbehavior,
+}
+
+// Bans a Node for good; shared bans travel in Gossip with the local Node's signature as evidence
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct BanNodeMsg {
+    pub public_key: Key,
+    pub reason: String,
+    pub share: bool,
+}
+
+// Lifts a ban the local Node imposed; bans other Nodes shared with it stay
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct UnbanNodeMsg {
+    pub public_key: Key,
+}
+
+// Tells the actors that carry a banned Node's traffic to stop carrying it
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct NodeBannedMsg {
+    pub public_key: Key,
+    pub ip_addr_opt: Option<IpAddr>,
+}
+
+// Tells the actors that stopped carrying a Node's traffic that they can carry it again
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct NodeUnbannedMsg {
+    pub public_key: Key,
+    pub ip_addr_opt: Option<IpAddr>,
+}
+
+// The address the rest of the network sees this Node at has changed
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct NewPublicIpMsg {
+    pub ip_addr: IpAddr,
+}
+
+// This Node's key pair has been rotated; the old key pair signed the new public key
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct NewPublicKeyMsg {
+    pub previous_public_key: Key,
+    pub endorsement: CryptData,
+}
+
+// The key pair this Node rotated away from no longer decodes anything
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct RetiredPublicKeyMsg {
+    pub public_key: Key,
+}
+
+// The operator has changed where routes may leave the network; routes already built keep their exits
+#[derive (Clone, Debug, PartialEq, Message)]
+pub struct SetExitLocationMsg {
+    pub exit_location: ExitLocation,
+}
+
+// One Node on the network as the local Node sees it
+#[derive (Clone, Debug, PartialEq)]
+pub struct TopologyNode {
+    pub public_key: Key,
+    pub node_addr_opt: Option<NodeAddr>,
+    pub is_local: bool,
+    pub reputation: i64,
+    // None for the local Node, and for neighbors that haven't been heard from since it started
+    pub last_seen_ms_ago_opt: Option<u64>,
+    pub latency_ms_opt: Option<u64>,
+    pub country_opt: Option<String>,
+}
+
+// The Node at from says in its record that the Node at to is its neighbor
+#[derive (Clone, Debug, PartialEq)]
+pub struct TopologyEdge {
+    pub from: Key,
+    pub to: Key,
+}
+
+#[derive (Clone, Debug, PartialEq, Default)]
+pub struct NeighborhoodTopology {
+    pub nodes: Vec<TopologyNode>,
+    pub edges: Vec<TopologyEdge>,
+}
+
+// For the UI's map of the network
+#[derive (Clone, Debug, PartialEq)]
+pub struct GetTopologyMsg {}
+
+impl Message for GetTopologyMsg {
+    type Result = NeighborhoodTopology;
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_location_allows_requested_countries_and_refuses_avoided_ones () {
+        let requested = ExitLocation {countries: vec! (String::from ("CA"), String::from ("US")), avoided_countries: vec! ()};
+        let avoided = ExitLocation {countries: vec! (), avoided_countries: vec! (String::from ("CN"))};
+
+        assert_eq! (requested.allows (Some ("US")), true);
+        assert_eq! (requested.allows (Some ("CN")), false);
+        assert_eq! (requested.allows (None), false);
+        assert_eq! (avoided.allows (Some ("US")), true);
+        assert_eq! (avoided.allows (Some ("CN")), false);
+        assert_eq! (avoided.allows (None), true);
+        assert_eq! (ExitLocation::default ().is_anywhere (), true);
+        assert_eq! (avoided.is_anywhere (), false);
+    }
+
+    #[test]
+    fn exit_location_displays_the_way_it_is_configured () {
+        let subject = ExitLocation {countries: vec! (String::from ("CA"), String::from ("US")), avoided_countries: vec! (String::from ("CN"))};
+
+        assert_eq! (subject.to_string (), String::from ("CA,US,!CN"));
+    }
+}
@@ -2,6 +2,7 @@
 use std::fmt;
 use std::fmt::Debug;
 use std::fmt::Formatter;
+use accountant::AccountantSubs;
 use proxy_server::ProxyServerSubs;
 use dispatcher::DispatcherSubs;
 use hopper::HopperSubs;
@@ -15,6 +16,7 @@ pub struct PeerActors {
     pub hopper: HopperSubs,
     pub proxy_client: ProxyClientSubs,
     pub neighborhood: NeighborhoodSubs,
+    pub accountant: AccountantSubs,
 }
 
 impl Debug for PeerActors {
@@ -0,0 +1,8 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+
+// What one SUB is worth in some fiat currency, according to a source outside the Node. Asking may
+// take a network round trip, so it's done on a thread of its own rather than by an actor.
+pub trait PriceOracle: Send {
+    // in whole units of the currency, such as dollars, per whole SUB; always more than zero
+    fn price (&self) -> Result<f64, String>;
+}
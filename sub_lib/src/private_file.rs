@@ -0,0 +1,24 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+#[cfg (unix)]
+use std::os::unix::fs::OpenOptionsExt;
+#[cfg (unix)]
+use std::os::unix::fs::PermissionsExt;
+
+// For secrets: only the Node's user can read what's written. The mode only applies to a new file,
+// so one that was already there is tightened up too.
+#[cfg (unix)]
+pub fn create_private_file (path: &Path) -> io::Result<File> {
+    let file = OpenOptions::new ().write (true).create (true).truncate (true).mode (0o600).open (path)?;
+    fs::set_permissions (path, fs::Permissions::from_mode (0o600))?;
+    Ok (file)
+}
+
+#[cfg (windows)]
+pub fn create_private_file (path: &Path) -> io::Result<File> {
+    OpenOptions::new ().write (true).create (true).truncate (true).open (path)
+}
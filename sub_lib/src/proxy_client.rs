@@ -1,4 +1,5 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::io::ErrorKind;
 use actix::Recipient;
 use actix::Syn;
 use cryptde::PlainData;
@@ -6,11 +7,40 @@ use cryptde::StreamKey;
 use hopper::ExpiredCoresPackage;
 use peer_actors::BindMessage;
 
+// An exit Node sends at most this many response chunks for a stream ahead of the credit its
+// originating Node has returned, and stops reading from the server until more comes back
+pub const RESPONSE_WINDOW_CHUNKS: u32 = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum ExitFailure {
+    MissingHostname,
+    DnsResolution,
+    ConnectionRefused,
+    Timeout,
+    ConnectionFailed,
+    // the consuming Node owes the exit Node more than it will carry
+    ServiceRefused,
+    // the response outgrew the exit Node's maximum response size; what came before it, and the data
+    // that comes with it, is good
+    ResponseTruncated,
+}
+
+impl ExitFailure {
+    pub fn from_error_kind (kind: ErrorKind) -> ExitFailure {
+        match kind {
+            ErrorKind::ConnectionRefused => ExitFailure::ConnectionRefused,
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => ExitFailure::Timeout,
+            _ => ExitFailure::ConnectionFailed
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct ClientResponsePayload {
     pub stream_key: StreamKey,
     pub last_response: bool,
-    pub data: PlainData
+    pub data: PlainData,
+    pub failure: Option<ExitFailure>
 }
 
 #[derive(Clone)]
@@ -18,3 +48,16 @@ pub struct ProxyClientSubs {
     pub bind: Recipient<Syn, BindMessage>,
     pub from_hopper: Recipient<Syn, ExpiredCoresPackage>,
 }
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_failure_from_error_kind_distinguishes_refusals_and_timeouts () {
+        assert_eq! (ExitFailure::from_error_kind (ErrorKind::ConnectionRefused), ExitFailure::ConnectionRefused);
+        assert_eq! (ExitFailure::from_error_kind (ErrorKind::TimedOut), ExitFailure::Timeout);
+        assert_eq! (ExitFailure::from_error_kind (ErrorKind::WouldBlock), ExitFailure::Timeout);
+        assert_eq! (ExitFailure::from_error_kind (ErrorKind::AddrNotAvailable), ExitFailure::ConnectionFailed);
+    }
+}
@@ -6,6 +6,7 @@ use cryptde::PlainData;
 use cryptde::StreamKey;
 use dispatcher::InboundClientData;
 use hopper::ExpiredCoresPackage;
+use hopper::IncipientCoresPackage;
 use peer_actors::BindMessage;
 
 #[derive (Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
@@ -25,9 +26,18 @@ pub struct ClientRequestPayload {
     pub originator_public_key: Key
 }
 
+// Goes to the exit Node alongside ClientRequestPayloads: credit for response chunks this Node has
+// passed on to its client, so the exit Node may send that many more
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ResponseCreditPayload {
+    pub stream_key: StreamKey,
+    pub credit_chunks: u32,
+}
+
 #[derive(Clone)]
 pub struct ProxyServerSubs { // ProxyServer will handle these messages:
     pub bind: Recipient<Syn, BindMessage>,
     pub from_dispatcher: Recipient<Syn, InboundClientData>,
     pub from_hopper: Recipient<Syn, ExpiredCoresPackage>,
+    pub from_proxy_client: Recipient<Syn, IncipientCoresPackage>,
 }
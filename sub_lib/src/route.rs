@@ -4,6 +4,7 @@ use hop::Hop;
 use cryptde::Key;
 use cryptde::CryptDE;
 use cryptde::CryptData;
+use cryptde::PlainData;
 use std::iter;
 
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
@@ -17,6 +18,7 @@ impl Route {
     pub fn new(route_segments: Vec<RouteSegment>, cryptde: &CryptDE) -> Result<Route, RouteError> {
         // crashpoint - send back a RouteError
         if route_segments.is_empty () {panic! ("A route must have at least one segment")}
+        // The Node that builds a route is the one that pays for it, and signs every hop to prove it
         let mut hops: Vec<Hop> = Vec::new ();
         let mut pending_recipient: Option<Component> = None;
         for segment_index in 0..route_segments.len () {
@@ -36,7 +38,7 @@ impl Route {
                 hops.push (match pending_recipient {
                     Some (recipient) => Hop::new(key, recipient),
                     None => Hop::new(key, Component::Hopper)
-                });
+                }.consumed_by (cryptde));
                 pending_recipient = None;
                 if (hop_index + 1) == route_segment.keys.len () {
                     pending_recipient = Some (route_segment.recipient);
@@ -44,7 +46,7 @@ impl Route {
             }
         }
         // crashpoint - should not be possible, can we restructure to remove the Option?
-        hops.push (Hop::new(&Key::new(b""), pending_recipient.expect ("Route segment without recipient")));
+        hops.push (Hop::new(&Key::new(b""), pending_recipient.expect ("Route segment without recipient")).consumed_by (cryptde));
         Route::hops_to_route (hops[1..].to_vec (), &route_segments[0].keys[0], cryptde)
     }
 
@@ -65,10 +67,9 @@ impl Route {
             None => return None,
             Some (h) => h
         };
+        self.hops = self.hops.iter ().map (|hop_enc| Route::peel_layer (next_hop_private_key, cryptde, hop_enc)).collect ();
 
-        let mut garbage_can: Vec<u8> = iter::repeat (0u8).take (top_hop_len).collect ();
-        cryptde.random (&mut garbage_can[..]);
-        self.hops.push (CryptData::new (&garbage_can[..]));
+        self.hops.push (Route::garbage (top_hop_len, cryptde));
 
         return Some (next_hop);
     }
@@ -81,20 +82,46 @@ impl Route {
         }
     }
 
+    // Each Node peels one layer off every hop it relays, so a hop is wrapped in one layer for
+    // its own Node and one for each Node that handles the route before it does.
     fn hops_to_route (hops: Vec<Hop>, top_hop_key: &Key, cryptde: &CryptDE) -> Result<Route, RouteError> {
         let mut hops_enc: Vec<CryptData> = Vec::new ();
         let mut hop_key = top_hop_key;
+        let mut layer_keys: Vec<&Key> = Vec::new ();
         for hop_index in 0..hops.len () {
             let data_hop = &hops[hop_index];
+            layer_keys.push (hop_key);
             // crashpoint - should not be possible, can this be restructured to remove Option?
-            hops_enc.push (match data_hop.encode (hop_key, cryptde) {
+            let mut hop_enc = match data_hop.encode (hop_key, cryptde) {
                 Ok (crypt_data) => crypt_data,
                 Err (_) => panic! ("Couldn't encode hop")
-            });
+            };
+            for layer_key in layer_keys.iter ().rev ().skip (1) {
+                // crashpoint - should not be possible, can this be restructured to remove Option?
+                hop_enc = match cryptde.encode (layer_key, &PlainData::new (&hop_enc.data[..])) {
+                    Ok (crypt_data) => crypt_data,
+                    Err (_) => panic! ("Couldn't encode hop layer")
+                };
+            }
+            hops_enc.push (hop_enc);
             hop_key = &data_hop.public_key;
         }
         Ok (Route {hops: hops_enc})
     }
+
+    fn peel_layer (key: &Key, cryptde: &CryptDE, hop_enc: &CryptData) -> CryptData {
+        match cryptde.decode (key, hop_enc) {
+            Ok (plain_data) => CryptData::new (&plain_data.data[..]),
+            // Garbage from earlier shifts has no layer for us; replace it so it can't be tracked
+            Err (_) => Route::garbage (hop_enc.data.len (), cryptde)
+        }
+    }
+
+    fn garbage (length: usize, cryptde: &CryptDE) -> CryptData {
+        let mut garbage_can: Vec<u8> = iter::repeat (0u8).take (length).collect ();
+        cryptde.random (&mut garbage_can[..]);
+        CryptData::new (&garbage_can[..])
+    }
 }
 
 #[derive (Debug)]
@@ -119,6 +146,15 @@ mod tests {
     use super::*;
     use cryptde_null::CryptDENull;
 
+    // layer_keys run from the hop's own Node back toward the originating Node
+    fn layered_hop (hop: Hop, layer_keys: Vec<&Key>, cryptde: &CryptDE) -> CryptData {
+        let mut hop_enc = hop.encode (layer_keys[0], cryptde).unwrap ();
+        for key in layer_keys.iter ().skip (1) {
+            hop_enc = cryptde.encode (key, &PlainData::new (&hop_enc.data[..])).unwrap ();
+        }
+        hop_enc
+    }
+
     #[test]
     fn new_can_make_long_multistop_route () {
         let a_key = Key::new (&[65, 65, 65]);
@@ -136,13 +172,13 @@ mod tests {
         ), &cryptde).unwrap ();
 
         assert_eq! (subject.hops, vec! (
-            Hop::new(&b_key, Component::Hopper).encode (&a_key, &cryptde).unwrap (),
-            Hop::new(&c_key, Component::Hopper).encode (&b_key, &cryptde).unwrap (),
-            Hop::new(&d_key, Component::Hopper).encode (&c_key, &cryptde).unwrap (),
-            Hop::new(&e_key, Component::ProxyClient).encode (&d_key, &cryptde).unwrap (),
-            Hop::new(&f_key, Component::Hopper).encode (&e_key, &cryptde).unwrap (),
-            Hop::new(&a_key, Component::Hopper).encode (&f_key, &cryptde).unwrap (),
-            Hop::new(&Key::new(b""), Component::ProxyServer).encode (&a_key, &cryptde).unwrap ()
+            layered_hop (Hop::new(&b_key, Component::Hopper).consumed_by (&cryptde), vec! (&a_key), &cryptde),
+            layered_hop (Hop::new(&c_key, Component::Hopper).consumed_by (&cryptde), vec! (&b_key, &a_key), &cryptde),
+            layered_hop (Hop::new(&d_key, Component::Hopper).consumed_by (&cryptde), vec! (&c_key, &b_key, &a_key), &cryptde),
+            layered_hop (Hop::new(&e_key, Component::ProxyClient).consumed_by (&cryptde), vec! (&d_key, &c_key, &b_key, &a_key), &cryptde),
+            layered_hop (Hop::new(&f_key, Component::Hopper).consumed_by (&cryptde), vec! (&e_key, &d_key, &c_key, &b_key, &a_key), &cryptde),
+            layered_hop (Hop::new(&a_key, Component::Hopper).consumed_by (&cryptde), vec! (&f_key, &e_key, &d_key, &c_key, &b_key, &a_key), &cryptde),
+            layered_hop (Hop::new(&Key::new(b""), Component::ProxyServer).consumed_by (&cryptde), vec! (&a_key, &f_key, &e_key, &d_key, &c_key, &b_key, &a_key), &cryptde)
         ));
     }
 
@@ -158,8 +194,8 @@ mod tests {
         ), &cryptde).unwrap ();
 
         assert_eq! (subject.hops, vec! (
-            Hop::new(&b_key, Component::Hopper).encode (&a_key, &cryptde).unwrap (),
-            Hop::new(&Key::new(b""), Component::Neighborhood).encode (&b_key, &cryptde).unwrap ()
+            layered_hop (Hop::new(&b_key, Component::Hopper).consumed_by (&cryptde), vec! (&a_key), &cryptde),
+            layered_hop (Hop::new(&Key::new(b""), Component::Neighborhood).consumed_by (&cryptde), vec! (&b_key, &a_key), &cryptde)
         ));
     }
 
@@ -174,18 +210,18 @@ mod tests {
             RouteSegment::new (vec! (&key12, &key34, &key56), Component::Neighborhood)
         ), &cryptde).unwrap ();
         assert_eq! (subject.hops, vec! (
-            Hop::new(&key34, Component::Hopper).encode (&key12, &cryptde).unwrap (),
-            Hop::new(&key56, Component::Hopper).encode (&key34, &cryptde).unwrap (),
-            Hop::new(&Key::new(b""), Component::Neighborhood).encode (&key56, &cryptde).unwrap ()
+            layered_hop (Hop::new(&key34, Component::Hopper).consumed_by (&cryptde), vec! (&key12), &cryptde),
+            layered_hop (Hop::new(&key56, Component::Hopper).consumed_by (&cryptde), vec! (&key34, &key12), &cryptde),
+            layered_hop (Hop::new(&Key::new(b""), Component::Neighborhood).consumed_by (&cryptde), vec! (&key56, &key34, &key12), &cryptde)
         ));
 
         let next_hop = subject.next_hop ( &CryptDENull::other_key (&key12), &cryptde).unwrap ();
 
-        assert_eq! (next_hop, Hop::new(&key34, Component::Hopper));
+        assert_eq! (next_hop, Hop::new(&key34, Component::Hopper).consumed_by (&cryptde));
         assert_eq! (subject.hops, vec! (
-            Hop::new(&key34, Component::Hopper).encode (&key12, &cryptde).unwrap (),
-            Hop::new(&key56, Component::Hopper).encode (&key34, &cryptde).unwrap (),
-            Hop::new(&Key::new(b""), Component::Neighborhood).encode (&key56, &cryptde).unwrap ()
+            layered_hop (Hop::new(&key34, Component::Hopper).consumed_by (&cryptde), vec! (&key12), &cryptde),
+            layered_hop (Hop::new(&key56, Component::Hopper).consumed_by (&cryptde), vec! (&key34, &key12), &cryptde),
+            layered_hop (Hop::new(&Key::new(b""), Component::Neighborhood).consumed_by (&cryptde), vec! (&key56, &key34, &key12), &cryptde)
         ));
     }
 
@@ -200,20 +236,20 @@ mod tests {
             RouteSegment::new (vec! (&key12, &key34, &key56), Component::Neighborhood)
         ), &cryptde).unwrap ();
         assert_eq! (subject.hops, vec! (
-            Hop::new(&key34, Component::Hopper).encode (&key12, &cryptde).unwrap (),
-            Hop::new(&key56, Component::Hopper).encode (&key34, &cryptde).unwrap (),
-            Hop::new(&Key::new(b""), Component::Neighborhood).encode (&key56, &cryptde).unwrap ()
+            layered_hop (Hop::new(&key34, Component::Hopper).consumed_by (&cryptde), vec! (&key12), &cryptde),
+            layered_hop (Hop::new(&key56, Component::Hopper).consumed_by (&cryptde), vec! (&key34, &key12), &cryptde),
+            layered_hop (Hop::new(&Key::new(b""), Component::Neighborhood).consumed_by (&cryptde), vec! (&key56, &key34, &key12), &cryptde)
         ));
         let top_hop_len = subject.hops.first ().unwrap ().data.len ();
 
         let next_hop = subject.shift ( &CryptDENull::other_key (&key12), &cryptde).unwrap ();
 
-        assert_eq! (next_hop, Hop::new(&key34, Component::Hopper));
+        assert_eq! (next_hop, Hop::new(&key34, Component::Hopper).consumed_by (&cryptde));
         let mut garbage_can: Vec<u8> = iter::repeat (0u8).take (top_hop_len).collect ();
         cryptde.random (&mut garbage_can[..]);
         assert_eq! (subject.hops, vec! (
-            Hop::new(&key56, Component::Hopper).encode (&key34, &cryptde).unwrap (),
-            Hop::new(&Key::new(b""), Component::Neighborhood).encode (&key56, &cryptde).unwrap (),
+            layered_hop (Hop::new(&key56, Component::Hopper).consumed_by (&cryptde), vec! (&key34), &cryptde),
+            layered_hop (Hop::new(&Key::new(b""), Component::Neighborhood).consumed_by (&cryptde), vec! (&key56, &key34), &cryptde),
             CryptData::new (&garbage_can[..])
         ))
     }
@@ -239,4 +275,39 @@ mod tests {
 
         assert_eq! (result, None);
     }
+
+    #[test]
+    fn each_node_can_decode_only_its_own_hop_after_peeling () {
+        let cryptde = CryptDENull::new ();
+        let key12 = Key::new (&[1, 2]);
+        let key34 = Key::new (&[3, 4]);
+        let key56 = Key::new (&[5, 6]);
+        let mut subject = Route::new (vec! (
+            RouteSegment::new (vec! (&key12, &key34, &key56), Component::Neighborhood)
+        ), &cryptde).unwrap ();
+
+        assert_eq! (subject.next_hop (&CryptDENull::other_key (&key34), &cryptde), None);
+        subject.shift (&CryptDENull::other_key (&key12), &cryptde).unwrap ();
+        assert_eq! (subject.next_hop (&CryptDENull::other_key (&key56), &cryptde), None);
+        let second_hop = subject.shift (&CryptDENull::other_key (&key34), &cryptde).unwrap ();
+        let third_hop = subject.shift (&CryptDENull::other_key (&key56), &cryptde).unwrap ();
+
+        assert_eq! (second_hop, Hop::new (&key56, Component::Hopper).consumed_by (&cryptde));
+        assert_eq! (third_hop, Hop::new (&Key::new (b""), Component::Neighborhood).consumed_by (&cryptde));
+    }
+
+    #[test]
+    fn shift_replaces_unpeelable_garbage_with_fresh_garbage_of_the_same_size () {
+        let cryptde = CryptDENull::new ();
+        let key12 = Key::new (&[1, 2]);
+        let key34 = Key::new (&[3, 4]);
+        let mut subject = Route::new (vec! (
+            RouteSegment::new (vec! (&key12, &key34), Component::Neighborhood)
+        ), &cryptde).unwrap ();
+        subject.hops.push (CryptData::new (b"unpeelable"));
+
+        subject.shift (&CryptDENull::other_key (&key12), &cryptde).unwrap ();
+
+        assert_eq! (subject.hops[1], CryptData::new (b"4444444444"));
+    }
 }
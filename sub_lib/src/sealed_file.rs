@@ -0,0 +1,104 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::fs;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use serde_cbor;
+use private_file::create_private_file;
+use sodiumoxide;
+use sodiumoxide::crypto::pwhash::scryptsalsa208sha256;
+use sodiumoxide::crypto::secretbox;
+
+// The secret is sealed with XSalsa20-Poly1305 under a key stretched from the passphrase with scrypt.
+// The field names are what identity files have always held, so old ones still load.
+#[derive (Serialize, Deserialize)]
+struct Sealed {
+    salt: Vec<u8>,
+    nonce: Vec<u8>,
+    sealed_private_key: Vec<u8>,
+}
+
+// Keeps a secret on disk, encrypted with a passphrase. Complaints name what the secret is, so the
+// operator can tell an identity file from a wallet file.
+pub struct SealedFile {
+    path: PathBuf,
+    passphrase: String,
+    contents_name: &'static str,
+}
+
+impl SealedFile {
+    pub fn new (path: &Path, passphrase: &str, contents_name: &'static str) -> SealedFile {
+        sodiumoxide::init ().expect ("Couldn't initialize libsodium");
+        SealedFile {
+            path: PathBuf::from (path),
+            passphrase: String::from (passphrase),
+            contents_name,
+        }
+    }
+
+    pub fn path (&self) -> &Path {
+        &self.path
+    }
+
+    // Ok (None) means nothing has been saved yet
+    pub fn load (&self) -> Result<Option<Vec<u8>>, String> {
+        let mut file = match File::open (&self.path) {
+            Ok (file) => file,
+            Err (ref e) if e.kind () == ErrorKind::NotFound => return Ok (None),
+            Err (e) => return Err (format! ("Couldn't open {:?}: {}", self.path, e))
+        };
+        let mut contents = vec! ();
+        if let Err (e) = file.read_to_end (&mut contents) {
+            return Err (format! ("Couldn't read {:?}: {}", self.path, e))
+        }
+        let sealed = match serde_cbor::de::from_slice::<Sealed> (&contents[..]) {
+            Ok (sealed) => sealed,
+            Err (e) => return Err (format! ("{:?} is not an {} file: {:?}", self.path, self.contents_name, e))
+        };
+        let (salt, nonce) = match (scryptsalsa208sha256::Salt::from_slice (&sealed.salt[..]), secretbox::Nonce::from_slice (&sealed.nonce[..])) {
+            (Some (salt), Some (nonce)) => (salt, nonce),
+            _ => return Err (format! ("{:?} is not an {} file", self.path, self.contents_name))
+        };
+        let key = self.stretch_passphrase (&salt)?;
+        match secretbox::open (&sealed.sealed_private_key[..], &nonce, &key) {
+            Ok (secret) => Ok (Some (secret)),
+            Err (()) => Err (format! ("Couldn't unlock {:?}: wrong passphrase", self.path))
+        }
+    }
+
+    // Writes beside the old file and then replaces it, so a crash mid-save leaves the last good copy.
+    // Only the Node's user can read either one: the passphrase is all that stands between anyone who
+    // can and the secret.
+    pub fn save (&self, secret: &[u8]) -> Result<(), String> {
+        let salt = scryptsalsa208sha256::gen_salt ();
+        let nonce = secretbox::gen_nonce ();
+        let key = self.stretch_passphrase (&salt)?;
+        let sealed = Sealed {
+            salt: salt.0.to_vec (),
+            nonce: nonce.0.to_vec (),
+            sealed_private_key: secretbox::seal (secret, &nonce, &key),
+        };
+        let contents = match serde_cbor::ser::to_vec (&sealed) {
+            Ok (contents) => contents,
+            Err (e) => return Err (format! ("Couldn't serialize {}: {:?}", self.contents_name, e))
+        };
+        let temporary_path = self.path.with_extension ("tmp");
+        let written = create_private_file (&temporary_path)
+            .and_then (|mut file| file.write_all (&contents[..]).and_then (|_| file.sync_all ()))
+            .and_then (|_| fs::rename (&temporary_path, &self.path));
+        match written {
+            Ok (_) => Ok (()),
+            Err (e) => Err (format! ("Couldn't save {:?}: {}", self.path, e))
+        }
+    }
+
+    fn stretch_passphrase (&self, salt: &scryptsalsa208sha256::Salt) -> Result<secretbox::Key, String> {
+        let mut key = secretbox::Key ([0; secretbox::KEYBYTES]);
+        let derived = scryptsalsa208sha256::derive_key (&mut key.0, self.passphrase.as_bytes (), salt,
+            scryptsalsa208sha256::OPSLIMIT_INTERACTIVE, scryptsalsa208sha256::MEMLIMIT_INTERACTIVE).is_ok ();
+        if derived {Ok (key)} else {Err (format! ("Couldn't derive a key from the {} passphrase", self.contents_name))}
+    }
+}
@@ -2,8 +2,25 @@
 use std::marker::Send;
 use main_tools::StdStreams;
 
+// Applies whichever of a server's settings can change while it runs. It's taken before
+// serve_without_root carries the server off to its own thread, and used from another thread after that.
+pub trait Reloader: Send {
+    fn reload (&self, args: &Vec<String>) -> Result<(), String>;
+}
+
+// Takes a server down in an orderly way when the Node is told to stop: nothing new is let in, what's
+// on its way out is sent, and anything worth keeping is saved. Like a Reloader, it's taken before
+// serve_without_root and used from another thread. Err says what couldn't be finished.
+pub trait Stopper: Send {
+    fn stop (&self) -> Result<(), String>;
+}
+
 pub trait SocketServer: Send {
     fn name (&self) -> String;
     fn initialize_as_root (&mut self, args: &Vec<String>, streams: &mut StdStreams);
     fn serve_without_root (&mut self);
+    // None if nothing about the server can change without a restart
+    fn reloader (&self) -> Option<Box<Reloader>> {None}
+    // None if the server has nothing to finish before the Node exits
+    fn stopper (&self) -> Option<Box<Stopper>> {None}
 }
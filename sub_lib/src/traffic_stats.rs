@@ -0,0 +1,88 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::atomic::ATOMIC_USIZE_INIT;
+
+// Traffic through the Node, counted where it happens and read by the UI Gateway. Like the log level,
+// these are global, so that counting a byte never costs a message and reading them never bothers
+// the actors that count them.
+
+// Totals since the Node started
+static BYTES_IN: AtomicUsize = ATOMIC_USIZE_INIT;
+static BYTES_OUT: AtomicUsize = ATOMIC_USIZE_INIT;
+static BYTES_RELAYED: AtomicUsize = ATOMIC_USIZE_INIT;
+static BYTES_ORIGINATED: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// How things stand now, each set by the one actor that knows
+static ACTIVE_STREAMS: AtomicUsize = ATOMIC_USIZE_INIT;
+static ROUTES_IN_USE: AtomicUsize = ATOMIC_USIZE_INIT;
+
+#[derive (Clone, Copy, Debug, PartialEq, Default)]
+pub struct TrafficTotals {
+    // read from and written to the Node's sockets, to browsers and neighbors alike
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    // in CORES packages passed along for other Nodes
+    pub bytes_relayed: u64,
+    // in CORES packages this Node put together itself: its own requests, and its answers as an exit
+    pub bytes_originated: u64,
+    pub active_streams: u64,
+    pub routes_in_use: u64,
+}
+
+pub fn count_bytes_in (bytes: usize) {
+    BYTES_IN.fetch_add (bytes, Ordering::Relaxed);
+}
+
+pub fn count_bytes_out (bytes: usize) {
+    BYTES_OUT.fetch_add (bytes, Ordering::Relaxed);
+}
+
+pub fn count_bytes_relayed (bytes: usize) {
+    BYTES_RELAYED.fetch_add (bytes, Ordering::Relaxed);
+}
+
+pub fn count_bytes_originated (bytes: usize) {
+    BYTES_ORIGINATED.fetch_add (bytes, Ordering::Relaxed);
+}
+
+pub fn set_active_streams (streams: usize) {
+    ACTIVE_STREAMS.store (streams, Ordering::Relaxed);
+}
+
+pub fn set_routes_in_use (routes: usize) {
+    ROUTES_IN_USE.store (routes, Ordering::Relaxed);
+}
+
+pub fn traffic_totals () -> TrafficTotals {
+    TrafficTotals {
+        bytes_in: BYTES_IN.load (Ordering::Relaxed) as u64,
+        bytes_out: BYTES_OUT.load (Ordering::Relaxed) as u64,
+        bytes_relayed: BYTES_RELAYED.load (Ordering::Relaxed) as u64,
+        bytes_originated: BYTES_ORIGINATED.load (Ordering::Relaxed) as u64,
+        active_streams: ACTIVE_STREAMS.load (Ordering::Relaxed) as u64,
+        routes_in_use: ROUTES_IN_USE.load (Ordering::Relaxed) as u64,
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    // Other tests count traffic at the same time, so only growth can be relied on
+    #[test]
+    fn counted_bytes_show_up_in_the_totals () {
+        let before = traffic_totals ();
+
+        count_bytes_in (100);
+        count_bytes_out (200);
+        count_bytes_relayed (300);
+        count_bytes_originated (400);
+
+        let after = traffic_totals ();
+        assert_eq! (after.bytes_in >= before.bytes_in + 100, true);
+        assert_eq! (after.bytes_out >= before.bytes_out + 200, true);
+        assert_eq! (after.bytes_relayed >= before.bytes_relayed + 300, true);
+        assert_eq! (after.bytes_originated >= before.bytes_originated + 400, true);
+    }
+}
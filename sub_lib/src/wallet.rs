@@ -0,0 +1,45 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::fmt;
+
+// An Ethereum account, known by its address: 0x and then 40 lowercase hex digits
+#[derive (Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Wallet {
+    pub address: String,
+}
+
+impl fmt::Display for Wallet {
+    fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write! (f, "{}", self.address)
+    }
+}
+
+impl Wallet {
+    // Accepts either case and leaves off the 0x if you like; mixed-case checksums aren't checked
+    pub fn new (address: &str) -> Result<Wallet, String> {
+        let digits = if address.starts_with ("0x") || address.starts_with ("0X") {&address[2..]} else {address};
+        if (digits.len () != 40) || !digits.chars ().all (|c| c.is_digit (16)) {
+            return Err (format! ("Not an Ethereum address: '{}'", address))
+        }
+        Ok (Wallet {address: format! ("0x{}", digits.to_lowercase ())})
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_are_normalized () {
+        let expected = Wallet {address: String::from ("0x12480e24eb5bec1a9d4369cab6a80cad3c0a377a")};
+
+        assert_eq! (Wallet::new ("0x12480E24eb5bec1a9D4369CaB6a80caD3c0A377A"), Ok (expected.clone ()));
+        assert_eq! (Wallet::new ("12480e24eb5bec1a9d4369cab6a80cad3c0a377a"), Ok (expected.clone ()));
+        assert_eq! (format! ("{}", expected), String::from ("0x12480e24eb5bec1a9d4369cab6a80cad3c0a377a"));
+    }
+
+    #[test]
+    fn malformed_addresses_are_refused () {
+        assert_eq! (Wallet::new ("0x12480e24eb5bec1a9d4369cab6a80cad3c0a377"), Err (String::from ("Not an Ethereum address: '0x12480e24eb5bec1a9d4369cab6a80cad3c0a377'")));
+        assert_eq! (Wallet::new ("0x12480e24eb5bec1a9d4369cab6a80cad3c0a377g"), Err (String::from ("Not an Ethereum address: '0x12480e24eb5bec1a9d4369cab6a80cad3c0a377g'")));
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use std::path::Path;
+use sealed_file::SealedFile;
+
+pub const WALLET_FILENAME: &str = "consuming_wallet.key";
+
+// Keeps the consuming wallet's hex private key in the data directory, encrypted with a password, so
+// it only has to be given once and the Node can't pay from it until unlocked
+pub struct WalletStore {
+    file: SealedFile,
+}
+
+impl WalletStore {
+    pub fn in_data_directory (data_directory: &Path, password: &str) -> WalletStore {
+        WalletStore {
+            file: SealedFile::new (&data_directory.join (WALLET_FILENAME), password, "Ethereum wallet"),
+        }
+    }
+
+    // Whether there's a wallet to unlock, before anybody is asked for the password
+    pub fn exists_in (data_directory: &Path) -> bool {
+        data_directory.join (WALLET_FILENAME).exists ()
+    }
+
+    pub fn path (&self) -> &Path {
+        self.file.path ()
+    }
+
+    // Ok (None) means no wallet has been saved yet
+    pub fn load (&self) -> Result<Option<String>, String> {
+        match self.file.load ()? {
+            Some (private_key) => String::from_utf8 (private_key)
+                .map (Some)
+                .map_err (|_| format! ("{:?} is not an Ethereum wallet file", self.path ())),
+            None => Ok (None)
+        }
+    }
+
+    pub fn save (&self, private_key: &str) -> Result<(), String> {
+        self.file.save (private_key.as_bytes ())
+    }
+}
+
+#[cfg (test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs;
+    use std::fs::File;
+    use std::io::Read;
+    #[cfg (unix)]
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    const PRIVATE_KEY: &str = "4646464646464646464646464646464646464646464646464646464646464646";
+
+    fn make_data_directory (name: &str) -> PathBuf {
+        let data_directory = temp_dir ().join ("wallet_store").join (name);
+        fs::create_dir_all (&data_directory).unwrap ();
+        let _ = fs::remove_file (data_directory.join (WALLET_FILENAME));
+        data_directory
+    }
+
+    #[test]
+    fn a_saved_wallet_is_sealed_and_unlocks_only_with_its_password () {
+        let data_directory = make_data_directory ("a_saved_wallet_is_sealed_and_unlocks_only_with_its_password");
+        assert_eq! (WalletStore::exists_in (&data_directory), false);
+        assert_eq! (WalletStore::in_data_directory (&data_directory, "password").load (), Ok (None));
+
+        WalletStore::in_data_directory (&data_directory, "password").save (PRIVATE_KEY).unwrap ();
+
+        assert_eq! (WalletStore::exists_in (&data_directory), true);
+        assert_eq! (WalletStore::in_data_directory (&data_directory, "password").load (), Ok (Some (String::from (PRIVATE_KEY))));
+        assert_eq! (WalletStore::in_data_directory (&data_directory, "guess").load ().err ().unwrap ().contains ("wrong passphrase"), true);
+        let mut contents = vec! ();
+        File::open (data_directory.join (WALLET_FILENAME)).unwrap ().read_to_end (&mut contents).unwrap ();
+        assert_eq! (contents.windows (16).any (|window| window == &PRIVATE_KEY.as_bytes ()[..16]), false);
+    }
+
+    #[cfg (unix)]
+    #[test]
+    fn only_the_owner_can_read_a_saved_wallet () {
+        let data_directory = make_data_directory ("only_the_owner_can_read_a_saved_wallet");
+        File::create (data_directory.join (WALLET_FILENAME)).unwrap ();
+        fs::set_permissions (data_directory.join (WALLET_FILENAME), fs::Permissions::from_mode (0o644)).unwrap ();
+
+        WalletStore::in_data_directory (&data_directory, "password").save (PRIVATE_KEY).unwrap ();
+
+        assert_eq! (fs::metadata (data_directory.join (WALLET_FILENAME)).unwrap ().permissions ().mode () & 0o777, 0o600);
+    }
+}
@@ -27,6 +27,22 @@ use log::Log;
 use log::Metadata;
 use log::Record;
 use log::set_logger;
+use sub_lib::accountant::AccountantSubs;
+use sub_lib::accountant::Financials;
+use sub_lib::accountant::GetFinancialsMsg;
+use sub_lib::accountant::GetNeighborStatsMsg;
+use sub_lib::accountant::GetNodeStatsMsg;
+use sub_lib::accountant::NodeStats;
+use sub_lib::accountant::ReportExitServiceMessage;
+use sub_lib::accountant::ReportConsumingWalletMessage;
+use sub_lib::accountant::ReportEarningWalletMessage;
+use sub_lib::accountant::ReportRatesMessage;
+use sub_lib::accountant::ReportFiatPriceMessage;
+use sub_lib::accountant::ReportPaymentReceivedMessage;
+use sub_lib::accountant::ReportChannelMessage;
+use sub_lib::accountant::ReportRoutingServiceMessage;
+use sub_lib::accountant::ReportTransactionMessage;
+use sub_lib::accountant::ServiceStandingMessage;
 use sub_lib::cryptde::CryptDE;
 use sub_lib::cryptde::Key;
 use sub_lib::cryptde_null::CryptDENull;
@@ -47,6 +63,20 @@ use sub_lib::route::Route;
 use sub_lib::route::RouteSegment;
 use sub_lib::stream_handler_pool::TransmitDataMsg;
 use sub_lib::neighborhood::NodeQueryMessage;
+use sub_lib::neighborhood::ExpiredNeighborhoodPackage;
+use sub_lib::neighborhood::NeighborMisbehaviorMessage;
+use sub_lib::neighborhood::BanNodeMsg;
+use sub_lib::neighborhood::UnbanNodeMsg;
+use sub_lib::neighborhood::NodeBannedMsg;
+use sub_lib::neighborhood::NodeUnbannedMsg;
+use sub_lib::neighborhood::NewPublicIpMsg;
+use sub_lib::neighborhood::NewPublicKeyMsg;
+use sub_lib::neighborhood::RetiredPublicKeyMsg;
+use sub_lib::neighborhood::SetExitLocationMsg;
+use sub_lib::neighborhood::GetTopologyMsg;
+use sub_lib::neighborhood::NeighborhoodTopology;
+use sub_lib::neighborhood::RouteQueryMessage;
+use sub_lib::neighborhood::RouteQueryResponse;
 
 lazy_static! {
     static ref CRYPT_DE_NULL: CryptDENull = CryptDENull::new ();
@@ -417,6 +447,7 @@ pub fn make_proxy_server_subs_from(addr: &Addr<Syn, Recorder>) -> ProxyServerSub
         bind: addr.clone ().recipient::<BindMessage>(),
         from_dispatcher: addr.clone ().recipient::<InboundClientData>(),
         from_hopper: addr.clone ().recipient::<ExpiredCoresPackage>(),
+        from_proxy_client: addr.clone ().recipient::<IncipientCoresPackage>(),
     }
 }
 
@@ -426,6 +457,8 @@ pub fn make_dispatcher_subs_from(addr: &Addr<Syn, Recorder>) -> DispatcherSubs {
         bind: addr.clone ().recipient::<BindMessage>(),
         from_proxy_server: addr.clone ().recipient::<TransmitDataMsg>(),
         from_hopper: addr.clone ().recipient::<HopperTemporaryTransmitDataMsg>(),
+        node_banned: addr.clone ().recipient::<NodeBannedMsg>(),
+        node_unbanned: addr.clone ().recipient::<NodeUnbannedMsg>(),
     }
 }
 
@@ -434,6 +467,9 @@ pub fn make_hopper_subs_from(addr: &Addr<Syn, Recorder>) -> HopperSubs {
         bind: addr.clone ().recipient::<BindMessage>(),
         from_hopper_client: addr.clone ().recipient::<IncipientCoresPackage>(),
         from_dispatcher: addr.clone ().recipient::<InboundClientData>(),
+        node_banned: addr.clone ().recipient::<NodeBannedMsg>(),
+        node_unbanned: addr.clone ().recipient::<NodeUnbannedMsg>(),
+        service_standing: addr.clone ().recipient::<ServiceStandingMessage>(),
     }
 }
 
@@ -447,11 +483,40 @@ pub fn make_proxy_client_subs_from(addr: &Addr<Syn, Recorder>) -> ProxyClientSub
 pub fn make_neighborhood_subs_from(addr: &Addr<Syn, Recorder>) -> NeighborhoodSubs {
     NeighborhoodSubs {
         bind: addr.clone ().recipient::<BindMessage>(),
+        route_query: addr.clone ().recipient::<RouteQueryMessage>(),
+        report_misbehavior: addr.clone ().recipient::<NeighborMisbehaviorMessage>(),
+        from_hopper: addr.clone ().recipient::<ExpiredNeighborhoodPackage>(),
+        ban_node: addr.clone ().recipient::<BanNodeMsg>(),
+        unban_node: addr.clone ().recipient::<UnbanNodeMsg>(),
+        new_public_ip: addr.clone ().recipient::<NewPublicIpMsg>(),
+        new_public_key: addr.clone ().recipient::<NewPublicKeyMsg>(),
+        retired_public_key: addr.clone ().recipient::<RetiredPublicKeyMsg>(),
+        set_exit_location: addr.clone ().recipient::<SetExitLocationMsg>(),
+        get_topology: addr.clone ().recipient::<GetTopologyMsg>(),
+    }
+}
+
+pub fn make_accountant_subs_from(addr: &Addr<Syn, Recorder>) -> AccountantSubs {
+    AccountantSubs {
+        bind: addr.clone ().recipient::<BindMessage>(),
+        report_routing_service: addr.clone ().recipient::<ReportRoutingServiceMessage>(),
+        report_exit_service: addr.clone ().recipient::<ReportExitServiceMessage>(),
+        report_payment_received: addr.clone ().recipient::<ReportPaymentReceivedMessage>(),
+        report_transaction: addr.clone ().recipient::<ReportTransactionMessage>(),
+        report_channel: addr.clone ().recipient::<ReportChannelMessage>(),
+        report_earning_wallet: addr.clone ().recipient::<ReportEarningWalletMessage>(),
+        report_consuming_wallet: addr.clone ().recipient::<ReportConsumingWalletMessage>(),
+        report_rates: addr.clone ().recipient::<ReportRatesMessage>(),
+        report_fiat_price: addr.clone ().recipient::<ReportFiatPriceMessage>(),
+        from_hopper: addr.clone ().recipient::<ExpiredCoresPackage>(),
+        get_neighbor_stats: addr.clone ().recipient::<GetNeighborStatsMsg>(),
+        get_node_stats: addr.clone ().recipient::<GetNodeStatsMsg>(),
+        get_financials: addr.clone ().recipient::<GetFinancialsMsg>(),
     }
 }
 
 // This must be called after System.new and before System.run
-pub fn make_peer_actors_from(proxy_server: Option<Recorder>, dispatcher: Option<Recorder>, hopper: Option<Recorder>, proxy_client: Option<Recorder>, neighborhood: Option<Recorder>) -> PeerActors {
+pub fn make_peer_actors_from(proxy_server: Option<Recorder>, dispatcher: Option<Recorder>, hopper: Option<Recorder>, proxy_client: Option<Recorder>, neighborhood: Option<Recorder>, accountant: Option<Recorder>) -> PeerActors {
     let proxy_server = match proxy_server {
         Some(proxy_server) => proxy_server,
         None => Recorder::new()
@@ -477,27 +542,34 @@ pub fn make_peer_actors_from(proxy_server: Option<Recorder>, dispatcher: Option<
         None => Recorder::new()
     };
 
-    make_peer_actors_from_recorders(proxy_server, dispatcher, hopper, proxy_client, neighborhood)
+    let accountant = match accountant {
+        Some(accountant) => accountant,
+        None => Recorder::new()
+    };
+
+    make_peer_actors_from_recorders(proxy_server, dispatcher, hopper, proxy_client, neighborhood, accountant)
 }
 
 // This must be called after System.new and before System.run
 pub fn make_peer_actors() -> PeerActors {
-    make_peer_actors_from_recorders(Recorder::new(), Recorder::new(), Recorder::new(), Recorder::new(), Recorder::new ())
+    make_peer_actors_from_recorders(Recorder::new(), Recorder::new(), Recorder::new(), Recorder::new(), Recorder::new (), Recorder::new ())
 }
 
-fn make_peer_actors_from_recorders(proxy_server: Recorder, dispatcher: Recorder, hopper: Recorder, proxy_client: Recorder, neighborhood: Recorder) -> PeerActors {
+fn make_peer_actors_from_recorders(proxy_server: Recorder, dispatcher: Recorder, hopper: Recorder, proxy_client: Recorder, neighborhood: Recorder, accountant: Recorder) -> PeerActors {
     let proxy_server_addr = proxy_server.start();
     let dispatcher_addr = dispatcher.start();
     let hopper_addr = hopper.start();
     let proxy_client_addr = proxy_client.start();
     let neighborhood_addr = neighborhood.start ();
+    let accountant_addr = accountant.start ();
 
     PeerActors {
         proxy_server: make_proxy_server_subs_from(&proxy_server_addr),
         dispatcher: make_dispatcher_subs_from(&dispatcher_addr),
         hopper: make_hopper_subs_from(&hopper_addr),
         proxy_client: make_proxy_client_subs_from(&proxy_client_addr),
-        neighborhood: make_neighborhood_subs_from(&neighborhood_addr)
+        neighborhood: make_neighborhood_subs_from(&neighborhood_addr),
+        accountant: make_accountant_subs_from(&accountant_addr),
     }
 }
 
@@ -511,6 +583,7 @@ pub fn make_meaningless_route () -> Route {
 
 pub struct Recorder {
     recording: Arc<Mutex<Recording>>,
+    route_query_responses: Vec<Option<RouteQueryResponse>>,
 }
 
 pub struct Recording {
@@ -583,13 +656,229 @@ impl Handler<NodeQueryMessage> for Recorder {
     }
 }
 
+impl Handler<RouteQueryMessage> for Recorder {
+    type Result = MessageResult<RouteQueryMessage>;
+
+    fn handle(&mut self, msg: RouteQueryMessage, _ctx: &mut Self::Context) -> <Self as Handler<RouteQueryMessage>>::Result {
+        self.record (msg);
+        if self.route_query_responses.is_empty () {
+            MessageResult(None)
+        }
+        else {
+            MessageResult(self.route_query_responses.remove (0))
+        }
+    }
+}
+
+impl Handler<GetNeighborStatsMsg> for Recorder {
+    type Result = MessageResult<GetNeighborStatsMsg>;
+
+    fn handle(&mut self, msg: GetNeighborStatsMsg, _ctx: &mut Self::Context) -> <Self as Handler<GetNeighborStatsMsg>>::Result {
+        self.record (msg);
+        MessageResult(Ok (vec! ()))
+    }
+}
+
+impl Handler<GetNodeStatsMsg> for Recorder {
+    type Result = MessageResult<GetNodeStatsMsg>;
+
+    fn handle(&mut self, msg: GetNodeStatsMsg, _ctx: &mut Self::Context) -> <Self as Handler<GetNodeStatsMsg>>::Result {
+        self.record (msg);
+        MessageResult(NodeStats::default ())
+    }
+}
+
+impl Handler<GetFinancialsMsg> for Recorder {
+    type Result = MessageResult<GetFinancialsMsg>;
+
+    fn handle(&mut self, msg: GetFinancialsMsg, _ctx: &mut Self::Context) -> <Self as Handler<GetFinancialsMsg>>::Result {
+        self.record (msg);
+        MessageResult(Ok (Financials::default ()))
+    }
+}
+
+impl Handler<GetTopologyMsg> for Recorder {
+    type Result = MessageResult<GetTopologyMsg>;
+
+    fn handle(&mut self, msg: GetTopologyMsg, _ctx: &mut Self::Context) -> <Self as Handler<GetTopologyMsg>>::Result {
+        self.record (msg);
+        MessageResult(NeighborhoodTopology::default ())
+    }
+}
+
+impl Handler<NeighborMisbehaviorMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: NeighborMisbehaviorMessage, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<ExpiredNeighborhoodPackage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: ExpiredNeighborhoodPackage, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<BanNodeMsg> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: BanNodeMsg, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<UnbanNodeMsg> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: UnbanNodeMsg, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<NodeBannedMsg> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: NodeBannedMsg, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<NodeUnbannedMsg> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: NodeUnbannedMsg, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<ServiceStandingMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: ServiceStandingMessage, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<NewPublicIpMsg> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: NewPublicIpMsg, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<NewPublicKeyMsg> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: NewPublicKeyMsg, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<ReportRoutingServiceMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportRoutingServiceMessage, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<ReportExitServiceMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportExitServiceMessage, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<ReportPaymentReceivedMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportPaymentReceivedMessage, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<ReportTransactionMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportTransactionMessage, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<ReportChannelMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportChannelMessage, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<ReportEarningWalletMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportEarningWalletMessage, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<ReportConsumingWalletMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportConsumingWalletMessage, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<ReportRatesMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportRatesMessage, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<ReportFiatPriceMessage> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReportFiatPriceMessage, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<SetExitLocationMsg> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetExitLocationMsg, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
+impl Handler<RetiredPublicKeyMsg> for Recorder {
+    type Result = ();
+
+    fn handle(&mut self, msg: RetiredPublicKeyMsg, _ctx: &mut Self::Context) {
+        self.record (msg);
+    }
+}
+
 impl Recorder {
     pub fn new () -> Recorder {
         Recorder {
             recording: Arc::new (Mutex::new (Recording {messages: vec! ()})),
+            route_query_responses: vec! (),
         }
     }
 
+    pub fn route_query_response (mut self, response: Option<RouteQueryResponse>) -> Recorder {
+        self.route_query_responses.push (response);
+        self
+    }
+
     pub fn record<T> (&mut self, item: T) where T: Any + Send {
         let mut recording = self.recording.lock ().unwrap ();
         let messages: &mut Vec<Box<Any + Send>> = &mut recording.messages;
@@ -687,9 +976,18 @@ mod tests {
     use actix::msgs;
     use actix::System;
     use sub_lib::cryptde::CryptData;
+    use sub_lib::cryptde::PlainData;
     use sub_lib::cryptde_null::CryptDENull;
     use sub_lib::hop::Hop;
 
+    fn layered_hop (hop: Hop, key: &Key, layers: usize, cryptde: &CryptDE) -> CryptData {
+        let mut hop_enc = hop.encode (key, cryptde).unwrap ();
+        for _ in 1..layers {
+            hop_enc = cryptde.encode (key, &PlainData::new (&hop_enc.data[..])).unwrap ();
+        }
+        hop_enc
+    }
+
     #[test]
     fn characterize_route_from_proxy_server() {
         let cryptde = CryptDENull::new();
@@ -698,9 +996,9 @@ mod tests {
         let subject = route_from_proxy_server(&key, &cryptde);
 
         assert_eq! (subject.hops, vec! (
-            Hop::new (&key, Component::Hopper).encode (&key, &cryptde).unwrap (),
-            Hop::new (&key, Component::ProxyClient).encode (&key, &cryptde).unwrap (),
-            Hop::new (&Key::new(b""), Component::ProxyServer).encode (&key, &cryptde).unwrap (),
+            layered_hop (Hop::new (&key, Component::Hopper).consumed_by (&cryptde), &key, 1, &cryptde),
+            layered_hop (Hop::new (&key, Component::ProxyClient).consumed_by (&cryptde), &key, 2, &cryptde),
+            layered_hop (Hop::new (&Key::new(b""), Component::ProxyServer).consumed_by (&cryptde), &key, 3, &cryptde),
         ));
     }
 
@@ -711,11 +1009,12 @@ mod tests {
 
         let subject = route_to_proxy_client(&key, &cryptde);
 
-        let mut garbage_can: Vec<u8> = iter::repeat (0u8).take (50).collect ();
+        let top_hop_len = route_from_proxy_server (&key, &cryptde).hops[0].data.len ();
+        let mut garbage_can: Vec<u8> = iter::repeat (0u8).take (top_hop_len).collect ();
         cryptde.random (&mut garbage_can[..]);
         assert_eq! (subject.hops, vec! (
-            Hop::new (&key, Component::ProxyClient).encode (&key, &cryptde).unwrap (),
-            Hop::new (&Key::new(b""), Component::ProxyServer).encode (&key, &cryptde).unwrap (),
+            layered_hop (Hop::new (&key, Component::ProxyClient).consumed_by (&cryptde), &key, 1, &cryptde),
+            layered_hop (Hop::new (&Key::new(b""), Component::ProxyServer).consumed_by (&cryptde), &key, 2, &cryptde),
             CryptData::new(&garbage_can[..])
         ));
     }
@@ -727,11 +1026,12 @@ mod tests {
 
         let subject = route_from_proxy_client(&key, &cryptde);
 
-        let mut garbage_can: Vec<u8> = iter::repeat (0u8).take (50).collect ();
+        let top_hop_len = route_from_proxy_server (&key, &cryptde).hops[0].data.len ();
+        let mut garbage_can: Vec<u8> = iter::repeat (0u8).take (top_hop_len).collect ();
         cryptde.random (&mut garbage_can[..]);
         assert_eq! (subject.hops, vec! (
-            Hop::new (&key, Component::ProxyClient).encode (&key, &cryptde).unwrap (),
-            Hop::new (&Key::new(b""), Component::ProxyServer).encode (&key, &cryptde).unwrap (),
+            layered_hop (Hop::new (&key, Component::ProxyClient).consumed_by (&cryptde), &key, 1, &cryptde),
+            layered_hop (Hop::new (&Key::new(b""), Component::ProxyServer).consumed_by (&cryptde), &key, 2, &cryptde),
             CryptData::new(&garbage_can[..])
         ));
     }
@@ -743,10 +1043,11 @@ mod tests {
 
         let subject = route_to_proxy_server(&key, &cryptde);
 
-        let mut garbage_can: Vec<u8> = iter::repeat (0u8).take (50).collect ();
+        let top_hop_len = route_from_proxy_server (&key, &cryptde).hops[0].data.len ();
+        let mut garbage_can: Vec<u8> = iter::repeat (0u8).take (top_hop_len).collect ();
         cryptde.random (&mut garbage_can[..]);
         assert_eq! (subject.hops, vec! (
-            Hop::new(&Key::new(b""), Component::ProxyServer).encode(&key, &cryptde).unwrap(),
+            Hop::new(&Key::new(b""), Component::ProxyServer).consumed_by (&cryptde).encode(&key, &cryptde).unwrap(),
             CryptData::new (&garbage_can[..]),
             CryptData::new (&garbage_can[..]),
         ));